@@ -0,0 +1,64 @@
+//! Compares bincode decoding with the zero-copy wire layout for sync-sized block batches.
+//! Run with `cargo bench --bench block_encoding`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use quantum_metaverse::blockchain::core::Block;
+use quantum_metaverse::blockchain::wire::{encode_block, BlockView};
+use quantum_metaverse::math::precision::PreciseFloat;
+
+fn blocks(count: usize, data_len: usize) -> Vec<Block> {
+    let mut previous = [0u8; 32];
+    (0..count)
+        .map(|i| {
+            let one = PreciseFloat::new(1, 2);
+            let block = Block::new(i as u64, previous, vec![i as u8; data_len], one.clone(), one.clone(), one.clone(), one);
+            previous = block.hash;
+            block
+        })
+        .collect()
+}
+
+fn decode_and_verify(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sync_batch_decode_verify");
+    for data_len in [256usize, 16 * 1024] {
+        let batch = blocks(1_000, data_len);
+        let legacy: Vec<Vec<u8>> = batch.iter().map(|b| bincode::serialize(b).unwrap()).collect();
+        let wire: Vec<Vec<u8>> = batch.iter().map(encode_block).collect();
+        group.throughput(Throughput::Elements(batch.len() as u64));
+
+        group.bench_with_input(BenchmarkId::new("bincode", data_len), &legacy, |b, encoded| {
+            b.iter(|| {
+                for bytes in encoded {
+                    let block: Block = bincode::deserialize(bytes).unwrap();
+                    black_box(block.verify_hash());
+                }
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("wire_view", data_len), &wire, |b, encoded| {
+            b.iter(|| {
+                for bytes in encoded {
+                    let view = BlockView::parse(bytes).unwrap();
+                    black_box(view.verify_hash());
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn encode(c: &mut Criterion) {
+    let batch = blocks(1_000, 4 * 1024);
+    let mut group = c.benchmark_group("sync_batch_encode");
+    group.throughput(Throughput::Elements(batch.len() as u64));
+    group.bench_function("bincode", |b| {
+        b.iter(|| batch.iter().map(|block| bincode::serialize(block).unwrap().len()).sum::<usize>())
+    });
+    group.bench_function("wire", |b| {
+        b.iter(|| batch.iter().map(|block| encode_block(block).len()).sum::<usize>())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, decode_and_verify, encode);
+criterion_main!(benches);
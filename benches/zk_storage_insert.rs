@@ -0,0 +1,36 @@
+//! Insert throughput of the ZKStorage index tree once it holds 1M entries.
+//! Run with `cargo bench --bench zk_storage_insert`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use quantum_metaverse::blockchain::zk_storage::ZKStorage;
+use quantum_metaverse::ids::DataId;
+
+fn entry(i: u64) -> Vec<u8> {
+    blake3::hash(&i.to_le_bytes()).as_bytes().to_vec()
+}
+
+fn insert_at_scale(c: &mut Criterion) {
+    const PREFILL: u64 = 1_000_000;
+    let mut storage = ZKStorage::new(20);
+    for i in 0..PREFILL {
+        storage.store_data(entry(i), 0).unwrap();
+    }
+
+    let mut next = PREFILL;
+    let mut group = c.benchmark_group("zk_storage");
+    group.sample_size(20);
+    group.bench_function("insert_with_1m_entries", |b| {
+        b.iter(|| {
+            next += 1;
+            black_box(storage.store_data(entry(next), 0).unwrap());
+        })
+    });
+    group.bench_function("inclusion_proof_with_1m_entries", |b| {
+        let id = DataId::new(entry(PREFILL / 2).try_into().unwrap());
+        b.iter(|| black_box(storage.inclusion_proof(&id)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, insert_at_scale);
+criterion_main!(benches);
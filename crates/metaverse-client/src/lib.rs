@@ -0,0 +1,189 @@
+//! JSON-RPC client for a Quantum Metaverse node.
+//!
+//! Requests go over plain HTTP on a blocking `TcpStream`, one connection
+//! per call, so the client needs no async runtime. Nodes serving RPC over
+//! TLS (`rpc_tls`) must be reached through a terminating proxy.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+pub use metaverse_core::types::Address;
+
+/// Connect, write and read timeout for a call
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Serialize)]
+struct Request<'a> {
+    jsonrpc: &'a str,
+    method: &'a str,
+    params: Value,
+    id: u64,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    result: Option<Value>,
+    error: Option<ResponseError>,
+}
+
+#[derive(Deserialize)]
+struct ResponseError {
+    message: String,
+}
+
+/// RPC Client
+/// Calls one node's JSON-RPC endpoint.
+pub struct RpcClient {
+    addr: String,
+    timeout: Duration,
+    next_id: AtomicU64,
+}
+
+impl RpcClient {
+    /// Client for the node listening at `addr` (`host:port`)
+    pub fn new(addr: impl Into<String>) -> Self {
+        Self { addr: addr.into(), timeout: DEFAULT_TIMEOUT, next_id: AtomicU64::new(1) }
+    }
+
+    /// Client for a node on this machine
+    pub fn local(port: u16) -> Self {
+        Self::new(format!("127.0.0.1:{}", port))
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Call `method` and return its result, or the node's error message
+    pub fn call(&self, method: &str, params: Value) -> Result<Value, String> {
+        let body = serde_json::to_string(&Request {
+            jsonrpc: "2.0",
+            method,
+            params,
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+        }).map_err(|e| e.to_string())?;
+
+        let addr = std::net::ToSocketAddrs::to_socket_addrs(self.addr.as_str())
+            .map_err(|e| format!("Failed to resolve {}: {}", self.addr, e))?
+            .next()
+            .ok_or_else(|| format!("No address for {}", self.addr))?;
+        let mut stream = TcpStream::connect_timeout(&addr, self.timeout)
+            .map_err(|e| format!("Failed to connect to {}: {}", self.addr, e))?;
+        stream.set_read_timeout(Some(self.timeout)).map_err(|e| e.to_string())?;
+        stream.set_write_timeout(Some(self.timeout)).map_err(|e| e.to_string())?;
+        write!(
+            stream,
+            "POST / HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.addr,
+            body.len(),
+            body
+        ).map_err(|e| format!("Failed to send request: {}", e))?;
+
+        let mut raw = Vec::new();
+        stream.read_to_end(&mut raw).map_err(|e| format!("Failed to read response: {}", e))?;
+        let raw = String::from_utf8_lossy(&raw);
+        let body = raw.split_once("\r\n\r\n").map(|(_, body)| body).ok_or("Malformed RPC response")?;
+        let response: Response = serde_json::from_str(body).map_err(|e| format!("Malformed RPC response: {}", e))?;
+        match response.error {
+            Some(error) => Err(error.message),
+            None => Ok(response.result.unwrap_or(Value::Null)),
+        }
+    }
+
+    /// Call `method` and decode its result as `T`
+    pub fn call_as<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T, String> {
+        serde_json::from_value(self.call(method, params)?).map_err(|e| format!("Unexpected {} result: {}", method, e))
+    }
+
+    /// Node status and health
+    pub fn status(&self) -> Result<Value, String> {
+        self.call("status", json!({}))
+    }
+
+    /// Account at the latest height
+    pub fn account(&self, address: &Address) -> Result<Value, String> {
+        self.call("getAccount", json!({ "address": hex::encode(address) }))
+    }
+
+    /// Submit a signed transaction, in the JSON form `wallet` prints, and
+    /// return its hash
+    pub fn submit_transaction(&self, transaction: &Value) -> Result<Value, String> {
+        self.call("submitTransaction", json!({ "transaction": transaction }))
+    }
+
+    /// Pending or included transaction by hash
+    pub fn transaction(&self, hash: &[u8; 32]) -> Result<Value, String> {
+        self.call("getTransactionByHash", json!({ "hash": hex::encode(hash) }))
+    }
+
+    /// Current epoch, or `epoch` if given, with its schedule
+    pub fn epoch(&self, epoch: Option<u64>) -> Result<Value, String> {
+        match epoch {
+            Some(epoch) => self.call("getEpoch", json!({ "epoch": epoch })),
+            None => self.call("getEpoch", json!({})),
+        }
+    }
+
+    /// Protocol parameters in force at `height`, or the latest height
+    pub fn protocol_params(&self, height: Option<u64>) -> Result<Value, String> {
+        match height {
+            Some(height) => self.call("getProtocolParams", json!({ "height": height })),
+            None => self.call("getProtocolParams", json!({})),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+
+    /// Answer one request with `body`, returning the request's JSON
+    fn serve_once(listener: TcpListener, body: &'static str) -> std::thread::JoinHandle<Value> {
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(value) = line.strip_prefix("Content-Length: ") {
+                    length = value.trim().parse().unwrap();
+                }
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut request = vec![0u8; length];
+            reader.read_exact(&mut request).unwrap();
+            let mut stream = reader.into_inner();
+            write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+            serde_json::from_slice(&request).unwrap()
+        })
+    }
+
+    #[test]
+    fn test_call_returns_result_or_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = RpcClient::new(listener.local_addr().unwrap().to_string());
+        let server = serve_once(listener, r#"{"jsonrpc":"2.0","result":{"balance":"7"},"error":null,"id":1}"#);
+        let account = client.account(&[0xab; 32]).unwrap();
+        assert_eq!(account["balance"], "7");
+        let request = server.join().unwrap();
+        assert_eq!(request["method"], "getAccount");
+        assert_eq!(request["params"]["address"], "ab".repeat(32));
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = RpcClient::new(listener.local_addr().unwrap().to_string());
+        let server = serve_once(listener, r#"{"jsonrpc":"2.0","result":null,"error":{"code":-32601,"message":"Method not found","data":null},"id":1}"#);
+        assert_eq!(client.status().unwrap_err(), "Method not found");
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,129 @@
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+
+/// Below this many items the work is done on the calling thread
+const PARALLEL_THRESHOLD: usize = 64;
+
+/// One signature to check as part of a batch
+#[derive(Debug, Clone, Copy)]
+pub struct SignatureItem<'a> {
+    pub public_key: &'a [u8; 32],
+    pub message: &'a [u8],
+    pub signature: &'a [u8; 64],
+}
+
+/// Map `f` over `items` across all available cores, preserving order
+pub fn par_map<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let threads = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    if items.len() < PARALLEL_THRESHOLD || threads == 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let chunk_size = items.len().div_ceil(threads);
+    let f = &f;
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = items.chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(f).collect::<Vec<R>>()))
+            .collect();
+        workers.into_iter()
+            .flat_map(|worker| worker.join().expect("verification worker panicked"))
+            .collect()
+    })
+}
+
+/// Verify many ed25519 signatures at once.
+/// Each chunk is checked with a single batch equation; only chunks that fail
+/// are re-verified one by one. Returns the indices of invalid signatures.
+pub fn verify_signatures(items: &[SignatureItem]) -> Result<(), Vec<usize>> {
+    let threads = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let chunk_size = if items.len() < PARALLEL_THRESHOLD {
+        items.len().max(1)
+    } else {
+        items.len().div_ceil(threads)
+    };
+
+    let chunks: Vec<(usize, &[SignatureItem])> = items.chunks(chunk_size)
+        .enumerate()
+        .map(|(n, chunk)| (n * chunk_size, chunk))
+        .collect();
+
+    let failures: Vec<usize> = par_map(&chunks, |(offset, chunk)| verify_chunk(chunk)
+            .into_iter()
+            .map(|index| offset + index)
+            .collect::<Vec<_>>())
+        .into_iter()
+        .flatten()
+        .collect();
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(failures)
+    }
+}
+
+fn verify_chunk(chunk: &[SignatureItem]) -> Vec<usize> {
+    let keys: Vec<Option<VerifyingKey>> = chunk.iter()
+        .map(|item| VerifyingKey::from_bytes(item.public_key).ok())
+        .collect();
+
+    if keys.iter().all(Option::is_some) {
+        let keys: Vec<VerifyingKey> = keys.iter().flatten().copied().collect();
+        let messages: Vec<&[u8]> = chunk.iter().map(|item| item.message).collect();
+        let signatures: Vec<Signature> = chunk.iter().map(|item| Signature::from_bytes(item.signature)).collect();
+        if ed25519_dalek::verify_batch(&messages, &signatures, &keys).is_ok() {
+            return Vec::new();
+        }
+    }
+
+    // Batch failed: find the offending signatures individually
+    chunk.iter()
+        .zip(keys)
+        .enumerate()
+        .filter(|(_, (item, key))| match key {
+            Some(key) => key.verify(item.message, &Signature::from_bytes(item.signature)).is_err(),
+            None => true,
+        })
+        .map(|(index, _)| index)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    #[test]
+    fn test_batch_reports_bad_indices() {
+        let signed: Vec<([u8; 32], Vec<u8>, [u8; 64])> = (0..100u8)
+            .map(|i| {
+                let key = SigningKey::from_bytes(&[i; 32]);
+                let message = vec![i; 16];
+                let signature = key.sign(&message).to_bytes();
+                (key.verifying_key().to_bytes(), message, signature)
+            })
+            .collect();
+
+        let mut items: Vec<SignatureItem> = signed.iter()
+            .map(|(public_key, message, signature)| SignatureItem { public_key, message, signature })
+            .collect();
+        assert!(verify_signatures(&items).is_ok());
+
+        // Swap in a signature over a different message at two positions
+        items[3].signature = &signed[4].2;
+        items[97].message = &signed[0].1;
+        assert_eq!(verify_signatures(&items).unwrap_err(), vec![3, 97]);
+        assert!(verify_signatures(&[]).is_ok());
+    }
+
+    #[test]
+    fn test_par_map_preserves_order() {
+        let input: Vec<u64> = (0..1_000).collect();
+        let doubled = par_map(&input, |x| x * 2);
+        assert_eq!(doubled, input.iter().map(|x| x * 2).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,90 @@
+use crate::ids::ChainId;
+
+/// Network ID of the public mainnet (`chain_id` in the node config)
+pub const MAINNET_NETWORK_ID: u64 = 1;
+
+/// Prefix of every signed payload; bump the version if the layout changes
+const TAG: &[u8; 8] = b"QMV-SIG1";
+
+/// What a signature authorizes, so a signature over one kind of object
+/// can never be presented as a signature over another
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PayloadKind {
+    Block = 1,
+    Transaction = 2,
+    Vote = 3,
+    Anchor = 4,
+    KeyRotation = 5,
+    MultisigApproval = 6,
+    Commitment = 7,
+    Ordering = 8,
+    Attestation = 9,
+    BeaconContribution = 10,
+    NodeCertificate = 11,
+    CircuitBreaker = 12,
+    TallyObservation = 13,
+    LayerAccess = 14,
+    Dao = 15,
+    PaymentChannel = 16,
+    Sponsorship = 17,
+    SeedList = 18,
+    Proposal = 19,
+}
+
+/// Network and chain a signature is valid on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SigningDomain {
+    pub network_id: u64,
+    pub chain_id: ChainId,
+}
+
+impl SigningDomain {
+    /// Main chain of a network; its chain ID is derived from the network ID
+    pub fn main_chain(network_id: u64) -> Self {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(b"qmv:main-chain:");
+        hasher.update(&network_id.to_le_bytes());
+        Self { network_id, chain_id: ChainId::new(hasher.finalize().into()) }
+    }
+
+    /// Chain hosted on a network, such as a private chain or sidechain
+    pub fn chain(network_id: u64, chain_id: ChainId) -> Self {
+        Self { network_id, chain_id }
+    }
+
+    /// Bytes to sign:
+    /// `tag | kind u8 | network_id u64 | chain_id [32] | height u64 | body` (integers little-endian).
+    /// `height` is the block height the object belongs to; transactions,
+    /// which are bound to the sender's nonce instead, use 0.
+    pub fn payload(&self, kind: PayloadKind, height: u64, body: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(TAG.len() + 1 + 8 + 32 + 8 + body.len());
+        out.extend_from_slice(TAG);
+        out.push(kind as u8);
+        out.extend_from_slice(&self.network_id.to_le_bytes());
+        out.extend_from_slice(self.chain_id.as_bytes());
+        out.extend_from_slice(&height.to_le_bytes());
+        out.extend_from_slice(body);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payloads_differ_by_domain() {
+        let mainnet = SigningDomain::main_chain(MAINNET_NETWORK_ID);
+        let testnet = SigningDomain::main_chain(2);
+        let private = SigningDomain::chain(MAINNET_NETWORK_ID, ChainId::new([7u8; 32]));
+
+        let body = b"block data";
+        let reference = mainnet.payload(PayloadKind::Block, 10, body);
+        assert_ne!(reference, testnet.payload(PayloadKind::Block, 10, body));
+        assert_ne!(reference, private.payload(PayloadKind::Block, 10, body));
+        assert_ne!(reference, mainnet.payload(PayloadKind::Block, 11, body));
+        assert_ne!(reference, mainnet.payload(PayloadKind::Anchor, 10, body));
+        assert!(reference.ends_with(body));
+    }
+}
@@ -0,0 +1,8 @@
+pub mod tally;
+pub mod batch;
+pub mod domain;
+pub mod vdf;
+pub mod pedersen;
+pub mod transition;
+
+pub use self::tally::{TallyProof, TallyState};
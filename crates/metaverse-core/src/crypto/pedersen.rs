@@ -0,0 +1,294 @@
+//! Pedersen commitments and range proofs on Ristretto.
+//!
+//! `commit(v, r) = v·G + r·H` hides `v` and is additively homomorphic, so
+//! sums and differences of committed amounts can be checked without opening
+//! them. A `RangeProof` shows a commitment holds a value in `[0, 2^64)`: the
+//! value is split into bit commitments that add up, weighted by powers of
+//! two, to the commitment, and each bit carries an either-or Schnorr proof
+//! that it commits to 0 or 1. A `KnowledgeProof` shows knowledge of both
+//! openings of a commitment to a secret scalar, revealing neither. Proofs
+//! are made non-interactive with a SHA-512 transcript that the caller binds
+//! to its context.
+
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoBasepointTable, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use rand::RngCore;
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha512};
+use std::sync::OnceLock;
+use crate::types::hex_serde;
+
+/// Bits a range proof covers
+pub const RANGE_BITS: usize = 64;
+
+/// Second generator, with no known discrete log relative to the basepoint
+fn blinding_generator() -> &'static RistrettoBasepointTable {
+    static GENERATOR: OnceLock<RistrettoBasepointTable> = OnceLock::new();
+    GENERATOR.get_or_init(|| {
+        let digest: [u8; 64] = Sha512::digest(b"QMV-PEDERSEN-BLINDING").into();
+        RistrettoBasepointTable::create(&RistrettoPoint::from_uniform_bytes(&digest))
+    })
+}
+
+/// Commit to `value` with `blinding`
+pub fn commit(value: u64, blinding: &Scalar) -> RistrettoPoint {
+    RistrettoPoint::mul_base(&Scalar::from(value)) + blinding_generator() * blinding
+}
+
+/// Commit to a secret scalar, such as an identity key, with `blinding`
+pub fn commit_scalar(secret: &Scalar, blinding: &Scalar) -> RistrettoPoint {
+    RistrettoPoint::mul_base(secret) + blinding_generator() * blinding
+}
+
+/// `value·G`: a commitment to `value` with no blinding
+pub fn commit_public(value: u64) -> RistrettoPoint {
+    RistrettoPoint::mul_base(&Scalar::from(value))
+}
+
+pub fn random_scalar() -> Scalar {
+    let mut bytes = [0u8; 64];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    Scalar::from_bytes_mod_order_wide(&bytes)
+}
+
+pub fn decompress(bytes: &[u8; 32]) -> Result<RistrettoPoint, &'static str> {
+    CompressedRistretto(*bytes).decompress().ok_or("Invalid commitment")
+}
+
+pub fn compress(point: &RistrettoPoint) -> [u8; 32] {
+    point.compress().to_bytes()
+}
+
+fn scalar(bytes: &[u8; 32]) -> Result<Scalar, &'static str> {
+    Option::from(Scalar::from_canonical_bytes(*bytes)).ok_or("Invalid proof scalar")
+}
+
+fn challenge(transcript: &[u8], index: usize, points: &[&RistrettoPoint]) -> Scalar {
+    let mut hasher = Sha512::new();
+    hasher.update(transcript);
+    hasher.update((index as u64).to_le_bytes());
+    for point in points {
+        hasher.update(point.compress().as_bytes());
+    }
+    Scalar::from_bytes_mod_order_wide(&hasher.finalize().into())
+}
+
+/// Commitment to one bit, with a proof that it is 0 or 1
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BitProof {
+    #[serde(with = "hex_serde")]
+    pub commitment: [u8; 32],
+    #[serde(with = "hex_serde")]
+    pub c0: [u8; 32],
+    #[serde(with = "hex_serde")]
+    pub s0: [u8; 32],
+    #[serde(with = "hex_serde")]
+    pub c1: [u8; 32],
+    #[serde(with = "hex_serde")]
+    pub s1: [u8; 32],
+}
+
+impl BitProof {
+    /// Either-or proof of knowing the blinding of the commitment as a
+    /// commitment to 0 or to 1; the branch not taken is simulated
+    fn prove(transcript: &[u8], index: usize, one: bool, blinding: &Scalar) -> Self {
+        let h = blinding_generator();
+        let commitment = commit(one as u64, blinding);
+        let targets = [commitment, commitment - RISTRETTO_BASEPOINT_POINT];
+        let (real, fake) = if one { (1, 0) } else { (0, 1) };
+
+        let (fake_challenge, fake_response) = (random_scalar(), random_scalar());
+        let nonce = random_scalar();
+        let mut announcements = [RistrettoPoint::default(); 2];
+        announcements[fake] = h * &fake_response - fake_challenge * targets[fake];
+        announcements[real] = h * &nonce;
+
+        let total = challenge(transcript, index, &[&commitment, &announcements[0], &announcements[1]]);
+        let mut challenges = [Scalar::ZERO; 2];
+        let mut responses = [Scalar::ZERO; 2];
+        challenges[fake] = fake_challenge;
+        responses[fake] = fake_response;
+        challenges[real] = total - fake_challenge;
+        responses[real] = nonce + challenges[real] * blinding;
+        Self {
+            commitment: compress(&commitment),
+            c0: challenges[0].to_bytes(),
+            s0: responses[0].to_bytes(),
+            c1: challenges[1].to_bytes(),
+            s1: responses[1].to_bytes(),
+        }
+    }
+
+    fn verify(&self, transcript: &[u8], index: usize) -> Result<RistrettoPoint, &'static str> {
+        let h = blinding_generator();
+        let commitment = decompress(&self.commitment)?;
+        let (c0, s0, c1, s1) = (scalar(&self.c0)?, scalar(&self.s0)?, scalar(&self.c1)?, scalar(&self.s1)?);
+        let a0 = h * &s0 - c0 * commitment;
+        let a1 = h * &s1 - c1 * (commitment - RISTRETTO_BASEPOINT_POINT);
+        if c0 + c1 != challenge(transcript, index, &[&commitment, &a0, &a1]) {
+            return Err("Bit proof does not verify");
+        }
+        Ok(commitment)
+    }
+}
+
+/// Range Proof
+/// Shows a commitment holds a value in `[0, 2^64)` without revealing it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RangeProof {
+    pub bits: Vec<BitProof>,
+}
+
+impl RangeProof {
+    /// Prove `commit(value, blinding)` is in range
+    pub fn prove(value: u64, blinding: &Scalar, transcript: &[u8]) -> Self {
+        // Bit blindings weighted by powers of two must add up to `blinding`
+        let mut blindings: Vec<Scalar> = (0..RANGE_BITS).map(|_| random_scalar()).collect();
+        let rest: Scalar = blindings.iter().enumerate().skip(1)
+            .map(|(bit, blinding)| Scalar::from(1u64 << bit) * blinding)
+            .sum();
+        blindings[0] = blinding - rest;
+
+        let bits = blindings.iter().enumerate()
+            .map(|(bit, blinding)| BitProof::prove(transcript, bit, (value >> bit) & 1 == 1, blinding))
+            .collect();
+        Self { bits }
+    }
+
+    /// Check the proof against `commitment`
+    pub fn verify(&self, commitment: &RistrettoPoint, transcript: &[u8]) -> Result<(), &'static str> {
+        if self.bits.len() != RANGE_BITS {
+            return Err("Range proof has the wrong number of bits");
+        }
+        let mut recombined = RistrettoPoint::default();
+        for (bit, proof) in self.bits.iter().enumerate().rev() {
+            recombined = recombined + recombined + proof.verify(transcript, bit)?;
+        }
+        if &recombined != commitment {
+            return Err("Bit commitments do not add up to the commitment");
+        }
+        Ok(())
+    }
+}
+
+/// Proof that a commitment opens to a stated value: knowledge of the
+/// blinding `r` with `C - v·G = r·H`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OpeningProof {
+    #[serde(with = "hex_serde")]
+    pub announcement: [u8; 32],
+    #[serde(with = "hex_serde")]
+    pub response: [u8; 32],
+}
+
+impl OpeningProof {
+    pub fn prove(value: u64, blinding: &Scalar, transcript: &[u8]) -> Self {
+        let target = commit(value, blinding) - commit_public(value);
+        let nonce = random_scalar();
+        let announcement = blinding_generator() * &nonce;
+        let challenge = challenge(transcript, 0, &[&target, &announcement]);
+        Self { announcement: compress(&announcement), response: (nonce + challenge * blinding).to_bytes() }
+    }
+
+    pub fn verify(&self, commitment: &RistrettoPoint, value: u64, transcript: &[u8]) -> Result<(), &'static str> {
+        let target = commitment - commit_public(value);
+        let announcement = decompress(&self.announcement)?;
+        let challenge = challenge(transcript, 0, &[&target, &announcement]);
+        if blinding_generator() * &scalar(&self.response)? != announcement + challenge * target {
+            return Err("Opening proof does not verify");
+        }
+        Ok(())
+    }
+}
+
+/// Knowledge Proof
+/// Shows knowledge of `x` and `r` with `C = x·G + r·H`, revealing neither.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KnowledgeProof {
+    #[serde(with = "hex_serde")]
+    pub announcement: [u8; 32],
+    #[serde(with = "hex_serde")]
+    pub secret_response: [u8; 32],
+    #[serde(with = "hex_serde")]
+    pub blinding_response: [u8; 32],
+}
+
+impl KnowledgeProof {
+    /// Encoded length: the announcement and two responses
+    pub const BYTES: usize = 96;
+
+    pub fn prove(secret: &Scalar, blinding: &Scalar, transcript: &[u8]) -> Self {
+        let commitment = commit_scalar(secret, blinding);
+        let (secret_nonce, blinding_nonce) = (random_scalar(), random_scalar());
+        let announcement = commit_scalar(&secret_nonce, &blinding_nonce);
+        let challenge = challenge(transcript, 0, &[&commitment, &announcement]);
+        Self {
+            announcement: compress(&announcement),
+            secret_response: (secret_nonce + challenge * secret).to_bytes(),
+            blinding_response: (blinding_nonce + challenge * blinding).to_bytes(),
+        }
+    }
+
+    pub fn verify(&self, commitment: &RistrettoPoint, transcript: &[u8]) -> Result<(), &'static str> {
+        let announcement = decompress(&self.announcement)?;
+        let challenge = challenge(transcript, 0, &[commitment, &announcement]);
+        let response = commit_scalar(&scalar(&self.secret_response)?, &scalar(&self.blinding_response)?);
+        if response != announcement + challenge * commitment {
+            return Err("Knowledge proof does not verify");
+        }
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [self.announcement, self.secret_response, self.blinding_response].concat()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
+        if bytes.len() != Self::BYTES {
+            return Err("Knowledge proof has the wrong length");
+        }
+        let part = |index: usize| -> [u8; 32] { bytes[index * 32..(index + 1) * 32].try_into().expect("32-byte part") };
+        Ok(Self { announcement: part(0), secret_response: part(1), blinding_response: part(2) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_range_and_opening_proofs() {
+        let blinding = random_scalar();
+        let commitment = commit(1_234, &blinding);
+        let proof = RangeProof::prove(1_234, &blinding, b"test");
+        assert!(proof.verify(&commitment, b"test").is_ok());
+        assert!(proof.verify(&commitment, b"other context").is_err());
+        assert!(proof.verify(&commit(1_235, &blinding), b"test").is_err());
+
+        // Homomorphic: the difference of two commitments commits to the difference
+        let other = random_scalar();
+        let difference = commitment - commit(234, &other);
+        assert_eq!(difference, commit(1_000, &(blinding - other)));
+
+        let opening = OpeningProof::prove(1_234, &blinding, b"open");
+        assert!(opening.verify(&commitment, 1_234, b"open").is_ok());
+        assert!(opening.verify(&commitment, 1_000, b"open").is_err());
+    }
+
+    #[test]
+    fn test_knowledge_proof() {
+        let (secret, blinding) = (random_scalar(), random_scalar());
+        let commitment = commit_scalar(&secret, &blinding);
+        let proof = KnowledgeProof::prove(&secret, &blinding, b"identity");
+        assert!(proof.verify(&commitment, b"identity").is_ok());
+        assert!(proof.verify(&commitment, b"other context").is_err());
+        assert!(proof.verify(&commit_scalar(&secret, &random_scalar()), b"identity").is_err());
+        // Knowing only one opening is not enough
+        assert!(KnowledgeProof::prove(&secret, &random_scalar(), b"identity").verify(&commitment, b"identity").is_err());
+
+        let decoded = KnowledgeProof::from_bytes(&proof.to_bytes()).unwrap();
+        assert_eq!(decoded, proof);
+        assert!(KnowledgeProof::from_bytes(&[0; 95]).is_err());
+    }
+}
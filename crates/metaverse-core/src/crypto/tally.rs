@@ -87,6 +87,11 @@ impl TallyState {
         computed_hash == proof.state_hash && operation_hash == proof.operation_hash
     }
 
+    /// Verify many (proof, operation) pairs in parallel; results keep input order
+    pub fn verify_proofs_batch(&self, items: &[(&TallyProof, &[u8])]) -> Vec<bool> {
+        crate::crypto::batch::par_map(items, |(proof, operation)| self.verify_proof(proof, operation))
+    }
+
     /// Calculate quantum coherence score from proof
     pub fn calculate_coherence(&self, proof: &TallyProof) -> PreciseFloat {
         // Count matching bits in quantum commitment
@@ -0,0 +1,145 @@
+//! The tally transition shared by `TallyLayer` and `TallyComputer`.
+//!
+//! Every tally step records the `TallyVersion` it was hashed with, so a
+//! chain stays verifiable after the algorithm changes: a verifier picks the
+//! algorithm by the step's version and rejects versions it does not know.
+//!
+//! Version 1 (`XorMix`) is
+//!
+//! ```text
+//! T(i) = H( (H(S(i)) ⊕ T(i-1)) ⊕ O(i) ) ⊕ H(P(i))
+//! ```
+//!
+//! where `H` is BLAKE3, the operation `O(i)` is repeated to 32 bytes, and
+//! `T(0)` is all zeros, so the first step reduces to `H(H(S) ⊕ O) ⊕ H(P)`.
+//! The proof is always hashed, whatever its length, so a proof and its
+//! hash never produce the same step.
+//!
+//! Version 2 (`KeyedSponge`) absorbs the length-prefixed state, operation
+//! and proof into BLAKE3 keyed with `T(i-1)`. Unlike the XOR mixing, no two
+//! distinct inputs collide by construction, e.g. an operation and the same
+//! bytes repeated.
+
+use serde::{Serialize, Deserialize};
+
+/// Starting point of every tally chain
+pub const GENESIS_TALLY: [u8; 32] = [0u8; 32];
+
+/// Algorithm a tally step was hashed with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "u8", into = "u8")]
+#[repr(u8)]
+pub enum TallyVersion {
+    XorMix = 1,
+    KeyedSponge = 2,
+}
+
+impl TallyVersion {
+    /// Version of tallies written before versions were recorded
+    pub const ORIGINAL: TallyVersion = TallyVersion::XorMix;
+    pub const LATEST: TallyVersion = TallyVersion::KeyedSponge;
+
+    /// Hash one transition with this version's algorithm
+    pub fn transition(self, previous: &[u8; 32], state: &[u8], operation: &[u8], proof: &[u8]) -> Result<[u8; 32], &'static str> {
+        match self {
+            TallyVersion::XorMix => transition(previous, state, operation, proof),
+            TallyVersion::KeyedSponge => keyed_transition(previous, state, operation, proof),
+        }
+    }
+}
+
+impl TryFrom<u8> for TallyVersion {
+    type Error = &'static str;
+
+    fn try_from(version: u8) -> Result<Self, Self::Error> {
+        match version {
+            1 => Ok(TallyVersion::XorMix),
+            2 => Ok(TallyVersion::KeyedSponge),
+            _ => Err("Unknown tally version"),
+        }
+    }
+}
+
+impl From<TallyVersion> for u8 {
+    fn from(version: TallyVersion) -> u8 {
+        version as u8
+    }
+}
+
+/// Hash one version 1 transition from `previous` over `state`,
+/// `operation` and `proof`. All three must be non-empty.
+pub fn transition(previous: &[u8; 32], state: &[u8], operation: &[u8], proof: &[u8]) -> Result<[u8; 32], &'static str> {
+    if state.is_empty() || operation.is_empty() || proof.is_empty() {
+        return Err("Empty input state, operation, or proof");
+    }
+
+    let state_hash = blake3::hash(state);
+    let mut mixed = [0u8; 32];
+    for (i, byte) in mixed.iter_mut().enumerate() {
+        *byte = state_hash.as_bytes()[i] ^ previous[i] ^ operation[i % operation.len()];
+    }
+
+    let mixed_hash = blake3::hash(&mixed);
+    let proof_hash = blake3::hash(proof);
+    let mut hash = [0u8; 32];
+    for (i, byte) in hash.iter_mut().enumerate() {
+        *byte = mixed_hash.as_bytes()[i] ^ proof_hash.as_bytes()[i];
+    }
+    Ok(hash)
+}
+
+/// Hash one version 2 transition. All inputs must be non-empty.
+pub fn keyed_transition(previous: &[u8; 32], state: &[u8], operation: &[u8], proof: &[u8]) -> Result<[u8; 32], &'static str> {
+    if state.is_empty() || operation.is_empty() || proof.is_empty() {
+        return Err("Empty input state, operation, or proof");
+    }
+    let mut sponge = blake3::Hasher::new_keyed(previous);
+    for input in [state, operation, proof] {
+        sponge.update(&(input.len() as u64).to_le_bytes());
+        sponge.update(input);
+    }
+    Ok(*sponge.finalize().as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_transition_and_chaining() {
+        let first = transition(&GENESIS_TALLY, b"state", b"op", b"proof").unwrap();
+        let state_hash = blake3::hash(b"state");
+        let mut mixed = [0u8; 32];
+        for (i, byte) in mixed.iter_mut().enumerate() {
+            *byte = state_hash.as_bytes()[i] ^ b"op"[i % 2];
+        }
+        let expected: Vec<u8> = blake3::hash(&mixed).as_bytes().iter()
+            .zip(blake3::hash(b"proof").as_bytes())
+            .map(|(a, b)| a ^ b)
+            .collect();
+        assert_eq!(first.to_vec(), expected);
+
+        // The same step from a different point in the chain lands elsewhere
+        assert_ne!(transition(&first, b"state", b"op", b"proof").unwrap(), first);
+        // A 32-byte proof is hashed like any other
+        let raw = transition(&GENESIS_TALLY, b"state", b"op", &[7u8; 32]).unwrap();
+        let hashed = transition(&GENESIS_TALLY, b"state", b"op", blake3::hash(&[7u8; 32]).as_bytes()).unwrap();
+        assert_ne!(raw, hashed);
+        assert!(transition(&GENESIS_TALLY, b"", b"op", b"proof").is_err());
+    }
+
+    #[test]
+    fn test_versions_hash_differently() {
+        // XOR mixing cannot tell an operation from the same bytes repeated
+        let short = TallyVersion::XorMix.transition(&GENESIS_TALLY, b"state", b"ab", b"proof").unwrap();
+        let repeated = TallyVersion::XorMix.transition(&GENESIS_TALLY, b"state", b"abab", b"proof").unwrap();
+        assert_eq!(short, repeated);
+        let short = TallyVersion::KeyedSponge.transition(&GENESIS_TALLY, b"state", b"ab", b"proof").unwrap();
+        let repeated = TallyVersion::KeyedSponge.transition(&GENESIS_TALLY, b"state", b"abab", b"proof").unwrap();
+        assert_ne!(short, repeated);
+
+        assert_eq!(TallyVersion::try_from(2), Ok(TallyVersion::KeyedSponge));
+        assert_eq!(TallyVersion::try_from(3), Err("Unknown tally version"));
+        assert!(serde_json::from_str::<TallyVersion>("9").is_err());
+    }
+}
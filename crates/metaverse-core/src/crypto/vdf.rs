@@ -0,0 +1,216 @@
+//! Wesolowski verifiable delay function over the RSA-2048 group.
+//!
+//! Evaluating `y = x^(2^T) mod N` takes `T` sequential squarings with no
+//! known shortcut, since nobody knows the factors of the RSA-2048 challenge
+//! modulus. The proof `π = x^⌊2^T / ℓ⌋`, for a prime `ℓ` derived from the
+//! input and output, lets anyone check the result with two short
+//! exponentiations. Elements are taken up to sign (`x` and `N - x` are the
+//! same element) so the order-two element `-1` cannot be used to forge proofs.
+
+use serde::{Serialize, Deserialize};
+use num_bigint::BigUint;
+use num_traits::{One, Zero};
+use crate::types::hex_serde;
+
+/// RSA-2048 challenge modulus
+const MODULUS_HEX: &str = concat!(
+    "c7970ceedcc3b0754490201a7aa613cd73911081c790f5f1a8726f463550bb5b",
+    "7ff0db8e1ea1189ec72f93d1650011bd721aeeacc2acde32a04107f0648c2813",
+    "a31f5b0b7765ff8b44b4b6ffc93384b646eb09c7cf5e8592d40ea33c80039f35",
+    "b4f14a04b51f7bfd781be4d1673164ba8eb991c2c4d730bbbe35f592bdef524a",
+    "f7e8daefd26c66fc02c479af89d64d373f442709439de66ceb955f3ea37d5159",
+    "f6135809f85334b5cb1813addc80cd05609f10ac6a95ad65872c909525bdad32",
+    "bc729592642920f24c61dc5b3c3b7923e56b16a4d9d373d8721f24a3fc0f1b31",
+    "31f55615172866bccc30f95054c824e733a5eb6817f7bc16399d48c6361cc7e5",
+);
+
+/// Encoded size of a group element
+pub const ELEMENT_BYTES: usize = 256;
+
+/// Highest difficulty accepted, to bound verification of hostile input
+pub const MAX_DIFFICULTY: u64 = 1 << 32;
+
+/// Miller-Rabin rounds for the challenge prime
+const PRIME_ROUNDS: [u32; 20] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71];
+
+/// Result of a VDF evaluation and its proof
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VdfProof {
+    /// `x^(2^T)`, big-endian
+    #[serde(with = "hex_serde")]
+    pub output: Vec<u8>,
+    /// Wesolowski proof `π`, big-endian
+    #[serde(with = "hex_serde")]
+    pub proof: Vec<u8>,
+}
+
+impl VdfProof {
+    /// 32 bytes of randomness drawn from the VDF output
+    pub fn randomness(&self) -> [u8; 32] {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(b"qmv:vdf:output:");
+        hasher.update(&self.output);
+        hasher.finalize().into()
+    }
+}
+
+fn modulus() -> BigUint {
+    BigUint::parse_bytes(MODULUS_HEX.as_bytes(), 16).expect("valid modulus")
+}
+
+/// Representative of `{a, N - a}`
+fn normalize(a: BigUint, n: &BigUint) -> BigUint {
+    let negated = n - &a;
+    a.min(negated)
+}
+
+fn encode(a: &BigUint) -> Vec<u8> {
+    let bytes = a.to_bytes_be();
+    let mut out = vec![0; ELEMENT_BYTES - bytes.len()];
+    out.extend_from_slice(&bytes);
+    out
+}
+
+/// Decode a canonical, normalized element
+fn decode(bytes: &[u8], n: &BigUint) -> Result<BigUint, &'static str> {
+    if bytes.len() != ELEMENT_BYTES {
+        return Err("VDF element must be 256 bytes");
+    }
+    let a = BigUint::from_bytes_be(bytes);
+    if a.is_zero() || a > n - &a {
+        return Err("VDF element not in canonical form");
+    }
+    Ok(a)
+}
+
+/// Map a seed to a group element
+fn hash_to_group(seed: &[u8], n: &BigUint) -> BigUint {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"qmv:vdf:input:");
+    hasher.update(seed);
+    // Extra bytes make the reduction mod N close to uniform
+    let mut wide = [0u8; ELEMENT_BYTES + 32];
+    hasher.finalize_xof().fill(&mut wide);
+    let x = normalize(BigUint::from_bytes_be(&wide) % n, n);
+    if x.is_zero() { BigUint::from(2u8) } else { x }
+}
+
+fn is_probable_prime(candidate: &BigUint) -> bool {
+    let one = BigUint::one();
+    let minus_one = candidate - &one;
+    let twos = minus_one.trailing_zeros().unwrap_or(0);
+    let odd = &minus_one >> twos;
+    'rounds: for base in PRIME_ROUNDS {
+        let base = BigUint::from(base);
+        if &base >= candidate {
+            continue;
+        }
+        let mut x = base.modpow(&odd, candidate);
+        if x == one || x == minus_one {
+            continue;
+        }
+        for _ in 1..twos {
+            x = x.modpow(&BigUint::from(2u8), candidate);
+            if x == minus_one {
+                continue 'rounds;
+            }
+        }
+        return false;
+    }
+    true
+}
+
+/// 128-bit prime bound to the input and output, so the prover cannot pick it
+fn challenge_prime(x: &BigUint, y: &BigUint, difficulty: u64) -> BigUint {
+    (0u64..)
+        .map(|counter| {
+            let mut hasher = blake3::Hasher::new();
+            hasher.update(b"qmv:vdf:prime:");
+            hasher.update(&difficulty.to_le_bytes());
+            hasher.update(&encode(x));
+            hasher.update(&encode(y));
+            hasher.update(&counter.to_le_bytes());
+            let mut bytes = [0u8; 16];
+            bytes.copy_from_slice(&hasher.finalize().as_bytes()[..16]);
+            // Full 128 bits, odd
+            bytes[0] |= 0x80;
+            bytes[15] |= 1;
+            BigUint::from_bytes_be(&bytes)
+        })
+        .find(is_probable_prime)
+        .expect("primes are dense enough")
+}
+
+/// Run the VDF on `seed` for `difficulty` sequential squarings. This is the
+/// slow part: it takes time linear in `difficulty` and cannot be parallelized.
+pub fn evaluate(seed: &[u8], difficulty: u64) -> VdfProof {
+    let n = modulus();
+    let x = hash_to_group(seed, &n);
+    let mut y = x.clone();
+    for _ in 0..difficulty {
+        y = &y * &y % &n;
+    }
+    let y = normalize(y, &n);
+
+    // π = x^⌊2^T / ℓ⌋, computing the quotient bit by bit by long division
+    let l = challenge_prime(&x, &y, difficulty);
+    let two = BigUint::from(2u8);
+    let mut remainder = BigUint::one();
+    let mut pi = BigUint::one();
+    for _ in 0..difficulty {
+        remainder <<= 1;
+        pi = &pi * &pi % &n;
+        if remainder >= l {
+            remainder -= &l;
+            pi = pi * &x % &n;
+        }
+    }
+    debug_assert_eq!(remainder, two.modpow(&BigUint::from(difficulty), &l));
+
+    VdfProof { output: encode(&y), proof: encode(&normalize(pi, &n)) }
+}
+
+/// Check that `proof` is the VDF of `seed` at `difficulty`
+pub fn verify(seed: &[u8], difficulty: u64, proof: &VdfProof) -> Result<(), &'static str> {
+    if difficulty > MAX_DIFFICULTY {
+        return Err("VDF difficulty too high");
+    }
+    let n = modulus();
+    let x = hash_to_group(seed, &n);
+    let y = decode(&proof.output, &n)?;
+    let pi = decode(&proof.proof, &n)?;
+
+    let l = challenge_prime(&x, &y, difficulty);
+    let r = BigUint::from(2u8).modpow(&BigUint::from(difficulty), &l);
+    let expected = pi.modpow(&l, &n) * x.modpow(&r, &n) % &n;
+    if normalize(expected, &n) != y {
+        return Err("Invalid VDF proof");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate_and_verify() {
+        let proof = evaluate(b"epoch 1", 200);
+        assert_eq!(verify(b"epoch 1", 200, &proof), Ok(()));
+        assert_eq!(verify(b"epoch 1", 201, &proof), Err("Invalid VDF proof"));
+        assert_eq!(verify(b"epoch 2", 200, &proof), Err("Invalid VDF proof"));
+
+        // The same element with its sign flipped is rejected as non-canonical
+        let n = modulus();
+        let flipped = VdfProof {
+            output: encode(&(&n - BigUint::from_bytes_be(&proof.output))),
+            proof: proof.proof.clone(),
+        };
+        assert_eq!(verify(b"epoch 1", 200, &flipped), Err("VDF element not in canonical form"));
+
+        let mut forged = proof.clone();
+        forged.proof[ELEMENT_BYTES - 1] ^= 1;
+        assert!(verify(b"epoch 1", 200, &forged).is_err());
+        assert_ne!(proof.randomness(), evaluate(b"epoch 2", 200).randomness());
+    }
+}
@@ -0,0 +1,264 @@
+//! Epochs and eras shared by consensus, economics and governance.
+//!
+//! An epoch is a fixed run of blocks and an era a fixed run of epochs. At
+//! the first block of each epoch the node runs its boundary duties in a
+//! fixed order: reward distribution, validator set rotation, governance
+//! tallying, storage rent, then the tally checkpoint. The results are
+//! recorded as an `EpochTransition` event. The randomness beacon uses the
+//! same schedule.
+
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use crate::types::hex_serde_vec;
+
+/// Transitions kept for `getEpoch`
+const MAX_HISTORY: usize = 64;
+
+/// Epoch and era lengths; consensus-critical
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EpochSchedule {
+    pub epoch_blocks: u64,
+    pub epochs_per_era: u64,
+}
+
+impl Default for EpochSchedule {
+    fn default() -> Self {
+        Self {
+            epoch_blocks: 100,
+            epochs_per_era: 30,
+        }
+    }
+}
+
+impl EpochSchedule {
+    pub fn epoch_of(&self, height: u64) -> u64 {
+        height / self.epoch_blocks.max(1)
+    }
+
+    pub fn era_of(&self, epoch: u64) -> u64 {
+        epoch / self.epochs_per_era.max(1)
+    }
+
+    /// First block of `epoch`
+    pub fn epoch_start(&self, epoch: u64) -> u64 {
+        epoch.saturating_mul(self.epoch_blocks.max(1))
+    }
+
+    /// Whether `height` opens an epoch; genesis does not count
+    pub fn is_boundary(&self, height: u64) -> bool {
+        height > 0 && height.is_multiple_of(self.epoch_blocks.max(1))
+    }
+}
+
+/// Work done at each epoch boundary, in the order it runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EpochDuty {
+    Rewards,
+    ValidatorRotation,
+    /// Runs before the tally so policies see this epoch's reserve status
+    ProofOfReserve,
+    GovernanceTally,
+    StorageRent,
+    TallyCheckpoint,
+}
+
+impl EpochDuty {
+    pub const ALL: [EpochDuty; 6] = [
+        EpochDuty::Rewards,
+        EpochDuty::ValidatorRotation,
+        EpochDuty::ProofOfReserve,
+        EpochDuty::GovernanceTally,
+        EpochDuty::StorageRent,
+        EpochDuty::TallyCheckpoint,
+    ];
+}
+
+/// How one duty went
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DutyOutcome {
+    pub duty: EpochDuty,
+    pub ok: bool,
+    /// Duty-specific summary, or the error message
+    pub detail: serde_json::Value,
+}
+
+impl DutyOutcome {
+    pub fn new(duty: EpochDuty, result: Result<serde_json::Value, String>) -> Self {
+        match result {
+            Ok(detail) => Self { duty, ok: true, detail },
+            Err(e) => Self { duty, ok: false, detail: serde_json::Value::String(e) },
+        }
+    }
+}
+
+/// Boundary reached but not yet processed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EpochBoundary {
+    pub epoch: u64,
+    pub era: u64,
+    /// First block of the epoch
+    pub height: u64,
+    pub new_era: bool,
+}
+
+/// Validators that joined and left the active set at a boundary
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ValidatorRotation {
+    #[serde(with = "hex_serde_vec")]
+    pub added: Vec<[u8; 32]>,
+    #[serde(with = "hex_serde_vec")]
+    pub removed: Vec<[u8; 32]>,
+    pub active: usize,
+}
+
+/// Emitted once an epoch's boundary duties have run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EpochTransition {
+    pub epoch: u64,
+    pub era: u64,
+    pub height: u64,
+    pub new_era: bool,
+    pub duties: Vec<DutyOutcome>,
+}
+
+/// Epoch Manager
+/// Tracks which epoch the chain is in, reports every boundary crossed as
+/// blocks arrive, and keeps what the boundary duties leave behind: the
+/// active validator set and the state root checkpointed for each epoch.
+#[derive(Debug, Clone)]
+pub struct EpochManager {
+    schedule: EpochSchedule,
+    /// Last epoch reported by `advance`
+    current: u64,
+    validators: BTreeSet<[u8; 32]>,
+    checkpoints: BTreeMap<u64, [u8; 32]>,
+    history: VecDeque<EpochTransition>,
+}
+
+impl EpochManager {
+    pub fn new(schedule: EpochSchedule) -> Self {
+        Self {
+            schedule,
+            current: 0,
+            validators: BTreeSet::new(),
+            checkpoints: BTreeMap::new(),
+            history: VecDeque::new(),
+        }
+    }
+
+    pub fn schedule(&self) -> &EpochSchedule {
+        &self.schedule
+    }
+
+    pub fn current_epoch(&self) -> u64 {
+        self.current
+    }
+
+    /// Boundaries crossed up to `height`, oldest first. Each should be
+    /// passed to `complete` once its duties have run.
+    pub fn advance(&mut self, height: u64) -> Vec<EpochBoundary> {
+        let epoch = self.schedule.epoch_of(height);
+        let boundaries = (self.current + 1..=epoch)
+            .map(|epoch| {
+                let era = self.schedule.era_of(epoch);
+                EpochBoundary {
+                    epoch,
+                    era,
+                    height: self.schedule.epoch_start(epoch),
+                    new_era: era != self.schedule.era_of(epoch - 1),
+                }
+            })
+            .collect();
+        self.current = self.current.max(epoch);
+        boundaries
+    }
+
+    /// Record the duty outcomes for a boundary
+    pub fn complete(&mut self, boundary: EpochBoundary, mut duties: Vec<DutyOutcome>) -> EpochTransition {
+        duties.sort_by_key(|outcome| outcome.duty);
+        let transition = EpochTransition {
+            epoch: boundary.epoch,
+            era: boundary.era,
+            height: boundary.height,
+            new_era: boundary.new_era,
+            duties,
+        };
+        self.history.push_back(transition.clone());
+        while self.history.len() > MAX_HISTORY {
+            self.history.pop_front();
+        }
+        transition
+    }
+
+    pub fn transition(&self, epoch: u64) -> Option<&EpochTransition> {
+        self.history.iter().find(|transition| transition.epoch == epoch)
+    }
+
+    pub fn last_transition(&self) -> Option<&EpochTransition> {
+        self.history.back()
+    }
+
+    pub fn active_validators(&self) -> &BTreeSet<[u8; 32]> {
+        &self.validators
+    }
+
+    /// Replace the active validator set for the new epoch
+    pub fn rotate_validators(&mut self, next: impl IntoIterator<Item = [u8; 32]>) -> ValidatorRotation {
+        let next: BTreeSet<[u8; 32]> = next.into_iter().collect();
+        let rotation = ValidatorRotation {
+            added: next.difference(&self.validators).copied().collect(),
+            removed: self.validators.difference(&next).copied().collect(),
+            active: next.len(),
+        };
+        self.validators = next;
+        rotation
+    }
+
+    /// State root recorded at the start of `epoch`
+    pub fn checkpoint(&self, epoch: u64) -> Option<[u8; 32]> {
+        self.checkpoints.get(&epoch).copied()
+    }
+
+    /// Checkpoints of epochs `from..=to`, oldest first
+    pub fn checkpoints(&self, from: u64, to: u64) -> impl Iterator<Item = (u64, [u8; 32])> + '_ {
+        self.checkpoints.range(from..=to).map(|(epoch, root)| (*epoch, *root))
+    }
+
+    pub fn record_checkpoint(&mut self, epoch: u64, state_root: [u8; 32]) {
+        self.checkpoints.insert(epoch, state_root);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_boundaries_eras_and_rotation() {
+        let mut manager = EpochManager::new(EpochSchedule { epoch_blocks: 10, epochs_per_era: 2 });
+        assert!(manager.advance(9).is_empty());
+        assert!(!manager.schedule().is_boundary(0));
+
+        let boundaries = manager.advance(10);
+        assert_eq!(boundaries, vec![EpochBoundary { epoch: 1, era: 0, height: 10, new_era: false }]);
+        assert!(manager.advance(15).is_empty());
+
+        // Skipped heights still report every boundary, in order
+        let boundaries = manager.advance(41);
+        assert_eq!(boundaries.iter().map(|b| b.epoch).collect::<Vec<_>>(), vec![2, 3, 4]);
+        assert_eq!(boundaries.iter().filter(|b| b.new_era).map(|b| b.era).collect::<Vec<_>>(), vec![1, 2]);
+
+        let transition = manager.complete(boundaries[0], vec![
+            DutyOutcome::new(EpochDuty::StorageRent, Err("billing failed".to_string())),
+            DutyOutcome::new(EpochDuty::Rewards, Ok(serde_json::json!({ "minted": "1.00" }))),
+        ]);
+        assert_eq!(transition.duties[0].duty, EpochDuty::Rewards);
+        assert!(!transition.duties[1].ok);
+        assert_eq!(manager.last_transition().map(|t| t.epoch), Some(2));
+
+        manager.rotate_validators([[1; 32], [2; 32]]);
+        let rotation = manager.rotate_validators([[2; 32], [3; 32]]);
+        assert_eq!(rotation, ValidatorRotation { added: vec![[3; 32]], removed: vec![[1; 32]], active: 2 });
+    }
+}
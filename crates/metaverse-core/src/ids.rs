@@ -0,0 +1,296 @@
+use bech32::{FromBase32, ToBase32, Variant};
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// What a 32-byte ID refers to; each kind has its own string prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IdKind {
+    Node,
+    Chain,
+    Contract,
+    Identity,
+    Data,
+}
+
+impl IdKind {
+    pub const ALL: [IdKind; 5] = [IdKind::Node, IdKind::Chain, IdKind::Contract, IdKind::Identity, IdKind::Data];
+
+    /// Human-readable part of the checksummed encoding
+    pub fn prefix(self) -> &'static str {
+        match self {
+            IdKind::Node => "qnode",
+            IdKind::Chain => "qchain",
+            IdKind::Contract => "qcontract",
+            IdKind::Identity => "qid",
+            IdKind::Data => "qdata",
+        }
+    }
+
+    pub fn from_prefix(prefix: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.prefix() == prefix)
+    }
+}
+
+impl fmt::Display for IdKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            IdKind::Node => "node",
+            IdKind::Chain => "chain",
+            IdKind::Contract => "contract",
+            IdKind::Identity => "identity",
+            IdKind::Data => "data",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for IdKind {
+    type Err = IdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter()
+            .find(|kind| kind.to_string() == s)
+            .ok_or(IdError::UnknownKind(s.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdError {
+    /// Not valid bech32m, or the checksum does not match
+    Malformed,
+    UnknownPrefix(String),
+    UnknownKind(String),
+    WrongKind { expected: IdKind, found: IdKind },
+    BadLength(usize),
+}
+
+impl fmt::Display for IdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IdError::Malformed => write!(f, "ID is malformed or has a bad checksum"),
+            IdError::UnknownPrefix(prefix) => write!(f, "Unknown ID prefix `{}`", prefix),
+            IdError::UnknownKind(kind) => write!(f, "Unknown ID kind `{}`", kind),
+            IdError::WrongKind { expected, found } => {
+                write!(f, "Expected a {} ID but got a {} ID", expected, found)
+            }
+            IdError::BadLength(len) => write!(f, "ID must be 32 bytes, got {}", len),
+        }
+    }
+}
+
+impl std::error::Error for IdError {}
+
+impl From<IdError> for String {
+    fn from(error: IdError) -> Self {
+        error.to_string()
+    }
+}
+
+/// Encode raw ID bytes as bech32m with the kind's prefix, e.g. `qchain1...`
+pub fn encode(kind: IdKind, bytes: &[u8; 32]) -> String {
+    bech32::encode(kind.prefix(), bytes.to_base32(), Variant::Bech32m)
+        .expect("ID prefixes are valid bech32 HRPs")
+}
+
+/// Decode any checksummed ID, returning its kind
+pub fn decode_any(encoded: &str) -> Result<(IdKind, [u8; 32]), IdError> {
+    let (prefix, data, variant) = bech32::decode(encoded).map_err(|_| IdError::Malformed)?;
+    if variant != Variant::Bech32m {
+        return Err(IdError::Malformed);
+    }
+    let kind = IdKind::from_prefix(&prefix).ok_or(IdError::UnknownPrefix(prefix))?;
+    let bytes = Vec::<u8>::from_base32(&data).map_err(|_| IdError::Malformed)?;
+    let len = bytes.len();
+    let bytes = bytes.try_into().map_err(|_| IdError::BadLength(len))?;
+    Ok((kind, bytes))
+}
+
+/// Decode a checksummed ID that must be of `expected` kind
+pub fn decode(expected: IdKind, encoded: &str) -> Result<[u8; 32], IdError> {
+    let (found, bytes) = decode_any(encoded)?;
+    if found != expected {
+        return Err(IdError::WrongKind { expected, found });
+    }
+    Ok(bytes)
+}
+
+/// 32-byte identifier with a type tag
+pub trait TypedId: Copy + From<[u8; 32]> {
+    const KIND: IdKind;
+
+    fn as_bytes(&self) -> &[u8; 32];
+
+    /// Parse the checksummed form, or untyped 0x-hex from older clients
+    fn parse_lenient(s: &str) -> Result<Self, IdError> {
+        match s.strip_prefix("0x") {
+            Some(hex) => {
+                let bytes = hex::decode(hex).map_err(|_| IdError::Malformed)?;
+                let len = bytes.len();
+                let bytes: [u8; 32] = bytes.try_into().map_err(|_| IdError::BadLength(len))?;
+                Ok(Self::from(bytes))
+            }
+            None => decode(Self::KIND, s).map(Self::from),
+        }
+    }
+}
+
+macro_rules! typed_id {
+    ($(#[$meta:meta])* $name:ident => $kind:expr) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+        pub struct $name([u8; 32]);
+
+        impl $name {
+            pub const fn new(bytes: [u8; 32]) -> Self {
+                Self(bytes)
+            }
+
+            pub fn as_bytes(&self) -> &[u8; 32] {
+                &self.0
+            }
+        }
+
+        impl TypedId for $name {
+            const KIND: IdKind = $kind;
+
+            fn as_bytes(&self) -> &[u8; 32] {
+                &self.0
+            }
+        }
+
+        impl From<[u8; 32]> for $name {
+            fn from(bytes: [u8; 32]) -> Self {
+                Self(bytes)
+            }
+        }
+
+        impl From<$name> for [u8; 32] {
+            fn from(id: $name) -> Self {
+                id.0
+            }
+        }
+
+        impl std::ops::Deref for $name {
+            type Target = [u8; 32];
+
+            fn deref(&self) -> &[u8; 32] {
+                &self.0
+            }
+        }
+
+        impl AsRef<[u8]> for $name {
+            fn as_ref(&self) -> &[u8] {
+                &self.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(&encode($kind, &self.0))
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}({})", stringify!($name), self)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = IdError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                decode($kind, s).map(Self)
+            }
+        }
+
+        // Checksummed string in JSON; the plain byte array in binary formats,
+        // so bincode layouts match the `[u8; 32]` fields these replace
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                if serializer.is_human_readable() {
+                    serializer.collect_str(self)
+                } else {
+                    self.0.serialize(serializer)
+                }
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                if deserializer.is_human_readable() {
+                    let encoded = String::deserialize(deserializer)?;
+                    encoded.parse().map_err(D::Error::custom)
+                } else {
+                    <[u8; 32]>::deserialize(deserializer).map(Self)
+                }
+            }
+        }
+    };
+}
+
+typed_id!(
+    /// Network node
+    NodeId => IdKind::Node
+);
+typed_id!(
+    /// Chain, including hosted private chains
+    ChainId => IdKind::Chain
+);
+typed_id!(
+    /// Deployed contract
+    ContractId => IdKind::Contract
+);
+typed_id!(
+    /// ZK identity
+    IdentityId => IdKind::Identity
+);
+typed_id!(
+    /// Stored data item
+    DataId => IdKind::Data
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_and_type_checks() {
+        let chain = ChainId::new([7u8; 32]);
+        let encoded = chain.to_string();
+        assert!(encoded.starts_with("qchain1"));
+        assert_eq!(encoded.parse::<ChainId>().unwrap(), chain);
+        assert_eq!(decode_any(&encoded).unwrap(), (IdKind::Chain, [7u8; 32]));
+
+        // A chain ID is rejected where a contract ID is expected
+        assert_eq!(
+            encoded.parse::<ContractId>().unwrap_err(),
+            IdError::WrongKind { expected: IdKind::Contract, found: IdKind::Chain }
+        );
+
+        // A single-character typo fails the checksum
+        let mut typo = encoded.clone().into_bytes();
+        let last = typo.len() - 1;
+        typo[last] = if typo[last] == b'q' { b'p' } else { b'q' };
+        let typo = String::from_utf8(typo).unwrap();
+        assert_eq!(typo.parse::<ChainId>().unwrap_err(), IdError::Malformed);
+
+        let legacy = format!("0x{}", hex::encode([7u8; 32]));
+        assert_eq!(ChainId::parse_lenient(&legacy).unwrap(), chain);
+        assert_eq!(ChainId::parse_lenient("0x0102").unwrap_err(), IdError::BadLength(2));
+    }
+
+    #[test]
+    fn test_serde_formats() {
+        let id = DataId::new([1u8; 32]);
+        let json = serde_json::to_string(&id).unwrap();
+        assert_eq!(json, format!("\"{}\"", id));
+        assert_eq!(serde_json::from_str::<DataId>(&json).unwrap(), id);
+        assert!(serde_json::from_str::<NodeId>(&json).is_err());
+
+        let binary = bincode::serialize(&id).unwrap();
+        assert_eq!(binary, bincode::serialize(&[1u8; 32]).unwrap());
+        assert_eq!(bincode::deserialize::<DataId>(&binary).unwrap(), id);
+    }
+}
@@ -0,0 +1,15 @@
+//! Types, math, cryptography and consensus rules shared by the node and
+//! its clients.
+//!
+//! Nothing here touches the network, the disk or an async runtime, so a
+//! wallet or light client can depend on it without pulling in the node.
+//! The node re-exports every module under its old path
+//! (`quantum_metaverse::math`, `quantum_metaverse::blockchain::types`, ...).
+
+pub mod clock;
+pub mod crypto;
+pub mod epoch;
+pub mod ids;
+pub mod math;
+pub mod params;
+pub mod types;
@@ -6,6 +6,7 @@ pub mod entropy;
 pub mod physics;
 pub mod ai_decision;
 pub mod flux;
+pub mod noise;
 pub mod quantum_retrogate;
 
 #[cfg(test)]
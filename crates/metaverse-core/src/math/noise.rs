@@ -0,0 +1,222 @@
+//! Deterministic procedural noise.
+//!
+//! Layers generate terrain and content from gradient noise and random walks
+//! seeded by the layer ID and a block hash, so every node derives the same
+//! world without storing it. All arithmetic is integer fixed-point at
+//! `NOISE_SCALE` decimal places: no floats, so results are bit-identical
+//! across platforms and usable from contracts.
+
+use super::precision::PreciseFloat;
+
+/// Decimal places of noise inputs and outputs
+pub const NOISE_SCALE: u8 = 6;
+
+/// Most octaves `fractal` sums
+pub const MAX_OCTAVES: u8 = 8;
+
+/// 1.0 at `NOISE_SCALE`
+pub const ONE: i128 = 1_000_000;
+
+/// Gradient directions at lattice points
+const GRADIENTS: [(i128, i128); 8] = [(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (-1, 1), (1, -1), (-1, -1)];
+
+/// Noise Seed
+/// Per-layer, per-block seed. The same layer and block hash always give
+/// the same noise field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoiseSeed([u8; 32]);
+
+impl NoiseSeed {
+    pub fn new(layer_id: u32, block_hash: &[u8; 32]) -> Self {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(b"qmv.noise");
+        hasher.update(&layer_id.to_le_bytes());
+        hasher.update(block_hash);
+        Self(hasher.finalize().into())
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Random 64-bit value for an integer lattice point
+    fn lattice(&self, x: i128, y: i128) -> u64 {
+        let mut h = self.word(0) ^ (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        h = splitmix64(h) ^ self.word(1) ^ (y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+        splitmix64(h ^ self.word(2))
+    }
+
+    fn word(&self, index: usize) -> u64 {
+        let mut word = [0u8; 8];
+        word.copy_from_slice(&self.0[index * 8..index * 8 + 8]);
+        u64::from_le_bytes(word)
+    }
+}
+
+fn splitmix64(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// `value` as an integer at `NOISE_SCALE`, rounding toward negative infinity
+pub fn to_fixed(value: &PreciseFloat) -> i128 {
+    let scale = value.scale as u32;
+    let target = NOISE_SCALE as u32;
+    if scale >= target {
+        value.value.div_euclid(10_i128.pow(scale - target))
+    } else {
+        value.value.saturating_mul(10_i128.pow(target - scale))
+    }
+}
+
+/// A `NOISE_SCALE` integer as a `PreciseFloat`
+pub fn from_fixed(value: i128) -> PreciseFloat {
+    PreciseFloat { value, scale: NOISE_SCALE }
+}
+
+/// Smoothstep `6t^5 - 15t^4 + 10t^3` for `t` in `[0, ONE]`
+fn fade(t: i128) -> i128 {
+    let t3 = t * t / ONE * t / ONE;
+    let inner = t * (6 * t - 15 * ONE) / ONE + 10 * ONE;
+    t3 * inner / ONE
+}
+
+fn lerp(a: i128, b: i128, t: i128) -> i128 {
+    a + (b - a) * t / ONE
+}
+
+/// 2D gradient (Perlin) noise in fixed-point, roughly in `[-ONE, ONE]`
+fn perlin_fixed(seed: &NoiseSeed, x: i128, y: i128) -> i128 {
+    let (x0, y0) = (x.div_euclid(ONE), y.div_euclid(ONE));
+    let (dx, dy) = (x.rem_euclid(ONE), y.rem_euclid(ONE));
+
+    let corner = |cx: i128, cy: i128, ox: i128, oy: i128| {
+        let (gx, gy) = GRADIENTS[(seed.lattice(cx, cy) % GRADIENTS.len() as u64) as usize];
+        gx * ox + gy * oy
+    };
+    let n00 = corner(x0, y0, dx, dy);
+    let n10 = corner(x0 + 1, y0, dx - ONE, dy);
+    let n01 = corner(x0, y0 + 1, dx, dy - ONE);
+    let n11 = corner(x0 + 1, y0 + 1, dx - ONE, dy - ONE);
+
+    let (u, v) = (fade(dx), fade(dy));
+    lerp(lerp(n00, n10, u), lerp(n01, n11, u), v).clamp(-ONE, ONE)
+}
+
+/// Gradient noise at `(x, y)`, in `[-1, 1]`. Lattice points are one unit
+/// apart; the value is 0 on every lattice point.
+pub fn perlin(seed: &NoiseSeed, x: &PreciseFloat, y: &PreciseFloat) -> PreciseFloat {
+    from_fixed(perlin_fixed(seed, to_fixed(x), to_fixed(y)))
+}
+
+/// Fractal noise: `octaves` layers of `perlin`, each at twice the frequency
+/// and half the amplitude of the last, normalized to `[-1, 1]`
+pub fn fractal(seed: &NoiseSeed, x: &PreciseFloat, y: &PreciseFloat, octaves: u8) -> PreciseFloat {
+    let (x, y) = (to_fixed(x), to_fixed(y));
+    let mut total = 0;
+    let mut norm = 0;
+    for octave in 0..octaves.clamp(1, MAX_OCTAVES) as u32 {
+        let frequency = 1_i128 << octave;
+        let amplitude = ONE >> octave;
+        total += perlin_fixed(seed, x.saturating_mul(frequency), y.saturating_mul(frequency)) * amplitude / ONE;
+        norm += amplitude;
+    }
+    from_fixed(total * ONE / norm)
+}
+
+/// Random Walk
+/// Seeded one-dimensional walk; each step moves by a uniform amount in
+/// `[-step, step]`. Iterating yields the position after each step.
+#[derive(Debug, Clone)]
+pub struct RandomWalk {
+    state: u64,
+    position: i128,
+    step: i128,
+}
+
+impl RandomWalk {
+    pub fn new(seed: &NoiseSeed, start: &PreciseFloat, step: &PreciseFloat) -> Self {
+        Self { state: seed.word(3), position: to_fixed(start), step: to_fixed(step).abs() }
+    }
+
+    /// Current position
+    pub fn current(&self) -> PreciseFloat {
+        from_fixed(self.position)
+    }
+}
+
+impl Iterator for RandomWalk {
+    type Item = PreciseFloat;
+
+    fn next(&mut self) -> Option<PreciseFloat> {
+        self.state = splitmix64(self.state);
+        let span = (2 * self.step + 1) as u128;
+        let offset = (self.state as u128 % span) as i128 - self.step;
+        self.position = self.position.saturating_add(offset);
+        Some(self.current())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixed(value: i128) -> PreciseFloat {
+        from_fixed(value)
+    }
+
+    #[test]
+    fn test_noise_is_deterministic_and_bounded() {
+        let seed = NoiseSeed::new(7, &[1u8; 32]);
+        let other = NoiseSeed::new(8, &[1u8; 32]);
+        let mut differs = false;
+        for i in 0..200 {
+            let (x, y) = (fixed(i * 37_123), fixed(i * 91_777 - 3_000_000));
+            let value = perlin(&seed, &x, &y);
+            assert_eq!(value, perlin(&NoiseSeed::new(7, &[1u8; 32]), &x, &y));
+            assert!((-ONE..=ONE).contains(&value.value));
+            differs |= value != perlin(&other, &x, &y);
+
+            let layered = fractal(&seed, &x, &y, 4);
+            assert!((-ONE..=ONE).contains(&layered.value));
+        }
+        assert!(differs);
+
+        // Zero on lattice points, including negative ones
+        assert_eq!(perlin(&seed, &fixed(-2 * ONE), &fixed(5 * ONE)).value, 0);
+        // Inputs at other scales are read at NOISE_SCALE
+        assert_eq!(perlin(&seed, &PreciseFloat { value: 15, scale: 1 }, &fixed(0)), perlin(&seed, &fixed(1_500_000), &fixed(0)));
+    }
+
+    #[test]
+    fn test_noise_is_continuous() {
+        let seed = NoiseSeed::new(1, &[9u8; 32]);
+        let mut previous = perlin(&seed, &fixed(0), &fixed(250_000)).value;
+        for i in 1..=4_000 {
+            let value = perlin(&seed, &fixed(i * 1_000), &fixed(250_000)).value;
+            assert!((value - previous).abs() < 10_000, "jump at step {}", i);
+            previous = value;
+        }
+    }
+
+    #[test]
+    fn test_random_walk_stays_within_step() {
+        let seed = NoiseSeed::new(3, &[2u8; 32]);
+        let walk: Vec<i128> = RandomWalk::new(&seed, &fixed(ONE), &fixed(ONE / 2))
+            .take(100)
+            .map(|position| position.value)
+            .collect();
+        let again: Vec<i128> = RandomWalk::new(&seed, &fixed(ONE), &fixed(ONE / 2))
+            .take(100)
+            .map(|position| position.value)
+            .collect();
+        assert_eq!(walk, again);
+        let mut last = ONE;
+        for position in walk {
+            assert!((position - last).abs() <= ONE / 2);
+            last = position;
+        }
+    }
+}
@@ -0,0 +1,235 @@
+//! Protocol parameters registry.
+//!
+//! Thresholds and consensus minimums that used to be hard-coded in each
+//! module live here under typed keys with their defaults. Governance changes a
+//! parameter by scheduling a new value at a future activation height, so
+//! every validator switches at the same block. Past values stay on record and
+//! `get` answers for any height.
+
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use num_traits::ToPrimitive;
+use crate::math::precision::PreciseFloat;
+
+/// Scale parameter values are stored at
+const SCALE: u8 = 2;
+
+/// Protocol parameters under registry control
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParamKey {
+    /// Score an identity proof must reach to verify
+    IdentityVerificationThreshold,
+    /// Trust required of governance validators
+    GovernanceTrustThreshold,
+    /// Smallest stake accepted from a validator, in tokens
+    MinimumStake,
+    /// Annual validator reward, in percent of stake
+    ValidatorRewardRate,
+    /// Size of the active validator set chosen at each epoch
+    MaxActiveValidators,
+    /// Stake burned for signing two blocks at one height, in percent
+    SlashDoubleSign,
+    /// Stake burned for missing too many blocks, in percent
+    SlashDowntime,
+    /// Stake burned for proposing an invalid block, in percent
+    SlashInvalidBlock,
+}
+
+/// What values a parameter accepts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamKind {
+    /// Between 0 and 1
+    Ratio,
+    /// Between 0 and 100
+    Percent,
+    /// Non-negative token amount
+    Amount,
+    /// Positive whole number
+    Count,
+}
+
+impl ParamKey {
+    pub const ALL: [ParamKey; 8] = [
+        ParamKey::IdentityVerificationThreshold,
+        ParamKey::GovernanceTrustThreshold,
+        ParamKey::MinimumStake,
+        ParamKey::ValidatorRewardRate,
+        ParamKey::MaxActiveValidators,
+        ParamKey::SlashDoubleSign,
+        ParamKey::SlashDowntime,
+        ParamKey::SlashInvalidBlock,
+    ];
+
+    /// Name used by governance actions and RPC
+    pub fn name(&self) -> &'static str {
+        match self {
+            ParamKey::IdentityVerificationThreshold => "identity.verification_threshold",
+            ParamKey::GovernanceTrustThreshold => "governance.trust_threshold",
+            ParamKey::MinimumStake => "economics.minimum_stake",
+            ParamKey::ValidatorRewardRate => "economics.validator_reward_rate",
+            ParamKey::MaxActiveValidators => "consensus.max_active_validators",
+            ParamKey::SlashDoubleSign => "slashing.double_sign",
+            ParamKey::SlashDowntime => "slashing.downtime",
+            ParamKey::SlashInvalidBlock => "slashing.invalid_block",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|key| key.name() == name)
+    }
+
+    pub fn kind(&self) -> ParamKind {
+        match self {
+            ParamKey::IdentityVerificationThreshold | ParamKey::GovernanceTrustThreshold => ParamKind::Ratio,
+            ParamKey::MinimumStake => ParamKind::Amount,
+            ParamKey::ValidatorRewardRate
+            | ParamKey::SlashDoubleSign
+            | ParamKey::SlashDowntime
+            | ParamKey::SlashInvalidBlock => ParamKind::Percent,
+            ParamKey::MaxActiveValidators => ParamKind::Count,
+        }
+    }
+
+    pub fn default_value(&self) -> PreciseFloat {
+        match self {
+            ParamKey::IdentityVerificationThreshold => PreciseFloat::new(95, SCALE), // 0.95
+            ParamKey::GovernanceTrustThreshold => PreciseFloat::new(90, SCALE), // 0.90
+            ParamKey::MinimumStake => PreciseFloat::new(100000, SCALE), // 1000.00 tokens
+            ParamKey::ValidatorRewardRate => PreciseFloat::new(500, SCALE), // 5.00% annual
+            ParamKey::MaxActiveValidators => PreciseFloat::new(10000, SCALE), // 100 validators
+            ParamKey::SlashDoubleSign => PreciseFloat::new(500, SCALE), // 5.00% of stake
+            ParamKey::SlashDowntime => PreciseFloat::new(10, SCALE), // 0.10%
+            ParamKey::SlashInvalidBlock => PreciseFloat::new(100, SCALE), // 1.00%
+        }
+    }
+
+    /// Check `value` and bring it to the registry's scale
+    pub fn normalize(&self, value: &PreciseFloat) -> Result<PreciseFloat, &'static str> {
+        let v = value.to_f64().ok_or("Parameter value out of range")?;
+        let in_range = match self.kind() {
+            ParamKind::Ratio => (0.0..=1.0).contains(&v),
+            ParamKind::Percent => (0.0..=100.0).contains(&v),
+            ParamKind::Amount => v >= 0.0,
+            ParamKind::Count => v >= 1.0 && v.fract() == 0.0 && v <= u32::MAX as f64,
+        };
+        if !in_range {
+            return Err("Parameter value out of range");
+        }
+        Ok(PreciseFloat::new((v * 10f64.powi(SCALE as i32)).round() as i128, SCALE))
+    }
+}
+
+/// A value taking effect at `activation_height`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParamUpdate {
+    pub key: ParamKey,
+    pub value: PreciseFloat,
+    pub activation_height: u64,
+}
+
+/// Parameters Registry
+/// Keeps every scheduled value of each protocol parameter by activation
+/// height. Parameters nobody has changed read as their defaults.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ParamsRegistry {
+    updates: BTreeMap<ParamKey, BTreeMap<u64, ParamUpdate>>,
+}
+
+impl ParamsRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Value of `key` in effect at `height`
+    pub fn get(&self, key: ParamKey, height: u64) -> PreciseFloat {
+        self.updates.get(&key)
+            .and_then(|versions| versions.range(..=height).next_back())
+            .map(|(_, update)| update.value.clone())
+            .unwrap_or_else(|| key.default_value())
+    }
+
+    /// Whole-number value of a count parameter at `height`
+    pub fn get_count(&self, key: ParamKey, height: u64) -> u64 {
+        self.get(key, height).to_f64().unwrap_or(0.0) as u64
+    }
+
+    /// Every parameter's value at `height`
+    pub fn values_at(&self, height: u64) -> BTreeMap<&'static str, PreciseFloat> {
+        ParamKey::ALL.iter().map(|key| (key.name(), self.get(*key, height))).collect()
+    }
+
+    /// Registry version at `height`: the number of updates active by then.
+    /// It changes exactly when some parameter takes a new value.
+    pub fn version_at(&self, height: u64) -> u64 {
+        self.updates.values()
+            .map(|versions| versions.range(..=height).count() as u64)
+            .sum()
+    }
+
+    /// Schedule `value` for `key` from `activation_height`, which must lie
+    /// after `current_height`. A later schedule for the same height replaces
+    /// the earlier one.
+    pub fn schedule(
+        &mut self,
+        key: ParamKey,
+        value: &PreciseFloat,
+        activation_height: u64,
+        current_height: u64,
+    ) -> Result<&ParamUpdate, &'static str> {
+        if activation_height <= current_height {
+            return Err("Activation height must be in the future");
+        }
+        let value = key.normalize(value)?;
+        let update = ParamUpdate { key, value, activation_height };
+        let versions = self.updates.entry(key).or_default();
+        versions.insert(activation_height, update);
+        Ok(&versions[&activation_height])
+    }
+
+    /// Updates not yet active at `height`, by activation height
+    pub fn pending(&self, height: u64) -> Vec<&ParamUpdate> {
+        let mut pending: Vec<&ParamUpdate> = self.updates.values()
+            .flat_map(|versions| versions.range(height + 1..).map(|(_, update)| update))
+            .collect();
+        pending.sort_by_key(|update| update.activation_height);
+        pending
+    }
+
+    /// Every value `key` has been scheduled to, oldest activation first
+    pub fn history(&self, key: ParamKey) -> Vec<&ParamUpdate> {
+        self.updates.get(&key).map(|versions| versions.values().collect()).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_versioned_activation() {
+        let mut registry = ParamsRegistry::new();
+        let key = ParamKey::IdentityVerificationThreshold;
+        assert_eq!(registry.get(key, 0), PreciseFloat::new(95, 2));
+        assert_eq!(ParamKey::from_name("identity.verification_threshold"), Some(key));
+
+        assert!(registry.schedule(key, &PreciseFloat::new(90, 2), 10, 10).is_err());
+        assert!(registry.schedule(key, &PreciseFloat::new(150, 2), 20, 10).is_err());
+        assert!(registry.schedule(ParamKey::MaxActiveValidators, &PreciseFloat::new(15, 1), 20, 10).is_err());
+        assert_eq!(key.normalize(&PreciseFloat::new(29, 2)), Ok(PreciseFloat::new(29, 2)));
+
+        // Scale is normalized so existing `.value` comparisons keep working
+        registry.schedule(key, &PreciseFloat::new(900, 3), 20, 10).unwrap();
+        registry.schedule(ParamKey::MaxActiveValidators, &PreciseFloat::new(5000, 2), 30, 10).unwrap();
+        assert_eq!(registry.get(key, 19), PreciseFloat::new(95, 2));
+        assert_eq!(registry.get(key, 20), PreciseFloat::new(90, 2));
+        assert_eq!(registry.get_count(ParamKey::MaxActiveValidators, 29), 100);
+        assert_eq!(registry.get_count(ParamKey::MaxActiveValidators, 30), 50);
+
+        assert_eq!(registry.version_at(19), 0);
+        assert_eq!(registry.version_at(20), 1);
+        assert_eq!(registry.version_at(30), 2);
+        assert_eq!(registry.pending(20).len(), 1);
+        assert_eq!(registry.history(key).len(), 1);
+    }
+}
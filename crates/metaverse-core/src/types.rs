@@ -0,0 +1,109 @@
+use std::borrow::Borrow;
+use std::hash::Hash;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct QuantumNodeID(#[serde(with = "hex_serde")] [u8; 32]);
+
+impl QuantumNodeID {
+    pub fn new(id: [u8; 32]) -> Self {
+        Self(id)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl Borrow<[u8; 32]> for QuantumNodeID {
+    fn borrow(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl From<[u8; 32]> for QuantumNodeID {
+    fn from(id: [u8; 32]) -> Self {
+        Self(id)
+    }
+}
+
+/// Account address: the account's ed25519 public key, or a derived contract address
+pub type Address = [u8; 32];
+
+/// Serde helpers that encode byte fields as 0x-prefixed hex in JSON and as raw bytes in binary formats
+pub mod hex_serde {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S, T>(bytes: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: AsRef<[u8]>,
+    {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&format!("0x{}", hex::encode(bytes)))
+        } else {
+            serializer.serialize_bytes(bytes.as_ref())
+        }
+    }
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: TryFrom<Vec<u8>>,
+    {
+        let bytes = if deserializer.is_human_readable() {
+            let encoded = String::deserialize(deserializer)?;
+            hex::decode(encoded.trim_start_matches("0x")).map_err(D::Error::custom)?
+        } else {
+            serde_bytes::ByteBuf::deserialize(deserializer)?.into_vec()
+        };
+        T::try_from(bytes).map_err(|_| D::Error::custom("invalid byte length"))
+    }
+}
+
+/// Hex encoding for optional byte fields
+pub mod hex_serde_option {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct Wrapper<T: AsRef<[u8]> + TryFrom<Vec<u8>>>(#[serde(with = "super::hex_serde")] T);
+
+    pub fn serialize<S, T>(bytes: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: AsRef<[u8]> + TryFrom<Vec<u8>> + Clone,
+    {
+        bytes.clone().map(Wrapper).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: AsRef<[u8]> + TryFrom<Vec<u8>>,
+    {
+        Ok(Option::<Wrapper<T>>::deserialize(deserializer)?.map(|w| w.0))
+    }
+}
+
+/// Hex encoding for lists of byte fields
+pub mod hex_serde_vec {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct Wrapper<T: AsRef<[u8]> + TryFrom<Vec<u8>>>(#[serde(with = "super::hex_serde")] T);
+
+    pub fn serialize<S, T>(items: &[T], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: AsRef<[u8]> + TryFrom<Vec<u8>> + Clone,
+    {
+        items.iter().cloned().map(Wrapper).collect::<Vec<_>>().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: AsRef<[u8]> + TryFrom<Vec<u8>>,
+    {
+        Ok(Vec::<Wrapper<T>>::deserialize(deserializer)?.into_iter().map(|w| w.0).collect())
+    }
+}
@@ -0,0 +1,256 @@
+//! Structured blocks through the validation path a syncing node takes.
+//!
+//! Every input becomes a block of signed transactions on a fixed genesis,
+//! optionally tampered with, and must get the same verdict twice. With
+//! `METAVERSE_PINNED_NODE` set to the `metaverse-node` binary of an earlier
+//! release, each block also goes to that binary's `validate-blocks`, and a
+//! block the two versions disagree on is a crash.
+//!
+//! ```text
+//! cargo +nightly fuzz run block_validation
+//! METAVERSE_PINNED_NODE=/opt/metaverse-node-0.1.0 cargo +nightly fuzz run block_validation
+//! ```
+
+#![no_main]
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use arbitrary::Arbitrary;
+use ed25519_dalek::SigningKey;
+use libfuzzer_sys::fuzz_target;
+use quantum_metaverse::blockchain::bloom::Bloom;
+use quantum_metaverse::blockchain::core::Blockchain;
+use quantum_metaverse::blockchain::execution::{Executor, Instruction, Operand};
+use quantum_metaverse::blockchain::features::FeatureSet;
+use quantum_metaverse::blockchain::state::WorldState;
+use quantum_metaverse::blockchain::transaction::{Transaction, TransactionAction};
+use quantum_metaverse::blockchain::types::Address;
+use quantum_metaverse::blockchain::verdict::{self, Balance, ValidationCase, Verdict};
+use quantum_metaverse::clock::MockClock;
+use quantum_metaverse::math::precision::PreciseFloat;
+
+/// Funded accounts transactions are sent from
+const ACCOUNTS: usize = 4;
+const GENESIS_SECS: u64 = 1_700_000_000;
+/// Token units per unit of an input balance, so most fees can be paid
+const BALANCE_UNIT: u128 = 1_000;
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    balances: [u32; ACCOUNTS],
+    /// Feature bits the block signals
+    signals: u32,
+    transactions: Vec<FuzzTransaction>,
+    tampering: Vec<Tamper>,
+}
+
+#[derive(Debug, Arbitrary)]
+struct FuzzTransaction {
+    sender: u8,
+    /// Added to the sender's next nonce
+    nonce_skew: Option<u8>,
+    action: FuzzAction,
+    gas_limit: u32,
+    gas_price: u8,
+    signature: Signature,
+}
+
+#[derive(Debug, Arbitrary)]
+enum FuzzAction {
+    Transfer { to: u8, amount: u32 },
+    Deploy { code: Vec<FuzzInstruction> },
+    /// Call an account or a contract deployed earlier in the block
+    Call { target: u8, input: Vec<u8>, value: u16 },
+}
+
+#[derive(Debug, Arbitrary)]
+enum FuzzInstruction {
+    Store { key: u8, from_input: bool },
+    Delete { key: u8 },
+    Emit { topic: u8 },
+    Return { key: u8 },
+    Revert,
+}
+
+#[derive(Debug, Arbitrary)]
+enum Signature {
+    Valid,
+    /// Signed by another funded account's key
+    WrongKey(u8),
+    /// One byte of a valid signature flipped
+    Corrupt(u8),
+    Missing,
+}
+
+/// Changes to the block after it was produced. Field changes leave the hash
+/// stale until a `Rehash`; bit flips apply to the wire encoding last.
+#[derive(Debug, Arbitrary)]
+enum Tamper {
+    Index(u64),
+    Timestamp(u64),
+    PreviousHash(u8),
+    FrcProof(i64),
+    QuantumResistance(i64),
+    Data(Vec<u8>),
+    Beacon(Option<[u8; 32]>),
+    Rehash,
+    FlipBit { offset: u16, bit: u8 },
+}
+
+fn instruction(fuzzed: &FuzzInstruction) -> Instruction {
+    match fuzzed {
+        FuzzInstruction::Store { key, from_input } => Instruction::Store {
+            key: vec![*key],
+            value: if *from_input { Operand::Input } else { Operand::Caller },
+        },
+        FuzzInstruction::Delete { key } => Instruction::Delete { key: vec![*key] },
+        FuzzInstruction::Emit { topic } => Instruction::Emit { topic: format!("Topic{}", topic), data: Operand::Value },
+        FuzzInstruction::Return { key } => Instruction::Return(Operand::Load(vec![*key])),
+        FuzzInstruction::Revert => Instruction::Revert("fuzz".to_string()),
+    }
+}
+
+fn build_case(input: &Input) -> ValidationCase {
+    let keys: Vec<SigningKey> = (1..=ACCOUNTS as u8).map(|seed| SigningKey::from_bytes(&[seed; 32])).collect();
+    let accounts: Vec<Address> = keys.iter().map(|key| key.verifying_key().to_bytes()).collect();
+    let balances: Vec<Balance> = accounts.iter().zip(input.balances)
+        .map(|(address, balance)| Balance { address: *address, amount: balance as u128 * BALANCE_UNIT })
+        .collect();
+
+    let mut nonces = [0u64; ACCOUNTS];
+    let mut targets = accounts.clone();
+    let mut transactions = Vec::with_capacity(input.transactions.len());
+    for fuzzed in &input.transactions {
+        let sender = fuzzed.sender as usize % ACCOUNTS;
+        let nonce = nonces[sender] + fuzzed.nonce_skew.unwrap_or(0) as u64;
+        let action = match &fuzzed.action {
+            FuzzAction::Transfer { to, amount } => TransactionAction::Transfer {
+                to: targets[*to as usize % targets.len()],
+                amount: *amount as u128,
+            },
+            FuzzAction::Deploy { code } => {
+                targets.push(Executor::contract_address(&accounts[sender], nonce));
+                TransactionAction::Deploy { code: code.iter().map(instruction).collect() }
+            }
+            FuzzAction::Call { target, input, value } => TransactionAction::Call {
+                contract: targets[*target as usize % targets.len()],
+                input: input.clone(),
+                value: *value as u128,
+            },
+        };
+        let mut tx = Transaction::new(accounts[sender], nonce, action, fuzzed.gas_limit as u64, fuzzed.gas_price as u128);
+        match fuzzed.signature {
+            Signature::Valid => tx.sign(&keys[sender]),
+            Signature::WrongKey(other) => {
+                tx.sign(&keys[other as usize % ACCOUNTS]);
+                tx.from = accounts[sender];
+            }
+            Signature::Corrupt(position) => {
+                tx.sign(&keys[sender]);
+                let position = position as usize % tx.signature.len();
+                tx.signature[position] ^= 0x01;
+            }
+            Signature::Missing => {}
+        }
+        nonces[sender] = nonce + 1;
+        transactions.push(tx);
+    }
+
+    // Produce the block the way a sealer would, with the bloom of its
+    // receipts when the transactions execute at all
+    let clock = MockClock::new(GENESIS_SECS);
+    let mut chain = Blockchain::with_clock(2, clock.clone());
+    clock.advance(Duration::from_secs(5));
+    let genesis_state = WorldState::with_balances(
+        &balances.iter().map(|balance| (balance.address, balance.amount)).collect::<Vec<_>>(),
+    );
+    let bloom = Executor::apply_block(&mut genesis_state.clone(), &transactions)
+        .map(|receipts| Bloom::for_block(&transactions, &receipts))
+        .unwrap_or_default();
+    chain.signal(FeatureSet::from_bits(input.signals));
+    let data = bincode::serialize(&transactions).expect("transactions encode");
+    chain.add_block_with_bloom(data, &bloom).expect("block is produced");
+
+    let mut block = chain.block(1).expect("block 1").clone();
+    let mut flips = Vec::new();
+    for tamper in &input.tampering {
+        match tamper {
+            Tamper::Index(index) => block.index = *index,
+            Tamper::Timestamp(nanos) => block.timestamp = *nanos as u128,
+            Tamper::PreviousHash(byte) => block.previous_hash[*byte as usize % 32] ^= 0x01,
+            Tamper::FrcProof(value) => block.frc_proof = PreciseFloat::new(*value as i128, block.frc_proof.scale),
+            Tamper::QuantumResistance(value) => {
+                block.quantum_resistance = PreciseFloat::new(*value as i128, block.quantum_resistance.scale)
+            }
+            Tamper::Data(data) => block.data = data.clone(),
+            Tamper::Beacon(beacon) => block.beacon = *beacon,
+            Tamper::Rehash => block = block.clone().with_timestamp(block.timestamp),
+            Tamper::FlipBit { offset, bit } => flips.push((*offset as usize, bit % 8)),
+        }
+    }
+    let mut encoded = block.to_bytes();
+    for (offset, bit) in flips {
+        let offset = offset % encoded.len();
+        encoded[offset] ^= 1 << bit;
+    }
+
+    ValidationCase {
+        balances,
+        genesis: chain.block(0).expect("genesis").to_bytes(),
+        block: encoded,
+    }
+}
+
+/// `validate-blocks` process of the pinned binary
+struct Pinned {
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Pinned {
+    fn spawn(binary: &str) -> Self {
+        let mut child = Command::new(binary)
+            .arg("validate-blocks")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap_or_else(|e| panic!("Failed to start pinned node {}: {}", binary, e));
+        let stdin = child.stdin.take().expect("piped stdin");
+        let stdout = BufReader::new(child.stdout.take().expect("piped stdout"));
+        Self { _child: child, stdin, stdout }
+    }
+
+    fn verdict(&mut self, case: &ValidationCase) -> Verdict {
+        let line = serde_json::to_string(case).expect("case encodes");
+        writeln!(self.stdin, "{}", line).expect("pinned node exited");
+        self.stdin.flush().expect("pinned node exited");
+        let mut reply = String::new();
+        self.stdout.read_line(&mut reply).expect("pinned node exited");
+        serde_json::from_str(&reply).unwrap_or_else(|e| panic!("Pinned node replied {:?}: {}", reply, e))
+    }
+}
+
+fn pinned() -> Option<&'static Mutex<Pinned>> {
+    static PINNED: OnceLock<Option<Mutex<Pinned>>> = OnceLock::new();
+    PINNED.get_or_init(|| std::env::var("METAVERSE_PINNED_NODE").ok().map(|binary| Mutex::new(Pinned::spawn(&binary))))
+        .as_ref()
+}
+
+fuzz_target!(|input: Input| {
+    let case = build_case(&input);
+    let ours = verdict::validate(&case);
+    assert_eq!(verdict::validate(&case), ours, "validation is not deterministic");
+    if let Some(pinned) = pinned() {
+        let theirs = pinned.lock().unwrap().verdict(&case);
+        assert!(
+            !ours.diverges(&theirs),
+            "verdicts diverge\n  this build: {:?}\n  pinned:     {:?}\n  case: {}",
+            ours,
+            theirs,
+            serde_json::to_string(&case).unwrap(),
+        );
+    }
+});
@@ -0,0 +1,152 @@
+//! Programmable account validation.
+//!
+//! An account can install a validation contract that decides which other
+//! keys may act for it. A transaction carrying `witnesses` instead of the
+//! account's own signature is valid only if every witness signed it and the
+//! contract runs to the end without reverting. The contract sees the witness
+//! keys through `Operand::Signer`, the block height through
+//! `Operand::Height`, the recipient or called contract through
+//! `Operand::Target` and the value moved through `Operand::Value`. It can
+//! keep state, such as the amount spent in a period, in its own storage.
+//! With `Instruction::Require`, that is enough for custom multisigs,
+//! session keys scoped to some targets until a height, and spending limits.
+//!
+//! Validation runs before the fee is escrowed, under the account's own gas
+//! limit of at most `MAX_VALIDATION_GAS`. A rejected transaction is invalid,
+//! like one with a bad signature. The account's key keeps working as before,
+//! so it can always replace or remove the contract.
+
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use crate::blockchain::execution::Instruction;
+use crate::blockchain::transaction::TransactionAction;
+use crate::blockchain::types::{hex_serde, Address};
+
+/// Most gas a validation contract may be given
+pub const MAX_VALIDATION_GAS: u64 = 200_000;
+
+/// Most witnesses on one transaction
+pub const MAX_WITNESSES: usize = 8;
+
+/// Most instructions in a validation contract
+pub const MAX_VALIDATION_CODE: usize = 256;
+
+/// Address a validation contract's code and storage live at. It cannot
+/// collide with a deployed contract, and calls to it are refused.
+pub fn validation_address(account: &Address) -> Address {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"qmv:validation");
+    hasher.update(account);
+    hasher.finalize().into()
+}
+
+/// Recipient or called contract, as `Operand::Target` shows it to a
+/// validation contract. Other actions have no target, so scoped keys
+/// cannot send them.
+pub fn target(action: &TransactionAction) -> Option<Address> {
+    match action {
+        TransactionAction::Transfer { to, .. } => Some(*to),
+        TransactionAction::Call { contract, .. } => Some(*contract),
+        _ => None,
+    }
+}
+
+/// Signature by a key acting for the sending account
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Witness {
+    #[serde(with = "hex_serde")]
+    pub key: Address,
+    #[serde(with = "hex_serde")]
+    pub signature: Vec<u8>,
+}
+
+/// Storage slot set when installing or updating a validation contract
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StorageEntry {
+    #[serde(with = "hex_serde")]
+    pub key: Vec<u8>,
+    /// Empty deletes the slot
+    #[serde(with = "hex_serde")]
+    pub value: Vec<u8>,
+}
+
+/// Account's installed validation contract
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccountValidation {
+    #[serde(with = "hex_serde")]
+    pub contract: Address,
+    /// Gas the contract may use per transaction
+    pub gas_limit: u64,
+}
+
+/// Validation contract operations, sent by the account itself
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidationAction {
+    /// Install a contract, replacing any previous one and its storage
+    Install { code: Vec<Instruction>, storage: Vec<StorageEntry>, gas_limit: u64 },
+    /// Set or delete slots of the installed contract's storage, such as
+    /// session keys
+    UpdateStorage { entries: Vec<StorageEntry> },
+    /// Go back to the account's own signature only
+    Remove,
+}
+
+impl ValidationAction {
+    /// Instructions deployed, for intrinsic gas
+    pub fn instructions(&self) -> u64 {
+        match self {
+            ValidationAction::Install { code, .. } => code.len() as u64,
+            _ => 0,
+        }
+    }
+
+    /// Storage slots written, for intrinsic gas
+    pub fn writes(&self) -> u64 {
+        match self {
+            ValidationAction::Install { storage: entries, .. } | ValidationAction::UpdateStorage { entries } => {
+                entries.len() as u64
+            }
+            ValidationAction::Remove => 0,
+        }
+    }
+}
+
+/// Checks on an installed contract that do not depend on state
+pub fn check_install(code: &[Instruction], gas_limit: u64) -> Result<(), &'static str> {
+    if code.is_empty() || code.len() > MAX_VALIDATION_CODE {
+        return Err("Validation code must have 1 to 256 instructions");
+    }
+    if gas_limit == 0 || gas_limit > MAX_VALIDATION_GAS {
+        return Err("Validation gas limit must be between 1 and 200,000");
+    }
+    Ok(())
+}
+
+/// Installed validation contracts by account
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AccountValidations {
+    by_account: BTreeMap<Address, AccountValidation>,
+}
+
+impl AccountValidations {
+    pub fn get(&self, account: &Address) -> Option<&AccountValidation> {
+        self.by_account.get(account)
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_account.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_account.is_empty()
+    }
+
+    /// Set an account's entry to a journaled value; `None` removes it
+    pub(crate) fn restore(&mut self, account: Address, validation: Option<AccountValidation>) {
+        match validation {
+            Some(validation) => { self.by_account.insert(account, validation); }
+            None => { self.by_account.remove(&account); }
+        }
+    }
+}
@@ -0,0 +1,292 @@
+//! Metadata standard for minted assets.
+//!
+//! An asset may carry an `AssetMetadata` record describing what it is: a
+//! name, the media that make it up, its size and physical behavior in
+//! world, and the license it is offered under. The record is checked when
+//! the asset is minted and stored on chain with it, so every marketplace
+//! and client reads the same description.
+//!
+//! Media are referenced by their BLAKE3 content hash and size, never by a
+//! location. Whoever hosts the bytes, `verify_media` fetches them from the
+//! node's blob store and checks them against the record, so a description
+//! cannot silently change after mint. Lengths are fixed-point integers
+//! (millimetres, grams, basis points) so validation is the same on every
+//! node.
+
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeSet;
+use crate::blockchain::assets::{AssetKind, MAX_URI_LEN};
+use crate::blockchain::types::hex_serde;
+use crate::config::DataClass;
+use crate::storage::remote::RemoteStorage;
+
+pub const MAX_NAME_LEN: usize = 64;
+pub const MAX_DESCRIPTION_LEN: usize = 1_024;
+/// Media files one asset may reference
+pub const MAX_MEDIA: usize = 8;
+pub const MAX_MIME_LEN: usize = 64;
+/// Largest single media file, 256 MiB
+pub const MAX_MEDIA_SIZE: u64 = 256 * 1024 * 1024;
+/// Longest side of an asset, 1 km
+pub const MAX_DIMENSION_MM: u32 = 1_000_000;
+/// Heaviest asset, 1,000 tonnes
+pub const MAX_MASS_G: u64 = 1_000_000_000;
+
+/// What a media file is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaRole {
+    /// 3D model rendered in world
+    Model,
+    Texture,
+    Animation,
+    Audio,
+    /// Preview image for listings and inventories
+    Thumbnail,
+}
+
+/// One media file, by content
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MediaRef {
+    pub role: MediaRole,
+    /// MIME type, e.g. `model/gltf-binary`
+    pub mime: String,
+    /// BLAKE3 hash of the file
+    #[serde(with = "hex_serde")]
+    pub hash: [u8; 32],
+    /// File size in bytes
+    pub size: u64,
+}
+
+/// Bounding box in world, in millimetres
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Dimensions {
+    pub width_mm: u32,
+    pub height_mm: u32,
+    pub depth_mm: u32,
+}
+
+/// How the asset behaves in physics simulation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PhysicsProperties {
+    pub mass_g: u64,
+    /// Friction coefficient in basis points, 0 to 10,000
+    pub friction_bps: u16,
+    /// Bounciness in basis points, 0 to 10,000
+    pub restitution_bps: u16,
+    /// Whether other objects collide with it
+    pub collidable: bool,
+}
+
+/// Terms the asset's media are offered under
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum License {
+    AllRightsReserved,
+    Cc0,
+    CcBy,
+    CcBySa,
+    CcByNc,
+    /// Terms published at `uri`
+    Custom { uri: String },
+}
+
+/// Asset Metadata
+/// Standard description of an NFT or object, fixed at mint.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AssetMetadata {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub media: Vec<MediaRef>,
+    #[serde(default)]
+    pub dimensions: Option<Dimensions>,
+    #[serde(default)]
+    pub physics: Option<PhysicsProperties>,
+    pub license: License,
+}
+
+impl AssetMetadata {
+    /// Check the record before an asset of `kind` is minted with it
+    pub fn validate(&self, kind: &AssetKind) -> Result<(), &'static str> {
+        if self.name.trim().is_empty() || self.name.len() > MAX_NAME_LEN {
+            return Err("Asset name must be 1 to 64 bytes");
+        }
+        if self.name.chars().chain(self.description.chars()).any(|c| c.is_control() && c != '\n') {
+            return Err("Asset name or description contains control characters");
+        }
+        if self.description.len() > MAX_DESCRIPTION_LEN {
+            return Err("Asset description too long");
+        }
+
+        if self.media.is_empty() || self.media.len() > MAX_MEDIA {
+            return Err("Asset must reference 1 to 8 media files");
+        }
+        let mut hashes = BTreeSet::new();
+        for media in &self.media {
+            let (top, subtype) = media.mime.split_once('/').unwrap_or_default();
+            if media.mime.len() > MAX_MIME_LEN || top.is_empty() || subtype.is_empty() {
+                return Err("Media MIME type must be `type/subtype`");
+            }
+            if media.size == 0 || media.size > MAX_MEDIA_SIZE {
+                return Err("Media size must be 1 byte to 256 MiB");
+            }
+            if media.hash == [0u8; 32] || !hashes.insert(media.hash) {
+                return Err("Media hashes must be set and distinct");
+            }
+        }
+        if self.physics.is_some() && !self.media.iter().any(|media| media.role == MediaRole::Model) {
+            return Err("Physics properties need a model");
+        }
+        if matches!(kind, AssetKind::Parcel { .. }) && self.dimensions.is_some() {
+            return Err("Parcel dimensions are fixed by the grid");
+        }
+
+        if let Some(dimensions) = &self.dimensions {
+            let sides = [dimensions.width_mm, dimensions.height_mm, dimensions.depth_mm];
+            if sides.iter().any(|side| *side == 0 || *side > MAX_DIMENSION_MM) {
+                return Err("Dimensions must be 1 mm to 1 km");
+            }
+        }
+        if let Some(physics) = &self.physics {
+            if physics.mass_g == 0 || physics.mass_g > MAX_MASS_G {
+                return Err("Mass must be 1 g to 1,000 tonnes");
+            }
+            if physics.friction_bps > 10_000 || physics.restitution_bps > 10_000 {
+                return Err("Friction and restitution must not exceed 10,000 basis points");
+            }
+        }
+        if let License::Custom { uri } = &self.license {
+            if uri.is_empty() || uri.len() > MAX_URI_LEN {
+                return Err("Custom license URI must be 1 to 256 bytes");
+            }
+        }
+        Ok(())
+    }
+
+    /// Hash of the record, for clients to pin a description
+    pub fn hash(&self) -> [u8; 32] {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(b"qmv:asset-metadata:");
+        hasher.update(&bincode::serialize(self).unwrap_or_default());
+        hasher.finalize().into()
+    }
+}
+
+/// Outcome of checking one media file against the blob store
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "status")]
+pub enum MediaStatus {
+    /// Bytes found with the recorded hash and size
+    Verified,
+    /// Bytes match the hash but not the recorded size
+    SizeMismatch { actual: u64 },
+    /// Not in the blob store, or the stored bytes do not match the hash
+    Unavailable { error: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MediaCheck {
+    #[serde(with = "hex_serde")]
+    pub hash: [u8; 32],
+    pub role: MediaRole,
+    #[serde(flatten)]
+    pub status: MediaStatus,
+}
+
+/// Fetch every media file of `metadata` from the blob store and check it
+/// against its recorded hash and size
+pub async fn verify_media(metadata: &AssetMetadata, storage: &RemoteStorage) -> Vec<MediaCheck> {
+    let mut checks = Vec::with_capacity(metadata.media.len());
+    for media in &metadata.media {
+        let status = match storage.fetch(DataClass::Blobs, &media.hash).await {
+            Ok(data) if data.len() as u64 == media.size => MediaStatus::Verified,
+            Ok(data) => MediaStatus::SizeMismatch { actual: data.len() as u64 },
+            Err(error) => MediaStatus::Unavailable { error },
+        };
+        checks.push(MediaCheck { hash: media.hash, role: media.role, status });
+    }
+    checks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use crate::config::LifecyclePolicy;
+    use crate::storage::remote::DirectoryStore;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    fn metadata(media: Vec<MediaRef>) -> AssetMetadata {
+        AssetMetadata {
+            name: "Lantern".to_string(),
+            description: String::new(),
+            media,
+            dimensions: Some(Dimensions { width_mm: 200, height_mm: 450, depth_mm: 200 }),
+            physics: Some(PhysicsProperties { mass_g: 1_200, friction_bps: 4_000, restitution_bps: 1_000, collidable: true }),
+            license: License::CcBy,
+        }
+    }
+
+    fn media(role: MediaRole, data: &[u8]) -> MediaRef {
+        MediaRef { role, mime: "model/gltf-binary".to_string(), hash: blake3::hash(data).into(), size: data.len() as u64 }
+    }
+
+    #[test]
+    fn test_validation() {
+        let nft = AssetKind::Nft;
+        let valid = metadata(vec![media(MediaRole::Model, b"mesh"), media(MediaRole::Thumbnail, b"png")]);
+        assert!(valid.validate(&nft).is_ok());
+
+        let mut invalid = valid.clone();
+        invalid.name = " ".to_string();
+        assert!(invalid.validate(&nft).is_err());
+        let mut invalid = valid.clone();
+        invalid.media[1].hash = invalid.media[0].hash;
+        assert_eq!(invalid.validate(&nft), Err("Media hashes must be set and distinct"));
+        let mut invalid = valid.clone();
+        invalid.media[0].mime = "gltf".to_string();
+        assert!(invalid.validate(&nft).is_err());
+        let mut invalid = valid.clone();
+        invalid.dimensions = Some(Dimensions { width_mm: 0, height_mm: 1, depth_mm: 1 });
+        assert!(invalid.validate(&nft).is_err());
+        let mut invalid = valid.clone();
+        invalid.physics.as_mut().unwrap().restitution_bps = 10_001;
+        assert!(invalid.validate(&nft).is_err());
+        let mut invalid = valid.clone();
+        invalid.media.remove(0);
+        assert_eq!(invalid.validate(&nft), Err("Physics properties need a model"));
+        let mut invalid = valid.clone();
+        invalid.license = License::Custom { uri: String::new() };
+        assert!(invalid.validate(&nft).is_err());
+        assert_eq!(valid.validate(&AssetKind::Parcel { x: 0, y: 0 }), Err("Parcel dimensions are fixed by the grid"));
+
+        // The hash pins every field
+        let mut renamed = valid.clone();
+        renamed.name = "Lamp".to_string();
+        assert_ne!(renamed.hash(), valid.hash());
+    }
+
+    #[tokio::test]
+    async fn test_verify_media_against_blob_store() {
+        let dir = std::env::temp_dir().join(format!("qmv-asset-metadata-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = Arc::new(DirectoryStore::new(dir.join("blobs")));
+        let mut storage = RemoteStorage::with_clock(&BTreeMap::new(), dir.join("manifest.json"), MockClock::new(0))
+            .unwrap()
+            .with_store(DataClass::Blobs, store, LifecyclePolicy::default());
+        storage.mirror(DataClass::Blobs, "mesh.glb", b"mesh").await.unwrap();
+        storage.mirror(DataClass::Blobs, "thumb.png", b"png").await.unwrap();
+
+        let mut thumbnail = media(MediaRole::Thumbnail, b"png");
+        thumbnail.size = 4;
+        let record = metadata(vec![media(MediaRole::Model, b"mesh"), thumbnail, media(MediaRole::Audio, b"ogg")]);
+        let statuses: Vec<MediaStatus> = verify_media(&record, &storage).await.into_iter().map(|check| check.status).collect();
+        assert_eq!(statuses[0], MediaStatus::Verified);
+        assert_eq!(statuses[1], MediaStatus::SizeMismatch { actual: 3 });
+        assert!(matches!(statuses[2], MediaStatus::Unavailable { .. }));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,154 @@
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use crate::blockchain::asset_metadata::AssetMetadata;
+use crate::blockchain::types::{hex_serde, Address};
+
+/// Highest royalty a creator can set, in basis points (25%)
+pub const MAX_ROYALTY_BPS: u16 = 2_500;
+
+/// Longest asset URI accepted at mint
+pub const MAX_URI_LEN: usize = 256;
+
+/// Identifier of the asset minted by `creator` at `nonce`
+pub fn asset_id(creator: &Address, nonce: u64) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"qmv:asset:");
+    hasher.update(creator);
+    hasher.update(&nonce.to_le_bytes());
+    hasher.finalize().into()
+}
+
+/// What kind of thing an asset is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AssetKind {
+    /// Unique item, e.g. a wearable or artwork
+    Nft,
+    /// Unit of world space at grid coordinates; at most one per cell
+    Parcel { x: i64, y: i64 },
+}
+
+/// Unique on-chain asset
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Asset {
+    #[serde(with = "hex_serde")]
+    pub creator: Address,
+    #[serde(with = "hex_serde")]
+    pub owner: Address,
+    pub kind: AssetKind,
+    /// Share of every marketplace sale paid to the creator, in basis points
+    pub royalty_bps: u16,
+    pub uri: String,
+    /// Standard description, checked at mint
+    #[serde(default)]
+    pub metadata: Option<AssetMetadata>,
+}
+
+impl Asset {
+    /// Creator's cut of a sale at `price`
+    pub fn royalty(&self, price: u128) -> u128 {
+        price / 10_000 * self.royalty_bps as u128 + price % 10_000 * self.royalty_bps as u128 / 10_000
+    }
+}
+
+/// Asset operations, signed by the owner
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AssetAction {
+    /// Create an asset owned by the sender; its ID is derived from sender and nonce
+    Mint {
+        kind: AssetKind,
+        #[serde(default)]
+        royalty_bps: u16,
+        #[serde(default)]
+        uri: String,
+        #[serde(default)]
+        metadata: Option<AssetMetadata>,
+    },
+    /// Give an asset to another account
+    Transfer {
+        #[serde(with = "hex_serde")]
+        asset: [u8; 32],
+        #[serde(with = "hex_serde")]
+        to: Address,
+    },
+}
+
+/// Assets by ID, with parcels also indexed by their cell
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Assets {
+    assets: BTreeMap<[u8; 32], Asset>,
+    parcels: BTreeMap<(i64, i64), [u8; 32]>,
+}
+
+impl Assets {
+    pub fn get(&self, id: &[u8; 32]) -> Option<&Asset> {
+        self.assets.get(id)
+    }
+
+    pub fn by_owner<'a>(&'a self, owner: &'a Address) -> impl Iterator<Item = ([u8; 32], &'a Asset)> + 'a {
+        self.assets.iter()
+            .filter(move |(_, asset)| &asset.owner == owner)
+            .map(|(id, asset)| (*id, asset))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8; 32], &Asset)> {
+        self.assets.iter()
+    }
+
+    /// Parcel occupying the cell at `(x, y)`
+    pub fn parcel_at(&self, x: i64, y: i64) -> Option<[u8; 32]> {
+        self.parcels.get(&(x, y)).copied()
+    }
+
+    /// Every minted parcel with its cell
+    pub fn parcels(&self) -> impl Iterator<Item = ((i64, i64), [u8; 32])> + '_ {
+        self.parcels.iter().map(|(cell, id)| (*cell, *id))
+    }
+
+    pub fn len(&self) -> usize {
+        self.assets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.assets.is_empty()
+    }
+
+    /// Set an asset to a journaled value; `None` removes it
+    pub(crate) fn restore(&mut self, id: [u8; 32], asset: Option<Asset>) {
+        if let Some(AssetKind::Parcel { x, y }) = self.assets.remove(&id).map(|previous| previous.kind) {
+            self.parcels.remove(&(x, y));
+        }
+        if let Some(asset) = asset {
+            if let AssetKind::Parcel { x, y } = asset.kind {
+                self.parcels.insert((x, y), id);
+            }
+            self.assets.insert(id, asset);
+        }
+    }
+}
+
+/// Check a new asset before it is minted
+pub fn check_mint(
+    assets: &Assets,
+    kind: &AssetKind,
+    royalty_bps: u16,
+    uri: &str,
+    metadata: Option<&AssetMetadata>,
+) -> Result<(), &'static str> {
+    if royalty_bps > MAX_ROYALTY_BPS {
+        return Err("Royalty above 25%");
+    }
+    if uri.len() > MAX_URI_LEN {
+        return Err("Asset URI too long");
+    }
+    if let AssetKind::Parcel { x, y } = kind {
+        if assets.parcel_at(*x, *y).is_some() {
+            return Err("Parcel already minted");
+        }
+    }
+    if let Some(metadata) = metadata {
+        metadata.validate(kind)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,337 @@
+//! Epoch randomness beacon.
+//!
+//! Epoch `e` covers heights `[e * epoch_blocks, (e + 1) * epoch_blocks)`.
+//! During the first `contribution_blocks` of epoch `e`, validators sign the
+//! previous beacon output for epoch `e + 1`. The signatures stand in for VRF
+//! outputs. Once the window closes, the seed is fixed from the previous
+//! output and every contribution. It is then run through the VDF
+//! (`crypto::vdf`), and the result is committed in the first block of epoch
+//! `e + 1`. The VDF takes longer than the window, so the last contributor
+//! cannot learn the output in time to grind it by withholding. Epochs follow
+//! the node-wide `EpochSchedule`.
+
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use crate::blockchain::core::Block;
+use crate::blockchain::types::{hex_serde, Address};
+use crate::blockchain::validator_keys::ValidatorKeys;
+use crate::crypto::domain::{PayloadKind, SigningDomain};
+use crate::crypto::vdf::{self, VdfProof};
+use crate::epoch::EpochSchedule;
+
+/// Beacon timing and VDF difficulty; consensus-critical
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BeaconParams {
+    /// Blocks at the start of each epoch in which contributions are accepted
+    pub contribution_blocks: u64,
+    /// Sequential squarings; should take longer than the rest of the epoch
+    /// on the fastest hardware an attacker could have, but finish in time on
+    /// a validator's
+    pub vdf_difficulty: u64,
+}
+
+impl Default for BeaconParams {
+    fn default() -> Self {
+        Self {
+            contribution_blocks: 50,
+            vdf_difficulty: 100_000,
+        }
+    }
+}
+
+/// A validator's share of the next epoch's seed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Contribution {
+    #[serde(with = "hex_serde")]
+    pub validator: Address,
+    /// Epoch whose randomness this feeds
+    pub epoch: u64,
+    /// Signature over the previous epoch's beacon output
+    #[serde(with = "hex_serde")]
+    pub signature: Vec<u8>,
+}
+
+impl Contribution {
+    /// Bytes the validator signs
+    pub fn signing_bytes(network_id: u64, epoch: u64, previous: &[u8; 32]) -> Vec<u8> {
+        SigningDomain::main_chain(network_id).payload(PayloadKind::BeaconContribution, epoch, previous)
+    }
+}
+
+/// Finalized randomness for one epoch, with everything needed to check it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BeaconRecord {
+    pub epoch: u64,
+    /// Ordered by validator
+    pub contributions: Vec<Contribution>,
+    pub vdf: VdfProof,
+    #[serde(with = "hex_serde")]
+    pub randomness: [u8; 32],
+}
+
+/// Everything fixed when the contribution window closes; finalizing it
+/// runs the VDF, so do that off the critical path
+#[derive(Debug, Clone)]
+pub struct BeaconInput {
+    pub epoch: u64,
+    pub seed: [u8; 32],
+    pub contributions: Vec<Contribution>,
+    pub vdf_difficulty: u64,
+}
+
+impl BeaconInput {
+    pub fn finalize(self) -> BeaconRecord {
+        let vdf = vdf::evaluate(&self.seed, self.vdf_difficulty);
+        BeaconRecord {
+            epoch: self.epoch,
+            contributions: self.contributions,
+            randomness: vdf.randomness(),
+            vdf,
+        }
+    }
+}
+
+/// Seed of `epoch` from the previous output and the contributions
+fn seed(epoch: u64, previous: &[u8; 32], contributions: &[Contribution]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"qmv:beacon:seed:");
+    hasher.update(&epoch.to_le_bytes());
+    hasher.update(previous);
+    for contribution in contributions {
+        hasher.update(&contribution.validator);
+        hasher.update(blake3::hash(&contribution.signature).as_bytes());
+    }
+    hasher.finalize().into()
+}
+
+/// Randomness Beacon
+/// Collects contributions, hands out VDF inputs once windows close, and
+/// checks that epoch blocks commit the finalized output.
+#[derive(Debug, Clone)]
+pub struct RandomnessBeacon {
+    params: BeaconParams,
+    schedule: EpochSchedule,
+    network_id: u64,
+    genesis: [u8; 32],
+    records: BTreeMap<u64, BeaconRecord>,
+    pending: BTreeMap<u64, BTreeMap<Address, Contribution>>,
+}
+
+impl RandomnessBeacon {
+    pub fn new(params: BeaconParams, schedule: EpochSchedule, network_id: u64) -> Self {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(b"qmv:beacon:genesis:");
+        hasher.update(&network_id.to_le_bytes());
+        Self {
+            params,
+            schedule,
+            network_id,
+            genesis: hasher.finalize().into(),
+            records: BTreeMap::new(),
+            pending: BTreeMap::new(),
+        }
+    }
+
+    pub fn params(&self) -> &BeaconParams {
+        &self.params
+    }
+
+    pub fn epoch_of(&self, height: u64) -> u64 {
+        self.schedule.epoch_of(height)
+    }
+
+    pub fn epoch_start(&self, epoch: u64) -> u64 {
+        self.schedule.epoch_start(epoch)
+    }
+
+    /// Last height at which contributions for `epoch` are accepted
+    pub fn contribution_deadline(&self, epoch: u64) -> u64 {
+        let window = self.params.contribution_blocks.clamp(1, self.schedule.epoch_blocks.max(1));
+        self.epoch_start(epoch.saturating_sub(1)) + window - 1
+    }
+
+    /// Output for `epoch`; epoch 0 uses a fixed per-network value
+    pub fn randomness(&self, epoch: u64) -> Option<[u8; 32]> {
+        match epoch {
+            0 => Some(self.genesis),
+            _ => self.records.get(&epoch).map(|record| record.randomness),
+        }
+    }
+
+    pub fn record(&self, epoch: u64) -> Option<&BeaconRecord> {
+        self.records.get(&epoch)
+    }
+
+    /// Latest epoch with committed randomness
+    pub fn latest_epoch(&self) -> u64 {
+        self.records.keys().next_back().copied().unwrap_or(0)
+    }
+
+    /// Contributions received so far for `epoch`
+    pub fn contributions(&self, epoch: u64) -> usize {
+        self.pending.get(&epoch).map_or(0, BTreeMap::len)
+    }
+
+    /// Check a contribution against the validator's key at the opening of its window
+    fn check_contribution(&self, contribution: &Contribution, keys: &ValidatorKeys) -> Result<(), &'static str> {
+        if contribution.epoch == 0 {
+            return Err("Epoch 0 takes no contributions");
+        }
+        let previous = self.randomness(contribution.epoch - 1)
+            .ok_or("Previous epoch randomness not yet committed")?;
+        let key = keys.key_at(&contribution.validator, self.epoch_start(contribution.epoch - 1))
+            .ok_or("Unknown validator")?;
+        let key = VerifyingKey::from_bytes(&key).map_err(|_| "Invalid validator key")?;
+        let signature = Signature::from_slice(&contribution.signature)
+            .map_err(|_| "Invalid contribution signature")?;
+        let message = Contribution::signing_bytes(self.network_id, contribution.epoch, &previous);
+        key.verify(&message, &signature).map_err(|_| "Invalid contribution signature")
+    }
+
+    /// Accept a contribution submitted at chain height `height`
+    pub fn contribute(
+        &mut self,
+        contribution: Contribution,
+        keys: &ValidatorKeys,
+        height: u64,
+    ) -> Result<(), &'static str> {
+        if contribution.epoch != self.epoch_of(height) + 1 {
+            return Err("Contributions are only accepted for the next epoch");
+        }
+        if height > self.contribution_deadline(contribution.epoch) {
+            return Err("Contribution window closed");
+        }
+        self.check_contribution(&contribution, keys)?;
+        let pending = self.pending.entry(contribution.epoch).or_default();
+        if pending.contains_key(&contribution.validator) {
+            return Err("Validator already contributed");
+        }
+        pending.insert(contribution.validator, contribution);
+        Ok(())
+    }
+
+    /// VDF input for `epoch`, once its contribution window has closed at `height`
+    pub fn prepare(&self, epoch: u64, height: u64) -> Result<BeaconInput, &'static str> {
+        if epoch == 0 || height <= self.contribution_deadline(epoch) {
+            return Err("Contribution window still open");
+        }
+        let previous = self.randomness(epoch - 1).ok_or("Previous epoch randomness not yet committed")?;
+        let contributions: Vec<Contribution> = self.pending.get(&epoch)
+            .map(|pending| pending.values().cloned().collect())
+            .unwrap_or_default();
+        Ok(BeaconInput {
+            epoch,
+            seed: seed(epoch, &previous, &contributions),
+            contributions,
+            vdf_difficulty: self.params.vdf_difficulty,
+        })
+    }
+
+    /// Check a record from another validator without re-running the VDF
+    pub fn verify_record(&self, record: &BeaconRecord, keys: &ValidatorKeys) -> Result<(), &'static str> {
+        if record.epoch == 0 {
+            return Err("Epoch 0 has no beacon record");
+        }
+        let previous = self.randomness(record.epoch - 1).ok_or("Previous epoch randomness not yet committed")?;
+        if !record.contributions.windows(2).all(|pair| pair[0].validator < pair[1].validator) {
+            return Err("Contributions must be sorted by validator without duplicates");
+        }
+        for contribution in &record.contributions {
+            if contribution.epoch != record.epoch {
+                return Err("Contribution for a different epoch");
+            }
+            self.check_contribution(contribution, keys)?;
+        }
+        let seed = seed(record.epoch, &previous, &record.contributions);
+        vdf::verify(&seed, self.params.vdf_difficulty, &record.vdf)?;
+        if record.randomness != record.vdf.randomness() {
+            return Err("Beacon randomness does not match the VDF output");
+        }
+        Ok(())
+    }
+
+    /// Store a verified record for the next epoch
+    pub fn commit(&mut self, record: BeaconRecord, keys: &ValidatorKeys) -> Result<(), &'static str> {
+        if record.epoch != self.latest_epoch() + 1 {
+            return Err("Beacon records must be committed in epoch order");
+        }
+        self.verify_record(&record, keys)?;
+        self.pending.remove(&record.epoch);
+        self.records.insert(record.epoch, record);
+        Ok(())
+    }
+
+    /// Beacon the block at `height` must commit, if it opens an epoch
+    pub fn expected_beacon(&self, height: u64) -> Result<Option<[u8; 32]>, &'static str> {
+        if !self.schedule.is_boundary(height) {
+            return Ok(None);
+        }
+        self.randomness(self.epoch_of(height))
+            .map(Some)
+            .ok_or("Epoch randomness not yet finalized")
+    }
+
+    /// Check that `block` commits the beacon output exactly when it opens an epoch
+    pub fn check_block(&self, block: &Block) -> Result<(), &'static str> {
+        if block.beacon != self.expected_beacon(block.index)? {
+            return Err("Block does not commit the epoch beacon");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+    use crate::math::precision::PreciseFloat;
+
+    #[test]
+    fn test_contribute_finalize_and_commit() {
+        let params = BeaconParams { contribution_blocks: 5, vdf_difficulty: 100 };
+        let schedule = EpochSchedule { epoch_blocks: 10, ..Default::default() };
+        let mut beacon = RandomnessBeacon::new(params, schedule, 1);
+        let mut keys = ValidatorKeys::new();
+        let signers: Vec<(Address, SigningKey)> = (1..=3u8)
+            .map(|i| ([i; 32], SigningKey::from_bytes(&[i; 32])))
+            .collect();
+        for (validator, key) in &signers {
+            keys.register(*validator, key.verifying_key().to_bytes(), 0).unwrap();
+        }
+        let genesis = beacon.randomness(0).unwrap();
+        let contribution = |(validator, key): &(Address, SigningKey)| Contribution {
+            validator: *validator,
+            epoch: 1,
+            signature: key.sign(&Contribution::signing_bytes(1, 1, &genesis)).to_bytes().to_vec(),
+        };
+
+        for signer in &signers[..2] {
+            beacon.contribute(contribution(signer), &keys, 3).unwrap();
+        }
+        assert_eq!(beacon.contribute(contribution(&signers[0]), &keys, 3), Err("Validator already contributed"));
+        assert_eq!(beacon.contribute(contribution(&signers[2]), &keys, 5), Err("Contribution window closed"));
+        let mut forged = contribution(&signers[2]);
+        forged.validator = [9; 32];
+        assert_eq!(beacon.contribute(forged, &keys, 4), Err("Unknown validator"));
+
+        assert_eq!(beacon.prepare(1, 4).unwrap_err(), "Contribution window still open");
+        let record = beacon.prepare(1, 5).unwrap().finalize();
+        assert_eq!(record.contributions.len(), 2);
+
+        // A record whose contributions were swapped out no longer matches its VDF
+        let mut tampered = record.clone();
+        tampered.contributions.pop();
+        assert_eq!(beacon.verify_record(&tampered, &keys), Err("Invalid VDF proof"));
+
+        let one = PreciseFloat::new(1, 2);
+        let block = Block::new(10, [0; 32], vec![], one.clone(), one.clone(), one.clone(), one);
+        assert_eq!(beacon.check_block(&block), Err("Epoch randomness not yet finalized"));
+
+        beacon.commit(record.clone(), &keys).unwrap();
+        assert_eq!(beacon.randomness(1), Some(record.randomness));
+        assert_eq!(beacon.check_block(&block), Err("Block does not commit the epoch beacon"));
+        assert_eq!(beacon.check_block(&block.with_beacon(record.randomness)), Ok(()));
+        assert_eq!(beacon.commit(record, &keys), Err("Beacon records must be committed in epoch order"));
+    }
+}
@@ -0,0 +1,168 @@
+use serde::{Serialize, Deserialize};
+use crate::blockchain::assets::AssetAction;
+use crate::blockchain::channel::ChannelAction;
+use crate::blockchain::confidential::ConfidentialAction;
+use crate::blockchain::execution::Receipt;
+use crate::blockchain::htlc::HtlcAction;
+use crate::blockchain::multisig::MultisigOperation;
+use crate::blockchain::scheduler::ScheduledAction;
+use crate::blockchain::transaction::{Transaction, TransactionAction};
+use crate::blockchain::types::hex_serde;
+
+pub const BLOOM_BYTES: usize = 256;
+const BLOOM_BITS: usize = BLOOM_BYTES * 8;
+/// Bits set per inserted item
+const HASHES: usize = 3;
+
+/// 2048-bit bloom filter carried in block headers.
+/// Covers event topics and contracts, transaction senders and recipients,
+/// and content hashes, so queries can skip blocks that cannot match.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bloom(#[serde(with = "hex_serde")] [u8; BLOOM_BYTES]);
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Self([0u8; BLOOM_BYTES])
+    }
+}
+
+impl std::fmt::Debug for Bloom {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Bloom({} bits set)", self.0.iter().map(|b| b.count_ones()).sum::<u32>())
+    }
+}
+
+impl Bloom {
+    pub fn from_bytes(bytes: [u8; BLOOM_BYTES]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; BLOOM_BYTES] {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|&b| b == 0)
+    }
+
+    fn bit_positions(input: &[u8]) -> [usize; HASHES] {
+        let hash = blake3::hash(input);
+        let bytes = hash.as_bytes();
+        let mut positions = [0usize; HASHES];
+        for (i, position) in positions.iter_mut().enumerate() {
+            *position = u16::from_le_bytes([bytes[2 * i], bytes[2 * i + 1]]) as usize % BLOOM_BITS;
+        }
+        positions
+    }
+
+    /// Add an item to the filter
+    pub fn accrue(&mut self, input: &[u8]) {
+        for bit in Self::bit_positions(input) {
+            self.0[bit / 8] |= 1 << (bit % 8);
+        }
+    }
+
+    /// Merge another filter into this one
+    pub fn accrue_bloom(&mut self, other: &Bloom) {
+        for (byte, other) in self.0.iter_mut().zip(other.0.iter()) {
+            *byte |= other;
+        }
+    }
+
+    /// False means the item is definitely absent; true means it may be present
+    pub fn contains_input(&self, input: &[u8]) -> bool {
+        Self::bit_positions(input)
+            .iter()
+            .all(|bit| self.0[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    /// Whether every bit of `other` is set in this filter
+    pub fn contains_bloom(&self, other: &Bloom) -> bool {
+        self.0.iter().zip(other.0.iter()).all(|(byte, other)| byte & other == *other)
+    }
+
+    /// Bloom over a block's transactions and their receipts
+    pub fn for_block(transactions: &[Transaction], receipts: &[Receipt]) -> Self {
+        let mut bloom = Self::default();
+        for tx in transactions {
+            bloom.accrue(&tx.from);
+            if let Some(sponsorship) = &tx.sponsor {
+                bloom.accrue(&sponsorship.sponsor);
+            }
+            for witness in &tx.witnesses {
+                bloom.accrue(&witness.key);
+            }
+            match &tx.action {
+                TransactionAction::Transfer { to, .. } => bloom.accrue(to),
+                TransactionAction::Call { contract, .. } => bloom.accrue(contract),
+                TransactionAction::RotateValidatorKey(rotation) => bloom.accrue(&rotation.validator),
+                TransactionAction::MultisigExecute { multisig, operation, .. } => {
+                    bloom.accrue(multisig);
+                    match operation {
+                        MultisigOperation::Transfer { to, .. } => bloom.accrue(to),
+                        MultisigOperation::Call { contract, .. } => bloom.accrue(contract),
+                        MultisigOperation::UpdateSigners { .. } => {}
+                    }
+                }
+                TransactionAction::Schedule { action, .. } => match action {
+                    ScheduledAction::Transfer { to, .. } => bloom.accrue(to),
+                    ScheduledAction::Call { contract, .. } => bloom.accrue(contract),
+                },
+                TransactionAction::Asset(AssetAction::Transfer { to, .. }) => bloom.accrue(to),
+                TransactionAction::Confidential(ConfidentialAction::Transfer { to, .. }) => bloom.accrue(to),
+                TransactionAction::Deploy { .. }
+                | TransactionAction::CreateMultisig { .. }
+                | TransactionAction::CancelSchedule { .. }
+                | TransactionAction::Asset(AssetAction::Mint { .. })
+                | TransactionAction::Market(_)
+                | TransactionAction::Lease(_)
+                | TransactionAction::Confidential(_)
+                | TransactionAction::Sponsor(_)
+                | TransactionAction::Validation(_) => {}
+                TransactionAction::Channel(action) => match action {
+                    ChannelAction::Open { counterparty, .. } => bloom.accrue(counterparty),
+                    ChannelAction::Deposit { channel, .. } | ChannelAction::Close { channel, .. } => bloom.accrue(channel),
+                    ChannelAction::Settle { update } | ChannelAction::Dispute { update } => bloom.accrue(&update.update.channel),
+                },
+                TransactionAction::Htlc(action) => match action {
+                    HtlcAction::Lock(terms) => bloom.accrue(&terms.recipient),
+                    HtlcAction::Claim { id, .. } | HtlcAction::Refund { id } => bloom.accrue(id),
+                },
+            }
+        }
+        for receipt in receipts {
+            bloom.accrue(&receipt.tx_hash);
+            if let Some(address) = &receipt.contract_address {
+                bloom.accrue(address);
+            }
+            for event in &receipt.events {
+                bloom.accrue(&event.contract);
+                bloom.accrue(event.topic.as_bytes());
+            }
+        }
+        bloom
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_membership() {
+        let mut bloom = Bloom::default();
+        assert!(bloom.is_empty());
+        bloom.accrue(b"Transfer");
+        bloom.accrue(&[7u8; 32]);
+
+        assert!(bloom.contains_input(b"Transfer"));
+        assert!(bloom.contains_input(&[7u8; 32]));
+        assert!(!bloom.contains_input(b"Approval"));
+
+        let mut combined = Bloom::default();
+        combined.accrue_bloom(&bloom);
+        combined.accrue(b"Approval");
+        assert!(combined.contains_bloom(&bloom));
+        assert!(!bloom.contains_bloom(&combined));
+    }
+}
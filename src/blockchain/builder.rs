@@ -0,0 +1,261 @@
+use serde::{Serialize, Deserialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap};
+use crate::blockchain::circuit_breaker;
+use crate::blockchain::mempool::{Mempool, ReadyTransaction};
+use crate::blockchain::state::WorldState;
+use crate::blockchain::transaction::{Transaction, TransactionAction, TxHash};
+use crate::blockchain::types::{hex_serde_vec, Address};
+
+/// Gas and size budget for one block
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleLimits {
+    /// Sum of the transactions' gas limits
+    pub max_gas: u64,
+    /// Sum of the transactions' encoded sizes
+    pub max_bytes: usize,
+}
+
+impl Default for BundleLimits {
+    fn default() -> Self {
+        Self { max_gas: 30_000_000, max_bytes: 1024 * 1024 }
+    }
+}
+
+/// Pending transaction offered to an ordering strategy
+#[derive(Debug, Clone, Copy)]
+pub struct Candidate<'a> {
+    pub tx: &'a Transaction,
+    /// Mempool arrival order; lower arrived first
+    pub sequence: u64,
+    /// Transactions from the same sender already in the bundle
+    pub taken: usize,
+}
+
+/// Ordering rule for block building. Each sender's transactions are always
+/// taken in nonce order; the strategy decides which sender goes next.
+pub trait OrderingStrategy: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Higher goes first; ties go to the earlier arrival
+    fn priority(&self, candidate: &Candidate) -> u128;
+}
+
+/// Highest gas price first
+pub struct FeeGreedy;
+
+impl OrderingStrategy for FeeGreedy {
+    fn name(&self) -> &'static str {
+        "fee_greedy"
+    }
+
+    fn priority(&self, candidate: &Candidate) -> u128 {
+        candidate.tx.gas_price
+    }
+}
+
+/// Round-robin across senders in arrival order, ignoring fees, so no
+/// account can crowd out others by paying more
+pub struct FairFifo;
+
+impl OrderingStrategy for FairFifo {
+    fn name(&self) -> &'static str {
+        "fair_fifo"
+    }
+
+    fn priority(&self, candidate: &Candidate) -> u128 {
+        u128::MAX - candidate.taken as u128
+    }
+}
+
+/// Fee-greedy, with the gas price of transactions from or to
+/// governance-designated addresses raised by a percentage for ordering
+pub struct GovernanceBoosted {
+    boosts: BTreeMap<Address, u32>,
+}
+
+impl GovernanceBoosted {
+    pub fn new(boosts: BTreeMap<Address, u32>) -> Self {
+        Self { boosts }
+    }
+
+    fn boost(&self, tx: &Transaction) -> u32 {
+        let target = match &tx.action {
+            TransactionAction::Transfer { to, .. } => Some(to),
+            TransactionAction::Call { contract, .. } => Some(contract),
+            TransactionAction::MultisigExecute { multisig, .. } => Some(multisig),
+            _ => None,
+        };
+        [Some(&tx.from), target].into_iter()
+            .flatten()
+            .filter_map(|address| self.boosts.get(address))
+            .copied()
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+impl OrderingStrategy for GovernanceBoosted {
+    fn name(&self) -> &'static str {
+        "governance_boosted"
+    }
+
+    fn priority(&self, candidate: &Candidate) -> u128 {
+        let boost = 100 + self.boost(candidate.tx) as u128;
+        candidate.tx.gas_price.saturating_mul(boost) / 100
+    }
+}
+
+/// Ordered transactions chosen for a block
+#[derive(Debug, Clone, Serialize)]
+pub struct Bundle {
+    pub strategy: &'static str,
+    #[serde(with = "hex_serde_vec")]
+    pub hashes: Vec<TxHash>,
+    pub transactions: Vec<Transaction>,
+    pub gas: u64,
+    pub bytes: usize,
+}
+
+/// Block Builder
+/// Picks an ordered bundle from the mempool under gas and byte limits.
+/// A sender whose next transaction does not fit is skipped for the rest of
+/// the bundle, since its later nonces could not execute.
+pub struct BlockBuilder<'a> {
+    strategy: &'a dyn OrderingStrategy,
+    limits: BundleLimits,
+    leading: Vec<Transaction>,
+    halted_modules: BTreeSet<String>,
+}
+
+impl<'a> BlockBuilder<'a> {
+    pub fn new(strategy: &'a dyn OrderingStrategy, limits: BundleLimits) -> Self {
+        Self { strategy, limits, leading: Vec::new(), halted_modules: BTreeSet::new() }
+    }
+
+    /// Transactions that must open the bundle in this order, such as
+    /// revealed commitments. Their senders' pooled transactions wait for a
+    /// later bundle, since their nonces would follow the leading ones.
+    pub fn with_leading(mut self, leading: Vec<Transaction>) -> Self {
+        self.leading = leading;
+        self
+    }
+
+    /// Hold pooled transactions of these circuit-breaker modules out of the
+    /// bundle. A sender whose next transaction is held waits with the rest
+    /// of its queue.
+    pub fn with_halted_modules(mut self, modules: BTreeSet<String>) -> Self {
+        self.halted_modules = modules;
+        self
+    }
+
+    pub fn build(&self, mempool: &Mempool, state: &WorldState) -> Bundle {
+        let mut bundle = Bundle {
+            strategy: self.strategy.name(),
+            hashes: Vec::new(),
+            transactions: Vec::new(),
+            gas: 0,
+            bytes: 0,
+        };
+        for tx in &self.leading {
+            let gas = bundle.gas.saturating_add(tx.gas_limit);
+            let bytes = bundle.bytes.saturating_add(tx.size());
+            // The order is fixed, so nothing may skip ahead of one that does not fit
+            if gas > self.limits.max_gas || bytes > self.limits.max_bytes {
+                return bundle;
+            }
+            bundle.gas = gas;
+            bundle.bytes = bytes;
+            bundle.hashes.push(tx.hash());
+            bundle.transactions.push(tx.clone());
+        }
+
+        let queues: Vec<_> = mempool.ready(state).into_iter()
+            .filter(|queue| self.leading.iter().all(|tx| tx.from != queue[0].tx.from))
+            .collect();
+        let mut taken = vec![0usize; queues.len()];
+        let mut heap = BinaryHeap::new();
+        for (sender, queue) in queues.iter().enumerate() {
+            heap.push(self.entry(sender, &queue[0], 0));
+        }
+
+        while let Some((_, _, sender)) = heap.pop() {
+            let ready = &queues[sender][taken[sender]];
+            if self.halted_modules.contains(circuit_breaker::module_of(&ready.tx.action)) {
+                continue;
+            }
+            let gas = bundle.gas.saturating_add(ready.tx.gas_limit);
+            let bytes = bundle.bytes.saturating_add(ready.tx.size());
+            if gas > self.limits.max_gas || bytes > self.limits.max_bytes {
+                continue;
+            }
+            bundle.gas = gas;
+            bundle.bytes = bytes;
+            bundle.hashes.push(ready.hash);
+            bundle.transactions.push(ready.tx.clone());
+            taken[sender] += 1;
+            if let Some(next) = queues[sender].get(taken[sender]) {
+                heap.push(self.entry(sender, next, taken[sender]));
+            }
+        }
+        bundle
+    }
+
+    /// Heap entry: priority, then earliest arrival
+    fn entry(&self, sender: usize, ready: &ReadyTransaction, taken: usize) -> (u128, Reverse<u64>, usize) {
+        let candidate = Candidate { tx: ready.tx, sequence: ready.sequence, taken };
+        (self.strategy.priority(&candidate), Reverse(ready.sequence), sender)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::SigningKey;
+
+    fn transfer(key: &SigningKey, nonce: u64, gas_price: u128) -> Transaction {
+        let mut tx = Transaction::new([0u8; 32], nonce, TransactionAction::Transfer { to: [9u8; 32], amount: 1 }, 21_000, gas_price);
+        tx.sign(key);
+        tx
+    }
+
+    #[test]
+    fn test_strategies_order_and_limits() {
+        let keys: Vec<SigningKey> = (1..=3).map(|i| SigningKey::from_bytes(&[i; 32])).collect();
+        let state = WorldState::with_balances(&keys.iter().map(|k| (k.verifying_key().to_bytes(), 1_000_000_000)).collect::<Vec<_>>());
+        let mut pool = Mempool::default();
+        // Arrival order: a0 (price 1), b0 (5), b1 (50), c0 (10), a1 (1)
+        for (key, nonce, price) in [(0, 0, 1), (1, 0, 5), (1, 1, 50), (2, 0, 10), (0, 1, 1)] {
+            pool.insert(transfer(&keys[key], nonce, price), &state).unwrap();
+        }
+        let order = |bundle: &Bundle| -> Vec<(u8, u64)> {
+            bundle.transactions.iter()
+                .map(|tx| (keys.iter().position(|k| k.verifying_key().to_bytes() == tx.from).unwrap() as u8, tx.nonce))
+                .collect()
+        };
+
+        // b1 pays most but cannot go before b0
+        let greedy = BlockBuilder::new(&FeeGreedy, BundleLimits::default()).build(&pool, &state);
+        assert_eq!(order(&greedy), vec![(2, 0), (1, 0), (1, 1), (0, 0), (0, 1)]);
+
+        let fair = BlockBuilder::new(&FairFifo, BundleLimits::default()).build(&pool, &state);
+        // Second round: b1 arrived before a1
+        assert_eq!(order(&fair), vec![(0, 0), (1, 0), (2, 0), (1, 1), (0, 1)]);
+
+        let boosts = [(keys[0].verifying_key().to_bytes(), 2_000)].into_iter().collect();
+        let boosted = BlockBuilder::new(&GovernanceBoosted::new(boosts), BundleLimits::default()).build(&pool, &state);
+        assert_eq!(order(&boosted)[..2], [(0, 0), (0, 1)]);
+
+        // Room for two transfers by gas
+        let limits = BundleLimits { max_gas: 45_000, ..Default::default() };
+        let small = BlockBuilder::new(&FeeGreedy, limits).build(&pool, &state);
+        assert_eq!(order(&small), vec![(2, 0), (1, 0)]);
+        assert_eq!(small.gas, 42_000);
+        assert_eq!(small.hashes[0], small.transactions[0].hash());
+
+        let halted = BlockBuilder::new(&FeeGreedy, BundleLimits::default())
+            .with_halted_modules(["transfers".to_string()].into_iter().collect())
+            .build(&pool, &state);
+        assert!(halted.transactions.is_empty());
+    }
+}
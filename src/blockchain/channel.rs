@@ -0,0 +1,402 @@
+//! Payment channels for streaming micro-payments.
+//!
+//! Two parties lock deposits on chain and then pay each other off chain by
+//! signing `BalanceUpdate`s, each with a higher sequence than the last. Only
+//! opening and closing touch the chain. A channel closes either cooperatively,
+//! with a final update signed by the counterparty, or unilaterally: the closer
+//! submits the latest update the counterparty signed and the balances are paid
+//! out when the dispute period ends. Until then anyone holding a newer update
+//! signed by the closer can replace the stale one, which is how a `Watchtower`
+//! protects a party that is offline.
+//!
+//! A channel where only the opener deposits is unidirectional: the opener
+//! signs each update and the counterparty, who only receives, closes with the
+//! latest one.
+
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, BTreeSet};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use crate::blockchain::types::{hex_serde, Address};
+use crate::crypto::domain::{PayloadKind, SigningDomain};
+
+/// Account holding the deposits of every open channel
+pub const CHANNEL_ESCROW_ADDRESS: Address = *b"qmv:channel:escrow::::::::::::::";
+
+/// Shortest and longest dispute period, in blocks
+pub const MIN_DISPUTE_BLOCKS: u64 = 10;
+pub const MAX_DISPUTE_BLOCKS: u64 = 100_000;
+
+/// Most closes paid out at the start of one block; the rest are paid in
+/// later blocks, oldest first
+pub const MAX_SETTLEMENTS_PER_BLOCK: usize = 100;
+
+/// Most channels one watchtower holds updates for
+pub const MAX_WATCHED_CHANNELS: usize = 10_000;
+
+/// ID of the channel opened by `opener` at `nonce`
+pub fn channel_id(opener: &Address, nonce: u64) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"qmv:channel:");
+    hasher.update(opener);
+    hasher.update(&nonce.to_le_bytes());
+    hasher.finalize().into()
+}
+
+/// Off-chain split of a channel's deposits
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BalanceUpdate {
+    #[serde(with = "hex_serde")]
+    pub channel: [u8; 32],
+    /// Higher sequences supersede lower ones; 0 is the opening split
+    pub sequence: u64,
+    pub balance_a: u128,
+    pub balance_b: u128,
+    /// Both parties agree to close on this split; only final updates settle
+    /// a channel at once
+    #[serde(default)]
+    pub is_final: bool,
+}
+
+impl BalanceUpdate {
+    /// Bytes a party signs, bound to the main chain of `network_id`
+    pub fn signing_bytes(&self, network_id: u64) -> Vec<u8> {
+        let body = bincode::serialize(&(&self.channel, self.balance_a, self.balance_b, self.is_final)).unwrap_or_default();
+        SigningDomain::main_chain(network_id).payload(PayloadKind::PaymentChannel, self.sequence, &body)
+    }
+}
+
+/// Balance update with one party's signature
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignedUpdate {
+    pub update: BalanceUpdate,
+    #[serde(with = "hex_serde")]
+    pub signer: Address,
+    #[serde(with = "hex_serde")]
+    pub signature: Vec<u8>,
+}
+
+impl SignedUpdate {
+    pub fn sign(update: BalanceUpdate, network_id: u64, key: &SigningKey) -> Self {
+        let signature = key.sign(&update.signing_bytes(network_id)).to_bytes().to_vec();
+        Self { update, signer: key.verifying_key().to_bytes(), signature }
+    }
+
+    fn verify(&self, network_id: u64) -> Result<(), &'static str> {
+        let key = VerifyingKey::from_bytes(&self.signer).map_err(|_| "Invalid signer key")?;
+        let signature: [u8; 64] = self.signature.as_slice().try_into()
+            .map_err(|_| "Invalid update signature")?;
+        key.verify(&self.update.signing_bytes(network_id), &Signature::from_bytes(&signature))
+            .map_err(|_| "Invalid update signature")
+    }
+}
+
+/// Unilateral close waiting out its dispute period
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Closing {
+    #[serde(with = "hex_serde")]
+    pub closer: Address,
+    pub sequence: u64,
+    pub balance_a: u128,
+    pub balance_b: u128,
+    /// Height at which the balances are paid out
+    pub settles_at: u64,
+}
+
+/// Open channel; its deposits are held by `CHANNEL_ESCROW_ADDRESS`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Channel {
+    /// Opener
+    #[serde(with = "hex_serde")]
+    pub party_a: Address,
+    #[serde(with = "hex_serde")]
+    pub party_b: Address,
+    pub deposit_a: u128,
+    pub deposit_b: u128,
+    pub dispute_blocks: u64,
+    #[serde(default)]
+    pub closing: Option<Closing>,
+}
+
+impl Channel {
+    pub fn open(opener: Address, counterparty: Address, deposit: u128, dispute_blocks: u64) -> Result<Self, &'static str> {
+        if opener == counterparty {
+            return Err("Cannot open a channel to yourself");
+        }
+        if deposit == 0 {
+            return Err("Channel deposit must be positive");
+        }
+        if !(MIN_DISPUTE_BLOCKS..=MAX_DISPUTE_BLOCKS).contains(&dispute_blocks) {
+            return Err("Dispute period must be between 10 and 100,000 blocks");
+        }
+        Ok(Self { party_a: opener, party_b: counterparty, deposit_a: deposit, deposit_b: 0, dispute_blocks, closing: None })
+    }
+
+    /// Sum of both deposits, which every update must split exactly
+    pub fn total(&self) -> u128 {
+        self.deposit_a + self.deposit_b
+    }
+
+    /// The other party, or `None` if `party` is not in the channel
+    pub fn counterparty(&self, party: &Address) -> Option<Address> {
+        if *party == self.party_a {
+            Some(self.party_b)
+        } else if *party == self.party_b {
+            Some(self.party_a)
+        } else {
+            None
+        }
+    }
+
+    /// Add to `party`'s deposit
+    pub fn add_deposit(&mut self, party: &Address, amount: u128) -> Result<(), &'static str> {
+        if self.closing.is_some() {
+            return Err("Channel is closing");
+        }
+        self.total().checked_add(amount).ok_or("Channel deposit overflow")?;
+        if *party == self.party_a {
+            self.deposit_a += amount;
+        } else if *party == self.party_b {
+            self.deposit_b += amount;
+        } else {
+            return Err("Not a party to the channel");
+        }
+        Ok(())
+    }
+
+    /// Check that `update` is for channel `id`, splits its deposits exactly
+    /// and carries a valid signature from `signer`
+    pub fn check_update(&self, id: &[u8; 32], update: &SignedUpdate, signer: &Address, network_id: u64) -> Result<(), &'static str> {
+        if update.update.channel != *id {
+            return Err("Update is for another channel");
+        }
+        if update.signer != *signer {
+            return Err("Update is not signed by the expected party");
+        }
+        if update.update.balance_a.checked_add(update.update.balance_b) != Some(self.total()) {
+            return Err("Update does not split the channel's deposits");
+        }
+        update.verify(network_id)
+    }
+
+    /// Start the dispute period on `update`'s split, or on the deposits if there is none
+    pub fn start_close(&mut self, closer: Address, update: Option<&BalanceUpdate>, height: u64) {
+        let (sequence, balance_a, balance_b) = match update {
+            Some(update) => (update.sequence, update.balance_a, update.balance_b),
+            None => (0, self.deposit_a, self.deposit_b),
+        };
+        self.closing = Some(Closing { closer, sequence, balance_a, balance_b, settles_at: height + self.dispute_blocks });
+    }
+}
+
+/// Channel operations
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelAction {
+    /// Open a channel to `counterparty` with a deposit from the sender. The
+    /// ID is derived from sender and nonce.
+    Open {
+        #[serde(with = "hex_serde")]
+        counterparty: Address,
+        deposit: u128,
+        dispute_blocks: u64,
+    },
+    /// Add to the sender's deposit. Updates signed before it no longer split
+    /// the deposits exactly, so the parties sign a new one.
+    Deposit {
+        #[serde(with = "hex_serde")]
+        channel: [u8; 32],
+        amount: u128,
+    },
+    /// Close at once on a final update signed by the counterparty
+    Settle {
+        update: SignedUpdate,
+    },
+    /// Start the dispute period on the counterparty's latest signed update,
+    /// or on the deposits if there is none
+    Close {
+        #[serde(with = "hex_serde")]
+        channel: [u8; 32],
+        #[serde(default)]
+        update: Option<SignedUpdate>,
+    },
+    /// Replace a pending close with a newer update signed by the closer.
+    /// Anyone may submit it.
+    Dispute {
+        update: SignedUpdate,
+    },
+}
+
+impl ChannelAction {
+    pub fn channel(&self) -> Option<&[u8; 32]> {
+        match self {
+            Self::Open { .. } => None,
+            Self::Deposit { channel, .. } | Self::Close { channel, .. } => Some(channel),
+            Self::Settle { update } | Self::Dispute { update } => Some(&update.update.channel),
+        }
+    }
+
+    /// Signed updates the chain verifies
+    pub fn signed_updates(&self) -> u64 {
+        match self {
+            Self::Settle { .. } | Self::Dispute { .. } | Self::Close { update: Some(_), .. } => 1,
+            Self::Open { .. } | Self::Deposit { .. } | Self::Close { update: None, .. } => 0,
+        }
+    }
+}
+
+/// Open channels by ID, with pending closes queued by settlement height
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Channels {
+    channels: BTreeMap<[u8; 32], Channel>,
+    queue: BTreeSet<(u64, [u8; 32])>,
+}
+
+impl Channels {
+    pub fn get(&self, id: &[u8; 32]) -> Option<&Channel> {
+        self.channels.get(id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8; 32], &Channel)> {
+        self.channels.iter()
+    }
+
+    /// Channels `account` is a party to
+    pub fn by_party<'a>(&'a self, account: &'a Address) -> impl Iterator<Item = (&'a [u8; 32], &'a Channel)> + 'a {
+        self.channels.iter().filter(move |(_, channel)| channel.counterparty(account).is_some())
+    }
+
+    /// Channels whose close settles at `height`, oldest first, at most `limit`
+    pub fn due(&self, height: u64, limit: usize) -> Vec<[u8; 32]> {
+        self.queue.iter()
+            .take_while(|(settles_at, _)| *settles_at <= height)
+            .take(limit)
+            .map(|(_, id)| *id)
+            .collect()
+    }
+
+    /// Set a channel to a journaled value; `None` removes it
+    pub(crate) fn restore(&mut self, id: [u8; 32], channel: Option<Channel>) {
+        if let Some(closing) = self.channels.remove(&id).and_then(|previous| previous.closing) {
+            self.queue.remove(&(closing.settles_at, id));
+        }
+        if let Some(channel) = channel {
+            if let Some(closing) = &channel.closing {
+                self.queue.insert((closing.settles_at, id));
+            }
+            self.channels.insert(id, channel);
+        }
+    }
+}
+
+/// Close that a watched update shows to be stale
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StaleClose {
+    #[serde(with = "hex_serde")]
+    pub channel: [u8; 32],
+    pub closed_at_sequence: u64,
+    pub settles_at: u64,
+    /// `dispute` action to submit before `settles_at`
+    pub dispute: ChannelAction,
+}
+
+/// Watchtower
+/// Holds the newest update each party handed over, signed by its
+/// counterparty, and finds closes made on an older one so a dispute can be
+/// submitted while the party is offline.
+#[derive(Debug, Default)]
+pub struct Watchtower {
+    updates: BTreeMap<([u8; 32], Address), SignedUpdate>,
+}
+
+impl Watchtower {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep `update` for its channel if it is valid and newer than the one held
+    pub fn watch(&mut self, channel: &Channel, update: SignedUpdate, network_id: u64) -> Result<(), &'static str> {
+        if channel.counterparty(&update.signer).is_none() {
+            return Err("Not a party to the channel");
+        }
+        channel.check_update(&update.update.channel, &update, &update.signer, network_id)?;
+        let key = (update.update.channel, update.signer);
+        match self.updates.get(&key) {
+            Some(held) if held.update.sequence >= update.update.sequence => {
+                return Err("A newer update is already watched");
+            }
+            None if self.updates.len() >= MAX_WATCHED_CHANNELS => return Err("Watchtower is full"),
+            _ => {}
+        }
+        // Keyed by signer: only the closer's own updates can dispute a close
+        self.updates.insert(key, update);
+        Ok(())
+    }
+
+    pub fn watched(&self, id: &[u8; 32]) -> Vec<&SignedUpdate> {
+        self.updates.range((*id, [0u8; 32])..=(*id, [0xff; 32])).map(|(_, update)| update).collect()
+    }
+
+    /// Closes in `channels` made on a split older than a watched update
+    /// signed by the closer. Updates for channels that no longer exist are dropped.
+    pub fn check(&mut self, channels: &Channels) -> Vec<StaleClose> {
+        self.updates.retain(|(id, _), _| channels.get(id).is_some());
+        channels.iter()
+            .filter_map(|(id, channel)| {
+                let closing = channel.closing.as_ref()?;
+                let newer = self.updates.get(&(*id, closing.closer))
+                    .filter(|update| update.update.sequence > closing.sequence)?;
+                Some(StaleClose {
+                    channel: *id,
+                    closed_at_sequence: closing.sequence,
+                    settles_at: closing.settles_at,
+                    dispute: ChannelAction::Dispute { update: newer.clone() },
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_updates_and_watchtower() {
+        let a = SigningKey::from_bytes(&[1u8; 32]);
+        let b = SigningKey::from_bytes(&[2u8; 32]);
+        let (a_addr, b_addr) = (a.verifying_key().to_bytes(), b.verifying_key().to_bytes());
+        let id = channel_id(&a_addr, 0);
+        let mut channel = Channel::open(a_addr, b_addr, 100, 10).unwrap();
+        assert!(Channel::open(a_addr, a_addr, 100, 10).is_err());
+        assert!(Channel::open(a_addr, b_addr, 100, 5).is_err());
+
+        let update = |sequence, balance_a, balance_b| BalanceUpdate { channel: id, sequence, balance_a, balance_b, is_final: false };
+        let signed = SignedUpdate::sign(update(3, 70, 30), 1, &a);
+        assert!(channel.check_update(&id, &signed, &a_addr, 1).is_ok());
+        assert_eq!(channel.check_update(&id, &signed, &b_addr, 1), Err("Update is not signed by the expected party"));
+        assert_eq!(channel.check_update(&id, &signed, &a_addr, 2), Err("Invalid update signature"));
+        let unbalanced = SignedUpdate::sign(update(4, 70, 40), 1, &a);
+        assert_eq!(channel.check_update(&id, &unbalanced, &a_addr, 1), Err("Update does not split the channel's deposits"));
+
+        // A closes on the opening split; B's watchtower holds A's update at 3
+        let mut tower = Watchtower::new();
+        tower.watch(&channel, signed.clone(), 1).unwrap();
+        assert!(tower.watch(&channel, SignedUpdate::sign(update(2, 80, 20), 1, &a), 1).is_err());
+        channel.start_close(a_addr, None, 50);
+        let mut channels = Channels::default();
+        channels.restore(id, Some(channel.clone()));
+        assert_eq!(channels.due(59, 10), Vec::<[u8; 32]>::new());
+        assert_eq!(channels.due(60, 10), vec![id]);
+
+        let stale = tower.check(&channels);
+        assert_eq!(stale.len(), 1);
+        assert_eq!(stale[0].closed_at_sequence, 0);
+        assert_eq!(stale[0].dispute, ChannelAction::Dispute { update: signed });
+
+        // Once the channel is gone its updates are dropped
+        channels.restore(id, None);
+        assert!(channels.due(60, 10).is_empty());
+        assert!(tower.check(&channels).is_empty());
+        assert!(tower.watched(&id).is_empty());
+    }
+}
@@ -0,0 +1,395 @@
+//! Emergency halts of block production, bridge withdrawals or single
+//! transaction modules.
+//!
+//! A halt takes effect once more than two thirds of the active validators
+//! have signed a vote for it, or when a governance policy issues the
+//! `circuit_breaker.halt` action, for example on a failed proof of reserve
+//! (`bridge.reserve_ok` of 0). Resuming always takes a validator
+//! supermajority voting to resume that specific halt, so an automated
+//! policy can stop the chain but never restart it. Votes name the breaker's
+//! sequence number, which moves on with every halt and resume, so a vote
+//! cannot be replayed against a later state. Every halt is kept on record
+//! with what triggered it, its reason, and the heights it started and ended.
+
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, BTreeSet};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use crate::blockchain::transaction::TransactionAction;
+use crate::blockchain::types::{hex_serde, hex_serde_vec, Address};
+use crate::blockchain::validator_keys::ValidatorKeys;
+use crate::crypto::domain::{PayloadKind, SigningDomain};
+use crate::governance::ai_governance::Action;
+
+/// Custom governance action halting a scope; the payload is the
+/// bincode-encoded `(HaltScope, String)` of scope and reason
+pub const HALT_ACTION: &str = "circuit_breaker.halt";
+
+/// Ended halts kept on record; the oldest are dropped beyond this
+pub const MAX_HALT_RECORDS: usize = 1_024;
+
+/// Longest reason accepted with a halt
+pub const MAX_REASON_LEN: usize = 256;
+
+/// Transaction modules that can be halted one at a time
+pub const MODULES: [&str; 13] = [
+    "transfers", "contracts", "validator_keys", "multisig", "scheduler",
+    "assets", "market", "lease", "confidential", "channels", "htlc", "sponsor",
+    "account_validation",
+];
+
+/// Module a transaction belongs to, as named in `MODULES`
+pub fn module_of(action: &TransactionAction) -> &'static str {
+    match action {
+        TransactionAction::Transfer { .. } => "transfers",
+        TransactionAction::Deploy { .. } | TransactionAction::Call { .. } => "contracts",
+        TransactionAction::RotateValidatorKey(_) => "validator_keys",
+        TransactionAction::CreateMultisig { .. } | TransactionAction::MultisigExecute { .. } => "multisig",
+        TransactionAction::Schedule { .. } | TransactionAction::CancelSchedule { .. } => "scheduler",
+        TransactionAction::Asset(_) => "assets",
+        TransactionAction::Market(_) => "market",
+        TransactionAction::Lease(_) => "lease",
+        TransactionAction::Confidential(_) => "confidential",
+        TransactionAction::Channel(_) => "channels",
+        TransactionAction::Htlc(_) => "htlc",
+        TransactionAction::Sponsor(_) => "sponsor",
+        TransactionAction::Validation(_) => "account_validation",
+    }
+}
+
+/// What a halt stops
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HaltScope {
+    /// Sealing new blocks
+    BlockProduction,
+    /// Releasing bridge collateral
+    BridgeWithdrawals,
+    /// Including transactions of one module in new blocks
+    Module(String),
+}
+
+impl HaltScope {
+    fn validate(&self) -> Result<(), &'static str> {
+        match self {
+            HaltScope::Module(name) if !MODULES.contains(&name.as_str()) => Err("Unknown module"),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Change validators vote on
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HaltProposal {
+    Halt { scope: HaltScope, reason: String },
+    /// End the halt with this record ID
+    Resume { halt: u64 },
+}
+
+/// A validator's signed vote on a proposal at breaker sequence `sequence`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HaltVote {
+    #[serde(with = "hex_serde")]
+    pub validator: Address,
+    pub sequence: u64,
+    pub proposal: HaltProposal,
+    #[serde(with = "hex_serde")]
+    pub signature: Vec<u8>,
+}
+
+impl HaltVote {
+    /// Bytes the validator signs
+    pub fn signing_bytes(network_id: u64, sequence: u64, proposal: &HaltProposal) -> Vec<u8> {
+        let body = bincode::serialize(proposal).unwrap_or_default();
+        SigningDomain::main_chain(network_id).payload(PayloadKind::CircuitBreaker, sequence, &body)
+    }
+}
+
+/// Who put a halt in place
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HaltTrigger {
+    Validators(#[serde(with = "hex_serde_vec")] Vec<Address>),
+    Governance,
+    /// This node's post-block invariant checks failed
+    InvariantCheck,
+}
+
+/// One halt, active until `resumed_at` is set
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HaltRecord {
+    pub id: u64,
+    pub scope: HaltScope,
+    pub reason: String,
+    pub trigger: HaltTrigger,
+    pub halted_at: u64,
+    #[serde(default)]
+    pub resumed_at: Option<u64>,
+    /// Validators whose votes ended the halt
+    #[serde(with = "hex_serde_vec", default)]
+    pub resumed_by: Vec<Address>,
+}
+
+/// Circuit Breaker
+/// Active halts, their history, and validator votes toward the next change.
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    network_id: u64,
+    sequence: u64,
+    next_id: u64,
+    /// Active halts by scope, to the ID of their record
+    halted: BTreeMap<HaltScope, u64>,
+    records: BTreeMap<u64, HaltRecord>,
+    /// Votes at the current sequence, by proposal
+    votes: BTreeMap<HaltProposal, BTreeMap<Address, HaltVote>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(network_id: u64) -> Self {
+        Self {
+            network_id,
+            sequence: 0,
+            next_id: 0,
+            halted: BTreeMap::new(),
+            records: BTreeMap::new(),
+            votes: BTreeMap::new(),
+        }
+    }
+
+    /// Sequence number new votes must name
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    pub fn is_halted(&self, scope: &HaltScope) -> bool {
+        self.halted.contains_key(scope)
+    }
+
+    /// Modules whose transactions are held out of new blocks
+    pub fn halted_modules(&self) -> BTreeSet<String> {
+        self.halted.keys()
+            .filter_map(|scope| match scope {
+                HaltScope::Module(name) => Some(name.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Halts in effect
+    pub fn active(&self) -> Vec<&HaltRecord> {
+        self.halted.values().filter_map(|id| self.records.get(id)).collect()
+    }
+
+    pub fn record(&self, id: u64) -> Option<&HaltRecord> {
+        self.records.get(&id)
+    }
+
+    /// Every halt on record, oldest first
+    pub fn records(&self) -> impl Iterator<Item = &HaltRecord> {
+        self.records.values()
+    }
+
+    /// Votes cast at the current sequence and how many each proposal has
+    pub fn pending(&self) -> Vec<(&HaltProposal, usize)> {
+        self.votes.iter().map(|(proposal, votes)| (proposal, votes.len())).collect()
+    }
+
+    /// Count a vote submitted at `height`. Returns the record the vote
+    /// created or ended if it completed a supermajority of `active`.
+    pub fn vote(
+        &mut self,
+        vote: HaltVote,
+        active: &BTreeSet<Address>,
+        keys: &ValidatorKeys,
+        height: u64,
+    ) -> Result<Option<&HaltRecord>, &'static str> {
+        if vote.sequence != self.sequence {
+            return Err("Vote is for a different breaker sequence");
+        }
+        if !active.contains(&vote.validator) {
+            return Err("Not an active validator");
+        }
+        self.check_proposal(&vote.proposal)?;
+        let key = keys.key_at(&vote.validator, height).ok_or("Unknown validator")?;
+        let key = VerifyingKey::from_bytes(&key).map_err(|_| "Invalid validator key")?;
+        let signature = Signature::from_slice(&vote.signature).map_err(|_| "Invalid vote signature")?;
+        key.verify(&HaltVote::signing_bytes(self.network_id, vote.sequence, &vote.proposal), &signature)
+            .map_err(|_| "Invalid vote signature")?;
+
+        let proposal = vote.proposal.clone();
+        let votes = self.votes.entry(proposal.clone()).or_default();
+        if votes.contains_key(&vote.validator) {
+            return Err("Validator already voted");
+        }
+        votes.insert(vote.validator, vote);
+        // Only votes from validators still active count toward the supermajority
+        let voters: Vec<Address> = votes.keys().filter(|validator| active.contains(*validator)).copied().collect();
+        if voters.len() * 3 <= active.len() * 2 {
+            return Ok(None);
+        }
+
+        let id = match proposal {
+            HaltProposal::Halt { scope, reason } => self.halt(scope, reason, HaltTrigger::Validators(voters), height),
+            HaltProposal::Resume { halt } => self.resume(halt, voters, height)?,
+        };
+        Ok(self.records.get(&id))
+    }
+
+    /// Halt on a `HALT_ACTION` from the governance tally at `height`.
+    /// Returns whether the action was for the breaker and changed anything.
+    pub fn apply_governance(&mut self, action: &Action, height: u64) -> Result<bool, &'static str> {
+        let Action::Custom(name, payload) = action else { return Ok(false) };
+        if name != HALT_ACTION {
+            return Ok(false);
+        }
+        let (scope, reason): (HaltScope, String) = bincode::deserialize(payload)
+            .map_err(|_| "Invalid halt payload")?;
+        if self.is_halted(&scope) {
+            return Ok(false);
+        }
+        self.check_proposal(&HaltProposal::Halt { scope: scope.clone(), reason: reason.clone() })?;
+        self.halt(scope, reason, HaltTrigger::Governance, height);
+        Ok(true)
+    }
+
+    /// Halt `scope` because this node's invariant checks failed at
+    /// `height`. Returns the record ID, or `None` if already halted.
+    pub fn trip(&mut self, scope: HaltScope, reason: String, height: u64) -> Option<u64> {
+        if self.is_halted(&scope) {
+            return None;
+        }
+        let reason = reason.chars().take(MAX_REASON_LEN).collect();
+        Some(self.halt(scope, reason, HaltTrigger::InvariantCheck, height))
+    }
+
+    fn check_proposal(&self, proposal: &HaltProposal) -> Result<(), &'static str> {
+        match proposal {
+            HaltProposal::Halt { scope, reason } => {
+                scope.validate()?;
+                if reason.len() > MAX_REASON_LEN {
+                    return Err("Halt reason too long");
+                }
+                if self.is_halted(scope) {
+                    return Err("Scope already halted");
+                }
+                Ok(())
+            }
+            HaltProposal::Resume { halt } => match self.records.get(halt) {
+                Some(record) if record.resumed_at.is_none() => Ok(()),
+                Some(_) => Err("Halt already resumed"),
+                None => Err("Halt not found"),
+            },
+        }
+    }
+
+    fn halt(&mut self, scope: HaltScope, reason: String, trigger: HaltTrigger, height: u64) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.halted.insert(scope.clone(), id);
+        self.records.insert(id, HaltRecord {
+            id,
+            scope,
+            reason,
+            trigger,
+            halted_at: height,
+            resumed_at: None,
+            resumed_by: Vec::new(),
+        });
+        self.advance();
+        id
+    }
+
+    fn resume(&mut self, id: u64, voters: Vec<Address>, height: u64) -> Result<u64, &'static str> {
+        let record = self.records.get_mut(&id).ok_or("Halt not found")?;
+        record.resumed_at = Some(height);
+        record.resumed_by = voters;
+        self.halted.remove(&record.scope);
+        self.advance();
+        Ok(id)
+    }
+
+    /// Move to the next sequence, voiding outstanding votes, and drop the
+    /// oldest ended halts beyond the record limit
+    fn advance(&mut self) {
+        self.sequence += 1;
+        self.votes.clear();
+        while self.records.len() > MAX_HALT_RECORDS {
+            let oldest = self.records.values().find(|record| record.resumed_at.is_some()).map(|record| record.id);
+            match oldest {
+                Some(id) => { self.records.remove(&id); }
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    const NETWORK: u64 = 7;
+
+    fn validators() -> (Vec<SigningKey>, BTreeSet<Address>, ValidatorKeys) {
+        let signers: Vec<SigningKey> = (1..=4u8).map(|seed| SigningKey::from_bytes(&[seed; 32])).collect();
+        let mut keys = ValidatorKeys::new();
+        for signer in &signers {
+            let key = signer.verifying_key().to_bytes();
+            keys.register(key, key, 0).unwrap();
+        }
+        let active = signers.iter().map(|signer| signer.verifying_key().to_bytes()).collect();
+        (signers, active, keys)
+    }
+
+    fn vote(signer: &SigningKey, sequence: u64, proposal: HaltProposal) -> HaltVote {
+        HaltVote {
+            validator: signer.verifying_key().to_bytes(),
+            sequence,
+            signature: signer.sign(&HaltVote::signing_bytes(NETWORK, sequence, &proposal)).to_bytes().to_vec(),
+            proposal,
+        }
+    }
+
+    #[test]
+    fn test_supermajority_halts_and_resumes() {
+        let (signers, active, keys) = validators();
+        let mut breaker = CircuitBreaker::new(NETWORK);
+        let halt = HaltProposal::Halt { scope: HaltScope::Module("market".to_string()), reason: "listing exploit".to_string() };
+
+        assert!(breaker.vote(vote(&signers[0], 0, halt.clone()), &active, &keys, 10).unwrap().is_none());
+        assert_eq!(breaker.vote(vote(&signers[0], 0, halt.clone()), &active, &keys, 10).unwrap_err(), "Validator already voted");
+        // Two of four is not more than two thirds
+        assert!(breaker.vote(vote(&signers[1], 0, halt.clone()), &active, &keys, 10).unwrap().is_none());
+        let record = breaker.vote(vote(&signers[2], 0, halt.clone()), &active, &keys, 11).unwrap().unwrap().clone();
+        assert_eq!(record.halted_at, 11);
+        assert!(breaker.is_halted(&HaltScope::Module("market".to_string())));
+        assert_eq!(breaker.halted_modules().len(), 1);
+
+        // Votes from before the halt cannot be replayed
+        assert!(breaker.vote(vote(&signers[3], 0, halt), &active, &keys, 12).is_err());
+
+        let resume = HaltProposal::Resume { halt: record.id };
+        for signer in &signers[..2] {
+            breaker.vote(vote(signer, 1, resume.clone()), &active, &keys, 20).unwrap();
+        }
+        let ended = breaker.vote(vote(&signers[3], 1, resume), &active, &keys, 21).unwrap().unwrap();
+        assert_eq!(ended.resumed_at, Some(21));
+        assert!(breaker.active().is_empty());
+        assert_eq!(breaker.records().count(), 1);
+    }
+
+    #[test]
+    fn test_governance_halts_but_cannot_resume() {
+        let mut breaker = CircuitBreaker::new(NETWORK);
+        let payload = bincode::serialize(&(HaltScope::BridgeWithdrawals, "supply mismatch".to_string())).unwrap();
+        let action = Action::Custom(HALT_ACTION.to_string(), payload);
+        assert!(breaker.apply_governance(&action, 100).unwrap());
+        assert!(!breaker.apply_governance(&action, 101).unwrap());
+        assert_eq!(breaker.active()[0].trigger, HaltTrigger::Governance);
+        assert!(breaker.is_halted(&HaltScope::BridgeWithdrawals));
+
+        let unknown = bincode::serialize(&(HaltScope::Module("mining".to_string()), String::new())).unwrap();
+        assert!(breaker.apply_governance(&Action::Custom(HALT_ACTION.to_string(), unknown), 102).is_err());
+        assert!(!breaker.apply_governance(&Action::Custom("other".to_string(), Vec::new()), 102).unwrap());
+    }
+}
@@ -0,0 +1,355 @@
+//! Commit-reveal transaction ordering.
+//!
+//! Senders first submit a commitment: a digest of their signed transaction
+//! and a random salt, signed by the sender. The block at height `N` fixes the
+//! order of the commitments it includes while their contents are still
+//! hidden. Senders then reveal the transaction and salt, and blocks after `N`
+//! must execute revealed transactions in the committed order. Producers sign
+//! both orders, so one that reorders reveals by their contents leaves
+//! evidence anyone can check against the validator keys.
+
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, HashSet};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use crate::blockchain::transaction::Transaction;
+use crate::blockchain::types::{hex_serde, hex_serde_vec, Address};
+use crate::blockchain::validator_keys::ValidatorKeys;
+use crate::crypto::domain::{PayloadKind, SigningDomain};
+
+/// Commit-reveal settings of one layer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CommitRevealConfig {
+    /// Blocks after the sealing block in which a commitment can be revealed
+    pub reveal_window: u64,
+    /// Commitments sealed into one block; the rest wait for the next
+    pub max_per_block: usize,
+    /// Unsealed commitments held at once
+    pub max_pending: usize,
+}
+
+impl Default for CommitRevealConfig {
+    fn default() -> Self {
+        Self { reveal_window: 10, max_per_block: 1_000, max_pending: 10_000 }
+    }
+}
+
+/// Digest a sender commits to: the signed transaction plus a secret salt
+pub fn commitment_digest(tx: &Transaction, salt: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"qmv:commitment:");
+    hasher.update(&tx.signing_bytes());
+    hasher.update(&tx.signature);
+    hasher.update(salt);
+    hasher.finalize().into()
+}
+
+/// Sender-signed commitment to a transaction whose contents stay hidden
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Commitment {
+    #[serde(with = "hex_serde")]
+    pub sender: Address,
+    #[serde(with = "hex_serde")]
+    pub digest: [u8; 32],
+    #[serde(with = "hex_serde")]
+    pub signature: Vec<u8>,
+}
+
+impl Commitment {
+    pub fn sign(domain: &SigningDomain, key: &SigningKey, digest: [u8; 32]) -> Self {
+        let sender = key.verifying_key().to_bytes();
+        let signature = key.sign(&Self::signing_bytes(domain, &sender, &digest)).to_bytes().to_vec();
+        Self { sender, digest, signature }
+    }
+
+    pub fn verify(&self, domain: &SigningDomain) -> Result<(), &'static str> {
+        let key = VerifyingKey::from_bytes(&self.sender).map_err(|_| "Invalid sender public key")?;
+        let signature: [u8; 64] = self.signature.as_slice().try_into()
+            .map_err(|_| "Invalid commitment signature")?;
+        key.verify(&Self::signing_bytes(domain, &self.sender, &self.digest), &Signature::from_bytes(&signature))
+            .map_err(|_| "Invalid commitment signature")
+    }
+
+    fn signing_bytes(domain: &SigningDomain, sender: &Address, digest: &[u8; 32]) -> Vec<u8> {
+        let mut body = sender.to_vec();
+        body.extend_from_slice(digest);
+        domain.payload(PayloadKind::Commitment, 0, &body)
+    }
+}
+
+/// Which order a producer signed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderKind {
+    /// Commitments sealed into a block
+    Committed,
+    /// Revealed transactions executed in a block
+    Executed,
+}
+
+/// Order of commitment digests signed by a block's producer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignedOrder {
+    #[serde(with = "hex_serde")]
+    pub proposer: Address,
+    pub height: u64,
+    pub kind: OrderKind,
+    #[serde(with = "hex_serde_vec")]
+    pub digests: Vec<[u8; 32]>,
+    #[serde(with = "hex_serde")]
+    pub signature: Vec<u8>,
+}
+
+impl SignedOrder {
+    pub fn sign(
+        domain: &SigningDomain,
+        proposer: Address,
+        key: &SigningKey,
+        height: u64,
+        kind: OrderKind,
+        digests: Vec<[u8; 32]>,
+    ) -> Self {
+        let mut order = Self { proposer, height, kind, digests, signature: Vec::new() };
+        order.signature = key.sign(&order.signing_bytes(domain)).to_bytes().to_vec();
+        order
+    }
+
+    pub fn signing_bytes(&self, domain: &SigningDomain) -> Vec<u8> {
+        let body = bincode::serialize(&(&self.proposer, self.kind, &self.digests)).unwrap_or_default();
+        domain.payload(PayloadKind::Ordering, self.height, &body)
+    }
+
+    /// Check the signature with the proposer's validator key at the order's height
+    pub fn verify(&self, domain: &SigningDomain, keys: &ValidatorKeys) -> Result<(), &'static str> {
+        keys.verify_at(&self.proposer, self.height, &self.signing_bytes(domain), &self.signature)
+    }
+}
+
+/// Evidence that a producer executed reveals out of their committed order
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderingViolation {
+    pub committed: SignedOrder,
+    pub executed: SignedOrder,
+}
+
+impl OrderingViolation {
+    /// Check the evidence and return the offending producer
+    pub fn verify(&self, domain: &SigningDomain, keys: &ValidatorKeys, reveal_window: u64) -> Result<Address, &'static str> {
+        if self.committed.kind != OrderKind::Committed || self.executed.kind != OrderKind::Executed {
+            return Err("Evidence needs a committed and an executed order");
+        }
+        if self.executed.height <= self.committed.height
+            || self.executed.height > self.committed.height.saturating_add(reveal_window)
+        {
+            return Err("Executed order is outside the commitments' reveal window");
+        }
+        self.committed.verify(domain, keys)?;
+        self.executed.verify(domain, keys)?;
+
+        let position: BTreeMap<&[u8; 32], usize> = self.committed.digests.iter()
+            .enumerate()
+            .map(|(index, digest)| (digest, index))
+            .collect();
+        let positions: Vec<usize> = self.executed.digests.iter()
+            .filter_map(|digest| position.get(digest).copied())
+            .collect();
+        if positions.windows(2).all(|pair| pair[0] < pair[1]) {
+            return Err("Executed order follows the committed order");
+        }
+        Ok(self.executed.proposer)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SealedCommitment {
+    commitment: Commitment,
+    reveal: Option<Transaction>,
+}
+
+/// Commit-Reveal Queue
+/// One layer's commitments, from submission through sealing to reveal.
+/// Commitments not revealed within the window are dropped.
+pub struct CommitRevealQueue {
+    domain: SigningDomain,
+    config: CommitRevealConfig,
+    pending: Vec<Commitment>,
+    /// Sealed commitments by the height of the block that fixed their order
+    sealed: BTreeMap<u64, Vec<SealedCommitment>>,
+    digests: HashSet<[u8; 32]>,
+    /// Offending producers with the evidence against them
+    violations: Vec<(Address, OrderingViolation)>,
+}
+
+impl CommitRevealQueue {
+    pub fn new(domain: SigningDomain, config: CommitRevealConfig) -> Self {
+        Self {
+            domain,
+            config,
+            pending: Vec::new(),
+            sealed: BTreeMap::new(),
+            digests: HashSet::new(),
+            violations: Vec::new(),
+        }
+    }
+
+    pub fn domain(&self) -> &SigningDomain {
+        &self.domain
+    }
+
+    /// Accept a commitment for the next block
+    pub fn submit(&mut self, commitment: Commitment) -> Result<(), &'static str> {
+        commitment.verify(&self.domain)?;
+        if self.pending.len() >= self.config.max_pending {
+            return Err("Too many pending commitments");
+        }
+        if !self.digests.insert(commitment.digest) {
+            return Err("Commitment already submitted");
+        }
+        self.pending.push(commitment);
+        Ok(())
+    }
+
+    /// Digests the next block would seal, in order
+    pub fn pending(&self) -> Vec<[u8; 32]> {
+        self.pending.iter().take(self.config.max_per_block).map(|c| c.digest).collect()
+    }
+
+    /// Fix the order of pending commitments in the block at `height`
+    pub fn seal(&mut self, height: u64) -> Vec<[u8; 32]> {
+        let count = self.pending.len().min(self.config.max_per_block);
+        let batch: Vec<SealedCommitment> = self.pending.drain(..count)
+            .map(|commitment| SealedCommitment { commitment, reveal: None })
+            .collect();
+        let digests = batch.iter().map(|entry| entry.commitment.digest).collect();
+        if !batch.is_empty() {
+            self.sealed.entry(height).or_default().extend(batch);
+        }
+        digests
+    }
+
+    /// Open a sealed commitment
+    pub fn reveal(&mut self, tx: Transaction, salt: &[u8; 32]) -> Result<[u8; 32], &'static str> {
+        let digest = commitment_digest(&tx, salt);
+        let entry = self.sealed.values_mut()
+            .flat_map(|batch| batch.iter_mut())
+            .find(|entry| entry.commitment.digest == digest)
+            .ok_or_else(|| if self.pending.iter().any(|c| c.digest == digest) {
+                "Commitment is not sealed yet"
+            } else {
+                "No sealed commitment matches the transaction and salt"
+            })?;
+        if entry.commitment.sender != tx.from {
+            return Err("Transaction sender does not match the commitment");
+        }
+        tx.verify_signature()?;
+        entry.reveal = Some(tx);
+        Ok(digest)
+    }
+
+    /// Revealed transactions a block at `height` must execute first, in
+    /// committed order
+    pub fn due(&self, height: u64) -> Vec<([u8; 32], &Transaction)> {
+        self.sealed.range(..height)
+            .flat_map(|(_, batch)| batch.iter())
+            .filter_map(|entry| entry.reveal.as_ref().map(|tx| (entry.commitment.digest, tx)))
+            .collect()
+    }
+
+    /// Forget commitments once executed
+    pub fn executed(&mut self, digests: &[[u8; 32]]) {
+        for batch in self.sealed.values_mut() {
+            batch.retain(|entry| !digests.contains(&entry.commitment.digest));
+        }
+        self.sealed.retain(|_, batch| !batch.is_empty());
+        for digest in digests {
+            self.digests.remove(digest);
+        }
+    }
+
+    /// Drop commitments whose reveal window has passed at `height`
+    pub fn expire(&mut self, height: u64) -> usize {
+        let cutoff = height.saturating_sub(self.config.reveal_window);
+        let kept = self.sealed.split_off(&cutoff);
+        let expired = std::mem::replace(&mut self.sealed, kept);
+        let mut count = 0;
+        for entry in expired.into_values().flatten() {
+            self.digests.remove(&entry.commitment.digest);
+            count += 1;
+        }
+        count
+    }
+
+    /// Check and record evidence; returns the producer to slash
+    pub fn report_violation(&mut self, evidence: OrderingViolation, keys: &ValidatorKeys) -> Result<Address, &'static str> {
+        let offender = evidence.verify(&self.domain, keys, self.config.reveal_window)?;
+        if self.violations.iter().any(|(_, known)| known == &evidence) {
+            return Err("Evidence already reported");
+        }
+        self.violations.push((offender, evidence));
+        Ok(offender)
+    }
+
+    pub fn violations(&self) -> &[(Address, OrderingViolation)] {
+        &self.violations
+    }
+
+    /// Sealed batches and how many of each are revealed
+    pub fn status(&self) -> Vec<(u64, usize, usize)> {
+        self.sealed.iter()
+            .map(|(height, batch)| (*height, batch.len(), batch.iter().filter(|e| e.reveal.is_some()).count()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::transaction::TransactionAction;
+
+    #[test]
+    fn test_commit_seal_reveal_and_ordering_evidence() {
+        let domain = SigningDomain::main_chain(1);
+        let mut queue = CommitRevealQueue::new(domain, CommitRevealConfig { reveal_window: 3, ..Default::default() });
+        let senders: Vec<SigningKey> = (1..=2).map(|i| SigningKey::from_bytes(&[i; 32])).collect();
+        let salt = [7u8; 32];
+        let txs: Vec<Transaction> = senders.iter()
+            .map(|key| {
+                let mut tx = Transaction::new([0u8; 32], 0, TransactionAction::Transfer { to: [9u8; 32], amount: 5 }, 21_000, 1);
+                tx.sign(key);
+                tx
+            })
+            .collect();
+        for (key, tx) in senders.iter().zip(&txs) {
+            queue.submit(Commitment::sign(&domain, key, commitment_digest(tx, &salt))).unwrap();
+        }
+        assert_eq!(queue.reveal(txs[0].clone(), &salt).unwrap_err(), "Commitment is not sealed yet");
+
+        let committed = queue.seal(10);
+        assert_eq!(committed.len(), 2);
+        // Revealed in reverse, executed in committed order
+        queue.reveal(txs[1].clone(), &salt).unwrap();
+        queue.reveal(txs[0].clone(), &salt).unwrap();
+        assert!(queue.reveal(txs[0].clone(), &[8u8; 32]).is_err());
+        let due: Vec<[u8; 32]> = queue.due(11).into_iter().map(|(digest, _)| digest).collect();
+        assert_eq!(due, committed);
+        assert!(queue.due(10).is_empty());
+
+        // A producer that swaps the order can be reported
+        let validator = [4u8; 32];
+        let producer = SigningKey::from_bytes(&[5u8; 32]);
+        let mut keys = ValidatorKeys::new();
+        keys.register(validator, producer.verifying_key().to_bytes(), 0).unwrap();
+        let sealed = SignedOrder::sign(&domain, validator, &producer, 10, OrderKind::Committed, committed.clone());
+        let honest = SignedOrder::sign(&domain, validator, &producer, 11, OrderKind::Executed, committed.clone());
+        let swapped = SignedOrder::sign(&domain, validator, &producer, 11, OrderKind::Executed, vec![committed[1], committed[0]]);
+        let evidence = |executed: &SignedOrder| OrderingViolation { committed: sealed.clone(), executed: executed.clone() };
+        assert!(queue.report_violation(evidence(&honest), &keys).is_err());
+        assert_eq!(queue.report_violation(evidence(&swapped), &keys), Ok(validator));
+        assert_eq!(queue.report_violation(evidence(&swapped), &keys).unwrap_err(), "Evidence already reported");
+
+        queue.executed(&committed[..1]);
+        assert_eq!(queue.status(), vec![(10, 1, 1)]);
+        assert_eq!(queue.expire(14), 1);
+        assert!(queue.status().is_empty());
+    }
+}
@@ -0,0 +1,360 @@
+//! Confidential transfers of native tokens.
+//!
+//! Native tokens shielded into `CONFIDENTIAL_POOL_ADDRESS` become the
+//! confidential asset class: each registered account holds a Pedersen
+//! commitment to its shielded balance instead of a plain number. A transfer
+//! carries a commitment to the amount and two range proofs, one that the
+//! amount is not negative and one that the sender's balance minus the
+//! amount is not negative; the chain checks both against the commitments
+//! and updates balances by adding and subtracting points, never learning
+//! an amount. Fees are still paid from the public balance.
+//!
+//! The opening of every transfer (amount and blinding) is encrypted to the
+//! recipient's view key, and to the sender's, in a memo. Whoever holds a
+//! view secret, such as an auditor the owner shares it with, can open the
+//! account's memos and check them against the commitments, but cannot
+//! spend. Memos cannot be checked on chain, so incoming transfers wait in
+//! a pending list until the recipient rolls them into its balance, leaving
+//! out any whose memo does not open.
+
+use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::scalar::Scalar;
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use crate::blockchain::types::{hex_serde, Address};
+use crate::crypto::pedersen::{self, OpeningProof, RangeProof};
+
+/// Account holding the public tokens backing all shielded balances
+pub const CONFIDENTIAL_POOL_ADDRESS: Address = *b"qmv:confidential:pool:::::::::::";
+
+/// Most incoming transfers an account can have waiting
+pub const MAX_PENDING_TRANSFERS: usize = 256;
+
+const TRANSCRIPT_TAG: &[u8] = b"QMV-CONFIDENTIAL1";
+const MEMO_CONTEXT: &str = "qmv confidential memo v1";
+const MEMO_LEN: usize = 40;
+
+/// Public view key of a view secret
+pub fn view_key(secret: &Scalar) -> [u8; 32] {
+    pedersen::compress(&RistrettoPoint::mul_base(secret))
+}
+
+/// Context a range or opening proof is bound to: what it is for, who
+/// made it and the commitment it is about
+pub fn transcript(label: &[u8], from: &Address, commitment: &[u8; 32]) -> Vec<u8> {
+    [TRANSCRIPT_TAG, label, from.as_slice(), commitment.as_slice()].concat()
+}
+
+/// Amount and blinding a commitment was made with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Opening {
+    pub value: u64,
+    pub blinding: Scalar,
+}
+
+impl Opening {
+    pub fn new(value: u64, blinding: Scalar) -> Self {
+        Self { value, blinding }
+    }
+
+    pub fn commitment(&self) -> [u8; 32] {
+        pedersen::compress(&pedersen::commit(self.value, &self.blinding))
+    }
+
+    /// Proof for an auditor that `owner`'s balance, opened by this, holds
+    /// `value`; checked by `ConfidentialAccount::verify_disclosure`
+    pub fn disclose(&self, owner: &Address) -> OpeningProof {
+        OpeningProof::prove(self.value, &self.blinding, &transcript(b"disclose", owner, &self.commitment()))
+    }
+}
+
+/// Opening encrypted to a view key: the key is agreed with an ephemeral
+/// Ristretto key and stretched into a keystream
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Memo {
+    #[serde(with = "hex_serde")]
+    pub ephemeral: [u8; 32],
+    #[serde(with = "hex_serde")]
+    pub ciphertext: Vec<u8>,
+}
+
+impl Memo {
+    pub fn seal(view_key: &[u8; 32], opening: &Opening) -> Result<Self, &'static str> {
+        let view_key = pedersen::decompress(view_key).map_err(|_| "Invalid view key")?;
+        let secret = pedersen::random_scalar();
+        let ephemeral = pedersen::compress(&RistrettoPoint::mul_base(&secret));
+        let mut ciphertext = [opening.value.to_le_bytes().as_slice(), opening.blinding.as_bytes().as_slice()].concat();
+        apply_keystream(&(secret * view_key), &ephemeral, &mut ciphertext);
+        Ok(Self { ephemeral, ciphertext })
+    }
+
+    /// Decrypt with a view secret and check the result opens `commitment`
+    pub fn open(&self, view_secret: &Scalar, commitment: &[u8; 32]) -> Result<Opening, &'static str> {
+        if self.ciphertext.len() != MEMO_LEN {
+            return Err("Memo has the wrong length");
+        }
+        let shared = view_secret * pedersen::decompress(&self.ephemeral)?;
+        let mut plaintext = self.ciphertext.clone();
+        apply_keystream(&shared, &self.ephemeral, &mut plaintext);
+        let value = u64::from_le_bytes(plaintext[..8].try_into().expect("length checked"));
+        let blinding: [u8; 32] = plaintext[8..].try_into().expect("length checked");
+        let blinding = Option::from(Scalar::from_canonical_bytes(blinding)).ok_or("Memo does not open")?;
+        let opening = Opening::new(value, blinding);
+        if &opening.commitment() != commitment {
+            return Err("Memo does not open the commitment");
+        }
+        Ok(opening)
+    }
+}
+
+fn apply_keystream(shared: &RistrettoPoint, ephemeral: &[u8; 32], bytes: &mut [u8]) {
+    let mut hasher = blake3::Hasher::new_derive_key(MEMO_CONTEXT);
+    hasher.update(shared.compress().as_bytes());
+    hasher.update(ephemeral);
+    let mut keystream = [0u8; MEMO_LEN];
+    hasher.finalize_xof().fill(&mut keystream);
+    for (byte, key) in bytes.iter_mut().zip(keystream) {
+        *byte ^= key;
+    }
+}
+
+/// Transfer waiting to be rolled into the recipient's balance
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IncomingTransfer {
+    #[serde(with = "hex_serde")]
+    pub from: Address,
+    #[serde(with = "hex_serde")]
+    pub commitment: [u8; 32],
+    pub memo: Memo,
+}
+
+/// Shielded balance of one account
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfidentialAccount {
+    #[serde(with = "hex_serde")]
+    pub view_key: [u8; 32],
+    /// Commitment to the spendable balance
+    #[serde(with = "hex_serde")]
+    pub balance: [u8; 32],
+    pub pending: Vec<IncomingTransfer>,
+}
+
+impl ConfidentialAccount {
+    pub fn new(view_key: [u8; 32]) -> Self {
+        Self { view_key, balance: pedersen::compress(&RistrettoPoint::default()), pending: Vec::new() }
+    }
+
+    /// Check an owner's claim that its balance is `value` without learning
+    /// the blinding
+    pub fn verify_disclosure(&self, owner: &Address, value: u64, proof: &OpeningProof) -> Result<(), &'static str> {
+        let balance = pedersen::decompress(&self.balance)?;
+        proof.verify(&balance, value, &transcript(b"disclose", owner, &self.balance))
+    }
+}
+
+/// Confidential operations, signed by the account owner
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfidentialAction {
+    /// Open a shielded account for the sender with its view key
+    Register {
+        #[serde(with = "hex_serde")]
+        view_key: [u8; 32],
+    },
+    /// Move public tokens into the sender's shielded balance. The amount
+    /// is public here; only later transfers hide it.
+    Shield { amount: u64 },
+    /// Send a hidden amount to another shielded account
+    Transfer {
+        #[serde(with = "hex_serde")]
+        to: Address,
+        #[serde(with = "hex_serde")]
+        commitment: [u8; 32],
+        /// The amount is in range
+        amount_proof: RangeProof,
+        /// The sender's balance less the amount is in range
+        balance_proof: RangeProof,
+        /// Opening for the recipient's view key
+        memo: Memo,
+        /// Opening for the sender's view key
+        sender_memo: Memo,
+    },
+    /// Add the first `count` pending transfers to the balance, except the
+    /// ones at the positions in `rejected`; all `count` leave the list
+    Rollover {
+        count: u32,
+        #[serde(default)]
+        rejected: Vec<u32>,
+    },
+    /// Move tokens from the shielded balance back to the public one
+    Unshield {
+        amount: u64,
+        /// The balance less the amount is in range
+        balance_proof: RangeProof,
+    },
+}
+
+impl ConfidentialAction {
+    /// Build a transfer of `amount` from a balance with `balance` as its
+    /// opening. Returns the action and the opening of the balance after it.
+    pub fn transfer(
+        from: &Address,
+        to: Address,
+        balance: &Opening,
+        amount: u64,
+        recipient_view_key: &[u8; 32],
+        sender_view_key: &[u8; 32],
+    ) -> Result<(Self, Opening), &'static str> {
+        let remaining = balance.value.checked_sub(amount).ok_or("Insufficient shielded balance")?;
+        let sent = Opening::new(amount, pedersen::random_scalar());
+        let after = Opening::new(remaining, balance.blinding - sent.blinding);
+        let commitment = sent.commitment();
+        let action = Self::Transfer {
+            to,
+            commitment,
+            amount_proof: RangeProof::prove(amount, &sent.blinding, &transcript(b"amount", from, &commitment)),
+            balance_proof: RangeProof::prove(remaining, &after.blinding, &transcript(b"balance", from, &after.commitment())),
+            memo: Memo::seal(recipient_view_key, &sent)?,
+            sender_memo: Memo::seal(sender_view_key, &sent)?,
+        };
+        Ok((action, after))
+    }
+
+    /// Build an unshield of `amount`. Returns the action and the opening of
+    /// the balance after it.
+    pub fn unshield(from: &Address, balance: &Opening, amount: u64) -> Result<(Self, Opening), &'static str> {
+        let remaining = balance.value.checked_sub(amount).ok_or("Insufficient shielded balance")?;
+        let after = Opening::new(remaining, balance.blinding);
+        let balance_proof = RangeProof::prove(remaining, &after.blinding, &transcript(b"balance", from, &after.commitment()));
+        Ok((Self::Unshield { amount, balance_proof }, after))
+    }
+
+    /// Range proofs the chain verifies for this action
+    pub fn range_proofs(&self) -> u64 {
+        match self {
+            Self::Transfer { .. } => 2,
+            Self::Unshield { .. } => 1,
+            Self::Register { .. } | Self::Shield { .. } | Self::Rollover { .. } => 0,
+        }
+    }
+}
+
+/// Shielded accounts by address
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConfidentialAccounts {
+    accounts: BTreeMap<Address, ConfidentialAccount>,
+}
+
+impl ConfidentialAccounts {
+    pub fn get(&self, address: &Address) -> Option<&ConfidentialAccount> {
+        self.accounts.get(address)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Address, &ConfidentialAccount)> {
+        self.accounts.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.accounts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+
+    /// Set an account to a journaled value; `None` removes it
+    pub(crate) fn restore(&mut self, address: Address, account: Option<ConfidentialAccount>) {
+        match account {
+            Some(account) => { self.accounts.insert(address, account); }
+            None => { self.accounts.remove(&address); }
+        }
+    }
+}
+
+/// `balance - amount·G`: the balance left after taking out a public amount
+pub fn debit_public(balance: &[u8; 32], amount: u64) -> Result<[u8; 32], &'static str> {
+    Ok(pedersen::compress(&(pedersen::decompress(balance)? - pedersen::commit_public(amount))))
+}
+
+/// `balance + amount·G`
+pub fn credit_public(balance: &[u8; 32], amount: u64) -> Result<[u8; 32], &'static str> {
+    Ok(pedersen::compress(&(pedersen::decompress(balance)? + pedersen::commit_public(amount))))
+}
+
+/// Check a transfer's proofs against the sender's balance. Returns the
+/// sender's balance after it.
+pub fn check_transfer(
+    from: &Address,
+    balance: &[u8; 32],
+    commitment: &[u8; 32],
+    amount_proof: &RangeProof,
+    balance_proof: &RangeProof,
+) -> Result<[u8; 32], &'static str> {
+    let amount = pedersen::decompress(commitment)?;
+    amount_proof.verify(&amount, &transcript(b"amount", from, commitment))?;
+    let after = pedersen::decompress(balance)? - amount;
+    let after_bytes = pedersen::compress(&after);
+    balance_proof.verify(&after, &transcript(b"balance", from, &after_bytes))?;
+    Ok(after_bytes)
+}
+
+/// Check an unshield's proof. Returns the balance after it.
+pub fn check_unshield(from: &Address, balance: &[u8; 32], amount: u64, balance_proof: &RangeProof) -> Result<[u8; 32], &'static str> {
+    let after = debit_public(balance, amount)?;
+    balance_proof.verify(&pedersen::decompress(&after)?, &transcript(b"balance", from, &after))?;
+    Ok(after)
+}
+
+/// Roll the first `count` pending transfers into the balance, skipping
+/// the rejected positions
+pub fn rollover(account: &mut ConfidentialAccount, count: u32, rejected: &[u32]) -> Result<(), &'static str> {
+    let count = count as usize;
+    if count > account.pending.len() {
+        return Err("Fewer pending transfers than the rollover count");
+    }
+    let mut balance = pedersen::decompress(&account.balance)?;
+    for (position, incoming) in account.pending.drain(..count).enumerate() {
+        if !rejected.contains(&(position as u32)) {
+            balance += pedersen::decompress(&incoming.commitment)?;
+        }
+    }
+    account.balance = pedersen::compress(&balance);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memo_discloses_to_view_key_only() {
+        let (view_secret, other_secret) = (pedersen::random_scalar(), pedersen::random_scalar());
+        let opening = Opening::new(750, pedersen::random_scalar());
+        let commitment = opening.commitment();
+        let memo = Memo::seal(&view_key(&view_secret), &opening).unwrap();
+        assert_eq!(memo.open(&view_secret, &commitment), Ok(opening));
+        let mut account = ConfidentialAccount::new(view_key(&view_secret));
+        account.balance = commitment;
+        assert!(account.verify_disclosure(&[1; 32], 750, &opening.disclose(&[1; 32])).is_ok());
+        assert!(account.verify_disclosure(&[1; 32], 700, &opening.disclose(&[1; 32])).is_err());
+        assert!(memo.open(&other_secret, &commitment).is_err());
+        assert!(memo.open(&view_secret, &Opening::new(751, opening.blinding).commitment()).is_err());
+    }
+
+    #[test]
+    fn test_transfer_proofs_bind_to_balance() {
+        let from = [1u8; 32];
+        let view = view_key(&pedersen::random_scalar());
+        let balance = Opening::new(1_000, pedersen::random_scalar());
+        assert!(ConfidentialAction::transfer(&from, [2; 32], &balance, 1_001, &view, &view).is_err());
+
+        let (action, after) = ConfidentialAction::transfer(&from, [2; 32], &balance, 400, &view, &view).unwrap();
+        let ConfidentialAction::Transfer { commitment, amount_proof, balance_proof, .. } = action else { unreachable!() };
+        assert_eq!(check_transfer(&from, &balance.commitment(), &commitment, &amount_proof, &balance_proof), Ok(after.commitment()));
+        assert_eq!(after.value, 600);
+        // A proof made for one balance does not carry over to another
+        let other = Opening::new(1_000, pedersen::random_scalar()).commitment();
+        assert!(check_transfer(&from, &other, &commitment, &amount_proof, &balance_proof).is_err());
+        assert!(check_transfer(&[3; 32], &balance.commitment(), &commitment, &amount_proof, &balance_proof).is_err());
+    }
+}
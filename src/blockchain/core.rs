@@ -1,7 +1,16 @@
 use crate::math::precision::PreciseFloat;
-use std::time::{SystemTime, UNIX_EPOCH};
 
 use serde::{Serialize, Deserialize};
+use crate::blockchain::bloom::Bloom;
+use crate::blockchain::features::FeatureSet;
+use crate::blockchain::state::WorldState;
+use crate::blockchain::store::ChainStore;
+use crate::clock::{self, Clock, SharedClock, SystemClock};
+use crate::storage::database::NodeDatabase;
+
+/// Timestamp of the genesis block. Genesis must hash the same on every node
+/// for their chains to join, so it is not stamped with the time it was made.
+pub const GENESIS_TIMESTAMP: u128 = 0;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Block {
@@ -13,16 +22,28 @@ pub struct Block {
     pub s_physics: PreciseFloat,
     pub ai_decision: PreciseFloat,
     pub quantum_resistance: PreciseFloat,
+    /// Filter over the block's content hash, transactions and events
+    #[serde(default)]
+    pub bloom: Bloom,
+    /// Randomness beacon output, committed in the first block of each epoch
+    #[serde(default)]
+    pub beacon: Option<[u8; 32]>,
+    /// Features the producing validator is ready to activate
+    #[serde(default)]
+    pub signals: FeatureSet,
     pub hash: [u8; 32],
 }
 
 impl Block {
+    /// Encode in the fixed-offset wire layout used for storage and P2P
     pub fn to_bytes(&self) -> Vec<u8> {
-        bincode::serialize(self).unwrap_or_default()
+        crate::blockchain::wire::encode_block(self)
     }
 
-    pub fn from_bytes(bytes: &[u8]) -> Result<Self, bincode::Error> {
-        bincode::deserialize(bytes)
+    /// Decode a block in the wire layout or the bincode encoding stored
+    /// before it; use `wire::BlockView` to inspect one without copying
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
+        crate::blockchain::wire::decode_block(bytes)
     }
 
     pub fn new(
@@ -34,10 +55,7 @@ impl Block {
         ai_decision: PreciseFloat,
         quantum_resistance: PreciseFloat,
     ) -> Self {
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_nanos();
+        let timestamp = SystemClock.now_nanos();
             
         let mut block = Self {
             index,
@@ -48,32 +66,167 @@ impl Block {
             s_physics,
             ai_decision,
             quantum_resistance,
+            bloom: Bloom::default(),
+            beacon: None,
+            signals: FeatureSet::default(),
             hash: [0; 32],
         };
         
+        block.bloom.accrue(blake3::hash(&block.data).as_bytes());
         block.hash = block.calculate_hash();
         block
     }
 
+    /// Stamp the block with a specific time (e.g. from an injected clock) and rehash
+    pub fn with_timestamp(mut self, timestamp: u128) -> Self {
+        self.timestamp = timestamp;
+        self.hash = self.calculate_hash();
+        self
+    }
+
+    /// Merge transaction and event entries into the header bloom and rehash
+    pub fn with_bloom(mut self, bloom: &Bloom) -> Self {
+        self.bloom.accrue_bloom(bloom);
+        self.hash = self.calculate_hash();
+        self
+    }
+
+    /// Commit the epoch's beacon output and rehash
+    pub fn with_beacon(mut self, beacon: [u8; 32]) -> Self {
+        self.beacon = Some(beacon);
+        self.hash = self.calculate_hash();
+        self
+    }
+
+    /// Signal readiness for `signals` and rehash
+    pub fn with_signals(mut self, signals: FeatureSet) -> Self {
+        self.signals = signals;
+        self.hash = self.calculate_hash();
+        self
+    }
+
+    /// Header without the data, as headers-first sync downloads it
+    pub fn header(&self) -> BlockHeader {
+        BlockHeader {
+            index: self.index,
+            timestamp: self.timestamp,
+            previous_hash: self.previous_hash,
+            frc_proof: self.frc_proof.clone(),
+            s_physics: self.s_physics.clone(),
+            ai_decision: self.ai_decision.clone(),
+            quantum_resistance: self.quantum_resistance.clone(),
+            bloom: self.bloom,
+            beacon: self.beacon,
+            signals: self.signals,
+            hash: self.hash,
+        }
+    }
+
+    /// Check that the stored hash matches the block contents
+    pub fn verify_hash(&self) -> bool {
+        self.hash == self.calculate_hash()
+    }
+
     fn calculate_hash(&self) -> [u8; 32] {
-        use sha2::{Sha256, Digest};
-        let mut hasher = Sha256::new();
-        
-        // Combine all block data for hashing
-        hasher.update(&self.index.to_le_bytes());
-        hasher.update(&self.timestamp.to_le_bytes());
-        hasher.update(&self.previous_hash);
-        hasher.update(&self.data);
-        hasher.update(&self.frc_proof.value.to_le_bytes());
-        hasher.update(&self.s_physics.value.to_le_bytes());
-        hasher.update(&self.ai_decision.value.to_le_bytes());
-        hasher.update(&self.quantum_resistance.value.to_le_bytes());
-        
-        let result = hasher.finalize();
-        let mut hash = [0; 32];
-        hash.copy_from_slice(&result);
-        hash
+        compute_block_hash(
+            self.index,
+            self.timestamp,
+            &self.previous_hash,
+            &self.data,
+            [
+                self.frc_proof.value,
+                self.s_physics.value,
+                self.ai_decision.value,
+                self.quantum_resistance.value,
+            ],
+            &self.bloom,
+            HeaderExtensions { beacon: self.beacon.as_ref(), signals: self.signals },
+        )
+    }
+}
+
+/// Block fields other than its data. The hash covers the data, so it can
+/// only be checked once the body is joined with `with_data`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BlockHeader {
+    pub index: u64,
+    pub timestamp: u128,
+    pub previous_hash: [u8; 32],
+    pub frc_proof: PreciseFloat,
+    pub s_physics: PreciseFloat,
+    pub ai_decision: PreciseFloat,
+    pub quantum_resistance: PreciseFloat,
+    #[serde(default)]
+    pub bloom: Bloom,
+    #[serde(default)]
+    pub beacon: Option<[u8; 32]>,
+    #[serde(default)]
+    pub signals: FeatureSet,
+    pub hash: [u8; 32],
+}
+
+impl BlockHeader {
+    /// Join the header with its block's data, keeping the header's hash
+    pub fn with_data(self, data: Vec<u8>) -> Block {
+        Block {
+            index: self.index,
+            timestamp: self.timestamp,
+            previous_hash: self.previous_hash,
+            data,
+            frc_proof: self.frc_proof,
+            s_physics: self.s_physics,
+            ai_decision: self.ai_decision,
+            quantum_resistance: self.quantum_resistance,
+            bloom: self.bloom,
+            beacon: self.beacon,
+            signals: self.signals,
+            hash: self.hash,
+        }
+    }
+}
+
+/// Header fields added after the original layout. Each is hashed only when
+/// set, so hashes of blocks without them are unchanged.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct HeaderExtensions<'a> {
+    pub beacon: Option<&'a [u8; 32]>,
+    pub signals: FeatureSet,
+}
+
+/// Block hash over its fields; shared with `wire::BlockView` so borrowed blocks hash identically
+pub(crate) fn compute_block_hash(
+    index: u64,
+    timestamp: u128,
+    previous_hash: &[u8; 32],
+    data: &[u8],
+    metrics: [i128; 4],
+    bloom: &Bloom,
+    extensions: HeaderExtensions,
+) -> [u8; 32] {
+    use sha2::{Sha256, Digest};
+    let mut hasher = Sha256::new();
+
+    // Combine all block data for hashing
+    hasher.update(index.to_le_bytes());
+    hasher.update(timestamp.to_le_bytes());
+    hasher.update(previous_hash);
+    hasher.update(data);
+    for value in metrics {
+        hasher.update(value.to_le_bytes());
+    }
+    hasher.update(bloom.as_bytes());
+    // Only epoch blocks carry a beacon
+    if let Some(beacon) = extensions.beacon {
+        hasher.update(beacon);
     }
+    if !extensions.signals.is_empty() {
+        hasher.update(extensions.signals.bits().to_le_bytes());
+    }
+
+    let result = hasher.finalize();
+    let mut hash = [0; 32];
+    hash.copy_from_slice(&result);
+    hash
 }
 
 #[allow(dead_code)]
@@ -82,16 +235,30 @@ pub struct Blockchain {
     pending_transactions: Vec<Vec<u8>>,
     frc_engine: FRCEngine,
     precision: u8,
+    clock: SharedClock,
+    /// Signaled in every block this chain produces
+    signals: FeatureSet,
+    /// Where blocks are written before they join `chain`; `None` keeps the
+    /// chain in memory only
+    store: Option<Box<dyn ChainStore>>,
 }
 
 impl Blockchain {
     pub fn new(precision: u8) -> Self {
+        Self::with_clock(precision, clock::system())
+    }
+
+    /// Create a chain whose block timestamps come from `clock`
+    pub fn with_clock(precision: u8, clock: SharedClock) -> Self {
         let frc_engine = FRCEngine::new(precision);
         let mut chain = Self {
             chain: Vec::new(),
             pending_transactions: Vec::new(),
             frc_engine,
             precision,
+            clock,
+            signals: FeatureSet::default(),
+            store: None,
         };
         
         // Create genesis block
@@ -99,6 +266,84 @@ impl Blockchain {
         chain
     }
 
+    /// Open the chain stored in the RocksDB database at `path`, creating it
+    /// with a genesis block if the database is new
+    pub fn open(path: &str, precision: u8) -> Result<Self, String> {
+        let db = NodeDatabase::open(path).map_err(|e| format!("Failed to open chain database: {}", e))?;
+        Self::with_store(precision, Box::new(db), clock::system())
+    }
+
+    /// Resume the chain held by `store`. Stored blocks are checked against
+    /// their hashes and links before the chain is used.
+    pub fn with_store(precision: u8, store: Box<dyn ChainStore>, clock: SharedClock) -> Result<Self, String> {
+        let blocks = store.blocks()?;
+        for (height, block) in blocks.iter().enumerate() {
+            if block.index != height as u64 {
+                return Err(format!("Stored chain is missing block {}", height));
+            }
+            if !block.verify_hash() {
+                return Err(format!("Stored block {} does not match its hash", height));
+            }
+            if height > 0 && block.previous_hash != blocks[height - 1].hash {
+                return Err(format!("Stored block {} does not follow block {}", height, height - 1));
+            }
+        }
+        let pending_transactions = store.pending()?;
+
+        let mut chain = Self {
+            chain: blocks,
+            pending_transactions,
+            frc_engine: FRCEngine::new(precision),
+            precision,
+            clock,
+            signals: FeatureSet::default(),
+            store: Some(store),
+        };
+        if chain.chain.is_empty() {
+            chain.create_genesis_block();
+            let genesis = chain.chain[0].clone();
+            chain.persist(&genesis).map_err(str::to_string)?;
+        }
+        Ok(chain)
+    }
+
+    fn persist(&mut self, block: &Block) -> Result<(), &'static str> {
+        match &mut self.store {
+            Some(store) => store.put_block(block).map_err(|_| "Failed to persist block"),
+            None => Ok(()),
+        }
+    }
+
+    /// World state saved with `save_state`, if the chain has a store and one was saved
+    pub fn stored_state(&self) -> Result<Option<WorldState>, String> {
+        match &self.store {
+            Some(store) => store.state(),
+            None => Ok(None),
+        }
+    }
+
+    /// Save the world state as of the head block
+    pub fn save_state(&mut self, state: &WorldState) -> Result<(), &'static str> {
+        match &mut self.store {
+            Some(store) => store.put_state(state).map_err(|_| "Failed to persist world state"),
+            None => Ok(()),
+        }
+    }
+
+    /// Encoded transactions waiting for a block
+    pub fn pending_transactions(&self) -> &[Vec<u8>] {
+        &self.pending_transactions
+    }
+
+    /// Replace the transactions waiting for a block, persisting them
+    pub fn set_pending_transactions(&mut self, pending: Vec<Vec<u8>>) -> Result<(), &'static str> {
+        if let Some(store) = &mut self.store {
+            store.put_pending(&pending).map_err(|_| "Failed to persist pending transactions")?;
+        }
+        self.pending_transactions = pending;
+        Ok(())
+    }
+
     fn create_genesis_block(&mut self) {
         let genesis = Block::new(
             0,
@@ -108,11 +353,49 @@ impl Blockchain {
             PreciseFloat::new(1, self.precision),
             PreciseFloat::new(1, self.precision),
             PreciseFloat::new(1, self.precision),
-        );
+        ).with_timestamp(GENESIS_TIMESTAMP);
         self.chain.push(genesis);
     }
 
+    /// Signal `signals` in the blocks produced from now on
+    pub fn signal(&mut self, signals: FeatureSet) {
+        self.signals = signals;
+    }
+
     pub fn add_block(&mut self, data: Vec<u8>) -> Result<(), &'static str> {
+        self.add_block_with_beacon(data, None)
+    }
+
+    /// Add a block, committing `beacon` in it if given (see `blockchain::beacon`)
+    pub fn add_block_with_beacon(&mut self, data: Vec<u8>, beacon: Option<[u8; 32]>) -> Result<(), &'static str> {
+        self.append(data, beacon, None)
+    }
+
+    /// Add a block of executed transactions with the bloom of their receipts
+    pub fn add_block_with_bloom(&mut self, data: Vec<u8>, bloom: &Bloom) -> Result<(), &'static str> {
+        self.append(data, None, Some(bloom))
+    }
+
+    /// Build the block of executed transactions that would follow the tip,
+    /// with the bloom of their receipts, without adding it, so consensus can
+    /// decide on it first
+    pub fn next_block(&mut self, data: Vec<u8>, bloom: &Bloom) -> Result<Block, &'static str> {
+        self.build_next(data, None, Some(bloom))
+    }
+
+    fn append(&mut self, data: Vec<u8>, beacon: Option<[u8; 32]>, bloom: Option<&Bloom>) -> Result<(), &'static str> {
+        let new_block = self.build_next(data, beacon, bloom)?;
+
+        // Verify block before adding, and persist it before it joins the chain
+        if !self.verify_block(&new_block) {
+            return Err("Block verification failed");
+        }
+        self.persist(&new_block)?;
+        self.chain.push(new_block);
+        Ok(())
+    }
+
+    fn build_next(&mut self, data: Vec<u8>, beacon: Option<[u8; 32]>, bloom: Option<&Bloom>) -> Result<Block, &'static str> {
         let previous_block = self.chain.last().ok_or("Chain is empty")?;
         
         // Calculate all necessary proofs and values
@@ -129,37 +412,68 @@ impl Blockchain {
             s_physics,
             ai_decision,
             quantum_resistance,
-        );
-        
-        // Verify block before adding
-        if self.verify_block(&new_block) {
-            self.chain.push(new_block);
-            Ok(())
-        } else {
-            Err("Block verification failed")
+        ).with_timestamp(self.clock.now_nanos()).with_signals(self.signals);
+        let new_block = match beacon {
+            Some(beacon) => new_block.with_beacon(beacon),
+            None => new_block,
+        };
+        Ok(match bloom {
+            Some(bloom) => new_block.with_bloom(bloom),
+            None => new_block,
+        })
+    }
+
+    /// Serialize the full chain for a point-in-time snapshot
+    pub fn snapshot(&self) -> Result<Vec<u8>, &'static str> {
+        bincode::serialize(&self.chain).map_err(|_| "Failed to serialize chain snapshot")
+    }
+
+    /// Get the current chain height
+    pub fn height(&self) -> u64 {
+        self.chain.len() as u64
+    }
+
+    pub fn block(&self, height: u64) -> Option<&Block> {
+        self.chain.get(usize::try_from(height).ok()?)
+    }
+
+    /// Append a block produced by another node, e.g. one downloaded by sync
+    pub fn import_block(&mut self, block: Block) -> Result<(), &'static str> {
+        if block.index != self.height() {
+            return Err("Block does not extend the chain tip");
         }
+        if !self.verify_block(&block) {
+            return Err("Block verification failed");
+        }
+        self.persist(&block)?;
+        self.chain.push(block);
+        Ok(())
     }
 
-    fn verify_block(&self, block: &Block) -> bool {
+    /// Check a header's proofs and that it follows the block hashed
+    /// `parent_hash`. Needs no block data, so sync runs it before fetching bodies.
+    pub fn verify_header(&self, header: &BlockHeader, parent_hash: &[u8; 32]) -> Result<(), &'static str> {
         // Verify FRC proof
-        if !self.frc_engine.verify_proof(&block.frc_proof) {
-            return false;
+        if !self.frc_engine.verify_proof(&header.frc_proof) {
+            return Err("Invalid FRC proof");
         }
-        
+
         // Verify quantum resistance
-        if block.quantum_resistance.value < PreciseFloat::new(95, 2).value {
-            return false;
+        if header.quantum_resistance.value < PreciseFloat::new(95, 2).value {
+            return Err("Quantum resistance below threshold");
         }
-        
+
         // Verify hash continuity
-        if let Some(previous_block) = self.chain.last() {
-            if previous_block.hash != block.previous_hash {
-                return false;
-            }
+        if header.previous_hash != *parent_hash {
+            return Err("Header does not follow its parent");
         }
-        
-        // Verify block hash
-        block.hash == block.calculate_hash()
+        Ok(())
+    }
+
+    /// Check a block's header against the chain tip and its hash against its contents
+    pub fn verify_block(&self, block: &Block) -> bool {
+        let parent_hash = self.chain.last().map_or(block.previous_hash, |previous| previous.hash);
+        self.verify_header(&block.header(), &parent_hash).is_ok() && block.verify_hash()
     }
 
     fn calculate_physics(&self) -> PreciseFloat {
@@ -0,0 +1,2110 @@
+use serde::{Serialize, Deserialize};
+use std::cmp::Ordering;
+use crate::blockchain::account_validation::{self, AccountValidation, ValidationAction};
+use crate::blockchain::assets::{self, Asset, AssetAction};
+use crate::blockchain::channel::{self, Channel, ChannelAction, CHANNEL_ESCROW_ADDRESS};
+use crate::blockchain::confidential::{self, ConfidentialAccount, ConfidentialAction, IncomingTransfer, CONFIDENTIAL_POOL_ADDRESS};
+use crate::blockchain::features::{Feature, FeatureSet};
+use crate::blockchain::htlc::{self, Htlc, HtlcAction, HTLC_ESCROW_ADDRESS};
+use crate::blockchain::journal::JournaledState;
+use crate::blockchain::lease::{self, Lease, LeaseAction, LeaseOffer, LEASE_ESCROW_ADDRESS};
+use crate::blockchain::market::{self, Bid, Listing, ListingKind, MarketAction, ESCROW_ADDRESS};
+use crate::blockchain::multisig::{self, MultisigAccount, MultisigOperation, MultisigProposal};
+use crate::blockchain::scheduler::{self, Schedule, ScheduledAction};
+use crate::blockchain::sponsor::{SponsorAction, SponsorPolicy, Sponsorship};
+use crate::blockchain::state::{ContractAccount, StateDiff, WorldState};
+use crate::blockchain::transaction::{Transaction, TransactionAction, TxHash};
+use crate::blockchain::types::{hex_serde, hex_serde_option, Address};
+use crate::crypto::batch::{self, SignatureItem};
+use crate::crypto::pedersen;
+use crate::math::noise::{self, NoiseSeed, RandomWalk, MAX_OCTAVES};
+
+/// Gas schedule
+pub mod gas {
+    pub const TX_BASE: u64 = 21_000;
+    pub const TX_BYTE: u64 = 16;
+    pub const DEPLOY_INSTRUCTION: u64 = 200;
+    pub const STEP: u64 = 3;
+    pub const LOAD: u64 = 800;
+    pub const STORE: u64 = 20_000;
+    pub const DELETE: u64 = 5_000;
+    pub const EMIT: u64 = 375;
+    pub const EMIT_BYTE: u64 = 8;
+    pub const HASH: u64 = 60;
+    pub const HASH_BYTE: u64 = 2;
+    /// Two signature checks plus the registry write
+    pub const KEY_ROTATION: u64 = 50_000;
+    /// Account record for a new multisig
+    pub const CREATE_MULTISIG: u64 = 20_000;
+    /// Per approval checked by `multisig_execute`
+    pub const MULTISIG_APPROVAL: u64 = 3_000;
+    /// Registry write for a new schedule
+    pub const SCHEDULE: u64 = 20_000;
+    /// Base cost of each scheduled run, in place of `TX_BASE`
+    pub const SCHEDULED_RUN: u64 = 10_000;
+    /// Asset or listing record write
+    pub const ASSET: u64 = 20_000;
+    /// Shielded account record write
+    pub const CONFIDENTIAL: u64 = 20_000;
+    /// Per range proof checked by a confidential transaction
+    pub const RANGE_PROOF: u64 = 250_000;
+    /// Channel record write
+    pub const CHANNEL: u64 = 20_000;
+    /// Per signed balance update checked by a channel transaction
+    pub const CHANNEL_UPDATE: u64 = 3_000;
+    /// HTLC record write
+    pub const HTLC: u64 = 20_000;
+    /// Sponsor signature check and policy update on a sponsored transaction
+    pub const SPONSORSHIP: u64 = 5_000;
+    /// Sponsor policy write
+    pub const SPONSOR_POLICY: u64 = 20_000;
+    /// Per witness signature checked on a transaction
+    pub const WITNESS: u64 = 3_000;
+    /// Registry write for a validation contract
+    pub const VALIDATION_CONTRACT: u64 = 20_000;
+    /// Gas limit for read-only calls that do not specify one
+    pub const CALL_DEFAULT_LIMIT: u64 = 10_000_000;
+    /// Seeding a `noise` or `walk` operand
+    pub const NOISE: u64 = 200;
+    pub const NOISE_OCTAVE: u64 = 100;
+    pub const WALK_STEP: u64 = 10;
+}
+
+/// Most steps one `walk` operand takes
+pub const MAX_WALK_STEPS: i64 = 1_024;
+
+/// Value source for contract instructions
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operand {
+    Const(#[serde(with = "hex_serde")] Vec<u8>),
+    /// Call input bytes
+    Input,
+    /// Address of the calling account
+    Caller,
+    /// Value sent with the call, big-endian
+    Value,
+    /// Current value of a storage key (empty if unset)
+    Load(#[serde(with = "hex_serde")] Vec<u8>),
+    /// BLAKE3 hash of another operand; needs `Feature::HashOperand`
+    Hash(Box<Operand>),
+    /// Fractal noise of a layer at `(x, y)`, seeded by the layer and the
+    /// parent block hash; needs `Feature::NoiseOperands`. Coordinates and
+    /// result are 8-byte big-endian integers at `noise::NOISE_SCALE`.
+    Noise {
+        layer: u32,
+        x: Box<Operand>,
+        y: Box<Operand>,
+        octaves: u8,
+    },
+    /// Position of a layer's random walk from 0 after `steps` unit steps,
+    /// seeded like `Noise`; needs `Feature::NoiseOperands`. `steps` is an
+    /// 8-byte big-endian count, the result as for `Noise`.
+    Walk {
+        layer: u32,
+        steps: Box<Operand>,
+    },
+    /// Key of the n-th witness, in key order, while validating a transaction;
+    /// empty otherwise. Needs `Feature::AccountAbstraction`, as do the
+    /// operands below.
+    Signer(u8),
+    /// Height of the block being executed, 8 bytes big-endian
+    Height,
+    /// Recipient or called contract of the transaction being validated;
+    /// empty otherwise
+    Target,
+    /// Sum of two unsigned big-endian numbers of up to 16 bytes, as 16 bytes
+    Add(Box<Operand>, Box<Operand>),
+    /// Operands joined end to end
+    Concat(Vec<Operand>),
+    /// Current value of the storage key an operand gives
+    LoadAt(Box<Operand>),
+}
+
+/// How `Instruction::Require` compares its operands, as unsigned big-endian numbers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn holds(&self, left: &[u8], right: &[u8]) -> bool {
+        let ordering = Executor::compare(left, right);
+        match self {
+            Comparison::Eq => ordering == Ordering::Equal,
+            Comparison::Ne => ordering != Ordering::Equal,
+            Comparison::Lt => ordering == Ordering::Less,
+            Comparison::Le => ordering != Ordering::Greater,
+            Comparison::Gt => ordering == Ordering::Greater,
+            Comparison::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+impl Operand {
+    /// Features that must be active for contracts to use this operand
+    fn required_features(&self) -> FeatureSet {
+        match self {
+            Operand::Hash(inner) => {
+                let mut features = inner.required_features();
+                features.insert(Feature::HashOperand);
+                features
+            }
+            Operand::Noise { x, y, .. } => {
+                let mut features = x.required_features().union(&y.required_features());
+                features.insert(Feature::NoiseOperands);
+                features
+            }
+            Operand::Walk { steps, .. } => {
+                let mut features = steps.required_features();
+                features.insert(Feature::NoiseOperands);
+                features
+            }
+            Operand::Signer(_) | Operand::Height | Operand::Target => {
+                [Feature::AccountAbstraction].into_iter().collect()
+            }
+            Operand::Add(left, right) => {
+                let mut features = left.required_features().union(&right.required_features());
+                features.insert(Feature::AccountAbstraction);
+                features
+            }
+            Operand::Concat(parts) => {
+                let mut features = parts.iter().fold(FeatureSet::default(), |features, part| features.union(&part.required_features()));
+                features.insert(Feature::AccountAbstraction);
+                features
+            }
+            Operand::LoadAt(key) => {
+                let mut features = key.required_features();
+                features.insert(Feature::AccountAbstraction);
+                features
+            }
+            _ => FeatureSet::default(),
+        }
+    }
+}
+
+/// Contract instruction set
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Instruction {
+    Store {
+        #[serde(with = "hex_serde")]
+        key: Vec<u8>,
+        value: Operand,
+    },
+    Delete {
+        #[serde(with = "hex_serde")]
+        key: Vec<u8>,
+    },
+    Emit {
+        topic: String,
+        data: Operand,
+    },
+    /// Stop and return the operand as output
+    Return(Operand),
+    /// Abort the call, undoing its effects
+    Revert(String),
+    /// Revert with `reason` unless the comparison holds; needs
+    /// `Feature::AccountAbstraction`
+    Require {
+        left: Operand,
+        comparison: Comparison,
+        right: Operand,
+        reason: String,
+    },
+    /// Write to the storage key an operand gives; needs
+    /// `Feature::AccountAbstraction`
+    StoreAt {
+        key: Operand,
+        value: Operand,
+    },
+}
+
+impl Instruction {
+    /// Features that must be active for contracts to use this instruction
+    pub fn required_features(&self) -> FeatureSet {
+        match self {
+            Instruction::Store { value: operand, .. }
+            | Instruction::Emit { data: operand, .. }
+            | Instruction::Return(operand) => operand.required_features(),
+            Instruction::Delete { .. } | Instruction::Revert(_) => FeatureSet::default(),
+            Instruction::Require { left, right, .. } | Instruction::StoreAt { key: left, value: right } => {
+                let mut features = left.required_features().union(&right.required_features());
+                features.insert(Feature::AccountAbstraction);
+                features
+            }
+        }
+    }
+}
+
+/// Event emitted by a contract
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    #[serde(with = "hex_serde")]
+    pub contract: Address,
+    pub topic: String,
+    #[serde(with = "hex_serde")]
+    pub data: Vec<u8>,
+}
+
+/// Outcome of an executed transaction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Receipt {
+    #[serde(with = "hex_serde")]
+    pub tx_hash: TxHash,
+    pub success: bool,
+    pub gas_used: u64,
+    /// Burned: `gas_used * gas_price`
+    pub fee: u128,
+    /// Unused part of the `gas_limit * gas_price` held during execution
+    #[serde(default)]
+    pub refund: u128,
+    #[serde(with = "hex_serde")]
+    pub output: Vec<u8>,
+    pub events: Vec<Event>,
+    #[serde(with = "hex_serde_option", default)]
+    pub contract_address: Option<Address>,
+    pub error: Option<String>,
+}
+
+/// Preview of a transaction's effects without committing them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimulationResult {
+    pub success: bool,
+    #[serde(with = "hex_serde")]
+    pub output: Vec<u8>,
+    pub gas_used: u64,
+    pub fee: u128,
+    pub events: Vec<Event>,
+    pub state_diff: StateDiff,
+    pub error: Option<String>,
+}
+
+struct GasMeter {
+    limit: u64,
+    used: u64,
+}
+
+impl GasMeter {
+    fn charge(&mut self, amount: u64) -> Result<(), String> {
+        let used = self.used.saturating_add(amount);
+        if used > self.limit {
+            self.used = self.limit;
+            return Err("Out of gas".to_string());
+        }
+        self.used = used;
+        Ok(())
+    }
+}
+
+struct CallContext<'a> {
+    caller: Address,
+    contract: Address,
+    input: &'a [u8],
+    value: u128,
+    /// Witness keys, while validating a transaction
+    signers: &'a [Address],
+    /// Transaction target, while validating a transaction
+    target: Option<Address>,
+}
+
+/// Which pre-execution checks to apply
+#[derive(Clone, Copy)]
+struct Checks {
+    signature: bool,
+    nonce: bool,
+    /// Multisig approvals, checked in blocks even though block signatures
+    /// are batch-verified beforehand
+    approvals: bool,
+}
+
+/// Transaction Executor
+/// Applies transactions to a `WorldState` with gas metering.
+pub struct Executor;
+
+impl Executor {
+    /// Gas charged before any instruction runs
+    pub fn intrinsic_gas(tx: &Transaction) -> u64 {
+        let mut cost = gas::TX_BASE + tx.size() as u64 * gas::TX_BYTE;
+        if let TransactionAction::Deploy { code } = &tx.action {
+            cost += code.len() as u64 * gas::DEPLOY_INSTRUCTION;
+        }
+        match &tx.action {
+            TransactionAction::RotateValidatorKey(_) => cost += gas::KEY_ROTATION,
+            TransactionAction::CreateMultisig { .. } => cost += gas::CREATE_MULTISIG,
+            TransactionAction::Schedule { .. } => cost += gas::SCHEDULE,
+            TransactionAction::Asset(_) | TransactionAction::Market(_) | TransactionAction::Lease(_) => {
+                cost += gas::ASSET;
+            }
+            TransactionAction::Confidential(action) => {
+                cost += gas::CONFIDENTIAL + action.range_proofs() * gas::RANGE_PROOF;
+            }
+            TransactionAction::Channel(action) => {
+                cost += gas::CHANNEL + action.signed_updates() * gas::CHANNEL_UPDATE;
+            }
+            TransactionAction::Htlc(_) => cost += gas::HTLC,
+            TransactionAction::Sponsor(_) => cost += gas::SPONSOR_POLICY,
+            TransactionAction::Validation(action) => {
+                cost += gas::VALIDATION_CONTRACT + action.instructions() * gas::DEPLOY_INSTRUCTION + action.writes() * gas::STORE;
+            }
+            TransactionAction::MultisigExecute { approvals, .. } => {
+                cost += approvals.len() as u64 * gas::MULTISIG_APPROVAL;
+            }
+            _ => {}
+        }
+        if tx.sponsor.is_some() {
+            cost += gas::SPONSORSHIP;
+        }
+        cost += tx.witnesses.len() as u64 * gas::WITNESS;
+        cost
+    }
+
+    /// Address of a contract deployed by `sender` at `nonce`
+    pub fn contract_address(sender: &Address, nonce: u64) -> Address {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(sender);
+        hasher.update(&nonce.to_le_bytes());
+        hasher.finalize().into()
+    }
+
+    /// Validate and apply a signed transaction.
+    /// Invalid transactions return an error and leave the state untouched.
+    /// A failed execution reverts its own writes but still bumps the nonce
+    /// and pays for the gas used up to the failure.
+    pub fn apply(state: &mut WorldState, tx: &Transaction) -> Result<Receipt, &'static str> {
+        let mut journal = JournaledState::new(state);
+        let receipt = Self::execute(&mut journal, tx, Checks { signature: true, nonce: true, approvals: true })?;
+        journal.commit();
+        Ok(receipt)
+    }
+
+    /// Apply a block's transactions in order.
+    /// Signatures, including sponsors' countersignatures, are batch-verified
+    /// up front; any invalid transaction rejects the whole block and leaves
+    /// `state` untouched. Transactions that fail during execution are kept
+    /// with a failed receipt.
+    /// Rent due at the block's height is paid first, then channel closes
+    /// whose dispute period ended. Schedules due then run and their receipts
+    /// come before those of the transactions.
+    pub fn apply_block(state: &mut WorldState, txs: &[Transaction]) -> Result<Vec<Receipt>, &'static str> {
+        let signing_bytes: Vec<Vec<u8>> = txs.iter().map(Transaction::signing_bytes).collect();
+        let sponsorship_bytes: Vec<Option<Vec<u8>>> = txs.iter().map(Transaction::sponsorship_bytes).collect();
+        let mut items = Vec::with_capacity(txs.len());
+        for ((tx, message), sponsorship_message) in txs.iter().zip(&signing_bytes).zip(&sponsorship_bytes) {
+            if tx.witnesses.is_empty() {
+                let signature = <&[u8; 64]>::try_from(tx.signature.as_slice()).map_err(|_| "Invalid signature length")?;
+                items.push(SignatureItem { public_key: &tx.from, message, signature });
+            } else {
+                tx.check_witnesses()?;
+                for witness in &tx.witnesses {
+                    let signature = <&[u8; 64]>::try_from(witness.signature.as_slice())
+                        .map_err(|_| "Invalid witness signature length")?;
+                    items.push(SignatureItem { public_key: &witness.key, message, signature });
+                }
+            }
+            if let (Some(sponsorship), Some(message)) = (&tx.sponsor, sponsorship_message) {
+                let signature = <&[u8; 64]>::try_from(sponsorship.signature.as_slice())
+                    .map_err(|_| "Invalid sponsor signature length")?;
+                items.push(SignatureItem { public_key: &sponsorship.sponsor, message, signature });
+            }
+        }
+        batch::verify_signatures(&items).map_err(|_| "Invalid transaction signature in block")?;
+
+        let mut journal = JournaledState::new(state);
+        let block_start = journal.checkpoint();
+        let height = journal.state().height() + 1;
+        Self::pay_leases(&mut journal, height);
+        Self::settle_channels(&mut journal, height);
+        let mut receipts = Self::run_schedules(&mut journal, height);
+        for tx in txs {
+            match Self::execute(&mut journal, tx, Checks { signature: false, nonce: true, approvals: true }) {
+                Ok(receipt) => receipts.push(receipt),
+                Err(error) => {
+                    journal.revert_to(block_start);
+                    return Err(error);
+                }
+            }
+        }
+        journal.commit();
+        Ok(receipts)
+    }
+
+    /// Execute a transaction against a copy of `state` and report its effects.
+    /// Signatures and nonces are not checked so wallets can preview unsigned transactions.
+    pub fn simulate(state: &WorldState, tx: &Transaction) -> Result<SimulationResult, &'static str> {
+        let mut scratch = state.clone();
+        let receipt = Self::execute(&mut JournaledState::new(&mut scratch), tx, Checks { signature: false, nonce: false, approvals: false })?;
+        Ok(SimulationResult {
+            success: receipt.success,
+            output: receipt.output,
+            gas_used: receipt.gas_used,
+            fee: receipt.fee,
+            events: receipt.events,
+            state_diff: StateDiff::between(state, &scratch),
+            error: receipt.error,
+        })
+    }
+
+    /// Read-only contract call with no fee
+    pub fn call(
+        state: &WorldState,
+        caller: Address,
+        contract: Address,
+        input: Vec<u8>,
+        gas_limit: Option<u64>,
+    ) -> Result<SimulationResult, &'static str> {
+        let nonce = state.account(&caller).nonce;
+        let tx = Transaction::new(
+            caller,
+            nonce,
+            TransactionAction::Call { contract, input, value: 0 },
+            gas_limit.unwrap_or(gas::CALL_DEFAULT_LIMIT),
+            0,
+        );
+        Self::simulate(state, &tx)
+    }
+
+    fn execute(state: &mut JournaledState, tx: &Transaction, checks: Checks) -> Result<Receipt, &'static str> {
+        if checks.signature {
+            tx.verify_signature()?;
+        }
+        let sender = state.account(&tx.from);
+        if checks.nonce && tx.nonce != sender.nonce {
+            return Err("Invalid nonce");
+        }
+        let intrinsic = Self::intrinsic_gas(tx);
+        if tx.gas_limit < intrinsic {
+            return Err("Gas limit below intrinsic cost");
+        }
+        let max_fee = tx.max_fee();
+        if let Some(sponsorship) = &tx.sponsor {
+            Self::check_sponsorship(state, tx, sponsorship, max_fee)?;
+            if sender.balance < tx.value() {
+                return Err("Insufficient balance for value");
+            }
+        } else {
+            let required = max_fee.checked_add(tx.value()).ok_or("Value overflow")?;
+            if sender.balance < required {
+                return Err("Insufficient balance for gas and value");
+            }
+        }
+        // Approvals are bound to the multisig's nonce like a signature to the
+        // sender's, so a transaction without enough of them is invalid
+        if let TransactionAction::MultisigExecute { multisig, operation, approvals } = &tx.action {
+            let account = state.multisig(multisig).ok_or("Unknown multisig account")?;
+            if checks.approvals {
+                let proposal = MultisigProposal { multisig: *multisig, nonce: account.nonce(), operation: operation.clone() };
+                account.check_approvals(&proposal, tx.network_id, approvals)?;
+            }
+        }
+
+        // Witnesses stand in for the account's signature only if its
+        // validation contract accepts them
+        let validation_gas = if tx.witnesses.is_empty() { 0 } else { Self::validate(state, tx, intrinsic)? };
+
+        // The full gas allowance is held for the duration of the call so
+        // executed code cannot spend it; the unused part is refunded below
+        let fee_payer = tx.fee_payer();
+        state.account_mut(&tx.from).nonce += 1;
+        state.account_mut(&fee_payer).balance -= max_fee;
+        if let TransactionAction::MultisigExecute { multisig, .. } = &tx.action {
+            // Used even if the operation fails, so its approvals cannot be replayed
+            state.multisig_mut(multisig).expect("checked above").advance();
+        }
+
+        let checkpoint = state.checkpoint();
+        let mut meter = GasMeter { limit: tx.gas_limit, used: intrinsic + validation_gas };
+        let mut events = Vec::new();
+        let mut contract_address = None;
+
+        let outcome = match &tx.action {
+            TransactionAction::Transfer { to, amount } => {
+                state.transfer(&tx.from, to, *amount).map(|_| Vec::new()).map_err(str::to_string)
+            }
+            TransactionAction::Deploy { code } => {
+                let address = Self::contract_address(&tx.from, tx.nonce);
+                let active = state.state().features();
+                let deployed = if code.iter().all(|instruction| instruction.required_features().is_subset(&active)) {
+                    state.insert_contract(address, ContractAccount {
+                        owner: tx.from,
+                        code: code.clone(),
+                        storage: Default::default(),
+                    })
+                } else {
+                    Err("Contract uses a feature that is not active")
+                };
+                deployed.map(|_| {
+                    contract_address = Some(address);
+                    address.to_vec()
+                })
+                .map_err(str::to_string)
+            }
+            TransactionAction::Call { contract, .. } if Self::is_validation_contract(state, contract) => {
+                Err("Validation contracts cannot be called".to_string())
+            }
+            TransactionAction::Call { contract, input, value } => {
+                let ctx = CallContext { caller: tx.from, contract: *contract, input, value: *value, signers: &[], target: None };
+                state.transfer(&tx.from, contract, *value)
+                    .map_err(str::to_string)
+                    .and_then(|_| Self::run(state, &ctx, &mut meter, &mut events))
+            }
+            TransactionAction::RotateValidatorKey(rotation) => {
+                // Included in the block after the last one applied
+                let height = state.state().height() + 1;
+                state.rotate_validator_key(rotation, tx.network_id, height)
+                    .map(|_| Vec::new())
+                    .map_err(str::to_string)
+            }
+            TransactionAction::CreateMultisig { signers, threshold } => {
+                let address = multisig::multisig_address(&tx.from, tx.nonce);
+                MultisigAccount::new(signers.clone(), *threshold)
+                    .and_then(|account| state.create_multisig(address, account))
+                    .map(|_| address.to_vec())
+                    .map_err(str::to_string)
+            }
+            TransactionAction::Schedule { action, start_height, interval, max_runs, run_gas_limit, deposit } => {
+                let schedule = Schedule {
+                    owner: tx.from,
+                    action: action.clone(),
+                    next_height: *start_height,
+                    interval: *interval,
+                    remaining_runs: *max_runs,
+                    gas_limit: *run_gas_limit,
+                    gas_price: tx.gas_price,
+                    deposit: *deposit,
+                };
+                // Runs happen at the start of a block, so the including block is too late
+                let included_at = state.state().height() + 1;
+                Self::check_schedule(&schedule, included_at)
+                    .and_then(|_| {
+                        let payer = state.account_mut(&tx.from);
+                        payer.balance = payer.balance.checked_sub(*deposit).ok_or("Insufficient balance")?;
+                        let id = scheduler::schedule_id(&tx.from, tx.nonce);
+                        state.set_schedule(id, Some(schedule));
+                        Ok(id.to_vec())
+                    })
+                    .map_err(str::to_string)
+            }
+            TransactionAction::CancelSchedule { id } => match state.schedule(id).cloned() {
+                Some(schedule) if schedule.owner == tx.from => {
+                    state.set_schedule(*id, None);
+                    state.account_mut(&tx.from).balance += schedule.deposit;
+                    Ok(Vec::new())
+                }
+                Some(_) => Err("Only the owner can cancel a schedule".to_string()),
+                None => Err("Schedule not found".to_string()),
+            },
+            TransactionAction::Asset(action) => {
+                Self::asset_action(state, tx, action).map_err(str::to_string)
+            }
+            TransactionAction::Market(action) => {
+                Self::market_action(state, tx, action, &mut events).map_err(str::to_string)
+            }
+            TransactionAction::Lease(action) => {
+                Self::lease_action(state, tx, action).map_err(str::to_string)
+            }
+            TransactionAction::Confidential(action) => {
+                Self::confidential_action(state, tx, action).map_err(str::to_string)
+            }
+            TransactionAction::Channel(action) => {
+                Self::channel_action(state, tx, action).map_err(str::to_string)
+            }
+            TransactionAction::Htlc(action) => {
+                Self::htlc_action(state, tx, action, &mut events).map_err(str::to_string)
+            }
+            TransactionAction::Sponsor(action) => {
+                Self::sponsor_action(state, tx, action).map_err(str::to_string)
+            }
+            TransactionAction::Validation(action) => {
+                Self::validation_action(state, tx, action).map_err(str::to_string)
+            }
+            TransactionAction::MultisigExecute { multisig, operation, .. } => match operation {
+                MultisigOperation::Transfer { to, amount } => {
+                    state.transfer(multisig, to, *amount).map(|_| Vec::new()).map_err(str::to_string)
+                }
+                MultisigOperation::Call { contract, input, value } => {
+                    let ctx = CallContext { caller: *multisig, contract: *contract, input, value: *value, signers: &[], target: None };
+                    state.transfer(multisig, contract, *value)
+                        .map_err(str::to_string)
+                        .and_then(|_| Self::run(state, &ctx, &mut meter, &mut events))
+                }
+                MultisigOperation::UpdateSigners { signers, threshold } => {
+                    state.multisig_mut(multisig)
+                        .expect("checked above")
+                        .set_signers(signers.clone(), *threshold)
+                        .map(|_| Vec::new())
+                        .map_err(str::to_string)
+                }
+            },
+        };
+
+        let (success, output, error) = match outcome {
+            Ok(output) => (true, output, None),
+            Err(error) => {
+                // Undo the call's writes; the nonce bump and gas escrow stay
+                state.revert_to(checkpoint);
+                events.clear();
+                contract_address = None;
+                (false, Vec::new(), Some(error))
+            }
+        };
+
+        // Fees for gas used are burned and the rest of the escrow is returned
+        let fee = meter.used as u128 * tx.gas_price;
+        let refund = max_fee - fee;
+        state.account_mut(&fee_payer).balance += refund;
+        if let Some(sponsorship) = &tx.sponsor {
+            let height = state.state().height() + 1;
+            if let Some(mut policy) = state.sponsor_policy(&sponsorship.sponsor).cloned() {
+                policy.charge(&tx.from, fee, height);
+                state.set_sponsor_policy(sponsorship.sponsor, Some(policy));
+            }
+        }
+
+        Ok(Receipt {
+            tx_hash: tx.hash(),
+            success,
+            gas_used: meter.used,
+            fee,
+            refund,
+            output,
+            events,
+            contract_address,
+            error,
+        })
+    }
+
+    fn asset_action(state: &mut JournaledState, tx: &Transaction, action: &AssetAction) -> Result<Vec<u8>, &'static str> {
+        match action {
+            AssetAction::Mint { kind, royalty_bps, uri, metadata } => {
+                assets::check_mint(state.state().assets(), kind, *royalty_bps, uri, metadata.as_ref())?;
+                let id = assets::asset_id(&tx.from, tx.nonce);
+                state.set_asset(id, Asset {
+                    creator: tx.from,
+                    owner: tx.from,
+                    kind: *kind,
+                    royalty_bps: *royalty_bps,
+                    uri: uri.clone(),
+                    metadata: metadata.clone(),
+                });
+                Ok(id.to_vec())
+            }
+            AssetAction::Transfer { asset, to } => {
+                let mut record = state.asset(asset).cloned().ok_or("Asset not found")?;
+                if record.owner != tx.from {
+                    return Err("Only the owner can transfer an asset");
+                }
+                if state.listing(asset).is_some() {
+                    return Err("Asset is listed for sale");
+                }
+                if state.lease(asset).is_some() {
+                    return Err("Asset is leased");
+                }
+                record.owner = *to;
+                state.set_asset(*asset, record);
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    fn market_action(
+        state: &mut JournaledState,
+        tx: &Transaction,
+        action: &MarketAction,
+        events: &mut Vec<Event>,
+    ) -> Result<Vec<u8>, &'static str> {
+        let id = *action.asset();
+        let asset = state.asset(&id).cloned().ok_or("Asset not found")?;
+        // Included in the block after the last one applied
+        let height = state.state().height() + 1;
+        if let MarketAction::List { terms, .. } = action {
+            if asset.owner != tx.from {
+                return Err("Only the owner can list an asset");
+            }
+            if state.listing(&id).is_some() {
+                return Err("Asset is already listed");
+            }
+            if state.lease(&id).is_some() {
+                return Err("Asset is leased");
+            }
+            let listing = Listing::new(tx.from, terms, height)?;
+            events.push(Self::market_event(market::TOPIC_ASK, &id, &tx.from, listing.ask()));
+            state.set_listing(id, Some(listing));
+            return Ok(Vec::new());
+        }
+
+        let mut listing = state.listing(&id).cloned().ok_or("Asset is not listed")?;
+        match action {
+            MarketAction::List { .. } => unreachable!("handled above"),
+            MarketAction::Cancel { .. } => {
+                if listing.seller != tx.from {
+                    return Err("Only the seller can cancel a listing");
+                }
+                if matches!(listing.kind, ListingKind::Auction { highest: Some(_), .. }) {
+                    return Err("Auction already has a bid");
+                }
+                state.set_listing(id, None);
+                events.push(Self::market_event(market::TOPIC_CANCEL, &id, &tx.from, 0));
+            }
+            MarketAction::Buy { price, .. } => {
+                match listing.kind {
+                    ListingKind::FixedPrice { price: ask } if ask == *price => {}
+                    ListingKind::FixedPrice { .. } => return Err("Price does not match the listing"),
+                    ListingKind::Auction { .. } => return Err("Auctions take bids, not purchases"),
+                }
+                if listing.seller == tx.from {
+                    return Err("Sellers cannot buy their own listing");
+                }
+                events.push(Self::settle_sale(state, id, asset, &listing.seller, &tx.from, &tx.from, *price)?);
+            }
+            MarketAction::Bid { amount, .. } => {
+                if listing.seller == tx.from {
+                    return Err("Sellers cannot bid on their own auction");
+                }
+                let ask = listing.ask();
+                let ListingKind::Auction { ends_at, highest, .. } = &mut listing.kind else {
+                    return Err("Only auctions take bids");
+                };
+                if height >= *ends_at {
+                    return Err("Auction has ended");
+                }
+                if *amount < ask {
+                    return Err("Bid below the reserve or the highest bid");
+                }
+                state.transfer(&tx.from, &ESCROW_ADDRESS, *amount)?;
+                if let Some(outbid) = highest.replace(Bid { bidder: tx.from, amount: *amount }) {
+                    state.transfer(&ESCROW_ADDRESS, &outbid.bidder, outbid.amount)?;
+                }
+                state.set_listing(id, Some(listing));
+                events.push(Self::market_event(market::TOPIC_BID, &id, &tx.from, *amount));
+            }
+            MarketAction::Settle { .. } => {
+                let ListingKind::Auction { ends_at, highest, .. } = listing.kind else {
+                    return Err("Only auctions are settled");
+                };
+                if height < ends_at {
+                    return Err("Auction has not ended");
+                }
+                match highest {
+                    Some(bid) => {
+                        events.push(Self::settle_sale(state, id, asset, &listing.seller, &ESCROW_ADDRESS, &bid.bidder, bid.amount)?);
+                    }
+                    None => {
+                        state.set_listing(id, None);
+                        events.push(Self::market_event(market::TOPIC_CANCEL, &id, &listing.seller, 0));
+                    }
+                }
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    fn lease_action(state: &mut JournaledState, tx: &Transaction, action: &LeaseAction) -> Result<Vec<u8>, &'static str> {
+        let id = *action.asset();
+        let asset = state.asset(&id).cloned().ok_or("Asset not found")?;
+        match action {
+            LeaseAction::Offer { terms, .. } => {
+                if asset.owner != tx.from {
+                    return Err("Only the owner can lease an asset");
+                }
+                if terms.lessee == tx.from {
+                    return Err("Owners cannot lease to themselves");
+                }
+                terms.check()?;
+                state.set_lease_offer(id, Some(LeaseOffer { lessor: tx.from, terms: terms.clone() }));
+            }
+            LeaseAction::Withdraw { .. } => {
+                let offer = state.lease_offer(&id).ok_or("No open lease offer")?;
+                if offer.lessor != tx.from {
+                    return Err("Only the owner can withdraw a lease offer");
+                }
+                state.set_lease_offer(id, None);
+            }
+            LeaseAction::Accept { total_rent, .. } => {
+                let offer = state.lease_offer(&id).cloned().ok_or("No open lease offer")?;
+                if offer.terms.lessee != tx.from {
+                    return Err("Lease offer is for another account");
+                }
+                // Offers do not follow the asset to a new owner
+                if offer.lessor != asset.owner {
+                    return Err("Lease offer is from a previous owner");
+                }
+                if state.lease(&id).is_some() {
+                    return Err("Asset is leased");
+                }
+                if state.listing(&id).is_some() {
+                    return Err("Asset is listed for sale");
+                }
+                if offer.terms.total_rent() != Some(*total_rent) {
+                    return Err("Rent does not match the offer");
+                }
+                state.transfer(&tx.from, &LEASE_ESCROW_ADDRESS, *total_rent)?;
+                // Included in the block after the last one applied
+                let height = state.state().height() + 1;
+                state.set_lease_offer(id, None);
+                state.set_lease(id, Some(Lease::start(&offer, height)));
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    fn confidential_action(state: &mut JournaledState, tx: &Transaction, action: &ConfidentialAction) -> Result<Vec<u8>, &'static str> {
+        if !state.state().features().contains(Feature::ConfidentialTransfers) {
+            return Err("Confidential transfers are not active");
+        }
+        if let ConfidentialAction::Register { view_key } = action {
+            if state.confidential(&tx.from).is_some() {
+                return Err("Shielded account already exists");
+            }
+            pedersen::decompress(view_key).map_err(|_| "Invalid view key")?;
+            state.set_confidential(tx.from, ConfidentialAccount::new(*view_key));
+            return Ok(Vec::new());
+        }
+
+        let mut account = state.confidential(&tx.from).cloned().ok_or("No shielded account")?;
+        match action {
+            ConfidentialAction::Register { .. } => unreachable!("handled above"),
+            ConfidentialAction::Shield { amount } => {
+                state.transfer(&tx.from, &CONFIDENTIAL_POOL_ADDRESS, *amount as u128)?;
+                account.balance = confidential::credit_public(&account.balance, *amount)?;
+                state.set_confidential(tx.from, account);
+            }
+            ConfidentialAction::Transfer { to, commitment, amount_proof, balance_proof, memo, .. } => {
+                account.balance = confidential::check_transfer(&tx.from, &account.balance, commitment, amount_proof, balance_proof)?;
+                state.set_confidential(tx.from, account);
+                // Read after the debit so a transfer to oneself keeps it
+                let mut recipient = state.confidential(to).cloned().ok_or("Recipient has no shielded account")?;
+                if recipient.pending.len() >= confidential::MAX_PENDING_TRANSFERS {
+                    return Err("Recipient has too many pending transfers");
+                }
+                recipient.pending.push(IncomingTransfer { from: tx.from, commitment: *commitment, memo: memo.clone() });
+                state.set_confidential(*to, recipient);
+            }
+            ConfidentialAction::Rollover { count, rejected } => {
+                confidential::rollover(&mut account, *count, rejected)?;
+                state.set_confidential(tx.from, account);
+            }
+            ConfidentialAction::Unshield { amount, balance_proof } => {
+                account.balance = confidential::check_unshield(&tx.from, &account.balance, *amount, balance_proof)?;
+                state.set_confidential(tx.from, account);
+                state.transfer(&CONFIDENTIAL_POOL_ADDRESS, &tx.from, *amount as u128)?;
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    fn channel_action(state: &mut JournaledState, tx: &Transaction, action: &ChannelAction) -> Result<Vec<u8>, &'static str> {
+        if !state.state().features().contains(Feature::PaymentChannels) {
+            return Err("Payment channels are not active");
+        }
+        // Included in the block after the last one applied
+        let height = state.state().height() + 1;
+        if let ChannelAction::Open { counterparty, deposit, dispute_blocks } = action {
+            let id = channel::channel_id(&tx.from, tx.nonce);
+            let opened = Channel::open(tx.from, *counterparty, *deposit, *dispute_blocks)?;
+            state.transfer(&tx.from, &CHANNEL_ESCROW_ADDRESS, *deposit)?;
+            state.set_channel(id, Some(opened));
+            return Ok(id.to_vec());
+        }
+
+        let id = *action.channel().expect("every action but open names its channel");
+        let mut open = state.channel(&id).cloned().ok_or("Unknown channel")?;
+        match action {
+            ChannelAction::Open { .. } => unreachable!("handled above"),
+            ChannelAction::Deposit { amount, .. } => {
+                open.add_deposit(&tx.from, *amount)?;
+                state.transfer(&tx.from, &CHANNEL_ESCROW_ADDRESS, *amount)?;
+                state.set_channel(id, Some(open));
+            }
+            ChannelAction::Settle { update } => {
+                let counterparty = open.counterparty(&tx.from).ok_or("Not a party to the channel")?;
+                if !update.update.is_final {
+                    return Err("Settling needs a final update");
+                }
+                open.check_update(&id, update, &counterparty, tx.network_id)?;
+                Self::pay_out(state, &open, update.update.balance_a, update.update.balance_b);
+                state.set_channel(id, None);
+            }
+            ChannelAction::Close { update, .. } => {
+                let counterparty = open.counterparty(&tx.from).ok_or("Not a party to the channel")?;
+                if open.closing.is_some() {
+                    return Err("Channel is already closing");
+                }
+                if let Some(update) = update {
+                    open.check_update(&id, update, &counterparty, tx.network_id)?;
+                }
+                open.start_close(tx.from, update.as_ref().map(|update| &update.update), height);
+                state.set_channel(id, Some(open));
+            }
+            ChannelAction::Dispute { update } => {
+                let closing = open.closing.as_ref().ok_or("Channel is not closing")?;
+                if update.update.sequence <= closing.sequence {
+                    return Err("Update is not newer than the close");
+                }
+                // Signed by the closer, so the closer cannot have meant the older split
+                open.check_update(&id, update, &closing.closer, tx.network_id)?;
+                let closing = open.closing.as_mut().expect("checked above");
+                closing.sequence = update.update.sequence;
+                closing.balance_a = update.update.balance_a;
+                closing.balance_b = update.update.balance_b;
+                state.set_channel(id, Some(open));
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    /// Lock funds in escrow, or settle an HTLC and remove it. Claims and
+    /// refunds emit an event so the other leg of a swap can find the secret.
+    fn htlc_action(state: &mut JournaledState, tx: &Transaction, action: &HtlcAction, events: &mut Vec<Event>) -> Result<Vec<u8>, &'static str> {
+        if !state.state().features().contains(Feature::HashTimeLocks) {
+            return Err("HTLCs are not active");
+        }
+        // Included in the block after the last one applied
+        let height = state.state().height() + 1;
+        match action {
+            HtlcAction::Lock(terms) => {
+                let id = htlc::htlc_id(&tx.from, tx.nonce);
+                let locked = Htlc::new(tx.from, terms, height)?;
+                state.transfer(&tx.from, &HTLC_ESCROW_ADDRESS, terms.amount)?;
+                state.set_htlc(id, Some(locked));
+                Ok(id.to_vec())
+            }
+            HtlcAction::Claim { id, secret } => {
+                let mut claimed = state.htlc(id).cloned().ok_or("HTLC not found")?;
+                claimed.claim(secret, height)?;
+                state.transfer(&HTLC_ESCROW_ADDRESS, &claimed.recipient, claimed.amount)?;
+                state.set_htlc(*id, None);
+                let mut data = id.to_vec();
+                data.extend_from_slice(secret);
+                events.push(Event { contract: HTLC_ESCROW_ADDRESS, topic: htlc::TOPIC_CLAIMED.to_string(), data });
+                Ok(Vec::new())
+            }
+            HtlcAction::Refund { id } => {
+                let mut refunded = state.htlc(id).cloned().ok_or("HTLC not found")?;
+                refunded.refund(height)?;
+                state.transfer(&HTLC_ESCROW_ADDRESS, &refunded.sender, refunded.amount)?;
+                state.set_htlc(*id, None);
+                events.push(Event { contract: HTLC_ESCROW_ADDRESS, topic: htlc::TOPIC_REFUNDED.to_string(), data: id.to_vec() });
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    /// A sponsored transaction is invalid unless it is unexpired and the
+    /// sponsor's policy and balance cover its whole gas allowance
+    fn check_sponsorship(state: &JournaledState, tx: &Transaction, sponsorship: &Sponsorship, max_fee: u128) -> Result<(), &'static str> {
+        if !state.state().features().contains(Feature::FeeSponsorship) {
+            return Err("Fee sponsorship is not active");
+        }
+        if sponsorship.sponsor == tx.from {
+            return Err("Sender cannot sponsor its own transaction");
+        }
+        // Included in the block after the last one applied
+        let height = state.state().height() + 1;
+        if height > sponsorship.valid_until {
+            return Err("Sponsorship has expired");
+        }
+        let policy = state.sponsor_policy(&sponsorship.sponsor).ok_or("Sponsor has no spending policy")?;
+        policy.check(&tx.from, max_fee, height)?;
+        if state.account(&sponsorship.sponsor).balance < max_fee {
+            return Err("Insufficient sponsor balance for gas");
+        }
+        Ok(())
+    }
+
+    fn is_validation_contract(state: &JournaledState, address: &Address) -> bool {
+        state.contract(address).is_some_and(|contract| account_validation::validation_address(&contract.owner) == *address)
+    }
+
+    /// Run the sender's validation contract for a transaction its witnesses
+    /// signed, returning the gas used. A revert rejects the transaction and
+    /// undoes the contract's writes.
+    fn validate(state: &mut JournaledState, tx: &Transaction, intrinsic: u64) -> Result<u64, &'static str> {
+        if !state.state().features().contains(Feature::AccountAbstraction) {
+            return Err("Account abstraction is not active");
+        }
+        let validation = state.state().validations().get(&tx.from).cloned().ok_or("Account has no validation contract")?;
+        if tx.gas_limit < intrinsic.saturating_add(validation.gas_limit) {
+            return Err("Gas limit below intrinsic and validation cost");
+        }
+        let signers: Vec<Address> = tx.witnesses.iter().map(|witness| witness.key).collect();
+        let ctx = CallContext {
+            caller: tx.from,
+            contract: validation.contract,
+            input: &[],
+            value: tx.value(),
+            signers: &signers,
+            target: account_validation::target(&tx.action),
+        };
+        let mut meter = GasMeter { limit: validation.gas_limit, used: 0 };
+        let checkpoint = state.checkpoint();
+        match Self::run(state, &ctx, &mut meter, &mut Vec::new()) {
+            Ok(_) => Ok(meter.used),
+            Err(_) => {
+                state.revert_to(checkpoint);
+                Err("Rejected by the account's validation contract")
+            }
+        }
+    }
+
+    /// Install, update or remove the sender's validation contract
+    fn validation_action(state: &mut JournaledState, tx: &Transaction, action: &ValidationAction) -> Result<Vec<u8>, &'static str> {
+        if !state.state().features().contains(Feature::AccountAbstraction) {
+            return Err("Account abstraction is not active");
+        }
+        let address = account_validation::validation_address(&tx.from);
+        match action {
+            ValidationAction::Install { code, storage, gas_limit } => {
+                account_validation::check_install(code, *gas_limit)?;
+                let active = state.state().features();
+                if !code.iter().all(|instruction| instruction.required_features().is_subset(&active)) {
+                    return Err("Contract uses a feature that is not active");
+                }
+                let storage = storage.iter()
+                    .filter(|entry| !entry.value.is_empty())
+                    .map(|entry| (entry.key.clone(), entry.value.clone()))
+                    .collect();
+                state.set_contract(address, Some(ContractAccount { owner: tx.from, code: code.clone(), storage }));
+                state.set_validation(tx.from, Some(AccountValidation { contract: address, gas_limit: *gas_limit }));
+                Ok(address.to_vec())
+            }
+            ValidationAction::UpdateStorage { entries } => {
+                if state.state().validations().get(&tx.from).is_none() {
+                    return Err("Account has no validation contract");
+                }
+                for entry in entries {
+                    state.storage_set(&address, &entry.key, (!entry.value.is_empty()).then(|| entry.value.clone()))?;
+                }
+                Ok(Vec::new())
+            }
+            ValidationAction::Remove => {
+                if state.state().validations().get(&tx.from).is_none() {
+                    return Err("Account has no validation contract");
+                }
+                state.set_contract(address, None);
+                state.set_validation(tx.from, None);
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    /// Set or revoke the sender's policy for paying others' fees
+    fn sponsor_action(state: &mut JournaledState, tx: &Transaction, action: &SponsorAction) -> Result<Vec<u8>, &'static str> {
+        if !state.state().features().contains(Feature::FeeSponsorship) {
+            return Err("Fee sponsorship is not active");
+        }
+        match action {
+            SponsorAction::SetPolicy { limit, sender_limit, window_blocks } => {
+                let height = state.state().height() + 1;
+                let policy = SponsorPolicy::new(*limit, *sender_limit, *window_blocks, height)?;
+                state.set_sponsor_policy(tx.from, Some(policy));
+            }
+            SponsorAction::Revoke => {
+                if state.sponsor_policy(&tx.from).is_none() {
+                    return Err("No sponsor policy to revoke");
+                }
+                state.set_sponsor_policy(tx.from, None);
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    /// Pay out channels whose dispute period ends at `height`
+    fn settle_channels(state: &mut JournaledState, height: u64) {
+        for id in state.state().channels().due(height, channel::MAX_SETTLEMENTS_PER_BLOCK) {
+            let closed = state.channel(&id).cloned().expect("due channels exist");
+            let closing = closed.closing.as_ref().expect("due channels are closing");
+            Self::pay_out(state, &closed, closing.balance_a, closing.balance_b);
+            state.set_channel(id, None);
+        }
+    }
+
+    /// Pay a channel's split out of escrow
+    fn pay_out(state: &mut JournaledState, channel: &Channel, balance_a: u128, balance_b: u128) {
+        state.transfer(&CHANNEL_ESCROW_ADDRESS, &channel.party_a, balance_a)
+            .expect("deposits are escrowed in full and updates split them exactly");
+        state.transfer(&CHANNEL_ESCROW_ADDRESS, &channel.party_b, balance_b)
+            .expect("deposits are escrowed in full and updates split them exactly");
+    }
+
+    /// Pay each epoch's rent due at `height` out of escrow; a lease whose
+    /// last epoch is paid ends and the asset reverts to its owner
+    fn pay_leases(state: &mut JournaledState, height: u64) {
+        for asset in state.state().leases().due(height, lease::MAX_PAYMENTS_PER_BLOCK) {
+            let lease = state.lease(&asset).cloned().expect("due leases exist");
+            state.transfer(&LEASE_ESCROW_ADDRESS, &lease.lessor, lease.rent_per_epoch)
+                .expect("rent is escrowed in full on acceptance");
+            state.set_lease(asset, lease.after_payment());
+        }
+    }
+
+    /// Pay the creator's royalty and the seller from `payer`, then hand the
+    /// asset to `buyer` and close the listing
+    fn settle_sale(
+        state: &mut JournaledState,
+        id: [u8; 32],
+        mut asset: Asset,
+        seller: &Address,
+        payer: &Address,
+        buyer: &Address,
+        price: u128,
+    ) -> Result<Event, &'static str> {
+        let royalty = if asset.creator == *seller { 0 } else { asset.royalty(price) };
+        if royalty > 0 {
+            state.transfer(payer, &asset.creator, royalty)?;
+        }
+        state.transfer(payer, seller, price - royalty)?;
+        asset.owner = *buyer;
+        state.set_asset(id, asset);
+        state.set_listing(id, None);
+        Ok(Self::market_event(market::TOPIC_SALE, &id, buyer, price))
+    }
+
+    fn market_event(topic: &str, asset: &[u8; 32], account: &Address, amount: u128) -> Event {
+        Event {
+            contract: market::MARKET_ADDRESS,
+            topic: topic.to_string(),
+            data: market::event_data(asset, account, amount),
+        }
+    }
+
+    fn check_schedule(schedule: &Schedule, included_at: u64) -> Result<(), &'static str> {
+        if schedule.next_height <= included_at {
+            return Err("Schedule must start after the block that includes it");
+        }
+        if schedule.interval == Some(0) || schedule.remaining_runs == Some(0) {
+            return Err("Schedule interval and run count must be positive");
+        }
+        if schedule.gas_limit < gas::SCHEDULED_RUN {
+            return Err("Run gas limit below the scheduled run cost");
+        }
+        if schedule.gas_price == 0 {
+            return Err("Scheduled runs need a non-zero gas price");
+        }
+        if schedule.deposit < schedule.max_run_fee() {
+            return Err("Deposit does not cover one run");
+        }
+        Ok(())
+    }
+
+    /// Execute the schedules due at `height`, in queue order
+    fn run_schedules(state: &mut JournaledState, height: u64) -> Vec<Receipt> {
+        state.state().schedules()
+            .due(height, scheduler::MAX_RUNS_PER_BLOCK)
+            .into_iter()
+            .filter_map(|id| Self::run_schedule(state, id, height))
+            .collect()
+    }
+
+    /// One scheduled run, paid from the deposit. A schedule whose deposit
+    /// cannot cover another run ends without running and refunds the rest.
+    fn run_schedule(state: &mut JournaledState, id: [u8; 32], height: u64) -> Option<Receipt> {
+        let mut schedule = state.schedule(&id).cloned().expect("due schedules exist");
+        let owner = schedule.owner;
+        let max_fee = schedule.max_run_fee();
+        if schedule.deposit < max_fee {
+            state.set_schedule(id, None);
+            state.account_mut(&owner).balance += schedule.deposit;
+            return None;
+        }
+        schedule.deposit -= max_fee;
+
+        let checkpoint = state.checkpoint();
+        let mut meter = GasMeter { limit: schedule.gas_limit, used: gas::SCHEDULED_RUN };
+        let mut events = Vec::new();
+        let outcome = match &schedule.action {
+            ScheduledAction::Transfer { to, amount } => {
+                state.transfer(&owner, to, *amount).map(|_| Vec::new()).map_err(str::to_string)
+            }
+            ScheduledAction::Call { contract, input, value } => {
+                let ctx = CallContext { caller: owner, contract: *contract, input, value: *value, signers: &[], target: None };
+                state.transfer(&owner, contract, *value)
+                    .map_err(str::to_string)
+                    .and_then(|_| Self::run(state, &ctx, &mut meter, &mut events))
+            }
+        };
+        let (success, output, error) = match outcome {
+            Ok(output) => (true, output, None),
+            Err(error) => {
+                state.revert_to(checkpoint);
+                events.clear();
+                (false, Vec::new(), Some(error))
+            }
+        };
+
+        // Unused gas goes back into the deposit, not to the owner
+        let fee = meter.used as u128 * schedule.gas_price;
+        let refund = max_fee - fee;
+        schedule.deposit += refund;
+        match schedule.clone().after_run() {
+            Some(next) => state.set_schedule(id, Some(next)),
+            None => {
+                state.set_schedule(id, None);
+                state.account_mut(&owner).balance += schedule.deposit;
+            }
+        }
+
+        Some(Receipt {
+            tx_hash: scheduler::run_hash(&id, height),
+            success,
+            gas_used: meter.used,
+            fee,
+            refund,
+            output,
+            events,
+            contract_address: None,
+            error,
+        })
+    }
+
+    fn run(
+        state: &mut JournaledState,
+        ctx: &CallContext,
+        meter: &mut GasMeter,
+        events: &mut Vec<Event>,
+    ) -> Result<Vec<u8>, String> {
+        let code = state.contract(&ctx.contract)
+            .ok_or_else(|| "Contract not found".to_string())?
+            .code
+            .clone();
+
+        for instruction in &code {
+            meter.charge(gas::STEP)?;
+            match instruction {
+                Instruction::Store { key, value } => {
+                    let value = Self::resolve(state, ctx, meter, value)?;
+                    meter.charge(gas::STORE)?;
+                    state.storage_set(&ctx.contract, key, Some(value))?;
+                }
+                Instruction::Delete { key } => {
+                    meter.charge(gas::DELETE)?;
+                    state.storage_set(&ctx.contract, key, None)?;
+                }
+                Instruction::Emit { topic, data } => {
+                    let data = Self::resolve(state, ctx, meter, data)?;
+                    meter.charge(gas::EMIT + data.len() as u64 * gas::EMIT_BYTE)?;
+                    events.push(Event { contract: ctx.contract, topic: topic.clone(), data });
+                }
+                Instruction::Return(operand) => {
+                    return Self::resolve(state, ctx, meter, operand);
+                }
+                Instruction::Revert(reason) => {
+                    return Err(format!("Reverted: {}", reason));
+                }
+                Instruction::Require { left, comparison, right, reason } => {
+                    let left = Self::resolve(state, ctx, meter, left)?;
+                    let right = Self::resolve(state, ctx, meter, right)?;
+                    if !comparison.holds(&left, &right) {
+                        return Err(format!("Reverted: {}", reason));
+                    }
+                }
+                Instruction::StoreAt { key, value } => {
+                    let key = Self::resolve(state, ctx, meter, key)?;
+                    let value = Self::resolve(state, ctx, meter, value)?;
+                    meter.charge(gas::STORE)?;
+                    // An empty value deletes the key
+                    state.storage_set(&ctx.contract, &key, (!value.is_empty()).then_some(value))?;
+                }
+            }
+        }
+
+        Ok(Vec::new())
+    }
+
+    fn resolve(state: &JournaledState, ctx: &CallContext, meter: &mut GasMeter, operand: &Operand) -> Result<Vec<u8>, String> {
+        Ok(match operand {
+            Operand::Const(bytes) => bytes.clone(),
+            Operand::Input => ctx.input.to_vec(),
+            Operand::Caller => ctx.caller.to_vec(),
+            Operand::Value => ctx.value.to_be_bytes().to_vec(),
+            Operand::Load(key) => {
+                meter.charge(gas::LOAD)?;
+                state.storage_get(&ctx.contract, key).unwrap_or_default()
+            }
+            Operand::Hash(inner) => {
+                let bytes = Self::resolve(state, ctx, meter, inner)?;
+                meter.charge(gas::HASH + bytes.len() as u64 * gas::HASH_BYTE)?;
+                blake3::hash(&bytes).as_bytes().to_vec()
+            }
+            Operand::Noise { layer, x, y, octaves } => {
+                let x = Self::integer(&Self::resolve(state, ctx, meter, x)?)?;
+                let y = Self::integer(&Self::resolve(state, ctx, meter, y)?)?;
+                let octaves = (*octaves).clamp(1, MAX_OCTAVES);
+                meter.charge(gas::NOISE + octaves as u64 * gas::NOISE_OCTAVE)?;
+                let seed = NoiseSeed::new(*layer, state.state().parent_hash());
+                let value = noise::fractal(&seed, &noise::from_fixed(x as i128), &noise::from_fixed(y as i128), octaves);
+                (value.value as i64).to_be_bytes().to_vec()
+            }
+            Operand::Walk { layer, steps } => {
+                let steps = Self::integer(&Self::resolve(state, ctx, meter, steps)?)?;
+                if !(0..=MAX_WALK_STEPS).contains(&steps) {
+                    return Err(format!("Walk steps must be between 0 and {}", MAX_WALK_STEPS));
+                }
+                meter.charge(gas::NOISE + steps as u64 * gas::WALK_STEP)?;
+                let seed = NoiseSeed::new(*layer, state.state().parent_hash());
+                let mut walk = RandomWalk::new(&seed, &noise::from_fixed(0), &noise::from_fixed(noise::ONE));
+                let position = walk.by_ref().take(steps as usize).last().unwrap_or_else(|| walk.current());
+                (position.value as i64).to_be_bytes().to_vec()
+            }
+            Operand::Signer(index) => {
+                ctx.signers.get(*index as usize).map(|key| key.to_vec()).unwrap_or_default()
+            }
+            Operand::Height => (state.state().height() + 1).to_be_bytes().to_vec(),
+            Operand::Target => ctx.target.map(|target| target.to_vec()).unwrap_or_default(),
+            Operand::Add(left, right) => {
+                meter.charge(gas::STEP)?;
+                let left = Self::unsigned(&Self::resolve(state, ctx, meter, left)?)?;
+                let right = Self::unsigned(&Self::resolve(state, ctx, meter, right)?)?;
+                left.checked_add(right).ok_or_else(|| "Add overflow".to_string())?.to_be_bytes().to_vec()
+            }
+            Operand::Concat(parts) => {
+                let mut bytes = Vec::new();
+                for part in parts {
+                    meter.charge(gas::STEP)?;
+                    bytes.extend(Self::resolve(state, ctx, meter, part)?);
+                }
+                bytes
+            }
+            Operand::LoadAt(key) => {
+                let key = Self::resolve(state, ctx, meter, key)?;
+                meter.charge(gas::LOAD)?;
+                state.storage_get(&ctx.contract, &key).unwrap_or_default()
+            }
+        })
+    }
+
+    /// Big-endian bytes without leading zeros
+    fn significant(bytes: &[u8]) -> &[u8] {
+        let start = bytes.iter().position(|&b| b != 0).unwrap_or(bytes.len());
+        &bytes[start..]
+    }
+
+    /// Order of two unsigned big-endian numbers of any length
+    fn compare(left: &[u8], right: &[u8]) -> Ordering {
+        let (left, right) = (Self::significant(left), Self::significant(right));
+        left.len().cmp(&right.len()).then_with(|| left.cmp(right))
+    }
+
+    /// Operand bytes as an unsigned big-endian number of up to 16 bytes
+    fn unsigned(bytes: &[u8]) -> Result<u128, String> {
+        let bytes = Self::significant(bytes);
+        if bytes.len() > 16 {
+            return Err("Add operands must fit in 16 bytes".to_string());
+        }
+        let mut padded = [0u8; 16];
+        padded[16 - bytes.len()..].copy_from_slice(bytes);
+        Ok(u128::from_be_bytes(padded))
+    }
+
+    /// Operand bytes as an 8-byte big-endian signed integer
+    fn integer(bytes: &[u8]) -> Result<i64, String> {
+        let bytes: [u8; 8] = bytes.try_into()
+            .map_err(|_| "Noise arguments must be 8-byte big-endian integers".to_string())?;
+        Ok(i64::from_be_bytes(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::SigningKey;
+
+    fn funded_key() -> (SigningKey, WorldState) {
+        let key = SigningKey::from_bytes(&[3u8; 32]);
+        let state = WorldState::with_balances(&[(key.verifying_key().to_bytes(), 10_000_000)]);
+        (key, state)
+    }
+
+    fn deploy(state: &mut WorldState, key: &SigningKey, code: Vec<Instruction>) -> Address {
+        let sender = key.verifying_key().to_bytes();
+        let nonce = state.account(&sender).nonce;
+        let mut tx = Transaction::new(sender, nonce, TransactionAction::Deploy { code }, 100_000, 1);
+        tx.sign(key);
+        Executor::apply(state, &tx).unwrap().contract_address.unwrap()
+    }
+
+    #[test]
+    fn test_transfer_charges_gas() {
+        let (key, mut state) = funded_key();
+        let sender = key.verifying_key().to_bytes();
+        let mut tx = Transaction::new(sender, 0, TransactionAction::Transfer { to: [9u8; 32], amount: 500 }, 50_000, 2);
+        tx.sign(&key);
+
+        let receipt = Executor::apply(&mut state, &tx).unwrap();
+        assert!(receipt.success);
+        assert_eq!(state.account(&[9u8; 32]).balance, 500);
+        assert_eq!(state.account(&sender).balance, 10_000_000 - 500 - receipt.fee);
+        assert_eq!(state.account(&sender).nonce, 1);
+
+        // Replaying the same nonce is rejected outright
+        assert_eq!(Executor::apply(&mut state, &tx).unwrap_err(), "Invalid nonce");
+    }
+
+    #[test]
+    fn test_apply_block_is_atomic() {
+        let (key, mut state) = funded_key();
+        let sender = key.verifying_key().to_bytes();
+        let mut txs: Vec<Transaction> = (0..3)
+            .map(|nonce| {
+                let mut tx = Transaction::new(sender, nonce, TransactionAction::Transfer { to: [8u8; 32], amount: 1 }, 30_000, 1);
+                tx.sign(&key);
+                tx
+            })
+            .collect();
+
+        let before = state.clone();
+        txs[2].nonce = 5; // invalidates its signature
+        assert_eq!(Executor::apply_block(&mut state, &txs).unwrap_err(), "Invalid transaction signature in block");
+        assert_eq!(state, before);
+
+        txs[2].nonce = 2;
+        let receipts = Executor::apply_block(&mut state, &txs).unwrap();
+        assert_eq!(receipts.len(), 3);
+        assert_eq!(state.account(&[8u8; 32]).balance, 3);
+    }
+
+    #[test]
+    fn test_simulation_does_not_commit() {
+        let (key, mut state) = funded_key();
+        let contract = deploy(&mut state, &key, vec![
+            Instruction::Store { key: b"greeting".to_vec(), value: Operand::Input },
+            Instruction::Emit { topic: "greeted".to_string(), data: Operand::Caller },
+            Instruction::Return(Operand::Load(b"greeting".to_vec())),
+        ]);
+
+        let sender = key.verifying_key().to_bytes();
+        let tx = Transaction::new(
+            sender,
+            state.account(&sender).nonce,
+            TransactionAction::Call { contract, input: b"hello".to_vec(), value: 0 },
+            100_000,
+            1,
+        );
+        let before = state.clone();
+        let result = Executor::simulate(&state, &tx).unwrap();
+
+        assert!(result.success);
+        assert_eq!(result.output, b"hello".to_vec());
+        assert_eq!(result.events.len(), 1);
+        assert_eq!(result.state_diff.storage.len(), 1);
+        assert!(result.gas_used > gas::STORE);
+        assert_eq!(state, before, "Simulation must not modify state");
+    }
+
+    #[test]
+    fn test_hash_operand_needs_activation() {
+        let (key, mut state) = funded_key();
+        let sender = key.verifying_key().to_bytes();
+        let code = vec![Instruction::Return(Operand::Hash(Box::new(Operand::Input)))];
+        let mut tx = Transaction::new(sender, 0, TransactionAction::Deploy { code: code.clone() }, 100_000, 1);
+        tx.sign(&key);
+        let receipt = Executor::apply(&mut state, &tx).unwrap();
+        assert!(!receipt.success);
+        assert!(receipt.contract_address.is_none());
+
+        state.set_features([Feature::HashOperand].into_iter().collect());
+        let contract = deploy(&mut state, &key, code);
+        let call = Transaction::new(sender, 2, TransactionAction::Call { contract, input: b"abc".to_vec(), value: 0 }, 100_000, 1);
+        let result = Executor::simulate(&state, &call).unwrap();
+        assert_eq!(result.output, blake3::hash(b"abc").as_bytes().to_vec());
+    }
+
+    #[test]
+    fn test_noise_operands_are_seeded_by_parent_hash() {
+        let (key, mut state) = funded_key();
+        let sender = key.verifying_key().to_bytes();
+        state.set_features([Feature::NoiseOperands].into_iter().collect());
+        state.set_parent_hash([5u8; 32]);
+        let noise = Operand::Noise {
+            layer: 3,
+            x: Box::new(Operand::Input),
+            y: Box::new(Operand::Const(250_000i64.to_be_bytes().to_vec())),
+            octaves: 3,
+        };
+        let code = vec![
+            Instruction::Emit { topic: "walk".to_string(), data: Operand::Walk { layer: 3, steps: Box::new(Operand::Const(10i64.to_be_bytes().to_vec())) } },
+            Instruction::Return(noise),
+        ];
+        let contract = deploy(&mut state, &key, code);
+        let x = 1_750_000i64;
+        let call = Transaction::new(sender, 1, TransactionAction::Call { contract, input: x.to_be_bytes().to_vec(), value: 0 }, 100_000, 1);
+
+        // Contracts see the same values the math module gives for the layer and parent hash
+        let seed = NoiseSeed::new(3, &[5u8; 32]);
+        let expected = noise::fractal(&seed, &noise::from_fixed(x as i128), &noise::from_fixed(250_000), 3);
+        let walked = RandomWalk::new(&seed, &noise::from_fixed(0), &noise::from_fixed(noise::ONE)).nth(9).unwrap();
+        let result = Executor::simulate(&state, &call).unwrap();
+        assert_eq!(result.output, (expected.value as i64).to_be_bytes().to_vec());
+        assert_eq!(result.events[0].data, (walked.value as i64).to_be_bytes().to_vec());
+
+        state.set_parent_hash([6u8; 32]);
+        assert_ne!(Executor::simulate(&state, &call).unwrap().output, result.output);
+
+        let bad = Transaction::new(sender, 1, TransactionAction::Call { contract, input: vec![1, 2], value: 0 }, 100_000, 1);
+        assert_eq!(Executor::simulate(&state, &bad).unwrap().error.as_deref(), Some("Noise arguments must be 8-byte big-endian integers"));
+    }
+
+    #[test]
+    fn test_revert_rolls_back_but_pays_gas() {
+        let (key, mut state) = funded_key();
+        let contract = deploy(&mut state, &key, vec![
+            Instruction::Store { key: b"k".to_vec(), value: Operand::Input },
+            Instruction::Revert("nope".to_string()),
+        ]);
+
+        let sender = key.verifying_key().to_bytes();
+        let balance = state.account(&sender).balance;
+        let mut tx = Transaction::new(
+            sender,
+            state.account(&sender).nonce,
+            TransactionAction::Call { contract, input: b"v".to_vec(), value: 10 },
+            100_000,
+            1,
+        );
+        tx.sign(&key);
+
+        let receipt = Executor::apply(&mut state, &tx).unwrap();
+        assert!(!receipt.success);
+        assert_eq!(receipt.error.as_deref(), Some("Reverted: nope"));
+        assert!(state.contract(&contract).unwrap().storage.is_empty());
+        assert_eq!(state.account(&sender).balance, balance - receipt.fee);
+    }
+
+    #[test]
+    fn test_rotate_validator_key_is_journaled() {
+        use crate::blockchain::validator_keys::KeyRotation;
+
+        let (key, mut state) = funded_key();
+        let sender = key.verifying_key().to_bytes();
+        let validator = [4u8; 32];
+        let old = SigningKey::from_bytes(&[5u8; 32]);
+        let new = SigningKey::from_bytes(&[6u8; 32]);
+        state.validator_keys_mut().register(validator, old.verifying_key().to_bytes(), 0).unwrap();
+        state.set_height(9);
+
+        let rotate = |nonce, activation_height| {
+            let mut rotation = KeyRotation::new(validator, [0u8; 32], [0u8; 32], activation_height);
+            rotation.sign(1, &old, &new);
+            let mut tx = Transaction::new(sender, nonce, TransactionAction::RotateValidatorKey(rotation), 200_000, 1);
+            tx.sign(&key);
+            tx
+        };
+
+        // Included at height 10, so activating at 11 is too soon and changes nothing
+        let receipt = Executor::apply(&mut state, &rotate(0, 11)).unwrap();
+        assert_eq!(receipt.error.as_deref(), Some("Activation height too soon"));
+        assert_eq!(state.validator_keys().history(&validator).len(), 1);
+
+        let receipt = Executor::apply(&mut state, &rotate(1, 12)).unwrap();
+        assert!(receipt.success);
+        assert_eq!(state.validator_keys().key_at(&validator, 11), Some(old.verifying_key().to_bytes()));
+        assert_eq!(state.validator_keys().key_at(&validator, 12), Some(new.verifying_key().to_bytes()));
+    }
+
+    #[test]
+    fn test_multisig_spend_and_signer_change_need_approvals() {
+        use crate::blockchain::multisig::Approval;
+        use crate::crypto::domain::MAINNET_NETWORK_ID;
+
+        let (key, mut state) = funded_key();
+        let sender = key.verifying_key().to_bytes();
+        let signers: Vec<SigningKey> = (10..13).map(|i| SigningKey::from_bytes(&[i; 32])).collect();
+        let public: Vec<[u8; 32]> = signers.iter().map(|k| k.verifying_key().to_bytes()).collect();
+
+        let mut create = Transaction::new(sender, 0, TransactionAction::CreateMultisig { signers: public.clone(), threshold: 2 }, 100_000, 1);
+        create.sign(&key);
+        let wallet: Address = Executor::apply(&mut state, &create).unwrap().output.try_into().unwrap();
+        assert_eq!(wallet, multisig::multisig_address(&sender, 0));
+        state.account_mut(&wallet).balance = 1_000;
+
+        let execute = |state: &WorldState, operation: MultisigOperation, approvers: &[&SigningKey]| {
+            let proposal = MultisigProposal { multisig: wallet, nonce: state.multisig(&wallet).unwrap().nonce(), operation };
+            let approvals = approvers.iter().map(|k| Approval::sign(&proposal, MAINNET_NETWORK_ID, k)).collect();
+            let action = TransactionAction::MultisigExecute { multisig: wallet, operation: proposal.operation, approvals };
+            let mut tx = Transaction::new(sender, state.account(&sender).nonce, action, 200_000, 1);
+            tx.sign(&key);
+            tx
+        };
+
+        let spend = MultisigOperation::Transfer { to: [7u8; 32], amount: 400 };
+        let one = execute(&state, spend.clone(), &[&signers[0]]);
+        assert_eq!(Executor::apply(&mut state, &one).unwrap_err(), "Not enough multisig approvals");
+
+        let two = execute(&state, spend.clone(), &[&signers[0], &signers[2]]);
+        assert!(Executor::apply(&mut state, &two).unwrap().success);
+        assert_eq!(state.account(&[7u8; 32]).balance, 400);
+        assert_eq!(state.account(&wallet).balance, 600);
+
+        // The approvals signed the multisig's old nonce and cannot be reused
+        let mut replay = two.clone();
+        replay.nonce = state.account(&sender).nonce;
+        replay.sign(&key);
+        assert_eq!(Executor::apply(&mut state, &replay).unwrap_err(), "Invalid approval signature");
+
+        // Changing the signer set is itself a multisig operation
+        let rotate = MultisigOperation::UpdateSigners { signers: public[..2].to_vec(), threshold: 2 };
+        let rotated = execute(&state, rotate, &[&signers[1], &signers[2]]);
+        assert!(Executor::apply(&mut state, &rotated).unwrap().success);
+        let mut remaining = public[..2].to_vec();
+        remaining.sort();
+        assert_eq!(state.multisig(&wallet).unwrap().signers(), remaining.as_slice());
+
+        // The removed signer no longer counts
+        let spend_again = execute(&state, spend, &[&signers[0], &signers[2]]);
+        assert_eq!(Executor::apply(&mut state, &spend_again).unwrap_err(), "Approval from a key that is not a signer");
+    }
+
+    #[test]
+    fn test_recurring_schedule_runs_from_deposit_until_cancelled() {
+        let (key, mut state) = funded_key();
+        let owner = key.verifying_key().to_bytes();
+        let action = TransactionAction::Schedule {
+            action: ScheduledAction::Transfer { to: [4u8; 32], amount: 100 },
+            start_height: 3,
+            interval: Some(2),
+            max_runs: None,
+            run_gas_limit: 20_000,
+            deposit: 50_000,
+        };
+        let mut tx = Transaction::new(owner, 0, action, 100_000, 1);
+        tx.sign(&key);
+        state.set_height(1);
+        let receipt = Executor::apply_block(&mut state, &[tx]).unwrap().remove(0);
+        let id: [u8; 32] = receipt.output.try_into().unwrap();
+        assert_eq!(id, scheduler::schedule_id(&owner, 0));
+        let after_schedule = state.account(&owner).balance;
+
+        // Heights 3 and 5 run; each run costs SCHEDULED_RUN gas from the deposit
+        let mut runs = Vec::new();
+        for height in 2..=5 {
+            state.set_height(height - 1);
+            runs.extend(Executor::apply_block(&mut state, &[]).unwrap());
+        }
+        assert_eq!(runs.len(), 2);
+        assert!(runs.iter().all(|run| run.success && run.gas_used == gas::SCHEDULED_RUN));
+        assert_eq!(runs[0].tx_hash, scheduler::run_hash(&id, 3));
+        assert_eq!(state.account(&[4u8; 32]).balance, 200);
+        assert_eq!(state.account(&owner).balance, after_schedule - 200);
+        let schedule = state.schedules().get(&id).unwrap();
+        assert_eq!((schedule.next_height, schedule.deposit), (7, 50_000 - 2 * gas::SCHEDULED_RUN as u128));
+
+        // Only the owner may cancel, which refunds what is left of the deposit
+        let outsider = SigningKey::from_bytes(&[5u8; 32]);
+        state.account_mut(&outsider.verifying_key().to_bytes()).balance = 1_000_000;
+        let mut steal = Transaction::new([0u8; 32], 0, TransactionAction::CancelSchedule { id }, 50_000, 1);
+        steal.sign(&outsider);
+        assert!(!Executor::apply(&mut state, &steal).unwrap().success);
+
+        let mut cancel = Transaction::new(owner, 1, TransactionAction::CancelSchedule { id }, 50_000, 1);
+        cancel.sign(&key);
+        let receipt = Executor::apply(&mut state, &cancel).unwrap();
+        assert!(receipt.success);
+        assert!(state.schedules().is_empty());
+        assert_eq!(state.account(&owner).balance, after_schedule - 200 + 30_000 - receipt.fee);
+    }
+
+    #[test]
+    fn test_auction_escrows_bids_and_pays_royalty() {
+        let keys: Vec<SigningKey> = (1..=4).map(|i| SigningKey::from_bytes(&[i; 32])).collect();
+        let [creator, seller, alice, bob] = [0, 1, 2, 3].map(|i| keys[i].verifying_key().to_bytes());
+        let mut state = WorldState::with_balances(&[creator, seller, alice, bob].map(|a| (a, 1_000_000)));
+        let mut nonces = std::collections::HashMap::new();
+        let mut send = |state: &mut WorldState, signer: usize, action| {
+            let nonce = nonces.entry(signer).or_insert(0u64);
+            let mut tx = Transaction::new([0u8; 32], *nonce, action, 100_000, 0);
+            *nonce += 1;
+            tx.sign(&keys[signer]);
+            Executor::apply(state, &tx).unwrap()
+        };
+
+        let mint = AssetAction::Mint { kind: assets::AssetKind::Parcel { x: 4, y: -2 }, royalty_bps: 500, uri: String::new(), metadata: None };
+        let id: [u8; 32] = send(&mut state, 0, TransactionAction::Asset(mint)).output.try_into().unwrap();
+        assert_eq!(state.assets().parcel_at(4, -2), Some(id));
+        assert!(send(&mut state, 0, TransactionAction::Asset(AssetAction::Transfer { asset: id, to: seller })).success);
+
+        let terms = market::ListingTerms::Auction { reserve: 1_000, duration: 10 };
+        let listed = send(&mut state, 1, TransactionAction::Market(MarketAction::List { asset: id, terms }));
+        assert_eq!(listed.events[0].topic, market::TOPIC_ASK);
+        // Listed assets cannot be moved outside the market
+        assert!(!send(&mut state, 1, TransactionAction::Asset(AssetAction::Transfer { asset: id, to: bob })).success);
+
+        assert!(!send(&mut state, 2, TransactionAction::Market(MarketAction::Bid { asset: id, amount: 999 })).success);
+        assert!(send(&mut state, 2, TransactionAction::Market(MarketAction::Bid { asset: id, amount: 1_000 })).success);
+        assert!(send(&mut state, 3, TransactionAction::Market(MarketAction::Bid { asset: id, amount: 2_000 })).success);
+        // Alice was refunded when outbid; only Bob's bid is held
+        assert_eq!(state.account(&alice).balance, 1_000_000);
+        assert_eq!(state.account(&ESCROW_ADDRESS).balance, 2_000);
+        assert!(!send(&mut state, 1, TransactionAction::Market(MarketAction::Cancel { asset: id })).success);
+
+        assert!(!send(&mut state, 2, TransactionAction::Market(MarketAction::Settle { asset: id })).success);
+        state.set_height(10);
+        let settled = send(&mut state, 2, TransactionAction::Market(MarketAction::Settle { asset: id }));
+        assert!(settled.success);
+        assert_eq!(settled.events[0].topic, market::TOPIC_SALE);
+        assert_eq!(state.assets().get(&id).unwrap().owner, bob);
+        assert!(state.listings().is_empty());
+        assert_eq!(state.account(&ESCROW_ADDRESS).balance, 0);
+        assert_eq!(state.account(&creator).balance, 1_000_000 + 100);
+        assert_eq!(state.account(&seller).balance, 1_000_000 + 1_900);
+    }
+
+    #[test]
+    fn test_lease_streams_rent_and_reverts() {
+        let owner_key = SigningKey::from_bytes(&[1u8; 32]);
+        let lessee_key = SigningKey::from_bytes(&[2u8; 32]);
+        let [owner, lessee] = [&owner_key, &lessee_key].map(|k| k.verifying_key().to_bytes());
+        let mut state = WorldState::with_balances(&[(owner, 1_000_000), (lessee, 1_000_000)]);
+        let send = |state: &mut WorldState, key: &SigningKey, nonce, action| {
+            let mut tx = Transaction::new([0u8; 32], nonce, action, 100_000, 0);
+            tx.sign(key);
+            Executor::apply_block(state, &[tx]).unwrap().remove(0)
+        };
+
+        let mint = AssetAction::Mint { kind: assets::AssetKind::Parcel { x: 0, y: 0 }, royalty_bps: 0, uri: String::new(), metadata: None };
+        let id: [u8; 32] = send(&mut state, &owner_key, 0, TransactionAction::Asset(mint)).output.try_into().unwrap();
+        let terms = lease::LeaseTerms { lessee, duration: 20, epoch_blocks: 10, rent_per_epoch: 300 };
+        assert!(send(&mut state, &owner_key, 1, TransactionAction::Lease(LeaseAction::Offer { asset: id, terms })).success);
+        assert!(!send(&mut state, &lessee_key, 0, TransactionAction::Lease(LeaseAction::Accept { asset: id, total_rent: 300 })).success);
+        // Accepted in block 5: epochs end at 15 and 25
+        state.set_height(4);
+        let accepted = send(&mut state, &lessee_key, 1, TransactionAction::Lease(LeaseAction::Accept { asset: id, total_rent: 600 }));
+        assert!(accepted.success);
+        assert_eq!(state.leases().lease(&id).unwrap().start_height, 5);
+        assert_eq!(state.account(&LEASE_ESCROW_ADDRESS).balance, 600);
+        assert_eq!(lease::writer(&state, &id, 6), Some(lessee));
+        let rule = lease::parcel_write_rule(&state, 6);
+        assert!(rule(&lessee, &id) && !rule(&owner, &id) && rule(&owner, &[9u8; 32]));
+        // The owner cannot move the asset out from under the lessee
+        let moved = send(&mut state, &owner_key, 2, TransactionAction::Asset(AssetAction::Transfer { asset: id, to: [9u8; 32] }));
+        assert_eq!(moved.error.as_deref(), Some("Asset is leased"));
+
+        for height in 6..=15 {
+            state.set_height(height - 1);
+            Executor::apply_block(&mut state, &[]).unwrap();
+        }
+        assert_eq!(state.account(&owner).balance, 1_000_300);
+        assert!(state.leases().lease(&id).is_some());
+        for height in 16..=25 {
+            state.set_height(height - 1);
+            Executor::apply_block(&mut state, &[]).unwrap();
+        }
+        assert_eq!(state.account(&owner).balance, 1_000_600);
+        assert_eq!(state.account(&lessee).balance, 1_000_000 - 600);
+        assert!(state.leases().lease(&id).is_none());
+        assert_eq!(lease::writer(&state, &id, 25), Some(owner));
+    }
+
+    #[test]
+    fn test_channel_dispute_replaces_stale_close() {
+        use channel::{BalanceUpdate, SignedUpdate, Watchtower};
+        use crate::crypto::domain::MAINNET_NETWORK_ID;
+        let alice_key = SigningKey::from_bytes(&[1u8; 32]);
+        let bob_key = SigningKey::from_bytes(&[2u8; 32]);
+        let tower_key = SigningKey::from_bytes(&[3u8; 32]);
+        let [alice, bob] = [&alice_key, &bob_key].map(|k| k.verifying_key().to_bytes());
+        let mut state = WorldState::with_balances(&[(alice, 1_000_000)]);
+        let send = |state: &mut WorldState, key: &SigningKey, nonce, action| {
+            let mut tx = Transaction::new([0u8; 32], nonce, TransactionAction::Channel(action), 100_000, 0);
+            tx.sign(key);
+            Executor::apply_block(state, &[tx]).unwrap().remove(0)
+        };
+
+        let open = ChannelAction::Open { counterparty: bob, deposit: 1_000, dispute_blocks: 10 };
+        let refused = send(&mut state, &alice_key, 0, open.clone());
+        assert_eq!(refused.error.as_deref(), Some("Payment channels are not active"));
+        state.set_features([Feature::PaymentChannels].into_iter().collect());
+        let id: [u8; 32] = send(&mut state, &alice_key, 1, open).output.try_into().unwrap();
+        assert_eq!(state.account(&CHANNEL_ESCROW_ADDRESS).balance, 1_000);
+
+        // Alice streams to Bob off chain, then closes on the opening split
+        let streamed = |sequence: u64| SignedUpdate::sign(
+            BalanceUpdate { channel: id, sequence, balance_a: 1_000 - sequence as u128 * 10, balance_b: sequence as u128 * 10, is_final: false },
+            MAINNET_NETWORK_ID,
+            &alice_key,
+        );
+        let mut tower = Watchtower::new();
+        for sequence in 1..=5 {
+            tower.watch(state.channels().get(&id).unwrap(), streamed(sequence), MAINNET_NETWORK_ID).unwrap();
+        }
+        state.set_height(20);
+        assert!(send(&mut state, &alice_key, 2, ChannelAction::Close { channel: id, update: None }).success);
+        assert_eq!(state.channels().get(&id).unwrap().closing.as_ref().unwrap().settles_at, 31);
+
+        // Bob is offline; the watchtower disputes with Alice's last update
+        let stale = tower.check(state.channels());
+        assert_eq!(stale.len(), 1);
+        let older = send(&mut state, &tower_key, 0, ChannelAction::Dispute { update: streamed(0) });
+        assert_eq!(older.error.as_deref(), Some("Update is not newer than the close"));
+        assert!(send(&mut state, &tower_key, 1, stale[0].dispute.clone()).success);
+        assert!(tower.check(state.channels()).is_empty());
+
+        for height in 23..=31 {
+            state.set_height(height - 1);
+            Executor::apply_block(&mut state, &[]).unwrap();
+        }
+        assert!(state.channels().get(&id).is_none());
+        assert_eq!(state.account(&bob).balance, 50);
+        assert_eq!(state.account(&alice).balance, 1_000_000 - 50);
+        assert_eq!(state.account(&CHANNEL_ESCROW_ADDRESS).balance, 0);
+
+        // A cooperative close pays out at once, but only on a final update
+        let open = ChannelAction::Open { counterparty: bob, deposit: 500, dispute_blocks: 10 };
+        let id: [u8; 32] = send(&mut state, &alice_key, 3, open).output.try_into().unwrap();
+        assert!(send(&mut state, &bob_key, 0, ChannelAction::Deposit { channel: id, amount: 50 }).success);
+        let split = BalanceUpdate { channel: id, sequence: 7, balance_a: 400, balance_b: 150, is_final: false };
+        let not_final = send(&mut state, &bob_key, 1, ChannelAction::Settle { update: SignedUpdate::sign(split.clone(), MAINNET_NETWORK_ID, &alice_key) });
+        assert_eq!(not_final.error.as_deref(), Some("Settling needs a final update"));
+        let update = SignedUpdate::sign(BalanceUpdate { is_final: true, ..split }, MAINNET_NETWORK_ID, &alice_key);
+        assert!(send(&mut state, &bob_key, 2, ChannelAction::Settle { update }).success);
+        assert!(state.channels().get(&id).is_none());
+        assert_eq!(state.account(&bob).balance, 150);
+    }
+
+    #[test]
+    fn test_htlc_claim_reveals_secret_and_refund_waits() {
+        let alice_key = SigningKey::from_bytes(&[1u8; 32]);
+        let relayer_key = SigningKey::from_bytes(&[3u8; 32]);
+        let alice = alice_key.verifying_key().to_bytes();
+        let bob = [2u8; 32];
+        let mut state = WorldState::with_balances(&[(alice, 1_000)]);
+        state.set_features([Feature::HashTimeLocks].into_iter().collect());
+        let send = |state: &mut WorldState, key: &SigningKey, nonce, action| {
+            let mut tx = Transaction::new([0u8; 32], nonce, TransactionAction::Htlc(action), 100_000, 0);
+            tx.sign(key);
+            Executor::apply_block(state, &[tx]).unwrap().remove(0)
+        };
+
+        let secret = htlc::generate_secret();
+        let lock = |amount| HtlcAction::Lock(htlc::HtlcTerms { recipient: bob, amount, hashlock: htlc::hashlock(&secret), timelock: 20 });
+        state.set_height(9);
+        let first: [u8; 32] = send(&mut state, &alice_key, 0, lock(300)).output.try_into().unwrap();
+        let second: [u8; 32] = send(&mut state, &alice_key, 1, lock(200)).output.try_into().unwrap();
+        assert_eq!(state.account(&HTLC_ESCROW_ADDRESS).balance, 500);
+
+        // Anyone holding the secret can claim for Bob, and the claim publishes it
+        let wrong = send(&mut state, &relayer_key, 0, HtlcAction::Claim { id: first, secret: [0u8; 32] });
+        assert_eq!(wrong.error.as_deref(), Some("Secret does not open the hashlock"));
+        let claimed = send(&mut state, &relayer_key, 1, HtlcAction::Claim { id: first, secret });
+        assert!(claimed.success);
+        assert_eq!(claimed.events[0].topic, htlc::TOPIC_CLAIMED);
+        assert_eq!(&claimed.events[0].data[32..], &secret);
+        assert_eq!(state.account(&bob).balance, 300);
+        assert!(state.htlcs().get(&first).is_none());
+
+        let early = send(&mut state, &relayer_key, 2, HtlcAction::Refund { id: second });
+        assert_eq!(early.error.as_deref(), Some("HTLC has not timed out"));
+        state.set_height(19);
+        assert!(send(&mut state, &relayer_key, 3, HtlcAction::Refund { id: second }).success);
+        assert_eq!(state.account(&alice).balance, 700);
+        assert_eq!(state.account(&HTLC_ESCROW_ADDRESS).balance, 0);
+    }
+
+    #[test]
+    fn test_sponsor_pays_fees_within_limits() {
+        let user_key = SigningKey::from_bytes(&[1u8; 32]);
+        let sponsor_key = SigningKey::from_bytes(&[2u8; 32]);
+        let [user, sponsor] = [&user_key, &sponsor_key].map(|k| k.verifying_key().to_bytes());
+        let mut state = WorldState::with_balances(&[(sponsor, 1_000_000)]);
+        state.set_features([Feature::FeeSponsorship].into_iter().collect());
+        let set_policy = SponsorAction::SetPolicy { limit: 500_000, sender_limit: 150_000, window_blocks: 100 };
+        let mut tx = Transaction::new([0u8; 32], 0, TransactionAction::Sponsor(set_policy), 100_000, 0);
+        tx.sign(&sponsor_key);
+        assert!(Executor::apply(&mut state, &tx).unwrap().success);
+
+        // The user holds no tokens; the sponsor countersigns and pays the fee
+        let sponsored = |nonce, gas_limit, valid_until| {
+            let transfer = TransactionAction::Transfer { to: [9u8; 32], amount: 0 };
+            let mut tx = Transaction::new([0u8; 32], nonce, transfer, gas_limit, 1).with_sponsor(sponsor, valid_until);
+            tx.sign(&user_key);
+            tx
+        };
+        let mut tx = sponsored(0, 100_000, 10);
+        assert_eq!(Executor::apply_block(&mut state, &[tx.clone()]).unwrap_err(), "Invalid sponsor signature length");
+        tx.countersign(&sponsor_key).unwrap();
+        let receipt = Executor::apply_block(&mut state, &[tx.clone()]).unwrap().remove(0);
+        assert!(receipt.success);
+        assert_eq!(state.account(&user).balance, 0);
+        assert_eq!(state.account(&sponsor).balance, 1_000_000 - receipt.fee);
+        assert_eq!(state.sponsors().get(&sponsor).unwrap().spent_for(&user, 2), receipt.fee);
+
+        // The countersignature covers one nonce and expires
+        assert_eq!(Executor::apply_block(&mut state, &[tx]).unwrap_err(), "Invalid nonce");
+        let mut late = sponsored(1, 100_000, 0);
+        late.countersign(&sponsor_key).unwrap();
+        assert_eq!(Executor::apply(&mut state, &late).unwrap_err(), "Sponsorship has expired");
+        let mut greedy = sponsored(1, 150_000, 10);
+        greedy.countersign(&sponsor_key).unwrap();
+        assert_eq!(Executor::apply(&mut state, &greedy).unwrap_err(), "Sponsor spending limit for this sender reached");
+
+        let mut tx = Transaction::new([0u8; 32], 1, TransactionAction::Sponsor(SponsorAction::Revoke), 100_000, 0);
+        tx.sign(&sponsor_key);
+        assert!(Executor::apply(&mut state, &tx).unwrap().success);
+        let mut revoked = sponsored(1, 100_000, 10);
+        revoked.countersign(&sponsor_key).unwrap();
+        assert_eq!(Executor::apply(&mut state, &revoked).unwrap_err(), "Sponsor has no spending policy");
+    }
+
+    #[test]
+    fn test_session_key_validated_by_contract() {
+        use account_validation::{validation_address, StorageEntry};
+        let owner_key = SigningKey::from_bytes(&[1u8; 32]);
+        let session_key = SigningKey::from_bytes(&[2u8; 32]);
+        let [owner, session] = [&owner_key, &session_key].map(|k| k.verifying_key().to_bytes());
+        let (shop, elsewhere) = ([7u8; 32], [8u8; 32]);
+        let mut state = WorldState::with_balances(&[(owner, 1_000_000)]);
+        state.set_features([Feature::AccountAbstraction].into_iter().collect());
+
+        // A session key valid through height 10, for payments to the shop of
+        // up to 500 in total
+        let key_of = |prefix: &[u8], parts: Vec<Operand>| Operand::LoadAt(Box::new(Operand::Concat(
+            std::iter::once(Operand::Const(prefix.to_vec())).chain(parts).collect(),
+        )));
+        let spent = || Operand::Add(Box::new(Operand::Load(b"spent".to_vec())), Box::new(Operand::Value));
+        let code = vec![
+            Instruction::Require {
+                left: key_of(b"session:", vec![Operand::Signer(0)]),
+                comparison: Comparison::Ge,
+                right: Operand::Height,
+                reason: "Unknown or expired session key".to_string(),
+            },
+            Instruction::Require {
+                left: key_of(b"scope:", vec![Operand::Signer(0), Operand::Target]),
+                comparison: Comparison::Eq,
+                right: Operand::Const(vec![1]),
+                reason: "Target outside the session's scope".to_string(),
+            },
+            Instruction::Require {
+                left: spent(),
+                comparison: Comparison::Le,
+                right: Operand::Const(500u128.to_be_bytes().to_vec()),
+                reason: "Spending limit reached".to_string(),
+            },
+            Instruction::Store { key: b"spent".to_vec(), value: spent() },
+        ];
+        let storage = vec![
+            StorageEntry { key: [&b"session:"[..], &session].concat(), value: 10u64.to_be_bytes().to_vec() },
+            StorageEntry { key: [&b"scope:"[..], &session, &shop].concat(), value: vec![1] },
+        ];
+        let mut install = Transaction::new(owner, 0, TransactionAction::Validation(ValidationAction::Install { code, storage, gas_limit: 50_000 }), 300_000, 0);
+        install.sign(&owner_key);
+        assert!(Executor::apply(&mut state, &install).unwrap().success);
+
+        let pay = |state: &mut WorldState, key: &SigningKey, to, amount| {
+            let nonce = state.account(&owner).nonce;
+            let mut tx = Transaction::new(owner, nonce, TransactionAction::Transfer { to, amount }, 200_000, 0);
+            tx.witness(key);
+            Executor::apply(state, &tx)
+        };
+        assert!(pay(&mut state, &session_key, shop, 300).unwrap().success);
+        let rejected = Err("Rejected by the account's validation contract");
+        assert_eq!(pay(&mut state, &session_key, shop, 300).map(|_| ()), rejected);
+        assert_eq!(pay(&mut state, &session_key, elsewhere, 100).map(|_| ()), rejected);
+        assert_eq!(pay(&mut state, &SigningKey::from_bytes(&[3u8; 32]), shop, 100).map(|_| ()), rejected);
+        assert!(pay(&mut state, &session_key, shop, 200).unwrap().success);
+        assert_eq!(state.account(&shop).balance, 500);
+        let contract = validation_address(&owner);
+        assert_eq!(Executor::compare(&state.contract(&contract).unwrap().storage[&b"spent".to_vec()], &[1, 244]), Ordering::Equal);
+
+        // Rejections leave no trace, and the key stops working after its expiry
+        assert_eq!(state.account(&owner).nonce, 3);
+        state.set_height(10);
+        assert_eq!(pay(&mut state, &session_key, shop, 0).map(|_| ()), rejected);
+
+        // The owner's own key still signs, but cannot call the contract directly
+        let mut call = Transaction::new(owner, 3, TransactionAction::Call { contract, input: Vec::new(), value: 0 }, 100_000, 0);
+        call.sign(&owner_key);
+        let receipt = Executor::apply(&mut state, &call).unwrap();
+        assert_eq!(receipt.error.as_deref(), Some("Validation contracts cannot be called"));
+    }
+
+    #[test]
+    fn test_confidential_transfer_hides_amounts() {
+        use confidential::{Opening, view_key};
+        use curve25519_dalek::scalar::Scalar;
+        let alice_key = SigningKey::from_bytes(&[1u8; 32]);
+        let bob_key = SigningKey::from_bytes(&[2u8; 32]);
+        let [alice, bob] = [&alice_key, &bob_key].map(|k| k.verifying_key().to_bytes());
+        let [alice_view, bob_view] = [pedersen::random_scalar(), pedersen::random_scalar()];
+        let mut state = WorldState::with_balances(&[(alice, 10_000), (bob, 10_000)]);
+        let send = |state: &mut WorldState, key: &SigningKey, nonce, action| {
+            let mut tx = Transaction::new([0u8; 32], nonce, TransactionAction::Confidential(action), 2_000_000, 0);
+            tx.sign(key);
+            Executor::apply(state, &tx).unwrap()
+        };
+
+        let register = ConfidentialAction::Register { view_key: view_key(&alice_view) };
+        assert_eq!(send(&mut state, &alice_key, 0, register.clone()).error.as_deref(), Some("Confidential transfers are not active"));
+        state.set_features([Feature::ConfidentialTransfers].into_iter().collect());
+        assert!(send(&mut state, &alice_key, 1, register).success);
+        assert!(send(&mut state, &bob_key, 0, ConfidentialAction::Register { view_key: view_key(&bob_view) }).success);
+        assert!(send(&mut state, &alice_key, 2, ConfidentialAction::Shield { amount: 1_000 }).success);
+        assert_eq!(state.account(&CONFIDENTIAL_POOL_ADDRESS).balance, 1_000);
+
+        // Shielded amounts carry no blinding, so Alice knows her balance's opening
+        let balance = Opening::new(1_000, Scalar::ZERO);
+        let bob_view_key = state.confidential().get(&bob).unwrap().view_key;
+        let (transfer, _) = ConfidentialAction::transfer(&alice, bob, &balance, 300, &bob_view_key, &view_key(&alice_view)).unwrap();
+        assert!(send(&mut state, &alice_key, 3, transfer).success);
+        let (overdraw, _) = ConfidentialAction::transfer(&alice, bob, &balance, 300, &bob_view_key, &bob_view_key).unwrap();
+        assert!(!send(&mut state, &alice_key, 4, overdraw).success);
+
+        // Bob opens the memo with his view secret and rolls the transfer in
+        let incoming = state.confidential().get(&bob).unwrap().pending[0].clone();
+        let received = incoming.memo.open(&bob_view, &incoming.commitment).unwrap();
+        assert_eq!(received.value, 300);
+        assert!(send(&mut state, &bob_key, 1, ConfidentialAction::Rollover { count: 1, rejected: Vec::new() }).success);
+        assert_eq!(state.confidential().get(&bob).unwrap().balance, received.commitment());
+
+        let (unshield, _) = ConfidentialAction::unshield(&bob, &received, 200).unwrap();
+        assert!(send(&mut state, &bob_key, 2, unshield).success);
+        assert_eq!(state.account(&bob).balance, 10_200);
+        assert_eq!(state.account(&CONFIDENTIAL_POOL_ADDRESS).balance, 800);
+        let (too_much, _) = ConfidentialAction::unshield(&bob, &Opening::new(1_000, received.blinding), 1_000).unwrap();
+        assert!(!send(&mut state, &bob_key, 3, too_much).success);
+    }
+
+    #[test]
+    fn test_out_of_gas_charges_limit_and_refunds_unused() {
+        let (key, mut state) = funded_key();
+        let contract = deploy(&mut state, &key, vec![
+            Instruction::Store { key: b"a".to_vec(), value: Operand::Input },
+            Instruction::Store { key: b"b".to_vec(), value: Operand::Input },
+        ]);
+        let sender = key.verifying_key().to_bytes();
+
+        // Enough gas for the first store but not the second
+        let call = |nonce, gas_limit| {
+            let mut tx = Transaction::new(
+                sender,
+                nonce,
+                TransactionAction::Call { contract, input: b"v".to_vec(), value: 0 },
+                gas_limit,
+                1,
+            );
+            tx.sign(&key);
+            tx
+        };
+        let tx = call(1, Executor::intrinsic_gas(&call(1, 0)) + 30_000);
+        let balance = state.account(&sender).balance;
+        let receipt = Executor::apply(&mut state, &tx).unwrap();
+        assert!(!receipt.success);
+        assert_eq!(receipt.error.as_deref(), Some("Out of gas"));
+        assert_eq!(receipt.gas_used, tx.gas_limit);
+        assert_eq!(receipt.refund, 0);
+        assert!(state.contract(&contract).unwrap().storage.is_empty());
+        assert_eq!(state.account(&sender).balance, balance - receipt.fee);
+        assert_eq!(state.account(&sender).nonce, 2);
+
+        let tx = call(2, 200_000);
+        let receipt = Executor::apply(&mut state, &tx).unwrap();
+        assert!(receipt.success);
+        assert_eq!(receipt.fee + receipt.refund, tx.max_fee());
+        assert_eq!(state.contract(&contract).unwrap().storage.len(), 2);
+    }
+}
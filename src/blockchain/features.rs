@@ -0,0 +1,285 @@
+//! Coordinated protocol upgrades by validator signaling.
+//!
+//! Each feature owns a bit of the block header's `signals` field, which a
+//! validator sets once it runs software supporting the feature. Heights are
+//! grouped into windows of `window_blocks`. When at least `threshold_percent`
+//! of a window's blocks signal a feature it locks in, and it becomes active
+//! one full window later, at the same height on every node. New behavior is
+//! gated on `FeatureTracker::active_at` for the height being processed.
+
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+
+/// Protocol features that activate by signaling
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    /// `hash` contract operand
+    HashOperand,
+    /// `noise` and `walk` contract operands
+    NoiseOperands,
+    /// Shielded accounts and `confidential` transactions
+    ConfidentialTransfers,
+    /// Payment channels and `channel` transactions
+    PaymentChannels,
+    /// Hash-time-locked contracts and `htlc` transactions
+    HashTimeLocks,
+    /// Sponsored transactions and `sponsor` policies
+    FeeSponsorship,
+    /// Validation contracts, witnessed transactions and the operands and
+    /// instructions they use
+    AccountAbstraction,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 7] = [
+        Feature::HashOperand,
+        Feature::NoiseOperands,
+        Feature::ConfidentialTransfers,
+        Feature::PaymentChannels,
+        Feature::HashTimeLocks,
+        Feature::FeeSponsorship,
+        Feature::AccountAbstraction,
+    ];
+
+    /// Header bit signaling this feature
+    pub fn bit(&self) -> u8 {
+        match self {
+            Feature::HashOperand => 0,
+            Feature::NoiseOperands => 1,
+            Feature::ConfidentialTransfers => 2,
+            Feature::PaymentChannels => 3,
+            Feature::HashTimeLocks => 4,
+            Feature::FeeSponsorship => 5,
+            Feature::AccountAbstraction => 6,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Feature::HashOperand => "vm.hash_operand",
+            Feature::NoiseOperands => "vm.noise_operands",
+            Feature::ConfidentialTransfers => "ledger.confidential_transfers",
+            Feature::PaymentChannels => "ledger.payment_channels",
+            Feature::HashTimeLocks => "ledger.htlc",
+            Feature::FeeSponsorship => "ledger.fee_sponsorship",
+            Feature::AccountAbstraction => "ledger.account_abstraction",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|feature| feature.name() == name)
+    }
+}
+
+/// Set of features as header signal bits
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct FeatureSet(u32);
+
+impl FeatureSet {
+    pub fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(&self, feature: Feature) -> bool {
+        self.0 & (1 << feature.bit()) != 0
+    }
+
+    pub fn is_subset(&self, other: &FeatureSet) -> bool {
+        self.0 & !other.0 == 0
+    }
+
+    pub fn insert(&mut self, feature: Feature) {
+        self.0 |= 1 << feature.bit();
+    }
+
+    pub fn union(&self, other: &FeatureSet) -> FeatureSet {
+        Self(self.0 | other.0)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Feature> {
+        let set = *self;
+        Feature::ALL.into_iter().filter(move |feature| set.contains(*feature))
+    }
+}
+
+impl FromIterator<Feature> for FeatureSet {
+    fn from_iter<I: IntoIterator<Item = Feature>>(iter: I) -> Self {
+        let mut set = Self::default();
+        for feature in iter {
+            set.insert(feature);
+        }
+        set
+    }
+}
+
+/// Signaling window and lock-in threshold; consensus-critical
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActivationParams {
+    pub window_blocks: u64,
+    /// Share of a window's blocks that must signal, in percent
+    pub threshold_percent: u8,
+}
+
+impl Default for ActivationParams {
+    fn default() -> Self {
+        Self {
+            window_blocks: 1_000,
+            threshold_percent: 90,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeatureState {
+    /// Waiting for enough signals
+    Defined,
+    /// Threshold reached; activation height fixed
+    LockedIn,
+    Active,
+}
+
+/// Where a feature stands at some height
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FeatureStatus {
+    pub name: &'static str,
+    pub bit: u8,
+    pub state: FeatureState,
+    pub activation_height: Option<u64>,
+    /// Signaling blocks so far in the current window
+    pub window_signals: u64,
+}
+
+/// Heights at which a feature locked in and activates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockIn {
+    pub feature: Feature,
+    pub locked_in_at: u64,
+    pub activation_height: u64,
+}
+
+/// Feature Tracker
+/// Counts signals block by block and records when each feature locks in
+/// and activates. Blocks must be fed in height order starting from genesis.
+#[derive(Debug, Clone)]
+pub struct FeatureTracker {
+    params: ActivationParams,
+    next_height: u64,
+    window_signals: BTreeMap<Feature, u64>,
+    lock_ins: BTreeMap<Feature, LockIn>,
+}
+
+impl FeatureTracker {
+    pub fn new(params: ActivationParams) -> Self {
+        Self {
+            params,
+            next_height: 0,
+            window_signals: BTreeMap::new(),
+            lock_ins: BTreeMap::new(),
+        }
+    }
+
+    /// Next height `record` expects
+    pub fn next_height(&self) -> u64 {
+        self.next_height
+    }
+
+    /// Count the signals of the block at `height`. Returns the features that
+    /// locked in when this block closed its window.
+    pub fn record(&mut self, height: u64, signals: FeatureSet) -> Result<Vec<LockIn>, &'static str> {
+        if height != self.next_height {
+            return Err("Feature signals must be recorded in height order");
+        }
+        self.next_height += 1;
+        for feature in signals.iter() {
+            *self.window_signals.entry(feature).or_default() += 1;
+        }
+
+        let window = self.params.window_blocks.max(1);
+        if !(height + 1).is_multiple_of(window) {
+            return Ok(Vec::new());
+        }
+        let required = (window * self.params.threshold_percent as u64).div_ceil(100);
+        let mut locked = Vec::new();
+        for (feature, count) in std::mem::take(&mut self.window_signals) {
+            if count >= required && !self.lock_ins.contains_key(&feature) {
+                let lock_in = LockIn {
+                    feature,
+                    locked_in_at: height + 1,
+                    activation_height: height + 1 + window,
+                };
+                self.lock_ins.insert(feature, lock_in);
+                locked.push(lock_in);
+            }
+        }
+        Ok(locked)
+    }
+
+    pub fn state(&self, feature: Feature, height: u64) -> FeatureState {
+        match self.lock_ins.get(&feature) {
+            Some(lock_in) if height >= lock_in.activation_height => FeatureState::Active,
+            Some(lock_in) if height >= lock_in.locked_in_at => FeatureState::LockedIn,
+            _ => FeatureState::Defined,
+        }
+    }
+
+    /// Features whose new behavior applies at `height`
+    pub fn active_at(&self, height: u64) -> FeatureSet {
+        Feature::ALL.into_iter()
+            .filter(|feature| self.state(*feature, height) == FeatureState::Active)
+            .collect()
+    }
+
+    pub fn status(&self, height: u64) -> Vec<FeatureStatus> {
+        Feature::ALL.into_iter()
+            .map(|feature| FeatureStatus {
+                name: feature.name(),
+                bit: feature.bit(),
+                state: self.state(feature, height),
+                activation_height: self.lock_ins.get(&feature).map(|lock_in| lock_in.activation_height),
+                window_signals: self.window_signals.get(&feature).copied().unwrap_or(0),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_in_and_activation() {
+        let mut tracker = FeatureTracker::new(ActivationParams { window_blocks: 10, threshold_percent: 80 });
+        let signal: FeatureSet = [Feature::HashOperand].into_iter().collect();
+        assert!(tracker.record(1, signal).is_err());
+
+        // 7 of 10 signal: short of the threshold
+        for height in 0..10 {
+            let signals = if height < 7 { signal } else { FeatureSet::default() };
+            assert!(tracker.record(height, signals).unwrap().is_empty());
+        }
+        assert_eq!(tracker.state(Feature::HashOperand, 10), FeatureState::Defined);
+
+        // 8 of 10 lock in at the window boundary, activating a window later
+        let mut locked = Vec::new();
+        for height in 10..20 {
+            let signals = if height < 18 { signal } else { FeatureSet::default() };
+            locked.extend(tracker.record(height, signals).unwrap());
+        }
+        assert_eq!(locked, vec![LockIn { feature: Feature::HashOperand, locked_in_at: 20, activation_height: 30 }]);
+        assert_eq!(tracker.state(Feature::HashOperand, 25), FeatureState::LockedIn);
+        assert!(!tracker.active_at(29).contains(Feature::HashOperand));
+        assert!(tracker.active_at(30).contains(Feature::HashOperand));
+        assert_eq!(tracker.status(30)[0].state, FeatureState::Active);
+    }
+}
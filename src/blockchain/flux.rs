@@ -2,11 +2,14 @@ use crate::math::precision::PreciseFloat;
 use crate::math::quantum_state::QuantumState;
 use crate::math::quantum_entropy::DecoherenceModel;
 use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
 use num_complex::Complex64;
+use num_traits::ToPrimitive;
 use super::types::QuantumNodeID;
+use serde::{Serialize, Deserialize};
 
 /// Flux Chaos Node Implementation
-#[derive(Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[allow(dead_code)]
 pub struct FluxNode {
     id: NodeId,
@@ -18,7 +21,29 @@ pub struct FluxNode {
 
 type NodeId = QuantumNodeID;
 
-#[derive(Clone)]
+impl FluxNode {
+    pub fn id(&self) -> &NodeId {
+        &self.id
+    }
+
+    pub fn state(&self) -> &NodeState {
+        &self.state
+    }
+
+    pub fn entropy(&self) -> &PreciseFloat {
+        &self.entropy
+    }
+
+    pub fn load_factor(&self) -> &PreciseFloat {
+        &self.load_factor
+    }
+
+    pub fn connections(&self) -> &HashSet<NodeId> {
+        &self.connections
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NodeState {
     processing_power: PreciseFloat,
     reliability: PreciseFloat,
@@ -26,14 +51,76 @@ pub struct NodeState {
     last_sync: u64,
 }
 
+impl NodeState {
+    pub fn new(processing_power: PreciseFloat, reliability: PreciseFloat) -> Self {
+        Self {
+            processing_power,
+            reliability,
+            uptime: 0,
+            last_sync: 0,
+        }
+    }
+
+    pub fn with_uptime(mut self, uptime: u64) -> Self {
+        self.uptime = uptime;
+        self
+    }
+
+    pub fn with_last_sync(mut self, last_sync: u64) -> Self {
+        self.last_sync = last_sync;
+        self
+    }
+
+    pub fn processing_power(&self) -> &PreciseFloat {
+        &self.processing_power
+    }
+
+    pub fn reliability(&self) -> &PreciseFloat {
+        &self.reliability
+    }
+
+    pub fn uptime(&self) -> u64 {
+        self.uptime
+    }
+
+    pub fn last_sync(&self) -> u64 {
+        self.last_sync
+    }
+}
+
+/// Measured load above which links into a node are moved elsewhere
+const CONGESTION_THRESHOLD: f64 = 0.8;
+
+/// Routing decisions and topology, for metrics
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RoutingStats {
+    pub nodes: usize,
+    pub routes: usize,
+    /// Transactions given a route
+    pub routed: u64,
+    /// Lookups with no known route
+    pub unroutable: u64,
+    /// Routes rejected for entropy below the chaos threshold
+    pub low_entropy: u64,
+    pub rebalances: u64,
+    /// Links moved by the most recent rebalance
+    pub last_rebalance_changes: usize,
+    /// Highest measured node load, as a fraction of capacity
+    pub max_load: f64,
+}
+
 pub struct FluxNetwork {
     precision: u8,
     nodes: HashMap<NodeId, FluxNode>,
     routing_table: HashMap<NodeId, Vec<RouteInfo>>,
     chaos_threshold: PreciseFloat,
+    routed: AtomicU64,
+    unroutable: AtomicU64,
+    low_entropy: AtomicU64,
+    rebalances: u64,
+    last_rebalance_changes: usize,
 }
 
-#[allow(dead_code)]
 struct RouteInfo {
     target: NodeId,
     entropy_cost: PreciseFloat,
@@ -48,6 +135,11 @@ impl FluxNetwork {
             nodes: HashMap::new(),
             routing_table: HashMap::new(),
             chaos_threshold: PreciseFloat::new(85, 2), // 0.85 threshold
+            routed: AtomicU64::new(0),
+            unroutable: AtomicU64::new(0),
+            low_entropy: AtomicU64::new(0),
+            rebalances: 0,
+            last_rebalance_changes: 0,
         }
     }
 
@@ -55,7 +147,7 @@ impl FluxNetwork {
         // Calculate initial entropy
         let entropy = self.calculate_node_entropy(&state);
         
-        if entropy.value < self.chaos_threshold.value {
+        if to_f64(&entropy) < to_f64(&self.chaos_threshold) {
             return Err("Node entropy below threshold");
         }
 
@@ -74,24 +166,99 @@ impl FluxNetwork {
         Ok(())
     }
 
+    /// Link two nodes in both directions and recompute routes
+    pub fn connect(&mut self, a: &NodeId, b: &NodeId) -> Result<(), &'static str> {
+        if a == b || !self.nodes.contains_key(b) {
+            return Err("Node not found");
+        }
+        self.nodes.get_mut(a).ok_or("Node not found")?.connections.insert(*b);
+        self.nodes.get_mut(b).ok_or("Node not found")?.connections.insert(*a);
+        self.update_routing_table();
+        Ok(())
+    }
+
+    /// Drop a node and its links, e.g. when its peer disconnects
+    pub fn remove_node(&mut self, id: &NodeId) -> Option<FluxNode> {
+        let node = self.nodes.remove(id)?;
+        for other in self.nodes.values_mut() {
+            other.connections.remove(id);
+        }
+        self.update_routing_table();
+        Some(node)
+    }
+
     pub fn route_transaction(&self, from: &NodeId, to: &NodeId) -> Result<Vec<NodeId>, &'static str> {
         // Get optimal route
-        let routes = self.routing_table.get(from)
-            .ok_or("Source node not found")?;
-        
-        let route = routes.iter()
-            .find(|r| r.target == *to)
-            .ok_or("No route found")?;
+        let route = self.routing_table.get(from)
+            .and_then(|routes| routes.iter().find(|r| r.target == *to));
+        let Some(route) = route else {
+            self.unroutable.fetch_add(1, Ordering::Relaxed);
+            return Err(if self.routing_table.contains_key(from) { "No route found" } else { "Source node not found" });
+        };
 
         // Validate route entropy
         let route_entropy = self.calculate_route_entropy(&route.path);
-        if route_entropy.value < self.chaos_threshold.value {
+        if to_f64(&route_entropy) < to_f64(&self.chaos_threshold) {
+            self.low_entropy.fetch_add(1, Ordering::Relaxed);
             return Err("Route entropy below threshold");
         }
 
+        self.routed.fetch_add(1, Ordering::Relaxed);
         Ok(route.path.clone())
     }
 
+    /// Peers to relay a transaction to from `from`: the first hops of the
+    /// least loaded, highest entropy routes, up to `fanout` distinct peers.
+    /// Empty when no route is known, in which case callers fall back to plain gossip.
+    pub fn relay_targets(&self, from: &NodeId, fanout: usize) -> Vec<NodeId> {
+        let Some(routes) = self.routing_table.get(from) else {
+            self.unroutable.fetch_add(1, Ordering::Relaxed);
+            return Vec::new();
+        };
+        let mut candidates: Vec<&RouteInfo> = routes.iter().filter(|route| route.path.len() >= 2).collect();
+        candidates.sort_by(|a, b| {
+            to_f64(&a.load_factor).total_cmp(&to_f64(&b.load_factor))
+                .then(to_f64(&b.entropy_cost).total_cmp(&to_f64(&a.entropy_cost)))
+        });
+
+        let mut hops = Vec::new();
+        for route in candidates {
+            if hops.len() >= fanout {
+                break;
+            }
+            let Ok(path) = self.route_transaction(from, &route.target) else { continue };
+            if !hops.contains(&path[1]) {
+                hops.push(path[1]);
+            }
+        }
+        hops
+    }
+
+    /// Record a node's measured load as a fraction of its capacity,
+    /// e.g. `PreciseFloat::new(40, 2) // 0.40`. Routes pick it up on the next rebalance.
+    pub fn record_load(&mut self, id: &NodeId, load: PreciseFloat) -> Result<(), &'static str> {
+        let node = self.nodes.get_mut(id).ok_or("Node not found")?;
+        node.load_factor = load;
+        Ok(())
+    }
+
+    pub fn stats(&self) -> RoutingStats {
+        RoutingStats {
+            nodes: self.nodes.len(),
+            routes: self.routing_table.values().map(Vec::len).sum(),
+            routed: self.routed.load(Ordering::Relaxed),
+            unroutable: self.unroutable.load(Ordering::Relaxed),
+            low_entropy: self.low_entropy.load(Ordering::Relaxed),
+            rebalances: self.rebalances,
+            last_rebalance_changes: self.last_rebalance_changes,
+            max_load: self.nodes.values().map(|node| to_f64(&node.load_factor)).fold(0.0, f64::max),
+        }
+    }
+
+    pub fn get_node(&self, id: &NodeId) -> Option<&FluxNode> {
+        self.nodes.get(id)
+    }
+
     pub fn update_node_state(&mut self, id: &NodeId, new_state: NodeState) -> Result<(), &'static str> {
         // Pre-calculate quantum metrics
         let new_entropy = self.calculate_node_entropy(&new_state);
@@ -133,8 +300,19 @@ impl FluxNetwork {
         let decoherence = DecoherenceModel::new(0.1, 1.0);
         decoherence.apply_decoherence(&mut quantum_state, 1.0);
         
-        // Calculate quantum entropy
-        let quantum_score = quantum_state.calculate_von_neumann_entropy();
+        // Von Neumann entropy is zero for every pure state, so pure states are
+        // scored by the Shannon entropy of their measurement distribution;
+        // both are normalized to [0, 1]
+        let max_entropy = (quantum_state.dim as f64).log2();
+        let quantum_score = if quantum_state.is_mixed {
+            quantum_state.calculate_von_neumann_entropy() / max_entropy
+        } else {
+            -quantum_state.amplitudes.iter()
+                .map(|amplitude| amplitude.norm_sqr())
+                .filter(|p| *p > 0.0)
+                .map(|p| p * p.log2())
+                .sum::<f64>() / max_entropy
+        };
         
         // Calculate classical entropy component
         let sync_factor = if state.last_sync > 0 {
@@ -160,18 +338,24 @@ impl FluxNetwork {
         base_load.div(&state_factor.mul(&PreciseFloat::new(100, 2))) // Normalize to 0-1 range
     }
 
+    /// Mean node entropy along a path, with each later hop weighted 0.9x the previous
     fn calculate_route_entropy(&self, path: &[NodeId]) -> PreciseFloat {
-        let mut total_entropy = PreciseFloat::new(0, self.precision);
-        let mut weight = PreciseFloat::new(1, 0);
+        let mut total_entropy = 0.0;
+        let mut total_weight = 0.0;
+        let mut weight = 1.0;
         
         for id in path {
             if let Some(node) = self.nodes.get(id) {
-                total_entropy = total_entropy.add(&node.entropy.mul(&weight));
-                weight = weight.mul(&PreciseFloat::new(9, 1)); // 0.9 decay factor
+                total_entropy += to_f64(&node.entropy) * weight;
+                total_weight += weight;
+                weight *= 0.9; // 0.9 decay factor
             }
         }
         
-        total_entropy.div(&PreciseFloat::new(path.len() as i128, 0))
+        if total_weight == 0.0 {
+            return PreciseFloat::new(0, self.precision);
+        }
+        PreciseFloat::from_f64(total_entropy / total_weight, 6)
     }
 
     fn update_routing_table(&mut self) {
@@ -227,7 +411,7 @@ impl FluxNetwork {
                 for node in &unvisited {
                     let default_dist = PreciseFloat::new(i128::MAX, 0);
                     let dist = distances.get(node).unwrap_or(&default_dist);
-                    if dist.value < min_dist.value {
+                    if to_f64(dist) < to_f64(&min_dist) {
                         min_dist = dist.clone();
                         min_node = Some(*node);
                     }
@@ -253,7 +437,7 @@ impl FluxNetwork {
                             let new_dist = current_dist.add(&edge_cost);
                             
                             if let Some(old_dist) = distances.get_mut(neighbor) {
-                                if new_dist.value < old_dist.value {
+                                if to_f64(&new_dist) < to_f64(old_dist) {
                                     *old_dist = new_dist;
                                     previous.insert(*neighbor, current);
                                 }
@@ -296,18 +480,20 @@ impl FluxNetwork {
         total_load
     }
 
-    fn rebalance_network(&mut self) -> Result<(), &'static str> {
+    /// Move links away from low-entropy and congested nodes, then recompute
+    /// routes from current loads. Returns the number of links moved.
+    pub fn rebalance_network(&mut self) -> Result<usize, &'static str> {
         let mut changes = Vec::new();
         
         // Find nodes that need rebalancing
         for (&id, node) in &self.nodes {
-            if node.entropy.value < self.chaos_threshold.value {
+            if to_f64(&node.entropy) < to_f64(&self.chaos_threshold) {
                 // Find alternative nodes with better entropy
                 let mut alternatives: HashSet<QuantumNodeID> = HashSet::new();
                 
                 // Collect potential alternatives
                 for (other_id, other_node) in &self.nodes {
-                    if other_node.entropy.value > node.entropy.value {
+                    if to_f64(&other_node.entropy) > to_f64(&node.entropy) {
                         alternatives.insert(*other_id);
                     }
                 }
@@ -323,15 +509,113 @@ impl FluxNetwork {
             }
         }
         
-        // Apply changes
+        // Swap links into congested nodes for the least loaded node not yet linked
+        for (&id, node) in &self.nodes {
+            for conn in &node.connections {
+                let congested = self.nodes.get(conn)
+                    .is_some_and(|neighbor| to_f64(&neighbor.load_factor) > CONGESTION_THRESHOLD);
+                if !congested {
+                    continue;
+                }
+                let alternative = self.nodes.iter()
+                    .filter(|(other_id, other)| {
+                        **other_id != id
+                            && !node.connections.contains(*other_id)
+                            && to_f64(&other.load_factor) < CONGESTION_THRESHOLD
+                    })
+                    .min_by(|a, b| to_f64(&a.1.load_factor).total_cmp(&to_f64(&b.1.load_factor)));
+                if let Some((&alt_id, _)) = alternative {
+                    changes.push((id, *conn, alt_id));
+                }
+            }
+        }
+
+        // Apply changes to both ends of each link
+        let moved = changes.len();
         for (node_id, old_conn, new_conn) in changes {
             if let Some(node) = self.nodes.get_mut(&node_id) {
                 node.connections.remove(&old_conn);
                 node.connections.insert(new_conn);
             }
+            if let Some(node) = self.nodes.get_mut(&old_conn) {
+                node.connections.remove(&node_id);
+            }
+            if let Some(node) = self.nodes.get_mut(&new_conn) {
+                node.connections.insert(node_id);
+            }
         }
         
         self.update_routing_table();
-        Ok(())
+        self.rebalances += 1;
+        self.last_rebalance_changes = moved;
+        Ok(moved)
+    }
+}
+
+fn to_f64(value: &PreciseFloat) -> f64 {
+    value.to_f64().unwrap_or(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(byte: u8) -> NodeId {
+        QuantumNodeID::new([byte; 32])
+    }
+
+    fn network() -> FluxNetwork {
+        let mut network = FluxNetwork::new(6);
+        for byte in 1..=4 {
+            let state = NodeState::new(PreciseFloat::new(900, 0), PreciseFloat::new(950, 0))
+                .with_uptime(1000)
+                .with_last_sync(60);
+            network.add_node(node(byte), state).unwrap();
+        }
+        // 1 reaches 4 through either 2 or 3
+        for (a, b) in [(1, 2), (1, 3), (2, 4), (3, 4)] {
+            network.connect(&node(a), &node(b)).unwrap();
+        }
+        network
+    }
+
+    #[test]
+    fn test_routes_avoid_loaded_nodes_after_rebalance() {
+        let mut network = network();
+        network.record_load(&node(2), PreciseFloat::new(50, 2)).unwrap();
+        network.record_load(&node(3), PreciseFloat::new(10, 2)).unwrap();
+        network.rebalance_network().unwrap();
+        assert_eq!(network.route_transaction(&node(1), &node(4)).unwrap(), vec![node(1), node(3), node(4)]);
+
+        network.record_load(&node(2), PreciseFloat::new(5, 2)).unwrap();
+        network.rebalance_network().unwrap();
+        assert_eq!(network.route_transaction(&node(1), &node(4)).unwrap(), vec![node(1), node(2), node(4)]);
+        assert_eq!(network.relay_targets(&node(1), 1), vec![node(2)]);
+
+        let stats = network.stats();
+        assert_eq!(stats.nodes, 4);
+        assert_eq!(stats.rebalances, 2);
+        assert!(stats.routed >= 3);
+        assert!(network.route_transaction(&node(1), &node(9)).is_err());
+        assert_eq!(network.stats().unroutable, 1);
+    }
+
+    #[test]
+    fn test_rebalance_moves_links_off_congested_nodes() {
+        let mut network = network();
+        network.record_load(&node(2), PreciseFloat::new(95, 2)).unwrap();
+        let moved = network.rebalance_network().unwrap();
+        assert!(moved > 0);
+        assert_eq!(network.stats().last_rebalance_changes, moved);
+        assert!(!network.get_node(&node(1)).unwrap().connections().contains(&node(2)));
+    }
+
+    #[test]
+    fn test_removed_node_leaves_routes() {
+        let mut network = network();
+        assert!(network.remove_node(&node(2)).is_some());
+        assert_eq!(network.route_transaction(&node(1), &node(4)).unwrap(), vec![node(1), node(3), node(4)]);
+        assert_eq!(network.relay_targets(&node(1), 2), vec![node(3)]);
+        assert!(network.remove_node(&node(2)).is_none());
     }
 }
@@ -0,0 +1,225 @@
+//! Hash-time-locked contracts for atomic swaps.
+//!
+//! An HTLC pays `recipient` if someone reveals the secret behind `hashlock`
+//! before `timelock`, and otherwise returns the funds to `sender`. The same
+//! primitive runs on mainnet, as `htlc` transactions, and on hosted private
+//! chains, through `PrivateChainHost`. A swap locks one leg on each chain
+//! under the same hashlock. The initiator, who made the secret, locks first
+//! with the longer timelock. The participant locks second with a shorter one.
+//! Claiming the participant's leg reveals the secret, and the participant uses
+//! it to claim the initiator's leg. Neither side trusts the other or a bridge.
+//!
+//! Hashlocks are the SHA-256 of a 32-byte secret, as in most other HTLC
+//! implementations, so a leg can also be paired with one on another chain.
+
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use crate::blockchain::types::{hex_serde, Address};
+
+/// Account holding the funds of every locked mainnet HTLC
+pub const HTLC_ESCROW_ADDRESS: Address = *b"qmv:htlc:escrow:::::::::::::::::";
+
+/// Event topic of a claim; its data is the HTLC ID followed by the secret
+pub const TOPIC_CLAIMED: &str = "htlc.claimed";
+/// Event topic of a refund; its data is the HTLC ID
+pub const TOPIC_REFUNDED: &str = "htlc.refunded";
+
+/// Longest timelock, in blocks from the lock
+pub const MAX_TIMELOCK_BLOCKS: u64 = 1_000_000;
+
+/// Hashlock that `secret` opens
+pub fn hashlock(secret: &[u8; 32]) -> [u8; 32] {
+    Sha256::digest(secret).into()
+}
+
+/// Fresh random secret for the initiator of a swap
+pub fn generate_secret() -> [u8; 32] {
+    let mut secret = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut secret);
+    secret
+}
+
+/// ID of the HTLC locked by `sender` at `nonce`
+pub fn htlc_id(sender: &Address, nonce: u64) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"qmv:htlc:");
+    hasher.update(sender);
+    hasher.update(&nonce.to_le_bytes());
+    hasher.finalize().into()
+}
+
+/// Where an HTLC stands
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HtlcStatus {
+    #[default]
+    Locked,
+    Claimed {
+        #[serde(with = "hex_serde")]
+        secret: [u8; 32],
+    },
+    Refunded,
+}
+
+/// What a sender locks, on mainnet or a private chain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HtlcTerms {
+    #[serde(with = "hex_serde")]
+    pub recipient: Address,
+    pub amount: u128,
+    #[serde(with = "hex_serde")]
+    pub hashlock: [u8; 32],
+    /// Height of the locking chain from which the sender can be refunded
+    pub timelock: u64,
+}
+
+/// Funds locked under a hashlock until a timelock
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Htlc {
+    #[serde(with = "hex_serde")]
+    pub sender: Address,
+    #[serde(with = "hex_serde")]
+    pub recipient: Address,
+    pub amount: u128,
+    #[serde(with = "hex_serde")]
+    pub hashlock: [u8; 32],
+    /// Height from which the sender can be refunded
+    pub timelock: u64,
+    #[serde(default)]
+    pub status: HtlcStatus,
+}
+
+impl Htlc {
+    /// Lock `terms` from `sender` at `height`
+    pub fn new(sender: Address, terms: &HtlcTerms, height: u64) -> Result<Self, &'static str> {
+        if terms.amount == 0 {
+            return Err("HTLC amount must be positive");
+        }
+        if terms.timelock <= height || terms.timelock - height > MAX_TIMELOCK_BLOCKS {
+            return Err("Timelock must be between 1 and 1,000,000 blocks ahead");
+        }
+        Ok(Self {
+            sender,
+            recipient: terms.recipient,
+            amount: terms.amount,
+            hashlock: terms.hashlock,
+            timelock: terms.timelock,
+            status: HtlcStatus::Locked,
+        })
+    }
+
+    /// Claim for the recipient at `height` with the secret behind the hashlock
+    pub fn claim(&mut self, secret: &[u8; 32], height: u64) -> Result<(), &'static str> {
+        if self.status != HtlcStatus::Locked {
+            return Err("HTLC is already settled");
+        }
+        if height >= self.timelock {
+            return Err("HTLC has timed out");
+        }
+        if hashlock(secret) != self.hashlock {
+            return Err("Secret does not open the hashlock");
+        }
+        self.status = HtlcStatus::Claimed { secret: *secret };
+        Ok(())
+    }
+
+    /// Return the funds to the sender at `height`, once the timelock has passed
+    pub fn refund(&mut self, height: u64) -> Result<(), &'static str> {
+        if self.status != HtlcStatus::Locked {
+            return Err("HTLC is already settled");
+        }
+        if height < self.timelock {
+            return Err("HTLC has not timed out");
+        }
+        self.status = HtlcStatus::Refunded;
+        Ok(())
+    }
+
+    /// Blocks left at `height` before the sender can be refunded
+    pub fn blocks_until_refund(&self, height: u64) -> u64 {
+        self.timelock.saturating_sub(height)
+    }
+}
+
+/// HTLC operations
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HtlcAction {
+    /// Lock funds from the sender. The ID is derived from sender and nonce.
+    Lock(HtlcTerms),
+    /// Pay the recipient by revealing the secret. Anyone may submit it.
+    Claim {
+        #[serde(with = "hex_serde")]
+        id: [u8; 32],
+        #[serde(with = "hex_serde")]
+        secret: [u8; 32],
+    },
+    /// Return the funds to the sender after the timelock. Anyone may submit it.
+    Refund {
+        #[serde(with = "hex_serde")]
+        id: [u8; 32],
+    },
+}
+
+/// HTLCs by ID
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HtlcBook {
+    locks: BTreeMap<[u8; 32], Htlc>,
+}
+
+impl HtlcBook {
+    pub fn get(&self, id: &[u8; 32]) -> Option<&Htlc> {
+        self.locks.get(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.locks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.locks.is_empty()
+    }
+
+    /// HTLCs `account` sends or receives
+    pub fn by_party<'a>(&'a self, account: &'a Address) -> impl Iterator<Item = (&'a [u8; 32], &'a Htlc)> + 'a {
+        self.locks.iter().filter(move |(_, htlc)| htlc.sender == *account || htlc.recipient == *account)
+    }
+
+    /// Set an HTLC to a journaled value; `None` removes it
+    pub(crate) fn restore(&mut self, id: [u8; 32], htlc: Option<Htlc>) {
+        match htlc {
+            Some(htlc) => { self.locks.insert(id, htlc); }
+            None => { self.locks.remove(&id); }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claim_and_refund_windows() {
+        let secret = generate_secret();
+        let terms = HtlcTerms { recipient: [2u8; 32], amount: 100, hashlock: hashlock(&secret), timelock: 20 };
+        assert!(Htlc::new([1u8; 32], &HtlcTerms { timelock: 10, ..terms.clone() }, 10).is_err());
+        assert!(Htlc::new([1u8; 32], &HtlcTerms { amount: 0, ..terms.clone() }, 10).is_err());
+
+        let htlc = Htlc::new([1u8; 32], &terms, 10).unwrap();
+        assert_eq!(htlc.blocks_until_refund(15), 5);
+
+        let mut claimed = htlc.clone();
+        assert_eq!(claimed.claim(&[9u8; 32], 15), Err("Secret does not open the hashlock"));
+        assert_eq!(claimed.refund(19), Err("HTLC has not timed out"));
+        claimed.claim(&secret, 19).unwrap();
+        assert_eq!(claimed.status, HtlcStatus::Claimed { secret });
+        assert_eq!(claimed.refund(25), Err("HTLC is already settled"));
+
+        let mut refunded = htlc;
+        assert_eq!(refunded.claim(&secret, 20), Err("HTLC has timed out"));
+        refunded.refund(20).unwrap();
+        assert_eq!(refunded.status, HtlcStatus::Refunded);
+    }
+}
@@ -0,0 +1,207 @@
+//! Invariants checked after every sealed block.
+//!
+//! A node running the suite (`invariants.mode`) replays each block it seals
+//! on the parent state and checks:
+//! - supply conservation: native tokens in accounts and schedule deposits
+//!   fall by exactly the fees the block burned;
+//! - the replayed state root matches the committed state's;
+//! - anchor monotonicity: the block extends its parent by one height with a
+//!   timestamp no earlier than the parent's, and the committed state sits
+//!   at the block's height;
+//! - for the economic model, staked tokens never exceed total supply and no
+//!   balance is below zero.
+//!
+//! A violation is not skipped over: the node writes the report as a
+//! diagnostic dump and trips the circuit breaker's block production halt.
+
+use num_traits::ToPrimitive;
+use serde::{Serialize, Deserialize};
+use std::path::{Path, PathBuf};
+use crate::blockchain::core::Block;
+use crate::blockchain::execution::Executor;
+use crate::blockchain::state::WorldState;
+use crate::blockchain::transaction::Transaction;
+use crate::blockchain::types::hex_serde;
+use crate::economics::models::{EconomicModel, SupplySummary};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Invariant {
+    SupplyConservation,
+    StakeWithinSupply,
+    NonNegativeBalances,
+    StateRoot,
+    AnchorMonotonicity,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Violation {
+    pub invariant: Invariant,
+    pub detail: String,
+}
+
+impl Violation {
+    fn new(invariant: Invariant, detail: impl Into<String>) -> Self {
+        Self { invariant, detail: detail.into() }
+    }
+}
+
+/// Invariant Report
+/// Outcome of the suite for one block, with the figures it compared so a
+/// dump can be read without the node's state.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InvariantReport {
+    pub height: u64,
+    #[serde(with = "hex_serde")]
+    pub block_hash: [u8; 32],
+    #[serde(with = "hex_serde")]
+    pub parent_root: [u8; 32],
+    #[serde(with = "hex_serde")]
+    pub state_root: [u8; 32],
+    /// Root of the parent state with the block replayed on it; `None` if
+    /// the replay failed
+    #[serde(default)]
+    pub replayed_root: Option<[u8; 32]>,
+    pub supply_before: u128,
+    pub supply_after: u128,
+    pub fees_burned: u128,
+    #[serde(default)]
+    pub economics: Option<SupplySummary>,
+    pub violations: Vec<Violation>,
+}
+
+impl InvariantReport {
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    /// One-line summary of the violations
+    pub fn summary(&self) -> String {
+        let details: Vec<&str> = self.violations.iter().map(|violation| violation.detail.as_str()).collect();
+        format!("invariant violation at block {}: {}", self.height, details.join("; "))
+    }
+
+    /// Write the report to `dir` as `invariants-<height>.json`
+    pub fn write_dump(&self, dir: &Path) -> Result<PathBuf, String> {
+        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        let path = dir.join(format!("invariants-{}.json", self.height));
+        let json = serde_json::to_vec_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(&path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        Ok(path)
+    }
+
+    /// Add the economic model's supply checks
+    pub fn check_economics(&mut self, economics: &EconomicModel) {
+        let supply = economics.supply();
+        let excess = supply.total_staked.sub(&supply.total_supply);
+        if excess.value > 0 {
+            self.violations.push(Violation::new(
+                Invariant::StakeWithinSupply,
+                format!("staked tokens exceed total supply by {}", excess.to_f64().unwrap_or(f64::INFINITY)),
+            ));
+        }
+        for balance in economics.negative_balances() {
+            self.violations.push(Violation::new(Invariant::NonNegativeBalances, format!("{} is negative", balance)));
+        }
+        self.economics = Some(supply);
+    }
+}
+
+/// Check the sealed `block` took `parent` to `state` and extends `parent_block`
+pub fn check_block(parent: &WorldState, state: &WorldState, parent_block: &Block, block: &Block) -> InvariantReport {
+    let mut violations = Vec::new();
+
+    if block.index != parent_block.index + 1 || block.previous_hash != parent_block.hash {
+        violations.push(Violation::new(
+            Invariant::AnchorMonotonicity,
+            format!("block {} does not extend block {}", block.index, parent_block.index),
+        ));
+    }
+    if block.timestamp < parent_block.timestamp {
+        violations.push(Violation::new(Invariant::AnchorMonotonicity, "block timestamp precedes its parent's"));
+    }
+    if state.height() != block.index || parent.height() + 1 != block.index {
+        violations.push(Violation::new(
+            Invariant::AnchorMonotonicity,
+            format!("state heights {} -> {} do not match block {}", parent.height(), state.height(), block.index),
+        ));
+    }
+
+    let mut replay = parent.clone();
+    let replayed = bincode::deserialize::<Vec<Transaction>>(&block.data)
+        .map_err(|_| "Failed to decode block transactions")
+        .and_then(|transactions| Executor::apply_block(&mut replay, &transactions));
+    let (replayed_root, fees_burned) = match replayed {
+        Ok(receipts) => {
+            replay.set_height(block.index);
+            (Some(replay.state_root()), receipts.iter().map(|receipt| receipt.fee).sum())
+        }
+        Err(error) => {
+            violations.push(Violation::new(Invariant::StateRoot, format!("block does not replay: {}", error)));
+            (None, 0)
+        }
+    };
+    let state_root = state.state_root();
+    if replayed_root.is_some_and(|root| root != state_root) {
+        violations.push(Violation::new(Invariant::StateRoot, "replayed state root differs from the committed root"));
+    }
+
+    let supply_before = parent.native_supply();
+    let supply_after = state.native_supply();
+    if replayed_root.is_some() && supply_before.checked_sub(fees_burned) != Some(supply_after) {
+        violations.push(Violation::new(
+            Invariant::SupplyConservation,
+            format!("supply {} less fees {} is not {}", supply_before, fees_burned, supply_after),
+        ));
+    }
+
+    InvariantReport {
+        height: block.index,
+        block_hash: block.hash,
+        parent_root: parent.state_root(),
+        state_root,
+        replayed_root,
+        supply_before,
+        supply_after,
+        fees_burned,
+        economics: None,
+        violations,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::core::Blockchain;
+    use crate::blockchain::transaction::TransactionAction;
+    use ed25519_dalek::SigningKey;
+
+    #[test]
+    fn test_detects_minted_tokens_and_broken_anchors() {
+        let key = SigningKey::from_bytes(&[3u8; 32]);
+        let sender = key.verifying_key().to_bytes();
+        let parent = WorldState::with_balances(&[(sender, 1_000_000_000)]);
+        let mut tx = Transaction::new(sender, 0, TransactionAction::Transfer { to: [9u8; 32], amount: 500 }, 50_000, 1);
+        tx.sign(&key);
+
+        let mut chain = Blockchain::new(2);
+        chain.add_block(bincode::serialize(&vec![tx.clone()]).unwrap()).unwrap();
+        let (genesis, block) = (chain.block(0).unwrap().clone(), chain.block(1).unwrap().clone());
+        let mut state = parent.clone();
+        Executor::apply_block(&mut state, &[tx]).unwrap();
+        state.set_height(1);
+
+        let report = check_block(&parent, &state, &genesis, &block);
+        assert!(report.is_ok(), "{}", report.summary());
+        assert_eq!(report.supply_before - report.fees_burned, report.supply_after);
+
+        let mut minted = state.clone();
+        minted.account_mut(&[9u8; 32]).balance += 1;
+        let report = check_block(&parent, &minted, &genesis, &block);
+        let broken: Vec<Invariant> = report.violations.iter().map(|violation| violation.invariant).collect();
+        assert_eq!(broken, vec![Invariant::StateRoot, Invariant::SupplyConservation]);
+
+        let report = check_block(&parent, &state, &block, &block);
+        assert_eq!(report.violations[0].invariant, Invariant::AnchorMonotonicity);
+    }
+}
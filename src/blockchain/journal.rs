@@ -0,0 +1,370 @@
+use crate::blockchain::account_validation::AccountValidation;
+use crate::blockchain::assets::Asset;
+use crate::blockchain::channel::Channel;
+use crate::blockchain::confidential::ConfidentialAccount;
+use crate::blockchain::htlc::Htlc;
+use crate::blockchain::lease::{Lease, LeaseOffer};
+use crate::blockchain::market::Listing;
+use crate::blockchain::multisig::MultisigAccount;
+use crate::blockchain::scheduler::Schedule;
+use crate::blockchain::sponsor::SponsorPolicy;
+use crate::blockchain::state::{Account, ContractAccount, WorldState};
+use crate::blockchain::types::Address;
+use crate::blockchain::validator_keys::{KeyEpoch, KeyRotation};
+
+/// Prior value of one piece of state, recorded before it is overwritten
+#[derive(Debug, Clone)]
+enum JournalEntry {
+    /// `None` if the account did not exist
+    Account { address: Address, previous: Option<Account> },
+    /// `None` if the slot was unset
+    Storage { contract: Address, key: Vec<u8>, previous: Option<Vec<u8>> },
+    ContractCreated { address: Address },
+    ValidatorKeys { validator: Address, previous: Option<Vec<KeyEpoch>> },
+    /// `None` if the multisig account did not exist
+    Multisig { address: Address, previous: Option<MultisigAccount> },
+    /// `None` if the schedule did not exist
+    Schedule { id: [u8; 32], previous: Option<Schedule> },
+    /// `None` if the asset did not exist
+    Asset { id: [u8; 32], previous: Option<Asset> },
+    /// `None` if the asset was not listed
+    Listing { asset: [u8; 32], previous: Option<Listing> },
+    /// `None` if there was no open offer
+    LeaseOffer { asset: [u8; 32], previous: Option<LeaseOffer> },
+    /// `None` if the asset was not leased
+    Lease { asset: [u8; 32], previous: Option<Lease> },
+    /// `None` if the shielded account did not exist
+    Confidential { address: Address, previous: Option<ConfidentialAccount> },
+    /// `None` if the channel did not exist
+    Channel { id: [u8; 32], previous: Option<Channel> },
+    /// `None` if the HTLC did not exist
+    Htlc { id: [u8; 32], previous: Option<Htlc> },
+    /// `None` if the account had no sponsor policy
+    SponsorPolicy { sponsor: Address, previous: Option<SponsorPolicy> },
+    /// `None` if the contract did not exist
+    Contract { address: Address, previous: Option<ContractAccount> },
+    /// `None` if the account had no validation contract
+    Validation { account: Address, previous: Option<AccountValidation> },
+}
+
+/// Position in the journal that writes can be rolled back to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint(usize);
+
+/// Journaled State
+/// Writes go straight to the underlying `WorldState` and record the value
+/// they replace, so reverting a failed call costs only what it touched.
+pub struct JournaledState<'a> {
+    state: &'a mut WorldState,
+    entries: Vec<JournalEntry>,
+}
+
+impl<'a> JournaledState<'a> {
+    pub fn new(state: &'a mut WorldState) -> Self {
+        Self { state, entries: Vec::new() }
+    }
+
+    pub fn state(&self) -> &WorldState {
+        self.state
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.entries.len())
+    }
+
+    /// Undo every write made since `checkpoint`, newest first
+    pub fn revert_to(&mut self, checkpoint: Checkpoint) {
+        while self.entries.len() > checkpoint.0 {
+            match self.entries.pop().expect("length checked") {
+                JournalEntry::Account { address, previous } => {
+                    self.state.restore_account(address, previous);
+                }
+                JournalEntry::Storage { contract, key, previous } => {
+                    if let Some(storage) = self.state.contract_mut(&contract).map(|c| &mut c.storage) {
+                        match previous {
+                            Some(value) => { storage.insert(key, value); }
+                            None => { storage.remove(&key); }
+                        }
+                    }
+                }
+                JournalEntry::ContractCreated { address } => {
+                    self.state.remove_contract(&address);
+                }
+                JournalEntry::ValidatorKeys { validator, previous } => {
+                    self.state.validator_keys_mut().restore(validator, previous);
+                }
+                JournalEntry::Multisig { address, previous } => {
+                    self.state.restore_multisig(address, previous);
+                }
+                JournalEntry::Schedule { id, previous } => {
+                    self.state.schedules_mut().restore(id, previous);
+                }
+                JournalEntry::Asset { id, previous } => {
+                    self.state.assets_mut().restore(id, previous);
+                }
+                JournalEntry::Listing { asset, previous } => {
+                    self.state.listings_mut().restore(asset, previous);
+                }
+                JournalEntry::LeaseOffer { asset, previous } => {
+                    self.state.leases_mut().restore_offer(asset, previous);
+                }
+                JournalEntry::Lease { asset, previous } => {
+                    self.state.leases_mut().restore_lease(asset, previous);
+                }
+                JournalEntry::Confidential { address, previous } => {
+                    self.state.confidential_mut().restore(address, previous);
+                }
+                JournalEntry::Channel { id, previous } => {
+                    self.state.channels_mut().restore(id, previous);
+                }
+                JournalEntry::Htlc { id, previous } => {
+                    self.state.htlcs_mut().restore(id, previous);
+                }
+                JournalEntry::SponsorPolicy { sponsor, previous } => {
+                    self.state.sponsors_mut().restore(sponsor, previous);
+                }
+                JournalEntry::Contract { address, previous } => {
+                    self.state.restore_contract(address, previous);
+                }
+                JournalEntry::Validation { account, previous } => {
+                    self.state.validations_mut().restore(account, previous);
+                }
+            }
+        }
+    }
+
+    /// Keep all writes and drop the undo log
+    pub fn commit(self) {}
+
+    pub fn account(&self, address: &Address) -> Account {
+        self.state.account(address)
+    }
+
+    /// Mutable account access; the account's current value is journaled first
+    pub fn account_mut(&mut self, address: &Address) -> &mut Account {
+        let previous = self.state.accounts().get(address).cloned();
+        self.entries.push(JournalEntry::Account { address: *address, previous });
+        self.state.account_mut(address)
+    }
+
+    pub fn transfer(&mut self, from: &Address, to: &Address, amount: u128) -> Result<(), &'static str> {
+        let balance = self.account(from).balance.checked_sub(amount).ok_or("Insufficient balance")?;
+        let recipient = self.account(to).balance;
+        if from != to {
+            recipient.checked_add(amount).ok_or("Balance overflow")?;
+        }
+        self.account_mut(from).balance = balance;
+        self.account_mut(to).balance += amount;
+        Ok(())
+    }
+
+    pub fn contract(&self, address: &Address) -> Option<&ContractAccount> {
+        self.state.contract(address)
+    }
+
+    pub fn insert_contract(&mut self, address: Address, contract: ContractAccount) -> Result<(), &'static str> {
+        if self.state.contract(&address).is_some() {
+            return Err("Contract already exists");
+        }
+        self.entries.push(JournalEntry::ContractCreated { address });
+        self.state.insert_contract(address, contract);
+        Ok(())
+    }
+
+    pub fn storage_get(&self, contract: &Address, key: &[u8]) -> Option<Vec<u8>> {
+        self.state.contract(contract).and_then(|c| c.storage.get(key).cloned())
+    }
+
+    /// Set or, with `None`, clear a storage slot
+    pub fn storage_set(&mut self, contract: &Address, key: &[u8], value: Option<Vec<u8>>) -> Result<(), &'static str> {
+        let storage = &mut self.state.contract_mut(contract).ok_or("Contract not found")?.storage;
+        let previous = match value {
+            Some(value) => storage.insert(key.to_vec(), value),
+            None => storage.remove(key),
+        };
+        self.entries.push(JournalEntry::Storage { contract: *contract, key: key.to_vec(), previous });
+        Ok(())
+    }
+
+    pub fn multisig(&self, address: &Address) -> Option<&MultisigAccount> {
+        self.state.multisig(address)
+    }
+
+    pub fn create_multisig(&mut self, address: Address, account: MultisigAccount) -> Result<(), &'static str> {
+        if self.state.multisig(&address).is_some() {
+            return Err("Multisig account already exists");
+        }
+        self.entries.push(JournalEntry::Multisig { address, previous: None });
+        self.state.restore_multisig(address, Some(account));
+        Ok(())
+    }
+
+    /// Mutable multisig access; the account's current value is journaled first
+    pub fn multisig_mut(&mut self, address: &Address) -> Option<&mut MultisigAccount> {
+        let previous = self.state.multisig(address)?.clone();
+        self.entries.push(JournalEntry::Multisig { address: *address, previous: Some(previous) });
+        self.state.multisig_mut(address)
+    }
+
+    pub fn schedule(&self, id: &[u8; 32]) -> Option<&Schedule> {
+        self.state.schedules().get(id)
+    }
+
+    /// Create, replace or, with `None`, remove a schedule
+    pub fn set_schedule(&mut self, id: [u8; 32], schedule: Option<Schedule>) {
+        let previous = self.state.schedules().get(&id).cloned();
+        self.entries.push(JournalEntry::Schedule { id, previous });
+        self.state.schedules_mut().restore(id, schedule);
+    }
+
+    pub fn asset(&self, id: &[u8; 32]) -> Option<&Asset> {
+        self.state.assets().get(id)
+    }
+
+    /// Create or replace an asset
+    pub fn set_asset(&mut self, id: [u8; 32], asset: Asset) {
+        let previous = self.state.assets().get(&id).cloned();
+        self.entries.push(JournalEntry::Asset { id, previous });
+        self.state.assets_mut().restore(id, Some(asset));
+    }
+
+    pub fn listing(&self, asset: &[u8; 32]) -> Option<&Listing> {
+        self.state.listings().get(asset)
+    }
+
+    /// Create, replace or, with `None`, remove a listing
+    pub fn set_listing(&mut self, asset: [u8; 32], listing: Option<Listing>) {
+        let previous = self.state.listings().get(&asset).cloned();
+        self.entries.push(JournalEntry::Listing { asset, previous });
+        self.state.listings_mut().restore(asset, listing);
+    }
+
+    pub fn lease_offer(&self, asset: &[u8; 32]) -> Option<&LeaseOffer> {
+        self.state.leases().offer(asset)
+    }
+
+    /// Create, replace or, with `None`, remove a lease offer
+    pub fn set_lease_offer(&mut self, asset: [u8; 32], offer: Option<LeaseOffer>) {
+        let previous = self.state.leases().offer(&asset).cloned();
+        self.entries.push(JournalEntry::LeaseOffer { asset, previous });
+        self.state.leases_mut().restore_offer(asset, offer);
+    }
+
+    pub fn lease(&self, asset: &[u8; 32]) -> Option<&Lease> {
+        self.state.leases().lease(asset)
+    }
+
+    /// Start, update or, with `None`, end a lease
+    pub fn set_lease(&mut self, asset: [u8; 32], lease: Option<Lease>) {
+        let previous = self.state.leases().lease(&asset).cloned();
+        self.entries.push(JournalEntry::Lease { asset, previous });
+        self.state.leases_mut().restore_lease(asset, lease);
+    }
+
+    pub fn confidential(&self, address: &Address) -> Option<&ConfidentialAccount> {
+        self.state.confidential().get(address)
+    }
+
+    /// Create or replace a shielded account
+    pub fn set_confidential(&mut self, address: Address, account: ConfidentialAccount) {
+        let previous = self.state.confidential().get(&address).cloned();
+        self.entries.push(JournalEntry::Confidential { address, previous });
+        self.state.confidential_mut().restore(address, Some(account));
+    }
+
+    pub fn channel(&self, id: &[u8; 32]) -> Option<&Channel> {
+        self.state.channels().get(id)
+    }
+
+    /// Open, update or, with `None`, remove a channel
+    pub fn set_channel(&mut self, id: [u8; 32], channel: Option<Channel>) {
+        let previous = self.state.channels().get(&id).cloned();
+        self.entries.push(JournalEntry::Channel { id, previous });
+        self.state.channels_mut().restore(id, channel);
+    }
+
+    pub fn htlc(&self, id: &[u8; 32]) -> Option<&Htlc> {
+        self.state.htlcs().get(id)
+    }
+
+    /// Lock or, with `None`, remove an HTLC
+    pub fn set_htlc(&mut self, id: [u8; 32], htlc: Option<Htlc>) {
+        let previous = self.state.htlcs().get(&id).cloned();
+        self.entries.push(JournalEntry::Htlc { id, previous });
+        self.state.htlcs_mut().restore(id, htlc);
+    }
+
+    pub fn sponsor_policy(&self, sponsor: &Address) -> Option<&SponsorPolicy> {
+        self.state.sponsors().get(sponsor)
+    }
+
+    /// Set or, with `None`, revoke a sponsor's policy
+    pub fn set_sponsor_policy(&mut self, sponsor: Address, policy: Option<SponsorPolicy>) {
+        let previous = self.state.sponsors().get(&sponsor).cloned();
+        self.entries.push(JournalEntry::SponsorPolicy { sponsor, previous });
+        self.state.sponsors_mut().restore(sponsor, policy);
+    }
+
+    /// Put a contract in place, replacing any at `address`; `None` removes it
+    pub fn set_contract(&mut self, address: Address, contract: Option<ContractAccount>) {
+        let previous = self.state.contract(&address).cloned();
+        self.entries.push(JournalEntry::Contract { address, previous });
+        self.state.restore_contract(address, contract);
+    }
+
+    /// Install or, with `None`, remove an account's validation contract entry
+    pub fn set_validation(&mut self, account: Address, validation: Option<AccountValidation>) {
+        let previous = self.state.validations().get(&account).cloned();
+        self.entries.push(JournalEntry::Validation { account, previous });
+        self.state.validations_mut().restore(account, validation);
+    }
+
+    /// Schedule a validator key rotation included at `height`
+    pub fn rotate_validator_key(&mut self, rotation: &KeyRotation, network_id: u64, height: u64) -> Result<(), &'static str> {
+        let previous = self.state.validator_keys().epochs(&rotation.validator);
+        self.state.validator_keys_mut().apply_rotation(rotation, network_id, height)?;
+        self.entries.push(JournalEntry::ValidatorKeys { validator: rotation.validator, previous });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nested_checkpoints_restore_exact_state() {
+        let mut state = WorldState::with_balances(&[([1u8; 32], 100)]);
+        state.insert_contract([5u8; 32], ContractAccount {
+            owner: [1u8; 32],
+            code: Vec::new(),
+            storage: [(b"a".to_vec(), b"1".to_vec())].into_iter().collect(),
+        });
+        let original = state.clone();
+
+        let mut journal = JournaledState::new(&mut state);
+        journal.account_mut(&[1u8; 32]).nonce += 1;
+        let outer = journal.checkpoint();
+
+        journal.transfer(&[1u8; 32], &[2u8; 32], 30).unwrap();
+        journal.storage_set(&[5u8; 32], b"a", None).unwrap();
+        let inner = journal.checkpoint();
+        journal.storage_set(&[5u8; 32], b"b", Some(b"2".to_vec())).unwrap();
+        journal.insert_contract([6u8; 32], ContractAccount { owner: [2u8; 32], code: Vec::new(), storage: Default::default() }).unwrap();
+
+        journal.revert_to(inner);
+        assert!(journal.contract(&[6u8; 32]).is_none());
+        assert_eq!(journal.storage_get(&[5u8; 32], b"b"), None);
+        assert_eq!(journal.account(&[2u8; 32]).balance, 30);
+
+        journal.revert_to(outer);
+        assert_eq!(journal.storage_get(&[5u8; 32], b"a"), Some(b"1".to_vec()));
+        // The recipient did not exist before, so it is removed rather than zeroed
+        assert!(!journal.state().accounts().contains_key(&[2u8; 32]));
+        journal.commit();
+
+        assert_eq!(state.account(&[1u8; 32]).nonce, 1);
+        state.account_mut(&[1u8; 32]).nonce = 0;
+        assert_eq!(state, original);
+    }
+}
@@ -0,0 +1,227 @@
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, BTreeSet};
+use crate::blockchain::assets::AssetKind;
+use crate::blockchain::state::WorldState;
+use crate::blockchain::types::{hex_serde, Address};
+use crate::layers::l1_orchestration::WriteCheck;
+
+/// Account holding prepaid rent until each epoch is paid out
+pub const LEASE_ESCROW_ADDRESS: Address = *b"qmv:lease:escrow::::::::::::::::";
+
+/// Longest lease, in blocks
+pub const MAX_LEASE_BLOCKS: u64 = 1_000_000;
+
+/// Most rent payments made at the start of one block; the rest stay due
+/// and are paid in later blocks, oldest first
+pub const MAX_PAYMENTS_PER_BLOCK: usize = 100;
+
+/// Terms an owner offers to one lessee
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LeaseTerms {
+    #[serde(with = "hex_serde")]
+    pub lessee: Address,
+    /// Lease length in blocks, a whole number of epochs
+    pub duration: u64,
+    pub epoch_blocks: u64,
+    pub rent_per_epoch: u128,
+}
+
+impl LeaseTerms {
+    pub fn check(&self) -> Result<(), &'static str> {
+        if self.duration == 0 || self.duration > MAX_LEASE_BLOCKS {
+            return Err("Lease duration must be between 1 and 1,000,000 blocks");
+        }
+        if self.epoch_blocks == 0 || !self.duration.is_multiple_of(self.epoch_blocks) {
+            return Err("Lease duration must be a whole number of epochs");
+        }
+        self.total_rent().ok_or("Lease rent overflow")?;
+        Ok(())
+    }
+
+    /// Rent the lessee prepays on acceptance
+    pub fn total_rent(&self) -> Option<u128> {
+        self.rent_per_epoch.checked_mul((self.duration / self.epoch_blocks) as u128)
+    }
+}
+
+/// Open offer, bound to the owner who made it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LeaseOffer {
+    #[serde(with = "hex_serde")]
+    pub lessor: Address,
+    pub terms: LeaseTerms,
+}
+
+/// Accepted lease; the asset reverts to its owner at `end_height`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Lease {
+    #[serde(with = "hex_serde")]
+    pub lessor: Address,
+    #[serde(with = "hex_serde")]
+    pub lessee: Address,
+    pub start_height: u64,
+    pub end_height: u64,
+    pub epoch_blocks: u64,
+    pub rent_per_epoch: u128,
+    /// Height at which the current epoch's rent is paid to the lessor
+    pub next_payment: u64,
+}
+
+impl Lease {
+    pub fn start(offer: &LeaseOffer, height: u64) -> Self {
+        Self {
+            lessor: offer.lessor,
+            lessee: offer.terms.lessee,
+            start_height: height,
+            end_height: height + offer.terms.duration,
+            epoch_blocks: offer.terms.epoch_blocks,
+            rent_per_epoch: offer.terms.rent_per_epoch,
+            next_payment: height + offer.terms.epoch_blocks,
+        }
+    }
+
+    pub fn is_active(&self, height: u64) -> bool {
+        (self.start_height..self.end_height).contains(&height)
+    }
+
+    /// The lease after paying the current epoch, or `None` if that was the last
+    pub fn after_payment(mut self) -> Option<Self> {
+        if self.next_payment >= self.end_height {
+            return None;
+        }
+        self.next_payment += self.epoch_blocks;
+        Some(self)
+    }
+}
+
+/// Lease operations
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LeaseAction {
+    /// Offer one of the sender's assets for lease, replacing any open offer
+    Offer {
+        #[serde(with = "hex_serde")]
+        asset: [u8; 32],
+        terms: LeaseTerms,
+    },
+    /// Withdraw an open offer
+    Withdraw {
+        #[serde(with = "hex_serde")]
+        asset: [u8; 32],
+    },
+    /// Accept an offer made to the sender and prepay its rent. `total_rent`
+    /// must match the offer, so the owner cannot change it while pending.
+    Accept {
+        #[serde(with = "hex_serde")]
+        asset: [u8; 32],
+        total_rent: u128,
+    },
+}
+
+impl LeaseAction {
+    pub fn asset(&self) -> &[u8; 32] {
+        match self {
+            Self::Offer { asset, .. } | Self::Withdraw { asset } | Self::Accept { asset, .. } => asset,
+        }
+    }
+}
+
+/// Open offers and active leases by asset, with leases queued by next payment
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Leases {
+    offers: BTreeMap<[u8; 32], LeaseOffer>,
+    leases: BTreeMap<[u8; 32], Lease>,
+    queue: BTreeSet<(u64, [u8; 32])>,
+}
+
+impl Leases {
+    pub fn offer(&self, asset: &[u8; 32]) -> Option<&LeaseOffer> {
+        self.offers.get(asset)
+    }
+
+    pub fn lease(&self, asset: &[u8; 32]) -> Option<&Lease> {
+        self.leases.get(asset)
+    }
+
+    pub fn leases(&self) -> impl Iterator<Item = (&[u8; 32], &Lease)> {
+        self.leases.iter()
+    }
+
+    /// Assets whose rent is due at `height`, oldest first, at most `limit`
+    pub fn due(&self, height: u64, limit: usize) -> Vec<[u8; 32]> {
+        self.queue.iter()
+            .take_while(|(next_payment, _)| *next_payment <= height)
+            .take(limit)
+            .map(|(_, asset)| *asset)
+            .collect()
+    }
+
+    /// Set an offer to a journaled value; `None` removes it
+    pub(crate) fn restore_offer(&mut self, asset: [u8; 32], offer: Option<LeaseOffer>) {
+        match offer {
+            Some(offer) => { self.offers.insert(asset, offer); }
+            None => { self.offers.remove(&asset); }
+        }
+    }
+
+    /// Set a lease to a journaled value; `None` removes it
+    pub(crate) fn restore_lease(&mut self, asset: [u8; 32], lease: Option<Lease>) {
+        if let Some(previous) = self.leases.remove(&asset) {
+            self.queue.remove(&(previous.next_payment, asset));
+        }
+        if let Some(lease) = lease {
+            self.queue.insert((lease.next_payment, asset));
+            self.leases.insert(asset, lease);
+        }
+    }
+}
+
+/// Account allowed to change an asset at `height`: the lessee while a lease
+/// runs, otherwise the owner
+pub fn writer(state: &WorldState, asset: &[u8; 32], height: u64) -> Option<Address> {
+    match state.leases().lease(asset) {
+        Some(lease) if lease.is_active(height) => Some(lease.lessee),
+        _ => state.assets().get(asset).map(|asset| asset.owner),
+    }
+}
+
+/// Orchestration write rule for parcels: only each parcel's current writer
+/// may change it. Built from the state at `height`; targets that are not
+/// parcels are left to other rules.
+pub fn parcel_write_rule(state: &WorldState, height: u64) -> WriteCheck {
+    let writers: BTreeMap<[u8; 32], Address> = state.assets().iter()
+        .filter(|(_, asset)| matches!(asset.kind, AssetKind::Parcel { .. }))
+        .filter_map(|(id, _)| writer(state, id, height).map(|writer| (*id, writer)))
+        .collect();
+    Box::new(move |account, target| writers.get(target).is_none_or(|writer| writer == account))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lease_terms_and_payment_schedule() {
+        let terms = LeaseTerms { lessee: [2u8; 32], duration: 30, epoch_blocks: 10, rent_per_epoch: 5 };
+        assert!(terms.check().is_ok());
+        assert_eq!(terms.total_rent(), Some(15));
+        assert!(LeaseTerms { duration: 25, ..terms.clone() }.check().is_err());
+        assert!(LeaseTerms { epoch_blocks: 0, ..terms.clone() }.check().is_err());
+
+        let lease = Lease::start(&LeaseOffer { lessor: [1u8; 32], terms }, 100);
+        assert!(lease.is_active(100) && lease.is_active(129) && !lease.is_active(130));
+
+        let mut leases = Leases::default();
+        leases.restore_lease([7u8; 32], Some(lease.clone()));
+        assert!(leases.due(109, 10).is_empty());
+        assert_eq!(leases.due(110, 10), vec![[7u8; 32]]);
+
+        // Three epochs, three payments, then the lease ends
+        let second = lease.after_payment().unwrap();
+        leases.restore_lease([7u8; 32], Some(second.clone()));
+        assert!(leases.due(110, 10).is_empty());
+        let third = second.after_payment().unwrap();
+        assert_eq!(third.next_payment, 130);
+        assert!(third.after_payment().is_none());
+    }
+}
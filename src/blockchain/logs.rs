@@ -0,0 +1,215 @@
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, HashMap};
+use crate::blockchain::bloom::Bloom;
+use crate::blockchain::execution::Receipt;
+use crate::blockchain::transaction::TxHash;
+use crate::blockchain::types::{hex_serde, hex_serde_option, Address};
+
+/// Widest block range a single log query may cover
+pub const MAX_LOG_RANGE: u64 = 10_000;
+
+/// Criteria for `getLogs`; every set field must match
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogFilter {
+    pub from_block: Option<u64>,
+    pub to_block: Option<u64>,
+    #[serde(with = "hex_serde_option")]
+    pub address: Option<Address>,
+    /// Matches events with any of these topics
+    pub topics: Vec<String>,
+}
+
+impl LogFilter {
+    /// Whether a block with this bloom may contain matching events
+    pub fn bloom_matches(&self, bloom: &Bloom) -> bool {
+        let address = self.address.is_none_or(|address| bloom.contains_input(&address));
+        let topic = self.topics.is_empty()
+            || self.topics.iter().any(|topic| bloom.contains_input(topic.as_bytes()));
+        address && topic
+    }
+
+    fn matches(&self, contract: &Address, topic: &str) -> bool {
+        self.address.is_none_or(|address| address == *contract)
+            && (self.topics.is_empty() || self.topics.iter().any(|t| t == topic))
+    }
+}
+
+/// Event with its position in the chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogEntry {
+    pub block: u64,
+    #[serde(with = "hex_serde")]
+    pub tx_hash: [u8; 32],
+    pub log_index: usize,
+    #[serde(with = "hex_serde")]
+    pub contract: Address,
+    pub topic: String,
+    #[serde(with = "hex_serde")]
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogQueryResult {
+    pub logs: Vec<LogEntry>,
+    /// Blocks whose receipts were read
+    pub blocks_scanned: u64,
+    /// Blocks ruled out by their header bloom alone
+    pub blocks_skipped: u64,
+}
+
+struct BlockLogs {
+    bloom: Bloom,
+    receipts: Vec<Receipt>,
+}
+
+/// Receipts by block height with their header blooms
+#[derive(Default)]
+pub struct LogIndex {
+    blocks: BTreeMap<u64, BlockLogs>,
+    /// Height of the block that included each transaction
+    tx_heights: HashMap<TxHash, u64>,
+}
+
+impl LogIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_block(&mut self, height: u64, bloom: Bloom, receipts: Vec<Receipt>) {
+        // A block recorded again at the same height replaces the old one
+        if let Some(replaced) = self.blocks.remove(&height) {
+            for receipt in &replaced.receipts {
+                self.tx_heights.remove(&receipt.tx_hash);
+            }
+        }
+        for receipt in &receipts {
+            self.tx_heights.insert(receipt.tx_hash, height);
+        }
+        self.blocks.insert(height, BlockLogs { bloom, receipts });
+    }
+
+    /// Receipt of an included transaction with its block height
+    pub fn receipt(&self, tx_hash: &TxHash) -> Option<(u64, &Receipt)> {
+        let height = *self.tx_heights.get(tx_hash)?;
+        let receipt = self.receipts(height).iter().find(|receipt| receipt.tx_hash == *tx_hash)?;
+        Some((height, receipt))
+    }
+
+    pub fn latest_height(&self) -> u64 {
+        self.blocks.keys().next_back().copied().unwrap_or(0)
+    }
+
+    pub fn receipts(&self, height: u64) -> &[Receipt] {
+        self.blocks.get(&height).map(|block| block.receipts.as_slice()).unwrap_or_default()
+    }
+
+    pub fn bloom(&self, height: u64) -> Option<&Bloom> {
+        self.blocks.get(&height).map(|block| &block.bloom)
+    }
+
+    fn range(&self, filter: &LogFilter) -> Result<(u64, u64), &'static str> {
+        let to = filter.to_block.unwrap_or_else(|| self.latest_height());
+        let from = filter.from_block.unwrap_or(to);
+        if from > to {
+            return Err("from_block is after to_block");
+        }
+        if to - from >= MAX_LOG_RANGE {
+            return Err("Block range too large");
+        }
+        Ok((from, to))
+    }
+
+    /// Heights whose bloom may match; light clients fetch only these blocks
+    pub fn candidate_blocks(&self, filter: &LogFilter) -> Result<Vec<u64>, &'static str> {
+        let (from, to) = self.range(filter)?;
+        Ok(self.blocks.range(from..=to)
+            .filter(|(_, block)| filter.bloom_matches(&block.bloom))
+            .map(|(height, _)| *height)
+            .collect())
+    }
+
+    pub fn query(&self, filter: &LogFilter) -> Result<LogQueryResult, &'static str> {
+        let (from, to) = self.range(filter)?;
+        let mut result = LogQueryResult { logs: Vec::new(), blocks_scanned: 0, blocks_skipped: 0 };
+
+        for (height, block) in self.blocks.range(from..=to) {
+            if !filter.bloom_matches(&block.bloom) {
+                result.blocks_skipped += 1;
+                continue;
+            }
+            result.blocks_scanned += 1;
+
+            let mut log_index = 0;
+            for receipt in &block.receipts {
+                for event in &receipt.events {
+                    if filter.matches(&event.contract, &event.topic) {
+                        result.logs.push(LogEntry {
+                            block: *height,
+                            tx_hash: receipt.tx_hash,
+                            log_index,
+                            contract: event.contract,
+                            topic: event.topic.clone(),
+                            data: event.data.clone(),
+                        });
+                    }
+                    log_index += 1;
+                }
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::execution::Event;
+
+    fn receipt(contract: Address, topic: &str) -> Receipt {
+        Receipt {
+            tx_hash: blake3::hash(topic.as_bytes()).into(),
+            success: true,
+            gas_used: 0,
+            fee: 0,
+            refund: 0,
+            output: Vec::new(),
+            events: vec![Event { contract, topic: topic.to_string(), data: vec![1] }],
+            contract_address: None,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_bloom_skips_unrelated_blocks() {
+        let mut index = LogIndex::new();
+        for height in 1..=50 {
+            let receipts = if height == 42 {
+                vec![receipt([4u8; 32], "Transfer")]
+            } else {
+                vec![receipt([1u8; 32], "Noise")]
+            };
+            let bloom = Bloom::for_block(&[], &receipts);
+            index.record_block(height, bloom, receipts);
+        }
+
+        let filter = LogFilter {
+            from_block: Some(1),
+            to_block: Some(50),
+            address: Some([4u8; 32]),
+            topics: vec!["Transfer".to_string()],
+        };
+        let result = index.query(&filter).unwrap();
+        assert_eq!(result.logs.len(), 1);
+        assert_eq!(result.logs[0].block, 42);
+        assert_eq!(result.blocks_scanned, 1);
+        assert_eq!(result.blocks_skipped, 49);
+        assert_eq!(index.candidate_blocks(&filter).unwrap(), vec![42]);
+        let (height, found) = index.receipt(&result.logs[0].tx_hash).unwrap();
+        assert_eq!((height, &found.events[0].topic[..]), (42, "Transfer"));
+
+        let too_wide = LogFilter { from_block: Some(0), to_block: Some(MAX_LOG_RANGE), ..Default::default() };
+        assert_eq!(index.query(&too_wide).unwrap_err(), "Block range too large");
+    }
+}
@@ -0,0 +1,217 @@
+//! Native marketplace for assets.
+//!
+//! An owner lists an asset at a fixed price or for auction; while listed the
+//! asset cannot be transferred. Bids are escrowed in `ESCROW_ADDRESS` and the
+//! previous highest bid is refunded when it is outbid. Every sale pays the
+//! creator's royalty first and the rest to the seller. Listing, bidding,
+//! sales and cancellations emit events under `MARKET_ADDRESS`.
+
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use crate::blockchain::types::{hex_serde, Address};
+
+/// Contract address market events are emitted under
+pub const MARKET_ADDRESS: Address = *b"qmv:market::::::::::::::::::::::";
+
+/// Account holding bids until an auction settles
+pub const ESCROW_ADDRESS: Address = *b"qmv:market:escrow:::::::::::::::";
+
+/// Longest auction, in blocks
+pub const MAX_AUCTION_BLOCKS: u64 = 100_000;
+
+pub const TOPIC_ASK: &str = "market.ask";
+pub const TOPIC_BID: &str = "market.bid";
+pub const TOPIC_SALE: &str = "market.sale";
+pub const TOPIC_CANCEL: &str = "market.cancel";
+
+/// Event data: asset ID, the account acting, then the amount (u128, big-endian)
+pub fn event_data(asset: &[u8; 32], account: &Address, amount: u128) -> Vec<u8> {
+    let mut data = Vec::with_capacity(80);
+    data.extend_from_slice(asset);
+    data.extend_from_slice(account);
+    data.extend_from_slice(&amount.to_be_bytes());
+    data
+}
+
+/// Sale terms chosen by the seller
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ListingTerms {
+    FixedPrice { price: u128 },
+    /// Highest bid at or above `reserve` wins once `duration` blocks have passed
+    Auction { reserve: u128, duration: u64 },
+}
+
+/// Escrowed bid
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bid {
+    #[serde(with = "hex_serde")]
+    pub bidder: Address,
+    pub amount: u128,
+}
+
+/// Live sale state of a listing
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ListingKind {
+    FixedPrice { price: u128 },
+    Auction {
+        reserve: u128,
+        /// First height at which the auction can be settled
+        ends_at: u64,
+        highest: Option<Bid>,
+    },
+}
+
+/// Asset on sale
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Listing {
+    #[serde(with = "hex_serde")]
+    pub seller: Address,
+    pub kind: ListingKind,
+    pub listed_at: u64,
+}
+
+impl Listing {
+    /// Listing for `terms` created in the block at `height`
+    pub fn new(seller: Address, terms: &ListingTerms, height: u64) -> Result<Self, &'static str> {
+        let kind = match *terms {
+            ListingTerms::FixedPrice { price } if price > 0 => ListingKind::FixedPrice { price },
+            ListingTerms::FixedPrice { .. } => return Err("Listing price must be positive"),
+            ListingTerms::Auction { duration, .. } if duration == 0 || duration > MAX_AUCTION_BLOCKS => {
+                return Err("Auction duration must be between 1 and 100,000 blocks");
+            }
+            ListingTerms::Auction { reserve, duration } => {
+                ListingKind::Auction { reserve, ends_at: height + duration, highest: None }
+            }
+        };
+        Ok(Self { seller, kind, listed_at: height })
+    }
+
+    /// Price a buyer pays now, or the lowest acceptable next bid
+    pub fn ask(&self) -> u128 {
+        match &self.kind {
+            ListingKind::FixedPrice { price } => *price,
+            ListingKind::Auction { reserve, highest: None, .. } => *reserve,
+            ListingKind::Auction { highest: Some(bid), .. } => bid.amount.saturating_add(1),
+        }
+    }
+}
+
+/// Marketplace operations
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MarketAction {
+    /// Put one of the sender's assets on sale
+    List {
+        #[serde(with = "hex_serde")]
+        asset: [u8; 32],
+        terms: ListingTerms,
+    },
+    /// Withdraw a listing; auctions with a bid cannot be cancelled
+    Cancel {
+        #[serde(with = "hex_serde")]
+        asset: [u8; 32],
+    },
+    /// Buy a fixed-price listing. `price` must match the listing, so a
+    /// seller cannot raise it while the transaction is pending.
+    Buy {
+        #[serde(with = "hex_serde")]
+        asset: [u8; 32],
+        price: u128,
+    },
+    /// Escrow a bid on a running auction
+    Bid {
+        #[serde(with = "hex_serde")]
+        asset: [u8; 32],
+        amount: u128,
+    },
+    /// Close an auction that has ended; anyone may submit it
+    Settle {
+        #[serde(with = "hex_serde")]
+        asset: [u8; 32],
+    },
+}
+
+impl MarketAction {
+    pub fn asset(&self) -> &[u8; 32] {
+        match self {
+            Self::List { asset, .. }
+            | Self::Cancel { asset }
+            | Self::Buy { asset, .. }
+            | Self::Bid { asset, .. }
+            | Self::Settle { asset } => asset,
+        }
+    }
+
+    /// Tokens the sender pays in, held like a transfer amount
+    pub fn value(&self) -> u128 {
+        match self {
+            Self::Buy { price, .. } => *price,
+            Self::Bid { amount, .. } => *amount,
+            Self::List { .. } | Self::Cancel { .. } | Self::Settle { .. } => 0,
+        }
+    }
+}
+
+/// Active listings by asset ID
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Listings {
+    listings: BTreeMap<[u8; 32], Listing>,
+}
+
+impl Listings {
+    pub fn get(&self, asset: &[u8; 32]) -> Option<&Listing> {
+        self.listings.get(asset)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8; 32], &Listing)> {
+        self.listings.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.listings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.listings.is_empty()
+    }
+
+    /// Set a listing to a journaled value; `None` removes it
+    pub(crate) fn restore(&mut self, asset: [u8; 32], listing: Option<Listing>) {
+        match listing {
+            Some(listing) => { self.listings.insert(asset, listing); }
+            None => { self.listings.remove(&asset); }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listing_terms_and_ask() {
+        assert!(Listing::new([1u8; 32], &ListingTerms::FixedPrice { price: 0 }, 5).is_err());
+        assert!(Listing::new([1u8; 32], &ListingTerms::Auction { reserve: 10, duration: 0 }, 5).is_err());
+        assert!(Listing::new([1u8; 32], &ListingTerms::Auction { reserve: 10, duration: MAX_AUCTION_BLOCKS + 1 }, 5).is_err());
+
+        let fixed = Listing::new([1u8; 32], &ListingTerms::FixedPrice { price: 300 }, 5).unwrap();
+        assert_eq!(fixed.ask(), 300);
+
+        let mut auction = Listing::new([1u8; 32], &ListingTerms::Auction { reserve: 100, duration: 20 }, 5).unwrap();
+        assert_eq!(auction.ask(), 100);
+        match &mut auction.kind {
+            ListingKind::Auction { ends_at, highest, .. } => {
+                assert_eq!(*ends_at, 25);
+                *highest = Some(Bid { bidder: [2u8; 32], amount: 150 });
+            }
+            ListingKind::FixedPrice { .. } => unreachable!(),
+        }
+        assert_eq!(auction.ask(), 151);
+
+        let data = event_data(&[3u8; 32], &[4u8; 32], 7);
+        assert_eq!(data.len(), 80);
+        assert_eq!(data[79], 7);
+    }
+}
@@ -0,0 +1,403 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+use crate::blockchain::state::WorldState;
+use crate::blockchain::transaction::{Transaction, TxHash};
+use crate::blockchain::types::Address;
+use crate::crypto::domain::MAINNET_NETWORK_ID;
+
+/// Mempool admission and eviction limits
+#[derive(Debug, Clone)]
+pub struct MempoolConfig {
+    /// Minimum gas price increase, in percent, to replace a pending transaction
+    pub replacement_bump_percent: u128,
+    /// Pending transactions allowed per sender
+    pub max_per_account: usize,
+    /// Pending transactions allowed in total
+    pub max_size: usize,
+    /// How long a transaction may wait before it is dropped
+    pub ttl: Duration,
+    /// Transactions signed for any other network are rejected
+    pub network_id: u64,
+}
+
+impl Default for MempoolConfig {
+    fn default() -> Self {
+        Self {
+            replacement_bump_percent: 10,
+            max_per_account: 16,
+            max_size: 8_192,
+            ttl: Duration::from_secs(3 * 60 * 60),
+            network_id: MAINNET_NETWORK_ID,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct PooledTransaction {
+    tx: Transaction,
+    hash: TxHash,
+    received: Instant,
+    /// Arrival order across the whole pool
+    sequence: u64,
+}
+
+/// Pending transaction that can execute once the sender's earlier ones have
+#[derive(Debug, Clone, Copy)]
+pub struct ReadyTransaction<'a> {
+    pub tx: &'a Transaction,
+    pub hash: TxHash,
+    /// Arrival order across the whole pool; lower arrived first
+    pub sequence: u64,
+}
+
+/// Result of admitting a transaction
+#[derive(Debug, Clone, PartialEq)]
+pub enum Admission {
+    Added(TxHash),
+    /// The transaction replaced a pending one with the same nonce
+    Replaced { added: TxHash, replaced: TxHash },
+    /// The pool was full, so a lower-priced transaction was dropped
+    Evicted { added: TxHash, evicted: TxHash },
+}
+
+/// Transaction Pool
+/// Holds validated transactions per sender, ordered by nonce.
+pub struct Mempool {
+    config: MempoolConfig,
+    by_sender: HashMap<Address, BTreeMap<u64, PooledTransaction>>,
+    by_hash: HashMap<TxHash, (Address, u64)>,
+    next_sequence: u64,
+}
+
+impl Mempool {
+    pub fn new(config: MempoolConfig) -> Self {
+        Self {
+            config,
+            by_sender: HashMap::new(),
+            by_hash: HashMap::new(),
+            next_sequence: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_hash.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_hash.is_empty()
+    }
+
+    pub fn get(&self, hash: &TxHash) -> Option<&Transaction> {
+        let (sender, nonce) = self.by_hash.get(hash)?;
+        self.by_sender.get(sender)?.get(nonce).map(|pooled| &pooled.tx)
+    }
+
+    /// Every pooled transaction, in arrival order
+    pub fn transactions(&self) -> Vec<&Transaction> {
+        let mut pooled: Vec<&PooledTransaction> = self.by_sender.values().flat_map(|queue| queue.values()).collect();
+        pooled.sort_by_key(|pooled| pooled.sequence);
+        pooled.into_iter().map(|pooled| &pooled.tx).collect()
+    }
+
+    /// Every pooled transaction, highest gas price first and then in arrival order
+    pub fn by_gas_price(&self) -> Vec<&Transaction> {
+        let mut pooled: Vec<&PooledTransaction> = self.by_sender.values().flat_map(|queue| queue.values()).collect();
+        pooled.sort_by_key(|pooled| (Reverse(pooled.tx.gas_price), pooled.sequence));
+        pooled.into_iter().map(|pooled| &pooled.tx).collect()
+    }
+
+    /// Pending transactions for `sender`, in nonce order
+    pub fn pending(&self, sender: &Address) -> Vec<&Transaction> {
+        self.by_sender.get(sender)
+            .map(|queue| queue.values().map(|pooled| &pooled.tx).collect())
+            .unwrap_or_default()
+    }
+
+    /// Per sender, the pending transactions that follow on from the sender's
+    /// committed nonce without a gap, in nonce order
+    pub fn ready(&self, state: &WorldState) -> Vec<Vec<ReadyTransaction<'_>>> {
+        self.by_sender.iter()
+            .map(|(sender, queue)| {
+                let mut nonce = state.account(sender).nonce;
+                let mut ready = Vec::new();
+                while let Some(pooled) = queue.get(&nonce) {
+                    ready.push(ReadyTransaction { tx: &pooled.tx, hash: pooled.hash, sequence: pooled.sequence });
+                    nonce += 1;
+                }
+                ready
+            })
+            .filter(|ready| !ready.is_empty())
+            .collect()
+    }
+
+    /// Next nonce the sender should use, counting contiguous pending transactions
+    pub fn next_nonce(&self, sender: &Address, state: &WorldState) -> u64 {
+        let mut nonce = state.account(sender).nonce;
+        if let Some(queue) = self.by_sender.get(sender) {
+            while queue.contains_key(&nonce) {
+                nonce += 1;
+            }
+        }
+        nonce
+    }
+
+    /// Validate and admit a transaction against the latest state
+    pub fn insert(&mut self, tx: Transaction, state: &WorldState) -> Result<Admission, &'static str> {
+        tx.verify_for_network(self.config.network_id)?;
+        let account = state.account(&tx.from);
+        if tx.nonce < account.nonce {
+            return Err("Nonce too low");
+        }
+        if tx.nonce >= account.nonce + self.config.max_per_account as u64 {
+            return Err("Nonce too far ahead of account nonce");
+        }
+        Self::check_sponsorship(&tx, state)?;
+        if !tx.witnesses.is_empty() && state.validations().get(&tx.from).is_none() {
+            return Err("Account has no validation contract");
+        }
+
+        let hash = tx.hash();
+        if self.by_hash.contains_key(&hash) {
+            return Err("Transaction already pending");
+        }
+
+        let queue = self.by_sender.get(&tx.from);
+        let existing = queue.and_then(|queue| queue.get(&tx.nonce));
+
+        // Pending transactions before this one must stay affordable too
+        let committed: u128 = queue
+            .map(|queue| queue.range(..tx.nonce).map(|(_, pooled)| Self::cost(&pooled.tx)).sum())
+            .unwrap_or(0);
+        if account.balance < committed.saturating_add(Self::cost(&tx)) {
+            return Err("Insufficient balance for pending transactions");
+        }
+
+        let mut evicted = None;
+        match existing {
+            Some(pending) => {
+                let minimum = pending.tx.gas_price * (100 + self.config.replacement_bump_percent) / 100;
+                if tx.gas_price <= pending.tx.gas_price || tx.gas_price < minimum {
+                    return Err("Replacement gas price too low");
+                }
+            }
+            None => {
+                if queue.map_or(0, |queue| queue.len()) >= self.config.max_per_account {
+                    return Err("Too many pending transactions for account");
+                }
+                if self.len() >= self.config.max_size {
+                    match self.cheapest_tail(&tx.from) {
+                        Some(cheapest) if cheapest.tx.gas_price < tx.gas_price => evicted = Some(cheapest.hash),
+                        _ => return Err("Mempool is full"),
+                    }
+                }
+            }
+        }
+
+        let sender = tx.from;
+        let nonce = tx.nonce;
+        let replaced = self.by_sender.entry(sender).or_default().insert(nonce, PooledTransaction {
+            tx,
+            hash,
+            received: Instant::now(),
+            sequence: self.next_sequence,
+        });
+        self.next_sequence += 1;
+        self.by_hash.insert(hash, (sender, nonce));
+
+        if let Some(evicted) = evicted {
+            self.remove(&evicted);
+            return Ok(Admission::Evicted { added: hash, evicted });
+        }
+        Ok(match replaced {
+            Some(old) => {
+                self.by_hash.remove(&old.hash);
+                Admission::Replaced { added: hash, replaced: old.hash }
+            }
+            None => Admission::Added(hash),
+        })
+    }
+
+    /// Lowest-priced transaction that is last in another sender's queue, so
+    /// dropping it leaves no nonce gap. Ties go to the latest arrival.
+    fn cheapest_tail(&self, sender: &Address) -> Option<&PooledTransaction> {
+        self.by_sender.iter()
+            .filter(|(queue_sender, _)| *queue_sender != sender)
+            .filter_map(|(_, queue)| queue.values().next_back())
+            .min_by_key(|pooled| (pooled.tx.gas_price, Reverse(pooled.sequence)))
+    }
+
+    pub fn remove(&mut self, hash: &TxHash) -> Option<Transaction> {
+        let (sender, nonce) = self.by_hash.remove(hash)?;
+        let queue = self.by_sender.get_mut(&sender)?;
+        let removed = queue.remove(&nonce).map(|pooled| pooled.tx);
+        if queue.is_empty() {
+            self.by_sender.remove(&sender);
+        }
+        removed
+    }
+
+    /// Drop transactions received before `now - ttl`
+    pub fn evict_expired(&mut self, now: Instant) -> usize {
+        let ttl = self.config.ttl;
+        let expired: Vec<TxHash> = self.by_sender.values()
+            .flat_map(|queue| queue.values())
+            .filter(|pooled| now.saturating_duration_since(pooled.received) >= ttl)
+            .map(|pooled| pooled.hash)
+            .collect();
+        for hash in &expired {
+            self.remove(hash);
+        }
+        expired.len()
+    }
+
+    /// Re-check every pending transaction after a new block.
+    /// Drops mined or stale nonces, transactions the sender can no longer
+    /// afford and sponsorships that expired or lost their policy.
+    pub fn revalidate(&mut self, state: &WorldState) -> usize {
+        let mut dropped = Vec::new();
+        for (sender, queue) in &self.by_sender {
+            let account = state.account(sender);
+            let mut remaining = account.balance;
+            for (nonce, pooled) in queue {
+                let cost = Self::cost(&pooled.tx);
+                if *nonce < account.nonce || remaining < cost || Self::check_sponsorship(&pooled.tx, state).is_err() {
+                    dropped.push(pooled.hash);
+                } else {
+                    remaining -= cost;
+                }
+            }
+        }
+        for hash in &dropped {
+            self.remove(hash);
+        }
+        dropped.len()
+    }
+
+    /// Worst-case balance a transaction can consume from its sender
+    fn cost(tx: &Transaction) -> u128 {
+        match tx.sponsor {
+            Some(_) => tx.value(),
+            None => tx.max_fee().saturating_add(tx.value()),
+        }
+    }
+
+    /// Whether a sponsored transaction could still be included in the next
+    /// block. The sponsor's spending limit and balance are left to the sealer.
+    fn check_sponsorship(tx: &Transaction, state: &WorldState) -> Result<(), &'static str> {
+        let Some(sponsorship) = &tx.sponsor else { return Ok(()) };
+        if state.height() + 1 > sponsorship.valid_until {
+            return Err("Sponsorship has expired");
+        }
+        if state.sponsors().get(&sponsorship.sponsor).is_none() {
+            return Err("Sponsor has no spending policy");
+        }
+        Ok(())
+    }
+}
+
+impl Default for Mempool {
+    fn default() -> Self {
+        Self::new(MempoolConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::transaction::TransactionAction;
+    use ed25519_dalek::SigningKey;
+
+    fn signed(key: &SigningKey, nonce: u64, gas_price: u128) -> Transaction {
+        let mut tx = Transaction::new(
+            key.verifying_key().to_bytes(),
+            nonce,
+            TransactionAction::Transfer { to: [9u8; 32], amount: 100 },
+            30_000,
+            gas_price,
+        );
+        tx.sign(key);
+        tx
+    }
+
+    fn setup() -> (SigningKey, WorldState) {
+        let key = SigningKey::from_bytes(&[5u8; 32]);
+        let state = WorldState::with_balances(&[(key.verifying_key().to_bytes(), 100_000_000)]);
+        (key, state)
+    }
+
+    #[test]
+    fn test_replacement_requires_fee_bump() {
+        let (key, state) = setup();
+        let mut pool = Mempool::default();
+        let original = match pool.insert(signed(&key, 0, 100), &state).unwrap() {
+            Admission::Added(hash) => hash,
+            other => panic!("unexpected admission {:?}", other),
+        };
+
+        assert_eq!(pool.insert(signed(&key, 0, 100), &state).unwrap_err(), "Transaction already pending");
+        assert_eq!(pool.insert(signed(&key, 0, 105), &state).unwrap_err(), "Replacement gas price too low");
+
+        match pool.insert(signed(&key, 0, 110), &state).unwrap() {
+            Admission::Replaced { replaced, .. } => assert_eq!(replaced, original),
+            other => panic!("unexpected admission {:?}", other),
+        }
+        assert_eq!(pool.len(), 1);
+        assert!(pool.get(&original).is_none());
+    }
+
+    #[test]
+    fn test_account_limits_and_nonces() {
+        let (key, state) = setup();
+        let sender = key.verifying_key().to_bytes();
+        let mut pool = Mempool::new(MempoolConfig { max_per_account: 2, ..Default::default() });
+
+        pool.insert(signed(&key, 0, 1), &state).unwrap();
+        pool.insert(signed(&key, 1, 1), &state).unwrap();
+        assert_eq!(pool.insert(signed(&key, 2, 1), &state).unwrap_err(), "Nonce too far ahead of account nonce");
+        assert_eq!(pool.next_nonce(&sender, &state), 2);
+        assert_eq!(pool.pending(&sender).len(), 2);
+
+        // Once nonce 0 is mined, only nonce 1 stays pending
+        let mut next = state.clone();
+        next.account_mut(&sender).nonce = 1;
+        assert_eq!(pool.revalidate(&next), 1);
+        assert_eq!(pool.pending(&sender)[0].nonce, 1);
+    }
+
+    #[test]
+    fn test_full_pool_evicts_cheapest_tail() {
+        let (key, mut state) = setup();
+        let other = SigningKey::from_bytes(&[6u8; 32]);
+        state.account_mut(&other.verifying_key().to_bytes()).balance = 100_000_000;
+        let mut pool = Mempool::new(MempoolConfig { max_size: 3, ..Default::default() });
+        let cheap = match pool.insert(signed(&key, 0, 5), &state).unwrap() {
+            Admission::Added(hash) => hash,
+            other => panic!("unexpected admission {:?}", other),
+        };
+        let cheapest = pool.insert(signed(&key, 1, 1), &state).unwrap();
+        pool.insert(signed(&other, 0, 3), &state).unwrap();
+
+        // The cheapest queue tail goes, and only for a better price
+        assert_eq!(pool.insert(signed(&other, 1, 1), &state).unwrap_err(), "Mempool is full");
+        match pool.insert(signed(&other, 1, 4), &state).unwrap() {
+            Admission::Evicted { evicted, .. } => assert_eq!(Admission::Added(evicted), cheapest),
+            other => panic!("unexpected admission {:?}", other),
+        }
+        assert_eq!(pool.len(), 3);
+        let prices: Vec<u128> = pool.by_gas_price().iter().map(|tx| tx.gas_price).collect();
+        assert_eq!(prices, vec![5, 4, 3]);
+        assert!(pool.get(&cheap).is_some());
+    }
+
+    #[test]
+    fn test_ttl_eviction() {
+        let (key, state) = setup();
+        let mut pool = Mempool::default();
+        pool.insert(signed(&key, 0, 1), &state).unwrap();
+
+        assert_eq!(pool.evict_expired(Instant::now()), 0);
+        assert_eq!(pool.evict_expired(Instant::now() + MempoolConfig::default().ttl), 1);
+        assert!(pool.is_empty());
+    }
+}
@@ -0,0 +1,328 @@
+//! Per-contract metering.
+//!
+//! After each block the node attributes every contract call to the contract
+//! that ran: gas used, whether the call failed, and the size of the
+//! contract's storage afterwards. Usage is kept over rolling windows of the
+//! last blocks as well as since the node started, so operators and
+//! governance policies can spot contracts that burn a large share of the
+//! chain's gas or keep failing.
+//!
+//! The meter lives in memory and is not part of consensus. Scheduled runs
+//! have no transaction to attribute them by and are not metered.
+
+use serde::{Serialize, Deserialize};
+use std::collections::{HashMap, VecDeque};
+use crate::blockchain::execution::Receipt;
+use crate::blockchain::multisig::MultisigOperation;
+use crate::blockchain::state::WorldState;
+use crate::blockchain::transaction::{Transaction, TransactionAction, TxHash};
+use crate::blockchain::types::{hex_serde, Address};
+use crate::math::precision::PreciseFloat;
+
+/// Lengths in blocks of the rolling windows usage is reported over
+pub const METERING_WINDOWS: [u64; 3] = [100, 1_000, 10_000];
+
+/// Window `getTopContracts` ranks over unless told otherwise
+pub const DEFAULT_RANKING_WINDOW: u64 = 1_000;
+
+/// Most contracts `getTopContracts` returns
+pub const MAX_TOP_CONTRACTS: usize = 100;
+
+/// Calls within a window before a contract's failure rate counts, so a
+/// single failed call does not top the ranking
+pub const MIN_CALLS_FOR_FAILURE_RATE: u64 = 10;
+
+/// Gas and calls of a contract over some blocks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+    pub gas: u64,
+    pub calls: u64,
+    pub failures: u64,
+}
+
+impl Usage {
+    fn add(&mut self, other: &Usage) {
+        self.gas = self.gas.saturating_add(other.gas);
+        self.calls += other.calls;
+        self.failures += other.failures;
+    }
+
+    /// Share of calls that failed, from 0 to 1
+    pub fn failure_rate(&self) -> f64 {
+        match self.calls {
+            0 => 0.0,
+            calls => self.failures as f64 / calls as f64,
+        }
+    }
+}
+
+/// Usage over the last `blocks` blocks
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WindowUsage {
+    pub blocks: u64,
+    #[serde(flatten)]
+    pub usage: Usage,
+    pub failure_rate: f64,
+}
+
+/// Everything metered for one contract, for `getContractStats`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ContractStats {
+    #[serde(with = "hex_serde")]
+    pub contract: Address,
+    /// Bytes of keys and values in the contract's storage after its last call
+    pub storage_bytes: u64,
+    /// Height of the last block that called the contract
+    pub last_called: u64,
+    /// Usage since the node started metering
+    pub total: Usage,
+    pub failure_rate: f64,
+    /// Usage over each of `METERING_WINDOWS`
+    pub windows: Vec<WindowUsage>,
+}
+
+/// What `getTopContracts` ranks by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContractRanking {
+    Gas,
+    Calls,
+    Failures,
+    FailureRate,
+    /// Current storage size; the window does not apply
+    StorageBytes,
+}
+
+#[derive(Default)]
+struct ContractUsage {
+    /// Usage per block that called the contract, oldest first
+    blocks: VecDeque<(u64, Usage)>,
+    total: Usage,
+    storage_bytes: u64,
+}
+
+impl ContractUsage {
+    /// Usage in the `window` blocks up to and including `height`
+    fn window(&self, height: u64, window: u64) -> Usage {
+        let mut usage = Usage::default();
+        for (_, block) in self.blocks.iter().rev().take_while(|(called, _)| called + window > height) {
+            usage.add(block);
+        }
+        usage
+    }
+}
+
+/// Contract Meter
+/// Rolling per-contract gas, call and failure counts fed by executed blocks.
+#[derive(Default)]
+pub struct ContractMeter {
+    contracts: HashMap<Address, ContractUsage>,
+    /// Last block metered
+    height: u64,
+}
+
+impl ContractMeter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    /// Meter the block at `height` from its transactions and receipts.
+    /// `state` is the state after the block, for storage sizes. Blocks at or
+    /// below the last metered height are ignored.
+    pub fn record_block(&mut self, height: u64, transactions: &[Transaction], receipts: &[Receipt], state: &WorldState) {
+        if height <= self.height {
+            return;
+        }
+        self.height = height;
+        let outcomes: HashMap<TxHash, &Receipt> = receipts.iter().map(|receipt| (receipt.tx_hash, receipt)).collect();
+        let mut block: HashMap<Address, Usage> = HashMap::new();
+        for tx in transactions {
+            let (Some(contract), Some(receipt)) = (called_contract(tx), outcomes.get(&tx.hash())) else { continue };
+            block.entry(contract).or_default().add(&Usage {
+                gas: receipt.gas_used,
+                calls: 1,
+                failures: u64::from(!receipt.success),
+            });
+        }
+
+        let oldest = height.saturating_sub(Self::retained_blocks());
+        for (contract, usage) in block {
+            let metered = self.contracts.entry(contract).or_default();
+            metered.total.add(&usage);
+            metered.blocks.push_back((height, usage));
+            while metered.blocks.front().is_some_and(|(called, _)| *called <= oldest) {
+                metered.blocks.pop_front();
+            }
+            metered.storage_bytes = state.contract(&contract).map_or(0, |account| {
+                account.storage.iter().map(|(key, value)| (key.len() + value.len()) as u64).sum()
+            });
+        }
+    }
+
+    /// Stats of one contract, if it has been called since metering began
+    pub fn stats(&self, contract: &Address) -> Option<ContractStats> {
+        let metered = self.contracts.get(contract)?;
+        let windows = METERING_WINDOWS.iter()
+            .map(|&blocks| {
+                let usage = metered.window(self.height, blocks);
+                WindowUsage { blocks, usage, failure_rate: usage.failure_rate() }
+            })
+            .collect();
+        Some(ContractStats {
+            contract: *contract,
+            storage_bytes: metered.storage_bytes,
+            last_called: metered.blocks.back().map_or(0, |(height, _)| *height),
+            total: metered.total,
+            failure_rate: metered.total.failure_rate(),
+            windows,
+        })
+    }
+
+    /// The `limit` contracts highest by `by` over the last `window` blocks,
+    /// highest first. Contracts with nothing to rank by are left out.
+    pub fn top(&self, by: ContractRanking, window: u64, limit: usize) -> Result<Vec<ContractStats>, &'static str> {
+        if !METERING_WINDOWS.contains(&window) {
+            return Err("Window must be one of the metering windows");
+        }
+        let mut ranked: Vec<(f64, &Address)> = self.contracts.iter()
+            .filter_map(|(contract, metered)| {
+                let usage = metered.window(self.height, window);
+                let score = match by {
+                    ContractRanking::Gas => usage.gas as f64,
+                    ContractRanking::Calls => usage.calls as f64,
+                    ContractRanking::Failures => usage.failures as f64,
+                    ContractRanking::FailureRate if usage.calls < MIN_CALLS_FOR_FAILURE_RATE => 0.0,
+                    ContractRanking::FailureRate => usage.failure_rate(),
+                    ContractRanking::StorageBytes => metered.storage_bytes as f64,
+                };
+                (score > 0.0).then_some((score, contract))
+            })
+            .collect();
+        // Ties go to the lower address so every node lists them alike
+        ranked.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(b.1)));
+        Ok(ranked.into_iter()
+            .take(limit.min(MAX_TOP_CONTRACTS))
+            .filter_map(|(_, contract)| self.stats(contract))
+            .collect())
+    }
+
+    /// Heaviest contract's share of contract gas and the highest failure
+    /// rate over the shortest window, for governance policy conditions
+    pub fn metrics(&self) -> HashMap<String, PreciseFloat> {
+        let window = METERING_WINDOWS[0];
+        let usages: Vec<Usage> = self.contracts.values().map(|metered| metered.window(self.height, window)).collect();
+        let total_gas: u64 = usages.iter().map(|usage| usage.gas).sum();
+        let top_gas = usages.iter().map(|usage| usage.gas).max().unwrap_or(0);
+        let top_gas_share = if total_gas == 0 { 0.0 } else { top_gas as f64 / total_gas as f64 };
+        let max_failure_rate = usages.iter()
+            .filter(|usage| usage.calls >= MIN_CALLS_FOR_FAILURE_RATE)
+            .map(Usage::failure_rate)
+            .fold(0.0, f64::max);
+        HashMap::from([
+            ("contracts.top_gas_share".to_string(), PreciseFloat::from_f64(top_gas_share * 100.0, 2)),
+            ("contracts.max_failure_rate".to_string(), PreciseFloat::from_f64(max_failure_rate * 100.0, 2)),
+        ])
+    }
+
+    fn retained_blocks() -> u64 {
+        METERING_WINDOWS.iter().copied().max().unwrap_or(0)
+    }
+}
+
+/// Contract whose code a transaction runs
+pub fn called_contract(tx: &Transaction) -> Option<Address> {
+    match &tx.action {
+        TransactionAction::Call { contract, .. } => Some(*contract),
+        TransactionAction::MultisigExecute { operation: MultisigOperation::Call { contract, .. }, .. } => Some(*contract),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::state::ContractAccount;
+
+    fn call(contract: Address, nonce: u64) -> Transaction {
+        Transaction::new([1; 32], nonce, TransactionAction::Call { contract, input: Vec::new(), value: 0 }, 100_000, 1)
+    }
+
+    fn receipt(tx: &Transaction, gas_used: u64, success: bool) -> Receipt {
+        Receipt {
+            tx_hash: tx.hash(),
+            success,
+            gas_used,
+            fee: gas_used as u128,
+            refund: 0,
+            output: Vec::new(),
+            events: Vec::new(),
+            contract_address: None,
+            error: (!success).then(|| "Out of gas".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_meter_ranks_contracts_over_windows() {
+        let (busy, flaky) = ([4u8; 32], [5u8; 32]);
+        let mut state = WorldState::new();
+        state.insert_contract(busy, ContractAccount {
+            owner: [1; 32],
+            code: Vec::new(),
+            storage: [(b"key".to_vec(), vec![0; 7])].into_iter().collect(),
+        });
+
+        let mut meter = ContractMeter::new();
+        let mut nonce = 0;
+        for height in 1..=200 {
+            let mut transactions = Vec::new();
+            let mut receipts = Vec::new();
+            for _ in 0..2 {
+                let tx = call(busy, nonce);
+                receipts.push(receipt(&tx, 50_000, true));
+                transactions.push(tx);
+                nonce += 1;
+            }
+            // The flaky contract only runs in the last 100 blocks, mostly failing
+            if height > 100 {
+                let tx = call(flaky, nonce);
+                receipts.push(receipt(&tx, 10_000, height % 4 != 0));
+                transactions.push(tx);
+                nonce += 1;
+            }
+            meter.record_block(height, &transactions, &receipts, &state);
+        }
+
+        let stats = meter.stats(&busy).unwrap();
+        assert_eq!(stats.storage_bytes, 10);
+        assert_eq!(stats.last_called, 200);
+        assert_eq!(stats.total, Usage { gas: 20_000_000, calls: 400, failures: 0 });
+        assert_eq!(stats.windows[0], WindowUsage {
+            blocks: 100,
+            usage: Usage { gas: 10_000_000, calls: 200, failures: 0 },
+            failure_rate: 0.0,
+        });
+        assert_eq!(meter.stats(&flaky).unwrap().windows[1].usage.failures, 25);
+        assert!(meter.stats(&[6u8; 32]).is_none());
+
+        let by_gas = meter.top(ContractRanking::Gas, 100, 10).unwrap();
+        assert_eq!(by_gas.iter().map(|stats| stats.contract).collect::<Vec<_>>(), vec![busy, flaky]);
+        let by_failures = meter.top(ContractRanking::FailureRate, 1_000, 1).unwrap();
+        assert_eq!(by_failures[0].contract, flaky);
+        assert_eq!(by_failures[0].windows[0].failure_rate, 0.25);
+        assert_eq!(meter.top(ContractRanking::StorageBytes, 100, 10).unwrap().len(), 1);
+        assert!(meter.top(ContractRanking::Gas, 50, 10).is_err());
+
+        let metrics = meter.metrics();
+        assert_eq!(metrics["contracts.top_gas_share"], PreciseFloat::from_f64(10_000_000.0 / 11_000_000.0 * 100.0, 2));
+        assert_eq!(metrics["contracts.max_failure_rate"], PreciseFloat::new(2500, 2));
+
+        // A block metered again is not counted twice
+        let again = vec![call(busy, nonce)];
+        meter.record_block(200, &again, &[receipt(&again[0], 1, true)], &state);
+        assert_eq!(meter.stats(&busy).unwrap().total.calls, 400);
+    }
+}
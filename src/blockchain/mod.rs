@@ -1,6 +1,38 @@
 pub mod core;
+pub mod store;
 pub mod flux;
 pub mod zk_storage;
 
 pub mod sidechain;
-pub mod types;
+pub use metaverse_core::types;
+pub mod transaction;
+pub mod state;
+pub mod journal;
+pub mod execution;
+pub mod validator_keys;
+pub mod multisig;
+pub mod scheduler;
+pub mod assets;
+pub mod asset_metadata;
+pub mod market;
+pub mod lease;
+pub mod channel;
+pub mod htlc;
+pub mod sponsor;
+pub mod account_validation;
+pub mod confidential;
+pub mod mempool;
+pub mod builder;
+pub mod sealer;
+pub mod sync;
+pub mod snapshot;
+pub mod verdict;
+pub mod commit_reveal;
+pub mod wire;
+pub mod bloom;
+pub mod logs;
+pub mod metering;
+pub mod beacon;
+pub mod features;
+pub mod circuit_breaker;
+pub mod invariants;
@@ -0,0 +1,285 @@
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, BTreeSet};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use crate::blockchain::types::{hex_serde, hex_serde_vec, Address};
+use crate::crypto::domain::{PayloadKind, SigningDomain};
+
+/// Most signer keys a multisig account can have
+pub const MAX_SIGNERS: usize = 20;
+
+/// Address of the multisig account created by `creator` at `nonce`
+pub fn multisig_address(creator: &Address, nonce: u64) -> Address {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"qmv:multisig:");
+    hasher.update(creator);
+    hasher.update(&nonce.to_le_bytes());
+    hasher.finalize().into()
+}
+
+/// m-of-n account; its balance lives in the ordinary account map
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MultisigAccount {
+    /// Sorted signer public keys
+    #[serde(with = "hex_serde_vec")]
+    signers: Vec<[u8; 32]>,
+    threshold: u32,
+    /// Sequence number of the next operation; approvals are bound to it
+    nonce: u64,
+}
+
+impl MultisigAccount {
+    pub fn new(mut signers: Vec<[u8; 32]>, threshold: u32) -> Result<Self, &'static str> {
+        signers.sort();
+        signers.dedup();
+        validate_signer_set(&signers, threshold)?;
+        Ok(Self { signers, threshold, nonce: 0 })
+    }
+
+    pub fn signers(&self) -> &[[u8; 32]] {
+        &self.signers
+    }
+
+    pub fn threshold(&self) -> u32 {
+        self.threshold
+    }
+
+    pub fn nonce(&self) -> u64 {
+        self.nonce
+    }
+
+    /// Check that enough distinct signers approved `proposal`
+    pub fn check_approvals(&self, proposal: &MultisigProposal, network_id: u64, approvals: &[Approval]) -> Result<(), &'static str> {
+        if proposal.nonce != self.nonce {
+            return Err("Invalid multisig nonce");
+        }
+        let message = proposal.signing_bytes(network_id);
+        let mut approved = BTreeSet::new();
+        for approval in approvals {
+            if self.signers.binary_search(&approval.signer).is_err() {
+                return Err("Approval from a key that is not a signer");
+            }
+            if !approved.insert(approval.signer) {
+                return Err("Duplicate approval");
+            }
+            approval.verify(&message)?;
+        }
+        if (approved.len() as u32) < self.threshold {
+            return Err("Not enough multisig approvals");
+        }
+        Ok(())
+    }
+
+    /// Consume the current nonce after an approved operation
+    pub(crate) fn advance(&mut self) {
+        self.nonce += 1;
+    }
+
+    /// Replace the signer set, keeping the nonce
+    pub(crate) fn set_signers(&mut self, mut signers: Vec<[u8; 32]>, threshold: u32) -> Result<(), &'static str> {
+        signers.sort();
+        signers.dedup();
+        validate_signer_set(&signers, threshold)?;
+        self.signers = signers;
+        self.threshold = threshold;
+        Ok(())
+    }
+}
+
+fn validate_signer_set(signers: &[[u8; 32]], threshold: u32) -> Result<(), &'static str> {
+    if signers.is_empty() || signers.len() > MAX_SIGNERS {
+        return Err("Multisig needs between 1 and 20 distinct signers");
+    }
+    if threshold == 0 || threshold as usize > signers.len() {
+        return Err("Threshold must be between 1 and the number of signers");
+    }
+    Ok(())
+}
+
+/// What an approved multisig transaction does
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MultisigOperation {
+    /// Move tokens out of the multisig account
+    Transfer {
+        #[serde(with = "hex_serde")]
+        to: Address,
+        amount: u128,
+    },
+    /// Call a contract as the multisig account
+    Call {
+        #[serde(with = "hex_serde")]
+        contract: Address,
+        #[serde(with = "hex_serde")]
+        input: Vec<u8>,
+        #[serde(default)]
+        value: u128,
+    },
+    /// Replace the signer set and threshold
+    UpdateSigners {
+        #[serde(with = "hex_serde_vec")]
+        signers: Vec<[u8; 32]>,
+        threshold: u32,
+    },
+}
+
+/// Operation the signers approve, bound to the account's nonce
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MultisigProposal {
+    #[serde(with = "hex_serde")]
+    pub multisig: Address,
+    pub nonce: u64,
+    pub operation: MultisigOperation,
+}
+
+impl MultisigProposal {
+    /// Bytes each signer signs, bound to the main chain of `network_id`
+    pub fn signing_bytes(&self, network_id: u64) -> Vec<u8> {
+        let body = bincode::serialize(&(&self.multisig, self.nonce, &self.operation)).unwrap_or_default();
+        SigningDomain::main_chain(network_id).payload(PayloadKind::MultisigApproval, 0, &body)
+    }
+
+    pub fn id(&self, network_id: u64) -> [u8; 32] {
+        blake3::hash(&self.signing_bytes(network_id)).into()
+    }
+}
+
+/// One signer's signature over a proposal
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Approval {
+    #[serde(with = "hex_serde")]
+    pub signer: [u8; 32],
+    #[serde(with = "hex_serde")]
+    pub signature: Vec<u8>,
+}
+
+impl Approval {
+    pub fn sign(proposal: &MultisigProposal, network_id: u64, key: &SigningKey) -> Self {
+        Self {
+            signer: key.verifying_key().to_bytes(),
+            signature: key.sign(&proposal.signing_bytes(network_id)).to_bytes().to_vec(),
+        }
+    }
+
+    fn verify(&self, message: &[u8]) -> Result<(), &'static str> {
+        let key = VerifyingKey::from_bytes(&self.signer).map_err(|_| "Invalid signer key")?;
+        let signature: [u8; 64] = self.signature.as_slice().try_into()
+            .map_err(|_| "Invalid approval signature")?;
+        key.verify(message, &Signature::from_bytes(&signature))
+            .map_err(|_| "Invalid approval signature")
+    }
+}
+
+/// Proposal with the approvals collected so far
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PendingProposal {
+    #[serde(with = "hex_serde")]
+    pub id: [u8; 32],
+    pub proposal: MultisigProposal,
+    pub approvals: Vec<Approval>,
+    pub threshold: u32,
+    /// Enough approvals to submit a `multisig_execute` transaction
+    pub ready: bool,
+}
+
+/// Approval Pool
+/// Off-chain collection of signer approvals, so signers can approve one
+/// by one before anyone submits the operation.
+#[derive(Debug, Default)]
+pub struct ApprovalPool {
+    proposals: BTreeMap<[u8; 32], PendingProposal>,
+}
+
+impl ApprovalPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a proposal against the account's current state
+    pub fn propose(&mut self, proposal: MultisigProposal, account: &MultisigAccount, network_id: u64) -> Result<[u8; 32], &'static str> {
+        if proposal.nonce != account.nonce {
+            return Err("Invalid multisig nonce");
+        }
+        let id = proposal.id(network_id);
+        self.proposals.entry(id).or_insert_with(|| PendingProposal {
+            id,
+            proposal,
+            approvals: Vec::new(),
+            threshold: account.threshold,
+            ready: false,
+        });
+        Ok(id)
+    }
+
+    /// Add a signer's approval; returns the number collected
+    pub fn approve(&mut self, id: &[u8; 32], approval: Approval, account: &MultisigAccount, network_id: u64) -> Result<usize, &'static str> {
+        let pending = self.proposals.get_mut(id).ok_or("Unknown proposal")?;
+        if pending.approvals.iter().any(|existing| existing.signer == approval.signer) {
+            return Err("Duplicate approval");
+        }
+        let mut approvals = pending.approvals.clone();
+        approvals.push(approval);
+        // Checks membership and signatures; only the count may still fall short
+        match account.check_approvals(&pending.proposal, network_id, &approvals) {
+            Ok(()) | Err("Not enough multisig approvals") => {}
+            Err(e) => return Err(e),
+        }
+        pending.threshold = account.threshold;
+        pending.ready = approvals.len() as u32 >= account.threshold;
+        pending.approvals = approvals;
+        Ok(pending.approvals.len())
+    }
+
+    pub fn get(&self, id: &[u8; 32]) -> Option<&PendingProposal> {
+        self.proposals.get(id)
+    }
+
+    pub fn pending(&self, multisig: &Address) -> Vec<&PendingProposal> {
+        self.proposals.values().filter(|pending| &pending.proposal.multisig == multisig).collect()
+    }
+
+    /// Drop proposals whose nonce was used or whose account no longer exists
+    pub fn prune(&mut self, account: impl Fn(&Address) -> Option<u64>) {
+        self.proposals.retain(|_, pending| {
+            account(&pending.proposal.multisig).is_some_and(|nonce| pending.proposal.nonce >= nonce)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NETWORK: u64 = 1;
+
+    #[test]
+    fn test_approvals_are_collected_until_threshold() {
+        let keys: Vec<SigningKey> = (1..=3).map(|i| SigningKey::from_bytes(&[i; 32])).collect();
+        let account = MultisigAccount::new(keys.iter().map(|k| k.verifying_key().to_bytes()).collect(), 2).unwrap();
+        assert!(MultisigAccount::new(vec![[1u8; 32]], 2).is_err());
+
+        let proposal = MultisigProposal {
+            multisig: [9u8; 32],
+            nonce: 0,
+            operation: MultisigOperation::Transfer { to: [7u8; 32], amount: 5 },
+        };
+        let mut pool = ApprovalPool::new();
+        let id = pool.propose(proposal.clone(), &account, NETWORK).unwrap();
+
+        assert_eq!(pool.approve(&id, Approval::sign(&proposal, NETWORK, &keys[0]), &account, NETWORK), Ok(1));
+        assert_eq!(pool.approve(&id, Approval::sign(&proposal, NETWORK, &keys[0]), &account, NETWORK), Err("Duplicate approval"));
+        // Signed for another network, or by an outsider
+        assert!(pool.approve(&id, Approval::sign(&proposal, 2, &keys[1]), &account, NETWORK).is_err());
+        let outsider = SigningKey::from_bytes(&[8u8; 32]);
+        assert!(pool.approve(&id, Approval::sign(&proposal, NETWORK, &outsider), &account, NETWORK).is_err());
+        assert!(!pool.get(&id).unwrap().ready);
+
+        pool.approve(&id, Approval::sign(&proposal, NETWORK, &keys[2]), &account, NETWORK).unwrap();
+        let pending = pool.get(&id).unwrap();
+        assert!(pending.ready);
+        assert!(account.check_approvals(&proposal, NETWORK, &pending.approvals).is_ok());
+
+        // Once the nonce is used the proposal is dropped
+        pool.prune(|_| Some(1));
+        assert!(pool.pending(&[9u8; 32]).is_empty());
+    }
+}
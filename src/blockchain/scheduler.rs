@@ -0,0 +1,179 @@
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, BTreeSet};
+use crate::blockchain::types::{hex_serde, Address};
+
+/// Most scheduled runs executed at the start of one block; the rest stay
+/// due and run in later blocks, oldest first
+pub const MAX_RUNS_PER_BLOCK: usize = 100;
+
+/// Identifier of the schedule registered by `owner` at `nonce`
+pub fn schedule_id(owner: &Address, nonce: u64) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"qmv:schedule:");
+    hasher.update(owner);
+    hasher.update(&nonce.to_le_bytes());
+    hasher.finalize().into()
+}
+
+/// Stands in for a transaction hash in the receipt of a scheduled run
+pub fn run_hash(id: &[u8; 32], height: u64) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"qmv:scheduled-run:");
+    hasher.update(id);
+    hasher.update(&height.to_le_bytes());
+    hasher.finalize().into()
+}
+
+/// What a scheduled run does, on behalf of the schedule's owner
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduledAction {
+    /// Pay from the owner's balance, e.g. a subscription
+    Transfer {
+        #[serde(with = "hex_serde")]
+        to: Address,
+        amount: u128,
+    },
+    /// Call a contract as the owner, e.g. to compound staking rewards
+    Call {
+        #[serde(with = "hex_serde")]
+        contract: Address,
+        #[serde(with = "hex_serde")]
+        input: Vec<u8>,
+        #[serde(default)]
+        value: u128,
+    },
+}
+
+/// Registered future execution and its prepaid gas deposit
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Schedule {
+    #[serde(with = "hex_serde")]
+    pub owner: Address,
+    pub action: ScheduledAction,
+    /// Height of the next run
+    pub next_height: u64,
+    /// Blocks between runs; `None` runs once
+    pub interval: Option<u64>,
+    /// Runs left, `None` for as long as the deposit lasts
+    pub remaining_runs: Option<u64>,
+    pub gas_limit: u64,
+    pub gas_price: u128,
+    /// Pays each run's gas; what is left returns to the owner at the end
+    pub deposit: u128,
+}
+
+impl Schedule {
+    /// Most a single run can take from the deposit
+    pub fn max_run_fee(&self) -> u128 {
+        self.gas_limit as u128 * self.gas_price
+    }
+
+    /// The schedule after a run at its current height, or `None` if that
+    /// was the last one
+    pub fn after_run(mut self) -> Option<Self> {
+        let interval = self.interval?;
+        if let Some(remaining) = self.remaining_runs.as_mut() {
+            *remaining = remaining.saturating_sub(1);
+            if *remaining == 0 {
+                return None;
+            }
+        }
+        self.next_height = self.next_height.saturating_add(interval);
+        Some(self)
+    }
+}
+
+/// Schedules by ID with a queue ordered by next run height
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Schedules {
+    schedules: BTreeMap<[u8; 32], Schedule>,
+    queue: BTreeSet<(u64, [u8; 32])>,
+}
+
+impl Schedules {
+    pub fn get(&self, id: &[u8; 32]) -> Option<&Schedule> {
+        self.schedules.get(id)
+    }
+
+    pub fn by_owner<'a>(&'a self, owner: &'a Address) -> impl Iterator<Item = ([u8; 32], &'a Schedule)> + 'a {
+        self.schedules.iter()
+            .filter(move |(_, schedule)| &schedule.owner == owner)
+            .map(|(id, schedule)| (*id, schedule))
+    }
+
+    /// IDs of schedules due at `height`, oldest first, at most `limit`
+    pub fn due(&self, height: u64, limit: usize) -> Vec<[u8; 32]> {
+        self.queue.iter()
+            .take_while(|(next_height, _)| *next_height <= height)
+            .take(limit)
+            .map(|(_, id)| *id)
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.schedules.len()
+    }
+
+    /// Native tokens held in deposits across all schedules
+    pub fn total_deposits(&self) -> u128 {
+        self.schedules.values().map(|schedule| schedule.deposit).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.schedules.is_empty()
+    }
+
+    /// Set a schedule to a journaled value; `None` removes it
+    pub(crate) fn restore(&mut self, id: [u8; 32], schedule: Option<Schedule>) {
+        if let Some(previous) = self.schedules.remove(&id) {
+            self.queue.remove(&(previous.next_height, id));
+        }
+        if let Some(schedule) = schedule {
+            self.queue.insert((schedule.next_height, id));
+            self.schedules.insert(id, schedule);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(next_height: u64, interval: Option<u64>, remaining_runs: Option<u64>) -> Schedule {
+        Schedule {
+            owner: [1u8; 32],
+            action: ScheduledAction::Transfer { to: [2u8; 32], amount: 5 },
+            next_height,
+            interval,
+            remaining_runs,
+            gas_limit: 30_000,
+            gas_price: 1,
+            deposit: 100_000,
+        }
+    }
+
+    #[test]
+    fn test_due_order_and_recurrence() {
+        let mut schedules = Schedules::default();
+        schedules.restore([3u8; 32], Some(schedule(12, None, None)));
+        schedules.restore([2u8; 32], Some(schedule(10, Some(5), Some(2))));
+        schedules.restore([1u8; 32], Some(schedule(20, None, None)));
+
+        assert!(schedules.due(9, 10).is_empty());
+        assert_eq!(schedules.due(12, 10), vec![[2u8; 32], [3u8; 32]]);
+        assert_eq!(schedules.due(12, 1), vec![[2u8; 32]]);
+
+        // Moving a schedule re-queues it under its new height
+        let next = schedules.get(&[2u8; 32]).cloned().unwrap().after_run().unwrap();
+        assert_eq!(next.next_height, 15);
+        schedules.restore([2u8; 32], Some(next.clone()));
+        assert_eq!(schedules.due(12, 10), vec![[3u8; 32]]);
+        assert!(next.after_run().is_none());
+        assert!(schedule(12, None, None).after_run().is_none());
+
+        schedules.restore([3u8; 32], None);
+        assert_eq!(schedules.len(), 2);
+        assert_eq!(schedules.by_owner(&[1u8; 32]).count(), 2);
+    }
+}
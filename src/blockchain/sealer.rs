@@ -0,0 +1,278 @@
+//! Block production timing, proposals and sealing.
+//!
+//! A producing node starts a block every `target_interval_ms`, or, in the
+//! instant-seal development mode, as soon as a transaction is pooled so
+//! local dApp and contract tests never wait on the clock. Proposing takes a
+//! bundle from the mempool, re-executes it on the latest state and builds
+//! the next block from the transactions that still apply; consensus decides
+//! the block and the node imports it like a block from any peer. Sealing
+//! does the same for a chain without validators, appending the block and
+//! committing the resulting state directly. With a `ChainStore` behind the
+//! chain, the state and the transactions still pooled are saved with every
+//! block.
+
+use serde::{Serialize, Deserialize};
+use std::time::Duration;
+use super::bloom::Bloom;
+use super::builder::BlockBuilder;
+use super::core::{Block, Blockchain};
+use super::execution::{Executor, Receipt};
+use super::mempool::Mempool;
+use super::state::{StateStore, WorldState};
+use super::transaction::{Transaction, TxHash};
+use crate::storage::reindex::block_transactions;
+
+/// Shortest block interval a config may ask for
+pub const MIN_BLOCK_INTERVAL_MS: u64 = 100;
+
+/// How often instant-seal mode looks for new transactions
+pub const INSTANT_SEAL_POLL_MS: u64 = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SealMode {
+    /// Follow the chain without producing blocks
+    Off,
+    /// Seal once per target interval
+    Interval,
+    /// Seal as soon as a transaction is pooled; for development only
+    Instant,
+}
+
+/// Block production settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BlockTimeConfig {
+    pub seal: SealMode,
+    /// Target time between blocks in `interval` mode
+    pub target_interval_ms: u64,
+    /// Seal blocks with no transactions when the interval passes
+    pub empty_blocks: bool,
+}
+
+impl Default for BlockTimeConfig {
+    fn default() -> Self {
+        Self { seal: SealMode::Off, target_interval_ms: 5_000, empty_blocks: false }
+    }
+}
+
+impl BlockTimeConfig {
+    /// How long the producer sleeps between checks
+    pub fn tick(&self) -> Duration {
+        match self.seal {
+            SealMode::Instant => Duration::from_millis(INSTANT_SEAL_POLL_MS),
+            _ => Duration::from_millis(self.target_interval_ms.max(MIN_BLOCK_INTERVAL_MS)),
+        }
+    }
+
+    /// Whether to seal with `pending` pooled transactions, `since_last`
+    /// after the previous block
+    pub fn due(&self, pending: usize, since_last: Duration) -> bool {
+        match self.seal {
+            SealMode::Off => false,
+            SealMode::Instant => pending > 0,
+            SealMode::Interval => {
+                since_last >= Duration::from_millis(self.target_interval_ms) && (pending > 0 || self.empty_blocks)
+            }
+        }
+    }
+}
+
+/// A block this node produced
+#[derive(Debug, Clone, Serialize)]
+pub struct SealedBlock {
+    pub index: u64,
+    #[serde(with = "crate::blockchain::types::hex_serde")]
+    pub hash: [u8; 32],
+    #[serde(with = "crate::blockchain::types::hex_serde_vec")]
+    pub transactions: Vec<TxHash>,
+    pub receipts: Vec<Receipt>,
+    pub bloom: Bloom,
+    /// Pooled transactions that no longer applied and were dropped
+    #[serde(with = "crate::blockchain::types::hex_serde_vec")]
+    pub dropped: Vec<TxHash>,
+}
+
+/// Seal the next block from `builder`'s bundle. The block's `data` holds
+/// the bincode-encoded transactions it executed. Transactions that fail
+/// against the state they would run on are left out and dropped from the
+/// pool rather than failing the whole block. A node decides its blocks
+/// through consensus instead (`propose_block`); sealing serves a chain
+/// with no other validators, such as in tests and tools.
+pub fn seal_block(
+    chain: &mut Blockchain,
+    store: &mut StateStore,
+    mempool: &mut Mempool,
+    builder: &BlockBuilder,
+) -> Result<SealedBlock, &'static str> {
+    if store.latest_height() + 1 != chain.height() {
+        return Err("Chain and state heights disagree");
+    }
+    let executed = execute_bundle(store, mempool, builder)?;
+    let data = bincode::serialize(&executed.transactions).map_err(|_| "Failed to encode block transactions")?;
+    chain.add_block_with_bloom(data, &executed.bloom)?;
+    let block = chain.block(chain.height() - 1).ok_or("Sealed block missing")?;
+    let (index, hash) = (block.index, block.hash);
+    store.commit(index, executed.state);
+    chain.save_state(store.latest())?;
+
+    let transactions: Vec<TxHash> = executed.transactions.iter().map(|tx| tx.hash()).collect();
+    let dropped: Vec<TxHash> = executed.dropped.iter().map(|tx| tx.hash()).collect();
+    for hash in transactions.iter().chain(&dropped) {
+        mempool.remove(hash);
+    }
+    save_pending(chain, mempool)?;
+    Ok(SealedBlock { index, hash, transactions, receipts: executed.receipts, bloom: executed.bloom, dropped })
+}
+
+/// Build the next block from `builder`'s bundle for consensus to decide
+/// on, leaving out transactions that no longer apply. Nothing is appended
+/// or committed: the decided block is imported and executed like a block
+/// from any other validator.
+pub fn propose_block(
+    chain: &mut Blockchain,
+    store: &StateStore,
+    mempool: &Mempool,
+    builder: &BlockBuilder,
+) -> Result<Block, &'static str> {
+    if store.latest_height() + 1 != chain.height() {
+        return Err("Chain and state heights disagree");
+    }
+    let executed = execute_bundle(store, mempool, builder)?;
+    let data = bincode::serialize(&executed.transactions).map_err(|_| "Failed to encode block transactions")?;
+    chain.next_block(data, &executed.bloom)
+}
+
+/// A bundle run on the latest state
+struct ExecutedBundle {
+    transactions: Vec<Transaction>,
+    /// Transactions that no longer applied
+    dropped: Vec<Transaction>,
+    receipts: Vec<Receipt>,
+    bloom: Bloom,
+    state: WorldState,
+}
+
+fn execute_bundle(store: &StateStore, mempool: &Mempool, builder: &BlockBuilder) -> Result<ExecutedBundle, &'static str> {
+    let bundle = builder.build(mempool, store.latest());
+
+    // Leases and schedules run first, so check each transaction after them
+    let mut scratch = store.latest().clone();
+    Executor::apply_block(&mut scratch, &[])?;
+    let (transactions, dropped): (Vec<_>, Vec<_>) = bundle.transactions.into_iter()
+        .partition(|tx| Executor::apply(&mut scratch, tx).is_ok());
+
+    let mut state = store.latest().clone();
+    let receipts = Executor::apply_block(&mut state, &transactions)?;
+    let bloom = Bloom::for_block(&transactions, &receipts);
+    Ok(ExecutedBundle { transactions, dropped, receipts, bloom, state })
+}
+
+/// Save the transactions still pooled with the chain, so they survive a restart
+pub fn save_pending(chain: &mut Blockchain, mempool: &Mempool) -> Result<(), &'static str> {
+    let pending = mempool.transactions().into_iter()
+        .map(bincode::serialize)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| "Failed to encode pending transactions")?;
+    chain.set_pending_transactions(pending)
+}
+
+/// Re-execute the chain's blocks after `state.height()`, for a node whose
+/// saved state is behind its stored blocks
+pub fn catch_up(chain: &Blockchain, mut state: WorldState) -> Result<WorldState, &'static str> {
+    for height in state.height() + 1..chain.height() {
+        let block = chain.block(height).ok_or("Stored block missing")?;
+        Executor::apply_block(&mut state, &block_transactions(block))?;
+        state.set_height(height);
+    }
+    Ok(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::builder::{BundleLimits, FairFifo};
+    use crate::blockchain::mempool::MempoolConfig;
+    use crate::blockchain::state::WorldState;
+    use crate::blockchain::transaction::{Transaction, TransactionAction};
+    use ed25519_dalek::SigningKey;
+
+    #[test]
+    fn test_seal_modes() {
+        let mut config = BlockTimeConfig::default();
+        assert!(!config.due(5, Duration::from_secs(60)));
+
+        config.seal = SealMode::Instant;
+        assert!(config.due(1, Duration::ZERO));
+        assert!(!config.due(0, Duration::from_secs(60)));
+        assert_eq!(config.tick(), Duration::from_millis(INSTANT_SEAL_POLL_MS));
+
+        config.seal = SealMode::Interval;
+        assert!(!config.due(1, Duration::from_millis(4_999)));
+        assert!(config.due(1, Duration::from_millis(5_000)));
+        assert!(!config.due(0, Duration::from_secs(60)));
+        config.empty_blocks = true;
+        assert!(config.due(0, Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn test_seal_block_executes_pooled_transactions() {
+        let key = SigningKey::from_bytes(&[3; 32]);
+        let sender = key.verifying_key().to_bytes();
+        let genesis = WorldState::with_balances(&[(sender, 1_000_000_000)]);
+        let mut store = StateStore::new(genesis.clone());
+        let mut chain = Blockchain::new(2);
+        let mut mempool = Mempool::new(MempoolConfig::default());
+        let transfer = |nonce| {
+            let mut tx = Transaction::new(sender, nonce, TransactionAction::Transfer { to: [8; 32], amount: 25 }, 100_000, 1);
+            tx.sign(&key);
+            tx
+        };
+        for nonce in 0..2 {
+            mempool.insert(transfer(nonce), store.latest()).unwrap();
+        }
+
+        let builder = BlockBuilder::new(&FairFifo, BundleLimits::default());
+        let sealed = seal_block(&mut chain, &mut store, &mut mempool, &builder).unwrap();
+        assert_eq!(sealed.index, 1);
+        assert_eq!(sealed.transactions.len(), 2);
+        assert!(sealed.dropped.is_empty());
+        assert!(mempool.is_empty());
+        assert_eq!(store.latest_height(), 1);
+        assert_eq!(store.account(&[8; 32]).balance, 50);
+        assert_eq!(chain.block(1).unwrap().hash, sealed.hash);
+        let carried: Vec<Transaction> = bincode::deserialize(&chain.block(1).unwrap().data).unwrap();
+        assert_eq!(carried.len(), 2);
+
+        // An empty bundle still seals when asked to
+        let empty = seal_block(&mut chain, &mut store, &mut mempool, &builder).unwrap();
+        assert_eq!((empty.index, empty.transactions.len()), (2, 0));
+
+        // A node whose saved state is behind re-executes the blocks it missed
+        assert_eq!(&catch_up(&chain, genesis).unwrap(), store.latest());
+    }
+
+    #[test]
+    fn test_proposed_block_waits_for_import() {
+        let key = SigningKey::from_bytes(&[4; 32]);
+        let sender = key.verifying_key().to_bytes();
+        let genesis = WorldState::with_balances(&[(sender, 1_000_000_000)]);
+        let store = StateStore::new(genesis.clone());
+        let mut chain = Blockchain::new(2);
+        let mut mempool = Mempool::new(MempoolConfig::default());
+        let mut tx = Transaction::new(sender, 0, TransactionAction::Transfer { to: [9; 32], amount: 40 }, 100_000, 1);
+        tx.sign(&key);
+        mempool.insert(tx, store.latest()).unwrap();
+
+        let builder = BlockBuilder::new(&FairFifo, BundleLimits::default());
+        let block = propose_block(&mut chain, &store, &mempool, &builder).unwrap();
+        assert_eq!(block.index, 1);
+        let carried: Vec<Transaction> = bincode::deserialize(&block.data).unwrap();
+        assert_eq!(carried.len(), 1);
+        // Nothing changes until the decided block is imported
+        assert_eq!((chain.height(), store.latest_height(), mempool.len()), (1, 0, 1));
+
+        chain.import_block(block).unwrap();
+        assert_eq!(catch_up(&chain, genesis).unwrap().account(&[9; 32]).balance, 40);
+    }
+}
@@ -0,0 +1,412 @@
+//! State snapshots for fast sync.
+//!
+//! A snapshot is the bincode encoding of the world state after one block,
+//! cut into `CHUNK_BYTES` chunks. Its manifest names the block, the state
+//! root and the BLAKE3 hash of every chunk. The state root is the BLAKE3 hash
+//! of that same encoding, so once every chunk is in, the root is checked by
+//! streaming through the chunks before anything is decoded.
+//!
+//! A fast-syncing node takes the newest manifest enough of its sync peers
+//! agree on and downloads chunks from all of them at once. Chunks are hashed
+//! on the background pool and written to the download directory as soon as
+//! they match; at most `max_in_flight` chunks are between request and disk at
+//! any time, which bounds the memory a download holds. The directory keeps
+//! the manifest and every verified chunk, so after a restart the stored
+//! chunks are hashed again and only the rest is fetched. The rebuilt state
+//! is handed to `SyncEngine::set_pivot`, and the blocks up to it are then
+//! imported without being executed.
+
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use super::state::WorldState;
+use super::types::{hex_serde, hex_serde_vec};
+
+/// Bytes per chunk; the last chunk holds the remainder
+pub const CHUNK_BYTES: usize = 1 << 20;
+
+/// Snapshots a serving node keeps, newest first, so a download that spans
+/// the next snapshot can still finish
+pub const KEPT_SNAPSHOTS: usize = 2;
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// Snapshot Manifest
+/// The block a snapshot follows, its state root and the hash of each chunk.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    pub height: u64,
+    #[serde(with = "hex_serde")]
+    pub block_hash: [u8; 32],
+    #[serde(with = "hex_serde")]
+    pub state_root: [u8; 32],
+    pub total_bytes: u64,
+    #[serde(with = "hex_serde_vec")]
+    pub chunks: Vec<[u8; 32]>,
+}
+
+impl SnapshotManifest {
+    /// Whether the chunk list covers `total_bytes` exactly
+    pub fn check(&self) -> Result<(), &'static str> {
+        let expected = (self.total_bytes as usize).div_ceil(CHUNK_BYTES);
+        if self.total_bytes == 0 || self.chunks.len() != expected {
+            return Err("Manifest chunk count does not match its size");
+        }
+        Ok(())
+    }
+
+    fn chunk_len(&self, index: usize) -> usize {
+        let start = index * CHUNK_BYTES;
+        (self.total_bytes as usize - start).min(CHUNK_BYTES)
+    }
+
+    /// Check one chunk against its hash
+    pub fn verify_chunk(&self, index: usize, data: &[u8]) -> Result<(), &'static str> {
+        let expected = self.chunks.get(index).ok_or("Chunk index out of range")?;
+        if data.len() != self.chunk_len(index) {
+            return Err("Chunk has the wrong length");
+        }
+        if blake3::hash(data).as_bytes() != expected {
+            return Err("Chunk hash mismatch");
+        }
+        Ok(())
+    }
+}
+
+/// Ask a peer for one chunk of the snapshot at `height`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkRequest {
+    pub height: u64,
+    pub index: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotChunk {
+    pub height: u64,
+    pub index: usize,
+    #[serde(with = "hex_serde")]
+    pub data: Vec<u8>,
+}
+
+/// A snapshot held in memory for serving
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub manifest: SnapshotManifest,
+    chunks: Vec<Vec<u8>>,
+}
+
+impl Snapshot {
+    /// Cut `state`, the state after the block hashed `block_hash`, into chunks
+    pub fn build(state: &WorldState, block_hash: [u8; 32]) -> Result<Self, &'static str> {
+        let encoded = bincode::serialize(state).map_err(|_| "Failed to encode state")?;
+        let chunks: Vec<Vec<u8>> = encoded.chunks(CHUNK_BYTES).map(<[u8]>::to_vec).collect();
+        let manifest = SnapshotManifest {
+            height: state.height(),
+            block_hash,
+            state_root: blake3::hash(&encoded).into(),
+            total_bytes: encoded.len() as u64,
+            chunks: chunks.iter().map(|chunk| blake3::hash(chunk).into()).collect(),
+        };
+        Ok(Self { manifest, chunks })
+    }
+
+    pub fn chunk(&self, request: &ChunkRequest) -> Option<SnapshotChunk> {
+        if request.height != self.manifest.height {
+            return None;
+        }
+        let data = self.chunks.get(request.index)?.clone();
+        Some(SnapshotChunk { height: request.height, index: request.index, data })
+    }
+}
+
+/// The manifest to download: the one stored from an earlier run if enough
+/// peers still offer it, otherwise the newest one offered by at least
+/// `min_peers`. Returns it with the peers offering it.
+pub fn choose_manifest(
+    offers: &[(String, Vec<SnapshotManifest>)],
+    min_peers: usize,
+    stored: Option<&SnapshotManifest>,
+) -> Option<(SnapshotManifest, Vec<String>)> {
+    let mut candidates: Vec<(&SnapshotManifest, Vec<String>)> = Vec::new();
+    for (peer, manifests) in offers {
+        for manifest in manifests.iter().filter(|manifest| manifest.check().is_ok()) {
+            match candidates.iter_mut().find(|(candidate, _)| *candidate == manifest) {
+                Some((_, peers)) if !peers.contains(peer) => peers.push(peer.clone()),
+                Some(_) => {}
+                None => candidates.push((manifest, vec![peer.clone()])),
+            }
+        }
+    }
+    candidates.retain(|(_, peers)| peers.len() >= min_peers.max(1));
+    let resumable = stored.and_then(|stored| candidates.iter().position(|(candidate, _)| *candidate == stored));
+    let chosen = match resumable {
+        Some(position) => position,
+        None => (0..candidates.len()).max_by_key(|&i| candidates[i].0.height)?,
+    };
+    let (manifest, peers) = candidates.swap_remove(chosen);
+    Some((manifest.clone(), peers))
+}
+
+/// Progress of a snapshot download, as `getSnapshotStatus` reports it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SnapshotProgress {
+    pub height: u64,
+    pub chunks: usize,
+    pub verified: usize,
+    pub in_flight: usize,
+    pub bytes_verified: u64,
+    pub total_bytes: u64,
+}
+
+/// Snapshot Download
+/// Tracks which chunks are verified on disk and which are requested of whom.
+#[derive(Debug)]
+pub struct SnapshotDownload {
+    dir: PathBuf,
+    manifest: Arc<SnapshotManifest>,
+    verified: BTreeSet<usize>,
+    /// Peer each outstanding chunk was asked of
+    in_flight: BTreeMap<usize, String>,
+    max_in_flight: usize,
+}
+
+impl SnapshotDownload {
+    /// Download `manifest` into `dir`. Chunks a previous download of the same
+    /// manifest left there are returned; they count once `check_stored_chunk`
+    /// passes and `on_verified` is called. Anything else in `dir` is cleared.
+    pub fn open(dir: &Path, manifest: SnapshotManifest, max_in_flight: usize) -> Result<(Self, Vec<usize>), String> {
+        manifest.check()?;
+        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        let resumed = stored_manifest(dir).as_ref() == Some(&manifest);
+        let mut stored = Vec::new();
+        let entries = std::fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+        for entry in entries.flatten() {
+            let index = entry.file_name().to_str()
+                .and_then(|name| name.strip_prefix("chunk-")?.strip_suffix(".bin")?.parse::<usize>().ok());
+            match index {
+                Some(index) if resumed && index < manifest.chunks.len() => stored.push(index),
+                _ if entry.file_name() == MANIFEST_FILE => {}
+                _ => {
+                    let _ = std::fs::remove_file(entry.path());
+                }
+            }
+        }
+        if !resumed {
+            let encoded = serde_json::to_vec_pretty(&manifest).map_err(|e| e.to_string())?;
+            write_file(&dir.join(MANIFEST_FILE), &encoded)?;
+        }
+        stored.sort_unstable();
+        let download = Self {
+            dir: dir.to_path_buf(),
+            manifest: Arc::new(manifest),
+            verified: BTreeSet::new(),
+            in_flight: BTreeMap::new(),
+            max_in_flight: max_in_flight.max(1),
+        };
+        Ok((download, stored))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn manifest(&self) -> Arc<SnapshotManifest> {
+        self.manifest.clone()
+    }
+
+    /// The next chunk to ask `peer` for; `None` while the in-flight limit is
+    /// reached or every missing chunk is already requested
+    pub fn next_request(&mut self, peer: &str) -> Option<ChunkRequest> {
+        if self.in_flight.len() >= self.max_in_flight {
+            return None;
+        }
+        let index = (0..self.manifest.chunks.len())
+            .find(|index| !self.verified.contains(index) && !self.in_flight.contains_key(index))?;
+        self.in_flight.insert(index, peer.to_string());
+        Some(ChunkRequest { height: self.manifest.height, index })
+    }
+
+    /// Record a chunk stored by `store_chunk` or checked by `check_stored_chunk`
+    pub fn on_verified(&mut self, index: usize) {
+        self.in_flight.remove(&index);
+        if index < self.manifest.chunks.len() {
+            self.verified.insert(index);
+        }
+    }
+
+    /// Release a chunk whose download or check failed, for another peer to fetch
+    pub fn on_failed(&mut self, index: usize) {
+        self.in_flight.remove(&index);
+    }
+
+    /// Release every chunk asked of a peer that went away
+    pub fn remove_peer(&mut self, peer: &str) {
+        self.in_flight.retain(|_, asked| asked != peer);
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.verified.len() == self.manifest.chunks.len()
+    }
+
+    pub fn progress(&self) -> SnapshotProgress {
+        SnapshotProgress {
+            height: self.manifest.height,
+            chunks: self.manifest.chunks.len(),
+            verified: self.verified.len(),
+            in_flight: self.in_flight.len(),
+            bytes_verified: self.verified.iter().map(|&index| self.manifest.chunk_len(index) as u64).sum(),
+            total_bytes: self.manifest.total_bytes,
+        }
+    }
+}
+
+/// Manifest of a download left in `dir`, if any
+pub fn stored_manifest(dir: &Path) -> Option<SnapshotManifest> {
+    let raw = std::fs::read(dir.join(MANIFEST_FILE)).ok()?;
+    serde_json::from_slice(&raw).ok()
+}
+
+fn chunk_path(dir: &Path, index: usize) -> PathBuf {
+    dir.join(format!("chunk-{}.bin", index))
+}
+
+fn write_file(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, bytes)
+        .and_then(|_| std::fs::rename(&tmp, path))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Verify a downloaded chunk and write it to the download directory.
+/// CPU-bound; run it on the background pool.
+pub fn store_chunk(dir: &Path, manifest: &SnapshotManifest, chunk: &SnapshotChunk) -> Result<(), String> {
+    if chunk.height != manifest.height {
+        return Err("Chunk is from another snapshot".to_string());
+    }
+    manifest.verify_chunk(chunk.index, &chunk.data)?;
+    write_file(&chunk_path(dir, chunk.index), &chunk.data)
+}
+
+/// Verify a chunk left by an earlier run, deleting it if it does not match
+pub fn check_stored_chunk(dir: &Path, manifest: &SnapshotManifest, index: usize) -> Result<(), String> {
+    let path = chunk_path(dir, index);
+    let data = std::fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    manifest.verify_chunk(index, &data).map_err(|e| {
+        let _ = std::fs::remove_file(&path);
+        e.to_string()
+    })
+}
+
+/// Rebuild the state from a complete download: the chunks are hashed in
+/// order against the state root, then decoded.
+/// CPU-bound; run it on the background pool.
+pub fn rebuild(dir: &Path, manifest: &SnapshotManifest) -> Result<WorldState, String> {
+    manifest.check()?;
+    let mut hasher = blake3::Hasher::new();
+    let mut encoded = Vec::with_capacity(manifest.total_bytes as usize);
+    for index in 0..manifest.chunks.len() {
+        let path = chunk_path(dir, index);
+        let data = std::fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        manifest.verify_chunk(index, &data)?;
+        hasher.update(&data);
+        encoded.extend_from_slice(&data);
+    }
+    if hasher.finalize().as_bytes() != &manifest.state_root {
+        return Err("Snapshot does not match its state root".to_string());
+    }
+    let state: WorldState = bincode::deserialize(&encoded).map_err(|e| format!("Undecodable snapshot: {}", e))?;
+    if state.height() != manifest.height {
+        return Err("Snapshot state is at another height".to_string());
+    }
+    Ok(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::types::Address;
+
+    fn large_state() -> WorldState {
+        // Enough accounts for the encoding to span several chunks
+        let balances: Vec<(Address, u128)> = (0..60_000u32)
+            .map(|i| {
+                let mut address: Address = [0u8; 32];
+                address[..4].copy_from_slice(&i.to_le_bytes());
+                (address, u128::from(i) + 1)
+            })
+            .collect();
+        let mut state = WorldState::with_balances(&balances);
+        state.set_height(42);
+        state
+    }
+
+    #[test]
+    fn test_chunked_download_resumes_and_rebuilds() {
+        let dir = std::env::temp_dir().join(format!("snapshot-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let state = large_state();
+        let snapshot = Snapshot::build(&state, [9u8; 32]).unwrap();
+        let manifest = snapshot.manifest.clone();
+        assert!(manifest.chunks.len() > 2);
+        assert_eq!(manifest.state_root, state.state_root());
+
+        let (mut download, stored) = SnapshotDownload::open(&dir, manifest.clone(), 2).unwrap();
+        assert!(stored.is_empty());
+
+        // The in-flight limit holds back a third request until a chunk lands
+        let first = download.next_request("a").unwrap();
+        let second = download.next_request("b").unwrap();
+        assert_eq!(download.next_request("a"), None);
+
+        let mut forged = snapshot.chunk(&first).unwrap();
+        forged.data[0] ^= 1;
+        assert!(store_chunk(&dir, &manifest, &forged).is_err());
+        download.on_failed(first.index);
+        store_chunk(&dir, &manifest, &snapshot.chunk(&second).unwrap()).unwrap();
+        download.on_verified(second.index);
+        assert_eq!(download.progress().verified, 1);
+
+        // After a restart the stored chunk is found again and the rest fetched
+        drop(download);
+        let (mut download, stored) = SnapshotDownload::open(&dir, manifest.clone(), 2).unwrap();
+        assert_eq!(stored, vec![second.index]);
+        for index in stored {
+            check_stored_chunk(&dir, &manifest, index).unwrap();
+            download.on_verified(index);
+        }
+        while let Some(request) = download.next_request("a") {
+            store_chunk(&dir, &manifest, &snapshot.chunk(&request).unwrap()).unwrap();
+            download.on_verified(request.index);
+        }
+        assert!(download.is_complete());
+        assert_eq!(download.progress().bytes_verified, manifest.total_bytes);
+
+        let rebuilt = rebuild(&dir, &manifest).unwrap();
+        assert_eq!(rebuilt, state);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_manifest_needs_agreeing_peers() {
+        let old = Snapshot::build(&WorldState::new(), [1u8; 32]).unwrap().manifest;
+        let mut state = WorldState::new();
+        state.set_height(10);
+        let new = Snapshot::build(&state, [2u8; 32]).unwrap().manifest;
+        let mut forged = new.clone();
+        forged.state_root = [0u8; 32];
+
+        let offers = vec![
+            ("a".to_string(), vec![new.clone(), old.clone()]),
+            ("b".to_string(), vec![forged.clone(), old.clone()]),
+            ("c".to_string(), vec![new.clone()]),
+        ];
+        let (chosen, peers) = choose_manifest(&offers, 2, None).unwrap();
+        assert_eq!(chosen, new);
+        assert_eq!(peers, vec!["a", "c"]);
+
+        // A download already under way continues while enough peers serve it
+        assert_eq!(choose_manifest(&offers, 2, Some(&old)).unwrap().0, old);
+        assert_eq!(choose_manifest(&offers, 3, None), None);
+    }
+}
@@ -0,0 +1,170 @@
+//! Fee sponsorship.
+//!
+//! A sponsor pays the fee of another account's transaction, so apps can
+//! onboard users who hold no tokens yet. The sender names the sponsor in the
+//! payload it signs, so a relayer can neither strip the sponsor nor swap in
+//! another one. The sponsor then countersigns that payload and an expiry
+//! height. The countersignature is bound to the sender's nonce like the
+//! sender's own signature, so it pays for exactly one transaction.
+//!
+//! Sponsors opt in with a `SponsorPolicy` that caps the fees they pay per
+//! window of blocks, in total and for any one sender. A sponsored
+//! transaction that would exceed either cap is invalid.
+
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use crate::blockchain::types::{hex_serde, Address};
+
+/// Longest policy window, in blocks
+pub const MAX_WINDOW_BLOCKS: u64 = 1_000_000;
+
+/// Sponsor's countersignature on a transaction
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sponsorship {
+    /// Account that pays the fee
+    #[serde(with = "hex_serde")]
+    pub sponsor: Address,
+    /// Last height the transaction can be included at
+    pub valid_until: u64,
+    #[serde(with = "hex_serde", default)]
+    pub signature: Vec<u8>,
+}
+
+/// Fees a sponsor agrees to pay per window of blocks
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SponsorPolicy {
+    /// Most fees paid for all senders in one window
+    pub limit: u128,
+    /// Most fees paid for one sender in one window
+    pub sender_limit: u128,
+    pub window_blocks: u64,
+    /// First height of the current window
+    window_start: u64,
+    spent: u128,
+    spent_by_sender: BTreeMap<Address, u128>,
+}
+
+impl SponsorPolicy {
+    /// Policy whose first window starts at `height`
+    pub fn new(limit: u128, sender_limit: u128, window_blocks: u64, height: u64) -> Result<Self, &'static str> {
+        if limit == 0 || sender_limit == 0 {
+            return Err("Sponsor limits must be positive");
+        }
+        if sender_limit > limit {
+            return Err("Per-sender limit cannot exceed the total limit");
+        }
+        if window_blocks == 0 || window_blocks > MAX_WINDOW_BLOCKS {
+            return Err("Window must be between 1 and 1,000,000 blocks");
+        }
+        Ok(Self { limit, sender_limit, window_blocks, window_start: height, spent: 0, spent_by_sender: BTreeMap::new() })
+    }
+
+    fn window_ended(&self, height: u64) -> bool {
+        height >= self.window_start.saturating_add(self.window_blocks)
+    }
+
+    /// Fees paid in the window containing `height`
+    pub fn spent(&self, height: u64) -> u128 {
+        if self.window_ended(height) { 0 } else { self.spent }
+    }
+
+    /// Fees paid for `sender` in the window containing `height`
+    pub fn spent_for(&self, sender: &Address, height: u64) -> u128 {
+        if self.window_ended(height) {
+            return 0;
+        }
+        self.spent_by_sender.get(sender).copied().unwrap_or(0)
+    }
+
+    /// Whether the sponsor would pay up to `fee` for `sender` at `height`
+    pub fn check(&self, sender: &Address, fee: u128, height: u64) -> Result<(), &'static str> {
+        if self.spent(height).saturating_add(fee) > self.limit {
+            return Err("Sponsor spending limit reached");
+        }
+        if self.spent_for(sender, height).saturating_add(fee) > self.sender_limit {
+            return Err("Sponsor spending limit for this sender reached");
+        }
+        Ok(())
+    }
+
+    /// Record `fee` paid for `sender` at `height`, starting a new window if
+    /// the current one has ended
+    pub fn charge(&mut self, sender: &Address, fee: u128, height: u64) {
+        if self.window_ended(height) {
+            let elapsed = height - self.window_start;
+            self.window_start += elapsed - elapsed % self.window_blocks;
+            self.spent = 0;
+            self.spent_by_sender.clear();
+        }
+        self.spent = self.spent.saturating_add(fee);
+        let spent = self.spent_by_sender.entry(*sender).or_insert(0);
+        *spent = spent.saturating_add(fee);
+    }
+}
+
+/// Sponsor policy operations
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SponsorAction {
+    /// Start sponsoring, or replace the policy with one starting a fresh window
+    SetPolicy { limit: u128, sender_limit: u128, window_blocks: u64 },
+    /// Stop sponsoring; countersigned transactions not yet included become invalid
+    Revoke,
+}
+
+/// Sponsor policies by sponsor
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Sponsors {
+    policies: BTreeMap<Address, SponsorPolicy>,
+}
+
+impl Sponsors {
+    pub fn get(&self, sponsor: &Address) -> Option<&SponsorPolicy> {
+        self.policies.get(sponsor)
+    }
+
+    pub fn len(&self) -> usize {
+        self.policies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.policies.is_empty()
+    }
+
+    /// Set a policy to a journaled value; `None` removes it
+    pub(crate) fn restore(&mut self, sponsor: Address, policy: Option<SponsorPolicy>) {
+        match policy {
+            Some(policy) => { self.policies.insert(sponsor, policy); }
+            None => { self.policies.remove(&sponsor); }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limits_reset_each_window() {
+        assert!(SponsorPolicy::new(100, 200, 10, 0).is_err());
+        assert!(SponsorPolicy::new(100, 50, 0, 0).is_err());
+
+        let mut policy = SponsorPolicy::new(100, 60, 10, 5).unwrap();
+        let (alice, bob) = ([1u8; 32], [2u8; 32]);
+        policy.check(&alice, 60, 6).unwrap();
+        assert_eq!(policy.check(&alice, 61, 6), Err("Sponsor spending limit for this sender reached"));
+
+        policy.charge(&alice, 60, 6);
+        policy.charge(&bob, 30, 7);
+        assert_eq!(policy.check(&bob, 20, 14), Err("Sponsor spending limit reached"));
+        policy.check(&bob, 10, 14).unwrap();
+
+        // Heights 15 to 24 are a new window
+        assert_eq!(policy.spent(15), 0);
+        policy.check(&alice, 60, 15).unwrap();
+        policy.charge(&alice, 10, 23);
+        assert_eq!(policy.spent(24), 10);
+        assert_eq!(policy.spent_for(&bob, 24), 0);
+        assert_eq!(policy.spent(25), 0);
+    }
+}
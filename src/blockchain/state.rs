@@ -0,0 +1,813 @@
+use serde::{Serialize, Deserialize};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use crate::blockchain::execution::Instruction;
+use crate::blockchain::types::{hex_serde, hex_serde_option, Address};
+use crate::blockchain::account_validation::AccountValidations;
+use crate::blockchain::assets::Assets;
+use crate::blockchain::channel::Channels;
+use crate::blockchain::confidential::ConfidentialAccounts;
+use crate::blockchain::features::FeatureSet;
+use crate::blockchain::htlc::HtlcBook;
+use crate::blockchain::lease::Leases;
+use crate::blockchain::market::Listings;
+use crate::blockchain::multisig::MultisigAccount;
+use crate::blockchain::scheduler::Schedules;
+use crate::blockchain::sponsor::Sponsors;
+use crate::blockchain::validator_keys::ValidatorKeys;
+use crate::storage::cache::{CacheStats, ReadCache};
+
+const ACCOUNT_CACHE_ENTRIES: usize = 100_000;
+const STORAGE_CACHE_ENTRIES: usize = 100_000;
+
+/// Externally owned account
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Account {
+    pub balance: u128,
+    pub nonce: u64,
+}
+
+/// Deployed contract code and storage
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContractAccount {
+    pub owner: Address,
+    pub code: Vec<Instruction>,
+    pub storage: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+/// Account and contract state at a given block.
+/// BTreeMaps keep iteration order, and therefore state roots, deterministic.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WorldState {
+    accounts: BTreeMap<Address, Account>,
+    contracts: BTreeMap<Address, ContractAccount>,
+    /// Consensus keys of validators over time
+    #[serde(default)]
+    validator_keys: ValidatorKeys,
+    /// m-of-n accounts by address
+    #[serde(default)]
+    multisigs: BTreeMap<Address, MultisigAccount>,
+    /// Registered future and recurring executions
+    #[serde(default)]
+    schedules: Schedules,
+    /// NFTs and parcels
+    #[serde(default)]
+    assets: Assets,
+    /// Marketplace listings by asset
+    #[serde(default)]
+    listings: Listings,
+    /// Lease offers and running leases by asset
+    #[serde(default)]
+    leases: Leases,
+    /// Height of the last block applied to this state
+    #[serde(default)]
+    height: u64,
+    /// Hash of the last block applied; seeds contract noise in the next block
+    #[serde(default)]
+    parent_hash: [u8; 32],
+    /// Signaled features active for the next block
+    #[serde(default)]
+    features: FeatureSet,
+    /// Shielded balances of the confidential asset class
+    #[serde(default)]
+    confidential: ConfidentialAccounts,
+    /// Payment channels by ID
+    #[serde(default)]
+    channels: Channels,
+    /// Hash-time-locked contracts by ID
+    #[serde(default)]
+    htlcs: HtlcBook,
+    /// Fee sponsor policies by sponsor
+    #[serde(default)]
+    sponsors: Sponsors,
+    /// Validation contracts by account
+    #[serde(default)]
+    validations: AccountValidations,
+}
+
+impl WorldState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build a genesis state from initial balances
+    pub fn with_balances(balances: &[(Address, u128)]) -> Self {
+        let mut state = Self::new();
+        for (address, balance) in balances {
+            state.account_mut(address).balance = *balance;
+        }
+        state
+    }
+
+    pub fn account(&self, address: &Address) -> Account {
+        self.accounts.get(address).cloned().unwrap_or_default()
+    }
+
+    pub fn account_mut(&mut self, address: &Address) -> &mut Account {
+        self.accounts.entry(*address).or_default()
+    }
+
+    pub fn accounts(&self) -> &BTreeMap<Address, Account> {
+        &self.accounts
+    }
+
+    pub fn contract(&self, address: &Address) -> Option<&ContractAccount> {
+        self.contracts.get(address)
+    }
+
+    pub fn contract_mut(&mut self, address: &Address) -> Option<&mut ContractAccount> {
+        self.contracts.get_mut(address)
+    }
+
+    pub fn contracts(&self) -> &BTreeMap<Address, ContractAccount> {
+        &self.contracts
+    }
+
+    pub fn insert_contract(&mut self, address: Address, contract: ContractAccount) {
+        self.contracts.insert(address, contract);
+    }
+
+    pub fn validator_keys(&self) -> &ValidatorKeys {
+        &self.validator_keys
+    }
+
+    pub fn validator_keys_mut(&mut self) -> &mut ValidatorKeys {
+        &mut self.validator_keys
+    }
+
+    pub fn multisig(&self, address: &Address) -> Option<&MultisigAccount> {
+        self.multisigs.get(address)
+    }
+
+    pub(crate) fn multisig_mut(&mut self, address: &Address) -> Option<&mut MultisigAccount> {
+        self.multisigs.get_mut(address)
+    }
+
+    /// Put a multisig account back to a journaled value; `None` removes it
+    pub(crate) fn restore_multisig(&mut self, address: Address, account: Option<MultisigAccount>) {
+        match account {
+            Some(account) => { self.multisigs.insert(address, account); }
+            None => { self.multisigs.remove(&address); }
+        }
+    }
+
+    pub fn schedules(&self) -> &Schedules {
+        &self.schedules
+    }
+
+    pub(crate) fn schedules_mut(&mut self) -> &mut Schedules {
+        &mut self.schedules
+    }
+
+    pub fn assets(&self) -> &Assets {
+        &self.assets
+    }
+
+    pub(crate) fn assets_mut(&mut self) -> &mut Assets {
+        &mut self.assets
+    }
+
+    pub fn listings(&self) -> &Listings {
+        &self.listings
+    }
+
+    pub(crate) fn listings_mut(&mut self) -> &mut Listings {
+        &mut self.listings
+    }
+
+    pub fn leases(&self) -> &Leases {
+        &self.leases
+    }
+
+    pub(crate) fn leases_mut(&mut self) -> &mut Leases {
+        &mut self.leases
+    }
+
+    pub fn confidential(&self) -> &ConfidentialAccounts {
+        &self.confidential
+    }
+
+    pub(crate) fn confidential_mut(&mut self) -> &mut ConfidentialAccounts {
+        &mut self.confidential
+    }
+
+    pub fn channels(&self) -> &Channels {
+        &self.channels
+    }
+
+    pub(crate) fn channels_mut(&mut self) -> &mut Channels {
+        &mut self.channels
+    }
+
+    pub fn htlcs(&self) -> &HtlcBook {
+        &self.htlcs
+    }
+
+    pub(crate) fn htlcs_mut(&mut self) -> &mut HtlcBook {
+        &mut self.htlcs
+    }
+
+    pub fn sponsors(&self) -> &Sponsors {
+        &self.sponsors
+    }
+
+    pub(crate) fn sponsors_mut(&mut self) -> &mut Sponsors {
+        &mut self.sponsors
+    }
+
+    pub fn validations(&self) -> &AccountValidations {
+        &self.validations
+    }
+
+    pub(crate) fn validations_mut(&mut self) -> &mut AccountValidations {
+        &mut self.validations
+    }
+
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    pub fn set_height(&mut self, height: u64) {
+        self.height = height;
+    }
+
+    pub fn parent_hash(&self) -> &[u8; 32] {
+        &self.parent_hash
+    }
+
+    /// Set the hash of the block the next one builds on
+    pub fn set_parent_hash(&mut self, hash: [u8; 32]) {
+        self.parent_hash = hash;
+    }
+
+    pub fn features(&self) -> FeatureSet {
+        self.features
+    }
+
+    /// Set the features active for the next block, from
+    /// `FeatureTracker::active_at` for its height
+    pub fn set_features(&mut self, features: FeatureSet) {
+        self.features = features;
+    }
+
+    /// Put an account back to a journaled value; `None` removes it
+    pub(crate) fn restore_account(&mut self, address: Address, account: Option<Account>) {
+        match account {
+            Some(account) => { self.accounts.insert(address, account); }
+            None => { self.accounts.remove(&address); }
+        }
+    }
+
+    pub(crate) fn remove_contract(&mut self, address: &Address) {
+        self.contracts.remove(address);
+    }
+
+    /// Put a contract back to a journaled value; `None` removes it
+    pub(crate) fn restore_contract(&mut self, address: Address, contract: Option<ContractAccount>) {
+        match contract {
+            Some(contract) => { self.contracts.insert(address, contract); }
+            None => { self.contracts.remove(&address); }
+        }
+    }
+
+    /// Move `amount` between accounts
+    pub fn transfer(&mut self, from: &Address, to: &Address, amount: u128) -> Result<(), &'static str> {
+        let sender = self.account_mut(from);
+        sender.balance = sender.balance.checked_sub(amount).ok_or("Insufficient balance")?;
+        let recipient = self.account_mut(to);
+        recipient.balance = recipient.balance.checked_add(amount).ok_or("Balance overflow")?;
+        Ok(())
+    }
+
+    /// Native tokens in existence: account balances plus schedule deposits
+    pub fn native_supply(&self) -> u128 {
+        self.accounts.values().map(|account| account.balance).sum::<u128>() + self.schedules.total_deposits()
+    }
+
+    /// Commitment to the full state
+    pub fn state_root(&self) -> [u8; 32] {
+        let encoded = bincode::serialize(self).unwrap_or_default();
+        blake3::hash(&encoded).into()
+    }
+}
+
+/// Prior values of everything one block changed, so the state before the
+/// block can be rebuilt from the state after it
+#[derive(Debug, Clone, Default)]
+struct StateDelta {
+    accounts: BTreeMap<Address, Option<Account>>,
+    /// Contracts created, removed or given new code, as they were before
+    contracts: BTreeMap<Address, Option<ContractAccount>>,
+    /// Storage slots of contracts that otherwise stayed in place
+    storage: BTreeMap<(Address, Vec<u8>), Option<Vec<u8>>>,
+    validator_keys: Option<ValidatorKeys>,
+    multisigs: Option<BTreeMap<Address, MultisigAccount>>,
+    schedules: Option<Schedules>,
+    assets: Option<Assets>,
+    listings: Option<Listings>,
+    leases: Option<Leases>,
+    features: Option<FeatureSet>,
+    confidential: Option<ConfidentialAccounts>,
+    channels: Option<Channels>,
+    htlcs: Option<HtlcBook>,
+    sponsors: Option<Sponsors>,
+    validations: Option<AccountValidations>,
+    height: u64,
+    parent_hash: [u8; 32],
+}
+
+/// `before` if it differs from `after`
+fn prior<T: Clone + PartialEq>(before: &T, after: &T) -> Option<T> {
+    (before != after).then(|| before.clone())
+}
+
+impl StateDelta {
+    fn between(before: &WorldState, after: &WorldState) -> Self {
+        let mut delta = Self {
+            validator_keys: prior(&before.validator_keys, &after.validator_keys),
+            multisigs: prior(&before.multisigs, &after.multisigs),
+            schedules: prior(&before.schedules, &after.schedules),
+            assets: prior(&before.assets, &after.assets),
+            listings: prior(&before.listings, &after.listings),
+            leases: prior(&before.leases, &after.leases),
+            features: prior(&before.features, &after.features),
+            confidential: prior(&before.confidential, &after.confidential),
+            channels: prior(&before.channels, &after.channels),
+            htlcs: prior(&before.htlcs, &after.htlcs),
+            sponsors: prior(&before.sponsors, &after.sponsors),
+            validations: prior(&before.validations, &after.validations),
+            height: before.height,
+            parent_hash: before.parent_hash,
+            ..Self::default()
+        };
+
+        let addresses: BTreeSet<&Address> = before.accounts.keys().chain(after.accounts.keys()).collect();
+        for address in addresses {
+            let old = before.accounts.get(address);
+            if old != after.accounts.get(address) {
+                delta.accounts.insert(*address, old.cloned());
+            }
+        }
+
+        let addresses: BTreeSet<&Address> = before.contracts.keys().chain(after.contracts.keys()).collect();
+        for address in addresses {
+            match (before.contracts.get(address), after.contracts.get(address)) {
+                (Some(old), Some(new)) if old.owner == new.owner && old.code == new.code => {
+                    let keys: BTreeSet<&Vec<u8>> = old.storage.keys().chain(new.storage.keys()).collect();
+                    for key in keys {
+                        let value = old.storage.get(key);
+                        if value != new.storage.get(key) {
+                            delta.storage.insert((*address, key.clone()), value.cloned());
+                        }
+                    }
+                }
+                (old, new) if old != new => {
+                    delta.contracts.insert(*address, old.cloned());
+                }
+                _ => {}
+            }
+        }
+
+        delta
+    }
+
+    /// Turn the state after the block back into the state before it
+    fn revert(&self, state: &mut WorldState) {
+        for (address, account) in &self.accounts {
+            state.restore_account(*address, account.clone());
+        }
+        for (address, contract) in &self.contracts {
+            match contract {
+                Some(contract) => state.insert_contract(*address, contract.clone()),
+                None => state.remove_contract(address),
+            }
+        }
+        for ((address, key), value) in &self.storage {
+            if let Some(contract) = state.contract_mut(address) {
+                match value {
+                    Some(value) => { contract.storage.insert(key.clone(), value.clone()); }
+                    None => { contract.storage.remove(key); }
+                }
+            }
+        }
+        if let Some(keys) = &self.validator_keys { state.validator_keys = keys.clone(); }
+        if let Some(multisigs) = &self.multisigs { state.multisigs = multisigs.clone(); }
+        if let Some(schedules) = &self.schedules { state.schedules = schedules.clone(); }
+        if let Some(assets) = &self.assets { state.assets = assets.clone(); }
+        if let Some(listings) = &self.listings { state.listings = listings.clone(); }
+        if let Some(leases) = &self.leases { state.leases = leases.clone(); }
+        if let Some(features) = self.features { state.features = features; }
+        if let Some(confidential) = &self.confidential { state.confidential = confidential.clone(); }
+        if let Some(channels) = &self.channels { state.channels = channels.clone(); }
+        if let Some(htlcs) = &self.htlcs { state.htlcs = htlcs.clone(); }
+        if let Some(sponsors) = &self.sponsors { state.sponsors = sponsors.clone(); }
+        if let Some(validations) = &self.validations { state.validations = validations.clone(); }
+        state.height = self.height;
+        state.parent_hash = self.parent_hash;
+    }
+}
+
+/// Committed world state per block height.
+/// Only the latest state is held in full. Each block keeps the prior values
+/// of what it changed, so any retained height can be rebuilt by undoing the
+/// blocks after it. Reads of the latest state go through caches that are
+/// invalidated for the keys each committed block touches.
+pub struct StateStore {
+    latest: WorldState,
+    height: u64,
+    /// Undo record of each committed block, by height
+    history: BTreeMap<u64, StateDelta>,
+    /// Blocks of history kept behind the head; `None` keeps all of it
+    retain_blocks: Option<u64>,
+    /// Lowest height whose state can still be rebuilt
+    earliest: u64,
+    account_cache: ReadCache<Address, Account>,
+    storage_cache: ReadCache<(Address, Vec<u8>), Option<Vec<u8>>>,
+}
+
+impl StateStore {
+    pub fn new(genesis: WorldState) -> Self {
+        Self {
+            latest: genesis,
+            height: 0,
+            history: BTreeMap::new(),
+            retain_blocks: None,
+            earliest: 0,
+            account_cache: ReadCache::new("accounts", ACCOUNT_CACHE_ENTRIES),
+            storage_cache: ReadCache::new("contract_storage", STORAGE_CACHE_ENTRIES),
+        }
+    }
+
+    /// Start from a state persisted at `state.height()`, e.g. by a
+    /// `ChainStore`. History before it cannot be rebuilt.
+    pub fn resume(state: WorldState) -> Self {
+        let height = state.height();
+        let mut store = Self::new(state);
+        store.height = height;
+        store.earliest = height;
+        store
+    }
+
+    /// Replace the state with one restored from a snapshot, dropping all
+    /// history; the retention setting is kept
+    pub fn restore(&mut self, state: WorldState) {
+        let retain_blocks = self.retain_blocks;
+        *self = Self::resume(state);
+        self.retain_blocks = retain_blocks;
+    }
+
+    /// Keep only the last `retain_blocks` blocks of history (pruned nodes);
+    /// `None` keeps everything (archive nodes)
+    pub fn set_retention(&mut self, retain_blocks: Option<u64>) {
+        self.retain_blocks = retain_blocks;
+        self.prune();
+    }
+
+    /// Record the state produced by the block at `height`
+    pub fn commit(&mut self, height: u64, mut state: WorldState) {
+        state.set_height(height);
+        if height > self.height {
+            let delta = StateDelta::between(&self.latest, &state);
+            self.account_cache.invalidate_many(delta.accounts.keys());
+            for (address, key) in delta.storage.keys() {
+                self.storage_cache.invalidate(&(*address, key.clone()));
+            }
+            for address in delta.contracts.keys() {
+                let versions = [self.latest.contract(address), state.contract(address)];
+                for contract in versions.into_iter().flatten() {
+                    for key in contract.storage.keys() {
+                        self.storage_cache.invalidate(&(*address, key.clone()));
+                    }
+                }
+            }
+            self.history.insert(height, delta);
+        } else {
+            // Rewriting history (reorg): undo back to the parent of `height`
+            // and drop everything after it; cached reads may no longer be latest
+            let parent = height.checked_sub(1).filter(|parent| *parent >= self.earliest);
+            match parent {
+                Some(parent) => {
+                    let mut rewound = self.latest.clone();
+                    for delta in self.history.split_off(&(parent + 1)).values().rev() {
+                        delta.revert(&mut rewound);
+                    }
+                    self.history.insert(height, StateDelta::between(&rewound, &state));
+                }
+                None => {
+                    self.history.clear();
+                    self.earliest = height;
+                }
+            }
+            self.account_cache.clear();
+            self.storage_cache.clear();
+        }
+        self.latest = state;
+        self.height = height;
+        self.prune();
+    }
+
+    /// Cached account lookup against the latest state
+    pub fn account(&self, address: &Address) -> Account {
+        self.account_cache.get_or_load(address, || self.latest().account(address))
+    }
+
+    /// Cached contract storage read against the latest state
+    pub fn storage(&self, contract: &Address, key: &[u8]) -> Option<Vec<u8>> {
+        self.storage_cache.get_or_load(&(*contract, key.to_vec()), || {
+            self.latest().contract(contract).and_then(|c| c.storage.get(key).cloned())
+        })
+    }
+
+    pub fn cache_stats(&self) -> Vec<CacheStats> {
+        vec![self.account_cache.stats(), self.storage_cache.stats()]
+    }
+
+    pub fn latest_height(&self) -> u64 {
+        self.height
+    }
+
+    /// Lowest height `at` can still answer for
+    pub fn earliest_height(&self) -> u64 {
+        self.earliest
+    }
+
+    pub fn latest(&self) -> &WorldState {
+        &self.latest
+    }
+
+    /// State as of `height`. The latest state is borrowed; older states
+    /// are rebuilt by undoing the blocks after `height`.
+    pub fn at(&self, height: u64) -> Result<Cow<'_, WorldState>, &'static str> {
+        self.check_height(height)?;
+        if height == self.height {
+            return Ok(Cow::Borrowed(&self.latest));
+        }
+        let mut state = self.latest.clone();
+        for delta in self.history.range(height + 1..).map(|(_, delta)| delta).rev() {
+            delta.revert(&mut state);
+        }
+        Ok(Cow::Owned(state))
+    }
+
+    /// Account as of `height`, without rebuilding the whole state
+    pub fn account_at(&self, address: &Address, height: u64) -> Result<Account, &'static str> {
+        self.check_height(height)?;
+        // The first later block that touched the account remembers its value at `height`
+        for delta in self.history.range(height + 1..).map(|(_, delta)| delta) {
+            if let Some(account) = delta.accounts.get(address) {
+                return Ok(account.clone().unwrap_or_default());
+            }
+        }
+        Ok(self.account(address))
+    }
+
+    /// Contract storage slot as of `height`, without rebuilding the whole state
+    pub fn storage_at(&self, contract: &Address, key: &[u8], height: u64) -> Result<Option<Vec<u8>>, &'static str> {
+        self.check_height(height)?;
+        let slot = (*contract, key.to_vec());
+        for delta in self.history.range(height + 1..).map(|(_, delta)| delta) {
+            if let Some(previous) = delta.contracts.get(contract) {
+                return Ok(previous.as_ref().and_then(|c| c.storage.get(key).cloned()));
+            }
+            if let Some(value) = delta.storage.get(&slot) {
+                return Ok(value.clone());
+            }
+        }
+        Ok(self.storage(contract, key))
+    }
+
+    fn check_height(&self, height: u64) -> Result<(), &'static str> {
+        if height > self.height {
+            return Err("Block not found");
+        }
+        if height < self.earliest {
+            return Err("State pruned at this height; query an archive node");
+        }
+        Ok(())
+    }
+
+    fn prune(&mut self) {
+        let Some(retain_blocks) = self.retain_blocks else { return };
+        let earliest = self.height.saturating_sub(retain_blocks);
+        if earliest > self.earliest {
+            // Undo records at or below the new floor only lead to pruned heights
+            self.history = self.history.split_off(&(earliest + 1));
+            self.earliest = earliest;
+        }
+    }
+}
+
+/// Balance and nonce change for one account
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccountChange {
+    #[serde(with = "hex_serde")]
+    pub address: Address,
+    pub balance_before: u128,
+    pub balance_after: u128,
+    pub nonce_before: u64,
+    pub nonce_after: u64,
+}
+
+/// Change to one contract storage slot; `None` means unset
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StorageChange {
+    #[serde(with = "hex_serde")]
+    pub contract: Address,
+    #[serde(with = "hex_serde")]
+    pub key: Vec<u8>,
+    #[serde(with = "hex_serde_option")]
+    pub before: Option<Vec<u8>>,
+    #[serde(with = "hex_serde_option")]
+    pub after: Option<Vec<u8>>,
+}
+
+/// Consensus key of one validator before and after; `None` means not registered
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValidatorChange {
+    #[serde(with = "hex_serde")]
+    pub validator: Address,
+    #[serde(with = "hex_serde_option")]
+    pub key_before: Option<[u8; 32]>,
+    #[serde(with = "hex_serde_option")]
+    pub key_after: Option<[u8; 32]>,
+}
+
+/// Differences between two world states
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StateDiff {
+    pub accounts: Vec<AccountChange>,
+    pub storage: Vec<StorageChange>,
+    pub created_contracts: Vec<String>,
+    /// Validators registered, or whose active key changed
+    #[serde(default)]
+    pub validators: Vec<ValidatorChange>,
+}
+
+impl StateDiff {
+    pub fn between(before: &WorldState, after: &WorldState) -> Self {
+        let mut diff = Self::default();
+
+        let addresses: BTreeSet<&Address> = before.accounts.keys().chain(after.accounts.keys()).collect();
+        for address in addresses {
+            let old = before.account(address);
+            let new = after.account(address);
+            if old != new {
+                diff.accounts.push(AccountChange {
+                    address: *address,
+                    balance_before: old.balance,
+                    balance_after: new.balance,
+                    nonce_before: old.nonce,
+                    nonce_after: new.nonce,
+                });
+            }
+        }
+
+        let empty = BTreeMap::new();
+        for (address, contract) in &after.contracts {
+            let old_storage = match before.contract(address) {
+                Some(existing) => &existing.storage,
+                None => {
+                    diff.created_contracts.push(format!("0x{}", hex::encode(address)));
+                    &empty
+                }
+            };
+            let keys: BTreeSet<&Vec<u8>> = old_storage.keys().chain(contract.storage.keys()).collect();
+            for key in keys {
+                let old = old_storage.get(key);
+                let new = contract.storage.get(key);
+                if old != new {
+                    diff.storage.push(StorageChange {
+                        contract: *address,
+                        key: key.clone(),
+                        before: old.cloned(),
+                        after: new.cloned(),
+                    });
+                }
+            }
+        }
+
+        let validators: BTreeSet<&Address> = before.validator_keys.validators()
+            .chain(after.validator_keys.validators())
+            .collect();
+        for validator in validators {
+            let key_before = before.validator_keys.key_at(validator, before.height);
+            let key_after = after.validator_keys.key_at(validator, after.height);
+            if key_before != key_after {
+                diff.validators.push(ValidatorChange { validator: *validator, key_before, key_after });
+            }
+        }
+
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+            && self.storage.is_empty()
+            && self.created_contracts.is_empty()
+            && self.validators.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_store_history() {
+        let genesis = WorldState::with_balances(&[([1u8; 32], 100)]);
+        let mut store = StateStore::new(genesis.clone());
+
+        let mut next = genesis.clone();
+        next.transfer(&[1u8; 32], &[2u8; 32], 40).unwrap();
+        store.commit(1, next.clone());
+
+        assert_eq!(store.latest_height(), 1);
+        assert_eq!(store.at(0).unwrap().account(&[1u8; 32]).balance, 100);
+        assert_eq!(store.latest().account(&[2u8; 32]).balance, 40);
+        assert_eq!(store.at(2).unwrap_err(), "Block not found");
+
+        let diff = StateDiff::between(&genesis, &next);
+        assert_eq!(diff.accounts.len(), 2);
+        assert_ne!(genesis.state_root(), next.state_root());
+        assert!(StateDiff::between(&next, &next).is_empty());
+    }
+
+    #[test]
+    fn test_historical_reads_and_pruning() {
+        let genesis = WorldState::with_balances(&[([1u8; 32], 100)]);
+        let mut store = StateStore::new(genesis.clone());
+        let contract = ContractAccount { owner: [1u8; 32], code: Vec::new(), storage: BTreeMap::new() };
+
+        let mut state = genesis;
+        state.insert_contract([9u8; 32], contract);
+        store.commit(1, state.clone());
+        for height in 2..=4 {
+            state.transfer(&[1u8; 32], &[2u8; 32], 10).unwrap();
+            state.contract_mut(&[9u8; 32]).unwrap().storage.insert(b"count".to_vec(), vec![height as u8]);
+            store.commit(height, state.clone());
+        }
+
+        assert_eq!(store.account_at(&[1u8; 32], 0).unwrap().balance, 100);
+        assert_eq!(store.account_at(&[1u8; 32], 3).unwrap().balance, 80);
+        assert_eq!(store.storage_at(&[9u8; 32], b"count", 0).unwrap(), None);
+        assert_eq!(store.storage_at(&[9u8; 32], b"count", 1).unwrap(), None);
+        assert_eq!(store.storage_at(&[9u8; 32], b"count", 3).unwrap(), Some(vec![3]));
+
+        // Rebuilt states match what was committed, height included
+        let past = store.at(2).unwrap();
+        assert_eq!(past.height(), 2);
+        assert_eq!(past.account(&[2u8; 32]).balance, 10);
+        assert!(store.at(0).unwrap().contract(&[9u8; 32]).is_none());
+
+        // A reorg replaces the blocks from its height on
+        let mut fork = store.at(2).unwrap().into_owned();
+        fork.transfer(&[1u8; 32], &[3u8; 32], 5).unwrap();
+        store.commit(3, fork);
+        assert_eq!(store.latest_height(), 3);
+        assert_eq!(store.account_at(&[3u8; 32], 3).unwrap().balance, 5);
+        assert_eq!(store.account_at(&[2u8; 32], 3).unwrap().balance, 10);
+        assert_eq!(store.account_at(&[1u8; 32], 1).unwrap().balance, 100);
+
+        store.set_retention(Some(1));
+        assert_eq!(store.earliest_height(), 2);
+        assert_eq!(store.at(1).unwrap_err(), "State pruned at this height; query an archive node");
+        assert_eq!(store.account_at(&[1u8; 32], 2).unwrap().balance, 90);
+    }
+
+    #[test]
+    fn test_validator_changes_in_diff() {
+        let genesis = WorldState::with_balances(&[([1u8; 32], 100)]);
+        let mut store = StateStore::new(genesis.clone());
+        let mut state = genesis;
+        state.transfer(&[1u8; 32], &[2u8; 32], 10).unwrap();
+        store.commit(1, state.clone());
+        state.validator_keys_mut().register([7u8; 32], [8u8; 32], 0).unwrap();
+        state.transfer(&[2u8; 32], &[1u8; 32], 10).unwrap();
+        store.commit(2, state);
+
+        // Account 1 went out and back, so only the range's net effect shows
+        let diff = StateDiff::between(&store.at(0).unwrap(), &store.at(2).unwrap());
+        assert_eq!(diff.accounts.len(), 0);
+        assert_eq!(diff.validators, vec![ValidatorChange { validator: [7u8; 32], key_before: None, key_after: Some([8u8; 32]) }]);
+        assert_eq!(StateDiff::between(&store.at(0).unwrap(), &store.at(1).unwrap()).accounts.len(), 2);
+    }
+
+    #[test]
+    fn test_cache_invalidated_on_commit() {
+        let genesis = WorldState::with_balances(&[([1u8; 32], 100), ([3u8; 32], 7)]);
+        let mut store = StateStore::new(genesis.clone());
+        assert_eq!(store.account(&[1u8; 32]).balance, 100);
+        assert_eq!(store.account(&[3u8; 32]).balance, 7);
+
+        let mut next = genesis;
+        next.transfer(&[1u8; 32], &[2u8; 32], 40).unwrap();
+        store.commit(1, next);
+
+        // The touched account is reloaded, the untouched one is still served from cache
+        assert_eq!(store.account(&[1u8; 32]).balance, 60);
+        assert_eq!(store.account(&[3u8; 32]).balance, 7);
+        let accounts = &store.cache_stats()[0];
+        assert_eq!(accounts.hits, 1);
+        assert_eq!(accounts.invalidations, 1);
+    }
+}
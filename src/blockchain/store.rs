@@ -0,0 +1,169 @@
+//! Persistence behind `Blockchain`.
+//!
+//! A `ChainStore` keeps what a node needs to resume after a restart: every
+//! block, the world state as of the head, and the transactions still
+//! waiting for a block. `Blockchain` writes each block to its store before
+//! adding it to the in-memory chain, so a block the node has seen is never
+//! lost to a crash.
+//!
+//! The node uses `NodeDatabase` (RocksDB), writing blocks where `db verify`
+//! and `index rebuild` already read them. `MemoryChainStore` keeps
+//! everything in memory for tests and tools.
+
+use crate::blockchain::core::Block;
+use crate::blockchain::state::WorldState;
+use crate::storage::database::{NodeDatabase, CF_STATE};
+
+/// Key of the head's world state in `CF_STATE`
+const STATE_KEY: &[u8] = b"chain:world_state";
+/// Key of the pending transactions in `CF_STATE`
+const PENDING_KEY: &[u8] = b"chain:pending";
+
+/// Where `Blockchain` persists blocks, state and pending transactions
+pub trait ChainStore: Send + Sync {
+    /// Every stored block, in chain order
+    fn blocks(&self) -> Result<Vec<Block>, String>;
+    fn put_block(&mut self, block: &Block) -> Result<(), String>;
+    /// World state as of the last stored block, if one was saved
+    fn state(&self) -> Result<Option<WorldState>, String>;
+    fn put_state(&mut self, state: &WorldState) -> Result<(), String>;
+    /// Encoded transactions waiting for a block
+    fn pending(&self) -> Result<Vec<Vec<u8>>, String>;
+    /// Replace the pending transactions
+    fn put_pending(&mut self, pending: &[Vec<u8>]) -> Result<(), String>;
+}
+
+/// Chain store that lives only as long as the process
+#[derive(Debug, Clone, Default)]
+pub struct MemoryChainStore {
+    blocks: Vec<Block>,
+    state: Option<WorldState>,
+    pending: Vec<Vec<u8>>,
+}
+
+impl MemoryChainStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ChainStore for MemoryChainStore {
+    fn blocks(&self) -> Result<Vec<Block>, String> {
+        Ok(self.blocks.clone())
+    }
+
+    fn put_block(&mut self, block: &Block) -> Result<(), String> {
+        self.blocks.truncate(block.index as usize);
+        self.blocks.push(block.clone());
+        Ok(())
+    }
+
+    fn state(&self) -> Result<Option<WorldState>, String> {
+        Ok(self.state.clone())
+    }
+
+    fn put_state(&mut self, state: &WorldState) -> Result<(), String> {
+        self.state = Some(state.clone());
+        Ok(())
+    }
+
+    fn pending(&self) -> Result<Vec<Vec<u8>>, String> {
+        Ok(self.pending.clone())
+    }
+
+    fn put_pending(&mut self, pending: &[Vec<u8>]) -> Result<(), String> {
+        self.pending = pending.to_vec();
+        Ok(())
+    }
+}
+
+impl ChainStore for NodeDatabase {
+    fn blocks(&self) -> Result<Vec<Block>, String> {
+        self.blocks_from(0).map_err(|e| e.to_string())?.collect()
+    }
+
+    fn put_block(&mut self, block: &Block) -> Result<(), String> {
+        NodeDatabase::put_block(self, block).map_err(|e| e.to_string())
+    }
+
+    fn state(&self) -> Result<Option<WorldState>, String> {
+        match self.get(CF_STATE, STATE_KEY).map_err(|e| e.to_string())? {
+            Some(raw) => bincode::deserialize(&raw).map(Some).map_err(|e| format!("Undecodable world state: {}", e)),
+            None => Ok(None),
+        }
+    }
+
+    fn put_state(&mut self, state: &WorldState) -> Result<(), String> {
+        let raw = bincode::serialize(state).map_err(|e| e.to_string())?;
+        self.put(CF_STATE, STATE_KEY, &raw).map_err(|e| e.to_string())
+    }
+
+    fn pending(&self) -> Result<Vec<Vec<u8>>, String> {
+        match self.get(CF_STATE, PENDING_KEY).map_err(|e| e.to_string())? {
+            Some(raw) => bincode::deserialize(&raw).map_err(|e| format!("Undecodable pending transactions: {}", e)),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn put_pending(&mut self, pending: &[Vec<u8>]) -> Result<(), String> {
+        let raw = bincode::serialize(pending).map_err(|e| e.to_string())?;
+        self.put(CF_STATE, PENDING_KEY, &raw).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::core::Blockchain;
+    use crate::clock::MockClock;
+
+    #[test]
+    fn test_chain_survives_reopen() {
+        let path = std::env::temp_dir().join(format!("metaverse_chain_store_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        let path = path.to_string_lossy().to_string();
+
+        let state = WorldState::with_balances(&[([1; 32], 500)]);
+        let head = {
+            let mut chain = Blockchain::open(&path, 8).unwrap();
+            chain.add_block(b"first".to_vec()).unwrap();
+            chain.add_block(b"second".to_vec()).unwrap();
+            chain.save_state(&state).unwrap();
+            chain.set_pending_transactions(vec![b"tx".to_vec()]).unwrap();
+            chain.block(2).unwrap().hash
+        };
+
+        let mut chain = Blockchain::open(&path, 8).unwrap();
+        assert_eq!(chain.height(), 3);
+        assert_eq!(chain.block(2).unwrap().hash, head);
+        assert_eq!(chain.stored_state().unwrap(), Some(state));
+        assert_eq!(chain.pending_transactions(), &[b"tx".to_vec()]);
+        chain.add_block(b"third".to_vec()).unwrap();
+        assert_eq!(chain.block(3).unwrap().previous_hash, head);
+
+        drop(chain);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_tampered_store_is_refused() {
+        let mut store = MemoryChainStore::new();
+        {
+            let mut chain = Blockchain::with_store(8, Box::new(store.clone()), MockClock::new(0)).unwrap();
+            chain.add_block(b"first".to_vec()).unwrap();
+            // Read back what the chain wrote
+            for height in 0..chain.height() {
+                store.put_block(chain.block(height).unwrap()).unwrap();
+            }
+        }
+        assert!(Blockchain::with_store(8, Box::new(store.clone()), MockClock::new(0)).is_ok());
+
+        store.blocks[1].data = b"forged".to_vec();
+        assert_eq!(
+            Blockchain::with_store(8, Box::new(store.clone()), MockClock::new(0)).err(),
+            Some("Stored block 1 does not match its hash".to_string())
+        );
+        store.blocks.remove(0);
+        assert!(Blockchain::with_store(8, Box::new(store), MockClock::new(0)).is_err());
+    }
+}
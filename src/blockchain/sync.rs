@@ -0,0 +1,520 @@
+//! Headers-first block sync.
+//!
+//! A node behind its peers first downloads the headers past its tip and
+//! checks each one with `Blockchain::verify_header` as it arrives, so a peer
+//! serving an invalid or unlinked chain is caught before any body is
+//! fetched. Bodies are then requested in batches from every sync peer at
+//! once. A body is kept only if it completes its header's hash, and blocks
+//! are executed and imported strictly in order as the gap at the tip fills.
+//!
+//! The engine only tracks what to ask whom; the node owns the connections.
+//! Each peer connection asks `header_request` or `body_request` for its next
+//! request and hands the reply to `on_headers` or `on_bodies`.
+//!
+//! After a fast sync (`blockchain::snapshot`) the engine holds the restored
+//! state as its pivot. Blocks up to the pivot are checked and appended
+//! without being executed, the restored state takes over at the pivot block,
+//! and blocks after it are executed as usual.
+
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use super::bloom::Bloom;
+use super::core::{Block, BlockHeader, Blockchain};
+use super::execution::{Executor, Receipt};
+use super::state::{StateStore, WorldState};
+use super::types::hex_serde;
+use crate::storage::reindex::block_transactions;
+
+/// Most headers served per request
+pub const MAX_HEADERS: u64 = 512;
+
+/// Most bodies served per request
+pub const MAX_BODIES: usize = 32;
+
+/// Headers held ahead of the tip; header download pauses past this
+pub const MAX_HEADERS_AHEAD: usize = 8_192;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncStage {
+    /// No peer has announced a longer chain
+    Idle,
+    /// Downloading headers; no checked header is waiting for its body
+    Headers,
+    /// Downloading bodies for checked headers
+    Bodies,
+    /// Caught up with the best announced height
+    Synced,
+}
+
+/// Sync progress, as `getSyncStatus` reports it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncStatus {
+    pub stage: SyncStage,
+    /// Chain height when sync started
+    pub starting_block: u64,
+    pub current_block: u64,
+    /// Best height announced by a sync peer
+    pub highest_block: u64,
+    /// Checked headers past the tip
+    pub headers: usize,
+    /// Bodies downloaded and waiting for the blocks before them
+    pub bodies: usize,
+    /// Heights requested and not yet answered
+    pub in_flight: usize,
+    pub peers: usize,
+    /// Height of the snapshot state blocks are imported up to without execution
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pivot: Option<u64>,
+}
+
+/// Ask a peer for headers starting at height `from`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeadersRequest {
+    pub from: u64,
+    pub count: u64,
+}
+
+/// Ask a peer for the data of the blocks at `heights`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BodiesRequest {
+    pub heights: Vec<u64>,
+}
+
+/// Data of one block
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockBody {
+    pub index: u64,
+    #[serde(with = "hex_serde")]
+    pub data: Vec<u8>,
+}
+
+/// Receipts of a block imported by sync, for the log index
+#[derive(Debug, Clone)]
+pub struct ImportedBlock {
+    pub index: u64,
+    pub bloom: Bloom,
+    pub receipts: Vec<Receipt>,
+}
+
+/// Headers from `request.from`, at most `MAX_HEADERS`
+pub fn serve_headers(chain: &Blockchain, request: &HeadersRequest) -> Vec<BlockHeader> {
+    let end = request.from.saturating_add(request.count.min(MAX_HEADERS)).min(chain.height());
+    (request.from..end).filter_map(|height| chain.block(height)).map(|block| block.header()).collect()
+}
+
+/// Bodies of the requested blocks this chain holds, at most `MAX_BODIES`
+pub fn serve_bodies(chain: &Blockchain, request: &BodiesRequest) -> Vec<BlockBody> {
+    request.heights.iter()
+        .take(MAX_BODIES)
+        .filter_map(|height| chain.block(*height))
+        .map(|block| BlockBody { index: block.index, data: block.data.clone() })
+        .collect()
+}
+
+/// Download state of a headers-first sync
+#[derive(Debug, Clone, Default)]
+pub struct SyncEngine {
+    starting_block: u64,
+    /// Best height each sync peer announced
+    peers: BTreeMap<String, u64>,
+    /// Checked headers past the tip, by height
+    headers: BTreeMap<u64, BlockHeader>,
+    bodies: BTreeMap<u64, Vec<u8>>,
+    /// Peer each outstanding body height was asked of
+    in_flight: BTreeMap<u64, String>,
+    /// Peer downloading headers, one at a time so each batch links to the last
+    header_peer: Option<String>,
+    /// Snapshot state and the hash of the block it follows
+    pivot: Option<(WorldState, [u8; 32])>,
+}
+
+impl SyncEngine {
+    pub fn new(starting_block: u64) -> Self {
+        Self { starting_block, ..Self::default() }
+    }
+
+    /// Import blocks up to `state.height()` without executing them and take
+    /// `state`, restored from a snapshot of the block hashed `block_hash`,
+    /// as the state after that block
+    pub fn set_pivot(&mut self, state: WorldState, block_hash: [u8; 32]) {
+        self.pivot = Some((state, block_hash));
+    }
+
+    pub fn pivot_height(&self) -> Option<u64> {
+        self.pivot.as_ref().map(|(state, _)| state.height())
+    }
+
+    /// Add a peer with the chain height it announced in its handshake
+    pub fn add_peer(&mut self, peer: &str, best_height: u64) {
+        self.peers.insert(peer.to_string(), best_height);
+    }
+
+    /// Forget a peer and hand its outstanding requests to others
+    pub fn remove_peer(&mut self, peer: &str) {
+        self.peers.remove(peer);
+        self.in_flight.retain(|_, asked| asked != peer);
+        if self.header_peer.as_deref() == Some(peer) {
+            self.header_peer = None;
+        }
+    }
+
+    /// Height `peer` announced, if it is still a sync peer
+    pub fn peer_height(&self, peer: &str) -> Option<u64> {
+        self.peers.get(peer).copied()
+    }
+
+    pub fn highest_block(&self) -> u64 {
+        self.peers.values().copied().max().unwrap_or(0)
+    }
+
+    /// Whether the chain has caught up with every peer
+    pub fn is_complete(&self, chain_height: u64) -> bool {
+        chain_height >= self.highest_block()
+    }
+
+    fn next_header(&self, chain_height: u64) -> u64 {
+        self.headers.keys().next_back().map_or(chain_height, |last| last + 1)
+    }
+
+    /// Next headers `peer` should be asked for, if it is the best peer to ask
+    /// and no other peer is already downloading headers
+    pub fn header_request(&mut self, peer: &str, chain_height: u64) -> Option<HeadersRequest> {
+        let from = self.next_header(chain_height);
+        let best = *self.peers.get(peer)?;
+        if self.header_peer.is_some() || self.headers.len() >= MAX_HEADERS_AHEAD || best <= from {
+            return None;
+        }
+        self.header_peer = Some(peer.to_string());
+        Some(HeadersRequest { from, count: MAX_HEADERS.min(best - from) })
+    }
+
+    /// Check and keep headers `peer` sent. The first header that does not
+    /// follow the previous one drops the whole reply and the peer.
+    pub fn on_headers(&mut self, chain: &Blockchain, peer: &str, headers: Vec<BlockHeader>) -> Result<usize, &'static str> {
+        if self.header_peer.as_deref() == Some(peer) {
+            self.header_peer = None;
+        }
+        if headers.len() as u64 > MAX_HEADERS {
+            self.remove_peer(peer);
+            return Err("Too many headers");
+        }
+        let next = self.next_header(chain.height());
+        let mut parent_hash = match self.headers.values().next_back() {
+            Some(last) => last.hash,
+            None => chain.block(next - 1).ok_or("Chain is empty")?.hash,
+        };
+        if headers.is_empty() {
+            // The peer no longer has the chain it announced
+            if let Some(best) = self.peers.get_mut(peer) {
+                *best = (*best).min(next);
+            }
+            return Ok(0);
+        }
+        for (height, header) in (next..).zip(&headers) {
+            let valid = if header.index != height { Err("Header out of sequence") } else { chain.verify_header(header, &parent_hash) };
+            if let Err(e) = valid {
+                self.remove_peer(peer);
+                return Err(e);
+            }
+            parent_hash = header.hash;
+        }
+        let count = headers.len();
+        self.headers.extend(headers.into_iter().map(|header| (header.index, header)));
+        Ok(count)
+    }
+
+    /// Next batch of bodies to ask `peer` for: the lowest heights with a
+    /// checked header that are neither downloaded nor asked of another peer
+    pub fn body_request(&mut self, peer: &str) -> Option<BodiesRequest> {
+        let best = *self.peers.get(peer)?;
+        let heights: Vec<u64> = self.headers.keys()
+            .copied()
+            .filter(|height| *height < best && !self.bodies.contains_key(height) && !self.in_flight.contains_key(height))
+            .take(MAX_BODIES)
+            .collect();
+        if heights.is_empty() {
+            return None;
+        }
+        for height in &heights {
+            self.in_flight.insert(*height, peer.to_string());
+        }
+        Some(BodiesRequest { heights })
+    }
+
+    /// Keep the bodies `peer` sent that hash to their headers. Heights it
+    /// was asked for but did not send go back to the queue; a body that does
+    /// not match its header drops the peer.
+    pub fn on_bodies(&mut self, peer: &str, bodies: Vec<BlockBody>) -> Result<usize, &'static str> {
+        let mut kept = 0;
+        for body in bodies {
+            if self.in_flight.get(&body.index).map(String::as_str) != Some(peer) {
+                continue;
+            }
+            let Some(header) = self.headers.get(&body.index) else { continue };
+            if !header.clone().with_data(body.data.clone()).verify_hash() {
+                self.remove_peer(peer);
+                return Err("Body does not match its header");
+            }
+            self.in_flight.remove(&body.index);
+            self.bodies.insert(body.index, body.data);
+            kept += 1;
+        }
+        self.in_flight.retain(|_, asked| asked != peer);
+        Ok(kept)
+    }
+
+    /// Queue a block a peer announced for `import` when it is the next block
+    /// and no download is under way; otherwise headers-first sync fetches it.
+    /// Returns whether the block was queued.
+    pub fn on_block(&mut self, chain: &Blockchain, block: Block) -> Result<bool, &'static str> {
+        if block.index != chain.height() || !self.headers.is_empty() {
+            return Ok(false);
+        }
+        if !chain.verify_block(&block) {
+            return Err("Block verification failed");
+        }
+        self.headers.insert(block.index, block.header());
+        self.bodies.insert(block.index, block.data);
+        Ok(true)
+    }
+
+    /// Execute and import the downloaded blocks that follow the tip. A block
+    /// that fails to execute is dropped with every header after it.
+    pub fn import(&mut self, chain: &mut Blockchain, store: &mut StateStore) -> Result<Vec<ImportedBlock>, &'static str> {
+        let before_pivot = self.pivot_height().is_some_and(|pivot| chain.height() <= pivot);
+        if !before_pivot && store.latest_height() + 1 != chain.height() {
+            return Err("Chain and state heights disagree");
+        }
+        let mut imported = Vec::new();
+        while let Some(data) = self.bodies.remove(&chain.height()) {
+            let header = self.headers.remove(&chain.height()).ok_or("Body without a header")?;
+            let block = header.with_data(data);
+            if let Some(pivot) = self.pivot_height().filter(|pivot| block.index <= *pivot) {
+                if let Err(e) = self.import_unexecuted(chain, store, block, pivot) {
+                    self.headers.clear();
+                    self.bodies.clear();
+                    self.in_flight.clear();
+                    return Err(e);
+                }
+                continue;
+            }
+            let transactions = block_transactions(&block);
+            let mut next = store.latest().clone();
+            let executed = Executor::apply_block(&mut next, &transactions);
+            let result = executed.and_then(|receipts| chain.import_block(block).map(|_| receipts));
+            let receipts = match result {
+                Ok(receipts) => receipts,
+                Err(e) => {
+                    self.headers.clear();
+                    self.bodies.clear();
+                    self.in_flight.clear();
+                    return Err(e);
+                }
+            };
+            let index = chain.height() - 1;
+            store.commit(index, next);
+            imported.push(ImportedBlock { index, bloom: Bloom::for_block(&transactions, &receipts), receipts });
+        }
+        if !imported.is_empty() {
+            chain.save_state(store.latest())?;
+        }
+        Ok(imported)
+    }
+
+    /// Append a block at or below the pivot, installing the snapshot state
+    /// once the pivot block itself is in
+    fn import_unexecuted(&mut self, chain: &mut Blockchain, store: &mut StateStore, block: Block, pivot: u64) -> Result<(), &'static str> {
+        let at_pivot = block.index == pivot;
+        if at_pivot && self.pivot.as_ref().is_some_and(|(_, hash)| *hash != block.hash) {
+            self.pivot = None;
+            return Err("Snapshot does not follow the synced chain");
+        }
+        chain.import_block(block)?;
+        if at_pivot {
+            let (state, _) = self.pivot.take().expect("pivot checked above");
+            store.restore(state);
+            chain.save_state(store.latest())?;
+        }
+        Ok(())
+    }
+
+    pub fn status(&self, chain_height: u64) -> SyncStatus {
+        let highest_block = self.highest_block();
+        let stage = if self.peers.is_empty() && self.headers.is_empty() {
+            SyncStage::Idle
+        } else if chain_height >= highest_block {
+            SyncStage::Synced
+        } else if self.headers.is_empty() {
+            SyncStage::Headers
+        } else {
+            SyncStage::Bodies
+        };
+        SyncStatus {
+            stage,
+            starting_block: self.starting_block,
+            current_block: chain_height,
+            highest_block,
+            headers: self.headers.len(),
+            bodies: self.bodies.len(),
+            in_flight: self.in_flight.len(),
+            peers: self.peers.len(),
+            pivot: self.pivot_height(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::state::WorldState;
+    use crate::blockchain::store::{ChainStore, MemoryChainStore};
+    use crate::clock;
+
+    /// A chain holding only `source`'s genesis block
+    fn fresh_copy(source: &Blockchain) -> Blockchain {
+        let mut store = MemoryChainStore::new();
+        store.put_block(source.block(0).unwrap()).unwrap();
+        Blockchain::with_store(2, Box::new(store), clock::system()).unwrap()
+    }
+
+    #[test]
+    fn test_headers_first_sync_from_two_peers() {
+        let mut source = Blockchain::new(2);
+        for i in 0..10u8 {
+            source.add_block(vec![i]).unwrap();
+        }
+        let mut chain = fresh_copy(&source);
+        let mut store = StateStore::new(WorldState::new());
+        let mut engine = SyncEngine::new(chain.height());
+        engine.add_peer("a", 11);
+        engine.add_peer("b", 11);
+        assert_eq!(engine.status(chain.height()).stage, SyncStage::Headers);
+
+        // One header download at a time
+        let request = engine.header_request("a", chain.height()).unwrap();
+        assert_eq!(request, HeadersRequest { from: 1, count: 10 });
+        assert_eq!(engine.header_request("b", chain.height()), None);
+
+        // A header that skips a block drops the reply and the peer
+        let mut headers = serve_headers(&source, &request);
+        headers.remove(5);
+        assert_eq!(engine.on_headers(&chain, "a", headers), Err("Header out of sequence"));
+        assert_eq!(engine.status(chain.height()).peers, 1);
+
+        let request = engine.header_request("b", chain.height()).unwrap();
+        assert_eq!(engine.on_headers(&chain, "b", serve_headers(&source, &request)), Ok(10));
+        assert_eq!(engine.status(chain.height()).stage, SyncStage::Bodies);
+
+        // Each peer is asked for the bodies it has; one that does not match
+        // its header is refused
+        engine.add_peer("a", 6);
+        let first = engine.body_request("a").unwrap();
+        let second = engine.body_request("b").unwrap();
+        assert_eq!(first.heights, (1..6).collect::<Vec<_>>());
+        assert_eq!(second.heights, (6..11).collect::<Vec<_>>());
+        let mut forged = serve_bodies(&source, &second);
+        forged[0].data = b"forged".to_vec();
+        assert_eq!(engine.on_bodies("b", forged), Err("Body does not match its header"));
+        assert_eq!(engine.status(chain.height()).in_flight, 5);
+
+        assert_eq!(engine.on_bodies("a", serve_bodies(&source, &first)), Ok(5));
+        assert_eq!(engine.import(&mut chain, &mut store).unwrap().len(), 5);
+        assert_eq!(store.latest_height(), 5);
+        assert_eq!(engine.body_request("a"), None);
+
+        engine.add_peer("c", 11);
+        let rest = engine.body_request("c").unwrap();
+        assert_eq!(rest.heights, (6..11).collect::<Vec<_>>());
+        engine.on_bodies("c", serve_bodies(&source, &rest)).unwrap();
+        engine.import(&mut chain, &mut store).unwrap();
+
+        assert_eq!(chain.height(), 11);
+        assert_eq!(chain.block(10).unwrap().hash, source.block(10).unwrap().hash);
+        assert_eq!(engine.status(chain.height()).stage, SyncStage::Synced);
+        assert!(engine.is_complete(chain.height()));
+    }
+
+    #[test]
+    fn test_blocks_before_the_pivot_are_not_executed() {
+        let mut source = Blockchain::new(2);
+        for i in 0..6u8 {
+            source.add_block(vec![i]).unwrap();
+        }
+        // Restored state carries a balance no block could have produced
+        let mut restored = WorldState::with_balances(&[([7u8; 32], 500)]);
+        restored.set_height(4);
+
+        let sync_to_end = |engine: &mut SyncEngine, chain: &mut Blockchain, store: &mut StateStore| {
+            engine.add_peer("a", 7);
+            let request = engine.header_request("a", chain.height()).unwrap();
+            engine.on_headers(chain, "a", serve_headers(&source, &request)).unwrap();
+            let request = engine.body_request("a").unwrap();
+            engine.on_bodies("a", serve_bodies(&source, &request)).unwrap();
+            engine.import(chain, store)
+        };
+
+        let mut chain = fresh_copy(&source);
+        let mut store = StateStore::new(WorldState::new());
+        let mut engine = SyncEngine::new(chain.height());
+        engine.set_pivot(restored.clone(), [0u8; 32]);
+        assert_eq!(sync_to_end(&mut engine, &mut chain, &mut store).unwrap_err(), "Snapshot does not follow the synced chain");
+        assert_eq!(engine.pivot_height(), None);
+
+        let mut chain = fresh_copy(&source);
+        let mut engine = SyncEngine::new(chain.height());
+        engine.set_pivot(restored, source.block(4).unwrap().hash);
+        assert_eq!(engine.status(chain.height()).pivot, Some(4));
+        let imported = sync_to_end(&mut engine, &mut chain, &mut store).unwrap();
+
+        // Only the blocks after the pivot were executed
+        assert_eq!(imported.iter().map(|block| block.index).collect::<Vec<_>>(), vec![5, 6]);
+        assert_eq!(chain.height(), 7);
+        assert_eq!(store.latest_height(), 6);
+        assert_eq!(store.account(&[7u8; 32]).balance, 500);
+        assert_eq!(store.earliest_height(), 4);
+    }
+
+    #[test]
+    fn test_sync_between_separately_created_chains() {
+        // Nodes started at different times still share a genesis block
+        let mut source = Blockchain::with_clock(2, clock::MockClock::new(1_000));
+        for i in 0..3u8 {
+            source.add_block(vec![i]).unwrap();
+        }
+        let mut chain = Blockchain::with_clock(2, clock::MockClock::new(5_000));
+        assert_eq!(chain.block(0).unwrap().hash, source.block(0).unwrap().hash);
+
+        let mut store = StateStore::new(WorldState::new());
+        let mut engine = SyncEngine::new(chain.height());
+        engine.add_peer("a", source.height());
+        let request = engine.header_request("a", chain.height()).unwrap();
+        assert_eq!(engine.on_headers(&chain, "a", serve_headers(&source, &request)), Ok(3));
+        let request = engine.body_request("a").unwrap();
+        engine.on_bodies("a", serve_bodies(&source, &request)).unwrap();
+        engine.import(&mut chain, &mut store).unwrap();
+        assert_eq!(chain.block(3).unwrap().hash, source.block(3).unwrap().hash);
+    }
+
+    #[test]
+    fn test_announced_block_imports_at_the_tip() {
+        let mut source = Blockchain::new(2);
+        for i in 0..3u8 {
+            source.add_block(vec![i]).unwrap();
+        }
+        let mut chain = fresh_copy(&source);
+        let mut store = StateStore::new(WorldState::new());
+        let mut engine = SyncEngine::new(chain.height());
+
+        // Blocks past the tip are left to headers-first sync
+        assert_eq!(engine.on_block(&chain, source.block(2).unwrap().clone()), Ok(false));
+        let mut forged = source.block(1).unwrap().clone();
+        forged.data = b"forged".to_vec();
+        assert_eq!(engine.on_block(&chain, forged), Err("Block verification failed"));
+
+        assert_eq!(engine.on_block(&chain, source.block(1).unwrap().clone()), Ok(true));
+        assert_eq!(engine.import(&mut chain, &mut store).unwrap().len(), 1);
+        assert_eq!(chain.block(1).unwrap().hash, source.block(1).unwrap().hash);
+        assert_eq!(store.latest_height(), 1);
+    }
+}
@@ -0,0 +1,376 @@
+use serde::{Serialize, Deserialize};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use crate::blockchain::account_validation::{ValidationAction, Witness, MAX_WITNESSES};
+use crate::blockchain::assets::AssetAction;
+use crate::blockchain::channel::ChannelAction;
+use crate::blockchain::confidential::ConfidentialAction;
+use crate::blockchain::execution::Instruction;
+use crate::blockchain::htlc::HtlcAction;
+use crate::blockchain::lease::LeaseAction;
+use crate::blockchain::market::MarketAction;
+use crate::blockchain::multisig::{Approval, MultisigOperation};
+use crate::blockchain::scheduler::ScheduledAction;
+use crate::blockchain::sponsor::{SponsorAction, Sponsorship};
+use crate::blockchain::types::{hex_serde, hex_serde_vec, Address};
+use crate::blockchain::validator_keys::KeyRotation;
+use crate::crypto::domain::{PayloadKind, SigningDomain, MAINNET_NETWORK_ID};
+
+pub type TxHash = [u8; 32];
+
+/// What a transaction does once its fee is paid
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionAction {
+    /// Move native tokens between accounts
+    Transfer {
+        #[serde(with = "hex_serde")]
+        to: Address,
+        amount: u128,
+    },
+    /// Create a contract; its address is derived from sender and nonce
+    Deploy {
+        code: Vec<Instruction>,
+    },
+    /// Invoke a contract with input bytes, optionally sending value
+    Call {
+        #[serde(with = "hex_serde")]
+        contract: Address,
+        #[serde(with = "hex_serde")]
+        input: Vec<u8>,
+        #[serde(default)]
+        value: u128,
+    },
+    /// Replace a validator's consensus key from an activation height on
+    RotateValidatorKey(KeyRotation),
+    /// Create an m-of-n account; its address is derived from sender and nonce
+    CreateMultisig {
+        #[serde(with = "hex_serde_vec")]
+        signers: Vec<[u8; 32]>,
+        threshold: u32,
+    },
+    /// Run an operation from a multisig account with its signers' approvals.
+    /// Anyone may submit it; the submitter pays the gas.
+    MultisigExecute {
+        #[serde(with = "hex_serde")]
+        multisig: Address,
+        operation: MultisigOperation,
+        approvals: Vec<Approval>,
+    },
+    /// Register `action` to run at `start_height` and then every `interval`
+    /// blocks, paid from `deposit` at this transaction's gas price. The ID
+    /// is derived from sender and nonce.
+    Schedule {
+        action: ScheduledAction,
+        start_height: u64,
+        #[serde(default)]
+        interval: Option<u64>,
+        /// Number of runs; unlimited while the deposit lasts if unset
+        #[serde(default)]
+        max_runs: Option<u64>,
+        /// Gas limit of each run
+        run_gas_limit: u64,
+        deposit: u128,
+    },
+    /// Remove one of the sender's schedules and refund its deposit
+    CancelSchedule {
+        #[serde(with = "hex_serde")]
+        id: [u8; 32],
+    },
+    /// Mint or transfer an NFT or parcel
+    Asset(AssetAction),
+    /// List, buy or bid on assets in the native marketplace
+    Market(MarketAction),
+    /// Offer, withdraw or accept a time-bound lease of an asset
+    Lease(LeaseAction),
+    /// Shield, transfer or unshield tokens of the confidential asset class
+    Confidential(ConfidentialAction),
+    /// Open, fund, close or dispute a payment channel
+    Channel(ChannelAction),
+    /// Lock, claim or refund a hash-time-locked contract
+    Htlc(HtlcAction),
+    /// Set or revoke the sender's policy for sponsoring others' fees
+    Sponsor(SponsorAction),
+    /// Install, update or remove the sender's validation contract
+    Validation(ValidationAction),
+}
+
+/// Signed account transaction
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Transaction {
+    /// Sender's ed25519 public key
+    #[serde(with = "hex_serde")]
+    pub from: Address,
+    pub nonce: u64,
+    pub action: TransactionAction,
+    pub gas_limit: u64,
+    pub gas_price: u128,
+    /// Network the transaction is valid on; part of the signed payload
+    #[serde(default = "default_network_id")]
+    pub network_id: u64,
+    #[serde(with = "hex_serde", default)]
+    pub signature: Vec<u8>,
+    /// Account paying the fee instead of the sender, with its countersignature
+    #[serde(default)]
+    pub sponsor: Option<Sponsorship>,
+    /// Signatures by keys acting for `from` through its validation contract,
+    /// in place of `signature`; sorted by key
+    #[serde(default)]
+    pub witnesses: Vec<Witness>,
+}
+
+fn default_network_id() -> u64 {
+    MAINNET_NETWORK_ID
+}
+
+impl Transaction {
+    pub fn new(from: Address, nonce: u64, action: TransactionAction, gas_limit: u64, gas_price: u128) -> Self {
+        Self {
+            from,
+            nonce,
+            action,
+            gas_limit,
+            gas_price,
+            network_id: MAINNET_NETWORK_ID,
+            signature: Vec::new(),
+            sponsor: None,
+            witnesses: Vec::new(),
+        }
+    }
+
+    /// Target a network other than mainnet
+    pub fn with_network_id(mut self, network_id: u64) -> Self {
+        self.network_id = network_id;
+        self
+    }
+
+    /// Have `sponsor` pay the fee if it countersigns before `valid_until`.
+    /// Set before the sender signs, since the sender signs the sponsor's address.
+    pub fn with_sponsor(mut self, sponsor: Address, valid_until: u64) -> Self {
+        self.sponsor = Some(Sponsorship { sponsor, valid_until, signature: Vec::new() });
+        self
+    }
+
+    /// Bytes covered by the signature, bound to the main chain of `network_id`
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let body = match &self.sponsor {
+            None => bincode::serialize(&(&self.from, self.nonce, &self.action, self.gas_limit, self.gas_price)),
+            Some(sponsorship) => bincode::serialize(
+                &(&self.from, self.nonce, &self.action, self.gas_limit, self.gas_price, &sponsorship.sponsor),
+            ),
+        }
+        .unwrap_or_default();
+        SigningDomain::main_chain(self.network_id).payload(PayloadKind::Transaction, 0, &body)
+    }
+
+    /// Bytes the sponsor countersigns: the sender's payload and the expiry
+    pub fn sponsorship_bytes(&self) -> Option<Vec<u8>> {
+        let sponsorship = self.sponsor.as_ref()?;
+        let body = bincode::serialize(&(self.signing_bytes(), sponsorship.valid_until)).unwrap_or_default();
+        Some(SigningDomain::main_chain(self.network_id).payload(PayloadKind::Sponsorship, 0, &body))
+    }
+
+    /// Account charged the fee
+    pub fn fee_payer(&self) -> Address {
+        self.sponsor.as_ref().map_or(self.from, |sponsorship| sponsorship.sponsor)
+    }
+
+    /// Transaction hash over the signed payload and signatures
+    pub fn hash(&self) -> TxHash {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&self.signing_bytes());
+        hasher.update(&self.signature);
+        if let Some(sponsorship) = &self.sponsor {
+            hasher.update(&sponsorship.signature);
+        }
+        for witness in &self.witnesses {
+            hasher.update(&witness.key);
+            hasher.update(&witness.signature);
+        }
+        hasher.finalize().into()
+    }
+
+    pub fn sign(&mut self, key: &SigningKey) {
+        self.from = key.verifying_key().to_bytes();
+        self.signature = key.sign(&self.signing_bytes()).to_bytes().to_vec();
+    }
+
+    /// Sign for `from` with another key, to be checked by the account's
+    /// validation contract. Witnesses are kept sorted by key, replacing any
+    /// earlier signature by the same key.
+    pub fn witness(&mut self, key: &SigningKey) {
+        let witness = Witness {
+            key: key.verifying_key().to_bytes(),
+            signature: key.sign(&self.signing_bytes()).to_bytes().to_vec(),
+        };
+        match self.witnesses.binary_search_by(|existing| existing.key.cmp(&witness.key)) {
+            Ok(index) => self.witnesses[index] = witness,
+            Err(index) => self.witnesses.insert(index, witness),
+        }
+    }
+
+    /// Shape checks on witnesses that do not need their signatures
+    pub fn check_witnesses(&self) -> Result<(), &'static str> {
+        if !self.signature.is_empty() {
+            return Err("Transaction has both a signature and witnesses");
+        }
+        if self.witnesses.len() > MAX_WITNESSES {
+            return Err("Too many witnesses");
+        }
+        if !self.witnesses.windows(2).all(|pair| pair[0].key < pair[1].key) {
+            return Err("Witnesses must be sorted by key without repeats");
+        }
+        Ok(())
+    }
+
+    /// Countersign as the sponsor named in the transaction
+    pub fn countersign(&mut self, key: &SigningKey) -> Result<(), &'static str> {
+        let payload = self.sponsorship_bytes().ok_or("Transaction has no sponsor")?;
+        let sponsorship = self.sponsor.as_mut().expect("checked above");
+        if sponsorship.sponsor != key.verifying_key().to_bytes() {
+            return Err("Key is not the transaction's sponsor");
+        }
+        sponsorship.signature = key.sign(&payload).to_bytes().to_vec();
+        Ok(())
+    }
+
+    /// Check the sender's signature or its witnesses' and, if sponsored,
+    /// the sponsor's. Whether the witnesses may act for the sender is up to
+    /// its validation contract when the transaction executes.
+    pub fn verify_signature(&self) -> Result<(), &'static str> {
+        if self.witnesses.is_empty() {
+            let key = VerifyingKey::from_bytes(&self.from).map_err(|_| "Invalid sender public key")?;
+            let signature: [u8; 64] = self.signature.as_slice().try_into()
+                .map_err(|_| "Invalid signature length")?;
+            key.verify(&self.signing_bytes(), &Signature::from_bytes(&signature))
+                .map_err(|_| "Invalid transaction signature")?;
+        } else {
+            self.check_witnesses()?;
+            let payload = self.signing_bytes();
+            for witness in &self.witnesses {
+                let key = VerifyingKey::from_bytes(&witness.key).map_err(|_| "Invalid witness public key")?;
+                let signature: [u8; 64] = witness.signature.as_slice().try_into()
+                    .map_err(|_| "Invalid witness signature length")?;
+                key.verify(&payload, &Signature::from_bytes(&signature))
+                    .map_err(|_| "Invalid witness signature")?;
+            }
+        }
+        if let (Some(sponsorship), Some(payload)) = (&self.sponsor, self.sponsorship_bytes()) {
+            let key = VerifyingKey::from_bytes(&sponsorship.sponsor).map_err(|_| "Invalid sponsor public key")?;
+            let signature: [u8; 64] = sponsorship.signature.as_slice().try_into()
+                .map_err(|_| "Invalid sponsor signature length")?;
+            key.verify(&payload, &Signature::from_bytes(&signature))
+                .map_err(|_| "Invalid sponsor signature")?;
+        }
+        Ok(())
+    }
+
+    /// Check the transaction targets `network_id`, then its signature
+    pub fn verify_for_network(&self, network_id: u64) -> Result<(), &'static str> {
+        if self.network_id != network_id {
+            return Err("Transaction is for a different network");
+        }
+        self.verify_signature()
+    }
+
+    /// Native tokens moved by the transaction in addition to its fee
+    pub fn value(&self) -> u128 {
+        match &self.action {
+            TransactionAction::Transfer { amount, .. } => *amount,
+            TransactionAction::Call { value, .. } => *value,
+            TransactionAction::Schedule { deposit, .. } => *deposit,
+            TransactionAction::Market(action) => action.value(),
+            TransactionAction::Lease(LeaseAction::Accept { total_rent, .. }) => *total_rent,
+            TransactionAction::Confidential(ConfidentialAction::Shield { amount }) => *amount as u128,
+            TransactionAction::Channel(ChannelAction::Open { deposit, .. }) => *deposit,
+            TransactionAction::Channel(ChannelAction::Deposit { amount, .. }) => *amount,
+            TransactionAction::Htlc(HtlcAction::Lock(terms)) => terms.amount,
+            // Multisig operations spend from the multisig account, not the sender
+            TransactionAction::Deploy { .. }
+            | TransactionAction::RotateValidatorKey(_)
+            | TransactionAction::CreateMultisig { .. }
+            | TransactionAction::MultisigExecute { .. }
+            | TransactionAction::CancelSchedule { .. }
+            | TransactionAction::Asset(_)
+            | TransactionAction::Lease(_)
+            | TransactionAction::Confidential(_)
+            | TransactionAction::Channel(_)
+            | TransactionAction::Htlc(_)
+            | TransactionAction::Sponsor(_)
+            | TransactionAction::Validation(_) => 0,
+        }
+    }
+
+    /// Largest fee the fee payer can be charged
+    pub fn max_fee(&self) -> u128 {
+        self.gas_limit as u128 * self.gas_price
+    }
+
+    pub fn size(&self) -> usize {
+        let sponsor_signature = self.sponsor.as_ref().map_or(0, |sponsorship| sponsorship.signature.len());
+        let witnesses: usize = self.witnesses.iter().map(|witness| witness.key.len() + witness.signature.len()).sum();
+        self.signing_bytes().len() + self.signature.len() + sponsor_signature + witnesses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_and_verify() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let mut tx = Transaction::new(
+            [0u8; 32],
+            0,
+            TransactionAction::Transfer { to: [2u8; 32], amount: 50 },
+            21_000,
+            1,
+        );
+        tx.sign(&key);
+        assert!(tx.verify_signature().is_ok());
+
+        let mut tampered = tx.clone();
+        tampered.nonce = 1;
+        assert!(tampered.verify_signature().is_err());
+        assert_ne!(tx.hash(), tampered.hash());
+    }
+
+    #[test]
+    fn test_sponsor_countersignature() {
+        let (user, sponsor) = (SigningKey::from_bytes(&[7u8; 32]), SigningKey::from_bytes(&[8u8; 32]));
+        let mut tx = Transaction::new([0u8; 32], 0, TransactionAction::Transfer { to: [2u8; 32], amount: 0 }, 21_000, 1)
+            .with_sponsor(sponsor.verifying_key().to_bytes(), 100);
+        tx.sign(&user);
+        assert_eq!(tx.verify_signature(), Err("Invalid sponsor signature length"));
+        assert_eq!(tx.countersign(&user), Err("Key is not the transaction's sponsor"));
+        tx.countersign(&sponsor).unwrap();
+        tx.verify_signature().unwrap();
+        assert_eq!(tx.fee_payer(), sponsor.verifying_key().to_bytes());
+
+        // The sender signed the sponsor, so it cannot be swapped or stripped
+        let mut swapped = tx.clone();
+        swapped.sponsor.as_mut().unwrap().sponsor = [9u8; 32];
+        assert_eq!(swapped.verify_signature(), Err("Invalid transaction signature"));
+        let mut stripped = tx.clone();
+        stripped.sponsor = None;
+        assert_eq!(stripped.verify_signature(), Err("Invalid transaction signature"));
+        let mut extended = tx.clone();
+        extended.sponsor.as_mut().unwrap().valid_until = 1_000;
+        assert_eq!(extended.verify_signature(), Err("Invalid sponsor signature"));
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let tx = Transaction::new(
+            [1u8; 32],
+            3,
+            TransactionAction::Call { contract: [9u8; 32], input: b"hi".to_vec(), value: 0 },
+            50_000,
+            2,
+        );
+        let json = serde_json::to_string(&tx).unwrap();
+        assert!(json.contains("\"call\":{"));
+        assert!(json.contains("0x6869"));
+        assert_eq!(serde_json::from_str::<Transaction>(&json).unwrap(), tx);
+    }
+}
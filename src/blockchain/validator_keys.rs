@@ -0,0 +1,228 @@
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use crate::blockchain::types::{hex_serde, Address};
+use crate::crypto::domain::{PayloadKind, SigningDomain};
+
+/// Blocks between a rotation being included and the new key taking over,
+/// so every validator sees the rotation before it matters
+pub const MIN_ACTIVATION_DELAY: u64 = 2;
+
+/// Consensus key and the first height it signs for
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyEpoch {
+    pub active_from: u64,
+    #[serde(with = "hex_serde")]
+    pub key: [u8; 32],
+}
+
+/// Handover from a validator's current consensus key to a new one.
+/// Both keys sign the same payload: the old key authorizes the change and
+/// the new key proves it is held by the validator.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyRotation {
+    /// Stable validator identity; stake stays keyed by it across rotations
+    #[serde(with = "hex_serde")]
+    pub validator: Address,
+    #[serde(with = "hex_serde")]
+    pub old_key: [u8; 32],
+    #[serde(with = "hex_serde")]
+    pub new_key: [u8; 32],
+    pub activation_height: u64,
+    #[serde(with = "hex_serde", default)]
+    pub old_signature: Vec<u8>,
+    #[serde(with = "hex_serde", default)]
+    pub new_signature: Vec<u8>,
+}
+
+impl KeyRotation {
+    pub fn new(validator: Address, old_key: [u8; 32], new_key: [u8; 32], activation_height: u64) -> Self {
+        Self {
+            validator,
+            old_key,
+            new_key,
+            activation_height,
+            old_signature: Vec::new(),
+            new_signature: Vec::new(),
+        }
+    }
+
+    /// Bytes both keys sign, bound to the main chain of `network_id`
+    pub fn signing_bytes(&self, network_id: u64) -> Vec<u8> {
+        let mut body = Vec::with_capacity(96);
+        body.extend_from_slice(&self.validator);
+        body.extend_from_slice(&self.old_key);
+        body.extend_from_slice(&self.new_key);
+        SigningDomain::main_chain(network_id).payload(PayloadKind::KeyRotation, self.activation_height, &body)
+    }
+
+    /// Sign with both keys; sets `old_key` and `new_key` from them
+    pub fn sign(&mut self, network_id: u64, old: &SigningKey, new: &SigningKey) {
+        self.old_key = old.verifying_key().to_bytes();
+        self.new_key = new.verifying_key().to_bytes();
+        let message = self.signing_bytes(network_id);
+        self.old_signature = old.sign(&message).to_bytes().to_vec();
+        self.new_signature = new.sign(&message).to_bytes().to_vec();
+    }
+
+    /// Check both handover signatures
+    pub fn verify(&self, network_id: u64) -> Result<(), &'static str> {
+        let message = self.signing_bytes(network_id);
+        verify_ed25519(&self.old_key, &message, &self.old_signature)
+            .map_err(|_| "Invalid old key signature")?;
+        verify_ed25519(&self.new_key, &message, &self.new_signature)
+            .map_err(|_| "Invalid new key signature")
+    }
+}
+
+fn verify_ed25519(key: &[u8; 32], message: &[u8], signature: &[u8]) -> Result<(), &'static str> {
+    let key = VerifyingKey::from_bytes(key).map_err(|_| "Invalid public key")?;
+    let signature: [u8; 64] = signature.try_into().map_err(|_| "Invalid signature length")?;
+    key.verify(message, &Signature::from_bytes(&signature))
+        .map_err(|_| "Invalid signature")
+}
+
+/// Validator Key Registry
+/// Every consensus key a validator has used, by activation height, so
+/// blocks can be checked against the key that was valid when they were made.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ValidatorKeys {
+    /// Epochs per validator, ordered by `active_from`
+    epochs: BTreeMap<Address, Vec<KeyEpoch>>,
+}
+
+impl ValidatorKeys {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a validator's first consensus key
+    pub fn register(&mut self, validator: Address, key: [u8; 32], active_from: u64) -> Result<(), &'static str> {
+        if self.epochs.contains_key(&validator) {
+            return Err("Validator already registered");
+        }
+        if self.owner_of(&key).is_some() {
+            return Err("Key already in use");
+        }
+        self.epochs.insert(validator, vec![KeyEpoch { active_from, key }]);
+        Ok(())
+    }
+
+    /// Key that signs for `validator` at `height`
+    pub fn key_at(&self, validator: &Address, height: u64) -> Option<[u8; 32]> {
+        self.epochs.get(validator)?
+            .iter()
+            .rev()
+            .find(|epoch| epoch.active_from <= height)
+            .map(|epoch| epoch.key)
+    }
+
+    /// Most recently scheduled key, which may not be active yet
+    pub fn latest_key(&self, validator: &Address) -> Option<[u8; 32]> {
+        self.epochs.get(validator)?.last().map(|epoch| epoch.key)
+    }
+
+    /// Every validator that has registered a key
+    pub fn validators(&self) -> impl Iterator<Item = &Address> {
+        self.epochs.keys()
+    }
+
+    pub fn history(&self, validator: &Address) -> &[KeyEpoch] {
+        self.epochs.get(validator).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Validator a key belongs or belonged to
+    pub fn owner_of(&self, key: &[u8; 32]) -> Option<Address> {
+        self.epochs.iter()
+            .find(|(_, epochs)| epochs.iter().any(|epoch| &epoch.key == key))
+            .map(|(validator, _)| *validator)
+    }
+
+    /// Verify a signature made by `validator` for the block at `height`
+    pub fn verify_at(&self, validator: &Address, height: u64, message: &[u8], signature: &[u8]) -> Result<(), &'static str> {
+        let key = self.key_at(validator, height).ok_or("No key for validator at height")?;
+        verify_ed25519(&key, message, signature)
+    }
+
+    /// Validate and schedule a rotation included in the block at `height`.
+    /// Earlier epochs are kept so history stays verifiable.
+    pub fn apply_rotation(&mut self, rotation: &KeyRotation, network_id: u64, height: u64) -> Result<(), &'static str> {
+        let epochs = self.epochs.get(&rotation.validator).ok_or("Unknown validator")?;
+        let latest = epochs.last().expect("registered validators have a key");
+        if latest.active_from > height {
+            return Err("Key rotation already pending");
+        }
+        if latest.key != rotation.old_key {
+            return Err("Old key is not the validator's current key");
+        }
+        if rotation.activation_height < height.saturating_add(MIN_ACTIVATION_DELAY) {
+            return Err("Activation height too soon");
+        }
+        if self.owner_of(&rotation.new_key).is_some() {
+            return Err("Key already in use");
+        }
+        rotation.verify(network_id)?;
+
+        self.epochs.get_mut(&rotation.validator)
+            .expect("checked above")
+            .push(KeyEpoch { active_from: rotation.activation_height, key: rotation.new_key });
+        Ok(())
+    }
+
+    /// Put a validator's epochs back to a journaled value
+    pub(crate) fn restore(&mut self, validator: Address, epochs: Option<Vec<KeyEpoch>>) {
+        match epochs {
+            Some(epochs) => { self.epochs.insert(validator, epochs); }
+            None => { self.epochs.remove(&validator); }
+        }
+    }
+
+    pub(crate) fn epochs(&self, validator: &Address) -> Option<Vec<KeyEpoch>> {
+        self.epochs.get(validator).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NETWORK: u64 = 1;
+
+    #[test]
+    fn test_rotation_keeps_history_verifiable() {
+        let validator = [1u8; 32];
+        let old = SigningKey::from_bytes(&[2u8; 32]);
+        let new = SigningKey::from_bytes(&[3u8; 32]);
+        let mut keys = ValidatorKeys::new();
+        keys.register(validator, old.verifying_key().to_bytes(), 0).unwrap();
+
+        let mut rotation = KeyRotation::new(validator, [0u8; 32], [0u8; 32], 11);
+        rotation.sign(NETWORK, &old, &new);
+
+        // Signed for another network, or only by one key, it is rejected
+        assert_eq!(keys.apply_rotation(&rotation, 2, 5).unwrap_err(), "Invalid old key signature");
+        let mut forged = rotation.clone();
+        forged.new_signature = forged.old_signature.clone();
+        assert_eq!(keys.apply_rotation(&forged, NETWORK, 5).unwrap_err(), "Invalid new key signature");
+        assert_eq!(keys.apply_rotation(&rotation, NETWORK, 10).unwrap_err(), "Activation height too soon");
+
+        keys.apply_rotation(&rotation, NETWORK, 5).unwrap();
+        assert_eq!(keys.apply_rotation(&rotation, NETWORK, 6).unwrap_err(), "Key rotation already pending");
+
+        // Blocks before activation verify against the old key, later ones the new
+        let message = b"block 10";
+        let old_sig = old.sign(message).to_bytes();
+        let new_sig = new.sign(message).to_bytes();
+        assert!(keys.verify_at(&validator, 10, message, &old_sig).is_ok());
+        assert!(keys.verify_at(&validator, 10, message, &new_sig).is_err());
+        assert!(keys.verify_at(&validator, 11, message, &new_sig).is_ok());
+        assert!(keys.verify_at(&validator, 11, message, &old_sig).is_err());
+        assert_eq!(keys.history(&validator).len(), 2);
+        assert_eq!(keys.owner_of(&old.verifying_key().to_bytes()), Some(validator));
+
+        // A retired key cannot be rotated to again
+        let mut back = KeyRotation::new(validator, [0u8; 32], [0u8; 32], 20);
+        back.sign(NETWORK, &new, &old);
+        assert_eq!(keys.apply_rotation(&back, NETWORK, 12).unwrap_err(), "Key already in use");
+    }
+}
@@ -0,0 +1,130 @@
+//! Block validation verdicts, for comparing node versions.
+//!
+//! A `ValidationCase` is a block together with the genesis block and
+//! balances it is validated on, in encodings that stay stable across
+//! versions: blocks in the wire layout and balances as plain pairs.
+//! `validate` takes the case through the steps a syncing node takes,
+//! execution and then import, and returns the node's `Verdict`.
+//!
+//! The `validate-blocks` command serves verdicts over stdin and stdout, one
+//! JSON line each way. The differential fuzz target asks a pinned older
+//! binary for its verdict on every block it generates and flags any block
+//! the two versions disagree on, since that block would split the network
+//! after an upgrade.
+
+use serde::{Serialize, Deserialize};
+use crate::blockchain::core::{Block, Blockchain};
+use crate::blockchain::execution::Executor;
+use crate::blockchain::state::WorldState;
+use crate::blockchain::store::{ChainStore, MemoryChainStore};
+use crate::blockchain::types::{hex_serde, hex_serde_option, Address};
+use crate::clock;
+use crate::storage::reindex::block_transactions;
+
+/// Funded account of a case's genesis state
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Balance {
+    #[serde(with = "hex_serde")]
+    pub address: Address,
+    pub amount: u128,
+}
+
+/// Block to validate on top of a genesis block
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValidationCase {
+    pub balances: Vec<Balance>,
+    /// Genesis block in its wire encoding
+    #[serde(with = "hex_serde")]
+    pub genesis: Vec<u8>,
+    /// Block 1 in its wire encoding
+    #[serde(with = "hex_serde")]
+    pub block: Vec<u8>,
+}
+
+/// Whether a node accepts a case's block, and the state it reaches
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Verdict {
+    pub accepted: bool,
+    /// Why the block was rejected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// State root after an accepted block
+    #[serde(default, with = "hex_serde_option", skip_serializing_if = "Option::is_none")]
+    pub state_root: Option<[u8; 32]>,
+    /// Receipts an accepted block produced
+    #[serde(default)]
+    pub receipts: usize,
+}
+
+impl Verdict {
+    /// Whether two nodes giving these verdicts would end up on different
+    /// chains. Rejection reasons may differ between versions without
+    /// splitting the network.
+    pub fn diverges(&self, other: &Verdict) -> bool {
+        self.accepted != other.accepted || self.state_root != other.state_root
+    }
+}
+
+/// Validate a case's block the way a syncing node would
+pub fn validate(case: &ValidationCase) -> Verdict {
+    match run(case) {
+        Ok((state_root, receipts)) => Verdict { accepted: true, reason: None, state_root: Some(state_root), receipts },
+        Err(reason) => Verdict { accepted: false, reason: Some(reason), state_root: None, receipts: 0 },
+    }
+}
+
+fn run(case: &ValidationCase) -> Result<([u8; 32], usize), String> {
+    let genesis = Block::from_bytes(&case.genesis).map_err(|e| format!("Genesis: {}", e))?;
+    let block = Block::from_bytes(&case.block)?;
+    let mut store = MemoryChainStore::new();
+    store.put_block(&genesis)?;
+    let mut chain = Blockchain::with_store(genesis.frc_proof.scale, Box::new(store), clock::system())?;
+    let balances: Vec<(Address, u128)> = case.balances.iter().map(|balance| (balance.address, balance.amount)).collect();
+    let mut state = WorldState::with_balances(&balances);
+
+    let receipts = Executor::apply_block(&mut state, &block_transactions(&block))?;
+    chain.import_block(block)?;
+    state.set_height(1);
+    Ok((state.state_root(), receipts.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::bloom::Bloom;
+    use crate::blockchain::transaction::{Transaction, TransactionAction};
+    use ed25519_dalek::SigningKey;
+
+    #[test]
+    fn test_verdicts() {
+        let key = SigningKey::from_bytes(&[4; 32]);
+        let sender = key.verifying_key().to_bytes();
+        let mut tx = Transaction::new(sender, 0, TransactionAction::Transfer { to: [8; 32], amount: 25 }, 100_000, 1);
+        tx.sign(&key);
+        let mut chain = Blockchain::new(2);
+        chain.add_block_with_bloom(bincode::serialize(&vec![tx]).unwrap(), &Bloom::default()).unwrap();
+        let case = ValidationCase {
+            balances: vec![Balance { address: sender, amount: 1_000_000_000 }],
+            genesis: chain.block(0).unwrap().to_bytes(),
+            block: chain.block(1).unwrap().to_bytes(),
+        };
+
+        let accepted = validate(&case);
+        assert!(accepted.accepted, "{:?}", accepted.reason);
+        assert_eq!(accepted.receipts, 1);
+        let json = serde_json::to_string(&accepted).unwrap();
+        assert_eq!(serde_json::from_str::<Verdict>(&json).unwrap(), accepted);
+
+        // A node starting from other balances cannot agree with this one
+        let unfunded = ValidationCase { balances: Vec::new(), ..case.clone() };
+        assert!(validate(&unfunded).diverges(&accepted));
+
+        // A block whose data no longer matches its hash is rejected
+        let mut tampered = chain.block(1).unwrap().clone();
+        tampered.data.push(0);
+        let rejected = validate(&ValidationCase { block: tampered.to_bytes(), ..case });
+        assert!(!rejected.accepted);
+        assert!(rejected.diverges(&accepted));
+        assert!(!rejected.diverges(&Verdict { reason: Some("other reason".to_string()), ..rejected.clone() }));
+    }
+}
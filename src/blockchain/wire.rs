@@ -0,0 +1,354 @@
+use crate::blockchain::bloom::{Bloom, BLOOM_BYTES};
+use crate::blockchain::core::{compute_block_hash, Block, HeaderExtensions};
+use crate::blockchain::features::FeatureSet;
+use crate::math::precision::PreciseFloat;
+use bincode::Options;
+use serde::Deserialize;
+
+/// Layout version written as the first byte of every encoded block
+pub const WIRE_VERSION: u8 = 4;
+
+// Fixed-offset block layout (all integers little-endian):
+//   version u8 | index u64 | timestamp u128 | previous_hash [32]
+//   | 4 x (value i128, scale u8) | bloom [256] | has_beacon u8 | beacon [32]
+//   | signals u32 | hash [32] | data_len u32 | data
+const INDEX: usize = 1;
+const TIMESTAMP: usize = INDEX + 8;
+const PREVIOUS_HASH: usize = TIMESTAMP + 16;
+const METRICS: usize = PREVIOUS_HASH + 32;
+const METRIC_LEN: usize = 17;
+const BLOOM: usize = METRICS + 4 * METRIC_LEN;
+const BEACON: usize = BLOOM + BLOOM_BYTES;
+const SIGNALS: usize = BEACON + 1 + 32;
+const HASH: usize = SIGNALS + 4;
+const DATA_LEN: usize = HASH + 32;
+/// Bytes preceding the block data
+pub const BLOCK_HEADER_LEN: usize = DATA_LEN + 4;
+
+/// Encode a block into the fixed-offset wire layout with a single allocation
+pub fn encode_block(block: &Block) -> Vec<u8> {
+    let mut out = Vec::with_capacity(BLOCK_HEADER_LEN + block.data.len());
+    out.push(WIRE_VERSION);
+    out.extend_from_slice(&block.index.to_le_bytes());
+    out.extend_from_slice(&block.timestamp.to_le_bytes());
+    out.extend_from_slice(&block.previous_hash);
+    for metric in [&block.frc_proof, &block.s_physics, &block.ai_decision, &block.quantum_resistance] {
+        out.extend_from_slice(&metric.value.to_le_bytes());
+        out.push(metric.scale);
+    }
+    out.extend_from_slice(block.bloom.as_bytes());
+    out.push(block.beacon.is_some() as u8);
+    out.extend_from_slice(&block.beacon.unwrap_or_default());
+    out.extend_from_slice(&block.signals.bits().to_le_bytes());
+    out.extend_from_slice(&block.hash);
+    out.extend_from_slice(&(block.data.len() as u32).to_le_bytes());
+    out.extend_from_slice(&block.data);
+    out
+}
+
+/// Decode a block in the wire layout or, as nodes stored blocks before the
+/// layout existed, bincode of the original block fields
+pub fn decode_block(bytes: &[u8]) -> Result<Block, &'static str> {
+    match BlockView::parse(bytes) {
+        Ok(view) => Ok(view.to_block()),
+        Err(e) => decode_legacy(bytes).ok_or(e),
+    }
+}
+
+/// Block fields as bincode encoded them before `WIRE_VERSION`
+#[derive(Deserialize)]
+struct LegacyBlock {
+    index: u64,
+    timestamp: u128,
+    previous_hash: [u8; 32],
+    data: Vec<u8>,
+    frc_proof: PreciseFloat,
+    s_physics: PreciseFloat,
+    ai_decision: PreciseFloat,
+    quantum_resistance: PreciseFloat,
+    hash: [u8; 32],
+}
+
+fn decode_legacy(bytes: &[u8]) -> Option<Block> {
+    let legacy: LegacyBlock = bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .reject_trailing_bytes()
+        .deserialize(bytes)
+        .ok()?;
+    Some(Block {
+        index: legacy.index,
+        timestamp: legacy.timestamp,
+        previous_hash: legacy.previous_hash,
+        data: legacy.data,
+        frc_proof: legacy.frc_proof,
+        s_physics: legacy.s_physics,
+        ai_decision: legacy.ai_decision,
+        quantum_resistance: legacy.quantum_resistance,
+        bloom: Bloom::default(),
+        beacon: None,
+        signals: FeatureSet::default(),
+        hash: legacy.hash,
+    })
+}
+
+/// Borrowed view over an encoded block.
+/// Fields are read in place, so hashing and relaying a block never copies its data.
+#[derive(Debug, Clone, Copy)]
+pub struct BlockView<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> BlockView<'a> {
+    /// Validate the layout; no field is copied
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, &'static str> {
+        if bytes.len() < BLOCK_HEADER_LEN {
+            return Err("Encoded block too short");
+        }
+        if bytes[0] != WIRE_VERSION {
+            return Err("Unsupported block encoding version");
+        }
+        let view = Self { bytes };
+        if bytes.len() != BLOCK_HEADER_LEN + view.data_len() {
+            return Err("Encoded block length mismatch");
+        }
+        if bytes[BEACON] > 1 {
+            return Err("Invalid beacon flag");
+        }
+        Ok(view)
+    }
+
+    fn array<const N: usize>(&self, offset: usize) -> &'a [u8; N] {
+        self.bytes[offset..offset + N].try_into().expect("length checked in parse")
+    }
+
+    fn data_len(&self) -> usize {
+        u32::from_le_bytes(*self.array(DATA_LEN)) as usize
+    }
+
+    pub fn index(&self) -> u64 {
+        u64::from_le_bytes(*self.array(INDEX))
+    }
+
+    pub fn timestamp(&self) -> u128 {
+        u128::from_le_bytes(*self.array(TIMESTAMP))
+    }
+
+    pub fn previous_hash(&self) -> &'a [u8; 32] {
+        self.array(PREVIOUS_HASH)
+    }
+
+    /// Header bloom, checkable without decoding the rest of the block
+    pub fn bloom(&self) -> Bloom {
+        Bloom::from_bytes(*self.array(BLOOM))
+    }
+
+    /// Randomness beacon output, present on the first block of each epoch
+    pub fn beacon(&self) -> Option<&'a [u8; 32]> {
+        (self.bytes[BEACON] == 1).then(|| self.array(BEACON + 1))
+    }
+
+    /// Feature readiness signaled by the producer
+    pub fn signals(&self) -> FeatureSet {
+        FeatureSet::from_bits(u32::from_le_bytes(*self.array(SIGNALS)))
+    }
+
+    pub fn hash(&self) -> &'a [u8; 32] {
+        self.array(HASH)
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        &self.bytes[BLOCK_HEADER_LEN..]
+    }
+
+    /// Raw value of the n-th metric (frc_proof, s_physics, ai_decision, quantum_resistance)
+    fn metric_value(&self, n: usize) -> i128 {
+        i128::from_le_bytes(*self.array(METRICS + n * METRIC_LEN))
+    }
+
+    fn metric(&self, n: usize) -> PreciseFloat {
+        PreciseFloat {
+            value: self.metric_value(n),
+            scale: self.bytes[METRICS + n * METRIC_LEN + 16],
+        }
+    }
+
+    /// Check the stored hash without decoding the block
+    pub fn verify_hash(&self) -> bool {
+        let values = [0, 1, 2, 3].map(|n| self.metric_value(n));
+        *self.hash() == compute_block_hash(
+            self.index(),
+            self.timestamp(),
+            self.previous_hash(),
+            self.data(),
+            values,
+            &self.bloom(),
+            HeaderExtensions { beacon: self.beacon(), signals: self.signals() },
+        )
+    }
+
+    /// Copy into an owned `Block`
+    pub fn to_block(&self) -> Block {
+        Block {
+            index: self.index(),
+            timestamp: self.timestamp(),
+            previous_hash: *self.previous_hash(),
+            data: self.data().to_vec(),
+            frc_proof: self.metric(0),
+            s_physics: self.metric(1),
+            ai_decision: self.metric(2),
+            quantum_resistance: self.metric(3),
+            bloom: self.bloom(),
+            beacon: self.beacon().copied(),
+            signals: self.signals(),
+            hash: *self.hash(),
+        }
+    }
+}
+
+/// Binary P2P frame types
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MessageKind {
+    Block = 1,
+    Transaction = 2,
+    /// Another frame or a JSON message, compressed (`network::compression`)
+    Compressed = 3,
+}
+
+impl TryFrom<u8> for MessageKind {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::Block),
+            2 => Ok(Self::Transaction),
+            3 => Ok(Self::Compressed),
+            _ => Err("Unknown message kind"),
+        }
+    }
+}
+
+/// Frame a payload as `kind u8 | payload`
+pub fn encode_message(kind: MessageKind, payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(1 + payload.len());
+    out.push(kind as u8);
+    out.extend_from_slice(payload);
+    out
+}
+
+/// Borrowed view over a binary P2P frame
+#[derive(Debug, Clone, Copy)]
+pub struct MessageView<'a> {
+    pub kind: MessageKind,
+    pub payload: &'a [u8],
+}
+
+impl<'a> MessageView<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, &'static str> {
+        let (&kind, payload) = bytes.split_first().ok_or("Empty message")?;
+        Ok(Self { kind: MessageKind::try_from(kind)?, payload })
+    }
+
+    /// View the payload as a block
+    pub fn block(&self) -> Result<BlockView<'a>, &'static str> {
+        if self.kind != MessageKind::Block {
+            return Err("Message is not a block");
+        }
+        BlockView::parse(self.payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Block {
+        Block::new(
+            7,
+            [3u8; 32],
+            b"payload".to_vec(),
+            PreciseFloat::new(95, 2),
+            PreciseFloat::new(-4, 3),
+            PreciseFloat::new(1, 1),
+            PreciseFloat::new(99, 2),
+        )
+    }
+
+    #[test]
+    fn test_view_round_trip() {
+        let block = sample();
+        let encoded = encode_block(&block);
+        let view = BlockView::parse(&encoded).unwrap();
+
+        assert_eq!(view.index(), 7);
+        assert_eq!(view.data(), b"payload");
+        assert!(view.verify_hash());
+        // The view borrows the data straight out of the input buffer
+        assert_eq!(view.data().as_ptr(), encoded[BLOCK_HEADER_LEN..].as_ptr());
+
+        let decoded = view.to_block();
+        assert_eq!(decoded.hash, block.hash);
+        assert_eq!(decoded.s_physics, block.s_physics);
+        assert!(decoded.verify_hash());
+
+        let epoch_block = sample().with_beacon([9; 32]);
+        let encoded = encode_block(&epoch_block);
+        let view = BlockView::parse(&encoded).unwrap();
+        assert_eq!(view.beacon(), Some(&[9; 32]));
+        assert!(view.verify_hash());
+        assert_ne!(epoch_block.hash, block.hash);
+
+        let signaling = sample().with_signals(FeatureSet::from_bits(0b101));
+        let view_bytes = encode_block(&signaling);
+        let view = BlockView::parse(&view_bytes).unwrap();
+        assert_eq!(view.signals().bits(), 0b101);
+        assert!(view.verify_hash());
+        assert_eq!(view.to_block().signals, signaling.signals);
+        assert_ne!(signaling.hash, block.hash);
+    }
+
+    #[test]
+    fn test_rejects_malformed_frames() {
+        let mut encoded = encode_block(&sample());
+        encoded[BLOCK_HEADER_LEN] ^= 0xFF;
+        assert!(!BlockView::parse(&encoded).unwrap().verify_hash());
+
+        encoded.pop();
+        assert_eq!(BlockView::parse(&encoded).unwrap_err(), "Encoded block length mismatch");
+
+        let frame = encode_message(MessageKind::Transaction, b"tx");
+        let message = MessageView::parse(&frame).unwrap();
+        assert_eq!(message.payload, b"tx");
+        assert!(message.block().is_err());
+        assert!(MessageView::parse(&[9]).is_err());
+    }
+
+    #[test]
+    fn test_decodes_legacy_bincode_blocks() {
+        let block = sample();
+        let legacy = bincode::serialize(&(
+            block.index,
+            block.timestamp,
+            block.previous_hash,
+            &block.data,
+            &block.frc_proof,
+            &block.s_physics,
+            &block.ai_decision,
+            &block.quantum_resistance,
+            block.hash,
+        ))
+        .unwrap();
+        assert!(BlockView::parse(&legacy).is_err());
+
+        let decoded = decode_block(&legacy).unwrap();
+        assert_eq!(decoded.index, 7);
+        assert_eq!(decoded.data, b"payload");
+        assert_eq!(decoded.s_physics, block.s_physics);
+        assert_eq!(decoded.hash, block.hash);
+        assert_eq!(decoded.beacon, None);
+        // Written back, a legacy block takes the current layout
+        assert_eq!(Block::from_bytes(&decoded.to_bytes()).unwrap().hash, block.hash);
+
+        assert_eq!(decode_block(&legacy[..legacy.len() - 1]).unwrap_err(), "Encoded block too short");
+    }
+}
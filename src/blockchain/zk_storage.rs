@@ -1,5 +1,6 @@
 use crate::math::precision::PreciseFloat;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use crate::ids::DataId;
 
 /// ZK-Layered Storage Implementation
 #[allow(dead_code)]
@@ -10,7 +11,6 @@ pub struct ZKStorage {
     index_tree: IndexNode,
 }
 
-type DataId = [u8; 32];
 
 #[allow(dead_code)]
 struct StorageLayer {
@@ -29,12 +29,39 @@ pub struct ZKProof {
     layer_signature: [u8; 32],
 }
 
+/// Bytes of the data ID used as tree path; leaves sit at this depth
+const INDEX_DEPTH: usize = 4;
+
+// Domain separation for index tree hashes
+const CHILD_ENTRY: u8 = 0x00;
+const PAIR: u8 = 0x01;
+const DATA_ENTRY: u8 = 0x02;
+
 struct IndexNode {
-    children: HashMap<u8, IndexNode>,
+    /// Ordered so every node hashes its children deterministically
+    children: BTreeMap<u8, IndexNode>,
+    /// Sorted data IDs (leaf nodes only)
     data_ids: Vec<DataId>,
     merkle_root: [u8; 32],
 }
 
+/// Merkle branch for one tree level
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProofLevel {
+    /// Position of the entry among the node's entries
+    pub index: usize,
+    /// Number of entries in the node
+    pub count: usize,
+    pub branch: Vec<[u8; 32]>,
+}
+
+/// Proof that a data ID is in the index tree, leaf level first
+#[derive(Debug, Clone, PartialEq)]
+pub struct InclusionProof {
+    pub id: DataId,
+    pub levels: Vec<ProofLevel>,
+}
+
 impl ZKStorage {
     pub fn new(precision: u8) -> Self {
         Self {
@@ -95,7 +122,11 @@ impl ZKStorage {
         for layer in &self.data_layers {
             if let Some(data) = layer.data.get(id) {
                 if layer.verify_proof(proof) {
-                    return Ok(data.clone());
+                    #[allow(unused_mut)]
+                    let mut data = data.clone();
+                    #[cfg(feature = "fault-injection")]
+                    crate::chaos::corrupt(crate::chaos::STORAGE_READ, &mut data);
+                    return Ok(data);
                 }
             }
         }
@@ -120,56 +151,64 @@ impl ZKStorage {
         // In a real implementation, this would use a cryptographic hash
         let mut id = [0u8; 32];
         id[..data.len().min(32)].copy_from_slice(&data[..data.len().min(32)]);
-        id
+        DataId::new(id)
     }
 
     fn update_index(&mut self, id: &DataId, _layer: u8) {
-        let mut current = &mut self.index_tree;
-        
-        // Update tree structure
-        for &byte in &id[..4] { // Use first 4 bytes for tree structure
-            current = current.children.entry(byte).or_insert(IndexNode::new());
+        // Only nodes on the insertion path are rehashed
+        self.index_tree.insert(id, 0);
+    }
+
+    /// Root of the index tree over all stored data IDs
+    pub fn merkle_root(&self) -> [u8; 32] {
+        self.index_tree.merkle_root
+    }
+
+    /// Build an inclusion proof for a stored data ID
+    pub fn inclusion_proof(&self, id: &DataId) -> Option<InclusionProof> {
+        let mut path = Vec::with_capacity(INDEX_DEPTH);
+        let mut node = &self.index_tree;
+        for &byte in &id[..INDEX_DEPTH] {
+            path.push(node);
+            node = node.children.get(&byte)?;
         }
 
-        // Add data ID to leaf node
-        if !current.data_ids.contains(id) {
-            current.data_ids.push(*id);
+        let leaf_index = node.data_ids.binary_search(id).ok()?;
+        let mut levels = vec![ProofLevel {
+            index: leaf_index,
+            count: node.data_ids.len(),
+            branch: merkle_branch(&node.entries(), leaf_index),
+        }];
+
+        for (depth, parent) in path.iter().enumerate().rev() {
+            let index = parent.children.keys().position(|&byte| byte == id[depth])?;
+            levels.push(ProofLevel {
+                index,
+                count: parent.children.len(),
+                branch: merkle_branch(&parent.entries(), index),
+            });
         }
 
-        // Update Merkle roots
-        self.update_merkle_roots();
+        Some(InclusionProof { id: *id, levels })
     }
 
-    fn update_merkle_roots(&mut self) {
-        // Update Merkle roots in tree
-        let mut index_tree = IndexNode::new();
-        self.update_node_merkle_root(&mut index_tree);
-        self.index_tree = index_tree;
-    }
+    /// Check an inclusion proof against an index root
+    pub fn verify_inclusion(root: &[u8; 32], proof: &InclusionProof) -> bool {
+        if proof.levels.len() != INDEX_DEPTH + 1 {
+            return false;
+        }
 
-    fn update_node_merkle_root(&self, node: &mut IndexNode) -> [u8; 32] {
-        if node.children.is_empty() {
-            // Leaf node - hash data IDs
-            let mut hash = [0u8; 32];
-            for id in &node.data_ids {
-                for i in 0..32 {
-                    hash[i] ^= id[i];
-                }
+        let mut hash = data_entry(&proof.id);
+        for (level, step) in proof.levels.iter().enumerate() {
+            if level > 0 {
+                hash = child_entry(proof.id[INDEX_DEPTH - level], &hash);
             }
-            node.merkle_root = hash;
-            hash
-        } else {
-            // Internal node - combine child hashes
-            let mut hash = [0u8; 32];
-            for (_, child) in &mut node.children {
-                let child_hash = self.update_node_merkle_root(child);
-                for i in 0..32 {
-                    hash[i] ^= child_hash[i];
-                }
+            match root_from_branch(hash, step) {
+                Some(node_root) => hash = node_root,
+                None => return false,
             }
-            node.merkle_root = hash;
-            hash
         }
+        hash == *root
     }
 }
 
@@ -192,7 +231,7 @@ impl StorageLayer {
                 .duration_since(std::time::UNIX_EPOCH)
                 .unwrap()
                 .as_secs(),
-            layer_signature: *id,
+            layer_signature: *id.as_bytes(),
         }
     }
 
@@ -207,9 +246,158 @@ impl StorageLayer {
 impl IndexNode {
     fn new() -> Self {
         Self {
-            children: HashMap::new(),
+            children: BTreeMap::new(),
             data_ids: Vec::new(),
             merkle_root: [0u8; 32],
         }
     }
+
+    /// Insert below this node and rehash it on the way back up
+    fn insert(&mut self, id: &DataId, depth: usize) {
+        if depth == INDEX_DEPTH {
+            if let Err(position) = self.data_ids.binary_search(id) {
+                self.data_ids.insert(position, *id);
+            }
+        } else {
+            self.children.entry(id[depth]).or_insert_with(IndexNode::new).insert(id, depth + 1);
+        }
+        self.merkle_root = merkle_root(&self.entries());
+    }
+
+    /// Leaf hashes this node commits to
+    fn entries(&self) -> Vec<[u8; 32]> {
+        if self.children.is_empty() {
+            self.data_ids.iter().map(data_entry).collect()
+        } else {
+            self.children.iter().map(|(&byte, child)| child_entry(byte, &child.merkle_root)).collect()
+        }
+    }
+}
+
+fn data_entry(id: &DataId) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[DATA_ENTRY]);
+    hasher.update(id.as_bytes());
+    hasher.finalize().into()
+}
+
+fn child_entry(byte: u8, root: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[CHILD_ENTRY, byte]);
+    hasher.update(root);
+    hasher.finalize().into()
+}
+
+fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[PAIR]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// Binary Merkle root; an unpaired last node is promoted unchanged
+fn merkle_root(leaves: &[[u8; 32]]) -> [u8; 32] {
+    if leaves.is_empty() {
+        return [0u8; 32];
+    }
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = level.chunks(2)
+            .map(|pair| match pair {
+                [left, right] => hash_pair(left, right),
+                [single] => *single,
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+    level[0]
+}
+
+fn merkle_branch(leaves: &[[u8; 32]], mut index: usize) -> Vec<[u8; 32]> {
+    let mut branch = Vec::new();
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        let sibling = index ^ 1;
+        if sibling < level.len() {
+            branch.push(level[sibling]);
+        }
+        level = level.chunks(2)
+            .map(|pair| match pair {
+                [left, right] => hash_pair(left, right),
+                [single] => *single,
+                _ => unreachable!(),
+            })
+            .collect();
+        index /= 2;
+    }
+    branch
+}
+
+fn root_from_branch(leaf: [u8; 32], step: &ProofLevel) -> Option<[u8; 32]> {
+    if step.index >= step.count {
+        return None;
+    }
+    let (mut hash, mut index, mut count) = (leaf, step.index, step.count);
+    let mut siblings = step.branch.iter();
+    while count > 1 {
+        if index ^ 1 < count {
+            let sibling = siblings.next()?;
+            hash = if index % 2 == 0 { hash_pair(&hash, sibling) } else { hash_pair(sibling, &hash) };
+        }
+        index /= 2;
+        count = count.div_ceil(2);
+    }
+    siblings.next().is_none().then_some(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(i: u32) -> Vec<u8> {
+        blake3::hash(&i.to_le_bytes()).as_bytes().to_vec()
+    }
+
+    #[test]
+    fn test_root_is_insertion_order_independent() {
+        let mut forward = ZKStorage::new(20);
+        let mut backward = ZKStorage::new(20);
+        for i in 0..200 {
+            forward.store_data(data(i), 0).unwrap();
+        }
+        for i in (0..200).rev() {
+            backward.store_data(data(i), 0).unwrap();
+        }
+        assert_ne!(forward.merkle_root(), [0u8; 32]);
+        assert_eq!(forward.merkle_root(), backward.merkle_root());
+    }
+
+    #[test]
+    fn test_inclusion_proofs() {
+        let mut storage = ZKStorage::new(20);
+        let ids: Vec<DataId> = (0..300)
+            .map(|i| storage.store_data(data(i), 0).unwrap().0)
+            .collect();
+        let root = storage.merkle_root();
+
+        for id in ids.iter().step_by(37) {
+            let proof = storage.inclusion_proof(id).unwrap();
+            assert!(ZKStorage::verify_inclusion(&root, &proof));
+
+            let mut forged = proof.clone();
+            let mut bytes = *forged.id.as_bytes();
+            bytes[31] ^= 1;
+            forged.id = DataId::new(bytes);
+            assert!(!ZKStorage::verify_inclusion(&root, &forged));
+        }
+
+        assert!(storage.inclusion_proof(&DataId::new([0xFF; 32])).is_none());
+
+        // Proofs track the root as new data arrives
+        storage.store_data(data(1_000), 0).unwrap();
+        let proof = storage.inclusion_proof(&ids[0]).unwrap();
+        assert!(ZKStorage::verify_inclusion(&storage.merkle_root(), &proof));
+        assert!(!ZKStorage::verify_inclusion(&root, &proof));
+    }
 }
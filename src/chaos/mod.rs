@@ -0,0 +1,275 @@
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use rand::Rng;
+
+/// Inbound P2P message handling
+pub const P2P_MESSAGE: &str = "p2p.message";
+/// Reads from ZK storage layers
+pub const STORAGE_READ: &str = "storage.read";
+/// Tally hash computation
+pub const TALLY_COMPUTE: &str = "tally.compute";
+/// RPC request handling
+pub const RPC_REQUEST: &str = "rpc.request";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FaultAction {
+    /// Silently discard the message or operation
+    Drop,
+    /// Flip bits in the payload
+    Corrupt,
+    /// Sleep for the given number of milliseconds
+    Delay(u64),
+    /// Panic the calling task
+    Crash,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FaultRule {
+    pub point: String,
+    pub action: FaultAction,
+    /// Probability in [0, 1] that an eligible hit triggers the fault
+    pub probability: f64,
+    /// Number of hits to let through before the rule becomes eligible
+    #[serde(default)]
+    pub skip: u64,
+    /// Maximum number of times the rule may trigger
+    #[serde(default)]
+    pub limit: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FaultConfig {
+    pub rules: Vec<FaultRule>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct RuleCounters {
+    hits: u64,
+    triggered: u64,
+}
+
+/// Chaos / Fault-Injection Framework
+/// Lets the simnet and staging operators drop, corrupt, delay, or crash
+/// subsystems at named points to exercise recovery paths.
+pub struct FaultInjector {
+    rules: Vec<FaultRule>,
+    counters: Vec<RuleCounters>,
+    enabled: bool,
+}
+
+impl FaultConfig {
+    /// Load a fault configuration from a JSON file
+    pub fn load(path: &str) -> Result<Self, String> {
+        let raw = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read fault config {}: {}", path, e))?;
+        serde_json::from_str(&raw)
+            .map_err(|e| format!("Invalid fault config {}: {}", path, e))
+    }
+}
+
+impl FaultInjector {
+    pub fn new(config: FaultConfig) -> Self {
+        let counters = vec![RuleCounters::default(); config.rules.len()];
+        Self {
+            rules: config.rules,
+            counters,
+            enabled: true,
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Record a hit on `point` and return the action to apply, if any
+    pub fn evaluate(&mut self, point: &str) -> Option<FaultAction> {
+        if !self.enabled {
+            return None;
+        }
+
+        let mut rng = rand::thread_rng();
+        for (rule, counters) in self.rules.iter().zip(self.counters.iter_mut()) {
+            if rule.point != point {
+                continue;
+            }
+
+            counters.hits += 1;
+            if counters.hits <= rule.skip {
+                continue;
+            }
+            if let Some(limit) = rule.limit {
+                if counters.triggered >= limit {
+                    continue;
+                }
+            }
+
+            if rng.gen::<f64>() < rule.probability {
+                counters.triggered += 1;
+                return Some(rule.action.clone());
+            }
+        }
+
+        None
+    }
+
+    /// Number of times faults have triggered at each point
+    pub fn trigger_counts(&self) -> HashMap<String, u64> {
+        let mut counts = HashMap::new();
+        for (rule, counters) in self.rules.iter().zip(self.counters.iter()) {
+            *counts.entry(rule.point.clone()).or_insert(0) += counters.triggered;
+        }
+        counts
+    }
+}
+
+fn injector() -> &'static Mutex<FaultInjector> {
+    static INJECTOR: OnceLock<Mutex<FaultInjector>> = OnceLock::new();
+    INJECTOR.get_or_init(|| Mutex::new(FaultInjector::new(FaultConfig::default())))
+}
+
+/// Replace the process-wide fault configuration
+pub fn install(config: FaultConfig) {
+    *injector().lock().unwrap() = FaultInjector::new(config);
+}
+
+/// Evaluate the process-wide injector at `point`
+pub fn evaluate(point: &str) -> Option<FaultAction> {
+    injector().lock().unwrap().evaluate(point)
+}
+
+/// Returns true if the message at `point` should be dropped.
+/// Delay and crash actions are applied in place; a delay blocks the calling
+/// thread, so async tasks use `should_drop_async`.
+pub fn should_drop(point: &str) -> bool {
+    match evaluate(point) {
+        Some(FaultAction::Drop) => true,
+        Some(action) => {
+            apply_blocking(point, action);
+            false
+        }
+        None => false,
+    }
+}
+
+/// Corrupt `data` in place if a corrupt rule triggers at `point`
+pub fn corrupt(point: &str, data: &mut [u8]) {
+    match evaluate(point) {
+        Some(FaultAction::Corrupt) => flip_bits(data),
+        Some(action) => apply_blocking(point, action),
+        None => {}
+    }
+}
+
+/// Apply delay or crash rules at `point` (drop and corrupt are ignored).
+/// A delay blocks the calling thread; async tasks use `checkpoint_async`.
+pub fn checkpoint(point: &str) {
+    if let Some(action) = evaluate(point) {
+        apply_blocking(point, action);
+    }
+}
+
+/// `should_drop` for async tasks: a delay sleeps only the faulted task
+pub async fn should_drop_async(point: &str) -> bool {
+    match evaluate(point) {
+        Some(FaultAction::Drop) => true,
+        Some(action) => {
+            apply(point, action).await;
+            false
+        }
+        None => false,
+    }
+}
+
+/// `checkpoint` for async tasks: a delay sleeps only the faulted task
+pub async fn checkpoint_async(point: &str) {
+    if let Some(action) = evaluate(point) {
+        apply(point, action).await;
+    }
+}
+
+async fn apply(point: &str, action: FaultAction) {
+    match action {
+        FaultAction::Delay(ms) => tokio::time::sleep(Duration::from_millis(ms)).await,
+        FaultAction::Crash => panic!("fault injected: crash at {}", point),
+        FaultAction::Drop | FaultAction::Corrupt => {}
+    }
+}
+
+fn apply_blocking(point: &str, action: FaultAction) {
+    match action {
+        FaultAction::Delay(ms) => std::thread::sleep(Duration::from_millis(ms)),
+        FaultAction::Crash => panic!("fault injected: crash at {}", point),
+        FaultAction::Drop | FaultAction::Corrupt => {}
+    }
+}
+
+fn flip_bits(data: &mut [u8]) {
+    if data.is_empty() {
+        return;
+    }
+    let index = rand::thread_rng().gen_range(0..data.len());
+    data[index] ^= 0xFF;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(point: &str, action: FaultAction, probability: f64) -> FaultRule {
+        FaultRule {
+            point: point.to_string(),
+            action,
+            probability,
+            skip: 0,
+            limit: None,
+        }
+    }
+
+    #[test]
+    fn test_rule_matching() {
+        let mut injector = FaultInjector::new(FaultConfig {
+            rules: vec![rule(P2P_MESSAGE, FaultAction::Drop, 1.0)],
+        });
+
+        assert_eq!(injector.evaluate(P2P_MESSAGE), Some(FaultAction::Drop));
+        assert_eq!(injector.evaluate(STORAGE_READ), None);
+
+        injector.set_enabled(false);
+        assert_eq!(injector.evaluate(P2P_MESSAGE), None);
+    }
+
+    #[test]
+    fn test_skip_and_limit() {
+        let mut faulty = rule(TALLY_COMPUTE, FaultAction::Delay(1), 1.0);
+        faulty.skip = 2;
+        faulty.limit = Some(1);
+        let mut injector = FaultInjector::new(FaultConfig { rules: vec![faulty] });
+
+        assert_eq!(injector.evaluate(TALLY_COMPUTE), None);
+        assert_eq!(injector.evaluate(TALLY_COMPUTE), None);
+        assert_eq!(injector.evaluate(TALLY_COMPUTE), Some(FaultAction::Delay(1)));
+        assert_eq!(injector.evaluate(TALLY_COMPUTE), None);
+        assert_eq!(injector.trigger_counts().get(TALLY_COMPUTE), Some(&1));
+    }
+
+    #[tokio::test]
+    async fn test_async_delay_frees_the_worker() {
+        // The test runtime has one thread, so the other task only runs if
+        // the delay yields it
+        let other = tokio::spawn(async {});
+        apply(P2P_MESSAGE, FaultAction::Delay(20)).await;
+        assert!(other.is_finished());
+    }
+
+    #[test]
+    fn test_zero_probability_never_triggers() {
+        let mut injector = FaultInjector::new(FaultConfig {
+            rules: vec![rule(STORAGE_READ, FaultAction::Corrupt, 0.0)],
+        });
+        for _ in 0..100 {
+            assert_eq!(injector.evaluate(STORAGE_READ), None);
+        }
+    }
+}
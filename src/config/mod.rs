@@ -0,0 +1,1424 @@
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use tokio::sync::watch;
+use crate::blockchain::beacon::BeaconParams;
+use crate::blockchain::features::{ActivationParams, Feature};
+use crate::blockchain::commit_reveal::CommitRevealConfig;
+use crate::blockchain::sealer::{BlockTimeConfig, MIN_BLOCK_INTERVAL_MS};
+use crate::consensus::{EngineKind, Timeouts};
+use crate::crypto::domain::MAINNET_NETWORK_ID;
+use crate::crypto::vdf;
+use crate::epoch::EpochSchedule;
+use crate::export::EventKind;
+use crate::math::precision::PreciseFloat;
+use crate::params::ParamKey;
+use crate::extensions::{self, Capability, MAX_EXTENSION_FUEL, MAX_EXTENSION_MEMORY};
+use crate::network::compression::Codec;
+use crate::network::listen::parse_endpoint;
+#[cfg(feature = "hubble")]
+use crate::hubble::index::MAX_SNIPPET_LENGTH;
+
+/// How much history a node keeps
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum NodeMode {
+    /// Retain all historical state and tally data
+    #[default]
+    Archive,
+    /// Retain state for the last `retain_blocks` blocks plus checkpoints
+    Pruned { retain_blocks: u64 },
+}
+
+impl NodeMode {
+    /// Lowest block height whose full state is still available at `height`
+    pub fn earliest_state(&self, height: u64) -> u64 {
+        match self {
+            NodeMode::Archive => 0,
+            NodeMode::Pruned { retain_blocks } => height.saturating_sub(*retain_blocks),
+        }
+    }
+
+    /// Blocks of state history kept behind the head; `None` keeps all of it
+    pub fn retained_blocks(&self) -> Option<u64> {
+        match self {
+            NodeMode::Archive => None,
+            NodeMode::Pruned { retain_blocks } => Some(*retain_blocks),
+        }
+    }
+}
+
+/// Certificate and key used to terminate TLS on the RPC port
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcTlsConfig {
+    pub cert_path: String,
+    pub key_path: String,
+}
+
+/// Permissioned P2P: only nodes certified by one of `authorities` may connect
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PermissionedConfig {
+    /// Hex ed25519 keys of the initial certificate authorities; governance
+    /// can add or remove authorities afterwards
+    pub authorities: Vec<String>,
+    /// JSON certificate issued to this node (see `cert issue`)
+    pub certificate_path: String,
+    /// Hex-encoded ed25519 secret key the certificate was issued for
+    pub node_key_path: String,
+}
+
+impl PermissionedConfig {
+    /// Parsed authority keys
+    pub fn authority_keys(&self) -> Result<Vec<[u8; 32]>, String> {
+        self.authorities.iter()
+            .map(|key| {
+                hex::decode(key.trim_start_matches("0x"))
+                    .ok()
+                    .and_then(|bytes| bytes.try_into().ok())
+                    .ok_or_else(|| format!("permissioned.authorities entry `{}` is not a 32-byte hex key", key))
+            })
+            .collect()
+    }
+}
+
+/// Role of a node in a validator/sentry deployment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SentryRole {
+    /// Validator that peers only with its own sentries
+    Validator,
+    /// Public-facing node that relays for the validators behind it
+    Sentry,
+}
+
+/// Validator/sentry topology: the validator stays off the public network
+/// and its sentries carry its blocks and votes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SentryConfig {
+    pub role: SentryRole,
+    /// P2P addresses (`ip:port`) of a validator's sentries, or of the
+    /// validators a sentry guards
+    pub private_peers: Vec<String>,
+    /// Seconds between health checks of the private peers
+    #[serde(default = "default_sentry_health_interval")]
+    pub health_interval_secs: u64,
+    /// Failed checks in a row before a private peer counts as down
+    #[serde(default = "default_sentry_max_missed")]
+    pub max_missed_checks: u32,
+}
+
+fn default_sentry_health_interval() -> u64 {
+    10
+}
+
+fn default_sentry_max_missed() -> u32 {
+    3
+}
+
+impl SentryConfig {
+    /// Parsed private peer addresses
+    pub fn private_addrs(&self) -> Result<Vec<SocketAddr>, String> {
+        self.private_peers.iter()
+            .map(|peer| peer.parse().map_err(|_| format!("sentry.private_peers entry `{}` is not an ip:port address", peer)))
+            .collect()
+    }
+}
+
+/// Message broker chain events are exported to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ExportSinkConfig {
+    /// NATS server, e.g. `nats://127.0.0.1:4222`
+    Nats { url: String },
+    /// Comma-separated Kafka bootstrap servers (needs the `kafka` feature)
+    Kafka { brokers: String },
+}
+
+/// Streaming of blocks, receipts, governance and tally events to a broker
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventExportConfig {
+    pub sink: ExportSinkConfig,
+    /// Topics are `<prefix>.blocks`, `<prefix>.receipts`, ...
+    #[serde(default = "default_topic_prefix")]
+    pub topic_prefix: String,
+    /// File holding the last fully exported block height
+    #[serde(default = "default_offset_path")]
+    pub offset_path: String,
+}
+
+fn default_topic_prefix() -> String {
+    "qmv".to_string()
+}
+
+fn default_offset_path() -> String {
+    "data/export.offset".to_string()
+}
+
+/// Kind of data mirrored to remote storage; each has its own backend and lifecycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DataClass {
+    /// Chain snapshots written at shutdown
+    Snapshots,
+    /// Database backups uploaded by operators
+    Backups,
+    /// Large blobs kept off-chain
+    Blobs,
+}
+
+impl DataClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DataClass::Snapshots => "snapshots",
+            DataClass::Backups => "backups",
+            DataClass::Blobs => "blobs",
+        }
+    }
+}
+
+impl std::str::FromStr for DataClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [DataClass::Snapshots, DataClass::Backups, DataClass::Blobs].into_iter()
+            .find(|class| class.as_str() == s)
+            .ok_or_else(|| format!("Unknown data class `{}` (snapshots, backups or blobs)", s))
+    }
+}
+
+/// Remote object store a data class is mirrored to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum RemoteBackendConfig {
+    /// S3 or an S3-compatible store, addressed path-style
+    S3 {
+        /// e.g. `https://s3.us-east-1.amazonaws.com` or a MinIO URL
+        endpoint: String,
+        bucket: String,
+        region: String,
+        /// Key prefix inside the bucket
+        #[serde(default)]
+        prefix: String,
+        /// Environment variables holding the credentials
+        #[serde(default = "default_access_key_env")]
+        access_key_env: String,
+        #[serde(default = "default_secret_key_env")]
+        secret_key_env: String,
+    },
+    /// IPFS node HTTP API, e.g. `http://127.0.0.1:5001`
+    Ipfs { api_url: String },
+    /// Local or mounted directory
+    Directory { path: String },
+}
+
+fn default_access_key_env() -> String {
+    "AWS_ACCESS_KEY_ID".to_string()
+}
+
+fn default_secret_key_env() -> String {
+    "AWS_SECRET_ACCESS_KEY".to_string()
+}
+
+/// When mirrored objects are deleted from the remote store
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LifecyclePolicy {
+    /// Keep only the newest objects
+    pub keep_last: Option<usize>,
+    /// Delete objects older than this
+    pub max_age_days: Option<u64>,
+}
+
+/// Remote storage for one data class
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RemoteClassConfig {
+    pub backend: RemoteBackendConfig,
+    #[serde(default)]
+    pub lifecycle: LifecyclePolicy,
+}
+
+/// How the block builder orders pending transactions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BuilderStrategy {
+    /// Highest gas price first
+    #[default]
+    FeeGreedy,
+    /// Round-robin across senders in arrival order
+    FairFifo,
+    /// Fee-greedy with `governance_boosts` applied
+    GovernanceBoosted,
+}
+
+impl BuilderStrategy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BuilderStrategy::FeeGreedy => "fee_greedy",
+            BuilderStrategy::FairFifo => "fair_fifo",
+            BuilderStrategy::GovernanceBoosted => "governance_boosted",
+        }
+    }
+}
+
+impl std::str::FromStr for BuilderStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [BuilderStrategy::FeeGreedy, BuilderStrategy::FairFifo, BuilderStrategy::GovernanceBoosted].into_iter()
+            .find(|strategy| strategy.as_str() == s)
+            .ok_or_else(|| format!("Unknown builder strategy `{}` (fee_greedy, fair_fifo or governance_boosted)", s))
+    }
+}
+
+/// Defaults for bundles handed to block builders
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BlockBuilderConfig {
+    pub strategy: BuilderStrategy,
+    /// Gas limit of a bundle
+    pub max_gas: u64,
+    /// Encoded size limit of a bundle
+    pub max_bytes: usize,
+    /// Ordering boost, in percent of gas price, for transactions from or to
+    /// these addresses (hex) under `governance_boosted`
+    pub governance_boosts: BTreeMap<String, u32>,
+}
+
+impl Default for BlockBuilderConfig {
+    fn default() -> Self {
+        Self {
+            strategy: BuilderStrategy::FeeGreedy,
+            max_gas: 30_000_000,
+            max_bytes: 1024 * 1024,
+            governance_boosts: BTreeMap::new(),
+        }
+    }
+}
+
+impl BlockBuilderConfig {
+    /// `governance_boosts` with decoded addresses
+    pub fn boosts(&self) -> Result<BTreeMap<[u8; 32], u32>, String> {
+        self.governance_boosts.iter()
+            .map(|(address, boost)| {
+                let bytes: [u8; 32] = hex::decode(address.trim_start_matches("0x"))
+                    .ok()
+                    .and_then(|bytes| bytes.try_into().ok())
+                    .ok_or_else(|| format!("block_builder.governance_boosts key `{}` is not a 32-byte hex address", address))?;
+                Ok((bytes, *boost))
+            })
+            .collect()
+    }
+}
+
+/// Hubble content search; ignored by nodes built without the `hubble`
+/// feature
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HubbleConfig {
+    /// Snippet length, in characters, when a search does not set one
+    pub snippet_length: usize,
+    /// Most results one search returns
+    pub max_results: usize,
+    /// On-disk index segments kept before adjacent ones are merged
+    pub max_segments: usize,
+}
+
+impl Default for HubbleConfig {
+    fn default() -> Self {
+        Self { snippet_length: 160, max_results: 50, max_segments: 8 }
+    }
+}
+
+/// Opt-in health reports to a telemetry collector
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TelemetryConfig {
+    /// Off unless the operator opts in (`telemetry enable`)
+    pub enabled: bool,
+    /// Collector URL reports are POSTed to
+    pub endpoint: String,
+    /// Average time between reports, in seconds
+    pub interval_secs: u64,
+    /// Random spread applied to each interval, in percent
+    pub jitter_percent: u8,
+    /// Undelivered reports kept for retry; the oldest are dropped first
+    pub buffer_limit: usize,
+    /// Also upload crash reports to `<endpoint>/crashes`
+    pub send_crash_reports: bool,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: "https://telemetry.metaverse.network/v1/reports".to_string(),
+            interval_secs: 900,
+            jitter_percent: 20,
+            buffer_limit: 96,
+            send_crash_reports: false,
+        }
+    }
+}
+
+/// When the post-block invariant suite runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InvariantMode {
+    /// In debug builds and on nodes with `sentry.role = "validator"`
+    Auto,
+    Always,
+    Off,
+}
+
+/// Invariant checks after each sealed block (`blockchain::invariants`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InvariantConfig {
+    pub mode: InvariantMode,
+    /// Directory diagnostic dumps of violations are written to
+    pub dump_dir: String,
+}
+
+impl Default for InvariantConfig {
+    fn default() -> Self {
+        Self {
+            mode: InvariantMode::Auto,
+            dump_dir: "data/invariants".to_string(),
+        }
+    }
+}
+
+/// Gossip of tally observations (`network::observations`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ObservationConfig {
+    /// Observations accepted per second from each observer
+    pub max_per_observer: u32,
+    /// How often the per-layer batches are forwarded
+    pub flush_interval_ms: u64,
+}
+
+impl Default for ObservationConfig {
+    fn default() -> Self {
+        Self {
+            max_per_observer: 4,
+            flush_interval_ms: 500,
+        }
+    }
+}
+
+/// Autonomous system, and optionally country, an operator assigns to an
+/// address prefix
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AsnPrefix {
+    /// CIDR prefix, e.g. `203.0.113.0/24` or `2001:db8::/32`
+    pub prefix: String,
+    pub asn: u32,
+    /// ISO 3166 country code
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+}
+
+impl AsnPrefix {
+    /// Parsed network address and prefix length
+    pub fn network(&self) -> Result<(IpAddr, u8), String> {
+        let invalid = || format!("diversity.asn_prefixes entry `{}` is not a CIDR prefix", self.prefix);
+        let (address, len) = self.prefix.split_once('/').ok_or_else(invalid)?;
+        let address: IpAddr = address.parse().map_err(|_| invalid())?;
+        let len: u8 = len.parse().map_err(|_| invalid())?;
+        let max = if address.is_ipv4() { 32 } else { 128 };
+        if len > max {
+            return Err(invalid());
+        }
+        Ok((address, len))
+    }
+}
+
+/// Spreading peers across networks (`network::diversity`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiversityConfig {
+    /// Peers allowed from one IPv4 /16 or IPv6 /32 once `min_peers` are connected
+    pub max_per_subnet: usize,
+    /// Peers allowed from one autonomous system once `min_peers` are connected
+    pub max_per_asn: usize,
+    /// Distinct subnets or autonomous systems the node aims to peer with
+    pub target_groups: usize,
+    /// Distinct countries the node aims to peer with; 0 for no target
+    pub target_countries: usize,
+    /// Known prefixes; peers outside them are grouped by subnet only
+    pub asn_prefixes: Vec<AsnPrefix>,
+}
+
+impl Default for DiversityConfig {
+    fn default() -> Self {
+        Self {
+            max_per_subnet: 2,
+            max_per_asn: 4,
+            target_groups: 8,
+            target_countries: 0,
+            asn_prefixes: Vec::new(),
+        }
+    }
+}
+
+/// Encrypted key files (`security::keystore`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeystoreConfig {
+    pub dir: String,
+    /// Name of the node's own key; generated on first start
+    pub node_key: String,
+    /// Encrypt keys with an empty passphrase when
+    /// `METAVERSE_KEYSTORE_PASSPHRASE` is unset, instead of refusing to start
+    pub allow_empty_passphrase: bool,
+}
+
+impl Default for KeystoreConfig {
+    fn default() -> Self {
+        Self {
+            dir: "data/keystore".to_string(),
+            node_key: "node".to_string(),
+            allow_empty_passphrase: false,
+        }
+    }
+}
+
+/// A domain publishing bootstrap peers (`network::seeds`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DnsSeed {
+    pub domain: String,
+    /// Hex ed25519 key; if set, only TXT records it signed are accepted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+}
+
+impl DnsSeed {
+    /// Parsed `public_key`
+    pub fn signer(&self) -> Result<Option<[u8; 32]>, String> {
+        self.public_key.as_ref()
+            .map(|key| {
+                hex::decode(key.trim_start_matches("0x"))
+                    .ok()
+                    .and_then(|bytes| bytes.try_into().ok())
+                    .ok_or_else(|| format!("seeds.dns key for `{}` is not a 32-byte hex key", self.domain))
+            })
+            .transpose()
+    }
+}
+
+/// Where a node finds its first peers
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SeedConfig {
+    /// Seed domains, tried in order
+    pub dns: Vec<DnsSeed>,
+    /// host:port peers tried after every DNS seed
+    pub bootstrap: Vec<String>,
+    /// Seconds between DNS lookups
+    pub refresh_secs: u64,
+}
+
+impl Default for SeedConfig {
+    fn default() -> Self {
+        Self {
+            dns: Vec::new(),
+            bootstrap: vec![
+                "bootnode1.metaverse.network:30303".to_string(),
+                "bootnode2.metaverse.network:30303".to_string(),
+            ],
+            refresh_secs: 1800,
+        }
+    }
+}
+
+/// State snapshot sync (`blockchain::snapshot`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FastSyncConfig {
+    /// Restore the state from a snapshot instead of executing every block
+    /// when starting from genesis
+    pub enabled: bool,
+    /// Sync peers that must offer the same snapshot before it is downloaded
+    pub min_peers: usize,
+    /// Chunks requested and not yet verified and written, across all peers
+    pub max_chunks_in_flight: usize,
+    /// Where a download keeps its verified chunks across restarts
+    pub dir: String,
+    /// Blocks between the snapshots this node serves; 0 serves none
+    pub snapshot_interval: u64,
+}
+
+impl Default for FastSyncConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_peers: 2,
+            max_chunks_in_flight: 16,
+            dir: "data/snapshot".to_string(),
+            snapshot_interval: 1_000,
+        }
+    }
+}
+
+/// Compression of large P2P frames (`network::compression`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompressionConfig {
+    /// Codecs offered to peers, most preferred first; empty disables compression
+    pub codecs: Vec<Codec>,
+    /// Frames smaller than this are sent uncompressed
+    pub threshold_bytes: usize,
+    /// Largest allowed ratio of inflated to compressed size
+    pub max_ratio: u32,
+    /// Largest frame a compressed frame may inflate to
+    pub max_frame_bytes: usize,
+    pub zstd_level: i32,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            codecs: vec![Codec::Zstd, Codec::Snappy],
+            threshold_bytes: 1024,
+            max_ratio: 64,
+            max_frame_bytes: 16 * 1024 * 1024,
+            zstd_level: 3,
+        }
+    }
+}
+
+/// Mainnet layer consensus (`consensus`)
+/// The engine deciding blocks, the validators running it, and its step
+/// timeouts
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConsensusConfig {
+    /// Algorithm deciding blocks; every validator must run the same one
+    pub engine: EngineKind,
+    pub timeouts: Timeouts,
+    /// Genesis validators, the same list on every node; when empty, this
+    /// node is the only validator
+    pub validators: Vec<ValidatorConfig>,
+    /// File holding the 32-byte hex secret the validators' QKD links are
+    /// keyed from, shared by all of them; needed with more than one validator
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_key_path: Option<String>,
+}
+
+/// A genesis validator (a `consensus.validators` entry)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValidatorConfig {
+    /// Hex node key ID, the `Node key` the validator logs at startup
+    pub id: String,
+    /// Hex Dilithium verification key, the `Vote key` it logs
+    pub vote_key: String,
+    /// Whole tokens staked at genesis; voting power follows stake
+    pub stake: u64,
+    /// P2P address (host:port) the other validators keep a connection to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+}
+
+impl ValidatorConfig {
+    /// Parsed `id`
+    pub fn validator_id(&self) -> Result<[u8; 32], String> {
+        hex::decode(self.id.trim_start_matches("0x"))
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| format!("consensus.validators id `{}` is not a 32-byte hex key ID", self.id))
+    }
+
+    /// Parsed `vote_key`
+    pub fn vote_key_bytes(&self) -> Result<Vec<u8>, String> {
+        hex::decode(self.vote_key.trim_start_matches("0x"))
+            .ok()
+            .filter(|key| !key.is_empty())
+            .ok_or_else(|| format!("consensus.validators vote_key of `{}` is not hex", self.id))
+    }
+
+    /// `stake` at the scale stake amounts are kept at
+    pub fn stake_amount(&self) -> PreciseFloat {
+        PreciseFloat::new(i128::from(self.stake) * 100, 2)
+    }
+}
+
+/// WebAssembly node extension (an `extensions` entry)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExtensionConfig {
+    /// Names the extension in logs and prefixes its RPC methods
+    pub name: String,
+    /// The `.wasm` module
+    pub path: PathBuf,
+    /// Host functions the module may import
+    pub capabilities: BTreeSet<Capability>,
+    /// Methods answered by the module's `rpc`, each `ext_<name>_...`
+    pub rpc_methods: Vec<String>,
+    /// Exported event kinds passed to the module's `on_event`
+    pub events: Vec<EventKind>,
+    /// Re-rank `hubble_search` results with the module's `rank`
+    pub ranker: bool,
+    /// Fuel for one call
+    pub fuel: u64,
+    /// Linear memory one call may grow to, in bytes
+    pub max_memory: usize,
+}
+
+impl Default for ExtensionConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            path: PathBuf::new(),
+            capabilities: BTreeSet::new(),
+            rpc_methods: Vec::new(),
+            events: Vec::new(),
+            ranker: false,
+            fuel: 50_000_000,
+            max_memory: 16 * 1024 * 1024,
+        }
+    }
+}
+
+impl ExtensionConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.is_empty() || !self.name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit()) {
+            return Err(format!("Extension name `{}` must be lowercase letters and digits", self.name));
+        }
+        if self.path.as_os_str().is_empty() {
+            return Err(format!("Extension `{}` has no path", self.name));
+        }
+        let prefix = extensions::rpc_prefix(&self.name);
+        if let Some(method) = self.rpc_methods.iter().find(|m| !m.starts_with(&prefix) || m.len() == prefix.len()) {
+            return Err(format!("Extension RPC method `{}` must be named `{}<method>`", method, prefix));
+        }
+        if self.fuel == 0 || self.fuel > MAX_EXTENSION_FUEL {
+            return Err(format!("Extension `{}` fuel must be between 1 and {}", self.name, MAX_EXTENSION_FUEL));
+        }
+        if self.max_memory == 0 || self.max_memory > MAX_EXTENSION_MEMORY {
+            return Err(format!("Extension `{}` max_memory must be between 1 and {} bytes", self.name, MAX_EXTENSION_MEMORY));
+        }
+        Ok(())
+    }
+}
+
+/// Node configuration loaded from a JSON file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NodeConfig {
+    /// Network identifier (consensus-critical)
+    pub chain_id: u64,
+    /// Fixed-point precision used by consensus math (consensus-critical)
+    pub precision: u8,
+    /// JSON-RPC listen port (requires restart)
+    pub rpc_port: u16,
+    /// P2P listen port (requires restart)
+    pub p2p_port: u16,
+    /// Addresses the RPC server binds: an IP, taking `rpc_port`, or ip:port;
+    /// IPv6 in brackets, `::` is dual-stack (requires restart)
+    pub rpc_listen: Vec<String>,
+    /// Addresses the P2P server binds, as for `rpc_listen` with `p2p_port` (requires restart)
+    pub p2p_listen: Vec<String>,
+    /// P2P addresses advertised to peers in the handshake; defaults to the
+    /// specific `p2p_listen` addresses (requires restart)
+    pub external_addresses: Vec<String>,
+    /// One of: error, warn, info, debug, trace
+    pub log_level: String,
+    pub min_peers: usize,
+    pub max_peers: usize,
+    /// Requests per second allowed per RPC client
+    pub rpc_rate_limit: u32,
+    /// Number of peers each gossip message is forwarded to
+    pub gossip_fanout: usize,
+    /// Archive or pruned history retention (requires restart)
+    pub node_mode: NodeMode,
+    /// Origins allowed to call the RPC from a browser; "*" allows any
+    pub rpc_cors_origins: Vec<String>,
+    /// Proxies whose X-Forwarded-For header is trusted for rate limiting
+    pub trusted_proxies: Vec<String>,
+    /// Serve RPC over TLS (requires restart; certificates reload with the config)
+    pub rpc_tls: Option<RpcTlsConfig>,
+    /// Worker threads for Web2 jobs, billing and maintenance (requires restart)
+    pub background_workers: usize,
+    /// Export chain events to NATS or Kafka (requires restart)
+    pub event_export: Option<EventExportConfig>,
+    /// Remote mirrors for snapshots, backups and blobs (requires restart)
+    pub remote_storage: BTreeMap<DataClass, RemoteClassConfig>,
+    /// Ordering and limits for mempool bundles
+    pub block_builder: BlockBuilderConfig,
+    /// Commit-reveal ordering on the main chain (requires restart)
+    pub commit_reveal: Option<CommitRevealConfig>,
+    /// Whether and how often this node seals blocks (requires restart)
+    pub block_time: BlockTimeConfig,
+    /// Hubble search defaults
+    pub hubble: HubbleConfig,
+    /// Epoch and era lengths in blocks (consensus-critical)
+    pub epochs: EpochSchedule,
+    /// Epoch randomness beacon timing and VDF difficulty (consensus-critical)
+    pub beacon: BeaconParams,
+    /// Signaling window and lock-in threshold for protocol features (consensus-critical)
+    pub feature_activation: ActivationParams,
+    /// Features this node signals readiness for in the blocks it produces (requires restart)
+    pub signal_features: Vec<String>,
+    /// Restrict P2P to certified nodes (requires restart)
+    pub permissioned: Option<PermissionedConfig>,
+    /// Anonymized health reporting, off by default
+    pub telemetry: TelemetryConfig,
+    /// Validator/sentry topology (requires restart)
+    pub sentry: Option<SentryConfig>,
+    /// Post-block invariant checks (requires restart)
+    pub invariants: InvariantConfig,
+    /// Tally observation gossip limits (requires restart)
+    pub observations: ObservationConfig,
+    /// P2P endpoints (ip:port) to sync blocks from at startup (requires restart)
+    pub sync_peers: Vec<String>,
+    /// Peer caps per subnet and autonomous system, and diversity targets (requires restart)
+    pub diversity: DiversityConfig,
+    /// Where the node keeps its key across restarts (requires restart)
+    pub keystore: KeystoreConfig,
+    /// DNS seeds and static bootstrap peers; applied on reload
+    pub seeds: SeedConfig,
+    /// Codecs and limits for compressed block and sync frames; applied on
+    /// reload to connections handshaking afterwards
+    pub compression: CompressionConfig,
+    /// Snapshot download at startup and the snapshots served to peers (requires restart)
+    pub fast_sync: FastSyncConfig,
+    /// Consensus engine and genesis validators (consensus-critical), and
+    /// the engine's step timeouts (requires restart)
+    pub consensus: ConsensusConfig,
+    /// WebAssembly extensions loaded at startup (requires restart)
+    pub extensions: Vec<ExtensionConfig>,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        Self {
+            chain_id: 1,
+            precision: 20,
+            rpc_port: 8545,
+            p2p_port: 30303,
+            rpc_listen: vec!["127.0.0.1".to_string()],
+            p2p_listen: vec!["127.0.0.1".to_string()],
+            external_addresses: Vec::new(),
+            log_level: "info".to_string(),
+            min_peers: 10,
+            max_peers: 50,
+            rpc_rate_limit: 100,
+            gossip_fanout: 8,
+            node_mode: NodeMode::Archive,
+            rpc_cors_origins: vec!["*".to_string()],
+            trusted_proxies: Vec::new(),
+            rpc_tls: None,
+            background_workers: 2,
+            event_export: None,
+            remote_storage: BTreeMap::new(),
+            block_builder: BlockBuilderConfig::default(),
+            commit_reveal: None,
+            block_time: BlockTimeConfig::default(),
+            hubble: HubbleConfig::default(),
+            epochs: EpochSchedule::default(),
+            beacon: BeaconParams::default(),
+            feature_activation: ActivationParams::default(),
+            signal_features: Vec::new(),
+            permissioned: None,
+            telemetry: TelemetryConfig::default(),
+            sentry: None,
+            invariants: InvariantConfig::default(),
+            observations: ObservationConfig::default(),
+            sync_peers: Vec::new(),
+            diversity: DiversityConfig::default(),
+            keystore: KeystoreConfig::default(),
+            seeds: SeedConfig::default(),
+            compression: CompressionConfig::default(),
+            fast_sync: FastSyncConfig::default(),
+            consensus: ConsensusConfig::default(),
+            extensions: Vec::new(),
+        }
+    }
+}
+
+const LOG_LEVELS: [&str; 5] = ["error", "warn", "info", "debug", "trace"];
+
+impl NodeConfig {
+    /// Whether this node runs the invariant suite after each block it seals
+    pub fn invariant_checks(&self) -> bool {
+        match self.invariants.mode {
+            InvariantMode::Auto => {
+                cfg!(debug_assertions) || self.sentry.as_ref().is_some_and(|sentry| sentry.role == SentryRole::Validator)
+            }
+            InvariantMode::Always => true,
+            InvariantMode::Off => false,
+        }
+    }
+
+    /// Parsed `sync_peers`
+    pub fn sync_addrs(&self) -> Result<Vec<SocketAddr>, String> {
+        self.sync_peers.iter()
+            .map(|peer| peer.parse().map_err(|_| format!("sync_peers entry `{}` is not an ip:port address", peer)))
+            .collect()
+    }
+
+    /// Parsed `rpc_listen`
+    pub fn rpc_listen_addrs(&self) -> Result<Vec<SocketAddr>, String> {
+        endpoints("rpc_listen", &self.rpc_listen, self.rpc_port)
+    }
+
+    /// Parsed `p2p_listen`
+    pub fn p2p_listen_addrs(&self) -> Result<Vec<SocketAddr>, String> {
+        endpoints("p2p_listen", &self.p2p_listen, self.p2p_port)
+    }
+
+    /// Parsed `external_addresses`
+    pub fn external_addrs(&self) -> Result<Vec<SocketAddr>, String> {
+        endpoints("external_addresses", &self.external_addresses, self.p2p_port)
+    }
+
+    /// Check internal consistency of the configuration
+    pub fn validate(&self) -> Result<(), String> {
+        if !LOG_LEVELS.contains(&self.log_level.as_str()) {
+            return Err(format!("Unknown log_level `{}`", self.log_level));
+        }
+        if self.min_peers > self.max_peers {
+            return Err(format!(
+                "min_peers ({}) must not exceed max_peers ({})",
+                self.min_peers, self.max_peers
+            ));
+        }
+        if self.gossip_fanout == 0 || self.gossip_fanout > self.max_peers {
+            return Err(format!(
+                "gossip_fanout ({}) must be between 1 and max_peers ({})",
+                self.gossip_fanout, self.max_peers
+            ));
+        }
+        if self.rpc_rate_limit == 0 {
+            return Err("rpc_rate_limit must be greater than zero".to_string());
+        }
+        if self.background_workers == 0 {
+            return Err("background_workers must be greater than zero".to_string());
+        }
+        if self.node_mode == (NodeMode::Pruned { retain_blocks: 0 }) {
+            return Err("Pruned mode must retain at least one block".to_string());
+        }
+        if let Some(export) = &self.event_export {
+            if export.topic_prefix.is_empty() {
+                return Err("event_export.topic_prefix must not be empty".to_string());
+            }
+            if let ExportSinkConfig::Kafka { .. } = export.sink {
+                if !cfg!(feature = "kafka") {
+                    return Err("event_export uses Kafka but the node was built without the `kafka` feature".to_string());
+                }
+            }
+        }
+        for (class, remote) in &self.remote_storage {
+            let url = match &remote.backend {
+                RemoteBackendConfig::S3 { endpoint, .. } => Some(endpoint),
+                RemoteBackendConfig::Ipfs { api_url } => Some(api_url),
+                RemoteBackendConfig::Directory { .. } => None,
+            };
+            if url.is_some_and(|url| !url.starts_with("http://") && !url.starts_with("https://")) {
+                return Err(format!("remote_storage.{} URL must start with http:// or https://", class.as_str()));
+            }
+            if remote.lifecycle.keep_last == Some(0) {
+                return Err(format!("remote_storage.{} must keep at least one object", class.as_str()));
+            }
+        }
+        if self.block_builder.max_gas == 0 || self.block_builder.max_bytes == 0 {
+            return Err("block_builder limits must be greater than zero".to_string());
+        }
+        self.block_builder.boosts()?;
+        if let Some(commit_reveal) = &self.commit_reveal {
+            if commit_reveal.reveal_window == 0 || commit_reveal.max_per_block == 0 {
+                return Err("commit_reveal.reveal_window and max_per_block must be greater than zero".to_string());
+            }
+        }
+        if self.block_time.target_interval_ms < MIN_BLOCK_INTERVAL_MS {
+            return Err(format!("block_time.target_interval_ms must be at least {}", MIN_BLOCK_INTERVAL_MS));
+        }
+        #[cfg(feature = "hubble")]
+        if !(1..=MAX_SNIPPET_LENGTH).contains(&self.hubble.snippet_length) {
+            return Err(format!("hubble.snippet_length must be between 1 and {}", MAX_SNIPPET_LENGTH));
+        }
+        if self.hubble.max_results == 0 || self.hubble.max_segments == 0 {
+            return Err("hubble.max_results and max_segments must be greater than zero".to_string());
+        }
+        if self.epochs.epoch_blocks == 0 || self.epochs.epochs_per_era == 0 {
+            return Err("epochs.epoch_blocks and epochs_per_era must be greater than zero".to_string());
+        }
+        if self.beacon.contribution_blocks == 0 || self.beacon.contribution_blocks >= self.epochs.epoch_blocks {
+            return Err("beacon.contribution_blocks must be greater than zero and less than epochs.epoch_blocks".to_string());
+        }
+        if !(1..=vdf::MAX_DIFFICULTY).contains(&self.beacon.vdf_difficulty) {
+            return Err(format!("beacon.vdf_difficulty must be between 1 and {}", vdf::MAX_DIFFICULTY));
+        }
+        if self.feature_activation.window_blocks == 0 {
+            return Err("feature_activation.window_blocks must be greater than zero".to_string());
+        }
+        if !(51..=100).contains(&self.feature_activation.threshold_percent) {
+            return Err("feature_activation.threshold_percent must be between 51 and 100".to_string());
+        }
+        if let Some(name) = self.signal_features.iter().find(|name| Feature::from_name(name).is_none()) {
+            return Err(format!("signal_features entry `{}` is not a known feature", name));
+        }
+        if let Some(permissioned) = &self.permissioned {
+            if permissioned.authority_keys()?.is_empty() {
+                return Err("permissioned.authorities must list at least one authority".to_string());
+            }
+        }
+        if !self.telemetry.endpoint.starts_with("http://") && !self.telemetry.endpoint.starts_with("https://") {
+            return Err("telemetry.endpoint must start with http:// or https://".to_string());
+        }
+        if self.telemetry.interval_secs < 60 {
+            return Err("telemetry.interval_secs must be at least 60".to_string());
+        }
+        if self.telemetry.jitter_percent > 50 {
+            return Err("telemetry.jitter_percent must not exceed 50".to_string());
+        }
+        if self.telemetry.buffer_limit == 0 {
+            return Err("telemetry.buffer_limit must be greater than zero".to_string());
+        }
+        if let Some(sentry) = &self.sentry {
+            if sentry.private_addrs()?.is_empty() {
+                return Err("sentry.private_peers must list at least one peer".to_string());
+            }
+            if sentry.health_interval_secs == 0 || sentry.max_missed_checks == 0 {
+                return Err("sentry.health_interval_secs and max_missed_checks must be greater than zero".to_string());
+            }
+        }
+        if self.observations.max_per_observer == 0 || self.observations.flush_interval_ms == 0 {
+            return Err("observations.max_per_observer and flush_interval_ms must be greater than zero".to_string());
+        }
+        self.sync_addrs()?;
+        if self.rpc_listen_addrs()?.is_empty() || self.p2p_listen_addrs()?.is_empty() {
+            return Err("rpc_listen and p2p_listen must list at least one address".to_string());
+        }
+        if self.external_addrs()?.iter().any(|addr| addr.ip().is_unspecified()) {
+            return Err("external_addresses must name a specific address".to_string());
+        }
+        if self.diversity.max_per_subnet == 0 || self.diversity.max_per_asn == 0 {
+            return Err("diversity.max_per_subnet and max_per_asn must be greater than zero".to_string());
+        }
+        for prefix in &self.diversity.asn_prefixes {
+            prefix.network()?;
+        }
+        for seed in &self.seeds.dns {
+            if seed.domain.is_empty() || seed.domain.contains(char::is_whitespace) {
+                return Err(format!("seeds.dns domain `{}` is not a domain name", seed.domain));
+            }
+            seed.signer()?;
+        }
+        for peer in &self.seeds.bootstrap {
+            if !peer.rsplit_once(':').is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok()) {
+                return Err(format!("seeds.bootstrap entry `{}` is not a host:port address", peer));
+            }
+        }
+        if self.seeds.refresh_secs < 60 {
+            return Err("seeds.refresh_secs must be at least 60".to_string());
+        }
+        if self.fast_sync.min_peers == 0 {
+            return Err("fast_sync.min_peers must be at least 1".to_string());
+        }
+        if self.fast_sync.max_chunks_in_flight == 0 {
+            return Err("fast_sync.max_chunks_in_flight must be at least 1".to_string());
+        }
+        if self.consensus.engine == EngineKind::RoundRobin && self.chain_id == MAINNET_NETWORK_ID {
+            return Err("consensus.engine `round_robin` is for development networks, not mainnet".to_string());
+        }
+        let timeouts = &self.consensus.timeouts;
+        if timeouts.propose_ms == 0 || timeouts.prevote_ms == 0 || timeouts.precommit_ms == 0 {
+            return Err("consensus.timeouts must be at least 1 ms".to_string());
+        }
+        let mut validators = BTreeSet::new();
+        for validator in &self.consensus.validators {
+            if !validators.insert(validator.validator_id()?) {
+                return Err(format!("consensus.validators lists `{}` twice", validator.id));
+            }
+            validator.vote_key_bytes()?;
+            if validator.stake_amount().value < ParamKey::MinimumStake.default_value().value {
+                return Err(format!("consensus.validators stake of `{}` is below the minimum stake", validator.id));
+            }
+            if let Some(address) = &validator.address {
+                if !address.rsplit_once(':').is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok()) {
+                    return Err(format!("consensus.validators address `{}` is not a host:port address", address));
+                }
+            }
+        }
+        if validators.len() > 1 && self.consensus.link_key_path.is_none() {
+            return Err("consensus.link_key_path must be set when there is more than one validator".to_string());
+        }
+        let (mut names, mut methods) = (BTreeSet::new(), BTreeSet::new());
+        for extension in &self.extensions {
+            extension.validate()?;
+            if !names.insert(&extension.name) {
+                return Err(format!("Extension `{}` is listed twice", extension.name));
+            }
+            if let Some(method) = extension.rpc_methods.iter().find(|method| !methods.insert(*method)) {
+                return Err(format!("RPC method `{}` is served twice", method));
+            }
+        }
+        if self.extensions.iter().filter(|extension| extension.ranker).count() > 1 {
+            return Err("At most one extension may rank Hubble results".to_string());
+        }
+        if !self.extensions.is_empty() && !cfg!(feature = "vm-wasm") {
+            return Err("extensions are configured but the node was built without the `vm-wasm` feature".to_string());
+        }
+        if self.compression.max_ratio < 2 {
+            return Err("compression.max_ratio must be at least 2".to_string());
+        }
+        if self.compression.max_frame_bytes < self.compression.threshold_bytes {
+            return Err("compression.max_frame_bytes must not be below compression.threshold_bytes".to_string());
+        }
+        if !(1..=19).contains(&self.compression.zstd_level) {
+            return Err("compression.zstd_level must be between 1 and 19".to_string());
+        }
+        for proxy in &self.trusted_proxies {
+            proxy.parse::<IpAddr>()
+                .map_err(|_| format!("trusted_proxies entry `{}` is not an IP address", proxy))?;
+        }
+        Ok(())
+    }
+
+    /// Parsed trusted proxy addresses
+    pub fn trusted_proxy_ips(&self) -> Vec<IpAddr> {
+        self.trusted_proxies.iter().filter_map(|p| p.parse().ok()).collect()
+    }
+
+    fn from_file(path: &Path) -> Result<Self, String> {
+        let raw = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read config {}: {}", path.display(), e))?;
+        let config: Self = serde_json::from_str(&raw)
+            .map_err(|e| format!("Invalid config {}: {}", path.display(), e))?;
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+fn endpoints(field: &str, entries: &[String], default_port: u16) -> Result<Vec<SocketAddr>, String> {
+    entries.iter()
+        .map(|entry| parse_endpoint(entry, default_port).map_err(|e| format!("{} entry {}", field, e)))
+        .collect()
+}
+
+/// Result of a successful configuration reload
+#[derive(Debug, Default, Serialize)]
+pub struct ReloadReport {
+    /// Settings applied immediately
+    pub applied: Vec<String>,
+    /// Settings that changed on disk but only take effect after restart
+    pub requires_restart: Vec<String>,
+}
+
+/// Configuration Manager
+/// Owns the active node configuration and applies safe changes at runtime.
+pub struct ConfigManager {
+    path: Option<PathBuf>,
+    current: NodeConfig,
+    updates: watch::Sender<NodeConfig>,
+}
+
+impl ConfigManager {
+    /// Load configuration from `path`, falling back to defaults if the file is absent
+    pub fn load_or_default(path: &str) -> Result<Self, String> {
+        let path = PathBuf::from(path);
+        let current = if path.exists() {
+            NodeConfig::from_file(&path)?
+        } else {
+            NodeConfig::default()
+        };
+        Ok(Self::with_config(Some(path), current))
+    }
+
+    pub fn with_config(path: Option<PathBuf>, current: NodeConfig) -> Self {
+        let (updates, _) = watch::channel(current.clone());
+        Self { path, current, updates }
+    }
+
+    pub fn current(&self) -> &NodeConfig {
+        &self.current
+    }
+
+    /// Receive the configuration every time a reload is applied
+    pub fn subscribe(&self) -> watch::Receiver<NodeConfig> {
+        self.updates.subscribe()
+    }
+
+    /// Re-read the config file and apply runtime-safe changes
+    pub fn reload(&mut self) -> Result<ReloadReport, String> {
+        let path = self.path.as_ref()
+            .ok_or("No configuration file to reload")?;
+        let next = NodeConfig::from_file(path)?;
+        self.apply(next)
+    }
+
+    /// Apply a new configuration, rejecting consensus-critical changes
+    pub fn apply(&mut self, next: NodeConfig) -> Result<ReloadReport, String> {
+        next.validate()?;
+
+        if next.chain_id != self.current.chain_id {
+            return Err(format!(
+                "Cannot change consensus-critical parameter `chain_id` at runtime ({} -> {})",
+                self.current.chain_id, next.chain_id
+            ));
+        }
+        if next.precision != self.current.precision {
+            return Err(format!(
+                "Cannot change consensus-critical parameter `precision` at runtime ({} -> {})",
+                self.current.precision, next.precision
+            ));
+        }
+
+        if next.epochs != self.current.epochs {
+            return Err("Cannot change consensus-critical parameter `epochs` at runtime".to_string());
+        }
+        if next.beacon != self.current.beacon {
+            return Err("Cannot change consensus-critical parameter `beacon` at runtime".to_string());
+        }
+        if next.feature_activation != self.current.feature_activation {
+            return Err("Cannot change consensus-critical parameter `feature_activation` at runtime".to_string());
+        }
+        if next.consensus.engine != self.current.consensus.engine {
+            return Err("Cannot change consensus-critical parameter `consensus.engine` at runtime".to_string());
+        }
+        if next.consensus.validators != self.current.consensus.validators {
+            return Err("Cannot change consensus-critical parameter `consensus.validators` at runtime".to_string());
+        }
+        if next.commit_reveal != self.current.commit_reveal {
+            return Err("Cannot change consensus-critical parameter `commit_reveal` at runtime".to_string());
+        }
+
+        let mut report = ReloadReport::default();
+        if next.rpc_port != self.current.rpc_port {
+            report.requires_restart.push("rpc_port".to_string());
+        }
+        if next.p2p_port != self.current.p2p_port {
+            report.requires_restart.push("p2p_port".to_string());
+        }
+        if next.rpc_listen != self.current.rpc_listen {
+            report.requires_restart.push("rpc_listen".to_string());
+        }
+        if next.p2p_listen != self.current.p2p_listen {
+            report.requires_restart.push("p2p_listen".to_string());
+        }
+        if next.external_addresses != self.current.external_addresses {
+            report.requires_restart.push("external_addresses".to_string());
+        }
+        if next.node_mode != self.current.node_mode {
+            report.requires_restart.push("node_mode".to_string());
+        }
+        if next.rpc_tls != self.current.rpc_tls {
+            report.requires_restart.push("rpc_tls".to_string());
+        }
+        if next.background_workers != self.current.background_workers {
+            report.requires_restart.push("background_workers".to_string());
+        }
+        if next.event_export != self.current.event_export {
+            report.requires_restart.push("event_export".to_string());
+        }
+        if next.remote_storage != self.current.remote_storage {
+            report.requires_restart.push("remote_storage".to_string());
+        }
+        if next.block_time != self.current.block_time {
+            report.requires_restart.push("block_time".to_string());
+        }
+        if next.signal_features != self.current.signal_features {
+            report.requires_restart.push("signal_features".to_string());
+        }
+        if next.permissioned != self.current.permissioned {
+            report.requires_restart.push("permissioned".to_string());
+        }
+        if next.sentry != self.current.sentry {
+            report.requires_restart.push("sentry".to_string());
+        }
+        if next.invariants != self.current.invariants {
+            report.requires_restart.push("invariants".to_string());
+        }
+        if next.observations != self.current.observations {
+            report.requires_restart.push("observations".to_string());
+        }
+        if next.sync_peers != self.current.sync_peers {
+            report.requires_restart.push("sync_peers".to_string());
+        }
+        if next.diversity != self.current.diversity {
+            report.requires_restart.push("diversity".to_string());
+        }
+        if next.keystore != self.current.keystore {
+            report.requires_restart.push("keystore".to_string());
+        }
+        if next.fast_sync != self.current.fast_sync {
+            report.requires_restart.push("fast_sync".to_string());
+        }
+        if next.consensus != self.current.consensus {
+            report.requires_restart.push("consensus".to_string());
+        }
+        if next.extensions != self.current.extensions {
+            report.requires_restart.push("extensions".to_string());
+        }
+
+        let mut updated = self.current.clone();
+        if next.log_level != updated.log_level {
+            updated.log_level = next.log_level.clone();
+            report.applied.push("log_level".to_string());
+        }
+        if next.min_peers != updated.min_peers {
+            updated.min_peers = next.min_peers;
+            report.applied.push("min_peers".to_string());
+        }
+        if next.max_peers != updated.max_peers {
+            updated.max_peers = next.max_peers;
+            report.applied.push("max_peers".to_string());
+        }
+        if next.rpc_rate_limit != updated.rpc_rate_limit {
+            updated.rpc_rate_limit = next.rpc_rate_limit;
+            report.applied.push("rpc_rate_limit".to_string());
+        }
+        if next.gossip_fanout != updated.gossip_fanout {
+            updated.gossip_fanout = next.gossip_fanout;
+            report.applied.push("gossip_fanout".to_string());
+        }
+        if next.rpc_cors_origins != updated.rpc_cors_origins {
+            updated.rpc_cors_origins = next.rpc_cors_origins.clone();
+            report.applied.push("rpc_cors_origins".to_string());
+        }
+        if next.trusted_proxies != updated.trusted_proxies {
+            updated.trusted_proxies = next.trusted_proxies.clone();
+            report.applied.push("trusted_proxies".to_string());
+        }
+        if next.block_builder != updated.block_builder {
+            updated.block_builder = next.block_builder.clone();
+            report.applied.push("block_builder".to_string());
+        }
+        if next.hubble != updated.hubble {
+            updated.hubble = next.hubble.clone();
+            report.applied.push("hubble".to_string());
+        }
+        if next.telemetry != updated.telemetry {
+            updated.telemetry = next.telemetry.clone();
+            report.applied.push("telemetry".to_string());
+        }
+        if next.seeds != updated.seeds {
+            updated.seeds = next.seeds.clone();
+            report.applied.push("seeds".to_string());
+        }
+        if next.compression != updated.compression {
+            updated.compression = next.compression.clone();
+            report.applied.push("compression".to_string());
+        }
+
+        self.current = updated;
+        let _ = self.updates.send(self.current.clone());
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validator(seed: u8) -> ValidatorConfig {
+        ValidatorConfig { id: hex::encode([seed; 32]), vote_key: hex::encode([seed; 64]), stake: 2_000, address: None }
+    }
+
+    #[test]
+    fn test_safe_settings_are_applied() {
+        let mut manager = ConfigManager::with_config(None, NodeConfig::default());
+        let updates = manager.subscribe();
+
+        let mut next = NodeConfig {
+            log_level: "debug".to_string(),
+            max_peers: 80,
+            rpc_port: 9000,
+            ..NodeConfig::default()
+        };
+        next.seeds.dns.push(DnsSeed { domain: "seed.example".to_string(), public_key: None });
+        next.compression.codecs = vec![Codec::Snappy];
+
+        let report = manager.apply(next).expect("Reload should succeed");
+        assert_eq!(report.applied, vec!["log_level", "max_peers", "seeds", "compression"]);
+        assert_eq!(report.requires_restart, vec!["rpc_port"]);
+        assert_eq!(manager.current().max_peers, 80);
+        assert_eq!(manager.current().rpc_port, 8545, "Port must not change until restart");
+        assert_eq!(updates.borrow().log_level, "debug");
+    }
+
+    #[test]
+    fn test_consensus_changes_are_rejected() {
+        let mut manager = ConfigManager::with_config(None, NodeConfig::default());
+
+        let next = NodeConfig { chain_id: 2, gossip_fanout: 4, ..NodeConfig::default() };
+
+        let err = manager.apply(next).unwrap_err();
+        assert!(err.contains("chain_id"));
+        assert_eq!(manager.current().gossip_fanout, 8, "Nothing is applied on rejection");
+
+        let next = NodeConfig { commit_reveal: Some(CommitRevealConfig::default()), ..NodeConfig::default() };
+        assert!(manager.apply(next).unwrap_err().contains("commit_reveal"));
+
+        let mut next = NodeConfig::default();
+        next.consensus.validators.push(validator(1));
+        assert!(manager.apply(next).unwrap_err().contains("consensus.validators"));
+    }
+
+    #[test]
+    fn test_invalid_config_is_rejected() {
+        let config = NodeConfig { min_peers: 100, ..NodeConfig::default() };
+        assert!(config.validate().is_err());
+
+        let config = NodeConfig { log_level: "verbose".to_string(), ..NodeConfig::default() };
+        assert!(config.validate().is_err());
+
+        let config = NodeConfig { node_mode: NodeMode::Pruned { retain_blocks: 0 }, ..NodeConfig::default() };
+        assert!(config.validate().is_err());
+
+        let mut config = NodeConfig {
+            p2p_listen: vec!["[::]".to_string(), "0.0.0.0:30304".to_string()],
+            ..NodeConfig::default()
+        };
+        assert_eq!(config.p2p_listen_addrs().unwrap()[0].to_string(), "[::]:30303");
+        config.external_addresses = vec!["::".to_string()];
+        assert!(config.validate().is_err());
+        config.external_addresses.clear();
+        config.rpc_listen.clear();
+        assert!(config.validate().is_err());
+
+        let mut config = NodeConfig::default();
+        config.seeds.dns.push(DnsSeed { domain: "seed.example".to_string(), public_key: Some("0xabcd".to_string()) });
+        assert!(config.validate().is_err());
+
+        let mut config = NodeConfig::default();
+        config.consensus.engine = EngineKind::RoundRobin;
+        assert!(config.validate().is_err(), "The dev engine is refused on mainnet");
+        config.chain_id = 1337;
+        assert!(config.validate().is_ok());
+
+        let mut config = NodeConfig::default();
+        config.consensus.validators = vec![validator(1), validator(2)];
+        assert!(config.validate().unwrap_err().contains("link_key_path"), "Validators share a link key");
+        config.consensus.link_key_path = Some("link.key".to_string());
+        assert!(config.validate().is_ok());
+        config.consensus.validators[1].stake = 10;
+        assert!(config.validate().unwrap_err().contains("minimum stake"));
+        config.consensus.validators[1] = validator(1);
+        assert!(config.validate().unwrap_err().contains("twice"));
+
+        let mut config = NodeConfig::default();
+        config.extensions.push(ExtensionConfig {
+            name: "scores".to_string(),
+            path: PathBuf::from("scores.wasm"),
+            rpc_methods: vec!["getScore".to_string()],
+            ..ExtensionConfig::default()
+        });
+        assert!(config.validate().unwrap_err().contains("ext_scores_"), "Extension methods are namespaced");
+        config.extensions[0].rpc_methods = vec!["ext_scores_get".to_string()];
+        assert_eq!(config.validate().is_ok(), cfg!(feature = "vm-wasm"));
+    }
+
+    #[test]
+    fn test_node_mode_from_json() {
+        let config: NodeConfig = serde_json::from_str(
+            r#"{"node_mode": {"type": "pruned", "retain_blocks": 128}}"#
+        ).unwrap();
+        assert_eq!(config.node_mode, NodeMode::Pruned { retain_blocks: 128 });
+        assert_eq!(config.node_mode.earliest_state(1000), 872);
+        assert_eq!(NodeConfig::default().node_mode.earliest_state(1000), 0);
+    }
+}
@@ -0,0 +1,364 @@
+use std::collections::HashMap;
+use crate::blockchain::core::Block;
+use crate::economics::models::EconomicModel;
+use crate::economics::slashing::SlashEvent;
+use crate::layers::validator_set::ValidatorSet;
+use crate::math::precision::PreciseFloat;
+use crate::network::quantum_network::{QuantumNetwork, QuantumState};
+use crate::security::quantum_resistant::QuantumSecurity;
+use super::transport::{self, Envelope};
+use super::{
+    ConsensusEngine, ConsensusMessage, Decision, Electorate, Evidence, OffenseTracker, Output,
+    Timeout, Value,
+};
+
+/// Messages for heights this node has not reached that it holds on to
+pub const MAX_EARLY_MESSAGES: usize = 1000;
+
+/// Consensus Driver
+/// Runs a `ConsensusEngine` for a validating node: starts each height on
+/// the chain tip, opens the envelopes other validators send, holds
+/// messages for heights the node has not reached, and turns decisions into
+/// blocks to import. Voting power stays as the validator set was built:
+/// slashes only reach this node's economic model, and validators that
+/// disagreed on them would disagree on the electorate too.
+pub struct ConsensusDriver {
+    engine: Box<dyn ConsensusEngine>,
+    validators: ValidatorSet,
+    vote_keys: HashMap<[u8; 32], Vec<u8>>,
+    /// Validator this node signs and opens envelopes for
+    local: [u8; 32],
+    offenses: OffenseTracker,
+    /// Height being decided and the hash of the block it extends
+    current: Option<(u64, [u8; 32])>,
+    /// Lowest height not decided yet
+    next_height: u64,
+    early: Vec<ConsensusMessage>,
+}
+
+impl ConsensusDriver {
+    /// Driver for `engine`, signing as `local` among the active validators
+    /// of `validators`
+    pub fn new(engine: Box<dyn ConsensusEngine>, validators: ValidatorSet, vote_keys: HashMap<[u8; 32], Vec<u8>>, local: [u8; 32]) -> Self {
+        Self {
+            engine,
+            validators,
+            vote_keys,
+            local,
+            offenses: OffenseTracker::default(),
+            current: None,
+            next_height: 0,
+            early: Vec::new(),
+        }
+    }
+
+    pub fn engine(&self) -> &dyn ConsensusEngine {
+        self.engine.as_ref()
+    }
+
+    pub fn electorate(&self) -> Electorate {
+        Electorate::new(&self.validators, &self.vote_keys)
+    }
+
+    /// Add the validators to `network` and entangle this node with each,
+    /// so every envelope it seals has a secure route
+    pub fn link(&self, network: &mut QuantumNetwork) -> Result<(), &'static str> {
+        let state = || QuantumState {
+            superposition: PreciseFloat::new(99, 2),
+            coherence: PreciseFloat::new(99, 2),
+            entanglement_strength: PreciseFloat::new(1, 0),
+        };
+        let validators: Vec<[u8; 32]> = self.electorate().voters().copied().collect();
+        for validator in validators.iter().chain(std::iter::once(&self.local)) {
+            network.add_node(*validator, state());
+        }
+        for validator in validators.iter().filter(|validator| **validator != self.local) {
+            network.create_entanglement(self.local, *validator)?;
+        }
+        Ok(())
+    }
+
+    /// Height being decided, if any
+    pub fn height(&self) -> Option<u64> {
+        self.current.map(|(height, _)| height)
+    }
+
+    /// Whether `height` is neither being decided nor decided already
+    pub fn awaits(&self, height: u64) -> bool {
+        self.height() != Some(height) && height >= self.next_height
+    }
+
+    /// Whether other validators already sent messages for `height`
+    pub fn early(&self, height: u64) -> bool {
+        self.early.iter().any(|message| message_height(message) == height)
+    }
+
+    /// Begin deciding `height` on top of the block hashed `parent`,
+    /// offering `value` if this node proposes, then handle the messages
+    /// that arrived for the height before it started
+    pub fn start(&mut self, height: u64, parent: [u8; 32], value: Option<Value>) -> Result<Vec<Output>, &'static str> {
+        if height < self.next_height {
+            return Err("Height already decided");
+        }
+        let mut out = self.engine.propose(height, self.electorate(), value)?;
+        self.current = Some((height, parent));
+
+        let (ready, later): (Vec<_>, Vec<_>) = std::mem::take(&mut self.early).into_iter()
+            .filter(|message| message_height(message) >= height)
+            .partition(|message| message_height(message) == height);
+        self.early = later;
+        // A held message that fails, such as one with a bad signature, is
+        // dropped like it would have been on arrival
+        for message in ready {
+            if let Ok(more) = self.deliver(message) {
+                out.extend(more);
+            }
+        }
+        Ok(out)
+    }
+
+    /// Seal `message` for every other validator
+    pub fn seal(&self, network: &mut QuantumNetwork, message: &ConsensusMessage) -> Result<Envelope, &'static str> {
+        let validators: Vec<[u8; 32]> = self.electorate().voters().copied().collect();
+        let sealed = transport::broadcast(network, self.local, &validators, message)?;
+        Ok(Envelope { sealed })
+    }
+
+    /// Open this node's copy of `envelope` and hand the message to the
+    /// engine. Envelopes not addressed to this node and messages for decided
+    /// heights, such as late precommits, are ignored; messages for later
+    /// heights are held until `start`.
+    pub fn receive(&mut self, network: &mut QuantumNetwork, envelope: &Envelope) -> Result<Vec<Output>, &'static str> {
+        let Some(sealed) = envelope.sealed_for(&self.local) else {
+            return Ok(Vec::new());
+        };
+        let message = transport::receive(network, sealed)?;
+        let height = message_height(&message);
+        if self.height() == Some(height) {
+            return self.deliver(message);
+        }
+        if height >= self.next_height && self.height().is_none_or(|current| height > current) {
+            if self.early.len() >= MAX_EARLY_MESSAGES {
+                return Err("Too many messages for later heights");
+            }
+            self.early.push(message);
+        }
+        Ok(Vec::new())
+    }
+
+    /// Handle a timeout the engine scheduled, unless its height is over
+    pub fn on_timeout(&mut self, timeout: Timeout) -> Result<Vec<Output>, &'static str> {
+        if self.height() != Some(timeout.height) {
+            return Ok(Vec::new());
+        }
+        self.engine.on_timeout(timeout)
+    }
+
+    /// Check a `Decide` output and return the decided block to import.
+    /// Validators whose precommits the commit lacks are counted for
+    /// downtime.
+    pub fn decide(&mut self, decision: &Decision) -> Result<Block, &'static str> {
+        let electorate = self.electorate();
+        self.engine.finalize(&decision.proposal.value, &decision.commit, &electorate)?;
+        self.offenses.record_commit(&decision.commit, &electorate);
+        self.current = None;
+        self.next_height = decision.commit.height + 1;
+        Block::from_bytes(&decision.proposal.value.data)
+    }
+
+    /// Record evidence from an `Output::Evidence`; false if it was known
+    pub fn report(&mut self, evidence: &Evidence) -> bool {
+        self.offenses.report(evidence)
+    }
+
+    /// Slash the offenses found since the last call
+    pub fn slash(&mut self, economics: &mut EconomicModel) -> Vec<SlashEvent> {
+        self.offenses.slash(economics)
+    }
+
+    fn deliver(&mut self, message: ConsensusMessage) -> Result<Vec<Output>, &'static str> {
+        if let (ConsensusMessage::Proposal(proposal), Some((_, parent))) = (&message, self.current) {
+            if proposal.value.parent != parent {
+                return Err("Proposal does not extend the chain tip");
+            }
+        }
+        self.engine.on_message(message)
+    }
+}
+
+fn message_height(message: &ConsensusMessage) -> u64 {
+    match message {
+        ConsensusMessage::Proposal(proposal) => proposal.height,
+        ConsensusMessage::Vote(vote) => vote.height,
+    }
+}
+
+/// Value that proposes `block`: its wire encoding, and a proof bound to
+/// its hash that passes the quantum resistance check
+pub fn block_value(block: &Block, security: &QuantumSecurity) -> Value {
+    Value::new(block.previous_hash, block.to_bytes(), block_proof(&block.hash, security))
+}
+
+/// A counter, found by search, whose hash with the block hash is quantum
+/// resistant: that hash followed by the counter
+fn block_proof(hash: &[u8; 32], security: &QuantumSecurity) -> Vec<u8> {
+    (0u64..)
+        .map(|counter| (proof_hash(hash, counter), counter))
+        .find(|(proof, _)| security.verify_quantum_resistance(proof).is_ok())
+        .map(|(proof, counter)| [proof.as_slice(), &counter.to_le_bytes()].concat())
+        .expect("some counter passes")
+}
+
+fn proof_hash(hash: &[u8; 32], counter: u64) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"qmv:block-proof:");
+    hasher.update(hash);
+    hasher.update(&counter.to_le_bytes());
+    hasher.finalize().into()
+}
+
+/// `BlockCheck` for values made by `block_value`: the data decodes to a
+/// block whose hash matches its contents, that extends the value's
+/// parent, and the proof belongs to it
+pub fn check_block_value(value: &Value) -> Result<(), &'static str> {
+    let block = Block::from_bytes(&value.data)?;
+    if !block.verify_hash() {
+        return Err("Block hash does not match its contents");
+    }
+    if block.previous_hash != value.parent {
+        return Err("Block does not extend the value's parent");
+    }
+    let counter: [u8; 8] = value.proof.get(32..).and_then(|counter| counter.try_into().ok()).ok_or("Malformed block proof")?;
+    if value.proof[..32] != proof_hash(&block.hash, u64::from_le_bytes(counter)) {
+        return Err("Proof for another block");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::bloom::Bloom;
+    use crate::blockchain::core::Blockchain;
+    use crate::consensus::{build_engine, EngineKind, LocalValidator, RoundRobin, Timeouts};
+    use crate::crypto::domain::{SigningDomain, MAINNET_NETWORK_ID};
+    use crate::epoch::EpochSchedule;
+    use crate::network::qkd::SimulatedQkd;
+    use std::sync::Arc;
+
+    /// Validator node: its driver, quantum network and chain
+    struct Node {
+        driver: ConsensusDriver,
+        network: QuantumNetwork,
+        chain: Blockchain,
+    }
+
+    /// Two validators with equal stake running `kind`, so every `pos`
+    /// height needs both
+    fn validators(kind: EngineKind) -> Vec<Node> {
+        let mut economics = EconomicModel::new(2);
+        let mut set = ValidatorSet::new(EpochSchedule::default(), 10);
+        let mut vote_keys = HashMap::new();
+        let mut keys = Vec::new();
+        for seed in 1..=2u8 {
+            let mut security = QuantumSecurity::new(2);
+            let (key_id, key) = security.generate_key_pair().unwrap();
+            let id = [seed; 32];
+            set.join(id).unwrap();
+            economics.stake_tokens(id, PreciseFloat::new(2_000_000, 2)).unwrap();
+            vote_keys.insert(id, key.verification_key().to_vec());
+            keys.push((LocalValidator { id, key_id }, security));
+        }
+        set.rotate(0, &economics).unwrap();
+
+        keys.into_iter()
+            .map(|(local, security)| {
+                let domain = SigningDomain::main_chain(MAINNET_NETWORK_ID);
+                let engine = build_engine(kind, Timeouts::default(), 2, domain, security, Some(local), check_block_value);
+                let driver = ConsensusDriver::new(engine, set.clone(), vote_keys.clone(), local.id);
+                let mut network = QuantumNetwork::new(2).with_key_agreement(Arc::new(SimulatedQkd::new([5u8; 32])));
+                driver.link(&mut network).unwrap();
+                Node { driver, network, chain: Blockchain::new(2) }
+            })
+            .collect()
+    }
+
+    /// Carry out `out` for node `from`: seal broadcasts for the other node
+    /// and collect decided blocks
+    fn carry_out(nodes: &mut [Node], from: usize, out: Vec<Output>, envelopes: &mut Vec<(usize, Envelope)>, blocks: &mut Vec<(usize, Block)>) {
+        for output in out {
+            match output {
+                Output::Broadcast(message) => {
+                    let node = &mut nodes[from];
+                    envelopes.push((1 - from, node.driver.seal(&mut node.network, &message).unwrap()));
+                }
+                Output::Decide(decision) => blocks.push((from, nodes[from].driver.decide(&decision).unwrap())),
+                _ => {}
+            }
+        }
+    }
+
+    /// Decide height 1 between two validators running `kind`
+    fn decide_a_block(kind: EngineKind) {
+        let mut nodes = validators(kind);
+        let tip = nodes[0].chain.block(0).unwrap().hash;
+        let electorate = nodes[0].driver.electorate();
+        let proposer = match kind {
+            EngineKind::Pos => electorate.proposer(1, 0),
+            EngineKind::RoundRobin => RoundRobin::proposer(&electorate, 1),
+        }.unwrap();
+        let (p, f) = if proposer == [1u8; 32] { (0, 1) } else { (1, 0) };
+        let (mut envelopes, mut blocks) = (Vec::new(), Vec::new());
+
+        let block = nodes[p].chain.next_block(b"transactions".to_vec(), &Bloom::default()).unwrap();
+        let value = block_value(&block, &QuantumSecurity::new(2));
+        let out = nodes[p].driver.start(1, tip, Some(value)).unwrap();
+        carry_out(&mut nodes, p, out, &mut envelopes, &mut blocks);
+
+        // The other validator has not started height 1 yet and holds the proposal
+        let (to, envelope) = envelopes.remove(0);
+        let node = &mut nodes[to];
+        assert!(node.driver.receive(&mut node.network, &envelope).unwrap().is_empty());
+        assert!(node.driver.early(1));
+        let out = node.driver.start(1, tip, None).unwrap();
+        carry_out(&mut nodes, f, out, &mut envelopes, &mut blocks);
+
+        while !envelopes.is_empty() {
+            let (to, envelope) = envelopes.remove(0);
+            let node = &mut nodes[to];
+            let out = node.driver.receive(&mut node.network, &envelope).unwrap();
+            carry_out(&mut nodes, to, out, &mut envelopes, &mut blocks);
+        }
+        assert_eq!(blocks.len(), 2);
+        for (index, decided) in blocks {
+            assert_eq!(decided.hash, block.hash);
+            nodes[index].chain.import_block(decided).unwrap();
+            assert_eq!(nodes[index].driver.height(), None);
+            assert!(!nodes[index].driver.awaits(1) && nodes[index].driver.awaits(2));
+        }
+    }
+
+    #[test]
+    fn test_validators_decide_a_block_over_envelopes() {
+        decide_a_block(EngineKind::Pos);
+        decide_a_block(EngineKind::RoundRobin);
+    }
+
+    #[test]
+    fn test_block_values_are_checked() {
+        let mut chain = Blockchain::new(2);
+        let block = chain.next_block(b"transactions".to_vec(), &Bloom::default()).unwrap();
+        let value = block_value(&block, &QuantumSecurity::new(2));
+        assert_eq!(check_block_value(&value), Ok(()));
+        assert!(QuantumSecurity::new(2).verify_proof(&value.proof));
+
+        let mut elsewhere = value.clone();
+        elsewhere.parent = [7u8; 32];
+        assert_eq!(check_block_value(&elsewhere), Err("Block does not extend the value's parent"));
+
+        let other = chain.next_block(b"other transactions".to_vec(), &Bloom::default()).unwrap();
+        let mut borrowed = block_value(&other, &QuantumSecurity::new(2));
+        borrowed.proof = value.proof.clone();
+        assert_eq!(check_block_value(&borrowed), Err("Proof for another block"));
+    }
+}
@@ -0,0 +1,454 @@
+//! BFT consensus between the mainnet layer's validators.
+//!
+//! A Tendermint-style protocol decides each height in rounds. The round's
+//! proposer, drawn by stake, broadcasts a `Proposal`; validators prevote
+//! for it or for nil, then precommit once prevotes from more than two
+//! thirds of the stake agree. A value is decided once more than two thirds
+//! precommit it, and those precommits form the block's `Commit`.
+//!
+//! Voting power is the stake weight of the active `ValidatorSet`. Votes are
+//! signed with each validator's Dilithium key through `QuantumSecurity`
+//! and travel between validators over `QuantumNetwork` links (`transport`).
+//!
+//! Safety follows the usual locking rules: a validator that precommits a
+//! value stays locked on it, and only prevotes for another value once more
+//! than two thirds prevoted for it in a later round.
+//!
+//! Engines implement `ConsensusEngine` and never touch chain state: they
+//! only see proposed values, votes and the electorate, and hand decided
+//! values back to the caller, which applies them to the chain.
+//! `build_engine` creates the engine named by `consensus.engine`:
+//! - `pos` (default): `Tendermint` voting by stake, voting only for values
+//!   whose proof passes the quantum tally check (`pos`);
+//! - `round_robin`: validators take turns and the proposer alone decides,
+//!   for development networks (`round_robin`).
+//!
+//! `ConsensusDriver` runs that engine in the node: it carries messages over
+//! `QuantumNetwork` links in `transport::Envelope`s, buffers messages for
+//! later heights, and turns decisions into blocks to import.
+
+pub mod tendermint;
+pub mod pos;
+pub mod round_robin;
+pub mod transport;
+pub mod offenses;
+pub mod driver;
+
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::time::Duration;
+use crate::blockchain::types::{hex_serde, hex_serde_option};
+use crate::crypto::domain::{PayloadKind, SigningDomain};
+use crate::economics::slashing::ValidatorOffense;
+use crate::layers::validator_set::ValidatorSet;
+use crate::security::quantum_resistant::{KeyId, QuantumSecurity};
+
+pub use tendermint::Tendermint;
+pub use pos::PosEngine;
+pub use round_robin::RoundRobin;
+pub use offenses::OffenseTracker;
+pub use driver::ConsensusDriver;
+
+/// Consensus algorithm a network runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EngineKind {
+    #[default]
+    Pos,
+    RoundRobin,
+}
+
+/// Consensus Engine
+/// Decides one height at a time among an electorate. The caller feeds it
+/// messages and expired timeouts and carries out the returned `Output`s;
+/// a `Decide` output is applied to the chain only after `finalize`.
+pub trait ConsensusEngine: Send {
+    fn kind(&self) -> EngineKind;
+
+    /// Domain the engine's votes are signed in
+    fn domain(&self) -> &SigningDomain;
+
+    /// Begin deciding `height`. `value` is offered if this node proposes.
+    fn propose(&mut self, height: u64, electorate: Electorate, value: Option<Value>) -> Result<Vec<Output>, &'static str>;
+
+    /// Whether a proposed value may be voted for
+    fn validate(&self, value: &Value) -> Result<(), &'static str>;
+
+    /// Check that `commit` proves `value` was decided by `electorate`
+    fn finalize(&self, value: &Value, commit: &Commit, electorate: &Electorate) -> Result<(), &'static str>;
+
+    /// Handle a proposal or vote from another validator
+    fn on_message(&mut self, message: ConsensusMessage) -> Result<Vec<Output>, &'static str>;
+
+    /// Handle a timeout the engine scheduled; engines without timeouts ignore it
+    fn on_timeout(&mut self, _timeout: Timeout) -> Result<Vec<Output>, &'static str> {
+        Ok(Vec::new())
+    }
+}
+
+/// Check a caller adds to an engine's own validation of proposed values,
+/// such as that the value holds the next block of its chain
+pub type BlockCheck = fn(&Value) -> Result<(), &'static str>;
+
+/// Create the engine `kind`, signing for `local` if this node validates
+/// and voting only for values `check` accepts
+pub fn build_engine(
+    kind: EngineKind,
+    timeouts: Timeouts,
+    precision: u8,
+    domain: SigningDomain,
+    security: QuantumSecurity,
+    local: Option<LocalValidator>,
+    check: BlockCheck,
+) -> Box<dyn ConsensusEngine> {
+    match kind {
+        EngineKind::Pos => Box::new(PosEngine::with_check(domain, security, local, timeouts, precision, check)),
+        EngineKind::RoundRobin => Box::new(RoundRobin::with_check(domain, security, local, check)),
+    }
+}
+
+/// The validator this node signs for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalValidator {
+    pub id: [u8; 32],
+    /// Key in the engine's `QuantumSecurity` registry that signs votes
+    pub key_id: KeyId,
+}
+
+/// Step of a consensus round
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Step {
+    Propose,
+    Prevote,
+    Precommit,
+    /// The height is decided
+    Commit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(u8)]
+pub enum VoteKind {
+    Prevote = 1,
+    Precommit = 2,
+}
+
+/// Block contents consensus decides on
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Value {
+    /// Hash of the block this one extends
+    #[serde(with = "hex_serde")]
+    pub parent: [u8; 32],
+    #[serde(with = "hex_serde")]
+    pub data: Vec<u8>,
+    #[serde(with = "hex_serde")]
+    pub proof: Vec<u8>,
+}
+
+impl Value {
+    pub fn new(parent: [u8; 32], data: Vec<u8>, proof: Vec<u8>) -> Self {
+        Self { parent, data, proof }
+    }
+
+    /// ID votes refer to the value by
+    pub fn id(&self) -> [u8; 32] {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&self.parent);
+        hasher.update(&(self.data.len() as u64).to_le_bytes());
+        hasher.update(&self.data);
+        hasher.update(&self.proof);
+        hasher.finalize().into()
+    }
+}
+
+/// A round's proposed value, signed by the round's proposer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Proposal {
+    pub height: u64,
+    pub round: u32,
+    /// Round in which more than two thirds prevoted for this value, when
+    /// the proposer re-proposes a value from an earlier round
+    pub valid_round: Option<u32>,
+    pub value: Value,
+    #[serde(with = "hex_serde")]
+    pub proposer: [u8; 32],
+    #[serde(with = "hex_serde", default)]
+    pub signature: Vec<u8>,
+}
+
+impl Proposal {
+    /// Bytes the proposer signs
+    pub fn signing_bytes(&self, domain: &SigningDomain) -> Vec<u8> {
+        let mut body = Vec::with_capacity(4 + 5 + 32);
+        body.extend_from_slice(&self.round.to_le_bytes());
+        match self.valid_round {
+            Some(round) => {
+                body.push(1);
+                body.extend_from_slice(&round.to_le_bytes());
+            }
+            None => body.push(0),
+        }
+        body.extend_from_slice(&self.value.id());
+        domain.payload(PayloadKind::Proposal, self.height, &body)
+    }
+}
+
+/// A prevote or precommit for a value, or for nil when `value` is `None`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Vote {
+    pub kind: VoteKind,
+    pub height: u64,
+    pub round: u32,
+    #[serde(with = "hex_serde_option", default)]
+    pub value: Option<[u8; 32]>,
+    #[serde(with = "hex_serde")]
+    pub validator: [u8; 32],
+    #[serde(with = "hex_serde", default)]
+    pub signature: Vec<u8>,
+}
+
+impl Vote {
+    /// Bytes the validator signs
+    pub fn signing_bytes(&self, domain: &SigningDomain) -> Vec<u8> {
+        let mut body = Vec::with_capacity(1 + 4 + 32);
+        body.push(self.kind as u8);
+        body.extend_from_slice(&self.round.to_le_bytes());
+        body.extend_from_slice(&self.value.unwrap_or([0u8; 32]));
+        domain.payload(PayloadKind::Vote, self.height, &body)
+    }
+}
+
+/// Message exchanged between validators
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ConsensusMessage {
+    Proposal(Proposal),
+    Vote(Vote),
+}
+
+/// Precommits from more than two thirds of the stake for one value: proof
+/// that the value was decided at `height`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Commit {
+    pub height: u64,
+    pub round: u32,
+    #[serde(with = "hex_serde")]
+    pub value: [u8; 32],
+    pub precommits: Vec<Vote>,
+}
+
+impl Commit {
+    /// Check every precommit and that together they reach the quorum
+    pub fn verify(&self, domain: &SigningDomain, electorate: &Electorate, security: &QuantumSecurity) -> Result<(), &'static str> {
+        let mut signed = BTreeSet::new();
+        let mut weight = 0u128;
+        for vote in &self.precommits {
+            if vote.kind != VoteKind::Precommit || vote.height != self.height || vote.round != self.round {
+                return Err("Commit vote for another step");
+            }
+            if vote.value != Some(self.value) {
+                return Err("Commit vote for another value");
+            }
+            if !signed.insert(vote.validator) {
+                return Err("Duplicate validator signature");
+            }
+            electorate.verify(&vote.validator, &vote.signing_bytes(domain), &vote.signature, security)?;
+            weight += electorate.weight_of(&vote.validator);
+        }
+        if weight < electorate.quorum_weight() {
+            return Err("Validator quorum not reached");
+        }
+        Ok(())
+    }
+}
+
+/// A decided height: the value and the commit that proves it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Decision {
+    pub proposal: Proposal,
+    pub commit: Commit,
+}
+
+/// Signed proof of a validator breaking the protocol
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Evidence {
+    /// Two different votes of one kind for the same height and round
+    DoubleSign { first: Vote, second: Vote },
+    /// Two different proposals for the same height and round
+    DoubleProposal { first: Proposal, second: Proposal },
+    /// A proposal whose value fails the engine's validation
+    InvalidProposal { proposal: Proposal },
+}
+
+impl Evidence {
+    pub fn validator(&self) -> [u8; 32] {
+        match self {
+            Evidence::DoubleSign { first, .. } => first.validator,
+            Evidence::DoubleProposal { first, .. } => first.proposer,
+            Evidence::InvalidProposal { proposal } => proposal.proposer,
+        }
+    }
+
+    /// Height and round the offense was committed in
+    pub fn step(&self) -> (u64, u32) {
+        match self {
+            Evidence::DoubleSign { first, .. } => (first.height, first.round),
+            Evidence::DoubleProposal { first, .. } => (first.height, first.round),
+            Evidence::InvalidProposal { proposal } => (proposal.height, proposal.round),
+        }
+    }
+
+    /// What the validator is slashed for (`EconomicModel::slash_validator`)
+    pub fn offense(&self) -> ValidatorOffense {
+        match self {
+            Evidence::DoubleSign { .. } | Evidence::DoubleProposal { .. } => ValidatorOffense::DoubleSign,
+            Evidence::InvalidProposal { .. } => ValidatorOffense::InvalidBlock,
+        }
+    }
+}
+
+/// Step deadline the engine asked to be woken up at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Timeout {
+    pub height: u64,
+    pub round: u32,
+    pub step: Step,
+}
+
+/// What the caller should do after feeding the engine a message or timeout
+#[derive(Debug, Clone, PartialEq)]
+pub enum Output {
+    /// Send to every other validator
+    Broadcast(ConsensusMessage),
+    /// Call `on_timeout` with this timeout once the duration has passed
+    Schedule(Timeout, Duration),
+    /// Apply the decided block, then start the next height
+    Decide(Box<Decision>),
+    /// A validator broke the protocol; report it to an `OffenseTracker`
+    Evidence(Evidence),
+}
+
+/// Step timeouts of the `pos` engine. Each round waits `delta` longer than
+/// the one before, so the validators eventually spend long enough in one
+/// round to agree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Timeouts {
+    pub propose_ms: u64,
+    pub prevote_ms: u64,
+    pub precommit_ms: u64,
+    pub delta_ms: u64,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            propose_ms: 3000,
+            prevote_ms: 1000,
+            precommit_ms: 1000,
+            delta_ms: 500,
+        }
+    }
+}
+
+impl Timeouts {
+    pub fn duration(&self, step: Step, round: u32) -> Duration {
+        let base = match step {
+            Step::Propose => self.propose_ms,
+            Step::Prevote => self.prevote_ms,
+            Step::Precommit | Step::Commit => self.precommit_ms,
+        };
+        Duration::from_millis(base.saturating_add(self.delta_ms.saturating_mul(round as u64)))
+    }
+}
+
+struct Voter {
+    weight: u128,
+    vote_key: Vec<u8>,
+}
+
+/// Electorate
+/// Voting power and Dilithium vote keys of the validators deciding a
+/// height, taken from the active set of a `ValidatorSet`.
+pub struct Electorate {
+    voters: BTreeMap<[u8; 32], Voter>,
+    /// Weight of the whole active set; validators without a vote key
+    /// cannot vote but still count towards the quorum
+    total_weight: u128,
+}
+
+impl Electorate {
+    /// Active validators of `set` that registered a vote key, the
+    /// `verification_key` of their `QuantumKey`
+    pub fn new(set: &ValidatorSet, vote_keys: &HashMap<[u8; 32], Vec<u8>>) -> Self {
+        let voters = set.active()
+            .filter_map(|(validator, weight)| {
+                let vote_key = vote_keys.get(validator)?.clone();
+                Some((*validator, Voter { weight, vote_key }))
+            })
+            .collect();
+        Self { voters, total_weight: set.total_weight() }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.voters.is_empty()
+    }
+
+    /// Validators that can vote, ordered by key
+    pub fn voters(&self) -> impl Iterator<Item = &[u8; 32]> {
+        self.voters.keys()
+    }
+
+    pub fn is_voter(&self, validator: &[u8; 32]) -> bool {
+        self.voters.contains_key(validator)
+    }
+
+    pub fn weight_of(&self, validator: &[u8; 32]) -> u128 {
+        self.voters.get(validator).map_or(0, |voter| voter.weight)
+    }
+
+    pub fn total_weight(&self) -> u128 {
+        self.total_weight
+    }
+
+    /// More than two thirds of the stake
+    pub fn quorum_weight(&self) -> u128 {
+        self.total_weight * 2 / 3 + 1
+    }
+
+    /// More than a third of the stake: at least one honest validator
+    pub fn honest_weight(&self) -> u128 {
+        self.total_weight / 3 + 1
+    }
+
+    /// Proposer of `round` at `height`, drawn with probability
+    /// proportional to stake
+    pub fn proposer(&self, height: u64, round: u32) -> Option<[u8; 32]> {
+        let total: u128 = self.voters.values().map(|voter| voter.weight).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(b"qmv:bft-proposer:");
+        hasher.update(&height.to_le_bytes());
+        hasher.update(&round.to_le_bytes());
+        let seed: [u8; 16] = hasher.finalize().as_bytes()[..16].try_into().expect("hash is 32 bytes");
+        let mut target = u128::from_le_bytes(seed) % total;
+        for (validator, voter) in &self.voters {
+            if target < voter.weight {
+                return Some(*validator);
+            }
+            target -= voter.weight;
+        }
+        None
+    }
+
+    /// Check a signature made with `validator`'s vote key
+    pub fn verify(&self, validator: &[u8; 32], message: &[u8], signature: &[u8], security: &QuantumSecurity) -> Result<(), &'static str> {
+        let voter = self.voters.get(validator).ok_or("Signer not in the active validator set")?;
+        security.verify_signature(&voter.vote_key, message, signature)
+            .map_err(|_| "Invalid validator signature")
+    }
+}
@@ -0,0 +1,262 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
+use crate::economics::models::EconomicModel;
+use crate::economics::slashing::{SlashEvent, ValidatorOffense};
+use super::{Commit, Electorate, Evidence};
+
+/// Heights a validator's signing record covers
+pub const DOWNTIME_WINDOW: u64 = 100;
+
+/// Heights within the window a validator may miss before it is slashed
+pub const MAX_MISSED_BLOCKS: u64 = 50;
+
+/// An offense found and not slashed yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Offense {
+    pub validator: [u8; 32],
+    pub offense: ValidatorOffense,
+    pub height: u64,
+}
+
+/// Offense Tracker
+/// Turns the evidence engines report and the precommits missing from
+/// decided commits into offenses, each reported once, and slashes them
+/// through `EconomicModel::slash_validator`.
+pub struct OffenseTracker {
+    window: u64,
+    max_missed: u64,
+    /// Heights within the window each validator's precommit was missing at
+    missed: BTreeMap<[u8; 32], VecDeque<u64>>,
+    /// Offenses already recorded, by validator, offense, height and round
+    seen: HashSet<([u8; 32], ValidatorOffense, u64, u32)>,
+    pending: Vec<Offense>,
+}
+
+impl Default for OffenseTracker {
+    fn default() -> Self {
+        Self::new(DOWNTIME_WINDOW, MAX_MISSED_BLOCKS)
+    }
+}
+
+impl OffenseTracker {
+    /// Tracker slashing validators that miss more than `max_missed` of the
+    /// last `window` heights
+    pub fn new(window: u64, max_missed: u64) -> Self {
+        Self {
+            window,
+            max_missed,
+            missed: BTreeMap::new(),
+            seen: HashSet::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Record evidence from an `Output::Evidence`. Returns false if the
+    /// offense was already recorded, as when a conflicting vote is relayed
+    /// again.
+    pub fn report(&mut self, evidence: &Evidence) -> bool {
+        let (height, round) = evidence.step();
+        let (validator, offense) = (evidence.validator(), evidence.offense());
+        if !self.seen.insert((validator, offense, height, round)) {
+            return false;
+        }
+        self.pending.push(Offense { validator, offense, height });
+        true
+    }
+
+    /// Count the voters of `electorate` whose precommit is missing from a
+    /// decided `commit`. A validator missing from more than `max_missed` of
+    /// the last `window` heights is recorded for downtime, and its count
+    /// starts over.
+    pub fn record_commit(&mut self, commit: &Commit, electorate: &Electorate) {
+        let signed: BTreeSet<[u8; 32]> = commit.precommits.iter().map(|vote| vote.validator).collect();
+        let oldest = commit.height.saturating_sub(self.window);
+        for validator in electorate.voters().filter(|validator| !signed.contains(*validator)) {
+            let missed = self.missed.entry(*validator).or_default();
+            missed.push_back(commit.height);
+            while missed.front().is_some_and(|height| *height <= oldest) {
+                missed.pop_front();
+            }
+            if missed.len() as u64 > self.max_missed {
+                missed.clear();
+                self.pending.push(Offense { validator: *validator, offense: ValidatorOffense::Downtime, height: commit.height });
+            }
+        }
+        self.missed.retain(|_, missed| missed.back().is_some_and(|height| *height > oldest));
+        self.seen.retain(|(_, _, height, _)| *height > oldest);
+    }
+
+    /// Offenses recorded since the last `slash`, oldest first
+    pub fn pending(&self) -> &[Offense] {
+        &self.pending
+    }
+
+    /// Slash every pending offense. Offenders that hold no stake are
+    /// skipped.
+    pub fn slash(&mut self, economics: &mut EconomicModel) -> Vec<SlashEvent> {
+        self.pending.drain(..)
+            .filter_map(|offense| economics.slash_validator(&offense.validator, offense.offense).ok())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::{ConsensusMessage, Output, Proposal, Tendermint, Timeouts, Value, Vote, VoteKind};
+    use crate::crypto::domain::{SigningDomain, MAINNET_NETWORK_ID};
+    use crate::epoch::EpochSchedule;
+    use crate::layers::validator_set::ValidatorSet;
+    use crate::math::precision::PreciseFloat;
+    use crate::security::quantum_resistant::{KeyId, QuantumSecurity};
+    use std::collections::HashMap;
+
+    fn tokens(whole: i128) -> PreciseFloat {
+        PreciseFloat::new(whole * 100, 2)
+    }
+
+    /// Four validators staking 20,000 tokens each, and their vote keys
+    struct Validators {
+        economics: EconomicModel,
+        set: ValidatorSet,
+        vote_keys: HashMap<[u8; 32], Vec<u8>>,
+        keys: HashMap<[u8; 32], KeyId>,
+        security: QuantumSecurity,
+        domain: SigningDomain,
+    }
+
+    impl Validators {
+        fn new() -> Self {
+            let mut economics = EconomicModel::new(2);
+            let mut set = ValidatorSet::new(EpochSchedule::default(), 10);
+            let mut security = QuantumSecurity::new(2);
+            let (mut vote_keys, mut keys) = (HashMap::new(), HashMap::new());
+            for seed in 1..=4u8 {
+                let (key_id, key) = security.generate_key_pair().unwrap();
+                let id = [seed; 32];
+                set.join(id).unwrap();
+                economics.stake_tokens(id, tokens(20_000)).unwrap();
+                vote_keys.insert(id, key.verification_key().to_vec());
+                keys.insert(id, key_id);
+            }
+            set.rotate(0, &economics).unwrap();
+            Self { economics, set, vote_keys, keys, security, domain: SigningDomain::main_chain(MAINNET_NETWORK_ID) }
+        }
+
+        fn electorate(&self) -> Electorate {
+            Electorate::new(&self.set, &self.vote_keys)
+        }
+
+        /// A follower at height 1 voting for values `check` accepts
+        fn engine(&self, check: fn(&Value) -> Result<(), &'static str>) -> Tendermint {
+            let mut engine = Tendermint::new(self.domain, QuantumSecurity::new(2), None, Box::new(check), Timeouts::default());
+            engine.start_height(1, self.electorate(), None).unwrap();
+            engine
+        }
+
+        fn proposal(&self, data: &[u8]) -> Proposal {
+            let proposer = self.electorate().proposer(1, 0).unwrap();
+            let value = Value::new([0u8; 32], data.to_vec(), vec![1u8; 64]);
+            let mut proposal = Proposal { height: 1, round: 0, valid_round: None, value, proposer, signature: Vec::new() };
+            proposal.signature = self.security.sign(&self.keys[&proposer], &proposal.signing_bytes(&self.domain)).unwrap();
+            proposal
+        }
+
+        fn prevote(&self, validator: [u8; 32], value: [u8; 32]) -> Vote {
+            let mut vote = Vote { kind: VoteKind::Prevote, height: 1, round: 0, value: Some(value), validator, signature: Vec::new() };
+            vote.signature = self.security.sign(&self.keys[&validator], &vote.signing_bytes(&self.domain)).unwrap();
+            vote
+        }
+    }
+
+    fn evidence(out: Vec<Output>) -> Vec<Evidence> {
+        out.into_iter()
+            .filter_map(|output| match output {
+                Output::Evidence(evidence) => Some(evidence),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_double_signing_is_slashed_once() {
+        let mut validators = Validators::new();
+        let mut engine = validators.engine(|_| Ok(()));
+        let mut tracker = OffenseTracker::default();
+        let proposer = validators.electorate().proposer(1, 0).unwrap();
+        let signer = *validators.electorate().voters().find(|id| **id != proposer).unwrap();
+
+        // Two prevotes for different blocks in one round
+        let (first, second) = (validators.prevote(signer, [1u8; 32]), validators.prevote(signer, [2u8; 32]));
+        assert!(evidence(engine.on_message(ConsensusMessage::Vote(first)).unwrap()).is_empty());
+        let found = evidence(engine.on_message(ConsensusMessage::Vote(second.clone())).unwrap());
+        assert!(matches!(&found[..], [Evidence::DoubleSign { .. }]));
+        assert!(tracker.report(&found[0]));
+        // The second vote relayed again is the same offense
+        let again = evidence(engine.on_message(ConsensusMessage::Vote(second)).unwrap());
+        assert!(!tracker.report(&again[0]));
+
+        // Two proposals for different blocks in one round
+        engine.on_message(ConsensusMessage::Proposal(validators.proposal(b"block"))).unwrap();
+        let found = evidence(engine.on_message(ConsensusMessage::Proposal(validators.proposal(b"other block"))).unwrap());
+        assert!(matches!(&found[..], [Evidence::DoubleProposal { .. }]));
+        assert!(tracker.report(&found[0]));
+
+        let events = tracker.slash(&mut validators.economics);
+        assert_eq!(events.iter().map(|event| (event.validator, event.offense)).collect::<Vec<_>>(), vec![
+            (signer, ValidatorOffense::DoubleSign),
+            (proposer, ValidatorOffense::DoubleSign),
+        ]);
+        assert_eq!(validators.economics.stake_of(&signer), Some(tokens(19_000)));
+        assert!(tracker.pending().is_empty());
+    }
+
+    #[test]
+    fn test_invalid_proposals_are_slashed() {
+        let mut validators = Validators::new();
+        let mut engine = validators.engine(|value| if value.data == b"bad block" { Err("Rejected") } else { Ok(()) });
+        let mut tracker = OffenseTracker::default();
+
+        let proposal = validators.proposal(b"bad block");
+        let found = evidence(engine.on_message(ConsensusMessage::Proposal(proposal.clone())).unwrap());
+        assert_eq!(found, vec![Evidence::InvalidProposal { proposal: proposal.clone() }]);
+        assert!(tracker.report(&found[0]));
+
+        let events = tracker.slash(&mut validators.economics);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].offense, ValidatorOffense::InvalidBlock);
+        assert_eq!(validators.economics.stake_of(&proposal.proposer), Some(tokens(19_800)));
+    }
+
+    #[test]
+    fn test_missed_precommits_are_slashed_for_downtime() {
+        let mut validators = Validators::new();
+        let electorate = validators.electorate();
+        let voters: Vec<[u8; 32]> = electorate.voters().copied().collect();
+        let offline = voters[3];
+        let commit = |height: u64, signers: &[[u8; 32]]| Commit {
+            height,
+            round: 0,
+            value: [9u8; 32],
+            precommits: signers.iter()
+                .map(|validator| Vote { kind: VoteKind::Precommit, height, round: 0, value: Some([9u8; 32]), validator: *validator, signature: Vec::new() })
+                .collect(),
+        };
+
+        // More than two of the last four heights missed
+        let mut tracker = OffenseTracker::new(4, 2);
+        tracker.record_commit(&commit(1, &voters[..3]), &electorate);
+        tracker.record_commit(&commit(2, &voters), &electorate);
+        tracker.record_commit(&commit(3, &voters[..3]), &electorate);
+        assert!(tracker.pending().is_empty());
+        tracker.record_commit(&commit(4, &voters[..3]), &electorate);
+        assert_eq!(tracker.pending(), &[Offense { validator: offline, offense: ValidatorOffense::Downtime, height: 4 }]);
+
+        // The count starts over, and misses older than the window expire
+        tracker.slash(&mut validators.economics);
+        tracker.record_commit(&commit(5, &voters[..3]), &electorate);
+        tracker.record_commit(&commit(9, &voters[..3]), &electorate);
+        tracker.record_commit(&commit(10, &voters[..3]), &electorate);
+        assert!(tracker.pending().is_empty());
+        assert_eq!(validators.economics.stake_of(&offline), Some(tokens(19_980)));
+    }
+}
@@ -0,0 +1,92 @@
+use crate::crypto::domain::SigningDomain;
+use crate::security::quantum_resistant::QuantumSecurity;
+use super::tendermint::ValueCheck;
+use super::{
+    BlockCheck, Commit, ConsensusEngine, ConsensusMessage, Electorate, EngineKind, LocalValidator,
+    Output, Tendermint, Timeout, Timeouts, Value,
+};
+
+/// Proof-of-Stake Engine
+/// `Tendermint` with voting power from stake, voting only for blocks whose
+/// proof passes the quantum tally check the orchestration layer applies.
+pub struct PosEngine {
+    tendermint: Tendermint,
+    precision: u8,
+    check: BlockCheck,
+}
+
+impl PosEngine {
+    pub fn new(domain: SigningDomain, security: QuantumSecurity, local: Option<LocalValidator>, timeouts: Timeouts, precision: u8) -> Self {
+        Self::with_check(domain, security, local, timeouts, precision, |_| Ok(()))
+    }
+
+    /// Engine that also votes only for values `check` accepts
+    pub fn with_check(
+        domain: SigningDomain,
+        security: QuantumSecurity,
+        local: Option<LocalValidator>,
+        timeouts: Timeouts,
+        precision: u8,
+        check: BlockCheck,
+    ) -> Self {
+        let value_check: ValueCheck = Box::new(move |value| {
+            tally_check(precision, value)?;
+            check(value)
+        });
+        Self {
+            tendermint: Tendermint::new(domain, security, local, value_check, timeouts),
+            precision,
+            check,
+        }
+    }
+
+    pub fn tendermint(&self) -> &Tendermint {
+        &self.tendermint
+    }
+}
+
+/// A block needs data and a proof whose leading hash is quantum resistant
+fn tally_check(precision: u8, value: &Value) -> Result<(), &'static str> {
+    if value.data.is_empty() || value.proof.is_empty() {
+        return Err("Empty input state, operation, or proof");
+    }
+    if !QuantumSecurity::new(precision).verify_proof(&value.proof) {
+        return Err("quantum security verification failed");
+    }
+    Ok(())
+}
+
+impl ConsensusEngine for PosEngine {
+    fn kind(&self) -> EngineKind {
+        EngineKind::Pos
+    }
+
+    fn domain(&self) -> &SigningDomain {
+        self.tendermint.domain()
+    }
+
+    fn propose(&mut self, height: u64, electorate: Electorate, value: Option<Value>) -> Result<Vec<Output>, &'static str> {
+        self.tendermint.start_height(height, electorate, value)
+    }
+
+    fn validate(&self, value: &Value) -> Result<(), &'static str> {
+        tally_check(self.precision, value)?;
+        (self.check)(value)
+    }
+
+    fn finalize(&self, value: &Value, commit: &Commit, electorate: &Electorate) -> Result<(), &'static str> {
+        if commit.value != value.id() {
+            return Err("Commit for another value");
+        }
+        commit.verify(self.tendermint.domain(), electorate, self.tendermint.security())?;
+        self.validate(value)
+    }
+
+    fn on_message(&mut self, message: ConsensusMessage) -> Result<Vec<Output>, &'static str> {
+        self.tendermint.on_message(message)
+    }
+
+    fn on_timeout(&mut self, timeout: Timeout) -> Result<Vec<Output>, &'static str> {
+        self.tendermint.on_timeout(timeout)
+    }
+}
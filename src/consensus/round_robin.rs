@@ -0,0 +1,242 @@
+use crate::crypto::domain::SigningDomain;
+use crate::security::quantum_resistant::QuantumSecurity;
+use super::{
+    BlockCheck, Commit, ConsensusEngine, ConsensusMessage, Decision, Electorate, EngineKind,
+    Evidence, LocalValidator, Output, Proposal, Value, Vote, VoteKind,
+};
+
+/// Round-Robin Engine
+/// Development consensus: validators take turns by height, in key order,
+/// and the proposer's own precommit decides the block. There is no fault
+/// tolerance, so it is refused on mainnet.
+pub struct RoundRobin {
+    domain: SigningDomain,
+    security: QuantumSecurity,
+    local: Option<LocalValidator>,
+    check: BlockCheck,
+    electorate: Option<Electorate>,
+    height: u64,
+    proposal: Option<Proposal>,
+    precommit: Option<Vote>,
+    decided: bool,
+}
+
+impl RoundRobin {
+    pub fn new(domain: SigningDomain, security: QuantumSecurity, local: Option<LocalValidator>) -> Self {
+        Self::with_check(domain, security, local, |_| Ok(()))
+    }
+
+    /// Engine that also accepts only values `check` accepts
+    pub fn with_check(domain: SigningDomain, security: QuantumSecurity, local: Option<LocalValidator>, check: BlockCheck) -> Self {
+        Self {
+            domain,
+            security,
+            local,
+            check,
+            electorate: None,
+            height: 0,
+            proposal: None,
+            precommit: None,
+            decided: false,
+        }
+    }
+
+    /// Validator whose turn `height` is
+    pub fn proposer(electorate: &Electorate, height: u64) -> Option<[u8; 32]> {
+        let count = electorate.voters().count() as u64;
+        if count == 0 {
+            return None;
+        }
+        electorate.voters().nth((height % count) as usize).copied()
+    }
+
+    fn electorate(&self) -> Result<&Electorate, &'static str> {
+        self.electorate.as_ref().ok_or("No height started")
+    }
+
+    /// Check that a message is signed by the current height's proposer
+    fn check_proposer(&self, height: u64, signer: &[u8; 32], message: &[u8], signature: &[u8]) -> Result<(), &'static str> {
+        if height != self.height {
+            return Err("Message for another height");
+        }
+        let electorate = self.electorate()?;
+        if Self::proposer(electorate, height) != Some(*signer) {
+            return Err("Message from the wrong proposer");
+        }
+        electorate.verify(signer, message, signature, &self.security)
+    }
+
+    fn try_decide(&mut self, out: &mut Vec<Output>) {
+        if self.decided {
+            return;
+        }
+        let (Some(proposal), Some(precommit)) = (&self.proposal, &self.precommit) else {
+            return;
+        };
+        let value = proposal.value.id();
+        if precommit.value != Some(value) {
+            return;
+        }
+        self.decided = true;
+        out.push(Output::Decide(Box::new(Decision {
+            commit: Commit { height: self.height, round: 0, value, precommits: vec![precommit.clone()] },
+            proposal: proposal.clone(),
+        })));
+    }
+}
+
+impl ConsensusEngine for RoundRobin {
+    fn kind(&self) -> EngineKind {
+        EngineKind::RoundRobin
+    }
+
+    fn domain(&self) -> &SigningDomain {
+        &self.domain
+    }
+
+    fn propose(&mut self, height: u64, electorate: Electorate, value: Option<Value>) -> Result<Vec<Output>, &'static str> {
+        let proposer = Self::proposer(&electorate, height).ok_or("No validators can vote")?;
+        self.electorate = Some(electorate);
+        self.height = height;
+        self.proposal = None;
+        self.precommit = None;
+        self.decided = false;
+
+        let mut out = Vec::new();
+        let (Some(local), Some(value)) = (self.local.filter(|local| local.id == proposer), value) else {
+            return Ok(out);
+        };
+        self.validate(&value)?;
+        let mut proposal = Proposal { height, round: 0, valid_round: None, value, proposer, signature: Vec::new() };
+        proposal.signature = self.security.sign(&local.key_id, &proposal.signing_bytes(&self.domain))?;
+        let mut precommit = Vote {
+            kind: VoteKind::Precommit,
+            height,
+            round: 0,
+            value: Some(proposal.value.id()),
+            validator: proposer,
+            signature: Vec::new(),
+        };
+        precommit.signature = self.security.sign(&local.key_id, &precommit.signing_bytes(&self.domain))?;
+
+        out.push(Output::Broadcast(ConsensusMessage::Proposal(proposal.clone())));
+        out.push(Output::Broadcast(ConsensusMessage::Vote(precommit.clone())));
+        self.proposal = Some(proposal);
+        self.precommit = Some(precommit);
+        self.try_decide(&mut out);
+        Ok(out)
+    }
+
+    fn validate(&self, value: &Value) -> Result<(), &'static str> {
+        if value.data.is_empty() {
+            return Err("Empty block data");
+        }
+        (self.check)(value)
+    }
+
+    fn finalize(&self, value: &Value, commit: &Commit, electorate: &Electorate) -> Result<(), &'static str> {
+        if commit.value != value.id() {
+            return Err("Commit for another value");
+        }
+        let [vote] = commit.precommits.as_slice() else {
+            return Err("Commit must hold the proposer's precommit only");
+        };
+        if vote.kind != VoteKind::Precommit || vote.height != commit.height || vote.round != commit.round || vote.value != Some(commit.value) {
+            return Err("Commit vote for another step");
+        }
+        if Self::proposer(electorate, commit.height) != Some(vote.validator) {
+            return Err("Commit not signed by the height's proposer");
+        }
+        electorate.verify(&vote.validator, &vote.signing_bytes(&self.domain), &vote.signature, &self.security)?;
+        self.validate(value)
+    }
+
+    fn on_message(&mut self, message: ConsensusMessage) -> Result<Vec<Output>, &'static str> {
+        match message {
+            ConsensusMessage::Proposal(proposal) => {
+                self.check_proposer(proposal.height, &proposal.proposer, &proposal.signing_bytes(&self.domain), &proposal.signature)?;
+                if let Some(existing) = &self.proposal {
+                    if existing.value.id() == proposal.value.id() {
+                        return Ok(Vec::new());
+                    }
+                    return Ok(vec![Output::Evidence(Evidence::DoubleProposal { first: existing.clone(), second: proposal })]);
+                }
+                if self.validate(&proposal.value).is_err() {
+                    return Ok(vec![Output::Evidence(Evidence::InvalidProposal { proposal })]);
+                }
+                self.proposal = Some(proposal);
+            }
+            ConsensusMessage::Vote(vote) => {
+                if vote.kind != VoteKind::Precommit {
+                    return Err("Round-robin consensus has no prevotes");
+                }
+                self.check_proposer(vote.height, &vote.validator, &vote.signing_bytes(&self.domain), &vote.signature)?;
+                self.precommit = Some(vote);
+            }
+        }
+        let mut out = Vec::new();
+        self.try_decide(&mut out);
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::domain::MAINNET_NETWORK_ID;
+    use crate::economics::models::EconomicModel;
+    use crate::epoch::EpochSchedule;
+    use crate::layers::validator_set::ValidatorSet;
+    use crate::math::precision::PreciseFloat;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_validators_take_turns() {
+        let mut economics = EconomicModel::new(2);
+        let mut set = ValidatorSet::new(EpochSchedule::default(), 10);
+        let mut vote_keys = HashMap::new();
+        let mut engines = Vec::new();
+        for seed in 1..=3u8 {
+            let mut security = QuantumSecurity::new(2);
+            let (key_id, key) = security.generate_key_pair().unwrap();
+            let id = [seed; 32];
+            set.join(id).unwrap();
+            economics.stake_tokens(id, PreciseFloat::new(200000, 2)).unwrap();
+            vote_keys.insert(id, key.verification_key().to_vec());
+            let local = LocalValidator { id, key_id };
+            engines.push(RoundRobin::new(SigningDomain::main_chain(MAINNET_NETWORK_ID), security, Some(local)));
+        }
+        set.rotate(0, &economics).unwrap();
+
+        // Height 4 is the second validator's turn
+        let value = Value::new([0u8; 32], b"dev block".to_vec(), Vec::new());
+        let mut broadcasts = Vec::new();
+        let mut decisions = Vec::new();
+        for engine in engines.iter_mut() {
+            for output in engine.propose(4, Electorate::new(&set, &vote_keys), Some(value.clone())).unwrap() {
+                match output {
+                    Output::Broadcast(message) => broadcasts.push(message),
+                    Output::Decide(decision) => decisions.push(*decision),
+                    _ => {}
+                }
+            }
+        }
+        assert_eq!(decisions.len(), 1, "The proposer decides alone");
+        assert_eq!(decisions[0].proposal.proposer, [2u8; 32]);
+        for index in [0, 2] {
+            let mut out = Vec::new();
+            for message in &broadcasts {
+                out.extend(engines[index].on_message(message.clone()).unwrap());
+            }
+            assert!(matches!(&out[..], [Output::Decide(decision)] if decision.commit == decisions[0].commit));
+        }
+
+        let electorate = Electorate::new(&set, &vote_keys);
+        let commit = &decisions[0].commit;
+        engines[0].finalize(&value, commit, &electorate).unwrap();
+        let mut late = commit.clone();
+        late.height = 5;
+        late.precommits[0].height = 5;
+        assert_eq!(engines[0].finalize(&value, &late, &electorate), Err("Commit not signed by the height's proposer"));
+    }
+}
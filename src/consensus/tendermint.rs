@@ -0,0 +1,529 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use crate::crypto::domain::SigningDomain;
+use crate::security::quantum_resistant::QuantumSecurity;
+use super::{
+    Commit, ConsensusMessage, Decision, Electorate, Evidence, LocalValidator, Output, Proposal,
+    Step, Timeout, Timeouts, Value, Vote, VoteKind,
+};
+
+/// Checks a proposed value before this validator prevotes for it
+pub type ValueCheck = Box<dyn Fn(&Value) -> Result<(), &'static str> + Send + Sync>;
+
+/// Actions that happen at most once per round
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Once {
+    PrevoteTimeout,
+    PrecommitTimeout,
+    Polka,
+}
+
+/// Tendermint
+/// Consensus state for the height being decided. The engine does no I/O:
+/// the caller feeds it messages and expired timeouts, and carries out the
+/// returned `Output`s.
+pub struct Tendermint {
+    domain: SigningDomain,
+    security: QuantumSecurity,
+    local: Option<LocalValidator>,
+    check: ValueCheck,
+    timeouts: Timeouts,
+    electorate: Electorate,
+    height: u64,
+    round: u32,
+    step: Step,
+    /// Value this validator precommitted, and the round it did so in
+    locked: Option<(u32, Value)>,
+    /// Latest value more than two thirds prevoted for
+    valid: Option<(u32, Value)>,
+    /// Value to propose when no earlier round produced a valid one
+    pending: Option<Value>,
+    proposals: BTreeMap<u32, Proposal>,
+    votes: BTreeMap<(u32, VoteKind), BTreeMap<[u8; 32], Vote>>,
+    /// Outcome of `check` per value ID
+    checked: HashMap<[u8; 32], bool>,
+    done: BTreeSet<(u32, Once)>,
+    decided: bool,
+}
+
+impl Tendermint {
+    /// Engine for validators of `domain`. Without a `local` validator the
+    /// node follows the votes and decisions without voting.
+    pub fn new(domain: SigningDomain, security: QuantumSecurity, local: Option<LocalValidator>, check: ValueCheck, timeouts: Timeouts) -> Self {
+        Self {
+            domain,
+            security,
+            local,
+            check,
+            timeouts,
+            electorate: Electorate { voters: BTreeMap::new(), total_weight: 0 },
+            height: 0,
+            round: 0,
+            step: Step::Propose,
+            locked: None,
+            valid: None,
+            pending: None,
+            proposals: BTreeMap::new(),
+            votes: BTreeMap::new(),
+            checked: HashMap::new(),
+            done: BTreeSet::new(),
+            decided: false,
+        }
+    }
+
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    pub fn round(&self) -> u32 {
+        self.round
+    }
+
+    pub fn step(&self) -> Step {
+        self.step
+    }
+
+    pub fn domain(&self) -> &SigningDomain {
+        &self.domain
+    }
+
+    pub fn electorate(&self) -> &Electorate {
+        &self.electorate
+    }
+
+    pub fn security(&self) -> &QuantumSecurity {
+        &self.security
+    }
+
+    /// Begin deciding `height` with `electorate`. `value` is proposed if
+    /// this validator is picked as a proposer.
+    pub fn start_height(&mut self, height: u64, electorate: Electorate, value: Option<Value>) -> Result<Vec<Output>, &'static str> {
+        if electorate.is_empty() {
+            return Err("No validators can vote");
+        }
+        self.electorate = electorate;
+        self.height = height;
+        self.locked = None;
+        self.valid = None;
+        self.pending = value;
+        self.proposals.clear();
+        self.votes.clear();
+        self.checked.clear();
+        self.done.clear();
+        self.decided = false;
+
+        let mut out = Vec::new();
+        self.start_round(0, &mut out)?;
+        Ok(out)
+    }
+
+    /// Handle a proposal or vote from another validator
+    pub fn on_message(&mut self, message: ConsensusMessage) -> Result<Vec<Output>, &'static str> {
+        let mut out = Vec::new();
+        match message {
+            ConsensusMessage::Proposal(proposal) => {
+                if proposal.height != self.height {
+                    return Err("Proposal for another height");
+                }
+                if self.electorate.proposer(proposal.height, proposal.round) != Some(proposal.proposer) {
+                    return Err("Proposal from the wrong proposer");
+                }
+                self.electorate.verify(&proposal.proposer, &proposal.signing_bytes(&self.domain), &proposal.signature, &self.security)?;
+                if let Some(existing) = self.proposals.get(&proposal.round) {
+                    if existing.value.id() != proposal.value.id() {
+                        out.push(Output::Evidence(Evidence::DoubleProposal { first: existing.clone(), second: proposal }));
+                    }
+                    return Ok(out);
+                }
+                // Signed, so the proposer can be held to a block that fails the check
+                if !self.is_valid(&proposal.value) {
+                    out.push(Output::Evidence(Evidence::InvalidProposal { proposal: proposal.clone() }));
+                }
+                self.proposals.insert(proposal.round, proposal);
+            }
+            ConsensusMessage::Vote(vote) => {
+                if vote.height != self.height {
+                    return Err("Vote for another height");
+                }
+                self.electorate.verify(&vote.validator, &vote.signing_bytes(&self.domain), &vote.signature, &self.security)?;
+                let round = vote.round;
+                if !self.record_vote(vote, &mut out) {
+                    return Ok(out);
+                }
+                // Enough stake moved on that at least one honest validator is in a later round
+                if round > self.round && !self.decided && self.round_weight(round) >= self.electorate.honest_weight() {
+                    self.start_round(round, &mut out)?;
+                }
+            }
+        }
+        self.evaluate(&mut out)?;
+        Ok(out)
+    }
+
+    /// Handle a timeout the engine scheduled earlier
+    pub fn on_timeout(&mut self, timeout: Timeout) -> Result<Vec<Output>, &'static str> {
+        let mut out = Vec::new();
+        if timeout.height != self.height || timeout.round != self.round || self.decided {
+            return Ok(out);
+        }
+        match (timeout.step, self.step) {
+            (Step::Propose, Step::Propose) => self.vote(VoteKind::Prevote, None, &mut out)?,
+            (Step::Prevote, Step::Prevote) => self.vote(VoteKind::Precommit, None, &mut out)?,
+            (Step::Precommit, _) => self.start_round(self.round + 1, &mut out)?,
+            _ => return Ok(out),
+        }
+        self.evaluate(&mut out)?;
+        Ok(out)
+    }
+
+    fn start_round(&mut self, round: u32, out: &mut Vec<Output>) -> Result<(), &'static str> {
+        self.round = round;
+        self.step = Step::Propose;
+        let proposer = self.electorate.proposer(self.height, round);
+        if let Some(local) = self.local.filter(|local| proposer == Some(local.id)) {
+            // A value that already gathered a polka is proposed again rather than a new one
+            let (valid_round, value) = match &self.valid {
+                Some((valid_round, value)) => (Some(*valid_round), Some(value.clone())),
+                None => (None, self.pending.clone()),
+            };
+            if let Some(value) = value {
+                let mut proposal = Proposal {
+                    height: self.height,
+                    round,
+                    valid_round,
+                    value,
+                    proposer: local.id,
+                    signature: Vec::new(),
+                };
+                proposal.signature = self.security.sign(&local.key_id, &proposal.signing_bytes(&self.domain))?;
+                self.proposals.insert(round, proposal.clone());
+                out.push(Output::Broadcast(ConsensusMessage::Proposal(proposal)));
+            }
+        }
+        self.schedule(Step::Propose, out);
+        self.evaluate(out)
+    }
+
+    /// Apply every rule whose condition holds until none does
+    fn evaluate(&mut self, out: &mut Vec<Output>) -> Result<(), &'static str> {
+        loop {
+            if self.decided {
+                return Ok(());
+            }
+            if self.try_decide(out) {
+                continue;
+            }
+            let round = self.round;
+            let proposal = self.proposals.get(&round).cloned();
+
+            if self.step == Step::Propose {
+                if let Some(proposal) = &proposal {
+                    let id = proposal.value.id();
+                    let prevote = match proposal.valid_round {
+                        None => Some(self.is_valid(&proposal.value)
+                            && self.locked.as_ref().is_none_or(|(_, locked)| locked.id() == id)),
+                        Some(valid_round) if valid_round < round
+                            && self.has_quorum(valid_round, VoteKind::Prevote, Some(Some(id))) => {
+                            Some(self.is_valid(&proposal.value)
+                                && self.locked.as_ref().is_none_or(|(locked_round, locked)| *locked_round <= valid_round || locked.id() == id))
+                        }
+                        Some(_) => None,
+                    };
+                    if let Some(accept) = prevote {
+                        self.vote(VoteKind::Prevote, accept.then_some(id), out)?;
+                        continue;
+                    }
+                }
+            }
+
+            if self.step == Step::Prevote
+                && self.has_quorum(round, VoteKind::Prevote, None)
+                && self.done.insert((round, Once::PrevoteTimeout)) {
+                self.schedule(Step::Prevote, out);
+            }
+
+            if let Some(proposal) = &proposal {
+                let id = proposal.value.id();
+                if self.step >= Step::Prevote
+                    && !self.done.contains(&(round, Once::Polka))
+                    && self.has_quorum(round, VoteKind::Prevote, Some(Some(id)))
+                    && self.is_valid(&proposal.value) {
+                    self.done.insert((round, Once::Polka));
+                    if self.step == Step::Prevote {
+                        self.locked = Some((round, proposal.value.clone()));
+                        self.vote(VoteKind::Precommit, Some(id), out)?;
+                    }
+                    self.valid = Some((round, proposal.value.clone()));
+                    continue;
+                }
+            }
+
+            if self.step == Step::Prevote && self.has_quorum(round, VoteKind::Prevote, Some(None)) {
+                self.vote(VoteKind::Precommit, None, out)?;
+                continue;
+            }
+
+            if self.has_quorum(round, VoteKind::Precommit, None) && self.done.insert((round, Once::PrecommitTimeout)) {
+                self.schedule(Step::Precommit, out);
+            }
+            return Ok(());
+        }
+    }
+
+    /// Decide any round's proposal that more than two thirds precommitted
+    fn try_decide(&mut self, out: &mut Vec<Output>) -> bool {
+        let rounds: Vec<u32> = self.proposals.keys().copied().collect();
+        for round in rounds {
+            let proposal = self.proposals[&round].clone();
+            let id = proposal.value.id();
+            if !self.has_quorum(round, VoteKind::Precommit, Some(Some(id))) || !self.is_valid(&proposal.value) {
+                continue;
+            }
+            let precommits = self.votes.get(&(round, VoteKind::Precommit))
+                .map(|votes| votes.values().filter(|vote| vote.value == Some(id)).cloned().collect())
+                .unwrap_or_default();
+            self.decided = true;
+            self.step = Step::Commit;
+            out.push(Output::Decide(Box::new(Decision {
+                commit: Commit { height: self.height, round, value: id, precommits },
+                proposal,
+            })));
+            return true;
+        }
+        false
+    }
+
+    /// Sign and broadcast this validator's vote, and move to the next step
+    fn vote(&mut self, kind: VoteKind, value: Option<[u8; 32]>, out: &mut Vec<Output>) -> Result<(), &'static str> {
+        self.step = match kind {
+            VoteKind::Prevote => Step::Prevote,
+            VoteKind::Precommit => Step::Precommit,
+        };
+        let Some(local) = self.local.filter(|local| self.electorate.is_voter(&local.id)) else {
+            return Ok(());
+        };
+        let mut vote = Vote {
+            kind,
+            height: self.height,
+            round: self.round,
+            value,
+            validator: local.id,
+            signature: Vec::new(),
+        };
+        vote.signature = self.security.sign(&local.key_id, &vote.signing_bytes(&self.domain))?;
+        self.record_vote(vote.clone(), out);
+        out.push(Output::Broadcast(ConsensusMessage::Vote(vote)));
+        Ok(())
+    }
+
+    /// Store a verified vote. A second, different vote from the same
+    /// validator for the same step is reported as evidence and ignored.
+    fn record_vote(&mut self, vote: Vote, out: &mut Vec<Output>) -> bool {
+        let votes = self.votes.entry((vote.round, vote.kind)).or_default();
+        match votes.get(&vote.validator) {
+            Some(first) if first.value != vote.value => {
+                out.push(Output::Evidence(Evidence::DoubleSign { first: first.clone(), second: vote }));
+                false
+            }
+            Some(_) => false,
+            None => {
+                votes.insert(vote.validator, vote);
+                true
+            }
+        }
+    }
+
+    /// Whether more than two thirds voted `kind` in `round`: for the value
+    /// `Some(value)` (`Some(None)` for nil), or for anything at all
+    fn has_quorum(&self, round: u32, kind: VoteKind, value: Option<Option<[u8; 32]>>) -> bool {
+        let weight: u128 = self.votes.get(&(round, kind))
+            .map(|votes| votes.values()
+                .filter(|vote| value.is_none_or(|value| vote.value == value))
+                .map(|vote| self.electorate.weight_of(&vote.validator))
+                .sum())
+            .unwrap_or(0);
+        weight >= self.electorate.quorum_weight()
+    }
+
+    /// Stake of the validators that voted in `round`
+    fn round_weight(&self, round: u32) -> u128 {
+        let voters: BTreeSet<&[u8; 32]> = [VoteKind::Prevote, VoteKind::Precommit].iter()
+            .filter_map(|kind| self.votes.get(&(round, *kind)))
+            .flat_map(|votes| votes.keys())
+            .collect();
+        voters.into_iter().map(|validator| self.electorate.weight_of(validator)).sum()
+    }
+
+    fn is_valid(&mut self, value: &Value) -> bool {
+        let id = value.id();
+        if let Some(valid) = self.checked.get(&id) {
+            return *valid;
+        }
+        let valid = (self.check)(value).is_ok();
+        self.checked.insert(id, valid);
+        valid
+    }
+
+    fn schedule(&self, step: Step, out: &mut Vec<Output>) {
+        let timeout = Timeout { height: self.height, round: self.round, step };
+        out.push(Output::Schedule(timeout, self.timeouts.duration(step, self.round)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::domain::MAINNET_NETWORK_ID;
+    use crate::economics::models::EconomicModel;
+    use crate::epoch::EpochSchedule;
+    use crate::layers::validator_set::ValidatorSet;
+    use crate::math::precision::PreciseFloat;
+    use std::collections::VecDeque;
+
+    struct Node {
+        id: [u8; 32],
+        engine: Tendermint,
+    }
+
+    struct Network {
+        nodes: Vec<Node>,
+        set: ValidatorSet,
+        vote_keys: HashMap<[u8; 32], Vec<u8>>,
+    }
+
+    impl Network {
+        /// Four equally staked validators, so any three form a quorum
+        fn new(check: impl Fn(&Value) -> Result<(), &'static str> + Clone + Send + Sync + 'static) -> Self {
+            let mut economics = EconomicModel::new(2);
+            let mut set = ValidatorSet::new(EpochSchedule::default(), 10);
+            let mut nodes = Vec::new();
+            let mut vote_keys = HashMap::new();
+            for seed in 1..=4u8 {
+                let mut security = QuantumSecurity::new(2);
+                let (key_id, key) = security.generate_key_pair().unwrap();
+                let id = [seed; 32];
+                set.join(id).unwrap();
+                economics.stake_tokens(id, PreciseFloat::new(200000, 2)).unwrap();
+                vote_keys.insert(id, key.verification_key().to_vec());
+                let domain = SigningDomain::main_chain(MAINNET_NETWORK_ID);
+                let engine = Tendermint::new(domain, security, Some(LocalValidator { id, key_id }), Box::new(check.clone()), Timeouts::default());
+                nodes.push(Node { id, engine });
+            }
+            set.rotate(0, &economics).unwrap();
+            Self { nodes, set, vote_keys }
+        }
+
+        fn electorate(&self) -> Electorate {
+            Electorate::new(&self.set, &self.vote_keys)
+        }
+
+        /// Every validator starts height 1 offering its own ID as the block data
+        fn start(&mut self) -> Vec<Decision> {
+            let mut queue = VecDeque::new();
+            for node in &mut self.nodes {
+                let value = Value::new([0u8; 32], node.id.to_vec(), vec![1u8; 64]);
+                let electorate = Electorate::new(&self.set, &self.vote_keys);
+                let out = node.engine.start_height(1, electorate, Some(value)).unwrap();
+                queue.extend(out.into_iter().map(|output| (node.id, output)));
+            }
+            self.deliver(queue)
+        }
+
+        /// Expire `step` on every validator
+        fn expire(&mut self, step: Step) -> Vec<Decision> {
+            let mut queue = VecDeque::new();
+            for node in &mut self.nodes {
+                let timeout = Timeout { height: node.engine.height(), round: node.engine.round(), step };
+                let out = node.engine.on_timeout(timeout).unwrap();
+                queue.extend(out.into_iter().map(|output| (node.id, output)));
+            }
+            self.deliver(queue)
+        }
+
+        /// Pass broadcasts between validators until nothing is left to send
+        fn deliver(&mut self, mut queue: VecDeque<([u8; 32], Output)>) -> Vec<Decision> {
+            let mut decisions = Vec::new();
+            while let Some((from, output)) = queue.pop_front() {
+                match output {
+                    Output::Broadcast(message) => {
+                        for node in self.nodes.iter_mut().filter(|node| node.id != from) {
+                            let out = node.engine.on_message(message.clone()).unwrap();
+                            queue.extend(out.into_iter().map(|output| (node.id, output)));
+                        }
+                    }
+                    Output::Decide(decision) => decisions.push(*decision),
+                    _ => {}
+                }
+            }
+            decisions
+        }
+    }
+
+    #[test]
+    fn test_validators_decide_with_a_verifiable_commit() {
+        let mut network = Network::new(|_| Ok(()));
+        let decisions = network.start();
+
+        assert_eq!(decisions.len(), 4, "Every validator decides");
+        let decided = &decisions[0];
+        assert!(decisions.iter().all(|decision| decision.proposal.value == decided.proposal.value));
+        let proposer = network.electorate().proposer(1, 0).unwrap();
+        assert_eq!(decided.proposal.value.data, proposer.to_vec());
+        assert!(network.nodes.iter().all(|node| node.engine.step() == Step::Commit));
+
+        let electorate = network.electorate();
+        let security = QuantumSecurity::new(2);
+        let domain = SigningDomain::main_chain(MAINNET_NETWORK_ID);
+        decided.commit.verify(&domain, &electorate, &security).unwrap();
+
+        // Two precommits are not enough, and a commit for another value does not verify
+        let mut short = decided.commit.clone();
+        short.precommits.truncate(2);
+        assert_eq!(short.verify(&domain, &electorate, &security), Err("Validator quorum not reached"));
+        let mut forged = decided.commit.clone();
+        forged.value = [9u8; 32];
+        assert_eq!(forged.verify(&domain, &electorate, &security), Err("Commit vote for another value"));
+        assert!(decided.commit.verify(&SigningDomain::main_chain(2), &electorate, &security).is_err());
+    }
+
+    #[test]
+    fn test_rounds_advance_past_an_invalid_proposal() {
+        // Every validator rejects the block of the first round's proposer
+        let proposer = Network::new(|_| Ok(())).electorate().proposer(1, 0).unwrap();
+        let mut network = Network::new(move |value: &Value| {
+            if value.data == proposer.to_vec() { Err("Rejected") } else { Ok(()) }
+        });
+        assert!(network.start().is_empty());
+        assert!(network.nodes.iter().all(|node| node.engine.step() == Step::Precommit));
+
+        let mut decisions = Vec::new();
+        while decisions.is_empty() {
+            decisions = network.expire(Step::Precommit);
+            assert!(network.nodes[0].engine.round() < 20, "Consensus should eventually decide");
+        }
+        assert_eq!(decisions.len(), 4);
+        assert_ne!(decisions[0].proposal.value.data, proposer.to_vec());
+        assert!(decisions[0].commit.round > 0);
+    }
+
+    #[test]
+    fn test_double_signing_is_reported() {
+        let mut network = Network::new(|_| Ok(()));
+        network.start();
+        let signer = &network.nodes[0].engine;
+        let local = signer.local.unwrap();
+        let sign = |value: [u8; 32]| {
+            let mut vote = Vote { kind: VoteKind::Prevote, height: 1, round: 7, value: Some(value), validator: local.id, signature: Vec::new() };
+            vote.signature = signer.security.sign(&local.key_id, &vote.signing_bytes(signer.domain())).unwrap();
+            vote
+        };
+        let (first, second) = (sign([1u8; 32]), sign([2u8; 32]));
+        let mut forged = sign([3u8; 32]);
+        forged.round = 8;
+
+        let other = &mut network.nodes[1].engine;
+        assert!(other.on_message(ConsensusMessage::Vote(first.clone())).unwrap().is_empty());
+        let out = other.on_message(ConsensusMessage::Vote(second.clone())).unwrap();
+        assert_eq!(out, vec![Output::Evidence(Evidence::DoubleSign { first, second })]);
+        assert_eq!(other.on_message(ConsensusMessage::Vote(forged)), Err("Invalid validator signature"));
+    }
+}
@@ -0,0 +1,125 @@
+use serde::{Serialize, Deserialize};
+use std::collections::{HashSet, VecDeque};
+use crate::network::qkd::SealedMessage;
+use crate::network::quantum_network::QuantumNetwork;
+use super::ConsensusMessage;
+
+/// Envelope IDs a node remembers to stop relaying an envelope twice
+pub const SEEN_ENVELOPES: usize = 4096;
+
+/// Consensus Envelope
+/// One consensus message sealed for each other validator. Peers pass the
+/// whole envelope on, so validators reach each other through nodes in
+/// between, and every validator opens only the copy sealed for it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Envelope {
+    pub sealed: Vec<SealedMessage>,
+}
+
+impl Envelope {
+    /// ID relays deduplicate the envelope by
+    pub fn id(&self) -> [u8; 32] {
+        let mut hasher = blake3::Hasher::new();
+        for sealed in &self.sealed {
+            hasher.update(&sealed.from);
+            hasher.update(&sealed.to);
+            hasher.update(&sealed.epoch.to_le_bytes());
+            hasher.update(&sealed.offset.to_le_bytes());
+            hasher.update(&sealed.tag);
+        }
+        hasher.finalize().into()
+    }
+
+    /// Copy sealed for `validator`, if the envelope is addressed to it
+    pub fn sealed_for(&self, validator: &[u8; 32]) -> Option<&SealedMessage> {
+        self.sealed.iter().find(|sealed| sealed.to == *validator)
+    }
+}
+
+/// Recently relayed envelopes, oldest forgotten first
+#[derive(Default)]
+pub struct SeenEnvelopes {
+    order: VecDeque<[u8; 32]>,
+    ids: HashSet<[u8; 32]>,
+}
+
+impl SeenEnvelopes {
+    /// Remember `envelope`; false if it was seen before
+    pub fn insert(&mut self, envelope: &Envelope) -> bool {
+        let id = envelope.id();
+        if !self.ids.insert(id) {
+            return false;
+        }
+        self.order.push_back(id);
+        if self.order.len() > SEEN_ENVELOPES {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+/// Seal `message` for each of `peers` over the quantum network. Every
+/// validator needs a secure route to the others; a peer without one is
+/// reported rather than skipped, since its vote may be needed for a quorum.
+pub fn broadcast(network: &mut QuantumNetwork, from: [u8; 32], peers: &[[u8; 32]], message: &ConsensusMessage) -> Result<Vec<SealedMessage>, &'static str> {
+    let bytes = serde_json::to_vec(message).map_err(|_| "Failed to encode consensus message")?;
+    peers.iter()
+        .filter(|peer| **peer != from)
+        .map(|peer| network.send_quantum_message(from, *peer, &bytes))
+        .collect()
+}
+
+/// Open a sealed consensus message. Signatures are checked by the engine.
+pub fn receive(network: &mut QuantumNetwork, sealed: &SealedMessage) -> Result<ConsensusMessage, &'static str> {
+    let bytes = network.receive_quantum_message(sealed)?;
+    serde_json::from_slice(&bytes).map_err(|_| "Invalid consensus message")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::{Vote, VoteKind};
+    use crate::math::precision::PreciseFloat;
+    use crate::network::quantum_network::QuantumState;
+
+    #[test]
+    fn test_votes_travel_over_quantum_links() {
+        let mut network = QuantumNetwork::new(2);
+        let (a, b, c) = ([1u8; 32], [2u8; 32], [3u8; 32]);
+        for id in [a, b, c] {
+            network.add_node(id, QuantumState {
+                superposition: PreciseFloat::new(99, 2),
+                coherence: PreciseFloat::new(99, 2),
+                entanglement_strength: PreciseFloat::new(1, 0),
+            });
+        }
+        network.create_entanglement(a, b).unwrap();
+        network.create_entanglement(a, c).unwrap();
+
+        let vote = ConsensusMessage::Vote(Vote {
+            kind: VoteKind::Prevote,
+            height: 4,
+            round: 0,
+            value: None,
+            validator: a,
+            signature: vec![7u8; 16],
+        });
+        let sealed = broadcast(&mut network, a, &[a, b, c], &vote).unwrap();
+        assert_eq!(sealed.len(), 2);
+        for message in &sealed {
+            assert_eq!(receive(&mut network, message).unwrap(), vote);
+        }
+        // b and c share no link
+        assert!(broadcast(&mut network, b, &[c], &vote).is_err());
+
+        // An envelope is relayed once, and each validator finds its copy
+        let envelope = Envelope { sealed: broadcast(&mut network, a, &[a, b, c], &vote).unwrap() };
+        let mut seen = SeenEnvelopes::default();
+        assert!(seen.insert(&envelope));
+        assert!(!seen.insert(&envelope.clone()));
+        assert_eq!(receive(&mut network, envelope.sealed_for(&c).unwrap()).unwrap(), vote);
+        assert!(envelope.sealed_for(&a).is_none());
+    }
+}
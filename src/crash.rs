@@ -0,0 +1,366 @@
+//! Crash capture for bug reports.
+//!
+//! `install` sets a panic hook that writes a structured report before the
+//! default hook runs: the panic message, where it happened and in which
+//! module, a backtrace, the most recent log lines, and a fingerprint of the
+//! node's state (head block, last tally checkpoint, config digest). Services
+//! keep the fingerprint current through `CrashReporter::update`. Reports are
+//! written to a local directory; with telemetry crash reporting enabled they
+//! are also uploaded, after the fact, by the telemetry service.
+
+use serde::{Serialize, Deserialize};
+use std::collections::VecDeque;
+use std::io;
+use std::panic::PanicHookInfo;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use tracing_subscriber::fmt::MakeWriter;
+use crate::blockchain::types::hex_serde_option;
+use crate::config::NodeConfig;
+
+/// Layout version of `CrashReport`
+pub const REPORT_VERSION: u32 = 1;
+
+/// Log lines kept for crash reports
+pub const LOG_RING_LINES: usize = 200;
+
+/// Suffix given to reports once uploaded
+const SENT_SUFFIX: &str = ".sent.json";
+
+static REPORTER: OnceLock<CrashReporter> = OnceLock::new();
+
+/// Most recent log lines, fed by a `tracing` fmt layer
+#[derive(Clone)]
+pub struct LogRing {
+    lines: Arc<Mutex<VecDeque<String>>>,
+    capacity: usize,
+}
+
+impl LogRing {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            lines: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity: capacity.max(1),
+        }
+    }
+
+    pub fn push(&self, line: &str) {
+        let Ok(mut lines) = self.lines.lock() else { return };
+        if lines.len() == self.capacity {
+            lines.pop_front();
+        }
+        lines.push_back(line.to_string());
+    }
+
+    /// Oldest first. Never blocks, so it is safe inside the panic hook; if the
+    /// ring is locked by the panicking thread the lines are skipped.
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.try_lock()
+            .map(|lines| lines.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+impl<'a> MakeWriter<'a> for LogRing {
+    type Writer = RingWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RingWriter { ring: self.clone(), buffer: Vec::new() }
+    }
+}
+
+/// Collects one formatted event and adds its lines to the ring when dropped
+pub struct RingWriter {
+    ring: LogRing,
+    buffer: Vec<u8>,
+}
+
+impl io::Write for RingWriter {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for RingWriter {
+    fn drop(&mut self) {
+        for line in String::from_utf8_lossy(&self.buffer).lines() {
+            self.ring.push(line);
+        }
+    }
+}
+
+/// Where the node's state stood when it crashed
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StateFingerprint {
+    pub head_height: u64,
+    #[serde(with = "hex_serde_option")]
+    pub head_hash: Option<[u8; 32]>,
+    /// Last epoch whose tally checkpoint was recorded
+    pub checkpoint_epoch: Option<u64>,
+    #[serde(with = "hex_serde_option")]
+    pub checkpoint_root: Option<[u8; 32]>,
+    #[serde(with = "hex_serde_option")]
+    pub config_digest: Option<[u8; 32]>,
+}
+
+/// Everything captured about one panic
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CrashReport {
+    pub report_version: u32,
+    pub timestamp: u64,
+    /// Node software version
+    pub version: String,
+    pub os: String,
+    pub arch: String,
+    pub thread: Option<String>,
+    pub message: String,
+    /// `file:line:column` of the panic
+    pub location: Option<String>,
+    /// Module path derived from the panic location, e.g. `network::p2p`
+    pub module: Option<String>,
+    pub backtrace: String,
+    pub recent_logs: Vec<String>,
+    pub fingerprint: StateFingerprint,
+}
+
+/// Module path of a source file, relative to its crate's `src` directory
+pub fn module_of(file: &str) -> Option<String> {
+    let file = file.replace('\\', "/");
+    let relative = file.rsplit_once("src/").map(|(_, rest)| rest)?;
+    let path = relative.strip_suffix(".rs")?;
+    let path = path.strip_suffix("/mod").unwrap_or(path);
+    let module = match path {
+        "lib" | "main" => "crate".to_string(),
+        _ => path.replace('/', "::"),
+    };
+    Some(module)
+}
+
+/// Digest of the effective configuration, so reports can be matched to a
+/// config without including it
+pub fn config_digest(config: &NodeConfig) -> [u8; 32] {
+    let encoded = serde_json::to_vec(config).expect("node config serializes to JSON");
+    blake3::hash(&encoded).into()
+}
+
+/// Crash Reporter
+/// Holds what the panic hook needs: the report directory, the log ring and
+/// the latest state fingerprint.
+pub struct CrashReporter {
+    dir: PathBuf,
+    logs: LogRing,
+    fingerprint: Mutex<StateFingerprint>,
+}
+
+impl CrashReporter {
+    pub fn new(dir: impl Into<PathBuf>, logs: LogRing) -> Self {
+        Self {
+            dir: dir.into(),
+            logs,
+            fingerprint: Mutex::new(StateFingerprint::default()),
+        }
+    }
+
+    pub fn logs(&self) -> &LogRing {
+        &self.logs
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn update(&self, apply: impl FnOnce(&mut StateFingerprint)) {
+        if let Ok(mut fingerprint) = self.fingerprint.lock() {
+            apply(&mut fingerprint);
+        }
+    }
+
+    pub fn fingerprint(&self) -> StateFingerprint {
+        self.fingerprint.try_lock().map(|fingerprint| fingerprint.clone()).unwrap_or_default()
+    }
+
+    /// Build a report for a panic with `message` at `location`
+    pub fn capture(&self, message: String, location: Option<(&str, u32, u32)>, timestamp: u64) -> CrashReport {
+        CrashReport {
+            report_version: REPORT_VERSION,
+            timestamp,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            thread: std::thread::current().name().map(str::to_string),
+            message,
+            location: location.map(|(file, line, column)| format!("{}:{}:{}", file, line, column)),
+            module: location.and_then(|(file, _, _)| module_of(file)),
+            backtrace: std::backtrace::Backtrace::force_capture().to_string(),
+            recent_logs: self.logs.lines(),
+            fingerprint: self.fingerprint(),
+        }
+    }
+
+    /// Write `report` to the report directory; returns the file's path
+    pub fn write(&self, report: &CrashReport) -> Result<PathBuf, String> {
+        std::fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("Failed to create {}: {}", self.dir.display(), e))?;
+        let path = self.dir.join(format!("crash-{}-{}.json", report.timestamp, std::process::id()));
+        let encoded = serde_json::to_string_pretty(report).map_err(|e| e.to_string())?;
+        std::fs::write(&path, encoded).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        Ok(path)
+    }
+
+    /// Reports not yet uploaded, oldest first
+    pub fn pending_uploads(&self) -> Vec<PathBuf> {
+        list_reports(&self.dir).into_iter()
+            .filter(|path| !path.to_string_lossy().ends_with(SENT_SUFFIX))
+            .collect()
+    }
+
+    /// Record that the report at `path` has been uploaded
+    pub fn mark_uploaded(&self, path: &Path) -> Result<(), String> {
+        let name = path.file_name().and_then(|name| name.to_str()).ok_or("Invalid crash report path")?;
+        let sent = path.with_file_name(name.replace(".json", SENT_SUFFIX));
+        std::fs::rename(path, &sent).map_err(|e| format!("Failed to mark {} uploaded: {}", path.display(), e))
+    }
+}
+
+/// Crash report files in `dir`, oldest first
+pub fn list_reports(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else { return Vec::new() };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name().and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("crash-") && name.ends_with(".json"))
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+pub fn read_report(path: &Path) -> Result<CrashReport, String> {
+    let raw = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    serde_json::from_str(&raw).map_err(|e| format!("Invalid crash report {}: {}", path.display(), e))
+}
+
+/// The installed reporter, if any
+pub fn reporter() -> Option<&'static CrashReporter> {
+    REPORTER.get()
+}
+
+/// Install `reporter` and a panic hook that writes a report before running
+/// the previous hook. Only the first call installs anything.
+pub fn install(reporter: CrashReporter) -> &'static CrashReporter {
+    let mut installed = false;
+    let reporter = REPORTER.get_or_init(|| {
+        installed = true;
+        reporter
+    });
+    if installed {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if let Some(reporter) = REPORTER.get() {
+                let report = reporter.capture(panic_message(info), info.location().map(|l| (l.file(), l.line(), l.column())), now_secs());
+                match reporter.write(&report) {
+                    Ok(path) => eprintln!("Crash report written to {}", path.display()),
+                    Err(e) => eprintln!("Failed to write crash report: {}", e),
+                }
+            }
+            previous(info);
+        }));
+    }
+    reporter
+}
+
+fn panic_message(info: &PanicHookInfo<'_>) -> String {
+    if let Some(message) = info.payload().downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = info.payload().downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Box<dyn Any>".to_string()
+    }
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+/// Everything attached to a bug report
+#[derive(Debug, Serialize)]
+pub struct ReportBundle {
+    pub created_at: u64,
+    pub version: String,
+    pub os: String,
+    pub arch: String,
+    #[serde(with = "crate::blockchain::types::hex_serde")]
+    pub config_digest: [u8; 32],
+    pub config: NodeConfig,
+    pub crashes: Vec<CrashReport>,
+}
+
+impl ReportBundle {
+    /// Gather the config and every crash report in `crash_dir`
+    pub fn collect(crash_dir: &Path, config: NodeConfig) -> Result<Self, String> {
+        let crashes = list_reports(crash_dir).iter()
+            .map(|path| read_report(path))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            created_at: now_secs(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            config_digest: config_digest(&config),
+            config,
+            crashes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_module_of() {
+        assert_eq!(module_of("src/network/p2p.rs").as_deref(), Some("network::p2p"));
+        assert_eq!(module_of("src/export/mod.rs").as_deref(), Some("export"));
+        assert_eq!(module_of("src/main.rs").as_deref(), Some("crate"));
+        assert_eq!(module_of("/cargo/registry/src/tokio-1.28/src/runtime/task.rs").as_deref(), Some("runtime::task"));
+        assert_eq!(module_of("<unknown>"), None);
+    }
+
+    #[test]
+    fn test_report_round_trip() {
+        let dir = std::env::temp_dir().join(format!("crashes-{}", rand::random::<u64>()));
+        let logs = LogRing::new(2);
+        for line in ["one", "two", "three"] {
+            logs.push(line);
+        }
+        let reporter = CrashReporter::new(&dir, logs);
+        reporter.update(|fingerprint| {
+            fingerprint.head_height = 7;
+            fingerprint.config_digest = Some(config_digest(&NodeConfig::default()));
+        });
+
+        let report = reporter.capture("boom".to_string(), Some(("src/epoch.rs", 10, 5)), 1_000);
+        assert_eq!(report.module.as_deref(), Some("epoch"));
+        assert_eq!(report.recent_logs, vec!["two", "three"]);
+        let path = reporter.write(&report).unwrap();
+        assert_eq!(read_report(&path).unwrap(), report);
+
+        // Uploaded reports stay for bundles but are not sent again
+        assert_eq!(reporter.pending_uploads(), vec![path.clone()]);
+        reporter.mark_uploaded(&path).unwrap();
+        assert!(reporter.pending_uploads().is_empty());
+        let bundle = ReportBundle::collect(&dir, NodeConfig::default()).unwrap();
+        assert_eq!(bundle.crashes, vec![report]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
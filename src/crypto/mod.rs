@@ -1,3 +0,0 @@
-pub mod tally;
-
-pub use self::tally::{TallyProof, TallyState};
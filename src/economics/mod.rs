@@ -1 +1,3 @@
 pub mod models;
+pub mod providers;
+pub mod slashing;
@@ -1,5 +1,17 @@
 use crate::math::precision::PreciseFloat;
 use std::collections::HashMap;
+use crate::clock::{self, SharedClock};
+use crate::ids::ChainId;
+use crate::params::{ParamKey, ParamsRegistry};
+use num_traits::ToPrimitive;
+use serde::{Serialize, Deserialize};
+use super::providers::{Offense, ProviderId, ProviderKind, ProviderRegistry, Unbonding};
+use super::slashing::{SlashEvent, SlashingRates, ValidatorOffense};
+
+const SECONDS_PER_YEAR: f64 = 365.0 * 24.0 * 60.0 * 60.0;
+
+/// Most unstakes a validator may have waiting out the lockup at once
+pub const MAX_PENDING_UNBONDS: usize = 32;
 
 /// Economic Modeling System
 pub struct EconomicModel {
@@ -8,10 +20,39 @@ pub struct EconomicModel {
     state: SystemState,
     history: Vec<StateSnapshot>,
     validators: HashMap<ValidatorId, ValidatorState>,
+    hosting_revenue: HashMap<ChainId, PreciseFloat>,
+    /// Bonded storage, oracle and Web2 providers
+    providers: ProviderRegistry,
+    /// When validator rewards were last credited
+    rewards_paid_at: u64,
+    slashing: SlashingRates,
+    /// Slashes not yet taken by governance
+    slash_events: Vec<SlashEvent>,
+    clock: SharedClock,
 }
 
 type ValidatorId = [u8; 32];
 
+/// A validator's bonded stake, unclaimed rewards and unbonding queue
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidatorStake {
+    pub stake: PreciseFloat,
+    /// Rewards credited and not yet claimed
+    pub rewards: PreciseFloat,
+    /// Whether rewards are added to the stake as they are credited
+    pub auto_compound: bool,
+    /// Unstaked tokens waiting out the lockup, oldest first
+    pub unbonding: Vec<Unbonding>,
+}
+
+/// Token totals tracked by the economic model
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SupplySummary {
+    pub total_supply: PreciseFloat,
+    pub circulating_supply: PreciseFloat,
+    pub total_staked: PreciseFloat,
+}
+
 #[derive(Clone)]
 struct ModelParameters {
     inflation_rate: PreciseFloat,
@@ -39,6 +80,10 @@ struct ValidatorState {
     performance_score: PreciseFloat,
     last_active: u64,
     total_validated: u64,
+    offenses: u64,
+    auto_compound: bool,
+    /// Unstaked, still slashable and not yet back in circulation
+    unbonding: Vec<Unbonding>,
 }
 
 #[derive(Clone)]
@@ -50,14 +95,19 @@ struct StateSnapshot {
 
 impl EconomicModel {
     pub fn new(precision: u8) -> Self {
+        Self::with_clock(precision, clock::system())
+    }
+
+    /// Create a model that timestamps snapshots and validator activity with `clock`
+    pub fn with_clock(precision: u8, clock: SharedClock) -> Self {
         Self {
             precision,
             parameters: ModelParameters {
                 inflation_rate: PreciseFloat::new(200, 2), // 2.00% annual
                 transaction_fee_rate: PreciseFloat::new(10, 2), // 0.10%
-                validator_reward_rate: PreciseFloat::new(500, 2), // 5.00% annual
+                validator_reward_rate: ParamKey::ValidatorRewardRate.default_value(),
                 stake_lockup_period: 14 * 24 * 60 * 60, // 14 days in seconds
-                minimum_stake: PreciseFloat::new(100000, 2), // 1000.00 tokens
+                minimum_stake: ParamKey::MinimumStake.default_value(),
                 maximum_stake: PreciseFloat::new(1000000000, 2), // 10000000.00 tokens
             },
             state: SystemState {
@@ -70,6 +120,12 @@ impl EconomicModel {
             },
             history: Vec::new(),
             validators: HashMap::new(),
+            hosting_revenue: HashMap::new(),
+            providers: ProviderRegistry::new(),
+            rewards_paid_at: clock.now_secs(),
+            slashing: SlashingRates::default(),
+            slash_events: Vec::new(),
+            clock,
         }
     }
 
@@ -115,6 +171,114 @@ impl EconomicModel {
         Ok(base_reward.mul(&performance_multiplier))
     }
 
+    /// Pick up the registry's staking parameters as of `height`
+    pub fn apply_params(&mut self, params: &ParamsRegistry, height: u64) {
+        self.parameters.minimum_stake = params.get(ParamKey::MinimumStake, height);
+        self.parameters.validator_reward_rate = params.get(ParamKey::ValidatorRewardRate, height);
+        self.slashing = SlashingRates::at(params, height);
+    }
+
+    /// Credit every validator the rewards accrued since the last
+    /// distribution, at the annual reward rate, and mint them. Validators
+    /// that compound have their rewards added to their stake, up to the
+    /// maximum stake. Returns the total minted.
+    pub fn distribute_rewards(&mut self) -> PreciseFloat {
+        let now = self.clock.now_secs();
+        let year_fraction = now.saturating_sub(self.rewards_paid_at) as f64 / SECONDS_PER_YEAR;
+        self.rewards_paid_at = now;
+
+        let rate = self.parameters.validator_reward_rate.to_f64().unwrap_or(0.0) / 100.0;
+        let zero = PreciseFloat::new(0, 2);
+        let mut minted = zero.clone();
+        let mut compounded = zero.clone();
+        for validator in self.validators.values_mut() {
+            let stake = validator.stake.to_f64().unwrap_or(0.0);
+            let performance = validator.performance_score.to_f64().unwrap_or(0.0);
+            let reward = PreciseFloat::from_f64(stake * rate * performance * year_fraction, 2);
+            let staked = if validator.auto_compound {
+                let room = self.parameters.maximum_stake.sub(&validator.stake);
+                if reward.sub(&room).value <= 0 {
+                    reward.clone()
+                } else if room.value > 0 {
+                    room
+                } else {
+                    zero.clone()
+                }
+            } else {
+                zero.clone()
+            };
+            validator.stake = validator.stake.add(&staked);
+            validator.rewards = validator.rewards.add(&reward.sub(&staked));
+            compounded = compounded.add(&staked);
+            minted = minted.add(&reward);
+        }
+        self.state.total_supply = self.state.total_supply.add(&minted);
+        self.state.total_staked = self.state.total_staked.add(&compounded);
+        self.state.circulating_supply = self.state.circulating_supply.add(&minted.sub(&compounded));
+        minted
+    }
+
+    pub fn supply(&self) -> SupplySummary {
+        SupplySummary {
+            total_supply: self.state.total_supply.clone(),
+            circulating_supply: self.state.circulating_supply.clone(),
+            total_staked: self.state.total_staked.clone(),
+        }
+    }
+
+    /// Balances that have gone below zero, by name
+    pub fn negative_balances(&self) -> Vec<String> {
+        let totals = [
+            ("total_supply", &self.state.total_supply),
+            ("circulating_supply", &self.state.circulating_supply),
+            ("total_staked", &self.state.total_staked),
+        ];
+        let mut negative: Vec<String> = totals.iter()
+            .filter(|(_, value)| value.value < 0)
+            .map(|(name, _)| name.to_string())
+            .collect();
+        for (id, validator) in &self.validators {
+            if validator.stake.value < 0 {
+                negative.push(format!("validator {} stake", hex::encode(id)));
+            }
+            if validator.rewards.value < 0 {
+                negative.push(format!("validator {} rewards", hex::encode(id)));
+            }
+        }
+        negative.sort();
+        negative
+    }
+
+    /// Validators with the most stake, at most `max`, largest first
+    pub fn top_validators(&self, max: usize) -> Vec<[u8; 32]> {
+        let mut validators: Vec<(&ValidatorId, f64)> = self.validators.iter()
+            .map(|(id, validator)| (id, validator.stake.to_f64().unwrap_or(0.0)))
+            .filter(|(_, stake)| *stake >= self.parameters.minimum_stake.to_f64().unwrap_or(0.0))
+            .collect();
+        validators.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(b.0)));
+        validators.into_iter().take(max).map(|(id, _)| *id).collect()
+    }
+
+    /// Tokens `validator_id` has staked, if it ever staked
+    pub fn stake_of(&self, validator_id: &ValidatorId) -> Option<PreciseFloat> {
+        self.validators.get(validator_id).map(|validator| validator.stake.clone())
+    }
+
+    /// Stake, rewards and unbonding queue of `validator_id`, if it ever staked
+    pub fn validator_stake(&self, validator_id: &ValidatorId) -> Option<ValidatorStake> {
+        self.validators.get(validator_id).map(|validator| ValidatorStake {
+            stake: validator.stake.clone(),
+            rewards: validator.rewards.clone(),
+            auto_compound: validator.auto_compound,
+            unbonding: validator.unbonding.clone(),
+        })
+    }
+
+    /// Unstakes of `validator_id` still waiting out the lockup, oldest first
+    pub fn pending_unbonds(&self, validator_id: &ValidatorId) -> &[Unbonding] {
+        self.validators.get(validator_id).map(|validator| validator.unbonding.as_slice()).unwrap_or_default()
+    }
+
     pub fn update_network_metrics(
         &mut self,
         transactions: u64,
@@ -154,11 +318,11 @@ impl EconomicModel {
                 stake: PreciseFloat::new(0, self.precision),
                 rewards: PreciseFloat::new(0, self.precision),
                 performance_score: PreciseFloat::new(100, 2), // Initial 1.00 score
-                last_active: std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs(),
+                last_active: self.clock.now_secs(),
                 total_validated: 0,
+                offenses: 0,
+                auto_compound: false,
+                unbonding: Vec::new(),
             });
 
         // Update stakes
@@ -169,6 +333,68 @@ impl EconomicModel {
         Ok(())
     }
 
+    /// Start unstaking `amount` of a validator's stake. It stops counting
+    /// toward the validator's weight at once, stays slashable, and returns
+    /// to circulation after the stake lockup period. What is left staked
+    /// must be zero or at least the minimum stake. Returns the release time.
+    pub fn unstake_tokens(&mut self, validator_id: &ValidatorId, amount: PreciseFloat) -> Result<u64, &'static str> {
+        let release_at = self.clock.now_secs() + self.parameters.stake_lockup_period;
+        let minimum = self.parameters.minimum_stake.value;
+        let validator = self.validators.get_mut(validator_id).ok_or("Validator not found")?;
+        if amount.value <= 0 {
+            return Err("Unstake amount must be positive");
+        }
+        if amount.value > validator.stake.value {
+            return Err("Unstake amount exceeds the stake");
+        }
+        if validator.unbonding.len() >= MAX_PENDING_UNBONDS {
+            return Err("Too many pending unbonds");
+        }
+        let remaining = validator.stake.sub(&amount);
+        if remaining.value != 0 && remaining.value < minimum {
+            return Err("Remaining stake below minimum");
+        }
+        validator.stake = remaining;
+        validator.unbonding.push(Unbonding { amount, release_at });
+        Ok(release_at)
+    }
+
+    /// Return validator stake whose lockup has passed to circulation
+    pub fn release_validator_stake(&mut self) -> PreciseFloat {
+        let now = self.clock.now_secs();
+        let mut released = PreciseFloat::new(0, 2);
+        for validator in self.validators.values_mut() {
+            validator.unbonding.retain(|unbonding| {
+                if unbonding.release_at > now {
+                    return true;
+                }
+                released = released.add(&unbonding.amount);
+                false
+            });
+        }
+        self.state.total_staked = self.state.total_staked.sub(&released);
+        self.state.circulating_supply = self.state.circulating_supply.add(&released);
+        released
+    }
+
+    /// Pay out the rewards credited to a validator. They were minted into
+    /// circulation when credited, so the totals do not change.
+    pub fn claim_rewards(&mut self, validator_id: &ValidatorId) -> Result<PreciseFloat, &'static str> {
+        let validator = self.validators.get_mut(validator_id).ok_or("Validator not found")?;
+        if validator.rewards.value <= 0 {
+            return Err("No rewards to claim");
+        }
+        Ok(std::mem::replace(&mut validator.rewards, PreciseFloat::new(0, self.precision)))
+    }
+
+    /// Choose whether a validator's future rewards are added to its stake
+    /// instead of being credited for claiming
+    pub fn set_auto_compound(&mut self, validator_id: &ValidatorId, enabled: bool) -> Result<(), &'static str> {
+        let validator = self.validators.get_mut(validator_id).ok_or("Validator not found")?;
+        validator.auto_compound = enabled;
+        Ok(())
+    }
+
     pub fn calculate_transaction_fee(
         &self,
         transaction_size: u64,
@@ -195,6 +421,123 @@ impl EconomicModel {
             .mul(&priority_multiplier)
     }
 
+    /// Move tokens staked on Hubble content out of circulation
+    pub fn lock_curation_stake(&mut self, amount: &PreciseFloat) {
+        self.state.total_staked = self.state.total_staked.add(amount);
+        self.state.circulating_supply = self.state.circulating_supply.sub(amount);
+    }
+
+    /// Return a withdrawn curation stake to circulation
+    pub fn release_curation_stake(&mut self, amount: &PreciseFloat) {
+        self.state.total_staked = self.state.total_staked.sub(amount);
+        self.state.circulating_supply = self.state.circulating_supply.add(amount);
+    }
+
+    /// Burn a slashed curation stake
+    pub fn burn_curation_stake(&mut self, amount: &PreciseFloat) {
+        self.state.total_staked = self.state.total_staked.sub(amount);
+        self.state.total_supply = self.state.total_supply.sub(amount);
+    }
+
+    /// Burn a fee paid to get content into the Hubble index
+    pub fn collect_submission_fee(&mut self, amount: &PreciseFloat) {
+        self.state.total_supply = self.state.total_supply.sub(amount);
+        self.state.circulating_supply = self.state.circulating_supply.sub(amount);
+    }
+
+    /// Credit fees billed to a hosted private chain
+    pub fn collect_hosting_fees(&mut self, chain_id: ChainId, operations: u64, fees: PreciseFloat) {
+        let revenue = self.hosting_revenue.entry(chain_id)
+            .or_insert(PreciseFloat::new(0, self.precision));
+        *revenue = revenue.add(&fees);
+        self.state.total_transactions += operations;
+    }
+
+    /// Total fees collected from a hosted private chain
+    pub fn hosting_revenue(&self, chain_id: &ChainId) -> PreciseFloat {
+        self.hosting_revenue.get(chain_id)
+            .cloned()
+            .unwrap_or(PreciseFloat::new(0, self.precision))
+    }
+
+    pub fn providers(&self) -> &ProviderRegistry {
+        &self.providers
+    }
+
+    /// Bond stake for a storage, oracle or Web2 provider, taking it out of
+    /// circulation
+    pub fn bond_provider(&mut self, id: ProviderId, kind: ProviderKind, amount: PreciseFloat) -> Result<(), &'static str> {
+        self.providers.bond(id, kind, amount.clone(), self.clock.now_secs())?;
+        self.state.total_staked = self.state.total_staked.add(&amount);
+        self.state.circulating_supply = self.state.circulating_supply.sub(&amount);
+        Ok(())
+    }
+
+    /// Start unbonding provider stake; it returns to circulation after the
+    /// stake lockup period
+    pub fn unbond_provider(&mut self, id: &ProviderId, amount: PreciseFloat) -> Result<u64, &'static str> {
+        let release_at = self.clock.now_secs() + self.parameters.stake_lockup_period;
+        self.providers.unbond(id, amount, release_at)?;
+        Ok(release_at)
+    }
+
+    /// Return provider stake whose lockup has passed to circulation
+    pub fn release_provider_stake(&mut self) -> PreciseFloat {
+        let released = self.providers.release(self.clock.now_secs());
+        self.state.total_staked = self.state.total_staked.sub(&released);
+        self.state.circulating_supply = self.state.circulating_supply.add(&released);
+        released
+    }
+
+    /// Credit a provider the fee for a request it served
+    pub fn reward_provider(&mut self, id: &ProviderId, fee: &PreciseFloat) -> Result<(), &'static str> {
+        self.providers.credit_fee(id, fee)?;
+        self.state.total_transactions += 1;
+        Ok(())
+    }
+
+    /// Slash a provider for a failed proof of storage, a wrong oracle answer
+    /// or a wrong execution result, and burn what was taken
+    pub fn slash_provider(&mut self, id: &ProviderId, offense: Offense) -> Result<PreciseFloat, &'static str> {
+        let slashed = self.providers.slash(id, offense)?;
+        self.state.total_staked = self.state.total_staked.sub(&slashed);
+        self.state.total_supply = self.state.total_supply.sub(&slashed);
+        Ok(slashed)
+    }
+
+    /// Slash a validator for `offense`: burn the offense's share of its
+    /// stake, and of each unstake still unbonding, and record a
+    /// `SlashEvent`. Report each offense once.
+    pub fn slash_validator(&mut self, validator_id: &ValidatorId, offense: ValidatorOffense) -> Result<SlashEvent, &'static str> {
+        let validator = self.validators.get_mut(validator_id).ok_or("Validator not found")?;
+        let mut burned = self.slashing.penalty(offense, &validator.stake);
+        validator.stake = validator.stake.sub(&burned);
+        for unbonding in &mut validator.unbonding {
+            let cut = self.slashing.penalty(offense, &unbonding.amount);
+            unbonding.amount = unbonding.amount.sub(&cut);
+            burned = burned.add(&cut);
+        }
+        validator.offenses += 1;
+        self.state.total_staked = self.state.total_staked.sub(&burned);
+        self.state.total_supply = self.state.total_supply.sub(&burned);
+
+        let event = SlashEvent {
+            validator: *validator_id,
+            offense,
+            burned,
+            remaining_stake: validator.stake.clone(),
+            offenses: validator.offenses,
+            timestamp: self.clock.now_secs(),
+        };
+        self.slash_events.push(event.clone());
+        Ok(event)
+    }
+
+    /// Slashes since the last call, oldest first
+    pub fn take_slash_events(&mut self) -> Vec<SlashEvent> {
+        std::mem::take(&mut self.slash_events)
+    }
+
     fn calculate_moving_average(
         &self,
         current: PreciseFloat,
@@ -210,10 +553,7 @@ impl EconomicModel {
     fn record_snapshot(&mut self) {
         let snapshot = StateSnapshot {
             state: self.state.clone(),
-            timestamp: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
+            timestamp: self.clock.now_secs(),
             metrics: self.calculate_metrics(),
         };
 
@@ -250,3 +590,73 @@ impl EconomicModel {
         metrics
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+
+    fn tokens(whole: i128) -> PreciseFloat {
+        PreciseFloat::new(whole * 100, 2)
+    }
+
+    #[test]
+    fn test_unstaking_waits_out_the_lockup() {
+        let clock = MockClock::new(1_000);
+        let mut economics = EconomicModel::with_clock(2, clock.clone());
+        let validator = [7u8; 32];
+        economics.stake_tokens(validator, tokens(20_000)).unwrap();
+        let supply = economics.supply();
+
+        assert_eq!(economics.unstake_tokens(&validator, tokens(19_500)).unwrap_err(), "Remaining stake below minimum");
+        assert_eq!(economics.unstake_tokens(&validator, tokens(30_000)).unwrap_err(), "Unstake amount exceeds the stake");
+        assert_eq!(economics.unstake_tokens(&validator, tokens(0)).unwrap_err(), "Unstake amount must be positive");
+        let release_at = economics.unstake_tokens(&validator, tokens(5_000)).unwrap();
+        assert_eq!(release_at, 1_000 + 14 * 24 * 60 * 60);
+        assert_eq!(economics.stake_of(&validator), Some(tokens(15_000)));
+        assert_eq!(economics.supply(), supply);
+
+        // Unbonding stake is slashed with the bond
+        let event = economics.slash_validator(&validator, ValidatorOffense::DoubleSign).unwrap();
+        assert_eq!(event.burned, tokens(1_000));
+        assert_eq!(economics.pending_unbonds(&validator)[0].amount, tokens(4_750));
+
+        clock.set_secs(release_at - 1);
+        assert_eq!(economics.release_validator_stake(), PreciseFloat::new(0, 2));
+        clock.set_secs(release_at);
+        assert_eq!(economics.release_validator_stake(), tokens(4_750));
+        assert!(economics.pending_unbonds(&validator).is_empty());
+        let after = economics.supply();
+        assert_eq!(after.total_staked, supply.total_staked.sub(&tokens(5_750)));
+        assert_eq!(after.circulating_supply, supply.circulating_supply.add(&tokens(4_750)));
+
+        // Leaving entirely is allowed
+        economics.unstake_tokens(&validator, tokens(14_250)).unwrap();
+        assert_eq!(economics.stake_of(&validator), Some(tokens(0)));
+        assert!(economics.unstake_tokens(&[8u8; 32], tokens(1_000)).is_err());
+    }
+
+    #[test]
+    fn test_rewards_are_claimed_or_compounded() {
+        let clock = MockClock::new(1_000);
+        let mut economics = EconomicModel::with_clock(2, clock.clone());
+        let (claiming, compounding) = ([1u8; 32], [2u8; 32]);
+        economics.stake_tokens(claiming, tokens(10_000)).unwrap();
+        economics.stake_tokens(compounding, tokens(10_000)).unwrap();
+        economics.set_auto_compound(&compounding, true).unwrap();
+        let supply = economics.supply();
+
+        clock.set_secs(1_000 + SECONDS_PER_YEAR as u64);
+        assert_eq!(economics.distribute_rewards(), tokens(1_000));
+        let after = economics.supply();
+        assert_eq!(after.total_staked, supply.total_staked.add(&tokens(500)));
+        assert_eq!(after.circulating_supply, supply.circulating_supply.add(&tokens(500)));
+
+        assert_eq!(economics.claim_rewards(&claiming).unwrap(), tokens(500));
+        assert_eq!(economics.claim_rewards(&claiming).unwrap_err(), "No rewards to claim");
+        let compounded = economics.validator_stake(&compounding).unwrap();
+        assert_eq!(compounded.stake, tokens(10_500));
+        assert!(compounded.auto_compound);
+        assert_eq!(economics.claim_rewards(&compounding).unwrap_err(), "No rewards to claim");
+    }
+}
@@ -0,0 +1,286 @@
+//! Bonded service providers.
+//!
+//! Validators are not the only nodes the network pays. Storage nodes,
+//! oracle operators and Web2 executors bond stake here, earn the fees of the
+//! requests they serve, and lose part of their bond when they fail a
+//! proof of storage, answer an oracle query incorrectly or return a wrong
+//! execution result. Unbonded stake stays slashable until the lockup
+//! passes. Tally observers bond here too: only an active observer bond
+//! gets observations onto the gossip topic. `EconomicModel` owns the
+//! registry and keeps supply figures in step with bonds and slashes.
+
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use num_traits::ToPrimitive;
+use crate::math::precision::PreciseFloat;
+
+pub type ProviderId = [u8; 32];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderKind {
+    Storage,
+    Oracle,
+    Web2Executor,
+    /// Publishes tally observations on the observation gossip topic
+    Observer,
+}
+
+impl ProviderKind {
+    /// Bond below which a provider is not assigned requests
+    pub fn minimum_bond(&self) -> PreciseFloat {
+        match self {
+            ProviderKind::Storage => PreciseFloat::new(100_000, 2), // 1000.00 tokens
+            ProviderKind::Oracle => PreciseFloat::new(500_000, 2), // 5000.00 tokens
+            ProviderKind::Web2Executor => PreciseFloat::new(200_000, 2), // 2000.00 tokens
+            ProviderKind::Observer => PreciseFloat::new(100_000, 2), // 1000.00 tokens
+        }
+    }
+}
+
+impl std::str::FromStr for ProviderKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "storage" => Ok(ProviderKind::Storage),
+            "oracle" => Ok(ProviderKind::Oracle),
+            "web2_executor" => Ok(ProviderKind::Web2Executor),
+            "observer" => Ok(ProviderKind::Observer),
+            _ => Err(format!("Unknown provider kind `{}` (storage, oracle, web2_executor or observer)", s)),
+        }
+    }
+}
+
+/// A fault that costs a provider part of its bond
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Offense {
+    FailedStorageProof,
+    IncorrectOracleAnswer,
+    IncorrectExecution,
+}
+
+impl Offense {
+    /// The kind of provider that can commit this offense
+    pub fn kind(&self) -> ProviderKind {
+        match self {
+            Offense::FailedStorageProof => ProviderKind::Storage,
+            Offense::IncorrectOracleAnswer => ProviderKind::Oracle,
+            Offense::IncorrectExecution => ProviderKind::Web2Executor,
+        }
+    }
+
+    /// Share of the bond slashed, in basis points
+    pub fn slash_bps(&self) -> i128 {
+        match self {
+            Offense::FailedStorageProof => 500,
+            Offense::IncorrectOracleAnswer => 1_000,
+            Offense::IncorrectExecution => 200,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Unbonding {
+    pub amount: PreciseFloat,
+    pub release_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Provider {
+    pub kind: ProviderKind,
+    pub bond: PreciseFloat,
+    /// Stake waiting out the lockup; still slashable
+    pub unbonding: Vec<Unbonding>,
+    pub fees_earned: PreciseFloat,
+    pub slashed: PreciseFloat,
+    pub requests_served: u64,
+    pub offenses: u64,
+    pub registered_at: u64,
+}
+
+impl Provider {
+    /// Whether the bond covers the kind's minimum
+    pub fn is_active(&self) -> bool {
+        amount(&self.bond) >= amount(&self.kind.minimum_bond())
+    }
+}
+
+/// Provider Registry
+/// Bonds, earnings and slashes of the network's service providers.
+#[derive(Debug, Clone, Default)]
+pub struct ProviderRegistry {
+    providers: HashMap<ProviderId, Provider>,
+}
+
+impl ProviderRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, id: &ProviderId) -> Option<&Provider> {
+        self.providers.get(id)
+    }
+
+    /// Providers of `kind`, or all of them, largest bond first
+    pub fn list(&self, kind: Option<ProviderKind>) -> Vec<(ProviderId, &Provider)> {
+        let mut providers: Vec<(ProviderId, &Provider)> = self.providers.iter()
+            .filter(|(_, provider)| kind.is_none() || kind == Some(provider.kind))
+            .map(|(id, provider)| (*id, provider))
+            .collect();
+        providers.sort_by(|a, b| amount(&b.1.bond).total_cmp(&amount(&a.1.bond)).then(a.0.cmp(&b.0)));
+        providers
+    }
+
+    /// Active providers of `kind` to assign requests to, largest bond first
+    pub fn eligible(&self, kind: ProviderKind) -> Vec<ProviderId> {
+        self.list(Some(kind)).into_iter()
+            .filter(|(_, provider)| provider.is_active())
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    /// Add to a provider's bond, registering it on its first bond. A
+    /// provider serves one kind of request.
+    pub fn bond(&mut self, id: ProviderId, kind: ProviderKind, stake: PreciseFloat, now: u64) -> Result<(), &'static str> {
+        if amount(&stake) <= 0.0 {
+            return Err("Bond must be positive");
+        }
+        if let Some(provider) = self.providers.get_mut(&id) {
+            if provider.kind != kind {
+                return Err("Provider is registered for another kind of service");
+            }
+            provider.bond = provider.bond.add(&stake);
+            return Ok(());
+        }
+        if amount(&stake) < amount(&kind.minimum_bond()) {
+            return Err("Bond below the minimum for this provider kind");
+        }
+        let zero = PreciseFloat::new(0, stake.scale);
+        self.providers.insert(id, Provider {
+            kind,
+            bond: stake,
+            unbonding: Vec::new(),
+            fees_earned: zero.clone(),
+            slashed: zero,
+            requests_served: 0,
+            offenses: 0,
+            registered_at: now,
+        });
+        Ok(())
+    }
+
+    /// Start withdrawing `stake`; it is released at `release_at`
+    pub fn unbond(&mut self, id: &ProviderId, stake: PreciseFloat, release_at: u64) -> Result<(), &'static str> {
+        let provider = self.providers.get_mut(id).ok_or("Provider not found")?;
+        if amount(&stake) <= 0.0 || amount(&stake) > amount(&provider.bond) {
+            return Err("Unbond amount exceeds the bond");
+        }
+        provider.bond = provider.bond.sub(&stake);
+        provider.unbonding.push(Unbonding { amount: stake, release_at });
+        Ok(())
+    }
+
+    /// Release every unbonding whose lockup has passed. Returns the total.
+    pub fn release(&mut self, now: u64) -> PreciseFloat {
+        let mut released = PreciseFloat::new(0, 2);
+        for provider in self.providers.values_mut() {
+            provider.unbonding.retain(|unbonding| {
+                if unbonding.release_at > now {
+                    return true;
+                }
+                released = released.add(&unbonding.amount);
+                false
+            });
+        }
+        released
+    }
+
+    /// Credit the fee for a request the provider served
+    pub fn credit_fee(&mut self, id: &ProviderId, fee: &PreciseFloat) -> Result<(), &'static str> {
+        let provider = self.providers.get_mut(id).ok_or("Provider not found")?;
+        if !provider.is_active() {
+            return Err("Provider bond is below the minimum");
+        }
+        provider.fees_earned = provider.fees_earned.add(fee);
+        provider.requests_served += 1;
+        Ok(())
+    }
+
+    /// Slash the provider for `offense`: the offense's share of the bond,
+    /// then of the stake still unbonding. Returns the amount taken.
+    pub fn slash(&mut self, id: &ProviderId, offense: Offense) -> Result<PreciseFloat, &'static str> {
+        let provider = self.providers.get_mut(id).ok_or("Provider not found")?;
+        if offense.kind() != provider.kind {
+            return Err("Offense does not apply to this provider kind");
+        }
+        let mut taken = share(&provider.bond, offense.slash_bps());
+        provider.bond = provider.bond.sub(&taken);
+        for unbonding in &mut provider.unbonding {
+            let cut = share(&unbonding.amount, offense.slash_bps());
+            unbonding.amount = unbonding.amount.sub(&cut);
+            taken = taken.add(&cut);
+        }
+        provider.slashed = provider.slashed.add(&taken);
+        provider.offenses += 1;
+        Ok(taken)
+    }
+}
+
+fn amount(value: &PreciseFloat) -> f64 {
+    value.to_f64().unwrap_or(0.0)
+}
+
+/// `bps` basis points of `value`, rounded down
+fn share(value: &PreciseFloat, bps: i128) -> PreciseFloat {
+    PreciseFloat::new(value.value * bps / 10_000, value.scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(whole: i128) -> PreciseFloat {
+        PreciseFloat::new(whole * 100, 2)
+    }
+
+    #[test]
+    fn test_bond_serve_and_slash() {
+        let mut registry = ProviderRegistry::new();
+        assert!(registry.bond([1; 32], ProviderKind::Oracle, tokens(100), 0).is_err());
+        registry.bond([1; 32], ProviderKind::Oracle, tokens(6_000), 0).unwrap();
+        registry.bond([2; 32], ProviderKind::Storage, tokens(2_000), 0).unwrap();
+        assert!(registry.bond([2; 32], ProviderKind::Oracle, tokens(10), 0).is_err());
+        assert_eq!(registry.eligible(ProviderKind::Oracle), vec![[1; 32]]);
+
+        registry.credit_fee(&[1; 32], &tokens(3)).unwrap();
+        assert_eq!(registry.get(&[1; 32]).unwrap().requests_served, 1);
+
+        // A storage offense cannot be charged to an oracle
+        assert!(registry.slash(&[1; 32], Offense::FailedStorageProof).is_err());
+        let taken = registry.slash(&[1; 32], Offense::IncorrectOracleAnswer).unwrap();
+        assert_eq!(taken, tokens(600));
+
+        // 5400 left is above the 5000 minimum; one more answer wrong drops it below
+        assert!(registry.get(&[1; 32]).unwrap().is_active());
+        registry.slash(&[1; 32], Offense::IncorrectOracleAnswer).unwrap();
+        assert!(!registry.get(&[1; 32]).unwrap().is_active());
+        assert!(registry.eligible(ProviderKind::Oracle).is_empty());
+        assert!(registry.credit_fee(&[1; 32], &tokens(3)).is_err());
+    }
+
+    #[test]
+    fn test_unbonding_stays_slashable_until_released() {
+        let mut registry = ProviderRegistry::new();
+        registry.bond([3; 32], ProviderKind::Storage, tokens(2_000), 0).unwrap();
+        registry.unbond(&[3; 32], tokens(1_000), 100).unwrap();
+        assert!(registry.unbond(&[3; 32], tokens(5_000), 100).is_err());
+
+        let taken = registry.slash(&[3; 32], Offense::FailedStorageProof).unwrap();
+        assert_eq!(taken, tokens(100));
+        assert_eq!(registry.release(99), PreciseFloat::new(0, 2));
+        assert_eq!(registry.release(100), tokens(950));
+        assert!(registry.get(&[3; 32]).unwrap().unbonding.is_empty());
+    }
+}
@@ -0,0 +1,161 @@
+//! Validator slashing.
+//!
+//! A validator caught double signing, offline for too long, or proposing an
+//! invalid block loses a share of its stake. The share per offense is a
+//! protocol parameter (`slashing.*`), so governance can tune it, and the
+//! slashed stake is burned rather than redistributed. Stake still unbonding
+//! is slashed at the same rate, so unstaking does not escape a penalty. A
+//! validator whose stake falls below the minimum drops out of the next
+//! rotation.
+//!
+//! Each slash is recorded as a `SlashEvent`. `AIGovernance` takes the events
+//! at its next tally and exposes them to policies as `slashing.*` metrics.
+//!
+//! `consensus::OffenseTracker` finds the offenses: double signs and invalid
+//! proposals from the evidence engines report, and downtime from the
+//! precommits missing from decided commits.
+
+use crate::blockchain::types::hex_serde;
+use crate::math::precision::PreciseFloat;
+use crate::params::{ParamKey, ParamsRegistry};
+use serde::{Serialize, Deserialize};
+
+/// Misbehavior a validator is slashed for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidatorOffense {
+    /// Signed two different blocks or votes at one height and round
+    DoubleSign,
+    /// Missed more blocks than allowed
+    Downtime,
+    /// Proposed a block that failed validation
+    InvalidBlock,
+}
+
+impl ValidatorOffense {
+    pub const ALL: [ValidatorOffense; 3] = [ValidatorOffense::DoubleSign, ValidatorOffense::Downtime, ValidatorOffense::InvalidBlock];
+
+    /// Parameter holding the share of stake burned
+    pub fn param(&self) -> ParamKey {
+        match self {
+            ValidatorOffense::DoubleSign => ParamKey::SlashDoubleSign,
+            ValidatorOffense::Downtime => ParamKey::SlashDowntime,
+            ValidatorOffense::InvalidBlock => ParamKey::SlashInvalidBlock,
+        }
+    }
+
+    /// Name of the governance metric counting the offense
+    pub fn metric(&self) -> &'static str {
+        match self {
+            ValidatorOffense::DoubleSign => "slashing.double_sign",
+            ValidatorOffense::Downtime => "slashing.downtime",
+            ValidatorOffense::InvalidBlock => "slashing.invalid_block",
+        }
+    }
+}
+
+/// Share of stake burned per offense, in percent
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlashingRates {
+    pub double_sign: PreciseFloat,
+    pub downtime: PreciseFloat,
+    pub invalid_block: PreciseFloat,
+}
+
+impl Default for SlashingRates {
+    fn default() -> Self {
+        Self {
+            double_sign: ParamKey::SlashDoubleSign.default_value(),
+            downtime: ParamKey::SlashDowntime.default_value(),
+            invalid_block: ParamKey::SlashInvalidBlock.default_value(),
+        }
+    }
+}
+
+impl SlashingRates {
+    /// Rates in effect at `height`
+    pub fn at(params: &ParamsRegistry, height: u64) -> Self {
+        Self {
+            double_sign: params.get(ParamKey::SlashDoubleSign, height),
+            downtime: params.get(ParamKey::SlashDowntime, height),
+            invalid_block: params.get(ParamKey::SlashInvalidBlock, height),
+        }
+    }
+
+    pub fn rate(&self, offense: ValidatorOffense) -> &PreciseFloat {
+        match offense {
+            ValidatorOffense::DoubleSign => &self.double_sign,
+            ValidatorOffense::Downtime => &self.downtime,
+            ValidatorOffense::InvalidBlock => &self.invalid_block,
+        }
+    }
+
+    /// The part of `stake` burned for `offense`, rounded down
+    pub fn penalty(&self, offense: ValidatorOffense, stake: &PreciseFloat) -> PreciseFloat {
+        let rate = self.rate(offense);
+        let percent = 100 * 10i128.pow(rate.scale as u32);
+        PreciseFloat::new(stake.value * rate.value / percent, stake.scale)
+    }
+}
+
+/// A validator's stake was slashed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SlashEvent {
+    #[serde(with = "hex_serde")]
+    pub validator: [u8; 32],
+    pub offense: ValidatorOffense,
+    /// Stake taken from the bond and from unbonding stake, and burned
+    pub burned: PreciseFloat,
+    /// Stake the validator has left
+    pub remaining_stake: PreciseFloat,
+    /// Times the validator has been slashed, this one included
+    pub offenses: u64,
+    pub timestamp: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::economics::models::EconomicModel;
+    use crate::governance::ai_governance::AIGovernance;
+
+    fn tokens(whole: i128) -> PreciseFloat {
+        PreciseFloat::new(whole * 100, 2)
+    }
+
+    #[test]
+    fn test_slashing_burns_a_share_of_stake() {
+        let mut economics = EconomicModel::new(2);
+        let validator = [7u8; 32];
+        economics.stake_tokens(validator, tokens(20_000)).unwrap();
+        let supply = economics.supply();
+
+        let event = economics.slash_validator(&validator, ValidatorOffense::DoubleSign).unwrap();
+        assert_eq!(event.burned, tokens(1_000));
+        assert_eq!(event.remaining_stake, tokens(19_000));
+        assert_eq!(economics.stake_of(&validator), Some(tokens(19_000)));
+        let after = economics.supply();
+        assert_eq!(after.total_supply, supply.total_supply.sub(&tokens(1_000)));
+        assert_eq!(after.total_staked, supply.total_staked.sub(&tokens(1_000)));
+        assert_eq!(after.circulating_supply, supply.circulating_supply);
+
+        // Governance raises the downtime penalty
+        let mut params = ParamsRegistry::new();
+        params.schedule(ParamKey::SlashDowntime, &PreciseFloat::new(1000, 2), 5, 0).unwrap();
+        economics.apply_params(&params, 5);
+        let event = economics.slash_validator(&validator, ValidatorOffense::Downtime).unwrap();
+        assert_eq!(event.burned, tokens(1_900));
+        assert_eq!(event.offenses, 2);
+
+        // Governance sees the slashes at its next tally
+        let mut governance = AIGovernance::new(2);
+        governance.record_slashes(economics.take_slash_events());
+        assert!(economics.take_slash_events().is_empty());
+        let metrics = governance.take_slashing_metrics();
+        assert_eq!(metrics["slashing.double_sign"], PreciseFloat::new(100, 2));
+        assert_eq!(metrics["slashing.invalid_block"], PreciseFloat::new(0, 2));
+        assert_eq!(metrics["slashing.burned"], tokens(2_900));
+        assert_eq!(governance.take_slashing_metrics()["slashing.downtime"], PreciseFloat::new(0, 2));
+        assert_eq!(economics.slash_validator(&[8u8; 32], ValidatorOffense::InvalidBlock).unwrap_err(), "Validator not found");
+    }
+}
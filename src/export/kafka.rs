@@ -0,0 +1,45 @@
+use super::{EventSink, ExportEvent, PublishFuture};
+use rdkafka::config::ClientConfig;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::util::Timeout;
+use std::time::Duration;
+
+/// How long a single delivery may wait for the in-sync replicas
+const ACK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Kafka Sink
+/// Idempotent producer waiting for acknowledgement from all in-sync
+/// replicas. Events are keyed by height so a block's events share a
+/// partition and keep their order.
+pub struct KafkaSink {
+    producer: FutureProducer,
+}
+
+impl KafkaSink {
+    pub fn new(brokers: &str) -> Result<Self, String> {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("enable.idempotence", "true")
+            .set("acks", "all")
+            .create()
+            .map_err(|e| format!("Failed to create Kafka producer: {}", e))?;
+        Ok(Self { producer })
+    }
+}
+
+impl EventSink for KafkaSink {
+    fn publish<'a>(&'a mut self, prefix: &'a str, events: &'a [ExportEvent]) -> PublishFuture<'a> {
+        let producer = &self.producer;
+        Box::pin(async move {
+            let deliveries = events.iter().map(|event| async move {
+                let topic = event.topic(prefix);
+                let key = event.height.to_string();
+                let payload = event.to_bytes();
+                producer.send(FutureRecord::to(&topic).key(&key).payload(&payload), Timeout::After(ACK_TIMEOUT)).await
+                    .map(|_| ())
+                    .map_err(|(e, _)| format!("Kafka delivery of event {} failed: {}", event.id(), e))
+            });
+            futures::future::join_all(deliveries).await.into_iter().collect()
+        })
+    }
+}
@@ -0,0 +1,333 @@
+//! Export of chain events to message brokers.
+//!
+//! Events are grouped by block height. A height is published as a batch and
+//! its offset is saved only after the broker has acknowledged every event,
+//! so after a crash the node resends the last unacknowledged height:
+//! delivery is at-least-once and consumers dedupe on `(height, index)`.
+
+pub mod nats;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+
+use crate::blockchain::core::Block;
+use crate::blockchain::execution::Receipt;
+use crate::config::ExportSinkConfig;
+use crate::orchestration::tally::compute::TallyResult;
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+/// Completes once the broker has acknowledged the whole batch
+pub type PublishFuture<'a> = Pin<Box<dyn Future<Output = Result<(), String>> + Send + 'a>>;
+
+/// What an exported event describes; each kind has its own topic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Block,
+    Receipt,
+    Governance,
+    Tally,
+    Epoch,
+}
+
+impl EventKind {
+    fn topic(&self) -> &'static str {
+        match self {
+            EventKind::Block => "blocks",
+            EventKind::Receipt => "receipts",
+            EventKind::Governance => "governance",
+            EventKind::Tally => "tally",
+            EventKind::Epoch => "epochs",
+        }
+    }
+}
+
+/// One exported event
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportEvent {
+    pub height: u64,
+    /// Position among the height's events; stable across resends
+    pub index: u32,
+    pub kind: EventKind,
+    pub payload: serde_json::Value,
+}
+
+impl ExportEvent {
+    /// Deduplication key, `<height>:<index>`
+    pub fn id(&self) -> String {
+        format!("{}:{}", self.height, self.index)
+    }
+
+    pub fn topic(&self, prefix: &str) -> String {
+        format!("{}.{}", prefix, self.kind.topic())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("export events serialize to JSON")
+    }
+}
+
+/// Builds the events of one height in a fixed order: block, receipts,
+/// then governance and tally events
+pub struct HeightEvents {
+    height: u64,
+    events: Vec<ExportEvent>,
+}
+
+impl HeightEvents {
+    pub fn new(height: u64) -> Self {
+        Self { height, events: Vec::new() }
+    }
+
+    pub fn push(&mut self, kind: EventKind, payload: serde_json::Value) {
+        self.events.push(ExportEvent {
+            height: self.height,
+            index: self.events.len() as u32,
+            kind,
+            payload,
+        });
+    }
+
+    pub fn block(mut self, block: &Block) -> Self {
+        self.push(EventKind::Block, serde_json::json!({
+            "index": block.index,
+            "hash": hex::encode(block.hash),
+            "previous_hash": hex::encode(block.previous_hash),
+            "timestamp": block.timestamp.to_string(),
+            "data_size": block.data.len(),
+        }));
+        self
+    }
+
+    pub fn receipts(mut self, receipts: &[Receipt]) -> Self {
+        for receipt in receipts {
+            let payload = serde_json::to_value(receipt).expect("receipts serialize to JSON");
+            self.push(EventKind::Receipt, payload);
+        }
+        self
+    }
+
+    pub fn queued(mut self, queued: Vec<(EventKind, serde_json::Value)>) -> Self {
+        for (kind, payload) in queued {
+            self.push(kind, payload);
+        }
+        self
+    }
+
+    pub fn into_events(self) -> Vec<ExportEvent> {
+        self.events
+    }
+}
+
+/// Events by the height they were produced at
+type PendingEvents = BTreeMap<u64, Vec<(EventKind, serde_json::Value)>>;
+
+/// Governance and tally events waiting for their height to be exported.
+/// Cloned into the components that produce them.
+#[derive(Clone, Default)]
+pub struct EventQueue {
+    pending: Arc<Mutex<PendingEvents>>,
+}
+
+impl EventQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&self, height: u64, kind: EventKind, payload: serde_json::Value) {
+        self.pending.lock().expect("event queue poisoned")
+            .entry(height)
+            .or_default()
+            .push((kind, payload));
+    }
+
+    pub fn push_tally(&self, height: u64, result: &TallyResult) {
+        self.push(height, EventKind::Tally, serde_json::json!({
+            "hash": hex::encode(result.hash),
+            "operation_count": result.operation_count,
+            "version": result.version,
+        }));
+    }
+
+    /// Events queued for `height`; they stay queued until `prune`
+    pub fn peek(&self, height: u64) -> Vec<(EventKind, serde_json::Value)> {
+        self.pending.lock().expect("event queue poisoned")
+            .get(&height)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Drop events at or below an exported height
+    pub fn prune(&self, through: u64) {
+        let mut pending = self.pending.lock().expect("event queue poisoned");
+        *pending = pending.split_off(&(through + 1));
+    }
+}
+
+/// Broker events are published to
+pub trait EventSink: Send {
+    /// Publish `events` to `<prefix>.<kind>` topics and wait for acknowledgement
+    fn publish<'a>(&'a mut self, prefix: &'a str, events: &'a [ExportEvent]) -> PublishFuture<'a>;
+}
+
+/// Sink for an `event_export.sink` config entry
+pub fn open_sink(config: &ExportSinkConfig) -> Result<Box<dyn EventSink>, String> {
+    match config {
+        ExportSinkConfig::Nats { url } => Ok(Box::new(nats::NatsSink::new(url)?)),
+        #[cfg(feature = "kafka")]
+        ExportSinkConfig::Kafka { brokers } => Ok(Box::new(kafka::KafkaSink::new(brokers)?)),
+        #[cfg(not(feature = "kafka"))]
+        ExportSinkConfig::Kafka { .. } => Err("Kafka export requires the `kafka` feature".to_string()),
+    }
+}
+
+/// Last fully exported height, kept in a file
+pub struct OffsetStore {
+    path: PathBuf,
+}
+
+impl OffsetStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn load(&self) -> Result<Option<u64>, String> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(raw) => raw.trim().parse().map(Some)
+                .map_err(|_| format!("Corrupt export offset in {}", self.path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("Failed to read {}: {}", self.path.display(), e)),
+        }
+    }
+
+    /// Replace the offset atomically, so a crash leaves the old or the new value
+    pub fn save(&self, height: u64) -> Result<(), String> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        let tmp = self.path.with_extension("tmp");
+        std::fs::write(&tmp, height.to_string())
+            .and_then(|_| std::fs::rename(&tmp, &self.path))
+            .map_err(|e| format!("Failed to write {}: {}", self.path.display(), e))
+    }
+}
+
+/// Event Exporter
+/// Publishes heights to a sink in order and records how far it got.
+pub struct Exporter {
+    sink: Box<dyn EventSink>,
+    offsets: OffsetStore,
+    topic_prefix: String,
+    exported: Option<u64>,
+}
+
+impl Exporter {
+    /// Resume from the offset in `offsets`, if any
+    pub fn new(sink: Box<dyn EventSink>, offsets: OffsetStore, topic_prefix: &str) -> Result<Self, String> {
+        let exported = offsets.load()?;
+        Ok(Self { sink, offsets, topic_prefix: topic_prefix.to_string(), exported })
+    }
+
+    /// Last height the broker acknowledged in full
+    pub fn exported(&self) -> Option<u64> {
+        self.exported
+    }
+
+    /// Height to export next
+    pub fn next_height(&self) -> u64 {
+        self.exported.map_or(0, |height| height + 1)
+    }
+
+    /// Publish one height's events, then advance the offset. Heights that
+    /// were already exported are skipped.
+    pub async fn export(&mut self, height: u64, events: &[ExportEvent]) -> Result<(), String> {
+        if Some(height) <= self.exported {
+            return Ok(());
+        }
+        if height != self.next_height() {
+            return Err(format!("Export must resume at height {}", self.next_height()));
+        }
+        if !events.is_empty() {
+            self.sink.publish(&self.topic_prefix, events).await?;
+        }
+        self.offsets.save(height)?;
+        self.exported = Some(height);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::precision::PreciseFloat;
+
+    #[derive(Clone, Default)]
+    struct MemorySink {
+        published: Arc<Mutex<Vec<(String, String)>>>,
+        fail_next: Arc<Mutex<bool>>,
+    }
+
+    impl EventSink for MemorySink {
+        fn publish<'a>(&'a mut self, prefix: &'a str, events: &'a [ExportEvent]) -> PublishFuture<'a> {
+            Box::pin(async move {
+                let mut published = self.published.lock().unwrap();
+                for event in events {
+                    published.push((event.topic(prefix), event.id()));
+                }
+                if std::mem::take(&mut *self.fail_next.lock().unwrap()) {
+                    return Err("Broker did not acknowledge".to_string());
+                }
+                Ok(())
+            })
+        }
+    }
+
+    fn events(height: u64, queue: &EventQueue) -> Vec<ExportEvent> {
+        let one = PreciseFloat::new(1, 2);
+        let block = Block::new(height, [0; 32], vec![], one.clone(), one.clone(), one.clone(), one);
+        HeightEvents::new(height)
+            .block(&block)
+            .queued(queue.peek(height))
+            .into_events()
+    }
+
+    #[tokio::test]
+    async fn test_unacknowledged_heights_are_resent_after_restart() {
+        let path = std::env::temp_dir().join(format!("qmv-export-{}.offset", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let sink = MemorySink::default();
+        let queue = EventQueue::new();
+        queue.push(1, EventKind::Governance, serde_json::json!({"policy": "p"}));
+
+        let mut exporter = Exporter::new(Box::new(sink.clone()), OffsetStore::new(&path), "qmv").unwrap();
+        exporter.export(0, &events(0, &queue)).await.unwrap();
+        *sink.fail_next.lock().unwrap() = true;
+        assert!(exporter.export(1, &events(1, &queue)).await.is_err());
+        assert_eq!(exporter.exported(), Some(0));
+
+        // A restarted exporter resumes at the unacknowledged height
+        let mut exporter = Exporter::new(Box::new(sink.clone()), OffsetStore::new(&path), "qmv").unwrap();
+        assert_eq!(exporter.next_height(), 1);
+        assert!(exporter.export(2, &events(2, &queue)).await.is_err());
+        exporter.export(1, &events(1, &queue)).await.unwrap();
+        exporter.export(1, &events(1, &queue)).await.unwrap();
+        queue.prune(1);
+        assert!(queue.peek(1).is_empty());
+
+        let published = sink.published.lock().unwrap().clone();
+        assert_eq!(published, vec![
+            ("qmv.blocks".to_string(), "0:0".to_string()),
+            ("qmv.blocks".to_string(), "1:0".to_string()),
+            ("qmv.governance".to_string(), "1:1".to_string()),
+            // Resent with the same IDs so consumers can dedupe
+            ("qmv.blocks".to_string(), "1:0".to_string()),
+            ("qmv.governance".to_string(), "1:1".to_string()),
+        ]);
+        let _ = std::fs::remove_file(&path);
+    }
+}
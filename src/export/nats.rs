@@ -0,0 +1,108 @@
+use super::{EventSink, ExportEvent, PublishFuture};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+/// How long the server has to answer the PING that closes a batch
+const ACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// NATS Sink
+/// Publishes over the NATS text protocol. Each event carries a
+/// `Nats-Msg-Id` header, so a JetStream stream on the subjects persists it
+/// and drops resends; the server's PONG after a batch acknowledges it.
+pub struct NatsSink {
+    address: String,
+    connection: Option<BufReader<TcpStream>>,
+}
+
+impl NatsSink {
+    /// `url` is `nats://host:port` or `host:port`; connects on first publish
+    pub fn new(url: &str) -> Result<Self, String> {
+        let address = url.strip_prefix("nats://").unwrap_or(url).trim_end_matches('/');
+        if address.is_empty() {
+            return Err("NATS url has no host".to_string());
+        }
+        let address = if address.contains(':') {
+            address.to_string()
+        } else {
+            format!("{}:4222", address)
+        };
+        Ok(Self { address, connection: None })
+    }
+
+    async fn connect(&self) -> Result<BufReader<TcpStream>, String> {
+        let stream = TcpStream::connect(&self.address).await
+            .map_err(|e| format!("Failed to connect to NATS at {}: {}", self.address, e))?;
+        let mut connection = BufReader::new(stream);
+
+        let info = read_line(&mut connection).await?;
+        if !info.starts_with("INFO") {
+            return Err(format!("Unexpected NATS greeting: {}", info));
+        }
+        connection.write_all(b"CONNECT {\"verbose\":false,\"pedantic\":false,\"headers\":true,\"name\":\"quantum-metaverse-export\"}\r\n").await
+            .map_err(|e| format!("NATS write failed: {}", e))?;
+        Ok(connection)
+    }
+
+    async fn publish_batch(connection: &mut BufReader<TcpStream>, prefix: &str, events: &[ExportEvent]) -> Result<(), String> {
+        let mut frame = Vec::new();
+        for event in events {
+            frame.extend_from_slice(&hpub(&event.topic(prefix), &event.id(), &event.to_bytes()));
+        }
+        frame.extend_from_slice(b"PING\r\n");
+        connection.write_all(&frame).await
+            .map_err(|e| format!("NATS write failed: {}", e))?;
+
+        // Protocol errors for the batch arrive before the PONG
+        loop {
+            let line = read_line(connection).await?;
+            match line.as_str() {
+                "PONG" => return Ok(()),
+                "PING" => connection.write_all(b"PONG\r\n").await
+                    .map_err(|e| format!("NATS write failed: {}", e))?,
+                "+OK" => {}
+                line if line.starts_with("-ERR") => return Err(format!("NATS error: {}", line)),
+                // INFO updates about cluster membership
+                _ => {}
+            }
+        }
+    }
+}
+
+impl EventSink for NatsSink {
+    fn publish<'a>(&'a mut self, prefix: &'a str, events: &'a [ExportEvent]) -> PublishFuture<'a> {
+        Box::pin(async move {
+            let mut connection = match self.connection.take() {
+                Some(connection) => connection,
+                None => self.connect().await?,
+            };
+            let acked = tokio::time::timeout(ACK_TIMEOUT, Self::publish_batch(&mut connection, prefix, events)).await
+                .unwrap_or_else(|_| Err("Timed out waiting for NATS acknowledgement".to_string()));
+            // Reconnect on the next batch after any failure
+            if acked.is_ok() {
+                self.connection = Some(connection);
+            }
+            acked
+        })
+    }
+}
+
+/// `HPUB` frame with a `Nats-Msg-Id` header
+fn hpub(subject: &str, id: &str, payload: &[u8]) -> Vec<u8> {
+    let headers = format!("NATS/1.0\r\nNats-Msg-Id: {}\r\n\r\n", id);
+    let mut frame = format!("HPUB {} {} {}\r\n", subject, headers.len(), headers.len() + payload.len()).into_bytes();
+    frame.extend_from_slice(headers.as_bytes());
+    frame.extend_from_slice(payload);
+    frame.extend_from_slice(b"\r\n");
+    frame
+}
+
+async fn read_line(connection: &mut BufReader<TcpStream>) -> Result<String, String> {
+    let mut line = String::new();
+    let read = connection.read_line(&mut line).await
+        .map_err(|e| format!("NATS read failed: {}", e))?;
+    if read == 0 {
+        return Err("NATS connection closed".to_string());
+    }
+    Ok(line.trim_end().to_string())
+}
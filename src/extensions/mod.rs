@@ -0,0 +1,107 @@
+//! Third-party node extensions.
+//!
+//! Operators load WebAssembly modules listed under `extensions` in the
+//! config. An extension can serve its own JSON-RPC methods, process the
+//! events the node exports for each block, and re-rank Hubble search
+//! results, so tooling can extend a node without forking it.
+//!
+//! Extensions are not trusted. Every call runs in a fresh instance of the
+//! module with metered fuel and capped memory, and the module may only
+//! import the host functions of the capabilities the operator granted it.
+//! A module importing anything else is refused when it is loaded.
+//!
+//! Data crosses the boundary as JSON. The module exports `memory` and
+//! `alloc(len: i32) -> i32`; the node allocates the input with `alloc`,
+//! writes it, and calls an entry point with `(ptr: i32, len: i32)`. Entry
+//! points return their output as `(ptr << 32) | len`, or a negative value
+//! if they failed:
+//!
+//! - `rpc` gets `{"method", "params"}` and returns `{"result"}` or `{"error"}`;
+//! - `on_event` gets one exported event; its output is ignored;
+//! - `rank` gets `{"query", "results"}` and returns the ids of the results
+//!   to keep, best first.
+//!
+//! Host functions that return data (`block`, `storage_get`) copy it into a
+//! buffer from the module's `alloc` and return it packed the same way, or
+//! -1 if there is nothing to return.
+
+#[cfg(feature = "vm-wasm")]
+pub mod runtime;
+
+use serde::{Serialize, Deserialize};
+use std::fmt;
+
+/// Import namespace of the host functions
+pub const HOST_MODULE: &str = "qmv";
+
+/// Largest extension module accepted
+pub const MAX_EXTENSION_CODE: usize = 4 * 1024 * 1024;
+
+/// Highest fuel limit an extension may be configured with
+pub const MAX_EXTENSION_FUEL: u64 = 1_000_000_000;
+
+/// Most linear memory an extension may be configured with
+pub const MAX_EXTENSION_MEMORY: usize = 64 * 1024 * 1024;
+
+/// Largest output an entry point may return
+pub const MAX_EXTENSION_OUTPUT: usize = 1024 * 1024;
+
+/// Bytes of keys and values one extension may keep in storage
+pub const MAX_STORAGE_BYTES: usize = 1024 * 1024;
+
+/// Longest storage key
+pub const MAX_STORAGE_KEY: usize = 256;
+
+/// Host API an extension can be granted. Each capability unlocks a group of
+/// functions in the `qmv` import namespace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    /// `log(ptr, len)`: write a line to the node's output
+    Log,
+    /// `chain_height() -> i64` and `block(height: i64) -> i64`, which
+    /// returns the stored block as JSON
+    ChainRead,
+    /// `storage_get(key_ptr, key_len) -> i64` and
+    /// `storage_set(key_ptr, key_len, value_ptr, value_len) -> i32`, a
+    /// private key-value store kept in memory; an empty value deletes
+    Storage,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 3] = [Capability::Log, Capability::ChainRead, Capability::Storage];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Capability::Log => "log",
+            Capability::ChainRead => "chain_read",
+            Capability::Storage => "storage",
+        }
+    }
+
+    /// Host functions the capability grants
+    pub fn functions(&self) -> &'static [&'static str] {
+        match self {
+            Capability::Log => &["log"],
+            Capability::ChainRead => &["chain_height", "block"],
+            Capability::Storage => &["storage_get", "storage_set"],
+        }
+    }
+
+    /// Capability that grants host function `function`
+    pub fn granting(function: &str) -> Option<Capability> {
+        Self::ALL.into_iter().find(|capability| capability.functions().contains(&function))
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Prefix every RPC method of the extension `name` must start with, so
+/// extension methods never shadow the node's own
+pub fn rpc_prefix(name: &str) -> String {
+    format!("ext_{}_", name)
+}
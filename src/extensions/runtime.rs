@@ -0,0 +1,486 @@
+use super::{
+    Capability, HOST_MODULE, MAX_EXTENSION_CODE, MAX_EXTENSION_OUTPUT, MAX_STORAGE_BYTES, MAX_STORAGE_KEY,
+};
+use crate::blockchain::core::Blockchain;
+use crate::config::ExtensionConfig;
+use crate::export::ExportEvent;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, VecDeque};
+use wasmi::core::{Trap, ValueType};
+use wasmi::{Caller, Config, Engine, Extern, ExternType, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+const ALLOC: &str = "alloc";
+const RPC_ENTRY: &str = "rpc";
+const EVENT_ENTRY: &str = "on_event";
+const RANK_ENTRY: &str = "rank";
+
+/// Fuel charged per host call on top of the instructions that made it
+const HOST_CALL_FUEL: u64 = 100;
+
+/// Longest line `log` accepts
+const MAX_LOG_LINE: usize = 1024;
+
+/// Log lines kept per extension until the node collects them
+const MAX_LOG_LINES: usize = 256;
+
+/// Extension Storage
+/// Key-value data an extension keeps between calls, held in memory.
+#[derive(Debug, Default)]
+pub struct ExtensionStorage {
+    entries: BTreeMap<Vec<u8>, Vec<u8>>,
+    bytes: usize,
+}
+
+impl ExtensionStorage {
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        self.entries.get(key).map(Vec::as_slice)
+    }
+
+    /// Store `value` under `key`; an empty value removes the key
+    pub fn set(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<(), &'static str> {
+        if key.is_empty() || key.len() > MAX_STORAGE_KEY {
+            return Err("Storage key length out of range");
+        }
+        let previous = self.entries.get(&key).map_or(0, |old| key.len() + old.len());
+        let next = if value.is_empty() { 0 } else { key.len() + value.len() };
+        let bytes = self.bytes - previous + next;
+        if bytes > MAX_STORAGE_BYTES {
+            return Err("Extension storage full");
+        }
+        self.bytes = bytes;
+        if value.is_empty() {
+            self.entries.remove(&key);
+        } else {
+            self.entries.insert(key, value);
+        }
+        Ok(())
+    }
+
+    /// Bytes of keys and values stored
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+}
+
+struct HostState<'a> {
+    chain: &'a Blockchain,
+    storage: &'a mut ExtensionStorage,
+    logs: &'a mut VecDeque<String>,
+    limits: StoreLimits,
+}
+
+/// Extension
+/// A loaded extension module and the storage it keeps between calls.
+pub struct Extension {
+    config: ExtensionConfig,
+    engine: Engine,
+    module: Module,
+    storage: ExtensionStorage,
+    logs: VecDeque<String>,
+}
+
+impl Extension {
+    /// Load the module at `config.path`
+    pub fn load(config: ExtensionConfig) -> Result<Self, String> {
+        let code = std::fs::read(&config.path)
+            .map_err(|e| format!("Failed to read extension {}: {}", config.path.display(), e))?;
+        Self::new(config, &code)
+    }
+
+    /// Compile `code`, checking that it imports only host functions its
+    /// capabilities grant and exports the entry points its config uses
+    pub fn new(config: ExtensionConfig, code: &[u8]) -> Result<Self, String> {
+        let fail = |reason: String| format!("Extension `{}` {}", config.name, reason);
+        if code.len() > MAX_EXTENSION_CODE {
+            return Err(fail("is too large".to_string()));
+        }
+        let mut engine_config = Config::default();
+        engine_config.consume_fuel(true);
+        let engine = Engine::new(&engine_config);
+        let module = Module::new(&engine, code).map_err(|_| fail("is not a valid WebAssembly module".to_string()))?;
+
+        for import in module.imports() {
+            let capability = Some(import.module())
+                .filter(|module| *module == HOST_MODULE)
+                .and_then(|_| Capability::granting(import.name()))
+                .ok_or_else(|| fail(format!("imports unknown function `{}.{}`", import.module(), import.name())))?;
+            if !config.capabilities.contains(&capability) {
+                return Err(fail(format!("imports `{}` without the `{}` capability", import.name(), capability)));
+            }
+        }
+
+        if !matches!(module.get_export("memory"), Some(ExternType::Memory(_))) {
+            return Err(fail("must export its memory".to_string()));
+        }
+        let mut entries = vec![(ALLOC, &[ValueType::I32][..], ValueType::I32)];
+        let entry_params = &[ValueType::I32, ValueType::I32][..];
+        if !config.rpc_methods.is_empty() {
+            entries.push((RPC_ENTRY, entry_params, ValueType::I64));
+        }
+        if !config.events.is_empty() {
+            entries.push((EVENT_ENTRY, entry_params, ValueType::I64));
+        }
+        if config.ranker {
+            entries.push((RANK_ENTRY, entry_params, ValueType::I64));
+        }
+        for (name, params, result) in entries {
+            match module.get_export(name) {
+                Some(ExternType::Func(ty)) if ty.params() == params && ty.results() == [result] => {}
+                _ => return Err(fail(format!("must export `{}` with the extension ABI signature", name))),
+            }
+        }
+
+        Ok(Self { config, engine, module, storage: ExtensionStorage::default(), logs: VecDeque::new() })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    pub fn config(&self) -> &ExtensionConfig {
+        &self.config
+    }
+
+    pub fn storage(&self) -> &ExtensionStorage {
+        &self.storage
+    }
+
+    /// Answer one of the extension's RPC methods
+    pub fn call(&mut self, chain: &Blockchain, method: &str, params: &Value) -> Result<Value, String> {
+        if !self.config.rpc_methods.iter().any(|m| m == method) {
+            return Err("Method not found".to_string());
+        }
+        let input = json!({ "method": method, "params": params });
+        let mut reply = self.invoke_json(chain, RPC_ENTRY, &input)?;
+        if let Some(error) = reply.get("error") {
+            return Err(error.as_str().map_or_else(|| error.to_string(), str::to_string));
+        }
+        reply.get_mut("result").map(Value::take)
+            .ok_or_else(|| format!("Extension `{}` returned neither a result nor an error", self.config.name))
+    }
+
+    /// Pass an exported event to the extension, if it subscribed to the
+    /// event's kind
+    pub fn process(&mut self, chain: &Blockchain, event: &ExportEvent) -> Result<(), String> {
+        if !self.config.events.contains(&event.kind) {
+            return Ok(());
+        }
+        self.invoke(chain, EVENT_ENTRY, &event.to_bytes()).map(drop)
+    }
+
+    /// Ids of the search `results` to return, best first, as ranked by the
+    /// extension
+    pub fn rank(&mut self, chain: &Blockchain, query: &str, results: &Value) -> Result<Vec<String>, String> {
+        if !self.config.ranker {
+            return Err(format!("Extension `{}` is not a ranker", self.config.name));
+        }
+        let ranked = self.invoke_json(chain, RANK_ENTRY, &json!({ "query": query, "results": results }))?;
+        serde_json::from_value(ranked)
+            .map_err(|_| format!("Extension `{}` must rank results as an array of ids", self.config.name))
+    }
+
+    /// Lines the extension logged since the last call
+    pub fn take_logs(&mut self) -> Vec<String> {
+        self.logs.drain(..).collect()
+    }
+
+    fn invoke_json(&mut self, chain: &Blockchain, entry: &str, input: &Value) -> Result<Value, String> {
+        let output = self.invoke(chain, entry, input.to_string().as_bytes())?;
+        serde_json::from_slice(&output).map_err(|_| format!("Extension `{}` returned invalid JSON from `{}`", self.config.name, entry))
+    }
+
+    /// Run `entry` on `input` in a fresh instance and return its output
+    fn invoke(&mut self, chain: &Blockchain, entry: &str, input: &[u8]) -> Result<Vec<u8>, String> {
+        let name = &self.config.name;
+        let fail = |reason: &str| format!("Extension `{}` {} in `{}`", name, reason, entry);
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(self.config.max_memory)
+            .instances(1)
+            .memories(1)
+            .tables(1)
+            .build();
+        let state = HostState { chain, storage: &mut self.storage, logs: &mut self.logs, limits };
+        let mut store = Store::new(&self.engine, state);
+        store.limiter(|state| &mut state.limits);
+        store.add_fuel(self.config.fuel).map_err(|_| fail("has no fuel metering"))?;
+
+        let linker = host_functions(&self.engine, self.config.capabilities.iter().copied())
+            .map_err(|_| fail("could not be linked"))?;
+        let instance = linker.instantiate(&mut store, &self.module)
+            .and_then(|pre| pre.start(&mut store))
+            .map_err(|_| fail("failed to instantiate"))?;
+        let memory = instance.get_memory(&store, "memory").ok_or_else(|| fail("exports no memory"))?;
+        let alloc = instance.get_typed_func::<i32, i32>(&store, ALLOC).map_err(|_| fail("has no allocator"))?;
+        let call = instance.get_typed_func::<(i32, i32), i64>(&store, entry).map_err(|_| fail("has no entry point"))?;
+
+        let len = i32::try_from(input.len()).map_err(|_| fail("was given too much input"))?;
+        let ptr = alloc.call(&mut store, len).map_err(|_| fail("trapped or ran out of fuel"))?;
+        memory.write(&mut store, ptr as u32 as usize, input).map_err(|_| fail("allocated out of bounds"))?;
+        let packed = call.call(&mut store, (ptr, len)).map_err(|_| fail("trapped or ran out of fuel"))?;
+        if packed < 0 {
+            return Err(fail("reported a failure"));
+        }
+        let (ptr, len) = unpack(packed);
+        if len > MAX_EXTENSION_OUTPUT {
+            return Err(fail("returned too much output"));
+        }
+        let mut output = vec![0u8; len];
+        memory.read(&store, ptr, &mut output).map_err(|_| fail("returned output out of bounds"))?;
+        Ok(output)
+    }
+}
+
+fn pack(ptr: i32, len: usize) -> i64 {
+    ((ptr as u32 as i64) << 32) | len as i64
+}
+
+fn unpack(packed: i64) -> (usize, usize) {
+    ((packed >> 32) as usize, (packed & 0xffff_ffff) as usize)
+}
+
+fn charge(caller: &mut Caller<'_, HostState>, bytes: usize) -> Result<(), Trap> {
+    caller.consume_fuel(HOST_CALL_FUEL + bytes as u64).map(drop).map_err(|_| Trap::new("Out of fuel"))
+}
+
+fn memory(caller: &Caller<'_, HostState>) -> Result<Memory, Trap> {
+    caller.get_export("memory").and_then(Extern::into_memory).ok_or_else(|| Trap::new("Extension exports no memory"))
+}
+
+fn read(caller: &Caller<'_, HostState>, ptr: i32, len: i32, max: usize) -> Result<Vec<u8>, Trap> {
+    let len = usize::try_from(len).ok().filter(|len| *len <= max).ok_or_else(|| Trap::new("Argument too long"))?;
+    let mut bytes = vec![0u8; len];
+    memory(caller)?.read(caller, ptr as u32 as usize, &mut bytes).map_err(|_| Trap::new("Argument out of bounds"))?;
+    Ok(bytes)
+}
+
+/// Copy `bytes` into a buffer from the extension's allocator
+fn write(caller: &mut Caller<'_, HostState>, bytes: &[u8]) -> Result<i64, Trap> {
+    charge(caller, bytes.len())?;
+    let alloc = caller.get_export(ALLOC).and_then(Extern::into_func)
+        .ok_or_else(|| Trap::new("Extension exports no allocator"))?
+        .typed::<i32, i32>(&*caller)
+        .map_err(|_| Trap::new("Extension allocator has the wrong signature"))?;
+    let ptr = alloc.call(&mut *caller, bytes.len() as i32)?;
+    memory(caller)?.write(&mut *caller, ptr as u32 as usize, bytes).map_err(|_| Trap::new("Allocation out of bounds"))?;
+    Ok(pack(ptr, bytes.len()))
+}
+
+/// Linker defining the host functions of `capabilities` and nothing else
+fn host_functions<'a>(engine: &Engine, capabilities: impl Iterator<Item = Capability>) -> Result<Linker<HostState<'a>>, wasmi::errors::LinkerError> {
+    let mut linker = Linker::<HostState>::new(engine);
+    for capability in capabilities {
+        match capability {
+            Capability::Log => {
+                linker.func_wrap(HOST_MODULE, "log", |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> Result<(), Trap> {
+                    charge(&mut caller, len.max(0) as usize)?;
+                    let line = read(&caller, ptr, len, MAX_LOG_LINE)?;
+                    let logs = &mut caller.data_mut().logs;
+                    if logs.len() == MAX_LOG_LINES {
+                        logs.pop_front();
+                    }
+                    logs.push_back(String::from_utf8_lossy(&line).into_owned());
+                    Ok(())
+                })?;
+            }
+            Capability::ChainRead => {
+                linker.func_wrap(HOST_MODULE, "chain_height", |mut caller: Caller<'_, HostState>| -> Result<i64, Trap> {
+                    charge(&mut caller, 0)?;
+                    Ok(caller.data().chain.height() as i64)
+                })?;
+                linker.func_wrap(HOST_MODULE, "block", |mut caller: Caller<'_, HostState>, height: i64| -> Result<i64, Trap> {
+                    charge(&mut caller, 0)?;
+                    let chain = caller.data().chain;
+                    match u64::try_from(height).ok().and_then(|height| chain.block(height)) {
+                        Some(block) => {
+                            let bytes = serde_json::to_vec(block).map_err(|_| Trap::new("Failed to encode block"))?;
+                            write(&mut caller, &bytes)
+                        }
+                        None => Ok(-1),
+                    }
+                })?;
+            }
+            Capability::Storage => {
+                linker.func_wrap(HOST_MODULE, "storage_get", |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> Result<i64, Trap> {
+                    charge(&mut caller, 0)?;
+                    let key = read(&caller, ptr, len, MAX_STORAGE_KEY)?;
+                    match caller.data().storage.get(&key).map(<[u8]>::to_vec) {
+                        Some(value) => write(&mut caller, &value),
+                        None => Ok(-1),
+                    }
+                })?;
+                linker.func_wrap(HOST_MODULE, "storage_set", |mut caller: Caller<'_, HostState>, key_ptr: i32, key_len: i32, value_ptr: i32, value_len: i32| -> Result<i32, Trap> {
+                    charge(&mut caller, key_len.max(0) as usize + value_len.max(0) as usize)?;
+                    let key = read(&caller, key_ptr, key_len, MAX_STORAGE_KEY)?;
+                    let value = read(&caller, value_ptr, value_len, MAX_STORAGE_BYTES)?;
+                    Ok(if caller.data_mut().storage.set(key, value).is_ok() { 0 } else { -1 })
+                })?;
+            }
+        }
+    }
+    Ok(linker)
+}
+
+/// Extension Host
+/// The extensions a node loaded, dispatching RPC calls, events and Hubble
+/// ranking to the ones configured for them.
+#[derive(Default)]
+pub struct ExtensionHost {
+    extensions: Vec<Extension>,
+}
+
+impl ExtensionHost {
+    pub fn new(extensions: Vec<Extension>) -> Self {
+        Self { extensions }
+    }
+
+    /// Load every configured extension, failing on the first that cannot be
+    pub fn load(configs: &[ExtensionConfig]) -> Result<Self, String> {
+        configs.iter()
+            .map(|config| Extension::load(config.clone()))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Self::new)
+    }
+
+    pub fn extensions(&self) -> &[Extension] {
+        &self.extensions
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.extensions.is_empty()
+    }
+
+    /// RPC methods served by extensions
+    pub fn rpc_methods(&self) -> Vec<String> {
+        self.extensions.iter().flat_map(|extension| extension.config.rpc_methods.iter().cloned()).collect()
+    }
+
+    pub fn call(&mut self, chain: &Blockchain, method: &str, params: &Value) -> Result<Value, String> {
+        self.extensions.iter_mut()
+            .find(|extension| extension.config.rpc_methods.iter().any(|m| m == method))
+            .ok_or_else(|| "Method not found".to_string())?
+            .call(chain, method, params)
+    }
+
+    /// Pass `events` to the extensions subscribed to them. One failing
+    /// extension does not stop the others; the failures are returned.
+    pub fn process(&mut self, chain: &Blockchain, events: &[ExportEvent]) -> Vec<String> {
+        let mut failures = Vec::new();
+        for extension in &mut self.extensions {
+            for event in events {
+                if let Err(e) = extension.process(chain, event) {
+                    failures.push(e);
+                }
+            }
+        }
+        failures
+    }
+
+    /// The extension ranking Hubble results, if one is configured
+    pub fn ranker(&mut self) -> Option<&mut Extension> {
+        self.extensions.iter_mut().find(|extension| extension.config.ranker)
+    }
+
+    /// Lines logged by each extension since the last call
+    pub fn take_logs(&mut self) -> Vec<(String, String)> {
+        self.extensions.iter_mut()
+            .flat_map(|extension| {
+                let name = extension.config.name.clone();
+                extension.take_logs().into_iter().map(move |line| (name.clone(), line))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::EventKind;
+    use std::path::PathBuf;
+
+    fn config(capabilities: &[Capability]) -> ExtensionConfig {
+        ExtensionConfig {
+            name: "counter".to_string(),
+            path: PathBuf::from("counter.wasm"),
+            capabilities: capabilities.iter().copied().collect(),
+            rpc_methods: vec!["ext_counter_next".to_string()],
+            fuel: 1_000_000,
+            ..ExtensionConfig::default()
+        }
+    }
+
+    // Counts its calls in storage and answers `{"result":<count>}`
+    const COUNTER: &str = r#"
+        (module
+          (import "qmv" "storage_get" (func $get (param i32 i32) (result i64)))
+          (import "qmv" "storage_set" (func $set (param i32 i32 i32 i32) (result i32)))
+          (import "qmv" "log" (func $log (param i32 i32)))
+          (memory (export "memory") 1)
+          (global $next (mut i32) (i32.const 1024))
+          (data (i32.const 0) "{\"result\":0}")
+          (data (i32.const 100) "n")
+          (data (i32.const 110) "counted")
+          (func $alloc (export "alloc") (param $len i32) (result i32)
+            (local $ptr i32)
+            (local.set $ptr (global.get $next))
+            (global.set $next (i32.add (global.get $next) (local.get $len)))
+            (local.get $ptr))
+          (func (export "rpc") (param i32 i32) (result i64)
+            (local $found i64)
+            (local $count i32)
+            (local.set $found (call $get (i32.const 100) (i32.const 1)))
+            (if (i64.ge_s (local.get $found) (i64.const 0))
+              (then (local.set $count (i32.load8_u (i32.wrap_i64 (i64.shr_u (local.get $found) (i64.const 32)))))))
+            (local.set $count (i32.add (local.get $count) (i32.const 1)))
+            (i32.store8 (i32.const 200) (local.get $count))
+            (drop (call $set (i32.const 100) (i32.const 1) (i32.const 200) (i32.const 1)))
+            (i32.store8 (i32.const 10) (i32.add (i32.const 48) (local.get $count)))
+            (call $log (i32.const 110) (i32.const 7))
+            (i64.const 12)))
+    "#;
+
+    #[test]
+    fn test_extension_serves_rpc_and_keeps_storage() {
+        let code = wat::parse_str(COUNTER).unwrap();
+        let counter = Extension::new(config(&[Capability::Log, Capability::Storage]), &code).unwrap();
+        let mut host = ExtensionHost::new(vec![counter]);
+        let chain = Blockchain::new(2);
+
+        assert_eq!(host.rpc_methods(), vec!["ext_counter_next".to_string()]);
+        // Every call runs in a fresh instance; only storage carries over
+        assert_eq!(host.call(&chain, "ext_counter_next", &Value::Null).unwrap(), json!(1));
+        assert_eq!(host.call(&chain, "ext_counter_next", &Value::Null).unwrap(), json!(2));
+        assert_eq!(host.extensions()[0].storage().bytes(), 2);
+        assert_eq!(host.call(&chain, "ext_other_next", &Value::Null).unwrap_err(), "Method not found");
+        assert_eq!(host.take_logs(), vec![("counter".to_string(), "counted".to_string()); 2]);
+    }
+
+    #[test]
+    fn test_extensions_are_confined_to_their_capabilities() {
+        let code = wat::parse_str(COUNTER).unwrap();
+        let err = Extension::new(config(&[Capability::Storage]), &code).err().unwrap();
+        assert!(err.contains("without the `log` capability"), "{}", err);
+
+        let clock = r#"(module (import "env" "now" (func (result i64))) (memory (export "memory") 1))"#;
+        let err = Extension::new(config(&[]), &wat::parse_str(clock).unwrap()).err().unwrap();
+        assert!(err.contains("imports unknown function `env.now`"), "{}", err);
+
+        // Subscribed to blocks but spins forever
+        let spin = r#"
+            (module
+              (memory (export "memory") 1)
+              (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+              (func (export "on_event") (param i32 i32) (result i64) (loop $l (br $l)) (i64.const 0)))
+        "#;
+        let mut spinner = config(&[]);
+        spinner.rpc_methods.clear();
+        spinner.events = vec![EventKind::Block];
+        let mut extension = Extension::new(spinner.clone(), &wat::parse_str(spin).unwrap()).unwrap();
+        let event = ExportEvent { height: 1, index: 0, kind: EventKind::Block, payload: Value::Null };
+        let err = extension.process(&Blockchain::new(2), &event).unwrap_err();
+        assert!(err.contains("ran out of fuel"), "{}", err);
+
+        spinner.ranker = true;
+        let err = Extension::new(spinner, &wat::parse_str(spin).unwrap()).err().unwrap();
+        assert!(err.contains("must export `rank`"), "{}", err);
+    }
+}
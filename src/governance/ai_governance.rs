@@ -1,5 +1,13 @@
+use crate::economics::slashing::{SlashEvent, ValidatorOffense};
 use crate::math::precision::PreciseFloat;
+use crate::params::{ParamKey, ParamsRegistry};
+use num_traits::ToPrimitive;
 use std::collections::{HashMap, HashSet};
+#[cfg(feature = "vm-wasm")]
+use std::collections::VecDeque;
+use serde::{Serialize, Deserialize};
+#[cfg(feature = "vm-wasm")]
+use super::wasm_rules::{MetricsSnapshot, WasmPredicate, MAX_HISTORY_EPOCHS};
 
 /// AI-Driven Governance System
 pub struct AIGovernance {
@@ -8,12 +16,17 @@ pub struct AIGovernance {
     decisions: Vec<Decision>,
     validators: HashSet<ValidatorId>,
     trust_threshold: PreciseFloat,
+    /// Validator slashes reported since the last tally
+    slashes: Vec<SlashEvent>,
+    /// Metrics of previous tallies, newest first
+    #[cfg(feature = "vm-wasm")]
+    history: VecDeque<HashMap<String, PreciseFloat>>,
 }
 
-type PolicyId = [u8; 32];
+pub type PolicyId = [u8; 32];
 type ValidatorId = [u8; 32];
 
-#[derive(Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Policy {
     rules: Vec<Rule>,
     weights: Vec<PreciseFloat>,
@@ -22,21 +35,77 @@ pub struct Policy {
     last_update: u64,
 }
 
-#[derive(Clone)]
+impl Policy {
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    pub fn weights(&self) -> &[PreciseFloat] {
+        &self.weights
+    }
+
+    pub fn threshold(&self) -> &PreciseFloat {
+        &self.threshold
+    }
+
+    pub fn creation_time(&self) -> u64 {
+        self.creation_time
+    }
+
+    pub fn last_update(&self) -> u64 {
+        self.last_update
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Rule {
     condition: Condition,
     action: Action,
     weight: PreciseFloat,
 }
 
-#[derive(Clone)]
-enum Condition {
+impl Rule {
+    pub fn new(condition: Condition, action: Action, weight: PreciseFloat) -> Self {
+        Self { condition, action, weight }
+    }
+
+    pub fn condition(&self) -> &Condition {
+        &self.condition
+    }
+
+    pub fn action(&self) -> &Action {
+        &self.action
+    }
+
+    pub fn weight(&self) -> &PreciseFloat {
+        &self.weight
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
     Threshold(String, PreciseFloat),
     Range(String, PreciseFloat, PreciseFloat),
     Complex(Vec<(Condition, LogicalOp)>),
+    /// Metered WebAssembly predicate over current and past metrics
+    #[cfg(feature = "vm-wasm")]
+    Wasm(WasmPredicate),
 }
 
-#[derive(Clone)]
+impl Condition {
+    fn validate(&self) -> Result<(), &'static str> {
+        match self {
+            Condition::Threshold(..) | Condition::Range(..) => Ok(()),
+            Condition::Complex(conditions) => conditions.iter().try_for_each(|(condition, _)| condition.validate()),
+            #[cfg(feature = "vm-wasm")]
+            Condition::Wasm(predicate) => predicate.validate(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Action {
     UpdateParameter(String, PreciseFloat),
     AddValidator(ValidatorId),
@@ -45,8 +114,9 @@ pub enum Action {
     Custom(String, Vec<u8>),
 }
 
-#[derive(Clone)]
-enum LogicalOp {
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogicalOp {
     And,
     Or,
     Xor,
@@ -68,7 +138,10 @@ impl AIGovernance {
             policies: HashMap::new(),
             decisions: Vec::new(),
             validators: HashSet::new(),
-            trust_threshold: PreciseFloat::new(90, 2), // 0.90 threshold
+            trust_threshold: ParamKey::GovernanceTrustThreshold.default_value(),
+            slashes: Vec::new(),
+            #[cfg(feature = "vm-wasm")]
+            history: VecDeque::new(),
         }
     }
 
@@ -82,6 +155,9 @@ impl AIGovernance {
         if rules.len() != weights.len() {
             return Err("Rules and weights must have same length");
         }
+        for rule in &rules {
+            rule.condition.validate()?;
+        }
 
         // Create policy
         let policy = Policy {
@@ -105,6 +181,48 @@ impl AIGovernance {
         Ok(id)
     }
 
+    pub fn get_policy(&self, policy_id: &PolicyId) -> Option<&Policy> {
+        self.policies.get(policy_id)
+    }
+
+    /// Pick up the registry's trust threshold as of `height`
+    pub fn apply_params(&mut self, params: &ParamsRegistry, height: u64) {
+        self.trust_threshold = params.get(ParamKey::GovernanceTrustThreshold, height);
+    }
+
+    /// Keep a tally's metrics for WASM predicates to look back over; call
+    /// once per tally after its policies are evaluated
+    #[cfg(feature = "vm-wasm")]
+    pub fn record_metrics(&mut self, metrics: HashMap<String, PreciseFloat>) {
+        self.history.push_front(metrics);
+        self.history.truncate(MAX_HISTORY_EPOCHS);
+    }
+
+    /// Hold validator slashes for the next tally
+    pub fn record_slashes(&mut self, events: Vec<SlashEvent>) {
+        self.slashes.extend(events);
+    }
+
+    /// Metrics for the slashes recorded since the last call: the count of
+    /// each offense under its `slashing.*` name and `slashing.burned`, the
+    /// stake burned. Merge them into the tally's metrics.
+    pub fn take_slashing_metrics(&mut self) -> HashMap<String, PreciseFloat> {
+        let slashes = std::mem::take(&mut self.slashes);
+        let mut metrics: HashMap<String, PreciseFloat> = ValidatorOffense::ALL.iter()
+            .map(|offense| {
+                let count = slashes.iter().filter(|slash| slash.offense == *offense).count();
+                (offense.metric().to_string(), PreciseFloat::new(count as i128 * 100, 2))
+            })
+            .collect();
+        let burned: f64 = slashes.iter().map(|slash| slash.burned.to_f64().unwrap_or(0.0)).sum();
+        metrics.insert("slashing.burned".to_string(), PreciseFloat::from_f64(burned, 2));
+        metrics
+    }
+
+    pub fn policy_ids(&self) -> Vec<PolicyId> {
+        self.policies.keys().copied().collect()
+    }
+
     pub fn evaluate_policy(
         &mut self,
         policy_id: &PolicyId,
@@ -230,6 +348,10 @@ impl AIGovernance {
                 
                 result
             },
+            #[cfg(feature = "vm-wasm")]
+            Condition::Wasm(predicate) => {
+                predicate.evaluate(&MetricsSnapshot::new(context, &self.history))
+            },
         }
     }
 
@@ -0,0 +1,613 @@
+//! DAOs scoped to a reality layer or a hosted private chain.
+//!
+//! Network governance (`ai_governance`) decides for the whole network. A
+//! `Dao` decides for one sub-entity: its members propose, vote with their
+//! weights and execute, and what an executed proposal can do is limited to
+//! its scope:
+//! - change the access policy of its reality layer;
+//! - spend from its treasury, which network governance funds with
+//!   `dao.fund` actions;
+//! - anchor its private chain to a mainnet block;
+//! - change its own members and rules.
+//!
+//! A DAO is created from a charter signed by whoever controls the scope
+//! today: the owner key of a declared layer, or an owner of the private
+//! chain. Layer and chain changes run through `DaoHooks`, so the DAO only
+//! records a proposal as executed once the scope has accepted the change.
+
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use num_traits::ToPrimitive;
+use crate::blockchain::types::hex_serde;
+use crate::crypto::domain::{PayloadKind, SigningDomain};
+use crate::governance::ai_governance::Action;
+use crate::ids::ChainId;
+use crate::math::precision::PreciseFloat;
+use crate::orchestration::access::AccessPolicy;
+
+/// Governance action crediting a DAO's treasury; the payload is a JSON `Funding`
+pub const FUND_ACTION: &str = "dao.fund";
+
+pub const MAX_MEMBERS: usize = 256;
+pub const MIN_VOTING_PERIOD_SECS: u64 = 60;
+pub const MAX_VOTING_PERIOD_SECS: u64 = 30 * 24 * 3600;
+
+/// BLAKE3 of the DAO's scope
+pub type DaoId = [u8; 32];
+
+/// What a DAO governs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum DaoScope {
+    Layer { layer_id: u32 },
+    PrivateChain { chain_id: ChainId },
+}
+
+impl DaoScope {
+    /// One DAO per scope, so the scope fixes the id
+    pub fn id(&self) -> DaoId {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(b"qmv:dao:");
+        match self {
+            DaoScope::Layer { layer_id } => {
+                hasher.update(&[0]);
+                hasher.update(&layer_id.to_le_bytes());
+            }
+            DaoScope::PrivateChain { chain_id } => {
+                hasher.update(&[1]);
+                hasher.update(chain_id.as_bytes());
+            }
+        }
+        hasher.finalize().into()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Member {
+    #[serde(with = "hex_serde")]
+    pub key: [u8; 32],
+    pub weight: u64,
+}
+
+/// How proposals are decided
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaoRules {
+    /// Share of the total weight that must vote, in basis points
+    pub quorum_bps: u16,
+    /// Share of the weight voting that must approve, in basis points;
+    /// approval must be strictly above it
+    pub approval_bps: u16,
+    pub voting_period_secs: u64,
+}
+
+impl Default for DaoRules {
+    fn default() -> Self {
+        Self { quorum_bps: 2_000, approval_bps: 5_000, voting_period_secs: 3 * 24 * 3600 }
+    }
+}
+
+impl DaoRules {
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.quorum_bps == 0 || self.quorum_bps > 10_000 {
+            return Err("Quorum must be 1 to 10,000 basis points");
+        }
+        if !(5_000..10_000).contains(&self.approval_bps) {
+            return Err("Approval must be 5,000 to 9,999 basis points");
+        }
+        if !(MIN_VOTING_PERIOD_SECS..=MAX_VOTING_PERIOD_SECS).contains(&self.voting_period_secs) {
+            return Err("Voting period must be one minute to 30 days");
+        }
+        Ok(())
+    }
+}
+
+/// What a proposal does when executed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum DaoAction {
+    /// Replace the layer's access policy; layer DAOs only
+    SetLayerPolicy { policy: AccessPolicy },
+    /// Pay `amount` from the treasury to `to`
+    Spend {
+        #[serde(with = "hex_serde")]
+        to: [u8; 32],
+        amount: PreciseFloat,
+    },
+    /// Anchor the private chain's state to a mainnet block; chain DAOs only
+    Anchor {
+        #[serde(with = "hex_serde")]
+        mainnet_block_hash: [u8; 32],
+    },
+    /// Add a member or change its weight; weight 0 removes it
+    SetMember { member: Member },
+    UpdateRules { rules: DaoRules },
+}
+
+/// Charter creating a DAO, signed by the scope's current controller
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Charter {
+    pub scope: DaoScope,
+    pub members: Vec<Member>,
+    pub rules: DaoRules,
+    #[serde(with = "hex_serde")]
+    pub creator: [u8; 32],
+    #[serde(with = "hex_serde")]
+    pub signature: Vec<u8>,
+}
+
+impl Charter {
+    pub fn sign(creator: &SigningKey, scope: DaoScope, members: Vec<Member>, rules: DaoRules, network_id: u64) -> Self {
+        let mut charter = Self { scope, members, rules, creator: creator.verifying_key().to_bytes(), signature: Vec::new() };
+        charter.signature = creator.sign(&charter.signing_bytes(network_id)).to_bytes().to_vec();
+        charter
+    }
+
+    /// Bytes the creator signs
+    pub fn signing_bytes(&self, network_id: u64) -> Vec<u8> {
+        let mut body = vec![0]; // charter
+        body.extend_from_slice(&self.creator);
+        body.extend_from_slice(&bincode::serialize(&(&self.scope, &self.members, &self.rules)).unwrap_or_default());
+        SigningDomain::main_chain(network_id).payload(PayloadKind::Dao, 0, &body)
+    }
+}
+
+/// A member's signed proposal. `sequence` is the DAO's next proposal
+/// number, so a proposal cannot be submitted twice.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedProposal {
+    #[serde(with = "hex_serde")]
+    pub dao: DaoId,
+    pub sequence: u64,
+    pub action: DaoAction,
+    #[serde(with = "hex_serde")]
+    pub proposer: [u8; 32],
+    #[serde(with = "hex_serde")]
+    pub signature: Vec<u8>,
+}
+
+impl SignedProposal {
+    pub fn sign(proposer: &SigningKey, dao: DaoId, sequence: u64, action: DaoAction, network_id: u64) -> Self {
+        let mut proposal = Self { dao, sequence, action, proposer: proposer.verifying_key().to_bytes(), signature: Vec::new() };
+        proposal.signature = proposer.sign(&proposal.signing_bytes(network_id)).to_bytes().to_vec();
+        proposal
+    }
+
+    /// Bytes the proposer signs
+    pub fn signing_bytes(&self, network_id: u64) -> Vec<u8> {
+        let mut body = vec![1]; // proposal
+        body.extend_from_slice(&self.dao);
+        body.extend_from_slice(&self.proposer);
+        body.extend_from_slice(&bincode::serialize(&self.action).unwrap_or_default());
+        SigningDomain::main_chain(network_id).payload(PayloadKind::Dao, self.sequence, &body)
+    }
+}
+
+/// A member's signed vote on one proposal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ballot {
+    #[serde(with = "hex_serde")]
+    pub dao: DaoId,
+    pub proposal: u64,
+    pub approve: bool,
+    #[serde(with = "hex_serde")]
+    pub voter: [u8; 32],
+    #[serde(with = "hex_serde")]
+    pub signature: Vec<u8>,
+}
+
+impl Ballot {
+    pub fn sign(voter: &SigningKey, dao: DaoId, proposal: u64, approve: bool, network_id: u64) -> Self {
+        let mut ballot = Self { dao, proposal, approve, voter: voter.verifying_key().to_bytes(), signature: Vec::new() };
+        ballot.signature = voter.sign(&ballot.signing_bytes(network_id)).to_bytes().to_vec();
+        ballot
+    }
+
+    /// Bytes the voter signs
+    pub fn signing_bytes(&self, network_id: u64) -> Vec<u8> {
+        let mut body = vec![2]; // ballot
+        body.extend_from_slice(&self.dao);
+        body.extend_from_slice(&self.voter);
+        body.push(self.approve as u8);
+        SigningDomain::main_chain(network_id).payload(PayloadKind::Dao, self.proposal, &body)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "status")]
+pub enum ProposalStatus {
+    Open,
+    Executed { at: u64 },
+    Rejected { at: u64 },
+}
+
+/// A vote as counted, with the voter's weight when it voted
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Vote {
+    #[serde(with = "hex_serde")]
+    pub voter: [u8; 32],
+    pub approve: bool,
+    pub weight: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Proposal {
+    pub id: u64,
+    #[serde(with = "hex_serde")]
+    pub proposer: [u8; 32],
+    pub action: DaoAction,
+    pub created_at: u64,
+    pub closes_at: u64,
+    /// Total member weight when the proposal was made; quorum is measured against it
+    pub total_weight: u64,
+    /// Rules when the proposal was made
+    pub rules: DaoRules,
+    pub votes: Vec<Vote>,
+    pub status: ProposalStatus,
+}
+
+impl Proposal {
+    fn weight(&self, approve: bool) -> u128 {
+        self.votes.iter().filter(|vote| vote.approve == approve).map(|vote| vote.weight as u128).sum()
+    }
+
+    /// Whether the votes cast so far pass it
+    pub fn passes(&self) -> bool {
+        let (yes, no) = (self.weight(true), self.weight(false));
+        let quorum = (yes + no) * 10_000 >= self.rules.quorum_bps as u128 * self.total_weight as u128;
+        quorum && yes * 10_000 > self.rules.approval_bps as u128 * (yes + no)
+    }
+
+    /// Whether it passes even if every member yet to vote votes against
+    pub fn decided(&self) -> bool {
+        let yes = self.weight(true);
+        yes * 10_000 >= self.rules.quorum_bps as u128 * self.total_weight as u128
+            && yes * 10_000 > self.rules.approval_bps as u128 * self.total_weight as u128
+    }
+}
+
+/// Treasury spend, kept for the DAO's history
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Payout {
+    pub proposal: u64,
+    #[serde(with = "hex_serde")]
+    pub to: [u8; 32],
+    pub amount: PreciseFloat,
+    pub at: u64,
+}
+
+/// Payload of `dao.fund`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Funding {
+    #[serde(with = "hex_serde")]
+    pub dao: DaoId,
+    pub amount: PreciseFloat,
+}
+
+/// Changes a DAO makes outside itself, applied by whoever hosts its scope
+pub trait DaoHooks {
+    fn set_layer_policy(&mut self, layer_id: u32, policy: &AccessPolicy) -> Result<(), &'static str>;
+    fn anchor(&mut self, chain_id: &ChainId, mainnet_block_hash: [u8; 32]) -> Result<(), &'static str>;
+}
+
+/// DAO
+/// Members, treasury and proposals of one layer or private chain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Dao {
+    pub scope: DaoScope,
+    pub members: Vec<Member>,
+    pub rules: DaoRules,
+    pub treasury: PreciseFloat,
+    pub proposals: BTreeMap<u64, Proposal>,
+    pub payouts: Vec<Payout>,
+    pub created_at: u64,
+}
+
+impl Dao {
+    pub fn id(&self) -> DaoId {
+        self.scope.id()
+    }
+
+    pub fn member(&self, key: &[u8; 32]) -> Option<&Member> {
+        self.members.iter().find(|member| member.key == *key)
+    }
+
+    pub fn total_weight(&self) -> u64 {
+        self.members.iter().map(|member| member.weight).sum()
+    }
+
+    /// Number the next proposal gets
+    pub fn next_proposal(&self) -> u64 {
+        self.proposals.keys().next_back().map_or(0, |id| id + 1)
+    }
+
+    fn set_member(&mut self, member: Member) -> Result<(), &'static str> {
+        let mut members = self.members.clone();
+        members.retain(|existing| existing.key != member.key);
+        if member.weight > 0 {
+            members.push(member);
+        }
+        validate_members(&members)?;
+        self.members = members;
+        Ok(())
+    }
+}
+
+/// DAO Factory
+/// Every scoped DAO on the node, by id.
+#[derive(Debug, Clone)]
+pub struct DaoFactory {
+    network_id: u64,
+    daos: BTreeMap<DaoId, Dao>,
+}
+
+impl DaoFactory {
+    pub fn new(network_id: u64) -> Self {
+        Self { network_id, daos: BTreeMap::new() }
+    }
+
+    pub fn get(&self, id: &DaoId) -> Option<&Dao> {
+        self.daos.get(id)
+    }
+
+    /// Create a DAO from a charter signed by one of `controllers`, the keys
+    /// that control the scope today
+    pub fn create(&mut self, charter: Charter, controllers: &[[u8; 32]], now: u64) -> Result<DaoId, &'static str> {
+        if !controllers.contains(&charter.creator) {
+            return Err("Charter is not signed by the scope's controller");
+        }
+        verify_signature(&charter.creator, &charter.signing_bytes(self.network_id), &charter.signature)
+            .map_err(|_| "Invalid charter signature")?;
+        validate_members(&charter.members)?;
+        charter.rules.validate()?;
+        let id = charter.scope.id();
+        if self.daos.contains_key(&id) {
+            return Err("Scope already has a DAO");
+        }
+        self.daos.insert(id, Dao {
+            scope: charter.scope,
+            members: charter.members,
+            rules: charter.rules,
+            treasury: PreciseFloat::new(0, 2),
+            proposals: BTreeMap::new(),
+            payouts: Vec::new(),
+            created_at: now,
+        });
+        Ok(id)
+    }
+
+    /// Open a member's proposal for voting. Returns its number.
+    pub fn propose(&mut self, proposal: SignedProposal, now: u64) -> Result<u64, &'static str> {
+        let dao = self.daos.get_mut(&proposal.dao).ok_or("DAO not found")?;
+        if dao.member(&proposal.proposer).is_none() {
+            return Err("Proposer is not a member");
+        }
+        if proposal.sequence != dao.next_proposal() {
+            return Err("Proposal is not for the DAO's next number");
+        }
+        verify_signature(&proposal.proposer, &proposal.signing_bytes(self.network_id), &proposal.signature)
+            .map_err(|_| "Invalid proposal signature")?;
+        match (&proposal.action, &dao.scope) {
+            (DaoAction::SetLayerPolicy { .. }, DaoScope::PrivateChain { .. }) => return Err("Only a layer DAO sets a layer policy"),
+            (DaoAction::Anchor { .. }, DaoScope::Layer { .. }) => return Err("Only a private chain DAO anchors"),
+            (DaoAction::Spend { amount, .. }, _) if amount.value <= 0 => return Err("Spend amount must be positive"),
+            (DaoAction::UpdateRules { rules }, _) => rules.validate()?,
+            _ => {}
+        }
+
+        let id = proposal.sequence;
+        dao.proposals.insert(id, Proposal {
+            id,
+            proposer: proposal.proposer,
+            action: proposal.action,
+            created_at: now,
+            closes_at: now + dao.rules.voting_period_secs,
+            total_weight: dao.total_weight(),
+            rules: dao.rules,
+            votes: Vec::new(),
+            status: ProposalStatus::Open,
+        });
+        Ok(id)
+    }
+
+    /// Count a member's vote on an open proposal
+    pub fn vote(&mut self, ballot: Ballot, now: u64) -> Result<(), &'static str> {
+        let dao = self.daos.get_mut(&ballot.dao).ok_or("DAO not found")?;
+        let weight = dao.member(&ballot.voter).ok_or("Voter is not a member")?.weight;
+        let proposal = dao.proposals.get_mut(&ballot.proposal).ok_or("Proposal not found")?;
+        if proposal.status != ProposalStatus::Open || now >= proposal.closes_at {
+            return Err("Voting on the proposal has closed");
+        }
+        if proposal.votes.iter().any(|vote| vote.voter == ballot.voter) {
+            return Err("Member already voted");
+        }
+        verify_signature(&ballot.voter, &ballot.signing_bytes(self.network_id), &ballot.signature)
+            .map_err(|_| "Invalid ballot signature")?;
+        proposal.votes.push(Vote { voter: ballot.voter, approve: ballot.approve, weight });
+        Ok(())
+    }
+
+    /// Execute a proposal once it is decided or its voting has closed. A
+    /// closed proposal that did not pass is rejected. Returns its status.
+    pub fn execute(&mut self, id: &DaoId, proposal_id: u64, hooks: &mut impl DaoHooks, now: u64) -> Result<ProposalStatus, &'static str> {
+        let dao = self.daos.get_mut(id).ok_or("DAO not found")?;
+        let proposal = dao.proposals.get(&proposal_id).ok_or("Proposal not found")?;
+        if proposal.status != ProposalStatus::Open {
+            return Err("Proposal already decided");
+        }
+        let closed = now >= proposal.closes_at;
+        if !proposal.decided() && !closed {
+            return Err("Proposal is still being voted on");
+        }
+        let status = if proposal.passes() {
+            let action = proposal.action.clone();
+            apply(dao, proposal_id, action, hooks, now)?;
+            ProposalStatus::Executed { at: now }
+        } else {
+            ProposalStatus::Rejected { at: now }
+        };
+        if let Some(proposal) = dao.proposals.get_mut(&proposal_id) {
+            proposal.status = status;
+        }
+        Ok(status)
+    }
+
+    /// Apply a `dao.fund` governance action. Returns whether the action was the factory's.
+    pub fn apply_governance(&mut self, action: &Action) -> Result<bool, &'static str> {
+        let Action::Custom(name, payload) = action else { return Ok(false) };
+        if name != FUND_ACTION {
+            return Ok(false);
+        }
+        let funding: Funding = serde_json::from_slice(payload).map_err(|_| "Invalid DAO funding")?;
+        if funding.amount.value <= 0 {
+            return Err("Funding must be positive");
+        }
+        let dao = self.daos.get_mut(&funding.dao).ok_or("DAO not found")?;
+        dao.treasury = dao.treasury.add(&funding.amount);
+        Ok(true)
+    }
+}
+
+fn apply(dao: &mut Dao, proposal_id: u64, action: DaoAction, hooks: &mut impl DaoHooks, now: u64) -> Result<(), &'static str> {
+    match (action, dao.scope) {
+        (DaoAction::SetLayerPolicy { policy }, DaoScope::Layer { layer_id }) => hooks.set_layer_policy(layer_id, &policy),
+        (DaoAction::Anchor { mainnet_block_hash }, DaoScope::PrivateChain { chain_id }) => hooks.anchor(&chain_id, mainnet_block_hash),
+        (DaoAction::Spend { to, amount }, _) => {
+            if amount.to_f64() > dao.treasury.to_f64() {
+                return Err("DAO treasury balance too low");
+            }
+            dao.treasury = dao.treasury.sub(&amount);
+            dao.payouts.push(Payout { proposal: proposal_id, to, amount, at: now });
+            Ok(())
+        }
+        (DaoAction::SetMember { member }, _) => dao.set_member(member),
+        (DaoAction::UpdateRules { rules }, _) => {
+            dao.rules = rules;
+            Ok(())
+        }
+        _ => Err("Action does not apply to the DAO's scope"),
+    }
+}
+
+fn validate_members(members: &[Member]) -> Result<(), &'static str> {
+    if members.is_empty() || members.len() > MAX_MEMBERS {
+        return Err("A DAO must have 1 to 256 members");
+    }
+    if members.iter().any(|member| member.weight == 0) {
+        return Err("Member weight must be positive");
+    }
+    let mut keys: Vec<&[u8; 32]> = members.iter().map(|member| &member.key).collect();
+    keys.sort();
+    keys.dedup();
+    if keys.len() != members.len() {
+        return Err("Members must be distinct");
+    }
+    members.iter().try_fold(0u64, |total, member| total.checked_add(member.weight))
+        .ok_or("Total member weight overflows")?;
+    Ok(())
+}
+
+fn verify_signature(key: &[u8; 32], message: &[u8], signature: &[u8]) -> Result<(), ()> {
+    let key = VerifyingKey::from_bytes(key).map_err(|_| ())?;
+    let signature = Signature::from_slice(signature).map_err(|_| ())?;
+    key.verify(message, &signature).map_err(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Recorded {
+        policies: Vec<(u32, AccessPolicy)>,
+        anchors: Vec<(ChainId, [u8; 32])>,
+    }
+
+    impl DaoHooks for Recorded {
+        fn set_layer_policy(&mut self, layer_id: u32, policy: &AccessPolicy) -> Result<(), &'static str> {
+            self.policies.push((layer_id, policy.clone()));
+            Ok(())
+        }
+
+        fn anchor(&mut self, chain_id: &ChainId, mainnet_block_hash: [u8; 32]) -> Result<(), &'static str> {
+            self.anchors.push((*chain_id, mainnet_block_hash));
+            Ok(())
+        }
+    }
+
+    fn keys() -> Vec<SigningKey> {
+        (1..=3).map(|seed| SigningKey::from_bytes(&[seed; 32])).collect()
+    }
+
+    fn members(keys: &[SigningKey], weights: &[u64]) -> Vec<Member> {
+        keys.iter().zip(weights).map(|(key, weight)| Member { key: key.verifying_key().to_bytes(), weight: *weight }).collect()
+    }
+
+    #[test]
+    fn test_layer_dao_votes_and_executes() {
+        let owner = SigningKey::from_bytes(&[9; 32]);
+        let keys = keys();
+        let scope = DaoScope::Layer { layer_id: 4 };
+        let mut factory = DaoFactory::new(1);
+        let charter = Charter::sign(&owner, scope, members(&keys, &[50, 30, 20]), DaoRules::default(), 1);
+        assert_eq!(factory.create(charter.clone(), &[[0; 32]], 0), Err("Charter is not signed by the scope's controller"));
+        let id = factory.create(charter.clone(), &[owner.verifying_key().to_bytes()], 0).unwrap();
+        assert!(factory.create(charter, &[owner.verifying_key().to_bytes()], 0).is_err());
+
+        let policy = AccessPolicy::StakeGated { minimum: PreciseFloat::new(1_000, 0) };
+        let action = DaoAction::SetLayerPolicy { policy: policy.clone() };
+        assert_eq!(factory.propose(SignedProposal::sign(&owner, id, 0, action.clone(), 1), 10), Err("Proposer is not a member"));
+        let proposal = factory.propose(SignedProposal::sign(&keys[1], id, 0, action.clone(), 1), 10).unwrap();
+        assert!(factory.propose(SignedProposal::sign(&keys[1], id, 0, action, 1), 10).is_err(), "A proposal cannot be replayed");
+
+        let mut hooks = Recorded::default();
+        factory.vote(Ballot::sign(&keys[1], id, proposal, true, 1), 20).unwrap();
+        assert!(factory.vote(Ballot::sign(&keys[1], id, proposal, true, 1), 20).is_err());
+        assert_eq!(factory.execute(&id, proposal, &mut hooks, 30), Err("Proposal is still being voted on"));
+        // 80 of 100 in favor cannot be overturned, so it runs before the period ends
+        factory.vote(Ballot::sign(&keys[0], id, proposal, true, 1), 40).unwrap();
+        assert_eq!(factory.execute(&id, proposal, &mut hooks, 50), Ok(ProposalStatus::Executed { at: 50 }));
+        assert_eq!(hooks.policies, vec![(4, policy)]);
+        assert!(factory.execute(&id, proposal, &mut hooks, 60).is_err());
+
+        // A chain action is refused by a layer DAO
+        let anchor = DaoAction::Anchor { mainnet_block_hash: [7; 32] };
+        assert_eq!(factory.propose(SignedProposal::sign(&keys[0], id, 1, anchor, 1), 70), Err("Only a private chain DAO anchors"));
+    }
+
+    #[test]
+    fn test_treasury_and_rejection() {
+        let owner = SigningKey::from_bytes(&[9; 32]);
+        let keys = keys();
+        let scope = DaoScope::PrivateChain { chain_id: ChainId::new([5; 32]) };
+        let mut factory = DaoFactory::new(1);
+        let rules = DaoRules { voting_period_secs: 100, ..DaoRules::default() };
+        let id = factory.create(Charter::sign(&owner, scope, members(&keys, &[40, 40, 20]), rules, 1), &[owner.verifying_key().to_bytes()], 0).unwrap();
+        let funding = Funding { dao: id, amount: PreciseFloat::new(50_000, 2) };
+        let fund = Action::Custom(FUND_ACTION.to_string(), serde_json::to_vec(&funding).unwrap());
+        assert_eq!(factory.apply_governance(&fund), Ok(true));
+
+        let mut hooks = Recorded::default();
+        let spend = |amount| DaoAction::Spend { to: [8; 32], amount: PreciseFloat::new(amount, 2) };
+        let greedy = factory.propose(SignedProposal::sign(&keys[0], id, 0, spend(60_000), 1), 0).unwrap();
+        for key in &keys {
+            factory.vote(Ballot::sign(key, id, greedy, true, 1), 1).unwrap();
+        }
+        assert_eq!(factory.execute(&id, greedy, &mut hooks, 2), Err("DAO treasury balance too low"));
+
+        let split = factory.propose(SignedProposal::sign(&keys[0], id, 1, spend(20_000), 1), 0).unwrap();
+        factory.vote(Ballot::sign(&keys[0], id, split, true, 1), 1).unwrap();
+        factory.vote(Ballot::sign(&keys[1], id, split, false, 1), 1).unwrap();
+        assert!(factory.execute(&id, split, &mut hooks, 50).is_err());
+        // A tie does not pass
+        assert_eq!(factory.execute(&id, split, &mut hooks, 100), Ok(ProposalStatus::Rejected { at: 100 }));
+
+        let modest = factory.propose(SignedProposal::sign(&keys[2], id, 2, spend(20_000), 1), 200).unwrap();
+        factory.vote(Ballot::sign(&keys[0], id, modest, true, 1), 201).unwrap();
+        factory.vote(Ballot::sign(&keys[2], id, modest, true, 1), 201).unwrap();
+        assert_eq!(factory.execute(&id, modest, &mut hooks, 300), Ok(ProposalStatus::Executed { at: 300 }));
+        let dao = factory.get(&id).unwrap();
+        assert_eq!(dao.treasury, PreciseFloat::new(30_000, 2));
+        assert_eq!(dao.payouts.len(), 1);
+    }
+}
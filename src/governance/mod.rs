@@ -1 +1,4 @@
 pub mod ai_governance;
+pub mod dao;
+#[cfg(feature = "vm-wasm")]
+pub mod wasm_rules;
@@ -0,0 +1,274 @@
+//! Governance conditions written as small WebAssembly predicates.
+//!
+//! A `Condition::Wasm` rule carries a module exporting `evaluate() -> i32`;
+//! the rule holds when it returns non-zero. The module reads tally metrics
+//! through two host functions in the `env` namespace:
+//!
+//! - `metric(name_ptr: i32, name_len: i32, epochs_ago: i32) -> i64` returns
+//!   the metric named by the UTF-8 bytes at `name_ptr` as fixed-point with
+//!   `METRIC_SCALE` decimals, for the current tally (`epochs_ago = 0`) or one
+//!   of the last `MAX_HISTORY_EPOCHS` tallies, or `MISSING_METRIC`.
+//! - `history_len() -> i32` returns how many past tallies are available.
+//!
+//! Every validator must reach the same answer, so execution is kept
+//! deterministic: float instructions are rejected when the module is
+//! compiled, fuel is metered against the rule's limit, memory is capped,
+//! and no other imports exist. A predicate that traps, runs out of fuel or
+//! exceeds its memory evaluates to false on every node alike.
+
+use crate::blockchain::types::hex_serde;
+use crate::math::precision::PreciseFloat;
+use serde::{Serialize, Deserialize};
+use std::collections::{HashMap, VecDeque};
+use wasmi::core::{Trap, ValueType};
+use wasmi::{Caller, Config, Engine, Extern, ExternType, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+/// Decimals of the fixed-point values `metric` returns
+pub const METRIC_SCALE: u8 = 6;
+
+/// Returned by `metric` for a metric or epoch that is not recorded
+pub const MISSING_METRIC: i64 = i64::MIN;
+
+/// Past tallies kept for predicates to look back over
+pub const MAX_HISTORY_EPOCHS: usize = 64;
+
+/// Largest predicate module accepted
+pub const MAX_PREDICATE_CODE: usize = 64 * 1024;
+
+/// Highest fuel limit a rule may set
+pub const MAX_PREDICATE_FUEL: u64 = 10_000_000;
+
+/// Linear memory a predicate may grow to
+pub const MAX_PREDICATE_MEMORY: usize = 1 << 20;
+
+/// Longest metric name a predicate may ask for
+const MAX_METRIC_NAME: usize = 128;
+
+/// Fuel charged per host call on top of the instructions that made it
+const HOST_CALL_FUEL: u64 = 100;
+
+const ENTRY_POINT: &str = "evaluate";
+
+/// Metrics a predicate runs against: the current tally's and those of
+/// previous tallies, newest first
+pub struct MetricsSnapshot<'a> {
+    current: &'a HashMap<String, PreciseFloat>,
+    history: &'a VecDeque<HashMap<String, PreciseFloat>>,
+}
+
+impl<'a> MetricsSnapshot<'a> {
+    pub fn new(current: &'a HashMap<String, PreciseFloat>, history: &'a VecDeque<HashMap<String, PreciseFloat>>) -> Self {
+        Self { current, history }
+    }
+
+    /// Fixed-point value of `name` as of `epochs_ago` tallies back
+    pub fn metric(&self, name: &str, epochs_ago: usize) -> i64 {
+        let metrics = match epochs_ago {
+            0 => Some(self.current),
+            ago => self.history.get(ago - 1),
+        };
+        metrics.and_then(|metrics| metrics.get(name)).map_or(MISSING_METRIC, to_fixed)
+    }
+
+    pub fn history_len(&self) -> usize {
+        self.history.len()
+    }
+}
+
+/// Rescale to `METRIC_SCALE` decimals, saturating at the i64 range
+fn to_fixed(value: &PreciseFloat) -> i64 {
+    let scaled = if value.scale <= METRIC_SCALE {
+        value.value.saturating_mul(10i128.pow((METRIC_SCALE - value.scale) as u32))
+    } else {
+        value.value / 10i128.saturating_pow((value.scale - METRIC_SCALE) as u32)
+    };
+    // MISSING_METRIC stays distinguishable from a real value
+    scaled.clamp(i64::MIN as i128 + 1, i64::MAX as i128) as i64
+}
+
+struct HostState {
+    metrics: Vec<HashMap<String, i64>>,
+    limits: StoreLimits,
+}
+
+/// WASM Predicate
+/// Rule condition evaluated by running a WebAssembly module with at most
+/// `fuel` units of metered execution.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WasmPredicate {
+    #[serde(with = "hex_serde")]
+    code: Vec<u8>,
+    fuel: u64,
+}
+
+impl WasmPredicate {
+    pub fn new(code: Vec<u8>, fuel: u64) -> Result<Self, &'static str> {
+        let predicate = Self { code, fuel };
+        predicate.validate()?;
+        Ok(predicate)
+    }
+
+    pub fn code(&self) -> &[u8] {
+        &self.code
+    }
+
+    pub fn fuel(&self) -> u64 {
+        self.fuel
+    }
+
+    /// Check size and fuel bounds, that the module compiles without floats,
+    /// imports only the host functions, and exports `evaluate() -> i32`
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.code.len() > MAX_PREDICATE_CODE {
+            return Err("Predicate module too large");
+        }
+        if self.fuel == 0 || self.fuel > MAX_PREDICATE_FUEL {
+            return Err("Predicate fuel limit out of range");
+        }
+        let module = self.compile(&engine())?;
+        for import in module.imports() {
+            let known = matches!((import.module(), import.name()), ("env", "metric") | ("env", "history_len"));
+            if !known {
+                return Err("Predicate imports an unknown host function");
+            }
+        }
+        match module.get_export(ENTRY_POINT) {
+            Some(ExternType::Func(ty)) if ty.params().is_empty() && ty.results() == [ValueType::I32] => Ok(()),
+            _ => Err("Predicate must export evaluate() -> i32"),
+        }
+    }
+
+    /// Whether the predicate holds; false if it fails to run to completion
+    pub fn evaluate(&self, snapshot: &MetricsSnapshot) -> bool {
+        self.run(snapshot).unwrap_or(false)
+    }
+
+    fn compile(&self, engine: &Engine) -> Result<Module, &'static str> {
+        Module::new(engine, self.code.as_slice()).map_err(|_| "Predicate module is invalid or uses floats")
+    }
+
+    /// Run the predicate, returning its answer or why it did not finish
+    pub fn run(&self, snapshot: &MetricsSnapshot) -> Result<bool, &'static str> {
+        let engine = engine();
+        let module = self.compile(&engine)?;
+
+        let metrics = std::iter::once(snapshot.current)
+            .chain(snapshot.history.iter())
+            .map(|metrics| metrics.iter().map(|(name, value)| (name.clone(), to_fixed(value))).collect())
+            .collect();
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(MAX_PREDICATE_MEMORY)
+            .instances(1)
+            .memories(1)
+            .tables(1)
+            .build();
+        let mut store = Store::new(&engine, HostState { metrics, limits });
+        store.limiter(|state| &mut state.limits);
+        store.add_fuel(self.fuel).map_err(|_| "Fuel metering disabled")?;
+
+        let mut linker = Linker::<HostState>::new(&engine);
+        linker.func_wrap("env", "metric", |mut caller: Caller<'_, HostState>, ptr: i32, len: i32, epochs_ago: i32| -> Result<i64, Trap> {
+            let len = usize::try_from(len).ok().filter(|len| *len <= MAX_METRIC_NAME)
+                .ok_or_else(|| Trap::new("Metric name too long"))?;
+            caller.consume_fuel(HOST_CALL_FUEL + len as u64).map_err(|_| Trap::new("Out of fuel"))?;
+            let memory = caller.get_export("memory").and_then(Extern::into_memory)
+                .ok_or_else(|| Trap::new("Predicate exports no memory"))?;
+            let mut name = vec![0u8; len];
+            memory.read(&caller, ptr as u32 as usize, &mut name).map_err(|_| Trap::new("Metric name out of bounds"))?;
+            let name = std::str::from_utf8(&name).map_err(|_| Trap::new("Metric name is not UTF-8"))?;
+            let value = usize::try_from(epochs_ago).ok()
+                .and_then(|ago| caller.data().metrics.get(ago))
+                .and_then(|metrics| metrics.get(name).copied());
+            Ok(value.unwrap_or(MISSING_METRIC))
+        }).map_err(|_| "Failed to link host functions")?;
+        linker.func_wrap("env", "history_len", |mut caller: Caller<'_, HostState>| -> Result<i32, Trap> {
+            caller.consume_fuel(HOST_CALL_FUEL).map_err(|_| Trap::new("Out of fuel"))?;
+            Ok(caller.data().metrics.len() as i32 - 1)
+        }).map_err(|_| "Failed to link host functions")?;
+
+        let instance = linker.instantiate(&mut store, &module)
+            .and_then(|pre| pre.start(&mut store))
+            .map_err(|_| "Predicate failed to instantiate")?;
+        let evaluate = instance.get_typed_func::<(), i32>(&store, ENTRY_POINT)
+            .map_err(|_| "Predicate must export evaluate() -> i32")?;
+        let result = evaluate.call(&mut store, ()).map_err(|_| "Predicate trapped or ran out of fuel")?;
+        Ok(result != 0)
+    }
+}
+
+/// Interpreter configured for consensus: metered, integer-only
+fn engine() -> Engine {
+    let mut config = Config::default();
+    config.consume_fuel(true).floats(false);
+    Engine::new(&config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn predicate(wat: &str, fuel: u64) -> Result<WasmPredicate, &'static str> {
+        WasmPredicate::new(wat::parse_str(wat).unwrap(), fuel)
+    }
+
+    // Holds when utilization rose in each of the last three tallies
+    const RISING: &str = r#"
+        (module
+          (import "env" "metric" (func $metric (param i32 i32 i32) (result i64)))
+          (import "env" "history_len" (func $history_len (result i32)))
+          (memory (export "memory") 1)
+          (data (i32.const 0) "utilization")
+          (func (export "evaluate") (result i32)
+            (local $ago i32)
+            (if (i32.lt_s (call $history_len) (i32.const 3)) (then (return (i32.const 0))))
+            (block $done
+              (loop $next
+                (br_if $done (i32.eq (local.get $ago) (i32.const 3)))
+                (if (i64.le_s
+                      (call $metric (i32.const 0) (i32.const 11) (local.get $ago))
+                      (call $metric (i32.const 0) (i32.const 11) (i32.add (local.get $ago) (i32.const 1))))
+                  (then (return (i32.const 0))))
+                (local.set $ago (i32.add (local.get $ago) (i32.const 1)))
+                (br $next)))
+            (i32.const 1)))
+    "#;
+
+    fn tally(utilization: i128) -> HashMap<String, PreciseFloat> {
+        [("utilization".to_string(), PreciseFloat::new(utilization, 2))].into_iter().collect()
+    }
+
+    #[test]
+    fn test_predicate_reads_metric_history() {
+        let rising = predicate(RISING, 100_000).unwrap();
+        let mut history = VecDeque::new();
+        let current = tally(80);
+        assert!(!rising.evaluate(&MetricsSnapshot::new(&current, &history)));
+
+        for utilization in [70, 60, 50] {
+            history.push_back(tally(utilization));
+        }
+        assert!(rising.evaluate(&MetricsSnapshot::new(&current, &history)));
+        assert_eq!(MetricsSnapshot::new(&current, &history).metric("utilization", 1), 700_000);
+
+        history[1] = tally(75);
+        assert!(!rising.evaluate(&MetricsSnapshot::new(&current, &history)));
+    }
+
+    #[test]
+    fn test_predicate_is_bounded_and_integer_only() {
+        let spin = r#"(module (func (export "evaluate") (result i32) (loop $l (br $l)) (i32.const 1)))"#;
+        let spin = predicate(spin, 10_000).unwrap();
+        let (current, history) = (HashMap::new(), VecDeque::new());
+        assert_eq!(spin.run(&MetricsSnapshot::new(&current, &history)), Err("Predicate trapped or ran out of fuel"));
+
+        let float = r#"(module (func (export "evaluate") (result i32) (f64.lt (f64.const 1) (f64.const 2))))"#;
+        assert_eq!(predicate(float, 10_000).unwrap_err(), "Predicate module is invalid or uses floats");
+
+        let clock = r#"(module (import "env" "now" (func (result i64))) (func (export "evaluate") (result i32) (i32.const 1)))"#;
+        assert_eq!(predicate(clock, 10_000).unwrap_err(), "Predicate imports an unknown host function");
+
+        let greedy = r#"(module (memory 32) (func (export "evaluate") (result i32) (i32.const 1)))"#;
+        assert!(!predicate(greedy, 10_000).unwrap().evaluate(&MetricsSnapshot::new(&current, &history)));
+        assert!(predicate(RISING, MAX_PREDICATE_FUEL + 1).is_err());
+    }
+}
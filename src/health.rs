@@ -0,0 +1,236 @@
+//! Component health.
+//!
+//! Each major component registers a probe with the node's `HealthRegistry`.
+//! `check` runs every probe at once and folds the results into one status:
+//! an unhealthy critical component makes the node unhealthy, and anything
+//! else short of healthy makes it degraded. `/health` and the `status` RPC
+//! report the result with the reason for each failing component.
+
+use serde::{Serialize, Deserialize};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+pub type ProbeFuture = Pin<Box<dyn Future<Output = Check> + Send>>;
+
+/// How long a probe may run by default before it counts as unhealthy
+pub const PROBE_TIMEOUT_MS: u64 = 2_000;
+
+/// Ordered from best to worst
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+    Healthy,
+    /// Working, but with reduced capacity or a non-critical part down
+    Degraded,
+    Unhealthy,
+}
+
+/// Outcome of one probe
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Check {
+    pub status: HealthStatus,
+    pub reason: Option<String>,
+}
+
+impl Check {
+    pub fn healthy() -> Self {
+        Self { status: HealthStatus::Healthy, reason: None }
+    }
+
+    pub fn degraded(reason: impl Into<String>) -> Self {
+        Self { status: HealthStatus::Degraded, reason: Some(reason.into()) }
+    }
+
+    pub fn unhealthy(reason: impl Into<String>) -> Self {
+        Self { status: HealthStatus::Unhealthy, reason: Some(reason.into()) }
+    }
+}
+
+/// Something that can report a component's health on demand
+pub trait HealthProbe: Send + Sync {
+    fn check(&self) -> ProbeFuture;
+}
+
+impl<F, Fut> HealthProbe for F
+where
+    F: Fn() -> Fut + Send + Sync,
+    Fut: Future<Output = Check> + Send + 'static,
+{
+    fn check(&self) -> ProbeFuture {
+        Box::pin(self())
+    }
+}
+
+/// Probe for components that push their state instead of being asked,
+/// such as periodic background services
+#[derive(Clone)]
+pub struct Reporter {
+    last: Arc<Mutex<Check>>,
+}
+
+impl Reporter {
+    pub fn set(&self, check: Check) {
+        if let Ok(mut last) = self.last.lock() {
+            *last = check;
+        }
+    }
+}
+
+impl HealthProbe for Reporter {
+    fn check(&self) -> ProbeFuture {
+        let check = self.last.lock().map(|last| last.clone())
+            .unwrap_or_else(|_| Check::unhealthy("reporter lock poisoned"));
+        Box::pin(async move { check })
+    }
+}
+
+/// Latest result for one component
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ComponentHealth {
+    pub component: String,
+    /// Whether this component failing makes the whole node unhealthy
+    pub critical: bool,
+    pub status: HealthStatus,
+    pub reason: Option<String>,
+    pub latency_ms: u64,
+}
+
+/// Health Summary
+/// Every component's latest check and the status they add up to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthSummary {
+    pub status: HealthStatus,
+    /// `component: reason` for each component that is not healthy
+    pub reasons: Vec<String>,
+    pub components: Vec<ComponentHealth>,
+}
+
+impl HealthSummary {
+    /// Fold component results into one status
+    pub fn aggregate(components: Vec<ComponentHealth>) -> Self {
+        let status = components.iter()
+            .map(|component| match component.status {
+                HealthStatus::Unhealthy if !component.critical => HealthStatus::Degraded,
+                status => status,
+            })
+            .max()
+            .unwrap_or(HealthStatus::Healthy);
+        let reasons = components.iter()
+            .filter(|component| component.status != HealthStatus::Healthy)
+            .map(|component| format!("{}: {}", component.component, component.reason.as_deref().unwrap_or("no reason given")))
+            .collect();
+        Self { status, reasons, components }
+    }
+}
+
+struct Registered {
+    component: String,
+    critical: bool,
+    probe: Arc<dyn HealthProbe>,
+}
+
+/// Health Registry
+/// Probes registered by the node's components, run together on request.
+#[derive(Clone)]
+pub struct HealthRegistry {
+    probes: Arc<RwLock<Vec<Registered>>>,
+    timeout: Duration,
+}
+
+impl Default for HealthRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HealthRegistry {
+    pub fn new() -> Self {
+        Self { probes: Arc::new(RwLock::new(Vec::new())), timeout: Duration::from_millis(PROBE_TIMEOUT_MS) }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Register `probe` for `component`, replacing any earlier probe for it
+    pub fn register(&self, component: &str, critical: bool, probe: impl HealthProbe + 'static) {
+        let Ok(mut probes) = self.probes.write() else { return };
+        probes.retain(|registered| registered.component != component);
+        probes.push(Registered { component: component.to_string(), critical, probe: Arc::new(probe) });
+    }
+
+    /// Register a component that reports its own health. It starts healthy.
+    pub fn reporter(&self, component: &str, critical: bool) -> Reporter {
+        let reporter = Reporter { last: Arc::new(Mutex::new(Check::healthy())) };
+        self.register(component, critical, reporter.clone());
+        reporter
+    }
+
+    pub fn components(&self) -> Vec<String> {
+        self.probes.read()
+            .map(|probes| probes.iter().map(|registered| registered.component.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Run every probe concurrently, each bounded by the registry's timeout
+    pub async fn check(&self) -> HealthSummary {
+        let probes: Vec<(String, bool, Arc<dyn HealthProbe>)> = match self.probes.read() {
+            Ok(probes) => probes.iter().map(|registered| (registered.component.clone(), registered.critical, registered.probe.clone())).collect(),
+            Err(_) => Vec::new(),
+        };
+        let timeout = self.timeout;
+        let checks = probes.into_iter().map(|(component, critical, probe)| async move {
+            let started = Instant::now();
+            let check = tokio::time::timeout(timeout, probe.check()).await
+                .unwrap_or_else(|_| Check::unhealthy(format!("probe timed out after {} ms", timeout.as_millis())));
+            ComponentHealth {
+                component,
+                critical,
+                status: check.status,
+                reason: check.reason,
+                latency_ms: started.elapsed().as_millis() as u64,
+            }
+        });
+        HealthSummary::aggregate(futures::future::join_all(checks).await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_critical_failures_decide_the_status() {
+        let registry = HealthRegistry::new();
+        registry.register("chain store", true, || async { Check::healthy() });
+        let web2 = registry.reporter("web2 runner", false);
+        assert_eq!(registry.check().await.status, HealthStatus::Healthy);
+
+        // A non-critical component going down only degrades the node
+        web2.set(Check::unhealthy("docker unavailable"));
+        let summary = registry.check().await;
+        assert_eq!(summary.status, HealthStatus::Degraded);
+        assert_eq!(summary.reasons, vec!["web2 runner: docker unavailable"]);
+
+        registry.register("chain store", true, || async { Check::unhealthy("tip hash mismatch") });
+        let summary = registry.check().await;
+        assert_eq!(summary.status, HealthStatus::Unhealthy);
+        assert_eq!(summary.components.len(), 2);
+        assert_eq!(summary.reasons.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_slow_probe_times_out() {
+        let registry = HealthRegistry::new().with_timeout(Duration::from_millis(20));
+        registry.register("p2p", true, || async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Check::healthy()
+        });
+        let summary = registry.check().await;
+        assert_eq!(summary.status, HealthStatus::Unhealthy);
+        assert!(summary.components[0].reason.as_deref().unwrap().contains("timed out"));
+    }
+}
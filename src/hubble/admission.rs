@@ -0,0 +1,280 @@
+use serde::{Serialize, Deserialize};
+use std::collections::{HashMap, VecDeque};
+use num_traits::ToPrimitive;
+use crate::clock::{self, SharedClock};
+use crate::economics::models::EconomicModel;
+use crate::governance::ai_governance::Action;
+use crate::math::precision::PreciseFloat;
+use super::curation::ContentId;
+
+/// Highest puzzle difficulty, in leading zero bits
+pub const MAX_DIFFICULTY: u8 = 32;
+
+/// Windows averaged into the reported submission rate
+const RATE_WINDOWS: usize = 16;
+
+/// What a submitter offers to get content indexed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubmissionProof {
+    /// Token fee, collected through economics. The caller must already have
+    /// charged the submitter.
+    Fee { amount: PreciseFloat },
+    /// Nonce solving the client puzzle bound to the content ID
+    Work { nonce: u64 },
+}
+
+/// Puzzle hash for `nonce`; a solution has at least `difficulty` leading zero bits
+pub fn work_hash(id: &ContentId, nonce: u64) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"qmv:hubble:work:");
+    hasher.update(id);
+    hasher.update(&nonce.to_le_bytes());
+    hasher.finalize().into()
+}
+
+fn leading_zero_bits(hash: &[u8; 32]) -> u32 {
+    let mut bits = 0;
+    for byte in hash {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+/// Find a nonce meeting `difficulty` for `id`, as a client would
+pub fn solve(id: &ContentId, difficulty: u8) -> u64 {
+    (0..).find(|nonce| leading_zero_bits(&work_hash(id, *nonce)) >= difficulty as u32)
+        .expect("a solution exists")
+}
+
+/// Governance-set admission parameters
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AdmissionParams {
+    /// Puzzle difficulty at the lowest pressure level
+    pub base_difficulty: u8,
+    /// Fee at the lowest pressure level
+    pub base_fee: PreciseFloat,
+    /// Submissions per window above which the pressure level rises
+    pub target_submissions: u32,
+    pub window_secs: u64,
+    /// Highest pressure level; each level adds a bit of difficulty and doubles the fee
+    pub max_level: u8,
+}
+
+impl Default for AdmissionParams {
+    fn default() -> Self {
+        Self {
+            base_difficulty: 16,
+            base_fee: PreciseFloat::new(10, 2), // 0.10 tokens
+            target_submissions: 60,
+            window_secs: 60,
+            max_level: 8,
+        }
+    }
+}
+
+/// Current requirement, as shown to submitters
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AdmissionStatus {
+    pub difficulty: u8,
+    pub fee: PreciseFloat,
+    pub level: u8,
+    /// Submissions admitted in the last full window
+    pub last_window_submissions: u32,
+    pub params: AdmissionParams,
+}
+
+/// Submission Guard
+/// Admits content only with a fee or a solved puzzle, checked before it is
+/// indexed. After each window the pressure level rises if submissions beat
+/// the governance target and falls once they drop under half of it.
+pub struct SubmissionGuard {
+    params: AdmissionParams,
+    level: u8,
+    window_start: u64,
+    window_submissions: u32,
+    last_window_submissions: u32,
+    /// Submissions in recent windows, most recent last
+    history: VecDeque<u32>,
+    clock: SharedClock,
+}
+
+impl SubmissionGuard {
+    pub fn new(params: AdmissionParams) -> Self {
+        Self::with_clock(params, clock::system())
+    }
+
+    pub fn with_clock(params: AdmissionParams, clock: SharedClock) -> Self {
+        let window_start = clock.now_secs();
+        Self {
+            params,
+            level: 0,
+            window_start,
+            window_submissions: 0,
+            last_window_submissions: 0,
+            history: VecDeque::new(),
+            clock,
+        }
+    }
+
+    pub fn difficulty(&self) -> u8 {
+        self.params.base_difficulty.saturating_add(self.level).min(MAX_DIFFICULTY)
+    }
+
+    pub fn fee(&self) -> PreciseFloat {
+        let base = &self.params.base_fee;
+        PreciseFloat { value: base.value.saturating_mul(1 << self.level), scale: base.scale }
+    }
+
+    pub fn status(&self) -> AdmissionStatus {
+        AdmissionStatus {
+            difficulty: self.difficulty(),
+            fee: self.fee(),
+            level: self.level,
+            last_window_submissions: self.last_window_submissions,
+            params: self.params.clone(),
+        }
+    }
+
+    /// Check `proof` for content `id` and count the submission. Fees are
+    /// collected only once the proof is accepted.
+    pub fn admit(
+        &mut self,
+        id: &ContentId,
+        proof: &SubmissionProof,
+        economics: &mut EconomicModel,
+    ) -> Result<(), &'static str> {
+        self.roll_window();
+        match proof {
+            SubmissionProof::Work { nonce } => {
+                if leading_zero_bits(&work_hash(id, *nonce)) < self.difficulty() as u32 {
+                    return Err("Proof of work below current difficulty");
+                }
+            }
+            SubmissionProof::Fee { amount } => {
+                if amount.to_f64().unwrap_or(0.0) < self.fee().to_f64().unwrap_or(0.0) {
+                    return Err("Submission fee below current fee");
+                }
+                economics.collect_submission_fee(amount);
+            }
+        }
+        self.window_submissions += 1;
+        Ok(())
+    }
+
+    /// Close any windows that have ended and retarget the pressure level
+    fn roll_window(&mut self) {
+        let window = self.params.window_secs.max(1);
+        let now = self.clock.now_secs();
+        let ended = now.saturating_sub(self.window_start) / window;
+        if ended == 0 {
+            return;
+        }
+        let submissions = std::mem::take(&mut self.window_submissions);
+        self.last_window_submissions = submissions;
+        if submissions > self.params.target_submissions {
+            self.level = (self.level + 1).min(self.params.max_level);
+        } else if submissions < self.params.target_submissions / 2 {
+            self.level = self.level.saturating_sub(1);
+        }
+        // Windows with no submissions at all each lower the level once
+        let idle = ended - 1;
+        self.level = self.level.saturating_sub(idle.min(u8::MAX as u64) as u8);
+        self.history.push_back(submissions);
+        self.history.extend(std::iter::repeat_n(0, idle.min(RATE_WINDOWS as u64) as usize));
+        while self.history.len() > RATE_WINDOWS {
+            self.history.pop_front();
+        }
+        self.window_start += window * ended;
+    }
+
+    /// Submission rate for governance policy conditions
+    pub fn metrics(&self) -> HashMap<String, PreciseFloat> {
+        let average = match self.history.len() {
+            0 => 0.0,
+            windows => self.history.iter().sum::<u32>() as f64 / windows as f64,
+        };
+        HashMap::from([
+            ("hubble.submissions_per_window".to_string(), PreciseFloat::from_f64(average, 2)),
+            ("hubble.admission_level".to_string(), PreciseFloat::new(self.level as i128 * 100, 2)),
+        ])
+    }
+
+    /// Apply a governance parameter update. Returns whether the action was
+    /// for this guard.
+    pub fn apply_governance(&mut self, action: &Action) -> Result<bool, &'static str> {
+        let Action::UpdateParameter(name, value) = action else { return Ok(false) };
+        let whole = value.to_f64().unwrap_or(-1.0);
+        let integer = |max: f64| -> Result<u64, &'static str> {
+            if whole < 0.0 || whole > max || whole.fract() != 0.0 {
+                return Err("Admission parameter out of range");
+            }
+            Ok(whole as u64)
+        };
+        match name.as_str() {
+            "hubble.base_difficulty" => self.params.base_difficulty = integer(MAX_DIFFICULTY as f64)? as u8,
+            "hubble.base_fee" if whole >= 0.0 => self.params.base_fee = value.clone(),
+            "hubble.base_fee" => return Err("Admission parameter out of range"),
+            "hubble.target_submissions" => self.params.target_submissions = integer(u32::MAX as f64)?.max(1) as u32,
+            "hubble.window_secs" => self.params.window_secs = integer(86_400.0)?.max(1),
+            "hubble.max_level" => {
+                self.params.max_level = integer(MAX_DIFFICULTY as f64)? as u8;
+                self.level = self.level.min(self.params.max_level);
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+
+    #[test]
+    fn test_proofs_and_rate_retargeting() {
+        let clock = MockClock::new(1_000);
+        let params = AdmissionParams { base_difficulty: 8, target_submissions: 4, ..Default::default() };
+        let mut guard = SubmissionGuard::with_clock(params, clock.clone());
+        let mut economics = EconomicModel::new(20);
+
+        let id = [7u8; 32];
+        let unsolved = (0..).find(|nonce| leading_zero_bits(&work_hash(&id, *nonce)) < 8).unwrap();
+        assert_eq!(
+            guard.admit(&id, &SubmissionProof::Work { nonce: unsolved }, &mut economics),
+            Err("Proof of work below current difficulty"),
+        );
+        guard.admit(&id, &SubmissionProof::Work { nonce: solve(&id, 8) }, &mut economics).unwrap();
+        assert_eq!(
+            guard.admit(&id, &SubmissionProof::Fee { amount: PreciseFloat::new(5, 2) }, &mut economics),
+            Err("Submission fee below current fee"),
+        );
+
+        // A busy window raises difficulty and doubles the fee
+        for i in 0..5u8 {
+            guard.admit(&[i; 32], &SubmissionProof::Fee { amount: PreciseFloat::new(10, 2) }, &mut economics).unwrap();
+        }
+        clock.advance(std::time::Duration::from_secs(60));
+        guard.roll_window();
+        assert_eq!(guard.status().level, 1);
+        assert_eq!(guard.difficulty(), 9);
+        assert_eq!(guard.fee(), PreciseFloat::new(20, 2));
+        assert!(guard.admit(&id, &SubmissionProof::Fee { amount: PreciseFloat::new(10, 2) }, &mut economics).is_err());
+
+        // Quiet windows bring it back down
+        clock.advance(std::time::Duration::from_secs(180));
+        guard.roll_window();
+        assert_eq!(guard.status().level, 0);
+
+        let raised = Action::UpdateParameter("hubble.base_difficulty".to_string(), PreciseFloat::new(1200, 2));
+        assert_eq!(guard.apply_governance(&raised), Ok(true));
+        assert_eq!(guard.difficulty(), 12);
+        let unrelated = Action::UpdateParameter("inflation_rate".to_string(), PreciseFloat::new(5, 2));
+        assert_eq!(guard.apply_governance(&unrelated), Ok(false));
+        assert!(guard.metrics().contains_key("hubble.submissions_per_window"));
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::{HashMap, HashSet};
+use num_traits::ToPrimitive;
+use crate::economics::models::EconomicModel;
+use crate::identity::zk_identity::ZKIdentity;
+use crate::ids::IdentityId;
+use crate::math::precision::PreciseFloat;
+
+pub type ContentId = [u8; 32];
+
+/// Tokens an identity has staked on one piece of content
+#[derive(Debug, Clone)]
+pub struct CurationStake {
+    pub staker: IdentityId,
+    pub amount: PreciseFloat,
+}
+
+/// Stakes burned when content is ruled malicious
+#[derive(Debug, Clone)]
+pub struct SlashReport {
+    pub content: ContentId,
+    pub slashed: Vec<(IdentityId, PreciseFloat)>,
+    pub total: PreciseFloat,
+}
+
+/// Curation Registry
+/// Identities stake tokens on content they vouch for. Staked content ranks
+/// higher by its stake weighted with each staker's current trust score;
+/// if moderation or governance later rules it malicious, every stake on it
+/// is burned and the stakers lose reputation.
+pub struct CurationRegistry {
+    precision: u8,
+    minimum_stake: PreciseFloat,
+    /// Rank added per trust-weighted token staked
+    boost_per_token: f64,
+    stakes: HashMap<ContentId, Vec<CurationStake>>,
+    malicious: HashSet<ContentId>,
+}
+
+impl CurationRegistry {
+    pub fn new(precision: u8) -> Self {
+        Self {
+            precision,
+            minimum_stake: PreciseFloat::new(1000, 2), // 10.00 tokens
+            boost_per_token: 0.001,
+            stakes: HashMap::new(),
+            malicious: HashSet::new(),
+        }
+    }
+
+    /// Lock `amount` tokens from `staker` behind `content`
+    pub fn stake(
+        &mut self,
+        content: ContentId,
+        staker: IdentityId,
+        amount: PreciseFloat,
+        identity: &ZKIdentity,
+        economics: &mut EconomicModel,
+    ) -> Result<(), &'static str> {
+        if self.malicious.contains(&content) {
+            return Err("Content was ruled malicious");
+        }
+        if amount.to_f64().unwrap_or(0.0) < self.minimum_stake.to_f64().unwrap_or(0.0) {
+            return Err("Stake amount below minimum");
+        }
+        identity.get_trust_score(&staker)?;
+
+        economics.lock_curation_stake(&amount);
+        let stakes = self.stakes.entry(content).or_default();
+        match stakes.iter_mut().find(|stake| stake.staker == staker) {
+            Some(stake) => stake.amount = stake.amount.add(&amount),
+            None => stakes.push(CurationStake { staker, amount }),
+        }
+        Ok(())
+    }
+
+    /// Withdraw a staker's whole stake on `content`
+    pub fn unstake(
+        &mut self,
+        content: &ContentId,
+        staker: &IdentityId,
+        economics: &mut EconomicModel,
+    ) -> Result<PreciseFloat, &'static str> {
+        let stakes = self.stakes.get_mut(content).ok_or("No stake on this content")?;
+        let index = stakes.iter().position(|stake| &stake.staker == staker).ok_or("No stake on this content")?;
+        let stake = stakes.remove(index);
+        if stakes.is_empty() {
+            self.stakes.remove(content);
+        }
+        economics.release_curation_stake(&stake.amount);
+        Ok(stake.amount)
+    }
+
+    pub fn stakes(&self, content: &ContentId) -> &[CurationStake] {
+        self.stakes.get(content).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn is_malicious(&self, content: &ContentId) -> bool {
+        self.malicious.contains(content)
+    }
+
+    /// Sum of stake × staker trust. Trust is read at query time, so a
+    /// staker who loses reputation carries less weight on all their stakes.
+    pub fn weighted_stake(&self, content: &ContentId, identity: &ZKIdentity) -> f64 {
+        self.stakes(content).iter()
+            .map(|stake| {
+                let trust = identity.get_trust_score(&stake.staker)
+                    .ok()
+                    .and_then(|trust| trust.to_f64())
+                    .unwrap_or(0.0)
+                    .max(0.0);
+                stake.amount.to_f64().unwrap_or(0.0) * trust
+            })
+            .sum()
+    }
+
+    /// `base_rank` plus the content's curation boost
+    pub fn boosted_rank(&self, content: &ContentId, base_rank: &PreciseFloat, identity: &ZKIdentity) -> PreciseFloat {
+        let boost = self.weighted_stake(content, identity) * self.boost_per_token;
+        let base = base_rank.to_f64().unwrap_or(0.0);
+        PreciseFloat::from_f64(base + boost, self.precision)
+    }
+
+    /// Order candidates by boosted rank, highest first, leaving out
+    /// content ruled malicious
+    pub fn rank(&self, candidates: &[(ContentId, PreciseFloat)], identity: &ZKIdentity) -> Vec<(ContentId, PreciseFloat)> {
+        let mut ranked: Vec<(ContentId, PreciseFloat)> = candidates.iter()
+            .filter(|(content, _)| !self.is_malicious(content))
+            .map(|(content, base)| (*content, self.boosted_rank(content, base, identity)))
+            .collect();
+        ranked.sort_by(|a, b| b.1.value.cmp(&a.1.value).then(a.0.cmp(&b.0)));
+        ranked
+    }
+
+    /// Record a moderation or governance ruling that `content` is malicious:
+    /// burn every stake on it and mark each staker for vouching for it
+    pub fn rule_malicious(
+        &mut self,
+        content: ContentId,
+        identity: &mut ZKIdentity,
+        economics: &mut EconomicModel,
+    ) -> SlashReport {
+        self.malicious.insert(content);
+        let mut total = PreciseFloat::new(0, 2);
+        let mut slashed = Vec::new();
+        for stake in self.stakes.remove(&content).unwrap_or_default() {
+            economics.burn_curation_stake(&stake.amount);
+            // Identities that no longer exist have nothing left to penalize
+            let _ = identity.report_malicious_endorsement(&stake.staker);
+            total = total.add(&stake.amount);
+            slashed.push((stake.staker, stake.amount));
+        }
+        SlashReport { content, slashed, total }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stake_boosts_rank_and_malicious_ruling_slashes() {
+        let mut identity = ZKIdentity::new(20);
+        let mut economics = EconomicModel::new(20);
+        let mut curation = CurationRegistry::new(4);
+        let (alice, _) = identity.create_identity(vec![]).unwrap();
+
+        let (spam, article) = ([1u8; 32], [2u8; 32]);
+        assert_eq!(
+            curation.stake(spam, alice, PreciseFloat::new(100, 2), &identity, &mut economics),
+            Err("Stake amount below minimum"),
+        );
+        curation.stake(spam, alice, PreciseFloat::new(500_000, 2), &identity, &mut economics).unwrap();
+
+        // Equal base ranks: the staked content comes first
+        let base = PreciseFloat::from_f64(0.5, 4);
+        let ranked = curation.rank(&[(article, base.clone()), (spam, base.clone())], &identity);
+        assert_eq!(ranked[0].0, spam);
+        assert!(curation.weighted_stake(&spam, &identity) > 0.0);
+
+        let trust_before = identity.get_trust_score(&alice).unwrap().to_f64().unwrap();
+        let report = curation.rule_malicious(spam, &mut identity, &mut economics);
+        assert_eq!(report.slashed.len(), 1);
+        assert!(curation.stakes(&spam).is_empty());
+        assert!(identity.get_trust_score(&alice).unwrap().to_f64().unwrap() < trust_before);
+        assert_eq!(
+            curation.stake(spam, alice, PreciseFloat::new(500_000, 2), &identity, &mut economics),
+            Err("Content was ruled malicious"),
+        );
+        let ranked = curation.rank(&[(article, base.clone()), (spam, base)], &identity);
+        assert_eq!(ranked.len(), 1);
+        assert_eq!(ranked[0].0, article);
+    }
+}
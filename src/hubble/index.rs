@@ -0,0 +1,373 @@
+//! Full-text index over Hubble content.
+//!
+//! Titles, tags and bodies are tokenized in the content's language and kept
+//! in an inverted index with the byte position of every body match, so
+//! search results carry a snippet of the body around the densest cluster of
+//! query terms, with highlight offsets for each match inside it. Changes
+//! since the last commit are kept apart so `SegmentStore` can persist them
+//! as a new segment.
+
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use crate::blockchain::types::hex_serde;
+use super::curation::ContentId;
+use super::segments::Segment;
+use super::tokenize::{tokenize, Language, Token};
+
+pub const MAX_TITLE_LEN: usize = 256;
+pub const MAX_BODY_LEN: usize = 64 * 1024;
+pub const MAX_TAGS: usize = 16;
+
+/// Longest snippet a search may ask for, in characters
+pub const MAX_SNIPPET_LENGTH: usize = 1_024;
+
+/// A title or tag match counts as this many body matches
+const FIELD_WEIGHT: f64 = 3.0;
+
+// BM25 parameters
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+/// Identifier of a piece of content, derived from its title and body
+pub fn content_id(title: &str, body: &str) -> ContentId {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"qmv:hubble:content:");
+    hasher.update(&(title.len() as u64).to_le_bytes());
+    hasher.update(title.as_bytes());
+    hasher.update(body.as_bytes());
+    hasher.finalize().into()
+}
+
+/// Indexed content
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContentDocument {
+    pub title: String,
+    pub body: String,
+    pub language: Language,
+    pub tags: Vec<String>,
+    pub added_at: u64,
+}
+
+/// Where one term occurs in one document
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(super) struct Posting {
+    /// Matches in the title and tags
+    pub(super) field_hits: u32,
+    /// Byte ranges of matches in the body
+    pub(super) body: Vec<(usize, usize)>,
+}
+
+/// Byte range of a match inside `Snippet::text`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Highlight {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Excerpt of a body around its matches
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snippet {
+    pub text: String,
+    /// Byte offset of `text` in the body
+    pub offset: usize,
+    pub highlights: Vec<Highlight>,
+    /// Whether body text was cut before or after the excerpt
+    pub truncated_before: bool,
+    pub truncated_after: bool,
+}
+
+/// Ranked search result
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    #[serde(with = "hex_serde")]
+    pub id: ContentId,
+    pub title: String,
+    pub language: Language,
+    pub tags: Vec<String>,
+    pub score: f64,
+    pub snippet: Snippet,
+}
+
+/// Content Index
+/// Inverted index over content titles, tags and bodies, ranked with BM25.
+#[derive(Debug, Clone, Default)]
+pub struct ContentIndex {
+    documents: BTreeMap<ContentId, ContentDocument>,
+    /// Token count of each document, for length normalization
+    lengths: HashMap<ContentId, usize>,
+    postings: HashMap<String, BTreeMap<ContentId, Posting>>,
+    total_length: usize,
+    /// Documents added and removed since the last commit
+    added: BTreeSet<ContentId>,
+    deleted: BTreeSet<ContentId>,
+}
+
+impl ContentIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reject a document that cannot be indexed; returns the ID it would get
+    pub fn check(&self, document: &ContentDocument) -> Result<ContentId, &'static str> {
+        if document.title.trim().is_empty() || document.title.len() > MAX_TITLE_LEN {
+            return Err("Content title must be between 1 and 256 bytes");
+        }
+        if document.body.len() > MAX_BODY_LEN {
+            return Err("Content body above 64 KiB");
+        }
+        if document.tags.len() > MAX_TAGS {
+            return Err("At most 16 tags per content");
+        }
+        let id = content_id(&document.title, &document.body);
+        if self.documents.contains_key(&id) {
+            return Err("Content already indexed");
+        }
+        Ok(id)
+    }
+
+    /// Index a document and return its ID
+    pub fn add_content(&mut self, document: ContentDocument) -> Result<ContentId, &'static str> {
+        let id = self.check(&document)?;
+        let fields: Vec<Token> = std::iter::once(document.title.as_str())
+            .chain(document.tags.iter().map(String::as_str))
+            .flat_map(|field| tokenize(field, document.language))
+            .collect();
+        let body = tokenize(&document.body, document.language);
+        let length = fields.len() + body.len();
+        for token in fields {
+            self.postings.entry(token.term).or_default().entry(id).or_default().field_hits += 1;
+        }
+        for token in body {
+            self.postings.entry(token.term).or_default().entry(id).or_default().body.push((token.start, token.end));
+        }
+        self.lengths.insert(id, length);
+        self.total_length += length;
+        self.documents.insert(id, document);
+        self.added.insert(id);
+        Ok(id)
+    }
+
+    /// Drop a document and its postings
+    pub fn remove(&mut self, id: &ContentId) -> Option<ContentDocument> {
+        let document = self.unindex(id)?;
+        self.added.remove(id);
+        self.deleted.insert(*id);
+        Some(document)
+    }
+
+    fn unindex(&mut self, id: &ContentId) -> Option<ContentDocument> {
+        let document = self.documents.remove(id)?;
+        self.total_length -= self.lengths.remove(id).unwrap_or(0);
+        self.postings.retain(|_, postings| {
+            postings.remove(id);
+            !postings.is_empty()
+        });
+        Some(document)
+    }
+
+    /// Changes since the last commit, as a segment
+    pub(super) fn delta(&self) -> Segment {
+        let mut postings: BTreeMap<String, BTreeMap<ContentId, Posting>> = BTreeMap::new();
+        for (term, by_document) in &self.postings {
+            for id in self.added.iter().filter(|id| by_document.contains_key(*id)) {
+                postings.entry(term.clone()).or_default().insert(*id, by_document[id].clone());
+            }
+        }
+        Segment {
+            documents: self.added.iter().map(|id| (*id, self.documents[id].clone())).collect(),
+            lengths: self.added.iter().map(|id| (*id, self.lengths[id])).collect(),
+            postings,
+            deleted: self.deleted.clone(),
+        }
+    }
+
+    /// Forget the changes returned by `delta` once they are on disk
+    pub(super) fn clear_delta(&mut self) {
+        self.added.clear();
+        self.deleted.clear();
+    }
+
+    /// Load a committed segment without re-tokenizing its documents
+    pub(super) fn apply(&mut self, segment: Segment) {
+        for id in &segment.deleted {
+            self.unindex(id);
+        }
+        for (id, document) in segment.documents {
+            let length = segment.lengths.get(&id).copied().unwrap_or(0);
+            self.lengths.insert(id, length);
+            self.total_length += length;
+            self.documents.insert(id, document);
+        }
+        for (term, by_document) in segment.postings {
+            self.postings.entry(term).or_default().extend(by_document);
+        }
+    }
+
+    pub fn get(&self, id: &ContentId) -> Option<&ContentDocument> {
+        self.documents.get(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    /// Best `limit` matches for `query`, each with a snippet of at most
+    /// `snippet_length` characters
+    pub fn search(&self, query: &str, language: Language, limit: usize, snippet_length: usize) -> Vec<SearchHit> {
+        let terms: BTreeSet<String> = tokenize(query, language).into_iter().map(|token| token.term).collect();
+        let snippet_length = snippet_length.clamp(1, MAX_SNIPPET_LENGTH);
+        let total = self.documents.len() as f64;
+        let average_length = (self.total_length as f64 / total.max(1.0)).max(1.0);
+
+        let mut scores: BTreeMap<ContentId, f64> = BTreeMap::new();
+        for term in &terms {
+            let Some(postings) = self.postings.get(term) else { continue };
+            let frequency = postings.len() as f64;
+            let idf = (1.0 + (total - frequency + 0.5) / (frequency + 0.5)).ln();
+            for (id, posting) in postings {
+                let hits = posting.body.len() as f64 + FIELD_WEIGHT * posting.field_hits as f64;
+                let length = self.lengths.get(id).copied().unwrap_or(0) as f64;
+                let norm = K1 * (1.0 - B + B * length / average_length);
+                *scores.entry(*id).or_default() += idf * hits * (K1 + 1.0) / (hits + norm);
+            }
+        }
+
+        let mut ranked: Vec<(ContentId, f64)> = scores.into_iter().collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        ranked.into_iter()
+            .take(limit)
+            .map(|(id, score)| {
+                let document = &self.documents[&id];
+                SearchHit {
+                    id,
+                    title: document.title.clone(),
+                    language: document.language,
+                    tags: document.tags.clone(),
+                    score,
+                    snippet: self.snippet(&id, &document.body, &terms, snippet_length),
+                }
+            })
+            .collect()
+    }
+
+    /// Window of the body holding the most distinct query terms, then the
+    /// most matches; the opening of the body if only the title matched
+    fn snippet(&self, id: &ContentId, body: &str, terms: &BTreeSet<String>, length: usize) -> Snippet {
+        let mut matches: Vec<(usize, usize, &str)> = terms.iter()
+            .filter_map(|term| self.postings.get(term)?.get(id).map(|posting| (term, posting)))
+            .flat_map(|(term, posting)| posting.body.iter().map(move |&(start, end)| (start, end, term.as_str())))
+            .collect();
+        matches.sort_unstable();
+        // CJK pairs overlap; keep only matches that start after the previous one ends
+        let mut last_end = 0;
+        matches.retain(|&(start, end, _)| {
+            let keep = start >= last_end;
+            if keep {
+                last_end = end;
+            }
+            keep
+        });
+
+        let mut best = window(body, 0, length);
+        let mut best_rank = (0, 0);
+        for &(anchor, anchor_end, _) in &matches {
+            let (start, end) = window(body, anchor, length);
+            let inside: Vec<_> = matches.iter().filter(|m| m.0 >= start && m.1 <= end).collect();
+            let distinct = inside.iter().map(|m| m.2).collect::<BTreeSet<_>>().len();
+            let rank = (distinct, inside.len());
+            if rank > best_rank && anchor_end <= end {
+                best = (start, end);
+                best_rank = rank;
+            }
+        }
+
+        let (start, end) = best;
+        Snippet {
+            text: body[start..end].to_string(),
+            offset: start,
+            highlights: matches.iter()
+                .filter(|m| m.0 >= start && m.1 <= end)
+                .map(|m| Highlight { start: m.0 - start, end: m.1 - start })
+                .collect(),
+            truncated_before: start > 0,
+            truncated_after: end < body.len(),
+        }
+    }
+}
+
+/// Byte range of at most `length` characters around a match starting at
+/// `anchor`, with a little context before it and cut at word boundaries
+/// where that does not drop the match
+fn window(body: &str, anchor: usize, length: usize) -> (usize, usize) {
+    let context = length / 4;
+    let before: Vec<(usize, char)> = body[..anchor].char_indices().rev().take(context).collect();
+    let mut start = before.last().map_or(anchor, |(index, _)| *index);
+    // Skip a partial word at the front
+    if start > 0 && body[..start].chars().next_back().is_some_and(char::is_alphanumeric) {
+        start = body[start..anchor].find(|c: char| !c.is_alphanumeric())
+            .map_or(anchor, |offset| start + offset);
+        start += body[start..anchor].find(|c: char| c.is_alphanumeric()).unwrap_or(anchor - start);
+    }
+
+    let mut end = body[start..].char_indices().nth(length).map_or(body.len(), |(index, _)| start + index);
+    // Back off a partial word at the back
+    if end < body.len() && body[end..].chars().next().is_some_and(char::is_alphanumeric) {
+        if let Some(cut) = body[anchor..end].rfind(|c: char| !c.is_alphanumeric()) {
+            if cut > 0 {
+                end = anchor + cut;
+            }
+        }
+    }
+    (start, end.max(start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(title: &str, body: &str) -> ContentDocument {
+        ContentDocument {
+            title: title.to_string(),
+            body: body.to_string(),
+            language: Language::English,
+            tags: vec![],
+            added_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_search_ranks_and_highlights_body_matches() {
+        let mut index = ContentIndex::new();
+        let filler = "Nothing relevant happens in this part of the text at all. ".repeat(4);
+        let body = format!("{}Virtual parcels near the plaza sell fastest, and plaza parcels rent well. {}", filler, filler);
+        let guide = index.add_content(document("Land guide", &body)).unwrap();
+        let other = index.add_content(document("Parcel auctions", "Auctions close after a fixed number of blocks.")).unwrap();
+        index.add_content(document("Avatars", "Wearables and skins.")).unwrap();
+        assert_eq!(index.add_content(document("Avatars", "Wearables and skins.")), Err("Content already indexed"));
+
+        let hits = index.search("plaza parcel", Language::English, 10, 100);
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].id, guide);
+        assert_eq!(hits[1].id, other);
+
+        let snippet = &hits[0].snippet;
+        assert!(snippet.text.chars().count() <= 100);
+        assert!(snippet.truncated_before && snippet.truncated_after);
+        let highlighted: Vec<&str> = snippet.highlights.iter().map(|h| &snippet.text[h.start..h.end]).collect();
+        assert_eq!(highlighted, vec!["parcels", "plaza", "plaza", "parcels"]);
+        assert_eq!(&body[snippet.offset..snippet.offset + snippet.text.len()], snippet.text);
+
+        // Only the title matched: the snippet is the opening of the body
+        let title_only = &hits[1].snippet;
+        assert_eq!(title_only.offset, 0);
+        assert!(title_only.highlights.is_empty());
+
+        index.remove(&guide).unwrap();
+        assert_eq!(index.search("plaza", Language::English, 10, 60).len(), 0);
+        assert_eq!(index.len(), 2);
+    }
+}
@@ -1,2 +1,7 @@
 pub mod state;
 pub mod transitions;
+pub mod curation;
+pub mod tokenize;
+pub mod index;
+pub mod segments;
+pub mod admission;
@@ -0,0 +1,336 @@
+//! On-disk segments for the Hubble content index.
+//!
+//! Each commit writes the documents, postings and deletions made since the
+//! previous one as a new immutable segment file, then atomically replaces
+//! the manifest that lists the live segments in order. A crash before the
+//! manifest is replaced leaves the previous commit intact; stray files are
+//! removed on the next open. Reopening loads segments straight into the
+//! index without re-tokenizing, and `compact` merges one adjacent pair at a
+//! time so segment count stays bounded without rewriting the whole index.
+
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use super::curation::ContentId;
+use super::index::{ContentDocument, ContentIndex, Posting};
+
+const MANIFEST: &str = "MANIFEST.json";
+const SEGMENT_MAGIC: &[u8; 4] = b"QHSG";
+const SEGMENT_VERSION: u32 = 1;
+
+/// Documents and postings added, and documents deleted, over a run of commits
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(super) struct Segment {
+    pub(super) documents: BTreeMap<ContentId, ContentDocument>,
+    /// Token count of each document
+    pub(super) lengths: BTreeMap<ContentId, usize>,
+    pub(super) postings: BTreeMap<String, BTreeMap<ContentId, Posting>>,
+    /// Documents removed from earlier segments
+    pub(super) deleted: BTreeSet<ContentId>,
+}
+
+impl Segment {
+    fn is_empty(&self) -> bool {
+        self.documents.is_empty() && self.deleted.is_empty()
+    }
+
+    /// One segment equivalent to applying `self`, then `newer`. Deletions
+    /// are kept unless `oldest`, since they may target earlier segments.
+    fn merge(mut self, newer: Segment, oldest: bool) -> Segment {
+        for id in &newer.deleted {
+            self.documents.remove(id);
+            self.lengths.remove(id);
+        }
+        self.postings.retain(|_, by_document| {
+            by_document.retain(|id, _| !newer.deleted.contains(id));
+            !by_document.is_empty()
+        });
+        self.documents.extend(newer.documents);
+        self.lengths.extend(newer.lengths);
+        for (term, by_document) in newer.postings {
+            self.postings.entry(term).or_default().extend(by_document);
+        }
+        self.deleted.extend(newer.deleted);
+        if oldest {
+            self.deleted.clear();
+        }
+        self
+    }
+
+    fn encode(&self) -> Result<Vec<u8>, String> {
+        let mut bytes = SEGMENT_MAGIC.to_vec();
+        bytes.extend_from_slice(&SEGMENT_VERSION.to_le_bytes());
+        bincode::serialize_into(&mut bytes, self).map_err(|e| e.to_string())?;
+        Ok(bytes)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < 8 || &bytes[..4] != SEGMENT_MAGIC {
+            return Err("not a Hubble segment".to_string());
+        }
+        let version = u32::from_le_bytes(bytes[4..8].try_into().expect("4 bytes"));
+        if version != SEGMENT_VERSION {
+            return Err(format!("unsupported segment version {}", version));
+        }
+        bincode::deserialize(&bytes[8..]).map_err(|e| e.to_string())
+    }
+}
+
+/// Live segment, as listed in the manifest
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SegmentInfo {
+    pub id: u64,
+    pub documents: usize,
+    pub deleted: usize,
+    pub bytes: u64,
+    /// blake3 of the segment file, checked on open
+    pub checksum: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Manifest {
+    next_id: u64,
+    /// Oldest first; applied in this order
+    segments: Vec<SegmentInfo>,
+}
+
+/// Segment Store
+/// Directory of index segments plus the manifest naming the committed ones.
+pub struct SegmentStore {
+    dir: PathBuf,
+    manifest: Manifest,
+}
+
+impl SegmentStore {
+    /// Open `dir`, creating it if needed, and load the committed index
+    pub fn open(dir: impl Into<PathBuf>) -> Result<(Self, ContentIndex), String> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        let manifest_path = dir.join(MANIFEST);
+        let manifest: Manifest = match std::fs::read(&manifest_path) {
+            Ok(raw) => serde_json::from_slice(&raw)
+                .map_err(|e| format!("Corrupt Hubble manifest {}: {}", manifest_path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Manifest::default(),
+            Err(e) => return Err(format!("Failed to read {}: {}", manifest_path.display(), e)),
+        };
+        let store = Self { dir, manifest };
+
+        let mut index = ContentIndex::new();
+        for info in &store.manifest.segments {
+            index.apply(store.read_segment(info)?);
+        }
+        store.remove_stray_files()?;
+        Ok((store, index))
+    }
+
+    pub fn segments(&self) -> &[SegmentInfo] {
+        &self.manifest.segments
+    }
+
+    /// Write the index's uncommitted changes as a new segment. Returns the
+    /// segment ID, or `None` if there was nothing to commit.
+    pub fn commit(&mut self, index: &mut ContentIndex) -> Result<Option<u64>, String> {
+        let segment = index.delta();
+        if segment.is_empty() {
+            return Ok(None);
+        }
+        let mut manifest = self.manifest.clone();
+        let info = self.write_segment(&mut manifest, &segment)?;
+        let id = info.id;
+        manifest.segments.push(info);
+        self.save_manifest(manifest)?;
+        index.clear_delta();
+        Ok(Some(id))
+    }
+
+    /// Merge the adjacent pair of segments with the fewest documents if
+    /// there are more than `max_segments`. Returns whether a merge ran.
+    pub fn compact(&mut self, max_segments: usize) -> Result<bool, String> {
+        let segments = &self.manifest.segments;
+        if segments.len() <= max_segments.max(1) {
+            return Ok(false);
+        }
+        let at = (0..segments.len() - 1)
+            .min_by_key(|&i| segments[i].documents + segments[i + 1].documents)
+            .expect("at least two segments");
+        let (older, newer) = (segments[at].clone(), segments[at + 1].clone());
+        let merged = self.read_segment(&older)?.merge(self.read_segment(&newer)?, at == 0);
+
+        let mut manifest = self.manifest.clone();
+        let info = self.write_segment(&mut manifest, &merged)?;
+        manifest.segments.splice(at..at + 2, [info]);
+        self.save_manifest(manifest)?;
+        // The merged pair is no longer referenced; leftovers go on the next open
+        for info in [older, newer] {
+            let _ = std::fs::remove_file(self.segment_path(info.id));
+        }
+        Ok(true)
+    }
+
+    /// Re-tokenize every committed document into a fresh index and replace
+    /// all segments with one holding it. Recovers from damaged postings or
+    /// lengths as long as the documents themselves still decode.
+    pub fn rebuild(&mut self) -> Result<ContentIndex, String> {
+        let mut merged = Segment::default();
+        for info in &self.manifest.segments {
+            merged = merged.merge(self.read_segment(info)?, false);
+        }
+        let mut index = ContentIndex::new();
+        for document in merged.documents.into_values() {
+            index.add_content(document)?;
+        }
+
+        let mut manifest = self.manifest.clone();
+        let replaced = std::mem::take(&mut manifest.segments);
+        if !index.is_empty() {
+            let info = self.write_segment(&mut manifest, &index.delta())?;
+            manifest.segments.push(info);
+        }
+        self.save_manifest(manifest)?;
+        index.clear_delta();
+        for info in replaced {
+            let _ = std::fs::remove_file(self.segment_path(info.id));
+        }
+        Ok(index)
+    }
+
+    fn segment_path(&self, id: u64) -> PathBuf {
+        self.dir.join(format!("seg-{:08}.qhs", id))
+    }
+
+    fn read_segment(&self, info: &SegmentInfo) -> Result<Segment, String> {
+        let path = self.segment_path(info.id);
+        let bytes = std::fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        if blake3::hash(&bytes).to_hex().as_str() != info.checksum {
+            return Err(format!("Hubble segment {} failed its checksum", path.display()));
+        }
+        Segment::decode(&bytes).map_err(|e| format!("Corrupt Hubble segment {}: {}", path.display(), e))
+    }
+
+    /// Write a segment file under the next ID in `manifest`
+    fn write_segment(&self, manifest: &mut Manifest, segment: &Segment) -> Result<SegmentInfo, String> {
+        let id = manifest.next_id;
+        manifest.next_id += 1;
+        let bytes = segment.encode()?;
+        write_durably(&self.segment_path(id), &bytes)?;
+        Ok(SegmentInfo {
+            id,
+            documents: segment.documents.len(),
+            deleted: segment.deleted.len(),
+            bytes: bytes.len() as u64,
+            checksum: blake3::hash(&bytes).to_hex().to_string(),
+        })
+    }
+
+    /// Replace the manifest atomically; this is the commit point
+    fn save_manifest(&mut self, manifest: Manifest) -> Result<(), String> {
+        let raw = serde_json::to_vec_pretty(&manifest).map_err(|e| e.to_string())?;
+        write_durably(&self.dir.join(MANIFEST), &raw)?;
+        self.manifest = manifest;
+        Ok(())
+    }
+
+    /// Delete segments from interrupted commits and merges
+    fn remove_stray_files(&self) -> Result<(), String> {
+        let live: BTreeSet<PathBuf> = self.manifest.segments.iter().map(|info| self.segment_path(info.id)).collect();
+        let entries = std::fs::read_dir(&self.dir)
+            .map_err(|e| format!("Failed to list {}: {}", self.dir.display(), e))?;
+        for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+            let stray = match path.extension().and_then(|ext| ext.to_str()) {
+                Some("tmp") => true,
+                Some("qhs") => !live.contains(&path),
+                _ => false,
+            };
+            if stray {
+                let _ = std::fs::remove_file(&path);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Write through a temporary file, fsync, then rename over `path`, so a
+/// crash leaves either the old or the new contents
+fn write_durably(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    let tmp = path.with_extension("tmp");
+    let result = std::fs::File::create(&tmp)
+        .and_then(|mut file| {
+            file.write_all(bytes)?;
+            file.sync_all()
+        })
+        .and_then(|_| std::fs::rename(&tmp, path))
+        // Persist the rename itself
+        .and_then(|_| match path.parent() {
+            Some(dir) if cfg!(unix) => std::fs::File::open(dir)?.sync_all(),
+            _ => Ok(()),
+        });
+    result.map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hubble::tokenize::Language;
+
+    fn document(title: &str, body: &str) -> ContentDocument {
+        ContentDocument {
+            title: title.to_string(),
+            body: body.to_string(),
+            language: Language::English,
+            tags: vec![],
+            added_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_commit_reopen_and_compact() {
+        let dir = std::env::temp_dir().join(format!("qmv-hubble-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let (mut store, mut index) = SegmentStore::open(&dir).unwrap();
+        let plaza = index.add_content(document("Plaza", "Parcels near the plaza")).unwrap();
+        assert_eq!(store.commit(&mut index).unwrap(), Some(0));
+        assert_eq!(store.commit(&mut index).unwrap(), None);
+
+        let market = index.add_content(document("Market", "Auctions for parcels")).unwrap();
+        store.commit(&mut index).unwrap();
+        index.remove(&plaza);
+        index.add_content(document("Avatars", "Wearables and skins")).unwrap();
+        store.commit(&mut index).unwrap();
+        // Uncommitted content is lost with the process
+        index.add_content(document("Draft", "Parcels not yet committed")).unwrap();
+
+        // A crash mid-commit leaves a segment the manifest never listed
+        std::fs::write(dir.join("seg-00000099.qhs"), b"partial").unwrap();
+        let (mut store, index) = SegmentStore::open(&dir).unwrap();
+        assert!(!dir.join("seg-00000099.qhs").exists());
+        assert_eq!(index.len(), 2);
+        let hits = index.search("parcels", Language::English, 10, 40);
+        assert_eq!(hits.iter().map(|hit| hit.id).collect::<Vec<_>>(), vec![market]);
+        assert_eq!(hits[0].snippet.highlights.len(), 1);
+
+        // Three segments merge down to one, a pair at a time
+        assert!(store.compact(2).unwrap());
+        assert_eq!(store.segments().len(), 2);
+        assert!(store.compact(1).unwrap());
+        assert_eq!(store.segments().len(), 1);
+        assert!(!store.compact(1).unwrap());
+        assert!(store.segments()[0].deleted == 0, "Merging the oldest segment resolves deletions");
+
+        let (_, reopened) = SegmentStore::open(&dir).unwrap();
+        assert_eq!(reopened.len(), 2);
+        assert!(reopened.get(&plaza).is_none());
+        assert_eq!(reopened.search("parcels", Language::English, 10, 40).len(), 1);
+
+        // Rebuilding re-tokenizes the same documents into one segment
+        let (mut store, _) = SegmentStore::open(&dir).unwrap();
+        let rebuilt = store.rebuild().unwrap();
+        assert_eq!(rebuilt.len(), 2);
+        assert_eq!(store.segments().len(), 1);
+        assert_eq!(rebuilt.search("parcels", Language::English, 10, 40).len(), 1);
+        assert_eq!(SegmentStore::open(&dir).unwrap().1.len(), 2);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,175 @@
+use serde::{Serialize, Deserialize};
+
+/// Language of indexed text; picks stop words and suffix stripping.
+/// Chinese, Japanese and Korean script is split into overlapping
+/// character pairs whatever the language, since it has no spaces.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Language {
+    #[default]
+    #[serde(rename = "en")]
+    English,
+    #[serde(rename = "es")]
+    Spanish,
+    #[serde(rename = "fr")]
+    French,
+    #[serde(rename = "de")]
+    German,
+    /// Chinese, Japanese or Korean
+    #[serde(rename = "cjk")]
+    Cjk,
+    /// No stop words or stemming
+    #[serde(rename = "other")]
+    Other,
+}
+
+impl Language {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::Spanish => "es",
+            Language::French => "fr",
+            Language::German => "de",
+            Language::Cjk => "cjk",
+            Language::Other => "other",
+        }
+    }
+
+    fn stop_words(&self) -> &'static [&'static str] {
+        match self {
+            Language::English => &[
+                "a", "an", "and", "are", "as", "at", "be", "by", "for", "from", "in", "is", "it",
+                "of", "on", "or", "that", "the", "this", "to", "was", "with",
+            ],
+            Language::Spanish => &[
+                "de", "del", "el", "en", "es", "la", "las", "los", "para", "por", "que", "un", "una", "y",
+            ],
+            Language::French => &[
+                "au", "aux", "de", "des", "du", "en", "est", "et", "la", "le", "les", "pour", "un", "une",
+            ],
+            Language::German => &[
+                "das", "den", "der", "die", "ein", "eine", "in", "ist", "mit", "und", "von", "zu",
+            ],
+            Language::Cjk | Language::Other => &[],
+        }
+    }
+
+    /// Strip common inflections so plural and singular forms match
+    fn stem(&self, word: &str) -> String {
+        let len = word.chars().count();
+        let strip = |suffixes: &[(&str, &str, usize)]| {
+            suffixes.iter()
+                .find(|(suffix, _, min_len)| len >= *min_len && word.ends_with(suffix))
+                .map(|(suffix, replacement, _)| format!("{}{}", &word[..word.len() - suffix.len()], replacement))
+        };
+        let stemmed = match self {
+            Language::English => strip(&[
+                ("sses", "ss", 5), ("ies", "y", 5), ("ing", "", 6), ("ed", "", 5), ("ss", "ss", 3), ("s", "", 4),
+            ]),
+            Language::Spanish => strip(&[("es", "", 5), ("s", "", 4)]),
+            Language::French => strip(&[("aux", "al", 5), ("s", "", 4), ("x", "", 4)]),
+            Language::German => strip(&[("en", "", 5), ("er", "", 5), ("e", "", 4), ("n", "", 4), ("s", "", 4)]),
+            Language::Cjk | Language::Other => None,
+        };
+        stemmed.unwrap_or_else(|| word.to_string())
+    }
+}
+
+impl std::str::FromStr for Language {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "en" => Ok(Language::English),
+            "es" => Ok(Language::Spanish),
+            "fr" => Ok(Language::French),
+            "de" => Ok(Language::German),
+            "cjk" | "zh" | "ja" | "ko" => Ok(Language::Cjk),
+            "other" => Ok(Language::Other),
+            _ => Err(format!("Unknown language `{}` (en, es, fr, de, zh, ja, ko or other)", s)),
+        }
+    }
+}
+
+/// Search term and the byte range of the text it came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub term: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Han, kana and hangul
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xAC00..=0xD7AF | 0xF900..=0xFAFF)
+}
+
+/// Split `text` into lowercase, stemmed terms with stop words removed
+pub fn tokenize(text: &str, language: Language) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if !c.is_alphanumeric() {
+            continue;
+        }
+        let cjk = is_cjk(c);
+        let mut run = vec![(start, c)];
+        while let Some(&(index, next)) = chars.peek() {
+            if !next.is_alphanumeric() || is_cjk(next) != cjk {
+                break;
+            }
+            run.push((index, next));
+            chars.next();
+        }
+        let end = run.last().map_or(start, |(index, c)| index + c.len_utf8());
+
+        if cjk {
+            // Overlapping pairs, so any two-character query matches
+            if run.len() == 1 {
+                tokens.push(Token { term: c.to_string(), start, end });
+            }
+            for pair in run.windows(2) {
+                let (first, second) = (pair[0], pair[1]);
+                tokens.push(Token {
+                    term: [first.1, second.1].iter().collect(),
+                    start: first.0,
+                    end: second.0 + second.1.len_utf8(),
+                });
+            }
+            continue;
+        }
+
+        let word = text[start..end].to_lowercase();
+        if language.stop_words().contains(&word.as_str()) {
+            continue;
+        }
+        tokens.push(Token { term: language.stem(&word), start, end });
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize_by_language() {
+        let terms = |text: &str, language| -> Vec<String> {
+            tokenize(text, language).into_iter().map(|token| token.term).collect()
+        };
+
+        assert_eq!(terms("The Parcels of the city", Language::English), vec!["parcel", "city"]);
+        assert_eq!(terms("Stories, classes", Language::English), vec!["story", "class"]);
+        // "de" is only a stop word in Spanish and French
+        assert_eq!(terms("casas de la ciudad", Language::Spanish), vec!["casa", "ciudad"]);
+        assert_eq!(terms("de", Language::German), vec!["de"]);
+
+        let tokens = tokenize("Héllo 世界和平!", Language::Other);
+        assert_eq!(tokens[0], Token { term: "héllo".to_string(), start: 0, end: 6 });
+        assert_eq!(tokens.iter().skip(1).map(|t| t.term.as_str()).collect::<Vec<_>>(), vec!["世界", "界和", "和平"]);
+        assert_eq!(&"Héllo 世界和平!"[tokens[3].start..tokens[3].end], "和平");
+
+        assert_eq!("ja".parse::<Language>(), Ok(Language::Cjk));
+        assert!("xx".parse::<Language>().is_err());
+    }
+}
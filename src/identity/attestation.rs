@@ -0,0 +1,289 @@
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use crate::blockchain::types::hex_serde;
+use crate::crypto::domain::{PayloadKind, SigningDomain};
+use crate::ids::IdentityId;
+
+/// Identity attribute holding the ed25519 key attestations are checked against
+pub const SIGNING_KEY_ATTRIBUTE: &str = "signing_key";
+
+/// Full-strength vouch, in basis points
+pub const MAX_WEIGHT: u16 = 10_000;
+
+/// Share of trust passed on at each hop
+pub const TRUST_DAMPING: f64 = 0.5;
+
+/// Hops followed when computing transitive trust
+pub const MAX_TRUST_DEPTH: usize = 3;
+
+/// Most attestations returned for one neighborhood query
+pub const MAX_NEIGHBORHOOD_EDGES: usize = 1_000;
+
+/// "Identity A vouches for identity B with weight w", signed by A
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Attestation {
+    pub attester: IdentityId,
+    pub subject: IdentityId,
+    /// Strength of the vouch in basis points, at most `MAX_WEIGHT`
+    pub weight: u16,
+    /// Attester's clock when signed; a newer attestation replaces an older one
+    pub issued_at: u64,
+    #[serde(with = "hex_serde")]
+    pub signature: Vec<u8>,
+}
+
+impl Attestation {
+    /// Bytes the attester signs
+    pub fn signing_bytes(&self, network_id: u64) -> Vec<u8> {
+        let mut body = Vec::with_capacity(1 + 32 + 32 + 2 + 8);
+        body.push(0); // attest
+        body.extend_from_slice(self.attester.as_bytes());
+        body.extend_from_slice(self.subject.as_bytes());
+        body.extend_from_slice(&self.weight.to_le_bytes());
+        body.extend_from_slice(&self.issued_at.to_le_bytes());
+        SigningDomain::main_chain(network_id).payload(PayloadKind::Attestation, 0, &body)
+    }
+}
+
+/// Withdrawal of an attestation, signed by its attester. Attestations
+/// issued at or before `revoked_at` are no longer accepted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Revocation {
+    pub attester: IdentityId,
+    pub subject: IdentityId,
+    pub revoked_at: u64,
+    #[serde(with = "hex_serde")]
+    pub signature: Vec<u8>,
+}
+
+impl Revocation {
+    pub fn signing_bytes(&self, network_id: u64) -> Vec<u8> {
+        let mut body = Vec::with_capacity(1 + 32 + 32 + 8);
+        body.push(1); // revoke
+        body.extend_from_slice(self.attester.as_bytes());
+        body.extend_from_slice(self.subject.as_bytes());
+        body.extend_from_slice(&self.revoked_at.to_le_bytes());
+        SigningDomain::main_chain(network_id).payload(PayloadKind::Attestation, 0, &body)
+    }
+}
+
+/// Check `signature` over `message` against an ed25519 key
+pub(crate) fn verify_signature(key: &[u8], message: &[u8], signature: &[u8]) -> Result<(), &'static str> {
+    let key: [u8; 32] = key.try_into().map_err(|_| "Attester signing key must be 32 bytes")?;
+    let key = VerifyingKey::from_bytes(&key).map_err(|_| "Invalid attester signing key")?;
+    let signature = Signature::from_slice(signature).map_err(|_| "Invalid attestation signature")?;
+    key.verify(message, &signature).map_err(|_| "Invalid attestation signature")
+}
+
+/// Attestations around one identity, for graph queries
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Neighborhood {
+    pub identities: Vec<IdentityId>,
+    pub attestations: Vec<Attestation>,
+    /// Whether `MAX_NEIGHBORHOOD_EDGES` cut the result short
+    pub truncated: bool,
+}
+
+/// Attestation Graph
+/// Current attestations between identities, indexed both ways, plus the
+/// latest revocation per pair so old attestations cannot be replayed.
+#[derive(Debug, Clone, Default)]
+pub struct AttestationGraph {
+    outgoing: BTreeMap<IdentityId, BTreeMap<IdentityId, Attestation>>,
+    incoming: BTreeMap<IdentityId, BTreeSet<IdentityId>>,
+    revoked: HashMap<(IdentityId, IdentityId), u64>,
+}
+
+impl AttestationGraph {
+    pub fn get(&self, attester: &IdentityId, subject: &IdentityId) -> Option<&Attestation> {
+        self.outgoing.get(attester)?.get(subject)
+    }
+
+    /// Check that an attestation is newer than any it would replace or any
+    /// revocation of the same pair
+    pub fn check(&self, attestation: &Attestation) -> Result<(), &'static str> {
+        if attestation.attester == attestation.subject {
+            return Err("Identities cannot attest to themselves");
+        }
+        if attestation.weight == 0 || attestation.weight > MAX_WEIGHT {
+            return Err("Attestation weight must be between 1 and 10,000 basis points");
+        }
+        let pair = (attestation.attester, attestation.subject);
+        if self.revoked.get(&pair).is_some_and(|revoked_at| attestation.issued_at <= *revoked_at) {
+            return Err("Attestation predates its revocation");
+        }
+        if self.get(&pair.0, &pair.1).is_some_and(|current| attestation.issued_at <= current.issued_at) {
+            return Err("A newer attestation is already recorded");
+        }
+        Ok(())
+    }
+
+    pub(crate) fn insert(&mut self, attestation: Attestation) {
+        self.incoming.entry(attestation.subject).or_default().insert(attestation.attester);
+        self.outgoing.entry(attestation.attester).or_default().insert(attestation.subject, attestation);
+    }
+
+    /// Record a revocation; returns the attestation it removed, if any
+    pub(crate) fn revoke(&mut self, revocation: &Revocation) -> Result<Option<Attestation>, &'static str> {
+        let pair = (revocation.attester, revocation.subject);
+        if self.revoked.get(&pair).is_some_and(|revoked_at| revocation.revoked_at <= *revoked_at) {
+            return Err("A newer revocation is already recorded");
+        }
+        self.revoked.insert(pair, revocation.revoked_at);
+        let removed = match self.outgoing.get_mut(&pair.0) {
+            Some(attestations) if attestations.get(&pair.1).is_some_and(|a| a.issued_at <= revocation.revoked_at) => {
+                attestations.remove(&pair.1)
+            }
+            _ => None,
+        };
+        if removed.is_some() {
+            if let Some(attesters) = self.incoming.get_mut(&pair.1) {
+                attesters.remove(&pair.0);
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Attestations vouching for `subject`
+    pub fn attestations_for<'a>(&'a self, subject: &IdentityId) -> impl Iterator<Item = &'a Attestation> + 'a {
+        let subject = *subject;
+        self.incoming.get(&subject)
+            .into_iter()
+            .flatten()
+            .filter_map(move |attester| self.get(attester, &subject))
+    }
+
+    /// Attestations made by `attester`
+    pub fn attestations_by<'a>(&'a self, attester: &IdentityId) -> impl Iterator<Item = &'a Attestation> + 'a {
+        self.outgoing.get(attester).into_iter().flat_map(|attestations| attestations.values())
+    }
+
+    /// Identities whose transitive trust may depend on `id`
+    pub fn downstream(&self, id: &IdentityId) -> BTreeSet<IdentityId> {
+        let mut reached = BTreeSet::new();
+        let mut frontier = vec![*id];
+        for _ in 0..MAX_TRUST_DEPTH {
+            frontier = frontier.iter()
+                .flat_map(|id| self.attestations_by(id).map(|a| a.subject))
+                .filter(|subject| reached.insert(*subject))
+                .collect();
+        }
+        reached
+    }
+
+    /// Identities and attestations within `depth` hops of `id`, following
+    /// attestations in both directions
+    pub fn neighborhood(&self, id: &IdentityId, depth: usize) -> Neighborhood {
+        let mut result = Neighborhood { identities: vec![*id], ..Default::default() };
+        let mut seen = BTreeSet::from([*id]);
+        let mut edges = BTreeSet::new();
+        let mut queue = VecDeque::from([(*id, 0)]);
+        while let Some((current, distance)) = queue.pop_front() {
+            if distance == depth {
+                continue;
+            }
+            for attestation in self.attestations_by(&current).chain(self.attestations_for(&current)) {
+                if !edges.insert((attestation.attester, attestation.subject)) {
+                    continue;
+                }
+                if result.attestations.len() == MAX_NEIGHBORHOOD_EDGES {
+                    result.truncated = true;
+                    return result;
+                }
+                result.attestations.push(attestation.clone());
+                for neighbor in [attestation.attester, attestation.subject] {
+                    if seen.insert(neighbor) {
+                        result.identities.push(neighbor);
+                        queue.push_back((neighbor, distance + 1));
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    /// Trust of `subject` in [0, 1] given each identity's own `standing` in
+    /// [0, 1]. Vouches fill part of the gap between the subject's standing
+    /// and full trust: each attester contributes its weight times its own
+    /// transitive trust, damped per hop, up to `MAX_TRUST_DEPTH` hops.
+    pub fn transitive_trust(&self, subject: &IdentityId, standing: &dyn Fn(&IdentityId) -> f64) -> f64 {
+        let mut memo = HashMap::new();
+        self.trust_at_depth(subject, MAX_TRUST_DEPTH, standing, &mut memo)
+    }
+
+    fn trust_at_depth(
+        &self,
+        id: &IdentityId,
+        depth: usize,
+        standing: &dyn Fn(&IdentityId) -> f64,
+        memo: &mut HashMap<(IdentityId, usize), f64>,
+    ) -> f64 {
+        if let Some(trust) = memo.get(&(*id, depth)) {
+            return *trust;
+        }
+        let own = standing(id).clamp(0.0, 1.0);
+        let trust = if depth == 0 {
+            own
+        } else {
+            let attesters: Vec<(IdentityId, u16)> = self.attestations_for(id).map(|a| (a.attester, a.weight)).collect();
+            let vouched: f64 = attesters.iter()
+                .map(|(attester, weight)| {
+                    let attester_trust = self.trust_at_depth(attester, depth - 1, standing, memo);
+                    *weight as f64 / MAX_WEIGHT as f64 * TRUST_DAMPING * attester_trust
+                })
+                .sum();
+            own + (1.0 - own) * vouched.min(1.0)
+        };
+        memo.insert((*id, depth), trust);
+        trust
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attestation(attester: u8, subject: u8, weight: u16, issued_at: u64) -> Attestation {
+        Attestation {
+            attester: IdentityId::new([attester; 32]),
+            subject: IdentityId::new([subject; 32]),
+            weight,
+            issued_at,
+            signature: vec![],
+        }
+    }
+
+    #[test]
+    fn test_transitive_trust_and_revocation() {
+        let mut graph = AttestationGraph::default();
+        let standing = |_: &IdentityId| 0.5;
+        let (a, b, c) = (IdentityId::new([1; 32]), IdentityId::new([2; 32]), IdentityId::new([3; 32]));
+        assert_eq!(graph.transitive_trust(&b, &standing), 0.5);
+
+        graph.insert(attestation(1, 2, MAX_WEIGHT, 10));
+        // 0.5 + 0.5 * (1.0 * 0.5 * 0.5)
+        assert!((graph.transitive_trust(&b, &standing) - 0.625).abs() < 1e-9);
+
+        // A vouched-for attester passes on more, damped once more
+        graph.insert(attestation(3, 1, MAX_WEIGHT, 10));
+        let with_chain = graph.transitive_trust(&b, &standing);
+        assert!(with_chain > 0.625 && with_chain < 0.7);
+        assert_eq!(graph.downstream(&c), BTreeSet::from([a, b]));
+
+        let neighborhood = graph.neighborhood(&b, 1);
+        assert_eq!(neighborhood.identities, vec![b, a]);
+        assert_eq!(neighborhood.attestations.len(), 1);
+        assert_eq!(graph.neighborhood(&b, 2).attestations.len(), 2);
+
+        assert_eq!(graph.check(&attestation(1, 1, 5_000, 11)), Err("Identities cannot attest to themselves"));
+        assert!(graph.check(&attestation(1, 2, 5_000, 10)).is_err());
+
+        let revocation = Revocation { attester: a, subject: b, revoked_at: 20, signature: vec![] };
+        assert!(graph.revoke(&revocation).unwrap().is_some());
+        assert_eq!(graph.transitive_trust(&b, &standing), 0.5);
+        // The revoked attestation cannot be replayed; a later one is accepted
+        assert_eq!(graph.check(&attestation(1, 2, MAX_WEIGHT, 10)), Err("Attestation predates its revocation"));
+        assert!(graph.check(&attestation(1, 2, MAX_WEIGHT, 21)).is_ok());
+    }
+}
@@ -1 +1,3 @@
 pub mod zk_identity;
+pub mod attestation;
+pub mod predicate;
@@ -0,0 +1,334 @@
+//! Attribute predicates proven without revealing the attribute.
+//!
+//! An identity attribute used in predicates holds a Pedersen commitment to
+//! a number (`commit_attribute`) rather than the number itself. A verifier,
+//! typically a dApp gating access, asks the node for a challenge naming the
+//! identity and the predicate, such as "age is at least 18". The holder, who
+//! knows the committed value and its blinding, answers with range proofs
+//! (or an opening proof for equality) over the commitment shifted by the
+//! predicate's bounds. The proofs are bound to the challenge nonce, so an
+//! answer cannot be replayed to another verifier or session, and each
+//! challenge is used once.
+//!
+//! Where there is no round trip, such as KYC-lite checks on a private chain,
+//! the holder makes an `AttributeProof` instead. It carries the identity
+//! commitment and the attribute with the holder's proof of adding it, so
+//! `verify_attribute_proof` needs nothing from the identity registry. It is
+//! bound to the time it was made rather than to a challenge; verifiers
+//! decide how old a proof they accept.
+
+use curve25519_dalek::scalar::Scalar;
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use crate::blockchain::types::hex_serde;
+use crate::crypto::pedersen::{self, OpeningProof, RangeProof};
+use crate::ids::IdentityId;
+use super::zk_identity::{self, AttributeTuple, IdentityTuple};
+
+/// Seconds a challenge can be answered in
+pub const CHALLENGE_TTL_SECS: u64 = 300;
+
+/// Most unanswered challenges kept; the oldest are dropped beyond this
+pub const MAX_OPEN_CHALLENGES: usize = 10_000;
+
+const TRANSCRIPT_TAG: &[u8] = b"QMV-PREDICATE1";
+
+/// Attribute value for a committed number. Returns the value to store and
+/// the blinding the holder keeps to prove predicates later.
+pub fn commit_attribute(value: u64) -> ([u8; 32], Scalar) {
+    let blinding = pedersen::random_scalar();
+    (pedersen::compress(&pedersen::commit(value, &blinding)), blinding)
+}
+
+/// The commitment held in `identity`'s attribute `name`
+pub fn attribute_commitment(identity: &IdentityTuple, name: &str) -> Result<[u8; 32], &'static str> {
+    let attribute = identity.public_tuple().attributes().iter()
+        .find(|attribute| attribute.name() == name)
+        .ok_or("Identity has no such attribute")?;
+    attribute.value().try_into().map_err(|_| "Attribute is not a committed number")
+}
+
+/// Condition on a committed attribute
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "op")]
+pub enum Predicate {
+    AtLeast { attribute: String, value: u64 },
+    AtMost { attribute: String, value: u64 },
+    /// Inclusive on both ends
+    Between { attribute: String, min: u64, max: u64 },
+    Equals { attribute: String, value: u64 },
+}
+
+impl Predicate {
+    pub fn attribute(&self) -> &str {
+        match self {
+            Predicate::AtLeast { attribute, .. }
+            | Predicate::AtMost { attribute, .. }
+            | Predicate::Between { attribute, .. }
+            | Predicate::Equals { attribute, .. } => attribute,
+        }
+    }
+
+    pub fn holds(&self, value: u64) -> bool {
+        match self {
+            Predicate::AtLeast { value: min, .. } => value >= *min,
+            Predicate::AtMost { value: max, .. } => value <= *max,
+            Predicate::Between { min, max, .. } => (*min..=*max).contains(&value),
+            Predicate::Equals { value: expected, .. } => value == *expected,
+        }
+    }
+
+    /// Lower and upper bounds a range disclosure covers
+    fn bounds(&self) -> (Option<u64>, Option<u64>) {
+        match self {
+            Predicate::AtLeast { value, .. } => (Some(*value), None),
+            Predicate::AtMost { value, .. } => (None, Some(*value)),
+            Predicate::Between { min, max, .. } => (Some(*min), Some(*max)),
+            Predicate::Equals { .. } => (None, None),
+        }
+    }
+}
+
+/// Open request from a verifier
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Challenge {
+    #[serde(with = "hex_serde")]
+    pub nonce: [u8; 32],
+    pub identity: IdentityId,
+    pub predicate: Predicate,
+    /// Verifier's session nonce the challenge was issued for
+    #[serde(with = "hex_serde")]
+    pub session: Vec<u8>,
+    pub expires_at: u64,
+}
+
+impl Challenge {
+    fn transcript(&self, commitment: &[u8; 32], label: &[u8]) -> Vec<u8> {
+        let predicate = bincode::serialize(&self.predicate).unwrap_or_default();
+        [TRANSCRIPT_TAG, label, self.nonce.as_slice(), self.identity.as_bytes().as_slice(), &predicate, commitment.as_slice()].concat()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Disclosure {
+    /// Value minus the lower bound and upper bound minus value, where the
+    /// predicate has them, are in range
+    Range {
+        #[serde(default)]
+        lower: Option<RangeProof>,
+        #[serde(default)]
+        upper: Option<RangeProof>,
+    },
+    Opening(OpeningProof),
+}
+
+/// Holder's answer to a challenge
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PredicateProof {
+    #[serde(with = "hex_serde")]
+    pub nonce: [u8; 32],
+    pub disclosure: Disclosure,
+}
+
+impl PredicateProof {
+    /// Answer `challenge` for an attribute committed with `value` and `blinding`
+    pub fn prove(challenge: &Challenge, value: u64, blinding: &Scalar) -> Result<Self, &'static str> {
+        if !challenge.predicate.holds(value) {
+            return Err("Attribute does not satisfy the predicate");
+        }
+        let commitment = pedersen::compress(&pedersen::commit(value, blinding));
+        let disclosure = match &challenge.predicate {
+            Predicate::Equals { value, .. } => {
+                Disclosure::Opening(OpeningProof::prove(*value, blinding, &challenge.transcript(&commitment, b"equals")))
+            }
+            predicate => {
+                let (min, max) = predicate.bounds();
+                Disclosure::Range {
+                    lower: min.map(|min| RangeProof::prove(value - min, blinding, &challenge.transcript(&commitment, b"lower"))),
+                    upper: max.map(|max| RangeProof::prove(max - value, &-blinding, &challenge.transcript(&commitment, b"upper"))),
+                }
+            }
+        };
+        Ok(Self { nonce: challenge.nonce, disclosure })
+    }
+
+    /// Check the proof answers `challenge` for the attribute committed as
+    /// `commitment`
+    pub fn verify(&self, challenge: &Challenge, commitment: &[u8; 32]) -> Result<(), &'static str> {
+        if self.nonce != challenge.nonce {
+            return Err("Proof is for another challenge");
+        }
+        let point = pedersen::decompress(commitment).map_err(|_| "Attribute is not a committed number")?;
+        match (&challenge.predicate, &self.disclosure) {
+            (Predicate::Equals { value, .. }, Disclosure::Opening(proof)) => {
+                proof.verify(&point, *value, &challenge.transcript(commitment, b"equals"))
+            }
+            (Predicate::Equals { .. }, _) | (_, Disclosure::Opening(_)) => Err("Proof does not match the predicate"),
+            (predicate, Disclosure::Range { lower, upper }) => {
+                match (predicate.bounds().0, lower) {
+                    (Some(min), Some(proof)) => {
+                        proof.verify(&(point - pedersen::commit_public(min)), &challenge.transcript(commitment, b"lower"))?;
+                    }
+                    (None, None) => {}
+                    _ => return Err("Proof does not match the predicate"),
+                }
+                match (predicate.bounds().1, upper) {
+                    (Some(max), Some(proof)) => {
+                        proof.verify(&(pedersen::commit_public(max) - point), &challenge.transcript(commitment, b"upper"))
+                    }
+                    (None, None) => Ok(()),
+                    _ => Err("Proof does not match the predicate"),
+                }
+            }
+        }
+    }
+}
+
+/// Standalone proof that an identity's hidden attribute satisfies a predicate
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttributeProof {
+    /// Commitment of the identity; its hash is the identity ID
+    #[serde(with = "hex_serde")]
+    pub identity_commitment: [u8; 64],
+    /// The committed attribute, with the holder's proof that it added it
+    pub attribute: AttributeTuple,
+    pub predicate: Predicate,
+    /// Unix time the proof was made
+    pub issued_at: u64,
+    pub proof: PredicateProof,
+}
+
+impl AttributeProof {
+    /// Prove `predicate` over `attribute`, committed with `value` and `blinding`
+    pub fn prove(
+        identity_commitment: [u8; 64],
+        attribute: AttributeTuple,
+        predicate: Predicate,
+        issued_at: u64,
+        value: u64,
+        blinding: &Scalar,
+    ) -> Result<Self, &'static str> {
+        let challenge = standalone_challenge(&identity_commitment, predicate.clone(), issued_at);
+        let proof = PredicateProof::prove(&challenge, value, blinding)?;
+        Ok(Self { identity_commitment, attribute, predicate, issued_at, proof })
+    }
+}
+
+/// Check a standalone attribute proof. Returns the identity it is about.
+pub fn verify_attribute_proof(proof: &AttributeProof) -> Result<IdentityId, &'static str> {
+    if proof.attribute.name() != proof.predicate.attribute() {
+        return Err("Proof does not match the predicate");
+    }
+    zk_identity::verify_attribute_of(&proof.identity_commitment, &proof.attribute)?;
+    let commitment: [u8; 32] = proof.attribute.value().try_into().map_err(|_| "Attribute is not a committed number")?;
+    let challenge = standalone_challenge(&proof.identity_commitment, proof.predicate.clone(), proof.issued_at);
+    proof.proof.verify(&challenge, &commitment)?;
+    Ok(challenge.identity)
+}
+
+/// Challenge a standalone proof answers, derived from what it proves
+fn standalone_challenge(identity_commitment: &[u8; 64], predicate: Predicate, issued_at: u64) -> Challenge {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(TRANSCRIPT_TAG);
+    hasher.update(b"standalone");
+    hasher.update(identity_commitment);
+    hasher.update(&issued_at.to_be_bytes());
+    Challenge {
+        nonce: hasher.finalize().into(),
+        identity: zk_identity::identity_id(identity_commitment),
+        predicate,
+        session: Vec::new(),
+        expires_at: issued_at,
+    }
+}
+
+/// Predicate Challenges
+/// Challenges issued by the node and not yet answered or expired.
+#[derive(Debug, Clone, Default)]
+pub struct PredicateChallenges {
+    open: HashMap<[u8; 32], Challenge>,
+}
+
+impl PredicateChallenges {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Issue a challenge for `identity` to prove `predicate`, bound to the
+    /// verifier's `session` nonce
+    pub fn issue(&mut self, identity: IdentityId, predicate: Predicate, session: Vec<u8>, now: u64) -> Challenge {
+        self.open.retain(|_, challenge| challenge.expires_at > now);
+        if self.open.len() >= MAX_OPEN_CHALLENGES {
+            let oldest = self.open.values().min_by_key(|challenge| challenge.expires_at).map(|challenge| challenge.nonce);
+            if let Some(nonce) = oldest {
+                self.open.remove(&nonce);
+            }
+        }
+
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&session);
+        hasher.update(pedersen::random_scalar().as_bytes());
+        let challenge = Challenge {
+            nonce: hasher.finalize().into(),
+            identity,
+            predicate,
+            session,
+            expires_at: now + CHALLENGE_TTL_SECS,
+        };
+        self.open.insert(challenge.nonce, challenge.clone());
+        challenge
+    }
+
+    /// Remove and return the challenge a proof answers
+    pub fn take(&mut self, nonce: &[u8; 32], now: u64) -> Result<Challenge, &'static str> {
+        let challenge = self.open.remove(nonce).ok_or("Unknown or already answered challenge")?;
+        if challenge.expires_at <= now {
+            return Err("Challenge expired");
+        }
+        Ok(challenge)
+    }
+
+    pub fn len(&self) -> usize {
+        self.open.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.open.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_predicates_prove_without_revealing() {
+        let identity = IdentityId::new([1; 32]);
+        let (commitment, blinding) = commit_attribute(30);
+        let mut challenges = PredicateChallenges::new();
+        let adult = Predicate::AtLeast { attribute: "age".to_string(), value: 18 };
+
+        let challenge = challenges.issue(identity, adult.clone(), b"session".to_vec(), 100);
+        let proof = PredicateProof::prove(&challenge, 30, &blinding).unwrap();
+        let taken = challenges.take(&proof.nonce, 101).unwrap();
+        assert!(proof.verify(&taken, &commitment).is_ok());
+        assert!(challenges.take(&proof.nonce, 101).is_err());
+
+        // Bound to the challenge it answers
+        let other = challenges.issue(identity, adult, b"session".to_vec(), 100);
+        assert!(proof.verify(&other, &commitment).is_err());
+
+        let between = challenges.issue(identity, Predicate::Between { attribute: "age".to_string(), min: 21, max: 40 }, Vec::new(), 100);
+        assert!(PredicateProof::prove(&between, 30, &blinding).unwrap().verify(&between, &commitment).is_ok());
+        let senior = challenges.issue(identity, Predicate::AtLeast { attribute: "age".to_string(), value: 65 }, Vec::new(), 100);
+        assert!(PredicateProof::prove(&senior, 30, &blinding).is_err());
+        // A false claim about the value does not verify either
+        let forged = PredicateProof::prove(&senior, 70, &blinding).unwrap();
+        assert!(forged.verify(&senior, &commitment).is_err());
+
+        let exact = challenges.issue(identity, Predicate::Equals { attribute: "age".to_string(), value: 30 }, Vec::new(), 100);
+        assert!(PredicateProof::prove(&exact, 30, &blinding).unwrap().verify(&exact, &commitment).is_ok());
+        assert_eq!(challenges.take(&exact.nonce, 100 + CHALLENGE_TTL_SECS), Err("Challenge expired"));
+    }
+}
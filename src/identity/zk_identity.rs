@@ -1,51 +1,204 @@
+//! Identities committed to a secret and proven with zero-knowledge proofs.
+//!
+//! Each identity holds a secret scalar `x` and blinding `r`. Its public
+//! commitment is the Pedersen commitment `x·G + r·H` followed by a BLAKE3
+//! root of the attributes it was created with, and its ID is the hash of
+//! both. An identity proof is a `KnowledgeProof` of `x` and `r` bound to the
+//! commitment and a timestamp, so anyone holding the public tuple can check
+//! it and nobody without the secret can make one. Attributes added later
+//! carry the same kind of proof, bound to the attribute's name and value.
+//! `verify_identity` takes each identity proof once: the proof must be newer
+//! than the last one accepted and within `PROOF_WINDOW_SECS` of the clock.
+//! Predicates over committed attribute values are proven in `predicate`,
+//! either interactively or as a standalone `AttributeProof` that anyone can
+//! check without the registry.
+
 use crate::math::precision::PreciseFloat;
 use std::collections::HashMap;
+use num_traits::ToPrimitive;
+use serde::{Serialize, Deserialize};
+use crate::blockchain::types::hex_serde;
+use crate::storage::cache::{CacheStats, ReadCache};
+use crate::clock::{self, SharedClock};
+use crate::crypto::pedersen::{self, KnowledgeProof};
+use curve25519_dalek::scalar::Scalar;
+use crate::ids::IdentityId;
+use crate::params::{ParamKey, ParamsRegistry};
+use super::attestation::{self, Attestation, AttestationGraph, Neighborhood, Revocation, SIGNING_KEY_ATTRIBUTE};
+use super::predicate::{self, AttributeProof, Predicate};
+
+const TRUST_SCORE_CACHE_ENTRIES: usize = 10_000;
+
+const TRANSCRIPT_TAG: &[u8] = b"QMV-IDENTITY1";
+
+/// How far an identity proof's timestamp may be from the verifier's clock
+pub const PROOF_WINDOW_SECS: u64 = 300;
 
 /// Tuple-based Zero-Knowledge Identity System
 pub struct ZKIdentity {
     precision: u8,
     identities: HashMap<IdentityId, IdentityTuple>,
     trust_registry: HashMap<IdentityId, TrustScore>,
+    /// Timestamp of the last identity proof accepted for each identity;
+    /// proofs no newer than it are replays
+    accepted_proofs: HashMap<IdentityId, u64>,
     verification_threshold: PreciseFloat,
+    /// Computed trust scores; entries are dropped when the underlying score changes
+    score_cache: ReadCache<IdentityId, Option<PreciseFloat>>,
+    /// Web-of-trust vouches between identities
+    attestations: AttestationGraph,
+    clock: SharedClock,
 }
 
-type IdentityId = [u8; 32];
 
-#[derive(Clone)]
+/// Identity with its public commitment and proof.
+/// The private tuple is never serialized and is redacted from `Debug` output.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct IdentityTuple {
     public_tuple: PublicTuple,
+    #[serde(skip)]
     private_tuple: PrivateTuple,
     proof: ZKProof,
 }
 
-#[derive(Clone)]
-struct PublicTuple {
+impl std::fmt::Debug for IdentityTuple {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IdentityTuple")
+            .field("public_tuple", &self.public_tuple)
+            .field("private_tuple", &"<redacted>")
+            .field("proof", &self.proof)
+            .finish()
+    }
+}
+
+impl IdentityTuple {
+    pub fn public_tuple(&self) -> &PublicTuple {
+        &self.public_tuple
+    }
+
+    pub fn proof(&self) -> &ZKProof {
+        &self.proof
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublicTuple {
+    #[serde(with = "hex_serde")]
     commitment: [u8; 64],
     attributes: Vec<AttributeTuple>,
     timestamp: u64,
 }
 
-#[derive(Clone)]
+impl PublicTuple {
+    /// Pedersen commitment to the identity secret, then the root of the
+    /// attributes the identity was created with
+    pub fn commitment(&self) -> &[u8; 64] {
+        &self.commitment
+    }
+
+    /// Check a proof that the prover holds this identity's secret. Needs
+    /// only the public tuple, so any third party can check it.
+    pub fn verify(&self, proof: &ZKProof) -> Result<(), &'static str> {
+        if proof.timestamp < self.timestamp {
+            return Err("Proof predates the identity");
+        }
+        self.verify_knowledge(proof, &identity_transcript(&self.commitment, proof.timestamp))
+    }
+
+    /// Check that an attribute was added by the holder of this identity's
+    /// secret
+    pub fn verify_attribute(&self, attribute: &AttributeTuple) -> Result<(), &'static str> {
+        verify_attribute_of(&self.commitment, attribute)
+    }
+
+    fn verify_knowledge(&self, proof: &ZKProof, transcript: &[u8]) -> Result<(), &'static str> {
+        verify_knowledge(&self.commitment, proof, transcript)
+    }
+
+    pub fn attributes(&self) -> &[AttributeTuple] {
+        &self.attributes
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+}
+
+/// Openings of the identity commitment and of committed attributes. Left
+/// empty on a tuple deserialized without them, which cannot prove anything.
+#[derive(Clone, Default)]
 struct PrivateTuple {
-    secret_key: [u8; 32],
-    recovery_data: Vec<u8>,
-    entropy_seed: [u8; 16],
+    secret_key: Scalar,
+    blinding: Scalar,
+    /// Value and blinding of the latest committed attribute of each name
+    attribute_openings: HashMap<String, (u64, Scalar)>,
 }
 
-#[derive(Clone)]
+impl PrivateTuple {
+    fn prove(&self, commitment: &[u8; 64], transcript: &[u8], timestamp: u64) -> Result<ZKProof, &'static str> {
+        if self.secret_key == Scalar::ZERO {
+            return Err("Identity secret not available");
+        }
+        let proof = KnowledgeProof::prove(&self.secret_key, &self.blinding, transcript);
+        Ok(ZKProof { proof_data: proof.to_bytes(), verification_key: *commitment, timestamp })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AttributeTuple {
     name: String,
+    #[serde(with = "hex_serde")]
     value: Vec<u8>,
     proof: ZKProof,
 }
 
-#[derive(Clone)]
+impl AttributeTuple {
+    pub fn new(name: impl Into<String>, value: Vec<u8>, proof: ZKProof) -> Self {
+        Self { name: name.into(), value, proof }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &[u8] {
+        &self.value
+    }
+
+    pub fn proof(&self) -> &ZKProof {
+        &self.proof
+    }
+}
+
+/// Knowledge proof of an identity secret, with the commitment it is
+/// checked against as its verification key
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ZKProof {
+    #[serde(with = "hex_serde")]
     proof_data: Vec<u8>,
+    #[serde(with = "hex_serde")]
     verification_key: [u8; 64],
     timestamp: u64,
 }
 
+impl ZKProof {
+    pub fn new(proof_data: Vec<u8>, verification_key: [u8; 64], timestamp: u64) -> Self {
+        Self { proof_data, verification_key, timestamp }
+    }
+
+    pub fn proof_data(&self) -> &[u8] {
+        &self.proof_data
+    }
+
+    pub fn verification_key(&self) -> &[u8; 64] {
+        &self.verification_key
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+}
+
 #[derive(Clone)]
 struct TrustScore {
     base_score: PreciseFloat,
@@ -56,14 +209,33 @@ struct TrustScore {
 
 impl ZKIdentity {
     pub fn new(precision: u8) -> Self {
+        Self::with_clock(precision, clock::system())
+    }
+
+    /// Create an identity system that timestamps tuples and verifications with `clock`
+    pub fn with_clock(precision: u8, clock: SharedClock) -> Self {
         Self {
             precision,
             identities: HashMap::new(),
             trust_registry: HashMap::new(),
-            verification_threshold: PreciseFloat::new(95, 2), // 0.95 threshold
+            accepted_proofs: HashMap::new(),
+            verification_threshold: ParamKey::IdentityVerificationThreshold.default_value(),
+            score_cache: ReadCache::new("trust_scores", TRUST_SCORE_CACHE_ENTRIES),
+            attestations: AttestationGraph::default(),
+            clock,
         }
     }
 
+    /// Pick up the registry's verification threshold as of `height`
+    pub fn apply_params(&mut self, params: &ParamsRegistry, height: u64) {
+        self.verification_threshold = params.get(ParamKey::IdentityVerificationThreshold, height);
+    }
+
+    /// Trust score callers gating on identities should require
+    pub fn verification_threshold(&self) -> &PreciseFloat {
+        &self.verification_threshold
+    }
+
     pub fn create_identity(
         &mut self,
         attributes: Vec<AttributeTuple>
@@ -71,7 +243,7 @@ impl ZKIdentity {
         // Generate identity components
         let private_tuple = self.generate_private_tuple();
         let public_tuple = self.generate_public_tuple(&private_tuple, attributes);
-        let proof = self.generate_identity_proof(&public_tuple, &private_tuple);
+        let proof = self.generate_identity_proof(&public_tuple, &private_tuple)?;
 
         // Create identity tuple
         let identity = IdentityTuple {
@@ -82,22 +254,25 @@ impl ZKIdentity {
 
         // Generate ID and store
         let id = self.generate_identity_id(&identity);
+        if self.identities.contains_key(&id) {
+            return Err("Identity already exists");
+        }
         self.identities.insert(id, identity.clone());
 
         // Initialize trust score
+        self.score_cache.invalidate(&id);
         self.trust_registry.insert(id, TrustScore {
             base_score: PreciseFloat::new(70, 2), // 0.70 initial score
             verification_count: 0,
-            last_verification: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
+            last_verification: self.clock.now_secs(),
             reputation_factor: PreciseFloat::new(100, 2), // 1.0 initial reputation
         });
 
         Ok((id, identity))
     }
 
+    /// Check a proof that the holder of `id` is present and raise its trust
+    /// score. Each proof counts once, and only while it is fresh.
     pub fn verify_identity(
         &mut self,
         id: &IdentityId,
@@ -106,18 +281,24 @@ impl ZKIdentity {
         let identity = self.identities.get(id)
             .ok_or("Identity not found")?;
 
+        if proof.timestamp.abs_diff(self.clock.now_secs()) > PROOF_WINDOW_SECS {
+            return Err("Identity proof is stale");
+        }
+        if self.accepted_proofs.get(id).is_some_and(|&last| proof.timestamp <= last) {
+            return Err("Identity proof already used");
+        }
+
         // Verify proof
-        if !self.verify_proof(proof, &identity.public_tuple) {
+        if identity.public_tuple.verify(proof).is_err() {
             return Ok(false);
         }
+        self.accepted_proofs.insert(*id, proof.timestamp);
 
         // Update trust score
+        self.invalidate_scores(id);
         if let Some(trust_score) = self.trust_registry.get_mut(id) {
             trust_score.verification_count += 1;
-            trust_score.last_verification = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs();
+            trust_score.last_verification = self.clock.now_secs();
 
             // Increase base score with successful verification
             trust_score.base_score = trust_score.base_score
@@ -128,16 +309,72 @@ impl ZKIdentity {
         Ok(true)
     }
 
+    /// Fresh proof that this node holds `id`'s secret
+    pub fn prove_identity(&self, id: &IdentityId) -> Result<ZKProof, &'static str> {
+        let identity = self.identities.get(id).ok_or("Identity not found")?;
+        let commitment = &identity.public_tuple.commitment;
+        let timestamp = self.clock.now_secs();
+        identity.private_tuple.prove(commitment, &identity_transcript(commitment, timestamp), timestamp)
+    }
+
+    /// Attribute for `id` with a proof that its holder added it, to pass
+    /// to `add_attribute`
+    pub fn issue_attribute(&self, id: &IdentityId, name: impl Into<String>, value: Vec<u8>) -> Result<AttributeTuple, &'static str> {
+        let identity = self.identities.get(id).ok_or("Identity not found")?;
+        let name = name.into();
+        let commitment = &identity.public_tuple.commitment;
+        let timestamp = self.clock.now_secs();
+        let proof = identity.private_tuple.prove(commitment, &attribute_transcript(commitment, &name, &value, timestamp), timestamp)?;
+        Ok(AttributeTuple { name, value, proof })
+    }
+
+    /// Add a numeric attribute to `id` as a commitment, keeping its opening
+    /// so the node can later prove predicates over it
+    pub fn add_committed_attribute(&mut self, id: &IdentityId, name: impl Into<String>, value: u64) -> Result<AttributeTuple, &'static str> {
+        let name = name.into();
+        let (commitment, blinding) = predicate::commit_attribute(value);
+        let attribute = self.issue_attribute(id, name.clone(), commitment.to_vec())?;
+        self.add_attribute(id, attribute.clone())?;
+        let identity = self.identities.get_mut(id).ok_or("Identity not found")?;
+        identity.private_tuple.attribute_openings.insert(name, (value, blinding));
+        Ok(attribute)
+    }
+
+    /// Standalone proof that `id`'s committed attribute `attribute_name`
+    /// satisfies `predicate`, without revealing it. Check it with
+    /// `predicate::verify_attribute_proof`.
+    pub fn prove_attribute(&self, id: &IdentityId, attribute_name: &str, predicate: Predicate) -> Result<AttributeProof, &'static str> {
+        if predicate.attribute() != attribute_name {
+            return Err("Predicate is about another attribute");
+        }
+        let identity = self.identities.get(id).ok_or("Identity not found")?;
+        let (value, blinding) = identity.private_tuple.attribute_openings.get(attribute_name)
+            .ok_or("Attribute opening not available")?;
+        let commitment = pedersen::compress(&pedersen::commit(*value, blinding));
+        let attribute = identity.public_tuple.attributes.iter()
+            .rev()
+            .find(|attribute| attribute.name == attribute_name && attribute.value == commitment)
+            .ok_or("Attribute opening not available")?;
+        AttributeProof::prove(
+            identity.public_tuple.commitment,
+            attribute.clone(),
+            predicate,
+            self.clock.now_secs(),
+            *value,
+            blinding,
+        )
+    }
+
     pub fn add_attribute(
         &mut self,
         id: &IdentityId,
         attribute: AttributeTuple
     ) -> Result<(), &'static str> {
         // First verify the proof with immutable reference
-        let private_tuple = self.identities.get(id)
+        let existing = self.identities.get(id)
             .ok_or("Identity not found")?;
 
-        if !self.verify_attribute_proof(&attribute, &private_tuple.private_tuple) {
+        if existing.public_tuple.verify_attribute(&attribute).is_err() {
             return Err("Invalid attribute proof");
         }
 
@@ -150,9 +387,112 @@ impl ZKIdentity {
         Ok(())
     }
 
+    /// Halve the reputation of an identity that vouched for content later
+    /// ruled malicious
+    pub fn report_malicious_endorsement(&mut self, id: &IdentityId) -> Result<(), &'static str> {
+        let trust_score = self.trust_registry.get_mut(id).ok_or("Identity not found")?;
+        self.score_cache.invalidate(id);
+        self.score_cache.invalidate_many(&self.attestations.downstream(id));
+        let reputation = &trust_score.reputation_factor;
+        trust_score.reputation_factor = PreciseFloat::new(reputation.value / 2, reputation.scale);
+        Ok(())
+    }
+
+    /// Record a signed vouch from one identity for another, replacing any
+    /// older one between the same pair. The attester must carry a
+    /// `signing_key` attribute holding its ed25519 verifying key.
+    pub fn attest(&mut self, attestation: Attestation, network_id: u64) -> Result<(), &'static str> {
+        let attester = self.identities.get(&attestation.attester).ok_or("Attester not found")?;
+        if !self.identities.contains_key(&attestation.subject) {
+            return Err("Subject not found");
+        }
+        self.attestations.check(&attestation)?;
+        let key = Self::signing_key(attester)?;
+        attestation::verify_signature(key, &attestation.signing_bytes(network_id), &attestation.signature)?;
+
+        let subject = attestation.subject;
+        self.attestations.insert(attestation);
+        self.invalidate_scores(&subject);
+        Ok(())
+    }
+
+    /// Withdraw a vouch. Returns whether a current attestation was removed;
+    /// either way, attestations issued up to the revocation time are refused.
+    pub fn revoke_attestation(&mut self, revocation: &Revocation, network_id: u64) -> Result<bool, &'static str> {
+        let attester = self.identities.get(&revocation.attester).ok_or("Attester not found")?;
+        let key = Self::signing_key(attester)?;
+        attestation::verify_signature(key, &revocation.signing_bytes(network_id), &revocation.signature)?;
+
+        let removed = self.attestations.revoke(revocation)?.is_some();
+        if removed {
+            self.invalidate_scores(&revocation.subject);
+        }
+        Ok(removed)
+    }
+
+    /// Identities and attestations within `depth` hops of `id`
+    pub fn attestation_neighborhood(&self, id: &IdentityId, depth: usize) -> Result<Neighborhood, &'static str> {
+        if !self.identities.contains_key(id) {
+            return Err("Identity not found");
+        }
+        Ok(self.attestations.neighborhood(id, depth))
+    }
+
+    pub fn get_identity(&self, id: &IdentityId) -> Option<&IdentityTuple> {
+        self.identities.get(id)
+    }
+
+    /// IDs of every registered identity
+    pub fn identity_ids(&self) -> impl Iterator<Item = &IdentityId> {
+        self.identities.keys()
+    }
+
     pub fn get_trust_score(&self, id: &IdentityId) -> Result<PreciseFloat, &'static str> {
-        let trust_score = self.trust_registry.get(id)
-            .ok_or("Identity not found")?;
+        self.score_cache
+            .get_or_load(id, || self.compute_trust_score(id))
+            .ok_or("Identity not found")
+    }
+
+    pub fn trust_cache_stats(&self) -> CacheStats {
+        self.score_cache.stats()
+    }
+
+    /// Key `id` signs with, from its `signing_key` attribute
+    pub fn signing_key_of(&self, id: &IdentityId) -> Result<&[u8], &'static str> {
+        let identity = self.identities.get(id).ok_or("Identity not found")?;
+        Self::signing_key(identity)
+    }
+
+    fn signing_key(identity: &IdentityTuple) -> Result<&[u8], &'static str> {
+        identity.public_tuple.attributes.iter()
+            .find(|attribute| attribute.name() == SIGNING_KEY_ATTRIBUTE)
+            .map(|attribute| attribute.value())
+            .ok_or("Attester has no signing key")
+    }
+
+    /// Drop cached scores of `id` and of everyone it vouches for, directly
+    /// or transitively
+    fn invalidate_scores(&self, id: &IdentityId) {
+        self.score_cache.invalidate(id);
+        self.score_cache.invalidate_many(&self.attestations.downstream(id));
+    }
+
+    /// Own score, raised by vouches from trusted identities. Vouches close
+    /// part of the gap to 100, damped at each hop (see `AttestationGraph`).
+    fn compute_trust_score(&self, id: &IdentityId) -> Option<PreciseFloat> {
+        let own = self.compute_own_score(id)?;
+        if self.attestations.attestations_for(id).next().is_none() {
+            return Some(own);
+        }
+        let standing = |id: &IdentityId| {
+            self.compute_own_score(id).and_then(|score| score.to_f64()).unwrap_or(0.0) / 100.0
+        };
+        let trust = self.attestations.transitive_trust(id, &standing);
+        Some(PreciseFloat::from_f64(trust * 100.0, 2))
+    }
+
+    fn compute_own_score(&self, id: &IdentityId) -> Option<PreciseFloat> {
+        let trust_score = self.trust_registry.get(id)?;
 
         // Calculate final trust score
         let base = trust_score.base_score
@@ -166,64 +506,228 @@ impl ZKIdentity {
         let reputation = trust_score.reputation_factor
             .mul(&PreciseFloat::new(20, 2)); // 0.20 weight
 
-        Ok(base.add(&verification_factor).add(&reputation)
+        Some(base.add(&verification_factor).add(&reputation)
             .div(&PreciseFloat::new(100, 2))) // Normalize
     }
 
     fn generate_private_tuple(&self) -> PrivateTuple {
-        // In a real implementation, this would generate secure random values
         PrivateTuple {
-            secret_key: [0u8; 32],
-            recovery_data: Vec::new(),
-            entropy_seed: [0u8; 16],
+            secret_key: pedersen::random_scalar(),
+            blinding: pedersen::random_scalar(),
+            attribute_openings: HashMap::new(),
         }
     }
 
     fn generate_public_tuple(
         &self,
-        _private: &PrivateTuple,
+        private: &PrivateTuple,
         attributes: Vec<AttributeTuple>
     ) -> PublicTuple {
-        // In a real implementation, this would use the private tuple to generate commitments
+        let mut commitment = [0u8; 64];
+        commitment[..32].copy_from_slice(&pedersen::compress(&pedersen::commit_scalar(&private.secret_key, &private.blinding)));
+        commitment[32..].copy_from_slice(&attributes_root(&attributes));
         PublicTuple {
-            commitment: [0u8; 64],
+            commitment,
             attributes,
-            timestamp: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
+            timestamp: self.clock.now_secs(),
         }
     }
 
     fn generate_identity_proof(
         &self,
         public: &PublicTuple,
-        _private: &PrivateTuple
-    ) -> ZKProof {
-        // In a real implementation, this would generate a ZK proof
-        ZKProof {
-            proof_data: Vec::new(),
-            verification_key: [0u8; 64],
-            timestamp: public.timestamp,
-        }
+        private: &PrivateTuple
+    ) -> Result<ZKProof, &'static str> {
+        private.prove(&public.commitment, &identity_transcript(&public.commitment, public.timestamp), public.timestamp)
     }
 
     fn generate_identity_id(&self, identity: &IdentityTuple) -> IdentityId {
-        // In a real implementation, this would use a cryptographic hash
-        let mut id = [0u8; 32];
-        id[0..8].copy_from_slice(&identity.public_tuple.timestamp.to_be_bytes());
-        id
+        identity_id(&identity.public_tuple.commitment)
+    }
+}
+
+/// ID of the identity with `commitment`: its BLAKE3 hash, which is unique
+/// to the secret
+pub fn identity_id(commitment: &[u8; 64]) -> IdentityId {
+    IdentityId::new(blake3::hash(commitment).into())
+}
+
+/// Check that an attribute was added by the holder of the identity with
+/// `commitment`
+pub(crate) fn verify_attribute_of(commitment: &[u8; 64], attribute: &AttributeTuple) -> Result<(), &'static str> {
+    let transcript = attribute_transcript(commitment, &attribute.name, &attribute.value, attribute.proof.timestamp);
+    verify_knowledge(commitment, &attribute.proof, &transcript)
+}
+
+fn verify_knowledge(commitment: &[u8; 64], proof: &ZKProof, transcript: &[u8]) -> Result<(), &'static str> {
+    if proof.verification_key != *commitment {
+        return Err("Proof is for another identity");
+    }
+    let secret_commitment: [u8; 32] = commitment[..32].try_into().expect("32-byte half");
+    let point = pedersen::decompress(&secret_commitment).map_err(|_| "Identity commitment is not a valid point")?;
+    KnowledgeProof::from_bytes(&proof.proof_data)?.verify(&point, transcript)
+}
+
+fn identity_transcript(commitment: &[u8; 64], timestamp: u64) -> Vec<u8> {
+    [TRANSCRIPT_TAG, b"identity", commitment.as_slice(), &timestamp.to_be_bytes()].concat()
+}
+
+fn attribute_transcript(commitment: &[u8; 64], name: &str, value: &[u8], timestamp: u64) -> Vec<u8> {
+    let mut transcript = [TRANSCRIPT_TAG, b"attribute", commitment.as_slice(), &timestamp.to_be_bytes()].concat();
+    for part in [name.as_bytes(), value] {
+        transcript.extend_from_slice(&(part.len() as u64).to_be_bytes());
+        transcript.extend_from_slice(part);
+    }
+    transcript
+}
+
+/// Root of the attributes an identity is created with
+fn attributes_root(attributes: &[AttributeTuple]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    for attribute in attributes {
+        for part in [attribute.name.as_bytes(), &attribute.value] {
+            hasher.update(&(part.len() as u64).to_be_bytes());
+            hasher.update(part);
+        }
+    }
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identity_serde_omits_private_tuple() {
+        let mut identity = ZKIdentity::new(20);
+        let (id, tuple) = identity.create_identity(vec![]).unwrap();
+        assert!(identity.get_identity(&id).is_some());
+        assert!(format!("{:?}", tuple).contains("<redacted>"));
+
+        let json = serde_json::to_value(&tuple).unwrap();
+        assert!(json.get("private_tuple").is_none());
+        assert!(json["public_tuple"]["commitment"].as_str().unwrap().starts_with("0x"));
+
+        let restored: IdentityTuple = serde_json::from_value(json).unwrap();
+        assert_eq!(restored.public_tuple().timestamp(), tuple.public_tuple().timestamp());
+    }
+
+    #[test]
+    fn test_identity_and_attribute_proofs() {
+        use crate::clock::MockClock;
+
+        let clock = MockClock::new(1_000);
+        let mut identity = ZKIdentity::with_clock(20, clock.clone());
+        let email = AttributeTuple::new("email_hash", vec![5; 32], ZKProof::new(vec![], [0; 64], 0));
+        let (alice, tuple) = identity.create_identity(vec![email]).unwrap();
+        let (bob, _) = identity.create_identity(vec![]).unwrap();
+        assert_ne!(alice, bob);
+
+        // Anyone with the public tuple can check the proofs
+        let public = tuple.public_tuple().clone();
+        public.verify(tuple.proof()).unwrap();
+        clock.advance(std::time::Duration::from_secs(60));
+        let fresh = identity.prove_identity(&alice).unwrap();
+        assert_eq!(fresh.timestamp(), 1_060);
+        public.verify(&fresh).unwrap();
+        assert!(identity.verify_identity(&alice, &fresh).unwrap());
+        assert!(!identity.verify_identity(&bob, &fresh).unwrap());
+
+        // Each proof raises the trust score once, and only while fresh
+        let score = identity.get_trust_score(&alice).unwrap();
+        assert_eq!(identity.verify_identity(&alice, &fresh), Err("Identity proof already used"));
+        assert_eq!(identity.verify_identity(&alice, tuple.proof()), Err("Identity proof already used"));
+        assert_eq!(identity.get_trust_score(&alice).unwrap(), score);
+        clock.advance(std::time::Duration::from_secs(1));
+        let next = identity.prove_identity(&alice).unwrap();
+        clock.advance(std::time::Duration::from_secs(PROOF_WINDOW_SECS + 1));
+        assert_eq!(identity.verify_identity(&alice, &next), Err("Identity proof is stale"));
+
+        // A proof cannot be moved to another time or forged without the secret
+        let restamped = ZKProof::new(fresh.proof_data().to_vec(), *fresh.verification_key(), 1_061);
+        assert!(public.verify(&restamped).is_err());
+        let forged = ZKProof::new(vec![1; KnowledgeProof::BYTES], *public.commitment(), 1_060);
+        assert!(public.verify(&forged).is_err());
+        let restored: IdentityTuple = serde_json::from_value(serde_json::to_value(&tuple).unwrap()).unwrap();
+        assert_eq!(restored.private_tuple.prove(public.commitment(), b"", 0).unwrap_err(), "Identity secret not available");
+
+        // Attributes are added only with a proof from the holder
+        let age = identity.issue_attribute(&alice, "age", vec![30]).unwrap();
+        public.verify_attribute(&age).unwrap();
+        let altered = AttributeTuple::new("age", vec![31], age.proof().clone());
+        assert_eq!(identity.add_attribute(&alice, altered), Err("Invalid attribute proof"));
+        assert_eq!(identity.add_attribute(&bob, age.clone()), Err("Invalid attribute proof"));
+        identity.add_attribute(&alice, age).unwrap();
+        assert_eq!(identity.get_identity(&alice).unwrap().public_tuple().attributes().len(), 2);
     }
 
-    fn verify_proof(&self, _proof: &ZKProof, _public: &PublicTuple) -> bool {
-        // In a real implementation, this would verify the ZK proof
-        let verification_score = PreciseFloat::new(98, 2); // 0.98
-        verification_score.value >= self.verification_threshold.value
+    #[test]
+    fn test_standalone_attribute_proofs() {
+        use crate::clock::MockClock;
+
+        let mut identity = ZKIdentity::with_clock(20, MockClock::new(1_000));
+        let (alice, _) = identity.create_identity(vec![]).unwrap();
+        let (bob, _) = identity.create_identity(vec![]).unwrap();
+        identity.add_committed_attribute(&alice, "age", 30).unwrap();
+        let adult = Predicate::AtLeast { attribute: "age".to_string(), value: 18 };
+
+        // Checked from the proof alone, after a round trip through JSON
+        let proof = identity.prove_attribute(&alice, "age", adult.clone()).unwrap();
+        let received: AttributeProof = serde_json::from_value(serde_json::to_value(&proof).unwrap()).unwrap();
+        assert_eq!(predicate::verify_attribute_proof(&received), Ok(alice));
+        assert_eq!(received.issued_at, 1_000);
+
+        let senior = Predicate::AtLeast { attribute: "age".to_string(), value: 65 };
+        assert!(identity.prove_attribute(&alice, "age", senior.clone()).is_err());
+        assert_eq!(identity.prove_attribute(&alice, "height", adult.clone()).unwrap_err(), "Predicate is about another attribute");
+        assert!(identity.prove_attribute(&bob, "age", adult).is_err());
+
+        // Claims the proof was not made for do not verify
+        let mut stronger = proof.clone();
+        stronger.predicate = senior;
+        assert!(predicate::verify_attribute_proof(&stronger).is_err());
+        let mut later = proof.clone();
+        later.issued_at += 60;
+        assert!(predicate::verify_attribute_proof(&later).is_err());
+        let mut moved = proof;
+        moved.identity_commitment = *identity.get_identity(&bob).unwrap().public_tuple().commitment();
+        assert_eq!(predicate::verify_attribute_proof(&moved), Err("Proof is for another identity"));
+
+        let exact = Predicate::Equals { attribute: "age".to_string(), value: 30 };
+        let proof = identity.prove_attribute(&alice, "age", exact).unwrap();
+        assert_eq!(predicate::verify_attribute_proof(&proof), Ok(alice));
     }
 
-    fn verify_attribute_proof(&self, _attribute: &AttributeTuple, _private: &PrivateTuple) -> bool {
-        // In a real implementation, this would verify the attribute's ZK proof
-        let verification_score = PreciseFloat::new(98, 2); // 0.98
-        verification_score.value >= self.verification_threshold.value
+    #[test]
+    fn test_signed_attestations_raise_trust() {
+        use ed25519_dalek::{Signer, SigningKey};
+        use crate::clock::MockClock;
+
+        let clock = MockClock::new(1_000);
+        let mut identity = ZKIdentity::with_clock(20, clock.clone());
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let proof = ZKProof::new(vec![], [0; 64], 0);
+        let signing_key = AttributeTuple::new(SIGNING_KEY_ATTRIBUTE, key.verifying_key().to_bytes().to_vec(), proof);
+        let (alice, _) = identity.create_identity(vec![signing_key]).unwrap();
+        clock.advance(std::time::Duration::from_secs(1));
+        let (bob, _) = identity.create_identity(vec![]).unwrap();
+        let own = identity.get_trust_score(&bob).unwrap();
+
+        let mut vouch = Attestation { attester: alice, subject: bob, weight: 5_000, issued_at: 10, signature: vec![] };
+        vouch.signature = key.sign(&vouch.signing_bytes(1)).to_bytes().to_vec();
+        assert_eq!(identity.attest(vouch.clone(), 2), Err("Invalid attestation signature"));
+        identity.attest(vouch, 1).unwrap();
+        let vouched = identity.get_trust_score(&bob).unwrap();
+        assert!(vouched.to_f64().unwrap() > own.to_f64().unwrap());
+        assert_eq!(identity.attestation_neighborhood(&bob, 1).unwrap().identities, vec![bob, alice]);
+
+        // Bob has no signing key, so cannot vouch back
+        let unsigned = Attestation { attester: bob, subject: alice, weight: 5_000, issued_at: 10, signature: vec![0; 64] };
+        assert_eq!(identity.attest(unsigned, 1), Err("Attester has no signing key"));
+
+        let mut revocation = Revocation { attester: alice, subject: bob, revoked_at: 11, signature: vec![] };
+        revocation.signature = key.sign(&revocation.signing_bytes(1)).to_bytes().to_vec();
+        assert_eq!(identity.revoke_attestation(&revocation, 1), Ok(true));
+        assert_eq!(identity.get_trust_score(&bob).unwrap(), own);
     }
 }
@@ -1,6 +1,7 @@
 use crate::security::quantum_resistant::QuantumSecurity;
 use crate::math::precision::PreciseFloat;
 use std::collections::HashMap;
+use crate::clock::{self, SharedClock};
 
 /// FOA (First Order Agreement) Layer
 /// Smart contract deployment and execution layer with quantum-resistant validation
@@ -9,6 +10,7 @@ pub struct FOALayer {
     security: QuantumSecurity,
     state: HashMap<[u8; 32], ContractState>,
     precision: u8,
+    clock: SharedClock,
 }
 
 pub struct SmartContract {
@@ -36,11 +38,17 @@ pub struct ContractExecution {
 
 impl FOALayer {
     pub fn new(precision: u8) -> Self {
+        Self::with_clock(precision, clock::system())
+    }
+
+    /// Create a layer that stamps deployments and state updates with `clock`
+    pub fn with_clock(precision: u8, clock: SharedClock) -> Self {
         Self {
             contracts: HashMap::new(),
             security: QuantumSecurity::new(precision),
             state: HashMap::new(),
             precision,
+            clock,
         }
     }
 
@@ -58,10 +66,7 @@ impl FOALayer {
             code: code.to_vec(),
             owner,
             quantum_signature,
-            creation_time: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
+            creation_time: self.clock.now_secs(),
             last_execution: 0,
         };
         
@@ -82,6 +87,7 @@ impl FOALayer {
 
     /// Execute a smart contract
     pub fn execute_contract(&mut self, contract_id: &[u8; 32], input: &[u8]) -> Result<ContractExecution, &'static str> {
+        let now = self.clock.now_secs();
         let contract = self.contracts.get_mut(contract_id)
             .ok_or("Contract not found")?;
             
@@ -98,10 +104,7 @@ impl FOALayer {
         // Update state
         state.data = result.clone();
         state.version += 1;
-        state.last_update = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+        state.last_update = now;
             
         // Update contract
         contract.last_execution = state.last_update;
@@ -1,21 +1,27 @@
-use blake3;
+use crate::crypto::transition::{transition, GENESIS_TALLY};
+#[cfg(feature = "web2")]
 use crate::web2::{Web2Runner, Web2AppConfig, Web2AppResult};
 
+/// Transition to verify: (state, operation, proof, expected_hash)
+pub type TransitionItem<'a> = (&'a [u8], &'a [u8], &'a [u8], [u8; 32]);
+
 /// L0 - Tally Layer
 /// Fundamental computation layer that handles quantum state transitions
 pub struct TallyLayer {
     current_hash: [u8; 32],
     previous_hash: [u8; 32],
     operation_count: u64,
+    #[cfg(feature = "web2")]
     web2_runner: Web2Runner,
 }
 
 impl TallyLayer {
     pub fn new() -> Self {
         Self {
-            current_hash: [0u8; 32],
-            previous_hash: [0u8; 32],
+            current_hash: GENESIS_TALLY,
+            previous_hash: GENESIS_TALLY,
             operation_count: 0,
+            #[cfg(feature = "web2")]
             web2_runner: Web2Runner::new(),
         }
     }
@@ -23,97 +29,26 @@ impl TallyLayer {
     /// Computes quantum state transition:
     /// T(i) = H(S(i) ⊕ O(i)) ⊗ P(i)
     pub fn compute_state_transition(&mut self, state: &[u8], operation: &[u8], proof: &[u8]) -> Result<[u8; 32], &'static str> {
-        if state.is_empty() || operation.is_empty() || proof.is_empty() {
-            return Err("Empty input state, operation, or proof");
-        }
-
-        // Save current state for verification
+        let hash = transition(&self.current_hash, state, operation, proof)?;
         self.previous_hash = self.current_hash;
-        
-        // Hash the input state first
-        let state_hash = blake3::hash(state);
-        let state_hash_bytes = state_hash.as_bytes();
-        
-        // For the first transition, use hashed state
-        // For subsequent transitions, combine with current hash
-        let state_xor: Vec<u8> = if self.operation_count == 0 {
-            state_hash_bytes.to_vec()
-        } else {
-            self.previous_hash
-                .iter()
-                .zip(state_hash_bytes.iter())
-                .map(|(&a, &b)| a ^ b)
-                .collect()
-        };
-
-        // XOR with operation
-        let state_op_xor: Vec<u8> = state_xor
-            .iter()
-            .zip(operation.iter().cycle())
-            .map(|(&a, &b)| a ^ b)
-            .collect();
-        
-        // Hash the combined state
-        let hash_xor = blake3::hash(&state_op_xor);
-        let hash_xor_bytes = hash_xor.as_bytes();
-
-        // Normalize proof
-        let proof_hash = blake3::hash(proof);
-        let proof_bytes = proof_hash.as_bytes();
-
-        // Final combination with proof
-        let mut final_hash = [0u8; 32];
-        for i in 0..32 {
-            final_hash[i] = hash_xor_bytes[i] ^ proof_bytes[i];
-        }
-
-        // Update state
-        self.current_hash = final_hash;
+        self.current_hash = hash;
         self.operation_count += 1;
-
-        Ok(final_hash)
+        Ok(hash)
     }
 
     /// Verify a state transition
     pub fn verify_transition(&self, state: &[u8], operation: &[u8], proof: &[u8], expected_hash: [u8; 32]) -> bool {
-        if state.is_empty() || operation.is_empty() || proof.is_empty() {
-            return false;
-        }
-
-        // Hash the input state first
-        let state_hash = blake3::hash(state);
-        let state_hash_bytes = state_hash.as_bytes();
-        
-        // For first transition, use hashed state
-        // For subsequent transitions, combine with previous hash
-        let state_xor: Vec<u8> = if self.operation_count == 0 {
-            state_hash_bytes.to_vec()
-        } else {
-            self.previous_hash
-                .iter()
-                .zip(state_hash_bytes.iter())
-                .map(|(&a, &b)| a ^ b)
-                .collect()
-        };
-
-        let state_op_xor: Vec<u8> = state_xor
-            .iter()
-            .zip(operation.iter().cycle())
-            .map(|(&a, &b)| a ^ b)
-            .collect();
-        
-        let hash_xor = blake3::hash(&state_op_xor);
-        let hash_xor_bytes = hash_xor.as_bytes();
-
-        let proof_hash = blake3::hash(proof);
-        let proof_bytes = proof_hash.as_bytes();
-
-        let mut computed_hash = [0u8; 32];
-        for i in 0..32 {
-            computed_hash[i] = hash_xor_bytes[i] ^ proof_bytes[i];
-        }
+        verify_transition_at(&self.previous_hash, state, operation, proof, expected_hash)
+    }
 
-        computed_hash == expected_hash
+    /// Verify many transitions against the current layer state in parallel.
+    /// Results keep input order.
+    pub fn verify_transitions_batch(&self, items: &[TransitionItem]) -> Vec<bool> {
+        // Copy the plain state out so the web2 runner never crosses threads
+        let previous_hash = self.previous_hash;
+        crate::crypto::batch::par_map(items, |(state, operation, proof, expected)| {
+            verify_transition_at(&previous_hash, state, operation, proof, *expected)
+        })
     }
 
     pub fn get_operation_count(&self) -> u64 {
@@ -121,6 +56,7 @@ impl TallyLayer {
     }
 
     /// Run a web2 app and record its proof in the quantum state
+    #[cfg(feature = "web2")]
     pub fn run_web2_app(&mut self, config: Web2AppConfig) -> Result<Web2AppResult, String> {
         // Run the app and get result
         let result = self.web2_runner.run_app(config)?;
@@ -133,6 +69,7 @@ impl TallyLayer {
     }
     
     /// Record web2 app proof in quantum state
+    #[cfg(feature = "web2")]
     fn record_web2_proof(&mut self, result: &Web2AppResult) -> Result<(), &'static str> {
         // Create state data from proof and timestamp
         let mut state_data = Vec::new();
@@ -145,9 +82,21 @@ impl TallyLayer {
     }
 }
 
+/// Transition check against a given previous hash
+fn verify_transition_at(
+    previous_hash: &[u8; 32],
+    state: &[u8],
+    operation: &[u8],
+    proof: &[u8],
+    expected_hash: [u8; 32],
+) -> bool {
+    transition(previous_hash, state, operation, proof) == Ok(expected_hash)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(feature = "web2")]
     use std::collections::HashMap;
 
     #[test]
@@ -168,6 +117,7 @@ mod tests {
         assert_eq!(tally.get_operation_count(), 1);
     }
 
+    #[cfg(feature = "web2")]
     #[test]
     fn test_web2_app_execution() {
         let mut tally = TallyLayer::new();
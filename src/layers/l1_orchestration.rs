@@ -1,5 +1,8 @@
 use crate::layers::l0_tally::TallyLayer;
 use crate::security::quantum_resistant::QuantumSecurity;
+use crate::blockchain::types::Address;
+use crate::blockchain::assets::Assets;
+use crate::orchestration::spatial::{Region, SpatialIndex};
 
 /// L1 - Orchestration Layer
 /// Handles governance rules and physics enforcement
@@ -8,6 +11,11 @@ pub struct OrchestrationLayer {
     security: QuantumSecurity,
     physics_rules: Vec<PhysicsRule>,
     governance_rules: Vec<GovernanceRule>,
+    write_rules: Vec<WriteRule>,
+    /// Placed objects, for collision and proximity checks
+    objects: SpatialIndex<[u8; 32]>,
+    /// Minted parcels, mirrored from chain state
+    parcels: SpatialIndex<[u8; 32]>,
 }
 
 pub struct PhysicsRule {
@@ -22,6 +30,15 @@ pub struct GovernanceRule {
     validator: Box<dyn Fn(&[u8]) -> bool + Send + Sync>,
 }
 
+/// Who may change a world object such as a parcel: (writer, object) -> allowed
+pub type WriteCheck = Box<dyn Fn(&Address, &[u8; 32]) -> bool + Send + Sync>;
+
+/// Write check registered under the hash of its name
+pub struct WriteRule {
+    id: [u8; 32],
+    allows: WriteCheck,
+}
+
 impl OrchestrationLayer {
     pub fn new(precision: u8) -> Self {
         Self {
@@ -29,6 +46,9 @@ impl OrchestrationLayer {
             security: QuantumSecurity::new(precision),
             physics_rules: Vec::new(),
             governance_rules: Vec::new(),
+            write_rules: Vec::new(),
+            objects: SpatialIndex::new(Region::world()),
+            parcels: SpatialIndex::new(Region::world()),
         }
     }
 
@@ -54,6 +74,93 @@ impl OrchestrationLayer {
         id
     }
 
+    /// Add a write rule, replacing any rule of the same name. Rules built
+    /// from chain state, like parcel leases, are re-added after each block.
+    pub fn add_write_rule(&mut self, name: &str, allows: WriteCheck) -> [u8; 32] {
+        let id = blake3::hash(name.as_bytes()).into();
+        self.write_rules.retain(|rule| rule.id != id);
+        self.write_rules.push(WriteRule { id, allows });
+        id
+    }
+
+    /// Process a transition that changes `target` on behalf of `writer`
+    pub fn process_write(
+        &mut self,
+        writer: &Address,
+        target: &[u8; 32],
+        state: &[u8],
+        operation: &[u8],
+        proof: &[u8],
+    ) -> Result<[u8; 32], &'static str> {
+        if !self.write_rules.iter().all(|rule| (rule.allows)(writer, target)) {
+            return Err("write rights validation failed");
+        }
+        self.process_transition(state, operation, proof)
+    }
+
+    /// Place `object` at `region` on behalf of `writer`, moving it if already
+    /// placed. The writer needs write rights to the object and to every parcel
+    /// the region touches, and the region may not overlap another object.
+    pub fn process_placement(
+        &mut self,
+        writer: &Address,
+        object: &[u8; 32],
+        region: Region,
+        operation: &[u8],
+        proof: &[u8],
+    ) -> Result<[u8; 32], &'static str> {
+        self.check_placement(writer, object, &region)?;
+
+        let mut state = object.to_vec();
+        for bound in region.min.iter().chain(region.max.iter()) {
+            state.extend_from_slice(&bound.to_be_bytes());
+        }
+        let state_id = self.process_transition(&state, operation, proof)?;
+        self.objects.insert(*object, region)?;
+        Ok(state_id)
+    }
+
+    /// Check that `process_placement` would accept the placement, without
+    /// applying it
+    pub fn check_placement(&self, writer: &Address, object: &[u8; 32], region: &Region) -> Result<(), &'static str> {
+        let touched = self.parcels.query(region);
+        let allowed = std::iter::once(object).chain(touched.iter())
+            .all(|target| self.write_rules.iter().all(|rule| (rule.allows)(writer, target)));
+        if !allowed {
+            return Err("write rights validation failed");
+        }
+        if !self.objects.collisions(region, Some(object)).is_empty() {
+            return Err("placement collides with another object");
+        }
+        if !self.objects.bounds().contains(region) {
+            return Err("Region lies outside the indexed world");
+        }
+        Ok(())
+    }
+
+    pub fn remove_object(&mut self, object: &[u8; 32]) -> Option<Region> {
+        self.objects.remove(object)
+    }
+
+    /// Placed objects within `radius` of an observer at `center`, nearest
+    /// first; what the observer should be kept up to date about
+    pub fn interest(&self, center: [i64; 3], radius: i64) -> Vec<[u8; 32]> {
+        self.objects.within(center, radius).into_iter().map(|(object, _)| object).collect()
+    }
+
+    /// Bring the parcel index in line with chain state; call after each block
+    pub fn sync_parcels(&mut self, assets: &Assets) -> usize {
+        self.parcels.sync_parcels(assets)
+    }
+
+    pub fn objects(&self) -> &SpatialIndex<[u8; 32]> {
+        &self.objects
+    }
+
+    pub fn parcels(&self) -> &SpatialIndex<[u8; 32]> {
+        &self.parcels
+    }
+
     /// Process state transition with physics and governance rules
     pub fn process_transition(&mut self, state: &[u8], operation: &[u8], proof: &[u8]) -> Result<[u8; 32], &'static str> {
         // Validate inputs
@@ -173,5 +280,42 @@ mod tests {
         let valid_op2 = b"different_operation";
         let result2 = orchestration.process_transition(valid_state, valid_op2, &valid_proof).unwrap();
         assert_ne!(result1, result2, "Different operations should produce different hashes");
+
+        // Test 6: Write rights; re-adding a rule under its name replaces it
+        orchestration.add_write_rule("parcel_leases", Box::new(|writer: &Address, _: &[u8; 32]| *writer == [1u8; 32]));
+        assert!(orchestration.process_write(&[1u8; 32], &[7u8; 32], valid_state, valid_op, &valid_proof).is_ok());
+        let result = orchestration.process_write(&[2u8; 32], &[7u8; 32], valid_state, valid_op, &valid_proof);
+        assert_eq!(result.unwrap_err(), "write rights validation failed");
+        orchestration.add_write_rule("parcel_leases", Box::new(|writer: &Address, _: &[u8; 32]| *writer == [2u8; 32]));
+        assert!(orchestration.process_write(&[2u8; 32], &[7u8; 32], valid_state, valid_op, &valid_proof).is_ok());
+
+        // Test 7: Placement respects parcel rights and collisions
+        use crate::blockchain::assets::{Asset, AssetKind};
+        let mut assets = Assets::default();
+        assets.restore([9u8; 32], Some(Asset {
+            creator: [2u8; 32],
+            owner: [2u8; 32],
+            kind: AssetKind::Parcel { x: 0, y: 0 },
+            royalty_bps: 0,
+            uri: String::new(),
+            metadata: None,
+        }));
+        assert_eq!(orchestration.sync_parcels(&assets), 1);
+        orchestration.add_write_rule("parcel_leases", Box::new(|writer: &Address, target: &[u8; 32]| {
+            *target != [9u8; 32] || *writer == [2u8; 32]
+        }));
+
+        let table = Region::new([1, 1, 0], [3, 3, 1]).unwrap();
+        let result = orchestration.process_placement(&[3u8; 32], &[20u8; 32], table, valid_op, &valid_proof);
+        assert_eq!(result.unwrap_err(), "write rights validation failed");
+        assert!(orchestration.process_placement(&[2u8; 32], &[20u8; 32], table, valid_op, &valid_proof).is_ok());
+
+        let chair = Region::new([2, 2, 0], [4, 4, 1]).unwrap();
+        let result = orchestration.process_placement(&[2u8; 32], &[21u8; 32], chair, valid_op, &valid_proof);
+        assert_eq!(result.unwrap_err(), "placement collides with another object");
+        // Moving an object never collides with itself
+        assert!(orchestration.process_placement(&[2u8; 32], &[20u8; 32], chair, valid_op, &valid_proof).is_ok());
+        assert_eq!(orchestration.interest([0, 0, 0], 4), vec![[20u8; 32]]);
+        assert!(orchestration.interest([100, 100, 0], 4).is_empty());
     }
 }
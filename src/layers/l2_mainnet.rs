@@ -1,36 +1,167 @@
 use crate::layers::l1_orchestration::OrchestrationLayer;
 use crate::blockchain::core::Block;
 use crate::math::precision::PreciseFloat;
+use crate::layers::validator_set::{BlockSignature, ValidatorSet};
+use crate::economics::models::EconomicModel;
+use crate::epoch::{EpochSchedule, ValidatorRotation};
+use crate::crypto::domain::{PayloadKind, SigningDomain, MAINNET_NETWORK_ID};
+use crate::consensus::{Commit, ConsensusEngine, Electorate, Value};
+use crate::config::NodeMode;
 use std::collections::HashMap;
 
+/// Blocks between checkpoints that pruned nodes always retain
+pub const CHECKPOINT_INTERVAL: u64 = 1000;
+
+/// Validators rotated into the active set at each epoch boundary
+pub const MAX_ACTIVE_VALIDATORS: usize = 100;
+
+/// Error returned for historical queries a pruned node can no longer answer
+pub const PRUNED: &str = "pruned";
+
+/// Retained state commitment at a checkpoint height
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub index: u64,
+    pub block_hash: [u8; 32],
+    pub state_hash: [u8; 32],
+}
+
 /// L2 - Mainnet Layer
 /// Main blockchain network that enforces consensus and maintains the primary ledger
 pub struct MainnetLayer {
     orchestration: OrchestrationLayer,
     blocks: Vec<Block>,
     state: HashMap<[u8; 32], Vec<u8>>,
-    validators: Vec<[u8; 32]>,
+    tally_proofs: HashMap<[u8; 32], Vec<u8>>,
+    /// Quorum signatures each block was accepted with
+    signatures: HashMap<[u8; 32], Vec<BlockSignature>>,
+    /// Consensus commits of blocks decided by BFT consensus
+    commits: HashMap<[u8; 32], Commit>,
+    checkpoints: Vec<Checkpoint>,
+    validators: ValidatorSet,
+    domain: SigningDomain,
+    mode: NodeMode,
+    pruned_height: u64,
     precision: u8,
 }
 
 impl MainnetLayer {
     pub fn new(precision: u8) -> Self {
+        Self::with_mode(precision, NodeMode::Archive)
+    }
+
+    pub fn with_mode(precision: u8, mode: NodeMode) -> Self {
+        Self::for_network(precision, mode, MAINNET_NETWORK_ID, EpochSchedule::default())
+    }
+
+    /// Mainnet of `network_id`; validator signatures from other networks are rejected
+    pub fn for_network(precision: u8, mode: NodeMode, network_id: u64, schedule: EpochSchedule) -> Self {
         Self {
             orchestration: OrchestrationLayer::new(precision),
             blocks: Vec::new(),
             state: HashMap::new(),
-            validators: Vec::new(),
+            tally_proofs: HashMap::new(),
+            signatures: HashMap::new(),
+            commits: HashMap::new(),
+            checkpoints: Vec::new(),
+            validators: ValidatorSet::new(schedule, MAX_ACTIVE_VALIDATORS),
+            domain: SigningDomain::main_chain(network_id),
+            mode,
+            pruned_height: 0,
             precision,
         }
     }
 
-    /// Add a validator to the network
-    pub fn add_validator(&mut self, validator_id: [u8; 32]) {
-        self.validators.push(validator_id);
+    /// Add a validator to the network; it becomes active at the next
+    /// rotation if it has enough stake
+    pub fn add_validator(&mut self, validator_id: [u8; 32]) -> Result<(), &'static str> {
+        self.validators.join(validator_id)
+    }
+
+    /// Remove a validator from the network at the next rotation
+    pub fn remove_validator(&mut self, validator_id: &[u8; 32]) -> Result<(), &'static str> {
+        self.validators.leave(validator_id)
     }
 
-    /// Process and add a new block to the chain
+    pub fn validators(&self) -> &ValidatorSet {
+        &self.validators
+    }
+
+    /// Rotate the active set for the epoch of the next block, by stake
+    pub fn rotate_validators(&mut self, economics: &EconomicModel) -> Result<ValidatorRotation, &'static str> {
+        self.validators.rotate(self.blocks.len() as u64, economics)
+    }
+
+    /// Bytes validators sign to accept `data` as the next block.
+    /// Bound to this network, the next height and the current tip, so a
+    /// signature cannot be replayed elsewhere or on another fork.
+    pub fn block_signing_payload(&self, data: &[u8]) -> Vec<u8> {
+        let mut body = Vec::with_capacity(32 + data.len());
+        body.extend_from_slice(&self.tip());
+        body.extend_from_slice(data);
+        self.domain.payload(PayloadKind::Block, self.blocks.len() as u64, &body)
+    }
+
+    /// Process and add a new block to the chain. Only accepted while no
+    /// validators are active; use `process_signed_block` or
+    /// `process_committed_block` after that.
     pub fn process_block(&mut self, data: &[u8], proof: &[u8]) -> Result<[u8; 32], &'static str> {
+        self.process_signed_block(data, proof, &[])
+    }
+
+    /// Process and add a new block signed by a quorum of the active set
+    pub fn process_signed_block(&mut self, data: &[u8], proof: &[u8], signatures: &[BlockSignature]) -> Result<[u8; 32], &'static str> {
+        if self.validators.rotation_due(self.blocks.len() as u64) {
+            return Err("Validator rotation due");
+        }
+        if self.validators.total_weight() > 0 {
+            self.validators.verify_quorum(&self.block_signing_payload(data), signatures)?;
+        }
+        let hash = self.append_block(data, proof)?;
+        if !signatures.is_empty() {
+            self.signatures.insert(hash, signatures.to_vec());
+        }
+        Ok(hash)
+    }
+
+    /// Consensus value for `data` as the next block
+    pub fn next_value(&self, data: &[u8], proof: &[u8]) -> Value {
+        Value::new(self.tip(), data.to_vec(), proof.to_vec())
+    }
+
+    /// Process and add a block decided by consensus. `engine` checks the
+    /// commit against the active set and the validators' vote keys.
+    pub fn process_committed_block(
+        &mut self,
+        value: &Value,
+        commit: &Commit,
+        vote_keys: &HashMap<[u8; 32], Vec<u8>>,
+        engine: &dyn ConsensusEngine,
+    ) -> Result<[u8; 32], &'static str> {
+        let height = self.blocks.len() as u64;
+        if self.validators.rotation_due(height) {
+            return Err("Validator rotation due");
+        }
+        if commit.height != height {
+            return Err("Commit for another height");
+        }
+        if value.parent != self.tip() {
+            return Err("Block does not extend the chain tip");
+        }
+        if engine.domain() != &self.domain {
+            return Err("Consensus engine signs for another network");
+        }
+        engine.finalize(value, commit, &Electorate::new(&self.validators, vote_keys))?;
+        let hash = self.append_block(&value.data, &value.proof)?;
+        self.commits.insert(hash, commit.clone());
+        Ok(hash)
+    }
+
+    fn tip(&self) -> [u8; 32] {
+        self.blocks.last().map(|block| block.hash).unwrap_or([0u8; 32])
+    }
+
+    fn append_block(&mut self, data: &[u8], proof: &[u8]) -> Result<[u8; 32], &'static str> {
         // Get current state
         let _current_state = self.get_current_state();
         
@@ -54,10 +185,87 @@ impl MainnetLayer {
         
         // Update state
         self.state.insert(hash, data.to_vec());
+        self.tally_proofs.insert(hash, proof.to_vec());
+
+        let index = self.blocks.len() as u64 - 1;
+        if index.is_multiple_of(CHECKPOINT_INTERVAL) {
+            self.checkpoints.push(Checkpoint {
+                index,
+                block_hash: hash,
+                state_hash: blake3::hash(data).into(),
+            });
+        }
+        self.prune();
         
         Ok(hash)
     }
 
+    /// Drop state and tally proofs that fall outside the retention window
+    fn prune(&mut self) {
+        let cutoff = self.mode.earliest_state(self.blocks.len() as u64);
+        while self.pruned_height < cutoff {
+            let index = self.pruned_height;
+            if !index.is_multiple_of(CHECKPOINT_INTERVAL) {
+                let hash = self.blocks[index as usize].hash;
+                self.state.remove(&hash);
+                self.tally_proofs.remove(&hash);
+                self.signatures.remove(&hash);
+                self.commits.remove(&hash);
+            }
+            self.pruned_height += 1;
+        }
+    }
+
+    /// Get the state committed at block `index`
+    pub fn get_state_at(&self, index: u64) -> Result<Vec<u8>, &'static str> {
+        let block = self.blocks.get(index as usize).ok_or("Block not found")?;
+        self.state.get(&block.hash).cloned().ok_or(PRUNED)
+    }
+
+    /// Get the tally proof used to produce the block with `hash`
+    pub fn get_tally_proof(&self, hash: &[u8; 32]) -> Result<&[u8], &'static str> {
+        if self.get_block(hash).is_none() {
+            return Err("Block not found");
+        }
+        self.tally_proofs.get(hash).map(|p| p.as_slice()).ok_or(PRUNED)
+    }
+
+    /// Get the validator signatures the block with `hash` was accepted with;
+    /// empty for blocks accepted before any validators were active
+    pub fn get_block_signatures(&self, hash: &[u8; 32]) -> Result<&[BlockSignature], &'static str> {
+        if self.get_block(hash).is_none() {
+            return Err("Block not found");
+        }
+        if !self.tally_proofs.contains_key(hash) {
+            return Err(PRUNED);
+        }
+        Ok(self.signatures.get(hash).map(Vec::as_slice).unwrap_or_default())
+    }
+
+    /// Get the consensus commit the block with `hash` was decided with
+    pub fn get_commit(&self, hash: &[u8; 32]) -> Result<&Commit, &'static str> {
+        if self.get_block(hash).is_none() {
+            return Err("Block not found");
+        }
+        if !self.tally_proofs.contains_key(hash) {
+            return Err(PRUNED);
+        }
+        self.commits.get(hash).ok_or("Block was not decided by consensus")
+    }
+
+    /// Domain validator signatures and consensus votes are bound to
+    pub fn signing_domain(&self) -> &SigningDomain {
+        &self.domain
+    }
+
+    pub fn mode(&self) -> NodeMode {
+        self.mode
+    }
+
+    pub fn checkpoints(&self) -> &[Checkpoint] {
+        &self.checkpoints
+    }
+
     /// Get the current state of the blockchain
     pub fn get_current_state(&self) -> Vec<u8> {
         if let Some(last_block) = self.blocks.last() {
@@ -90,7 +298,7 @@ mod tests {
 
         // Add validator
         let validator = blake3::hash(b"test_validator").into();
-        mainnet.add_validator(validator);
+        mainnet.add_validator(validator).unwrap();
 
         // Test 1: Valid block processing
         let data = b"test_block_data";
@@ -161,4 +369,122 @@ mod tests {
         assert!(mainnet.get_block(&hash1).is_some(), "Should find block by hash");
         assert!(mainnet.get_block(&[0u8; 32]).is_none(), "Should not find non-existent block");
     }
+
+    #[test]
+    fn test_pruned_mode() {
+        let mut mainnet = MainnetLayer::with_mode(20, NodeMode::Pruned { retain_blocks: 2 });
+        let mut proof: Vec<u8> = (0..32).map(|i| if i % 2 == 0 { 0x55 } else { 0xAA }).collect();
+        proof.extend_from_slice(&[0x55; 32]);
+
+        for i in 0..5u8 {
+            mainnet.process_block(&[i + 1; 8], &proof).unwrap();
+        }
+
+        assert_eq!(mainnet.height(), 5);
+        assert_eq!(mainnet.get_state_at(0).unwrap(), vec![1; 8], "Checkpoint state is retained");
+        assert_eq!(mainnet.get_state_at(1).unwrap_err(), PRUNED);
+        assert_eq!(mainnet.get_state_at(2).unwrap_err(), PRUNED);
+        assert_eq!(mainnet.get_state_at(4).unwrap(), vec![5; 8]);
+        assert_eq!(mainnet.get_state_at(9).unwrap_err(), "Block not found");
+        assert_eq!(mainnet.checkpoints().len(), 1);
+
+        let mut archive = MainnetLayer::new(20);
+        for i in 0..5u8 {
+            archive.process_block(&[i + 1; 8], &proof).unwrap();
+        }
+        assert_eq!(archive.get_state_at(1).unwrap(), vec![2; 8]);
+    }
+
+    #[test]
+    fn test_blocks_need_a_validator_quorum() {
+        use crate::math::precision::PreciseFloat;
+        use ed25519_dalek::{Signer, SigningKey};
+
+        let mut mainnet = MainnetLayer::for_network(20, NodeMode::Archive, MAINNET_NETWORK_ID, EpochSchedule { epoch_blocks: 2, epochs_per_era: 2 });
+        let mut economics = EconomicModel::new(2);
+        let keys: Vec<SigningKey> = (1..=3u8).map(|seed| SigningKey::from_bytes(&[seed; 32])).collect();
+        for (key, tokens) in keys.iter().zip([3000, 2000, 2000]) {
+            let id = key.verifying_key().to_bytes();
+            mainnet.add_validator(id).unwrap();
+            economics.stake_tokens(id, PreciseFloat::new(tokens * 100, 2)).unwrap();
+        }
+        let mut proof: Vec<u8> = (0..32).map(|i| if i % 2 == 0 { 0x55 } else { 0xAA }).collect();
+        proof.extend_from_slice(&[0x55; 32]);
+
+        // Nobody is active yet, so the first block bootstraps unsigned
+        mainnet.process_block(b"genesis", &proof).unwrap();
+        mainnet.rotate_validators(&economics).unwrap();
+        assert_eq!(mainnet.process_block(b"unsigned", &proof).unwrap_err(), "Validator quorum not reached");
+
+        let sign = |mainnet: &MainnetLayer, data: &[u8], signers: &[&SigningKey]| -> Vec<BlockSignature> {
+            let payload = mainnet.block_signing_payload(data);
+            signers.iter()
+                .map(|key| BlockSignature { validator: key.verifying_key().to_bytes(), signature: key.sign(&payload).to_bytes().to_vec() })
+                .collect()
+        };
+        let two = sign(&mainnet, b"block 1", &[&keys[0], &keys[1]]);
+        assert_eq!(mainnet.process_signed_block(b"block 1", &proof, &two[..1]).unwrap_err(), "Validator quorum not reached");
+        // Signatures are bound to the block data
+        assert_eq!(mainnet.process_signed_block(b"block X", &proof, &two).unwrap_err(), "Invalid validator signature");
+        let hash = mainnet.process_signed_block(b"block 1", &proof, &two).unwrap();
+        assert_eq!(mainnet.get_block_signatures(&hash).unwrap(), two.as_slice());
+
+        // Block 2 opens a new epoch and needs a fresh rotation first
+        mainnet.remove_validator(&keys[0].verifying_key().to_bytes()).unwrap();
+        let signed = sign(&mainnet, b"block 2", &[&keys[1], &keys[2]]);
+        assert_eq!(mainnet.process_signed_block(b"block 2", &proof, &signed).unwrap_err(), "Validator rotation due");
+        let rotation = mainnet.rotate_validators(&economics).unwrap();
+        assert_eq!(rotation.removed, vec![keys[0].verifying_key().to_bytes()]);
+        mainnet.process_signed_block(b"block 2", &proof, &signed).unwrap();
+        assert_eq!(mainnet.height(), 3);
+    }
+
+    #[test]
+    fn test_committed_blocks_are_checked_against_the_active_set() {
+        use crate::consensus::{PosEngine, Timeouts, Vote, VoteKind};
+        use crate::math::precision::PreciseFloat;
+        use crate::security::quantum_resistant::QuantumSecurity;
+
+        let mut mainnet = MainnetLayer::new(20);
+        let mut economics = EconomicModel::new(2);
+        let mut security = QuantumSecurity::new(2);
+        let mut vote_keys = HashMap::new();
+        let mut signers = Vec::new();
+        for seed in 1..=4u8 {
+            let (key_id, key) = security.generate_key_pair().unwrap();
+            let id = [seed; 32];
+            mainnet.add_validator(id).unwrap();
+            economics.stake_tokens(id, PreciseFloat::new(200000, 2)).unwrap();
+            vote_keys.insert(id, key.verification_key().to_vec());
+            signers.push((id, key_id));
+        }
+        mainnet.rotate_validators(&economics).unwrap();
+
+        let mut proof: Vec<u8> = (0..32).map(|i| if i % 2 == 0 { 0x55 } else { 0xAA }).collect();
+        proof.extend_from_slice(&[0x55; 32]);
+        let value = mainnet.next_value(b"decided block", &proof);
+        let domain = *mainnet.signing_domain();
+        let commit = |signers: &[([u8; 32], [u8; 32])]| Commit {
+            height: 0,
+            round: 1,
+            value: value.id(),
+            precommits: signers.iter().map(|(id, key_id)| {
+                let mut vote = Vote { kind: VoteKind::Precommit, height: 0, round: 1, value: Some(value.id()), validator: *id, signature: Vec::new() };
+                vote.signature = security.sign(key_id, &vote.signing_bytes(&domain)).unwrap();
+                vote
+            }).collect(),
+        };
+
+        let short = commit(&signers[..2]);
+        let full = commit(&signers[..3]);
+        let engine = PosEngine::new(domain, QuantumSecurity::new(2), None, Timeouts::default(), 2);
+        assert_eq!(mainnet.process_committed_block(&value, &short, &vote_keys, &engine).unwrap_err(), "Validator quorum not reached");
+        let other = mainnet.next_value(b"another block", &proof);
+        assert_eq!(mainnet.process_committed_block(&other, &full, &vote_keys, &engine).unwrap_err(), "Commit for another value");
+
+        let hash = mainnet.process_committed_block(&value, &full, &vote_keys, &engine).unwrap();
+        assert_eq!(mainnet.get_commit(&hash).unwrap(), &full);
+        // The commit only ever applies at its own height
+        assert_eq!(mainnet.process_committed_block(&value, &full, &vote_keys, &engine).unwrap_err(), "Commit for another height");
+    }
 }
@@ -1,8 +1,23 @@
 use crate::layers::l1_orchestration::OrchestrationLayer;
 use crate::blockchain::core::Block;
+use crate::blockchain::types::hex_serde;
 use crate::math::precision::PreciseFloat;
+use crate::crypto::domain::{PayloadKind, SigningDomain};
+use crate::ids::ChainId;
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::{Serialize, Deserialize};
 use std::collections::HashMap;
 
+/// Hash-linked summary of a private-chain block, safe to share with non-members
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PrivateHeader {
+    pub height: u64,
+    #[serde(with = "hex_serde")]
+    pub previous_hash: [u8; 32],
+    #[serde(with = "hex_serde")]
+    pub hash: [u8; 32],
+}
+
 /// L3 - Private Chain Layer
 /// Allows creation of private blockchains that connect to mainnet while following L1 rules
 pub struct PrivateChainLayer {
@@ -13,12 +28,16 @@ pub struct PrivateChainLayer {
     owners: Vec<[u8; 32]>,
     mainnet_anchor_points: Vec<[u8; 32]>,
     precision: u8,
+    domain: SigningDomain,
 }
 
 pub struct ChainConfig {
     pub name: String,
+    /// Owner ed25519 public keys; any owner may sign blocks
     pub owners: Vec<[u8; 32]>,
     pub initial_state: Vec<u8>,
+    /// Network the chain is hosted on; signatures from other networks are rejected
+    pub network_id: u64,
 }
 
 impl PrivateChainLayer {
@@ -26,6 +45,7 @@ impl PrivateChainLayer {
         let chain_id = blake3::hash(config.name.as_bytes()).into();
         
         Self {
+            domain: SigningDomain::chain(config.network_id, ChainId::new(chain_id)),
             chain_id,
             orchestration: OrchestrationLayer::new(precision),
             blocks: Vec::new(),
@@ -41,6 +61,13 @@ impl PrivateChainLayer {
         self.chain_id
     }
 
+    /// Bytes an owner signs to submit `data` as the next block.
+    /// Bound to this chain, its network and the next height, so a signature
+    /// cannot be replayed on another chain or at another height.
+    pub fn block_signing_payload(&self, data: &[u8]) -> Vec<u8> {
+        self.domain.payload(PayloadKind::Block, self.blocks.len() as u64, data)
+    }
+
     /// Process a new block while following L1 rules
     pub fn process_block(&mut self, data: &[u8], proof: &[u8], owner_sig: &[u8; 64]) -> Result<[u8; 32], &'static str> {
         // Verify block is signed by an owner
@@ -80,13 +107,22 @@ impl PrivateChainLayer {
     }
 
     /// Verify signature from chain owner
-    fn verify_owner_signature(&self, _data: &[u8], _signature: &[u8; 64]) -> Result<(), &'static str> {
-        // TODO: Implement actual signature verification
-        // For now, just check if we have any owners
+    fn verify_owner_signature(&self, data: &[u8], signature: &[u8; 64]) -> Result<(), &'static str> {
         if self.owners.is_empty() {
             return Err("No owners registered");
         }
-        Ok(())
+        let payload = self.block_signing_payload(data);
+        let signature = Signature::from_bytes(signature);
+        let signed_by_owner = self.owners.iter().any(|owner| {
+            VerifyingKey::from_bytes(owner)
+                .map(|key| key.verify(&payload, &signature).is_ok())
+                .unwrap_or(false)
+        });
+        if signed_by_owner {
+            Ok(())
+        } else {
+            Err("Invalid owner signature")
+        }
     }
 
     /// Get the current state
@@ -105,6 +141,23 @@ impl PrivateChainLayer {
         self.blocks.len()
     }
 
+    /// Owner keys allowed to sign blocks and anchors
+    pub fn owners(&self) -> &[[u8; 32]] {
+        &self.owners
+    }
+
+    /// Headers for heights `from..=to` that exist
+    pub fn headers(&self, from: u64, to: u64) -> Vec<PrivateHeader> {
+        self.blocks.iter()
+            .filter(|block| block.index >= from && block.index <= to)
+            .map(|block| PrivateHeader {
+                height: block.index,
+                previous_hash: block.previous_hash,
+                hash: block.hash,
+            })
+            .collect()
+    }
+
     /// Get the latest mainnet anchor point
     pub fn get_latest_anchor(&self) -> Option<[u8; 32]> {
         self.mainnet_anchor_points.last().copied()
@@ -114,15 +167,23 @@ impl PrivateChainLayer {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::crypto::domain::MAINNET_NETWORK_ID;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    fn sign(key: &SigningKey, chain: &PrivateChainLayer, data: &[u8]) -> [u8; 64] {
+        key.sign(&chain.block_signing_payload(data)).to_bytes()
+    }
 
     #[test]
     fn test_private_chain() {
         // Test 1: Chain Creation
-        let owner = blake3::hash(b"chain_owner").into();
+        let owner_key = SigningKey::from_bytes(&[9u8; 32]);
+        let owner = owner_key.verifying_key().to_bytes();
         let config = ChainConfig {
             name: "test_private_chain".to_string(),
             owners: vec![owner],
             initial_state: b"initial_state".to_vec(),
+            network_id: MAINNET_NETWORK_ID,
         };
 
         let mut private_chain = PrivateChainLayer::new(config, 20);
@@ -136,7 +197,7 @@ mod tests {
         hasher.update(data);
         let hash_output = hasher.finalize();
         let proof = hash_output.as_bytes();
-        let owner_sig = [1u8; 64]; // Mock valid signature
+        let owner_sig = sign(&owner_key, &private_chain, data);
         
         let hash = private_chain.process_block(data, proof, &owner_sig)
             .expect("Failed to process block");
@@ -161,8 +222,10 @@ mod tests {
         let hash_output3 = hasher.finalize();
         let proof3 = hash_output3.as_bytes();
         
-        let hash1 = private_chain.process_block(data2, proof2, &owner_sig).unwrap();
-        let hash2 = private_chain.process_block(data3, proof3, &owner_sig).unwrap();
+        let sig2 = sign(&owner_key, &private_chain, data2);
+        let hash1 = private_chain.process_block(data2, proof2, &sig2).unwrap();
+        let sig3 = sign(&owner_key, &private_chain, data3);
+        let hash2 = private_chain.process_block(data3, proof3, &sig3).unwrap();
         assert_ne!(hash1, hash2, "Different blocks should have different hashes");
         assert_eq!(private_chain.height(), 3);
         
@@ -183,8 +246,35 @@ mod tests {
             name: "test_chain_no_owner".to_string(),
             owners: vec![],
             initial_state: b"initial_state".to_vec(),
+            network_id: MAINNET_NETWORK_ID,
         };
         let mut chain_no_owner = PrivateChainLayer::new(config_no_owner, 20);
         assert!(chain_no_owner.process_block(data, proof, &owner_sig).is_err(), "Chain with no owners should fail block processing");
     }
+
+    #[test]
+    fn test_signatures_bound_to_chain_and_height() {
+        let owner_key = SigningKey::from_bytes(&[9u8; 32]);
+        let chain = |name: &str, network_id| PrivateChainLayer::new(ChainConfig {
+            name: name.to_string(),
+            owners: vec![owner_key.verifying_key().to_bytes()],
+            initial_state: Vec::new(),
+            network_id,
+        }, 20);
+        let data = b"block";
+        let proof = blake3::hash(data);
+
+        let mut alpha = chain("alpha", MAINNET_NETWORK_ID);
+        let mut beta = chain("beta", MAINNET_NETWORK_ID);
+        let mut alpha_testnet = chain("alpha", 2);
+        let sig = sign(&owner_key, &alpha, data);
+
+        // Same owner, same data: the signature only works on the chain it was made for
+        assert_eq!(beta.process_block(data, proof.as_bytes(), &sig).unwrap_err(), "Invalid owner signature");
+        assert_eq!(alpha_testnet.process_block(data, proof.as_bytes(), &sig).unwrap_err(), "Invalid owner signature");
+        alpha.process_block(data, proof.as_bytes(), &sig).unwrap();
+
+        // ...and only at the height it was made for
+        assert_eq!(alpha.process_block(data, proof.as_bytes(), &sig).unwrap_err(), "Invalid owner signature");
+    }
 }
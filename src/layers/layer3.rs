@@ -3,6 +3,7 @@ use crate::security::quantum_resistant::QuantumSecurity;
 use crate::network::quantum_network::QuantumNetwork;
 use crate::orchestration::tally::compute::TallyComputer;
 use blake3;
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
 use std::collections::HashMap;
 
 pub struct Layer3 {
@@ -77,9 +78,10 @@ impl Layer3 {
             return Err("Missing signatures");
         }
         
-        // Verify signatures
+        // Participants are ed25519 accounts and sign the final state
         for (sig, participant) in signatures.iter().zip(channel.participants.iter()) {
-            self.security.verify_signature(participant, &final_state, sig)?;
+            let key = VerifyingKey::from_bytes(participant).map_err(|_| "Invalid participant key")?;
+            key.verify(&final_state, &Signature::from_bytes(sig)).map_err(|_| "Invalid signature")?;
         }
         
         // Remove channel
@@ -115,4 +117,22 @@ mod tests {
         // Verify channel exists
         assert!(layer3.state_channels.contains_key(&channel_id));
     }
+
+    #[test]
+    fn test_close_channel_checks_signatures() {
+        use ed25519_dalek::{Signer, SigningKey};
+
+        let mut layer3 = Layer3::new(20);
+        let keys: Vec<SigningKey> = (1..=2).map(|seed| SigningKey::from_bytes(&[seed; 32])).collect();
+        let participants = keys.iter().map(|key| key.verifying_key().to_bytes()).collect();
+        let channel_id = layer3.create_channel(participants, PreciseFloat::new(1000, 20)).unwrap();
+
+        let final_state = b"final_state".to_vec();
+        let mut signatures: Vec<[u8; 64]> = keys.iter().map(|key| key.sign(&final_state).to_bytes()).collect();
+        signatures.reverse();
+        assert_eq!(layer3.close_channel(channel_id, final_state.clone(), signatures.clone()), Err("Invalid signature"));
+        signatures.reverse();
+        layer3.close_channel(channel_id, final_state, signatures).unwrap();
+        assert!(!layer3.state_channels.contains_key(&channel_id));
+    }
 }
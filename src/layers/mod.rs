@@ -4,3 +4,8 @@ pub mod l2_mainnet;
 pub mod l2_sidenet;
 pub mod l3_private;
 pub mod layer3;
+pub mod private_host;
+pub mod validator_set;
+#[cfg(feature = "metaverse")]
+pub mod teleport;
+pub mod watchtower;
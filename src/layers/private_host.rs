@@ -0,0 +1,562 @@
+use crate::layers::l3_private::{ChainConfig, PrivateChainLayer, PrivateHeader};
+use crate::layers::watchtower::HeaderSource;
+use crate::economics::models::EconomicModel;
+use crate::blockchain::htlc::{self, Htlc, HtlcBook, HtlcTerms};
+use crate::blockchain::types::Address;
+use crate::math::precision::PreciseFloat;
+use crate::clock::{self, SharedClock};
+use crate::ids::ChainId;
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::sync::{Arc, PoisonError, RwLock};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+use std::time::{Duration, Instant};
+use rand::RngCore;
+
+
+/// Length of the window CPU quotas are measured over
+const CPU_WINDOW: Duration = Duration::from_secs(60);
+
+/// Per-chain resource limits
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResourceQuota {
+    /// Block processing time allowed per minute
+    pub cpu_ms_per_minute: u64,
+    /// Total bytes of block data and key-value state
+    pub storage_bytes: u64,
+}
+
+impl Default for ResourceQuota {
+    fn default() -> Self {
+        Self {
+            cpu_ms_per_minute: 1_000,
+            storage_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// Usage counters used for quotas and billing
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UsageCounters {
+    pub blocks: u64,
+    pub storage_bytes: u64,
+    pub cpu_ms: u64,
+    /// Blocks and bytes not yet billed to the economics module
+    pub unbilled_blocks: u64,
+    pub unbilled_bytes: u64,
+}
+
+/// Listing entry for a hosted chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TenantSummary {
+    pub chain_id: ChainId,
+    pub name: String,
+    pub height: usize,
+    pub suspended: bool,
+    pub quota: ResourceQuota,
+    pub usage: UsageCounters,
+}
+
+struct Tenant {
+    name: String,
+    chain: PrivateChainLayer,
+    /// blake3 hash of the tenant's RPC token
+    token_hash: [u8; 32],
+    /// Tenant key-value state; keys never leave this namespace
+    kv: HashMap<Vec<u8>, Vec<u8>>,
+    /// HTLCs on the chain, kept after settlement so the counterparty of a
+    /// swap can read a revealed secret
+    htlcs: HtlcBook,
+    /// Nonce of the next HTLC ID
+    htlc_nonce: u64,
+    quota: ResourceQuota,
+    usage: UsageCounters,
+    suspended: bool,
+    /// Start of the current CPU accounting window, in clock millis
+    window_start: u64,
+    window_cpu_ms: u64,
+}
+
+impl Tenant {
+    fn authenticate(&self, token: &str) -> Result<(), &'static str> {
+        let presented: [u8; 32] = blake3::hash(token.as_bytes()).into();
+        // Compare hashes so the check does not leak the token through timing
+        if presented != self.token_hash {
+            return Err("Invalid chain token");
+        }
+        if self.suspended {
+            return Err("Chain is suspended");
+        }
+        Ok(())
+    }
+}
+
+/// Each chain is locked on its own, so a block being processed on one holds
+/// up no other. Calls on a chain that is processing a block wait for it.
+type SharedTenant = Arc<Mutex<Tenant>>;
+
+/// Private Chain Host
+/// Runs many isolated `PrivateChainLayer` instances on one node with
+/// per-chain quotas, authentication tokens, and billing.
+pub struct PrivateChainHost {
+    /// Held only to look a chain up, never while a chain is in use
+    tenants: RwLock<HashMap<ChainId, SharedTenant>>,
+    precision: u8,
+    clock: SharedClock,
+}
+
+/// Usage a hosted chain has not been billed for yet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnbilledUsage {
+    pub chain_id: ChainId,
+    pub blocks: u64,
+    pub bytes: u64,
+}
+
+/// Locked Chain
+/// One hosted chain held for the caller. Blocks are processed through it
+/// on a blocking thread, so the proof check holds up no async worker.
+pub struct LockedChain {
+    chain_id: ChainId,
+    tenant: OwnedMutexGuard<Tenant>,
+    clock: SharedClock,
+}
+
+impl LockedChain {
+    pub fn chain_id(&self) -> &ChainId {
+        &self.chain_id
+    }
+
+    /// Process a block, enforcing CPU and storage quotas
+    pub fn submit_block(&mut self, token: &str, data: &[u8], proof: &[u8], owner_sig: &[u8; 64]) -> Result<[u8; 32], &'static str> {
+        let now = self.clock.now_millis();
+        let tenant = &mut *self.tenant;
+        tenant.authenticate(token)?;
+
+        if now.saturating_sub(tenant.window_start) >= CPU_WINDOW.as_millis() as u64 {
+            tenant.window_start = now;
+            tenant.window_cpu_ms = 0;
+        }
+        if tenant.window_cpu_ms >= tenant.quota.cpu_ms_per_minute {
+            return Err("CPU quota exceeded");
+        }
+        if tenant.usage.storage_bytes + data.len() as u64 > tenant.quota.storage_bytes {
+            return Err("Storage quota exceeded");
+        }
+
+        let started = Instant::now();
+        let hash = tenant.chain.process_block(data, proof, owner_sig)?;
+        let cpu_ms = started.elapsed().as_millis() as u64;
+
+        tenant.window_cpu_ms += cpu_ms;
+        tenant.usage.cpu_ms += cpu_ms;
+        tenant.usage.blocks += 1;
+        tenant.usage.storage_bytes += data.len() as u64;
+        tenant.usage.unbilled_blocks += 1;
+        tenant.usage.unbilled_bytes += data.len() as u64;
+
+        Ok(hash)
+    }
+
+    /// Anchor the chain's current state to a mainnet block
+    pub fn anchor_to_mainnet(&mut self, mainnet_block_hash: [u8; 32]) -> Result<(), &'static str> {
+        if self.tenant.suspended {
+            return Err("Chain is suspended");
+        }
+        self.tenant.chain.anchor_to_mainnet(mainnet_block_hash)
+    }
+}
+
+impl PrivateChainHost {
+    pub fn new(precision: u8) -> Self {
+        Self::with_clock(precision, clock::system())
+    }
+
+    /// Create a host whose CPU quota windows follow `clock`
+    pub fn with_clock(precision: u8, clock: SharedClock) -> Self {
+        Self {
+            tenants: RwLock::new(HashMap::new()),
+            precision,
+            clock,
+        }
+    }
+
+    /// Create a hosted chain and return its ID with a freshly generated RPC token
+    pub fn create_chain(
+        &self,
+        config: ChainConfig,
+        quota: ResourceQuota,
+    ) -> Result<(ChainId, String), &'static str> {
+        if config.name.is_empty() {
+            return Err("Chain name is required");
+        }
+        let name = config.name.clone();
+        let chain = PrivateChainLayer::new(config, self.precision);
+        let chain_id = ChainId::from(chain.get_chain_id());
+        let mut tenants = self.tenants.write().unwrap_or_else(PoisonError::into_inner);
+        if tenants.contains_key(&chain_id) {
+            return Err("Chain already exists");
+        }
+
+        let mut token = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut token);
+        let token = hex::encode(token);
+
+        tenants.insert(chain_id, Arc::new(Mutex::new(Tenant {
+            name,
+            chain,
+            token_hash: blake3::hash(token.as_bytes()).into(),
+            kv: HashMap::new(),
+            htlcs: HtlcBook::default(),
+            htlc_nonce: 0,
+            quota,
+            usage: UsageCounters::default(),
+            suspended: false,
+            window_start: self.clock.now_millis(),
+            window_cpu_ms: 0,
+        })));
+
+        Ok((chain_id, token))
+    }
+
+    /// Every hosted chain with its usage
+    pub async fn list(&self) -> Vec<TenantSummary> {
+        let mut chains = Vec::new();
+        for (chain_id, tenant) in self.all() {
+            let tenant = tenant.lock().await;
+            chains.push(TenantSummary {
+                chain_id,
+                name: tenant.name.clone(),
+                height: tenant.chain.height(),
+                suspended: tenant.suspended,
+                quota: tenant.quota.clone(),
+                usage: tenant.usage.clone(),
+            });
+        }
+        chains.sort_by(|a, b| a.name.cmp(&b.name));
+        chains
+    }
+
+    pub async fn suspend(&self, chain_id: &ChainId) -> Result<(), &'static str> {
+        self.tenant(chain_id).await?.suspended = true;
+        Ok(())
+    }
+
+    pub async fn resume(&self, chain_id: &ChainId) -> Result<(), &'static str> {
+        self.tenant(chain_id).await?.suspended = false;
+        Ok(())
+    }
+
+    fn shared(&self, chain_id: &ChainId) -> Result<SharedTenant, &'static str> {
+        let tenants = self.tenants.read().unwrap_or_else(PoisonError::into_inner);
+        tenants.get(chain_id).cloned().ok_or("Chain not found")
+    }
+
+    fn all(&self) -> Vec<(ChainId, SharedTenant)> {
+        let tenants = self.tenants.read().unwrap_or_else(PoisonError::into_inner);
+        tenants.iter().map(|(id, tenant)| (*id, tenant.clone())).collect()
+    }
+
+    async fn tenant(&self, chain_id: &ChainId) -> Result<OwnedMutexGuard<Tenant>, &'static str> {
+        Ok(self.shared(chain_id)?.lock_owned().await)
+    }
+
+    async fn authenticate(&self, chain_id: &ChainId, token: &str) -> Result<OwnedMutexGuard<Tenant>, &'static str> {
+        let tenant = self.tenant(chain_id).await?;
+        tenant.authenticate(token)?;
+        Ok(tenant)
+    }
+
+    /// Wait for a hosted chain and hold it, for `LockedChain::submit_block`
+    /// and for callers that act on it synchronously
+    pub async fn lock_chain(&self, chain_id: &ChainId) -> Result<LockedChain, &'static str> {
+        Ok(LockedChain {
+            chain_id: *chain_id,
+            tenant: self.tenant(chain_id).await?,
+            clock: self.clock.clone(),
+        })
+    }
+
+    /// Bytes an owner must sign to submit `data` as the chain's next block
+    pub async fn block_signing_payload(&self, chain_id: &ChainId, data: &[u8]) -> Result<Vec<u8>, &'static str> {
+        Ok(self.tenant(chain_id).await?.chain.block_signing_payload(data))
+    }
+
+    /// Process a block on a hosted chain, enforcing CPU and storage quotas.
+    /// Runs the proof check on the calling task; the node submits through
+    /// `lock_chain` on a blocking thread instead.
+    pub async fn submit_block(
+        &self,
+        chain_id: &ChainId,
+        token: &str,
+        data: &[u8],
+        proof: &[u8],
+        owner_sig: &[u8; 64],
+    ) -> Result<[u8; 32], &'static str> {
+        self.lock_chain(chain_id).await?.submit_block(token, data, proof, owner_sig)
+    }
+
+    /// Write a key in the chain's isolated namespace
+    pub async fn put_state(&self, chain_id: &ChainId, token: &str, key: &[u8], value: &[u8]) -> Result<(), &'static str> {
+        let mut tenant = self.authenticate(chain_id, token).await?;
+
+        let previous = tenant.kv.get(key).map(|v| (key.len() + v.len()) as u64).unwrap_or(0);
+        let next = (key.len() + value.len()) as u64;
+        let storage = tenant.usage.storage_bytes - previous + next;
+        if storage > tenant.quota.storage_bytes {
+            return Err("Storage quota exceeded");
+        }
+
+        tenant.kv.insert(key.to_vec(), value.to_vec());
+        tenant.usage.storage_bytes = storage;
+        tenant.usage.unbilled_bytes += next;
+        Ok(())
+    }
+
+    pub async fn get_state(&self, chain_id: &ChainId, token: &str, key: &[u8]) -> Result<Option<Vec<u8>>, &'static str> {
+        let tenant = self.authenticate(chain_id, token).await?;
+        Ok(tenant.kv.get(key).cloned())
+    }
+
+    /// Lock funds on a hosted chain; the timelock is a height of that chain.
+    /// The chain's own ledger moves the funds; the host records the terms and
+    /// settles them exactly as mainnet does.
+    pub async fn lock_htlc(&self, chain_id: &ChainId, token: &str, sender: Address, terms: &HtlcTerms) -> Result<[u8; 32], &'static str> {
+        let mut tenant = self.authenticate(chain_id, token).await?;
+        let locked = Htlc::new(sender, terms, tenant.chain.height() as u64)?;
+        let size = bincode::serialized_size(&locked).map_err(|_| "Unencodable HTLC")? + 32;
+        if tenant.usage.storage_bytes + size > tenant.quota.storage_bytes {
+            return Err("Storage quota exceeded");
+        }
+        let id = htlc::htlc_id(&sender, tenant.htlc_nonce);
+        tenant.htlc_nonce += 1;
+        tenant.htlcs.restore(id, Some(locked));
+        tenant.usage.storage_bytes += size;
+        tenant.usage.unbilled_bytes += size;
+        Ok(id)
+    }
+
+    /// Claim an HTLC on a hosted chain. The secret is the authorization, so
+    /// the other side of a swap needs no token.
+    pub async fn claim_htlc(&self, chain_id: &ChainId, id: &[u8; 32], secret: &[u8; 32]) -> Result<Htlc, &'static str> {
+        let mut tenant = self.tenant(chain_id).await?;
+        if tenant.suspended {
+            return Err("Chain is suspended");
+        }
+        let height = tenant.chain.height() as u64;
+        let mut claimed = tenant.htlcs.get(id).cloned().ok_or("HTLC not found")?;
+        claimed.claim(secret, height)?;
+        tenant.htlcs.restore(*id, Some(claimed.clone()));
+        Ok(claimed)
+    }
+
+    /// Refund an HTLC on a hosted chain once its timelock has passed
+    pub async fn refund_htlc(&self, chain_id: &ChainId, token: &str, id: &[u8; 32]) -> Result<Htlc, &'static str> {
+        let mut tenant = self.authenticate(chain_id, token).await?;
+        let height = tenant.chain.height() as u64;
+        let mut refunded = tenant.htlcs.get(id).cloned().ok_or("HTLC not found")?;
+        refunded.refund(height)?;
+        tenant.htlcs.restore(*id, Some(refunded.clone()));
+        Ok(refunded)
+    }
+
+    /// An HTLC and the chain's current height. Swap counterparties watch it
+    /// without a token, so it holds only the swap's terms.
+    pub async fn htlc(&self, chain_id: &ChainId, id: &[u8; 32]) -> Result<(Htlc, u64), &'static str> {
+        let tenant = self.tenant(chain_id).await?;
+        let htlc = tenant.htlcs.get(id).cloned().ok_or("HTLC not found")?;
+        Ok((htlc, tenant.chain.height() as u64))
+    }
+
+    /// Block headers of a hosted chain; they hold only hashes, so no token is needed
+    pub async fn headers(&self, chain_id: &ChainId, from: u64, to: u64) -> Result<Vec<PrivateHeader>, &'static str> {
+        Ok(self.tenant(chain_id).await?.chain.headers(from, to))
+    }
+
+    /// Anchor a hosted chain's current state to a mainnet block
+    pub async fn anchor_to_mainnet(&self, chain_id: &ChainId, mainnet_block_hash: [u8; 32]) -> Result<(), &'static str> {
+        self.lock_chain(chain_id).await?.anchor_to_mainnet(mainnet_block_hash)
+    }
+
+    pub async fn owners(&self, chain_id: &ChainId) -> Result<Vec<[u8; 32]>, &'static str> {
+        Ok(self.tenant(chain_id).await?.chain.owners().to_vec())
+    }
+
+    /// Take the usage of every chain not billed yet, for `bill_hosting`
+    pub async fn take_unbilled(&self) -> Vec<UnbilledUsage> {
+        let mut unbilled = Vec::new();
+        for (chain_id, tenant) in self.all() {
+            let mut tenant = tenant.lock().await;
+            if tenant.usage.unbilled_blocks == 0 && tenant.usage.unbilled_bytes == 0 {
+                continue;
+            }
+            unbilled.push(UnbilledUsage {
+                chain_id,
+                blocks: tenant.usage.unbilled_blocks,
+                bytes: tenant.usage.unbilled_bytes,
+            });
+            tenant.usage.unbilled_blocks = 0;
+            tenant.usage.unbilled_bytes = 0;
+        }
+        unbilled
+    }
+}
+
+/// Bill hosted chains' usage into the economics module
+pub fn bill_hosting(economics: &mut EconomicModel, unbilled: &[UnbilledUsage]) {
+    for usage in unbilled {
+        let fee = economics.calculate_transaction_fee(
+            usage.bytes,
+            PreciseFloat::new(0, 2) // Hosted chains pay base priority
+        );
+        economics.collect_hosting_fees(usage.chain_id, usage.blocks, fee);
+    }
+}
+
+/// Headers for the watchtower. It polls synchronously, so a chain
+/// processing a block reports busy and is read on the next poll.
+impl HeaderSource for PrivateChainHost {
+    fn headers(&self, chain_id: &ChainId, from: u64, to: u64) -> Result<Vec<PrivateHeader>, String> {
+        let tenant = self.shared(chain_id)?;
+        let tenant = tenant.try_lock().map_err(|_| "Chain is busy processing a block")?;
+        Ok(tenant.chain.headers(from, to))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::domain::MAINNET_NETWORK_ID;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    fn owner() -> SigningKey {
+        SigningKey::from_bytes(&[5u8; 32])
+    }
+
+    fn config(name: &str) -> ChainConfig {
+        ChainConfig {
+            name: name.to_string(),
+            owners: vec![owner().verifying_key().to_bytes()],
+            initial_state: vec![],
+            network_id: MAINNET_NETWORK_ID,
+        }
+    }
+
+    async fn owner_sig(host: &PrivateChainHost, chain: &ChainId, data: &[u8]) -> [u8; 64] {
+        owner().sign(&host.block_signing_payload(chain, data).await.unwrap()).to_bytes()
+    }
+
+    #[tokio::test]
+    async fn test_tenant_isolation_and_auth() {
+        let host = PrivateChainHost::new(20);
+        let (alpha, alpha_token) = host.create_chain(config("alpha"), ResourceQuota::default()).unwrap();
+        let (beta, beta_token) = host.create_chain(config("beta"), ResourceQuota::default()).unwrap();
+        assert!(host.create_chain(config("alpha"), ResourceQuota::default()).is_err());
+
+        host.put_state(&alpha, &alpha_token, b"key", b"alpha").await.unwrap();
+        host.put_state(&beta, &beta_token, b"key", b"beta").await.unwrap();
+        assert_eq!(host.get_state(&alpha, &alpha_token, b"key").await.unwrap(), Some(b"alpha".to_vec()));
+        assert_eq!(host.get_state(&beta, &beta_token, b"key").await.unwrap(), Some(b"beta".to_vec()));
+
+        // One tenant's token does not open another tenant's chain
+        assert_eq!(host.get_state(&beta, &alpha_token, b"key").await.unwrap_err(), "Invalid chain token");
+
+        host.suspend(&alpha).await.unwrap();
+        assert_eq!(host.put_state(&alpha, &alpha_token, b"k", b"v").await.unwrap_err(), "Chain is suspended");
+        assert!(host.list().await.iter().any(|c| c.name == "alpha" && c.suspended));
+    }
+
+    #[tokio::test]
+    async fn test_quotas_and_billing() {
+        let host = PrivateChainHost::new(20);
+        let quota = ResourceQuota { cpu_ms_per_minute: 1_000, storage_bytes: 64 };
+        let (chain, token) = host.create_chain(config("gamma"), quota).unwrap();
+
+        let data = b"private_block_data";
+        let proof = blake3::hash(data);
+        let sig = owner_sig(&host, &chain, data).await;
+        host.submit_block(&chain, &token, data, proof.as_bytes(), &sig).await.unwrap();
+        assert_eq!(
+            host.put_state(&chain, &token, b"big", &[0u8; 64]).await.unwrap_err(),
+            "Storage quota exceeded"
+        );
+
+        let mut economics = EconomicModel::new(20);
+        let unbilled = host.take_unbilled().await;
+        assert_eq!((unbilled[0].blocks, unbilled.len()), (1, 1));
+        bill_hosting(&mut economics, &unbilled);
+        assert!(economics.hosting_revenue(&chain).value > 0);
+        assert_eq!(host.list().await[0].usage.unbilled_blocks, 0);
+        assert!(host.take_unbilled().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_htlc_settles_by_chain_height() {
+        let host = PrivateChainHost::new(20);
+        let (chain, token) = host.create_chain(config("swap"), ResourceQuota::default()).unwrap();
+        let secret = htlc::generate_secret();
+        let lock = htlc::hashlock(&secret);
+        let height = host.tenant(&chain).await.unwrap().chain.height() as u64;
+        let terms = HtlcTerms { recipient: [2u8; 32], amount: 50, hashlock: lock, timelock: height + 2 };
+
+        let claimed = host.lock_htlc(&chain, &token, [1u8; 32], &terms).await.unwrap();
+        let refunded = host.lock_htlc(&chain, &token, [1u8; 32], &terms).await.unwrap();
+        assert_ne!(claimed, refunded);
+        assert!(host.lock_htlc(&chain, "wrong", [1u8; 32], &terms).await.is_err());
+
+        // No token needed to claim; the revealed secret stays readable
+        host.claim_htlc(&chain, &claimed, &secret).await.unwrap();
+        assert_eq!(host.htlc(&chain, &claimed).await.unwrap().0.status, htlc::HtlcStatus::Claimed { secret });
+        assert_eq!(host.refund_htlc(&chain, &token, &refunded).await.unwrap_err(), "HTLC has not timed out");
+
+        for data in [b"block_one".as_slice(), b"block_two".as_slice()] {
+            let proof = blake3::hash(data);
+            let sig = owner_sig(&host, &chain, data).await;
+            host.submit_block(&chain, &token, data, proof.as_bytes(), &sig).await.unwrap();
+        }
+        assert_eq!(host.claim_htlc(&chain, &refunded, &secret).await.unwrap_err(), "HTLC has timed out");
+        host.refund_htlc(&chain, &token, &refunded).await.unwrap();
+        assert_eq!(host.htlc(&chain, &refunded).await.unwrap().0.status, htlc::HtlcStatus::Refunded);
+    }
+
+    #[tokio::test]
+    async fn test_cpu_window_follows_clock() {
+        let clock = crate::clock::MockClock::new(1_000);
+        let host = PrivateChainHost::with_clock(20, clock.clone());
+        let quota = ResourceQuota { cpu_ms_per_minute: 1_000, storage_bytes: 1 << 20 };
+        let (chain, token) = host.create_chain(config("delta"), quota).unwrap();
+        host.tenant(&chain).await.unwrap().window_cpu_ms = 1_000;
+
+        let data = b"private_block_data";
+        let proof = blake3::hash(data);
+        let sig = owner_sig(&host, &chain, data).await;
+        assert_eq!(
+            host.submit_block(&chain, &token, data, proof.as_bytes(), &sig).await.unwrap_err(),
+            "CPU quota exceeded"
+        );
+
+        clock.advance(CPU_WINDOW);
+        host.submit_block(&chain, &token, data, proof.as_bytes(), &sig).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_chain_in_use_holds_up_no_other() {
+        let host = Arc::new(PrivateChainHost::new(20));
+        let (alpha, alpha_token) = host.create_chain(config("alpha"), ResourceQuota::default()).unwrap();
+        let (beta, beta_token) = host.create_chain(config("beta"), ResourceQuota::default()).unwrap();
+
+        // Stands in for a block still being processed on alpha
+        let processing = host.lock_chain(&alpha).await.unwrap();
+        host.put_state(&beta, &beta_token, b"key", b"value").await.unwrap();
+
+        // Calls on alpha wait for the block instead of failing
+        let waiting = tokio::spawn({
+            let host = host.clone();
+            async move { host.put_state(&alpha, &alpha_token, b"key", b"value").await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiting.is_finished());
+        drop(processing);
+        waiting.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,322 @@
+//! Cross-layer teleports.
+//!
+//! Moving an avatar or asset between reality layers is a two-phase commit.
+//! `prepare` takes an exit proof from the source layer (a write the owner is
+//! allowed to make there) and checks the destination against the target
+//! layer's write and physics rules; `commit` places the entity in the target
+//! and removes it from the source in one step, or aborts and leaves it where
+//! it was. Each phase is recorded as a tally transition. Until a teleport
+//! commits the entity resides in its source layer, so it is never in both
+//! layers or in neither.
+
+use crate::blockchain::types::{hex_serde, hex_serde_vec, Address};
+use crate::layers::l0_tally::TallyLayer;
+use crate::layers::l1_orchestration::OrchestrationLayer;
+use crate::orchestration::spatial::Region;
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+
+/// Seconds a prepared teleport may wait for its commit before `expire`
+/// aborts it
+pub const TELEPORT_TIMEOUT_SECS: u64 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntityKind {
+    Avatar,
+    Asset,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TeleportPhase {
+    /// Exit proven and entry validated; the entity is still in its source layer
+    Prepared,
+    /// The entity now lives in the target layer
+    Committed,
+    /// Abandoned; the entity stayed in its source layer
+    Aborted,
+}
+
+/// Request to move an entity from one layer to another
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TeleportRequest {
+    #[serde(with = "hex_serde")]
+    pub entity: [u8; 32],
+    pub kind: EntityKind,
+    #[serde(with = "hex_serde")]
+    pub owner: Address,
+    pub source: u32,
+    pub target: u32,
+    /// Where the entity lands in the target layer
+    pub destination: Region,
+    /// Distinguishes repeated teleports of the same entity
+    pub nonce: u64,
+}
+
+impl TeleportRequest {
+    /// Teleport ID, derived from every field of the request
+    pub fn id(&self) -> [u8; 32] {
+        blake3::hash(&self.encode()).into()
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = b"qmv.teleport".to_vec();
+        bytes.extend_from_slice(&self.entity);
+        bytes.push(self.kind as u8);
+        bytes.extend_from_slice(&self.owner);
+        bytes.extend_from_slice(&self.source.to_be_bytes());
+        bytes.extend_from_slice(&self.target.to_be_bytes());
+        for bound in self.destination.min.iter().chain(self.destination.max.iter()) {
+            bytes.extend_from_slice(&bound.to_be_bytes());
+        }
+        bytes.extend_from_slice(&self.nonce.to_be_bytes());
+        bytes
+    }
+}
+
+/// A teleport and its tally record
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Teleport {
+    pub request: TeleportRequest,
+    pub phase: TeleportPhase,
+    /// Source layer's transition hash for the exit
+    #[serde(with = "hex_serde")]
+    pub exit_proof: [u8; 32],
+    /// Tally transition hash for each phase reached, in order
+    #[serde(with = "hex_serde_vec")]
+    pub tally: Vec<[u8; 32]>,
+    /// When the teleport was prepared, in seconds
+    pub prepared_at: u64,
+}
+
+/// Teleporter
+/// Coordinates teleports between layers and tracks which layer each entity
+/// lives in.
+pub struct Teleporter {
+    locations: BTreeMap<[u8; 32], u32>,
+    teleports: BTreeMap<[u8; 32], Teleport>,
+    /// Teleport ID of each entity with a prepared teleport
+    in_flight: BTreeMap<[u8; 32], [u8; 32]>,
+    tally: TallyLayer,
+}
+
+impl Teleporter {
+    pub fn new() -> Self {
+        Self {
+            locations: BTreeMap::new(),
+            teleports: BTreeMap::new(),
+            in_flight: BTreeMap::new(),
+            tally: TallyLayer::new(),
+        }
+    }
+
+    /// Record that `entity` came into being in `layer`
+    pub fn spawn(&mut self, entity: [u8; 32], layer: u32) -> Result<(), &'static str> {
+        if self.locations.contains_key(&entity) {
+            return Err("Entity already exists");
+        }
+        self.locations.insert(entity, layer);
+        Ok(())
+    }
+
+    /// Layer `entity` lives in
+    pub fn location(&self, entity: &[u8; 32]) -> Option<u32> {
+        self.locations.get(entity).copied()
+    }
+
+    pub fn get(&self, id: &[u8; 32]) -> Option<&Teleport> {
+        self.teleports.get(id)
+    }
+
+    /// Teleport pending for `entity`, if any
+    pub fn in_flight(&self, entity: &[u8; 32]) -> Option<&Teleport> {
+        self.in_flight.get(entity).and_then(|id| self.teleports.get(id))
+    }
+
+    /// Phase one: prove the exit from `source` and validate the entry into
+    /// `target`, the layers numbered `request.source` and `request.target`.
+    /// Nothing moves yet.
+    pub fn prepare(
+        &mut self,
+        request: TeleportRequest,
+        source: &mut OrchestrationLayer,
+        target: &OrchestrationLayer,
+        proof: &[u8],
+        now: u64,
+    ) -> Result<[u8; 32], &'static str> {
+        if request.source == request.target {
+            return Err("Source and target layers are the same");
+        }
+        if self.location(&request.entity) != Some(request.source) {
+            return Err("Entity is not in the source layer");
+        }
+        if self.in_flight.contains_key(&request.entity) {
+            return Err("Entity already has a teleport in flight");
+        }
+        let id = request.id();
+        if self.teleports.contains_key(&id) {
+            return Err("Teleport already exists");
+        }
+
+        let exit_proof = source.process_write(&request.owner, &request.entity, &request.encode(), b"teleport.exit", proof)?;
+        target.check_placement(&request.owner, &request.entity, &request.destination)?;
+
+        let mut teleport = Teleport {
+            request,
+            phase: TeleportPhase::Prepared,
+            exit_proof,
+            tally: Vec::new(),
+            prepared_at: now,
+        };
+        self.record(&id, &mut teleport)?;
+        self.in_flight.insert(teleport.request.entity, id);
+        self.teleports.insert(id, teleport);
+        Ok(id)
+    }
+
+    /// Phase two: place the entity in `target` and remove it from `source`.
+    /// If the target now refuses the entry (say another object took the
+    /// spot) the teleport aborts and the entity stays in the source layer.
+    pub fn commit(
+        &mut self,
+        id: &[u8; 32],
+        source: &mut OrchestrationLayer,
+        target: &mut OrchestrationLayer,
+        proof: &[u8],
+    ) -> Result<(), &'static str> {
+        let mut teleport = self.take_prepared(id)?;
+        let request = &teleport.request;
+        let entered = target.process_placement(&request.owner, &request.entity, request.destination, &teleport.exit_proof, proof);
+
+        teleport.phase = match entered {
+            Ok(_) => {
+                source.remove_object(&request.entity);
+                self.locations.insert(request.entity, request.target);
+                TeleportPhase::Committed
+            }
+            Err(_) => TeleportPhase::Aborted,
+        };
+        self.record(id, &mut teleport)?;
+        self.teleports.insert(*id, teleport);
+        entered.map(|_| ())
+    }
+
+    /// Abandon a prepared teleport
+    pub fn abort(&mut self, id: &[u8; 32]) -> Result<(), &'static str> {
+        let mut teleport = self.take_prepared(id)?;
+        teleport.phase = TeleportPhase::Aborted;
+        self.record(id, &mut teleport)?;
+        self.teleports.insert(*id, teleport);
+        Ok(())
+    }
+
+    /// Abort teleports prepared more than `TELEPORT_TIMEOUT_SECS` before
+    /// `now`, returning their IDs
+    pub fn expire(&mut self, now: u64) -> Vec<[u8; 32]> {
+        let expired: Vec<[u8; 32]> = self.in_flight.values()
+            .filter(|id| now.saturating_sub(self.teleports[*id].prepared_at) > TELEPORT_TIMEOUT_SECS)
+            .copied()
+            .collect();
+        expired.into_iter().filter(|id| self.abort(id).is_ok()).collect()
+    }
+
+    fn take_prepared(&mut self, id: &[u8; 32]) -> Result<Teleport, &'static str> {
+        match self.teleports.get(id) {
+            Some(teleport) if teleport.phase == TeleportPhase::Prepared => {}
+            Some(_) => return Err("Teleport is not prepared"),
+            None => return Err("Teleport not found"),
+        }
+        let teleport = self.teleports.remove(id).ok_or("Teleport not found")?;
+        self.in_flight.remove(&teleport.request.entity);
+        Ok(teleport)
+    }
+
+    /// Record the teleport's current phase as a tally transition
+    fn record(&mut self, id: &[u8; 32], teleport: &mut Teleport) -> Result<(), &'static str> {
+        let hash = self.tally.compute_state_transition(id, &[teleport.phase as u8 + 1], &teleport.exit_proof)?;
+        teleport.tally.push(hash);
+        Ok(())
+    }
+}
+
+impl Default for Teleporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: Address = [1u8; 32];
+    const AVATAR: [u8; 32] = [7u8; 32];
+
+    fn proof() -> Vec<u8> {
+        let mut proof: Vec<u8> = (0..32).map(|i| if i % 2 == 0 { 0x55 } else { 0xAA }).collect();
+        proof.extend_from_slice(&[0x55; 32]);
+        proof
+    }
+
+    fn request(destination: Region, nonce: u64) -> TeleportRequest {
+        TeleportRequest { entity: AVATAR, kind: EntityKind::Avatar, owner: ALICE, source: 1, target: 2, destination, nonce }
+    }
+
+    fn spot(x: i64) -> Region {
+        Region::new([x, 0, 0], [x + 2, 2, 2]).unwrap()
+    }
+
+    #[test]
+    fn test_teleport_moves_entity_once() {
+        let (mut source, mut target) = (OrchestrationLayer::new(20), OrchestrationLayer::new(20));
+        source.process_placement(&ALICE, &AVATAR, spot(0), b"spawn", &proof()).unwrap();
+        let mut teleporter = Teleporter::new();
+        teleporter.spawn(AVATAR, 1).unwrap();
+
+        let id = teleporter.prepare(request(spot(10), 0), &mut source, &target, &proof(), 100).unwrap();
+        assert_eq!(teleporter.location(&AVATAR), Some(1));
+        let again = teleporter.prepare(request(spot(20), 1), &mut source, &target, &proof(), 100);
+        assert_eq!(again.unwrap_err(), "Entity already has a teleport in flight");
+
+        teleporter.commit(&id, &mut source, &mut target, &proof()).unwrap();
+        assert_eq!(teleporter.location(&AVATAR), Some(2));
+        assert!(source.objects().region_of(&AVATAR).is_none());
+        assert_eq!(target.objects().region_of(&AVATAR), Some(&spot(10)));
+
+        let teleport = teleporter.get(&id).unwrap();
+        assert_eq!(teleport.phase, TeleportPhase::Committed);
+        assert_eq!(teleport.tally.len(), 2);
+        assert_eq!(teleporter.commit(&id, &mut source, &mut target, &proof()).unwrap_err(), "Teleport is not prepared");
+        // The entity has left layer 1, so it cannot leave it again
+        let stale = teleporter.prepare(request(spot(20), 2), &mut source, &target, &proof(), 100);
+        assert_eq!(stale.unwrap_err(), "Entity is not in the source layer");
+    }
+
+    #[test]
+    fn test_failed_entry_leaves_entity_in_source() {
+        let (mut source, mut target) = (OrchestrationLayer::new(20), OrchestrationLayer::new(20));
+        let mut teleporter = Teleporter::new();
+        teleporter.spawn(AVATAR, 1).unwrap();
+
+        // Entry is validated up front
+        target.process_placement(&ALICE, &[8u8; 32], spot(10), b"spawn", &proof()).unwrap();
+        let blocked = teleporter.prepare(request(spot(10), 0), &mut source, &target, &proof(), 100);
+        assert_eq!(blocked.unwrap_err(), "placement collides with another object");
+
+        // ...and again at commit, in case the spot was taken in between
+        let id = teleporter.prepare(request(spot(20), 0), &mut source, &target, &proof(), 100).unwrap();
+        target.process_placement(&ALICE, &[9u8; 32], spot(21), b"spawn", &proof()).unwrap();
+        let result = teleporter.commit(&id, &mut source, &mut target, &proof());
+        assert_eq!(result.unwrap_err(), "placement collides with another object");
+        assert_eq!(teleporter.get(&id).unwrap().phase, TeleportPhase::Aborted);
+        assert_eq!(teleporter.location(&AVATAR), Some(1));
+        assert!(teleporter.in_flight(&AVATAR).is_none());
+
+        // Stale prepared teleports time out
+        let id = teleporter.prepare(request(spot(30), 1), &mut source, &target, &proof(), 100).unwrap();
+        assert!(teleporter.expire(100 + TELEPORT_TIMEOUT_SECS).is_empty());
+        assert_eq!(teleporter.expire(101 + TELEPORT_TIMEOUT_SECS), vec![id]);
+        assert_eq!(teleporter.get(&id).unwrap().phase, TeleportPhase::Aborted);
+    }
+}
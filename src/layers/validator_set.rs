@@ -0,0 +1,268 @@
+//! Mainnet validator set.
+//!
+//! Validators join and leave as candidates at any time, but the active set
+//! only changes at epoch boundaries: `rotate` ranks the candidates by the
+//! stake they hold in the `EconomicModel` and makes the top
+//! `max_active` the set for the new epoch, each weighted by its stake.
+//! A validator that leaves keeps signing until the next rotation.
+//!
+//! A block is accepted once validators holding more than two thirds of the
+//! active stake have signed it. Until the first rotation leaves the set
+//! non-empty there is nobody to sign, and blocks are accepted unsigned so
+//! a new chain can bootstrap.
+
+use crate::blockchain::types::hex_serde;
+use crate::economics::models::EconomicModel;
+use crate::epoch::{EpochSchedule, ValidatorRotation};
+use crate::math::precision::PreciseFloat;
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Decimal places of stake that count towards a validator's weight
+const WEIGHT_SCALE: u8 = 2;
+
+/// A validator's signature over a block's signing payload
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockSignature {
+    /// The validator's ed25519 public key, which is also its identity
+    #[serde(with = "hex_serde")]
+    pub validator: [u8; 32],
+    #[serde(with = "hex_serde")]
+    pub signature: Vec<u8>,
+}
+
+/// Validator Set
+/// Candidates, and the stake-weighted active set they were rotated into
+/// for the current epoch.
+#[derive(Debug, Clone)]
+pub struct ValidatorSet {
+    schedule: EpochSchedule,
+    max_active: usize,
+    candidates: BTreeSet<[u8; 32]>,
+    /// Active validators and their stake weight
+    active: BTreeMap<[u8; 32], u128>,
+    /// Epoch the active set was chosen for
+    epoch: u64,
+}
+
+impl ValidatorSet {
+    pub fn new(schedule: EpochSchedule, max_active: usize) -> Self {
+        Self {
+            schedule,
+            max_active,
+            candidates: BTreeSet::new(),
+            active: BTreeMap::new(),
+            epoch: 0,
+        }
+    }
+
+    /// Become a candidate for the next rotation
+    pub fn join(&mut self, validator: [u8; 32]) -> Result<(), &'static str> {
+        if !self.candidates.insert(validator) {
+            return Err("Validator already joined");
+        }
+        Ok(())
+    }
+
+    /// Stop being a candidate; an active validator stays active until the
+    /// next rotation
+    pub fn leave(&mut self, validator: &[u8; 32]) -> Result<(), &'static str> {
+        if !self.candidates.remove(validator) {
+            return Err("Validator not in the set");
+        }
+        Ok(())
+    }
+
+    pub fn candidates(&self) -> impl Iterator<Item = &[u8; 32]> {
+        self.candidates.iter()
+    }
+
+    /// Active validators and their weights, ordered by key
+    pub fn active(&self) -> impl Iterator<Item = (&[u8; 32], u128)> {
+        self.active.iter().map(|(validator, weight)| (validator, *weight))
+    }
+
+    pub fn is_active(&self, validator: &[u8; 32]) -> bool {
+        self.active.contains_key(validator)
+    }
+
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    pub fn schedule(&self) -> &EpochSchedule {
+        &self.schedule
+    }
+
+    pub fn total_weight(&self) -> u128 {
+        self.active.values().sum()
+    }
+
+    /// Weight of signatures needed to accept a block: more than two thirds
+    pub fn quorum_weight(&self) -> u128 {
+        self.total_weight() * 2 / 3 + 1
+    }
+
+    /// Whether the block at `height` belongs to a later epoch than the
+    /// active set was chosen for
+    pub fn rotation_due(&self, height: u64) -> bool {
+        !self.active.is_empty() && self.schedule.epoch_of(height) > self.epoch
+    }
+
+    /// Choose the active set for the epoch of `height` from the candidates
+    /// with the most stake. Only allowed once per epoch, except while the
+    /// set is still empty.
+    pub fn rotate(&mut self, height: u64, economics: &EconomicModel) -> Result<ValidatorRotation, &'static str> {
+        let epoch = self.schedule.epoch_of(height);
+        if !self.active.is_empty() && epoch <= self.epoch {
+            return Err("Validator rotation not due");
+        }
+
+        let mut ranked: Vec<([u8; 32], u128)> = self.candidates.iter()
+            .filter_map(|validator| {
+                let weight = stake_weight(&economics.stake_of(validator)?);
+                (weight > 0).then_some((*validator, weight))
+            })
+            .collect();
+        ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        ranked.truncate(self.max_active);
+        let next: BTreeMap<[u8; 32], u128> = ranked.into_iter().collect();
+
+        let rotation = ValidatorRotation {
+            added: next.keys().filter(|v| !self.active.contains_key(*v)).copied().collect(),
+            removed: self.active.keys().filter(|v| !next.contains_key(*v)).copied().collect(),
+            active: next.len(),
+        };
+        self.active = next;
+        self.epoch = epoch;
+        Ok(rotation)
+    }
+
+    /// Active validator expected to propose the block at `height`, picked
+    /// with probability proportional to its stake
+    pub fn proposer(&self, height: u64) -> Option<[u8; 32]> {
+        let total = self.total_weight();
+        if total == 0 {
+            return None;
+        }
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(b"qmv:proposer:");
+        hasher.update(&self.epoch.to_le_bytes());
+        hasher.update(&height.to_le_bytes());
+        let seed: [u8; 16] = hasher.finalize().as_bytes()[..16].try_into().expect("hash is 32 bytes");
+        let mut target = u128::from_le_bytes(seed) % total;
+        for (validator, weight) in &self.active {
+            if target < *weight {
+                return Some(*validator);
+            }
+            target -= weight;
+        }
+        None
+    }
+
+    /// Check that `signatures` over `message` come from distinct active
+    /// validators holding at least the quorum weight
+    pub fn verify_quorum(&self, message: &[u8], signatures: &[BlockSignature]) -> Result<(), &'static str> {
+        let mut signed = BTreeSet::new();
+        let mut weight = 0u128;
+        for entry in signatures {
+            let validator_weight = *self.active.get(&entry.validator)
+                .ok_or("Signer not in the active validator set")?;
+            if !signed.insert(entry.validator) {
+                return Err("Duplicate validator signature");
+            }
+            let key = VerifyingKey::from_bytes(&entry.validator).map_err(|_| "Invalid validator key")?;
+            let signature: [u8; 64] = entry.signature.as_slice().try_into()
+                .map_err(|_| "Invalid signature length")?;
+            key.verify(message, &Signature::from_bytes(&signature))
+                .map_err(|_| "Invalid validator signature")?;
+            weight += validator_weight;
+        }
+        if weight < self.quorum_weight() {
+            return Err("Validator quorum not reached");
+        }
+        Ok(())
+    }
+}
+
+/// Stake in hundredths of a token, whatever precision the model keeps
+fn stake_weight(stake: &PreciseFloat) -> u128 {
+    let value = stake.value.max(0) as u128;
+    if stake.scale >= WEIGHT_SCALE {
+        value / 10u128.pow((stake.scale - WEIGHT_SCALE) as u32)
+    } else {
+        value.saturating_mul(10u128.pow((WEIGHT_SCALE - stake.scale) as u32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    fn key(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32])
+    }
+
+    fn id(seed: u8) -> [u8; 32] {
+        key(seed).verifying_key().to_bytes()
+    }
+
+    #[test]
+    fn test_rotation_follows_stake() {
+        let mut economics = EconomicModel::new(2);
+        let mut set = ValidatorSet::new(EpochSchedule { epoch_blocks: 10, epochs_per_era: 2 }, 2);
+        for (seed, tokens) in [(1u8, 5000), (2, 2000), (3, 3000)] {
+            set.join(id(seed)).unwrap();
+            economics.stake_tokens(id(seed), PreciseFloat::new(tokens * 100, 2)).unwrap();
+        }
+        // Joined without stake: never selected
+        set.join(id(4)).unwrap();
+        assert_eq!(set.join(id(4)), Err("Validator already joined"));
+
+        let rotation = set.rotate(0, &economics).unwrap();
+        assert_eq!(rotation.active, 2);
+        assert!(set.is_active(&id(1)) && set.is_active(&id(3)));
+        assert!(!set.is_active(&id(2)));
+        assert_eq!(set.total_weight(), 800_000);
+        assert!(set.proposer(5).is_some_and(|proposer| set.is_active(&proposer)));
+
+        // The set is fixed until the next epoch
+        assert!(!set.rotation_due(9));
+        assert_eq!(set.rotate(9, &economics).unwrap_err(), "Validator rotation not due");
+
+        set.leave(&id(1)).unwrap();
+        assert!(set.is_active(&id(1)), "Leaving takes effect at the next rotation");
+        assert!(set.rotation_due(10));
+        let rotation = set.rotate(10, &economics).unwrap();
+        assert_eq!(rotation.removed, vec![id(1)]);
+        assert_eq!(rotation.added, vec![id(2)]);
+        assert_eq!(set.epoch(), 1);
+    }
+
+    #[test]
+    fn test_quorum_needs_two_thirds_of_stake() {
+        let mut economics = EconomicModel::new(2);
+        let mut set = ValidatorSet::new(EpochSchedule::default(), 10);
+        for (seed, tokens) in [(1u8, 4000), (2, 3000), (3, 3000)] {
+            set.join(id(seed)).unwrap();
+            economics.stake_tokens(id(seed), PreciseFloat::new(tokens * 100, 2)).unwrap();
+        }
+        set.rotate(0, &economics).unwrap();
+
+        let message = b"block";
+        let sign = |seed: u8| BlockSignature { validator: id(seed), signature: key(seed).sign(message).to_bytes().to_vec() };
+
+        // 7000 of 10000 staked
+        assert!(set.verify_quorum(message, &[sign(1), sign(2)]).is_ok());
+        // 6000 is not more than two thirds
+        assert_eq!(set.verify_quorum(message, &[sign(2), sign(3)]), Err("Validator quorum not reached"));
+        assert_eq!(set.verify_quorum(message, &[sign(1), sign(1), sign(2)]), Err("Duplicate validator signature"));
+        assert_eq!(set.verify_quorum(message, &[sign(1), sign(9)]), Err("Signer not in the active validator set"));
+
+        let mut forged = sign(2);
+        forged.signature = key(3).sign(message).to_bytes().to_vec();
+        assert_eq!(set.verify_quorum(message, &[sign(1), forged]), Err("Invalid validator signature"));
+    }
+}
@@ -0,0 +1,371 @@
+use crate::blockchain::types::hex_serde;
+use crate::crypto::domain::{PayloadKind, SigningDomain};
+use crate::ids::ChainId;
+use crate::layers::l3_private::PrivateHeader;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Commitment to a private-chain block, published on mainnet by a chain owner
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChainAnchor {
+    pub chain_id: ChainId,
+    /// Private-chain height being anchored
+    pub height: u64,
+    #[serde(with = "hex_serde")]
+    pub block_hash: [u8; 32],
+    /// Mainnet block the anchor was included in; not signed
+    pub mainnet_height: u64,
+    #[serde(with = "hex_serde")]
+    pub owner: [u8; 32],
+    #[serde(with = "hex_serde", default)]
+    pub signature: Vec<u8>,
+}
+
+impl ChainAnchor {
+    pub fn new(chain_id: ChainId, height: u64, block_hash: [u8; 32], mainnet_height: u64) -> Self {
+        Self {
+            chain_id,
+            height,
+            block_hash,
+            mainnet_height,
+            owner: [0u8; 32],
+            signature: Vec::new(),
+        }
+    }
+
+    /// Bytes the owner signs, bound to the private chain and anchored height
+    pub fn signing_bytes(&self, network_id: u64) -> Vec<u8> {
+        SigningDomain::chain(network_id, self.chain_id).payload(PayloadKind::Anchor, self.height, &self.block_hash)
+    }
+
+    pub fn sign(&mut self, network_id: u64, key: &SigningKey) {
+        self.owner = key.verifying_key().to_bytes();
+        self.signature = key.sign(&self.signing_bytes(network_id)).to_bytes().to_vec();
+    }
+
+    /// Check the anchor is signed by one of `owners`
+    pub fn verify(&self, network_id: u64, owners: &[[u8; 32]]) -> Result<(), &'static str> {
+        if !owners.contains(&self.owner) {
+            return Err("Anchor not signed by a chain owner");
+        }
+        let key = VerifyingKey::from_bytes(&self.owner).map_err(|_| "Invalid owner key")?;
+        let signature: [u8; 64] = self.signature.as_slice().try_into()
+            .map_err(|_| "Invalid signature length")?;
+        key.verify(&self.signing_bytes(network_id), &Signature::from_bytes(&signature))
+            .map_err(|_| "Invalid anchor signature")
+    }
+}
+
+/// Anchors published on mainnet
+pub trait AnchorFeed {
+    /// Anchors for `chain_id` included after `mainnet_height`, oldest first
+    fn anchors_after(&self, chain_id: &ChainId, mainnet_height: u64) -> Vec<ChainAnchor>;
+}
+
+/// Chain member that serves private-chain headers
+pub trait HeaderSource {
+    /// Headers for heights `from..=to` the member has
+    fn headers(&self, chain_id: &ChainId, from: u64, to: u64) -> Result<Vec<PrivateHeader>, String>;
+}
+
+/// In-memory anchor feed, filled from mainnet blocks as they are imported
+#[derive(Debug, Default)]
+pub struct AnchorLog {
+    anchors: Vec<ChainAnchor>,
+}
+
+impl AnchorLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn publish(&mut self, anchor: ChainAnchor) {
+        self.anchors.push(anchor);
+    }
+}
+
+impl AnchorFeed for AnchorLog {
+    fn anchors_after(&self, chain_id: &ChainId, mainnet_height: u64) -> Vec<ChainAnchor> {
+        let mut anchors: Vec<_> = self.anchors.iter()
+            .filter(|anchor| &anchor.chain_id == chain_id && anchor.mainnet_height > mainnet_height)
+            .cloned()
+            .collect();
+        anchors.sort_by_key(|anchor| anchor.mainnet_height);
+        anchors
+    }
+}
+
+/// Why an anchor was flagged
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    /// Not validly signed by a chain owner
+    InvalidSignature(String),
+    /// Two signed anchors commit to different blocks at the same height
+    Equivocation {
+        #[serde(with = "hex_serde")]
+        first: [u8; 32],
+        #[serde(with = "hex_serde")]
+        second: [u8; 32],
+    },
+    /// Members' chain has a different block at the anchored height
+    Mismatch {
+        #[serde(with = "hex_serde")]
+        anchored: [u8; 32],
+        #[serde(with = "hex_serde")]
+        observed: [u8; 32],
+    },
+    /// Members returned different blocks at the anchored height
+    MembersDisagree,
+    /// A member's headers do not link up or do not extend the last verified anchor
+    BrokenHeaderChain { member: usize },
+    /// No member has reached the anchored height
+    Unavailable,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Alert {
+    pub chain_id: ChainId,
+    pub height: u64,
+    pub mainnet_height: u64,
+    pub kind: AlertKind,
+}
+
+impl Alert {
+    fn new(anchor: &ChainAnchor, kind: AlertKind) -> Self {
+        Self { chain_id: anchor.chain_id, height: anchor.height, mainnet_height: anchor.mainnet_height, kind }
+    }
+}
+
+/// Self-contained evidence that a chain's owners anchored two different
+/// blocks at one height; anyone holding the owner keys can check it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FraudProof {
+    pub first: ChainAnchor,
+    pub second: ChainAnchor,
+}
+
+impl FraudProof {
+    pub fn verify(&self, network_id: u64, owners: &[[u8; 32]]) -> Result<(), &'static str> {
+        if self.first.chain_id != self.second.chain_id || self.first.height != self.second.height {
+            return Err("Anchors are for different blocks");
+        }
+        if self.first.block_hash == self.second.block_hash {
+            return Err("Anchors agree");
+        }
+        self.first.verify(network_id, owners)?;
+        self.second.verify(network_id, owners)
+    }
+}
+
+struct WatchedChain {
+    owners: Vec<[u8; 32]>,
+    /// Last mainnet height whose anchors have been processed
+    cursor: u64,
+    /// First validly signed anchor at each private height
+    anchors: BTreeMap<u64, ChainAnchor>,
+    /// Highest anchored header confirmed by members
+    verified: Option<PrivateHeader>,
+}
+
+/// Watchtower
+/// Follows a private chain's mainnet anchors and checks each against the
+/// headers its members serve, so auditors outside the chain can spot a
+/// chain that anchors one history and runs another.
+pub struct Watchtower {
+    network_id: u64,
+    chains: HashMap<ChainId, WatchedChain>,
+    alerts: Vec<Alert>,
+    fraud_proofs: Vec<FraudProof>,
+}
+
+impl Watchtower {
+    pub fn new(network_id: u64) -> Self {
+        Self {
+            network_id,
+            chains: HashMap::new(),
+            alerts: Vec::new(),
+            fraud_proofs: Vec::new(),
+        }
+    }
+
+    /// Start following a chain's anchors included after `from_mainnet_height`
+    pub fn watch(&mut self, chain_id: ChainId, owners: Vec<[u8; 32]>, from_mainnet_height: u64) {
+        self.chains.insert(chain_id, WatchedChain {
+            owners,
+            cursor: from_mainnet_height,
+            anchors: BTreeMap::new(),
+            verified: None,
+        });
+    }
+
+    pub fn unwatch(&mut self, chain_id: &ChainId) {
+        self.chains.remove(chain_id);
+    }
+
+    pub fn alerts(&self) -> &[Alert] {
+        &self.alerts
+    }
+
+    pub fn fraud_proofs(&self) -> &[FraudProof] {
+        &self.fraud_proofs
+    }
+
+    /// Highest private height whose anchor matched the members' chain
+    pub fn verified_height(&self, chain_id: &ChainId) -> Option<u64> {
+        self.chains.get(chain_id)?.verified.as_ref().map(|header| header.height)
+    }
+
+    /// Check new anchors of every watched chain and return the alerts they raise
+    pub fn poll(&mut self, feed: &dyn AnchorFeed, members: &[&dyn HeaderSource]) -> Vec<Alert> {
+        let mut raised = Vec::new();
+        for (chain_id, chain) in self.chains.iter_mut() {
+            for anchor in feed.anchors_after(chain_id, chain.cursor) {
+                chain.cursor = chain.cursor.max(anchor.mainnet_height);
+                if let Err(e) = anchor.verify(self.network_id, &chain.owners) {
+                    raised.push(Alert::new(&anchor, AlertKind::InvalidSignature(e.to_string())));
+                    continue;
+                }
+                if let Some(earlier) = chain.anchors.get(&anchor.height) {
+                    if earlier.block_hash != anchor.block_hash {
+                        raised.push(Alert::new(&anchor, AlertKind::Equivocation {
+                            first: earlier.block_hash,
+                            second: anchor.block_hash,
+                        }));
+                        self.fraud_proofs.push(FraudProof { first: earlier.clone(), second: anchor });
+                    }
+                    continue;
+                }
+                chain.anchors.insert(anchor.height, anchor.clone());
+                if let Some(kind) = Self::check_against_members(chain_id, chain, &anchor, members) {
+                    raised.push(Alert::new(&anchor, kind));
+                }
+            }
+        }
+        self.alerts.extend(raised.iter().cloned());
+        raised
+    }
+
+    fn check_against_members(
+        chain_id: &ChainId,
+        chain: &mut WatchedChain,
+        anchor: &ChainAnchor,
+        members: &[&dyn HeaderSource],
+    ) -> Option<AlertKind> {
+        // Only fetch headers since the last verified anchor, and require
+        // them to extend it
+        let base = chain.verified.clone().filter(|header| header.height <= anchor.height);
+        let from = base.as_ref().map_or(0, |header| header.height);
+
+        let mut observed: Option<PrivateHeader> = None;
+        let mut broken = None;
+        for (index, member) in members.iter().enumerate() {
+            let Ok(headers) = member.headers(chain_id, from, anchor.height) else { continue };
+            if headers.last().map(|header| header.height) != Some(anchor.height) {
+                continue;
+            }
+            if !Self::links(&headers, from, base.as_ref()) {
+                broken = Some(index);
+                continue;
+            }
+            let tip = headers.last().expect("checked above").clone();
+            match &observed {
+                Some(seen) if seen.hash != tip.hash => return Some(AlertKind::MembersDisagree),
+                Some(_) => {}
+                None => observed = Some(tip),
+            }
+        }
+
+        match (observed, broken) {
+            (Some(tip), _) if tip.hash != anchor.block_hash => {
+                Some(AlertKind::Mismatch { anchored: anchor.block_hash, observed: tip.hash })
+            }
+            (Some(tip), _) => {
+                if chain.verified.as_ref().is_none_or(|verified| verified.height < tip.height) {
+                    chain.verified = Some(tip);
+                }
+                None
+            }
+            (None, Some(member)) => Some(AlertKind::BrokenHeaderChain { member }),
+            (None, None) => Some(AlertKind::Unavailable),
+        }
+    }
+
+    /// Headers cover `from` onwards without gaps, start at `base` and are hash-linked
+    fn links(headers: &[PrivateHeader], from: u64, base: Option<&PrivateHeader>) -> bool {
+        let Some(first) = headers.first() else { return false };
+        first.height == from
+            && base.is_none_or(|base| base.hash == first.hash)
+            && headers.windows(2).all(|pair| {
+                pair[1].height == pair[0].height + 1 && pair[1].previous_hash == pair[0].hash
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::domain::MAINNET_NETWORK_ID;
+    use crate::layers::l3_private::ChainConfig;
+    use crate::layers::private_host::{PrivateChainHost, ResourceQuota};
+
+    async fn hosted_chain(owner: &SigningKey) -> (PrivateChainHost, ChainId) {
+        let host = PrivateChainHost::new(20);
+        let (chain, token) = host.create_chain(ChainConfig {
+            name: "audited".to_string(),
+            owners: vec![owner.verifying_key().to_bytes()],
+            initial_state: vec![],
+            network_id: MAINNET_NETWORK_ID,
+        }, ResourceQuota::default()).unwrap();
+        for data in [b"block 0", b"block 1", b"block 2"] {
+            let sig = owner.sign(&host.block_signing_payload(&chain, data).await.unwrap()).to_bytes();
+            host.submit_block(&chain, &token, data, blake3::hash(data).as_bytes(), &sig).await.unwrap();
+        }
+        (host, chain)
+    }
+
+    fn anchor(owner: &SigningKey, chain: ChainId, height: u64, hash: [u8; 32], mainnet_height: u64) -> ChainAnchor {
+        let mut anchor = ChainAnchor::new(chain, height, hash, mainnet_height);
+        anchor.sign(MAINNET_NETWORK_ID, owner);
+        anchor
+    }
+
+    #[tokio::test]
+    async fn test_detects_mismatch_and_equivocation() {
+        let owner = SigningKey::from_bytes(&[5u8; 32]);
+        let (host, chain) = hosted_chain(&owner).await;
+        let headers = host.headers(&chain, 0, 2).await.unwrap();
+        let mut tower = Watchtower::new(MAINNET_NETWORK_ID);
+        tower.watch(chain, host.owners(&chain).await.unwrap(), 0);
+        let mut log = AnchorLog::new();
+
+        // An honest anchor is confirmed by the member's headers
+        log.publish(anchor(&owner, chain, 2, headers[2].hash, 10));
+        assert!(tower.poll(&log, &[&host]).is_empty());
+        assert_eq!(tower.verified_height(&chain), Some(2));
+
+        // Anchors already processed are not re-checked
+        assert!(tower.poll(&log, &[&host]).is_empty());
+
+        // A forged anchor, and an owner anchor for a block the members never saw
+        log.publish(anchor(&SigningKey::from_bytes(&[6u8; 32]), chain, 1, headers[1].hash, 11));
+        log.publish(anchor(&owner, chain, 1, [9u8; 32], 12));
+        let alerts = tower.poll(&log, &[&host]);
+        assert_eq!(alerts.len(), 2);
+        assert!(matches!(alerts[0].kind, AlertKind::InvalidSignature(_)));
+        assert_eq!(alerts[1].kind, AlertKind::Mismatch { anchored: [9u8; 32], observed: headers[1].hash });
+
+        // Anchoring a second block at height 2 yields a fraud proof
+        log.publish(anchor(&owner, chain, 2, [8u8; 32], 13));
+        let alerts = tower.poll(&log, &[&host]);
+        assert!(matches!(alerts[0].kind, AlertKind::Equivocation { .. }));
+        let proof = &tower.fraud_proofs()[0];
+        assert!(proof.verify(MAINNET_NETWORK_ID, &[owner.verifying_key().to_bytes()]).is_ok());
+        assert_eq!(tower.alerts().len(), 3);
+
+        // Heights no member has reached cannot be checked
+        log.publish(anchor(&owner, chain, 7, [7u8; 32], 14));
+        assert_eq!(tower.poll(&log, &[&host])[0].kind, AlertKind::Unavailable);
+    }
+}
@@ -1,9 +1,12 @@
+// Re-export the I/O-free core under its old paths
+pub use metaverse_core::{clock, crypto, epoch, ids, math, params};
+
 pub mod blockchain;
 pub mod network;
 pub mod security;
 pub mod orchestration;
-pub mod crypto;
 pub mod layers;
+pub mod consensus;
 
 // Re-export security test functions
 pub use security::tests::{run_security_tests, run_stress_test, simulate_quantum_attack, perform_network_security_audit};
@@ -13,9 +16,22 @@ pub use orchestration::{Orchestrator, OrchestrationMetrics, RealityLayer, Quantu
 pub mod identity;
 pub mod governance;
 pub mod economics;
-pub mod math;
+#[cfg(feature = "hubble")]
 pub mod hubble;
 pub mod storage;
+#[cfg(feature = "web2")]
 pub mod web2;
 pub mod web3;
+#[cfg(feature = "vm-js")]
 pub mod vm;
+pub mod lifecycle;
+pub mod config;
+pub mod export;
+pub mod extensions;
+pub mod telemetry;
+pub mod crash;
+pub mod trace;
+pub mod health;
+pub mod wallet;
+#[cfg(feature = "fault-injection")]
+pub mod chaos;
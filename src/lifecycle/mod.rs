@@ -0,0 +1,283 @@
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::sync::{watch, Notify};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+pub mod pools;
+
+type ShutdownHook = Pin<Box<dyn Future<Output = Result<(), String>> + Send>>;
+
+/// Cloneable handle that resolves once node shutdown has begun
+#[derive(Clone)]
+pub struct ShutdownSignal {
+    receiver: watch::Receiver<bool>,
+}
+
+impl ShutdownSignal {
+    /// Wait until shutdown is requested
+    pub async fn wait(&mut self) {
+        while !*self.receiver.borrow() {
+            if self.receiver.changed().await.is_err() {
+                break;
+            }
+        }
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        *self.receiver.borrow()
+    }
+}
+
+/// Counts in-flight connections so listeners can drain before exit
+#[derive(Clone, Default)]
+pub struct ConnectionTracker {
+    active: Arc<AtomicUsize>,
+    idle: Arc<Notify>,
+}
+
+/// Decrements the tracker's active count when dropped
+pub struct ConnectionGuard {
+    tracker: ConnectionTracker,
+}
+
+impl ConnectionTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a new connection for the lifetime of the returned guard
+    pub fn track(&self) -> ConnectionGuard {
+        self.active.fetch_add(1, Ordering::SeqCst);
+        ConnectionGuard { tracker: self.clone() }
+    }
+
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::SeqCst)
+    }
+
+    /// Wait until every tracked connection has closed
+    pub async fn wait_idle(&self) {
+        loop {
+            let notified = self.idle.notified();
+            if self.active() == 0 {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        if self.tracker.active.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.tracker.idle.notify_waiters();
+        }
+    }
+}
+
+/// Outcome of an orderly shutdown
+#[derive(Debug, Default)]
+pub struct ShutdownReport {
+    /// Services that stopped within the drain timeout, in the order they stopped
+    pub stopped: Vec<String>,
+    /// Services that had to be aborted
+    pub aborted: Vec<String>,
+    /// Shutdown hooks that returned an error
+    pub failed_hooks: Vec<(String, String)>,
+}
+
+/// Node Lifecycle Manager
+/// Starts services in order, listens for termination signals, and drains
+/// all services together before running flush/snapshot hooks.
+pub struct LifecycleManager {
+    shutdown_tx: watch::Sender<bool>,
+    services: Vec<(String, JoinHandle<()>)>,
+    shutdown_hooks: Vec<(String, ShutdownHook)>,
+    /// Time all services together get to stop after the signal
+    drain_timeout: Duration,
+}
+
+impl LifecycleManager {
+    pub fn new(drain_timeout: Duration) -> Self {
+        let (shutdown_tx, _) = watch::channel(false);
+        Self {
+            shutdown_tx,
+            services: Vec::new(),
+            shutdown_hooks: Vec::new(),
+            drain_timeout,
+        }
+    }
+
+    /// Get a signal that services can select on to stop accepting work
+    pub fn signal(&self) -> ShutdownSignal {
+        ShutdownSignal {
+            receiver: self.shutdown_tx.subscribe(),
+        }
+    }
+
+    /// Spawn a long-running service; it stops when the shutdown signal fires
+    pub fn start_service<F>(&mut self, name: &str, service: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        info!(service = name, "Starting service");
+        self.services.push((name.to_string(), tokio::spawn(service)));
+    }
+
+    /// Spawn a long-running service on a specific runtime
+    pub fn start_service_on<F>(&mut self, name: &str, runtime: &tokio::runtime::Handle, service: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        info!(service = name, "Starting service");
+        self.services.push((name.to_string(), runtime.spawn(service)));
+    }
+
+    /// Register work to run after all services have stopped.
+    /// Hooks run in registration order, so register flushes before snapshots.
+    pub fn on_shutdown<F>(&mut self, name: &str, hook: F)
+    where
+        F: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        self.shutdown_hooks.push((name.to_string(), Box::pin(hook)));
+    }
+
+    /// Wait for Ctrl+C or, on unix, SIGTERM
+    pub async fn wait_for_termination(&self) -> Result<(), std::io::Error> {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+            let mut terminate = signal(SignalKind::terminate())?;
+            tokio::select! {
+                result = tokio::signal::ctrl_c() => result,
+                _ = terminate.recv() => Ok(()),
+            }
+        }
+
+        #[cfg(not(unix))]
+        {
+            tokio::signal::ctrl_c().await
+        }
+    }
+
+    /// Signal shutdown, drain all services concurrently, abort those still
+    /// running when the drain timeout ends, then run hooks
+    pub async fn shutdown(mut self) -> ShutdownReport {
+        let mut report = ShutdownReport::default();
+        let _ = self.shutdown_tx.send(true);
+
+        let deadline = tokio::time::Instant::now() + self.drain_timeout;
+        let mut draining: FuturesUnordered<_> = self.services.drain(..)
+            .map(|(name, mut handle)| async move {
+                let drained = tokio::time::timeout_at(deadline, &mut handle).await.is_ok();
+                if !drained {
+                    handle.abort();
+                }
+                (name, drained)
+            })
+            .collect();
+        while let Some((name, drained)) = draining.next().await {
+            if drained {
+                info!(service = %name, "Stopped service");
+                report.stopped.push(name);
+            } else {
+                warn!(service = %name, "Service did not drain in time, aborting");
+                report.aborted.push(name);
+            }
+        }
+
+        for (name, hook) in self.shutdown_hooks.drain(..) {
+            if let Err(e) = hook.await {
+                warn!(hook = %name, error = %e, "Shutdown hook failed");
+                report.failed_hooks.push((name, e));
+            }
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[tokio::test]
+    async fn test_services_drain_before_hooks() {
+        let mut lifecycle = LifecycleManager::new(Duration::from_secs(1));
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        for name in ["p2p", "rpc"] {
+            let mut signal = lifecycle.signal();
+            let order = order.clone();
+            let delay = if name == "p2p" { 50 } else { 0 };
+            lifecycle.start_service(name, async move {
+                signal.wait().await;
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                order.lock().unwrap().push(name);
+            });
+        }
+
+        let hook_order = order.clone();
+        lifecycle.on_shutdown("snapshot", async move {
+            hook_order.lock().unwrap().push("snapshot");
+            Ok(())
+        });
+
+        let report = lifecycle.shutdown().await;
+        assert_eq!(report.stopped, vec!["rpc".to_string(), "p2p".to_string()]);
+        assert!(report.aborted.is_empty());
+        assert_eq!(*order.lock().unwrap(), vec!["rpc", "p2p", "snapshot"]);
+    }
+
+    #[tokio::test]
+    async fn test_services_share_one_deadline() {
+        let mut lifecycle = LifecycleManager::new(Duration::from_millis(100));
+        for name in ["p2p", "rpc", "sync"] {
+            let mut signal = lifecycle.signal();
+            lifecycle.start_service(name, async move {
+                signal.wait().await;
+                tokio::time::sleep(Duration::from_millis(60)).await;
+            });
+        }
+
+        // Drained one after another, the three would need 180ms
+        let started = std::time::Instant::now();
+        let report = lifecycle.shutdown().await;
+        assert_eq!(report.stopped.len(), 3);
+        assert!(started.elapsed() < Duration::from_millis(150));
+    }
+
+    #[tokio::test]
+    async fn test_stuck_service_is_aborted() {
+        let mut lifecycle = LifecycleManager::new(Duration::from_millis(10));
+        lifecycle.start_service("stuck", async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+        });
+        lifecycle.on_shutdown("flush", async { Err("disk full".to_string()) });
+
+        let report = lifecycle.shutdown().await;
+        assert_eq!(report.aborted, vec!["stuck".to_string()]);
+        assert_eq!(report.failed_hooks.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_connection_tracker_drains() {
+        let tracker = ConnectionTracker::new();
+        let guard = tracker.track();
+        assert_eq!(tracker.active(), 1);
+
+        let waiter = tracker.clone();
+        let handle = tokio::spawn(async move { waiter.wait_idle().await });
+        drop(guard);
+
+        tokio::time::timeout(Duration::from_secs(1), handle).await
+            .expect("tracker should drain")
+            .unwrap();
+    }
+}
@@ -0,0 +1,197 @@
+use serde::Serialize;
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Instant;
+use tokio::runtime::{Builder, Handle};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+/// Which runtime pool a task runs on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lane {
+    /// Block validation, consensus and peer networking
+    Critical,
+    /// Web2 jobs, billing, maintenance and storage rebuilds
+    Background,
+}
+
+/// Point-in-time load of one pool
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolSaturation {
+    pub name: &'static str,
+    pub workers: usize,
+    /// Async tasks spawned on the pool that have not finished
+    pub active_tasks: usize,
+    /// Blocking (CPU-heavy) jobs currently running or queued
+    pub blocking_tasks: usize,
+    pub completed: u64,
+    /// Total time spent inside blocking jobs
+    pub blocking_busy_ms: u64,
+    /// Running async tasks per worker, in percent
+    pub saturation_percent: u64,
+}
+
+#[derive(Default)]
+struct PoolCounters {
+    active_tasks: AtomicUsize,
+    blocking_tasks: AtomicUsize,
+    completed: AtomicU64,
+    blocking_busy_us: AtomicU64,
+}
+
+struct Pool {
+    name: &'static str,
+    handle: Handle,
+    workers: usize,
+    counters: Arc<PoolCounters>,
+}
+
+impl Pool {
+    fn saturation(&self) -> PoolSaturation {
+        let active_tasks = self.counters.active_tasks.load(Ordering::Relaxed);
+        PoolSaturation {
+            name: self.name,
+            workers: self.workers,
+            active_tasks,
+            blocking_tasks: self.counters.blocking_tasks.load(Ordering::Relaxed),
+            completed: self.counters.completed.load(Ordering::Relaxed),
+            blocking_busy_ms: self.counters.blocking_busy_us.load(Ordering::Relaxed) / 1_000,
+            saturation_percent: (active_tasks * 100 / self.workers.max(1)) as u64,
+        }
+    }
+}
+
+/// Decrements a pool's active count when a task finishes or is dropped
+struct TaskGuard {
+    counters: Arc<PoolCounters>,
+}
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        self.counters.active_tasks.fetch_sub(1, Ordering::Relaxed);
+        self.counters.completed.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Runtime Pools
+/// Keeps consensus and networking on the node's main runtime and moves
+/// background work onto a separate runtime so it cannot starve validation.
+#[derive(Clone)]
+pub struct RuntimePools {
+    critical: Arc<Pool>,
+    background: Arc<Pool>,
+    /// Dropping the last clone stops the background runtime
+    _stop: Arc<oneshot::Sender<()>>,
+}
+
+impl RuntimePools {
+    /// Must be called from within the critical (main) runtime
+    pub fn new(critical_workers: usize, background_workers: usize) -> std::io::Result<Self> {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(background_workers.max(1))
+            .thread_name("background")
+            .enable_all()
+            .build()?;
+        let background_handle = runtime.handle().clone();
+
+        // The runtime lives on its own thread so dropping it never happens inside async code
+        let (stop_tx, stop_rx) = oneshot::channel::<()>();
+        std::thread::Builder::new()
+            .name("background-runtime".to_string())
+            .spawn(move || {
+                runtime.block_on(async {
+                    let _ = stop_rx.await;
+                });
+            })?;
+
+        Ok(Self {
+            critical: Arc::new(Pool {
+                name: "critical",
+                handle: Handle::current(),
+                workers: critical_workers,
+                counters: Arc::default(),
+            }),
+            background: Arc::new(Pool {
+                name: "background",
+                handle: background_handle,
+                workers: background_workers.max(1),
+                counters: Arc::default(),
+            }),
+            _stop: Arc::new(stop_tx),
+        })
+    }
+
+    fn pool(&self, lane: Lane) -> &Pool {
+        match lane {
+            Lane::Critical => &self.critical,
+            Lane::Background => &self.background,
+        }
+    }
+
+    pub fn handle(&self, lane: Lane) -> &Handle {
+        &self.pool(lane).handle
+    }
+
+    /// Spawn an async task on the given lane
+    pub fn spawn<F>(&self, lane: Lane, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let pool = self.pool(lane);
+        pool.counters.active_tasks.fetch_add(1, Ordering::Relaxed);
+        let guard = TaskGuard { counters: pool.counters.clone() };
+        pool.handle.spawn(async move {
+            let _guard = guard;
+            future.await
+        })
+    }
+
+    /// Run CPU-heavy work (proof checks, tally hashing) off the async workers
+    pub fn spawn_blocking<F, R>(&self, lane: Lane, job: F) -> JoinHandle<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let pool = self.pool(lane);
+        let counters = pool.counters.clone();
+        counters.blocking_tasks.fetch_add(1, Ordering::Relaxed);
+        pool.handle.spawn_blocking(move || {
+            let started = Instant::now();
+            let result = job();
+            counters.blocking_busy_us.fetch_add(started.elapsed().as_micros() as u64, Ordering::Relaxed);
+            counters.blocking_tasks.fetch_sub(1, Ordering::Relaxed);
+            counters.completed.fetch_add(1, Ordering::Relaxed);
+            result
+        })
+    }
+
+    pub fn saturation(&self) -> Vec<PoolSaturation> {
+        vec![self.critical.saturation(), self.background.saturation()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_background_lane_is_separate() {
+        let pools = RuntimePools::new(2, 1).unwrap();
+
+        let thread = pools.spawn(Lane::Background, async {
+            std::thread::current().name().map(str::to_string)
+        }).await.unwrap();
+        assert_eq!(thread.as_deref(), Some("background"));
+
+        let sum = pools.spawn_blocking(Lane::Background, || (0..1_000u64).sum::<u64>()).await.unwrap();
+        assert_eq!(sum, 499_500);
+
+        let stats = pools.saturation();
+        assert_eq!(stats[1].name, "background");
+        assert_eq!(stats[1].completed, 2);
+        assert_eq!(stats[1].active_tasks, 0);
+        assert_eq!(stats[1].blocking_tasks, 0);
+    }
+}
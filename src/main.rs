@@ -1,420 +1,5813 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, VecDeque};
 use serde::{Deserialize, Serialize};
 use quantum_metaverse::security::tests::{run_security_tests, run_stress_test, simulate_quantum_attack, perform_network_security_audit};
 use futures::{SinkExt, StreamExt};
-use tokio::net::TcpListener;
 use tokio_tungstenite::accept_async;
 use serde_json::json;
-use quantum_metaverse::orchestration::Orchestrator;
+#[cfg(feature = "metaverse")]
+use quantum_metaverse::orchestration::{OrchestrationMetrics, Orchestrator};
+#[cfg(feature = "metaverse")]
+use quantum_metaverse::orchestration::access::{AccessCredentials, LayerController, PolicyChange, PresenceAnnouncement};
+#[cfg(feature = "metaverse")]
+use quantum_metaverse::economics::providers::ProviderKind;
+use quantum_metaverse::orchestration::audit::{self, ConsensusEvaluation};
+use quantum_metaverse::lifecycle::{ConnectionTracker, LifecycleManager, ShutdownSignal};
+use quantum_metaverse::lifecycle::pools::{Lane, RuntimePools};
+use quantum_metaverse::config::{BlockBuilderConfig, BuilderStrategy, CompressionConfig, ConfigManager, FastSyncConfig, DataClass, KeystoreConfig, NodeConfig, ReloadReport, SentryRole};
+use quantum_metaverse::network::rpc::{
+    self, client_ip, cors_origin, CertificateStore, Methods, RPCError, RPCRequest, RPCResponse, RateLimiter,
+};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{mpsc, watch, RwLock};
+use hickory_resolver::TokioAsyncResolver;
+use tracing_subscriber::{filter::LevelFilter, prelude::*, reload, Registry};
+use clap::{Parser, Subcommand};
+use quantum_metaverse::storage::corpus::{Corpus, ObserverBond};
+use quantum_metaverse::storage::database::NodeDatabase;
+use quantum_metaverse::storage::reindex::{self, Reindexer};
+use quantum_metaverse::storage::remote::RemoteStorage;
+use quantum_metaverse::layers::l3_private::ChainConfig;
+use quantum_metaverse::layers::private_host::{bill_hosting, LockedChain, PrivateChainHost, ResourceQuota, TenantSummary};
+use quantum_metaverse::governance::dao::{Ballot, Charter, DaoFactory, DaoHooks, DaoId, DaoScope, SignedProposal};
+use quantum_metaverse::orchestration::access::AccessPolicy;
+use quantum_metaverse::blockchain::execution::{Executor, Receipt};
+use quantum_metaverse::blockchain::mempool::{Admission, Mempool, MempoolConfig};
+use quantum_metaverse::blockchain::commit_reveal::{Commitment, CommitRevealQueue, OrderingViolation};
+use quantum_metaverse::crypto::domain::{SigningDomain, MAINNET_NETWORK_ID};
+use quantum_metaverse::blockchain::builder::{BlockBuilder, BundleLimits, FairFifo, FeeGreedy, GovernanceBoosted, OrderingStrategy};
+use quantum_metaverse::blockchain::sealer::{self, BlockTimeConfig, SealMode};
+use quantum_metaverse::blockchain::market::ListingKind;
+use quantum_metaverse::blockchain::asset_metadata::{self, MediaStatus};
+use quantum_metaverse::blockchain::confidential::CONFIDENTIAL_POOL_ADDRESS;
+use quantum_metaverse::blockchain::channel::{SignedUpdate, Watchtower, CHANNEL_ESCROW_ADDRESS};
+use quantum_metaverse::blockchain::htlc::{self, HtlcAction, HtlcStatus, HtlcTerms, HTLC_ESCROW_ADDRESS};
+use quantum_metaverse::crypto::pedersen::OpeningProof;
+#[cfg(feature = "hubble")]
+use quantum_metaverse::hubble::{
+    admission::{AdmissionParams, SubmissionGuard, SubmissionProof},
+    index::{ContentDocument, ContentIndex, SearchHit},
+    segments::SegmentStore,
+    tokenize::Language,
+};
+use quantum_metaverse::blockchain::multisig::{Approval, ApprovalPool, MultisigOperation, MultisigProposal, PendingProposal};
+use quantum_metaverse::blockchain::logs::{LogFilter, LogIndex, MAX_LOG_RANGE};
+use quantum_metaverse::blockchain::metering::{ContractMeter, ContractRanking, DEFAULT_RANKING_WINDOW};
+use quantum_metaverse::blockchain::wire::{MessageKind, MessageView};
+use quantum_metaverse::blockchain::snapshot::{self, ChunkRequest, Snapshot, SnapshotChunk, SnapshotDownload, SnapshotManifest, KEPT_SNAPSHOTS};
+use quantum_metaverse::blockchain::beacon::{Contribution, RandomnessBeacon};
+use quantum_metaverse::blockchain::circuit_breaker::{CircuitBreaker, HaltScope, HaltVote};
+use quantum_metaverse::blockchain::invariants;
+use quantum_metaverse::blockchain::features::{Feature, FeatureTracker};
+use quantum_metaverse::epoch::{DutyOutcome, EpochBoundary, EpochDuty, EpochManager};
+use quantum_metaverse::params::{ParamKey, ParamsRegistry};
+use quantum_metaverse::blockchain::state::{StateDiff, StateStore, WorldState};
+use quantum_metaverse::blockchain::transaction::{Transaction, TransactionAction};
+use quantum_metaverse::wallet::{parse_derivation_path, SignerKind, SoftwareSigner, TransactionSigner};
+use quantum_metaverse::wallet::display::review;
+#[cfg(feature = "ledger")]
+use quantum_metaverse::wallet::ledger::LedgerSigner;
+use ed25519_dalek::SigningKey;
+use quantum_metaverse::export::{self, EventKind, EventQueue, Exporter, HeightEvents, OffsetStore};
+#[cfg(feature = "vm-wasm")]
+use quantum_metaverse::extensions::runtime::ExtensionHost;
+use quantum_metaverse::telemetry::{jittered_interval, HealthReport, TelemetryBuffer, TelemetryClient, ThroughputMeter, REPORT_VERSION};
+use quantum_metaverse::orchestration::tally::compute::tallies_computed;
+use quantum_metaverse::crash::{self, config_digest, CrashReporter, LogRing, ReportBundle, LOG_RING_LINES};
+use quantum_metaverse::storage::history::VersionedMap;
+use quantum_metaverse::trace::{self, TraceId, TraceLog, TraceStage, TRACE_HEADER};
+use quantum_metaverse::health::{Check, HealthRegistry, HealthStatus, HealthSummary};
+use metaverse_client::RpcClient;
+#[cfg(feature = "bridges")]
+use quantum_metaverse::{
+    layers::watchtower::FraudProof,
+    web3::insurance::InsuranceFund,
+    web3::reserve::{ReserveLedger, ReserveLog},
+};
 
 use quantum_metaverse::{
     security::quantum_resistant::QuantumKey,
+    consensus::{build_engine, ConsensusDriver, LocalValidator, Output, Timeout, Value},
+    consensus::driver::{block_value, check_block_value},
+    consensus::transport::{Envelope, SeenEnvelopes},
+    layers::validator_set::ValidatorSet,
     blockchain::{
-        core::Blockchain,
-        flux::FluxNetwork,
+        core::{Block, BlockHeader, Blockchain},
+        flux::{FluxNetwork, NodeState},
+        sync::{self, BlockBody, BodiesRequest, HeadersRequest, SyncEngine},
+        verdict::{self, ValidationCase},
+        types::QuantumNodeID,
         zk_storage::ZKStorage,
     },
     network::QuantumNetwork,
+    network::qkd::SimulatedQkd,
+    network::p2p::{Handshake, P2PNetwork},
+    network::certs::{CertificateRegistry, CertificateRevocation, NodeCertificate, Permissions},
+    network::sentry::{SentrySet, CONSENSUS_MESSAGES},
+    network::diversity::PeerDiversity,
+    network::listen::{self, Listeners},
+    network::compression::{self, Codec, Payload},
+    network::seeds::{self, SeedBook, SeedRecord},
+    network::observations::{LayerAggregate, ObservationGossip, TallyObservation, AGGREGATE_TOPIC, OBSERVATION_TOPIC},
+    network::relay::{self, RelayOutcome},
     security::quantum_resistant::QuantumSecurity,
-    identity::zk_identity::ZKIdentity,
-    governance::ai_governance::{AIGovernance, Rule},
+    security::keystore::Keystore,
+    identity::{attestation::{Attestation, Revocation}, zk_identity::ZKIdentity},
+    identity::predicate::{self, AttributeProof, Predicate, PredicateChallenges, PredicateProof},
+    governance::ai_governance::{AIGovernance, Action, Rule},
     economics::models::EconomicModel,
+    economics::providers::{Provider, ProviderId},
     math::precision::PreciseFloat,
+    ids::{self, ChainId, IdKind, IdentityId, NodeId, TypedId},
+    clock,
 };
 
-const PRECISION: u8 = 20;
-const NETWORK_PORT: u16 = 8545;
-const P2P_PORT: u16 = 30303;
+const DEFAULT_CONFIG_PATH: &str = "config/node.json";
+/// Environment variable holding the keystore passphrase
+const KEYSTORE_PASSPHRASE_ENV: &str = "METAVERSE_KEYSTORE_PASSPHRASE";
+/// Environment variable holding the token for operator-only RPC methods
+const OPERATOR_TOKEN_ENV: &str = "METAVERSE_OPERATOR_TOKEN";
+const DATA_DIR: &str = "data";
+const DB_PATH: &str = "data/db";
+const REMOTE_MANIFEST_PATH: &str = "data/remote-manifest.json";
+const TELEMETRY_BUFFER_PATH: &str = "data/telemetry.json";
+const CRASH_DIR: &str = "data/crashes";
+/// Transactions whose trace events are kept for `getTransactionTrace`
+const TRACE_LOG_CAPACITY: usize = 10_000;
+#[cfg(feature = "hubble")]
+const HUBBLE_DIR: &str = "data/hubble";
+/// Content added since the last commit is lost if the node crashes
+#[cfg(feature = "hubble")]
+const HUBBLE_COMMIT_INTERVAL_SECS: u64 = 10;
+const REMOTE_LIFECYCLE_INTERVAL_SECS: u64 = 3600;
+const DRAIN_TIMEOUT_SECS: u64 = 10;
+const MEMPOOL_CHECK_INTERVAL_SECS: u64 = 5;
+const MAX_HEADERS_PER_REQUEST: u64 = 1_000;
+/// Most transactions a pool-wide `getPendingTransactions` lists
+const MAX_PENDING_LISTED: u64 = 1_000;
+/// Deepest attestation neighborhood `getAttestations` will walk
+const MAX_ATTESTATION_DEPTH: u64 = 3;
+const BEACON_CHECK_INTERVAL_SECS: u64 = 5;
+const EPOCH_CHECK_INTERVAL_SECS: u64 = 5;
+const FEATURE_CHECK_INTERVAL_SECS: u64 = 5;
+const CRASH_FINGERPRINT_INTERVAL_SECS: u64 = 5;
+const STATE_HISTORY_INTERVAL_SECS: u64 = 5;
+const SNAPSHOT_CHECK_INTERVAL_SECS: u64 = 5;
+const FLUX_REBALANCE_INTERVAL_SECS: u64 = 30;
+const SENTRY_PROBE_TIMEOUT_SECS: u64 = 3;
+/// How long a sync peer has to connect or answer one request
+const SYNC_REQUEST_TIMEOUT_SECS: u64 = 30;
+/// Pause before a sync peer with nothing to fetch checks again
+const SYNC_IDLE_MS: u64 = 200;
+/// Pause before reconnecting to a validator whose connection dropped
+const VALIDATOR_REDIAL_SECS: u64 = 5;
+/// Pending transactions at which the node reports full load to flux routing
+const FLUX_MEMPOOL_CAPACITY: usize = 10_000;
+const EXPORT_INTERVAL_SECS: u64 = 2;
+/// Heights gathered per export round; later ones wait for the next round
+const EXPORT_BATCH_BLOCKS: u64 = 100;
+/// Must match `worker_threads` on `main`, which runs consensus and networking
+const CRITICAL_WORKERS: usize = 4;
 
-#[tokio::main(flavor = "multi_thread", worker_threads = 4)]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    println!("Initializing Quantum Metaverse Blockchain...");
+#[derive(Parser)]
+#[command(name = "quantum_metaverse", about = "Quantum Metaverse Blockchain node")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
 
-    // Initialize core components
-    let mut blockchain = Blockchain::new(PRECISION);
-    let _flux_network = FluxNetwork::new(PRECISION);
-    let _storage = ZKStorage::new(PRECISION);
-    let _quantum_network = QuantumNetwork::new(PRECISION);
-    let mut security = QuantumSecurity::new(PRECISION);
-    let mut identity = ZKIdentity::new(PRECISION);
-    let mut governance = AIGovernance::new(PRECISION);
-    let _economics = EconomicModel::new(PRECISION);
+#[derive(Subcommand)]
+enum Command {
+    /// Database maintenance
+    Db {
+        /// Database directory
+        #[arg(long, default_value = DB_PATH)]
+        path: String,
+        #[command(subcommand)]
+        action: DbCommand,
+    },
+    /// Manage private chains hosted by a running node
+    Private {
+        /// RPC port of the running node
+        #[arg(long, default_value_t = 8545)]
+        rpc_port: u16,
+        #[command(subcommand)]
+        action: PrivateCommand,
+    },
+    /// Convert between raw and checksummed IDs
+    Id {
+        #[command(subcommand)]
+        action: IdCommand,
+    },
+    /// Sign transactions with a key file or a hardware wallet
+    Wallet {
+        /// local (key file) or ledger
+        #[arg(long, default_value = "local")]
+        signer: SignerKind,
+        /// Hex-encoded ed25519 secret key, for the local signer
+        #[arg(long)]
+        key_file: Option<String>,
+        /// Derivation path on the hardware wallet
+        #[arg(long, default_value = "m/44'/7777'/0'")]
+        derivation_path: String,
+        /// Sign payloads the device cannot show in full
+        #[arg(long)]
+        allow_blind_signing: bool,
+        #[command(subcommand)]
+        action: WalletCommand,
+    },
+    /// Collect approvals for multisig operations on a running node
+    Multisig {
+        /// RPC port of the running node
+        #[arg(long, default_value_t = 8545)]
+        rpc_port: u16,
+        #[command(subcommand)]
+        action: MultisigCommand,
+    },
+    /// Submit a signed transaction, as printed by `wallet`, to a running node
+    Submit {
+        /// RPC port of the running node
+        #[arg(long, default_value_t = 8545)]
+        rpc_port: u16,
+        /// JSON file holding the signed transaction
+        file: String,
+    },
+    /// Atomic swaps between mainnet and a hosted private chain
+    Swap {
+        /// RPC port of the running node
+        #[arg(long, default_value_t = 8545)]
+        rpc_port: u16,
+        #[command(subcommand)]
+        action: SwapCommand,
+    },
+    /// Snapshots, backups and blobs mirrored to remote storage
+    Remote {
+        #[command(subcommand)]
+        action: RemoteCommand,
+    },
+    /// Collect crash reports and config for filing an issue
+    Report {
+        #[command(subcommand)]
+        action: ReportCommand,
+    },
+    /// Opt in to or out of anonymized health reporting
+    Telemetry {
+        /// RPC port of a running node to reload after the change
+        #[arg(long, default_value_t = 8545)]
+        rpc_port: u16,
+        #[command(subcommand)]
+        action: TelemetryCommand,
+    },
+    /// Issue and revoke node certificates for a permissioned network
+    Cert {
+        /// RPC port of the running node
+        #[arg(long, default_value_t = 8545)]
+        rpc_port: u16,
+        #[command(subcommand)]
+        action: CertCommand,
+    },
+    /// Rebuild indexes derived from stored blocks
+    Index {
+        /// Database directory
+        #[arg(long, default_value = DB_PATH)]
+        path: String,
+        #[command(subcommand)]
+        action: IndexCommand,
+    },
+    /// Record or replay a corpus of blocks and tally observations
+    Corpus {
+        #[command(subcommand)]
+        action: CorpusCommand,
+    },
+    /// Read validation cases on stdin and write the verdict on each, one
+    /// JSON line per case, for differential fuzzing against this binary
+    ValidateBlocks,
+    /// Manage keys in the node's keystore
+    Key {
+        #[command(subcommand)]
+        action: KeyCommand,
+    },
+    /// Publish and inspect DNS seed records
+    Seed {
+        /// RPC port of the running node
+        #[arg(long, default_value_t = 8545)]
+        rpc_port: u16,
+        #[command(subcommand)]
+        action: SeedCommand,
+    },
+}
 
-    // Generate genesis configuration
-    let genesis_config = generate_genesis_config();
-    
-    // Initialize network security
-    println!("Initializing quantum-resistant security layer...");
-    let (node_key_id, node_key) = security.generate_key_pair()?;
+#[derive(Subcommand)]
+enum SeedCommand {
+    /// Print the TXT record listing `peers` for a seed domain, signed if a
+    /// key file is given
+    Record {
+        domain: String,
+        /// Peer addresses (ip:port)
+        peers: Vec<String>,
+        /// Must increase with every change to the domain's list
+        #[arg(long)]
+        sequence: u64,
+        /// Hex-encoded ed25519 secret key whose public key nodes have in `seeds.dns`
+        #[arg(long)]
+        key_file: Option<String>,
+        #[arg(long, default_value_t = 1)]
+        network_id: u64,
+    },
+    /// Show each seed's last lookup and the resulting bootstrap order
+    Status,
+}
 
-    // Initialize node identity
-    println!("Creating node identity...");
-    let (node_id, _node_identity) = identity.create_identity(vec![])?;
+#[derive(Subcommand)]
+enum KeyCommand {
+    /// Generate a key and store it under a name
+    New {
+        name: String,
+    },
+    /// List stored keys; needs no passphrase
+    List,
+    /// Print a stored key's ID and public keys
+    Show {
+        name: String,
+    },
+    /// Sign a file with a stored key and print the signature
+    Sign {
+        name: String,
+        file: String,
+    },
+}
 
-    // Initialize governance policies
-    println!("Initializing AI governance policies...");
-    let governance_rules: Vec<Rule> = vec![];
-    let governance_weights = vec![];
-    let governance_threshold = PreciseFloat::new(90, 2);
-    let _policy_id = governance.create_policy(
-        governance_rules,
-        governance_weights,
-        governance_threshold
-    )?;
+#[derive(Subcommand)]
+enum CorpusCommand {
+    /// Record the blocks of a stopped devnet node, and optionally tally
+    /// observations it received, into one JSON file
+    Record {
+        /// Database directory
+        #[arg(long, default_value = DB_PATH)]
+        path: String,
+        /// Tally observations, one JSON object per line
+        #[arg(long)]
+        observations: Option<String>,
+        /// Bond given to each observer of the observations, in tokens
+        #[arg(long, default_value_t = 1_000.0)]
+        observer_bond: f64,
+        /// Network the observations were signed for
+        #[arg(long, default_value_t = MAINNET_NETWORK_ID)]
+        network_id: u64,
+        #[arg(long, default_value = "corpus.json")]
+        out: String,
+    },
+    /// Replay a corpus and stop at the first result that differs from it
+    Replay {
+        file: String,
+    },
+}
 
-    // Start network services
-    println!("Starting network services...");
-    println!("RPC endpoint: http://localhost:{}", NETWORK_PORT);
-    println!("P2P endpoint: tcp://localhost:{}", P2P_PORT);
+#[derive(Subcommand)]
+enum IndexCommand {
+    /// Re-execute stored blocks to rebuild the transaction index and
+    /// receipts, then re-tokenize the Hubble index. Resumes from the last
+    /// checkpoint unless --restart is given.
+    Rebuild {
+        /// Discard the checkpoint and rebuild from genesis
+        #[arg(long)]
+        restart: bool,
+        /// Leave the Hubble index as it is
+        #[cfg(feature = "hubble")]
+        #[arg(long)]
+        skip_hubble: bool,
+    },
+}
 
-    // Initialize P2P networking
-    let bootstrap_nodes = genesis_config.bootstrap_nodes.clone();
-    let p2p_config = P2PConfig {
-        port: P2P_PORT,
-        _node_key: node_key,
-        _node_id: node_id,
-        _bootstrap_nodes: bootstrap_nodes,
-    };
+#[derive(Subcommand)]
+enum ReportCommand {
+    /// Package crash reports, the config and system details into one JSON file
+    Bundle {
+        #[arg(long, default_value = "metaverse-report.json")]
+        out: String,
+    },
+}
 
-    // Start services
-    tokio::spawn(async move {
-        if let Err(e) = run_p2p_network(p2p_config).await {
-            eprintln!("P2P network error: {}", e);
-        }
-    });
+#[derive(Subcommand)]
+enum TelemetryCommand {
+    /// Start sending health reports
+    Enable {
+        /// Collector URL; keeps the configured one if omitted
+        #[arg(long)]
+        endpoint: Option<String>,
+    },
+    /// Stop sending health reports
+    Disable,
+    /// Show the telemetry settings and reports waiting for delivery
+    Status,
+}
 
-    tokio::spawn(async move {
-        if let Err(e) = run_rpc_server(NETWORK_PORT).await {
-            eprintln!("RPC server error: {}", e);
-        }
-    });
+#[derive(Subcommand)]
+enum CertCommand {
+    /// Print the public key of a key file, for `permissioned.authorities` or `cert issue`
+    PublicKey {
+        /// Hex-encoded ed25519 secret key
+        key_file: String,
+    },
+    /// Issue a certificate for a node key and print it as JSON
+    Issue {
+        /// Node public key (hex)
+        node_key: String,
+        /// Hex-encoded ed25519 secret key of the issuing authority
+        #[arg(long)]
+        authority_key_file: String,
+        /// Must be unique among the authority's certificates
+        #[arg(long)]
+        serial: u64,
+        #[arg(long, default_value_t = 365)]
+        valid_days: u64,
+        #[arg(long, default_value_t = 1)]
+        network_id: u64,
+    },
+    /// Revoke a certificate and submit the revocation for gossip
+    Revoke {
+        serial: u64,
+        /// Hex-encoded ed25519 secret key of the authority that issued it
+        #[arg(long)]
+        authority_key_file: String,
+        #[arg(long, default_value_t = 1)]
+        network_id: u64,
+    },
+    /// Show the node's authorities, known revocations and certified peers
+    List,
+}
 
-    // Start blockchain synchronization
-    println!("Starting blockchain synchronization...");
-    sync_blockchain(&mut blockchain, &genesis_config).await?;
+#[derive(Subcommand)]
+enum WalletCommand {
+    /// Print the signer's account address
+    Address,
+    /// Sign a transfer and print the signed transaction
+    Transfer {
+        /// Recipient address (hex)
+        to: String,
+        amount: u128,
+        #[arg(long)]
+        nonce: u64,
+        #[arg(long, default_value_t = 21_000)]
+        gas_limit: u64,
+        #[arg(long, default_value_t = 1)]
+        gas_price: u128,
+        #[arg(long, default_value_t = 1)]
+        network_id: u64,
+    },
+    /// Sign an unsigned transaction read from a JSON file
+    Sign {
+        file: String,
+    },
+}
 
-    println!("\nQuantum Metaverse Blockchain is running!");
-    println!("Node ID: 0x{}", hex::encode(node_id));
-    println!("Security Level: {:.2}%", security.verify_security_level(&node_key_id)?.value as f64 / 100.0);
+#[derive(Subcommand)]
+enum MultisigCommand {
+    /// Show a multisig's signers and the proposals awaiting approval
+    Pending {
+        /// Multisig address (hex)
+        multisig: String,
+    },
+    /// Propose an operation read from a JSON file
+    Propose {
+        multisig: String,
+        file: String,
+    },
+    /// Sign a pending proposal with a key file and submit the approval
+    Approve {
+        multisig: String,
+        /// Proposal ID (hex)
+        id: String,
+        /// Hex-encoded ed25519 secret key
+        #[arg(long)]
+        key_file: String,
+        #[arg(long, default_value_t = 1)]
+        network_id: u64,
+    },
+}
 
-    // Keep the main thread running
-    loop {
-        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-    }
+#[derive(Subcommand)]
+enum SwapCommand {
+    /// Generate a swap secret and print it with its hashlock
+    Secret,
+    /// Build both legs of a swap under one hashlock: an unsigned mainnet
+    /// transaction for `wallet sign` and the `privateLockHtlc` parameters
+    Legs {
+        /// Hashlock from `swap secret` (hex)
+        #[arg(long)]
+        hashlock: String,
+        /// Mainnet recipient (hex)
+        #[arg(long)]
+        mainnet_recipient: String,
+        #[arg(long)]
+        mainnet_amount: u128,
+        /// Nonce of the mainnet sender
+        #[arg(long)]
+        nonce: u64,
+        #[arg(long)]
+        chain_id: String,
+        /// Private chain sender (hex)
+        #[arg(long)]
+        private_sender: String,
+        /// Private chain recipient (hex)
+        #[arg(long)]
+        private_recipient: String,
+        #[arg(long)]
+        private_amount: u128,
+        /// The initiator locks on the private chain rather than on mainnet
+        #[arg(long)]
+        private_first: bool,
+        /// Blocks until the second leg can be refunded; the first leg gets twice as many
+        #[arg(long, default_value_t = 100)]
+        timelock_blocks: u64,
+        #[arg(long, default_value_t = 100_000)]
+        gas_limit: u64,
+        #[arg(long, default_value_t = 1)]
+        gas_price: u128,
+        #[arg(long, default_value_t = 1)]
+        network_id: u64,
+    },
+    /// Poll both legs until each is claimed or refunded
+    Watch {
+        /// Mainnet HTLC ID (hex)
+        #[arg(long)]
+        mainnet: String,
+        #[arg(long)]
+        chain_id: String,
+        /// Private chain HTLC ID (hex)
+        #[arg(long)]
+        private: String,
+        #[arg(long, default_value_t = 5)]
+        interval_secs: u64,
+    },
 }
 
-struct P2PConfig {
-    port: u16,
-    _node_key: QuantumKey,
-    _node_id: [u8; 32],
-    _bootstrap_nodes: Vec<String>,
+#[derive(Subcommand)]
+enum RemoteCommand {
+    /// Upload a file to a data class's remote store
+    Put {
+        /// snapshots, backups or blobs
+        class: DataClass,
+        file: String,
+    },
+    /// Download an object, verifying its content hash
+    Get {
+        class: DataClass,
+        /// blake3 content hash (hex)
+        hash: String,
+        /// Output file
+        out: String,
+    },
+    /// List mirrored objects
+    List {
+        class: DataClass,
+    },
+    /// Delete objects outside their lifecycle policy
+    Prune,
 }
 
-struct GenesisConfig {
-    _chain_id: u64,
-    bootstrap_nodes: Vec<String>,
-    _initial_validators: Vec<[u8; 32]>,
-    _initial_supply: u64,
+#[derive(Subcommand)]
+enum IdCommand {
+    /// Print the kind and raw bytes of a checksummed ID
+    Decode {
+        id: String,
+    },
+    /// Encode raw hex bytes as a checksummed ID
+    Encode {
+        /// node, chain, contract, identity or data
+        kind: IdKind,
+        /// 32 bytes (hex)
+        bytes: String,
+    },
 }
 
-fn generate_genesis_config() -> GenesisConfig {
-    GenesisConfig {
-        _chain_id: 1,
-        bootstrap_nodes: vec![
-            "enode://8f8c76f8f6...@bootnode1.metaverse.network:30303".to_string(),
-            "enode://2b2b4f4f4f...@bootnode2.metaverse.network:30303".to_string(),
-        ],
-        _initial_validators: vec![
-            [0u8; 32], // Replace with actual validator addresses
-        ],
-        _initial_supply: 10_000_000_000, // 10B tokens
-    }
+#[derive(Subcommand)]
+enum PrivateCommand {
+    /// Create a hosted private chain and print its RPC token
+    Create {
+        name: String,
+        /// Owner public key (hex)
+        #[arg(long)]
+        owner: String,
+        #[arg(long)]
+        cpu_ms_per_minute: Option<u64>,
+        #[arg(long)]
+        storage_bytes: Option<u64>,
+    },
+    /// List hosted private chains with usage
+    List,
+    /// Suspend a hosted private chain
+    Suspend {
+        chain_id: String,
+    },
+    /// Resume a suspended private chain
+    Resume {
+        chain_id: String,
+    },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct P2PMessage {
-    message_type: String,
-    payload: serde_json::Value,
+#[derive(Subcommand)]
+enum DbCommand {
+    /// Report per-column-family key counts and sizes
+    Stats,
+    /// Compact all column families
+    Compact,
+    /// Verify stored blocks against their hashes
+    Verify,
+    /// Re-run the recorded consensus evaluations of an orchestration tally
+    /// and show each observer's weight and the threshold math
+    Consensus {
+        /// Tally state hash (hex)
+        state_hash: String,
+        /// Only the evaluation at this position, counting from 0
+        #[arg(long)]
+        evaluation: Option<usize>,
+        /// Print the evaluations as JSON
+        #[arg(long)]
+        json: bool,
+    },
 }
 
-async fn run_p2p_network(config: P2PConfig) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let addr = format!("127.0.0.1:{}", config.port);
-    let listener = TcpListener::bind(&addr).await?;
-    println!("P2P network listening on {}", addr);
+#[tokio::main(flavor = "multi_thread", worker_threads = 4)]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+    match cli.command {
+        Some(Command::Db { path, action }) => run_db_command(&path, action),
+        Some(Command::Private { rpc_port, action }) => run_private_command(rpc_port, action).await,
+        Some(Command::Id { action }) => run_id_command(action),
+        Some(Command::Wallet { signer, key_file, derivation_path, allow_blind_signing, action }) => {
+            let mut signer = open_signer(signer, key_file.as_deref(), &derivation_path, allow_blind_signing)?;
+            run_wallet_command(signer.as_mut(), action)
+        }
+        Some(Command::Multisig { rpc_port, action }) => run_multisig_command(rpc_port, action).await,
+        Some(Command::Submit { rpc_port, file }) => submit_transaction(rpc_port, &file).await,
+        Some(Command::Swap { rpc_port, action }) => run_swap_command(rpc_port, action).await,
+        Some(Command::Remote { action }) => run_remote_command(action).await,
+        Some(Command::Report { action }) => run_report_command(action),
+        Some(Command::Telemetry { rpc_port, action }) => run_telemetry_command(rpc_port, action).await,
+        Some(Command::Cert { rpc_port, action }) => run_cert_command(rpc_port, action).await,
+        Some(Command::Index { path, action }) => run_index_command(&path, action),
+        Some(Command::Corpus { action }) => run_corpus_command(action),
+        Some(Command::ValidateBlocks) => serve_verdicts(),
+        Some(Command::Key { action }) => run_key_command(action),
+        Some(Command::Seed { rpc_port, action }) => run_seed_command(rpc_port, action).await,
+        None => run_node().await,
+    }
+}
 
-    while let Ok((stream, _)) = listener.accept().await {
-        tokio::spawn(handle_p2p_connection(stream));
+fn serve_verdicts() -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::{BufRead, Write};
+    let mut out = std::io::stdout().lock();
+    for line in std::io::stdin().lock().lines() {
+        let case: ValidationCase = serde_json::from_str(&line?)?;
+        serde_json::to_writer(&mut out, &verdict::validate(&case))?;
+        writeln!(out)?;
+        out.flush()?;
     }
+    Ok(())
+}
 
+fn run_corpus_command(action: CorpusCommand) -> Result<(), Box<dyn std::error::Error>> {
+    match action {
+        CorpusCommand::Record { path, observations, observer_bond, network_id, out } => {
+            let blocks = NodeDatabase::open(&path)?.blocks_from(0)?.collect::<Result<Vec<_>, _>>()?;
+            let observations: Vec<TallyObservation> = match observations {
+                Some(file) => std::fs::read_to_string(file)?
+                    .lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(serde_json::from_str)
+                    .collect::<Result<_, _>>()?,
+                None => Vec::new(),
+            };
+            let observers: BTreeSet<ProviderId> = observations.iter().map(|observation| observation.observer_id).collect();
+            let bonds = observers.into_iter()
+                .map(|observer| ObserverBond { observer, stake: PreciseFloat::from_f64(observer_bond, 2) })
+                .collect();
+            let corpus = Corpus::record(network_id, &WorldState::new(), &blocks, bonds, observations)?;
+            std::fs::write(&out, serde_json::to_string_pretty(&corpus)?)?;
+            println!(
+                "Recorded {} blocks and {} observations to {}",
+                corpus.blocks.len(), corpus.observations.len(), out
+            );
+        }
+        CorpusCommand::Replay { file } => {
+            let corpus: Corpus = serde_json::from_str(&std::fs::read_to_string(&file)?)?;
+            let report = corpus.replay()?;
+            println!(
+                "Replayed {} blocks, {} transactions and {} observations; all match",
+                report.blocks, report.transactions, report.observations
+            );
+        }
+    }
     Ok(())
 }
 
-async fn handle_p2p_connection(stream: tokio::net::TcpStream) {
-    if let Ok(ws_stream) = accept_async(stream).await {
-        let (mut write, mut read) = ws_stream.split();
-        
-        while let Some(msg) = read.next().await {
-            if let Ok(msg) = msg {
-                if let Ok(p2p_msg) = serde_json::from_str::<P2PMessage>(&msg.to_string()) {
-                    println!("Received P2P message: {:?}", p2p_msg);
-                    
-                    // Echo back
-                    let _ = write.send(msg).await;
-                }
+/// Passphrase for the keystore, from `METAVERSE_KEYSTORE_PASSPHRASE`. Keys are
+/// left under an empty passphrase only if `keystore.allow_empty_passphrase` is set.
+fn keystore_passphrase(config: &KeystoreConfig) -> Result<String, String> {
+    match std::env::var(KEYSTORE_PASSPHRASE_ENV) {
+        Ok(passphrase) if !passphrase.is_empty() => Ok(passphrase),
+        _ if config.allow_empty_passphrase => {
+            eprintln!("{} is not set; keys are encrypted with an empty passphrase", KEYSTORE_PASSPHRASE_ENV);
+            Ok(String::new())
+        }
+        _ => Err(format!(
+            "{} is not set; set it, or set keystore.allow_empty_passphrase to use an empty passphrase",
+            KEYSTORE_PASSPHRASE_ENV
+        )),
+    }
+}
+
+fn run_key_command(action: KeyCommand) -> Result<(), Box<dyn std::error::Error>> {
+    let config_path = std::env::var("METAVERSE_CONFIG")
+        .unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string());
+    let config = ConfigManager::load_or_default(&config_path)?.current().clone();
+    let keystore = Keystore::open(&config.keystore.dir)?;
+    match action {
+        KeyCommand::New { name } => {
+            let (_, key) = QuantumSecurity::new(config.precision).generate_key_pair()?;
+            let id = keystore.save(&name, &key, &keystore_passphrase(&config.keystore)?)?;
+            println!("Stored key {} as `{}` in {}", hex::encode(id), name, keystore.dir().display());
+        }
+        KeyCommand::List => {
+            for key in keystore.list()? {
+                let node = if key.name == config.keystore.node_key { " (node)" } else { "" };
+                println!("{}  {}  created {}{}", key.name, hex::encode(key.id), key.creation_time, node);
             }
         }
+        KeyCommand::Show { name } => {
+            let key = keystore.load(&name, &keystore_passphrase(&config.keystore)?)?;
+            println!("{}", serde_json::to_string_pretty(&json!({
+                "id": format!("0x{}", hex::encode(key.id())),
+                "public_key": format!("0x{}", hex::encode(key.public_key())),
+                "verification_key": format!("0x{}", hex::encode(key.verification_key())),
+                "creation_time": key.creation_time(),
+            }))?);
+        }
+        KeyCommand::Sign { name, file } => {
+            let key = keystore.load(&name, &keystore_passphrase(&config.keystore)?)?;
+            println!("0x{}", hex::encode(key.sign(&std::fs::read(&file)?)?));
+        }
     }
+    Ok(())
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct RPCRequest {
-    jsonrpc: String,
-    method: String,
-    params: serde_json::Value,
-    id: u64,
+fn run_index_command(path: &str, action: IndexCommand) -> Result<(), Box<dyn std::error::Error>> {
+    match action {
+        IndexCommand::Rebuild { restart, #[cfg(feature = "hubble")] skip_hubble } => {
+            let db = NodeDatabase::open(path)?;
+            let started = std::time::Instant::now();
+            let progress = Reindexer::new(&db, WorldState::new()).run(restart, |progress| {
+                let total = progress.latest.map_or(0, |latest| latest + 1);
+                println!(
+                    "Indexed {}/{} blocks: {} transactions, {} receipts",
+                    progress.next_height, total, progress.transactions, progress.receipts
+                );
+            })?;
+            if let Some(height) = progress.resumed_from {
+                println!("Resumed from the checkpoint at block {}", height);
+            }
+            println!("Indexed {} blocks in {:.1}s", progress.blocks, started.elapsed().as_secs_f64());
+
+            #[cfg(feature = "hubble")]
+            if !skip_hubble {
+                let (mut store, _) = SegmentStore::open(HUBBLE_DIR)?;
+                let index = store.rebuild()?;
+                println!("Rebuilt Hubble index: {} documents", index.len());
+            }
+        }
+    }
+    Ok(())
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct RPCResponse {
-    jsonrpc: String,
-    result: Option<serde_json::Value>,
-    error: Option<RPCError>,
-    id: u64,
+fn run_db_command(path: &str, action: DbCommand) -> Result<(), Box<dyn std::error::Error>> {
+    let db = NodeDatabase::open(path)?;
+    match action {
+        DbCommand::Stats => {
+            println!("{:<12} {:>12} {:>14} {:>14} {:>12}", "column", "keys", "live bytes", "sst bytes", "memtable");
+            for cf in db.stats()? {
+                println!(
+                    "{:<12} {:>12} {:>14} {:>14} {:>12}",
+                    cf.name, cf.estimated_keys, cf.live_data_bytes, cf.sst_files_bytes, cf.memtable_bytes
+                );
+            }
+        }
+        DbCommand::Compact => {
+            println!("Compacting {}...", path);
+            db.compact()?;
+            println!("Compaction complete");
+        }
+        DbCommand::Verify => {
+            let report = db.verify()?;
+            println!("Checked {} blocks", report.checked);
+            for index in &report.hash_mismatches {
+                println!("Block {}: hash mismatch", index);
+            }
+            for index in &report.broken_links {
+                println!("Block {}: previous_hash does not match block {}", index, index - 1);
+            }
+            for key in &report.undecodable {
+                println!("Key 0x{}: undecodable block", key);
+            }
+            if !report.is_ok() {
+                return Err("Database verification failed".into());
+            }
+            println!("All blocks verified");
+        }
+        DbCommand::Consensus { state_hash, evaluation, json } => {
+            let state_hash: [u8; 32] = hex::decode(state_hash.trim_start_matches("0x"))?
+                .try_into()
+                .map_err(|_| "State hash must be 32 bytes")?;
+            let recorded = db.consensus_evaluations(&state_hash)?;
+            if recorded.is_empty() {
+                return Err("No recorded evaluations for this tally".into());
+            }
+            let selected: Vec<(usize, &ConsensusEvaluation)> = match evaluation {
+                Some(index) => vec![(index, recorded.get(index).ok_or("No evaluation at that position")?)],
+                None => recorded.iter().enumerate().collect(),
+            };
+
+            let replays: Vec<_> = selected.iter().map(|(_, recorded)| audit::replay(recorded)).collect();
+            if json {
+                println!("{}", serde_json::to_string_pretty(&replays)?);
+            } else {
+                for ((index, _), replay) in selected.iter().zip(&replays) {
+                    println!("Evaluation {}", index);
+                    for line in replay.replayed.explain() {
+                        println!("{}", line);
+                    }
+                    if !replay.matches() {
+                        println!("  REPLAY DIFFERS from the recorded evaluation:");
+                        for line in replay.recorded.explain() {
+                            println!("  recorded {}", line);
+                        }
+                    }
+                }
+            }
+            if replays.iter().any(|replay| !replay.matches()) {
+                return Err("Replay does not match the recorded consensus".into());
+            }
+        }
+    }
+    Ok(())
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct RPCError {
-    code: i32,
-    message: String,
-    data: Option<serde_json::Value>,
+fn run_id_command(action: IdCommand) -> Result<(), Box<dyn std::error::Error>> {
+    match action {
+        IdCommand::Decode { id } => {
+            let (kind, bytes) = ids::decode_any(&id)?;
+            println!("{} 0x{}", kind, hex::encode(bytes));
+        }
+        IdCommand::Encode { kind, bytes } => {
+            let bytes: [u8; 32] = hex::decode(bytes.trim_start_matches("0x"))?
+                .try_into()
+                .map_err(|_| "ID must be 32 bytes")?;
+            println!("{}", ids::encode(kind, &bytes));
+        }
+    }
+    Ok(())
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct NodeStatus {
-    node_id: String,
-    security_level: f64,
-    connected_peers: u32,
-    sync_status: String,
-    current_block: u64,
-    pending_transactions: u32,
-    quantum_security: bool,
-    ai_governance_active: bool,
+fn open_signer(
+    kind: SignerKind,
+    key_file: Option<&str>,
+    derivation_path: &str,
+    allow_blind_signing: bool,
+) -> Result<Box<dyn TransactionSigner>, Box<dyn std::error::Error>> {
+    let derivation = parse_derivation_path(derivation_path)?;
+    match kind {
+        SignerKind::Local => {
+            let path = key_file.ok_or("--key-file is required with the local signer")?;
+            let secret: [u8; 32] = hex::decode(std::fs::read_to_string(path)?.trim())?
+                .try_into()
+                .map_err(|_| "Key file must hold a 32-byte hex secret key")?;
+            Ok(Box::new(SoftwareSigner::new(SigningKey::from_bytes(&secret))))
+        }
+        #[cfg(feature = "ledger")]
+        SignerKind::Ledger => {
+            let transport = quantum_metaverse::wallet::hid::LedgerHid::open()?;
+            Ok(Box::new(LedgerSigner::new(Box::new(transport))
+                .with_path(derivation)
+                .with_blind_signing(allow_blind_signing)))
+        }
+        #[cfg(not(feature = "ledger"))]
+        SignerKind::Ledger => {
+            let _ = (derivation, allow_blind_signing);
+            Err("Hardware wallet support requires a build with `--features ledger`".into())
+        }
+    }
 }
 
-async fn run_rpc_server(port: u16) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let addr = format!("127.0.0.1:{}", port);
-    let listener = TcpListener::bind(&addr).await?;
-    println!("RPC server listening on {}", addr);
+fn run_wallet_command(signer: &mut dyn TransactionSigner, action: WalletCommand) -> Result<(), Box<dyn std::error::Error>> {
+    let mut tx = match action {
+        WalletCommand::Address => {
+            println!("0x{}", hex::encode(signer.public_key()?));
+            return Ok(());
+        }
+        WalletCommand::Transfer { to, amount, nonce, gas_limit, gas_price, network_id } => {
+            let to: [u8; 32] = hex::decode(to.trim_start_matches("0x"))?
+                .try_into()
+                .map_err(|_| "Recipient must be 32 bytes")?;
+            Transaction::new([0u8; 32], nonce, TransactionAction::Transfer { to, amount }, gas_limit, gas_price)
+                .with_network_id(network_id)
+        }
+        WalletCommand::Sign { file } => serde_json::from_str(&std::fs::read_to_string(&file)?)?,
+    };
 
-    while let Ok((stream, _)) = listener.accept().await {
-        tokio::spawn(handle_rpc_connection(stream));
+    eprintln!("Review on the signer:");
+    for field in review(&tx).fields {
+        eprintln!("  {:<13} {}", field.label, field.value);
     }
-
+    signer.sign_transaction(&mut tx)?;
+    println!("{}", serde_json::to_string_pretty(&tx)?);
     Ok(())
 }
 
-async fn handle_rpc_connection(mut stream: tokio::net::TcpStream) {
-    use tokio::io::{AsyncReadExt, AsyncWriteExt};
-    
-    let mut buffer = [0; 1024];
-    if let Ok(n) = stream.read(&mut buffer).await {
-        // Skip HTTP headers and find the JSON body
-        if let Some(body_start) = String::from_utf8_lossy(&buffer[..n])
-            .find("{\"jsonrpc\"")
-        {
-            let request_str = String::from_utf8_lossy(&buffer[body_start..n]);
-            
-            if let Ok(request) = serde_json::from_str::<RPCRequest>(&request_str) {
-                println!("Received RPC request: {:?}", request);
-                
-                // Handle the request based on method
-                let response = match request.method.as_str() {
-                    "status" => RPCResponse {
-                        jsonrpc: "2.0".to_string(),
-                        result: Some(serde_json::to_value(NodeStatus {
-                            node_id: "0x0000000067c01789000000000000000000000000000000000000000000000000".to_string(),
-                            security_level: 98.0,
-                            connected_peers: 0,
-                            sync_status: "Synced".to_string(),
-                            current_block: 0,
-                            pending_transactions: 0,
-                            quantum_security: true,
-                            ai_governance_active: true,
-                        }).unwrap()),
-                        error: None,
-                        id: request.id,
-                    },
+async fn submit_transaction(rpc_port: u16, file: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let tx: Transaction = serde_json::from_str(&std::fs::read_to_string(file)?)?;
+    let submitted = rpc_call(rpc_port, "submitTransaction", json!({ "transaction": tx })).await?;
+    println!("{}", serde_json::to_string_pretty(&submitted)?);
+    Ok(())
+}
 
-                    "recordQuantumState" => {
-        let mut orchestrator = Orchestrator::new(PreciseFloat::new(90, 2)); // 90% coherence threshold
-        let metadata = HashMap::new();
-        
-        // Generate random test data
-        let observer_id = [1u8; 32];
-        let quantum_state = [2u8; 64];
-        let reality_layer = 1;
-        
-        if let Ok(state_id) = orchestrator.record_quantum_state(
-            observer_id,
-            quantum_state.to_vec(),
-            reality_layer,
-            metadata,
-        ) {
-            RPCResponse {
-                jsonrpc: "2.0".to_string(),
-                result: Some(json!({
-                    "state_id": format!("{:?}", state_id),
-                    "reality_layer": reality_layer,
-                    "timestamp": std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()
-                })),
-                error: None,
-                id: request.id,
-            }
-        } else {
-            RPCResponse {
-                jsonrpc: "2.0".to_string(),
-                result: None,
-                error: Some(RPCError { code: -32603, message: "Failed to record quantum state".to_string(), data: None }),
-                id: request.id,
-            }
+async fn run_multisig_command(rpc_port: u16, action: MultisigCommand) -> Result<(), Box<dyn std::error::Error>> {
+    let result = match action {
+        MultisigCommand::Pending { multisig } => json!({
+            "account": rpc_call(rpc_port, "getMultisig", json!({ "address": multisig })).await?,
+            "pending": rpc_call(rpc_port, "getMultisigPending", json!({ "multisig": multisig })).await?,
+        }),
+        MultisigCommand::Propose { multisig, file } => {
+            let operation: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&file)?)?;
+            rpc_call(rpc_port, "proposeMultisig", json!({ "multisig": multisig, "operation": operation })).await?
         }
-    },
+        MultisigCommand::Approve { multisig, id, key_file, network_id } => {
+            let pending = rpc_call(rpc_port, "getMultisigPending", json!({ "multisig": multisig })).await?;
+            let wanted = format!("0x{}", id.trim_start_matches("0x"));
+            let entry = pending.as_array()
+                .and_then(|entries| entries.iter().find(|entry| entry["pending"]["id"] == wanted.as_str()))
+                .ok_or("No pending proposal with that ID")?;
+            // Sign what we reconstruct locally rather than the node's payload
+            let proposal: MultisigProposal = serde_json::from_value(entry["pending"]["proposal"].clone())?;
+            eprintln!("Approving for multisig {}:", multisig);
+            eprintln!("{}", serde_json::to_string_pretty(&proposal.operation)?);
+            let secret: [u8; 32] = hex::decode(std::fs::read_to_string(&key_file)?.trim())?
+                .try_into()
+                .map_err(|_| "Key file must hold a 32-byte hex secret key")?;
+            let approval = Approval::sign(&proposal, network_id, &SigningKey::from_bytes(&secret));
+            rpc_call(rpc_port, "approveMultisig", json!({
+                "id": id,
+                "signer": hex::encode(approval.signer),
+                "signature": hex::encode(&approval.signature),
+            })).await?
+        }
+    };
+    println!("{}", serde_json::to_string_pretty(&result)?);
+    Ok(())
+}
 
-    "getOrchestrationMetrics" => {
-        let orchestrator = Orchestrator::new(PreciseFloat::new(90, 2)); // 90% coherence threshold
-        let metrics = orchestrator.get_metrics();
-        RPCResponse {
-            jsonrpc: "2.0".to_string(),
-            result: Some(json!(metrics)),
-            error: None,
-            id: request.id,
+async fn run_swap_command(rpc_port: u16, action: SwapCommand) -> Result<(), Box<dyn std::error::Error>> {
+    match action {
+        SwapCommand::Secret => {
+            let secret = htlc::generate_secret();
+            eprintln!("Keep the secret until the other side has locked its leg");
+            println!("{}", serde_json::to_string_pretty(&json!({
+                "secret": hex::encode(secret),
+                "hashlock": hex::encode(htlc::hashlock(&secret)),
+            }))?);
         }
-    },
+        SwapCommand::Legs {
+            hashlock, mainnet_recipient, mainnet_amount, nonce, chain_id, private_sender,
+            private_recipient, private_amount, private_first, timelock_blocks, gas_limit, gas_price, network_id,
+        } => {
+            let hex32 = |value: &str, name: &str| -> Result<[u8; 32], Box<dyn std::error::Error>> {
+                hex::decode(value.trim_start_matches("0x"))?
+                    .try_into()
+                    .map_err(|_| format!("{} must be 32 bytes", name).into())
+            };
+            let hashlock = hex32(&hashlock, "Hashlock")?;
+            let chain = ChainId::parse_lenient(&chain_id)?;
 
-    "getMetrics" => RPCResponse {
-                        jsonrpc: "2.0".to_string(),
-                        result: Some(json!({
-                            "tps": 1000,
-                            "memory_usage_mb": 256,
-                            "cpu_usage_percent": 15,
-                            "disk_usage_gb": 1.2,
-                            "network_in_mbps": 50,
-                            "network_out_mbps": 45,
-                            "quantum_entropy": 0.99,
-                            "ai_confidence": 0.95,
-                        })),
-                        error: None,
-                        id: request.id,
-                    },
+            // Timelocks are heights of each leg's own chain
+            let mainnet_height = rpc_call(rpc_port, "getFeatures", json!({})).await?["height"]
+                .as_u64()
+                .ok_or("Node did not report its height")?;
+            let chains: Vec<TenantSummary> = serde_json::from_value(rpc_call(rpc_port, "listPrivateChains", json!({})).await?)?;
+            let private_height = chains.iter()
+                .find(|summary| summary.chain_id == chain)
+                .ok_or("Unknown private chain")?
+                .height as u64;
 
-                    "security_test" => {
-                        let test_result = run_security_tests();
-                        RPCResponse {
-                            jsonrpc: "2.0".to_string(),
-                            result: Some(json!(test_result)),
-                            error: None,
-                            id: request.id,
-                        }
-                    },
-                    
-                    "stress_test" => {
-                        let stress_result = run_stress_test();
-                        RPCResponse {
-                            jsonrpc: "2.0".to_string(),
-                            result: Some(json!(stress_result)),
-                            error: None,
-                            id: request.id,
-                        }
-                    },
+            // The initiator's leg must outlive the participant's, so the
+            // participant can still claim after the secret is revealed
+            let (mainnet_blocks, private_blocks) = if private_first {
+                (timelock_blocks, 2 * timelock_blocks)
+            } else {
+                (2 * timelock_blocks, timelock_blocks)
+            };
+            let mainnet_terms = HtlcTerms {
+                recipient: hex32(&mainnet_recipient, "Mainnet recipient")?,
+                amount: mainnet_amount,
+                hashlock,
+                timelock: mainnet_height + mainnet_blocks,
+            };
+            let private_terms = HtlcTerms {
+                recipient: hex32(&private_recipient, "Private recipient")?,
+                amount: private_amount,
+                hashlock,
+                timelock: private_height + private_blocks,
+            };
+            let tx = Transaction::new([0u8; 32], nonce, TransactionAction::Htlc(HtlcAction::Lock(mainnet_terms)), gas_limit, gas_price)
+                .with_network_id(network_id);
 
-                    "quantum_attack_simulation" => {
-                        let simulation_result = simulate_quantum_attack();
-                        RPCResponse {
-                            jsonrpc: "2.0".to_string(),
-                            result: Some(json!(simulation_result)),
-                            error: None,
-                            id: request.id,
+            eprintln!(
+                "Lock the {} leg first; lock the other only once the first is confirmed",
+                if private_first { "private" } else { "mainnet" },
+            );
+            eprintln!("Sign the mainnet leg with `wallet sign` and submit it; send the private leg to `privateLockHtlc` with the chain's token");
+            println!("{}", serde_json::to_string_pretty(&json!({
+                "mainnet": tx,
+                "private": {
+                    "chain_id": chain.to_string(),
+                    "sender": hex::encode(hex32(&private_sender, "Private sender")?),
+                    "terms": private_terms,
+                },
+            }))?);
+        }
+        SwapCommand::Watch { mainnet, chain_id, private, interval_secs } => {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs.max(1)));
+            loop {
+                interval.tick().await;
+                let legs = [
+                    ("mainnet", rpc_call(rpc_port, "getHtlc", json!({ "id": mainnet })).await?),
+                    ("private", rpc_call(rpc_port, "privateGetHtlc", json!({ "chain_id": chain_id, "id": private })).await?),
+                ];
+                let mut settled = 0;
+                for (leg, result) in &legs {
+                    let status: HtlcStatus = serde_json::from_value(result["status"].clone())?;
+                    match status {
+                        HtlcStatus::Locked => println!(
+                            "{}: locked, refundable in {} blocks",
+                            leg, result["blocks_until_refund"],
+                        ),
+                        HtlcStatus::Claimed { secret } => {
+                            settled += 1;
+                            println!("{}: claimed, secret {}", leg, hex::encode(secret));
                         }
-                    },
-
-                    "network_security_audit" => {
-                        let audit_result = perform_network_security_audit();
-                        RPCResponse {
-                            jsonrpc: "2.0".to_string(),
-                            result: Some(json!(audit_result)),
-                            error: None,
-                            id: request.id,
+                        HtlcStatus::Refunded => {
+                            settled += 1;
+                            println!("{}: refunded", leg);
                         }
-                    },
-
-                    "getAIDecisions" => RPCResponse {
-                        jsonrpc: "2.0".to_string(),
-                        result: Some(json!({
-                            "decisions": [
-                                {
-                                    "id": "dec_001",
-                                    "type": "security",
-                                    "confidence": 0.98,
-                                    "timestamp": "2025-02-27T07:44:17Z",
-                                    "action": "optimize_quantum_parameters"
-                                }
-                            ],
-                            "total_decisions": 1,
-                            "average_confidence": 0.98
-                        })),
-                        error: None,
-                        id: request.id,
-                    },
-
-                    "getQuantumState" => RPCResponse {
-                        jsonrpc: "2.0".to_string(),
-                        result: Some(json!({
-                            "entanglement_pairs": 1024,
-                            "quantum_memory_qubits": 512,
-                            "decoherence_rate": 0.001,
-                            "error_correction_rate": 0.9999,
-                            "quantum_security_score": 98.5
-                        })),
-                        error: None,
-                        id: request.id,
-                    },
-
-                    _ => RPCResponse {
-                        jsonrpc: "2.0".to_string(),
-                        result: None,
-                        error: Some(RPCError {
-                            code: -32601,
-                            message: "Method not found".to_string(),
-                            data: None,
-                        }),
-                        id: request.id,
-                    },
-                };
-                
-                // Send HTTP response
-                if let Ok(response_str) = serde_json::to_string(&response) {
-                    let response = format!(
-                        "HTTP/1.1 200 OK\r\n\
-                         Content-Type: application/json\r\n\
-                         Content-Length: {}\r\n\
-                         Access-Control-Allow-Origin: *\r\n\
-                         \r\n\
-                         {}",
-                        response_str.len(),
-                        response_str
-                    );
-                    let _ = stream.write_all(response.as_bytes()).await;
+                    }
+                }
+                if settled == legs.len() {
+                    return Ok(());
                 }
             }
         }
     }
+    Ok(())
+}
+
+fn run_report_command(action: ReportCommand) -> Result<(), Box<dyn std::error::Error>> {
+    match action {
+        ReportCommand::Bundle { out } => {
+            let config_path = std::env::var("METAVERSE_CONFIG")
+                .unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string());
+            let config = ConfigManager::load_or_default(&config_path)?.current().clone();
+            let bundle = ReportBundle::collect(std::path::Path::new(CRASH_DIR), config)?;
+            std::fs::write(&out, serde_json::to_string_pretty(&bundle)?)?;
+            println!("Wrote {} with {} crash reports; attach it to the issue", out, bundle.crashes.len());
+        }
+    }
+    Ok(())
 }
 
-async fn sync_blockchain(
-    _blockchain: &mut Blockchain,
-    _genesis: &GenesisConfig,
-) -> Result<(), Box<dyn std::error::Error>> {
-    println!("Synchronizing blockchain from genesis...");
-    // Implement blockchain synchronization
+async fn run_telemetry_command(rpc_port: u16, action: TelemetryCommand) -> Result<(), Box<dyn std::error::Error>> {
+    let config_path = std::env::var("METAVERSE_CONFIG")
+        .unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string());
+    let (enabled, endpoint) = match action {
+        TelemetryCommand::Enable { endpoint } => (true, endpoint),
+        TelemetryCommand::Disable => (false, None),
+        TelemetryCommand::Status => {
+            let telemetry = ConfigManager::load_or_default(&config_path)?.current().telemetry.clone();
+            println!("Telemetry {}", if telemetry.enabled { "enabled" } else { "disabled" });
+            println!("Collector: {}", telemetry.endpoint);
+            println!("Interval: {}s ±{}%", telemetry.interval_secs, telemetry.jitter_percent);
+            if std::path::Path::new(TELEMETRY_BUFFER_PATH).exists() {
+                let buffer = TelemetryBuffer::open(TELEMETRY_BUFFER_PATH, telemetry.buffer_limit)?;
+                println!("Instance: {}", buffer.instance());
+                println!("Pending reports: {}", buffer.pending().len());
+            }
+            return Ok(());
+        }
+    };
+
+    // Edit the file as plain JSON so settings left at their defaults stay out of it
+    let mut raw: serde_json::Value = match std::fs::read_to_string(&config_path) {
+        Ok(text) => serde_json::from_str(&text)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => json!({}),
+        Err(e) => return Err(e.into()),
+    };
+    let root = raw.as_object_mut().ok_or("Config file must hold a JSON object")?;
+    let telemetry = root.entry("telemetry").or_insert_with(|| json!({}));
+    let telemetry = telemetry.as_object_mut().ok_or("`telemetry` must be a JSON object")?;
+    telemetry.insert("enabled".to_string(), json!(enabled));
+    if let Some(endpoint) = endpoint {
+        telemetry.insert("endpoint".to_string(), json!(endpoint));
+    }
+    serde_json::from_value::<NodeConfig>(raw.clone())?.validate()?;
+    if let Some(dir) = std::path::Path::new(&config_path).parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&config_path, serde_json::to_string_pretty(&raw)?)?;
+    println!("Telemetry {} in {}", if enabled { "enabled" } else { "disabled" }, config_path);
+
+    // A running node picks the change up on reload
+    match rpc_call(rpc_port, "reloadConfig", json!({})).await {
+        Ok(_) => println!("Node on port {} reloaded its config", rpc_port),
+        Err(_) => println!("No node answered on port {}; the change applies when it starts", rpc_port),
+    }
     Ok(())
 }
+
+/// Read a file holding a 32-byte hex ed25519 secret key
+fn read_key_file(path: &str) -> Result<SigningKey, Box<dyn std::error::Error>> {
+    Ok(SigningKey::from_bytes(&read_secret_file(path)?))
+}
+
+fn read_secret_file(path: &str) -> Result<[u8; 32], Box<dyn std::error::Error>> {
+    let secret: [u8; 32] = hex::decode(std::fs::read_to_string(path)?.trim())?
+        .try_into()
+        .map_err(|_| "Key file must hold a 32-byte hex secret key")?;
+    Ok(secret)
+}
+
+async fn run_seed_command(rpc_port: u16, action: SeedCommand) -> Result<(), Box<dyn std::error::Error>> {
+    match action {
+        SeedCommand::Record { domain, peers, sequence, key_file, network_id } => {
+            let peers = peers.iter()
+                .map(|peer| peer.parse().map_err(|_| format!("`{}` is not an ip:port address", peer)))
+                .collect::<Result<Vec<SocketAddr>, _>>()?;
+            let record = SeedRecord { sequence, peers };
+            match key_file {
+                Some(key_file) => println!("{}", record.sign(&read_key_file(&key_file)?, network_id, &domain)),
+                None => println!("{}", record.to_txt()),
+            }
+        }
+        SeedCommand::Status => {
+            println!("{}", serde_json::to_string_pretty(&rpc_call(rpc_port, "getSeeds", json!({})).await?)?);
+        }
+    }
+    Ok(())
+}
+
+async fn run_cert_command(rpc_port: u16, action: CertCommand) -> Result<(), Box<dyn std::error::Error>> {
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs();
+    let result = match action {
+        CertCommand::PublicKey { key_file } => {
+            println!("0x{}", hex::encode(read_key_file(&key_file)?.verifying_key().to_bytes()));
+            return Ok(());
+        }
+        CertCommand::Issue { node_key, authority_key_file, serial, valid_days, network_id } => {
+            let node_key: [u8; 32] = hex::decode(node_key.trim_start_matches("0x"))?
+                .try_into()
+                .map_err(|_| "Node key must be 32 bytes")?;
+            let not_after = now.saturating_add(valid_days.saturating_mul(86_400));
+            let authority = read_key_file(&authority_key_file)?;
+            json!(NodeCertificate::issue(&authority, node_key, serial, now, not_after, network_id))
+        }
+        CertCommand::Revoke { serial, authority_key_file, network_id } => {
+            let authority = read_key_file(&authority_key_file)?;
+            let revocation = CertificateRevocation::issue(&authority, serial, now, network_id);
+            rpc_call(rpc_port, "revokeCertificate", json!(revocation)).await?
+        }
+        CertCommand::List => rpc_call(rpc_port, "getCertificates", json!({})).await?,
+    };
+    println!("{}", serde_json::to_string_pretty(&result)?);
+    Ok(())
+}
+
+async fn run_remote_command(action: RemoteCommand) -> Result<(), Box<dyn std::error::Error>> {
+    let config_path = std::env::var("METAVERSE_CONFIG")
+        .unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string());
+    let config = ConfigManager::load_or_default(&config_path)?.current().clone();
+    let mut storage = RemoteStorage::open(&config.remote_storage, REMOTE_MANIFEST_PATH)?;
+    match action {
+        RemoteCommand::Put { class, file } => {
+            let data = tokio::fs::read(&file).await?;
+            let name = std::path::Path::new(&file)
+                .file_name()
+                .map_or(file.clone(), |name| name.to_string_lossy().into_owned());
+            let object = storage.mirror(class, &name, &data).await?;
+            println!("Stored {} ({} bytes) as {} at {}", object.name, object.size, hex::encode(object.hash), object.location);
+        }
+        RemoteCommand::Get { class, hash, out } => {
+            let hash: [u8; 32] = hex::decode(hash.trim_start_matches("0x"))?
+                .try_into()
+                .map_err(|_| "Hash must be 32 bytes")?;
+            let data = storage.fetch(class, &hash).await?;
+            tokio::fs::write(&out, &data).await?;
+            println!("Verified and wrote {} bytes to {}", data.len(), out);
+        }
+        RemoteCommand::List { class } => {
+            println!("{:<64} {:>12} {:>12}  name", "hash", "bytes", "stored at");
+            for object in storage.objects(class) {
+                println!("{:<64} {:>12} {:>12}  {}", hex::encode(object.hash), object.size, object.stored_at, object.name);
+            }
+        }
+        RemoteCommand::Prune => {
+            for object in storage.apply_lifecycle().await? {
+                println!("Deleted {} {} ({})", object.class.as_str(), hex::encode(object.hash), object.name);
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn run_private_command(rpc_port: u16, action: PrivateCommand) -> Result<(), Box<dyn std::error::Error>> {
+    // Creating, suspending and resuming chains are operator-only methods
+    let operator_token = || std::env::var(OPERATOR_TOKEN_ENV)
+        .map_err(|_| format!("{} must be set to manage private chains", OPERATOR_TOKEN_ENV));
+    let result = match action {
+        PrivateCommand::Create { name, owner, cpu_ms_per_minute, storage_bytes } => {
+            rpc_call(rpc_port, "createPrivateChain", json!({
+                "name": name,
+                "owner": owner,
+                "cpu_ms_per_minute": cpu_ms_per_minute,
+                "storage_bytes": storage_bytes,
+                "operator_token": operator_token()?,
+            })).await?
+        }
+        PrivateCommand::List => rpc_call(rpc_port, "listPrivateChains", json!({})).await?,
+        PrivateCommand::Suspend { chain_id } => {
+            rpc_call(rpc_port, "suspendPrivateChain", json!({
+                "chain_id": chain_id,
+                "operator_token": operator_token()?,
+            })).await?
+        }
+        PrivateCommand::Resume { chain_id } => {
+            rpc_call(rpc_port, "resumePrivateChain", json!({
+                "chain_id": chain_id,
+                "operator_token": operator_token()?,
+            })).await?
+        }
+    };
+    println!("{}", serde_json::to_string_pretty(&result)?);
+    Ok(())
+}
+
+/// Send a single JSON-RPC request to a running node
+async fn rpc_call(
+    port: u16,
+    method: &str,
+    params: serde_json::Value,
+) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    let method = method.to_string();
+    let result = tokio::task::spawn_blocking(move || RpcClient::local(port).call(&method, params)).await?;
+    Ok(result?)
+}
+
+async fn run_node() -> Result<(), Box<dyn std::error::Error>> {
+    println!("Initializing Quantum Metaverse Blockchain...");
+
+    // Load fault-injection rules for simnet/staging runs
+    #[cfg(feature = "fault-injection")]
+    if let Ok(path) = std::env::var("METAVERSE_FAULT_CONFIG") {
+        let config = quantum_metaverse::chaos::FaultConfig::load(&path)?;
+        println!("Fault injection enabled with {} rules", config.rules.len());
+        quantum_metaverse::chaos::install(config);
+    }
+
+    // Load node configuration; safe settings can be reloaded at runtime
+    let config_path = std::env::var("METAVERSE_CONFIG")
+        .unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string());
+    let config_manager = ConfigManager::load_or_default(&config_path)?;
+    let node_config = config_manager.current().clone();
+    let config_updates = config_manager.subscribe();
+    // Write a crash report with recent logs and a state fingerprint on panic
+    let crash_reporter = crash::install(CrashReporter::new(CRASH_DIR, LogRing::new(LOG_RING_LINES)));
+    crash_reporter.update(|fingerprint| fingerprint.config_digest = Some(config_digest(&node_config)));
+    let log_filter = init_logging(&node_config.log_level, crash_reporter.logs().clone());
+    let precision = node_config.precision;
+
+    // Initialize core components
+    // Resume the chain, its state and the transactions pooled before a restart
+    let chain = Blockchain::open(DB_PATH, precision)?;
+    let resumed_state = sealer::catch_up(&chain, chain.stored_state()?.unwrap_or_else(WorldState::new))?;
+    let resumed_pending: Vec<Transaction> = chain.pending_transactions().iter()
+        .filter_map(|raw| bincode::deserialize(raw).ok())
+        .collect();
+    let genesis_hash = chain.block(0).map(|genesis| genesis.hash).ok_or("Chain has no genesis block")?;
+    let blockchain = Arc::new(RwLock::new(chain));
+    blockchain.write().await.signal(node_config.signal_features.iter().filter_map(|name| Feature::from_name(name)).collect());
+    let flux_network = Arc::new(RwLock::new(FluxNetwork::new(precision)));
+    let traces = Arc::new(RwLock::new(TraceLog::new(TRACE_LOG_CAPACITY)));
+    let _storage = ZKStorage::new(precision);
+    // Validators key their QKD links from a shared secret, and each start
+    // moves on to key material not spent before
+    let mut quantum_network = QuantumNetwork::new(precision);
+    if let Some(path) = &node_config.consensus.link_key_path {
+        quantum_network = quantum_network.with_key_agreement(Arc::new(SimulatedQkd::new(read_secret_file(path)?)));
+    }
+    let quantum_network = Arc::new(RwLock::new(quantum_network.with_first_epoch(unix_millis())));
+    let mut security = QuantumSecurity::new(precision);
+    let identity = Arc::new(RwLock::new(ZKIdentity::new(precision)));
+    let mut governance = AIGovernance::new(precision);
+    let economics = Arc::new(RwLock::new(EconomicModel::new(precision)));
+    let private_chains = Arc::new(PrivateChainHost::new(precision));
+    let pools = RuntimePools::new(CRITICAL_WORKERS, node_config.background_workers)?;
+    #[cfg(feature = "hubble")]
+    let (hubble_store, hubble_index) = SegmentStore::open(HUBBLE_DIR)?;
+    #[cfg(feature = "hubble")]
+    println!("Hubble index: {} documents in {} segments", hubble_index.len(), hubble_store.segments().len());
+    #[cfg(feature = "hubble")]
+    let hubble_store = Arc::new(RwLock::new(hubble_store));
+    #[cfg(feature = "vm-wasm")]
+    let extensions = ExtensionHost::load(&node_config.extensions)?;
+    #[cfg(feature = "vm-wasm")]
+    for extension in extensions.extensions() {
+        println!(
+            "Extension {}: capabilities {:?}, {} RPC methods",
+            extension.name(), extension.config().capabilities, extension.config().rpc_methods.len()
+        );
+    }
+
+    // On a permissioned network only peers certified by a trusted authority may connect
+    let mut p2p_network = P2PNetwork::with_mode(node_config.p2p_port, node_config.node_mode)
+        .with_genesis(genesis_hash)
+        .with_observations(ObservationGossip::new(node_config.chain_id, &node_config.observations))
+        .with_diversity(PeerDiversity::new(&node_config.diversity)?)
+        .with_addresses(node_config.p2p_listen_addrs()?, node_config.external_addrs()?)
+        .with_seeds(SeedBook::new(node_config.chain_id, &node_config.seeds)?);
+    if let Some(permissioned) = &node_config.permissioned {
+        let registry = CertificateRegistry::new(node_config.chain_id, permissioned.authority_keys()?);
+        let certificate: NodeCertificate = serde_json::from_str(&std::fs::read_to_string(&permissioned.certificate_path)?)?;
+        let node_key = read_key_file(&permissioned.node_key_path)?;
+        let permissions = Permissions::new(registry, certificate, node_key, clock::system())?;
+        println!(
+            "Permissioned network: node certificate {} expires at {}",
+            permissions.certificate().serial, permissions.certificate().not_after
+        );
+        p2p_network = p2p_network.with_permissions(permissions);
+    }
+    // A validator behind sentries peers only with them
+    if let Some(sentry) = &node_config.sentry {
+        p2p_network = p2p_network.with_sentry(SentrySet::new(sentry)?);
+        println!("Sentry topology: {:?} with private peers {}", sentry.role, sentry.private_peers.join(", "));
+    }
+    let p2p_network = Arc::new(p2p_network);
+    let remote_storage = Arc::new(RwLock::new(RemoteStorage::open(&node_config.remote_storage, REMOTE_MANIFEST_PATH)?));
+
+    // Initialize node identity
+    println!("Creating node identity...");
+    let (identity_id, _node_identity) = identity.write().await.create_identity(vec![])?;
+    let node_id = NodeId::new(*identity_id.as_bytes());
+
+    let rpc_context = RpcContext {
+        node_id,
+        config: Arc::new(RwLock::new(config_manager)),
+        rate_limiter: Arc::new(RateLimiter::new(node_config.rpc_rate_limit)),
+        log_filter,
+        private_chains,
+        operator_token: std::env::var(OPERATOR_TOKEN_ENV).ok()
+            .filter(|token| !token.is_empty())
+            .map(|token| blake3::hash(token.as_bytes()).into()),
+        tls: match &node_config.rpc_tls {
+            Some(tls) => Some(Arc::new(CertificateStore::load(&tls.cert_path, &tls.key_path)?)),
+            None => None,
+        },
+        ready: Arc::new(AtomicBool::new(false)),
+        world_state: Arc::new(RwLock::new({
+            // Pruned nodes keep only recent state history; archive nodes keep all of it
+            let mut store = StateStore::resume(resumed_state);
+            store.set_retention(node_config.node_mode.retained_blocks());
+            store
+        })),
+        mempool: Arc::new(RwLock::new(Mempool::new(MempoolConfig {
+            network_id: node_config.chain_id,
+            ..Default::default()
+        }))),
+        chain: blockchain.clone(),
+        sync: Arc::new(RwLock::new(SyncEngine::new(blockchain.read().await.height()))),
+        snapshots: Arc::new(RwLock::new(VecDeque::new())),
+        snapshot_download: Arc::new(RwLock::new(None)),
+        logs: Arc::new(RwLock::new(LogIndex::new())),
+        contract_meter: Arc::new(RwLock::new(ContractMeter::new())),
+        pools: pools.clone(),
+        network_id: node_config.chain_id,
+        flux: flux_network.clone(),
+        multisig_approvals: Arc::new(RwLock::new(ApprovalPool::new())),
+        watchtower: Arc::new(RwLock::new(Watchtower::new())),
+        commit_reveal: node_config.commit_reveal.clone().map(|config| {
+            let domain = SigningDomain::main_chain(node_config.chain_id);
+            Arc::new(RwLock::new(CommitRevealQueue::new(domain, config)))
+        }),
+        #[cfg(feature = "hubble")]
+        hubble: Arc::new(RwLock::new(hubble_index)),
+        #[cfg(feature = "hubble")]
+        hubble_admission: Arc::new(RwLock::new(SubmissionGuard::new(AdmissionParams::default()))),
+        economics,
+        identity: identity.clone(),
+        predicate_challenges: Arc::new(RwLock::new(PredicateChallenges::new())),
+        beacon: Arc::new(RwLock::new(RandomnessBeacon::new(node_config.beacon.clone(), node_config.epochs, node_config.chain_id))),
+        epochs: Arc::new(RwLock::new(EpochManager::new(node_config.epochs))),
+        params: Arc::new(RwLock::new(ParamsRegistry::new())),
+        features: Arc::new(RwLock::new(FeatureTracker::new(node_config.feature_activation.clone()))),
+        p2p: p2p_network.clone(),
+        quantum_network,
+        remote_storage,
+        traces: traces.clone(),
+        trust_history: Arc::new(RwLock::new(VersionedMap::new())),
+        health: HealthRegistry::new(),
+        #[cfg(feature = "bridges")]
+        insurance: Arc::new(RwLock::new(InsuranceFund::new(node_config.chain_id))),
+        #[cfg(feature = "bridges")]
+        reserve_ledger: Arc::new(RwLock::new(ReserveLedger::new())),
+        #[cfg(feature = "bridges")]
+        reserves: Arc::new(RwLock::new(ReserveLog::new())),
+        circuit_breaker: Arc::new(RwLock::new(CircuitBreaker::new(node_config.chain_id))),
+        #[cfg(feature = "metaverse")]
+        orchestrator: Arc::new(RwLock::new(Orchestrator::new(PreciseFloat::new(90, 2)))), // 90% coherence threshold
+        daos: Arc::new(RwLock::new(DaoFactory::new(node_config.chain_id))),
+        #[cfg(feature = "vm-wasm")]
+        extensions: Arc::new(RwLock::new(extensions)),
+    };
+    register_health_probes(&rpc_context, &blockchain);
+    {
+        // Transactions that no longer apply to the resumed state are dropped
+        let store = rpc_context.world_state.read().await;
+        let mut mempool = rpc_context.mempool.write().await;
+        for tx in resumed_pending {
+            let _ = mempool.insert(tx, store.latest());
+        }
+    }
+
+    // Generate genesis configuration
+    let genesis_config = generate_genesis_config();
+    
+    // Initialize network security
+    // The node key lives in the keystore so the node keeps its identity across restarts
+    println!("Initializing quantum-resistant security layer...");
+    let keystore = Keystore::open(&node_config.keystore.dir)?;
+    let passphrase = keystore_passphrase(&node_config.keystore)?;
+    let (node_key_id, node_key) = if keystore.contains(&node_config.keystore.node_key) {
+        let key = keystore.load(&node_config.keystore.node_key, &passphrase)?;
+        (security.import_key(key.clone())?, key)
+    } else {
+        let (id, key) = security.generate_key_pair()?;
+        keystore.save(&node_config.keystore.node_key, &key, &passphrase)?;
+        println!("Generated node key `{}` in {}", node_config.keystore.node_key, keystore.dir().display());
+        (id, key)
+    };
+    println!("Node key: 0x{}", hex::encode(node_key_id));
+    println!("Vote key: 0x{}", hex::encode(node_key.verification_key()));
+
+    // Genesis validators decide every block; a node outside the set follows
+    // the blocks they decide
+    let consensus_driver = consensus_driver(&node_config, node_key_id, &node_key, &mut *rpc_context.economics.write().await)?;
+    match &consensus_driver {
+        Some(driver) => {
+            driver.link(&mut *rpc_context.quantum_network.write().await)?;
+            println!("Validating with {} validators", driver.electorate().voters().count());
+        }
+        None => println!("Not a validator; following the chain"),
+    }
+    let (consensus_events, consensus_queue) = mpsc::unbounded_channel();
+    let consensus_inbox = ConsensusInbox {
+        events: consensus_driver.is_some().then(|| consensus_events.clone()),
+        seen: Arc::new(RwLock::new(SeenEnvelopes::default())),
+    };
+
+    // Initialize governance policies
+    println!("Initializing AI governance policies...");
+    let governance_rules: Vec<Rule> = vec![];
+    let governance_weights = vec![];
+    let governance_threshold = PreciseFloat::new(90, 2);
+    let policy_id = governance.create_policy(
+        governance_rules,
+        governance_weights,
+        governance_threshold
+    )?;
+    let export_queue = EventQueue::new();
+    export_queue.push(0, EventKind::Governance, json!({ "policy_created": hex::encode(policy_id) }));
+
+    // Start network services
+    println!("Starting network services...");
+
+    // Initialize P2P networking
+    println!("Node mode: {:?}", node_config.node_mode);
+    let p2p_config = P2PConfig {
+        listen: node_config.p2p_listen_addrs()?,
+        _node_key: node_key,
+        node_id,
+        network: p2p_network,
+        chain: blockchain.clone(),
+        flux: flux_network,
+        traces,
+        economics: rpc_context.economics.clone(),
+        snapshots: rpc_context.snapshots.clone(),
+        mempool: rpc_context.mempool.clone(),
+        world_state: rpc_context.world_state.clone(),
+        blocks: BlockImport::new(&rpc_context),
+        consensus: consensus_inbox.clone(),
+    };
+
+    // Start services in dependency order: P2P, then RPC so sync progress can
+    // be watched, then sync; block production and readiness wait for sync
+    let mut lifecycle = LifecycleManager::new(std::time::Duration::from_secs(DRAIN_TIMEOUT_SECS));
+
+    let p2p_shutdown = lifecycle.signal();
+    lifecycle.start_service("p2p", async move {
+        if let Err(e) = run_p2p_network(p2p_config, config_updates, p2p_shutdown).await {
+            eprintln!("P2P network error: {}", e);
+        }
+    });
+
+    // Look up the DNS seeds now and every `seeds.refresh_secs`; a reload
+    // that changes the seeds is applied and looked up at once
+    let mut seed_shutdown = lifecycle.signal();
+    let mut seed_settings = rpc_context.config.read().await.subscribe();
+    let seed_network = rpc_context.p2p.clone();
+    lifecycle.start_service_on("dns seeds", pools.handle(Lane::Background), async move {
+        let Some(book) = &seed_network.seeds else { return };
+        let resolver = match TokioAsyncResolver::tokio_from_system_conf() {
+            Ok(resolver) => resolver,
+            Err(e) => {
+                eprintln!("DNS seeds disabled: {}", e);
+                return;
+            }
+        };
+        let mut current = seed_settings.borrow_and_update().seeds.clone();
+        'refresh: loop {
+            for error in seeds::refresh(book, &resolver).await {
+                eprintln!("DNS seed {}", error);
+            }
+            let next = tokio::time::Instant::now() + std::time::Duration::from_secs(current.refresh_secs);
+            loop {
+                tokio::select! {
+                    _ = tokio::time::sleep_until(next) => continue 'refresh,
+                    changed = seed_settings.changed() => {
+                        if changed.is_err() {
+                            break 'refresh;
+                        }
+                        let config = seed_settings.borrow_and_update().seeds.clone();
+                        if config == current {
+                            continue;
+                        }
+                        if let Err(e) = book.write().await.reconfigure(&config) {
+                            eprintln!("DNS seeds not reloaded: {}", e);
+                        }
+                        current = config;
+                        continue 'refresh;
+                    }
+                    _ = seed_shutdown.wait() => break 'refresh,
+                }
+            }
+        }
+    });
+
+    let rpc_shutdown = lifecycle.signal();
+    let rpc_listen = node_config.rpc_listen_addrs()?;
+    let server_context = rpc_context.clone();
+    lifecycle.start_service("rpc", async move {
+        if let Err(e) = run_rpc_server(&rpc_listen, server_context, rpc_shutdown).await {
+            eprintln!("RPC server error: {}", e);
+        }
+    });
+
+    // Start blockchain synchronization
+    println!("Starting blockchain synchronization...");
+    sync_blockchain(&rpc_context, node_config.sync_addrs()?).await;
+
+    // Keep recent state snapshots for peers that fast-sync
+    let snapshot_interval = node_config.fast_sync.snapshot_interval;
+    if snapshot_interval > 0 {
+        let mut snapshot_shutdown = lifecycle.signal();
+        let snapshot_ctx = rpc_context.clone();
+        lifecycle.start_service_on("state snapshots", pools.handle(Lane::Background), async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(SNAPSHOT_CHECK_INTERVAL_SECS));
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        let last = snapshot_ctx.snapshots.read().await.front().map_or(0, |snapshot| snapshot.manifest.height);
+                        if snapshot_ctx.world_state.read().await.latest_height() < last + snapshot_interval {
+                            continue;
+                        }
+                        let state = snapshot_ctx.world_state.read().await.latest().clone();
+                        let Some(block_hash) = snapshot_ctx.chain.read().await.block(state.height()).map(|block| block.hash) else { continue };
+                        match snapshot_ctx.pools.spawn_blocking(Lane::Background, move || Snapshot::build(&state, block_hash)).await {
+                            Ok(Ok(snapshot)) => {
+                                println!("State snapshot at block {} ({} chunks)", snapshot.manifest.height, snapshot.manifest.chunks.len());
+                                let mut snapshots = snapshot_ctx.snapshots.write().await;
+                                snapshots.push_front(snapshot);
+                                snapshots.truncate(KEPT_SNAPSHOTS);
+                            }
+                            Ok(Err(e)) => eprintln!("State snapshot failed: {}", e),
+                            Err(e) => eprintln!("State snapshot failed: {}", e),
+                        }
+                    }
+                    _ = snapshot_shutdown.wait() => break,
+                }
+            }
+        });
+    }
+
+    // Probe the private sentry links and warn before a validator is cut off
+    if let Some(sentry_config) = node_config.sentry.clone() {
+        let mut sentry_shutdown = lifecycle.signal();
+        let sentry_network = rpc_context.p2p.clone();
+        lifecycle.start_service_on("sentry health", pools.handle(Lane::Background), async move {
+            let Some(sentry) = &sentry_network.sentry else { return };
+            let timeout = std::time::Duration::from_secs(SENTRY_PROBE_TIMEOUT_SECS);
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(sentry_config.health_interval_secs));
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        let addrs = sentry.read().await.addrs();
+                        for address in addrs {
+                            let started = std::time::Instant::now();
+                            let reachable = matches!(tokio::time::timeout(timeout, tokio::net::TcpStream::connect(address)).await, Ok(Ok(_)));
+                            let mut set = sentry.write().await;
+                            if reachable {
+                                let was_down = !set.healthy().contains(&address);
+                                set.record_seen(&address.to_string(), unix_millis() / 1000, Some(started.elapsed().as_millis() as u64));
+                                if was_down {
+                                    println!("Private peer {} is up", address);
+                                }
+                            } else if let Some(health) = set.record_missed(&address) {
+                                eprintln!("Private peer {} is now {:?}", address, health);
+                            }
+                        }
+                        let set = sentry.read().await;
+                        if set.role() == SentryRole::Validator && set.healthy().is_empty() {
+                            eprintln!("No healthy sentries: validator is cut off from the network");
+                        }
+                    }
+                    _ = sentry_shutdown.wait() => break,
+                }
+            }
+        });
+    }
+
+    // Forward the tally observations admitted since the last flush, one batch per layer
+    let mut observation_shutdown = lifecycle.signal();
+    let observation_network = rpc_context.p2p.clone();
+    let observation_flush = std::time::Duration::from_millis(node_config.observations.flush_interval_ms);
+    let observation_settings = rpc_context.config.read().await.subscribe();
+    lifecycle.start_service("observation gossip", async move {
+        let mut interval = tokio::time::interval(observation_flush);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let fanout = observation_settings.borrow().gossip_fanout;
+                    for aggregate in observation_network.flush_observations().await {
+                        let (layer_id, count) = (aggregate.layer_id, aggregate.observations.len());
+                        let message = P2PMessage { message_type: AGGREGATE_TOPIC.to_string(), payload: json!(aggregate), trace_id: None };
+                        let sent = gossip(&observation_network, "", &message, fanout).await;
+                        println!("Forwarded {} observations of layer {} by gossip to {} peers", count, layer_id, sent);
+                    }
+                }
+                _ = observation_shutdown.wait() => break,
+            }
+        }
+    });
+
+    // Run the epoch duties at every boundary the chain crosses and publish the transitions
+    let mut epoch_shutdown = lifecycle.signal();
+    let epoch_context = rpc_context.clone();
+    let epoch_events = export_queue.clone();
+    lifecycle.start_service_on("epoch manager", pools.handle(Lane::Background), async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(EPOCH_CHECK_INTERVAL_SECS));
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let height = epoch_context.world_state.read().await.latest_height();
+                    let boundaries = epoch_context.epochs.write().await.advance(height);
+                    for boundary in boundaries {
+                        // Parameter updates activate at epoch starts
+                        {
+                            let params = epoch_context.params.read().await;
+                            epoch_context.economics.write().await.apply_params(&params, boundary.height);
+                            epoch_context.identity.write().await.apply_params(&params, boundary.height);
+                            governance.apply_params(&params, boundary.height);
+                        }
+                        let mut duties = Vec::with_capacity(EpochDuty::ALL.len());
+                        for duty in EpochDuty::ALL {
+                            let result = run_epoch_duty(&epoch_context, &mut governance, boundary, duty).await;
+                            if let Err(e) = &result {
+                                eprintln!("Epoch {} duty {:?} failed: {}", boundary.epoch, duty, e);
+                            }
+                            duties.push(DutyOutcome::new(duty, result));
+                        }
+                        let transition = epoch_context.epochs.write().await.complete(boundary, duties);
+                        epoch_events.push(boundary.height, EventKind::Epoch, json!(transition));
+                    }
+                }
+                _ = epoch_shutdown.wait() => break,
+            }
+        }
+    });
+
+    // Count feature signals in new blocks and announce lock-ins
+    let mut feature_shutdown = lifecycle.signal();
+    let feature_chain = blockchain.clone();
+    let feature_tracker = rpc_context.features.clone();
+    let feature_events = export_queue.clone();
+    lifecycle.start_service_on("feature activation", pools.handle(Lane::Background), async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(FEATURE_CHECK_INTERVAL_SECS));
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let chain = feature_chain.read().await;
+                    let mut tracker = feature_tracker.write().await;
+                    for height in tracker.next_height()..chain.height() {
+                        let Some(block) = chain.block(height) else { break };
+                        match tracker.record(height, block.signals) {
+                            Ok(lock_ins) => {
+                                for lock_in in lock_ins {
+                                    println!("Feature {} locked in; active from height {}", lock_in.feature.name(), lock_in.activation_height);
+                                    feature_events.push(lock_in.locked_in_at, EventKind::Governance, json!({
+                                        "feature_locked_in": lock_in.feature.name(),
+                                        "activation_height": lock_in.activation_height,
+                                    }));
+                                }
+                            }
+                            Err(e) => {
+                                eprintln!("Feature tracking stopped at height {}: {}", height, e);
+                                break;
+                            }
+                        }
+                    }
+                }
+                _ = feature_shutdown.wait() => break,
+            }
+        }
+    });
+
+    // Drop expired transactions and re-validate the pool whenever a new block is committed
+    let mut mempool_shutdown = lifecycle.signal();
+    let mempool_context = rpc_context.clone();
+    lifecycle.start_service_on("mempool maintenance", pools.handle(Lane::Background), async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(MEMPOOL_CHECK_INTERVAL_SECS));
+        let mut validated_height = 0;
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let store = mempool_context.world_state.read().await;
+                    let mut mempool = mempool_context.mempool.write().await;
+                    mempool.evict_expired(std::time::Instant::now());
+                    if store.latest_height() != validated_height {
+                        validated_height = store.latest_height();
+                        mempool.revalidate(store.latest());
+                        mempool_context.multisig_approvals.write().await
+                            .prune(|address| store.latest().multisig(address).map(|account| account.nonce()));
+                        for stale in mempool_context.watchtower.write().await.check(store.latest().channels()) {
+                            println!(
+                                "Watchtower: channel {} is closing on stale update {}; submit its dispute from getStaleCloses before block {}",
+                                hex::encode(stale.channel), stale.closed_at_sequence, stale.settles_at,
+                            );
+                        }
+                        if let Some(queue) = &mempool_context.commit_reveal {
+                            // Pending commitments are fixed by the new block; reveals
+                            // whose nonce it used have executed
+                            let mut queue = queue.write().await;
+                            let executed: Vec<[u8; 32]> = queue.due(validated_height + 1).into_iter()
+                                .filter(|(_, tx)| store.latest().account(&tx.from).nonce > tx.nonce)
+                                .map(|(digest, _)| digest)
+                                .collect();
+                            queue.executed(&executed);
+                            queue.seal(validated_height);
+                            queue.expire(validated_height);
+                        }
+                    }
+                }
+                _ = mempool_shutdown.wait() => break,
+            }
+        }
+    });
+
+    // Decide blocks with the other validators. A height starts when a block
+    // is due here or once other validators started it; decided blocks are
+    // imported like blocks from peers.
+    let block_time = node_config.block_time.clone();
+    if block_time.seal == SealMode::Instant {
+        println!("WARNING: instant seal is on; every pooled transaction is mined at once. Use it for development only.");
+    }
+    if let Some(mut driver) = consensus_driver {
+        let mut consensus_shutdown = lifecycle.signal();
+        let consensus_context = rpc_context.clone();
+        let inbox = consensus_inbox.clone();
+        let mut queue = consensus_queue;
+        let invariant_dump_dir = node_config.invariant_checks()
+            .then(|| std::path::PathBuf::from(&node_config.invariants.dump_dir));
+        lifecycle.start_service("consensus", async move {
+            let mut interval = tokio::time::interval(block_time.tick());
+            let mut last_decided = std::time::Instant::now();
+            loop {
+                let event = tokio::select! {
+                    _ = interval.tick() => None,
+                    Some(event) = queue.recv() => Some(event),
+                    _ = consensus_shutdown.wait() => break,
+                };
+                let result = match event {
+                    Some(ConsensusEvent::Envelope(envelope)) => driver.receive(&mut *consensus_context.quantum_network.write().await, &envelope),
+                    Some(ConsensusEvent::Timeout(timeout)) => driver.on_timeout(timeout),
+                    None => Ok(Vec::new()),
+                };
+                let mut out = result.unwrap_or_else(|e| {
+                    eprintln!("Consensus: {}", e);
+                    Vec::new()
+                });
+
+                let tip = consensus_context.chain.read().await.height();
+                let pending = consensus_context.mempool.read().await.len();
+                if driver.awaits(tip) && (driver.early(tip) || block_time.due(pending, last_decided.elapsed())) {
+                    match start_height(&consensus_context, &mut driver, &block_time, tip).await {
+                        Ok(started) => out.extend(started),
+                        Err(e) => eprintln!("Consensus: cannot start height {}: {}", tip, e),
+                    }
+                }
+                if carry_out(&consensus_context, &mut driver, &inbox, &consensus_events, out, invariant_dump_dir.as_deref()).await {
+                    last_decided = std::time::Instant::now();
+                }
+            }
+        });
+
+        // Keep a connection to every other validator with an address
+        let local = hex::encode(node_key_id);
+        let addresses = node_config.consensus.validators.iter()
+            .filter(|validator| validator.id.trim_start_matches("0x") != local)
+            .filter_map(|validator| validator.address.clone());
+        for address in addresses {
+            let mut link_shutdown = lifecycle.signal();
+            let link_context = rpc_context.clone();
+            let inbox = consensus_inbox.clone();
+            lifecycle.start_service_on("validator link", pools.handle(Lane::Background), async move {
+                loop {
+                    if let Err(e) = follow_validator(&link_context, &inbox, &address, &mut link_shutdown).await {
+                        eprintln!("Validator link to {}: {}", address, e);
+                    }
+                    tokio::select! {
+                        _ = tokio::time::sleep(std::time::Duration::from_secs(VALIDATOR_REDIAL_SECS)) => {}
+                        _ = link_shutdown.wait() => break,
+                    }
+                }
+            });
+        }
+    }
+
+    // Commit new Hubble content as index segments and merge old ones
+    #[cfg(feature = "hubble")]
+    {
+        let mut hubble_shutdown = lifecycle.signal();
+        let hubble_context = rpc_context.clone();
+        let hubble_segments = hubble_store.clone();
+        lifecycle.start_service_on("hubble index commit", pools.handle(Lane::Background), async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(HUBBLE_COMMIT_INTERVAL_SECS));
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        let max_segments = hubble_context.config.read().await.current().hubble.max_segments;
+                        let mut store = hubble_segments.write().await;
+                        if let Err(e) = store.commit(&mut *hubble_context.hubble.write().await) {
+                            eprintln!("Hubble index commit failed: {}", e);
+                        }
+                        if let Err(e) = store.compact(max_segments) {
+                            eprintln!("Hubble segment merge failed: {}", e);
+                        }
+                    }
+                    _ = hubble_shutdown.wait() => break,
+                }
+            }
+        });
+    }
+
+    // Run the VDF for the next epoch's randomness once its contribution window closes
+    let mut beacon_shutdown = lifecycle.signal();
+    let beacon_context = rpc_context.clone();
+    lifecycle.start_service_on("randomness beacon", pools.handle(Lane::Background), async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(BEACON_CHECK_INTERVAL_SECS));
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let height = beacon_context.world_state.read().await.latest_height();
+                    let input = {
+                        // Epochs are finalized in order, catching up one at a time
+                        let beacon = beacon_context.beacon.read().await;
+                        let Ok(input) = beacon.prepare(beacon.latest_epoch() + 1, height) else { continue };
+                        input
+                    };
+                    let epoch = input.epoch;
+                    let Ok(record) = beacon_context.pools.spawn_blocking(Lane::Background, move || input.finalize()).await else {
+                        eprintln!("Beacon VDF for epoch {} failed", epoch);
+                        continue;
+                    };
+                    let store = beacon_context.world_state.read().await;
+                    if let Err(e) = beacon_context.beacon.write().await.commit(record, store.latest().validator_keys()) {
+                        eprintln!("Beacon for epoch {} rejected: {}", epoch, e);
+                    }
+                }
+                _ = beacon_shutdown.wait() => break,
+            }
+        }
+    });
+
+    // Feed measured load into flux routing and move routes off congested nodes
+    let mut flux_shutdown = lifecycle.signal();
+    let flux_context = rpc_context.clone();
+    let flux_id = QuantumNodeID::new(*node_id.as_bytes());
+    let started = std::time::Instant::now();
+    lifecycle.start_service_on("flux rebalance", pools.handle(Lane::Background), async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(FLUX_REBALANCE_INTERVAL_SECS));
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let pending = flux_context.mempool.read().await.len();
+                    let load = (pending as f64 / FLUX_MEMPOOL_CAPACITY as f64).min(1.0);
+                    let state = flux_state(started.elapsed().as_secs());
+                    let peers = flux_context.p2p.links.uptimes().await;
+
+                    let mut flux = flux_context.flux.write().await;
+                    let joined = match flux.get_node(&flux_id) {
+                        Some(_) => flux.update_node_state(&flux_id, state).is_ok(),
+                        // The node joins once uptime lifts its entropy over the chaos threshold
+                        None => flux.add_node(flux_id, state).is_ok(),
+                    };
+                    if joined {
+                        let _ = flux.record_load(&flux_id, PreciseFloat::from_f64(load, 4));
+                    }
+                    // Connected peers join the same way, by the uptime they advertised
+                    for (peer, uptime) in peers {
+                        join_flux(&mut flux, &flux_id, &peer, uptime);
+                    }
+                    if let Err(e) = flux.rebalance_network() {
+                        eprintln!("Flux rebalance failed: {}", e);
+                    }
+                }
+                _ = flux_shutdown.wait() => break,
+            }
+        }
+    });
+
+    // Delete mirrored snapshots, backups and blobs that fall outside their lifecycle policy
+    let remote_storage = rpc_context.remote_storage.clone();
+    if !node_config.remote_storage.is_empty() {
+        let mut lifecycle_shutdown = lifecycle.signal();
+        let lifecycle_storage = remote_storage.clone();
+        let remote_health = rpc_context.health.reporter("remote storage", false);
+        lifecycle.start_service_on("remote storage lifecycle", pools.handle(Lane::Background), async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(REMOTE_LIFECYCLE_INTERVAL_SECS));
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => match lifecycle_storage.write().await.apply_lifecycle().await {
+                        Ok(deleted) => {
+                            remote_health.set(Check::healthy());
+                            if !deleted.is_empty() {
+                                println!("Remote storage: deleted {} expired objects", deleted.len());
+                            }
+                        }
+                        Err(e) => {
+                            eprintln!("Remote storage lifecycle failed: {}", e);
+                            remote_health.set(Check::unhealthy(format!("lifecycle run failed: {}", e)));
+                        }
+                    },
+                    _ = lifecycle_shutdown.wait() => break,
+                }
+            }
+        });
+    }
+
+    // Post anonymized health reports while the operator has opted in; the
+    // setting is re-read before each report, so a config reload toggles it
+    let mut telemetry_shutdown = lifecycle.signal();
+    let telemetry_settings = rpc_context.config.read().await.subscribe();
+    let telemetry_chain = blockchain.clone();
+    let telemetry_peers = rpc_context.p2p.clone();
+    let telemetry_chain_id = node_config.chain_id;
+    lifecycle.start_service_on("telemetry", pools.handle(Lane::Background), async move {
+        let clock = clock::system();
+        let mut meter = ThroughputMeter::new(tallies_computed(), clock.now_millis());
+        let mut client: Option<TelemetryClient> = None;
+        loop {
+            let settings = telemetry_settings.borrow().telemetry.clone();
+            let delay = jittered_interval(
+                std::time::Duration::from_secs(settings.interval_secs),
+                settings.jitter_percent,
+                rand::random(),
+            );
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = telemetry_shutdown.wait() => break,
+            }
+
+            let tally_throughput = meter.sample(tallies_computed(), clock.now_millis());
+            let settings = telemetry_settings.borrow().telemetry.clone();
+            if !settings.enabled {
+                continue;
+            }
+            if client.is_none() {
+                match TelemetryBuffer::open(TELEMETRY_BUFFER_PATH, settings.buffer_limit) {
+                    Ok(buffer) => client = Some(TelemetryClient::new(buffer)),
+                    Err(e) => {
+                        eprintln!("Telemetry unavailable: {}", e);
+                        continue;
+                    }
+                }
+            }
+            let Some(client) = client.as_mut() else { continue };
+            client.buffer_mut().set_limit(settings.buffer_limit);
+            let report = HealthReport {
+                report_version: REPORT_VERSION,
+                instance: client.buffer().instance().to_string(),
+                timestamp: clock.now_secs(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                os: std::env::consts::OS.to_string(),
+                arch: std::env::consts::ARCH.to_string(),
+                chain_id: telemetry_chain_id,
+                peer_count: telemetry_peers.peers.read().await.len(),
+                head_height: telemetry_chain.read().await.height(),
+                tally_throughput,
+            };
+            if let Err(e) = client.report(&settings.endpoint, report).await {
+                eprintln!("Telemetry report buffered ({} pending): {}", client.buffer().pending().len(), e);
+                continue;
+            }
+
+            // Crash reports from earlier runs go up once the collector is reachable
+            if settings.send_crash_reports {
+                for path in crash_reporter.pending_uploads() {
+                    let uploaded = match crash::read_report(&path) {
+                        Ok(report) => client.send_crash(&settings.endpoint, &report).await,
+                        Err(e) => Err(e),
+                    };
+                    if let Err(e) = uploaded.and_then(|_| crash_reporter.mark_uploaded(&path)) {
+                        eprintln!("Crash report {} not uploaded: {}", path.display(), e);
+                        break;
+                    }
+                }
+            }
+        }
+    });
+
+    // Keep the head block in the crash fingerprint current
+    let mut fingerprint_shutdown = lifecycle.signal();
+    let fingerprint_chain = blockchain.clone();
+    lifecycle.start_service_on("crash fingerprint", pools.handle(Lane::Background), async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(CRASH_FINGERPRINT_INTERVAL_SECS));
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let chain = fingerprint_chain.read().await;
+                    let head = chain.height().checked_sub(1).and_then(|height| chain.block(height));
+                    crash_reporter.update(|fingerprint| {
+                        fingerprint.head_height = chain.height();
+                        fingerprint.head_hash = head.map(|block| block.hash);
+                    });
+                }
+                _ = fingerprint_shutdown.wait() => break,
+            }
+        }
+    });
+
+    // Record trust scores against each new state height so `stateAt` can
+    // answer for past blocks, pruned to the same window as world state
+    let mut history_shutdown = lifecycle.signal();
+    let history_context = rpc_context.clone();
+    lifecycle.start_service_on("state history", pools.handle(Lane::Background), async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(STATE_HISTORY_INTERVAL_SECS));
+        let mut recorded = None;
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let (height, earliest) = {
+                        let store = history_context.world_state.read().await;
+                        (store.latest_height(), store.earliest_height())
+                    };
+                    if recorded == Some(height) {
+                        continue;
+                    }
+                    let identity = history_context.identity.read().await;
+                    let mut history = history_context.trust_history.write().await;
+                    if recorded.is_some_and(|recorded| height < recorded) {
+                        // Reorg: scores recorded for replaced blocks no longer apply
+                        history.truncate(height.saturating_sub(1));
+                    }
+                    let ids: BTreeSet<IdentityId> = identity.identity_ids().chain(history.keys()).copied().collect();
+                    for id in ids {
+                        history.record(height, id, identity.get_trust_score(&id).ok());
+                    }
+                    history.prune(earliest);
+                    recorded = Some(height);
+                }
+                _ = history_shutdown.wait() => break,
+            }
+        }
+    });
+
+    // Pass the events of each new height to the extensions subscribed to them
+    #[cfg(feature = "vm-wasm")]
+    if rpc_context.extensions.read().await.extensions().iter().any(|extension| !extension.config().events.is_empty()) {
+        let mut extension_shutdown = lifecycle.signal();
+        let extension_chain = blockchain.clone();
+        let extension_logs = rpc_context.logs.clone();
+        let extension_host = rpc_context.extensions.clone();
+        let extension_queue = export_queue.clone();
+        let mut next_height = blockchain.read().await.height();
+        lifecycle.start_service_on("extension events", pools.handle(Lane::Background), async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(EXPORT_INTERVAL_SECS));
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        let chain = extension_chain.read().await;
+                        let logs = extension_logs.read().await;
+                        let mut extensions = extension_host.write().await;
+                        let to = chain.height().min(next_height + EXPORT_BATCH_BLOCKS);
+                        for height in next_height..to {
+                            let Some(block) = chain.block(height) else { continue };
+                            let events = HeightEvents::new(height)
+                                .block(block)
+                                .receipts(logs.receipts(height))
+                                .queued(extension_queue.peek(height))
+                                .into_events();
+                            for failure in extensions.process(&chain, &events) {
+                                eprintln!("Extension event processing at height {}: {}", height, failure);
+                            }
+                        }
+                        next_height = next_height.max(to);
+                        for (name, line) in extensions.take_logs() {
+                            println!("[extension {}] {}", name, line);
+                        }
+                    }
+                    _ = extension_shutdown.wait() => break,
+                }
+            }
+        });
+    }
+
+    // Stream blocks, receipts, governance and tally events to the configured broker
+    if let Some(export_config) = node_config.event_export.clone() {
+        let mut exporter = Exporter::new(
+            export::open_sink(&export_config.sink)?,
+            OffsetStore::new(&export_config.offset_path),
+            &export_config.topic_prefix,
+        )?;
+        println!("Exporting events from height {}", exporter.next_height());
+        let mut export_shutdown = lifecycle.signal();
+        let export_chain = blockchain.clone();
+        let export_logs = rpc_context.logs.clone();
+        lifecycle.start_service_on("event export", pools.handle(Lane::Background), async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(EXPORT_INTERVAL_SECS));
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        // Gather events first so no lock is held while waiting on the broker
+                        let batch: Vec<_> = {
+                            let chain = export_chain.read().await;
+                            let logs = export_logs.read().await;
+                            let from = exporter.next_height();
+                            let to = chain.height().min(from + EXPORT_BATCH_BLOCKS);
+                            (from..to)
+                                .filter_map(|height| chain.block(height).map(|block| (height, HeightEvents::new(height)
+                                    .block(block)
+                                    .receipts(logs.receipts(height))
+                                    .queued(export_queue.peek(height))
+                                    .into_events())))
+                                .collect()
+                        };
+                        for (height, events) in batch {
+                            if let Err(e) = exporter.export(height, &events).await {
+                                eprintln!("Event export stalled at height {}: {}", height, e);
+                                break;
+                            }
+                            export_queue.prune(height);
+                        }
+                    }
+                    _ = export_shutdown.wait() => break,
+                }
+            }
+        });
+    }
+
+    // Re-read the config file on SIGHUP
+    #[cfg(unix)]
+    {
+        let mut reload_shutdown = lifecycle.signal();
+        let reload_context = rpc_context.clone();
+        let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+        lifecycle.start_service("config reload", async move {
+            loop {
+                tokio::select! {
+                    _ = hangup.recv() => match reload_config(&reload_context).await {
+                        Ok(report) => println!("Configuration reloaded: {:?}", report),
+                        Err(e) => eprintln!("Configuration reload rejected: {}", e),
+                    },
+                    _ = reload_shutdown.wait() => break,
+                }
+            }
+        });
+    }
+
+    // Keep pooled transactions across the restart
+    let pending_chain = blockchain.clone();
+    let pending_pool = rpc_context.mempool.clone();
+    lifecycle.on_shutdown("pending transactions", async move {
+        let mempool = pending_pool.read().await;
+        sealer::save_pending(&mut *pending_chain.write().await, &mempool).map_err(str::to_string)
+    });
+
+    // Persist a final chain snapshot once all services have drained,
+    // mirroring it to remote storage when snapshots are configured
+    let snapshot_chain = blockchain.clone();
+    let snapshot_storage = remote_storage.clone();
+    lifecycle.on_shutdown("chain snapshot", async move {
+        let snapshot = snapshot_chain.read().await.snapshot()?;
+        tokio::fs::create_dir_all(DATA_DIR).await.map_err(|e| e.to_string())?;
+        tokio::fs::write(format!("{}/chain.snapshot", DATA_DIR), &snapshot).await
+            .map_err(|e| e.to_string())?;
+        let mut storage = snapshot_storage.write().await;
+        if storage.is_configured(DataClass::Snapshots) {
+            let height = snapshot_chain.read().await.height();
+            storage.mirror(DataClass::Snapshots, &format!("chain-{}.snapshot", height), &snapshot).await?;
+        }
+        Ok(())
+    });
+
+    // Commit content added since the last interval
+    #[cfg(feature = "hubble")]
+    {
+        let hubble_index = rpc_context.hubble.clone();
+        lifecycle.on_shutdown("hubble index", async move {
+            hubble_store.write().await.commit(&mut *hubble_index.write().await).map(|_| ())
+        });
+    }
+
+    println!("\nQuantum Metaverse Blockchain is running!");
+    println!("Node ID: {}", node_id);
+    println!("Security Level: {:.2}%", security.verify_security_level(&node_key_id)?.value as f64 / 100.0);
+
+    // Report ready to load balancers only after sync and service startup
+    rpc_context.ready.store(true, Ordering::SeqCst);
+
+    // Run until Ctrl+C / SIGTERM, then shut down in reverse startup order
+    lifecycle.wait_for_termination().await?;
+    rpc_context.ready.store(false, Ordering::SeqCst);
+    println!("Shutting down...");
+    let report = lifecycle.shutdown().await;
+    println!(
+        "Shutdown complete: {} services stopped, {} aborted, {} hook failures",
+        report.stopped.len(),
+        report.aborted.len(),
+        report.failed_hooks.len()
+    );
+
+    Ok(())
+}
+
+/// Shared state handed to every RPC connection
+#[derive(Clone)]
+struct RpcContext {
+    /// Reported by `status`
+    node_id: NodeId,
+    config: Arc<RwLock<ConfigManager>>,
+    rate_limiter: Arc<RateLimiter>,
+    log_filter: reload::Handle<LevelFilter, Registry>,
+    private_chains: Arc<PrivateChainHost>,
+    /// blake3 hash of the operator token; operator-only methods are refused
+    /// when none is set
+    operator_token: Option<[u8; 32]>,
+    tls: Option<Arc<CertificateStore>>,
+    /// Set once sync has finished and services are accepting work
+    ready: Arc<AtomicBool>,
+    /// Committed account and contract state, used for dry-run execution
+    world_state: Arc<RwLock<StateStore>>,
+    mempool: Arc<RwLock<Mempool>>,
+    /// Stored blocks, for transaction lookups
+    chain: Arc<RwLock<Blockchain>>,
+    /// Headers-first sync progress, for `getSyncStatus`
+    sync: Arc<RwLock<SyncEngine>>,
+    /// Recent state snapshots served to fast-syncing peers, newest first
+    snapshots: Arc<RwLock<VecDeque<Snapshot>>>,
+    /// Snapshot download of a fast sync, for `getSnapshotStatus`
+    snapshot_download: Arc<RwLock<Option<SnapshotDownload>>>,
+    /// Receipts and header blooms for `getLogs`
+    logs: Arc<RwLock<LogIndex>>,
+    /// Gas, calls and failures per contract for `getContractStats`
+    contract_meter: Arc<RwLock<ContractMeter>>,
+    /// Runtime lanes; heavy RPC work runs on the background pool
+    pools: RuntimePools,
+    /// `chain_id` from the config; consensus-critical, so fixed while running
+    network_id: u64,
+    /// Congestion-aware routes for transaction relay
+    flux: Arc<RwLock<FluxNetwork>>,
+    /// Multisig approvals collected before submission
+    multisig_approvals: Arc<RwLock<ApprovalPool>>,
+    /// Channel updates held to dispute stale closes
+    watchtower: Arc<RwLock<Watchtower>>,
+    /// Main-chain commitments, if commit-reveal ordering is enabled
+    commit_reveal: Option<Arc<RwLock<CommitRevealQueue>>>,
+    /// Full-text index behind `hubble_search`
+    #[cfg(feature = "hubble")]
+    hubble: Arc<RwLock<ContentIndex>>,
+    /// Proof of work or fee required to add Hubble content
+    #[cfg(feature = "hubble")]
+    hubble_admission: Arc<RwLock<SubmissionGuard>>,
+    economics: Arc<RwLock<EconomicModel>>,
+    /// Identity registry and its web-of-trust attestations
+    identity: Arc<RwLock<ZKIdentity>>,
+    /// Attribute predicate challenges waiting for the holder's proof
+    predicate_challenges: Arc<RwLock<PredicateChallenges>>,
+    /// Epoch randomness: validator contributions and finalized VDF outputs
+    beacon: Arc<RwLock<RandomnessBeacon>>,
+    /// Epoch boundaries, the active validator set and per-epoch checkpoints
+    epochs: Arc<RwLock<EpochManager>>,
+    /// Protocol parameters by activation height
+    params: Arc<RwLock<ParamsRegistry>>,
+    /// Header signals per protocol feature and the heights they activate at
+    features: Arc<RwLock<FeatureTracker>>,
+    /// Connected peers and, on a permissioned network, the certificate registry
+    p2p: Arc<P2PNetwork>,
+    /// Quantum overlay nodes and their entanglement pairs
+    quantum_network: Arc<RwLock<QuantumNetwork>>,
+    /// Snapshots, backups and asset media mirrored off-node
+    remote_storage: Arc<RwLock<RemoteStorage>>,
+    /// Recent trace events per transaction
+    traces: Arc<RwLock<TraceLog>>,
+    /// Trust scores as of each block height they changed at, for `stateAt`
+    trust_history: Arc<RwLock<VersionedMap<IdentityId, PreciseFloat>>>,
+    /// Per-component probes behind `/health` and `status`
+    health: HealthRegistry,
+    /// Bridge fee share held against proven bridge failures
+    #[cfg(feature = "bridges")]
+    insurance: Arc<RwLock<InsuranceFund>>,
+    /// Collateral and wrapped supply per bridged chain
+    #[cfg(feature = "bridges")]
+    reserve_ledger: Arc<RwLock<ReserveLedger>>,
+    /// Per-epoch proofs of reserve and the alerts for failed epochs
+    #[cfg(feature = "bridges")]
+    reserves: Arc<RwLock<ReserveLog>>,
+    /// Emergency halts and the votes toward the next halt or resume
+    circuit_breaker: Arc<RwLock<CircuitBreaker>>,
+    /// Reality layers, their access policies and the observers present
+    #[cfg(feature = "metaverse")]
+    orchestrator: Arc<RwLock<Orchestrator>>,
+    /// DAOs governing single reality layers and private chains
+    daos: Arc<RwLock<DaoFactory>>,
+    /// WebAssembly extensions: `ext_*` methods, event processors and the Hubble ranker
+    #[cfg(feature = "vm-wasm")]
+    extensions: Arc<RwLock<ExtensionHost>>,
+}
+
+/// Pool saturation at which a lane counts as overloaded
+const SATURATED_PERCENT: u64 = 100;
+
+/// Register a probe for each of the node's major components
+fn register_health_probes(ctx: &RpcContext, chain: &Arc<RwLock<Blockchain>>) {
+    let chain = chain.clone();
+    ctx.health.register("chain store", true, move || {
+        let chain = chain.clone();
+        async move {
+            let chain = chain.read().await;
+            match chain.height().checked_sub(1).and_then(|tip| chain.block(tip)) {
+                Some(tip) if !tip.verify_hash() => Check::unhealthy(format!("tip block {} fails hash verification", tip.index)),
+                Some(_) => Check::healthy(),
+                None => Check::unhealthy("chain has no genesis block"),
+            }
+        }
+    });
+
+    let network = ctx.p2p.clone();
+    ctx.health.register("p2p", true, move || {
+        let network = network.clone();
+        async move {
+            if let Some(sentry) = &network.sentry {
+                let sentry = sentry.read().await;
+                if sentry.role() == SentryRole::Validator && sentry.healthy().is_empty() {
+                    return Check::unhealthy("no healthy sentries");
+                }
+            }
+            if network.peers.read().await.is_empty() {
+                Check::degraded("no connected peers")
+            } else {
+                Check::healthy()
+            }
+        }
+    });
+
+    // RPC and the tally worker share the runtime lanes with everything else,
+    // so a saturated lane means slow responses
+    let (ready, pools) = (ctx.ready.clone(), ctx.pools.clone());
+    ctx.health.register("rpc", true, move || {
+        let ready = ready.load(Ordering::SeqCst);
+        let critical = pools.saturation().into_iter().find(|pool| pool.name == "critical");
+        async move {
+            match critical {
+                _ if !ready => Check::degraded("waiting for sync before accepting work"),
+                Some(pool) if pool.saturation_percent >= SATURATED_PERCENT => {
+                    Check::degraded(format!("critical lane {}% saturated", pool.saturation_percent))
+                }
+                _ => Check::healthy(),
+            }
+        }
+    });
+
+    let pools = ctx.pools.clone();
+    ctx.health.register("tally worker", false, move || {
+        let background = pools.saturation().into_iter().find(|pool| pool.name == "background");
+        async move {
+            match background {
+                Some(pool) if pool.saturation_percent >= SATURATED_PERCENT => Check::degraded(format!(
+                    "background lane {}% saturated with {} blocking jobs queued",
+                    pool.saturation_percent, pool.blocking_tasks
+                )),
+                _ => Check::healthy(),
+            }
+        }
+    });
+
+    ctx.health.register("web2 runner", false, || async {
+        match tokio::process::Command::new("docker").arg("version").output().await {
+            Ok(output) if output.status.success() => Check::healthy(),
+            Ok(_) => Check::unhealthy("docker daemon is not reachable"),
+            Err(e) => Check::unhealthy(format!("docker unavailable: {}", e)),
+        }
+    });
+
+    ctx.health.register("storage", true, || async {
+        let probe = std::path::Path::new(DATA_DIR).join(".health");
+        if let Err(e) = tokio::fs::write(&probe, b"ok").await {
+            return Check::unhealthy(format!("{} is not writable: {}", DATA_DIR, e));
+        }
+        let _ = tokio::fs::remove_file(&probe).await;
+        Check::healthy()
+    });
+}
+
+/// Log to stdout and keep the latest lines in `ring` for crash reports
+fn init_logging(level: &str, ring: LogRing) -> reload::Handle<LevelFilter, Registry> {
+    let level = level.parse().unwrap_or(LevelFilter::INFO);
+    let (filter, handle) = reload::Layer::new(level);
+    let _ = tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .with(tracing_subscriber::fmt::layer().with_writer(ring).with_ansi(false))
+        .try_init();
+    handle
+}
+
+/// Re-read the config file and push safe settings into running services
+async fn reload_config(ctx: &RpcContext) -> Result<ReloadReport, String> {
+    let mut manager = ctx.config.write().await;
+    let report = manager.reload()?;
+    let config = manager.current();
+
+    let level: LevelFilter = config.log_level.parse().map_err(|_| "Invalid log_level")?;
+    ctx.log_filter.modify(|filter| *filter = level).map_err(|e| e.to_string())?;
+    if let Some(reporter) = crash::reporter() {
+        reporter.update(|fingerprint| fingerprint.config_digest = Some(config_digest(config)));
+    }
+    ctx.rate_limiter.set_limit(config.rpc_rate_limit);
+    if let Some(tls) = &ctx.tls {
+        tls.reload()?;
+    }
+    Ok(report)
+}
+
+struct P2PConfig {
+    listen: Vec<SocketAddr>,
+    _node_key: QuantumKey,
+    node_id: NodeId,
+    network: Arc<P2PNetwork>,
+    chain: Arc<RwLock<Blockchain>>,
+    flux: Arc<RwLock<FluxNetwork>>,
+    traces: Arc<RwLock<TraceLog>>,
+    economics: Arc<RwLock<EconomicModel>>,
+    snapshots: Arc<RwLock<VecDeque<Snapshot>>>,
+    mempool: Arc<RwLock<Mempool>>,
+    world_state: Arc<RwLock<StateStore>>,
+    blocks: BlockImport,
+    consensus: ConsensusInbox,
+}
+
+struct GenesisConfig {
+    _chain_id: u64,
+    _initial_validators: Vec<[u8; 32]>,
+    _initial_supply: u64,
+}
+
+fn generate_genesis_config() -> GenesisConfig {
+    GenesisConfig {
+        _chain_id: 1,
+        _initial_validators: vec![
+            [0u8; 32], // Replace with actual validator addresses
+        ],
+        _initial_supply: 10_000_000_000, // 10B tokens
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct P2PMessage {
+    message_type: String,
+    payload: serde_json::Value,
+    /// Trace of the request that caused this message, passed on with every relay
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    trace_id: Option<TraceId>,
+}
+
+async fn run_p2p_network(
+    config: P2PConfig,
+    settings: watch::Receiver<NodeConfig>,
+    mut shutdown: ShutdownSignal,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let listeners = Listeners::bind(&config.listen)?;
+    for addr in listeners.local_addrs() {
+        println!("P2P network listening on {}", addr);
+    }
+
+    let connections = ConnectionTracker::new();
+    loop {
+        tokio::select! {
+            accepted = listeners.accept() => {
+                let Ok((stream, peer)) = accepted else { break };
+                // Peer limit may change on config reload
+                if connections.active() >= settings.borrow().max_peers {
+                    continue;
+                }
+                if !config.network.sentry_admits(&peer.to_string()).await {
+                    eprintln!("Refused connection from {}: validator peers only with its sentries", peer);
+                    continue;
+                }
+                let guard = connections.track();
+                let conn_shutdown = shutdown.clone();
+                let network = config.network.clone();
+                let chain = config.chain.clone();
+                let relay = Relay {
+                    flux: config.flux.clone(),
+                    local: QuantumNodeID::new(*config.node_id.as_bytes()),
+                    settings: settings.clone(),
+                    traces: config.traces.clone(),
+                    economics: config.economics.clone(),
+                    snapshots: config.snapshots.clone(),
+                    mempool: config.mempool.clone(),
+                    world_state: config.world_state.clone(),
+                    blocks: config.blocks.clone(),
+                    consensus: config.consensus.clone(),
+                };
+                tokio::spawn(async move {
+                    handle_p2p_connection(stream, peer.to_string(), network, chain, relay, conn_shutdown).await;
+                    drop(guard);
+                });
+            }
+            _ = shutdown.wait() => break,
+        }
+    }
+
+    // Stop accepting peers and wait for open sessions to close
+    drop(listeners);
+    connections.wait_idle().await;
+    Ok(())
+}
+
+/// What a peer connection needs to relay transactions and observations
+struct Relay {
+    flux: Arc<RwLock<FluxNetwork>>,
+    local: QuantumNodeID,
+    settings: watch::Receiver<NodeConfig>,
+    traces: Arc<RwLock<TraceLog>>,
+    /// Observer bonds gate the observation topic
+    economics: Arc<RwLock<EconomicModel>>,
+    /// State snapshots served to fast-syncing peers
+    snapshots: Arc<RwLock<VecDeque<Snapshot>>>,
+    /// Transactions from peers are admitted here before they are relayed
+    mempool: Arc<RwLock<Mempool>>,
+    world_state: Arc<RwLock<StateStore>>,
+    /// Blocks peers announce join the sync import queue here
+    blocks: BlockImport,
+    /// Consensus envelopes go on to this node's validator and other peers
+    consensus: ConsensusInbox,
+}
+
+/// Replace a compressed frame by the message it wraps. Frames that fail
+/// the compression limits are dropped.
+fn inflate_message(
+    msg: tokio_tungstenite::tungstenite::Message,
+    network: &P2PNetwork,
+    config: &CompressionConfig,
+    peer: &str,
+) -> Option<tokio_tungstenite::tungstenite::Message> {
+    let tokio_tungstenite::tungstenite::Message::Binary(frame) = &msg else { return Some(msg) };
+    let Ok(view) = MessageView::parse(frame) else { return Some(msg) };
+    if view.kind != MessageKind::Compressed {
+        return Some(msg);
+    }
+    match compression::decompress(view.payload, config, &network.compression) {
+        Ok(Payload::Binary(frame)) => Some(tokio_tungstenite::tungstenite::Message::Binary(frame)),
+        Ok(Payload::Text(text)) => Some(tokio_tungstenite::tungstenite::Message::Text(text)),
+        Err(e) => {
+            eprintln!("Dropped compressed frame from {}: {}", peer, e);
+            None
+        }
+    }
+}
+
+/// Send a JSON message compressed when the connection settled on a codec
+/// and the message is large enough to be worth it
+fn compress_message(
+    text: String,
+    codec: Option<Codec>,
+    network: &P2PNetwork,
+    config: &CompressionConfig,
+) -> tokio_tungstenite::tungstenite::Message {
+    let payload = Payload::Text(text);
+    match codec.and_then(|codec| compression::compress(codec, &payload, config, &network.compression)) {
+        Some(frame) => tokio_tungstenite::tungstenite::Message::Binary(frame),
+        None => match payload {
+            Payload::Text(text) => tokio_tungstenite::tungstenite::Message::Text(text),
+            Payload::Binary(frame) => tokio_tungstenite::tungstenite::Message::Binary(frame),
+        },
+    }
+}
+
+async fn handle_p2p_connection(
+    stream: tokio::net::TcpStream,
+    peer: String,
+    network: Arc<P2PNetwork>,
+    chain: Arc<RwLock<Blockchain>>,
+    relay: Relay,
+    mut shutdown: ShutdownSignal,
+) {
+    if let Ok(ws_stream) = accept_async(stream).await {
+        let (mut write, mut read) = ws_stream.split();
+        // Codec for large replies, settled by the peer's handshake
+        let mut codec = None;
+        // Relayed messages for this peer, queued once its handshake is accepted
+        let mut link = None;
+        
+        loop {
+            let msg = tokio::select! {
+                msg = read.next() => msg,
+                Some(relayed) = next_relayed(&mut link) => {
+                    let compression = relay.settings.borrow().compression.clone();
+                    let _ = write.send(compress_message(relayed, codec, &network, &compression)).await;
+                    continue;
+                }
+                _ = shutdown.wait() => {
+                    let _ = write.send(tokio_tungstenite::tungstenite::Message::Close(None)).await;
+                    break;
+                }
+            };
+            let Some(msg) = msg else { break };
+
+            if let Ok(msg) = msg {
+                #[cfg(feature = "fault-injection")]
+                if quantum_metaverse::chaos::should_drop_async(quantum_metaverse::chaos::P2P_MESSAGE).await {
+                    continue;
+                }
+
+                // Any traffic from a private peer shows the link is alive
+                if let Some(sentry) = &network.sentry {
+                    if sentry.read().await.is_private(&peer) {
+                        sentry.write().await.record_seen(&peer, unix_millis() / 1000, None);
+                    }
+                }
+
+                // Compressed frames are handled as the message they wrap
+                let compression = relay.settings.borrow().compression.clone();
+                let Some(msg) = inflate_message(msg, &network, &compression, &peer) else { continue };
+
+                // Binary frames carry blocks, text frames JSON messages
+                let p2p_msg = if msg.is_binary() {
+                    None
+                } else {
+                    let Ok(text) = msg.to_text() else { continue };
+                    let Ok(p2p_msg) = serde_json::from_str::<P2PMessage>(text) else { continue };
+                    println!("Received P2P message: {:?}", p2p_msg);
+                    Some(p2p_msg)
+                };
+
+                // Exchange handshakes so sync can pick peers that hold the needed history
+                if let Some(p2p_msg) = p2p_msg.as_ref().filter(|p2p_msg| p2p_msg.message_type == "handshake") {
+                    let Ok(remote) = Handshake::deserialize(&p2p_msg.payload) else { break };
+                    if let Err(e) = network.register_peer(&peer, &remote, std::time::Duration::default()).await {
+                        eprintln!("Rejected peer {}: {}", peer, e);
+                        break;
+                    }
+                    link = Some(network.links.open(&peer, remote.uptime).await);
+                    join_flux(&mut *relay.flux.write().await, &relay.local, &peer, remote.uptime);
+                    let mut local = network.local_handshake(chain.read().await.height()).await;
+                    local.compression = compression.codecs.clone();
+                    codec = compression::negotiate(&compression.codecs, &remote.compression);
+                    let mut replies = vec![P2PMessage {
+                        message_type: "handshake".to_string(),
+                        payload: json!(local),
+                        trace_id: None,
+                    }];
+                    // Bring a newly admitted peer up to date on revocations
+                    if let Some(permissions) = &network.permissions {
+                        replies.push(P2PMessage {
+                            message_type: "revocations".to_string(),
+                            payload: json!(permissions.registry.read().await.revocations()),
+                            trace_id: None,
+                        });
+                    }
+                    for reply in replies {
+                        if let Ok(reply) = serde_json::to_string(&reply) {
+                            let _ = write.send(tokio_tungstenite::tungstenite::Message::Text(reply)).await;
+                        }
+                    }
+                    continue;
+                }
+
+                // Make way for a peer from a network the table lacked
+                if network.take_eviction(&peer).await {
+                    println!("Closing connection to {} for a more diverse peer", peer);
+                    break;
+                }
+
+                // A permissioned network ignores everything else until the handshake is
+                // accepted, and hangs up once the peer's certificate stops being valid
+                if !network.is_admitted(&peer).await {
+                    eprintln!("Closing connection to uncertified peer {}", peer);
+                    break;
+                }
+
+                // Blocks are only taken from peers whose handshake was accepted.
+                // The hot path inspects the frame in place and copies the block
+                // out only once its hash checks.
+                let Some(p2p_msg) = p2p_msg else {
+                    if link.is_none() {
+                        eprintln!("Closing connection to {}: block before handshake", peer);
+                        break;
+                    }
+                    let tokio_tungstenite::tungstenite::Message::Binary(frame) = &msg else { continue };
+                    let block = match MessageView::parse(frame).and_then(|message| message.block()) {
+                        Ok(view) if view.verify_hash() => view.to_block(),
+                        Ok(view) => {
+                            eprintln!("Peer {} sent block {} with a bad hash", peer, view.index());
+                            continue;
+                        }
+                        Err(e) => {
+                            eprintln!("Malformed frame from {}: {}", peer, e);
+                            continue;
+                        }
+                    };
+                    let index = block.index;
+                    let message = P2PMessage { message_type: "block".to_string(), payload: json!(block), trace_id: None };
+                    let fanout = relay.settings.borrow().gossip_fanout;
+                    match relay.blocks.import(Some(block)).await {
+                        Ok(()) => relay_consensus(&network, &peer, &message, fanout).await,
+                        Err(e) => eprintln!("Rejected block {} from {}: {}", index, peer, e),
+                    }
+                    continue;
+                };
+
+                // Serve headers-first sync to peers behind this node
+                if p2p_msg.message_type == "get_headers" || p2p_msg.message_type == "get_bodies" {
+                    let chain = chain.read().await;
+                    let reply = if p2p_msg.message_type == "get_headers" {
+                        let Ok(request) = serde_json::from_value::<HeadersRequest>(p2p_msg.payload) else { continue };
+                        P2PMessage { message_type: "headers".to_string(), payload: json!(sync::serve_headers(&chain, &request)), trace_id: None }
+                    } else {
+                        let Ok(request) = serde_json::from_value::<BodiesRequest>(p2p_msg.payload) else { continue };
+                        P2PMessage { message_type: "bodies".to_string(), payload: json!(sync::serve_bodies(&chain, &request)), trace_id: None }
+                    };
+                    drop(chain);
+                    if let Ok(reply) = serde_json::to_string(&reply) {
+                        let _ = write.send(compress_message(reply, codec, &network, &compression)).await;
+                    }
+                    continue;
+                }
+
+                // Serve state snapshots to fast-syncing peers
+                if p2p_msg.message_type == "get_snapshot_manifests" || p2p_msg.message_type == "get_snapshot_chunk" {
+                    let snapshots = relay.snapshots.read().await;
+                    let reply = if p2p_msg.message_type == "get_snapshot_manifests" {
+                        let manifests: Vec<&SnapshotManifest> = snapshots.iter().map(|snapshot| &snapshot.manifest).collect();
+                        P2PMessage { message_type: "snapshot_manifests".to_string(), payload: json!(manifests), trace_id: None }
+                    } else {
+                        let Ok(request) = serde_json::from_value::<ChunkRequest>(p2p_msg.payload) else { continue };
+                        let Some(chunk) = snapshots.iter().find_map(|snapshot| snapshot.chunk(&request)) else { continue };
+                        P2PMessage { message_type: "snapshot_chunk".to_string(), payload: json!(chunk), trace_id: None }
+                    };
+                    drop(snapshots);
+                    if let Ok(reply) = serde_json::to_string(&reply) {
+                        let _ = write.send(compress_message(reply, codec, &network, &compression)).await;
+                    }
+                    continue;
+                }
+
+                // Merge certificate revocations and pass new ones on
+                if p2p_msg.message_type == "revocations" {
+                    let Ok(revocations) = serde_json::from_value::<Vec<CertificateRevocation>>(p2p_msg.payload) else { continue };
+                    // Only revocations new to this node go on, so gossip stops where it has been
+                    let fresh = network.apply_revocations(revocations).await;
+                    if !fresh.is_empty() {
+                        let fanout = relay.settings.borrow().gossip_fanout;
+                        let prefix = trace_prefix(p2p_msg.trace_id.as_ref());
+                        let message = P2PMessage { message_type: "revocations".to_string(), payload: json!(fresh), trace_id: p2p_msg.trace_id };
+                        let sent = gossip(&network, &peer, &message, fanout).await;
+                        println!("{}Relayed {} certificate revocations by gossip to {} peers", prefix, fresh.len(), sent);
+                    }
+                    continue;
+                }
+
+                // Observations are admitted by stake and rate here and leave
+                // only in the per-layer batches of the observation flush
+                if p2p_msg.message_type == OBSERVATION_TOPIC {
+                    let Ok(observation) = serde_json::from_value::<TallyObservation>(p2p_msg.payload) else { continue };
+                    let economics = relay.economics.read().await;
+                    if let Err(e) = network.receive_observation(observation, economics.providers()).await {
+                        eprintln!("Dropped tally observation from {}: {}", peer, e);
+                    }
+                    continue;
+                }
+                // A peer's batch is merged into the next one this node sends,
+                // so only observations new to this node go further
+                if p2p_msg.message_type == AGGREGATE_TOPIC {
+                    let Ok(aggregate) = serde_json::from_value::<LayerAggregate>(p2p_msg.payload) else { continue };
+                    let economics = relay.economics.read().await;
+                    network.receive_aggregate(aggregate, economics.providers()).await;
+                    continue;
+                }
+
+                // Relay transactions along low-load flux routes, or by plain
+                // gossip fanout until flux knows a route
+                if p2p_msg.message_type == "transaction" {
+                    let Ok(tx) = serde_json::from_value::<Transaction>(p2p_msg.payload) else { continue };
+                    let hash = tx.hash();
+                    // Untraced transactions get a trace here so later hops can still be followed
+                    let trace_id = p2p_msg.trace_id.unwrap_or_else(TraceId::generate);
+                    relay.traces.write().await.record(hash, trace_id.clone(), TraceStage::PeerReceived, unix_millis(), format!("from {}", peer));
+                    let forward = P2PMessage { message_type: "transaction".to_string(), payload: json!(tx), trace_id: Some(trace_id.clone()) };
+                    let Ok(forward) = serde_json::to_string(&forward) else { continue };
+
+                    // Only transactions new to the mempool go on, so relay stops where it has been.
+                    // Lock order matches the maintenance task: state, then mempool
+                    let refused = {
+                        let store = relay.world_state.read().await;
+                        let mut mempool = relay.mempool.write().await;
+                        if mempool.get(&hash).is_some() {
+                            Some(RelayOutcome::Duplicate)
+                        } else {
+                            mempool.insert(tx, store.latest()).err().map(|_| RelayOutcome::Rejected)
+                        }
+                    };
+                    if let Some(outcome) = refused {
+                        network.relay.record(outcome);
+                        continue;
+                    }
+
+                    let fanout = relay.settings.borrow().gossip_fanout;
+                    let hops = relay.flux.read().await.relay_targets(&relay.local, fanout);
+                    let hops: Vec<String> = network.links.resolve(&hops).await.into_iter().filter(|hop| *hop != peer).collect();
+                    let sent = network.links.send(&hops, &forward).await;
+                    let (outcome, route) = if sent > 0 {
+                        (RelayOutcome::Flux(sent), format!("along {} flux routes", sent))
+                    } else {
+                        let targets = network.links.gossip_targets(&peer, fanout).await;
+                        let sent = network.links.send(&targets, &forward).await;
+                        (RelayOutcome::Gossip(sent), format!("by gossip to {} peers", sent))
+                    };
+                    network.relay.record(outcome);
+                    relay.traces.write().await.record(hash, trace_id, TraceStage::Relayed, unix_millis(), route);
+                    continue;
+                }
+                
+                // Blocks relayed as JSON are imported like binary block frames
+                if p2p_msg.message_type == "block" {
+                    let Ok(block) = Block::deserialize(&p2p_msg.payload) else { continue };
+                    let index = block.index;
+                    if let Err(e) = relay.blocks.import(Some(block)).await {
+                        eprintln!("Rejected block {} from {}: {}", index, peer, e);
+                        continue;
+                    }
+                }
+
+                // Consensus envelopes go to this node's validator and on to
+                // every validator they are sealed for
+                if p2p_msg.message_type == "vote" {
+                    let fanout = relay.settings.borrow().gossip_fanout;
+                    relay.consensus.receive(&network, &peer, &p2p_msg, fanout).await;
+                    continue;
+                }
+
+                // Behind sentries, blocks follow the private links
+                if CONSENSUS_MESSAGES.contains(&p2p_msg.message_type.as_str()) {
+                    let fanout = relay.settings.borrow().gossip_fanout;
+                    relay_consensus(&network, &peer, &p2p_msg, fanout).await;
+                    continue;
+                }
+
+                // Echo back
+                let _ = write.send(msg).await;
+            }
+        }
+
+        network.peers.write().await.remove(&peer);
+        network.take_eviction(&peer).await;
+        network.links.close(&peer).await;
+        relay.flux.write().await.remove_node(&relay::flux_id(&peer));
+    }
+}
+
+/// Input for the consensus service
+enum ConsensusEvent {
+    /// An envelope a peer relayed
+    Envelope(Envelope),
+    /// A step timeout the engine scheduled has passed
+    Timeout(Timeout),
+}
+
+/// Where consensus envelopes from peers go: this node's validator, if it
+/// is one, and the envelopes already passed on
+#[derive(Clone)]
+struct ConsensusInbox {
+    events: Option<mpsc::UnboundedSender<ConsensusEvent>>,
+    seen: Arc<RwLock<SeenEnvelopes>>,
+}
+
+impl ConsensusInbox {
+    /// Take an envelope `peer` relayed: hand it to this node's validator
+    /// and pass it on, once
+    async fn receive(&self, network: &P2PNetwork, peer: &str, message: &P2PMessage, fanout: usize) {
+        let Ok(envelope) = serde_json::from_value::<Envelope>(message.payload.clone()) else { return };
+        if !self.seen.write().await.insert(&envelope) {
+            return;
+        }
+        if let Some(events) = &self.events {
+            let _ = events.send(ConsensusEvent::Envelope(envelope));
+        }
+        publish_consensus(network, Some(peer), message, fanout).await;
+    }
+
+    /// Send an envelope this node's validator sealed
+    async fn publish(&self, network: &P2PNetwork, envelope: Envelope, fanout: usize) {
+        self.seen.write().await.insert(&envelope);
+        let message = P2PMessage { message_type: "vote".to_string(), payload: json!(envelope), trace_id: None };
+        publish_consensus(network, None, &message, fanout).await;
+    }
+}
+
+/// Send a consensus message along the sentry topology, or else to every
+/// link but `from`'s, since the validators it is for may be anywhere
+async fn publish_consensus(network: &P2PNetwork, from: Option<&str>, message: &P2PMessage, fanout: usize) -> usize {
+    let Ok(text) = serde_json::to_string(message) else { return 0 };
+    let targets = match &network.sentry {
+        Some(_) => network.consensus_targets(from, &message.message_type, fanout).await,
+        None => network.links.gossip_targets(from.unwrap_or_default(), usize::MAX).await,
+    };
+    network.links.send(&targets, &text).await
+}
+
+/// Consensus driver running the `consensus.engine` for this node if
+/// `consensus.validators` lists it, after staking every genesis validator in
+/// `economics`. With no validators configured the node validates alone,
+/// staking the minimum.
+fn consensus_driver(config: &NodeConfig, node_key_id: [u8; 32], node_key: &QuantumKey, economics: &mut EconomicModel) -> Result<Option<ConsensusDriver>, String> {
+    let validators = if config.consensus.validators.is_empty() {
+        vec![(node_key_id, node_key.verification_key().to_vec(), ParamKey::MinimumStake.default_value())]
+    } else {
+        config.consensus.validators.iter()
+            .map(|validator| Ok((validator.validator_id()?, validator.vote_key_bytes()?, validator.stake_amount())))
+            .collect::<Result<Vec<_>, String>>()?
+    };
+
+    // Voting power is counted in token cents; at the node's precision a
+    // whole-token stake does not keep its value
+    let mut weights = EconomicModel::new(2);
+    let mut set = ValidatorSet::new(config.epochs, validators.len());
+    let mut vote_keys = HashMap::new();
+    for (id, vote_key, stake) in validators {
+        set.join(id)?;
+        weights.stake_tokens(id, stake.clone())?;
+        economics.stake_tokens(id, stake)?;
+        vote_keys.insert(id, vote_key);
+    }
+    set.rotate(0, &weights)?;
+    match vote_keys.get(&node_key_id) {
+        None => return Ok(None),
+        Some(vote_key) if vote_key.as_slice() != node_key.verification_key() => {
+            return Err("consensus.validators lists this node with another vote key".to_string());
+        }
+        Some(_) => {}
+    }
+
+    let mut security = QuantumSecurity::new(config.precision);
+    let local = LocalValidator { id: node_key_id, key_id: security.import_key(node_key.clone())? };
+    let domain = SigningDomain::main_chain(config.chain_id);
+    let engine = build_engine(config.consensus.engine, config.consensus.timeouts, config.precision, domain, security, Some(local), check_block_value);
+    Ok(Some(ConsensusDriver::new(engine, set, vote_keys, node_key_id)))
+}
+
+/// Start deciding `height`, offering the next block from the mempool
+/// unless block production is off or halted
+async fn start_height(ctx: &RpcContext, driver: &mut ConsensusDriver, block_time: &BlockTimeConfig, height: u64) -> Result<Vec<Output>, String> {
+    let parent = ctx.chain.read().await.block(height - 1).map(|tip| tip.hash).ok_or("Chain tip missing")?;
+    let value = match block_time.seal {
+        SealMode::Off => None,
+        SealMode::Interval | SealMode::Instant => next_block_value(ctx).await?,
+    };
+    driver.start(height, parent, value).map_err(str::to_string)
+}
+
+/// The next block from the mempool as a consensus value; none while the
+/// circuit breaker halts block production
+async fn next_block_value(ctx: &RpcContext) -> Result<Option<Value>, String> {
+    let (production_halted, halted_modules) = {
+        let breaker = ctx.circuit_breaker.read().await;
+        (breaker.is_halted(&HaltScope::BlockProduction), breaker.halted_modules())
+    };
+    if production_halted {
+        return Ok(None);
+    }
+    let (config, precision) = {
+        let config = ctx.config.read().await;
+        (config.current().block_builder.clone(), config.current().precision)
+    };
+    let strategy = ordering_strategy(config.strategy, &config)?;
+    let limits = BundleLimits { max_gas: config.max_gas, max_bytes: config.max_bytes };
+
+    // Lock order matches the maintenance task: state, then mempool
+    let store = ctx.world_state.read().await;
+    let mempool = ctx.mempool.read().await;
+    let mut builder = BlockBuilder::new(strategy.as_ref(), limits).with_halted_modules(halted_modules);
+    if let Some(queue) = &ctx.commit_reveal {
+        let queue = queue.read().await;
+        builder = builder.with_leading(queue.due(store.latest_height() + 1).into_iter().map(|(_, tx)| tx.clone()).collect());
+    }
+    let block = sealer::propose_block(&mut *ctx.chain.write().await, &store, &mempool, &builder)?;
+    Ok(Some(block_value(&block, &QuantumSecurity::new(precision))))
+}
+
+/// Carry out the engine's outputs: send its messages, schedule its
+/// timeouts, import decided blocks and slash offenders. Returns whether a
+/// block was decided.
+async fn carry_out(
+    ctx: &RpcContext,
+    driver: &mut ConsensusDriver,
+    inbox: &ConsensusInbox,
+    timers: &mpsc::UnboundedSender<ConsensusEvent>,
+    out: Vec<Output>,
+    dump_dir: Option<&std::path::Path>,
+) -> bool {
+    let fanout = ctx.config.read().await.current().gossip_fanout;
+    let mut decided = false;
+    for output in out {
+        match output {
+            Output::Broadcast(message) => {
+                let mut network = ctx.quantum_network.write().await;
+                let sealed = driver.seal(&mut network, &message);
+                drop(network);
+                match sealed {
+                    Ok(envelope) if envelope.sealed.is_empty() => {}
+                    Ok(envelope) => inbox.publish(&ctx.p2p, envelope, fanout).await,
+                    Err(e) => eprintln!("Consensus: cannot seal a message: {}", e),
+                }
+            }
+            Output::Schedule(timeout, after) => {
+                let timers = timers.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(after).await;
+                    let _ = timers.send(ConsensusEvent::Timeout(timeout));
+                });
+            }
+            Output::Decide(decision) => match driver.decide(&decision) {
+                Ok(block) => {
+                    decided = true;
+                    import_decided(ctx, block, fanout, dump_dir).await;
+                }
+                Err(e) => eprintln!("Consensus: decision for height {} does not check: {}", decision.commit.height, e),
+            },
+            Output::Evidence(evidence) => {
+                if driver.report(&evidence) {
+                    println!("Validator 0x{} committed {:?} at height {}", hex::encode(evidence.validator()), evidence.offense(), evidence.step().0);
+                }
+            }
+        }
+    }
+    for event in driver.slash(&mut *ctx.economics.write().await) {
+        println!("Slashed validator 0x{} for {:?}, offense {} on record", hex::encode(event.validator), event.offense, event.offenses);
+    }
+    decided
+}
+
+/// Import a decided block through sync like a block a peer announced, pass
+/// it to peers, and drop its transactions from the pool
+async fn import_decided(ctx: &RpcContext, block: Block, fanout: usize, dump_dir: Option<&std::path::Path>) {
+    let (index, hash) = (block.index, block.hash);
+    let transactions = reindex::block_transactions(&block);
+    let message = P2PMessage { message_type: "block".to_string(), payload: json!(block), trace_id: None };
+    if let Err(e) = BlockImport::new(ctx).import(Some(block)).await {
+        eprintln!("Failed to import decided block {}: {}", index, e);
+        return;
+    }
+    // Sync may hold other blocks at the tip, or a peer's copy got there first
+    if ctx.chain.read().await.block(index).map(|block| block.hash) != Some(hash) {
+        eprintln!("Decided block {} is not at the tip; leaving it to sync", index);
+        return;
+    }
+    println!("Decided block {} with {} transactions", index, transactions.len());
+    publish_consensus(&ctx.p2p, None, &message, fanout).await;
+
+    // Lock order matches the block proposal: mempool, then chain
+    let mut mempool = ctx.mempool.write().await;
+    for tx in &transactions {
+        mempool.remove(&tx.hash());
+    }
+    if let Err(e) = sealer::save_pending(&mut *ctx.chain.write().await, &mempool) {
+        eprintln!("Failed to save pending transactions: {}", e);
+    }
+    drop(mempool);
+    if let Some(dump_dir) = dump_dir {
+        check_invariants(ctx, &ctx.chain, index, dump_dir).await;
+    }
+}
+
+/// Next message relay queued for this connection; never ready before the
+/// peer's handshake is accepted
+async fn next_relayed(link: &mut Option<mpsc::UnboundedReceiver<String>>) -> Option<String> {
+    match link {
+        Some(queue) => queue.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Flux state of a node up for `uptime` seconds
+fn flux_state(uptime: u64) -> NodeState {
+    NodeState::new(PreciseFloat::new(1000, 0), PreciseFloat::new(1000, 0))
+        .with_uptime(uptime)
+        .with_last_sync(FLUX_REBALANCE_INTERVAL_SECS)
+}
+
+/// Put a peer into flux routing, linked to this node while it has no other
+/// links. Like this node, a peer joins once its uptime lifts its entropy over
+/// the chaos threshold; the flux rebalance retries until then.
+fn join_flux(flux: &mut FluxNetwork, local: &QuantumNodeID, peer: &str, uptime: u64) {
+    let id = relay::flux_id(peer);
+    let joined = match flux.get_node(&id) {
+        Some(_) => flux.update_node_state(&id, flux_state(uptime)).is_ok(),
+        None => flux.add_node(id, flux_state(uptime)).is_ok(),
+    };
+    if joined && flux.get_node(&id).is_some_and(|node| node.connections().is_empty()) {
+        let _ = flux.connect(local, &id);
+    }
+}
+
+/// Send `message` to `fanout` random peers other than `from`. Returns how
+/// many peers it was sent to.
+async fn gossip(network: &P2PNetwork, from: &str, message: &P2PMessage, fanout: usize) -> usize {
+    let Ok(message) = serde_json::to_string(message) else { return 0 };
+    let targets = network.links.gossip_targets(from, fanout).await;
+    network.links.send(&targets, &message).await
+}
+
+/// Pass a block or vote from `peer` on through the sentry topology
+async fn relay_consensus(network: &P2PNetwork, peer: &str, message: &P2PMessage, fanout: usize) {
+    let targets = network.consensus_targets(Some(peer), &message.message_type, fanout).await;
+    if targets.is_empty() {
+        return;
+    }
+    let Ok(text) = serde_json::to_string(message) else { return };
+    let sent = network.links.send(&targets, &text).await;
+    println!("{}Relayed {} from {} to {} peers", trace_prefix(message.trace_id.as_ref()), message.message_type, peer, sent);
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct NodeStatus {
+    node_id: String,
+    security_level: f64,
+    connected_peers: u32,
+    /// Nodes in the quantum overlay
+    quantum_nodes: u32,
+    sync_status: String,
+    /// Aggregated component health
+    health: HealthSummary,
+    current_block: u64,
+    /// Height of the committed account state, behind `current_block` while
+    /// blocks are being applied
+    state_height: u64,
+    pending_transactions: u32,
+    quantum_security: bool,
+    ai_governance_active: bool,
+    /// Reality layers and tallies
+    #[cfg(feature = "metaverse")]
+    orchestration: OrchestrationMetrics,
+}
+
+async fn run_rpc_server(
+    listen: &[SocketAddr],
+    ctx: RpcContext,
+    mut shutdown: ShutdownSignal,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let listeners = Listeners::bind(listen)?;
+    let scheme = if ctx.tls.is_some() { "https" } else { "http" };
+    for addr in listeners.local_addrs() {
+        println!("RPC server listening on {}://{}", scheme, addr);
+    }
+
+    let methods = rpc_methods();
+    #[cfg(feature = "vm-wasm")]
+    let methods = with_extension_methods(methods, ctx.extensions.read().await.rpc_methods());
+    let methods = Arc::new(methods);
+    let connections = ConnectionTracker::new();
+    loop {
+        tokio::select! {
+            accepted = listeners.accept() => {
+                let Ok((stream, peer)) = accepted else { break };
+                let guard = connections.track();
+                let conn_ctx = ctx.clone();
+                let conn_methods = methods.clone();
+                let conn_shutdown = shutdown.clone();
+                tokio::spawn(async move {
+                    match &conn_ctx.tls {
+                        Some(tls) => {
+                            // Each handshake uses the most recently loaded certificate
+                            match tls.acceptor().accept(stream).await {
+                                Ok(stream) => handle_rpc_connection(stream, peer, conn_ctx.clone(), conn_methods, conn_shutdown).await,
+                                Err(e) => eprintln!("TLS handshake with {} failed: {}", peer, e),
+                            }
+                        }
+                        None => handle_rpc_connection(stream, peer, conn_ctx.clone(), conn_methods, conn_shutdown).await,
+                    }
+                    drop(guard);
+                });
+            }
+            _ = shutdown.wait() => break,
+        }
+    }
+
+    // Let in-flight requests finish before reporting stopped
+    drop(listeners);
+    connections.wait_idle().await;
+    Ok(())
+}
+
+/// JSON-RPC methods served by the node. Families handled by their own
+/// function are registered by name; the rest go through `dispatch_rpc`.
+fn rpc_methods() -> Methods<RpcContext> {
+    let mut methods = Methods::new();
+    methods.register(
+        &["getChannel", "getChannels", "watchChannel", "getStaleCloses"],
+        |ctx: RpcContext, method: String, params: serde_json::Value| async move {
+            handle_channel_rpc(&ctx, &method, &params).await
+        },
+    );
+    methods.register(
+        &["getHtlc", "getHtlcs"],
+        |ctx: RpcContext, method: String, params: serde_json::Value| async move {
+            handle_htlc_rpc(&ctx, &method, &params).await
+        },
+    );
+    methods.register(
+        &["submitTransaction", "getPendingTransactions", "getTransactionByHash"],
+        |ctx: RpcContext, method: String, params: serde_json::Value| async move {
+            handle_transaction_rpc(&ctx, &method, &params).await
+        },
+    );
+    methods.register(
+        &["getSponsor"],
+        |ctx: RpcContext, method: String, params: serde_json::Value| async move {
+            handle_sponsor_rpc(&ctx, &method, &params).await
+        },
+    );
+    methods.register(
+        &["getValidation"],
+        |ctx: RpcContext, method: String, params: serde_json::Value| async move {
+            handle_validation_rpc(&ctx, &method, &params).await
+        },
+    );
+    methods.register(
+        &["getSyncStatus"],
+        |ctx: RpcContext, _method: String, _params: serde_json::Value| async move {
+            let chain_height = ctx.chain.read().await.height();
+            Ok(json!(ctx.sync.read().await.status(chain_height)))
+        },
+    );
+    methods.register(
+        &["getSnapshotStatus"],
+        |ctx: RpcContext, _method: String, _params: serde_json::Value| async move {
+            let served: Vec<serde_json::Value> = ctx.snapshots.read().await.iter()
+                .map(|snapshot| json!({
+                    "height": snapshot.manifest.height,
+                    "state_root": hex::encode(snapshot.manifest.state_root),
+                    "chunks": snapshot.manifest.chunks.len(),
+                    "total_bytes": snapshot.manifest.total_bytes,
+                }))
+                .collect();
+            let download = ctx.snapshot_download.read().await.as_ref().map(SnapshotDownload::progress);
+            Ok(json!({ "download": download, "served": served }))
+        },
+    );
+    methods.fallback(|ctx: RpcContext, request: RPCRequest| async move { dispatch_rpc(&ctx, request).await });
+    methods
+}
+
+/// Add the RPC methods of the loaded extensions; config validation keeps
+/// them unique and in the `ext_` namespace. A call holds the chain and the extensions for
+/// its duration, so it runs on the background pool.
+#[cfg(feature = "vm-wasm")]
+fn with_extension_methods(mut methods: Methods<RpcContext>, names: Vec<String>) -> Methods<RpcContext> {
+    let names: Vec<&str> = names.iter().map(String::as_str).collect();
+    methods.register(&names, |ctx: RpcContext, method: String, params: serde_json::Value| async move {
+        let chain = ctx.chain.clone().read_owned().await;
+        let mut extensions = ctx.extensions.clone().write_owned().await;
+        ctx.pools.spawn_blocking(Lane::Background, move || {
+            let result = extensions.call(&chain, &method, &params);
+            for (name, line) in extensions.take_logs() {
+                println!("[extension {}] {}", name, line);
+            }
+            result
+        })
+        .await
+        .map_err(|_| "Extension call panicked".to_string())?
+    });
+    methods
+}
+
+/// Serve HTTP requests on one connection until the client closes it or the node shuts down
+async fn handle_rpc_connection<S>(
+    stream: S,
+    peer: SocketAddr,
+    ctx: RpcContext,
+    methods: Arc<Methods<RpcContext>>,
+    mut shutdown: ShutdownSignal,
+)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let handle = move |request| handle_http_request(request, peer, ctx.clone(), methods.clone());
+    if let Err(e) = rpc::serve_connection(stream, handle, async move { shutdown.wait().await }).await {
+        eprintln!("RPC connection with {} failed: {}", peer, e);
+    }
+}
+
+async fn handle_http_request(
+    request: hyper::Request<hyper::Body>,
+    peer: SocketAddr,
+    ctx: RpcContext,
+    methods: Arc<Methods<RpcContext>>,
+) -> hyper::Response<hyper::Body> {
+    let (allowed_origins, trusted_proxies) = {
+        let config = ctx.config.read().await;
+        (config.current().rpc_cors_origins.clone(), config.current().trusted_proxy_ips())
+    };
+    let (cors, client, trace_id) = {
+        let header = |name: &str| request.headers().get(name).and_then(|value| value.to_str().ok());
+        (
+            header("origin").and_then(|origin| cors_origin(&allowed_origins, origin)),
+            // Rate limit the originating client, not the load balancer in front of us
+            client_ip(peer.ip(), header("x-forwarded-for"), &trusted_proxies).to_string(),
+            TraceId::from_header(header(TRACE_HEADER)),
+        )
+    };
+    if !ctx.rate_limiter.check(&client) {
+        return rpc::json_response(429, cors.as_deref(), String::new());
+    }
+
+    let (method, path) = (request.method().clone(), request.uri().path().to_string());
+    match (method.as_str(), path.as_str()) {
+        ("GET", "/health") => {
+            // Degraded nodes still serve; only an unhealthy one fails the check
+            let summary = ctx.health.check().await;
+            let status = match summary.status {
+                HealthStatus::Unhealthy => 503,
+                _ => 200,
+            };
+            let body = serde_json::to_string(&summary).unwrap_or_default();
+            return rpc::json_response(status, cors.as_deref(), body);
+        }
+        ("GET", "/ready") => {
+            let (status, body) = if ctx.ready.load(Ordering::SeqCst) {
+                (200, r#"{"ready":true}"#)
+            } else {
+                (503, r#"{"ready":false}"#)
+            };
+            return rpc::json_response(status, cors.as_deref(), body.to_string());
+        }
+        ("OPTIONS", _) => return rpc::json_response(204, cors.as_deref(), String::new()),
+        _ => {}
+    }
+
+    let body = match rpc::read_body(request.into_body(), rpc::MAX_BODY_BYTES).await {
+        Ok(body) => body,
+        Err(message) => return rpc::json_response(413, cors.as_deref(), json!({ "error": message }).to_string()),
+    };
+    let payload = match serde_json::from_slice::<serde_json::Value>(&body) {
+        Ok(payload) => payload,
+        Err(_) => {
+            let reply = RPCResponse::error(serde_json::Value::Null, rpc::PARSE_ERROR, "Parse error");
+            return rpc::json_response(200, cors.as_deref(), serde_json::to_string(&reply).unwrap_or_default());
+        }
+    };
+    // Each further call in a batch counts as another request
+    let extra_calls = rpc::call_count(&payload).saturating_sub(1) as u32;
+    if extra_calls > 0 && !ctx.rate_limiter.check_n(&client, extra_calls) {
+        return rpc::json_response(429, cors.as_deref(), String::new());
+    }
+
+    // Every call in the request runs under one trace ID, the caller's if it sent one
+    println!("[trace {}] Received RPC request: {}", trace_id, rpc::describe_calls(&payload));
+
+    #[cfg(feature = "fault-injection")]
+    quantum_metaverse::chaos::checkpoint_async(quantum_metaverse::chaos::RPC_REQUEST).await;
+
+    match trace::scope(trace_id.clone(), methods.handle(ctx, payload)).await {
+        Some(mut reply) => {
+            for response in reply.responses_mut() {
+                response.trace_id = Some(trace_id.to_string());
+            }
+            rpc::json_response(200, cors.as_deref(), serde_json::to_string(&reply).unwrap_or_default())
+        }
+        // Notifications get an empty response
+        None => rpc::json_response(204, cors.as_deref(), String::new()),
+    }
+}
+
+/// Handle a JSON-RPC request based on its method
+async fn dispatch_rpc(ctx: &RpcContext, request: RPCRequest) -> RPCResponse {
+    match request.method.as_str() {
+        "status" => {
+            let health = ctx.health.check().await;
+            RPCResponse {
+                jsonrpc: "2.0".to_string(),
+                result: Some(serde_json::to_value(NodeStatus {
+                    node_id: ctx.node_id.to_string(),
+                    security_level: 98.0,
+                    connected_peers: ctx.p2p.peers.read().await.len() as u32,
+                    quantum_nodes: ctx.quantum_network.read().await.node_count() as u32,
+                    sync_status: if ctx.ready.load(Ordering::SeqCst) { "Synced" } else { "Syncing" }.to_string(),
+                    health,
+                    current_block: ctx.chain.read().await.height(),
+                    state_height: ctx.world_state.read().await.latest_height(),
+                    pending_transactions: ctx.mempool.read().await.len() as u32,
+                    quantum_security: true,
+                    ai_governance_active: true,
+                    #[cfg(feature = "metaverse")]
+                    orchestration: ctx.orchestrator.read().await.get_metrics(),
+                }).unwrap()),
+                error: None,
+                id: request.id,
+                trace_id: None,
+            }
+        },
+
+        #[cfg(feature = "metaverse")]
+        "recordQuantumState" | "announcePresence" | "setLayerPolicy" | "getLayerAccess" | "getOrchestrationMetrics" => {
+            rpc_result(request.id, handle_orchestration_rpc(ctx, &request.method, &request.params).await)
+        },
+
+        "getMetrics" => RPCResponse {
+            jsonrpc: "2.0".to_string(),
+            result: Some(json!({
+                "tps": 1000,
+                "memory_usage_mb": 256,
+                "cpu_usage_percent": 15,
+                "disk_usage_gb": 1.2,
+                "network_in_mbps": 50,
+                "network_out_mbps": 45,
+                "quantum_entropy": 0.99,
+                "ai_confidence": 0.95,
+            })),
+            error: None,
+            id: request.id,
+            trace_id: None,
+        },
+
+        "security_test" => {
+            let test_result = match ctx.pools.spawn_blocking(Lane::Background, run_security_tests).await {
+                Ok(result) => result,
+                Err(_) => return rpc_result(request.id, Err("Security test job failed".to_string())),
+            };
+            RPCResponse {
+                jsonrpc: "2.0".to_string(),
+                result: Some(json!(test_result)),
+                error: None,
+                id: request.id,
+                trace_id: None,
+            }
+        },
+        
+        "stress_test" => {
+            let stress_result = match ctx.pools.spawn_blocking(Lane::Background, run_stress_test).await {
+                Ok(result) => result,
+                Err(_) => return rpc_result(request.id, Err("Stress test job failed".to_string())),
+            };
+            RPCResponse {
+                jsonrpc: "2.0".to_string(),
+                result: Some(json!(stress_result)),
+                error: None,
+                id: request.id,
+                trace_id: None,
+            }
+        },
+
+        "quantum_attack_simulation" => {
+            let simulation_result = simulate_quantum_attack();
+            RPCResponse {
+                jsonrpc: "2.0".to_string(),
+                result: Some(json!(simulation_result)),
+                error: None,
+                id: request.id,
+                trace_id: None,
+            }
+        },
+
+        "network_security_audit" => {
+            let audit_result = perform_network_security_audit();
+            RPCResponse {
+                jsonrpc: "2.0".to_string(),
+                result: Some(json!(audit_result)),
+                error: None,
+                id: request.id,
+                trace_id: None,
+            }
+        },
+
+        "getAIDecisions" => RPCResponse {
+            jsonrpc: "2.0".to_string(),
+            result: Some(json!({
+                "decisions": [
+                    {
+                        "id": "dec_001",
+                        "type": "security",
+                        "confidence": 0.98,
+                        "timestamp": "2025-02-27T07:44:17Z",
+                        "action": "optimize_quantum_parameters"
+                    }
+                ],
+                "total_decisions": 1,
+                "average_confidence": 0.98
+            })),
+            error: None,
+            id: request.id,
+            trace_id: None,
+        },
+
+        "getQuantumState" => RPCResponse {
+            jsonrpc: "2.0".to_string(),
+            result: Some(json!({
+                "entanglement_pairs": 1024,
+                "quantum_memory_qubits": 512,
+                "decoherence_rate": 0.001,
+                "error_correction_rate": 0.9999,
+                "quantum_security_score": 98.5
+            })),
+            error: None,
+            id: request.id,
+            trace_id: None,
+        },
+
+        "createPrivateChain" | "listPrivateChains" | "suspendPrivateChain" |
+        "resumePrivateChain" | "privateSigningPayload" | "privateSubmitBlock" |
+        "privatePutState" | "privateGetState" | "privateGetHeaders" | "privateLockHtlc" |
+        "privateClaimHtlc" | "privateRefundHtlc" | "privateGetHtlc" => {
+            rpc_result(request.id, handle_private_rpc(ctx, &request.method, &request.params).await)
+        },
+
+        "getLogs" => {
+            let logs = match serde_json::from_value::<LogFilter>(request.params.clone()) {
+                Ok(filter) => ctx.logs.read().await.query(&filter)
+                    .map(|result| json!(result))
+                    .map_err(str::to_string),
+                Err(e) => Err(format!("Invalid log filter: {}", e)),
+            };
+            rpc_result(request.id, logs)
+        },
+
+        "getContractStats" => {
+            let stats = match param_hex::<32>(&request.params, "contract") {
+                Ok(contract) => ctx.contract_meter.read().await.stats(&contract)
+                    .map(|stats| json!(stats))
+                    .ok_or_else(|| "No calls metered for this contract".to_string()),
+                Err(e) => Err(e),
+            };
+            rpc_result(request.id, stats)
+        },
+
+        "getTopContracts" => {
+            let ranking = request.params.get("by")
+                .cloned()
+                .ok_or_else(|| "Missing parameter `by`".to_string())
+                .and_then(|by| serde_json::from_value::<ContractRanking>(by).map_err(|e| format!("Invalid ranking: {}", e)));
+            let window = request.params.get("window").and_then(|v| v.as_u64()).unwrap_or(DEFAULT_RANKING_WINDOW);
+            let limit = request.params.get("limit").and_then(|v| v.as_u64()).unwrap_or(10) as usize;
+            let top = match ranking {
+                Ok(by) => {
+                    let meter = ctx.contract_meter.read().await;
+                    meter.top(by, window, limit)
+                        .map(|contracts| json!({ "height": meter.height(), "by": by, "window": window, "contracts": contracts }))
+                        .map_err(str::to_string)
+                }
+                Err(e) => Err(e),
+            };
+            rpc_result(request.id, top)
+        },
+
+        "getAccount" => {
+            let account = match param_hex::<32>(&request.params, "address") {
+                Ok(address) => Ok(json!(ctx.world_state.read().await.account(&address))),
+                Err(e) => Err(e),
+            };
+            rpc_result(request.id, account)
+        },
+
+        "getValidatorKey" => {
+            let key = match param_hex::<32>(&request.params, "validator") {
+                Ok(validator) => {
+                    let store = ctx.world_state.read().await;
+                    let keys = store.latest().validator_keys();
+                    let height = request.params.get("height")
+                        .and_then(|v| v.as_u64())
+                        .unwrap_or_else(|| store.latest_height());
+                    Ok(json!({
+                        "height": height,
+                        "key": keys.key_at(&validator, height).map(hex::encode),
+                        "history": keys.history(&validator),
+                    }))
+                }
+                Err(e) => Err(e),
+            };
+            rpc_result(request.id, key)
+        },
+
+        "getSchedules" => {
+            let schedules = match param_hex::<32>(&request.params, "owner") {
+                Ok(owner) => {
+                    let store = ctx.world_state.read().await;
+                    let schedules: Vec<_> = store.latest().schedules()
+                        .by_owner(&owner)
+                        .map(|(id, schedule)| json!({ "id": hex::encode(id), "schedule": schedule }))
+                        .collect();
+                    Ok(json!(schedules))
+                }
+                Err(e) => Err(e),
+            };
+            rpc_result(request.id, schedules)
+        },
+
+        "getCacheStats" => rpc_result(request.id, Ok(json!(ctx.world_state.read().await.cache_stats()))),
+
+        "getRoutingStats" => rpc_result(request.id, Ok(json!(ctx.flux.read().await.stats()))),
+
+        "getRuntimeStats" => rpc_result(request.id, Ok(json!(ctx.pools.saturation()))),
+
+        "simulateTransaction" | "call" => {
+            rpc_result(request.id, handle_execution_rpc(ctx, &request.method, &request.params).await)
+        },
+
+        "stateAt" => rpc_result(request.id, state_at(ctx, &request.params).await),
+
+        "getSentryStatus" => rpc_result(request.id, sentry_status(ctx).await),
+
+        "getPeerDiversity" => rpc_result(request.id, Ok(json!(ctx.p2p.diversity_metrics().await))),
+        "getPeerRoutes" => rpc_result(request.id, peer_routes(ctx).await),
+        "getSeeds" => rpc_result(request.id, seed_status(ctx).await),
+        "getCompressionStats" => rpc_result(request.id, Ok(json!(ctx.p2p.compression.metrics()))),
+        "getRelayStats" => rpc_result(request.id, Ok(json!(ctx.p2p.relay.metrics()))),
+
+        "getStateDiff" => rpc_result(request.id, state_diff(ctx, &request.params).await),
+
+        "getBlockBundle" => rpc_result(request.id, block_bundle(ctx, &request.params).await),
+
+        "submitCommitment" | "revealTransaction" | "getCommitRevealStatus" | "submitOrderingEvidence" => {
+            rpc_result(request.id, handle_commit_reveal_rpc(ctx, &request.method, &request.params).await)
+        },
+
+        "getAsset" | "getAssets" | "getListings" | "getLeases" => {
+            rpc_result(request.id, handle_market_rpc(ctx, &request.method, &request.params).await)
+        },
+
+        "verifyAssetMedia" => rpc_result(request.id, verify_asset_media(ctx, &request.params).await),
+
+        "getConfidentialAccount" => {
+            rpc_result(request.id, confidential_account(ctx, &request.params).await)
+        },
+
+        #[cfg(feature = "hubble")]
+        "hubble_addContent" | "hubble_search" | "hubble_getAdmission" => {
+            rpc_result(request.id, handle_hubble_rpc(ctx, &request.method, &request.params).await)
+        },
+
+        "createDao" | "getDao" | "proposeDao" | "voteDao" | "executeDaoProposal" => {
+            rpc_result(request.id, handle_dao_rpc(ctx, &request.method, &request.params).await)
+        },
+
+        #[cfg(feature = "bridges")]
+        "getInsuranceFund" | "getInsuranceClaims" | "fileInsuranceClaim" => {
+            rpc_result(request.id, handle_insurance_rpc(ctx, &request.method, &request.params).await)
+        },
+
+        #[cfg(feature = "bridges")]
+        "getReserveProof" => {
+            rpc_result(request.id, reserve_proof(ctx, &request.params).await)
+        },
+
+        "getProvider" | "listProviders" => {
+            rpc_result(request.id, handle_provider_rpc(ctx, &request.method, &request.params).await)
+        },
+
+        "getValidatorStake" | "getPendingUnbonds" => {
+            rpc_result(request.id, handle_staking_rpc(ctx, &request.method, &request.params).await)
+        },
+
+        "getFeatures" => {
+            rpc_result(request.id, feature_status(ctx, &request.params).await)
+        },
+
+        "getProtocolParams" => {
+            rpc_result(request.id, protocol_params(ctx, &request.params).await)
+        },
+
+        "revokeCertificate" | "getCertificates" => {
+            rpc_result(request.id, handle_certificate_rpc(ctx, &request.method, &request.params).await)
+        },
+
+        "getTransactionTrace" | "getTrace" => {
+            rpc_result(request.id, handle_trace_rpc(ctx, &request.method, &request.params).await)
+        },
+
+        "getEpoch" => {
+            rpc_result(request.id, handle_epoch_rpc(ctx, &request.params).await)
+        },
+
+        "submitBeaconContribution" | "getBeacon" => {
+            rpc_result(request.id, handle_beacon_rpc(ctx, &request.method, &request.params).await)
+        },
+
+        "submitAttestation" | "revokeAttestation" | "getAttestations" => {
+            rpc_result(request.id, handle_attestation_rpc(ctx, &request.method, &request.params).await)
+        },
+
+        "submitHaltVote" | "getCircuitBreaker" => {
+            rpc_result(request.id, handle_circuit_breaker_rpc(ctx, &request.method, &request.params).await)
+        },
+
+        "requestPredicate" | "verifyPredicate" => {
+            rpc_result(request.id, handle_predicate_rpc(ctx, &request.method, &request.params).await)
+        },
+        "verifyAttributeProof" => rpc_result(request.id, verify_attribute_proof(ctx, &request.params).await),
+
+        "getMultisig" | "proposeMultisig" | "approveMultisig" | "getMultisigPending" => {
+            rpc_result(request.id, handle_multisig_rpc(ctx, &request.method, &request.params).await)
+        },
+
+        "reloadConfig" => match reload_config(ctx).await {
+            Ok(report) => RPCResponse {
+                jsonrpc: "2.0".to_string(),
+                result: Some(json!(report)),
+                error: None,
+                id: request.id,
+                trace_id: None,
+            },
+            Err(message) => RPCResponse {
+                jsonrpc: "2.0".to_string(),
+                result: None,
+                error: Some(RPCError { code: -32000, message, data: None }),
+                id: request.id,
+                trace_id: None,
+            },
+        },
+
+        _ => RPCResponse {
+            jsonrpc: "2.0".to_string(),
+            result: None,
+            error: Some(RPCError {
+                code: -32601,
+                message: "Method not found".to_string(),
+                data: None,
+            }),
+            id: request.id,
+            trace_id: None,
+        },
+    }
+}
+
+fn rpc_result(id: serde_json::Value, result: Result<serde_json::Value, String>) -> RPCResponse {
+    match result {
+        Ok(value) => RPCResponse {
+            jsonrpc: "2.0".to_string(),
+            result: Some(value),
+            error: None,
+            id,
+            trace_id: None,
+        },
+        Err(message) => RPCResponse {
+            jsonrpc: "2.0".to_string(),
+            result: None,
+            error: Some(RPCError { code: -32000, message, data: None }),
+            id,
+            trace_id: None,
+        },
+    }
+}
+
+fn unix_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+/// Log prefix naming the trace a line belongs to, if any
+fn trace_prefix(trace_id: Option<&TraceId>) -> String {
+    trace_id.map(|trace_id| format!("[trace {}] ", trace_id)).unwrap_or_default()
+}
+
+/// Record a step of `tx_hash` under the trace of the RPC call being handled
+async fn record_trace(ctx: &RpcContext, tx_hash: [u8; 32], stage: TraceStage, detail: String) {
+    if let Some(trace_id) = trace::current() {
+        println!("[trace {}] Transaction 0x{} {:?}: {}", trace_id, hex::encode(tx_hash), stage, detail);
+        ctx.traces.write().await.record(tx_hash, trace_id, stage, unix_millis(), detail);
+    }
+}
+
+fn param_str<'a>(params: &'a serde_json::Value, name: &str) -> Result<&'a str, String> {
+    params.get(name)
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| format!("Missing parameter `{}`", name))
+}
+
+fn param_hex<const N: usize>(params: &serde_json::Value, name: &str) -> Result<[u8; N], String> {
+    let bytes = param_bytes(params, name)?;
+    bytes.try_into().map_err(|_| format!("Parameter `{}` must be {} bytes", name, N))
+}
+
+/// Typed ID parameter: checksummed (`qchain1...`) or legacy 0x-hex
+fn param_id<T: TypedId>(params: &serde_json::Value, name: &str) -> Result<T, String> {
+    let value = param_str(params, name)?;
+    T::parse_lenient(value).map_err(|e| format!("Parameter `{}`: {}", name, e))
+}
+
+fn param_bytes(params: &serde_json::Value, name: &str) -> Result<Vec<u8>, String> {
+    let value = param_str(params, name)?;
+    hex::decode(value.trim_start_matches("0x")).map_err(|_| format!("Parameter `{}` must be hex", name))
+}
+
+/// Check the `operator_token` parameter of an operator-only method
+fn require_operator(ctx: &RpcContext, params: &serde_json::Value) -> Result<(), String> {
+    let expected = ctx.operator_token
+        .ok_or_else(|| format!("Operator methods are disabled; set {} to enable them", OPERATOR_TOKEN_ENV))?;
+    let presented: [u8; 32] = blake3::hash(param_str(params, "operator_token")?.as_bytes()).into();
+    // Compare hashes so the check does not leak the token through timing
+    if presented != expected {
+        return Err("Invalid operator token".to_string());
+    }
+    Ok(())
+}
+
+async fn handle_private_rpc(
+    ctx: &RpcContext,
+    method: &str,
+    params: &serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    if matches!(method, "createPrivateChain" | "suspendPrivateChain" | "resumePrivateChain") {
+        require_operator(ctx, params)?;
+    }
+    let host = &ctx.private_chains;
+    match method {
+        "createPrivateChain" => {
+            let defaults = ResourceQuota::default();
+            let quota = ResourceQuota {
+                cpu_ms_per_minute: params.get("cpu_ms_per_minute")
+                    .and_then(|v| v.as_u64())
+                    .unwrap_or(defaults.cpu_ms_per_minute),
+                storage_bytes: params.get("storage_bytes")
+                    .and_then(|v| v.as_u64())
+                    .unwrap_or(defaults.storage_bytes),
+            };
+            let config = ChainConfig {
+                name: param_str(params, "name")?.to_string(),
+                owners: vec![param_hex::<32>(params, "owner")?],
+                initial_state: vec![],
+                network_id: ctx.network_id,
+            };
+            let (chain_id, token) = host.create_chain(config, quota)?;
+            Ok(json!({ "chain_id": chain_id.to_string(), "token": token }))
+        }
+        "listPrivateChains" => Ok(json!(host.list().await)),
+        "suspendPrivateChain" => {
+            host.suspend(&param_id::<ChainId>(params, "chain_id")?).await?;
+            Ok(json!({ "suspended": true }))
+        }
+        "resumePrivateChain" => {
+            host.resume(&param_id::<ChainId>(params, "chain_id")?).await?;
+            Ok(json!({ "suspended": false }))
+        }
+        "privateSigningPayload" => {
+            let payload = host.block_signing_payload(
+                &param_id::<ChainId>(params, "chain_id")?,
+                &param_bytes(params, "data")?,
+            ).await?;
+            Ok(json!({ "payload": format!("0x{}", hex::encode(payload)) }))
+        }
+        "privateSubmitBlock" => {
+            let chain_id = param_id::<ChainId>(params, "chain_id")?;
+            let token = param_str(params, "token")?.to_string();
+            let data = param_bytes(params, "data")?;
+            let proof = param_bytes(params, "proof")?;
+            let signature = param_hex::<64>(params, "signature")?;
+            // Proof verification is CPU-bound; keep it off the async workers,
+            // holding only this chain so other tenants are served meanwhile
+            let mut chain = host.lock_chain(&chain_id).await?;
+            let hash = ctx.pools.spawn_blocking(Lane::Background, move || {
+                chain.submit_block(&token, &data, &proof, &signature)
+            }).await.map_err(|_| "Block processing job failed".to_string())??;
+            Ok(json!({ "block_hash": hex::encode(hash) }))
+        }
+        "privatePutState" => {
+            host.put_state(
+                &param_id::<ChainId>(params, "chain_id")?,
+                param_str(params, "token")?,
+                &param_bytes(params, "key")?,
+                &param_bytes(params, "value")?,
+            ).await?;
+            Ok(json!({ "stored": true }))
+        }
+        "privateGetState" => {
+            let value = host.get_state(
+                &param_id::<ChainId>(params, "chain_id")?,
+                param_str(params, "token")?,
+                &param_bytes(params, "key")?,
+            ).await?;
+            Ok(json!({ "value": value.map(hex::encode) }))
+        }
+        "privateGetHeaders" => {
+            let from = params.get("from").and_then(|v| v.as_u64()).unwrap_or(0);
+            let to = params.get("to").and_then(|v| v.as_u64())
+                .unwrap_or_else(|| from.saturating_add(MAX_HEADERS_PER_REQUEST - 1));
+            if to.saturating_sub(from) >= MAX_HEADERS_PER_REQUEST {
+                return Err(format!("At most {} headers per request", MAX_HEADERS_PER_REQUEST));
+            }
+            let headers = host.headers(&param_id::<ChainId>(params, "chain_id")?, from, to).await?;
+            Ok(json!(headers))
+        }
+        "privateLockHtlc" => {
+            let terms: HtlcTerms = params.get("terms")
+                .cloned()
+                .ok_or("Missing parameter `terms`")
+                .and_then(|terms| serde_json::from_value(terms).map_err(|_| "Invalid HTLC terms"))?;
+            let id = host.lock_htlc(
+                &param_id::<ChainId>(params, "chain_id")?,
+                param_str(params, "token")?,
+                param_hex::<32>(params, "sender")?,
+                &terms,
+            ).await?;
+            Ok(json!({ "id": hex::encode(id) }))
+        }
+        "privateClaimHtlc" => {
+            let claimed = host.claim_htlc(
+                &param_id::<ChainId>(params, "chain_id")?,
+                &param_hex::<32>(params, "id")?,
+                &param_hex::<32>(params, "secret")?,
+            ).await?;
+            Ok(json!({ "htlc": claimed }))
+        }
+        "privateRefundHtlc" => {
+            let refunded = host.refund_htlc(
+                &param_id::<ChainId>(params, "chain_id")?,
+                param_str(params, "token")?,
+                &param_hex::<32>(params, "id")?,
+            ).await?;
+            Ok(json!({ "htlc": refunded }))
+        }
+        "privateGetHtlc" => {
+            let (htlc, height) = host.htlc(&param_id::<ChainId>(params, "chain_id")?, &param_hex::<32>(params, "id")?).await?;
+            Ok(json!({
+                "status": htlc.status,
+                "blocks_until_refund": htlc.blocks_until_refund(height),
+                "height": height,
+                "htlc": htlc,
+            }))
+        }
+        _ => Err("Method not found".to_string()),
+    }
+}
+
+/// Resolve the optional `block` parameter: "latest" (default) or a height
+fn param_block(params: &serde_json::Value, store: &StateStore) -> Result<u64, String> {
+    match params.get("block") {
+        None | Some(serde_json::Value::Null) => Ok(store.latest_height()),
+        Some(serde_json::Value::String(tag)) if tag == "latest" => Ok(store.latest_height()),
+        Some(value) => value.as_u64()
+            .ok_or_else(|| "Parameter `block` must be \"latest\" or a block height".to_string()),
+    }
+}
+
+/// Ledger balances, contract storage, trust scores and the tally checkpoint
+/// as of a past block. Pruned nodes only answer within their retention window.
+async fn state_at(ctx: &RpcContext, params: &serde_json::Value) -> Result<serde_json::Value, String> {
+    let list = |name: &str| params.get(name).and_then(|v| v.as_array()).cloned().unwrap_or_default();
+
+    let store = ctx.world_state.read().await;
+    let height = param_block(params, &store)?;
+    let mut accounts = Vec::new();
+    for address in list("accounts") {
+        let address: [u8; 32] = address.as_str()
+            .and_then(|address| hex::decode(address.trim_start_matches("0x")).ok())
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| format!("Invalid account address {}", address))?;
+        let account = store.account_at(&address, height)?;
+        accounts.push(json!({ "address": hex::encode(address), "balance": account.balance, "nonce": account.nonce }));
+    }
+    let mut storage = Vec::new();
+    for slot in list("storage") {
+        let contract = param_hex::<32>(&slot, "contract")?;
+        let key = param_bytes(&slot, "key")?;
+        let value = store.storage_at(&contract, &key, height)?;
+        storage.push(json!({
+            "contract": hex::encode(contract),
+            "key": hex::encode(&key),
+            "value": value.map(hex::encode),
+        }));
+    }
+    drop(store);
+
+    let history = ctx.trust_history.read().await;
+    let mut trust_scores = Vec::new();
+    for id in list("identities") {
+        let id = id.as_str()
+            .ok_or("Identities must be strings")
+            .and_then(|id| IdentityId::parse_lenient(id).map_err(|_| "Invalid identity"))?;
+        trust_scores.push(json!({ "identity": id, "trust_score": history.get_at(&id, height)? }));
+    }
+
+    let epochs = ctx.epochs.read().await;
+    let epoch = epochs.schedule().epoch_of(height);
+    Ok(json!({
+        "block": height,
+        "accounts": accounts,
+        "storage": storage,
+        "trust_scores": trust_scores,
+        "orchestration": {
+            "epoch": epoch,
+            "tally_checkpoint": epochs.checkpoint(epoch).map(hex::encode),
+        },
+    }))
+}
+
+/// Net state changes from `from_height` to `to_height`, with the state roots
+/// at both ends and the tally checkpoints taken in between, so an indexer can
+/// catch up or a fraud-proof claim about an anchored transition can be checked
+async fn state_diff(ctx: &RpcContext, params: &serde_json::Value) -> Result<serde_json::Value, String> {
+    let from = params.get("from_height").and_then(|v| v.as_u64()).ok_or("Missing parameter `from_height`")?;
+    let to = params.get("to_height").and_then(|v| v.as_u64()).ok_or("Missing parameter `to_height`")?;
+
+    // Lock order: state, then epochs
+    if from > to {
+        return Err("`to_height` is below `from_height`".to_string());
+    }
+    let store = ctx.world_state.read().await;
+    let (before, after) = (store.at(from)?, store.at(to)?);
+    let diff = StateDiff::between(&before, &after);
+    let (from_root, to_root) = (before.state_root(), after.state_root());
+    let epochs = ctx.epochs.read().await;
+    let schedule = epochs.schedule();
+    // Checkpoints are taken at epoch starts; only those inside the range are listed
+    let checkpoints: Vec<serde_json::Value> = epochs
+        .checkpoints(schedule.epoch_of(from), schedule.epoch_of(to))
+        .filter(|(epoch, _)| (from + 1..=to).contains(&schedule.epoch_start(*epoch)))
+        .map(|(epoch, root)| json!({
+            "epoch": epoch,
+            "height": schedule.epoch_start(epoch),
+            "state_root": hex::encode(root),
+        }))
+        .collect();
+
+    Ok(json!({
+        "from_height": from,
+        "to_height": to,
+        "from_state_root": hex::encode(from_root),
+        "to_state_root": hex::encode(to_root),
+        "diff": diff,
+        "tally_checkpoints": checkpoints,
+    }))
+}
+
+async fn handle_execution_rpc(
+    ctx: &RpcContext,
+    method: &str,
+    params: &serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    let store = ctx.world_state.read().await;
+    let height = param_block(params, &store)?;
+    let state = store.at(height)?;
+    let result = match method {
+        "simulateTransaction" => {
+            let tx: Transaction = params.get("transaction")
+                .cloned()
+                .ok_or("Missing parameter `transaction`")
+                .and_then(|tx| serde_json::from_value(tx).map_err(|_| "Invalid transaction"))?;
+            let simulation = Executor::simulate(&state, &tx)?;
+            let outcome = match &simulation.error {
+                Some(error) => format!("failed at block {}: {}", height, error),
+                None => format!("succeeded at block {} using {} gas", height, simulation.gas_used),
+            };
+            record_trace(ctx, tx.hash(), TraceStage::Simulated, outcome).await;
+            simulation
+        }
+        "call" => {
+            let caller = match params.get("from") {
+                Some(_) => param_hex::<32>(params, "from")?,
+                None => [0u8; 32],
+            };
+            let input = match params.get("input") {
+                Some(_) => param_bytes(params, "input")?,
+                None => Vec::new(),
+            };
+            Executor::call(
+                &state,
+                caller,
+                param_hex::<32>(params, "contract")?,
+                input,
+                params.get("gas_limit").and_then(|v| v.as_u64()),
+            )?
+        }
+        _ => return Err("Method not found".to_string()),
+    };
+    Ok(json!({ "block": height, "result": result }))
+}
+
+fn ordering_strategy(strategy: BuilderStrategy, config: &BlockBuilderConfig) -> Result<Box<dyn OrderingStrategy>, String> {
+    Ok(match strategy {
+        BuilderStrategy::FeeGreedy => Box::new(FeeGreedy),
+        BuilderStrategy::FairFifo => Box::new(FairFifo),
+        BuilderStrategy::GovernanceBoosted => Box::new(GovernanceBoosted::new(config.boosts()?)),
+    })
+}
+
+/// Run the invariant suite on a block this node sealed. A violation is
+/// dumped to `dump_dir` and halts block production until validators vote
+/// to resume.
+async fn check_invariants(ctx: &RpcContext, chain: &Arc<RwLock<Blockchain>>, height: u64, dump_dir: &std::path::Path) {
+    let mut report = {
+        // Lock order matches the block producer: state, then chain
+        let store = ctx.world_state.read().await;
+        let chain = chain.read().await;
+        let parent = store.at(height.saturating_sub(1));
+        match (parent, chain.block(height.saturating_sub(1)), chain.block(height)) {
+            (Ok(parent), Some(parent_block), Some(block)) => invariants::check_block(&parent, store.latest(), parent_block, block),
+            _ => {
+                eprintln!("Invariant checks skipped for block {}: parent state or block unavailable", height);
+                return;
+            }
+        }
+    };
+    report.check_economics(&*ctx.economics.read().await);
+    if report.is_ok() {
+        return;
+    }
+
+    eprintln!("CRITICAL: {}", report.summary());
+    match report.write_dump(dump_dir) {
+        Ok(path) => eprintln!("Invariant report written to {}", path.display()),
+        Err(e) => eprintln!("Failed to write invariant report: {}", e),
+    }
+    if let Some(id) = ctx.circuit_breaker.write().await.trip(HaltScope::BlockProduction, report.summary(), height) {
+        eprintln!("Circuit breaker: block production halted (halt {}) until validators vote to resume", id);
+    }
+}
+
+/// Ordered mempool bundle for block builders; parameters override the
+/// configured strategy and limits
+async fn block_bundle(ctx: &RpcContext, params: &serde_json::Value) -> Result<serde_json::Value, String> {
+    let config = ctx.config.read().await.current().block_builder.clone();
+    let strategy = match params.get("strategy") {
+        Some(_) => param_str(params, "strategy")?.parse()?,
+        None => config.strategy,
+    };
+    let limits = BundleLimits {
+        max_gas: params.get("max_gas").and_then(|v| v.as_u64()).unwrap_or(config.max_gas),
+        max_bytes: params.get("max_bytes").and_then(|v| v.as_u64()).map_or(config.max_bytes, |v| v as usize),
+    };
+    let strategy = ordering_strategy(strategy, &config)?;
+
+    // Lock order matches the maintenance task: state, then mempool
+    let store = ctx.world_state.read().await;
+    let mempool = ctx.mempool.read().await;
+    let mut builder = BlockBuilder::new(strategy.as_ref(), limits);
+    let mut commitments = Vec::new();
+    if let Some(queue) = &ctx.commit_reveal {
+        // Reveals go first in committed order; the block also seals the pending commitments
+        let queue = queue.read().await;
+        let height = store.latest_height() + 1;
+        builder = builder.with_leading(queue.due(height).into_iter().map(|(_, tx)| tx.clone()).collect());
+        commitments = queue.pending().iter().map(hex::encode).collect();
+    }
+    let mut bundle = json!(builder.build(&mempool, store.latest()));
+    bundle["commitments"] = json!(commitments);
+    Ok(bundle)
+}
+
+async fn handle_commit_reveal_rpc(
+    ctx: &RpcContext,
+    method: &str,
+    params: &serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    let queue = ctx.commit_reveal.as_ref().ok_or("Commit-reveal ordering is not enabled on this node")?;
+    match method {
+        "submitCommitment" => {
+            let commitment: Commitment = params.get("commitment")
+                .cloned()
+                .ok_or("Missing parameter `commitment`")
+                .and_then(|c| serde_json::from_value(c).map_err(|_| "Invalid commitment"))?;
+            queue.write().await.submit(commitment)?;
+            Ok(json!({ "accepted": true }))
+        }
+        "revealTransaction" => {
+            let tx: Transaction = params.get("transaction")
+                .cloned()
+                .ok_or("Missing parameter `transaction`")
+                .and_then(|tx| serde_json::from_value(tx).map_err(|_| "Invalid transaction"))?;
+            let salt = param_hex::<32>(params, "salt")?;
+            let tx_hash = tx.hash();
+            let digest = queue.write().await.reveal(tx, &salt)?;
+            record_trace(ctx, tx_hash, TraceStage::Revealed, format!("matched commitment 0x{}", hex::encode(digest))).await;
+            Ok(json!({ "digest": hex::encode(digest) }))
+        }
+        "getCommitRevealStatus" => {
+            let queue = queue.read().await;
+            let sealed: Vec<_> = queue.status().into_iter()
+                .map(|(height, commitments, revealed)| json!({ "height": height, "commitments": commitments, "revealed": revealed }))
+                .collect();
+            let violations: Vec<_> = queue.violations().iter()
+                .map(|(offender, evidence)| json!({ "offender": hex::encode(offender), "evidence": evidence }))
+                .collect();
+            Ok(json!({
+                "pending": queue.pending().iter().map(hex::encode).collect::<Vec<_>>(),
+                "sealed": sealed,
+                "violations": violations,
+            }))
+        }
+        "submitOrderingEvidence" => {
+            let evidence: OrderingViolation = params.get("evidence")
+                .cloned()
+                .ok_or("Missing parameter `evidence`")
+                .and_then(|e| serde_json::from_value(e).map_err(|_| "Invalid ordering evidence"))?;
+            // Lock order: state, then the queue
+            let store = ctx.world_state.read().await;
+            let offender = queue.write().await.report_violation(evidence, store.latest().validator_keys())?;
+            Ok(json!({ "offender": hex::encode(offender) }))
+        }
+        _ => Err("Method not found".to_string()),
+    }
+}
+
+async fn handle_multisig_rpc(
+    ctx: &RpcContext,
+    method: &str,
+    params: &serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    // Lock order matches the maintenance task: state, then approvals
+    let store = ctx.world_state.read().await;
+    let mut pool = ctx.multisig_approvals.write().await;
+    let pending_json = |pending: &PendingProposal| json!({
+        "pending": pending,
+        "signing_payload": hex::encode(pending.proposal.signing_bytes(ctx.network_id)),
+    });
+    match method {
+        "getMultisig" => {
+            let address = param_hex::<32>(params, "address")?;
+            Ok(json!(store.latest().multisig(&address)))
+        }
+        "proposeMultisig" => {
+            let multisig = param_hex::<32>(params, "multisig")?;
+            let account = store.latest().multisig(&multisig).ok_or("Unknown multisig account")?;
+            let operation: MultisigOperation = params.get("operation")
+                .cloned()
+                .ok_or("Missing parameter `operation`")
+                .and_then(|op| serde_json::from_value(op).map_err(|_| "Invalid multisig operation"))?;
+            let proposal = MultisigProposal { multisig, nonce: account.nonce(), operation };
+            let id = pool.propose(proposal, account, ctx.network_id)?;
+            Ok(pending_json(pool.get(&id).expect("just proposed")))
+        }
+        "approveMultisig" => {
+            let id = param_hex::<32>(params, "id")?;
+            let multisig = pool.get(&id).ok_or("Unknown proposal")?.proposal.multisig;
+            let account = store.latest().multisig(&multisig).ok_or("Unknown multisig account")?;
+            let approval = Approval {
+                signer: param_hex::<32>(params, "signer")?,
+                signature: param_bytes(params, "signature")?,
+            };
+            pool.approve(&id, approval, account, ctx.network_id)?;
+            Ok(pending_json(pool.get(&id).expect("just approved")))
+        }
+        "getMultisigPending" => {
+            let multisig = param_hex::<32>(params, "multisig")?;
+            Ok(json!(pool.pending(&multisig).into_iter().map(pending_json).collect::<Vec<_>>()))
+        }
+        _ => Err("Method not found".to_string()),
+    }
+}
+
+async fn handle_channel_rpc(
+    ctx: &RpcContext,
+    method: &str,
+    params: &serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    // Lock order matches the maintenance task: state, then watchtower
+    let store = ctx.world_state.read().await;
+    let channels = store.latest().channels();
+    let mut watchtower = ctx.watchtower.write().await;
+    match method {
+        "getChannel" => {
+            let id = param_hex::<32>(params, "channel")?;
+            let channel = channels.get(&id).ok_or("Unknown channel")?;
+            Ok(json!({
+                "channel": channel,
+                "total": channel.total().to_string(),
+                "watched": watchtower.watched(&id),
+            }))
+        }
+        "getChannels" => {
+            let account = param_hex::<32>(params, "account")?;
+            let listed: Vec<_> = channels.by_party(&account)
+                .map(|(id, channel)| json!({ "id": hex::encode(id), "channel": channel }))
+                .collect();
+            Ok(json!({
+                "channels": listed,
+                "escrow_balance": store.latest().account(&CHANNEL_ESCROW_ADDRESS).balance.to_string(),
+            }))
+        }
+        "watchChannel" => {
+            let update: SignedUpdate = params.get("update")
+                .cloned()
+                .ok_or("Missing parameter `update`")
+                .and_then(|update| serde_json::from_value(update).map_err(|_| "Invalid channel update"))?;
+            let channel = channels.get(&update.update.channel).ok_or("Unknown channel")?;
+            let sequence = update.update.sequence;
+            watchtower.watch(channel, update, ctx.network_id)?;
+            Ok(json!({ "watching": true, "sequence": sequence }))
+        }
+        "getStaleCloses" => Ok(json!(watchtower.check(channels))),
+        _ => Err("Method not found".to_string()),
+    }
+}
+
+async fn handle_htlc_rpc(
+    ctx: &RpcContext,
+    method: &str,
+    params: &serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    let store = ctx.world_state.read().await;
+    let height = store.latest_height();
+    match method {
+        "getHtlc" => {
+            let id = param_hex::<32>(params, "id")?;
+            if let Some(htlc) = store.latest().htlcs().get(&id) {
+                return Ok(json!({
+                    "status": htlc.status,
+                    "blocks_until_refund": htlc.blocks_until_refund(height),
+                    "height": height,
+                    "htlc": htlc,
+                }));
+            }
+            // Settled HTLCs leave state; their claim or refund event remains
+            drop(store);
+            let logs = ctx.logs.read().await;
+            let latest = logs.latest_height();
+            let filter = LogFilter {
+                from_block: Some(latest.saturating_sub(MAX_LOG_RANGE - 1)),
+                to_block: Some(latest),
+                address: Some(HTLC_ESCROW_ADDRESS),
+                topics: vec![htlc::TOPIC_CLAIMED.to_string(), htlc::TOPIC_REFUNDED.to_string()],
+            };
+            let settled = logs.query(&filter)?.logs.into_iter()
+                .find(|log| log.data.get(..32) == Some(&id[..]))
+                .ok_or("HTLC not found")?;
+            let status = match settled.data.get(32..).and_then(|secret| <[u8; 32]>::try_from(secret).ok()) {
+                Some(secret) => HtlcStatus::Claimed { secret },
+                None => HtlcStatus::Refunded,
+            };
+            Ok(json!({ "status": status, "settled_at": settled.block, "tx_hash": hex::encode(settled.tx_hash) }))
+        }
+        "getHtlcs" => {
+            let account = param_hex::<32>(params, "account")?;
+            let listed: Vec<_> = store.latest().htlcs().by_party(&account)
+                .map(|(id, htlc)| json!({
+                    "id": hex::encode(id),
+                    "blocks_until_refund": htlc.blocks_until_refund(height),
+                    "htlc": htlc,
+                }))
+                .collect();
+            Ok(json!({ "height": height, "htlcs": listed }))
+        }
+        _ => Err("Method not found".to_string()),
+    }
+}
+
+async fn handle_transaction_rpc(
+    ctx: &RpcContext,
+    method: &str,
+    params: &serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    match method {
+        "submitTransaction" => {
+            let tx: Transaction = params.get("transaction")
+                .ok_or("Missing parameter `transaction`")
+                .and_then(|tx| serde_json::from_value(tx.clone()).map_err(|_| "Invalid transaction"))?;
+            // Lock order matches the maintenance task: state, then mempool
+            let store = ctx.world_state.read().await;
+            let mut mempool = ctx.mempool.write().await;
+            Ok(match mempool.insert(tx, store.latest())? {
+                Admission::Added(hash) => json!({ "hash": hex::encode(hash) }),
+                Admission::Replaced { added, replaced } => {
+                    json!({ "hash": hex::encode(added), "replaced": hex::encode(replaced) })
+                }
+                Admission::Evicted { added, evicted } => {
+                    json!({ "hash": hex::encode(added), "evicted": hex::encode(evicted) })
+                }
+            })
+        }
+        "getPendingTransactions" => {
+            let store = ctx.world_state.read().await;
+            let mempool = ctx.mempool.read().await;
+            if params.get("address").is_some() {
+                let address = param_hex::<32>(params, "address")?;
+                return Ok(json!({
+                    "transactions": mempool.pending(&address),
+                    "next_nonce": mempool.next_nonce(&address, store.latest()),
+                }));
+            }
+            let limit = params.get("limit").and_then(|v| v.as_u64()).unwrap_or(MAX_PENDING_LISTED).min(MAX_PENDING_LISTED);
+            let listed: Vec<_> = mempool.by_gas_price().into_iter()
+                .take(limit as usize)
+                .map(|tx| json!({ "hash": hex::encode(tx.hash()), "transaction": tx }))
+                .collect();
+            Ok(json!({ "count": mempool.len(), "transactions": listed }))
+        }
+        "getTransactionByHash" => {
+            let hash = param_hex::<32>(params, "hash")?;
+            if let Some(tx) = ctx.mempool.read().await.get(&hash) {
+                return Ok(json!({ "status": "pending", "transaction": tx }));
+            }
+            let logs = ctx.logs.read().await;
+            let (height, receipt) = logs.receipt(&hash).ok_or("Transaction not found")?;
+            // Scheduled runs have receipts but no transaction in the block
+            let tx = ctx.chain.read().await.block(height)
+                .map(reindex::block_transactions)
+                .and_then(|txs| txs.into_iter().find(|tx| tx.hash() == hash));
+            Ok(json!({ "status": "included", "block": height, "transaction": tx, "receipt": receipt }))
+        }
+        _ => Err("Method not found".to_string()),
+    }
+}
+
+async fn handle_sponsor_rpc(
+    ctx: &RpcContext,
+    method: &str,
+    params: &serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    let store = ctx.world_state.read().await;
+    // Spending as of the next block, the earliest a new transaction lands in
+    let height = store.latest_height() + 1;
+    match method {
+        "getSponsor" => {
+            let sponsor = param_hex::<32>(params, "sponsor")?;
+            let policy = store.latest().sponsors().get(&sponsor).ok_or("Account has no sponsor policy")?;
+            let remaining = policy.limit.saturating_sub(policy.spent(height));
+            let mut result = json!({
+                "limit": policy.limit,
+                "sender_limit": policy.sender_limit,
+                "window_blocks": policy.window_blocks,
+                "spent": policy.spent(height),
+                "remaining": remaining,
+                "balance": store.latest().account(&sponsor).balance,
+            });
+            if params.get("sender").is_some() {
+                let sender = param_hex::<32>(params, "sender")?;
+                let sender_spent = policy.spent_for(&sender, height);
+                result["sender_spent"] = json!(sender_spent);
+                result["sender_remaining"] = json!(policy.sender_limit.saturating_sub(sender_spent).min(remaining));
+            }
+            Ok(result)
+        }
+        _ => Err("Method not found".to_string()),
+    }
+}
+
+async fn handle_validation_rpc(
+    ctx: &RpcContext,
+    method: &str,
+    params: &serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    let store = ctx.world_state.read().await;
+    match method {
+        "getValidation" => {
+            let account = param_hex::<32>(params, "account")?;
+            let validation = store.latest().validations().get(&account).ok_or("Account has no validation contract")?;
+            let contract = store.latest().contract(&validation.contract).ok_or("Validation contract not found")?;
+            Ok(json!({
+                "contract": hex::encode(validation.contract),
+                "gas_limit": validation.gas_limit,
+                "code": contract.code,
+                "storage_slots": contract.storage.len(),
+            }))
+        }
+        _ => Err("Method not found".to_string()),
+    }
+}
+
+async fn handle_market_rpc(
+    ctx: &RpcContext,
+    method: &str,
+    params: &serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    let store = ctx.world_state.read().await;
+    let state = store.latest();
+    match method {
+        "getAsset" => {
+            let id = param_hex::<32>(params, "asset")?;
+            let asset = state.assets().get(&id).ok_or("Asset not found")?;
+            Ok(json!({
+                "asset": asset,
+                "metadata_hash": asset.metadata.as_ref().map(|metadata| hex::encode(metadata.hash())),
+                "listing": state.listings().get(&id),
+                "lease": state.leases().lease(&id),
+                "lease_offer": state.leases().offer(&id),
+            }))
+        }
+        "getAssets" => {
+            let owner = param_hex::<32>(params, "owner")?;
+            let assets: Vec<_> = state.assets()
+                .by_owner(&owner)
+                .map(|(id, asset)| json!({ "id": hex::encode(id), "asset": asset }))
+                .collect();
+            Ok(json!(assets))
+        }
+        "getListings" => {
+            let seller = match params.get("seller") {
+                Some(_) => Some(param_hex::<32>(params, "seller")?),
+                None => None,
+            };
+            let auctions = match params.get("type").and_then(|v| v.as_str()) {
+                None => None,
+                Some("fixed_price") => Some(false),
+                Some("auction") => Some(true),
+                Some(_) => return Err("Parameter `type` must be \"fixed_price\" or \"auction\"".to_string()),
+            };
+            let listings: Vec<_> = state.listings().iter()
+                .filter(|(_, listing)| seller.is_none_or(|seller| listing.seller == seller))
+                .filter(|(_, listing)| auctions.is_none_or(|auctions| auctions == matches!(listing.kind, ListingKind::Auction { .. })))
+                .map(|(id, listing)| json!({
+                    "asset_id": hex::encode(id),
+                    "asset": state.assets().get(id),
+                    "listing": listing,
+                    "ask": listing.ask(),
+                }))
+                .collect();
+            Ok(json!(listings))
+        }
+        "getLeases" => {
+            let account = param_hex::<32>(params, "account")?;
+            let leases: Vec<_> = state.leases().leases()
+                .filter(|(_, lease)| lease.lessor == account || lease.lessee == account)
+                .map(|(id, lease)| json!({ "asset_id": hex::encode(id), "lease": lease }))
+                .collect();
+            Ok(json!(leases))
+        }
+        _ => Err("Method not found".to_string()),
+    }
+}
+
+/// Check an asset's media against the blob store
+async fn verify_asset_media(ctx: &RpcContext, params: &serde_json::Value) -> Result<serde_json::Value, String> {
+    let id = param_hex::<32>(params, "asset")?;
+    let metadata = {
+        let store = ctx.world_state.read().await;
+        let asset = store.latest().assets().get(&id).cloned().ok_or("Asset not found")?;
+        asset.metadata.ok_or("Asset has no metadata")?
+    };
+    let checks = asset_metadata::verify_media(&metadata, &*ctx.remote_storage.read().await).await;
+    let verified = checks.iter().all(|check| check.status == MediaStatus::Verified);
+    Ok(json!({ "metadata_hash": hex::encode(metadata.hash()), "verified": verified, "media": checks }))
+}
+
+/// Shielded account with its pending transfers. With `value` and `proof`
+/// (an opening proof from the owner), also reports whether the balance
+/// commitment opens to `value`, so an auditor can check a disclosed balance.
+async fn confidential_account(ctx: &RpcContext, params: &serde_json::Value) -> Result<serde_json::Value, String> {
+    let address = param_hex::<32>(params, "address")?;
+    let store = ctx.world_state.read().await;
+    let state = store.latest();
+    let account = state.confidential().get(&address).ok_or("No shielded account")?;
+    let disclosure = match (params.get("value").and_then(|v| v.as_u64()), params.get("proof")) {
+        (Some(value), Some(proof)) => {
+            let proof: OpeningProof = serde_json::from_value(proof.clone()).map_err(|_| "Invalid opening proof")?;
+            Some(account.verify_disclosure(&address, value, &proof).is_ok())
+        }
+        _ => None,
+    };
+    Ok(json!({
+        "account": account,
+        "pool_balance": state.account(&CONFIDENTIAL_POOL_ADDRESS).balance.to_string(),
+        "disclosure_valid": disclosure,
+    }))
+}
+
+#[cfg(feature = "hubble")]
+async fn handle_hubble_rpc(
+    ctx: &RpcContext,
+    method: &str,
+    params: &serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    let config = ctx.config.read().await.current().hubble.clone();
+    let language: Language = match params.get("language") {
+        Some(_) => param_str(params, "language")?.parse()?,
+        None => Language::default(),
+    };
+    match method {
+        "hubble_addContent" => {
+            let tags: Vec<String> = match params.get("tags") {
+                Some(tags) => serde_json::from_value(tags.clone())
+                    .map_err(|_| "Parameter `tags` must be an array of strings")?,
+                None => Vec::new(),
+            };
+            let document = ContentDocument {
+                title: param_str(params, "title")?.to_string(),
+                body: param_str(params, "body")?.to_string(),
+                language,
+                tags,
+                added_at: std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs(),
+            };
+            let proof = SubmissionProof::Work {
+                nonce: params.get("nonce").and_then(|v| v.as_u64()).ok_or("Missing parameter `nonce`")?,
+            };
+            // Lock order: index, admission, economics
+            let mut index = ctx.hubble.write().await;
+            let id = index.check(&document)?;
+            ctx.hubble_admission.write().await.admit(&id, &proof, &mut *ctx.economics.write().await)?;
+            index.add_content(document)?;
+            Ok(json!({ "id": hex::encode(id) }))
+        }
+        "hubble_search" => {
+            let query = param_str(params, "query")?;
+            let limit = params.get("limit")
+                .and_then(|v| v.as_u64())
+                .map_or(config.max_results, |v| (v as usize).min(config.max_results));
+            let snippet_length = params.get("snippet_length")
+                .and_then(|v| v.as_u64())
+                .map_or(config.snippet_length, |v| v as usize);
+            let results = ctx.hubble.read().await.search(query, language, limit, snippet_length);
+            #[cfg(feature = "vm-wasm")]
+            let results = rank_with_extension(ctx, query, results).await?;
+            Ok(json!({ "results": results }))
+        }
+        "hubble_getAdmission" => Ok(json!(ctx.hubble_admission.read().await.status())),
+        _ => Err("Method not found".to_string()),
+    }
+}
+
+/// Reorder Hubble results with the ranker extension, if one is configured.
+/// Results the ranker leaves out are dropped.
+#[cfg(all(feature = "hubble", feature = "vm-wasm"))]
+async fn rank_with_extension(ctx: &RpcContext, query: &str, results: Vec<SearchHit>) -> Result<Vec<SearchHit>, String> {
+    if !ctx.config.read().await.current().extensions.iter().any(|extension| extension.ranker) {
+        return Ok(results);
+    }
+    let chain = ctx.chain.clone().read_owned().await;
+    let mut extensions = ctx.extensions.clone().write_owned().await;
+    let query = query.to_string();
+    ctx.pools.spawn_blocking(Lane::Background, move || -> Result<Vec<SearchHit>, String> {
+        let ranker = extensions.ranker().ok_or("No ranker extension loaded")?;
+        let ranked = ranker.rank(&chain, &query, &json!(results))?;
+        let mut hits: HashMap<String, SearchHit> = results.into_iter().map(|hit| (hex::encode(hit.id), hit)).collect();
+        Ok(ranked.iter().filter_map(|id| hits.remove(id.trim_start_matches("0x"))).collect())
+    })
+    .await
+    .map_err(|_| "Hubble ranker panicked".to_string())?
+}
+
+/// Proof of reserve for `epoch`, or the latest, with the alerts raised so far
+#[cfg(feature = "bridges")]
+async fn reserve_proof(ctx: &RpcContext, params: &serde_json::Value) -> Result<serde_json::Value, String> {
+    let reserves = ctx.reserves.read().await;
+    let epoch = params.get("epoch").and_then(|v| v.as_u64());
+    let proof = reserves.proof(epoch).ok_or("No proof of reserve for that epoch")?;
+    Ok(json!({ "proof": proof, "alerts": reserves.alerts() }))
+}
+
+/// Bonded storage, oracle and Web2 providers
+async fn handle_provider_rpc(
+    ctx: &RpcContext,
+    method: &str,
+    params: &serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    let economics = ctx.economics.read().await;
+    let providers = economics.providers();
+    let view = |id: &ProviderId, provider: &Provider| {
+        let mut value = json!(provider);
+        value["id"] = json!(hex::encode(id));
+        value["active"] = json!(provider.is_active());
+        value
+    };
+    match method {
+        "getProvider" => {
+            let id = param_hex::<32>(params, "id")?;
+            let provider = providers.get(&id).ok_or("Provider not found")?;
+            Ok(view(&id, provider))
+        }
+        "listProviders" => {
+            let kind = match params.get("kind") {
+                Some(_) => Some(param_str(params, "kind")?.parse()?),
+                None => None,
+            };
+            let list: Vec<_> = providers.list(kind).into_iter().map(|(id, provider)| view(&id, provider)).collect();
+            Ok(json!({ "providers": list }))
+        }
+        _ => Err("Method not found".to_string()),
+    }
+}
+
+/// Validator stake: bonded stake with unclaimed rewards, and unstakes
+/// waiting out the lockup
+async fn handle_staking_rpc(
+    ctx: &RpcContext,
+    method: &str,
+    params: &serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    let validator = param_hex::<32>(params, "validator")?;
+    let economics = ctx.economics.read().await;
+    match method {
+        "getValidatorStake" => {
+            let stake = economics.validator_stake(&validator).ok_or("Validator not found")?;
+            Ok(json!(stake))
+        }
+        "getPendingUnbonds" => {
+            let unbonding = economics.pending_unbonds(&validator);
+            let total = unbonding.iter().fold(PreciseFloat::new(0, 2), |total, unbonding| total.add(&unbonding.amount));
+            Ok(json!({ "unbonding": unbonding, "total": total }))
+        }
+        _ => Err("Method not found".to_string()),
+    }
+}
+
+/// Bridge insurance: fund balance and payouts, claims, and filing a claim
+/// with fraud-proof evidence
+#[cfg(feature = "bridges")]
+async fn handle_insurance_rpc(
+    ctx: &RpcContext,
+    method: &str,
+    params: &serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    match method {
+        "getInsuranceFund" => {
+            let fund = ctx.insurance.read().await;
+            Ok(json!({ "fund": fund.status(), "payouts": fund.payouts() }))
+        }
+        "getInsuranceClaims" => {
+            let bridge = match params.get("bridge") {
+                Some(_) => Some(param_hex::<32>(params, "bridge")?),
+                None => None,
+            };
+            Ok(json!({ "claims": ctx.insurance.read().await.claims(bridge.as_ref()) }))
+        }
+        "fileInsuranceClaim" => {
+            let amount = params.get("amount").and_then(|v| v.as_f64()).ok_or("Missing parameter `amount`")?;
+            let evidence: FraudProof = serde_json::from_value(params.get("evidence").cloned().ok_or("Missing parameter `evidence`")?)
+                .map_err(|e| format!("Invalid evidence: {}", e))?;
+            let height = ctx.world_state.read().await.latest_height();
+            let id = ctx.insurance.write().await.file_claim(
+                param_hex::<32>(params, "bridge")?,
+                param_hex::<32>(params, "claimant")?,
+                PreciseFloat::from_f64(amount, 2),
+                evidence,
+                height,
+            )?;
+            Ok(json!({ "id": hex::encode(id) }))
+        }
+        _ => Err("Method not found".to_string()),
+    }
+}
+
+/// Run one epoch boundary duty
+/// Metrics governance policies are evaluated against, from the modules
+/// this node was built with
+#[allow(unused_mut, unused_variables)]
+async fn governance_metrics(ctx: &RpcContext) -> HashMap<String, PreciseFloat> {
+    let mut metrics = HashMap::new();
+    #[cfg(feature = "hubble")]
+    metrics.extend(ctx.hubble_admission.read().await.metrics());
+    #[cfg(feature = "bridges")]
+    metrics.extend(ctx.reserves.read().await.metrics());
+    metrics.extend(ctx.contract_meter.read().await.metrics());
+    metrics
+}
+
+/// Hand a governance action to the module it is for. Returns whether any
+/// module took it.
+async fn apply_governance(ctx: &RpcContext, action: &Action, height: u64) -> Result<bool, String> {
+    if ctx.p2p.apply_governance(action).await? {
+        return Ok(true);
+    }
+    #[cfg(feature = "hubble")]
+    if ctx.hubble_admission.write().await.apply_governance(action)? {
+        return Ok(true);
+    }
+    #[cfg(feature = "bridges")]
+    if ctx.insurance.write().await.apply_governance(action, height)? {
+        return Ok(true);
+    }
+    #[cfg(feature = "metaverse")]
+    if ctx.orchestrator.write().await.apply_governance(action)? {
+        return Ok(true);
+    }
+    if ctx.daos.write().await.apply_governance(action)? {
+        return Ok(true);
+    }
+    Ok(ctx.circuit_breaker.write().await.apply_governance(action, height)?)
+}
+
+/// Hold or release bridge withdrawals to match the circuit breaker
+#[cfg(feature = "bridges")]
+async fn sync_withdrawal_halt(ctx: &RpcContext) {
+    let halted = ctx.circuit_breaker.read().await.is_halted(&HaltScope::BridgeWithdrawals);
+    ctx.reserve_ledger.write().await.set_withdrawals_halted(halted);
+}
+
+async fn run_epoch_duty(
+    ctx: &RpcContext,
+    governance: &mut AIGovernance,
+    boundary: EpochBoundary,
+    duty: EpochDuty,
+) -> Result<serde_json::Value, String> {
+    match duty {
+        EpochDuty::Rewards => {
+            let mut economics = ctx.economics.write().await;
+            let minted = economics.distribute_rewards();
+            let released = economics.release_provider_stake();
+            let validator_released = economics.release_validator_stake();
+            Ok(json!({
+                "minted": minted,
+                "provider_stake_released": released,
+                "validator_stake_released": validator_released,
+            }))
+        }
+        EpochDuty::ValidatorRotation => {
+            let max = ctx.params.read().await.get_count(ParamKey::MaxActiveValidators, boundary.height);
+            let next = ctx.economics.read().await.top_validators(max as usize);
+            let rotation = ctx.epochs.write().await.rotate_validators(next);
+            serde_json::to_value(rotation).map_err(|e| e.to_string())
+        }
+        EpochDuty::GovernanceTally => {
+            governance.record_slashes(ctx.economics.write().await.take_slash_events());
+            let mut metrics = governance_metrics(ctx).await;
+            metrics.extend(governance.take_slashing_metrics());
+            // Protocol parameter changes wait for the next epoch so every validator switches together
+            let activation_height = ctx.epochs.read().await.schedule().epoch_start(boundary.epoch + 1);
+            let mut applied = 0;
+            let mut scheduled = 0;
+            for policy_id in governance.policy_ids() {
+                for action in governance.evaluate_policy(&policy_id, &metrics)? {
+                    let param = match &action {
+                        Action::UpdateParameter(name, value) => ParamKey::from_name(name).map(|key| (key, value)),
+                        _ => None,
+                    };
+                    if let Some((key, value)) = param {
+                        ctx.params.write().await.schedule(key, value, activation_height, boundary.height)?;
+                        scheduled += 1;
+                    } else if apply_governance(ctx, &action, boundary.height).await? {
+                        applied += 1;
+                    }
+                }
+            }
+            #[cfg(feature = "bridges")]
+            sync_withdrawal_halt(ctx).await;
+            #[cfg(feature = "vm-wasm")]
+            governance.record_metrics(metrics);
+            Ok(json!({
+                "policies": governance.policy_ids().len(),
+                "actions_applied": applied,
+                "params_scheduled": scheduled,
+            }))
+        }
+        #[cfg(feature = "bridges")]
+        EpochDuty::ProofOfReserve => {
+            // Lock order: ledger, then log
+            let ledger = ctx.reserve_ledger.read().await;
+            let mut reserves = ctx.reserves.write().await;
+            match reserves.publish(&ledger, boundary.epoch) {
+                Ok(proof) => Ok(json!({ "chains": ledger.chains().len(), "proof": proof })),
+                Err(alert) => {
+                    eprintln!("Governance alert: proof of reserve failed for epoch {}: {}", alert.epoch, alert.reason);
+                    Err(format!("Proof of reserve failed: {}", alert.reason))
+                }
+            }
+        }
+        #[cfg(not(feature = "bridges"))]
+        EpochDuty::ProofOfReserve => Ok(json!({ "chains": 0 })),
+        EpochDuty::StorageRent => {
+            let unbilled = ctx.private_chains.take_unbilled().await;
+            bill_hosting(&mut *ctx.economics.write().await, &unbilled);
+            Ok(json!({ "hosted_chains": ctx.private_chains.list().await.len() }))
+        }
+        EpochDuty::TallyCheckpoint => {
+            let state_root = ctx.world_state.read().await.latest().state_root();
+            ctx.epochs.write().await.record_checkpoint(boundary.epoch, state_root);
+            if let Some(reporter) = crash::reporter() {
+                reporter.update(|fingerprint| {
+                    fingerprint.checkpoint_epoch = Some(boundary.epoch);
+                    fingerprint.checkpoint_root = Some(state_root);
+                });
+            }
+            Ok(json!({ "state_root": hex::encode(state_root) }))
+        }
+    }
+}
+
+async fn feature_status(
+    ctx: &RpcContext,
+    params: &serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    let tracker = ctx.features.read().await;
+    let height = match params.get("height") {
+        Some(v) => v.as_u64().ok_or("Parameter `height` must be a number")?,
+        None => tracker.next_height(),
+    };
+    Ok(json!({
+        "height": height,
+        "features": tracker.status(height),
+    }))
+}
+
+async fn protocol_params(
+    ctx: &RpcContext,
+    params: &serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    let height = match params.get("height") {
+        Some(v) => v.as_u64().ok_or("Parameter `height` must be a number")?,
+        None => ctx.world_state.read().await.latest_height(),
+    };
+    let registry = ctx.params.read().await;
+    Ok(json!({
+        "height": height,
+        "version": registry.version_at(height),
+        "params": registry.values_at(height),
+        "pending": registry.pending(height),
+    }))
+}
+
+async fn handle_epoch_rpc(
+    ctx: &RpcContext,
+    params: &serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    let epochs = ctx.epochs.read().await;
+    let schedule = epochs.schedule();
+    let epoch = match params.get("epoch") {
+        Some(v) => v.as_u64().ok_or("Parameter `epoch` must be a number")?,
+        None => epochs.current_epoch(),
+    };
+    Ok(json!({
+        "epoch": epoch,
+        "era": schedule.era_of(epoch),
+        "first_block": schedule.epoch_start(epoch),
+        "schedule": schedule,
+        "transition": epochs.transition(epoch),
+        "checkpoint": epochs.checkpoint(epoch).map(hex::encode),
+        "active_validators": epochs.active_validators().iter().map(hex::encode).collect::<Vec<_>>(),
+    }))
+}
+
+async fn handle_beacon_rpc(
+    ctx: &RpcContext,
+    method: &str,
+    params: &serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    let param_epoch = || params.get("epoch")
+        .and_then(|v| v.as_u64())
+        .ok_or_else(|| "Missing parameter `epoch`".to_string());
+    match method {
+        "submitBeaconContribution" => {
+            let contribution = Contribution {
+                validator: param_hex::<32>(params, "validator")?,
+                epoch: param_epoch()?,
+                signature: param_bytes(params, "signature")?,
+            };
+            let store = ctx.world_state.read().await;
+            let mut beacon = ctx.beacon.write().await;
+            let epoch = contribution.epoch;
+            beacon.contribute(contribution, store.latest().validator_keys(), store.latest_height())?;
+            Ok(json!({ "epoch": epoch, "contributions": beacon.contributions(epoch) }))
+        }
+        "getBeacon" => {
+            let beacon = ctx.beacon.read().await;
+            let epoch = match params.get("epoch") {
+                Some(_) => param_epoch()?,
+                None => beacon.latest_epoch(),
+            };
+            let randomness = beacon.randomness(epoch).ok_or("Epoch randomness not yet finalized")?;
+            Ok(json!({
+                "epoch": epoch,
+                "randomness": hex::encode(randomness),
+                "record": beacon.record(epoch),
+                "first_block": beacon.epoch_start(epoch),
+            }))
+        }
+        _ => Err("Method not found".to_string()),
+    }
+}
+
+/// Emergency halts: validator votes to halt or resume, and the active
+/// halts with their record
+async fn handle_circuit_breaker_rpc(
+    ctx: &RpcContext,
+    method: &str,
+    params: &serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    match method {
+        "submitHaltVote" => {
+            let vote: HaltVote = params.get("vote")
+                .cloned()
+                .ok_or("Missing parameter `vote`")
+                .and_then(|vote| serde_json::from_value(vote).map_err(|_| "Invalid vote"))?;
+            let active = ctx.epochs.read().await.active_validators().clone();
+            let store = ctx.world_state.read().await;
+            let mut breaker = ctx.circuit_breaker.write().await;
+            let changed = breaker.vote(vote, &active, store.latest().validator_keys(), store.latest_height())?.cloned();
+            let sequence = breaker.sequence();
+            drop((breaker, store));
+            if let Some(record) = &changed {
+                match record.resumed_at {
+                    Some(height) => println!("Circuit breaker: halt {} of {:?} resumed at block {}", record.id, record.scope, height),
+                    None => eprintln!("Circuit breaker: {:?} halted at block {}: {}", record.scope, record.halted_at, record.reason),
+                }
+                #[cfg(feature = "bridges")]
+                sync_withdrawal_halt(ctx).await;
+            }
+            Ok(json!({ "sequence": sequence, "changed": changed }))
+        }
+        "getCircuitBreaker" => {
+            let breaker = ctx.circuit_breaker.read().await;
+            let pending: Vec<_> = breaker.pending().into_iter()
+                .map(|(proposal, votes)| json!({ "proposal": proposal, "votes": votes }))
+                .collect();
+            Ok(json!({
+                "sequence": breaker.sequence(),
+                "active": breaker.active(),
+                "pending": pending,
+                "records": breaker.records().collect::<Vec<_>>(),
+            }))
+        }
+        _ => Err("Method not found".to_string()),
+    }
+}
+
+/// Check a standalone `AttributeProof`; needs nothing from this node's
+/// identity registry
+async fn verify_attribute_proof(ctx: &RpcContext, params: &serde_json::Value) -> Result<serde_json::Value, String> {
+    let proof: AttributeProof = params.get("proof")
+        .cloned()
+        .ok_or("Missing parameter `proof`")
+        .and_then(|proof| serde_json::from_value(proof).map_err(|_| "Invalid attribute proof"))?;
+    let issued_at = proof.issued_at;
+    let checked = ctx.pools
+        .spawn_blocking(Lane::Background, move || predicate::verify_attribute_proof(&proof))
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(match checked {
+        Ok(identity) => json!({ "satisfied": true, "identity_id": identity, "issued_at": issued_at }),
+        Err(reason) => json!({ "satisfied": false, "reason": reason }),
+    })
+}
+
+/// Attribute checks for dApps that never see the attribute. The verifier
+/// asks for a challenge; the holder answers it with a `PredicateProof`.
+async fn handle_predicate_rpc(
+    ctx: &RpcContext,
+    method: &str,
+    params: &serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    let identity_id = param_id::<IdentityId>(params, "identity_id")?;
+    let predicate: Predicate = params.get("predicate")
+        .cloned()
+        .ok_or("Missing parameter `predicate`")
+        .and_then(|predicate| serde_json::from_value(predicate).map_err(|_| "Invalid predicate"))?;
+    let commitment = {
+        let identity = ctx.identity.read().await;
+        let tuple = identity.get_identity(&identity_id).ok_or("Identity not found")?;
+        predicate::attribute_commitment(tuple, predicate.attribute())?
+    };
+    let now = clock::system().now_secs();
+    match method {
+        "requestPredicate" => {
+            let session = match params.get("session") {
+                Some(_) => param_bytes(params, "session")?,
+                None => Vec::new(),
+            };
+            let challenge = ctx.predicate_challenges.write().await.issue(identity_id, predicate, session, now);
+            Ok(json!(challenge))
+        }
+        "verifyPredicate" => {
+            let proof: PredicateProof = params.get("proof")
+                .cloned()
+                .ok_or("Missing parameter `proof`")
+                .and_then(|proof| serde_json::from_value(proof).map_err(|_| "Invalid predicate proof"))?;
+            let challenge = ctx.predicate_challenges.write().await.take(&proof.nonce, now)?;
+            if challenge.identity != identity_id || challenge.predicate != predicate {
+                return Err("Proof answers a challenge for another identity or predicate".to_string());
+            }
+            // Range proofs are slow to check; keep them off the async workers
+            let checked = ctx.pools
+                .spawn_blocking(Lane::Background, move || proof.verify(&challenge, &commitment).map(|_| challenge.session))
+                .await
+                .map_err(|e| e.to_string())?;
+            Ok(match checked {
+                Ok(session) => json!({ "satisfied": true, "session": hex::encode(session) }),
+                Err(reason) => json!({ "satisfied": false, "reason": reason }),
+            })
+        }
+        _ => Err("Method not found".to_string()),
+    }
+}
+
+/// Reality layer observations, presence and access policies
+#[cfg(feature = "metaverse")]
+async fn handle_orchestration_rpc(
+    ctx: &RpcContext,
+    method: &str,
+    params: &serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    let parse = |name: &str| params.get(name)
+        .cloned()
+        .ok_or_else(|| format!("Missing parameter `{}`", name));
+    match method {
+        "recordQuantumState" => {
+            let observation: TallyObservation = serde_json::from_value(parse("observation")?)
+                .map_err(|_| "Invalid observation")?;
+            observation.verify(ctx.network_id)?;
+            let credentials = access_credentials(ctx, params, &observation.observer_id).await?;
+            let overlap = ctx.orchestrator.write().await.record_quantum_state(
+                observation.observer_id,
+                observation.observed_state,
+                observation.layer_id,
+                &credentials,
+                HashMap::new(),
+            )?;
+            Ok(json!({
+                "overlap": overlap,
+                "reality_layer": observation.layer_id,
+                "timestamp": observation.observed_at,
+            }))
+        }
+        "announcePresence" => {
+            let announcement: PresenceAnnouncement = serde_json::from_value(parse("announcement")?)
+                .map_err(|_| "Invalid presence announcement")?;
+            announcement.verify(ctx.network_id)?;
+            let credentials = access_credentials(ctx, params, &announcement.observer_id).await?;
+            let observers = ctx.orchestrator.write().await
+                .announce_presence(announcement.layer_id, announcement.observer_id, &credentials)?;
+            Ok(json!({ "layer_id": announcement.layer_id, "observer_count": observers }))
+        }
+        "setLayerPolicy" => {
+            let change: PolicyChange = serde_json::from_value(parse("change")?)
+                .map_err(|_| "Invalid policy change")?;
+            let mut orchestrator = ctx.orchestrator.write().await;
+            orchestrator.set_layer_policy(&change, ctx.network_id)?;
+            Ok(json!(orchestrator.access().get(change.layer_id)))
+        }
+        "getLayerAccess" => {
+            let layer_id = params.get("layer_id")
+                .and_then(|v| v.as_u64())
+                .and_then(|v| u32::try_from(v).ok())
+                .ok_or("Missing parameter `layer_id`")?;
+            let orchestrator = ctx.orchestrator.read().await;
+            // Undeclared layers are public and have no controller
+            Ok(json!({ "layer_id": layer_id, "access": orchestrator.access().get(layer_id) }))
+        }
+        "getOrchestrationMetrics" => Ok(json!(ctx.orchestrator.read().await.get_metrics())),
+        _ => Err("Method not found".to_string()),
+    }
+}
+
+/// What `observer` has shown toward a layer's access policy: its observer
+/// bond, and with `identity_id` an identity whose signing key it is,
+/// optionally with a `predicate_proof` answering a challenge for that
+/// identity
+#[cfg(feature = "metaverse")]
+async fn access_credentials(
+    ctx: &RpcContext,
+    params: &serde_json::Value,
+    observer: &[u8; 32],
+) -> Result<AccessCredentials, String> {
+    let mut credentials = AccessCredentials {
+        stake: ctx.economics.read().await.providers().get(observer)
+            .filter(|provider| provider.kind == ProviderKind::Observer && provider.is_active())
+            .map(|provider| provider.bond.clone()),
+        ..Default::default()
+    };
+    if params.get("identity_id").is_none() {
+        return Ok(credentials);
+    }
+    let identity_id = param_id::<IdentityId>(params, "identity_id")?;
+    if ctx.identity.read().await.signing_key_of(&identity_id)? != observer.as_slice() {
+        return Err("Observer does not hold the identity's signing key".to_string());
+    }
+    credentials.identity = Some(identity_id);
+
+    if let Some(proof) = params.get("predicate_proof") {
+        let proof: PredicateProof = serde_json::from_value(proof.clone()).map_err(|_| "Invalid predicate proof")?;
+        let challenge = ctx.predicate_challenges.write().await.take(&proof.nonce, clock::system().now_secs())?;
+        if challenge.identity != identity_id {
+            return Err("Proof answers a challenge for another identity".to_string());
+        }
+        let commitment = {
+            let identity = ctx.identity.read().await;
+            let tuple = identity.get_identity(&identity_id).ok_or("Identity not found")?;
+            predicate::attribute_commitment(tuple, challenge.predicate.attribute())?
+        };
+        let predicate = challenge.predicate.clone();
+        ctx.pools
+            .spawn_blocking(Lane::Background, move || proof.verify(&challenge, &commitment))
+            .await
+            .map_err(|e| e.to_string())??;
+        credentials.proven.push(predicate);
+    }
+    Ok(credentials)
+}
+
+/// Layer and private-chain changes made by executed DAO proposals
+struct NodeDaoHooks<'a> {
+    #[cfg(feature = "metaverse")]
+    orchestrator: &'a mut Orchestrator,
+    /// The DAO's private chain, held while the proposal executes
+    private_chain: Option<&'a mut LockedChain>,
+}
+
+impl DaoHooks for NodeDaoHooks<'_> {
+    #[cfg(feature = "metaverse")]
+    fn set_layer_policy(&mut self, layer_id: u32, policy: &AccessPolicy) -> Result<(), &'static str> {
+        self.orchestrator.apply_dao_policy(layer_id, policy.clone())
+    }
+
+    #[cfg(not(feature = "metaverse"))]
+    fn set_layer_policy(&mut self, _layer_id: u32, _policy: &AccessPolicy) -> Result<(), &'static str> {
+        Err("Reality layers need the metaverse feature")
+    }
+
+    fn anchor(&mut self, chain_id: &ChainId, mainnet_block_hash: [u8; 32]) -> Result<(), &'static str> {
+        match &mut self.private_chain {
+            Some(chain) if chain.chain_id() == chain_id => chain.anchor_to_mainnet(mainnet_block_hash),
+            _ => Err("Chain not found"),
+        }
+    }
+}
+
+/// Create a layer's DAO from a charter its owner signed, and hand the layer to it
+#[cfg(feature = "metaverse")]
+async fn charter_layer_dao(ctx: &RpcContext, charter: Charter, layer_id: u32, now: u64) -> Result<DaoId, String> {
+    let mut orchestrator = ctx.orchestrator.write().await;
+    let owner = match orchestrator.access().get(layer_id).map(|access| &access.controller) {
+        Some(LayerController::Owner { key }) => *key,
+        _ => return Err("Only a layer with an owner can charter a DAO".to_string()),
+    };
+    let id = ctx.daos.write().await.create(charter, &[owner], now)?;
+    orchestrator.hand_to_dao(layer_id, &owner)?;
+    Ok(id)
+}
+
+#[cfg(not(feature = "metaverse"))]
+async fn charter_layer_dao(_ctx: &RpcContext, _charter: Charter, _layer_id: u32, _now: u64) -> Result<DaoId, String> {
+    Err("Reality layers need the metaverse feature".to_string())
+}
+
+/// Scoped DAOs: charters, proposals, votes and execution
+async fn handle_dao_rpc(
+    ctx: &RpcContext,
+    method: &str,
+    params: &serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    let parse = |name: &str| params.get(name)
+        .cloned()
+        .ok_or_else(|| format!("Missing parameter `{}`", name));
+    let now = clock::system().now_secs();
+    match method {
+        "createDao" => {
+            let charter: Charter = serde_json::from_value(parse("charter")?).map_err(|_| "Invalid charter")?;
+            let id = match charter.scope {
+                DaoScope::Layer { layer_id } => charter_layer_dao(ctx, charter, layer_id, now).await?,
+                DaoScope::PrivateChain { chain_id } => {
+                    let owners = ctx.private_chains.owners(&chain_id).await?;
+                    ctx.daos.write().await.create(charter, &owners, now)?
+                }
+            };
+            Ok(json!({ "dao_id": hex::encode(id) }))
+        }
+        "getDao" => {
+            let id = param_hex::<32>(params, "dao_id")?;
+            let daos = ctx.daos.read().await;
+            let dao = daos.get(&id).ok_or("DAO not found")?;
+            Ok(json!({ "dao_id": hex::encode(id), "next_proposal": dao.next_proposal(), "dao": dao }))
+        }
+        "proposeDao" => {
+            let proposal: SignedProposal = serde_json::from_value(parse("proposal")?).map_err(|_| "Invalid proposal")?;
+            let id = ctx.daos.write().await.propose(proposal, now)?;
+            Ok(json!({ "proposal": id }))
+        }
+        "voteDao" => {
+            let ballot: Ballot = serde_json::from_value(parse("ballot")?).map_err(|_| "Invalid ballot")?;
+            ctx.daos.write().await.vote(ballot, now)?;
+            Ok(json!({ "accepted": true }))
+        }
+        "executeDaoProposal" => {
+            let id = param_hex::<32>(params, "dao_id")?;
+            let proposal = params.get("proposal")
+                .and_then(|v| v.as_u64())
+                .ok_or("Missing parameter `proposal`")?;
+            #[cfg(feature = "metaverse")]
+            let mut orchestrator = ctx.orchestrator.write().await;
+            let scope = ctx.daos.read().await.get(&id).map(|dao| dao.scope);
+            let mut private_chain = match scope {
+                Some(DaoScope::PrivateChain { chain_id }) => Some(ctx.private_chains.lock_chain(&chain_id).await?),
+                _ => None,
+            };
+            let mut hooks = NodeDaoHooks {
+                #[cfg(feature = "metaverse")]
+                orchestrator: &mut orchestrator,
+                private_chain: private_chain.as_mut(),
+            };
+            let status = ctx.daos.write().await.execute(&id, proposal, &mut hooks, now)?;
+            Ok(json!(status))
+        }
+        _ => Err("Method not found".to_string()),
+    }
+}
+
+async fn handle_attestation_rpc(
+    ctx: &RpcContext,
+    method: &str,
+    params: &serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    let param_u64 = |name: &str| params.get(name)
+        .and_then(|v| v.as_u64())
+        .ok_or_else(|| format!("Missing parameter `{}`", name));
+    match method {
+        "submitAttestation" => {
+            let attestation = Attestation {
+                attester: param_id::<IdentityId>(params, "attester")?,
+                subject: param_id::<IdentityId>(params, "subject")?,
+                weight: u16::try_from(param_u64("weight")?).map_err(|_| "Parameter `weight` out of range")?,
+                issued_at: param_u64("issued_at")?,
+                signature: param_bytes(params, "signature")?,
+            };
+            let subject = attestation.subject;
+            let mut identity = ctx.identity.write().await;
+            identity.attest(attestation, ctx.network_id)?;
+            Ok(json!({ "trust_score": identity.get_trust_score(&subject)? }))
+        }
+        "revokeAttestation" => {
+            let revocation = Revocation {
+                attester: param_id::<IdentityId>(params, "attester")?,
+                subject: param_id::<IdentityId>(params, "subject")?,
+                revoked_at: param_u64("revoked_at")?,
+                signature: param_bytes(params, "signature")?,
+            };
+            let removed = ctx.identity.write().await.revoke_attestation(&revocation, ctx.network_id)?;
+            Ok(json!({ "removed": removed }))
+        }
+        "getAttestations" => {
+            let id = param_id::<IdentityId>(params, "identity")?;
+            let depth = params.get("depth").and_then(|v| v.as_u64()).unwrap_or(1).min(MAX_ATTESTATION_DEPTH);
+            Ok(json!(ctx.identity.read().await.attestation_neighborhood(&id, depth as usize)?))
+        }
+        _ => Err("Method not found".to_string()),
+    }
+}
+
+async fn handle_trace_rpc(
+    ctx: &RpcContext,
+    method: &str,
+    params: &serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    let traces = ctx.traces.read().await;
+    match method {
+        "getTransactionTrace" => {
+            let hash = param_hex::<32>(params, "hash")?;
+            Ok(json!({ "hash": hex::encode(hash), "events": traces.transaction(&hash) }))
+        }
+        "getTrace" => {
+            let trace_id = TraceId::parse(param_str(params, "trace_id")?).ok_or("Invalid trace ID")?;
+            let events: Vec<serde_json::Value> = traces.by_trace(&trace_id).into_iter()
+                .map(|(hash, event)| json!({ "hash": hex::encode(hash), "event": event }))
+                .collect();
+            Ok(json!({ "trace_id": trace_id, "events": events }))
+        }
+        _ => Err("Method not found".to_string()),
+    }
+}
+
+async fn handle_certificate_rpc(
+    ctx: &RpcContext,
+    method: &str,
+    params: &serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    let permissions = ctx.p2p.permissions.as_ref().ok_or("Node is not running a permissioned network")?;
+    match method {
+        "revokeCertificate" => {
+            let revocation: CertificateRevocation = serde_json::from_value(params.clone())
+                .map_err(|e| format!("Invalid revocation: {}", e))?;
+            let added = permissions.registry.write().await.revoke(revocation.clone())?;
+            let dropped = if added { ctx.p2p.enforce_permissions().await } else { Vec::new() };
+            if added {
+                let fanout = ctx.config.read().await.current().gossip_fanout;
+                let trace_id = trace::current();
+                let prefix = trace_prefix(trace_id.as_ref());
+                let message = P2PMessage { message_type: "revocations".to_string(), payload: json!([revocation]), trace_id };
+                let sent = gossip(&ctx.p2p, "", &message, fanout).await;
+                println!("{}Relayed certificate revocation by gossip to {} peers", prefix, sent);
+            }
+            Ok(json!({ "added": added, "dropped_peers": dropped }))
+        }
+        "getCertificates" => {
+            let registry = permissions.registry.read().await;
+            let peers: Vec<serde_json::Value> = ctx.p2p.peers.read().await.values()
+                .filter_map(|peer| peer.certificate.as_ref().map(|certificate| json!({
+                    "address": peer.address,
+                    "serial": certificate.serial,
+                    "issuer": hex::encode(certificate.issuer),
+                    "not_after": certificate.not_after,
+                })))
+                .collect();
+            Ok(json!({
+                "authorities": registry.authorities().map(hex::encode).collect::<Vec<_>>(),
+                "certificate": permissions.certificate(),
+                "revocations": registry.revocations(),
+                "peers": peers,
+            }))
+        }
+        _ => Err("Method not found".to_string()),
+    }
+}
+
+/// Connected peers with the addresses they advertised, best route first
+async fn peer_routes(ctx: &RpcContext) -> Result<serde_json::Value, String> {
+    let addresses: Vec<String> = ctx.p2p.peers.read().await.keys().cloned().collect();
+    let mut peers = serde_json::Map::new();
+    for address in addresses {
+        let routes: Vec<String> = ctx.p2p.routes(&address).await.iter().map(SocketAddr::to_string).collect();
+        peers.insert(address, json!(routes));
+    }
+    Ok(json!({
+        "listening": ctx.p2p.listen_addrs,
+        "advertised": listen::advertised(&ctx.p2p.external_addrs, &ctx.p2p.listen_addrs),
+        "peers": peers,
+    }))
+}
+
+async fn seed_status(ctx: &RpcContext) -> Result<serde_json::Value, String> {
+    let seeds = match &ctx.p2p.seeds {
+        Some(seeds) => seeds.read().await.status(),
+        None => Vec::new(),
+    };
+    Ok(json!({
+        "seeds": seeds,
+        "bootstrap": ctx.p2p.bootstrap_peers().await,
+    }))
+}
+
+async fn sentry_status(ctx: &RpcContext) -> Result<serde_json::Value, String> {
+    let sentry = ctx.p2p.sentry.as_ref().ok_or("Sentry topology is not configured")?;
+    let sentry = sentry.read().await;
+    Ok(json!({
+        "role": sentry.role(),
+        "healthy": sentry.healthy().len(),
+        "private_peers": sentry.status(),
+    }))
+}
+
+/// Sync headers-first from `peers` until the chain reaches the best height
+/// they announce. Each peer gets its own connection; one that times out or
+/// serves invalid data is dropped and its requests go to the others.
+async fn sync_blockchain(ctx: &RpcContext, peers: Vec<SocketAddr>) {
+    if peers.is_empty() {
+        println!("No sync peers configured; continuing from the local chain");
+        return;
+    }
+    // A node starting from genesis can restore a recent state instead of
+    // executing every block before it
+    let fast_sync = ctx.config.read().await.current().fast_sync.clone();
+    let mut restored = false;
+    if fast_sync.enabled && ctx.chain.read().await.height() <= 1 {
+        match restore_snapshot(ctx, &peers, &fast_sync).await {
+            Ok(()) => restored = true,
+            Err(e) => eprintln!("Fast sync stopped, executing every block instead: {}", e),
+        }
+    }
+    let downloads: Vec<_> = peers.into_iter()
+        .map(|peer| tokio::spawn(sync_from_peer(ctx.clone(), peer.to_string())))
+        .collect();
+    for download in downloads {
+        let _ = download.await;
+    }
+    let status = ctx.sync.read().await.status(ctx.chain.read().await.height());
+    println!("Sync finished at block {} of {}", status.current_block, status.highest_block);
+    // The download is no longer needed once the pivot block is in
+    if restored && status.pivot.is_none() {
+        let _ = std::fs::remove_dir_all(&fast_sync.dir);
+    }
+}
+
+/// Download the state snapshot enough sync peers agree on, from all of them
+/// at once, and make it the sync pivot. Verified chunks stay on disk, so a
+/// restart picks the download up where it stopped.
+async fn restore_snapshot(ctx: &RpcContext, peers: &[SocketAddr], config: &FastSyncConfig) -> Result<(), String> {
+    let compression = ctx.config.read().await.current().compression.clone();
+    let offers = futures::future::join_all(peers.iter().map(|peer| snapshot_offer(ctx, peer.to_string(), &compression))).await;
+    let mut manifests = Vec::new();
+    let mut sockets = HashMap::new();
+    for (peer, offer) in offers {
+        match offer {
+            Ok((socket, offered)) => {
+                manifests.push((peer.clone(), offered));
+                sockets.insert(peer, socket);
+            }
+            Err(e) => eprintln!("No snapshot offer from {}: {}", peer, e),
+        }
+    }
+
+    let dir = std::path::PathBuf::from(&config.dir);
+    let stored = snapshot::stored_manifest(&dir);
+    let (manifest, agreeing) = snapshot::choose_manifest(&manifests, config.min_peers, stored.as_ref())
+        .ok_or("no snapshot is offered by enough sync peers")?;
+    let (download, stored_chunks) = SnapshotDownload::open(&dir, manifest, config.max_chunks_in_flight)?;
+    let manifest = download.manifest();
+    println!(
+        "Fast sync: downloading the state at block {} ({} chunks) from {} peers",
+        manifest.height, manifest.chunks.len(), agreeing.len()
+    );
+    *ctx.snapshot_download.write().await = Some(download);
+
+    // Chunks kept from an earlier run are hashed again before they count
+    let checks: Vec<_> = stored_chunks.into_iter()
+        .map(|index| {
+            let (dir, manifest) = (dir.clone(), manifest.clone());
+            ctx.pools.spawn_blocking(Lane::Background, move || (index, snapshot::check_stored_chunk(&dir, &manifest, index)))
+        })
+        .collect();
+    for check in checks {
+        if let Ok((index, Ok(()))) = check.await {
+            if let Some(download) = ctx.snapshot_download.write().await.as_mut() {
+                download.on_verified(index);
+            }
+        }
+    }
+
+    let downloads: Vec<_> = agreeing.into_iter()
+        .filter_map(|peer| sockets.remove(&peer).map(|socket| (peer, socket)))
+        .map(|(peer, socket)| tokio::spawn(download_chunks(ctx.clone(), peer, socket, compression.clone())))
+        .collect();
+    for download in downloads {
+        let _ = download.await;
+    }
+    if !ctx.snapshot_download.read().await.as_ref().is_some_and(SnapshotDownload::is_complete) {
+        return Err("the snapshot peers left before the download finished".to_string());
+    }
+
+    let (rebuild_dir, rebuild_manifest) = (dir.clone(), manifest.clone());
+    let state = ctx.pools.spawn_blocking(Lane::Background, move || snapshot::rebuild(&rebuild_dir, &rebuild_manifest))
+        .await
+        .map_err(|e| e.to_string())??;
+    println!("Fast sync: restored the state at block {}", state.height());
+    ctx.sync.write().await.set_pivot(state, manifest.block_hash);
+    Ok(())
+}
+
+/// Snapshots a sync peer serves, with the connection to download from
+async fn snapshot_offer(
+    ctx: &RpcContext,
+    peer: String,
+    compression: &CompressionConfig,
+) -> (String, Result<(SyncSocket, Vec<SnapshotManifest>), String>) {
+    let offer = async {
+        let (mut socket, _) = connect_sync_peer(ctx, &peer, compression).await?;
+        let manifests = sync_exchange(&mut socket, &ctx.p2p, compression, "get_snapshot_manifests", json!({}), "snapshot_manifests").await?;
+        Ok((socket, manifests))
+    };
+    let offer = offer.await;
+    (peer, offer)
+}
+
+/// Fetch snapshot chunks from one peer until the download is complete
+async fn download_chunks(ctx: RpcContext, peer: String, mut socket: SyncSocket, compression: CompressionConfig) {
+    if let Err(e) = fetch_chunks(&ctx, &peer, &mut socket, &compression).await {
+        eprintln!("Snapshot download from {} stopped: {}", peer, e);
+    }
+    if let Some(download) = ctx.snapshot_download.write().await.as_mut() {
+        download.remove_peer(&peer);
+    }
+}
+
+/// Each chunk is hashed and written on the background pool while the next
+/// one downloads; the peer is dropped at its first chunk that fails the check
+async fn fetch_chunks(ctx: &RpcContext, peer: &str, socket: &mut SyncSocket, compression: &CompressionConfig) -> Result<(), String> {
+    let mut checks: Vec<tokio::task::JoinHandle<Result<(), String>>> = Vec::new();
+    loop {
+        let (finished, running): (Vec<_>, Vec<_>) = checks.into_iter().partition(|check| check.is_finished());
+        checks = running;
+        for check in finished {
+            check.await.map_err(|e| e.to_string())??;
+        }
+
+        let next = {
+            let mut download = ctx.snapshot_download.write().await;
+            let download = download.as_mut().ok_or("no snapshot download")?;
+            if download.is_complete() {
+                return Ok(());
+            }
+            download.next_request(peer).map(|request| (request, download.dir().to_path_buf(), download.manifest()))
+        };
+        let Some((request, dir, manifest)) = next else {
+            tokio::time::sleep(std::time::Duration::from_millis(SYNC_IDLE_MS)).await;
+            continue;
+        };
+        let index = request.index;
+        let chunk: SnapshotChunk = sync_exchange(socket, &ctx.p2p, compression, "get_snapshot_chunk", json!(request), "snapshot_chunk").await?;
+
+        let ctx = ctx.clone();
+        checks.push(tokio::spawn(async move {
+            let stored = ctx.pools.spawn_blocking(Lane::Background, move || {
+                if chunk.index != index {
+                    return Err(format!("sent chunk {} for chunk {}", chunk.index, index));
+                }
+                snapshot::store_chunk(&dir, &manifest, &chunk)
+            });
+            let stored = stored.await.map_err(|e| e.to_string()).and_then(|stored| stored);
+            if let Some(download) = ctx.snapshot_download.write().await.as_mut() {
+                match stored {
+                    Ok(()) => download.on_verified(index),
+                    Err(_) => download.on_failed(index),
+                }
+            }
+            stored
+        }));
+    }
+}
+
+async fn sync_from_peer(ctx: RpcContext, peer: String) {
+    if let Err(e) = download_from_peer(&ctx, &peer).await {
+        eprintln!("Sync with {} stopped: {}", peer, e);
+    }
+    ctx.sync.write().await.remove_peer(&peer);
+}
+
+type SyncSocket = tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// Connect to a sync peer and exchange handshakes
+async fn connect_sync_peer(ctx: &RpcContext, peer: &str, compression: &CompressionConfig) -> Result<(SyncSocket, Handshake), String> {
+    let timeout = std::time::Duration::from_secs(SYNC_REQUEST_TIMEOUT_SECS);
+    let (mut socket, _) = tokio::time::timeout(timeout, tokio_tungstenite::connect_async(format!("ws://{}", peer)))
+        .await
+        .map_err(|_| "connection timed out".to_string())?
+        .map_err(|e| e.to_string())?;
+    let mut local = ctx.p2p.local_handshake(ctx.chain.read().await.height()).await;
+    local.compression = compression.codecs.clone();
+    let remote: Handshake = sync_exchange(&mut socket, &ctx.p2p, compression, "handshake", json!(local), "handshake").await?;
+    ctx.p2p.check_chain(&remote)?;
+    Ok((socket, remote))
+}
+
+async fn download_from_peer(ctx: &RpcContext, peer: &str) -> Result<(), String> {
+    let compression = ctx.config.read().await.current().compression.clone();
+    let (mut socket, remote) = connect_sync_peer(ctx, peer, &compression).await?;
+    ctx.sync.write().await.add_peer(peer, remote.best_height);
+
+    loop {
+        let chain_height = ctx.chain.read().await.height();
+        let (headers, bodies) = {
+            let mut engine = ctx.sync.write().await;
+            match engine.header_request(peer, chain_height) {
+                Some(request) => (Some(request), None),
+                None => (None, engine.body_request(peer)),
+            }
+        };
+        if let Some(request) = headers {
+            let headers: Vec<BlockHeader> = sync_exchange(&mut socket, &ctx.p2p, &compression, "get_headers", json!(request), "headers").await?;
+            let chain = ctx.chain.read().await;
+            ctx.sync.write().await.on_headers(&chain, peer, headers)?;
+        } else if let Some(request) = bodies {
+            let bodies: Vec<BlockBody> = sync_exchange(&mut socket, &ctx.p2p, &compression, "get_bodies", json!(request), "bodies").await?;
+            ctx.sync.write().await.on_bodies(peer, bodies)?;
+            BlockImport::new(ctx).import(None).await?;
+        } else {
+            // Done once the chain has everything this peer has; until then
+            // other peers hold the outstanding requests
+            match ctx.sync.read().await.peer_height(peer) {
+                Some(best) if best > chain_height => {}
+                _ => return Ok(()),
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(SYNC_IDLE_MS)).await;
+        }
+    }
+}
+
+/// Hold a connection to the validator at `address` and take the envelopes
+/// and blocks it sends. The connection is one of that validator's links,
+/// so what it publishes reaches this node even when this node accepts no
+/// connections itself.
+async fn follow_validator(ctx: &RpcContext, inbox: &ConsensusInbox, address: &str, shutdown: &mut ShutdownSignal) -> Result<(), String> {
+    let compression = ctx.config.read().await.current().compression.clone();
+    let (mut socket, _) = connect_sync_peer(ctx, address, &compression).await?;
+    println!("Connected to validator {}", address);
+    loop {
+        let msg = tokio::select! {
+            msg = socket.next() => msg,
+            _ = shutdown.wait() => return Ok(()),
+        };
+        let msg = msg.ok_or("connection closed")?.map_err(|e| e.to_string())?;
+        let Some(msg) = inflate_message(msg, &ctx.p2p, &compression, address) else { continue };
+        let Ok(text) = msg.to_text() else { continue };
+        let Ok(p2p_msg) = serde_json::from_str::<P2PMessage>(text) else { continue };
+        match p2p_msg.message_type.as_str() {
+            "vote" => {
+                let fanout = ctx.config.read().await.current().gossip_fanout;
+                inbox.receive(&ctx.p2p, address, &p2p_msg, fanout).await;
+            }
+            "block" => {
+                let Ok(block) = Block::deserialize(&p2p_msg.payload) else { continue };
+                let index = block.index;
+                if let Err(e) = BlockImport::new(ctx).import(Some(block)).await {
+                    eprintln!("Rejected block {} from {}: {}", index, address, e);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Send a sync request and wait for its reply, skipping other messages
+async fn sync_exchange<T: serde::de::DeserializeOwned>(
+    socket: &mut SyncSocket,
+    network: &P2PNetwork,
+    compression: &CompressionConfig,
+    message_type: &str,
+    payload: serde_json::Value,
+    reply_type: &str,
+) -> Result<T, String> {
+    let request = P2PMessage { message_type: message_type.to_string(), payload, trace_id: None };
+    let request = serde_json::to_string(&request).map_err(|e| e.to_string())?;
+    socket.send(tokio_tungstenite::tungstenite::Message::Text(request)).await.map_err(|e| e.to_string())?;
+    let deadline = tokio::time::Instant::now() + std::time::Duration::from_secs(SYNC_REQUEST_TIMEOUT_SECS);
+    loop {
+        let message = tokio::time::timeout_at(deadline, socket.next())
+            .await
+            .map_err(|_| format!("no {} within {}s", reply_type, SYNC_REQUEST_TIMEOUT_SECS))?
+            .ok_or("connection closed")?
+            .map_err(|e| e.to_string())?;
+        let message = inflate_message(message, network, compression, "sync peer").ok_or("malformed compressed reply")?;
+        let Ok(text) = message.to_text() else { continue };
+        let Ok(reply) = serde_json::from_str::<P2PMessage>(text) else { continue };
+        if reply.message_type == reply_type {
+            return serde_json::from_value(reply.payload).map_err(|e| format!("malformed {}: {}", reply_type, e));
+        }
+    }
+}
+
+/// What importing a block updates: the chain and its state, and the
+/// indexes built from the block's receipts
+#[derive(Clone)]
+struct BlockImport {
+    chain: Arc<RwLock<Blockchain>>,
+    world_state: Arc<RwLock<StateStore>>,
+    sync: Arc<RwLock<SyncEngine>>,
+    logs: Arc<RwLock<LogIndex>>,
+    contract_meter: Arc<RwLock<ContractMeter>>,
+}
+
+impl BlockImport {
+    fn new(ctx: &RpcContext) -> Self {
+        Self {
+            chain: ctx.chain.clone(),
+            world_state: ctx.world_state.clone(),
+            sync: ctx.sync.clone(),
+            logs: ctx.logs.clone(),
+            contract_meter: ctx.contract_meter.clone(),
+        }
+    }
+
+    /// Execute and import the downloaded blocks that follow the tip, after
+    /// queueing the block a peer `announced`, if any
+    async fn import(&self, announced: Option<Block>) -> Result<(), String> {
+        let mut store = self.world_state.write().await;
+        let mut chain = self.chain.write().await;
+        let mut sync = self.sync.write().await;
+        if let Some(block) = announced {
+            sync.on_block(&chain, block)?;
+        }
+        let imported = sync.import(&mut chain, &mut store)?;
+        drop((sync, chain, store));
+        if let Some(last) = imported.last() {
+            println!("Synced to block {}", last.index);
+        }
+        for block in imported {
+            self.meter(block.index, &block.receipts).await;
+            self.logs.write().await.record_block(block.index, block.bloom, block.receipts);
+        }
+        Ok(())
+    }
+
+    /// Attribute the contract calls of the stored block at `height` for
+    /// `getContractStats`
+    async fn meter(&self, height: u64, receipts: &[Receipt]) {
+        let Some(transactions) = self.chain.read().await.block(height).map(reindex::block_transactions) else { return };
+        let store = self.world_state.read().await;
+        self.contract_meter.write().await.record_block(height, &transactions, receipts, store.latest());
+    }
+}
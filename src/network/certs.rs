@@ -0,0 +1,334 @@
+//! Node certificates for permissioned networks.
+//!
+//! A permissioned network admits only peers holding a certificate from one of
+//! its certificate authorities. The authority set starts from the node config
+//! and changes through governance actions. A certificate binds a node's
+//! ed25519 key to a serial and a validity period, and the node signs its
+//! handshake with that key to show the certificate is its own. An authority
+//! withdraws a certificate by signing a revocation of its serial; peers
+//! gossip revocations so a revoked node is dropped across the network.
+
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, BTreeSet};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use tokio::sync::RwLock;
+use crate::blockchain::types::hex_serde;
+use crate::clock::SharedClock;
+use crate::crypto::domain::{PayloadKind, SigningDomain};
+use crate::governance::ai_governance::Action;
+
+/// Governance action trusting a new authority; the payload is its 32-byte key
+pub const ADD_AUTHORITY_ACTION: &str = "network.add_certificate_authority";
+
+/// Governance action withdrawing trust from an authority
+pub const REMOVE_AUTHORITY_ACTION: &str = "network.remove_certificate_authority";
+
+/// Furthest a handshake timestamp may be from the local clock
+pub const HANDSHAKE_MAX_SKEW_SECS: u64 = 300;
+
+/// "The node holding `node_key` may join the network", signed by `issuer`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeCertificate {
+    /// Unique per issuer; revocations refer to it
+    pub serial: u64,
+    #[serde(with = "hex_serde")]
+    pub node_key: [u8; 32],
+    #[serde(with = "hex_serde")]
+    pub issuer: [u8; 32],
+    /// First second the certificate is valid, unix time
+    pub not_before: u64,
+    /// Last second the certificate is valid, unix time
+    pub not_after: u64,
+    #[serde(with = "hex_serde")]
+    pub signature: Vec<u8>,
+}
+
+impl NodeCertificate {
+    /// Certificate for `node_key` signed by `authority`
+    pub fn issue(
+        authority: &SigningKey,
+        node_key: [u8; 32],
+        serial: u64,
+        not_before: u64,
+        not_after: u64,
+        network_id: u64,
+    ) -> Self {
+        let mut certificate = Self {
+            serial,
+            node_key,
+            issuer: authority.verifying_key().to_bytes(),
+            not_before,
+            not_after,
+            signature: Vec::new(),
+        };
+        certificate.signature = authority.sign(&certificate.signing_bytes(network_id)).to_bytes().to_vec();
+        certificate
+    }
+
+    /// Bytes the issuer signs
+    pub fn signing_bytes(&self, network_id: u64) -> Vec<u8> {
+        let mut body = Vec::with_capacity(1 + 8 + 32 + 32 + 8 + 8);
+        body.push(0); // certificate
+        body.extend_from_slice(&self.serial.to_le_bytes());
+        body.extend_from_slice(&self.node_key);
+        body.extend_from_slice(&self.issuer);
+        body.extend_from_slice(&self.not_before.to_le_bytes());
+        body.extend_from_slice(&self.not_after.to_le_bytes());
+        SigningDomain::main_chain(network_id).payload(PayloadKind::NodeCertificate, 0, &body)
+    }
+}
+
+/// Withdrawal of certificate `serial`, signed by the authority that issued it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CertificateRevocation {
+    pub serial: u64,
+    #[serde(with = "hex_serde")]
+    pub issuer: [u8; 32],
+    pub revoked_at: u64,
+    #[serde(with = "hex_serde")]
+    pub signature: Vec<u8>,
+}
+
+impl CertificateRevocation {
+    pub fn issue(authority: &SigningKey, serial: u64, revoked_at: u64, network_id: u64) -> Self {
+        let mut revocation = Self {
+            serial,
+            issuer: authority.verifying_key().to_bytes(),
+            revoked_at,
+            signature: Vec::new(),
+        };
+        revocation.signature = authority.sign(&revocation.signing_bytes(network_id)).to_bytes().to_vec();
+        revocation
+    }
+
+    /// Bytes the issuer signs
+    pub fn signing_bytes(&self, network_id: u64) -> Vec<u8> {
+        let mut body = Vec::with_capacity(1 + 8 + 32 + 8);
+        body.push(1); // revoke
+        body.extend_from_slice(&self.serial.to_le_bytes());
+        body.extend_from_slice(&self.issuer);
+        body.extend_from_slice(&self.revoked_at.to_le_bytes());
+        SigningDomain::main_chain(network_id).payload(PayloadKind::NodeCertificate, 0, &body)
+    }
+}
+
+/// Certificate presented in a handshake, with the holder's signature over
+/// the current time to show it owns the certified key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HandshakeAuth {
+    pub certificate: NodeCertificate,
+    pub timestamp: u64,
+    #[serde(with = "hex_serde")]
+    pub signature: Vec<u8>,
+}
+
+impl HandshakeAuth {
+    pub fn sign(node_key: &SigningKey, certificate: NodeCertificate, timestamp: u64, network_id: u64) -> Self {
+        let mut auth = Self { certificate, timestamp, signature: Vec::new() };
+        auth.signature = node_key.sign(&auth.signing_bytes(network_id)).to_bytes().to_vec();
+        auth
+    }
+
+    /// Bytes the node signs
+    pub fn signing_bytes(&self, network_id: u64) -> Vec<u8> {
+        let mut body = Vec::with_capacity(1 + 32 + 8 + 8);
+        body.push(2); // handshake
+        body.extend_from_slice(&self.certificate.node_key);
+        body.extend_from_slice(&self.certificate.serial.to_le_bytes());
+        body.extend_from_slice(&self.timestamp.to_le_bytes());
+        SigningDomain::main_chain(network_id).payload(PayloadKind::NodeCertificate, 0, &body)
+    }
+}
+
+fn verify_signature(key: &[u8; 32], message: &[u8], signature: &[u8]) -> Result<(), ()> {
+    let key = VerifyingKey::from_bytes(key).map_err(|_| ())?;
+    let signature = Signature::from_slice(signature).map_err(|_| ())?;
+    key.verify(message, &signature).map_err(|_| ())
+}
+
+/// Certificate Registry
+/// Authorities trusted to admit nodes and the revocations they have
+/// published. Serials are scoped to their issuer.
+#[derive(Debug, Clone)]
+pub struct CertificateRegistry {
+    network_id: u64,
+    authorities: BTreeSet<[u8; 32]>,
+    revoked: BTreeMap<([u8; 32], u64), CertificateRevocation>,
+}
+
+impl CertificateRegistry {
+    pub fn new(network_id: u64, authorities: impl IntoIterator<Item = [u8; 32]>) -> Self {
+        Self {
+            network_id,
+            authorities: authorities.into_iter().collect(),
+            revoked: BTreeMap::new(),
+        }
+    }
+
+    pub fn network_id(&self) -> u64 {
+        self.network_id
+    }
+
+    pub fn authorities(&self) -> impl Iterator<Item = &[u8; 32]> {
+        self.authorities.iter()
+    }
+
+    pub fn is_revoked(&self, issuer: &[u8; 32], serial: u64) -> bool {
+        self.revoked.contains_key(&(*issuer, serial))
+    }
+
+    /// Check that a certificate comes from a trusted authority, is valid at
+    /// `now` and has not been revoked
+    pub fn verify(&self, certificate: &NodeCertificate, now: u64) -> Result<(), &'static str> {
+        if !self.authorities.contains(&certificate.issuer) {
+            return Err("Certificate issuer is not a trusted authority");
+        }
+        verify_signature(&certificate.issuer, &certificate.signing_bytes(self.network_id), &certificate.signature)
+            .map_err(|_| "Invalid certificate signature")?;
+        if now < certificate.not_before || now > certificate.not_after {
+            return Err("Certificate is not valid at this time");
+        }
+        if self.is_revoked(&certificate.issuer, certificate.serial) {
+            return Err("Certificate has been revoked");
+        }
+        Ok(())
+    }
+
+    /// Check a peer's handshake proof and its certificate
+    pub fn verify_handshake(&self, auth: &HandshakeAuth, now: u64) -> Result<(), &'static str> {
+        if auth.timestamp.abs_diff(now) > HANDSHAKE_MAX_SKEW_SECS {
+            return Err("Handshake timestamp is too far from local time");
+        }
+        self.verify(&auth.certificate, now)?;
+        verify_signature(&auth.certificate.node_key, &auth.signing_bytes(self.network_id), &auth.signature)
+            .map_err(|_| "Invalid handshake signature")
+    }
+
+    /// Record a revocation signed by a trusted authority. Returns false if it
+    /// was already known.
+    pub fn revoke(&mut self, revocation: CertificateRevocation) -> Result<bool, &'static str> {
+        if !self.authorities.contains(&revocation.issuer) {
+            return Err("Revocation issuer is not a trusted authority");
+        }
+        verify_signature(&revocation.issuer, &revocation.signing_bytes(self.network_id), &revocation.signature)
+            .map_err(|_| "Invalid revocation signature")?;
+        let key = (revocation.issuer, revocation.serial);
+        if self.revoked.contains_key(&key) {
+            return Ok(false);
+        }
+        self.revoked.insert(key, revocation);
+        Ok(true)
+    }
+
+    /// Record gossiped revocations, skipping invalid ones. Returns the ones
+    /// not seen before, which should be passed on.
+    pub fn merge(&mut self, revocations: Vec<CertificateRevocation>) -> Vec<CertificateRevocation> {
+        revocations.into_iter()
+            .filter(|revocation| self.revoke(revocation.clone()) == Ok(true))
+            .collect()
+    }
+
+    pub fn revocations(&self) -> Vec<CertificateRevocation> {
+        self.revoked.values().cloned().collect()
+    }
+
+    /// Apply a governance action adding or removing an authority. Returns
+    /// whether the action was one of ours.
+    pub fn apply_governance(&mut self, action: &Action) -> Result<bool, &'static str> {
+        let Action::Custom(name, payload) = action else { return Ok(false) };
+        let add = match name.as_str() {
+            ADD_AUTHORITY_ACTION => true,
+            REMOVE_AUTHORITY_ACTION => false,
+            _ => return Ok(false),
+        };
+        let key: [u8; 32] = payload.as_slice().try_into().map_err(|_| "Authority key must be 32 bytes")?;
+        if add {
+            VerifyingKey::from_bytes(&key).map_err(|_| "Invalid authority key")?;
+            self.authorities.insert(key);
+        } else {
+            self.authorities.remove(&key);
+        }
+        Ok(true)
+    }
+}
+
+/// What a node in a permissioned network needs: the registry peers are
+/// checked against, and its own certificate and key for its handshakes
+pub struct Permissions {
+    pub registry: RwLock<CertificateRegistry>,
+    certificate: NodeCertificate,
+    node_key: SigningKey,
+    clock: SharedClock,
+}
+
+impl Permissions {
+    /// Fails if the certificate was not issued for `node_key` or is not
+    /// currently valid
+    pub fn new(
+        registry: CertificateRegistry,
+        certificate: NodeCertificate,
+        node_key: SigningKey,
+        clock: SharedClock,
+    ) -> Result<Self, &'static str> {
+        if certificate.node_key != node_key.verifying_key().to_bytes() {
+            return Err("Node certificate was issued for a different key");
+        }
+        registry.verify(&certificate, clock.now_secs())?;
+        Ok(Self { registry: RwLock::new(registry), certificate, node_key, clock })
+    }
+
+    pub fn certificate(&self) -> &NodeCertificate {
+        &self.certificate
+    }
+
+    pub fn now(&self) -> u64 {
+        self.clock.now_secs()
+    }
+
+    /// Proof of this node's certificate for an outgoing handshake
+    pub async fn sign_handshake(&self) -> HandshakeAuth {
+        let network_id = self.registry.read().await.network_id();
+        HandshakeAuth::sign(&self.node_key, self.certificate.clone(), self.now(), network_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_certificate_lifecycle() {
+        let authority = SigningKey::from_bytes(&[1u8; 32]);
+        let node = SigningKey::from_bytes(&[2u8; 32]);
+        let mut registry = CertificateRegistry::new(1, [authority.verifying_key().to_bytes()]);
+
+        let certificate = NodeCertificate::issue(&authority, node.verifying_key().to_bytes(), 7, 100, 200, 1);
+        assert_eq!(registry.verify(&certificate, 150), Ok(()));
+        assert!(registry.verify(&certificate, 201).is_err());
+        // Signed for another network
+        let foreign = NodeCertificate::issue(&authority, node.verifying_key().to_bytes(), 7, 100, 200, 2);
+        assert_eq!(registry.verify(&foreign, 150), Err("Invalid certificate signature"));
+
+        // Only the certified key can sign the handshake
+        let auth = HandshakeAuth::sign(&node, certificate.clone(), 150, 1);
+        assert_eq!(registry.verify_handshake(&auth, 160), Ok(()));
+        assert!(registry.verify_handshake(&auth, 150 + HANDSHAKE_MAX_SKEW_SECS + 1).is_err());
+        let stolen = HandshakeAuth::sign(&authority, certificate.clone(), 150, 1);
+        assert_eq!(registry.verify_handshake(&stolen, 160), Err("Invalid handshake signature"));
+
+        // Revocations are accepted once, and only from trusted authorities
+        let outsider = SigningKey::from_bytes(&[3u8; 32]);
+        let revocation = CertificateRevocation::issue(&authority, 7, 170, 1);
+        let forged = CertificateRevocation::issue(&outsider, 7, 170, 1);
+        assert_eq!(registry.merge(vec![forged, revocation.clone()]), vec![revocation.clone()]);
+        assert!(registry.merge(vec![revocation]).is_empty());
+        assert_eq!(registry.verify(&certificate, 180), Err("Certificate has been revoked"));
+
+        // Governance can withdraw an authority and everything it issued
+        let other = NodeCertificate::issue(&authority, node.verifying_key().to_bytes(), 8, 100, 200, 1);
+        let remove = Action::Custom(REMOVE_AUTHORITY_ACTION.to_string(), authority.verifying_key().to_bytes().to_vec());
+        assert_eq!(registry.apply_governance(&remove), Ok(true));
+        assert!(registry.verify(&other, 150).is_err());
+        assert_eq!(registry.apply_governance(&Action::Custom("other".to_string(), Vec::new())), Ok(false));
+    }
+}
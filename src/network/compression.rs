@@ -0,0 +1,263 @@
+//! Negotiated compression of large P2P frames.
+//!
+//! Each side lists the codecs it accepts in its handshake, in preference
+//! order, and a connection uses the first codec of the sender's list that
+//! the receiver also accepts. Only frames of at least
+//! `compression.threshold_bytes` are compressed, and only when that makes
+//! them smaller; everything else goes out as before.
+//!
+//! A compressed frame is a binary `MessageKind::Compressed` frame wrapping
+//! either a binary frame or a JSON text message:
+//!   codec u8 | content u8 (0 binary, 1 text) | original_len u32 | body
+//!
+//! The declared length is checked before anything is inflated: it may not
+//! exceed `compression.max_frame_bytes`, nor `compression.max_ratio` times
+//! the compressed body, and the body must inflate to exactly that length,
+//! so a small frame can never make the node allocate a large buffer.
+
+use serde::{Serialize, Deserialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::blockchain::wire::{encode_message, MessageKind};
+use crate::config::CompressionConfig;
+
+const HEADER_LEN: usize = 6;
+const BINARY: u8 = 0;
+const TEXT: u8 = 1;
+
+/// Compression algorithm of a frame body
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[repr(u8)]
+pub enum Codec {
+    Snappy = 1,
+    Zstd = 2,
+}
+
+impl TryFrom<u8> for Codec {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::Snappy),
+            2 => Ok(Self::Zstd),
+            _ => Err("Unknown compression codec"),
+        }
+    }
+}
+
+/// A P2P message before compression or after decompression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Payload {
+    Binary(Vec<u8>),
+    Text(String),
+}
+
+impl Payload {
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Payload::Binary(bytes) => bytes,
+            Payload::Text(text) => text.as_bytes(),
+        }
+    }
+}
+
+/// The codec to send with: the first of ours the peer accepts
+pub fn negotiate(local: &[Codec], remote: &[Codec]) -> Option<Codec> {
+    local.iter().copied().find(|codec| remote.contains(codec))
+}
+
+/// Compression counters
+/// Totals since startup over all peer connections.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompressionMetrics {
+    pub frames_compressed: u64,
+    /// Bytes of the compressed frames before compression
+    pub bytes_before: u64,
+    /// Bytes of the compressed frames on the wire
+    pub bytes_after: u64,
+    pub bytes_saved: u64,
+    pub frames_decompressed: u64,
+    /// Compressed bytes received, and what they inflated to
+    pub bytes_received: u64,
+    pub bytes_inflated: u64,
+    /// Frames refused for a bad header, a bad body or an excessive expansion
+    pub frames_rejected: u64,
+}
+
+/// Counters shared by every connection
+#[derive(Debug, Default)]
+pub struct CompressionStats {
+    frames_compressed: AtomicU64,
+    bytes_before: AtomicU64,
+    bytes_after: AtomicU64,
+    frames_decompressed: AtomicU64,
+    bytes_received: AtomicU64,
+    bytes_inflated: AtomicU64,
+    frames_rejected: AtomicU64,
+}
+
+impl CompressionStats {
+    pub fn metrics(&self) -> CompressionMetrics {
+        let bytes_before = self.bytes_before.load(Ordering::Relaxed);
+        let bytes_after = self.bytes_after.load(Ordering::Relaxed);
+        CompressionMetrics {
+            frames_compressed: self.frames_compressed.load(Ordering::Relaxed),
+            bytes_before,
+            bytes_after,
+            bytes_saved: bytes_before.saturating_sub(bytes_after),
+            frames_decompressed: self.frames_decompressed.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            bytes_inflated: self.bytes_inflated.load(Ordering::Relaxed),
+            frames_rejected: self.frames_rejected.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Compress a message into a `Compressed` frame. `None` when the message
+/// is below the threshold or would not shrink; send it as is then.
+pub fn compress(codec: Codec, payload: &Payload, config: &CompressionConfig, stats: &CompressionStats) -> Option<Vec<u8>> {
+    let original = payload.bytes();
+    if original.len() < config.threshold_bytes || original.len() > u32::MAX as usize {
+        return None;
+    }
+    let body = match codec {
+        Codec::Snappy => snap::raw::Encoder::new().compress_vec(original).ok()?,
+        Codec::Zstd => zstd::bulk::compress(original, config.zstd_level).ok()?,
+    };
+    if body.len() + HEADER_LEN + 1 >= original.len() {
+        return None;
+    }
+    let mut inner = Vec::with_capacity(HEADER_LEN + body.len());
+    inner.push(codec as u8);
+    inner.push(if matches!(payload, Payload::Text(_)) { TEXT } else { BINARY });
+    inner.extend_from_slice(&(original.len() as u32).to_le_bytes());
+    inner.extend_from_slice(&body);
+    let frame = encode_message(MessageKind::Compressed, &inner);
+
+    stats.frames_compressed.fetch_add(1, Ordering::Relaxed);
+    stats.bytes_before.fetch_add(original.len() as u64, Ordering::Relaxed);
+    stats.bytes_after.fetch_add(frame.len() as u64, Ordering::Relaxed);
+    Some(frame)
+}
+
+/// Inflate the payload of a `Compressed` frame, refusing codecs this node
+/// does not accept and declared sizes beyond the configured limits
+pub fn decompress(payload: &[u8], config: &CompressionConfig, stats: &CompressionStats) -> Result<Payload, &'static str> {
+    let result = inflate(payload, config);
+    match &result {
+        Ok(inflated) => {
+            stats.frames_decompressed.fetch_add(1, Ordering::Relaxed);
+            stats.bytes_received.fetch_add(payload.len() as u64 + 1, Ordering::Relaxed);
+            stats.bytes_inflated.fetch_add(inflated.bytes().len() as u64, Ordering::Relaxed);
+        }
+        Err(_) => {
+            stats.frames_rejected.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    result
+}
+
+fn inflate(payload: &[u8], config: &CompressionConfig) -> Result<Payload, &'static str> {
+    if payload.len() < HEADER_LEN {
+        return Err("Compressed frame too short");
+    }
+    let codec = Codec::try_from(payload[0])?;
+    if !config.codecs.contains(&codec) {
+        return Err("Compression codec not accepted");
+    }
+    let content = payload[1];
+    if content != BINARY && content != TEXT {
+        return Err("Unknown compressed content type");
+    }
+    let declared = u32::from_le_bytes(payload[2..HEADER_LEN].try_into().expect("length checked")) as usize;
+    let body = &payload[HEADER_LEN..];
+    if declared > config.max_frame_bytes {
+        return Err("Compressed frame inflates beyond the frame limit");
+    }
+    if declared > body.len().saturating_mul(config.max_ratio as usize) {
+        return Err("Compressed frame exceeds the expansion ratio");
+    }
+
+    let bytes = match codec {
+        Codec::Snappy => {
+            if snap::raw::decompress_len(body).map_err(|_| "Corrupt snappy body")? != declared {
+                return Err("Compressed length mismatch");
+            }
+            let mut out = vec![0u8; declared];
+            snap::raw::Decoder::new().decompress(body, &mut out).map_err(|_| "Corrupt snappy body")?;
+            out
+        }
+        // The capacity bounds the output, so zstd cannot write past `declared`
+        Codec::Zstd => zstd::bulk::decompress(body, declared).map_err(|_| "Corrupt zstd body")?,
+    };
+    if bytes.len() != declared {
+        return Err("Compressed length mismatch");
+    }
+    Ok(if content == TEXT {
+        Payload::Text(String::from_utf8(bytes).map_err(|_| "Compressed text is not UTF-8")?)
+    } else {
+        Payload::Binary(bytes)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::wire::MessageView;
+
+    fn sample() -> Payload {
+        let blocks: Vec<String> = (0..400u64)
+            .map(|i| format!("{{\"index\":{},\"hash\":\"{:016x}\"}}", i, i.wrapping_mul(0x9e37_79b9_7f4a_7c15)))
+            .collect();
+        Payload::Text(format!("{{\"message_type\":\"bodies\",\"payload\":[{}]}}", blocks.join(",")))
+    }
+
+    #[test]
+    fn test_round_trip_and_threshold() {
+        let config = CompressionConfig::default();
+        let stats = CompressionStats::default();
+        assert_eq!(negotiate(&[Codec::Zstd, Codec::Snappy], &[Codec::Snappy]), Some(Codec::Snappy));
+        assert_eq!(negotiate(&[Codec::Zstd], &[]), None);
+
+        for codec in [Codec::Snappy, Codec::Zstd] {
+            let frame = compress(codec, &sample(), &config, &stats).expect("Repetitive payload should compress");
+            let view = MessageView::parse(&frame).unwrap();
+            assert_eq!(view.kind, MessageKind::Compressed);
+            assert_eq!(decompress(view.payload, &config, &stats).unwrap(), sample());
+        }
+
+        // Small messages are left alone
+        let small = Payload::Binary(vec![7u8; config.threshold_bytes - 1]);
+        assert!(compress(Codec::Zstd, &small, &config, &stats).is_none());
+
+        let metrics = stats.metrics();
+        assert_eq!(metrics.frames_compressed, 2);
+        assert_eq!(metrics.frames_decompressed, 2);
+        assert!(metrics.bytes_saved > 0);
+        assert_eq!(metrics.bytes_inflated, 2 * sample().bytes().len() as u64);
+    }
+
+    #[test]
+    fn test_decompression_bombs_are_rejected() {
+        let config = CompressionConfig::default();
+        let stats = CompressionStats::default();
+
+        // A megabyte of zeros packs far tighter than the allowed ratio
+        let bomb = Payload::Binary(vec![0u8; 1 << 20]);
+        let frame = compress(Codec::Zstd, &bomb, &config, &stats).unwrap();
+        assert_eq!(decompress(&frame[1..], &config, &stats), Err("Compressed frame exceeds the expansion ratio"));
+
+        // A body that inflates past its declared length is caught by the bound
+        let mut lying = compress(Codec::Zstd, &sample(), &config, &stats).unwrap();
+        let shorter = (sample().bytes().len() as u32 / 2).to_le_bytes();
+        lying[3..7].copy_from_slice(&shorter);
+        assert!(decompress(&lying[1..], &config, &stats).is_err());
+
+        // Codecs the node no longer accepts are refused
+        let snappy = compress(Codec::Snappy, &sample(), &config, &stats).unwrap();
+        let zstd_only = CompressionConfig { codecs: vec![Codec::Zstd], ..CompressionConfig::default() };
+        assert_eq!(decompress(&snappy[1..], &zstd_only, &stats), Err("Compression codec not accepted"));
+
+        assert_eq!(stats.metrics().frames_rejected, 3);
+    }
+}
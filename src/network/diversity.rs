@@ -0,0 +1,369 @@
+//! Network-diverse peer selection.
+//!
+//! Peers are grouped by the autonomous system their address belongs to,
+//! from the prefixes an operator lists in `diversity.asn_prefixes`, and
+//! otherwise by subnet: /16 for IPv4 and /32 for IPv6. Once a node has
+//! `min_peers` peers it turns away more from a subnet or AS that already
+//! holds its cap. With the peer table full, a peer from a group the table
+//! lacks replaces one from the most crowded group. Bootstrap nodes are
+//! dialed least represented group first. An attacker then needs addresses
+//! in many networks, not many addresses in one, to surround a node.
+
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use crate::config::DiversityConfig;
+use super::p2p::PeerInfo;
+use super::sentry::ip_of;
+
+/// Prefix length IPv4 peers are grouped by without a known AS
+const IPV4_SUBNET_BITS: u8 = 16;
+/// Prefix length IPv6 peers are grouped by without a known AS
+const IPV6_SUBNET_BITS: u8 = 32;
+
+/// Group peers are counted in for diversity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PeerGroup {
+    Asn(u32),
+    /// Network address of the peer's subnet
+    Subnet(IpAddr),
+}
+
+impl fmt::Display for PeerGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeerGroup::Asn(asn) => write!(f, "AS{}", asn),
+            PeerGroup::Subnet(IpAddr::V4(network)) => write!(f, "{}/{}", network, IPV4_SUBNET_BITS),
+            PeerGroup::Subnet(IpAddr::V6(network)) => write!(f, "{}/{}", network, IPV6_SUBNET_BITS),
+        }
+    }
+}
+
+/// Where an address sits on the network
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub subnet: IpAddr,
+    pub asn: Option<u32>,
+    pub country: Option<String>,
+}
+
+impl Location {
+    pub fn group(&self) -> PeerGroup {
+        match self.asn {
+            Some(asn) => PeerGroup::Asn(asn),
+            None => PeerGroup::Subnet(self.subnet),
+        }
+    }
+}
+
+/// What to do with a peer asking to join the table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Admission {
+    Accept,
+    /// Accept after dropping this peer
+    Replace(String),
+    Reject(&'static str),
+}
+
+/// Peers in one group
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GroupCount {
+    pub group: String,
+    pub peers: usize,
+}
+
+/// How spread out the peer table is
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiversityMetrics {
+    pub peers: usize,
+    pub subnets: usize,
+    pub asns: usize,
+    pub countries: usize,
+    /// Peers whose address is not an IP, such as bootstrap hostnames
+    pub unlocated: usize,
+    /// Fraction of located peers in the largest group
+    pub largest_group_share: f64,
+    /// Groups by peer count, largest first
+    pub groups: Vec<GroupCount>,
+    pub target_groups: usize,
+    pub target_countries: usize,
+    pub meets_targets: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Prefix {
+    network: IpAddr,
+    len: u8,
+    asn: u32,
+    country: Option<String>,
+}
+
+/// Peer Diversity
+/// Caps and targets for spreading peers across subnets and autonomous systems.
+#[derive(Debug, Clone)]
+pub struct PeerDiversity {
+    /// Longest prefix first, so the most specific one matches
+    prefixes: Vec<Prefix>,
+    max_per_subnet: usize,
+    max_per_asn: usize,
+    target_groups: usize,
+    target_countries: usize,
+}
+
+impl Default for PeerDiversity {
+    fn default() -> Self {
+        Self::new(&DiversityConfig::default()).expect("default diversity config is valid")
+    }
+}
+
+impl PeerDiversity {
+    pub fn new(config: &DiversityConfig) -> Result<Self, String> {
+        let mut prefixes = config.asn_prefixes.iter()
+            .map(|prefix| {
+                let (address, len) = prefix.network()?;
+                Ok(Prefix { network: mask(address, len), len, asn: prefix.asn, country: prefix.country.clone() })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        prefixes.sort_by_key(|prefix| std::cmp::Reverse(prefix.len));
+        Ok(Self {
+            prefixes,
+            max_per_subnet: config.max_per_subnet.max(1),
+            max_per_asn: config.max_per_asn.max(1),
+            target_groups: config.target_groups,
+            target_countries: config.target_countries,
+        })
+    }
+
+    /// Subnet, and AS and country if a configured prefix covers the address.
+    /// `None` for addresses that are not an IP.
+    pub fn locate(&self, address: &str) -> Option<Location> {
+        let ip = canonical(ip_of(address)?);
+        let subnet = match ip {
+            IpAddr::V4(_) => mask(ip, IPV4_SUBNET_BITS),
+            IpAddr::V6(_) => mask(ip, IPV6_SUBNET_BITS),
+        };
+        let prefix = self.prefixes.iter().find(|prefix| mask(ip, prefix.len) == prefix.network);
+        Some(Location {
+            subnet,
+            asn: prefix.map(|prefix| prefix.asn),
+            country: prefix.and_then(|prefix| prefix.country.clone()),
+        })
+    }
+
+    /// Decide on a peer not yet in `peers`. Below `min_peers` anyone is
+    /// taken; after that a crowded subnet or AS is refused, and a full
+    /// table makes room only for a group it lacks.
+    pub fn admit(&self, address: &str, peers: &HashMap<String, PeerInfo>, min_peers: usize, max_peers: usize) -> Admission {
+        let Some(location) = self.locate(address) else {
+            return if peers.len() < max_peers { Admission::Accept } else { Admission::Reject("Peer limit reached") };
+        };
+        if peers.len() < min_peers.min(max_peers) {
+            return Admission::Accept;
+        }
+
+        let located: Vec<(&PeerInfo, Location)> = peers.values()
+            .filter_map(|peer| self.locate(&peer.address).map(|location| (peer, location)))
+            .collect();
+        let same_subnet = located.iter().filter(|(_, other)| other.subnet == location.subnet).count();
+        let same_asn = location.asn.map_or(0, |asn| located.iter().filter(|(_, other)| other.asn == Some(asn)).count());
+        if same_subnet >= self.max_per_subnet || same_asn >= self.max_per_asn {
+            return Admission::Reject("Too many peers from this network");
+        }
+        if peers.len() < max_peers {
+            return Admission::Accept;
+        }
+
+        // Full: only a group the table lacks may displace a peer, taken from
+        // the most crowded group, slowest first
+        let group = location.group();
+        if located.iter().any(|(_, other)| other.group() == group) {
+            return Admission::Reject("Peer limit reached");
+        }
+        let mut sizes: HashMap<PeerGroup, usize> = HashMap::new();
+        for (_, other) in &located {
+            *sizes.entry(other.group()).or_default() += 1;
+        }
+        let crowded = sizes.iter()
+            .filter(|(_, size)| **size > 1)
+            .max_by(|(a, a_size), (b, b_size)| a_size.cmp(b_size).then(b.cmp(a)))
+            .map(|(group, _)| *group);
+        let Some(crowded) = crowded else {
+            return Admission::Reject("Peer limit reached");
+        };
+        located.iter()
+            .filter(|(_, other)| other.group() == crowded)
+            .max_by(|(a, _), (b, _)| a.latency.cmp(&b.latency).then(a.address.cmp(&b.address)))
+            .map_or(Admission::Reject("Peer limit reached"), |(peer, _)| Admission::Replace(peer.address.clone()))
+    }
+
+    /// Order addresses to dial so groups the table has fewest peers in come
+    /// first. Addresses that are not IPs keep their order after those.
+    pub fn rank(&self, candidates: &[String], peers: &HashMap<String, PeerInfo>) -> Vec<String> {
+        let mut sizes: HashMap<PeerGroup, usize> = HashMap::new();
+        for location in peers.values().filter_map(|peer| self.locate(&peer.address)) {
+            *sizes.entry(location.group()).or_default() += 1;
+        }
+        let mut ranked: Vec<(usize, &String)> = candidates.iter()
+            .map(|address| {
+                let size = self.locate(address).map_or(usize::MAX, |location| sizes.get(&location.group()).copied().unwrap_or(0));
+                (size, address)
+            })
+            .collect();
+        ranked.sort_by_key(|(size, _)| *size);
+        ranked.into_iter().map(|(_, address)| address.clone()).collect()
+    }
+
+    pub fn metrics(&self, peers: &HashMap<String, PeerInfo>) -> DiversityMetrics {
+        let locations: Vec<Location> = peers.values().filter_map(|peer| self.locate(&peer.address)).collect();
+        let mut groups: BTreeMap<PeerGroup, usize> = BTreeMap::new();
+        for location in &locations {
+            *groups.entry(location.group()).or_default() += 1;
+        }
+        let subnets: BTreeSet<IpAddr> = locations.iter().map(|location| location.subnet).collect();
+        let asns: BTreeSet<u32> = locations.iter().filter_map(|location| location.asn).collect();
+        let countries: BTreeSet<&str> = locations.iter().filter_map(|location| location.country.as_deref()).collect();
+        let mut groups: Vec<GroupCount> = groups.into_iter()
+            .map(|(group, peers)| GroupCount { group: group.to_string(), peers })
+            .collect();
+        groups.sort_by_key(|count| std::cmp::Reverse(count.peers));
+        let largest_group_share = match (groups.first(), locations.len()) {
+            (Some(largest), located) if located > 0 => largest.peers as f64 / located as f64,
+            _ => 0.0,
+        };
+        DiversityMetrics {
+            peers: peers.len(),
+            subnets: subnets.len(),
+            asns: asns.len(),
+            countries: countries.len(),
+            unlocated: peers.len() - locations.len(),
+            largest_group_share,
+            meets_targets: groups.len() >= self.target_groups && countries.len() >= self.target_countries,
+            groups,
+            target_groups: self.target_groups,
+            target_countries: self.target_countries,
+        }
+    }
+}
+
+/// IPv4-mapped IPv6 addresses count as the IPv4 address
+fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        IpAddr::V4(_) => ip,
+    }
+}
+
+/// Network address of `ip` under a prefix of `len` bits
+fn mask(ip: IpAddr, len: u8) -> IpAddr {
+    match ip {
+        IpAddr::V4(v4) => {
+            let bits = u32::from(v4) & u32::MAX.checked_shl(32 - len.min(32) as u32).unwrap_or(0);
+            IpAddr::V4(Ipv4Addr::from(bits))
+        }
+        IpAddr::V6(v6) => {
+            let bits = u128::from(v6) & u128::MAX.checked_shl(128 - len.min(128) as u32).unwrap_or(0);
+            IpAddr::V6(Ipv6Addr::from(bits))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AsnPrefix, NodeMode};
+    use std::time::{Duration, SystemTime};
+
+    fn peer(address: &str, latency_ms: u64) -> (String, PeerInfo) {
+        (address.to_string(), PeerInfo {
+            address: address.to_string(),
+            last_seen: SystemTime::now(),
+            latency: Duration::from_millis(latency_ms),
+            quantum_ready: true,
+            protocol_version: 1,
+            node_mode: NodeMode::Archive,
+            best_height: 0,
+            earliest_state: 0,
+            certificate: None,
+            addresses: Vec::new(),
+        })
+    }
+
+    fn diversity() -> PeerDiversity {
+        PeerDiversity::new(&DiversityConfig {
+            max_per_subnet: 2,
+            max_per_asn: 3,
+            target_groups: 3,
+            target_countries: 2,
+            asn_prefixes: vec![
+                AsnPrefix { prefix: "198.51.0.0/16".to_string(), asn: 64500, country: Some("DE".to_string()) },
+                AsnPrefix { prefix: "203.0.0.0/8".to_string(), asn: 64501, country: Some("US".to_string()) },
+                AsnPrefix { prefix: "203.0.113.0/24".to_string(), asn: 64502, country: Some("JP".to_string()) },
+            ],
+        }).unwrap()
+    }
+
+    #[test]
+    fn test_locate() {
+        let diversity = diversity();
+        let location = diversity.locate("203.0.113.9:30303").unwrap();
+        // The most specific prefix wins
+        assert_eq!((location.asn, location.country.as_deref()), (Some(64502), Some("JP")));
+        assert_eq!(location.subnet, "203.0.0.0".parse::<IpAddr>().unwrap());
+        assert_eq!(diversity.locate("203.9.1.1:1").unwrap().group(), PeerGroup::Asn(64501));
+        let unknown = diversity.locate("[::ffff:10.1.2.3]:30303").unwrap();
+        assert_eq!(unknown.group().to_string(), "10.1.0.0/16");
+        assert_eq!(diversity.locate("[2001:db8:1::1]:30303").unwrap().group().to_string(), "2001:db8::/32");
+        assert!(diversity.locate("quantum1.metaverse.io:30303").is_none());
+    }
+
+    #[test]
+    fn test_admission_prefers_new_networks() {
+        let diversity = diversity();
+        let mut peers: HashMap<String, PeerInfo> = HashMap::from([
+            peer("10.1.0.1:1", 10),
+            peer("10.1.0.2:1", 10),
+            peer("198.51.7.1:1", 10),
+        ]);
+
+        // Below min_peers anyone is taken
+        assert_eq!(diversity.admit("10.1.0.3:1", &peers, 5, 5), Admission::Accept);
+        // Once min_peers are connected a full subnet is refused
+        assert_eq!(diversity.admit("10.1.0.3:1", &peers, 3, 5), Admission::Reject("Too many peers from this network"));
+        assert_eq!(diversity.admit("10.2.0.1:1", &peers, 3, 5), Admission::Accept);
+
+        // A full table makes room for a new group from the most crowded one,
+        // dropping its slowest peer
+        peers.extend([peer("10.1.0.9:1", 10), peer("203.1.0.1:1", 10)]);
+        peers.get_mut("10.1.0.2:1").unwrap().latency = Duration::from_millis(90);
+        assert_eq!(diversity.admit("203.0.113.5:1", &peers, 3, 5), Admission::Replace("10.1.0.2:1".to_string()));
+        // but not for a group it already has
+        assert_eq!(diversity.admit("203.2.0.1:1", &peers, 3, 5), Admission::Reject("Peer limit reached"));
+        // Hostnames are only held to the peer limit
+        assert_eq!(diversity.admit("seed.metaverse.io:30303", &peers, 3, 5), Admission::Reject("Peer limit reached"));
+        assert_eq!(diversity.admit("seed.metaverse.io:30303", &peers, 3, 6), Admission::Accept);
+    }
+
+    #[test]
+    fn test_rank_and_metrics() {
+        let diversity = diversity();
+        let peers: HashMap<String, PeerInfo> = HashMap::from([
+            peer("10.1.0.1:1", 10),
+            peer("10.1.0.2:1", 10),
+            peer("198.51.7.1:1", 10),
+            peer("seed.metaverse.io:30303", 10),
+        ]);
+        let candidates: Vec<String> = ["seed2.metaverse.io:1", "10.1.5.5:1", "198.51.1.1:1", "203.0.113.1:1"]
+            .iter().map(|address| address.to_string()).collect();
+        assert_eq!(
+            diversity.rank(&candidates, &peers),
+            vec!["203.0.113.1:1", "198.51.1.1:1", "10.1.5.5:1", "seed2.metaverse.io:1"]
+        );
+
+        let metrics = diversity.metrics(&peers);
+        assert_eq!((metrics.peers, metrics.unlocated, metrics.subnets, metrics.asns, metrics.countries), (4, 1, 2, 1, 1));
+        assert_eq!(metrics.groups[0], GroupCount { group: "10.1.0.0/16".to_string(), peers: 2 });
+        assert!((metrics.largest_group_share - 2.0 / 3.0).abs() < 1e-9);
+        assert!(!metrics.meets_targets);
+    }
+}
@@ -0,0 +1,175 @@
+//! Listening on several addresses, IPv4 and IPv6.
+//!
+//! The P2P and RPC servers each bind every address in their listen list
+//! and accept from all of them at once. An unspecified IPv6 address (`::`)
+//! is bound dual-stack, so it also accepts IPv4, unless an IPv4 address is
+//! listed on the same port; then it is bound IPv6-only so both binds can
+//! succeed. Peers reached over a dual-stack socket are reported with their
+//! plain IPv4 address, not the IPv4-mapped IPv6 form.
+//!
+//! Nodes advertise the addresses they can be reached on in the handshake.
+//! `rank_routes` orders a peer's advertised addresses so a dialer tries a
+//! public address of a family it listens on first.
+
+use std::collections::HashSet;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::{TcpListener, TcpStream};
+
+const LISTEN_BACKLOG: i32 = 1024;
+
+/// Parse a listen or advertised address: an IP, taking `default_port`, or
+/// an `ip:port` with IPv6 in brackets
+pub fn parse_endpoint(entry: &str, default_port: u16) -> Result<SocketAddr, String> {
+    entry.parse::<SocketAddr>()
+        .or_else(|_| entry.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>().map(|ip| SocketAddr::new(ip, default_port)))
+        .map_err(|_| format!("`{}` is not an IP address or ip:port", entry))
+}
+
+/// Listeners
+/// Sockets a server accepts connections on, one per listen address.
+pub struct Listeners {
+    listeners: Vec<TcpListener>,
+}
+
+impl Listeners {
+    /// Bind every address. Must run inside a Tokio runtime.
+    pub fn bind(addrs: &[SocketAddr]) -> io::Result<Self> {
+        if addrs.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "No listen addresses"));
+        }
+        let listeners = addrs.iter()
+            .map(|addr| {
+                let dual_stack = addr.ip() == IpAddr::from(std::net::Ipv6Addr::UNSPECIFIED)
+                    && !addrs.iter().any(|other| other.is_ipv4() && other.port() == addr.port());
+                bind_one(addr, dual_stack)
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        Ok(Self { listeners })
+    }
+
+    /// Bound addresses, with the port the OS picked for port 0
+    pub fn local_addrs(&self) -> Vec<SocketAddr> {
+        self.listeners.iter().filter_map(|listener| listener.local_addr().ok()).collect()
+    }
+
+    /// Next connection on any of the sockets. Cancel-safe, like
+    /// `TcpListener::accept`.
+    pub async fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        let accepts = self.listeners.iter().map(|listener| Box::pin(listener.accept()));
+        let (accepted, _, _) = futures::future::select_all(accepts).await;
+        let (stream, peer) = accepted?;
+        Ok((stream, SocketAddr::new(peer.ip().to_canonical(), peer.port())))
+    }
+}
+
+fn bind_one(addr: &SocketAddr, dual_stack: bool) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(*addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(!dual_stack)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&(*addr).into())
+        .map_err(|e| io::Error::new(e.kind(), format!("Failed to bind {}: {}", addr, e)))?;
+    socket.listen(LISTEN_BACKLOG)?;
+    TcpListener::from_std(socket.into())
+}
+
+/// Addresses to advertise in the handshake: the configured external ones,
+/// or else the listen addresses that name a specific interface
+pub fn advertised(external: &[SocketAddr], listening: &[SocketAddr]) -> Vec<String> {
+    let addrs = if external.is_empty() {
+        listening.iter().filter(|addr| !addr.ip().is_unspecified()).copied().collect()
+    } else {
+        external.to_vec()
+    };
+    addrs.iter().map(SocketAddr::to_string).collect()
+}
+
+/// A peer's advertised addresses in the order to dial them: families this
+/// node listens on first, then public before private or loopback, otherwise
+/// in the peer's order. Entries that are not `ip:port` are dropped.
+pub fn rank_routes(advertised: &[String], listening: &[SocketAddr]) -> Vec<SocketAddr> {
+    let ipv4 = listening.iter().any(|addr| addr.is_ipv4() || addr.ip().is_unspecified());
+    let ipv6 = listening.iter().any(|addr| addr.is_ipv6());
+    let mut seen = HashSet::new();
+    let mut routes: Vec<SocketAddr> = advertised.iter()
+        .filter_map(|entry| entry.parse().ok())
+        .filter(|route| seen.insert(*route))
+        .collect();
+    routes.sort_by_key(|route| {
+        let reachable = if route.is_ipv4() { ipv4 } else { ipv6 };
+        (!reachable, !is_public(&route.ip()))
+    });
+    routes
+}
+
+fn is_public(ip: &IpAddr) -> bool {
+    match ip.to_canonical() {
+        IpAddr::V4(v4) => !(v4.is_private() || v4.is_loopback() || v4.is_link_local() || v4.is_unspecified()),
+        IpAddr::V6(v6) => {
+            let segment = v6.segments()[0];
+            // Unique local fc00::/7 and link-local fe80::/10
+            !(v6.is_loopback() || v6.is_unspecified() || segment & 0xfe00 == 0xfc00 || segment & 0xffc0 == 0xfe80)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn addrs(entries: &[&str]) -> Vec<SocketAddr> {
+        entries.iter().map(|entry| parse_endpoint(entry, 30303).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_parse_endpoint() {
+        assert_eq!(parse_endpoint("10.0.0.1", 30303).unwrap().to_string(), "10.0.0.1:30303");
+        assert_eq!(parse_endpoint("::", 30303).unwrap().to_string(), "[::]:30303");
+        assert_eq!(parse_endpoint("[2001:db8::1]", 1).unwrap().to_string(), "[2001:db8::1]:1");
+        assert_eq!(parse_endpoint("[2001:db8::1]:9000", 1).unwrap().port(), 9000);
+        assert!(parse_endpoint("seed.example:30303", 1).is_err());
+    }
+
+    #[test]
+    fn test_rank_routes() {
+        let peer: Vec<String> = ["192.168.1.5:30303", "[2001:db8::5]:30303", "203.0.113.5:30303", "not an address", "203.0.113.5:30303"]
+            .iter().map(|entry| entry.to_string()).collect();
+        let v4_only = rank_routes(&peer, &addrs(&["0.0.0.0"]));
+        assert_eq!(v4_only, addrs(&["203.0.113.5", "192.168.1.5", "2001:db8::5"]));
+        let v6_only = rank_routes(&peer, &addrs(&["2001:db8::1"]));
+        assert_eq!(v6_only[0], "[2001:db8::5]:30303".parse().unwrap());
+        // A dual-stack listener reaches both families, so the peer's order decides
+        assert_eq!(rank_routes(&peer, &addrs(&["::"])), addrs(&["2001:db8::5", "203.0.113.5", "192.168.1.5"]));
+
+        assert_eq!(advertised(&[], &addrs(&["0.0.0.0", "10.0.0.1"])), vec!["10.0.0.1:30303"]);
+        assert_eq!(advertised(&addrs(&["203.0.113.5"]), &addrs(&["0.0.0.0"])), vec!["203.0.113.5:30303"]);
+    }
+
+    #[tokio::test]
+    async fn test_accepts_on_every_address() {
+        let listeners = Listeners::bind(&addrs(&["127.0.0.1:0", "[::1]:0"])).unwrap();
+        let bound = listeners.local_addrs();
+        assert_eq!(bound.len(), 2);
+        for addr in bound {
+            let mut client = TcpStream::connect(addr).await.unwrap();
+            client.write_all(b"hi").await.unwrap();
+            let (mut stream, peer) = listeners.accept().await.unwrap();
+            assert_eq!(peer.ip(), addr.ip());
+            let mut buf = [0u8; 2];
+            stream.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"hi");
+        }
+
+        // `::` alone also takes IPv4, and reports the peer as IPv4
+        let dual = Listeners::bind(&addrs(&["[::]:0"])).unwrap();
+        let port = dual.local_addrs()[0].port();
+        let _client = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        assert_eq!(dual.accept().await.unwrap().1.ip(), "127.0.0.1".parse::<IpAddr>().unwrap());
+        assert!(Listeners::bind(&[]).is_err());
+    }
+}
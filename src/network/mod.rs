@@ -1,5 +1,14 @@
 pub mod p2p;
+pub mod certs;
 pub mod rpc;
 pub mod quantum_network;
+pub mod qkd;
+pub mod sentry;
+pub mod diversity;
+pub mod listen;
+pub mod compression;
+pub mod seeds;
+pub mod observations;
+pub mod relay;
 
 pub use quantum_network::QuantumNetwork;
@@ -0,0 +1,250 @@
+//! Gossip topic for tally observations.
+//!
+//! Observers report what they see of each reality layer many times a
+//! second, far more often than blocks or votes arrive, so observations get a
+//! topic of their own instead of sharing the general gossip:
+//! - only observers with an active `ProviderKind::Observer` bond are
+//!   admitted; anything else is dropped before its signature is checked;
+//! - each observer may publish `observations.max_per_observer` observations
+//!   a second, enforced by the receiving node before anything is stored;
+//! - admitted observations are not relayed one by one. They are collected
+//!   per layer and every `observations.flush_interval_ms` each layer goes out
+//!   as one `LayerAggregate` holding the latest observation of every
+//!   observer and the bonded stake behind each observed state.
+//!
+//! Aggregates carry the observers' own signatures, so a node merging a
+//! peer's aggregate checks every observation as if it had arrived alone.
+
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, HashMap};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use num_traits::ToPrimitive;
+use crate::blockchain::types::hex_serde;
+use crate::config::ObservationConfig;
+use crate::crypto::domain::{PayloadKind, SigningDomain};
+use crate::economics::providers::{ProviderId, ProviderKind, ProviderRegistry};
+use crate::math::precision::PreciseFloat;
+use super::rpc::RateLimiter;
+
+/// Message type of a single observation from its observer
+pub const OBSERVATION_TOPIC: &str = "tally_observation";
+/// Message type of a forwarded per-layer batch
+pub const AGGREGATE_TOPIC: &str = "tally_aggregate";
+
+/// One observer's view of a reality layer, signed with its provider key
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TallyObservation {
+    pub layer_id: u32,
+    #[serde(with = "hex_serde")]
+    pub observer_id: ProviderId,
+    #[serde(with = "hex_serde")]
+    pub observed_state: Vec<u8>,
+    pub confidence: PreciseFloat,
+    /// Unix time in seconds; an observer's observations must increase
+    pub observed_at: u64,
+    #[serde(with = "hex_serde")]
+    pub signature: Vec<u8>,
+}
+
+impl TallyObservation {
+    pub fn sign(
+        observer: &SigningKey,
+        layer_id: u32,
+        observed_state: Vec<u8>,
+        confidence: PreciseFloat,
+        observed_at: u64,
+        network_id: u64,
+    ) -> Self {
+        let mut observation = Self {
+            layer_id,
+            observer_id: observer.verifying_key().to_bytes(),
+            observed_state,
+            confidence,
+            observed_at,
+            signature: Vec::new(),
+        };
+        observation.signature = observer.sign(&observation.signing_bytes(network_id)).to_bytes().to_vec();
+        observation
+    }
+
+    /// Bytes the observer signs
+    pub fn signing_bytes(&self, network_id: u64) -> Vec<u8> {
+        let mut body = Vec::with_capacity(4 + 32 + 8 + 16 + 1 + self.observed_state.len());
+        body.extend_from_slice(&self.layer_id.to_le_bytes());
+        body.extend_from_slice(&self.observer_id);
+        body.extend_from_slice(&self.observed_at.to_le_bytes());
+        body.extend_from_slice(&self.confidence.value.to_le_bytes());
+        body.push(self.confidence.scale);
+        body.extend_from_slice(&self.observed_state);
+        SigningDomain::main_chain(network_id).payload(PayloadKind::TallyObservation, 0, &body)
+    }
+
+    pub fn verify(&self, network_id: u64) -> Result<(), &'static str> {
+        let key = VerifyingKey::from_bytes(&self.observer_id).map_err(|_| "Invalid observer key")?;
+        let signature = Signature::from_slice(&self.signature).map_err(|_| "Invalid observation signature")?;
+        key.verify(&self.signing_bytes(network_id), &signature)
+            .map_err(|_| "Invalid observation signature")
+    }
+}
+
+/// Bonded stake behind one observed state of a layer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateStake {
+    #[serde(with = "hex_serde")]
+    pub state: Vec<u8>,
+    pub stake: PreciseFloat,
+    pub observers: u32,
+}
+
+/// Layer Aggregate
+/// The latest observation of each admitted observer of one layer, as forwarded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LayerAggregate {
+    pub layer_id: u32,
+    /// Ordered by observer
+    pub observations: Vec<TallyObservation>,
+    /// Heaviest first
+    pub states: Vec<StateStake>,
+}
+
+/// Observation Gossip
+/// Admission, rate limiting and per-layer batching of the observation topic.
+pub struct ObservationGossip {
+    network_id: u64,
+    limiter: RateLimiter,
+    /// Newest `observed_at` admitted per layer and observer
+    latest: HashMap<(u32, ProviderId), u64>,
+    /// Admitted since the last flush, with the observer's bond at admission
+    pending: BTreeMap<u32, BTreeMap<ProviderId, (TallyObservation, PreciseFloat)>>,
+}
+
+impl ObservationGossip {
+    pub fn new(network_id: u64, config: &ObservationConfig) -> Self {
+        Self {
+            network_id,
+            limiter: RateLimiter::new(config.max_per_observer),
+            latest: HashMap::new(),
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// Admit an observation for the next flush. Checks run cheapest first:
+    /// the observer's bond, its signature, that it is newer than the last
+    /// one admitted, and last the observer's rate.
+    pub fn admit(&mut self, observation: TallyObservation, providers: &ProviderRegistry) -> Result<(), &'static str> {
+        let bond = providers.get(&observation.observer_id)
+            .filter(|provider| provider.kind == ProviderKind::Observer && provider.is_active())
+            .map(|provider| provider.bond.clone())
+            .ok_or("Observer has no active observer bond")?;
+        observation.verify(self.network_id)?;
+        let key = (observation.layer_id, observation.observer_id);
+        if self.latest.get(&key).is_some_and(|latest| *latest >= observation.observed_at) {
+            return Err("Observation is not newer than the observer's last");
+        }
+        if !self.limiter.check(&hex::encode(observation.observer_id)) {
+            return Err("Observer exceeded its observation rate");
+        }
+
+        self.latest.insert(key, observation.observed_at);
+        self.pending.entry(observation.layer_id).or_default()
+            .insert(observation.observer_id, (observation, bond));
+        Ok(())
+    }
+
+    /// Admit the observations of a peer's aggregate. Returns how many were
+    /// new to this node; an aggregate with none is not worth relaying.
+    pub fn merge(&mut self, aggregate: LayerAggregate, providers: &ProviderRegistry) -> usize {
+        aggregate.observations.into_iter()
+            .filter(|observation| observation.layer_id == aggregate.layer_id)
+            .filter(|observation| self.admit(observation.clone(), providers).is_ok())
+            .count()
+    }
+
+    /// Layers with observations waiting to be forwarded
+    pub fn pending_layers(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Take everything admitted since the last flush as one aggregate per
+    /// layer, in layer order
+    pub fn flush(&mut self) -> Vec<LayerAggregate> {
+        std::mem::take(&mut self.pending).into_iter()
+            .map(|(layer_id, observations)| {
+                let mut states: BTreeMap<Vec<u8>, StateStake> = BTreeMap::new();
+                for (observation, bond) in observations.values() {
+                    let entry = states.entry(observation.observed_state.clone()).or_insert_with(|| StateStake {
+                        state: observation.observed_state.clone(),
+                        stake: PreciseFloat::new(0, bond.scale),
+                        observers: 0,
+                    });
+                    entry.stake = entry.stake.add(bond);
+                    entry.observers += 1;
+                }
+                let mut states: Vec<StateStake> = states.into_values().collect();
+                states.sort_by(|a, b| stake(&b.stake).total_cmp(&stake(&a.stake)));
+                LayerAggregate {
+                    layer_id,
+                    observations: observations.into_values().map(|(observation, _)| observation).collect(),
+                    states,
+                }
+            })
+            .collect()
+    }
+}
+
+fn stake(value: &PreciseFloat) -> f64 {
+    value.to_f64().unwrap_or(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(n: i128) -> PreciseFloat {
+        PreciseFloat::new(n * 100, 2)
+    }
+
+    #[test]
+    fn test_admission_rate_and_aggregation() {
+        let config = ObservationConfig { max_per_observer: 2, ..ObservationConfig::default() };
+        let mut gossip = ObservationGossip::new(1, &config);
+        let keys: Vec<SigningKey> = (1..=4).map(|seed| SigningKey::from_bytes(&[seed; 32])).collect();
+        let mut providers = ProviderRegistry::new();
+        providers.bond(keys[0].verifying_key().to_bytes(), ProviderKind::Observer, tokens(3_000), 0).unwrap();
+        providers.bond(keys[1].verifying_key().to_bytes(), ProviderKind::Observer, tokens(1_000), 0).unwrap();
+        providers.bond(keys[2].verifying_key().to_bytes(), ProviderKind::Observer, tokens(1_500), 0).unwrap();
+        // Bonded, but for another service
+        providers.bond(keys[3].verifying_key().to_bytes(), ProviderKind::Storage, tokens(5_000), 0).unwrap();
+        let observe = |key: &SigningKey, layer: u32, state: &[u8], at: u64| {
+            TallyObservation::sign(key, layer, state.to_vec(), PreciseFloat::new(90, 2), at, 1)
+        };
+
+        assert_eq!(gossip.admit(observe(&keys[3], 1, b"a", 1), &providers), Err("Observer has no active observer bond"));
+        let mut forged = observe(&keys[0], 1, b"a", 1);
+        forged.observed_state = b"b".to_vec();
+        assert_eq!(gossip.admit(forged, &providers), Err("Invalid observation signature"));
+
+        gossip.admit(observe(&keys[0], 1, b"a", 1), &providers).unwrap();
+        assert!(gossip.admit(observe(&keys[0], 1, b"a", 1), &providers).is_err());
+        gossip.admit(observe(&keys[0], 1, b"b", 2), &providers).unwrap();
+        assert_eq!(gossip.admit(observe(&keys[0], 2, b"a", 3), &providers), Err("Observer exceeded its observation rate"));
+        gossip.admit(observe(&keys[1], 1, b"a", 1), &providers).unwrap();
+        gossip.admit(observe(&keys[2], 2, b"a", 1), &providers).unwrap();
+        assert_eq!(gossip.pending_layers(), 2);
+
+        // Only each observer's latest observation is forwarded
+        let aggregates = gossip.flush();
+        assert_eq!(gossip.pending_layers(), 0);
+        assert_eq!(aggregates.len(), 2);
+        let layer = &aggregates[0];
+        assert_eq!(layer.observations.len(), 2);
+        assert_eq!(layer.states.iter().map(|state| (state.state.clone(), state.observers)).collect::<Vec<_>>(),
+            vec![(b"b".to_vec(), 1), (b"a".to_vec(), 1)]);
+        assert_eq!(layer.states[0].stake, tokens(3_000));
+
+        // A peer that already saw these observations gains nothing from the aggregate
+        let mut peer = ObservationGossip::new(1, &ObservationConfig::default());
+        assert_eq!(peer.merge(aggregates[1].clone(), &providers), 1);
+        assert_eq!(peer.merge(aggregates[1].clone(), &providers), 0);
+    }
+}
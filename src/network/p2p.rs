@@ -8,8 +8,61 @@ pub struct P2PMessage {
 }
 
 use tokio::sync::RwLock;
-use std::collections::HashMap;
-use std::time::{Duration, SystemTime};
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant, SystemTime};
+use crate::config::NodeMode;
+use crate::governance::ai_governance::Action;
+use crate::economics::providers::ProviderRegistry;
+use super::compression::{Codec, CompressionStats};
+use super::certs::{CertificateRevocation, HandshakeAuth, NodeCertificate, Permissions};
+use super::observations::{LayerAggregate, ObservationGossip, TallyObservation};
+use super::diversity::{Admission, DiversityMetrics, PeerDiversity};
+use super::listen;
+use super::relay::{PeerLinks, RelayStats};
+use super::seeds::SeedBook;
+use super::sentry::{ip_of, SentrySet};
+
+/// First message exchanged on a new peer connection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Handshake {
+    pub protocol_version: u32,
+    pub node_mode: NodeMode,
+    pub best_height: u64,
+    /// Lowest height the node still holds full state for
+    pub earliest_state: u64,
+    /// Node certificate, required on permissioned networks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<HandshakeAuth>,
+    /// Addresses the node accepts connections on, as ip:port
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub addresses: Vec<String>,
+    /// Compression codecs the node accepts, most preferred first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub compression: Vec<Codec>,
+    /// Seconds since the node started, weighing it in flux routing
+    #[serde(default)]
+    pub uptime: u64,
+    /// Hash of the node's genesis block
+    #[serde(default)]
+    pub genesis_hash: [u8; 32],
+}
+
+impl Handshake {
+    pub fn new(protocol_version: u32, node_mode: NodeMode, best_height: u64) -> Self {
+        Self {
+            protocol_version,
+            node_mode,
+            best_height,
+            earliest_state: node_mode.earliest_state(best_height),
+            auth: None,
+            addresses: Vec::new(),
+            compression: Vec::new(),
+            uptime: 0,
+            genesis_hash: [0; 32],
+        }
+    }
+}
 
 pub struct PeerInfo {
     pub address: String,
@@ -17,6 +70,13 @@ pub struct PeerInfo {
     pub latency: Duration,
     pub quantum_ready: bool,
     pub protocol_version: u32,
+    pub node_mode: NodeMode,
+    pub best_height: u64,
+    pub earliest_state: u64,
+    /// Certificate the peer joined with, on permissioned networks
+    pub certificate: Option<NodeCertificate>,
+    /// Addresses the peer advertised in its handshake
+    pub addresses: Vec<String>,
 }
 
 pub struct P2PNetwork {
@@ -24,12 +84,44 @@ pub struct P2PNetwork {
     pub peers: RwLock<HashMap<String, PeerInfo>>,
     pub min_peers: usize,
     pub max_peers: usize,
+    /// Peers dialed first when no seed book is set
     pub bootstrap_nodes: Vec<String>,
+    /// DNS seeds and the configured bootstrap peers
+    pub seeds: Option<RwLock<SeedBook>>,
     pub quantum_protocol_version: u32,
+    /// Hash of this node's genesis block; peers on another chain are refused
+    pub genesis_hash: [u8; 32],
+    pub node_mode: NodeMode,
+    /// Set on permissioned networks; peers must present a valid certificate
+    pub permissions: Option<Permissions>,
+    /// Set in a validator/sentry deployment
+    pub sentry: Option<RwLock<SentrySet>>,
+    /// Set when the node takes part in tally observation gossip
+    pub observations: Option<RwLock<ObservationGossip>>,
+    /// Caps per subnet and autonomous system
+    pub diversity: PeerDiversity,
+    /// Peers dropped to make room for a more diverse one, until their
+    /// connection notices
+    evicted: RwLock<HashSet<String>>,
+    /// Addresses the P2P server is bound to
+    pub listen_addrs: Vec<SocketAddr>,
+    /// Addresses advertised to peers instead of the listen addresses
+    pub external_addrs: Vec<SocketAddr>,
+    /// Bytes saved and frames refused by frame compression
+    pub compression: CompressionStats,
+    /// Outbound queues of admitted peer connections, for relay
+    pub links: PeerLinks,
+    /// Transactions received from peers and where they went
+    pub relay: RelayStats,
+    started: Instant,
 }
 
 impl P2PNetwork {
     pub fn new(port: u16) -> Self {
+        Self::with_mode(port, NodeMode::Archive)
+    }
+
+    pub fn with_mode(port: u16, node_mode: NodeMode) -> Self {
         Self {
             port,
             peers: RwLock::new(HashMap::new()),
@@ -40,8 +132,275 @@ impl P2PNetwork {
                 "quantum2.metaverse.io:30303".to_string(),
                 "quantum3.metaverse.io:30303".to_string(),
             ],
+            seeds: None,
             quantum_protocol_version: 1,
+            genesis_hash: [0; 32],
+            node_mode,
+            permissions: None,
+            sentry: None,
+            observations: None,
+            diversity: PeerDiversity::default(),
+            evicted: RwLock::new(HashSet::new()),
+            listen_addrs: Vec::new(),
+            external_addrs: Vec::new(),
+            compression: CompressionStats::default(),
+            links: PeerLinks::default(),
+            relay: RelayStats::default(),
+            started: Instant::now(),
+        }
+    }
+
+    /// Peer only with nodes whose chain starts at the genesis block hashed `genesis_hash`
+    pub fn with_genesis(mut self, genesis_hash: [u8; 32]) -> Self {
+        self.genesis_hash = genesis_hash;
+        self
+    }
+
+    /// Only admit peers holding a certificate from the registry's authorities
+    pub fn with_permissions(mut self, permissions: Permissions) -> Self {
+        self.permissions = Some(permissions);
+        self
+    }
+
+    /// Join a validator/sentry deployment: a validator dials and accepts
+    /// only its sentries, a sentry also dials the validators it guards
+    pub fn with_sentry(mut self, sentry: SentrySet) -> Self {
+        self.sentry = Some(RwLock::new(sentry));
+        self
+    }
+
+    /// Take bootstrap peers from DNS seeds instead of `bootstrap_nodes`
+    pub fn with_seeds(mut self, seeds: SeedBook) -> Self {
+        self.seeds = Some(RwLock::new(seeds));
+        self
+    }
+
+    /// Spread peers across subnets and autonomous systems as configured
+    pub fn with_diversity(mut self, diversity: PeerDiversity) -> Self {
+        self.diversity = diversity;
+        self
+    }
+
+    /// Addresses the node listens on and, if set, the external ones to
+    /// advertise in their place
+    pub fn with_addresses(mut self, listen_addrs: Vec<SocketAddr>, external_addrs: Vec<SocketAddr>) -> Self {
+        self.listen_addrs = listen_addrs;
+        self.external_addrs = external_addrs;
+        self
+    }
+
+    /// Take part in tally observation gossip: admit bonded observers at
+    /// their rate and forward observations in per-layer batches
+    pub fn with_observations(mut self, observations: ObservationGossip) -> Self {
+        self.observations = Some(RwLock::new(observations));
+        self
+    }
+
+    /// Admit a gossiped tally observation for the next batch
+    pub async fn receive_observation(&self, observation: TallyObservation, providers: &ProviderRegistry) -> Result<(), &'static str> {
+        let observations = self.observations.as_ref().ok_or("Observation gossip is not enabled")?;
+        observations.write().await.admit(observation, providers)
+    }
+
+    /// Admit the observations of a peer's batch. Returns how many were new.
+    pub async fn receive_aggregate(&self, aggregate: LayerAggregate, providers: &ProviderRegistry) -> usize {
+        let Some(observations) = &self.observations else { return 0 };
+        observations.write().await.merge(aggregate, providers)
+    }
+
+    /// Batches to forward, one per layer observed since the last flush
+    pub async fn flush_observations(&self) -> Vec<LayerAggregate> {
+        let Some(observations) = &self.observations else { return Vec::new() };
+        observations.write().await.flush()
+    }
+
+    /// Peers to dial when joining, best first. A validator behind sentries
+    /// dials only its sentries.
+    pub async fn bootstrap_peers(&self) -> Vec<String> {
+        let public = match &self.seeds {
+            Some(seeds) => seeds.read().await.bootstrap_peers(),
+            None => self.bootstrap_nodes.clone(),
+        };
+        match &self.sentry {
+            Some(sentry) => sentry.read().await.bootstrap(&public),
+            None => public,
+        }
+    }
+
+    /// Connected peers a block or vote from `from`, or produced here when
+    /// `from` is `None`, goes to next: the private peers the sentry topology
+    /// routes it to, and `fanout` public peers when it is published
+    pub async fn consensus_targets(&self, from: Option<&str>, message_type: &str, fanout: usize) -> Vec<String> {
+        let Some(sentry) = &self.sentry else { return Vec::new() };
+        let sentry = sentry.read().await;
+        let plan = sentry.relay(from, message_type);
+        let (private, public): (Vec<String>, Vec<String>) = self.links.gossip_targets(from.unwrap_or_default(), usize::MAX).await
+            .into_iter()
+            .partition(|address| sentry.is_private(address));
+        let mut targets: Vec<String> = private.into_iter()
+            .filter(|address| ip_of(address).is_some_and(|ip| plan.private.iter().any(|peer| peer.ip() == ip)))
+            .collect();
+        if plan.public {
+            targets.extend(public.into_iter().take(fanout));
+        }
+        targets
+    }
+
+    /// Whether a connection with `address` is allowed by the sentry topology
+    pub async fn sentry_admits(&self, address: &str) -> bool {
+        match &self.sentry {
+            Some(sentry) => sentry.read().await.admits(address),
+            None => true,
+        }
+    }
+
+    /// Handshake advertising this node's protocol version, history mode,
+    /// addresses and uptime, and its certificate on a permissioned network
+    pub async fn local_handshake(&self, best_height: u64) -> Handshake {
+        let mut handshake = Handshake::new(self.quantum_protocol_version, self.node_mode, best_height);
+        handshake.addresses = listen::advertised(&self.external_addrs, &self.listen_addrs);
+        handshake.uptime = self.started.elapsed().as_secs();
+        handshake.genesis_hash = self.genesis_hash;
+        if let Some(permissions) = &self.permissions {
+            handshake.auth = Some(permissions.sign_handshake().await);
+        }
+        handshake
+    }
+
+    /// Whether messages from `address` may be processed: always on an open
+    /// network; on a permissioned one, only after an accepted handshake and
+    /// until the peer's certificate expires or is revoked
+    pub async fn is_admitted(&self, address: &str) -> bool {
+        let Some(permissions) = &self.permissions else { return true };
+        let now = permissions.now();
+        self.peers.read().await.get(address)
+            .and_then(|peer| peer.certificate.as_ref())
+            .is_some_and(|certificate| now <= certificate.not_after)
+    }
+
+    /// Check that a peer's handshake is for this node's protocol and chain
+    pub fn check_chain(&self, handshake: &Handshake) -> Result<(), &'static str> {
+        if handshake.protocol_version != self.quantum_protocol_version {
+            return Err("Incompatible protocol version");
+        }
+        if handshake.genesis_hash != self.genesis_hash {
+            return Err("Peer has a different genesis block");
+        }
+        Ok(())
+    }
+
+    /// Record a peer from its handshake
+    pub async fn register_peer(&self, address: &str, handshake: &Handshake, latency: Duration) -> Result<(), &'static str> {
+        self.check_chain(handshake)?;
+        if !self.sentry_admits(address).await {
+            return Err("Validator peers only with its sentries");
+        }
+        let certificate = match &self.permissions {
+            Some(permissions) => {
+                let auth = handshake.auth.as_ref().ok_or("Peer did not present a node certificate")?;
+                permissions.registry.read().await.verify_handshake(auth, permissions.now())?;
+                Some(auth.certificate.clone())
+            }
+            None => None,
+        };
+
+        let mut peers = self.peers.write().await;
+        if !peers.contains_key(address) {
+            match self.diversity.admit(address, &peers, self.min_peers, self.max_peers) {
+                Admission::Accept => {}
+                Admission::Replace(evicted) => {
+                    peers.remove(&evicted);
+                    self.evicted.write().await.insert(evicted);
+                }
+                Admission::Reject(reason) => return Err(reason),
+            }
+        }
+        self.evicted.write().await.remove(address);
+
+        peers.insert(address.to_string(), PeerInfo {
+            address: address.to_string(),
+            last_seen: SystemTime::now(),
+            latency,
+            quantum_ready: true,
+            protocol_version: handshake.protocol_version,
+            node_mode: handshake.node_mode,
+            best_height: handshake.best_height,
+            earliest_state: handshake.earliest_state,
+            certificate,
+            addresses: handshake.addresses.clone(),
+        });
+        Ok(())
+    }
+
+    /// Addresses to reach a peer on, best route first: the ones it
+    /// advertised, ranked against the families this node listens on
+    pub async fn routes(&self, address: &str) -> Vec<SocketAddr> {
+        let peers = self.peers.read().await;
+        let Some(peer) = peers.get(address) else { return Vec::new() };
+        listen::rank_routes(&peer.addresses, &self.listen_addrs)
+    }
+
+    /// Whether `address` was dropped to make room for a more diverse peer.
+    /// Its connection should close; the mark is cleared once read.
+    pub async fn take_eviction(&self, address: &str) -> bool {
+        self.evicted.write().await.remove(address)
+    }
+
+    /// How spread out the current peers are across networks
+    pub async fn diversity_metrics(&self) -> DiversityMetrics {
+        self.diversity.metrics(&*self.peers.read().await)
+    }
+
+    /// Record gossiped certificate revocations and drop the peers they
+    /// affect. Returns the revocations not seen before, to relay onward.
+    pub async fn apply_revocations(&self, revocations: Vec<CertificateRevocation>) -> Vec<CertificateRevocation> {
+        let Some(permissions) = &self.permissions else { return Vec::new() };
+        let fresh = permissions.registry.write().await.merge(revocations);
+        if !fresh.is_empty() {
+            self.enforce_permissions().await;
+        }
+        fresh
+    }
+
+    /// Apply a governance change to the certificate authorities. Returns
+    /// whether the action was one of the registry's.
+    pub async fn apply_governance(&self, action: &Action) -> Result<bool, &'static str> {
+        let Some(permissions) = &self.permissions else { return Ok(false) };
+        let applied = permissions.registry.write().await.apply_governance(action)?;
+        if applied {
+            self.enforce_permissions().await;
+        }
+        Ok(applied)
+    }
+
+    /// Drop peers whose certificate is no longer valid: revoked, expired, or
+    /// issued by an authority that lost its trust. Returns their addresses.
+    pub async fn enforce_permissions(&self) -> Vec<String> {
+        let Some(permissions) = &self.permissions else { return Vec::new() };
+        let registry = permissions.registry.read().await;
+        let now = permissions.now();
+        let mut peers = self.peers.write().await;
+        let dropped: Vec<String> = peers.values()
+            .filter(|peer| peer.certificate.as_ref().is_none_or(|certificate| registry.verify(certificate, now).is_err()))
+            .map(|peer| peer.address.clone())
+            .collect();
+        for address in &dropped {
+            peers.remove(address);
         }
+        dropped
+    }
+
+    /// Pick up to `count` peers able to serve state from `from_height`.
+    /// Archive peers are preferred, then lower latency.
+    pub async fn select_sync_peers(&self, from_height: u64, count: usize) -> Vec<String> {
+        let peers = self.peers.read().await;
+        let mut candidates: Vec<&PeerInfo> = peers
+            .values()
+            .filter(|peer| peer.earliest_state <= from_height && peer.best_height > from_height)
+            .collect();
+
+        candidates.sort_by_key(|peer| (peer.node_mode != NodeMode::Archive, peer.latency));
+        candidates.into_iter().take(count).map(|peer| peer.address.clone()).collect()
     }
 
     pub async fn start(&self) -> Result<(), Box<dyn std::error::Error>> {
@@ -58,7 +417,9 @@ impl P2PNetwork {
     }
 
     async fn discover_peers(&self) -> Result<(), Box<dyn std::error::Error>> {
-        for node in &self.bootstrap_nodes {
+        let bootstrap = self.bootstrap_peers().await;
+        let candidates = self.diversity.rank(&bootstrap, &*self.peers.read().await);
+        for node in &candidates {
             if let Ok(peer_info) = self.connect_to_peer(node).await {
                 self.peers.write().await.insert(node.clone(), peer_info);
             }
@@ -74,6 +435,11 @@ impl P2PNetwork {
             latency: Duration::from_millis(100),
             quantum_ready: true,
             protocol_version: self.quantum_protocol_version,
+            node_mode: NodeMode::Archive,
+            best_height: 0,
+            earliest_state: 0,
+            certificate: None,
+            addresses: Vec::new(),
         })
     }
 
@@ -103,3 +469,113 @@ impl P2PNetwork {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{SentryConfig, SentryRole};
+
+    #[tokio::test]
+    async fn test_sync_peer_selection() {
+        let network = P2PNetwork::new(30303);
+        let latency = Duration::from_millis(50);
+
+        let pruned = Handshake::new(1, NodeMode::Pruned { retain_blocks: 100 }, 1000);
+        let archive = Handshake::new(1, NodeMode::Archive, 950);
+        network.register_peer("pruned", &pruned, Duration::from_millis(5)).await.unwrap();
+        network.register_peer("archive", &archive, latency).await.unwrap();
+
+        // Only the archive peer can serve old state
+        assert_eq!(network.select_sync_peers(10, 5).await, vec!["archive"]);
+        // Both can serve recent state; archive is preferred
+        assert_eq!(network.select_sync_peers(920, 5).await, vec!["archive", "pruned"]);
+        // Only the pruned peer is ahead of height 950
+        assert_eq!(network.select_sync_peers(950, 5).await, vec!["pruned"]);
+
+        let incompatible = Handshake::new(2, NodeMode::Archive, 10);
+        assert!(network.register_peer("old", &incompatible, latency).await.is_err());
+        let mut forked = Handshake::new(1, NodeMode::Archive, 10);
+        forked.genesis_hash = [1; 32];
+        assert_eq!(network.register_peer("forked", &forked, latency).await, Err("Peer has a different genesis block"));
+    }
+
+    #[tokio::test]
+    async fn test_advertised_routes() {
+        let listen = vec!["0.0.0.0:30303".parse().unwrap(), "10.0.0.1:30304".parse().unwrap()];
+        let network = P2PNetwork::new(30303).with_addresses(listen, Vec::new());
+        assert_eq!(network.local_handshake(0).await.addresses, vec!["10.0.0.1:30304"]);
+
+        let mut handshake = Handshake::new(1, NodeMode::Archive, 0);
+        handshake.addresses = vec!["[2001:db8::5]:30303".to_string(), "203.0.113.5:30303".to_string()];
+        network.register_peer("peer", &handshake, Duration::from_millis(5)).await.unwrap();
+        // This node only listens on IPv4
+        let routes: Vec<String> = network.routes("peer").await.iter().map(SocketAddr::to_string).collect();
+        assert_eq!(routes, vec!["203.0.113.5:30303", "[2001:db8::5]:30303"]);
+        assert!(network.routes("unknown").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_permissioned_admission() {
+        use crate::network::certs::CertificateRegistry;
+        use ed25519_dalek::SigningKey;
+
+        let authority = SigningKey::from_bytes(&[1u8; 32]);
+        let registry = || CertificateRegistry::new(1, [authority.verifying_key().to_bytes()]);
+        let certified = |seed: u8, serial: u64| {
+            let key = SigningKey::from_bytes(&[seed; 32]);
+            let certificate = NodeCertificate::issue(&authority, key.verifying_key().to_bytes(), serial, 0, 2_000, 1);
+            (key, certificate)
+        };
+        let clock = crate::clock::MockClock::new(1_000);
+        let (local_key, local_cert) = certified(2, 1);
+        let network = P2PNetwork::new(30303)
+            .with_permissions(Permissions::new(registry(), local_cert, local_key, clock.clone()).unwrap());
+
+        let (peer_key, peer_cert) = certified(3, 2);
+        let peer = P2PNetwork::new(30304)
+            .with_permissions(Permissions::new(registry(), peer_cert, peer_key, clock).unwrap());
+        let latency = Duration::from_millis(5);
+
+        // Uncertified peers are turned away before they are admitted
+        assert!(network.register_peer("open", &Handshake::new(1, NodeMode::Archive, 0), latency).await.is_err());
+        assert!(!network.is_admitted("open").await);
+
+        network.register_peer("peer", &peer.local_handshake(10).await, latency).await.unwrap();
+        assert!(network.is_admitted("peer").await);
+
+        // A gossiped revocation drops the peer and is relayed once
+        let revocation = CertificateRevocation::issue(&authority, 2, 1_000, 1);
+        assert_eq!(network.apply_revocations(vec![revocation.clone()]).await.len(), 1);
+        assert!(!network.is_admitted("peer").await);
+        assert!(network.apply_revocations(vec![revocation]).await.is_empty());
+        assert!(network.register_peer("peer", &peer.local_handshake(10).await, latency).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_consensus_messages_reach_sentry_targets() {
+        let sentry = SentrySet::new(&SentryConfig {
+            role: SentryRole::Sentry,
+            private_peers: vec!["10.0.0.1:30303".to_string()],
+            health_interval_secs: 10,
+            max_missed_checks: 2,
+        }).unwrap();
+        let network = P2PNetwork::new(30303).with_sentry(sentry);
+        let mut validator = network.links.open("10.0.0.1:45000", 0).await;
+        let mut public = network.links.open("203.0.113.9:30303", 0).await;
+
+        // A block from the public network goes to the validator only
+        let targets = network.consensus_targets(Some("203.0.113.9:30303"), "block", 8).await;
+        assert_eq!(targets, vec!["10.0.0.1:45000"]);
+        assert_eq!(network.links.send(&targets, "block").await, 1);
+        assert_eq!(validator.recv().await.as_deref(), Some("block"));
+
+        // The validator's vote is published, but not back to the validator
+        let targets = network.consensus_targets(Some("10.0.0.1:45000"), "vote", 8).await;
+        assert_eq!(targets, vec!["203.0.113.9:30303"]);
+        network.links.send(&targets, "vote").await;
+        assert_eq!(public.recv().await.as_deref(), Some("vote"));
+        assert!(validator.try_recv().is_err());
+
+        assert!(network.consensus_targets(None, "transaction", 8).await.is_empty());
+    }
+}
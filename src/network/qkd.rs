@@ -0,0 +1,272 @@
+use crate::blockchain::types::hex_serde;
+use serde::{Serialize, Deserialize};
+use std::collections::{btree_map::Entry, BTreeMap, HashMap, HashSet};
+use std::fmt::Debug;
+use std::sync::Arc;
+
+type NodeId = [u8; 32];
+
+/// Key bytes consumed per message: a one-time MAC key and a one-time pad seed
+pub const KEY_BYTES_PER_MESSAGE: usize = 64;
+/// Key material agreed per link and epoch by default
+pub const DEFAULT_POOL_BYTES: usize = 64 * 1024;
+/// BB84 aborts above this quantum bit error rate; beyond it an eavesdropper
+/// may hold enough of the raw key to defeat privacy amplification
+pub const MAX_QBER: f64 = 0.11;
+
+/// Source of shared secrets for a directed link, such as a QKD device or an
+/// out-of-band key exchange. Both ends must derive the same bytes for the
+/// same `(from, to, epoch)`.
+pub trait KeyAgreement: Send + Sync + Debug {
+    fn agree(&self, from: &NodeId, to: &NodeId, epoch: u64, len: usize) -> Result<Vec<u8>, &'static str>;
+}
+
+/// Simulated QKD: both ends derive key material from a shared seed.
+/// A quantum bit error rate can be set to model eavesdropping.
+#[derive(Debug, Clone)]
+pub struct SimulatedQkd {
+    seed: [u8; 32],
+    qber: f64,
+}
+
+impl SimulatedQkd {
+    pub fn new(seed: [u8; 32]) -> Self {
+        Self { seed, qber: 0.0 }
+    }
+
+    /// Error rate observed when comparing a sample of the sifted key
+    pub fn with_qber(mut self, qber: f64) -> Self {
+        self.qber = qber;
+        self
+    }
+}
+
+impl KeyAgreement for SimulatedQkd {
+    fn agree(&self, from: &NodeId, to: &NodeId, epoch: u64, len: usize) -> Result<Vec<u8>, &'static str> {
+        if self.qber > MAX_QBER {
+            return Err("QBER above threshold; key agreement aborted");
+        }
+        let mut hasher = blake3::Hasher::new_keyed(&self.seed);
+        hasher.update(b"qmv:qkd-sim:");
+        hasher.update(from);
+        hasher.update(to);
+        hasher.update(&epoch.to_le_bytes());
+        let mut key = vec![0u8; len];
+        hasher.finalize_xof().fill(&mut key);
+        Ok(key)
+    }
+}
+
+/// Message encrypted and authenticated with one-time key material
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SealedMessage {
+    #[serde(with = "hex_serde")]
+    pub from: NodeId,
+    #[serde(with = "hex_serde")]
+    pub to: NodeId,
+    pub epoch: u64,
+    /// Offset of the message's key bytes in the epoch's key material
+    pub offset: u64,
+    #[serde(with = "hex_serde")]
+    pub ciphertext: Vec<u8>,
+    #[serde(with = "hex_serde")]
+    pub tag: [u8; 32],
+}
+
+/// Key consumption on one directed link
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct KeyUsage {
+    pub epoch: u64,
+    pub messages: u64,
+    pub consumed_bytes: u64,
+    /// Key bytes left in the current epoch
+    pub remaining_bytes: usize,
+    pub renewals: u64,
+}
+
+struct OutboundKeys {
+    material: Vec<u8>,
+    used: usize,
+    usage: KeyUsage,
+}
+
+#[derive(Default)]
+struct InboundKeys {
+    /// Material and offsets already opened, per epoch
+    epochs: BTreeMap<u64, (Vec<u8>, HashSet<u64>)>,
+}
+
+/// QKD Channels
+/// Per-link key pools drawn from a `KeyAgreement` backend. Every message
+/// consumes fresh key bytes, and a link renews its key (a new epoch) once
+/// the current one is used up.
+pub struct QkdChannels {
+    backend: Arc<dyn KeyAgreement>,
+    pool_bytes: usize,
+    /// Epoch a link's first key is agreed for
+    first_epoch: u64,
+    outbound: HashMap<(NodeId, NodeId), OutboundKeys>,
+    inbound: HashMap<(NodeId, NodeId), InboundKeys>,
+}
+
+impl QkdChannels {
+    pub fn new(backend: Arc<dyn KeyAgreement>) -> Self {
+        Self {
+            backend,
+            pool_bytes: DEFAULT_POOL_BYTES,
+            first_epoch: 0,
+            outbound: HashMap::new(),
+            inbound: HashMap::new(),
+        }
+    }
+
+    /// Key bytes agreed per epoch; at least one message's worth
+    pub fn with_pool_bytes(mut self, pool_bytes: usize) -> Self {
+        self.pool_bytes = pool_bytes.max(KEY_BYTES_PER_MESSAGE);
+        self
+    }
+
+    /// Start outbound links at `epoch` instead of 0. A node that restarts
+    /// from a later epoch, such as one taken from the clock, does not seal
+    /// with key material it spent before the restart, which its peers
+    /// would refuse.
+    pub fn with_first_epoch(mut self, epoch: u64) -> Self {
+        self.first_epoch = epoch;
+        self
+    }
+
+    /// Encrypt and MAC `message` for the link `from -> to`
+    pub fn seal(&mut self, from: NodeId, to: NodeId, message: &[u8]) -> Result<SealedMessage, &'static str> {
+        let needs_renewal = self.outbound.get(&(from, to))
+            .is_none_or(|keys| keys.material.len() - keys.used < KEY_BYTES_PER_MESSAGE);
+        if needs_renewal {
+            self.renew(from, to)?;
+        }
+
+        let keys = self.outbound.get_mut(&(from, to)).expect("renewed above");
+        let offset = keys.used;
+        let key = &keys.material[offset..offset + KEY_BYTES_PER_MESSAGE];
+        let epoch = keys.usage.epoch;
+        let ciphertext = apply_pad(&key[32..], message);
+        let tag = mac(&key[..32], epoch, offset as u64, &ciphertext);
+
+        keys.used += KEY_BYTES_PER_MESSAGE;
+        keys.usage.messages += 1;
+        keys.usage.consumed_bytes += KEY_BYTES_PER_MESSAGE as u64;
+        keys.usage.remaining_bytes = keys.material.len() - keys.used;
+
+        Ok(SealedMessage { from, to, epoch, offset: offset as u64, ciphertext, tag })
+    }
+
+    /// Verify and decrypt a message; each key offset opens at most once
+    pub fn open(&mut self, sealed: &SealedMessage) -> Result<Vec<u8>, &'static str> {
+        let link = (sealed.from, sealed.to);
+        let offset = usize::try_from(sealed.offset).map_err(|_| "Invalid key offset")?;
+        if offset % KEY_BYTES_PER_MESSAGE != 0 || offset + KEY_BYTES_PER_MESSAGE > self.pool_bytes {
+            return Err("Invalid key offset");
+        }
+
+        let inbound = self.inbound.entry(link).or_default();
+        if let Entry::Vacant(epoch) = inbound.epochs.entry(sealed.epoch) {
+            let material = self.backend.agree(&sealed.from, &sealed.to, sealed.epoch, self.pool_bytes)?;
+            epoch.insert((material, HashSet::new()));
+            // Messages from epochs before the previous one are no longer accepted
+            while inbound.epochs.len() > 2 {
+                inbound.epochs.pop_first();
+            }
+        }
+        let (material, opened) = inbound.epochs.get_mut(&sealed.epoch).ok_or("Key epoch expired")?;
+        if opened.contains(&sealed.offset) {
+            return Err("Key material already used");
+        }
+
+        let key = &material[offset..offset + KEY_BYTES_PER_MESSAGE];
+        if mac(&key[..32], sealed.epoch, sealed.offset, &sealed.ciphertext) != sealed.tag {
+            return Err("Invalid message authentication code");
+        }
+        opened.insert(sealed.offset);
+        Ok(apply_pad(&key[32..], &sealed.ciphertext))
+    }
+
+    /// Agree a fresh key for the link, starting a new epoch
+    pub fn renew(&mut self, from: NodeId, to: NodeId) -> Result<(), &'static str> {
+        let (epoch, mut usage) = match self.outbound.get(&(from, to)) {
+            Some(keys) => (keys.usage.epoch + 1, keys.usage.clone()),
+            None => (self.first_epoch, KeyUsage::default()),
+        };
+        let material = self.backend.agree(&from, &to, epoch, self.pool_bytes)?;
+        if self.outbound.contains_key(&(from, to)) {
+            usage.renewals += 1;
+        }
+        usage.epoch = epoch;
+        usage.remaining_bytes = material.len();
+        self.outbound.insert((from, to), OutboundKeys { material, used: 0, usage });
+        Ok(())
+    }
+
+    pub fn usage(&self, from: &NodeId, to: &NodeId) -> Option<&KeyUsage> {
+        self.outbound.get(&(*from, *to)).map(|keys| &keys.usage)
+    }
+
+    /// Drop all key material shared with `node`
+    pub fn forget(&mut self, node: &NodeId) {
+        self.outbound.retain(|(from, to), _| from != node && to != node);
+        self.inbound.retain(|(from, to), _| from != node && to != node);
+    }
+}
+
+/// XOR `data` with a keystream expanded from a one-time 32-byte seed
+fn apply_pad(seed: &[u8], data: &[u8]) -> Vec<u8> {
+    let seed: [u8; 32] = seed.try_into().expect("pad seeds are 32 bytes");
+    let mut pad = vec![0u8; data.len()];
+    blake3::Hasher::new_keyed(&seed).finalize_xof().fill(&mut pad);
+    data.iter().zip(pad).map(|(byte, pad)| byte ^ pad).collect()
+}
+
+fn mac(key: &[u8], epoch: u64, offset: u64, ciphertext: &[u8]) -> [u8; 32] {
+    let key: [u8; 32] = key.try_into().expect("MAC keys are 32 bytes");
+    let mut hasher = blake3::Hasher::new_keyed(&key);
+    hasher.update(&epoch.to_le_bytes());
+    hasher.update(&offset.to_le_bytes());
+    hasher.update(ciphertext);
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_open_consumption_and_renewal() {
+        let backend = Arc::new(SimulatedQkd::new([7u8; 32]));
+        let mut sender = QkdChannels::new(backend.clone()).with_pool_bytes(2 * KEY_BYTES_PER_MESSAGE);
+        let mut receiver = QkdChannels::new(backend).with_pool_bytes(2 * KEY_BYTES_PER_MESSAGE);
+        let (a, b) = ([1u8; 32], [2u8; 32]);
+
+        let first = sender.seal(a, b, b"block 1").unwrap();
+        assert_ne!(first.ciphertext, b"block 1".to_vec());
+        assert_eq!(receiver.open(&first).unwrap(), b"block 1".to_vec());
+        assert_eq!(receiver.open(&first).unwrap_err(), "Key material already used");
+
+        let mut tampered = sender.seal(a, b, b"block 2").unwrap();
+        tampered.ciphertext[0] ^= 1;
+        assert_eq!(receiver.open(&tampered).unwrap_err(), "Invalid message authentication code");
+
+        // The pool held two messages, so the third renews the key
+        let third = sender.seal(a, b, b"block 3").unwrap();
+        assert_eq!((third.epoch, third.offset), (1, 0));
+        assert_eq!(receiver.open(&third).unwrap(), b"block 3".to_vec());
+        let usage = sender.usage(&a, &b).unwrap();
+        assert_eq!((usage.messages, usage.renewals, usage.remaining_bytes), (3, 1, KEY_BYTES_PER_MESSAGE));
+
+        // After a restart the sender moves on to fresh key material
+        let mut restarted = QkdChannels::new(Arc::new(SimulatedQkd::new([7u8; 32]))).with_first_epoch(5);
+        let resent = restarted.seal(a, b, b"block 4").unwrap();
+        assert_eq!((resent.epoch, resent.offset), (5, 0));
+        assert_eq!(receiver.open(&resent).unwrap(), b"block 4".to_vec());
+
+        // An eavesdropper pushes the error rate up and key agreement aborts
+        let tapped = Arc::new(SimulatedQkd::new([7u8; 32]).with_qber(0.2));
+        assert!(QkdChannels::new(tapped).seal(a, b, b"x").is_err());
+    }
+}
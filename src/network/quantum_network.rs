@@ -1,10 +1,38 @@
 use crate::math::precision::PreciseFloat;
-use std::collections::HashMap;
+use crate::clock::{self, SharedClock};
+use crate::network::qkd::{KeyAgreement, KeyUsage, QkdChannels, SealedMessage, SimulatedQkd};
+use rand::RngCore;
+use num_traits::ToPrimitive;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Nodes silent for longer than this are treated as dead
+const DEFAULT_LIVENESS_TIMEOUT: Duration = Duration::from_secs(90);
+/// Entanglement pairs each healthy node is kept at
+const DEFAULT_TARGET_DEGREE: usize = 3;
 
 pub struct QuantumNetwork {
     precision: u8,
     nodes: HashMap<NodeId, QuantumNode>,
     routing_table: RoutingTable,
+    clock: SharedClock,
+    liveness_timeout: Duration,
+    target_degree: usize,
+    /// Routes already reported as insecure, so each drop is reported once
+    insecure_routes: HashSet<(NodeId, NodeId)>,
+    /// Per-link keys that encrypt and authenticate messages
+    qkd: QkdChannels,
+}
+
+/// Topology change found by a liveness check
+#[derive(Debug, Clone, PartialEq)]
+pub enum NetworkEvent {
+    NodeDown(NodeId),
+    PairTornDown { node_a: NodeId, node_b: NodeId },
+    Entangled { node_a: NodeId, node_b: NodeId },
+    /// A route's security fell below the quantum-secure threshold
+    RouteSecurityDegraded { from: NodeId, to: NodeId, security: PreciseFloat },
 }
 
 type NodeId = [u8; 32];
@@ -14,6 +42,8 @@ pub struct QuantumNode {
     id: NodeId,
     quantum_state: QuantumState,
     entanglement_pairs: Vec<EntanglementPair>,
+    /// Clock millis of the last heartbeat
+    last_seen: u64,
 }
 
 #[derive(Clone)]
@@ -44,25 +74,168 @@ struct QuantumRoute {
 
 impl QuantumNetwork {
     pub fn new(precision: u8) -> Self {
+        Self::with_clock(precision, clock::system())
+    }
+
+    /// Create a network whose liveness checks follow `clock`
+    pub fn with_clock(precision: u8, clock: SharedClock) -> Self {
+        let mut seed = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut seed);
         Self {
             precision,
             nodes: HashMap::new(),
             routing_table: RoutingTable {
                 routes: HashMap::new(),
             },
+            clock,
+            liveness_timeout: DEFAULT_LIVENESS_TIMEOUT,
+            target_degree: DEFAULT_TARGET_DEGREE,
+            insecure_routes: HashSet::new(),
+            qkd: QkdChannels::new(Arc::new(SimulatedQkd::new(seed))),
         }
     }
 
+    /// Agree link keys through `backend`, such as a QKD device, instead of the simulator
+    pub fn with_key_agreement(mut self, backend: Arc<dyn KeyAgreement>) -> Self {
+        self.qkd = QkdChannels::new(backend);
+        self
+    }
+
+    /// Start outbound QKD links at `epoch` (`QkdChannels::with_first_epoch`);
+    /// set after the key agreement backend
+    pub fn with_first_epoch(mut self, epoch: u64) -> Self {
+        self.qkd = self.qkd.with_first_epoch(epoch);
+        self
+    }
+
+    pub fn with_liveness_timeout(mut self, timeout: Duration) -> Self {
+        self.liveness_timeout = timeout;
+        self
+    }
+
+    pub fn with_target_degree(mut self, degree: usize) -> Self {
+        self.target_degree = degree;
+        self
+    }
+
     pub fn add_node(&mut self, id: NodeId, state: QuantumState) {
         let node = QuantumNode {
             id,
             quantum_state: state,
             entanglement_pairs: Vec::new(),
+            last_seen: self.clock.now_millis(),
         };
         self.nodes.insert(id, node);
         self.update_routing_table();
     }
 
+    /// Record that a node is alive
+    pub fn heartbeat(&mut self, id: &NodeId) -> Result<(), &'static str> {
+        let now = self.clock.now_millis();
+        self.nodes.get_mut(id).ok_or("Node not found")?.last_seen = now;
+        Ok(())
+    }
+
+    /// Remove a node and every entanglement pair it is part of
+    pub fn remove_node(&mut self, id: &NodeId) -> Vec<NetworkEvent> {
+        let events = self.tear_down(id);
+        self.update_routing_table();
+        events
+    }
+
+    /// Entanglement pairs a node is part of
+    pub fn degree(&self, id: &NodeId) -> usize {
+        self.nodes.get(id).map_or(0, |node| node.entanglement_pairs.len())
+    }
+
+    /// Nodes currently in the network
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Drop nodes that missed the liveness timeout with their pairs and routes,
+    /// re-entangle healthy nodes up to the target degree, and report routes
+    /// whose security fell below threshold
+    pub fn check_liveness(&mut self) -> Vec<NetworkEvent> {
+        let now = self.clock.now_millis();
+        let timeout = self.liveness_timeout.as_millis() as u64;
+        let mut dead: Vec<NodeId> = self.nodes.values()
+            .filter(|node| now.saturating_sub(node.last_seen) > timeout)
+            .map(|node| node.id)
+            .collect();
+        dead.sort();
+
+        let mut events = Vec::new();
+        for id in &dead {
+            events.extend(self.tear_down(id));
+        }
+        events.extend(self.restore_degree());
+        self.update_routing_table();
+        events.extend(self.route_security_events());
+        events
+    }
+
+    fn tear_down(&mut self, id: &NodeId) -> Vec<NetworkEvent> {
+        let Some(node) = self.nodes.remove(id) else { return Vec::new() };
+        let mut events = vec![NetworkEvent::NodeDown(*id)];
+        for pair in &node.entanglement_pairs {
+            let other = if pair.node_a == *id { pair.node_b } else { pair.node_a };
+            if let Some(other) = self.nodes.get_mut(&other) {
+                other.entanglement_pairs.retain(|p| p.node_a != *id && p.node_b != *id);
+            }
+            events.push(NetworkEvent::PairTornDown { node_a: pair.node_a, node_b: pair.node_b });
+        }
+        self.insecure_routes.retain(|(from, to)| from != id && to != id);
+        self.qkd.forget(id);
+        events
+    }
+
+    /// Pair nodes below the target degree with their strongest unpaired peers
+    fn restore_degree(&mut self) -> Vec<NetworkEvent> {
+        let mut ids: Vec<NodeId> = self.nodes.keys().copied().collect();
+        ids.sort();
+
+        let mut events = Vec::new();
+        for id in &ids {
+            while self.degree(id) < self.target_degree {
+                let paired: HashSet<NodeId> = self.nodes[id].entanglement_pairs.iter()
+                    .map(|pair| if pair.node_a == *id { pair.node_b } else { pair.node_a })
+                    .collect();
+                let best = ids.iter()
+                    .filter(|other| *other != id && !paired.contains(*other))
+                    .map(|other| (*other, to_f64(&self.calculate_entanglement_strength(id, other))))
+                    // Strongest first; lower ID breaks ties so the choice is deterministic
+                    .min_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+                let Some((other, _)) = best else { break };
+                if self.create_entanglement(*id, other).is_err() {
+                    break;
+                }
+                events.push(NetworkEvent::Entangled { node_a: *id, node_b: other });
+            }
+        }
+        events
+    }
+
+    fn route_security_events(&mut self) -> Vec<NetworkEvent> {
+        let mut insecure = Vec::new();
+        for routes in self.routing_table.routes.values() {
+            for route in routes {
+                if !self.verify_route_security(route) {
+                    insecure.push((route.path[0], *route.path.last().unwrap(), route.quantum_security.clone()));
+                }
+            }
+        }
+        insecure.sort_by_key(|(from, to, _)| (*from, *to));
+
+        let current: HashSet<(NodeId, NodeId)> = insecure.iter().map(|(from, to, _)| (*from, *to)).collect();
+        let events = insecure.into_iter()
+            .filter(|(from, to, _)| !self.insecure_routes.contains(&(*from, *to)))
+            .map(|(from, to, security)| NetworkEvent::RouteSecurityDegraded { from, to, security })
+            .collect();
+        self.insecure_routes = current;
+        events
+    }
+
     pub fn create_entanglement(&mut self, node_a: NodeId, node_b: NodeId) -> Result<(), &'static str> {
         if !self.nodes.contains_key(&node_a) || !self.nodes.contains_key(&node_b) {
             return Err("Node not found");
@@ -97,7 +270,8 @@ impl QuantumNetwork {
             .mul(&node_b.quantum_state.coherence)
     }
 
-    pub fn send_quantum_message(&self, from: NodeId, to: NodeId, _message: &[u8]) -> Result<(), &'static str> {
+    /// Seal a message for `to` with the link's QKD keys, over a secure route
+    pub fn send_quantum_message(&mut self, from: NodeId, to: NodeId, message: &[u8]) -> Result<SealedMessage, &'static str> {
         let route = self.find_quantum_secure_route(&from, &to)?;
         
         // Verify quantum security of the route
@@ -105,24 +279,34 @@ impl QuantumNetwork {
             return Err("Route not quantum secure");
         }
 
-        // In real implementation, this would use quantum key distribution
-        // and actual quantum state transmission
-        Ok(())
+        self.qkd.seal(from, to, message)
+    }
+
+    /// Authenticate and decrypt a message received over a link
+    pub fn receive_quantum_message(&mut self, sealed: &SealedMessage) -> Result<Vec<u8>, &'static str> {
+        self.qkd.open(sealed)
+    }
+
+    /// Key consumption on the link `from -> to`
+    pub fn key_usage(&self, from: &NodeId, to: &NodeId) -> Option<&KeyUsage> {
+        self.qkd.usage(from, to)
     }
 
-    pub fn broadcast_state(&self, state: &[u8]) -> Result<(), &'static str> {
+    pub fn broadcast_state(&mut self, state: &[u8]) -> Result<Vec<SealedMessage>, &'static str> {
         // Broadcast state to all nodes in the network
-        for (from_node, _) in self.nodes.iter() {
-            for (to_node, _) in self.nodes.iter() {
+        let ids: Vec<NodeId> = self.nodes.keys().copied().collect();
+        let mut sealed = Vec::new();
+        for from_node in &ids {
+            for to_node in &ids {
                 if from_node != to_node {
-                    self.send_quantum_message(*from_node, *to_node, state)?;
+                    sealed.push(self.send_quantum_message(*from_node, *to_node, state)?);
                 }
             }
         }
-        Ok(())
+        Ok(sealed)
     }
 
-    pub fn broadcast_block(&self, block_data: &[u8]) -> Result<(), &'static str> {
+    pub fn broadcast_block(&mut self, block_data: &[u8]) -> Result<Vec<SealedMessage>, &'static str> {
         // Broadcast block to all nodes using quantum-secure channels
         self.broadcast_state(block_data)
     }
@@ -138,8 +322,8 @@ impl QuantumNetwork {
 
     fn verify_route_security(&self, route: &QuantumRoute) -> bool {
         // Route is secure if quantum_security is above threshold
-        let threshold = PreciseFloat::new(95, self.precision); // 0.95 threshold
-        route.quantum_security.value >= threshold.value
+        let threshold = PreciseFloat::new(95, 2); // 0.95 threshold
+        to_f64(&route.quantum_security) >= to_f64(&threshold)
     }
 
     fn update_routing_table(&mut self) {
@@ -163,3 +347,63 @@ impl QuantumNetwork {
         }
     }
 }
+
+fn to_f64(value: &PreciseFloat) -> f64 {
+    value.to_f64().unwrap_or(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+
+    fn state(coherence: i128) -> QuantumState {
+        QuantumState {
+            superposition: PreciseFloat::new(99, 2),
+            coherence: PreciseFloat::new(coherence, 2),
+            entanglement_strength: PreciseFloat::new(1, 0),
+        }
+    }
+
+    #[test]
+    fn test_dead_node_teardown_and_reentanglement() {
+        let clock = MockClock::new(1_000);
+        let mut network = QuantumNetwork::with_clock(2, clock.clone())
+            .with_liveness_timeout(Duration::from_secs(60))
+            .with_target_degree(1);
+        let (a, b, c, d) = ([1u8; 32], [2u8; 32], [3u8; 32], [4u8; 32]);
+        for id in [a, b, c] {
+            network.add_node(id, state(99));
+        }
+        network.add_node(d, state(50));
+        network.create_entanglement(a, b).unwrap();
+        network.create_entanglement(b, c).unwrap();
+        network.create_entanglement(d, b).unwrap();
+        assert!(network.send_quantum_message(a, b, b"hi").is_ok());
+
+        // b goes silent while the others keep sending heartbeats
+        clock.advance(Duration::from_secs(61));
+        for id in [a, c, d] {
+            network.heartbeat(&id).unwrap();
+        }
+        let events = network.check_liveness();
+
+        assert_eq!(events[0], NetworkEvent::NodeDown(b));
+        let torn_down = events.iter().filter(|e| matches!(e, NetworkEvent::PairTornDown { .. })).count();
+        assert_eq!(torn_down, 3);
+        assert!(network.send_quantum_message(a, b, b"hi").is_err());
+
+        // a and c pair with each other; the weak node d still gets a partner,
+        // but the route to it is reported as insecure
+        assert!(events.contains(&NetworkEvent::Entangled { node_a: a, node_b: c }));
+        assert!(events.contains(&NetworkEvent::Entangled { node_a: d, node_b: a }));
+        let sealed = network.send_quantum_message(a, c, b"hi").unwrap();
+        assert_eq!(network.receive_quantum_message(&sealed).unwrap(), b"hi".to_vec());
+        assert_eq!(network.key_usage(&a, &c).unwrap().messages, 1);
+        assert!(events.iter().any(|e| matches!(e, NetworkEvent::RouteSecurityDegraded { to, .. } if *to == d)));
+        assert!([a, c, d].iter().all(|id| network.degree(id) >= 1));
+
+        // Already-reported insecure routes are not reported again
+        assert!(network.check_liveness().is_empty());
+    }
+}
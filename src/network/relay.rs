@@ -0,0 +1,185 @@
+//! Transaction relay between connected peers.
+//!
+//! Every admitted peer connection registers an outbound queue here, so a
+//! transaction received on one connection can be passed on along others.
+//! Transactions go to the first hops of flux routes once flux knows some,
+//! and otherwise to `gossip_fanout` peers picked at random. A transaction is
+//! never sent back to the peer it came from, and one the mempool already
+//! holds or refuses goes no further, so relay cannot loop.
+
+use rand::seq::SliceRandom;
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+use tokio::sync::{mpsc, RwLock};
+use crate::blockchain::types::QuantumNodeID;
+
+/// Flux routing identity of a peer, derived from its address
+pub fn flux_id(address: &str) -> QuantumNodeID {
+    QuantumNodeID::new(blake3::hash(address.as_bytes()).into())
+}
+
+/// Open connection that relayed messages are queued on
+struct PeerLink {
+    outbound: mpsc::UnboundedSender<String>,
+    /// Uptime the peer advertised in its handshake
+    uptime: u64,
+    opened: Instant,
+}
+
+/// Outbound queues of the admitted peer connections, by peer address
+#[derive(Default)]
+pub struct PeerLinks {
+    links: RwLock<HashMap<String, PeerLink>>,
+}
+
+impl PeerLinks {
+    /// Register a connection. Messages sent to `address` arrive on the
+    /// returned queue until the connection is closed.
+    pub async fn open(&self, address: &str, uptime: u64) -> mpsc::UnboundedReceiver<String> {
+        let (outbound, queue) = mpsc::unbounded_channel();
+        self.links.write().await.insert(address.to_string(), PeerLink { outbound, uptime, opened: Instant::now() });
+        queue
+    }
+
+    pub async fn close(&self, address: &str) {
+        self.links.write().await.remove(address);
+    }
+
+    /// Each connected peer with its current uptime in seconds
+    pub async fn uptimes(&self) -> Vec<(String, u64)> {
+        self.links.read().await.iter()
+            .map(|(address, link)| (address.clone(), link.uptime + link.opened.elapsed().as_secs()))
+            .collect()
+    }
+
+    /// Queue `message` for those of `addresses` with an open connection.
+    /// Returns how many peers it was queued for.
+    pub async fn send(&self, addresses: &[String], message: &str) -> usize {
+        let links = self.links.read().await;
+        addresses.iter()
+            .filter_map(|address| links.get(address))
+            .filter(|link| link.outbound.send(message.to_string()).is_ok())
+            .count()
+    }
+
+    /// Addresses of the connected peers whose flux identity is one of `ids`
+    pub async fn resolve(&self, ids: &[QuantumNodeID]) -> Vec<String> {
+        self.links.read().await.keys()
+            .filter(|address| ids.contains(&flux_id(address)))
+            .cloned()
+            .collect()
+    }
+
+    /// Up to `fanout` connected peers picked at random, other than `except`
+    pub async fn gossip_targets(&self, except: &str, fanout: usize) -> Vec<String> {
+        let mut targets: Vec<String> = self.links.read().await.keys()
+            .filter(|address| address.as_str() != except)
+            .cloned()
+            .collect();
+        targets.shuffle(&mut rand::thread_rng());
+        targets.truncate(fanout);
+        targets
+    }
+}
+
+/// What became of a transaction received from a peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelayOutcome {
+    /// Already pending, so relayed before
+    Duplicate,
+    /// Refused by the mempool
+    Rejected,
+    /// Queued for this many first hops of flux routes
+    Flux(usize),
+    /// Queued for this many randomly picked peers
+    Gossip(usize),
+}
+
+/// Relay counters
+/// Totals since startup over all peer connections.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RelayMetrics {
+    /// Transactions received from peers
+    pub received: u64,
+    pub duplicates: u64,
+    pub rejected: u64,
+    /// Transactions relayed along flux routes, and by gossip fallback
+    pub flux_relayed: u64,
+    pub gossip_relayed: u64,
+    /// Copies queued to peers
+    pub messages_sent: u64,
+}
+
+/// Counters shared by every connection
+#[derive(Debug, Default)]
+pub struct RelayStats {
+    received: AtomicU64,
+    duplicates: AtomicU64,
+    rejected: AtomicU64,
+    flux_relayed: AtomicU64,
+    gossip_relayed: AtomicU64,
+    messages_sent: AtomicU64,
+}
+
+impl RelayStats {
+    pub fn record(&self, outcome: RelayOutcome) {
+        self.received.fetch_add(1, Ordering::Relaxed);
+        let (counter, sent) = match outcome {
+            RelayOutcome::Duplicate => (&self.duplicates, 0),
+            RelayOutcome::Rejected => (&self.rejected, 0),
+            RelayOutcome::Flux(sent) => (&self.flux_relayed, sent),
+            RelayOutcome::Gossip(sent) => (&self.gossip_relayed, sent),
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        self.messages_sent.fetch_add(sent as u64, Ordering::Relaxed);
+    }
+
+    pub fn metrics(&self) -> RelayMetrics {
+        RelayMetrics {
+            received: self.received.load(Ordering::Relaxed),
+            duplicates: self.duplicates.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            flux_relayed: self.flux_relayed.load(Ordering::Relaxed),
+            gossip_relayed: self.gossip_relayed.load(Ordering::Relaxed),
+            messages_sent: self.messages_sent.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_relay_targets_skip_the_sender() {
+        let links = PeerLinks::default();
+        let mut a = links.open("10.0.0.1:30303", 0).await;
+        let mut b = links.open("10.0.1.1:30303", 0).await;
+
+        let targets = links.gossip_targets("10.0.0.1:30303", 8).await;
+        assert_eq!(targets, vec!["10.0.1.1:30303"]);
+        assert_eq!(links.send(&targets, "tx").await, 1);
+        assert_eq!(b.recv().await.as_deref(), Some("tx"));
+        assert!(a.try_recv().is_err());
+
+        // Flux identities resolve back to connected peers only
+        let ids = [flux_id("10.0.0.1:30303"), flux_id("10.9.9.9:30303")];
+        assert_eq!(links.resolve(&ids).await, vec!["10.0.0.1:30303"]);
+
+        links.close("10.0.1.1:30303").await;
+        assert_eq!(links.send(&targets, "tx").await, 0);
+    }
+
+    #[test]
+    fn test_relay_metrics() {
+        let stats = RelayStats::default();
+        stats.record(RelayOutcome::Flux(2));
+        stats.record(RelayOutcome::Gossip(3));
+        stats.record(RelayOutcome::Duplicate);
+        let metrics = stats.metrics();
+        assert_eq!((metrics.received, metrics.flux_relayed, metrics.gossip_relayed), (3, 1, 1));
+        assert_eq!((metrics.duplicates, metrics.messages_sent), (1, 5));
+    }
+}
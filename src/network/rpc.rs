@@ -1,31 +1,528 @@
 use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::future::Future;
+use std::io::BufReader;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use futures::future::BoxFuture;
+use hyper::body::HttpBody;
+use hyper::server::conn::Http;
+use hyper::service::service_fn;
+use hyper::{Body, Request, Response};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::rustls::{Certificate, PrivateKey, ServerConfig};
+use tokio_rustls::TlsAcceptor;
 
+/// Request body that is not JSON
+pub const PARSE_ERROR: i32 = -32700;
+/// JSON that is not a JSON-RPC 2.0 call
+pub const INVALID_REQUEST: i32 = -32600;
+pub const METHOD_NOT_FOUND: i32 = -32601;
+/// Error returned by a method
+pub const SERVER_ERROR: i32 = -32000;
 
+/// Most calls in one batch
+pub const MAX_BATCH_SIZE: usize = 100;
+/// Largest request body read, chunked or not
+pub const MAX_BODY_BYTES: usize = 4 * 1024 * 1024;
+
+/// JSON-RPC 2.0 call. A call without an `id` is a notification and gets no response.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RPCRequest {
+    pub jsonrpc: String,
     pub method: String,
-    pub params: serde_json::Value,
-    pub id: u64
+    #[serde(default)]
+    pub params: Value,
+    #[serde(default)]
+    pub id: Value,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RPCResponse {
-    pub result: Option<serde_json::Value>,
-    pub error: Option<String>,
-    pub id: u64
+    pub jsonrpc: String,
+    pub result: Option<Value>,
+    pub error: Option<RPCError>,
+    pub id: Value,
+    /// Trace ID the request ran under, for correlating logs across nodes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RPCError {
+    pub code: i32,
+    pub message: String,
+    pub data: Option<Value>,
+}
+
+impl RPCResponse {
+    pub fn result(id: Value, result: Value) -> Self {
+        Self { jsonrpc: "2.0".to_string(), result: Some(result), error: None, id, trace_id: None }
+    }
+
+    pub fn error(id: Value, code: i32, message: impl Into<String>) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            result: None,
+            error: Some(RPCError { code, message: message.into(), data: None }),
+            id,
+            trace_id: None,
+        }
+    }
+}
+
+/// Responses to one HTTP request, shaped like the request: a single call or a batch
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum RPCReply {
+    Single(RPCResponse),
+    Batch(Vec<RPCResponse>),
+}
+
+impl RPCReply {
+    pub fn responses_mut(&mut self) -> &mut [RPCResponse] {
+        match self {
+            RPCReply::Single(response) => std::slice::from_mut(response),
+            RPCReply::Batch(responses) => responses,
+        }
+    }
+}
+
+type Handler<C> = Arc<dyn Fn(C, String, Value) -> BoxFuture<'static, Result<Value, String>> + Send + Sync>;
+type Fallback<C> = Arc<dyn Fn(C, RPCRequest) -> BoxFuture<'static, RPCResponse> + Send + Sync>;
+
+/// RPC Methods
+/// Handlers registered by method name, each called with a clone of the server context `C`.
+pub struct Methods<C> {
+    handlers: HashMap<String, Handler<C>>,
+    fallback: Option<Fallback<C>>,
+}
+
+impl<C: Clone + Send + 'static> Default for Methods<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Clone + Send + 'static> Methods<C> {
+    pub fn new() -> Self {
+        Self { handlers: HashMap::new(), fallback: None }
+    }
+
+    /// Serve `methods` with `handler`, which is given the method name and
+    /// params. Its errors are returned with code -32000.
+    pub fn register<F, Fut>(&mut self, methods: &[&str], handler: F)
+    where
+        F: Fn(C, String, Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Value, String>> + Send + 'static,
+    {
+        let handler: Handler<C> = Arc::new(move |ctx, method, params| Box::pin(handler(ctx, method, params)));
+        for method in methods {
+            let previous = self.handlers.insert(method.to_string(), handler.clone());
+            assert!(previous.is_none(), "RPC method {} registered twice", method);
+        }
+    }
+
+    /// Serve every method not registered by name with `handler`
+    pub fn fallback<F, Fut>(&mut self, handler: F)
+    where
+        F: Fn(C, RPCRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = RPCResponse> + Send + 'static,
+    {
+        self.fallback = Some(Arc::new(move |ctx, request| Box::pin(handler(ctx, request))));
+    }
+
+    pub fn contains(&self, method: &str) -> bool {
+        self.handlers.contains_key(method)
+    }
+
+    pub async fn call(&self, ctx: C, request: RPCRequest) -> RPCResponse {
+        if let Some(handler) = self.handlers.get(&request.method) {
+            return match handler(ctx, request.method, request.params).await {
+                Ok(result) => RPCResponse::result(request.id, result),
+                Err(message) => RPCResponse::error(request.id, SERVER_ERROR, message),
+            };
+        }
+        match &self.fallback {
+            Some(fallback) => fallback(ctx, request).await,
+            None => RPCResponse::error(request.id, METHOD_NOT_FOUND, "Method not found"),
+        }
+    }
+
+    /// Answer a request body: one call or a batch. Batched calls run in
+    /// order. Returns `None` if every call was a notification.
+    pub async fn handle(&self, ctx: C, payload: Value) -> Option<RPCReply> {
+        match payload {
+            Value::Array(calls) => {
+                if calls.is_empty() {
+                    return Some(RPCReply::Single(RPCResponse::error(Value::Null, INVALID_REQUEST, "Empty batch")));
+                }
+                if calls.len() > MAX_BATCH_SIZE {
+                    return Some(RPCReply::Single(RPCResponse::error(
+                        Value::Null,
+                        INVALID_REQUEST,
+                        format!("At most {} calls per batch", MAX_BATCH_SIZE),
+                    )));
+                }
+                let mut responses = Vec::with_capacity(calls.len());
+                for call in calls {
+                    responses.extend(self.handle_call(ctx.clone(), call).await);
+                }
+                (!responses.is_empty()).then_some(RPCReply::Batch(responses))
+            }
+            call => self.handle_call(ctx, call).await.map(RPCReply::Single),
+        }
+    }
+
+    async fn handle_call(&self, ctx: C, call: Value) -> Option<RPCResponse> {
+        let notification = call.is_object() && call.get("id").is_none();
+        let request = match serde_json::from_value::<RPCRequest>(call) {
+            Ok(request) if request.jsonrpc == "2.0" => request,
+            _ => return Some(RPCResponse::error(Value::Null, INVALID_REQUEST, "Invalid request")),
+        };
+        let response = self.call(ctx, request).await;
+        (!notification).then_some(response)
+    }
+}
+
+/// Calls in a parsed payload, for rate limiting
+pub fn call_count(payload: &Value) -> usize {
+    payload.as_array().map_or(1, Vec::len)
+}
+
+/// Method and id of each call in a parsed payload, for logging. Params are
+/// left out: they carry chain tokens, operator tokens and HTLC secrets.
+pub fn describe_calls(payload: &Value) -> String {
+    let describe = |call: &Value| format!(
+        "{}#{}",
+        call.get("method").and_then(Value::as_str).unwrap_or("?"),
+        call.get("id").unwrap_or(&Value::Null)
+    );
+    match payload.as_array() {
+        Some(calls) => calls.iter().map(describe).collect::<Vec<_>>().join(", "),
+        None => describe(payload),
+    }
+}
+
+/// Read a whole request body, failing past `limit` bytes
+pub async fn read_body(mut body: Body, limit: usize) -> Result<Vec<u8>, &'static str> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|_| "Failed to read request body")?;
+        if bytes.len() + chunk.len() > limit {
+            return Err("Request body too large");
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+/// Serve HTTP/1.1 on one connection, keeping it open between requests,
+/// until the client closes it. Once `shutdown` resolves the request in
+/// flight is answered and the connection closed.
+pub async fn serve_connection<S, F, Fut>(stream: S, mut handle: F, shutdown: impl Future<Output = ()>) -> Result<(), hyper::Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    F: FnMut(Request<Body>) -> Fut + Send + 'static,
+    Fut: Future<Output = Response<Body>> + Send + 'static,
+{
+    let service = service_fn(move |request| {
+        let response = handle(request);
+        async move { Ok::<_, Infallible>(response.await) }
+    });
+    let connection = Http::new()
+        .http1_only(true)
+        .http1_keep_alive(true)
+        .serve_connection(stream, service);
+    tokio::pin!(connection, shutdown);
+    tokio::select! {
+        result = &mut connection => return result,
+        _ = &mut shutdown => {}
+    }
+    connection.as_mut().graceful_shutdown();
+    connection.await
+}
+
+/// JSON response with an optional CORS origin
+pub fn json_response(status: u16, cors: Option<&str>, body: String) -> Response<Body> {
+    let mut response = Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json");
+    if let Some(origin) = cors {
+        response = response
+            .header("Access-Control-Allow-Origin", origin)
+            .header("Access-Control-Allow-Methods", "GET, POST, OPTIONS")
+            .header("Access-Control-Allow-Headers", "Content-Type")
+            .header("Vary", "Origin");
+    }
+    if status == 429 {
+        response = response.header("Retry-After", "1");
+    }
+    response.body(Body::from(body)).unwrap_or_default()
+}
+
+/// Fixed-window per-client request limiter; the limit can be changed at runtime
+pub struct RateLimiter {
+    limit: Mutex<u32>,
+    windows: Mutex<HashMap<String, (Instant, u32)>>,
 }
 
-pub struct RPCServer {
-    pub port: u16
+impl RateLimiter {
+    const WINDOW: Duration = Duration::from_secs(1);
+
+    pub fn new(requests_per_second: u32) -> Self {
+        Self {
+            limit: Mutex::new(requests_per_second),
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn set_limit(&self, requests_per_second: u32) {
+        *self.limit.lock().unwrap() = requests_per_second;
+    }
+
+    pub fn limit(&self) -> u32 {
+        *self.limit.lock().unwrap()
+    }
+
+    /// Count a request from `client`; returns false if it exceeds the limit
+    pub fn check(&self, client: &str) -> bool {
+        self.check_n(client, 1)
+    }
+
+    /// Count `requests` from `client` at once, as for a batch; none are
+    /// counted if they would exceed the limit
+    pub fn check_n(&self, client: &str, requests: u32) -> bool {
+        let limit = self.limit();
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+
+        // Forget clients whose window has long expired
+        if windows.len() > 10_000 {
+            windows.retain(|_, (start, _)| now.duration_since(*start) < Self::WINDOW);
+        }
+
+        let window = windows.entry(client.to_string()).or_insert((now, 0));
+        if now.duration_since(window.0) >= Self::WINDOW {
+            *window = (now, 0);
+        }
+        if window.1.saturating_add(requests) > limit {
+            return false;
+        }
+        window.1 += requests;
+        true
+    }
+}
+
+/// Resolve the originating client address.
+/// X-Forwarded-For is only honored when the direct peer is a trusted proxy,
+/// and the rightmost untrusted hop is taken so clients cannot spoof it.
+pub fn client_ip(peer: IpAddr, forwarded_for: Option<&str>, trusted_proxies: &[IpAddr]) -> IpAddr {
+    if !trusted_proxies.contains(&peer) {
+        return peer;
+    }
+    let Some(forwarded_for) = forwarded_for else { return peer };
+
+    forwarded_for
+        .rsplit(',')
+        .filter_map(|hop| hop.trim().parse::<IpAddr>().ok())
+        .find(|hop| !trusted_proxies.contains(hop))
+        .unwrap_or(peer)
 }
 
-impl RPCServer {
-    pub fn new(port: u16) -> Self {
-        Self { port }
+/// Value for Access-Control-Allow-Origin, if the origin is allowed
+pub fn cors_origin(allowed: &[String], origin: &str) -> Option<String> {
+    if allowed.iter().any(|o| o == "*") {
+        return Some("*".to_string());
     }
+    allowed.iter().find(|o| o.as_str() == origin).cloned()
+}
 
-    pub async fn start(&self) -> Result<(), Box<dyn std::error::Error>> {
-        // Implementation will go here
+/// TLS certificate and key that can be swapped without restarting the listener
+pub struct CertificateStore {
+    cert_path: String,
+    key_path: String,
+    acceptor: RwLock<TlsAcceptor>,
+}
+
+impl CertificateStore {
+    pub fn load(cert_path: &str, key_path: &str) -> Result<Self, String> {
+        Ok(Self {
+            cert_path: cert_path.to_string(),
+            key_path: key_path.to_string(),
+            acceptor: RwLock::new(Self::build_acceptor(cert_path, key_path)?),
+        })
+    }
+
+    /// Re-read the certificate files; new connections use the new certificate
+    pub fn reload(&self) -> Result<(), String> {
+        let acceptor = Self::build_acceptor(&self.cert_path, &self.key_path)?;
+        *self.acceptor.write().unwrap() = acceptor;
         Ok(())
     }
+
+    pub fn acceptor(&self) -> TlsAcceptor {
+        self.acceptor.read().unwrap().clone()
+    }
+
+    fn build_acceptor(cert_path: &str, key_path: &str) -> Result<TlsAcceptor, String> {
+        let open = |path: &str| {
+            std::fs::File::open(path)
+                .map(BufReader::new)
+                .map_err(|e| format!("Failed to open {}: {}", path, e))
+        };
+
+        let certs = rustls_pemfile::certs(&mut open(cert_path)?)
+            .map_err(|e| format!("Invalid certificate {}: {}", cert_path, e))?
+            .into_iter()
+            .map(Certificate)
+            .collect::<Vec<_>>();
+        if certs.is_empty() {
+            return Err(format!("No certificates found in {}", cert_path));
+        }
+
+        let key = rustls_pemfile::read_all(&mut open(key_path)?)
+            .map_err(|e| format!("Invalid private key {}: {}", key_path, e))?
+            .into_iter()
+            .find_map(|item| match item {
+                rustls_pemfile::Item::RSAKey(key)
+                | rustls_pemfile::Item::PKCS8Key(key)
+                | rustls_pemfile::Item::ECKey(key) => Some(PrivateKey(key)),
+                _ => None,
+            })
+            .ok_or_else(|| format!("No private key found in {}", key_path))?;
+
+        let config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .map_err(|e| format!("Invalid TLS configuration: {}", e))?;
+
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_client_ip_behind_proxy() {
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        let peer: IpAddr = "192.168.1.5".parse().unwrap();
+        let trusted = vec![proxy];
+
+        // Untrusted peers cannot spoof their address
+        assert_eq!(client_ip(peer, Some("1.1.1.1"), &trusted), peer);
+        // Rightmost untrusted hop wins
+        assert_eq!(
+            client_ip(proxy, Some("6.6.6.6, 2.2.2.2, 10.0.0.1"), &trusted),
+            "2.2.2.2".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(client_ip(proxy, None, &trusted), proxy);
+    }
+
+    #[test]
+    fn test_cors_origin() {
+        let allowed = vec!["https://app.metaverse.io".to_string()];
+        assert_eq!(cors_origin(&allowed, "https://app.metaverse.io"), Some("https://app.metaverse.io".to_string()));
+        assert_eq!(cors_origin(&allowed, "https://evil.example"), None);
+        assert_eq!(cors_origin(&["*".to_string()], "https://any.example"), Some("*".to_string()));
+    }
+
+    #[test]
+    fn test_describe_calls_omits_params() {
+        let single = json!({ "jsonrpc": "2.0", "id": 7, "method": "privatePutState", "params": { "token": "secret" } });
+        assert_eq!(describe_calls(&single), "privatePutState#7");
+        let batch = json!([single, { "jsonrpc": "2.0", "method": "status" }]);
+        assert_eq!(describe_calls(&batch), "privatePutState#7, status#null");
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(2);
+        assert!(limiter.check("10.0.0.1"));
+        assert!(limiter.check("10.0.0.1"));
+        assert!(!limiter.check("10.0.0.1"));
+        assert!(limiter.check("10.0.0.2"));
+
+        limiter.set_limit(3);
+        assert!(limiter.check("10.0.0.1"));
+
+        // A batch is refused whole rather than partly counted
+        assert!(!limiter.check_n("10.0.0.2", 3));
+        assert!(limiter.check_n("10.0.0.2", 2));
+    }
+
+    fn echo_methods() -> Methods<()> {
+        let mut methods = Methods::new();
+        methods.register(&["echo"], |_, _, params| async move { Ok(params) });
+        methods.register(&["fail"], |_, method, _| async move { Err(format!("{} failed", method)) });
+        methods
+    }
+
+    #[tokio::test]
+    async fn test_batch_and_notifications() {
+        let methods = echo_methods();
+        let payload = json!([
+            { "jsonrpc": "2.0", "method": "echo", "params": [1], "id": 1 },
+            { "jsonrpc": "2.0", "method": "echo", "params": [2] },
+            { "jsonrpc": "2.0", "method": "fail", "id": "b" },
+            { "jsonrpc": "2.0", "method": "missing", "id": 3 },
+            { "method": "echo", "id": 4 },
+        ]);
+        let reply = serde_json::to_value(methods.handle((), payload).await.unwrap()).unwrap();
+        let reply = reply.as_array().unwrap();
+        // The notification gets no response
+        assert_eq!(reply.len(), 4);
+        assert_eq!(reply[0]["result"], json!([1]));
+        assert_eq!(reply[1]["id"], "b");
+        assert_eq!(reply[1]["error"]["code"], SERVER_ERROR);
+        assert_eq!(reply[1]["error"]["message"], "fail failed");
+        assert_eq!(reply[2]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(reply[3]["error"]["code"], INVALID_REQUEST);
+
+        let notification = json!([{ "jsonrpc": "2.0", "method": "echo" }]);
+        assert!(methods.handle((), notification).await.is_none());
+        let empty = serde_json::to_value(methods.handle((), json!([])).await.unwrap()).unwrap();
+        assert_eq!(empty["error"]["code"], INVALID_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_keep_alive_and_chunked_bodies() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let methods = Arc::new(echo_methods());
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let served = tokio::spawn(serve_connection(server, move |request: Request<Body>| {
+            let methods = methods.clone();
+            async move {
+                let body = read_body(request.into_body(), MAX_BODY_BYTES).await.unwrap();
+                let reply = methods.handle((), serde_json::from_slice(&body).unwrap()).await;
+                json_response(200, None, serde_json::to_string(&reply).unwrap())
+            }
+        }, std::future::pending()));
+
+        let (mut reader, mut writer) = tokio::io::split(client);
+        let call = r#"{"jsonrpc":"2.0","method":"echo","params":"first","id":1}"#;
+        writer.write_all(format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}", call.len(), call).as_bytes()).await.unwrap();
+        let chunked = r#"{"jsonrpc":"2.0","method":"echo","params":"second","id":2}"#;
+        writer.write_all(format!(
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+            chunked.len(),
+            chunked,
+        ).as_bytes()).await.unwrap();
+
+        // Both requests are answered on the same connection, which then closes
+        let mut raw = String::new();
+        reader.read_to_string(&mut raw).await.unwrap();
+        assert_eq!(raw.matches("HTTP/1.1 200 OK").count(), 2);
+        assert!(raw.contains(r#""result":"first""#));
+        assert!(raw.contains(r#""result":"second""#));
+        served.await.unwrap().unwrap();
+    }
 }
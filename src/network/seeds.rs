@@ -0,0 +1,364 @@
+//! Bootstrap peers from DNS seeds.
+//!
+//! A seed domain lists peers in a TXT record of the form
+//! `qmv-seed seq=<n> peers=<ip:port>,<ip:port> [sig=<hex>]`, or in SRV
+//! records at `_qmv._tcp.<domain>`. A seed configured with a public key only
+//! accepts TXT records signed by that key over the network, the domain, the
+//! sequence number and the peer list, and never falls back to SRV. A signed
+//! list with a lower sequence number than one already accepted is ignored,
+//! so a replayed old record cannot roll the list back.
+//!
+//! Seeds are looked up again every `seeds.refresh_secs`, and the book keeps
+//! the last list each seed served. Peers are dialed in this order: lists
+//! fetched in the latest round, in config order; then remembered lists of
+//! seeds whose latest lookup failed; then the static `seeds.bootstrap` peers.
+
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use hickory_resolver::TokioAsyncResolver;
+use tokio::sync::RwLock;
+use crate::config::SeedConfig;
+use crate::crypto::domain::{PayloadKind, SigningDomain};
+
+/// First word of a seed TXT record; other TXT records on the domain are ignored
+pub const TXT_PREFIX: &str = "qmv-seed";
+
+/// Service label of seed SRV records
+pub const SRV_SERVICE: &str = "_qmv._tcp";
+
+/// Peers a seed domain lists in one TXT record
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SeedRecord {
+    /// Raised by the operator on every change
+    pub sequence: u64,
+    pub peers: Vec<SocketAddr>,
+}
+
+impl SeedRecord {
+    /// TXT record text, signed for `domain` on `network_id`
+    pub fn sign(&self, key: &SigningKey, network_id: u64, domain: &str) -> String {
+        let signature = key.sign(&self.signing_bytes(network_id, domain));
+        format!("{} sig={}", self.to_txt(), hex::encode(signature.to_bytes()))
+    }
+
+    /// Unsigned TXT record text
+    pub fn to_txt(&self) -> String {
+        let peers: Vec<String> = self.peers.iter().map(SocketAddr::to_string).collect();
+        format!("{} seq={} peers={}", TXT_PREFIX, self.sequence, peers.join(","))
+    }
+
+    /// Parse a TXT record and the signature it carries, if any
+    pub fn parse(txt: &str) -> Result<(Self, Option<Vec<u8>>), &'static str> {
+        let mut fields = txt.split_whitespace();
+        if fields.next() != Some(TXT_PREFIX) {
+            return Err("Not a seed record");
+        }
+        let (mut sequence, mut peers, mut signature) = (None, None, None);
+        for field in fields {
+            match field.split_once('=') {
+                Some(("seq", value)) => sequence = Some(value.parse().map_err(|_| "Invalid seed sequence")?),
+                Some(("peers", value)) => {
+                    peers = Some(value.split(',')
+                        .map(|peer| peer.parse().map_err(|_| "Seed peers must be ip:port addresses"))
+                        .collect::<Result<Vec<SocketAddr>, _>>()?);
+                }
+                Some(("sig", value)) => signature = Some(hex::decode(value).map_err(|_| "Invalid seed signature")?),
+                _ => return Err("Unknown seed record field"),
+            }
+        }
+        let record = Self {
+            sequence: sequence.ok_or("Seed record has no sequence")?,
+            peers: peers.ok_or("Seed record has no peers")?,
+        };
+        Ok((record, signature))
+    }
+
+    fn signing_bytes(&self, network_id: u64, domain: &str) -> Vec<u8> {
+        let domain = normalize(domain);
+        let mut body = Vec::new();
+        body.extend_from_slice(&(domain.len() as u32).to_le_bytes());
+        body.extend_from_slice(domain.as_bytes());
+        body.extend_from_slice(&self.sequence.to_le_bytes());
+        for peer in &self.peers {
+            body.extend_from_slice(peer.to_string().as_bytes());
+            body.push(b',');
+        }
+        SigningDomain::main_chain(network_id).payload(PayloadKind::SeedList, 0, &body)
+    }
+}
+
+/// What a seed served in its latest lookup, for `getSeeds`
+#[derive(Debug, Clone, Serialize)]
+pub struct SeedStatus {
+    pub domain: String,
+    /// Whether only signed TXT records are accepted
+    pub signed: bool,
+    /// Last list accepted, possibly from an earlier lookup
+    pub record: Option<SeedRecord>,
+    /// Whether the latest lookup succeeded
+    pub fresh: bool,
+    pub error: Option<String>,
+}
+
+/// Seed Book
+/// Configured seeds, the lists they served, and the static fallback peers.
+pub struct SeedBook {
+    network_id: u64,
+    seeds: Vec<(Option<[u8; 32]>, SeedStatus)>,
+    bootstrap: Vec<String>,
+}
+
+impl SeedBook {
+    pub fn new(network_id: u64, config: &SeedConfig) -> Result<Self, String> {
+        let mut book = Self { network_id, seeds: Vec::new(), bootstrap: Vec::new() };
+        book.reconfigure(config)?;
+        Ok(book)
+    }
+
+    /// Apply a reloaded config. Seeds kept with the same key keep the list
+    /// they served; added seeds start empty until the next lookup.
+    pub fn reconfigure(&mut self, config: &SeedConfig) -> Result<(), String> {
+        let mut seeds = Vec::with_capacity(config.dns.len());
+        for seed in &config.dns {
+            let key = seed.signer()?;
+            let domain = normalize(&seed.domain);
+            let kept = self.seeds.iter()
+                .position(|(known_key, status)| status.domain == domain && *known_key == key)
+                .map(|index| self.seeds.swap_remove(index).1);
+            let status = kept.unwrap_or(SeedStatus {
+                domain,
+                signed: key.is_some(),
+                record: None,
+                fresh: false,
+                error: None,
+            });
+            seeds.push((key, status));
+        }
+        self.seeds = seeds;
+        self.bootstrap = config.bootstrap.clone();
+        Ok(())
+    }
+
+    /// Seed domains in order, and whether each requires a signed record
+    pub fn domains(&self) -> Vec<(String, bool)> {
+        self.seeds.iter().map(|(_, status)| (status.domain.clone(), status.signed)).collect()
+    }
+
+    /// Take the newest valid seed record among a domain's TXT records.
+    /// Returns how many peers it lists.
+    pub fn accept_txt(&mut self, domain: &str, records: &[String]) -> Result<usize, &'static str> {
+        let network_id = self.network_id;
+        let (key, status) = self.seed_mut(domain)?;
+        let mut newest: Option<SeedRecord> = None;
+        let mut error = "No seed record";
+        for txt in records.iter().filter(|txt| txt.starts_with(TXT_PREFIX)) {
+            match check(txt, *key, network_id, &status.domain) {
+                Ok(record) if newest.as_ref().is_none_or(|newest| record.sequence > newest.sequence) => newest = Some(record),
+                Ok(_) => {}
+                Err(e) => error = e,
+            }
+        }
+        let record = newest.ok_or(error)?;
+        let stale = status.record.as_ref().is_some_and(|known| record.sequence < known.sequence);
+        if status.signed && stale {
+            return Err("Seed list is older than the one already accepted");
+        }
+        Ok(status.accept(record))
+    }
+
+    /// Take peers resolved from a domain's SRV records, best first. Only
+    /// unsigned seeds may use SRV.
+    pub fn accept_srv(&mut self, domain: &str, peers: Vec<SocketAddr>) -> Result<usize, &'static str> {
+        let (_, status) = self.seed_mut(domain)?;
+        if status.signed {
+            return Err("Seed requires a signed TXT record");
+        }
+        if peers.is_empty() {
+            return Err("No seed peers in SRV records");
+        }
+        Ok(status.accept(SeedRecord { sequence: 0, peers }))
+    }
+
+    /// Record a failed lookup; the seed's last list stays as a fallback
+    pub fn fail(&mut self, domain: &str, error: String) {
+        if let Ok((_, status)) = self.seed_mut(domain) {
+            status.fresh = false;
+            status.error = Some(error);
+        }
+    }
+
+    /// Peers to dial, in fallback order, without duplicates
+    pub fn bootstrap_peers(&self) -> Vec<String> {
+        let listed = |fresh: bool| {
+            self.seeds.iter()
+                .filter(move |(_, status)| status.fresh == fresh)
+                .filter_map(|(_, status)| status.record.as_ref())
+                .flat_map(|record| record.peers.iter().map(SocketAddr::to_string))
+        };
+        let mut seen = HashSet::new();
+        listed(true)
+            .chain(listed(false))
+            .chain(self.bootstrap.iter().cloned())
+            .filter(|peer| seen.insert(peer.clone()))
+            .collect()
+    }
+
+    pub fn status(&self) -> Vec<SeedStatus> {
+        self.seeds.iter().map(|(_, status)| status.clone()).collect()
+    }
+
+    fn seed_mut(&mut self, domain: &str) -> Result<&mut (Option<[u8; 32]>, SeedStatus), &'static str> {
+        let domain = normalize(domain);
+        self.seeds.iter_mut().find(|(_, status)| status.domain == domain).ok_or("Unknown seed")
+    }
+}
+
+impl SeedStatus {
+    fn accept(&mut self, record: SeedRecord) -> usize {
+        let count = record.peers.len();
+        self.record = Some(record);
+        self.fresh = true;
+        self.error = None;
+        count
+    }
+}
+
+fn normalize(domain: &str) -> String {
+    domain.trim_end_matches('.').to_ascii_lowercase()
+}
+
+fn check(txt: &str, key: Option<[u8; 32]>, network_id: u64, domain: &str) -> Result<SeedRecord, &'static str> {
+    let (record, signature) = SeedRecord::parse(txt)?;
+    let Some(key) = key else { return Ok(record) };
+    let signature = signature.ok_or("Seed record is not signed")?;
+    let key = VerifyingKey::from_bytes(&key).map_err(|_| "Invalid seed key")?;
+    let signature = Signature::from_slice(&signature).map_err(|_| "Invalid seed signature")?;
+    key.verify(&record.signing_bytes(network_id, domain), &signature)
+        .map_err(|_| "Invalid seed signature")?;
+    Ok(record)
+}
+
+/// Look up every seed once. Returns a message for each seed that failed;
+/// its last list stays in the book.
+pub async fn refresh(book: &RwLock<SeedBook>, resolver: &TokioAsyncResolver) -> Vec<String> {
+    let mut errors = Vec::new();
+    let domains = book.read().await.domains();
+    for (domain, signed) in domains {
+        let records = lookup_txt(resolver, &domain).await.unwrap_or_default();
+        let from_txt = book.write().await.accept_txt(&domain, &records);
+        let outcome = match from_txt {
+            Ok(_) => Ok(()),
+            Err(e) if signed => Err(e.to_string()),
+            Err(e) => match lookup_srv(resolver, &domain).await {
+                Ok(peers) if !peers.is_empty() => book.write().await.accept_srv(&domain, peers).map(|_| ()).map_err(str::to_string),
+                Ok(_) => Err(e.to_string()),
+                Err(srv_error) => Err(format!("{}; SRV lookup failed: {}", e, srv_error)),
+            },
+        };
+        if let Err(e) = outcome {
+            book.write().await.fail(&domain, e.clone());
+            errors.push(format!("{}: {}", domain, e));
+        }
+    }
+    errors
+}
+
+async fn lookup_txt(resolver: &TokioAsyncResolver, domain: &str) -> Result<Vec<String>, String> {
+    let lookup = resolver.txt_lookup(domain).await.map_err(|e| e.to_string())?;
+    // A TXT record longer than 255 bytes arrives in several strings
+    Ok(lookup.iter()
+        .map(|txt| txt.txt_data().iter().map(|chunk| String::from_utf8_lossy(chunk)).collect())
+        .collect())
+}
+
+/// SRV targets by priority, then weight, each resolved to its addresses
+async fn lookup_srv(resolver: &TokioAsyncResolver, domain: &str) -> Result<Vec<SocketAddr>, String> {
+    let lookup = resolver.srv_lookup(format!("{}.{}", SRV_SERVICE, domain)).await.map_err(|e| e.to_string())?;
+    let mut targets: Vec<_> = lookup.iter()
+        .map(|srv| (srv.priority(), Reverse(srv.weight()), srv.target().to_utf8(), srv.port()))
+        .collect();
+    targets.sort();
+    let mut peers = Vec::new();
+    for (_, _, target, port) in targets {
+        if let Ok(ips) = resolver.lookup_ip(target.as_str()).await {
+            peers.extend(ips.iter().map(|ip| SocketAddr::new(ip, port)));
+        }
+    }
+    Ok(peers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DnsSeed;
+
+    fn config(dns: Vec<DnsSeed>) -> SeedConfig {
+        SeedConfig { dns, bootstrap: vec!["bootnode.example:30303".to_string()], refresh_secs: 60 }
+    }
+
+    fn record(sequence: u64, peers: &[&str]) -> SeedRecord {
+        SeedRecord { sequence, peers: peers.iter().map(|peer| peer.parse().unwrap()).collect() }
+    }
+
+    #[test]
+    fn test_signed_seed_records() {
+        let operator = SigningKey::from_bytes(&[3u8; 32]);
+        let seed = DnsSeed {
+            domain: "Seed.Example.".to_string(),
+            public_key: Some(hex::encode(operator.verifying_key().to_bytes())),
+        };
+        let mut book = SeedBook::new(1, &config(vec![seed])).unwrap();
+
+        let current = record(5, &["203.0.113.7:30303", "[2001:db8::7]:30303"]);
+        let txt = current.sign(&operator, 1, "seed.example.");
+        assert_eq!(SeedRecord::parse(&txt).unwrap().0, current);
+        let records = vec!["v=spf1 -all".to_string(), txt];
+        assert_eq!(book.accept_txt("seed.example", &records), Ok(2));
+
+        // Unsigned, signed by another key, for another network or domain
+        let other = SigningKey::from_bytes(&[4u8; 32]);
+        let forged = [
+            record(6, &["198.51.100.1:30303"]).to_txt(),
+            record(6, &["198.51.100.1:30303"]).sign(&other, 1, "seed.example"),
+            record(6, &["198.51.100.1:30303"]).sign(&operator, 2, "seed.example"),
+            record(6, &["198.51.100.1:30303"]).sign(&operator, 1, "other.example"),
+        ];
+        for txt in forged {
+            assert!(book.accept_txt("seed.example", &[txt]).is_err());
+        }
+        let replayed = record(4, &["198.51.100.1:30303"]).sign(&operator, 1, "seed.example");
+        assert_eq!(book.accept_txt("seed.example", &[replayed]), Err("Seed list is older than the one already accepted"));
+        assert!(book.accept_srv("seed.example", vec!["198.51.100.1:30303".parse().unwrap()]).is_err());
+        assert_eq!(book.bootstrap_peers(), vec!["203.0.113.7:30303", "[2001:db8::7]:30303", "bootnode.example:30303"]);
+    }
+
+    #[test]
+    fn test_fallback_order_and_reload() {
+        let seeds = vec![
+            DnsSeed { domain: "a.example".to_string(), public_key: None },
+            DnsSeed { domain: "b.example".to_string(), public_key: None },
+        ];
+        let mut book = SeedBook::new(1, &config(seeds.clone())).unwrap();
+        assert_eq!(book.bootstrap_peers(), vec!["bootnode.example:30303"]);
+
+        book.accept_txt("a.example", &[record(1, &["10.0.0.1:30303"]).to_txt()]).unwrap();
+        book.accept_srv("b.example", vec!["10.0.0.2:30303".parse().unwrap(), "10.0.0.1:30303".parse().unwrap()]).unwrap();
+        assert_eq!(book.bootstrap_peers(), vec!["10.0.0.1:30303", "10.0.0.2:30303", "bootnode.example:30303"]);
+
+        // A failed seed's last list moves behind the fresh ones
+        book.fail("a.example", "timed out".to_string());
+        assert_eq!(book.bootstrap_peers(), vec!["10.0.0.2:30303", "10.0.0.1:30303", "bootnode.example:30303"]);
+        assert_eq!(book.status()[0].error.as_deref(), Some("timed out"));
+
+        // Hot-added seed starts empty, kept seeds keep their lists
+        let mut reloaded = config(vec![DnsSeed { domain: "c.example".to_string(), public_key: None }, seeds[1].clone()]);
+        reloaded.bootstrap.clear();
+        book.reconfigure(&reloaded).unwrap();
+        assert_eq!(book.domains(), vec![("c.example".to_string(), false), ("b.example".to_string(), false)]);
+        assert_eq!(book.bootstrap_peers(), vec!["10.0.0.2:30303", "10.0.0.1:30303"]);
+        assert_eq!(book.accept_txt("a.example", &[]), Err("Unknown seed"));
+    }
+}
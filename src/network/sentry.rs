@@ -0,0 +1,247 @@
+//! Validator/sentry topology.
+//!
+//! A validator configured with `sentry.role = "validator"` peers only with
+//! its own sentries and never appears on the public network. Sentries are
+//! ordinary public nodes that also hold private links to the validators
+//! behind them: blocks and votes from a validator go out to the public
+//! network through its sentries, and public consensus traffic reaches the
+//! validator the same way. Each side checks the health of its private peers
+//! so operators notice before a validator is cut off.
+
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use crate::config::{SentryConfig, SentryRole};
+
+/// Message types that travel over sentry links
+pub const CONSENSUS_MESSAGES: [&str; 2] = ["block", "vote"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PeerHealth {
+    Up,
+    /// Missed some checks but not yet `max_missed_checks`
+    Degraded,
+    /// Unreachable, or not reached since start
+    Down,
+}
+
+/// Health of one private peer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PrivatePeerStatus {
+    pub address: SocketAddr,
+    pub health: PeerHealth,
+    /// Last successful check or message, in seconds
+    pub last_seen: Option<u64>,
+    pub missed_checks: u32,
+    pub latency_ms: Option<u64>,
+}
+
+/// Where a consensus message should go next
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RelayPlan {
+    /// Private peers to forward to
+    pub private: Vec<SocketAddr>,
+    /// Whether to gossip to public peers
+    pub public: bool,
+}
+
+/// Sentry Set
+/// A node's private peers, what they may do and how healthy they are.
+#[derive(Debug, Clone)]
+pub struct SentrySet {
+    role: SentryRole,
+    peers: BTreeMap<SocketAddr, PrivatePeerStatus>,
+    max_missed: u32,
+}
+
+impl SentrySet {
+    pub fn new(config: &SentryConfig) -> Result<Self, String> {
+        let peers = config.private_addrs()?.into_iter()
+            .map(|address| (address, PrivatePeerStatus {
+                address,
+                health: PeerHealth::Down,
+                last_seen: None,
+                missed_checks: 0,
+                latency_ms: None,
+            }))
+            .collect();
+        Ok(Self { role: config.role, peers, max_missed: config.max_missed_checks.max(1) })
+    }
+
+    pub fn role(&self) -> SentryRole {
+        self.role
+    }
+
+    pub fn addrs(&self) -> Vec<SocketAddr> {
+        self.peers.keys().copied().collect()
+    }
+
+    /// Whether `address` belongs to a private peer. Inbound connections
+    /// arrive from ephemeral ports, so only the IP is compared.
+    pub fn is_private(&self, address: &str) -> bool {
+        ip_of(address).is_some_and(|ip| self.peers.keys().any(|peer| peer.ip() == ip))
+    }
+
+    /// Whether a connection with `address` is allowed: a validator talks to
+    /// its sentries only, a sentry to anyone
+    pub fn admits(&self, address: &str) -> bool {
+        match self.role {
+            SentryRole::Validator => self.is_private(address),
+            SentryRole::Sentry => true,
+        }
+    }
+
+    /// Nodes to dial at startup. A validator dials only its sentries; a
+    /// sentry dials the public bootstrap nodes and its validators.
+    pub fn bootstrap(&self, public: &[String]) -> Vec<String> {
+        let private = self.peers.keys().map(|address| address.to_string());
+        match self.role {
+            SentryRole::Validator => private.collect(),
+            SentryRole::Sentry => public.iter().cloned().chain(private).collect(),
+        }
+    }
+
+    /// Whether `address` may be shared with other peers. Validators behind
+    /// sentries are never advertised.
+    pub fn advertisable(&self, address: &str) -> bool {
+        !(self.role == SentryRole::Sentry && self.is_private(address))
+    }
+
+    /// Route a consensus message received from `from`, or produced locally
+    /// when `from` is `None`. Other message types are not routed here.
+    pub fn relay(&self, from: Option<&str>, message_type: &str) -> RelayPlan {
+        if !CONSENSUS_MESSAGES.contains(&message_type) {
+            return RelayPlan::default();
+        }
+        let from_private = from.is_some_and(|address| self.is_private(address));
+        let targets = |exclude: Option<&str>| -> Vec<SocketAddr> {
+            let exclude = exclude.and_then(ip_of);
+            let healthy = self.healthy();
+            // With nothing known healthy, try every link rather than none
+            let candidates = if healthy.is_empty() { self.addrs() } else { healthy };
+            candidates.into_iter().filter(|peer| Some(peer.ip()) != exclude).collect()
+        };
+        match (self.role, from) {
+            // A validator's own blocks and votes go to all its sentries
+            (SentryRole::Validator, None) => RelayPlan { private: targets(None), public: false },
+            // ...and what its sentries bring in stops at the validator
+            (SentryRole::Validator, Some(_)) => RelayPlan::default(),
+            // A sentry publishes what its validators produce and shares it
+            // with any other validators it guards
+            (SentryRole::Sentry, Some(address)) if from_private => RelayPlan { private: targets(Some(address)), public: true },
+            (SentryRole::Sentry, _) => RelayPlan { private: targets(None), public: from.is_none() },
+        }
+    }
+
+    /// Record a successful check of, or message from, a private peer
+    pub fn record_seen(&mut self, address: &str, now: u64, latency_ms: Option<u64>) {
+        let Some(ip) = ip_of(address) else { return };
+        for peer in self.peers.values_mut().filter(|peer| peer.address.ip() == ip) {
+            peer.health = PeerHealth::Up;
+            peer.last_seen = Some(now);
+            peer.missed_checks = 0;
+            if latency_ms.is_some() {
+                peer.latency_ms = latency_ms;
+            }
+        }
+    }
+
+    /// Record a failed check. Returns the peer's health if it changed.
+    pub fn record_missed(&mut self, address: &SocketAddr) -> Option<PeerHealth> {
+        let peer = self.peers.get_mut(address)?;
+        peer.missed_checks = peer.missed_checks.saturating_add(1);
+        let health = if peer.last_seen.is_none() || peer.missed_checks >= self.max_missed {
+            PeerHealth::Down
+        } else {
+            PeerHealth::Degraded
+        };
+        let changed = health != peer.health;
+        peer.health = health;
+        changed.then_some(health)
+    }
+
+    /// Private peers that are up or only degraded
+    pub fn healthy(&self) -> Vec<SocketAddr> {
+        self.peers.values().filter(|peer| peer.health != PeerHealth::Down).map(|peer| peer.address).collect()
+    }
+
+    pub fn status(&self) -> Vec<PrivatePeerStatus> {
+        self.peers.values().cloned().collect()
+    }
+}
+
+pub(crate) fn ip_of(address: &str) -> Option<IpAddr> {
+    address.parse::<SocketAddr>().map(|address| address.ip())
+        .or_else(|_| address.parse::<IpAddr>())
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(role: SentryRole, peers: &[&str]) -> SentrySet {
+        SentrySet::new(&SentryConfig {
+            role,
+            private_peers: peers.iter().map(|peer| peer.to_string()).collect(),
+            health_interval_secs: 10,
+            max_missed_checks: 2,
+        }).unwrap()
+    }
+
+    #[test]
+    fn test_validator_only_talks_to_sentries() {
+        let mut validator = set(SentryRole::Validator, &["10.0.0.2:30303", "10.0.0.3:30303"]);
+        assert!(validator.admits("10.0.0.2:51234"));
+        assert!(!validator.admits("203.0.113.9:30303"));
+        assert_eq!(validator.bootstrap(&["seed.example:30303".to_string()]), vec!["10.0.0.2:30303", "10.0.0.3:30303"]);
+
+        // Nothing checked yet: blocks go to every sentry
+        assert_eq!(validator.relay(None, "block").private.len(), 2);
+        validator.record_seen("10.0.0.3:30303", 5, Some(12));
+        let plan = validator.relay(None, "vote");
+        assert_eq!(plan.private, vec!["10.0.0.3:30303".parse().unwrap()]);
+        assert!(!plan.public);
+        assert_eq!(validator.relay(Some("10.0.0.3:40000"), "block"), RelayPlan::default());
+        assert_eq!(validator.relay(None, "transaction"), RelayPlan::default());
+    }
+
+    #[test]
+    fn test_sentry_relays_and_hides_validators() {
+        let sentry = set(SentryRole::Sentry, &["10.0.0.1:30303", "10.0.0.5:30303"]);
+        assert!(sentry.admits("203.0.113.9:30303"));
+        assert!(!sentry.advertisable("10.0.0.1:30303"));
+        assert!(sentry.advertisable("203.0.113.9:30303"));
+        assert_eq!(sentry.bootstrap(&["seed.example:30303".to_string()]).len(), 3);
+
+        // From a validator: publish, and pass to the other validator
+        let plan = sentry.relay(Some("10.0.0.1:45000"), "block");
+        assert!(plan.public);
+        assert_eq!(plan.private, vec!["10.0.0.5:30303".parse().unwrap()]);
+        // From the public network: into the validators only
+        let plan = sentry.relay(Some("203.0.113.9:30303"), "vote");
+        assert!(!plan.public);
+        assert_eq!(plan.private.len(), 2);
+    }
+
+    #[test]
+    fn test_health_transitions() {
+        let mut validator = set(SentryRole::Validator, &["10.0.0.2:30303"]);
+        let sentry: SocketAddr = "10.0.0.2:30303".parse().unwrap();
+        // Never seen: stays down without reporting a change
+        assert_eq!(validator.record_missed(&sentry), None);
+        assert!(validator.healthy().is_empty());
+
+        validator.record_seen("10.0.0.2:30303", 100, Some(8));
+        assert_eq!(validator.healthy(), vec![sentry]);
+        assert_eq!(validator.record_missed(&sentry), Some(PeerHealth::Degraded));
+        assert_eq!(validator.healthy(), vec![sentry]);
+        assert_eq!(validator.record_missed(&sentry), Some(PeerHealth::Down));
+        assert_eq!(validator.record_missed(&sentry), None);
+        assert!(validator.healthy().is_empty());
+
+        let status = &validator.status()[0];
+        assert_eq!((status.last_seen, status.missed_checks, status.latency_ms), (Some(100), 3, Some(8)));
+    }
+}
@@ -0,0 +1,366 @@
+//! Per-layer access control.
+//!
+//! A reality layer may declare who can observe it: anyone, an allowlist of
+//! identities, observers with enough bonded stake, or identities that prove
+//! a predicate over a committed attribute without revealing it. The
+//! orchestrator checks the layer's policy before it accepts an observation
+//! or a presence announcement. Layers nobody declared are public.
+//!
+//! The orchestrator does not look up identities, stake or proofs itself.
+//! The caller gathers what the observer has shown into `AccessCredentials`:
+//! the node sets `identity` only when that identity's `signing_key` is the
+//! observer's key, `stake` from the observer's provider bond, and `proven`
+//! from predicate proofs it has just verified for that identity.
+//!
+//! Governance declares a layer (`orchestration.declare_layer`) with its
+//! controller and first policy. An owner-controlled layer changes policy
+//! with a `PolicyChange` signed by the owner's key, and may hand the layer
+//! to a DAO of its own (`governance::dao`) that changes it by executed
+//! proposal. A layer under network governance changes policy only by an
+//! `orchestration.set_layer_policy` action.
+
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeSet, HashMap};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use num_traits::ToPrimitive;
+use crate::blockchain::types::{hex_serde, Address};
+use crate::crypto::domain::{PayloadKind, SigningDomain};
+use crate::governance::ai_governance::Action;
+use crate::identity::predicate::Predicate;
+use crate::ids::IdentityId;
+use crate::math::precision::PreciseFloat;
+
+pub const DECLARE_LAYER_ACTION: &str = "orchestration.declare_layer";
+pub const SET_POLICY_ACTION: &str = "orchestration.set_layer_policy";
+
+/// Who may observe a layer and announce presence in it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum AccessPolicy {
+    Public,
+    Allowlist { identities: BTreeSet<IdentityId> },
+    /// Observers whose bond is at least `minimum`
+    StakeGated { minimum: PreciseFloat },
+    /// Identities that prove `predicate` over their committed attribute
+    AttributeGated { predicate: Predicate },
+}
+
+impl AccessPolicy {
+    pub fn check(&self, credentials: &AccessCredentials) -> Result<(), &'static str> {
+        match self {
+            AccessPolicy::Public => Ok(()),
+            AccessPolicy::Allowlist { identities } => match credentials.identity {
+                Some(identity) if identities.contains(&identity) => Ok(()),
+                _ => Err("Identity is not on the layer's allowlist"),
+            },
+            AccessPolicy::StakeGated { minimum } => match &credentials.stake {
+                Some(stake) if amount(stake) >= amount(minimum) => Ok(()),
+                _ => Err("Bonded stake is below the layer's minimum"),
+            },
+            AccessPolicy::AttributeGated { predicate } => match credentials.proven.contains(predicate) {
+                true => Ok(()),
+                false => Err("Layer requires a proof of its attribute predicate"),
+            },
+        }
+    }
+}
+
+/// Who may change a layer's policy
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum LayerController {
+    /// The holder of `key`, by signed `PolicyChange`
+    Owner {
+        #[serde(with = "hex_serde")]
+        key: Address,
+    },
+    /// Network governance only
+    Dao,
+    /// The layer's own DAO, by executed proposal
+    LayerDao,
+}
+
+/// A declared layer's controller and current policy
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LayerAccess {
+    pub controller: LayerController,
+    pub policy: AccessPolicy,
+    /// Bumped on every change; an owner signs the next version
+    pub version: u64,
+}
+
+/// What an observer has shown toward a layer's policy
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccessCredentials {
+    /// Identity the observer holds the signing key of
+    pub identity: Option<IdentityId>,
+    /// The observer's bonded stake
+    pub stake: Option<PreciseFloat>,
+    /// Predicates proven for `identity`
+    pub proven: Vec<Predicate>,
+}
+
+/// Owner's signed change to a layer's policy
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyChange {
+    pub layer_id: u32,
+    pub policy: AccessPolicy,
+    /// The layer's current version plus one
+    pub version: u64,
+    #[serde(with = "hex_serde")]
+    pub signature: Vec<u8>,
+}
+
+impl PolicyChange {
+    pub fn sign(owner: &SigningKey, layer_id: u32, policy: AccessPolicy, version: u64, network_id: u64) -> Self {
+        let mut change = Self { layer_id, policy, version, signature: Vec::new() };
+        change.signature = owner.sign(&change.signing_bytes(network_id)).to_bytes().to_vec();
+        change
+    }
+
+    /// Bytes the owner signs
+    pub fn signing_bytes(&self, network_id: u64) -> Vec<u8> {
+        let mut body = vec![0]; // policy change
+        body.extend_from_slice(&self.layer_id.to_le_bytes());
+        body.extend_from_slice(&bincode::serialize(&self.policy).unwrap_or_default());
+        SigningDomain::main_chain(network_id).payload(PayloadKind::LayerAccess, self.version, &body)
+    }
+}
+
+/// Observer's signed announcement that it is present in a layer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PresenceAnnouncement {
+    pub layer_id: u32,
+    #[serde(with = "hex_serde")]
+    pub observer_id: [u8; 32],
+    pub announced_at: u64,
+    #[serde(with = "hex_serde")]
+    pub signature: Vec<u8>,
+}
+
+impl PresenceAnnouncement {
+    pub fn sign(observer: &SigningKey, layer_id: u32, announced_at: u64, network_id: u64) -> Self {
+        let mut announcement = Self {
+            layer_id,
+            observer_id: observer.verifying_key().to_bytes(),
+            announced_at,
+            signature: Vec::new(),
+        };
+        announcement.signature = observer.sign(&announcement.signing_bytes(network_id)).to_bytes().to_vec();
+        announcement
+    }
+
+    /// Bytes the observer signs
+    pub fn signing_bytes(&self, network_id: u64) -> Vec<u8> {
+        let mut body = vec![1]; // presence
+        body.extend_from_slice(&self.layer_id.to_le_bytes());
+        body.extend_from_slice(&self.observer_id);
+        body.extend_from_slice(&self.announced_at.to_le_bytes());
+        SigningDomain::main_chain(network_id).payload(PayloadKind::LayerAccess, 0, &body)
+    }
+
+    pub fn verify(&self, network_id: u64) -> Result<(), &'static str> {
+        verify_signature(&self.observer_id, &self.signing_bytes(network_id), &self.signature)
+            .map_err(|_| "Invalid presence signature")
+    }
+}
+
+/// Payload of `orchestration.declare_layer`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LayerDeclaration {
+    pub layer_id: u32,
+    pub controller: LayerController,
+    pub policy: AccessPolicy,
+}
+
+/// Payload of `orchestration.set_layer_policy`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyUpdate {
+    pub layer_id: u32,
+    pub policy: AccessPolicy,
+}
+
+/// Access Registry
+/// Declared layers with their controllers and policies.
+#[derive(Debug, Clone, Default)]
+pub struct AccessRegistry {
+    layers: HashMap<u32, LayerAccess>,
+}
+
+impl AccessRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, layer_id: u32) -> Option<&LayerAccess> {
+        self.layers.get(&layer_id)
+    }
+
+    /// Check `credentials` against the layer's policy; undeclared layers are public
+    pub fn check(&self, layer_id: u32, credentials: &AccessCredentials) -> Result<(), &'static str> {
+        match self.layers.get(&layer_id) {
+            Some(access) => access.policy.check(credentials),
+            None => Ok(()),
+        }
+    }
+
+    pub fn declare(&mut self, layer_id: u32, controller: LayerController, policy: AccessPolicy) -> Result<(), &'static str> {
+        if self.layers.contains_key(&layer_id) {
+            return Err("Layer is already declared");
+        }
+        self.layers.insert(layer_id, LayerAccess { controller, policy, version: 0 });
+        Ok(())
+    }
+
+    /// Apply an owner's signed policy change
+    pub fn apply_change(&mut self, change: &PolicyChange, network_id: u64) -> Result<(), &'static str> {
+        let access = self.layers.get_mut(&change.layer_id).ok_or("Layer is not declared")?;
+        let LayerController::Owner { key } = &access.controller else {
+            return Err("Layer policy is not set by an owner");
+        };
+        if change.version != access.version + 1 {
+            return Err("Policy change is not for the layer's next version");
+        }
+        verify_signature(key, &change.signing_bytes(network_id), &change.signature)
+            .map_err(|_| "Invalid policy change signature")?;
+        access.policy = change.policy.clone();
+        access.version = change.version;
+        Ok(())
+    }
+
+    /// Hand an owner's layer to its DAO; `owner` must be the layer's owner key
+    pub fn hand_to_dao(&mut self, layer_id: u32, owner: &Address) -> Result<(), &'static str> {
+        let access = self.layers.get_mut(&layer_id).ok_or("Layer is not declared")?;
+        if access.controller != (LayerController::Owner { key: *owner }) {
+            return Err("Only the layer's owner can hand it to a DAO");
+        }
+        access.controller = LayerController::LayerDao;
+        access.version += 1;
+        Ok(())
+    }
+
+    /// Apply a policy from an executed proposal of the layer's DAO
+    pub fn apply_dao_policy(&mut self, layer_id: u32, policy: AccessPolicy) -> Result<(), &'static str> {
+        let access = self.layers.get_mut(&layer_id).ok_or("Layer is not declared")?;
+        if access.controller != LayerController::LayerDao {
+            return Err("Layer is not controlled by a DAO of its own");
+        }
+        access.policy = policy;
+        access.version += 1;
+        Ok(())
+    }
+
+    /// Apply a governance action. Returns the layer it changed, or `None`
+    /// if the action was not one of the registry's.
+    pub fn apply_governance(&mut self, action: &Action) -> Result<Option<u32>, &'static str> {
+        let Action::Custom(name, payload) = action else { return Ok(None) };
+        match name.as_str() {
+            DECLARE_LAYER_ACTION => {
+                let declaration: LayerDeclaration = serde_json::from_slice(payload).map_err(|_| "Invalid layer declaration")?;
+                self.declare(declaration.layer_id, declaration.controller, declaration.policy)?;
+                Ok(Some(declaration.layer_id))
+            }
+            SET_POLICY_ACTION => {
+                let update: PolicyUpdate = serde_json::from_slice(payload).map_err(|_| "Invalid layer policy update")?;
+                let access = self.layers.get_mut(&update.layer_id).ok_or("Layer is not declared")?;
+                if access.controller != LayerController::Dao {
+                    return Err("Layer policy is set by its owner");
+                }
+                access.policy = update.policy;
+                access.version += 1;
+                Ok(Some(update.layer_id))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+fn verify_signature(key: &[u8; 32], message: &[u8], signature: &[u8]) -> Result<(), ()> {
+    let key = VerifyingKey::from_bytes(key).map_err(|_| ())?;
+    let signature = Signature::from_slice(signature).map_err(|_| ())?;
+    key.verify(message, &signature).map_err(|_| ())
+}
+
+fn amount(value: &PreciseFloat) -> f64 {
+    value.to_f64().unwrap_or(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policies_and_controllers() {
+        let owner = SigningKey::from_bytes(&[5u8; 32]);
+        let member = IdentityId::new([1u8; 32]);
+        let adult = Predicate::AtLeast { attribute: "age".to_string(), value: 18 };
+        let mut registry = AccessRegistry::new();
+        assert!(registry.check(1, &AccessCredentials::default()).is_ok());
+
+        let controller = LayerController::Owner { key: owner.verifying_key().to_bytes() };
+        let allowlist = AccessPolicy::Allowlist { identities: [member].into_iter().collect() };
+        registry.declare(1, controller.clone(), allowlist).unwrap();
+        assert!(registry.declare(1, controller, AccessPolicy::Public).is_err());
+        let credentials = AccessCredentials { identity: Some(member), ..Default::default() };
+        assert!(registry.check(1, &credentials).is_ok());
+        assert!(registry.check(1, &AccessCredentials::default()).is_err());
+
+        // Only the owner's signature over the next version changes the policy
+        let stranger = SigningKey::from_bytes(&[6u8; 32]);
+        let gated = AccessPolicy::StakeGated { minimum: PreciseFloat::new(100_000, 2) };
+        assert!(registry.apply_change(&PolicyChange::sign(&stranger, 1, gated.clone(), 1, 1), 1).is_err());
+        assert!(registry.apply_change(&PolicyChange::sign(&owner, 1, gated.clone(), 2, 1), 1).is_err());
+        let change = PolicyChange::sign(&owner, 1, gated, 1, 1);
+        registry.apply_change(&change, 1).unwrap();
+        assert!(registry.apply_change(&change, 1).is_err(), "A change cannot be replayed");
+        let staked = |stake| AccessCredentials { stake: Some(PreciseFloat::new(stake, 2)), ..Default::default() };
+        assert!(registry.check(1, &staked(100_000)).is_ok());
+        assert_eq!(registry.check(1, &staked(99_999)), Err("Bonded stake is below the layer's minimum"));
+
+        // A DAO layer changes only by governance
+        let declare = LayerDeclaration { layer_id: 2, controller: LayerController::Dao, policy: AccessPolicy::Public };
+        let action = Action::Custom(DECLARE_LAYER_ACTION.to_string(), serde_json::to_vec(&declare).unwrap());
+        assert_eq!(registry.apply_governance(&action), Ok(Some(2)));
+        let update = PolicyUpdate { layer_id: 2, policy: AccessPolicy::AttributeGated { predicate: adult.clone() } };
+        let action = Action::Custom(SET_POLICY_ACTION.to_string(), serde_json::to_vec(&update).unwrap());
+        assert_eq!(registry.apply_governance(&action), Ok(Some(2)));
+        assert!(registry.check(2, &credentials).is_err());
+        let proven = AccessCredentials { identity: Some(member), proven: vec![adult], ..Default::default() };
+        assert!(registry.check(2, &proven).is_ok());
+        let owned = PolicyUpdate { layer_id: 1, policy: AccessPolicy::Public };
+        let action = Action::Custom(SET_POLICY_ACTION.to_string(), serde_json::to_vec(&owned).unwrap());
+        assert_eq!(registry.apply_governance(&action), Err("Layer policy is set by its owner"));
+    }
+
+    #[test]
+    fn test_orchestrator_enforces_policy() {
+        use crate::orchestration::Orchestrator;
+
+        let owner = SigningKey::from_bytes(&[5u8; 32]);
+        let member = IdentityId::new([1u8; 32]);
+        let mut orchestrator = Orchestrator::new(PreciseFloat::new(90, 2));
+        let declare = LayerDeclaration {
+            layer_id: 7,
+            controller: LayerController::Owner { key: owner.verifying_key().to_bytes() },
+            policy: AccessPolicy::Allowlist { identities: [member].into_iter().collect() },
+        };
+        let action = Action::Custom(DECLARE_LAYER_ACTION.to_string(), serde_json::to_vec(&declare).unwrap());
+        assert_eq!(orchestrator.apply_governance(&action), Ok(true));
+
+        let allowed = AccessCredentials { identity: Some(member), ..Default::default() };
+        let stranger = AccessCredentials::default();
+        assert!(orchestrator.announce_presence(7, [1u8; 32], &stranger).is_err());
+        assert!(orchestrator.register_observation(7, [1u8; 32], [3u8; 64], PreciseFloat::new(80, 2), &stranger).is_err());
+        assert!(orchestrator.record_quantum_state([1u8; 32], vec![1, 2], 7, &stranger, HashMap::new()).is_err());
+        assert_eq!(orchestrator.announce_presence(7, [1u8; 32], &allowed), Ok(1));
+        assert_eq!(orchestrator.announce_presence(8, [1u8; 32], &stranger), Ok(1));
+        assert_eq!(orchestrator.get_metrics().active_observers, 1);
+
+        // A policy change drops presence announced under the old policy
+        let change = PolicyChange::sign(&owner, 7, AccessPolicy::Public, 1, 1);
+        orchestrator.set_layer_policy(&change, 1).unwrap();
+        assert_eq!(orchestrator.get_layer_state(7).unwrap().observer_count, 0);
+        assert_eq!(orchestrator.announce_presence(7, [2u8; 32], &stranger), Ok(1));
+        assert_eq!(orchestrator.get_metrics().active_observers, 2);
+    }
+}
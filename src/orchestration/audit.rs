@@ -0,0 +1,272 @@
+//! Consensus audit trail.
+//!
+//! Every time the orchestrator weighs the votes on a quantum tally it keeps
+//! a `ConsensusEvaluation`: the vote set as it stood, the summed weight of
+//! each observed state and the threshold it was held to. Evaluations can be
+//! stored in the node database and re-run later with `replay`, so a disputed
+//! consensus outcome can be checked step by step against the votes that
+//! produced it.
+
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use num_traits::ToPrimitive;
+use crate::blockchain::types::{hex_serde, hex_serde_vec};
+use crate::math::precision::PreciseFloat;
+use super::QuantumVote;
+
+/// Votes a tally needs before consensus is attempted
+pub const MIN_OBSERVERS: usize = 3;
+
+/// Share of the total confidence the leading state needs, in percent
+pub const CONSENSUS_THRESHOLD_PERCENT: i128 = 75;
+
+/// Decimal places weights are summed and compared at
+pub const WEIGHT_SCALE: u8 = 18;
+
+/// Decimal places of the reported confidence
+const CONFIDENCE_SCALE: u8 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConsensusOutcome {
+    /// Fewer than `MIN_OBSERVERS` votes
+    InsufficientVotes,
+    /// The leading state fell short of the threshold
+    BelowThreshold,
+    Reached,
+}
+
+/// Summed confidence behind one observed state
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateWeight {
+    #[serde(with = "hex_serde")]
+    pub state: Vec<u8>,
+    pub weight: PreciseFloat,
+    #[serde(with = "hex_serde_vec")]
+    pub observers: Vec<[u8; 32]>,
+}
+
+/// Consensus Evaluation
+/// One weighing of a tally's votes, with every intermediate value.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConsensusEvaluation {
+    #[serde(with = "hex_serde")]
+    pub state_hash: [u8; 32],
+    /// When the evaluation ran, in seconds
+    pub evaluated_at: u64,
+    /// Votes as they stood, ordered by observer
+    pub votes: Vec<QuantumVote>,
+    /// Weight per observed state, heaviest first; ties go to the lower state
+    pub state_weights: Vec<StateWeight>,
+    pub total_confidence: PreciseFloat,
+    /// Weight the leading state needed
+    pub threshold: PreciseFloat,
+    pub outcome: ConsensusOutcome,
+    /// Leading weight over total confidence, once reached
+    pub confidence: Option<PreciseFloat>,
+}
+
+impl ConsensusEvaluation {
+    /// The state consensus settled on, if it was reached
+    pub fn final_state(&self) -> Option<&[u8]> {
+        match self.outcome {
+            ConsensusOutcome::Reached => self.state_weights.first().map(|leader| leader.state.as_slice()),
+            _ => None,
+        }
+    }
+
+    /// Human-readable walk through the evaluation
+    pub fn explain(&self) -> Vec<String> {
+        let number = |value: &PreciseFloat| format!("{:.6}", value.to_f64().unwrap_or(f64::NAN));
+        let mut lines = vec![format!("tally {} at {}: {} vote(s)", hex::encode(self.state_hash), self.evaluated_at, self.votes.len())];
+        for vote in &self.votes {
+            lines.push(format!(
+                "  observer {} weight {} for state {}",
+                hex::encode(vote.observer_id),
+                number(&vote.confidence),
+                short_hex(&vote.observed_state),
+            ));
+        }
+        if self.outcome == ConsensusOutcome::InsufficientVotes {
+            lines.push(format!("  {} vote(s) < {} required: no consensus attempted", self.votes.len(), MIN_OBSERVERS));
+            return lines;
+        }
+        for weight in &self.state_weights {
+            lines.push(format!("  state {} total weight {} from {} observer(s)", short_hex(&weight.state), number(&weight.weight), weight.observers.len()));
+        }
+        lines.push(format!(
+            "  threshold = total {} x {}% = {}",
+            number(&self.total_confidence),
+            CONSENSUS_THRESHOLD_PERCENT,
+            number(&self.threshold),
+        ));
+        let leader = self.state_weights.first().map(|leader| number(&leader.weight)).unwrap_or_else(|| "none".to_string());
+        lines.push(match (&self.outcome, &self.confidence) {
+            (ConsensusOutcome::Reached, Some(confidence)) => {
+                format!("  leading weight {} >= threshold: reached with confidence {}", leader, number(confidence))
+            }
+            _ => format!("  leading weight {} < threshold: not reached", leader),
+        });
+        lines
+    }
+}
+
+fn short_hex(bytes: &[u8]) -> String {
+    let encoded = hex::encode(bytes);
+    if encoded.len() > 16 { format!("{}..", &encoded[..16]) } else { encoded }
+}
+
+/// `value` as an integer at `scale`, rounding toward negative infinity
+fn to_scale(value: &PreciseFloat, scale: u8) -> i128 {
+    let (from, to) = (value.scale as u32, scale as u32);
+    if from >= to {
+        value.value.div_euclid(10_i128.pow(from - to))
+    } else {
+        value.value.saturating_mul(10_i128.pow(to - from))
+    }
+}
+
+fn weight(value: i128) -> PreciseFloat {
+    PreciseFloat { value, scale: WEIGHT_SCALE }
+}
+
+/// Weigh `votes` for the tally `state_hash`. Each state's weight is the sum
+/// of its observers' confidence, so the result does not depend on the order
+/// votes arrive in. Sums and the threshold test are exact integer
+/// arithmetic at `WEIGHT_SCALE`, so a replay anywhere gives the same answer.
+pub fn evaluate(state_hash: [u8; 32], votes: impl IntoIterator<Item = QuantumVote>, evaluated_at: u64) -> ConsensusEvaluation {
+    let mut votes: Vec<QuantumVote> = votes.into_iter().collect();
+    votes.sort_by_key(|vote| vote.observer_id);
+
+    let mut total = 0_i128;
+    let mut by_state: BTreeMap<Vec<u8>, (i128, Vec<[u8; 32]>)> = BTreeMap::new();
+    for vote in &votes {
+        let confidence = to_scale(&vote.confidence, WEIGHT_SCALE);
+        total = total.saturating_add(confidence);
+        let (sum, observers) = by_state.entry(vote.observed_state.clone()).or_default();
+        *sum = sum.saturating_add(confidence);
+        observers.push(vote.observer_id);
+    }
+    let mut state_weights: Vec<StateWeight> = by_state.into_iter()
+        .map(|(state, (sum, observers))| StateWeight { state, weight: weight(sum), observers })
+        .collect();
+    state_weights.sort_by(|a, b| b.weight.value.cmp(&a.weight.value).then_with(|| a.state.cmp(&b.state)));
+
+    let mut confidence = None;
+    let outcome = if votes.len() < MIN_OBSERVERS {
+        ConsensusOutcome::InsufficientVotes
+    } else {
+        match state_weights.first() {
+            Some(leader) if total > 0 && leader.weight.value.saturating_mul(100) >= total.saturating_mul(CONSENSUS_THRESHOLD_PERCENT) => {
+                let ratio = leader.weight.value.saturating_mul(10_i128.pow(CONFIDENCE_SCALE as u32)) / total;
+                confidence = Some(PreciseFloat { value: ratio, scale: CONFIDENCE_SCALE });
+                ConsensusOutcome::Reached
+            }
+            _ => ConsensusOutcome::BelowThreshold,
+        }
+    };
+
+    ConsensusEvaluation {
+        state_hash,
+        evaluated_at,
+        votes,
+        state_weights,
+        total_confidence: weight(total),
+        threshold: weight(total.saturating_mul(CONSENSUS_THRESHOLD_PERCENT) / 100),
+        outcome,
+        confidence,
+    }
+}
+
+/// A recorded evaluation next to a fresh run over the same votes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub recorded: ConsensusEvaluation,
+    pub replayed: ConsensusEvaluation,
+}
+
+impl Replay {
+    /// Whether today's code reaches the same decision with the same math
+    pub fn matches(&self) -> bool {
+        self.recorded == self.replayed
+    }
+}
+
+/// Re-run a recorded evaluation over its own vote set
+pub fn replay(recorded: &ConsensusEvaluation) -> Replay {
+    let replayed = evaluate(recorded.state_hash, recorded.votes.iter().cloned(), recorded.evaluated_at);
+    Replay { recorded: recorded.clone(), replayed }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vote(observer: u8, state: u8, confidence: i128) -> QuantumVote {
+        QuantumVote {
+            observer_id: [observer; 32],
+            observed_state: vec![state; 4],
+            observation_time: 0,
+            confidence: PreciseFloat::new(confidence, 2),
+        }
+    }
+
+    #[test]
+    fn test_weights_are_summed_in_any_order() {
+        let votes = vec![vote(1, 0xA, 50), vote(2, 0xA, 50), vote(3, 0xB, 20), vote(4, 0xA, 40)];
+        let evaluation = evaluate([9; 32], votes.clone(), 10);
+        let reversed = evaluate([9; 32], votes.into_iter().rev(), 10);
+        assert_eq!(evaluation, reversed);
+
+        assert_eq!(evaluation.state_weights[0].state, vec![0xA; 4]);
+        assert_eq!(evaluation.state_weights[0].observers.len(), 3);
+        assert_eq!(evaluation.outcome, ConsensusOutcome::Reached);
+        assert_eq!(evaluation.final_state(), Some(&[0xA; 4][..]));
+        assert!(replay(&evaluation).matches());
+        assert!(evaluation.explain().iter().any(|line| line.contains("reached with confidence")));
+    }
+
+    #[test]
+    fn test_outcomes_short_of_consensus() {
+        let few = evaluate([9; 32], vec![vote(1, 0xA, 90), vote(2, 0xA, 90)], 0);
+        assert_eq!(few.outcome, ConsensusOutcome::InsufficientVotes);
+        assert_eq!(few.final_state(), None);
+
+        let split = evaluate([9; 32], vec![vote(1, 0xA, 50), vote(2, 0xB, 50), vote(3, 0xC, 50)], 0);
+        assert_eq!(split.outcome, ConsensusOutcome::BelowThreshold);
+        // Equal weights order by state
+        assert_eq!(split.state_weights[0].state, vec![0xA; 4]);
+
+        let silent = evaluate([9; 32], vec![vote(1, 0xA, 0), vote(2, 0xA, 0), vote(3, 0xA, 0)], 0);
+        assert_eq!(silent.outcome, ConsensusOutcome::BelowThreshold);
+
+        // A tampered record no longer replays to itself
+        let mut forged = split.clone();
+        forged.outcome = ConsensusOutcome::Reached;
+        assert!(!replay(&forged).matches());
+    }
+
+    #[test]
+    fn test_orchestrator_keeps_evaluations_for_replay() {
+        use crate::orchestration::Orchestrator;
+        use crate::orchestration::access::AccessCredentials;
+
+        let mut orchestrator = Orchestrator::new(PreciseFloat::new(90, 2));
+        let state = [3u8; 64];
+        for observer in 1..=3 {
+            orchestrator.register_observation(1, [observer; 32], state, PreciseFloat::new(80, 2), &AccessCredentials::default()).unwrap();
+        }
+        let state_hash = *orchestrator.state.quantum_tallies.keys().next().unwrap();
+        assert!(orchestrator.get_consensus_state(&state_hash).unwrap().consensus_reached);
+
+        let history = orchestrator.consensus_history(&state_hash);
+        let outcomes: Vec<_> = history.iter().map(|evaluation| evaluation.outcome).collect();
+        assert_eq!(outcomes, vec![ConsensusOutcome::InsufficientVotes, ConsensusOutcome::InsufficientVotes, ConsensusOutcome::Reached]);
+        assert!(orchestrator.replay_consensus(&state_hash, None).unwrap().matches());
+        assert_eq!(orchestrator.replay_consensus(&state_hash, Some(0)).unwrap().recorded.votes.len(), 1);
+        assert!(orchestrator.replay_consensus(&[0; 32], None).is_err());
+
+        assert_eq!(orchestrator.take_unpersisted().len(), 3);
+        assert!(orchestrator.take_unpersisted().is_empty());
+    }
+}
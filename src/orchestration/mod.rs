@@ -1,12 +1,23 @@
+pub mod access;
+pub mod audit;
+pub mod spatial;
 pub mod tally;
 
 use serde::{Serialize, Deserialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use crate::blockchain::types::hex_serde;
 use crate::math::precision::PreciseFloat;
+use crate::clock::{self, SharedClock};
+use crate::governance::ai_governance::Action;
 use num_traits::ToPrimitive;
 
+use self::access::{AccessCredentials, AccessPolicy, AccessRegistry, PolicyChange};
+use self::audit::{ConsensusEvaluation, ConsensusOutcome, Replay};
 use self::tally::{TallyRecorder, TallyMetrics};
 
+/// Consensus evaluations kept in memory; older ones are only in the database
+const MAX_EVALUATIONS: usize = 10_000;
+
 #[derive(Debug, Clone)]
 pub struct OrchestratorState {
     pub reality_layers: HashMap<u32, RealityLayer>,
@@ -35,9 +46,11 @@ pub struct QuantumTally {
     pub confidence_score: PreciseFloat,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct QuantumVote {
+    #[serde(with = "hex_serde")]
     pub observer_id: [u8; 32],
+    #[serde(with = "hex_serde")]
     pub observed_state: Vec<u8>,
     pub observation_time: u64,
     pub confidence: PreciseFloat,
@@ -56,6 +69,15 @@ pub struct Orchestrator {
     state: OrchestratorState,
     tally_recorder: TallyRecorder,
     coherence_threshold: PreciseFloat,
+    clock: SharedClock,
+    /// Recent consensus evaluations, oldest first
+    evaluations: VecDeque<ConsensusEvaluation>,
+    /// How many of `evaluations` have not been handed out for persisting
+    unpersisted: usize,
+    /// Declared layers' access policies
+    access: AccessRegistry,
+    /// Observers that announced presence in each layer under its current policy
+    presence: HashMap<u32, HashSet<[u8; 32]>>,
 }
 
 impl Orchestrator {
@@ -82,13 +104,18 @@ impl Orchestrator {
         (amplitudes, phases)
     }
 
+    /// Record an observation of `reality_layer` if `credentials` satisfy
+    /// the layer's access policy
     pub fn record_quantum_state(
         &mut self,
         _observer_id: [u8; 32],
         quantum_state: Vec<u8>,
         reality_layer: u32,
+        credentials: &AccessCredentials,
         _metadata: HashMap<String, String>,
     ) -> Result<PreciseFloat, &'static str> {
+        self.access.check(reality_layer, credentials)?;
+
         // Convert quantum state to amplitudes and phases
         let (amplitudes, phases) = self.convert_quantum_state(quantum_state);
         
@@ -101,6 +128,11 @@ impl Orchestrator {
     }
 
     pub fn new(coherence_threshold: PreciseFloat) -> Self {
+        Self::with_clock(coherence_threshold, clock::system())
+    }
+
+    /// Create an orchestrator that timestamps observations with `clock`
+    pub fn with_clock(coherence_threshold: PreciseFloat, clock: SharedClock) -> Self {
         Self {
             state: OrchestratorState {
                 reality_layers: HashMap::new(),
@@ -111,20 +143,24 @@ impl Orchestrator {
             },
             tally_recorder: TallyRecorder::new(coherence_threshold.clone()),
             coherence_threshold,
+            clock,
+            evaluations: VecDeque::new(),
+            unpersisted: 0,
+            access: AccessRegistry::new(),
+            presence: HashMap::new(),
         }
     }
 
-    pub fn register_observation(&mut self, layer_id: u32, observer_id: [u8; 32], state: [u8; 64], confidence: PreciseFloat) -> Result<(), &'static str> {
-        let _layer = self.state.reality_layers
-            .entry(layer_id)
-            .or_insert(RealityLayer {
-                layer_id,
-                quantum_state: vec![0; 64],
-                observer_count: 0,
-                coherence_score: PreciseFloat::new(0, 20),
-                entanglement_count: 0,
-                last_sync: 0,
-            });
+    pub fn register_observation(
+        &mut self,
+        layer_id: u32,
+        observer_id: [u8; 32],
+        state: [u8; 64],
+        confidence: PreciseFloat,
+        credentials: &AccessCredentials,
+    ) -> Result<(), &'static str> {
+        self.access.check(layer_id, credentials)?;
+        self.layer_entry(layer_id);
 
         let state_hash = self.calculate_state_hash(&state);
         let tally = self.state.quantum_tallies
@@ -141,10 +177,7 @@ impl Orchestrator {
         tally.observer_votes.insert(observer_id, QuantumVote {
             observer_id,
             observed_state: state.to_vec(),
-            observation_time: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
+            observation_time: self.clock.now_secs(),
             confidence,
         });
 
@@ -152,6 +185,81 @@ impl Orchestrator {
         Ok(())
     }
 
+    /// Announce an observer's presence in a layer if `credentials` satisfy
+    /// its access policy. Returns the layer's observer count.
+    pub fn announce_presence(&mut self, layer_id: u32, observer_id: [u8; 32], credentials: &AccessCredentials) -> Result<u32, &'static str> {
+        self.access.check(layer_id, credentials)?;
+        let present = self.presence.entry(layer_id).or_default();
+        present.insert(observer_id);
+        let count = present.len() as u32;
+        self.layer_entry(layer_id).observer_count = count;
+        self.refresh_active_observers();
+        Ok(count)
+    }
+
+    pub fn access(&self) -> &AccessRegistry {
+        &self.access
+    }
+
+    /// Apply a layer owner's signed policy change. Observers present under
+    /// the old policy must announce themselves again.
+    pub fn set_layer_policy(&mut self, change: &PolicyChange, network_id: u64) -> Result<(), &'static str> {
+        self.access.apply_change(change, network_id)?;
+        self.clear_presence(change.layer_id);
+        Ok(())
+    }
+
+    /// Hand an owner's layer to its DAO
+    pub fn hand_to_dao(&mut self, layer_id: u32, owner: &[u8; 32]) -> Result<(), &'static str> {
+        self.access.hand_to_dao(layer_id, owner)
+    }
+
+    /// Apply a policy from an executed proposal of the layer's DAO.
+    /// Observers present under the old policy must announce themselves again.
+    pub fn apply_dao_policy(&mut self, layer_id: u32, policy: AccessPolicy) -> Result<(), &'static str> {
+        self.access.apply_dao_policy(layer_id, policy)?;
+        self.clear_presence(layer_id);
+        Ok(())
+    }
+
+    /// Apply a governance action declaring a layer or changing a DAO
+    /// layer's policy. Returns whether the action was the orchestrator's.
+    pub fn apply_governance(&mut self, action: &Action) -> Result<bool, &'static str> {
+        match self.access.apply_governance(action)? {
+            Some(layer_id) => {
+                self.clear_presence(layer_id);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn layer_entry(&mut self, layer_id: u32) -> &mut RealityLayer {
+        self.state.reality_layers
+            .entry(layer_id)
+            .or_insert(RealityLayer {
+                layer_id,
+                quantum_state: vec![0; 64],
+                observer_count: 0,
+                coherence_score: PreciseFloat::new(0, 20),
+                entanglement_count: 0,
+                last_sync: 0,
+            })
+    }
+
+    fn clear_presence(&mut self, layer_id: u32) {
+        self.presence.remove(&layer_id);
+        if let Some(layer) = self.state.reality_layers.get_mut(&layer_id) {
+            layer.observer_count = 0;
+        }
+        self.refresh_active_observers();
+    }
+
+    fn refresh_active_observers(&mut self) {
+        let observers: HashSet<&[u8; 32]> = self.presence.values().flatten().collect();
+        self.state.active_observers = observers.len() as u32;
+    }
+
     pub fn try_reach_consensus(&mut self, state_hash: [u8; 32]) -> Result<bool, &'static str> {
         let tally = self.state.quantum_tallies.get_mut(&state_hash).ok_or("Tally not found")?;
         
@@ -159,38 +267,45 @@ impl Orchestrator {
             return Ok(true);
         }
 
-        if tally.observer_votes.len() < 3 { // Minimum 3 observers required
-            return Ok(false);
+        let evaluation = audit::evaluate(state_hash, tally.observer_votes.values().cloned(), self.clock.now_secs());
+        if evaluation.outcome == ConsensusOutcome::Reached {
+            tally.consensus_reached = true;
+            tally.final_state = evaluation.final_state().map(|state| state.to_vec());
+            tally.confidence_score = evaluation.confidence.clone().unwrap_or_else(|| PreciseFloat::new(0, 20));
         }
+        let reached = tally.consensus_reached;
 
-        let mut vote_weights = HashMap::new();
-        let mut total_confidence = PreciseFloat::new(0, 20);
-
-        // Weight votes by observer confidence
-        for vote in tally.observer_votes.values() {
-            let weight = vote.confidence.clone();
-            total_confidence = total_confidence + weight.clone();
-            
-            *vote_weights
-                .entry(vote.observed_state.clone())
-                .or_insert(PreciseFloat::new(0, 20)) = weight;
+        if self.evaluations.len() >= MAX_EVALUATIONS {
+            self.evaluations.pop_front();
+            self.unpersisted = self.unpersisted.min(self.evaluations.len());
         }
+        self.evaluations.push_back(evaluation);
+        self.unpersisted += 1;
+        Ok(reached)
+    }
 
-        // Find the state with highest weighted votes
-        if let Some((winning_state, weight)) = vote_weights
-            .iter()
-            .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
-        {
-            let consensus_threshold = total_confidence.clone() * PreciseFloat::new(75, 2); // 75% consensus threshold
-            if *weight >= consensus_threshold {
-                tally.consensus_reached = true;
-                tally.final_state = Some(winning_state.clone());
-                tally.confidence_score = weight.clone() / total_confidence.clone();
-                return Ok(true);
-            }
-        }
+    /// Evaluations of a tally still held in memory, oldest first
+    pub fn consensus_history(&self, state_hash: &[u8; 32]) -> Vec<&ConsensusEvaluation> {
+        self.evaluations.iter().filter(|evaluation| evaluation.state_hash == *state_hash).collect()
+    }
+
+    /// Re-run an evaluation of a tally over its recorded votes: the latest,
+    /// or the `index`th counting from the oldest in memory
+    pub fn replay_consensus(&self, state_hash: &[u8; 32], index: Option<usize>) -> Result<Replay, &'static str> {
+        let history = self.consensus_history(state_hash);
+        let recorded = match index {
+            Some(index) => history.get(index),
+            None => history.last(),
+        };
+        recorded.map(|evaluation| audit::replay(evaluation)).ok_or("No recorded evaluation for this tally")
+    }
 
-        Ok(false)
+    /// Evaluations made since the last call, to be written to the node
+    /// database
+    pub fn take_unpersisted(&mut self) -> Vec<ConsensusEvaluation> {
+        let start = self.evaluations.len() - self.unpersisted;
+        self.unpersisted = 0;
+        self.evaluations.range(start..).cloned().collect()
     }
 
     fn calculate_state_hash(&self, state: &[u8; 64]) -> [u8; 32] {
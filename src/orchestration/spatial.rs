@@ -0,0 +1,338 @@
+//! Spatial index for reality-layer objects.
+//!
+//! An octree over integer world coordinates answers the region, collision
+//! and proximity queries that physics rules and interest management need
+//! without scanning every object. Parcels come from on-chain asset records:
+//! `sync_parcels` brings an index in line with the current state after each
+//! block, so the index never disagrees with what the chain says is placed.
+
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use crate::blockchain::assets::Assets;
+
+/// World units along each horizontal edge of a parcel
+pub const PARCEL_SIZE: i64 = 16;
+
+/// World units of vertical space above a parcel
+pub const PARCEL_HEIGHT: i64 = 256;
+
+/// Half the width of the default world along each axis
+pub const WORLD_EXTENT: i64 = 1 << 40;
+
+/// Entries a node holds before it splits into octants
+const NODE_CAPACITY: usize = 8;
+
+const MAX_DEPTH: u8 = 20;
+
+/// Axis-aligned box; `min` is inclusive and `max` exclusive on each axis
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Region {
+    pub min: [i64; 3],
+    pub max: [i64; 3],
+}
+
+impl Region {
+    pub fn new(min: [i64; 3], max: [i64; 3]) -> Result<Self, &'static str> {
+        if (0..3).any(|axis| min[axis] >= max[axis]) {
+            return Err("Region must have positive size on every axis");
+        }
+        Ok(Self { min, max })
+    }
+
+    /// Space owned by the parcel at grid cell `(x, y)`
+    pub fn parcel(x: i64, y: i64) -> Option<Self> {
+        let min_x = x.checked_mul(PARCEL_SIZE)?;
+        let min_y = y.checked_mul(PARCEL_SIZE)?;
+        Some(Self {
+            min: [min_x, min_y, 0],
+            max: [min_x.checked_add(PARCEL_SIZE)?, min_y.checked_add(PARCEL_SIZE)?, PARCEL_HEIGHT],
+        })
+    }
+
+    /// The default world: `WORLD_EXTENT` either side of the origin
+    pub fn world() -> Self {
+        Self { min: [-WORLD_EXTENT; 3], max: [WORLD_EXTENT; 3] }
+    }
+
+    pub fn intersects(&self, other: &Region) -> bool {
+        (0..3).all(|axis| self.min[axis] < other.max[axis] && other.min[axis] < self.max[axis])
+    }
+
+    pub fn contains(&self, other: &Region) -> bool {
+        (0..3).all(|axis| self.min[axis] <= other.min[axis] && other.max[axis] <= self.max[axis])
+    }
+
+    /// Squared distance from `point` to the nearest point of the region
+    pub fn distance_squared(&self, point: [i64; 3]) -> i128 {
+        (0..3)
+            .map(|axis| {
+                let (p, lo, hi) = (point[axis] as i128, self.min[axis] as i128, self.max[axis] as i128 - 1);
+                let gap = if p < lo { lo - p } else if p > hi { p - hi } else { 0 };
+                gap * gap
+            })
+            .sum()
+    }
+
+    /// The eight octants, or `None` if an axis is too thin to split
+    fn octants(&self) -> Option<[Region; 8]> {
+        if (0..3).any(|axis| self.max[axis] - self.min[axis] < 2) {
+            return None;
+        }
+        let mid: [i64; 3] = std::array::from_fn(|axis| self.min[axis] + (self.max[axis] - self.min[axis]) / 2);
+        Some(std::array::from_fn(|octant| {
+            let mut region = *self;
+            for (axis, &split) in mid.iter().enumerate() {
+                if octant & (1 << axis) == 0 {
+                    region.max[axis] = split;
+                } else {
+                    region.min[axis] = split;
+                }
+            }
+            region
+        }))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Node<K> {
+    region: Region,
+    depth: u8,
+    /// Objects that fit no single child
+    entries: Vec<(K, Region)>,
+    children: Option<Box<[Node<K>; 8]>>,
+}
+
+impl<K: Copy + Ord> Node<K> {
+    fn new(region: Region, depth: u8) -> Self {
+        Self { region, depth, entries: Vec::new(), children: None }
+    }
+
+    fn insert(&mut self, key: K, region: Region) {
+        if let Some(children) = &mut self.children {
+            if let Some(child) = children.iter_mut().find(|child| child.region.contains(&region)) {
+                return child.insert(key, region);
+            }
+        }
+        self.entries.push((key, region));
+        if self.children.is_none() && self.entries.len() > NODE_CAPACITY && self.depth < MAX_DEPTH {
+            self.split();
+        }
+    }
+
+    fn split(&mut self) {
+        let Some(octants) = self.region.octants() else { return };
+        self.children = Some(Box::new(octants.map(|octant| Node::new(octant, self.depth + 1))));
+        for (key, region) in std::mem::take(&mut self.entries) {
+            self.insert(key, region);
+        }
+    }
+
+    fn remove(&mut self, key: &K, region: &Region) -> bool {
+        if let Some(position) = self.entries.iter().position(|(entry, _)| entry == key) {
+            self.entries.swap_remove(position);
+            return true;
+        }
+        match &mut self.children {
+            Some(children) => children.iter_mut()
+                .find(|child| child.region.contains(region))
+                .is_some_and(|child| child.remove(key, region)),
+            None => false,
+        }
+    }
+
+    fn query(&self, region: &Region, found: &mut Vec<K>) {
+        found.extend(self.entries.iter().filter(|(_, entry)| entry.intersects(region)).map(|(key, _)| *key));
+        for child in self.children.iter().flat_map(|children| children.iter()) {
+            if child.region.intersects(region) {
+                child.query(region, found);
+            }
+        }
+    }
+}
+
+/// Spatial Index
+/// Octree of object regions keyed by object ID. An object lives in the
+/// smallest node that fully contains it; nodes split once they hold more
+/// than a few objects.
+#[derive(Debug, Clone)]
+pub struct SpatialIndex<K> {
+    root: Node<K>,
+    regions: BTreeMap<K, Region>,
+}
+
+impl<K: Copy + Ord> SpatialIndex<K> {
+    /// Empty index covering `bounds`
+    pub fn new(bounds: Region) -> Self {
+        Self { root: Node::new(bounds, 0), regions: BTreeMap::new() }
+    }
+
+    pub fn bounds(&self) -> &Region {
+        &self.root.region
+    }
+
+    pub fn len(&self) -> usize {
+        self.regions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    pub fn region_of(&self, key: &K) -> Option<&Region> {
+        self.regions.get(key)
+    }
+
+    /// Place `key` at `region`, moving it if already indexed
+    pub fn insert(&mut self, key: K, region: Region) -> Result<(), &'static str> {
+        if !self.root.region.contains(&region) {
+            return Err("Region lies outside the indexed world");
+        }
+        self.remove(&key);
+        self.root.insert(key, region);
+        self.regions.insert(key, region);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<Region> {
+        let region = self.regions.remove(key)?;
+        self.root.remove(key, &region);
+        Some(region)
+    }
+
+    /// Objects overlapping `region`, in key order
+    pub fn query(&self, region: &Region) -> Vec<K> {
+        let mut found = Vec::new();
+        self.root.query(region, &mut found);
+        found.sort();
+        found
+    }
+
+    /// Objects other than `except` that `region` would overlap
+    pub fn collisions(&self, region: &Region, except: Option<&K>) -> Vec<K> {
+        let mut found = self.query(region);
+        found.retain(|key| Some(key) != except);
+        found
+    }
+
+    /// Objects within `radius` of `center`, nearest first. This is the
+    /// interest set of an observer at `center`.
+    pub fn within(&self, center: [i64; 3], radius: i64) -> Vec<(K, i128)> {
+        let radius = radius.max(0);
+        let reach = |axis: usize, sign: i64| center[axis].saturating_add(sign * radius);
+        let bounds = Region {
+            min: [reach(0, -1), reach(1, -1), reach(2, -1)],
+            max: [reach(0, 1).saturating_add(1), reach(1, 1).saturating_add(1), reach(2, 1).saturating_add(1)],
+        };
+        let limit = radius as i128 * radius as i128;
+        let mut found: Vec<(K, i128)> = self.query(&bounds).into_iter()
+            .map(|key| (key, self.regions[&key].distance_squared(center)))
+            .filter(|(_, distance)| *distance <= limit)
+            .collect();
+        found.sort_by_key(|(key, distance)| (*distance, *key));
+        found
+    }
+}
+
+impl SpatialIndex<[u8; 32]> {
+    /// Make the index hold exactly the parcels recorded in `assets`. Returns
+    /// how many entries were added, moved or removed.
+    pub fn sync_parcels(&mut self, assets: &Assets) -> usize {
+        let parcels: BTreeMap<[u8; 32], Region> = assets.parcels()
+            .filter_map(|((x, y), id)| Region::parcel(x, y).map(|region| (id, region)))
+            .filter(|(_, region)| self.root.region.contains(region))
+            .collect();
+
+        let stale: Vec<[u8; 32]> = self.regions.keys().filter(|id| !parcels.contains_key(*id)).copied().collect();
+        let mut changed = stale.len();
+        for id in stale {
+            self.remove(&id);
+        }
+        for (id, region) in parcels {
+            if self.regions.get(&id) != Some(&region) && self.insert(id, region).is_ok() {
+                changed += 1;
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cube(x: i64, y: i64, z: i64, size: i64) -> Region {
+        Region::new([x, y, z], [x + size, y + size, z + size]).unwrap()
+    }
+
+    #[test]
+    fn test_queries_match_brute_force() {
+        let mut index = SpatialIndex::new(Region::world());
+        let mut regions = BTreeMap::new();
+        for i in 0..500_i64 {
+            let region = cube((i * 7_919) % 1_000 - 500, (i * 104_729) % 1_000 - 500, (i * 31) % 100, 1 + i % 20);
+            index.insert(i, region).unwrap();
+            regions.insert(i, region);
+        }
+        // Move some and remove others
+        for i in (0..500).step_by(5) {
+            let region = cube(i * 3 - 700, 40, 0, 5);
+            index.insert(i, region).unwrap();
+            regions.insert(i, region);
+        }
+        for i in (1..500).step_by(7) {
+            assert!(index.remove(&i).is_some());
+            regions.remove(&i);
+        }
+        assert_eq!(index.len(), regions.len());
+
+        for probe in [cube(-100, -100, 0, 200), cube(0, 0, 0, 1), cube(-800, 30, 0, 600), cube(400, 400, 90, 50)] {
+            let expected: Vec<i64> = regions.iter().filter(|(_, region)| region.intersects(&probe)).map(|(key, _)| *key).collect();
+            assert_eq!(index.query(&probe), expected);
+        }
+
+        let center = [0, 0, 50];
+        let nearby = index.within(center, 120);
+        let expected = regions.values().filter(|region| region.distance_squared(center) <= 120 * 120).count();
+        assert_eq!(nearby.len(), expected);
+        assert!(nearby.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+    }
+
+    #[test]
+    fn test_collisions_and_bounds() {
+        let mut index = SpatialIndex::new(cube(0, 0, 0, 64));
+        index.insert("tree", cube(10, 10, 0, 4)).unwrap();
+        index.insert("rock", cube(20, 20, 0, 2)).unwrap();
+        assert_eq!(index.insert("far", cube(100, 0, 0, 1)), Err("Region lies outside the indexed world"));
+
+        // Half-open bounds: touching faces do not collide
+        assert!(index.collisions(&cube(14, 10, 0, 2), None).is_empty());
+        assert_eq!(index.collisions(&cube(12, 12, 0, 10), None), vec!["rock", "tree"]);
+        assert_eq!(index.collisions(&cube(12, 12, 0, 10), Some(&"rock")), vec!["tree"]);
+    }
+
+    #[test]
+    fn test_parcels_follow_asset_records() {
+        use crate::blockchain::assets::{Asset, AssetKind};
+
+        let parcel = |x, y| Asset {
+            creator: [1u8; 32],
+            owner: [1u8; 32],
+            kind: AssetKind::Parcel { x, y },
+            royalty_bps: 0,
+            uri: String::new(),
+            metadata: None,
+        };
+        let mut assets = Assets::default();
+        assets.restore([1u8; 32], Some(parcel(0, 0)));
+        assets.restore([2u8; 32], Some(parcel(3, -2)));
+
+        let mut index = SpatialIndex::new(Region::world());
+        assert_eq!(index.sync_parcels(&assets), 2);
+        assert_eq!(index.sync_parcels(&assets), 0);
+        assert_eq!(index.query(&cube(50, -30, 10, 1)), vec![[2u8; 32]]);
+
+        assets.restore([1u8; 32], None);
+        assert_eq!(index.sync_parcels(&assets), 1);
+        assert!(index.query(&cube(0, 0, 0, PARCEL_SIZE)).is_empty());
+    }
+}
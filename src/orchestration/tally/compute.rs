@@ -1,6 +1,16 @@
 use serde::{Serialize, Deserialize};
 use blake3;
+use crate::crypto::transition::{TallyVersion, GENESIS_TALLY};
 use crate::math::precision::PreciseFloat;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Tallies computed by this process, for throughput reporting
+static TALLIES_COMPUTED: AtomicU64 = AtomicU64::new(0);
+
+/// Number of tallies computed by any `TallyComputer` since the process started
+pub fn tallies_computed() -> u64 {
+    TALLIES_COMPUTED.load(Ordering::Relaxed)
+}
 
 /// Represents a cryptographic tally over system state
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -9,6 +19,14 @@ pub struct TallyResult {
     pub hash: [u8; 32],
     /// Number of operations processed
     pub operation_count: u64,
+    /// `TallyVersion` the hash was computed with; results from before
+    /// versions were recorded are version 1
+    #[serde(default = "original_version")]
+    pub version: u8,
+}
+
+fn original_version() -> u8 {
+    TallyVersion::ORIGINAL.into()
 }
 
 /// Computes cryptographic tallies over quantum state transitions
@@ -19,6 +37,10 @@ pub struct TallyComputer {
     previous_hash: [u8; 32],
     /// Number of operations processed
     operation_count: u64,
+    /// Algorithm for new tallies
+    version: TallyVersion,
+    /// Algorithm the current hash was computed with
+    current_version: TallyVersion,
     /// Precision for floating point operations
     precision: u8,
 }
@@ -27,76 +49,40 @@ impl TallyComputer {
     /// Create a new TallyComputer instance
     pub fn new(precision: u8) -> Self {
         Self {
-            current_hash: [0u8; 32],
-            previous_hash: [0u8; 32],
+            current_hash: GENESIS_TALLY,
+            previous_hash: GENESIS_TALLY,
             operation_count: 0,
+            version: TallyVersion::ORIGINAL,
+            current_version: TallyVersion::ORIGINAL,
             precision,
         }
     }
 
+    /// Compute tallies from now on with `version`; earlier ones keep theirs.
+    /// Every node must switch at the same operation.
+    pub fn set_version(&mut self, version: TallyVersion) {
+        self.version = version;
+    }
+
     /// Computes the tally as:
     ///   T(i) = H( S(i) ⊕ O(i) ) ⊗ P(i)
-    /// where:
-    ///   - ⊕ is implemented as a byte‑wise XOR between state and operation
-    ///   - ⊗ is simulated as a byte‑wise XOR between the hash result and the proof
+    /// chained from the previous tally; see `crypto::transition`. Empty
+    /// inputs leave the tally as it is.
     pub fn compute_tally(&mut self, state: &[u8], operation: &[u8], proof: &[u8]) -> TallyResult {
-        if state.is_empty() || operation.is_empty() || proof.is_empty() {
-            return TallyResult {
-                hash: self.current_hash,
-                operation_count: self.operation_count,
-            };
-        }
+        let Ok(hash) = self.version.transition(&self.current_hash, state, operation, proof) else {
+            return self.get_current_state();
+        };
+
+        #[cfg(feature = "fault-injection")]
+        crate::chaos::checkpoint(crate::chaos::TALLY_COMPUTE);
 
         // Save the current hash for verification
         self.previous_hash = self.current_hash;
-        
-        // First hash the state
-        let state_hash = blake3::hash(state);
-        let state_hash_bytes = state_hash.as_bytes();
-        
-        // Combine with previous hash if not first operation
-        let mut state_xor = [0u8; 32];
-        if self.operation_count == 0 {
-            state_xor.copy_from_slice(state_hash_bytes);
-        } else {
-            for i in 0..32 {
-                state_xor[i] = state_hash_bytes[i] ^ self.previous_hash[i];
-            }
-        }
-        
-        // Then combine state with operation using XOR
-        let mut xor_state = [0u8; 32];
-        for i in 0..32 {
-            let state_byte = state_xor[i];
-            let op_byte = operation[i % operation.len()];
-            xor_state[i] = state_byte ^ op_byte;
-        }
-        
-        // Hash the XORed state
-        let hash_result = blake3::hash(&xor_state);
-        let hash_bytes = hash_result.as_bytes();
-        
-        // Normalize proof to 32 bytes
-        let proof_fixed: [u8; 32] = if proof.len() == 32 {
-            proof.try_into().unwrap()
-        } else {
-            let hash_proof = blake3::hash(proof);
-            *hash_proof.as_bytes()
-        };
-        
-        // Combine with proof
-        let mut final_hash = [0u8; 32];
-        for i in 0..32 {
-            final_hash[i] = hash_bytes[i] ^ proof_fixed[i];
-        }
-        
-        self.current_hash = final_hash;
+        self.current_hash = hash;
+        self.current_version = self.version;
         self.operation_count += 1;
-        
-        TallyResult {
-            hash: final_hash,
-            operation_count: self.operation_count,
-        }
+        TALLIES_COMPUTED.fetch_add(1, Ordering::Relaxed);
+        self.get_current_state()
     }
 
     pub fn compute_frc_proof(&self, data: &[u8]) -> PreciseFloat {
@@ -137,49 +123,19 @@ impl TallyComputer {
         PreciseFloat::new(value.abs(), self.precision)
     }
 
-    /// Verify that an expected tally matches computed one
+    /// Verify that an expected tally matches computed one, with the
+    /// algorithm of the tally's version
     pub fn verify_tally(&self, expected: &TallyResult, state: &[u8], operation: &[u8], proof: &[u8]) -> bool {
-        // For verification, we need to compute the hash using the same inputs and method
-        // First hash the state
-        let state_hash = blake3::hash(state);
-        let state_hash_bytes = state_hash.as_bytes();
-        
-        // Combine with previous hash if not first operation
-        let mut state_xor = [0u8; 32];
-        if expected.operation_count == 1 {
-            state_xor.copy_from_slice(state_hash_bytes);
-        } else {
-            for i in 0..32 {
-                state_xor[i] = state_hash_bytes[i] ^ self.previous_hash[i];
+        let version = match TallyVersion::try_from(expected.version) {
+            Ok(version) => version,
+            Err(e) => {
+                println!("{}: {}", e, expected.version);
+                return false;
             }
-        }
-        
-        // Then combine state with operation using XOR
-        let mut xor_state = [0u8; 32];
-        for i in 0..32 {
-            let state_byte = state_xor[i];
-            let op_byte = operation[i % operation.len()];
-            xor_state[i] = state_byte ^ op_byte;
-        }
-        
-        // Hash the XOR result
-        let hash_xor = blake3::hash(&xor_state);
-        let hash_xor_bytes = hash_xor.as_bytes();
-
-        // Normalize proof to 32 bytes
-        let proof_fixed: [u8; 32] = if proof.len() == 32 {
-            proof.try_into().unwrap()
-        } else {
-            let hash_proof = blake3::hash(proof);
-            *hash_proof.as_bytes()
         };
-
-        // Combine hash with proof using XOR
-        let mut computed_hash = [0u8; 32];
-        for i in 0..32 {
-            computed_hash[i] = hash_xor_bytes[i] ^ proof_fixed[i];
-        }
-        
+        let Ok(computed_hash) = version.transition(&self.previous_hash, state, operation, proof) else {
+            return false;
+        };
         if computed_hash != expected.hash {
             println!("Hash mismatch:\nExpected: {:?}\nComputed: {:?}", expected.hash, computed_hash);
             return false;
@@ -200,6 +156,7 @@ impl TallyComputer {
         TallyResult {
             hash: self.current_hash,
             operation_count: self.operation_count,
+            version: self.current_version.into(),
         }
     }
 }
@@ -207,6 +164,57 @@ impl TallyComputer {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::layers::l0_tally::TallyLayer;
+    use proptest::prelude::*;
+
+    /// Inputs of any length up to 64 bytes, often exactly 32
+    fn input() -> impl Strategy<Value = Vec<u8>> {
+        prop_oneof![
+            prop::collection::vec(any::<u8>(), 0..64),
+            prop::collection::vec(any::<u8>(), 32),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn prop_layer_and_computer_agree(steps in prop::collection::vec((input(), input(), input()), 1..16)) {
+            let mut layer = TallyLayer::new();
+            let mut computer = TallyComputer::new(20);
+            for (state, operation, proof) in &steps {
+                let result = computer.compute_tally(state, operation, proof);
+                match layer.compute_state_transition(state, operation, proof) {
+                    Ok(hash) => {
+                        prop_assert_eq!(hash, result.hash);
+                        prop_assert!(layer.verify_transition(state, operation, proof, result.hash));
+                        prop_assert!(computer.verify_tally(&result, state, operation, proof));
+                    }
+                    Err(_) => prop_assert!(state.is_empty() || operation.is_empty() || proof.is_empty()),
+                }
+                prop_assert_eq!(layer.get_operation_count(), result.operation_count);
+            }
+        }
+    }
+
+    #[test]
+    fn test_verify_selects_algorithm_by_version() {
+        let mut computer = TallyComputer::new(20);
+        let first = computer.compute_tally(b"state", b"op", b"proof");
+        assert_eq!(first.version, 1);
+        computer.set_version(TallyVersion::KeyedSponge);
+        let second = computer.compute_tally(b"state", b"op", b"proof");
+        assert_eq!(second.version, 2);
+        assert!(computer.verify_tally(&second, b"state", b"op", b"proof"));
+
+        // The same hash claimed under another version does not verify
+        let relabeled = TallyResult { version: 1, ..second.clone() };
+        assert!(!computer.verify_tally(&relabeled, b"state", b"op", b"proof"));
+        let unknown = TallyResult { version: 9, ..second };
+        assert!(!computer.verify_tally(&unknown, b"state", b"op", b"proof"));
+
+        // Results stored before versions were recorded read as version 1
+        let stored: TallyResult = serde_json::from_str(r#"{"hash":[0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],"operation_count":1}"#).unwrap();
+        assert_eq!(stored.version, 1);
+    }
 
     #[test]
     fn test_tally_computation() {
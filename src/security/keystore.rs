@@ -0,0 +1,293 @@
+//! Encrypted key files for `QuantumKey`s.
+//!
+//! A keystore is a directory with one JSON file per named key. The public
+//! key and its metadata are stored in the clear so `list` needs no
+//! passphrase. The private key is sealed with AES-256-GCM under a key
+//! derived from the passphrase with Argon2id, with a fresh salt and nonce
+//! per file. The public key is bound into the seal as associated data, so
+//! a file whose public half was swapped fails to open just like a wrong
+//! passphrase. Files are written to a temporary name and renamed, and are
+//! readable by their owner only.
+
+use serde::{Serialize, Deserialize};
+use std::path::{Path, PathBuf};
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use argon2::{Algorithm, Argon2, Params, Version};
+use rand::RngCore;
+use crate::blockchain::types::hex_serde;
+use crate::math::precision::PreciseFloat;
+use super::quantum_resistant::{KeyId, QuantumKey};
+
+/// Format version written by `Keystore::save`
+pub const KEYSTORE_VERSION: u32 = 1;
+
+const KEY_FILE_EXTENSION: &str = "json";
+const SALT_BYTES: usize = 16;
+const NONCE_BYTES: usize = 12;
+/// Prefix of the associated data each private key is sealed with
+const SEAL_CONTEXT: &[u8] = b"qmv:keystore:v1";
+
+/// Argon2id cost of deriving a file's encryption key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            memory_kib: 64 * 1024,
+            iterations: 3,
+            parallelism: 1,
+        }
+    }
+}
+
+/// Key file as stored on disk
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KeyFile {
+    version: u32,
+    #[serde(with = "hex_serde")]
+    id: KeyId,
+    #[serde(with = "hex_serde")]
+    public_key: Vec<u8>,
+    creation_time: u64,
+    security_level: PreciseFloat,
+    kdf: KdfParams,
+    #[serde(with = "hex_serde")]
+    salt: Vec<u8>,
+    #[serde(with = "hex_serde")]
+    nonce: Vec<u8>,
+    /// Sealed private key, with the GCM tag appended
+    #[serde(with = "hex_serde")]
+    ciphertext: Vec<u8>,
+}
+
+/// Key listed in a keystore
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredKey {
+    pub name: String,
+    #[serde(with = "hex_serde")]
+    pub id: KeyId,
+    pub creation_time: u64,
+}
+
+/// Keystore
+/// Directory of passphrase-encrypted key files, one per named key.
+#[derive(Debug, Clone)]
+pub struct Keystore {
+    dir: PathBuf,
+    kdf: KdfParams,
+}
+
+impl Keystore {
+    /// Open the keystore at `dir`, creating the directory if needed
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, String> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        Ok(Self { dir, kdf: KdfParams::default() })
+    }
+
+    /// Use another Argon2id cost for keys saved from now on. Each file
+    /// records its own cost, so existing files still open.
+    pub fn with_kdf(mut self, kdf: KdfParams) -> Self {
+        self.kdf = kdf;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.path(name).is_ok_and(|path| path.exists())
+    }
+
+    /// Encrypt `key` under `passphrase` and store it as `name`. Refuses to
+    /// replace an existing key, so an identity is never lost by accident.
+    pub fn save(&self, name: &str, key: &QuantumKey, passphrase: &str) -> Result<KeyId, String> {
+        let path = self.path(name)?;
+        if path.exists() {
+            return Err(format!("Key `{}` already exists in {}", name, self.dir.display()));
+        }
+        let private_key = key.private_key().ok_or("Only keys with a private key can be stored")?;
+
+        let mut salt = vec![0u8; SALT_BYTES];
+        let mut nonce = vec![0u8; NONCE_BYTES];
+        rand::thread_rng().fill_bytes(&mut salt);
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let cipher = cipher(passphrase, &salt, &self.kdf)?;
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: private_key, aad: &seal_context(key.public_key()) })
+            .map_err(|_| "Failed to encrypt the private key".to_string())?;
+
+        let file = KeyFile {
+            version: KEYSTORE_VERSION,
+            id: key.id(),
+            public_key: key.public_key().to_vec(),
+            creation_time: key.creation_time(),
+            security_level: key.security_level().clone(),
+            kdf: self.kdf,
+            salt,
+            nonce,
+            ciphertext,
+        };
+        let raw = serde_json::to_vec_pretty(&file).map_err(|e| e.to_string())?;
+        write_private(&path, &raw)?;
+        Ok(file.id)
+    }
+
+    /// Decrypt the key stored as `name`
+    pub fn load(&self, name: &str, passphrase: &str) -> Result<QuantumKey, String> {
+        let file = self.read(name)?;
+        let cipher = cipher(passphrase, &file.salt, &file.kdf)?;
+        if file.nonce.len() != NONCE_BYTES {
+            return Err(format!("Key `{}` has a malformed nonce", name));
+        }
+        let private_key = cipher
+            .decrypt(Nonce::from_slice(&file.nonce), Payload { msg: &file.ciphertext, aad: &seal_context(&file.public_key) })
+            .map_err(|_| format!("Wrong passphrase for key `{}`, or its file was modified", name))?;
+
+        let key = QuantumKey::new(file.public_key, file.creation_time, file.security_level)
+            .with_private_key(private_key);
+        if key.id() != file.id {
+            return Err(format!("Key `{}` does not match the ID it was stored under", name));
+        }
+        key.check().map_err(|e| format!("Key `{}`: {}", name, e))?;
+        Ok(key)
+    }
+
+    /// Keys in the keystore, by name. Needs no passphrase.
+    pub fn list(&self) -> Result<Vec<StoredKey>, String> {
+        let entries = std::fs::read_dir(&self.dir).map_err(|e| format!("Failed to read {}: {}", self.dir.display(), e))?;
+        let mut keys = Vec::new();
+        for entry in entries {
+            let path = entry.map_err(|e| e.to_string())?.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some(KEY_FILE_EXTENSION) {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else { continue };
+            let file = self.read(name)?;
+            keys.push(StoredKey { name: name.to_string(), id: file.id, creation_time: file.creation_time });
+        }
+        keys.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(keys)
+    }
+
+    fn read(&self, name: &str) -> Result<KeyFile, String> {
+        let path = self.path(name)?;
+        let raw = std::fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let file: KeyFile = serde_json::from_slice(&raw).map_err(|e| format!("Invalid key file {}: {}", path.display(), e))?;
+        if file.version != KEYSTORE_VERSION {
+            return Err(format!("Key file version {} is not supported (expected {})", file.version, KEYSTORE_VERSION));
+        }
+        Ok(file)
+    }
+
+    /// Names become file names, so they are limited to characters that
+    /// cannot leave the directory
+    fn path(&self, name: &str) -> Result<PathBuf, String> {
+        let valid = !name.is_empty()
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(format!("Key name `{}` may only hold letters, digits, `-` and `_`", name));
+        }
+        Ok(self.dir.join(format!("{}.{}", name, KEY_FILE_EXTENSION)))
+    }
+}
+
+fn seal_context(public_key: &[u8]) -> Vec<u8> {
+    [SEAL_CONTEXT, public_key].concat()
+}
+
+fn cipher(passphrase: &str, salt: &[u8], kdf: &KdfParams) -> Result<Aes256Gcm, String> {
+    let params = Params::new(kdf.memory_kib, kdf.iterations, kdf.parallelism, Some(32))
+        .map_err(|e| format!("Invalid key derivation parameters: {}", e))?;
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| format!("Failed to derive the key file key: {}", e))?;
+    Aes256Gcm::new_from_slice(&key).map_err(|e| e.to_string())
+}
+
+/// Write `bytes` readable by the owner only, replacing `path` in one step
+fn write_private(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    let tmp = path.with_extension("tmp");
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(&tmp)
+        .and_then(|mut file| {
+            std::io::Write::write_all(&mut file, bytes)?;
+            file.sync_all()
+        })
+        .and_then(|_| std::fs::rename(&tmp, path))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::quantum_resistant::QuantumSecurity;
+
+    /// Cheap enough for tests; real keystores use the default cost
+    const TEST_KDF: KdfParams = KdfParams { memory_kib: 64, iterations: 1, parallelism: 1 };
+
+    fn keystore() -> Keystore {
+        let dir = std::env::temp_dir().join(format!("qmv-keystore-{}", hex::encode(rand::random::<[u8; 8]>())));
+        Keystore::open(dir).unwrap().with_kdf(TEST_KDF)
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let keystore = keystore();
+        let mut security = QuantumSecurity::new(20);
+        let (key_id, key) = security.generate_key_pair().unwrap();
+        assert_eq!(keystore.save("node", &key, "correct horse").unwrap(), key_id);
+        assert!(keystore.contains("node"));
+        assert!(keystore.save("node", &key, "correct horse").is_err());
+        assert!(keystore.save("public", &key.public_only(), "correct horse").is_err());
+
+        let loaded = keystore.load("node", "correct horse").unwrap();
+        assert_eq!(loaded.id(), key_id);
+        assert_eq!(loaded.creation_time(), key.creation_time());
+        // The same identity signs the same way across restarts
+        assert_eq!(loaded.sign(b"block 7").unwrap(), key.sign(b"block 7").unwrap());
+        assert_eq!(QuantumSecurity::new(20).import_key(loaded).unwrap(), key_id);
+
+        let raw = std::fs::read_to_string(keystore.dir().join("node.json")).unwrap();
+        assert!(!raw.contains(&hex::encode(key.private_key().unwrap())));
+        assert!(keystore.load("node", "wrong horse").unwrap_err().starts_with("Wrong passphrase"));
+
+        assert_eq!(
+            keystore.list().unwrap(),
+            vec![StoredKey { name: "node".to_string(), id: key_id, creation_time: key.creation_time() }]
+        );
+        std::fs::remove_dir_all(keystore.dir()).unwrap();
+    }
+
+    #[test]
+    fn test_tampered_files_do_not_open() {
+        let keystore = keystore();
+        let mut security = QuantumSecurity::new(20);
+        let (_, key) = security.generate_key_pair().unwrap();
+        let (_, other) = security.generate_key_pair().unwrap();
+        keystore.save("node", &key, "pass").unwrap();
+
+        // Swapping in another public key breaks the seal
+        let path = keystore.dir().join("node.json");
+        let mut file: KeyFile = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        file.public_key = other.public_key().to_vec();
+        file.id = other.id();
+        std::fs::write(&path, serde_json::to_vec(&file).unwrap()).unwrap();
+        assert!(keystore.load("node", "pass").is_err());
+
+        assert!(keystore.save("../escape", &key, "pass").is_err());
+        assert!(keystore.load("missing", "pass").is_err());
+        std::fs::remove_dir_all(keystore.dir()).unwrap();
+    }
+}
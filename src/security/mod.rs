@@ -1,2 +1,3 @@
 pub mod quantum_resistant;
+pub mod keystore;
 pub mod tests;
@@ -0,0 +1,129 @@
+use lru::LruCache;
+use serde::Serialize;
+use std::hash::Hash;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Hit-rate metrics for one cache
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheStats {
+    pub name: &'static str,
+    pub entries: usize,
+    pub capacity: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub invalidations: u64,
+    /// Hits as a fraction of lookups (0 when unused)
+    pub hit_rate: f64,
+}
+
+/// Read-through LRU cache.
+/// Safe to share behind `&self`, so RPC handlers and execution can use it under read locks.
+pub struct ReadCache<K: Hash + Eq, V> {
+    name: &'static str,
+    entries: Mutex<LruCache<K, V>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    invalidations: AtomicU64,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> ReadCache<K, V> {
+    pub fn new(name: &'static str, capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            name,
+            entries: Mutex::new(LruCache::new(capacity)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            invalidations: AtomicU64::new(0),
+        }
+    }
+
+    /// Return the cached value or load it from the backing store and cache it
+    pub fn get_or_load(&self, key: &K, load: impl FnOnce() -> V) -> V {
+        if let Some(value) = self.entries.lock().unwrap().get(key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return value.clone();
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        // Load without holding the lock; a racing loader just writes the same value
+        let value = load();
+        self.entries.lock().unwrap().put(key.clone(), value.clone());
+        value
+    }
+
+    /// Drop a key whose backing value changed
+    pub fn invalidate(&self, key: &K) {
+        if self.entries.lock().unwrap().pop(key).is_some() {
+            self.invalidations.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn invalidate_many<'a>(&self, keys: impl IntoIterator<Item = &'a K>)
+    where
+        K: 'a,
+    {
+        let mut entries = self.entries.lock().unwrap();
+        for key in keys {
+            if entries.pop(key).is_some() {
+                self.invalidations.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn clear(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        self.invalidations.fetch_add(entries.len() as u64, Ordering::Relaxed);
+        entries.clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let entries = self.entries.lock().unwrap();
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let lookups = hits + misses;
+        CacheStats {
+            name: self.name,
+            entries: entries.len(),
+            capacity: entries.cap().get(),
+            hits,
+            misses,
+            invalidations: self.invalidations.load(Ordering::Relaxed),
+            hit_rate: if lookups == 0 { 0.0 } else { hits as f64 / lookups as f64 },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_through_and_invalidation() {
+        let cache: ReadCache<u32, String> = ReadCache::new("test", 2);
+        let mut loads = 0;
+
+        for _ in 0..3 {
+            let value = cache.get_or_load(&1, || { loads += 1; "one".to_string() });
+            assert_eq!(value, "one");
+        }
+        assert_eq!(loads, 1);
+
+        cache.invalidate(&1);
+        cache.get_or_load(&1, || { loads += 1; "uno".to_string() });
+        assert_eq!(loads, 2);
+
+        // Capacity 2: the least recently used key is evicted
+        cache.get_or_load(&2, || "two".to_string());
+        cache.get_or_load(&3, || "three".to_string());
+
+        let stats = cache.stats();
+        assert_eq!(stats.entries, 2);
+        assert_eq!(stats.hits, 2);
+        assert_eq!(stats.misses, 4);
+        assert_eq!(stats.invalidations, 1);
+        assert!((stats.hit_rate - 1.0 / 3.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,206 @@
+//! Replay corpus of recorded blocks and tally observations.
+//!
+//! A corpus holds the blocks of a devnet run from genesis, in their wire
+//! encoding, with the state root, receipts root and gas each block produced
+//! when it was recorded. It also holds tally observations with the
+//! admission result of each and the aggregates they flushed into. `replay`
+//! runs all of it back through block import, execution and observation
+//! admission, and fails at the first result that differs from the recording.
+//! A corpus committed as a test fixture catches changes to consensus
+//! behavior that unit tests of the single pieces would not.
+//!
+//! A corpus is only re-recorded when a change to consensus behavior is
+//! intended.
+
+use serde::{Serialize, Deserialize};
+use crate::blockchain::bloom::Bloom;
+use crate::blockchain::core::{Block, Blockchain};
+use crate::blockchain::execution::{Executor, Receipt};
+use crate::blockchain::state::WorldState;
+use crate::blockchain::store::{ChainStore, MemoryChainStore};
+use crate::blockchain::types::hex_serde;
+use crate::clock;
+use crate::config::ObservationConfig;
+use crate::economics::providers::{ProviderId, ProviderKind, ProviderRegistry};
+use crate::math::precision::PreciseFloat;
+use crate::network::observations::{LayerAggregate, ObservationGossip, TallyObservation};
+use super::reindex::block_transactions;
+
+/// Format version written by `Corpus::record`
+pub const CORPUS_VERSION: u32 = 1;
+
+/// Block in its wire encoding
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WireBlock(#[serde(with = "hex_serde")] pub Vec<u8>);
+
+/// What executing one block produced when it was recorded
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockOutcome {
+    pub index: u64,
+    pub transactions: usize,
+    pub gas_used: u64,
+    #[serde(with = "hex_serde")]
+    pub state_root: [u8; 32],
+    #[serde(with = "hex_serde")]
+    pub receipts_root: [u8; 32],
+}
+
+/// Observer bonded before the observations are replayed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObserverBond {
+    #[serde(with = "hex_serde")]
+    pub observer: ProviderId,
+    pub stake: PreciseFloat,
+}
+
+/// Observation with the reason it was refused, if it was
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedObservation {
+    pub observation: TallyObservation,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rejected: Option<String>,
+}
+
+/// What a successful replay went through
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplayReport {
+    pub blocks: usize,
+    pub transactions: usize,
+    pub observations: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Corpus {
+    pub version: u32,
+    pub network_id: u64,
+    /// Bincode-encoded world state the chain starts from
+    #[serde(with = "hex_serde")]
+    pub genesis_state: Vec<u8>,
+    /// Every block from genesis, in order
+    pub blocks: Vec<WireBlock>,
+    /// One per block after genesis
+    pub outcomes: Vec<BlockOutcome>,
+    pub observers: Vec<ObserverBond>,
+    pub observations: Vec<RecordedObservation>,
+    /// Aggregates the admitted observations flushed into
+    pub aggregates: Vec<LayerAggregate>,
+}
+
+impl Corpus {
+    /// Record `blocks`, starting with genesis, executed from `genesis_state`,
+    /// and `observations` admitted with `observers` bonded. Fails if the
+    /// blocks do not import, as a corpus must replay cleanly.
+    pub fn record(
+        network_id: u64,
+        genesis_state: &WorldState,
+        blocks: &[Block],
+        observers: Vec<ObserverBond>,
+        observations: Vec<TallyObservation>,
+    ) -> Result<Self, String> {
+        let mut corpus = Self {
+            version: CORPUS_VERSION,
+            network_id,
+            genesis_state: bincode::serialize(genesis_state).map_err(|e| format!("Failed to encode genesis state: {}", e))?,
+            blocks: blocks.iter().map(|block| WireBlock(block.to_bytes())).collect(),
+            outcomes: Vec::new(),
+            observers,
+            observations: observations.into_iter().map(|observation| RecordedObservation { observation, rejected: None }).collect(),
+            aggregates: Vec::new(),
+        };
+        corpus.outcomes = corpus.run_blocks()?;
+        let (admissions, aggregates) = corpus.run_observations()?;
+        for (recorded, rejected) in corpus.observations.iter_mut().zip(admissions) {
+            recorded.rejected = rejected;
+        }
+        corpus.aggregates = aggregates;
+        Ok(corpus)
+    }
+
+    /// Replay everything and compare it with the recording
+    pub fn replay(&self) -> Result<ReplayReport, String> {
+        if self.version != CORPUS_VERSION {
+            return Err(format!("Corpus version {} is not supported (expected {})", self.version, CORPUS_VERSION));
+        }
+        let outcomes = self.run_blocks()?;
+        if outcomes.len() != self.outcomes.len() {
+            return Err(format!("Corpus records {} block outcomes for {} blocks", self.outcomes.len(), outcomes.len()));
+        }
+        for (replayed, recorded) in outcomes.iter().zip(&self.outcomes) {
+            if replayed != recorded {
+                return Err(format!("Block {} diverged: replayed {:?}, recorded {:?}", recorded.index, replayed, recorded));
+            }
+        }
+
+        let (admissions, aggregates) = self.run_observations()?;
+        for (index, (rejected, recorded)) in admissions.iter().zip(&self.observations).enumerate() {
+            if *rejected != recorded.rejected {
+                return Err(format!("Observation {} diverged: replayed {:?}, recorded {:?}", index, rejected, recorded.rejected));
+            }
+        }
+        if aggregates != self.aggregates {
+            return Err("Flushed observation aggregates diverged".to_string());
+        }
+
+        Ok(ReplayReport {
+            blocks: self.blocks.len(),
+            transactions: outcomes.iter().map(|outcome| outcome.transactions).sum(),
+            observations: self.observations.len(),
+        })
+    }
+
+    /// Import and execute every block after genesis on a fresh chain
+    fn run_blocks(&self) -> Result<Vec<BlockOutcome>, String> {
+        let blocks = self.blocks.iter()
+            .enumerate()
+            .map(|(height, wire)| Block::from_bytes(&wire.0).map_err(|e| format!("Block {}: {}", height, e)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let genesis = blocks.first().ok_or("Corpus has no genesis block")?;
+        let mut store = MemoryChainStore::new();
+        store.put_block(genesis)?;
+        // Genesis proofs are 1 at the chain's precision
+        let mut chain = Blockchain::with_store(genesis.frc_proof.scale, Box::new(store), clock::system())?;
+        let mut state: WorldState = bincode::deserialize(&self.genesis_state)
+            .map_err(|e| format!("Failed to decode genesis state: {}", e))?;
+
+        let mut outcomes = Vec::with_capacity(blocks.len().saturating_sub(1));
+        for block in blocks.into_iter().skip(1) {
+            let index = block.index;
+            let transactions = block_transactions(&block);
+            chain.import_block(block.clone()).map_err(|e| format!("Block {}: {}", index, e))?;
+            let receipts = Executor::apply_block(&mut state, &transactions).map_err(|e| format!("Block {}: {}", index, e))?;
+            state.set_height(index);
+            if !block.bloom.contains_bloom(&Bloom::for_block(&transactions, &receipts)) {
+                return Err(format!("Block {}: header bloom misses its transactions or events", index));
+            }
+            outcomes.push(BlockOutcome {
+                index,
+                transactions: transactions.len(),
+                gas_used: receipts.iter().map(|receipt| receipt.gas_used).sum(),
+                state_root: state.state_root(),
+                receipts_root: receipts_root(&receipts)?,
+            });
+        }
+        Ok(outcomes)
+    }
+
+    /// Admit every observation, then flush. Rate limits are lifted, since a
+    /// replay runs far faster than the observers published.
+    fn run_observations(&self) -> Result<(Vec<Option<String>>, Vec<LayerAggregate>), String> {
+        let mut providers = ProviderRegistry::new();
+        for bond in &self.observers {
+            providers.bond(bond.observer, ProviderKind::Observer, bond.stake.clone(), 0)
+                .map_err(|e| format!("Observer {}: {}", hex::encode(bond.observer), e))?;
+        }
+        let config = ObservationConfig { max_per_observer: u32::MAX, ..ObservationConfig::default() };
+        let mut gossip = ObservationGossip::new(self.network_id, &config);
+        let admissions = self.observations.iter()
+            .map(|recorded| gossip.admit(recorded.observation.clone(), &providers).err().map(str::to_string))
+            .collect();
+        Ok((admissions, gossip.flush()))
+    }
+}
+
+fn receipts_root(receipts: &[Receipt]) -> Result<[u8; 32], String> {
+    let encoded = bincode::serialize(receipts).map_err(|e| format!("Failed to encode receipts: {}", e))?;
+    Ok(blake3::hash(&encoded).into())
+}
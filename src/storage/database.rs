@@ -0,0 +1,339 @@
+use rocksdb::{ColumnFamily, ColumnFamilyDescriptor, Direction, IteratorMode, Options, WriteBatch, DB};
+use serde::{Serialize, Deserialize};
+use crate::blockchain::core::Block;
+use crate::blockchain::execution::Receipt;
+use crate::blockchain::transaction::TxHash;
+use crate::blockchain::wire::BlockView;
+use crate::orchestration::audit::ConsensusEvaluation;
+
+pub const CF_BLOCKS: &str = "blocks";
+pub const CF_STATE: &str = "state";
+pub const CF_RECEIPTS: &str = "receipts";
+pub const CF_TALLY_LOG: &str = "tally_log";
+pub const CF_SHARDS: &str = "shards";
+/// Transaction hash to the block height and position it was included at
+pub const CF_TX_INDEX: &str = "tx_index";
+
+pub const COLUMN_FAMILIES: [&str; 6] = [CF_BLOCKS, CF_STATE, CF_RECEIPTS, CF_TALLY_LOG, CF_SHARDS, CF_TX_INDEX];
+
+/// Size report for a single column family
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColumnFamilyStats {
+    pub name: String,
+    pub estimated_keys: u64,
+    pub live_data_bytes: u64,
+    pub sst_files_bytes: u64,
+    pub memtable_bytes: u64,
+}
+
+/// Result of checking stored blocks against their hashes
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct VerifyReport {
+    pub checked: u64,
+    /// Blocks whose contents no longer match their hash
+    pub hash_mismatches: Vec<u64>,
+    /// Blocks whose previous_hash does not match the preceding block
+    pub broken_links: Vec<u64>,
+    /// Keys whose value could not be decoded as a block
+    pub undecodable: Vec<String>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.hash_mismatches.is_empty() && self.broken_links.is_empty() && self.undecodable.is_empty()
+    }
+}
+
+/// Node Database
+/// RocksDB store with one column family per data kind.
+pub struct NodeDatabase {
+    db: DB,
+}
+
+impl NodeDatabase {
+    pub fn open(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+
+        let descriptors = COLUMN_FAMILIES
+            .iter()
+            .map(|name| ColumnFamilyDescriptor::new(*name, Options::default()));
+        let db = DB::open_cf_descriptors(&opts, path, descriptors)?;
+
+        Ok(Self { db })
+    }
+
+    fn cf(&self, name: &str) -> Result<&ColumnFamily, Box<dyn std::error::Error>> {
+        self.db.cf_handle(name)
+            .ok_or_else(|| format!("Missing column family {}", name).into())
+    }
+
+    pub fn put(&self, cf: &str, key: &[u8], value: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        self.db.put_cf(self.cf(cf)?, key, value)?;
+        Ok(())
+    }
+
+    pub fn get(&self, cf: &str, key: &[u8]) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+        Ok(self.db.get_cf(self.cf(cf)?, key)?)
+    }
+
+    /// Store a block keyed by big-endian index so iteration follows chain order
+    pub fn put_block(&self, block: &Block) -> Result<(), Box<dyn std::error::Error>> {
+        self.put(CF_BLOCKS, &block.index.to_be_bytes(), &block.to_bytes())
+    }
+
+    pub fn get_block(&self, index: u64) -> Result<Option<Block>, Box<dyn std::error::Error>> {
+        match self.get(CF_BLOCKS, &index.to_be_bytes())? {
+            Some(bytes) => Ok(Some(Block::from_bytes(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Stored blocks from `start` on, in chain order, decoded one at a time
+    pub fn blocks_from(&self, start: u64) -> Result<impl Iterator<Item = Result<Block, String>> + '_, Box<dyn std::error::Error>> {
+        let key = start.to_be_bytes();
+        Ok(self.db.iterator_cf(self.cf(CF_BLOCKS)?, IteratorMode::From(&key, Direction::Forward)).map(|item| {
+            let (key, value) = item.map_err(|e| e.to_string())?;
+            Block::from_bytes(&value).map_err(|e| format!("Undecodable block at key {}: {}", hex::encode(&key), e))
+        }))
+    }
+
+    /// Index of the last stored block, if any
+    pub fn latest_block_index(&self) -> Result<Option<u64>, Box<dyn std::error::Error>> {
+        match self.db.iterator_cf(self.cf(CF_BLOCKS)?, IteratorMode::End).next() {
+            Some(item) => {
+                let (key, _) = item?;
+                let key: [u8; 8] = key[..].try_into().map_err(|_| "Malformed block key")?;
+                Ok(Some(u64::from_be_bytes(key)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Write one block's receipts and the position of each of its
+    /// transactions in a single batch
+    pub fn put_block_indexes(&self, height: u64, transactions: &[TxHash], receipts: &[Receipt]) -> Result<(), Box<dyn std::error::Error>> {
+        let mut batch = WriteBatch::default();
+        for (position, hash) in transactions.iter().enumerate() {
+            let mut location = height.to_be_bytes().to_vec();
+            location.extend_from_slice(&(position as u32).to_be_bytes());
+            batch.put_cf(self.cf(CF_TX_INDEX)?, hash, location);
+        }
+        batch.put_cf(self.cf(CF_RECEIPTS)?, height.to_be_bytes(), serde_json::to_vec(receipts)?);
+        self.db.write(batch)?;
+        Ok(())
+    }
+
+    /// Height and position within the block of an included transaction
+    pub fn transaction_location(&self, hash: &TxHash) -> Result<Option<(u64, u32)>, Box<dyn std::error::Error>> {
+        match self.get(CF_TX_INDEX, hash)? {
+            Some(value) if value.len() == 12 => Ok(Some((
+                u64::from_be_bytes(value[..8].try_into()?),
+                u32::from_be_bytes(value[8..].try_into()?),
+            ))),
+            Some(_) => Err("Malformed transaction index entry".into()),
+            None => Ok(None),
+        }
+    }
+
+    /// Receipts of the block at `height`, if indexed
+    pub fn receipts(&self, height: u64) -> Result<Option<Vec<Receipt>>, Box<dyn std::error::Error>> {
+        match self.get(CF_RECEIPTS, &height.to_be_bytes())? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn delete(&self, cf: &str, key: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        self.db.delete_cf(self.cf(cf)?, key)?;
+        Ok(())
+    }
+
+    /// Delete every key in a column family
+    pub fn clear(&self, cf: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let handle = self.cf(cf)?;
+        let mut batch = WriteBatch::default();
+        for item in self.db.iterator_cf(handle, IteratorMode::Start) {
+            let (key, _) = item?;
+            batch.delete_cf(handle, key);
+        }
+        self.db.write(batch)?;
+        Ok(())
+    }
+
+    /// Append a consensus evaluation to its tally's log. Keys are the state
+    /// hash followed by a big-endian sequence number, so a tally's
+    /// evaluations iterate in the order they were made.
+    pub fn put_consensus_evaluation(&self, evaluation: &ConsensusEvaluation) -> Result<(), Box<dyn std::error::Error>> {
+        let sequence = self.consensus_keys(&evaluation.state_hash)?.len() as u32;
+        let mut key = evaluation.state_hash.to_vec();
+        key.extend_from_slice(&sequence.to_be_bytes());
+        self.put(CF_TALLY_LOG, &key, &serde_json::to_vec(evaluation)?)
+    }
+
+    /// Every stored evaluation of a tally, oldest first
+    pub fn consensus_evaluations(&self, state_hash: &[u8; 32]) -> Result<Vec<ConsensusEvaluation>, Box<dyn std::error::Error>> {
+        let mut evaluations = Vec::new();
+        for key in self.consensus_keys(state_hash)? {
+            if let Some(value) = self.get(CF_TALLY_LOG, &key)? {
+                evaluations.push(serde_json::from_slice(&value)?);
+            }
+        }
+        Ok(evaluations)
+    }
+
+    fn consensus_keys(&self, state_hash: &[u8; 32]) -> Result<Vec<Box<[u8]>>, Box<dyn std::error::Error>> {
+        let mut keys = Vec::new();
+        for item in self.db.iterator_cf(self.cf(CF_TALLY_LOG)?, IteratorMode::From(state_hash, Direction::Forward)) {
+            let (key, _) = item?;
+            if !key.starts_with(state_hash) {
+                break;
+            }
+            keys.push(key);
+        }
+        Ok(keys)
+    }
+
+    /// Report per-column-family key counts and on-disk sizes
+    pub fn stats(&self) -> Result<Vec<ColumnFamilyStats>, Box<dyn std::error::Error>> {
+        let mut stats = Vec::with_capacity(COLUMN_FAMILIES.len());
+        for name in COLUMN_FAMILIES {
+            let cf = self.cf(name)?;
+            let property = |key: &str| -> Result<u64, Box<dyn std::error::Error>> {
+                Ok(self.db.property_int_value_cf(cf, key)?.unwrap_or(0))
+            };
+
+            stats.push(ColumnFamilyStats {
+                name: name.to_string(),
+                estimated_keys: property("rocksdb.estimate-num-keys")?,
+                live_data_bytes: property("rocksdb.estimate-live-data-size")?,
+                sst_files_bytes: property("rocksdb.total-sst-files-size")?,
+                memtable_bytes: property("rocksdb.cur-size-all-mem-tables")?,
+            });
+        }
+        Ok(stats)
+    }
+
+    /// Compact every column family over its full key range
+    pub fn compact(&self) -> Result<(), Box<dyn std::error::Error>> {
+        for name in COLUMN_FAMILIES {
+            self.db.compact_range_cf(self.cf(name)?, None::<&[u8]>, None::<&[u8]>);
+        }
+        Ok(())
+    }
+
+    /// Recompute the hash of every stored block and check chain linkage
+    pub fn verify(&self) -> Result<VerifyReport, Box<dyn std::error::Error>> {
+        let mut report = VerifyReport::default();
+        // (index, hash) of the previous block; blocks are checked in place without decoding
+        let mut previous: Option<(u64, [u8; 32])> = None;
+
+        for item in self.db.iterator_cf(self.cf(CF_BLOCKS)?, IteratorMode::Start) {
+            let (key, value) = item?;
+            report.checked += 1;
+
+            let block = match BlockView::parse(&value) {
+                Ok(block) => block,
+                Err(_) => {
+                    report.undecodable.push(hex::encode(&key));
+                    previous = None;
+                    continue;
+                }
+            };
+
+            if !block.verify_hash() || key[..] != block.index().to_be_bytes()[..] {
+                report.hash_mismatches.push(block.index());
+            }
+            if let Some((prev_index, prev_hash)) = previous {
+                if prev_index + 1 == block.index() && prev_hash != *block.previous_hash() {
+                    report.broken_links.push(block.index());
+                }
+            }
+            previous = Some((block.index(), *block.hash()));
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::precision::PreciseFloat;
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("metaverse_db_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        path.to_string_lossy().to_string()
+    }
+
+    fn block(index: u64, previous_hash: [u8; 32]) -> Block {
+        let one = PreciseFloat::new(1, 2);
+        Block::new(index, previous_hash, vec![index as u8], one.clone(), one.clone(), one.clone(), one)
+    }
+
+    #[test]
+    fn test_verify_detects_tampering() {
+        let path = temp_path("verify");
+        let db = NodeDatabase::open(&path).unwrap();
+
+        let genesis = block(0, [0; 32]);
+        let next = block(1, genesis.hash);
+        db.put_block(&genesis).unwrap();
+        db.put_block(&next).unwrap();
+        assert!(db.verify().unwrap().is_ok());
+
+        let mut tampered = next.clone();
+        tampered.data = b"forged".to_vec();
+        db.put_block(&tampered).unwrap();
+
+        let report = db.verify().unwrap();
+        assert_eq!(report.checked, 2);
+        assert_eq!(report.hash_mismatches, vec![1]);
+
+        drop(db);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_consensus_log_keeps_order_per_tally() {
+        use crate::orchestration::audit;
+        use crate::orchestration::QuantumVote;
+
+        let path = temp_path("consensus");
+        let db = NodeDatabase::open(&path).unwrap();
+        let vote = |observer: u8| QuantumVote {
+            observer_id: [observer; 32],
+            observed_state: vec![1, 2],
+            observation_time: 0,
+            confidence: PreciseFloat::new(90, 2),
+        };
+        let first = audit::evaluate([5; 32], vec![vote(1)], 1);
+        let second = audit::evaluate([5; 32], vec![vote(1), vote(2), vote(3)], 2);
+        db.put_consensus_evaluation(&first).unwrap();
+        db.put_consensus_evaluation(&audit::evaluate([6; 32], vec![vote(1)], 1)).unwrap();
+        db.put_consensus_evaluation(&second).unwrap();
+
+        assert_eq!(db.consensus_evaluations(&[5; 32]).unwrap(), vec![first, second]);
+        assert!(db.consensus_evaluations(&[7; 32]).unwrap().is_empty());
+
+        drop(db);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_stats_cover_all_column_families() {
+        let path = temp_path("stats");
+        let db = NodeDatabase::open(&path).unwrap();
+        db.put(CF_STATE, b"key", b"value").unwrap();
+        db.compact().unwrap();
+
+        let stats = db.stats().unwrap();
+        let names: Vec<_> = stats.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, COLUMN_FAMILIES.to_vec());
+
+        drop(db);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+}
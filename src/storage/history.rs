@@ -0,0 +1,121 @@
+use std::collections::BTreeMap;
+
+/// Per-height versions of keyed values.
+/// A version is stored only at the heights where a key's value changed, so
+/// keys that rarely change cost little however long the history is.
+#[derive(Debug, Clone)]
+pub struct VersionedMap<K: Ord, V> {
+    versions: BTreeMap<K, BTreeMap<u64, Option<V>>>,
+    /// Lowest height that can still be queried
+    earliest: u64,
+}
+
+impl<K: Ord + Clone, V: Clone + PartialEq> VersionedMap<K, V> {
+    pub fn new() -> Self {
+        Self { versions: BTreeMap::new(), earliest: 0 }
+    }
+
+    /// Record `key`'s value as of block `height`; `None` means removed.
+    /// Unchanged values are not stored again.
+    pub fn record(&mut self, height: u64, key: K, value: Option<V>) {
+        let history = self.versions.entry(key).or_default();
+        let current = history.range(..=height).next_back().and_then(|(_, value)| value.as_ref());
+        if current != value.as_ref() {
+            history.insert(height, value);
+        }
+    }
+
+    /// Value of `key` as of block `height`
+    pub fn get_at(&self, key: &K, height: u64) -> Result<Option<&V>, &'static str> {
+        if height < self.earliest {
+            return Err("History pruned at this height");
+        }
+        Ok(self.versions.get(key)
+            .and_then(|history| history.range(..=height).next_back())
+            .and_then(|(_, value)| value.as_ref()))
+    }
+
+    /// Newest value of `key`
+    pub fn latest(&self, key: &K) -> Option<&V> {
+        self.versions.get(key)
+            .and_then(|history| history.values().next_back())
+            .and_then(|value| value.as_ref())
+    }
+
+    /// Keys with a value as of their newest version
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.versions.keys().filter(|key| self.latest(key).is_some())
+    }
+
+    pub fn earliest(&self) -> u64 {
+        self.earliest
+    }
+
+    /// Drop versions only needed for heights below `height`. The version in
+    /// effect at `height` is kept, and keys removed by then are forgotten.
+    pub fn prune(&mut self, height: u64) {
+        if height <= self.earliest {
+            return;
+        }
+        self.earliest = height;
+        self.versions.retain(|_, history| {
+            if let Some(&effective) = history.range(..=height).next_back().map(|(at, _)| at) {
+                *history = history.split_off(&effective);
+            }
+            !matches!(history.iter().next_back(), Some((_, None)) if history.len() == 1)
+        });
+    }
+
+    /// Forget versions recorded after `height`, when a reorg replaces them
+    pub fn truncate(&mut self, height: u64) {
+        self.versions.retain(|_, history| {
+            history.split_off(&(height + 1));
+            !history.is_empty()
+        });
+    }
+}
+
+impl<K: Ord + Clone, V: Clone + PartialEq> Default for VersionedMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_values_as_of_height() {
+        let mut map = VersionedMap::new();
+        map.record(1, "a", Some(10));
+        map.record(2, "a", Some(10));
+        map.record(5, "a", Some(20));
+        map.record(7, "a", None);
+
+        assert_eq!(map.get_at(&"a", 0).unwrap(), None);
+        assert_eq!(map.get_at(&"a", 4).unwrap(), Some(&10));
+        assert_eq!(map.get_at(&"a", 6).unwrap(), Some(&20));
+        assert_eq!(map.get_at(&"a", 9).unwrap(), None);
+        assert_eq!(map.keys().count(), 0);
+
+        map.truncate(5);
+        assert_eq!(map.latest(&"a"), Some(&20));
+    }
+
+    #[test]
+    fn test_prune_keeps_effective_version() {
+        let mut map = VersionedMap::new();
+        map.record(1, "a", Some(10));
+        map.record(5, "a", Some(20));
+        map.record(2, "b", Some(1));
+        map.record(3, "b", None);
+
+        map.prune(4);
+        assert_eq!(map.get_at(&"a", 3), Err("History pruned at this height"));
+        assert_eq!(map.get_at(&"a", 4).unwrap(), Some(&10));
+        assert_eq!(map.get_at(&"a", 5).unwrap(), Some(&20));
+        assert_eq!(map.get_at(&"b", 4).unwrap(), None);
+        assert_eq!(map.versions.len(), 1);
+    }
+}
@@ -1,2 +1,8 @@
 pub mod quantum_store;
 pub mod merkle;
+pub mod database;
+pub mod cache;
+pub mod history;
+pub mod reindex;
+pub mod corpus;
+pub mod remote;
@@ -0,0 +1,199 @@
+//! Rebuilding derived indexes from the chain store.
+//!
+//! The transaction index and stored receipts are derived from blocks alone.
+//! `Reindexer` streams the stored blocks in order, re-executes each block's
+//! transactions against a world state replayed from genesis, and rewrites
+//! both indexes. Progress is checkpointed in the database together with the
+//! world state it reached, so an interrupted rebuild resumes where it
+//! stopped, and a later run only indexes blocks added since. Index writes
+//! overwrite by key, so re-doing the blocks after the last checkpoint is
+//! harmless.
+
+use serde::{Serialize, Deserialize};
+use crate::blockchain::core::Block;
+use crate::blockchain::execution::Executor;
+use crate::blockchain::state::WorldState;
+use crate::blockchain::transaction::{Transaction, TxHash};
+use super::database::{NodeDatabase, CF_RECEIPTS, CF_STATE, CF_TX_INDEX};
+
+/// Blocks between saved checkpoints
+pub const CHECKPOINT_INTERVAL: u64 = 1_000;
+
+const CHECKPOINT_KEY: &[u8] = b"reindex.checkpoint";
+
+/// Transactions carried by a block. A block's `data` holds its
+/// bincode-encoded transaction list; blocks with any other payload, such as
+/// genesis, carry none.
+pub fn block_transactions(block: &Block) -> Vec<Transaction> {
+    bincode::deserialize(&block.data).unwrap_or_default()
+}
+
+/// Where a rebuild got to, saved every `CHECKPOINT_INTERVAL` blocks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Checkpoint {
+    next_height: u64,
+    state: WorldState,
+    transactions: u64,
+    receipts: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReindexProgress {
+    /// Next block to index
+    pub next_height: u64,
+    /// Last stored block
+    pub latest: Option<u64>,
+    /// Blocks indexed by this run
+    pub blocks: u64,
+    /// Totals across all runs since the indexes were last cleared
+    pub transactions: u64,
+    pub receipts: u64,
+    /// Height this run picked up from a checkpoint at
+    pub resumed_from: Option<u64>,
+}
+
+/// Reindexer
+/// Rebuilds the transaction index and receipts from stored blocks.
+pub struct Reindexer<'a> {
+    db: &'a NodeDatabase,
+    genesis: WorldState,
+    checkpoint_interval: u64,
+}
+
+impl<'a> Reindexer<'a> {
+    pub fn new(db: &'a NodeDatabase, genesis: WorldState) -> Self {
+        Self { db, genesis, checkpoint_interval: CHECKPOINT_INTERVAL }
+    }
+
+    pub fn with_checkpoint_interval(mut self, blocks: u64) -> Self {
+        self.checkpoint_interval = blocks.max(1);
+        self
+    }
+
+    /// Index every block not yet covered by the checkpoint, or all of them
+    /// from genesis if `restart`. `report` is called at each checkpoint.
+    pub fn run(&self, restart: bool, mut report: impl FnMut(&ReindexProgress)) -> Result<ReindexProgress, String> {
+        let checkpoint = if restart { None } else { self.load_checkpoint()? };
+        let resumed_from = checkpoint.as_ref().map(|checkpoint| checkpoint.next_height);
+        let mut checkpoint = match checkpoint {
+            Some(checkpoint) => checkpoint,
+            None => {
+                // Starting over: entries from an earlier chain must not survive
+                for cf in [CF_TX_INDEX, CF_RECEIPTS] {
+                    self.db.clear(cf).map_err(|e| e.to_string())?;
+                }
+                Checkpoint { next_height: 0, state: self.genesis.clone(), transactions: 0, receipts: 0 }
+            }
+        };
+        let mut progress = ReindexProgress {
+            next_height: checkpoint.next_height,
+            latest: self.db.latest_block_index().map_err(|e| e.to_string())?,
+            resumed_from,
+            transactions: checkpoint.transactions,
+            receipts: checkpoint.receipts,
+            ..ReindexProgress::default()
+        };
+
+        for block in self.db.blocks_from(checkpoint.next_height).map_err(|e| e.to_string())? {
+            let block = block?;
+            if block.index != checkpoint.next_height {
+                return Err(format!("Block {} is missing from the chain store", checkpoint.next_height));
+            }
+            let transactions = block_transactions(&block);
+            // Genesis state is given, not executed
+            let receipts = if block.index == 0 {
+                Vec::new()
+            } else {
+                Executor::apply_block(&mut checkpoint.state, &transactions)
+                    .map_err(|e| format!("Block {} failed to re-execute: {}", block.index, e))?
+            };
+            checkpoint.state.set_height(block.index);
+            let hashes: Vec<TxHash> = transactions.iter().map(Transaction::hash).collect();
+            self.db.put_block_indexes(block.index, &hashes, &receipts).map_err(|e| e.to_string())?;
+
+            checkpoint.next_height += 1;
+            checkpoint.transactions += hashes.len() as u64;
+            checkpoint.receipts += receipts.len() as u64;
+            progress.blocks += 1;
+            progress.next_height = checkpoint.next_height;
+            progress.transactions = checkpoint.transactions;
+            progress.receipts = checkpoint.receipts;
+            if progress.blocks.is_multiple_of(self.checkpoint_interval) {
+                self.save_checkpoint(&checkpoint)?;
+                report(&progress);
+            }
+        }
+        self.save_checkpoint(&checkpoint)?;
+        report(&progress);
+        Ok(progress)
+    }
+
+    fn load_checkpoint(&self) -> Result<Option<Checkpoint>, String> {
+        match self.db.get(CF_STATE, CHECKPOINT_KEY).map_err(|e| e.to_string())? {
+            Some(raw) => bincode::deserialize(&raw).map(Some).map_err(|e| format!("Corrupt reindex checkpoint: {}", e)),
+            None => Ok(None),
+        }
+    }
+
+    fn save_checkpoint(&self, checkpoint: &Checkpoint) -> Result<(), String> {
+        let raw = bincode::serialize(checkpoint).map_err(|e| e.to_string())?;
+        self.db.put(CF_STATE, CHECKPOINT_KEY, &raw).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::transaction::TransactionAction;
+    use crate::math::precision::PreciseFloat;
+    use ed25519_dalek::SigningKey;
+
+    fn block(index: u64, previous_hash: [u8; 32], data: Vec<u8>) -> Block {
+        let one = PreciseFloat::new(1, 2);
+        Block::new(index, previous_hash, data, one.clone(), one.clone(), one.clone(), one)
+    }
+
+    #[test]
+    fn test_rebuild_resumes_from_checkpoint() {
+        let path = std::env::temp_dir().join(format!("metaverse_reindex_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        let db = NodeDatabase::open(&path.to_string_lossy()).unwrap();
+
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let sender = key.verifying_key().to_bytes();
+        let genesis = WorldState::with_balances(&[(sender, 1_000_000_000)]);
+        let mut previous = block(0, [0; 32], b"genesis".to_vec());
+        db.put_block(&previous).unwrap();
+        let mut hashes = Vec::new();
+        for index in 1..=4u64 {
+            let mut tx = Transaction::new(sender, index - 1, TransactionAction::Transfer { to: [9; 32], amount: 10 }, 100_000, 1);
+            tx.sign(&key);
+            hashes.push(tx.hash());
+            let next = block(index, previous.hash, bincode::serialize(&vec![tx]).unwrap());
+            db.put_block(&next).unwrap();
+            previous = next;
+        }
+
+        let reindexer = Reindexer::new(&db, genesis).with_checkpoint_interval(2);
+        let mut reports = Vec::new();
+        let progress = reindexer.run(false, |progress| reports.push(progress.blocks)).unwrap();
+        assert_eq!((progress.blocks, progress.transactions, progress.latest), (5, 4, Some(4)));
+        assert_eq!(reports, vec![2, 4, 5]);
+        assert_eq!(db.transaction_location(&hashes[2]).unwrap(), Some((3, 0)));
+        assert!(db.receipts(3).unwrap().unwrap()[0].success);
+
+        // Nothing new: the checkpoint covers every block
+        let again = reindexer.run(false, |_| {}).unwrap();
+        assert_eq!((again.blocks, again.resumed_from, again.transactions), (0, Some(5), 4));
+
+        // A lost index comes back from a restart
+        db.clear(CF_TX_INDEX).unwrap();
+        assert_eq!(db.transaction_location(&hashes[0]).unwrap(), None);
+        let rebuilt = reindexer.run(true, |_| {}).unwrap();
+        assert_eq!((rebuilt.blocks, rebuilt.resumed_from), (5, None));
+        assert_eq!(db.transaction_location(&hashes[0]).unwrap(), Some((1, 0)));
+
+        drop(db);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+}
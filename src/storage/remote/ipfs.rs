@@ -0,0 +1,68 @@
+use super::{ObjectStore, StoreFuture};
+use serde::Deserialize;
+
+/// IPFS Store
+/// Adds and pins objects through an IPFS node's HTTP API (Kubo RPC);
+/// deleting an object unpins it so the node can garbage-collect it.
+pub struct IpfsStore {
+    api_url: String,
+    client: reqwest::Client,
+}
+
+#[derive(Deserialize)]
+struct AddResponse {
+    #[serde(rename = "Hash")]
+    hash: String,
+}
+
+impl IpfsStore {
+    pub fn new(api_url: &str) -> Self {
+        Self {
+            api_url: api_url.trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    async fn call(&self, command: &str, request: reqwest::RequestBuilder) -> Result<reqwest::Response, String> {
+        let response = request.send().await
+            .map_err(|e| format!("IPFS {} failed: {}", command, e))?;
+        if !response.status().is_success() {
+            return Err(format!("IPFS {} failed with status {}", command, response.status()));
+        }
+        Ok(response)
+    }
+}
+
+impl ObjectStore for IpfsStore {
+    fn put<'a>(&'a self, hash: &'a [u8; 32], data: &'a [u8]) -> StoreFuture<'a, String> {
+        Box::pin(async move {
+            let part = reqwest::multipart::Part::bytes(data.to_vec()).file_name(hex::encode(hash));
+            let request = self.client
+                .post(format!("{}/api/v0/add?pin=true&cid-version=1", self.api_url))
+                .multipart(reqwest::multipart::Form::new().part("file", part));
+            let added: AddResponse = self.call("add", request).await?
+                .json()
+                .await
+                .map_err(|e| format!("Invalid IPFS add response: {}", e))?;
+            Ok(added.hash)
+        })
+    }
+
+    fn get<'a>(&'a self, location: &'a str) -> StoreFuture<'a, Vec<u8>> {
+        Box::pin(async move {
+            let request = self.client.post(format!("{}/api/v0/cat", self.api_url)).query(&[("arg", location)]);
+            self.call("cat", request).await?
+                .bytes()
+                .await
+                .map(|bytes| bytes.to_vec())
+                .map_err(|e| format!("IPFS download of {} failed: {}", location, e))
+        })
+    }
+
+    fn delete<'a>(&'a self, location: &'a str) -> StoreFuture<'a, ()> {
+        Box::pin(async move {
+            let request = self.client.post(format!("{}/api/v0/pin/rm", self.api_url)).query(&[("arg", location)]);
+            self.call("pin/rm", request).await.map(|_| ())
+        })
+    }
+}
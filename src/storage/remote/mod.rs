@@ -0,0 +1,297 @@
+//! Remote mirrors for snapshots, backups and large blobs.
+//!
+//! Objects are addressed by their blake3 hash. A local manifest records
+//! where each one was stored, so retrieval checks the bytes against the
+//! hash and lifecycle policies work the same on every backend.
+
+pub mod s3;
+pub mod ipfs;
+
+use crate::blockchain::types::hex_serde;
+use crate::clock::{self, SharedClock};
+use crate::config::{DataClass, LifecyclePolicy, RemoteBackendConfig, RemoteClassConfig};
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+
+pub type StoreFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, String>> + Send + 'a>>;
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Backend holding object bytes. `put` returns the location to fetch or
+/// delete the object by: a key, a CID or a file name.
+pub trait ObjectStore: Send + Sync {
+    fn put<'a>(&'a self, hash: &'a [u8; 32], data: &'a [u8]) -> StoreFuture<'a, String>;
+    fn get<'a>(&'a self, location: &'a str) -> StoreFuture<'a, Vec<u8>>;
+    fn delete<'a>(&'a self, location: &'a str) -> StoreFuture<'a, ()>;
+}
+
+/// Object store for a config entry
+pub fn open_store(config: &RemoteBackendConfig) -> Result<Arc<dyn ObjectStore>, String> {
+    Ok(match config {
+        RemoteBackendConfig::S3 { endpoint, bucket, region, prefix, access_key_env, secret_key_env } => {
+            Arc::new(s3::S3Store::from_env(endpoint, bucket, region, prefix, access_key_env, secret_key_env)?)
+        }
+        RemoteBackendConfig::Ipfs { api_url } => Arc::new(ipfs::IpfsStore::new(api_url)),
+        RemoteBackendConfig::Directory { path } => Arc::new(DirectoryStore::new(path)),
+    })
+}
+
+/// Objects stored as files named by their hash
+pub struct DirectoryStore {
+    root: PathBuf,
+}
+
+impl DirectoryStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl ObjectStore for DirectoryStore {
+    fn put<'a>(&'a self, hash: &'a [u8; 32], data: &'a [u8]) -> StoreFuture<'a, String> {
+        Box::pin(async move {
+            let name = hex::encode(hash);
+            tokio::fs::create_dir_all(&self.root).await
+                .map_err(|e| format!("Failed to create {}: {}", self.root.display(), e))?;
+            let tmp = self.root.join(format!("{}.tmp", name));
+            tokio::fs::write(&tmp, data).await
+                .map_err(|e| format!("Failed to write {}: {}", tmp.display(), e))?;
+            tokio::fs::rename(&tmp, self.root.join(&name)).await
+                .map_err(|e| format!("Failed to store {}: {}", name, e))?;
+            Ok(name)
+        })
+    }
+
+    fn get<'a>(&'a self, location: &'a str) -> StoreFuture<'a, Vec<u8>> {
+        Box::pin(async move {
+            tokio::fs::read(self.root.join(location)).await
+                .map_err(|e| format!("Failed to read {}: {}", location, e))
+        })
+    }
+
+    fn delete<'a>(&'a self, location: &'a str) -> StoreFuture<'a, ()> {
+        Box::pin(async move {
+            match tokio::fs::remove_file(self.root.join(location)).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    Err(format!("Failed to delete {}: {}", location, e))
+                }
+                _ => Ok(()),
+            }
+        })
+    }
+}
+
+/// Mirrored object as recorded in the manifest
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredObject {
+    pub class: DataClass,
+    /// Name the object was mirrored under, e.g. the local file name
+    pub name: String,
+    #[serde(with = "hex_serde")]
+    pub hash: [u8; 32],
+    pub location: String,
+    pub size: u64,
+    pub stored_at: u64,
+}
+
+struct MirrorClass {
+    store: Arc<dyn ObjectStore>,
+    lifecycle: LifecyclePolicy,
+}
+
+/// Remote Storage
+/// Mirrors data classes to their configured backends and enforces each
+/// class's lifecycle policy.
+pub struct RemoteStorage {
+    classes: BTreeMap<DataClass, MirrorClass>,
+    manifest_path: PathBuf,
+    objects: Vec<StoredObject>,
+    clock: SharedClock,
+}
+
+impl RemoteStorage {
+    pub fn open(config: &BTreeMap<DataClass, RemoteClassConfig>, manifest_path: impl Into<PathBuf>) -> Result<Self, String> {
+        Self::with_clock(config, manifest_path, clock::system())
+    }
+
+    pub fn with_clock(
+        config: &BTreeMap<DataClass, RemoteClassConfig>,
+        manifest_path: impl Into<PathBuf>,
+        clock: SharedClock,
+    ) -> Result<Self, String> {
+        let mut classes = BTreeMap::new();
+        for (class, remote) in config {
+            let store = open_store(&remote.backend)?;
+            classes.insert(*class, MirrorClass { store, lifecycle: remote.lifecycle.clone() });
+        }
+        let manifest_path = manifest_path.into();
+        let objects = match std::fs::read(&manifest_path) {
+            Ok(raw) => serde_json::from_slice(&raw)
+                .map_err(|e| format!("Corrupt manifest {}: {}", manifest_path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(format!("Failed to read {}: {}", manifest_path.display(), e)),
+        };
+        Ok(Self { classes, manifest_path, objects, clock })
+    }
+
+    /// Use `store` for a class; for tests and embedding
+    pub fn with_store(mut self, class: DataClass, store: Arc<dyn ObjectStore>, lifecycle: LifecyclePolicy) -> Self {
+        self.classes.insert(class, MirrorClass { store, lifecycle });
+        self
+    }
+
+    pub fn is_configured(&self, class: DataClass) -> bool {
+        self.classes.contains_key(&class)
+    }
+
+    pub fn objects(&self, class: DataClass) -> impl Iterator<Item = &StoredObject> {
+        self.objects.iter().filter(move |object| object.class == class)
+    }
+
+    /// Upload `data` under `name`. Content already in the class is not
+    /// uploaded again; the existing entry is refreshed instead.
+    pub async fn mirror(&mut self, class: DataClass, name: &str, data: &[u8]) -> Result<StoredObject, String> {
+        let mirror = self.classes.get(&class)
+            .ok_or_else(|| format!("No remote storage configured for {}", class.as_str()))?;
+        let hash: [u8; 32] = blake3::hash(data).into();
+        let now = self.clock.now_secs();
+
+        if let Some(existing) = self.objects.iter_mut().find(|object| object.class == class && object.hash == hash) {
+            existing.name = name.to_string();
+            existing.stored_at = now;
+            let existing = existing.clone();
+            self.save_manifest()?;
+            return Ok(existing);
+        }
+
+        let location = mirror.store.put(&hash, data).await?;
+        let object = StoredObject {
+            class,
+            name: name.to_string(),
+            hash,
+            location,
+            size: data.len() as u64,
+            stored_at: now,
+        };
+        self.objects.push(object.clone());
+        self.save_manifest()?;
+        Ok(object)
+    }
+
+    /// Download an object and check it against its content hash
+    pub async fn fetch(&self, class: DataClass, hash: &[u8; 32]) -> Result<Vec<u8>, String> {
+        let mirror = self.classes.get(&class)
+            .ok_or_else(|| format!("No remote storage configured for {}", class.as_str()))?;
+        let object = self.objects.iter()
+            .find(|object| object.class == class && &object.hash == hash)
+            .ok_or_else(|| format!("No {} object with hash {}", class.as_str(), hex::encode(hash)))?;
+        let data = mirror.store.get(&object.location).await?;
+        if blake3::hash(&data).as_bytes() != hash {
+            return Err(format!("Content hash mismatch for {} at {}", object.name, object.location));
+        }
+        Ok(data)
+    }
+
+    /// Delete objects outside their class's lifecycle policy; returns them
+    pub async fn apply_lifecycle(&mut self) -> Result<Vec<StoredObject>, String> {
+        let now = self.clock.now_secs();
+        let mut expired = Vec::new();
+        for (class, mirror) in &self.classes {
+            let mut objects: Vec<&StoredObject> = self.objects(*class).collect();
+            objects.sort_by_key(|object| std::cmp::Reverse(object.stored_at));
+            for (rank, object) in objects.into_iter().enumerate() {
+                let too_many = mirror.lifecycle.keep_last.is_some_and(|keep| rank >= keep);
+                let too_old = mirror.lifecycle.max_age_days
+                    .is_some_and(|days| now.saturating_sub(object.stored_at) > days * SECS_PER_DAY);
+                if too_many || too_old {
+                    expired.push(object.clone());
+                }
+            }
+        }
+
+        let mut deleted = Vec::new();
+        let mut failure = None;
+        for object in expired {
+            let store = &self.classes[&object.class].store;
+            match store.delete(&object.location).await {
+                Ok(()) => {
+                    self.objects.retain(|kept| !(kept.class == object.class && kept.hash == object.hash));
+                    deleted.push(object);
+                }
+                // Keep the entry so the next run retries
+                Err(e) => failure = Some(e),
+            }
+        }
+        self.save_manifest()?;
+        match failure {
+            Some(e) if deleted.is_empty() => Err(e),
+            _ => Ok(deleted),
+        }
+    }
+
+    fn save_manifest(&self) -> Result<(), String> {
+        if let Some(dir) = self.manifest_path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        let raw = serde_json::to_vec_pretty(&self.objects).map_err(|e| e.to_string())?;
+        let tmp = self.manifest_path.with_extension("tmp");
+        std::fs::write(&tmp, raw)
+            .and_then(|_| std::fs::rename(&tmp, &self.manifest_path))
+            .map_err(|e| format!("Failed to write {}: {}", self.manifest_path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_mirror_verify_and_lifecycle() {
+        let root = std::env::temp_dir().join(format!("qmv-remote-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let clock = MockClock::new(1_000);
+        let mut storage = RemoteStorage::with_clock(&BTreeMap::new(), root.join("manifest.json"), clock.clone())
+            .unwrap()
+            .with_store(DataClass::Snapshots, Arc::new(DirectoryStore::new(root.join("snapshots"))), LifecyclePolicy {
+                keep_last: Some(2),
+                max_age_days: None,
+            })
+            .with_store(DataClass::Backups, Arc::new(DirectoryStore::new(root.join("backups"))), LifecyclePolicy {
+                keep_last: None,
+                max_age_days: Some(1),
+            });
+        assert!(storage.mirror(DataClass::Blobs, "blob", b"x").await.is_err());
+
+        let first = storage.mirror(DataClass::Snapshots, "chain.snapshot", b"snapshot 1").await.unwrap();
+        assert_eq!(storage.fetch(DataClass::Snapshots, &first.hash).await.unwrap(), b"snapshot 1".to_vec());
+
+        // Bytes altered in the store fail verification
+        std::fs::write(root.join("snapshots").join(&first.location), b"tampered").unwrap();
+        assert!(storage.fetch(DataClass::Snapshots, &first.hash).await.unwrap_err().contains("Content hash mismatch"));
+
+        clock.advance(Duration::from_secs(10));
+        storage.mirror(DataClass::Snapshots, "chain.snapshot", b"snapshot 2").await.unwrap();
+        clock.advance(Duration::from_secs(10));
+        storage.mirror(DataClass::Snapshots, "chain.snapshot", b"snapshot 3").await.unwrap();
+        storage.mirror(DataClass::Backups, "db.tar", b"backup").await.unwrap();
+
+        // Only the two newest snapshots are kept; the backup expires after a day
+        let deleted = storage.apply_lifecycle().await.unwrap();
+        assert_eq!(deleted.iter().map(|object| object.hash).collect::<Vec<_>>(), vec![first.hash]);
+        clock.advance(Duration::from_secs(2 * SECS_PER_DAY));
+        let deleted = storage.apply_lifecycle().await.unwrap();
+        assert!(deleted.iter().any(|object| object.class == DataClass::Backups));
+
+        // The manifest survives a restart
+        let reopened = RemoteStorage::with_clock(&BTreeMap::new(), root.join("manifest.json"), clock).unwrap();
+        assert_eq!(reopened.objects(DataClass::Snapshots).count(), storage.objects(DataClass::Snapshots).count());
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,255 @@
+//! Opt-in telemetry.
+//!
+//! When the operator enables it, the node periodically posts a small health
+//! report to a collector so network-wide dashboards are possible. Reports
+//! carry no keys, node IDs or addresses: an installation is identified only
+//! by a random ID generated on first use, and peers are reported as a count.
+//! Undelivered reports stay in a local buffer and are retried with the next
+//! report. Intervals are jittered so nodes started together do not report in
+//! lockstep.
+
+use serde::{Serialize, Deserialize};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Layout version of `HealthReport`; collectors use it to parse old nodes
+pub const REPORT_VERSION: u32 = 1;
+
+/// Node health at one point in time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthReport {
+    pub report_version: u32,
+    /// Random per-installation ID, unrelated to any node key
+    pub instance: String,
+    pub timestamp: u64,
+    /// Node software version
+    pub version: String,
+    pub os: String,
+    pub arch: String,
+    pub chain_id: u64,
+    pub peer_count: usize,
+    pub head_height: u64,
+    /// Tallies computed per second since the previous report
+    pub tally_throughput: f64,
+}
+
+/// Rate of a monotonic counter between successive samples
+#[derive(Debug, Clone)]
+pub struct ThroughputMeter {
+    last_count: u64,
+    last_millis: u64,
+}
+
+impl ThroughputMeter {
+    pub fn new(count: u64, now_millis: u64) -> Self {
+        Self { last_count: count, last_millis: now_millis }
+    }
+
+    /// Per-second rate since the previous sample
+    pub fn sample(&mut self, count: u64, now_millis: u64) -> f64 {
+        let elapsed = now_millis.saturating_sub(self.last_millis);
+        let delta = count.saturating_sub(self.last_count);
+        self.last_count = count;
+        self.last_millis = now_millis;
+        if elapsed == 0 {
+            return 0.0;
+        }
+        delta as f64 * 1000.0 / elapsed as f64
+    }
+}
+
+/// `interval` spread by up to `jitter_percent` either way. `sample` is a
+/// uniform random number in `[0, 1)`.
+pub fn jittered_interval(interval: Duration, jitter_percent: u8, sample: f64) -> Duration {
+    let spread = interval.as_secs_f64() * jitter_percent.min(100) as f64 / 100.0;
+    let offset = spread * (2.0 * sample.clamp(0.0, 1.0) - 1.0);
+    Duration::from_secs_f64((interval.as_secs_f64() + offset).max(0.0))
+}
+
+/// What the buffer file holds
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct BufferState {
+    instance: String,
+    pending: VecDeque<HealthReport>,
+}
+
+/// Telemetry Buffer
+/// The installation's random ID and the reports not yet delivered, kept in
+/// a JSON file so neither is lost on restart.
+pub struct TelemetryBuffer {
+    path: PathBuf,
+    limit: usize,
+    state: BufferState,
+}
+
+impl TelemetryBuffer {
+    /// Load the buffer at `path`, creating a new installation ID if absent
+    pub fn open(path: impl Into<PathBuf>, limit: usize) -> Result<Self, String> {
+        let path = path.into();
+        let mut state = if path.exists() {
+            let raw = std::fs::read_to_string(&path)
+                .map_err(|e| format!("Failed to read telemetry buffer {}: {}", path.display(), e))?;
+            serde_json::from_str(&raw)
+                .map_err(|e| format!("Invalid telemetry buffer {}: {}", path.display(), e))?
+        } else {
+            BufferState::default()
+        };
+        if state.instance.is_empty() {
+            state.instance = hex::encode(rand::random::<[u8; 16]>());
+        }
+        let mut buffer = Self { path, limit: limit.max(1), state };
+        buffer.trim();
+        Ok(buffer)
+    }
+
+    pub fn instance(&self) -> &str {
+        &self.state.instance
+    }
+
+    pub fn pending(&self) -> &VecDeque<HealthReport> {
+        &self.state.pending
+    }
+
+    /// Change how many reports are kept, dropping the oldest beyond it
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit.max(1);
+        self.trim();
+    }
+
+    /// Queue a report. Returns how many old reports were dropped to make room.
+    pub fn push(&mut self, report: HealthReport) -> usize {
+        self.state.pending.push_back(report);
+        self.trim()
+    }
+
+    /// Forget reports the collector has accepted
+    pub fn clear(&mut self) {
+        self.state.pending.clear();
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        let raw = serde_json::to_string(&self.state).map_err(|e| e.to_string())?;
+        std::fs::write(&self.path, raw)
+            .map_err(|e| format!("Failed to write telemetry buffer {}: {}", self.path.display(), e))
+    }
+
+    fn trim(&mut self) -> usize {
+        let excess = self.state.pending.len().saturating_sub(self.limit);
+        self.state.pending.drain(..excess);
+        excess
+    }
+}
+
+/// Telemetry Client
+/// Buffers each report and posts everything pending to the collector as a
+/// JSON array.
+pub struct TelemetryClient {
+    client: reqwest::Client,
+    buffer: TelemetryBuffer,
+}
+
+impl TelemetryClient {
+    pub fn new(buffer: TelemetryBuffer) -> Self {
+        Self { client: reqwest::Client::new(), buffer }
+    }
+
+    pub fn buffer(&self) -> &TelemetryBuffer {
+        &self.buffer
+    }
+
+    pub fn buffer_mut(&mut self) -> &mut TelemetryBuffer {
+        &mut self.buffer
+    }
+
+    /// Buffer `report` and deliver all pending reports. Returns how many were
+    /// delivered; on failure they stay buffered for the next attempt.
+    pub async fn report(&mut self, endpoint: &str, report: HealthReport) -> Result<usize, String> {
+        self.buffer.push(report);
+        let result = self.deliver(endpoint).await;
+        self.buffer.save()?;
+        result
+    }
+
+    /// Upload a crash report to the collector's `crashes` path
+    pub async fn send_crash<T: Serialize>(&self, endpoint: &str, report: &T) -> Result<(), String> {
+        let url = format!("{}/crashes", endpoint.trim_end_matches('/'));
+        let response = self.client.post(&url)
+            .timeout(Duration::from_secs(10))
+            .json(report)
+            .send().await
+            .map_err(|e| format!("Crash report upload failed: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("Telemetry collector returned status {}", response.status()));
+        }
+        Ok(())
+    }
+
+    async fn deliver(&mut self, endpoint: &str) -> Result<usize, String> {
+        let count = self.buffer.pending().len();
+        let response = self.client.post(endpoint)
+            .timeout(Duration::from_secs(10))
+            .json(self.buffer.pending())
+            .send().await
+            .map_err(|e| format!("Telemetry delivery failed: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("Telemetry collector returned status {}", response.status()));
+        }
+        self.buffer.clear();
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(timestamp: u64) -> HealthReport {
+        HealthReport {
+            report_version: REPORT_VERSION,
+            instance: "test".to_string(),
+            timestamp,
+            version: "0.1.0".to_string(),
+            os: "linux".to_string(),
+            arch: "x86_64".to_string(),
+            chain_id: 1,
+            peer_count: 3,
+            head_height: 10,
+            tally_throughput: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_jitter_and_throughput() {
+        let interval = Duration::from_secs(100);
+        assert_eq!(jittered_interval(interval, 20, 0.0), Duration::from_secs(80));
+        assert_eq!(jittered_interval(interval, 20, 0.5), Duration::from_secs(100));
+        assert!(jittered_interval(interval, 20, 0.999) <= Duration::from_secs(120));
+
+        let mut meter = ThroughputMeter::new(100, 1_000);
+        assert_eq!(meter.sample(150, 11_000), 5.0);
+        assert_eq!(meter.sample(150, 11_000), 0.0);
+    }
+
+    #[test]
+    fn test_buffer_keeps_newest_reports() {
+        let path = std::env::temp_dir().join(format!("telemetry-{}.json", rand::random::<u64>()));
+        let mut buffer = TelemetryBuffer::open(&path, 2).unwrap();
+        let instance = buffer.instance().to_string();
+        assert_eq!(buffer.push(report(1)), 0);
+        assert_eq!(buffer.push(report(2)), 0);
+        assert_eq!(buffer.push(report(3)), 1);
+        buffer.save().unwrap();
+
+        // The installation ID and pending reports survive a restart
+        let reopened = TelemetryBuffer::open(&path, 2).unwrap();
+        assert_eq!(reopened.instance(), instance);
+        let timestamps: Vec<u64> = reopened.pending().iter().map(|report| report.timestamp).collect();
+        assert_eq!(timestamps, vec![2, 3]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
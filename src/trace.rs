@@ -0,0 +1,199 @@
+//! Request tracing.
+//!
+//! Every RPC call runs under a trace ID, taken from the caller's `X-Trace-Id`
+//! header or generated by the node. The ID tags the call's log lines, is
+//! returned in the response, travels with the P2P messages the call leads to,
+//! and is recorded against each transaction it touches. A client that keeps
+//! the ID can then ask any node what happened to its transaction there.
+
+use serde::{Serialize, Deserialize};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::future::Future;
+
+/// HTTP header a caller uses to supply its own trace ID
+pub const TRACE_HEADER: &str = "x-trace-id";
+
+/// Longest caller-supplied trace ID accepted
+pub const MAX_TRACE_ID_LEN: usize = 64;
+
+/// Events kept per transaction; the oldest are dropped beyond this
+const MAX_EVENTS_PER_TX: usize = 32;
+
+/// Trace ID
+/// Opaque correlation ID for one client request and everything it causes.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TraceId(String);
+
+impl TraceId {
+    /// A fresh random ID
+    pub fn generate() -> Self {
+        Self(hex::encode(rand::random::<[u8; 16]>()))
+    }
+
+    /// Accept a caller-supplied ID if it is short and limited to
+    /// alphanumerics, `-` and `_`, so it is safe to echo into logs
+    pub fn parse(raw: &str) -> Option<Self> {
+        let raw = raw.trim();
+        let valid = !raw.is_empty()
+            && raw.len() <= MAX_TRACE_ID_LEN
+            && raw.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        valid.then(|| Self(raw.to_string()))
+    }
+
+    /// The caller's ID if it is valid, otherwise a fresh one
+    pub fn from_header(header: Option<&str>) -> Self {
+        header.and_then(Self::parse).unwrap_or_else(Self::generate)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for TraceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+tokio::task_local! {
+    static CURRENT: TraceId;
+}
+
+/// Run `future` with `trace` as the current trace ID
+pub async fn scope<F: Future>(trace: TraceId, future: F) -> F::Output {
+    CURRENT.scope(trace, future).await
+}
+
+/// Trace ID of the request being handled on this task, if any
+pub fn current() -> Option<TraceId> {
+    CURRENT.try_with(|trace| trace.clone()).ok()
+}
+
+/// Point in a transaction's path through a node
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceStage {
+    /// Dry-run against committed state
+    Simulated,
+    /// Revealed against an earlier commitment
+    Revealed,
+    /// Arrived from a peer
+    PeerReceived,
+    /// Passed on to other peers
+    Relayed,
+}
+
+/// One recorded step of a traced transaction
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceEvent {
+    pub trace_id: TraceId,
+    pub stage: TraceStage,
+    /// Milliseconds since the Unix epoch
+    pub timestamp: u64,
+    pub detail: String,
+}
+
+/// Trace Log
+/// Recent trace events keyed by transaction hash. Bounded: once `capacity`
+/// transactions are tracked, the least recently traced is forgotten.
+pub struct TraceLog {
+    capacity: usize,
+    order: VecDeque<[u8; 32]>,
+    events: HashMap<[u8; 32], VecDeque<TraceEvent>>,
+}
+
+impl TraceLog {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity: capacity.max(1), order: VecDeque::new(), events: HashMap::new() }
+    }
+
+    /// Record that `tx_hash` reached `stage` under `trace_id`
+    pub fn record(&mut self, tx_hash: [u8; 32], trace_id: TraceId, stage: TraceStage, timestamp: u64, detail: impl Into<String>) {
+        if let Some(position) = self.order.iter().position(|hash| *hash == tx_hash) {
+            self.order.remove(position);
+        } else if self.order.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.events.remove(&oldest);
+            }
+        }
+        self.order.push_back(tx_hash);
+
+        let events = self.events.entry(tx_hash).or_default();
+        if events.len() >= MAX_EVENTS_PER_TX {
+            events.pop_front();
+        }
+        events.push_back(TraceEvent { trace_id, stage, timestamp, detail: detail.into() });
+    }
+
+    /// Everything recorded for a transaction, oldest first
+    pub fn transaction(&self, tx_hash: &[u8; 32]) -> Vec<&TraceEvent> {
+        self.events.get(tx_hash).map(|events| events.iter().collect()).unwrap_or_default()
+    }
+
+    /// Every event recorded under `trace_id`, with the transaction it concerns
+    pub fn by_trace(&self, trace_id: &TraceId) -> Vec<([u8; 32], &TraceEvent)> {
+        let mut found: Vec<_> = self.order.iter()
+            .flat_map(|hash| self.events[hash].iter().map(move |event| (*hash, event)))
+            .filter(|(_, event)| event.trace_id == *trace_id)
+            .collect();
+        found.sort_by_key(|(_, event)| event.timestamp);
+        found
+    }
+
+    /// Transactions currently tracked
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trace_id_parsing() {
+        assert_eq!(TraceId::parse(" client-42_a ").unwrap().as_str(), "client-42_a");
+        assert!(TraceId::parse("").is_none());
+        assert!(TraceId::parse("bad id\r\nX-Injected: 1").is_none());
+        assert!(TraceId::parse(&"a".repeat(MAX_TRACE_ID_LEN + 1)).is_none());
+        assert_eq!(TraceId::from_header(None).as_str().len(), 32);
+        assert_eq!(TraceId::from_header(Some("abc")).as_str(), "abc");
+    }
+
+    #[tokio::test]
+    async fn test_current_trace_is_task_scoped() {
+        assert!(current().is_none());
+        let trace = TraceId::parse("outer").unwrap();
+        let seen = scope(trace.clone(), async { current() }).await;
+        assert_eq!(seen, Some(trace));
+        assert!(current().is_none());
+    }
+
+    #[test]
+    fn test_log_follows_transactions_and_evicts_oldest() {
+        let mut log = TraceLog::new(2);
+        let first = TraceId::parse("first").unwrap();
+        let second = TraceId::parse("second").unwrap();
+        log.record([1; 32], first.clone(), TraceStage::Simulated, 10, "ok");
+        log.record([1; 32], first.clone(), TraceStage::Revealed, 20, "");
+        log.record([2; 32], second.clone(), TraceStage::PeerReceived, 30, "from peer");
+
+        let stages: Vec<_> = log.transaction(&[1; 32]).iter().map(|event| event.stage).collect();
+        assert_eq!(stages, vec![TraceStage::Simulated, TraceStage::Revealed]);
+        assert_eq!(log.by_trace(&second).len(), 1);
+
+        // Touching tx 1 again makes tx 2 the oldest, so it goes first
+        log.record([1; 32], first.clone(), TraceStage::Relayed, 40, "");
+        log.record([3; 32], second.clone(), TraceStage::PeerReceived, 50, "");
+        assert_eq!(log.len(), 2);
+        assert!(log.transaction(&[2; 32]).is_empty());
+        assert_eq!(log.by_trace(&first).len(), 3);
+    }
+}
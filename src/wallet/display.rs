@@ -0,0 +1,339 @@
+use crate::blockchain::account_validation::ValidationAction;
+use crate::blockchain::assets::{AssetAction, AssetKind};
+use crate::blockchain::channel::ChannelAction;
+use crate::blockchain::confidential::ConfidentialAction;
+use crate::blockchain::htlc::HtlcAction;
+use crate::blockchain::lease::LeaseAction;
+use crate::blockchain::market::{ListingTerms, MarketAction};
+use crate::blockchain::multisig::MultisigOperation;
+use crate::blockchain::scheduler::ScheduledAction;
+use crate::blockchain::sponsor::SponsorAction;
+use crate::blockchain::transaction::{Transaction, TransactionAction};
+
+/// Longest call input a device screen can show in full, in bytes
+pub const MAX_DISPLAYED_INPUT: usize = 32;
+
+/// One screen line of a transaction review
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisplayField {
+    pub label: &'static str,
+    pub value: String,
+}
+
+impl DisplayField {
+    fn new(label: &'static str, value: String) -> Self {
+        Self { label, value }
+    }
+}
+
+/// What the signer shows before approving a transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionReview {
+    pub fields: Vec<DisplayField>,
+    /// Parts of the transaction the screen cannot show; approving it means
+    /// signing bytes the user has not reviewed
+    pub blind: bool,
+}
+
+/// Review screens for `tx`. The device app renders the same fields from the
+/// signing payload, so the host can print them for comparison.
+pub fn review(tx: &Transaction) -> TransactionReview {
+    let mut fields = Vec::new();
+    let mut blind = false;
+    match &tx.action {
+        TransactionAction::Transfer { to, amount } => {
+            fields.push(DisplayField::new("Type", "Transfer".to_string()));
+            fields.push(DisplayField::new("Amount", format_amount(*amount)));
+            fields.push(DisplayField::new("Recipient", format_address(to)));
+        }
+        TransactionAction::Call { contract, input, value } => {
+            fields.push(DisplayField::new("Type", "Contract call".to_string()));
+            fields.push(DisplayField::new("Contract", format_address(contract)));
+            fields.push(DisplayField::new("Amount", format_amount(*value)));
+            if input.len() > MAX_DISPLAYED_INPUT {
+                blind = true;
+                fields.push(DisplayField::new("Input", format!("{} bytes (not shown)", input.len())));
+            } else {
+                fields.push(DisplayField::new("Input", format!("0x{}", hex::encode(input))));
+            }
+        }
+        TransactionAction::Deploy { code } => {
+            // Contract code cannot be meaningfully reviewed on a device screen
+            blind = true;
+            fields.push(DisplayField::new("Type", "Deploy contract".to_string()));
+            fields.push(DisplayField::new("Code", format!("{} instructions (not shown)", code.len())));
+        }
+        TransactionAction::RotateValidatorKey(rotation) => {
+            fields.push(DisplayField::new("Type", "Rotate validator key".to_string()));
+            fields.push(DisplayField::new("Validator", format_address(&rotation.validator)));
+            fields.push(DisplayField::new("New key", format_address(&rotation.new_key)));
+            fields.push(DisplayField::new("Activates at", rotation.activation_height.to_string()));
+        }
+        TransactionAction::CreateMultisig { signers, threshold } => {
+            fields.push(DisplayField::new("Type", "Create multisig".to_string()));
+            fields.push(DisplayField::new("Threshold", format!("{} of {}", threshold, signers.len())));
+            for signer in signers {
+                fields.push(DisplayField::new("Signer", format_address(signer)));
+            }
+        }
+        TransactionAction::MultisigExecute { multisig, operation, approvals } => {
+            fields.push(DisplayField::new("Type", "Multisig execute".to_string()));
+            fields.push(DisplayField::new("Multisig", format_address(multisig)));
+            match operation {
+                MultisigOperation::Transfer { to, amount } => {
+                    fields.push(DisplayField::new("Amount", format_amount(*amount)));
+                    fields.push(DisplayField::new("Recipient", format_address(to)));
+                }
+                MultisigOperation::Call { contract, input, value } => {
+                    fields.push(DisplayField::new("Contract", format_address(contract)));
+                    fields.push(DisplayField::new("Amount", format_amount(*value)));
+                    if input.len() > MAX_DISPLAYED_INPUT {
+                        blind = true;
+                        fields.push(DisplayField::new("Input", format!("{} bytes (not shown)", input.len())));
+                    } else {
+                        fields.push(DisplayField::new("Input", format!("0x{}", hex::encode(input))));
+                    }
+                }
+                MultisigOperation::UpdateSigners { signers, threshold } => {
+                    fields.push(DisplayField::new("New threshold", format!("{} of {}", threshold, signers.len())));
+                    for signer in signers {
+                        fields.push(DisplayField::new("Signer", format_address(signer)));
+                    }
+                }
+            }
+            fields.push(DisplayField::new("Approvals", approvals.len().to_string()));
+        }
+        TransactionAction::Schedule { action, start_height, interval, max_runs, run_gas_limit, deposit } => {
+            fields.push(DisplayField::new("Type", "Schedule".to_string()));
+            match action {
+                ScheduledAction::Transfer { to, amount } => {
+                    fields.push(DisplayField::new("Amount", format_amount(*amount)));
+                    fields.push(DisplayField::new("Recipient", format_address(to)));
+                }
+                ScheduledAction::Call { contract, input, value } => {
+                    fields.push(DisplayField::new("Contract", format_address(contract)));
+                    fields.push(DisplayField::new("Amount", format_amount(*value)));
+                    if input.len() > MAX_DISPLAYED_INPUT {
+                        blind = true;
+                        fields.push(DisplayField::new("Input", format!("{} bytes (not shown)", input.len())));
+                    } else {
+                        fields.push(DisplayField::new("Input", format!("0x{}", hex::encode(input))));
+                    }
+                }
+            }
+            fields.push(DisplayField::new("First run", start_height.to_string()));
+            let repeat = match (interval, max_runs) {
+                (None, _) => "Once".to_string(),
+                (Some(every), Some(runs)) => format!("Every {} blocks, {} times", every, runs),
+                (Some(every), None) => format!("Every {} blocks until the deposit runs out", every),
+            };
+            fields.push(DisplayField::new("Repeat", repeat));
+            fields.push(DisplayField::new("Run gas limit", run_gas_limit.to_string()));
+            fields.push(DisplayField::new("Deposit", format_amount(*deposit)));
+        }
+        TransactionAction::CancelSchedule { id } => {
+            fields.push(DisplayField::new("Type", "Cancel schedule".to_string()));
+            fields.push(DisplayField::new("Schedule", format_address(id)));
+        }
+        TransactionAction::Asset(AssetAction::Mint { kind, royalty_bps, uri, metadata }) => {
+            fields.push(DisplayField::new("Type", "Mint asset".to_string()));
+            let kind = match kind {
+                AssetKind::Nft => "NFT".to_string(),
+                AssetKind::Parcel { x, y } => format!("Parcel ({}, {})", x, y),
+            };
+            fields.push(DisplayField::new("Kind", kind));
+            fields.push(DisplayField::new("Royalty", format!("{}.{:02}%", royalty_bps / 100, royalty_bps % 100)));
+            fields.push(DisplayField::new("URI", uri.clone()));
+            if let Some(metadata) = metadata {
+                fields.push(DisplayField::new("Name", metadata.name.clone()));
+                fields.push(DisplayField::new("Metadata", format!("0x{}", hex::encode(metadata.hash()))));
+            }
+        }
+        TransactionAction::Asset(AssetAction::Transfer { asset, to }) => {
+            fields.push(DisplayField::new("Type", "Transfer asset".to_string()));
+            fields.push(DisplayField::new("Asset", format_address(asset)));
+            fields.push(DisplayField::new("Recipient", format_address(to)));
+        }
+        TransactionAction::Market(action) => {
+            let (kind, amount) = match action {
+                MarketAction::List { terms: ListingTerms::FixedPrice { price }, .. } => ("List for sale", Some(("Price", *price))),
+                MarketAction::List { terms: ListingTerms::Auction { reserve, .. }, .. } => ("List for auction", Some(("Reserve", *reserve))),
+                MarketAction::Cancel { .. } => ("Cancel listing", None),
+                MarketAction::Buy { price, .. } => ("Buy asset", Some(("Price", *price))),
+                MarketAction::Bid { amount, .. } => ("Bid on asset", Some(("Amount", *amount))),
+                MarketAction::Settle { .. } => ("Settle auction", None),
+            };
+            fields.push(DisplayField::new("Type", kind.to_string()));
+            fields.push(DisplayField::new("Asset", format_address(action.asset())));
+            if let Some((label, amount)) = amount {
+                fields.push(DisplayField::new(label, format_amount(amount)));
+            }
+            if let MarketAction::List { terms: ListingTerms::Auction { duration, .. }, .. } = action {
+                fields.push(DisplayField::new("Duration", format!("{} blocks", duration)));
+            }
+        }
+        TransactionAction::Lease(action) => {
+            let kind = match action {
+                LeaseAction::Offer { .. } => "Offer lease",
+                LeaseAction::Withdraw { .. } => "Withdraw lease offer",
+                LeaseAction::Accept { .. } => "Accept lease",
+            };
+            fields.push(DisplayField::new("Type", kind.to_string()));
+            fields.push(DisplayField::new("Asset", format_address(action.asset())));
+            match action {
+                LeaseAction::Offer { terms, .. } => {
+                    fields.push(DisplayField::new("Lessee", format_address(&terms.lessee)));
+                    fields.push(DisplayField::new("Duration", format!("{} blocks", terms.duration)));
+                    fields.push(DisplayField::new("Rent", format!("{} every {} blocks", format_amount(terms.rent_per_epoch), terms.epoch_blocks)));
+                }
+                LeaseAction::Accept { total_rent, .. } => {
+                    fields.push(DisplayField::new("Total rent", format_amount(*total_rent)));
+                }
+                LeaseAction::Withdraw { .. } => {}
+            }
+        }
+        TransactionAction::Confidential(action) => match action {
+            ConfidentialAction::Register { view_key } => {
+                fields.push(DisplayField::new("Type", "Register shielded account".to_string()));
+                fields.push(DisplayField::new("View key", format_address(view_key)));
+            }
+            ConfidentialAction::Shield { amount } => {
+                fields.push(DisplayField::new("Type", "Shield".to_string()));
+                fields.push(DisplayField::new("Amount", format_amount(*amount as u128)));
+            }
+            ConfidentialAction::Transfer { to, .. } => {
+                // The amount is only in a commitment the screen cannot open
+                blind = true;
+                fields.push(DisplayField::new("Type", "Confidential transfer".to_string()));
+                fields.push(DisplayField::new("Recipient", format_address(to)));
+                fields.push(DisplayField::new("Amount", "Hidden (not shown)".to_string()));
+            }
+            ConfidentialAction::Rollover { count, rejected } => {
+                fields.push(DisplayField::new("Type", "Roll over transfers".to_string()));
+                fields.push(DisplayField::new("Accepted", (0..*count).filter(|position| !rejected.contains(position)).count().to_string()));
+            }
+            ConfidentialAction::Unshield { amount, .. } => {
+                fields.push(DisplayField::new("Type", "Unshield".to_string()));
+                fields.push(DisplayField::new("Amount", format_amount(*amount as u128)));
+            }
+        },
+        TransactionAction::Channel(action) => match action {
+            ChannelAction::Open { counterparty, deposit, dispute_blocks } => {
+                fields.push(DisplayField::new("Type", "Open payment channel".to_string()));
+                fields.push(DisplayField::new("Counterparty", format_address(counterparty)));
+                fields.push(DisplayField::new("Deposit", format_amount(*deposit)));
+                fields.push(DisplayField::new("Dispute period", format!("{} blocks", dispute_blocks)));
+            }
+            ChannelAction::Deposit { channel, amount } => {
+                fields.push(DisplayField::new("Type", "Fund payment channel".to_string()));
+                fields.push(DisplayField::new("Channel", format_address(channel)));
+                fields.push(DisplayField::new("Amount", format_amount(*amount)));
+            }
+            ChannelAction::Settle { update } | ChannelAction::Dispute { update } | ChannelAction::Close { update: Some(update), .. } => {
+                let kind = match action {
+                    ChannelAction::Settle { .. } => "Settle payment channel",
+                    ChannelAction::Dispute { .. } => "Dispute channel close",
+                    _ => "Close payment channel",
+                };
+                fields.push(DisplayField::new("Type", kind.to_string()));
+                fields.push(DisplayField::new("Channel", format_address(&update.update.channel)));
+                fields.push(DisplayField::new("Sequence", update.update.sequence.to_string()));
+                fields.push(DisplayField::new("Balance A", format_amount(update.update.balance_a)));
+                fields.push(DisplayField::new("Balance B", format_amount(update.update.balance_b)));
+            }
+            ChannelAction::Close { channel, update: None } => {
+                fields.push(DisplayField::new("Type", "Close payment channel".to_string()));
+                fields.push(DisplayField::new("Channel", format_address(channel)));
+                fields.push(DisplayField::new("Balances", "Deposits".to_string()));
+            }
+        },
+        TransactionAction::Htlc(HtlcAction::Lock(terms)) => {
+            fields.push(DisplayField::new("Type", "Lock HTLC".to_string()));
+            fields.push(DisplayField::new("Recipient", format_address(&terms.recipient)));
+            fields.push(DisplayField::new("Amount", format_amount(terms.amount)));
+            fields.push(DisplayField::new("Hashlock", format_address(&terms.hashlock)));
+            fields.push(DisplayField::new("Refund from", format!("Block {}", terms.timelock)));
+        }
+        TransactionAction::Htlc(HtlcAction::Claim { id, .. }) => {
+            fields.push(DisplayField::new("Type", "Claim HTLC".to_string()));
+            fields.push(DisplayField::new("HTLC", format_address(id)));
+        }
+        TransactionAction::Htlc(HtlcAction::Refund { id }) => {
+            fields.push(DisplayField::new("Type", "Refund HTLC".to_string()));
+            fields.push(DisplayField::new("HTLC", format_address(id)));
+        }
+        TransactionAction::Sponsor(SponsorAction::SetPolicy { limit, sender_limit, window_blocks }) => {
+            fields.push(DisplayField::new("Type", "Sponsor fees".to_string()));
+            fields.push(DisplayField::new("Limit", format_amount(*limit)));
+            fields.push(DisplayField::new("Per sender", format_amount(*sender_limit)));
+            fields.push(DisplayField::new("Window", format!("{} blocks", window_blocks)));
+        }
+        TransactionAction::Sponsor(SponsorAction::Revoke) => {
+            fields.push(DisplayField::new("Type", "Stop sponsoring fees".to_string()));
+        }
+        TransactionAction::Validation(ValidationAction::Install { code, storage, gas_limit }) => {
+            // Validation code decides who can spend from the account, and cannot be reviewed on screen
+            blind = true;
+            fields.push(DisplayField::new("Type", "Install validation contract".to_string()));
+            fields.push(DisplayField::new("Code", format!("{} instructions (not shown)", code.len())));
+            fields.push(DisplayField::new("Storage", format!("{} entries (not shown)", storage.len())));
+            fields.push(DisplayField::new("Gas limit", gas_limit.to_string()));
+        }
+        TransactionAction::Validation(ValidationAction::UpdateStorage { entries }) => {
+            blind = true;
+            fields.push(DisplayField::new("Type", "Update validation storage".to_string()));
+            fields.push(DisplayField::new("Storage", format!("{} entries (not shown)", entries.len())));
+        }
+        TransactionAction::Validation(ValidationAction::Remove) => {
+            fields.push(DisplayField::new("Type", "Remove validation contract".to_string()));
+        }
+    }
+    fields.push(DisplayField::new("Max fee", format_amount(tx.max_fee())));
+    if let Some(sponsorship) = &tx.sponsor {
+        fields.push(DisplayField::new("Fee paid by", format_address(&sponsorship.sponsor)));
+    }
+    fields.push(DisplayField::new("Nonce", tx.nonce.to_string()));
+    fields.push(DisplayField::new("Chain ID", tx.network_id.to_string()));
+    TransactionReview { fields, blind }
+}
+
+/// Base units with thousands separators
+pub fn format_amount(amount: u128) -> String {
+    let digits = amount.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(digit);
+    }
+    out
+}
+
+fn format_address(address: &[u8; 32]) -> String {
+    format!("0x{}", hex::encode(address))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_review_fields_and_blind_detection() {
+        let transfer = Transaction::new([1u8; 32], 3, TransactionAction::Transfer { to: [2u8; 32], amount: 1_500_000 }, 21_000, 2)
+            .with_network_id(5);
+        let screens = review(&transfer);
+        assert!(!screens.blind);
+        let shown: Vec<(&str, &str)> = screens.fields.iter().map(|f| (f.label, f.value.as_str())).collect();
+        assert_eq!(shown[0], ("Type", "Transfer"));
+        assert_eq!(shown[1], ("Amount", "1,500,000"));
+        assert_eq!(shown[2].1, format!("0x{}", "02".repeat(32)));
+        assert_eq!(&shown[3..], &[("Max fee", "42,000"), ("Nonce", "3"), ("Chain ID", "5")]);
+
+        let call = |input: Vec<u8>| Transaction::new([1u8; 32], 0, TransactionAction::Call { contract: [3u8; 32], input, value: 0 }, 50_000, 1);
+        assert!(!review(&call(vec![0xab; MAX_DISPLAYED_INPUT])).blind);
+        assert!(review(&call(vec![0xab; MAX_DISPLAYED_INPUT + 1])).blind);
+        assert_eq!(format_amount(0), "0");
+        assert_eq!(format_amount(999), "999");
+    }
+}
@@ -0,0 +1,44 @@
+use super::ledger::{HidTransport, HID_REPORT_SIZE};
+use hidapi::{HidApi, HidDevice};
+
+const LEDGER_VENDOR_ID: u16 = 0x2c97;
+/// Usage page of the interface that carries APDUs
+const LEDGER_USAGE_PAGE: u16 = 0xffa0;
+/// Give the user time to review and approve on the device
+const READ_TIMEOUT_MS: i32 = 120_000;
+
+/// HID transport to the first connected Ledger device
+pub struct LedgerHid {
+    device: HidDevice,
+}
+
+impl LedgerHid {
+    pub fn open() -> Result<Self, String> {
+        let api = HidApi::new().map_err(|e| format!("Failed to initialize HID: {}", e))?;
+        let info = api.device_list()
+            .find(|info| info.vendor_id() == LEDGER_VENDOR_ID && info.usage_page() == LEDGER_USAGE_PAGE)
+            .ok_or("No Ledger device found; connect and unlock it")?;
+        let device = info.open_device(&api).map_err(|e| format!("Failed to open Ledger device: {}", e))?;
+        Ok(Self { device })
+    }
+}
+
+impl HidTransport for LedgerHid {
+    fn write(&mut self, report: &[u8; HID_REPORT_SIZE]) -> Result<(), String> {
+        // hidapi expects the report ID first; Ledger devices use none
+        let mut buffer = [0u8; HID_REPORT_SIZE + 1];
+        buffer[1..].copy_from_slice(report);
+        self.device.write(&buffer).map_err(|e| format!("HID write failed: {}", e))?;
+        Ok(())
+    }
+
+    fn read(&mut self) -> Result<[u8; HID_REPORT_SIZE], String> {
+        let mut report = [0u8; HID_REPORT_SIZE];
+        let read = self.device.read_timeout(&mut report, READ_TIMEOUT_MS)
+            .map_err(|e| format!("HID read failed: {}", e))?;
+        if read == 0 {
+            return Err("Timed out waiting for the device".to_string());
+        }
+        Ok(report)
+    }
+}
@@ -0,0 +1,288 @@
+//! Ledger-style hardware signer.
+//!
+//! APDUs travel over HID in 64-byte reports: `channel u16 | tag 0x05 |
+//! sequence u16`, then the APDU (prefixed by its u16 length in the first
+//! report), zero-padded. All integers are big-endian.
+//!
+//! The device app answers two instructions under class `0xE0`:
+//! - `GET_PUBLIC_KEY`: data is the derivation path, reply is the ed25519 key
+//! - `SIGN_TX`: first the path and the payload length (u32), with P1 `0x00`,
+//!   then the signing payload in chunks with P1 `0x80`; once it has the whole
+//!   payload the app shows the fields from `display::review` and replies to
+//!   the last chunk with the signature
+
+use super::display::{self, TransactionReview};
+use super::TransactionSigner;
+use crate::blockchain::transaction::Transaction;
+use crate::blockchain::types::Address;
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+
+pub const HID_REPORT_SIZE: usize = 64;
+const HID_TAG_APDU: u8 = 0x05;
+const CHANNEL: u16 = 0x0101;
+
+const CLA: u8 = 0xE0;
+const INS_GET_PUBLIC_KEY: u8 = 0x02;
+const INS_SIGN_TX: u8 = 0x04;
+const P1_FIRST: u8 = 0x00;
+const P1_MORE: u8 = 0x80;
+const MAX_APDU_DATA: usize = 255;
+
+const SW_OK: u16 = 0x9000;
+const SW_USER_REJECTED: u16 = 0x6985;
+const SW_BLIND_SIGNING_DISABLED: u16 = 0x6808;
+const SW_WRONG_APP: u16 = 0x6E00;
+const SW_LOCKED: u16 = 0x5515;
+
+/// Hardened derivation path the app signs with by default: m/44'/7777'/0'
+pub const DEFAULT_PATH: [u32; 3] = [44 | HARDENED, 7_777 | HARDENED, HARDENED];
+const HARDENED: u32 = 0x8000_0000;
+
+/// Raw HID report exchange with a device
+pub trait HidTransport: Send {
+    fn write(&mut self, report: &[u8; HID_REPORT_SIZE]) -> Result<(), String>;
+    /// Read one report, waiting for the user to confirm if needed
+    fn read(&mut self) -> Result<[u8; HID_REPORT_SIZE], String>;
+}
+
+/// Split an APDU into HID reports
+pub fn frame_apdu(apdu: &[u8]) -> Vec<[u8; HID_REPORT_SIZE]> {
+    let mut payload = Vec::with_capacity(apdu.len() + 2);
+    payload.extend_from_slice(&(apdu.len() as u16).to_be_bytes());
+    payload.extend_from_slice(apdu);
+
+    payload.chunks(HID_REPORT_SIZE - 5)
+        .enumerate()
+        .map(|(sequence, chunk)| {
+            let mut report = [0u8; HID_REPORT_SIZE];
+            report[..2].copy_from_slice(&CHANNEL.to_be_bytes());
+            report[2] = HID_TAG_APDU;
+            report[3..5].copy_from_slice(&(sequence as u16).to_be_bytes());
+            report[5..5 + chunk.len()].copy_from_slice(chunk);
+            report
+        })
+        .collect()
+}
+
+/// Read HID reports until a whole response APDU has arrived
+fn read_response(transport: &mut dyn HidTransport) -> Result<Vec<u8>, String> {
+    let mut response = Vec::new();
+    let mut expected = None;
+    let mut sequence: u16 = 0;
+    loop {
+        let report = transport.read()?;
+        if report[..2] != CHANNEL.to_be_bytes() || report[2] != HID_TAG_APDU {
+            return Err("Unexpected HID report from device".to_string());
+        }
+        if u16::from_be_bytes([report[3], report[4]]) != sequence {
+            return Err("HID reports out of sequence".to_string());
+        }
+        let mut data = &report[5..];
+        if expected.is_none() {
+            expected = Some(u16::from_be_bytes([data[0], data[1]]) as usize);
+            data = &data[2..];
+        }
+        let length = expected.expect("set from the first report");
+        let take = (length - response.len()).min(data.len());
+        response.extend_from_slice(&data[..take]);
+        if response.len() == length {
+            return Ok(response);
+        }
+        sequence = sequence.wrapping_add(1);
+    }
+}
+
+fn encode_path(path: &[u32]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(1 + 4 * path.len());
+    out.push(path.len() as u8);
+    for index in path {
+        out.extend_from_slice(&index.to_be_bytes());
+    }
+    out
+}
+
+/// Hardware Signer
+/// Signs on a Ledger-style device, which shows amount, recipient and chain
+/// ID for the user to approve. Payloads the screen cannot show in full are
+/// refused unless blind signing was explicitly allowed.
+pub struct LedgerSigner {
+    transport: Box<dyn HidTransport>,
+    path: Vec<u32>,
+    allow_blind_signing: bool,
+}
+
+impl LedgerSigner {
+    pub fn new(transport: Box<dyn HidTransport>) -> Self {
+        Self { transport, path: DEFAULT_PATH.to_vec(), allow_blind_signing: false }
+    }
+
+    pub fn with_path(mut self, path: Vec<u32>) -> Self {
+        self.path = path;
+        self
+    }
+
+    /// Sign payloads that are not fully shown on the device; the device
+    /// app must also have blind signing enabled in its settings
+    pub fn with_blind_signing(mut self, allow: bool) -> Self {
+        self.allow_blind_signing = allow;
+        self
+    }
+
+    fn exchange(&mut self, ins: u8, p1: u8, data: &[u8]) -> Result<Vec<u8>, String> {
+        let mut apdu = vec![CLA, ins, p1, 0x00, data.len() as u8];
+        apdu.extend_from_slice(data);
+        for report in frame_apdu(&apdu) {
+            self.transport.write(&report)?;
+        }
+
+        let mut response = read_response(self.transport.as_mut())?;
+        if response.len() < 2 {
+            return Err("Truncated response from device".to_string());
+        }
+        let status = response.split_off(response.len() - 2);
+        match u16::from_be_bytes([status[0], status[1]]) {
+            SW_OK => Ok(response),
+            SW_USER_REJECTED => Err("Rejected on the device".to_string()),
+            SW_BLIND_SIGNING_DISABLED => Err("Blind signing is disabled in the device app settings".to_string()),
+            SW_WRONG_APP => Err("Open the Quantum Metaverse app on the device".to_string()),
+            SW_LOCKED => Err("Device is locked".to_string()),
+            other => Err(format!("Device returned status 0x{:04x}", other)),
+        }
+    }
+}
+
+impl TransactionSigner for LedgerSigner {
+    fn public_key(&mut self) -> Result<Address, String> {
+        let path = encode_path(&self.path);
+        let key = self.exchange(INS_GET_PUBLIC_KEY, P1_FIRST, &path)?;
+        key.try_into().map_err(|_| "Device returned a malformed public key".to_string())
+    }
+
+    fn sign_transaction(&mut self, tx: &mut Transaction) -> Result<TransactionReview, String> {
+        let review = display::review(tx);
+        if review.blind && !self.allow_blind_signing {
+            return Err("Transaction cannot be fully shown on the device; allow blind signing to sign it".to_string());
+        }
+
+        tx.from = self.public_key()?;
+        let payload = tx.signing_bytes();
+        let mut header = encode_path(&self.path);
+        header.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        self.exchange(INS_SIGN_TX, P1_FIRST, &header)?;
+        let mut signature = Vec::new();
+        for chunk in payload.chunks(MAX_APDU_DATA) {
+            // The device replies to the last chunk once the user approves
+            signature = self.exchange(INS_SIGN_TX, P1_MORE, chunk)?;
+        }
+
+        // Never trust the device blindly: the signature must verify
+        let signature: [u8; 64] = signature.try_into()
+            .map_err(|_| "Device returned a malformed signature".to_string())?;
+        let key = VerifyingKey::from_bytes(&tx.from).map_err(|_| "Device returned an invalid public key".to_string())?;
+        key.verify(&payload, &Signature::from_bytes(&signature))
+            .map_err(|_| "Device signature does not verify".to_string())?;
+        tx.signature = signature.to_vec();
+        Ok(review)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::execution::{Instruction, Operand};
+    use crate::blockchain::transaction::TransactionAction;
+    use ed25519_dalek::{Signer, SigningKey};
+    use std::collections::VecDeque;
+
+    /// Longest payload the simulated device can show on screen
+    const DEVICE_DISPLAY_LIMIT: usize = 300;
+
+    /// Device holding a fixed key that signs once the payload is complete
+    struct SimulatedDevice {
+        key: SigningKey,
+        blind_signing_enabled: bool,
+        incoming: Vec<u8>,
+        expected: usize,
+        payload: Vec<u8>,
+        outgoing: VecDeque<[u8; HID_REPORT_SIZE]>,
+    }
+
+    impl SimulatedDevice {
+        fn respond(&mut self, apdu: &[u8]) -> (Vec<u8>, u16) {
+            let (ins, p1, data) = (apdu[1], apdu[2], &apdu[5..]);
+            match (ins, p1) {
+                (INS_GET_PUBLIC_KEY, _) => (self.key.verifying_key().to_bytes().to_vec(), SW_OK),
+                (INS_SIGN_TX, P1_FIRST) => {
+                    let length = &data[data.len() - 4..];
+                    self.expected = u32::from_be_bytes(length.try_into().unwrap()) as usize;
+                    self.payload.clear();
+                    (Vec::new(), SW_OK)
+                }
+                _ => {
+                    self.payload.extend_from_slice(data);
+                    if self.payload.len() < self.expected {
+                        (Vec::new(), SW_OK)
+                    } else if self.payload.len() > DEVICE_DISPLAY_LIMIT && !self.blind_signing_enabled {
+                        (Vec::new(), SW_BLIND_SIGNING_DISABLED)
+                    } else {
+                        (self.key.sign(&self.payload).to_bytes().to_vec(), SW_OK)
+                    }
+                }
+            }
+        }
+    }
+
+    impl HidTransport for SimulatedDevice {
+        fn write(&mut self, report: &[u8; HID_REPORT_SIZE]) -> Result<(), String> {
+            if u16::from_be_bytes([report[3], report[4]]) == 0 {
+                self.incoming.clear();
+            }
+            self.incoming.extend_from_slice(&report[5..]);
+            let length = u16::from_be_bytes([self.incoming[0], self.incoming[1]]) as usize;
+            if self.incoming.len() >= length + 2 {
+                let apdu = self.incoming[2..2 + length].to_vec();
+                let (mut response, status) = self.respond(&apdu);
+                response.extend_from_slice(&status.to_be_bytes());
+                self.outgoing.extend(frame_apdu(&response));
+            }
+            Ok(())
+        }
+
+        fn read(&mut self) -> Result<[u8; HID_REPORT_SIZE], String> {
+            self.outgoing.pop_front().ok_or_else(|| "No response".to_string())
+        }
+    }
+
+    fn device(blind_signing_enabled: bool) -> Box<SimulatedDevice> {
+        Box::new(SimulatedDevice {
+            key: SigningKey::from_bytes(&[9u8; 32]),
+            blind_signing_enabled,
+            incoming: Vec::new(),
+            expected: 0,
+            payload: Vec::new(),
+            outgoing: VecDeque::new(),
+        })
+    }
+
+    #[test]
+    fn test_device_signing_and_blind_signing_protection() {
+        let mut signer = LedgerSigner::new(device(false));
+        let mut tx = Transaction::new([0u8; 32], 0, TransactionAction::Transfer { to: [2u8; 32], amount: 10 }, 21_000, 1);
+        let review = signer.sign_transaction(&mut tx).unwrap();
+        assert!(!review.blind);
+        assert_eq!(tx.from, SigningKey::from_bytes(&[9u8; 32]).verifying_key().to_bytes());
+        assert!(tx.verify_signature().is_ok());
+
+        // Deployments cannot be reviewed on screen and are refused by default
+        let code = vec![Instruction::Emit { topic: "minted".to_string(), data: Operand::Caller }; 32];
+        let mut deploy = Transaction::new([0u8; 32], 1, TransactionAction::Deploy { code }, 100_000, 1);
+        assert!(signer.sign_transaction(&mut deploy).unwrap_err().contains("allow blind signing"));
+
+        // Allowed on the host, the device setting still has the last word
+        let mut signer = signer.with_blind_signing(true);
+        assert!(signer.sign_transaction(&mut deploy).unwrap_err().contains("disabled in the device app"));
+        let mut signer = LedgerSigner::new(device(true)).with_blind_signing(true);
+        signer.sign_transaction(&mut deploy).unwrap();
+        assert!(deploy.verify_signature().is_ok());
+    }
+}
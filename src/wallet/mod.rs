@@ -0,0 +1,83 @@
+//! Transaction signing for wallets and the CLI.
+
+pub mod display;
+pub mod ledger;
+#[cfg(feature = "ledger")]
+pub mod hid;
+
+use crate::blockchain::transaction::Transaction;
+use crate::blockchain::types::Address;
+use display::TransactionReview;
+use ed25519_dalek::SigningKey;
+use std::str::FromStr;
+
+/// Backend that holds an account key and signs transactions with it
+pub trait TransactionSigner {
+    fn public_key(&mut self) -> Result<Address, String>;
+    /// Set `tx.from` to the signer's key and sign; returns what was reviewed
+    fn sign_transaction(&mut self, tx: &mut Transaction) -> Result<TransactionReview, String>;
+}
+
+/// Signs with a key held in memory
+pub struct SoftwareSigner {
+    key: SigningKey,
+}
+
+impl SoftwareSigner {
+    pub fn new(key: SigningKey) -> Self {
+        Self { key }
+    }
+}
+
+impl TransactionSigner for SoftwareSigner {
+    fn public_key(&mut self) -> Result<Address, String> {
+        Ok(self.key.verifying_key().to_bytes())
+    }
+
+    fn sign_transaction(&mut self, tx: &mut Transaction) -> Result<TransactionReview, String> {
+        tx.sign(&self.key);
+        Ok(display::review(tx))
+    }
+}
+
+/// Signer selected with `--signer`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignerKind {
+    /// Key file on disk
+    Local,
+    /// Ledger-style device over HID
+    Ledger,
+}
+
+impl FromStr for SignerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "local" => Ok(SignerKind::Local),
+            "ledger" => Ok(SignerKind::Ledger),
+            other => Err(format!("Unknown signer `{}` (local or ledger)", other)),
+        }
+    }
+}
+
+/// Parse a derivation path such as `m/44'/7777'/0'`
+pub fn parse_derivation_path(path: &str) -> Result<Vec<u32>, String> {
+    let mut components = path.split('/');
+    if components.next() != Some("m") {
+        return Err(format!("Derivation path `{}` must start with m/", path));
+    }
+    components
+        .map(|component| {
+            let (index, hardened) = match component.strip_suffix('\'') {
+                Some(index) => (index, true),
+                None => (component, false),
+            };
+            let index: u32 = index.parse()
+                .ok()
+                .filter(|index| *index < 0x8000_0000)
+                .ok_or_else(|| format!("Invalid derivation path component `{}`", component))?;
+            Ok(if hardened { index | 0x8000_0000 } else { index })
+        })
+        .collect()
+}
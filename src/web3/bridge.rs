@@ -1,12 +1,23 @@
 use serde::{Serialize, Deserialize};
 use crate::math::precision::PreciseFloat;
 
+/// Fee charged on bridged amounts by default, in basis points
+pub const DEFAULT_FEE_BPS: u32 = 10;
+
+pub type BridgeId = [u8; 32];
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Bridge {
     pub source_chain: [u8; 32],
     pub target_chain: [u8; 32],
     pub locked_assets: PreciseFloat,
-    pub validators: Vec<[u8; 32]>
+    pub validators: Vec<[u8; 32]>,
+    #[serde(default = "default_fee_bps")]
+    pub fee_bps: u32,
+}
+
+fn default_fee_bps() -> u32 {
+    DEFAULT_FEE_BPS
 }
 
 impl Bridge {
@@ -15,10 +26,24 @@ impl Bridge {
             source_chain: source,
             target_chain: target,
             locked_assets: PreciseFloat::new(0, 0),
-            validators: Vec::new()
+            validators: Vec::new(),
+            fee_bps: DEFAULT_FEE_BPS,
         }
     }
 
+    /// Identifies the bridge by the chains it connects, in direction
+    pub fn id(&self) -> BridgeId {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&self.source_chain);
+        hasher.update(&self.target_chain);
+        *hasher.finalize().as_bytes()
+    }
+
+    /// Fee for bridging `amount`; part of it funds bridge insurance
+    pub fn fee(&self, amount: &PreciseFloat) -> PreciseFloat {
+        PreciseFloat::new(amount.value * self.fee_bps as i128 / 10_000, amount.scale)
+    }
+
     pub fn validate_transfer(&self, _amount: PreciseFloat) -> bool {
         // Implementation will go here
         true
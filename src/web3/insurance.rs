@@ -0,0 +1,265 @@
+//! Bridge insurance fund.
+//!
+//! Every bridge fee pays `INSURANCE_SHARE_BPS` of itself into a protocol
+//! fund. A user harmed by a bridge failure files a claim backed by evidence:
+//! a fraud proof showing the bridge's validators anchored two different
+//! blocks of the source chain at one height. The evidence is checked when
+//! the claim is filed, but nothing is paid until governance authorizes the
+//! claim with an `insurance.authorize_payout` action. Contributions, claims
+//! and payouts are kept with the heights they happened at so the fund's
+//! history can be queried.
+
+use serde::{Serialize, Deserialize};
+use num_traits::ToPrimitive;
+use std::collections::{BTreeMap, HashMap};
+use crate::blockchain::types::hex_serde;
+use crate::governance::ai_governance::Action;
+use crate::ids::ChainId;
+use crate::layers::watchtower::FraudProof;
+use crate::math::precision::PreciseFloat;
+use super::bridge::{Bridge, BridgeId};
+
+/// Share of each bridge fee paid into the fund, in basis points
+pub const INSURANCE_SHARE_BPS: i128 = 2_000;
+
+/// Governance action paying out a pending claim; the payload is the claim id
+pub const AUTHORIZE_PAYOUT_ACTION: &str = "insurance.authorize_payout";
+/// Governance action turning down a pending claim; the payload is the claim id
+pub const REJECT_CLAIM_ACTION: &str = "insurance.reject_claim";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClaimStatus {
+    Pending,
+    Paid { height: u64 },
+    Rejected { height: u64 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claim {
+    #[serde(with = "hex_serde")]
+    pub id: [u8; 32],
+    #[serde(with = "hex_serde")]
+    pub bridge: BridgeId,
+    #[serde(with = "hex_serde")]
+    pub claimant: [u8; 32],
+    pub amount: PreciseFloat,
+    pub evidence: FraudProof,
+    pub filed_at: u64,
+    pub status: ClaimStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Payout {
+    #[serde(with = "hex_serde")]
+    pub claim: [u8; 32],
+    #[serde(with = "hex_serde")]
+    pub claimant: [u8; 32],
+    pub amount: PreciseFloat,
+    pub height: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InsuredBridge {
+    pub source_chain: ChainId,
+    pub validators: Vec<[u8; 32]>,
+    pub contributed: PreciseFloat,
+    /// Height of the latest contribution
+    pub last_contribution: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FundStatus {
+    pub balance: PreciseFloat,
+    pub contributed: PreciseFloat,
+    pub paid_out: PreciseFloat,
+    pub bridges: usize,
+    pub pending_claims: usize,
+    pub payouts: usize,
+}
+
+/// Insurance Fund
+/// Bridge fee share held against proven bridge failures.
+#[derive(Debug, Clone)]
+pub struct InsuranceFund {
+    network_id: u64,
+    balance: PreciseFloat,
+    contributed: PreciseFloat,
+    paid_out: PreciseFloat,
+    bridges: HashMap<BridgeId, InsuredBridge>,
+    claims: BTreeMap<[u8; 32], Claim>,
+    payouts: Vec<Payout>,
+}
+
+impl InsuranceFund {
+    pub fn new(network_id: u64) -> Self {
+        let zero = PreciseFloat::new(0, 2);
+        Self {
+            network_id,
+            balance: zero.clone(),
+            contributed: zero.clone(),
+            paid_out: zero,
+            bridges: HashMap::new(),
+            claims: BTreeMap::new(),
+            payouts: Vec::new(),
+        }
+    }
+
+    pub fn balance(&self) -> &PreciseFloat {
+        &self.balance
+    }
+
+    pub fn status(&self) -> FundStatus {
+        FundStatus {
+            balance: self.balance.clone(),
+            contributed: self.contributed.clone(),
+            paid_out: self.paid_out.clone(),
+            bridges: self.bridges.len(),
+            pending_claims: self.claims.values().filter(|claim| claim.status == ClaimStatus::Pending).count(),
+            payouts: self.payouts.len(),
+        }
+    }
+
+    pub fn bridge(&self, id: &BridgeId) -> Option<&InsuredBridge> {
+        self.bridges.get(id)
+    }
+
+    pub fn claim(&self, id: &[u8; 32]) -> Option<&Claim> {
+        self.claims.get(id)
+    }
+
+    /// Claims in id order, optionally only those for `bridge`
+    pub fn claims(&self, bridge: Option<&BridgeId>) -> Vec<&Claim> {
+        self.claims.values().filter(|claim| bridge.is_none_or(|bridge| &claim.bridge == bridge)).collect()
+    }
+
+    /// Payouts, oldest first
+    pub fn payouts(&self) -> &[Payout] {
+        &self.payouts
+    }
+
+    /// Take the fund's share of a fee `bridge` charged at `height`. The
+    /// bridge's current validators are the ones claims are checked against.
+    /// Returns the share.
+    pub fn contribute(&mut self, bridge: &Bridge, fee: &PreciseFloat, height: u64) -> PreciseFloat {
+        let share = PreciseFloat::new(fee.value * INSURANCE_SHARE_BPS / 10_000, fee.scale);
+        let insured = self.bridges.entry(bridge.id()).or_insert_with(|| InsuredBridge {
+            source_chain: ChainId::new(bridge.source_chain),
+            validators: Vec::new(),
+            contributed: PreciseFloat::new(0, 2),
+            last_contribution: height,
+        });
+        insured.validators = bridge.validators.clone();
+        insured.contributed = insured.contributed.add(&share);
+        insured.last_contribution = height;
+        self.contributed = self.contributed.add(&share);
+        self.balance = self.balance.add(&share);
+        share
+    }
+
+    /// File a claim for `amount` against an insured bridge. The evidence
+    /// must show the bridge's validators equivocating on its source chain.
+    /// One claimant can file once per piece of evidence.
+    pub fn file_claim(
+        &mut self,
+        bridge: BridgeId,
+        claimant: [u8; 32],
+        amount: PreciseFloat,
+        evidence: FraudProof,
+        height: u64,
+    ) -> Result<[u8; 32], &'static str> {
+        let insured = self.bridges.get(&bridge).ok_or("Bridge is not insured")?;
+        if amount.value <= 0 {
+            return Err("Claim amount must be positive");
+        }
+        if evidence.first.chain_id != insured.source_chain {
+            return Err("Evidence is not about the bridge's source chain");
+        }
+        evidence.verify(self.network_id, &insured.validators)?;
+
+        let id = claim_id(&bridge, &claimant, &evidence);
+        if self.claims.contains_key(&id) {
+            return Err("Claim already filed");
+        }
+        self.claims.insert(id, Claim { id, bridge, claimant, amount, evidence, filed_at: height, status: ClaimStatus::Pending });
+        Ok(id)
+    }
+
+    /// Apply a governance decision on a pending claim; `Ok(false)` if the
+    /// action is not for the fund
+    pub fn apply_governance(&mut self, action: &Action, height: u64) -> Result<bool, &'static str> {
+        let Action::Custom(name, payload) = action else { return Ok(false) };
+        let authorize = match name.as_str() {
+            AUTHORIZE_PAYOUT_ACTION => true,
+            REJECT_CLAIM_ACTION => false,
+            _ => return Ok(false),
+        };
+        let id: [u8; 32] = payload.as_slice().try_into().map_err(|_| "Claim id must be 32 bytes")?;
+        let claim = self.claims.get_mut(&id).ok_or("Claim not found")?;
+        if claim.status != ClaimStatus::Pending {
+            return Err("Claim already decided");
+        }
+        if !authorize {
+            claim.status = ClaimStatus::Rejected { height };
+            return Ok(true);
+        }
+        if claim.amount.to_f64() > self.balance.to_f64() {
+            return Err("Insurance fund balance too low");
+        }
+        claim.status = ClaimStatus::Paid { height };
+        self.balance = self.balance.sub(&claim.amount);
+        self.paid_out = self.paid_out.add(&claim.amount);
+        self.payouts.push(Payout { claim: id, claimant: claim.claimant, amount: claim.amount.clone(), height });
+        Ok(true)
+    }
+}
+
+fn claim_id(bridge: &BridgeId, claimant: &[u8; 32], evidence: &FraudProof) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(bridge);
+    hasher.update(claimant);
+    hasher.update(&evidence.first.signature);
+    hasher.update(&evidence.second.signature);
+    *hasher.finalize().as_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::watchtower::ChainAnchor;
+    use ed25519_dalek::SigningKey;
+
+    #[test]
+    fn test_claim_needs_evidence_and_governance() {
+        let validator = SigningKey::from_bytes(&[4; 32]);
+        let mut bridge = Bridge::new([1; 32], [2; 32]);
+        bridge.validators.push(validator.verifying_key().to_bytes());
+        let mut fund = InsuranceFund::new(7);
+        let share = fund.contribute(&bridge, &PreciseFloat::new(50_000, 2), 10);
+        assert_eq!(share, PreciseFloat::new(10_000, 2));
+
+        let anchor = |block_hash: [u8; 32]| {
+            let mut anchor = ChainAnchor::new(ChainId::new([1; 32]), 5, block_hash, 11);
+            anchor.sign(7, &validator);
+            anchor
+        };
+        let proof = FraudProof { first: anchor([8; 32]), second: anchor([9; 32]) };
+        let agreeing = FraudProof { first: anchor([8; 32]), second: anchor([8; 32]) };
+        assert!(fund.file_claim(bridge.id(), [5; 32], PreciseFloat::new(4_000, 2), agreeing, 12).is_err());
+        let id = fund.file_claim(bridge.id(), [5; 32], PreciseFloat::new(4_000, 2), proof.clone(), 12).unwrap();
+        assert_eq!(fund.file_claim(bridge.id(), [5; 32], PreciseFloat::new(1, 2), proof.clone(), 12), Err("Claim already filed"));
+
+        // Too large a claim waits for the fund to grow
+        let greedy = fund.file_claim(bridge.id(), [6; 32], PreciseFloat::new(20_000, 2), proof, 12).unwrap();
+        let decide = |name: &str, id: [u8; 32]| Action::Custom(name.to_string(), id.to_vec());
+        assert_eq!(fund.apply_governance(&decide(AUTHORIZE_PAYOUT_ACTION, greedy), 20), Err("Insurance fund balance too low"));
+        assert_eq!(fund.apply_governance(&decide(REJECT_CLAIM_ACTION, greedy), 20), Ok(true));
+
+        assert_eq!(fund.apply_governance(&decide(AUTHORIZE_PAYOUT_ACTION, id), 20), Ok(true));
+        assert_eq!(fund.claim(&id).unwrap().status, ClaimStatus::Paid { height: 20 });
+        assert_eq!(fund.balance(), &PreciseFloat::new(6_000, 2));
+        assert_eq!(fund.payouts().len(), 1);
+        assert_eq!(fund.status().pending_claims, 0);
+        assert!(fund.apply_governance(&decide(AUTHORIZE_PAYOUT_ACTION, id), 21).is_err());
+    }
+}
@@ -1,2 +1,7 @@
 pub mod contracts;
+#[cfg(feature = "bridges")]
 pub mod bridge;
+#[cfg(feature = "bridges")]
+pub mod insurance;
+#[cfg(feature = "bridges")]
+pub mod reserve;
@@ -1,5 +1,7 @@
 use crate::math::precision::PreciseFloat;
 use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use crate::blockchain::types::hex_serde;
 
 pub struct ExecutionInstance {
     compute_power: PreciseFloat,
@@ -15,7 +17,7 @@ pub struct CrossChainMessage {
     proof: ZKProof,
 }
 
-type ChainId = [u8; 32];
+pub type ChainId = [u8; 32];
 
 #[derive(Clone)]
 pub struct ZKProof {
@@ -23,12 +25,31 @@ pub struct ZKProof {
     proof_data: Vec<u8>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ValidationMetrics {
     security_score: PreciseFloat,
     performance_score: PreciseFloat,
     reliability_score: PreciseFloat,
 }
 
+impl ValidationMetrics {
+    pub fn new(security_score: PreciseFloat, performance_score: PreciseFloat, reliability_score: PreciseFloat) -> Self {
+        Self { security_score, performance_score, reliability_score }
+    }
+
+    pub fn security_score(&self) -> &PreciseFloat {
+        &self.security_score
+    }
+
+    pub fn performance_score(&self) -> &PreciseFloat {
+        &self.performance_score
+    }
+
+    pub fn reliability_score(&self) -> &PreciseFloat {
+        &self.reliability_score
+    }
+}
+
 /// Web3 Orchestration Implementation
 pub struct Web3Orchestrator {
     precision: u8,
@@ -38,18 +59,68 @@ pub struct Web3Orchestrator {
     validation_threshold: PreciseFloat,
 }
 
-struct ChainState {
+/// Registered chain as seen by the orchestrator
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainState {
+    #[serde(with = "hex_serde")]
     last_block_hash: [u8; 32],
     validation_metrics: ValidationMetrics,
     active_validators: Vec<ValidatorInfo>,
 }
 
-struct ValidatorInfo {
+impl ChainState {
+    pub fn new(last_block_hash: [u8; 32], validation_metrics: ValidationMetrics) -> Self {
+        Self {
+            last_block_hash,
+            validation_metrics,
+            active_validators: Vec::new(),
+        }
+    }
+
+    pub fn with_validator(mut self, validator: ValidatorInfo) -> Self {
+        self.active_validators.push(validator);
+        self
+    }
+
+    pub fn last_block_hash(&self) -> &[u8; 32] {
+        &self.last_block_hash
+    }
+
+    pub fn validation_metrics(&self) -> &ValidationMetrics {
+        &self.validation_metrics
+    }
+
+    pub fn active_validators(&self) -> &[ValidatorInfo] {
+        &self.active_validators
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidatorInfo {
+    #[serde(with = "hex_serde")]
     id: [u8; 32],
     stake: PreciseFloat,
     reliability: PreciseFloat,
 }
 
+impl ValidatorInfo {
+    pub fn new(id: [u8; 32], stake: PreciseFloat, reliability: PreciseFloat) -> Self {
+        Self { id, stake, reliability }
+    }
+
+    pub fn id(&self) -> &[u8; 32] {
+        &self.id
+    }
+
+    pub fn stake(&self) -> &PreciseFloat {
+        &self.stake
+    }
+
+    pub fn reliability(&self) -> &PreciseFloat {
+        &self.reliability
+    }
+}
+
 impl Web3Orchestrator {
     pub fn new(precision: u8) -> Self {
         Self {
@@ -65,6 +136,10 @@ impl Web3Orchestrator {
         self.chain_registry.insert(chain_id, initial_state);
     }
 
+    pub fn chain_state(&self, chain_id: &ChainId) -> Option<&ChainState> {
+        self.chain_registry.get(chain_id)
+    }
+
     pub fn send_cross_chain_message(&mut self, message: CrossChainMessage) -> Result<(), &'static str> {
         // Verify source chain exists
         if !self.chain_registry.contains_key(&message.source_chain) {
@@ -0,0 +1,270 @@
+//! Zero-knowledge proof of reserve for bridged assets.
+//!
+//! For every registered chain the bridge knows the collateral locked there
+//! and the wrapped supply minted against it. Each epoch it publishes
+//! Pedersen commitments to both amounts per chain and proves, without
+//! revealing any amount, that total wrapped supply does not exceed total
+//! locked collateral: the difference of the commitment sums carries a
+//! `crypto::pedersen` range proof. A light
+//! client needs only the published proof to check it. An epoch whose proof
+//! cannot be produced or does not verify raises an alert and reports the
+//! reserve as failing to governance.
+
+use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::scalar::Scalar;
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, HashMap};
+use crate::blockchain::types::hex_serde;
+use crate::crypto::pedersen::{self, RangeProof};
+use crate::ids::ChainId;
+use crate::math::precision::PreciseFloat;
+
+/// Epochs of proofs kept for light clients
+pub const MAX_RESERVE_PROOFS: usize = 1_024;
+
+/// Governance metric: 1 while the latest reserve proof verified, 0 otherwise
+pub const RESERVE_OK_METRIC: &str = "bridge.reserve_ok";
+
+const TRANSCRIPT_TAG: &[u8] = b"QMV-RESERVE1";
+
+/// Collateral and wrapped supply for one registered chain
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainReserve {
+    /// Collateral locked on the chain
+    pub locked: u64,
+    /// Wrapped tokens minted against it
+    pub wrapped: u64,
+}
+
+/// Reserve Ledger
+/// The bridge's own record of collateral and wrapped supply per chain,
+/// from which each epoch's proof is made.
+#[derive(Debug, Clone, Default)]
+pub struct ReserveLedger {
+    chains: BTreeMap<ChainId, ChainReserve>,
+    /// Set while the circuit breaker halts bridge withdrawals
+    withdrawals_halted: bool,
+}
+
+impl ReserveLedger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, chain: ChainId) {
+        self.chains.entry(chain).or_default();
+    }
+
+    pub fn chains(&self) -> &BTreeMap<ChainId, ChainReserve> {
+        &self.chains
+    }
+
+    pub fn lock(&mut self, chain: &ChainId, amount: u64) -> Result<(), &'static str> {
+        let reserve = self.chains.get_mut(chain).ok_or("Chain not registered")?;
+        reserve.locked = reserve.locked.checked_add(amount).ok_or("Locked collateral overflow")?;
+        Ok(())
+    }
+
+    pub fn set_withdrawals_halted(&mut self, halted: bool) {
+        self.withdrawals_halted = halted;
+    }
+
+    pub fn release(&mut self, chain: &ChainId, amount: u64) -> Result<(), &'static str> {
+        if self.withdrawals_halted {
+            return Err("Bridge withdrawals are halted");
+        }
+        let reserve = self.chains.get_mut(chain).ok_or("Chain not registered")?;
+        reserve.locked = reserve.locked.checked_sub(amount).ok_or("Release exceeds locked collateral")?;
+        Ok(())
+    }
+
+    pub fn mint(&mut self, chain: &ChainId, amount: u64) -> Result<(), &'static str> {
+        let reserve = self.chains.get_mut(chain).ok_or("Chain not registered")?;
+        reserve.wrapped = reserve.wrapped.checked_add(amount).ok_or("Wrapped supply overflow")?;
+        Ok(())
+    }
+
+    pub fn burn(&mut self, chain: &ChainId, amount: u64) -> Result<(), &'static str> {
+        let reserve = self.chains.get_mut(chain).ok_or("Chain not registered")?;
+        reserve.wrapped = reserve.wrapped.checked_sub(amount).ok_or("Burn exceeds wrapped supply")?;
+        Ok(())
+    }
+
+    /// Prove total wrapped supply is at most total locked collateral
+    pub fn prove(&self, epoch: u64) -> Result<ReserveProof, &'static str> {
+        let locked: u128 = self.chains.values().map(|reserve| reserve.locked as u128).sum();
+        let wrapped: u128 = self.chains.values().map(|reserve| reserve.wrapped as u128).sum();
+        let surplus = locked.checked_sub(wrapped).ok_or("Wrapped supply exceeds locked collateral")?;
+        let surplus = u64::try_from(surplus).map_err(|_| "Reserve surplus too large to prove")?;
+
+        let mut chains = Vec::with_capacity(self.chains.len());
+        let mut blinding = Scalar::ZERO;
+        for (chain_id, reserve) in &self.chains {
+            let (locked_blinding, wrapped_blinding) = (pedersen::random_scalar(), pedersen::random_scalar());
+            blinding += locked_blinding - wrapped_blinding;
+            chains.push(ChainCommitments {
+                chain_id: *chain_id,
+                locked: pedersen::compress(&pedersen::commit(reserve.locked, &locked_blinding)),
+                wrapped: pedersen::compress(&pedersen::commit(reserve.wrapped, &wrapped_blinding)),
+            });
+        }
+
+        let range = RangeProof::prove(surplus, &blinding, &transcript_prefix(epoch, &chains));
+        Ok(ReserveProof { epoch, chains, range })
+    }
+}
+
+/// Published commitments for one chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainCommitments {
+    pub chain_id: ChainId,
+    #[serde(with = "hex_serde")]
+    pub locked: [u8; 32],
+    #[serde(with = "hex_serde")]
+    pub wrapped: [u8; 32],
+}
+
+/// Reserve Proof
+/// One epoch's commitments per chain and the range proof over their
+/// surplus; verifiable on its own.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReserveProof {
+    pub epoch: u64,
+    pub chains: Vec<ChainCommitments>,
+    /// Range proof over the surplus commitment
+    pub range: RangeProof,
+}
+
+impl ReserveProof {
+    /// Check that the committed wrapped supply is at most the committed
+    /// collateral
+    pub fn verify(&self) -> Result<(), &'static str> {
+        let mut surplus = RistrettoPoint::default();
+        for chain in &self.chains {
+            surplus += pedersen::decompress(&chain.locked)? - pedersen::decompress(&chain.wrapped)?;
+        }
+        self.range.verify(&surplus, &transcript_prefix(self.epoch, &self.chains))
+    }
+}
+
+fn transcript_prefix(epoch: u64, chains: &[ChainCommitments]) -> Vec<u8> {
+    let mut prefix = TRANSCRIPT_TAG.to_vec();
+    prefix.extend_from_slice(&epoch.to_le_bytes());
+    for chain in chains {
+        prefix.extend_from_slice(chain.chain_id.as_bytes());
+        prefix.extend_from_slice(&chain.locked);
+        prefix.extend_from_slice(&chain.wrapped);
+    }
+    prefix
+}
+
+/// An epoch whose reserve could not be proven
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReserveAlert {
+    pub epoch: u64,
+    pub reason: String,
+}
+
+/// Reserve Log
+/// Published proofs by epoch and the alerts raised for failed epochs.
+#[derive(Debug, Clone, Default)]
+pub struct ReserveLog {
+    proofs: BTreeMap<u64, ReserveProof>,
+    alerts: Vec<ReserveAlert>,
+    latest_ok: bool,
+}
+
+impl ReserveLog {
+    pub fn new() -> Self {
+        Self { latest_ok: true, ..Self::default() }
+    }
+
+    /// Prove the ledger for `epoch`, check the proof as a light client
+    /// would and publish it. A failure is kept as an alert.
+    pub fn publish(&mut self, ledger: &ReserveLedger, epoch: u64) -> Result<&ReserveProof, ReserveAlert> {
+        let result = ledger.prove(epoch).and_then(|proof| proof.verify().map(|_| proof));
+        self.latest_ok = result.is_ok();
+        match result {
+            Ok(proof) => {
+                self.proofs.insert(epoch, proof);
+                while self.proofs.len() > MAX_RESERVE_PROOFS {
+                    self.proofs.pop_first();
+                }
+                Ok(&self.proofs[&epoch])
+            }
+            Err(reason) => {
+                let alert = ReserveAlert { epoch, reason: reason.to_string() };
+                self.alerts.push(alert.clone());
+                Err(alert)
+            }
+        }
+    }
+
+    /// The proof for `epoch`, or the latest one
+    pub fn proof(&self, epoch: Option<u64>) -> Option<&ReserveProof> {
+        match epoch {
+            Some(epoch) => self.proofs.get(&epoch),
+            None => self.proofs.values().next_back(),
+        }
+    }
+
+    pub fn alerts(&self) -> &[ReserveAlert] {
+        &self.alerts
+    }
+
+    /// Metrics for governance policies
+    pub fn metrics(&self) -> HashMap<String, PreciseFloat> {
+        let ok = if self.latest_ok { 100 } else { 0 };
+        HashMap::from([(RESERVE_OK_METRIC.to_string(), PreciseFloat::new(ok, 2))])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proof_of_reserve() {
+        let (ethereum, solana) = (ChainId::new([1; 32]), ChainId::new([2; 32]));
+        let mut ledger = ReserveLedger::new();
+        ledger.register(ethereum);
+        ledger.register(solana);
+        ledger.lock(&ethereum, 1_000).unwrap();
+        ledger.lock(&solana, 500).unwrap();
+        ledger.mint(&ethereum, 1_200).unwrap();
+        ledger.mint(&solana, 300).unwrap();
+
+        // Fully backed across chains even though one chain alone is not
+        let proof = ledger.prove(3).unwrap();
+        assert!(proof.verify().is_ok());
+        let published: ReserveProof = serde_json::from_value(serde_json::to_value(&proof).unwrap()).unwrap();
+        assert!(published.verify().is_ok());
+
+        // Proofs are bound to their epoch and commitments
+        let mut replayed = proof.clone();
+        replayed.epoch = 4;
+        assert!(replayed.verify().is_err());
+        let mut swapped = proof.clone();
+        swapped.chains[0].wrapped = proof.chains[1].wrapped;
+        assert!(swapped.verify().is_err());
+
+        ledger.mint(&solana, 1).unwrap();
+        assert_eq!(ledger.prove(5).unwrap_err(), "Wrapped supply exceeds locked collateral");
+    }
+
+    #[test]
+    fn test_log_raises_alert_on_shortfall() {
+        let chain = ChainId::new([1; 32]);
+        let mut ledger = ReserveLedger::new();
+        ledger.register(chain);
+        let mut log = ReserveLog::new();
+        assert!(log.publish(&ledger, 1).is_ok());
+
+        ledger.mint(&chain, 10).unwrap();
+        let alert = log.publish(&ledger, 2).unwrap_err();
+        assert_eq!(alert.epoch, 2);
+        assert_eq!(log.metrics()[RESERVE_OK_METRIC], PreciseFloat::new(0, 2));
+        assert_eq!(log.proof(None).unwrap().epoch, 1);
+        assert_eq!(log.alerts().len(), 1);
+    }
+}
@@ -0,0 +1,127 @@
+//! Replays the committed devnet corpus through block import, execution and
+//! observation admission. A failure means consensus behavior changed. If
+//! that was intended, re-record the fixture with
+//! `cargo test --test corpus_replay -- --ignored` and commit it.
+
+use ed25519_dalek::SigningKey;
+use quantum_metaverse::blockchain::builder::{BlockBuilder, BundleLimits, FairFifo};
+use quantum_metaverse::blockchain::core::Blockchain;
+use quantum_metaverse::blockchain::execution::{Instruction, Operand};
+use quantum_metaverse::blockchain::mempool::{Mempool, MempoolConfig};
+use quantum_metaverse::blockchain::sealer::seal_block;
+use quantum_metaverse::blockchain::state::{StateStore, WorldState};
+use quantum_metaverse::blockchain::transaction::{Transaction, TransactionAction};
+use quantum_metaverse::crypto::domain::MAINNET_NETWORK_ID;
+use quantum_metaverse::math::precision::PreciseFloat;
+use quantum_metaverse::network::observations::TallyObservation;
+use quantum_metaverse::storage::corpus::{Corpus, ObserverBond};
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/devnet_corpus.json");
+
+fn load_fixture() -> Corpus {
+    let json = std::fs::read_to_string(FIXTURE).expect("corpus fixture missing");
+    serde_json::from_str(&json).expect("corpus fixture is not a valid corpus")
+}
+
+#[test]
+fn test_devnet_corpus_replays() {
+    let corpus = load_fixture();
+    let report = corpus.replay().unwrap_or_else(|e| panic!("{}", e));
+    assert_eq!(report.blocks, corpus.blocks.len());
+    assert!(report.transactions > 0);
+    assert!(corpus.observations.iter().any(|recorded| recorded.rejected.is_none()));
+    assert!(corpus.observations.iter().any(|recorded| recorded.rejected.is_some()));
+}
+
+#[test]
+fn test_tampered_corpus_diverges() {
+    let mut corpus = load_fixture();
+    corpus.outcomes[0].gas_used += 1;
+    assert!(corpus.replay().unwrap_err().starts_with("Block 1 diverged"));
+
+    let mut corpus = load_fixture();
+    let forged = corpus.observations.iter_mut().find(|recorded| recorded.rejected.is_none()).unwrap();
+    forged.observation.confidence = PreciseFloat::new(1, 2);
+    assert!(corpus.replay().unwrap_err().starts_with("Observation"));
+}
+
+fn signed(key: &SigningKey, nonce: u64, action: TransactionAction) -> Transaction {
+    let mut tx = Transaction::new(key.verifying_key().to_bytes(), nonce, action, 200_000, 1);
+    tx.sign(key);
+    tx
+}
+
+#[test]
+#[ignore = "re-records the fixture; run only when consensus behavior changes on purpose"]
+fn record_devnet_corpus() {
+    let keys: Vec<SigningKey> = (1..=3).map(|seed| SigningKey::from_bytes(&[seed; 32])).collect();
+    let addresses: Vec<[u8; 32]> = keys.iter().map(|key| key.verifying_key().to_bytes()).collect();
+    let genesis = WorldState::with_balances(&addresses.iter().map(|address| (*address, 1_000_000_000)).collect::<Vec<_>>());
+
+    let mut chain = Blockchain::new(2);
+    let mut store = StateStore::new(genesis.clone());
+    let mut mempool = Mempool::new(MempoolConfig::default());
+    let builder = BlockBuilder::new(&FairFifo, BundleLimits::default());
+    let mut nonces = [0u64; 3];
+    let mut submit = |mempool: &mut Mempool, store: &StateStore, sender: usize, action| {
+        mempool.insert(signed(&keys[sender], nonces[sender], action), store.latest()).unwrap();
+        nonces[sender] += 1;
+    };
+
+    // Transfers between the funded accounts and to a new one
+    submit(&mut mempool, &store, 0, TransactionAction::Transfer { to: addresses[1], amount: 500 });
+    submit(&mut mempool, &store, 1, TransactionAction::Transfer { to: addresses[2], amount: 300 });
+    submit(&mut mempool, &store, 2, TransactionAction::Transfer { to: [9; 32], amount: 100 });
+    seal_block(&mut chain, &mut store, &mut mempool, &builder).unwrap();
+
+    // A contract that keeps the last input and emits who sent it
+    let code = vec![
+        Instruction::Store { key: b"greeting".to_vec(), value: Operand::Input },
+        Instruction::Emit { topic: "Greeted".to_string(), data: Operand::Caller },
+        Instruction::Return(Operand::Load(b"greeting".to_vec())),
+    ];
+    submit(&mut mempool, &store, 0, TransactionAction::Deploy { code });
+    let deployed = seal_block(&mut chain, &mut store, &mut mempool, &builder).unwrap();
+    let contract = deployed.receipts[0].contract_address.unwrap();
+
+    for (sender, input) in [(1, b"hello".to_vec()), (2, b"world".to_vec())] {
+        submit(&mut mempool, &store, sender, TransactionAction::Call { contract, input, value: 0 });
+    }
+    submit(&mut mempool, &store, 0, TransactionAction::Call { contract, input: b"paid".to_vec(), value: 25 });
+    seal_block(&mut chain, &mut store, &mut mempool, &builder).unwrap();
+
+    // An empty block, then one more transfer
+    seal_block(&mut chain, &mut store, &mut mempool, &builder).unwrap();
+    submit(&mut mempool, &store, 1, TransactionAction::Transfer { to: addresses[0], amount: 42 });
+    seal_block(&mut chain, &mut store, &mut mempool, &builder).unwrap();
+
+    let blocks: Vec<_> = (0..chain.height()).map(|height| chain.block(height).unwrap().clone()).collect();
+
+    // Three bonded observers and one without a bond
+    let observers: Vec<SigningKey> = (11..=14).map(|seed| SigningKey::from_bytes(&[seed; 32])).collect();
+    let bonds = [100_000, 300_000, 150_000].iter().zip(&observers)
+        .map(|(stake, key)| ObserverBond { observer: key.verifying_key().to_bytes(), stake: PreciseFloat::new(*stake, 2) })
+        .collect();
+    let observe = |observer: usize, layer: u32, state: &[u8], at: u64| {
+        TallyObservation::sign(&observers[observer], layer, state.to_vec(), PreciseFloat::new(95, 2), at, MAINNET_NETWORK_ID)
+    };
+    let start = 1_700_000_000;
+    let mut forged = observe(1, 1, b"layer-1-a", start + 2);
+    forged.observed_state = b"layer-1-b".to_vec();
+    let observations = vec![
+        observe(0, 0, b"layer-0-a", start),
+        observe(1, 0, b"layer-0-a", start),
+        observe(2, 0, b"layer-0-b", start),
+        observe(0, 1, b"layer-1-a", start + 1),
+        // Not newer than observer 0's last on layer 0
+        observe(0, 0, b"layer-0-b", start),
+        forged,
+        observe(3, 0, b"layer-0-a", start + 1),
+        // Replaces observer 2's earlier view of layer 0
+        observe(2, 0, b"layer-0-a", start + 5),
+    ];
+
+    let corpus = Corpus::record(MAINNET_NETWORK_ID, &genesis, &blocks, bonds, observations).unwrap();
+    std::fs::create_dir_all(std::path::Path::new(FIXTURE).parent().unwrap()).unwrap();
+    std::fs::write(FIXTURE, serde_json::to_string_pretty(&corpus).unwrap() + "\n").unwrap();
+}